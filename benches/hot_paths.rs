@@ -0,0 +1,111 @@
+//! Benchmarks of the storage and protocol hot paths.
+//!
+//! ```sh
+//! cargo bench --bench hot_paths -- --save-baseline main
+//! cargo bench --bench hot_paths -- --baseline main
+//! ```
+//!
+//! The second run compares every benchmark with the saved baseline and reports a regression
+//! beyond the noise threshold of 5%. The lookups and the broadcasts are measured the way they
+//! are done and the way they were done, through the scan of every participant and the
+//! encoding per receiver, so the output tells the gain at each peer count.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gossip_p2p::bench::{self, SinkTransport, StorageFixture, PEER_COUNTS};
+use std::hint::black_box;
+use std::time::Duration;
+
+fn storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage");
+    for peers in PEER_COUNTS {
+        let fixture = StorageFixture::new(peers);
+        group.bench_with_input(BenchmarkId::new("receivers", peers), &fixture, |b, f| {
+            b.iter(|| black_box(f.receivers()))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("get_participants_list", peers),
+            &fixture,
+            |b, f| b.iter(|| black_box(f.participants_list())),
+        );
+    }
+    group.finish();
+}
+
+fn lookups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contains_addr");
+    for peers in PEER_COUNTS {
+        let fixture = StorageFixture::new(peers);
+        let last = *fixture.addrs().last().unwrap();
+        group.bench_with_input(BenchmarkId::new("index", peers), &fixture, |b, f| {
+            b.iter(|| black_box(f.contains(black_box(last))))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("scan (before)", peers),
+            &fixture,
+            |b, f| b.iter(|| black_box(f.scan_contains(black_box(last)))),
+        );
+    }
+    group.finish();
+}
+
+fn messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message round trip");
+    for (name, message) in bench::messages() {
+        group.bench_function(name, |b| b.iter(|| black_box(bench::round_trip(&message))));
+    }
+    group.finish();
+}
+
+fn formatting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("format_addrs_capped");
+    for count in [10, 10_000, 100_000] {
+        let addrs = bench::addresses(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &addrs, |b, addrs| {
+            b.iter(|| black_box(bench::format_addrs(addrs)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("format_list_of_addrs");
+    for count in [10, 10_000] {
+        let addrs = bench::addresses(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &addrs, |b, addrs| {
+            b.iter(|| black_box(bench::format_all_addrs(addrs)))
+        });
+    }
+    group.finish();
+}
+
+fn broadcasts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broadcast");
+    let message = bench::messages().pop().unwrap().1;
+    for peers in [10, 100] {
+        let receivers = bench::connections(peers);
+        let mut sink = SinkTransport::default();
+        group.bench_with_input(
+            BenchmarkId::new("encoded once", peers),
+            &receivers,
+            |b, r| b.iter(|| bench::broadcast(&mut sink, r, &message)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("encoded per receiver (before)", peers),
+            &receivers,
+            |b, r| b.iter(|| bench::broadcast_per_receiver(&mut sink, r, &message)),
+        );
+    }
+    group.finish();
+}
+
+fn config() -> Criterion {
+    Criterion::default()
+        .noise_threshold(0.05)
+        .measurement_time(Duration::from_secs(2))
+        .warm_up_time(Duration::from_millis(500))
+}
+
+criterion_group! {
+    name = hot_paths;
+    config = config();
+    targets = storage, lookups, messages, formatting, broadcasts
+}
+criterion_main!(hot_paths);
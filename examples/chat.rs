@@ -0,0 +1,47 @@
+//! A chat over the gossip network, built on nothing but the prelude: every line typed is
+//! published on the `chat` topic, and every line a peer publishes there is printed.
+//!
+//! ```sh
+//! cargo run --example chat -- 8080
+//! cargo run --example chat -- 8081 127.0.0.1:8080
+//! ```
+
+use gossip_p2p::prelude::*;
+
+use std::io::BufRead;
+use std::thread;
+use std::time::Duration;
+
+/// The topic the lines are published on.
+const TOPIC: &str = "chat";
+
+fn main() -> Result<(), ParticipantError> {
+    let mut args = std::env::args().skip(1);
+    let port = args.next().and_then(|port| port.parse().ok()).unwrap_or(0);
+    let mut builder = ParticipantBuilder::new(Duration::from_secs(60)).port(port);
+    if let Some(addr) = args.next() {
+        builder = builder.connect(addr);
+    }
+    let mut participant = builder.build()?;
+    participant
+        .subscribe(TOPIC, |from, payload| {
+            println!("{}: {}", from, String::from_utf8_lossy(&payload));
+        })
+        .expect("the topic name is valid");
+
+    let handle = participant.handle();
+    thread::spawn(move || {
+        for text in std::io::stdin().lock().lines().map_while(Result::ok) {
+            let publish = Command::Publish {
+                topic: TOPIC.to_owned(),
+                text,
+            };
+            match handle.command(publish, Duration::from_secs(5)) {
+                Some(out) => out.iter().for_each(|line| eprintln!("{}", line)),
+                None => break,
+            }
+        }
+        handle.shutdown();
+    });
+    participant.run()
+}
@@ -0,0 +1,993 @@
+//! Admin Socket.
+//!
+//! The console only serves whoever holds the standard input of a participant. With
+//! `--admin-socket=<path>`, a participant also listens on a Unix domain socket for scripts and
+//! for the `ctl` command, and runs every console command sent there. Each connection is served
+//! by a thread of its own, up to `MAX_ADMIN_CLIENTS` at once, and speaks newline-delimited
+//! JSON: one flat object per request, naming the command in `cmd` and its arguments in fields
+//! of their own, see `AdminRequest`, and one object per response.
+//!
+//! ```plaintext
+//! > {"cmd": "mute", "addr": "127.0.0.1:8081", "secs": 60}
+//! < {"ok":true,"output":["Muted \"127.0.0.1:8081\" for 1m 00s"]}
+//! > {"cmd": "peer"}
+//! < {"ok":false,"error":"Malformed request: missing field `addr`"}
+//! ```
+//!
+//! A request is decoded into an `AdminRequest` and turned into its `Command` field by field, with
+//! the checks the console applies to the same arguments, so a field never spills into the
+//! next as words of a console line would. `ctl` parses its words as the console does and sends
+//! the request of the resulting command. The response carries the output of the command; what
+//! it logs later, such as the outcome of a `probe`, goes to the standard output of the
+//! participant. A malformed request is answered with an error and the connection stays open;
+//! only a request longer than `MAX_REQUEST_BYTES` closes it.
+//!
+//! Access is controlled by the file system: the socket is made readable and writable by its
+//! owner only as soon as it is bound, and whoever can open it controls the participant, `quit`
+//! included. Placing it in a directory only its user can enter also closes the short window
+//! before its permissions are set.
+
+use crate::cli::parse_duration;
+use crate::console::{parse_command, Command, DEFAULT_MUTE_DURATION};
+use crate::participant::descriptors::DescriptorBudget;
+use crate::participant::probe::{DEFAULT_PROBE_COUNT, DEFAULT_PROBE_INTERVAL};
+use crate::participant::schema::Schema;
+use crate::participant::threads::{Join, SpawnError, ThreadRegistry};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// The number of admin connections served at once; a connection over it is refused.
+pub const MAX_ADMIN_CLIENTS: usize = 16;
+
+/// How often the thread accepting admin connections checks whether the participant stops.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest request accepted, newline excluded.
+pub const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// How long `ctl` waits for the response of the participant.
+pub const CTL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A number or duration field, given either as a JSON number or as a string the console
+/// would read, such as `"10m"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Given(serde_json::Number),
+    Text(String),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Given(number) => write!(f, "{}", number),
+            Number::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Number {
+    /// Reads the number as an integer, or fails with `invalid` applied to what was given.
+    fn integer<T: std::str::FromStr>(&self, invalid: &str) -> Result<T, String> {
+        let text = self.to_string();
+        text.parse()
+            .map_err(|_| format!("Invalid {} \"{}\"", invalid, text))
+    }
+
+    /// Reads the number as a duration, a bare number counting seconds.
+    fn duration(&self) -> Result<Duration, String> {
+        parse_duration(&self.to_string())
+    }
+
+    /// Writes `duration` as the console reads it.
+    fn of_duration(duration: Duration) -> Self {
+        Number::Text(format!("{}ms", duration.as_millis()))
+    }
+
+    /// Writes `value` as a JSON number.
+    fn of(value: impl Into<serde_json::Number>) -> Self {
+        Number::Given(value.into())
+    }
+}
+
+/// A request of the admin socket: the console command named in `cmd`, with each of its
+/// arguments in a field of its own. The optional fields take the defaults of the console.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case", deny_unknown_fields)]
+enum AdminRequest {
+    Peers {
+        /// `export-csv` to write the table to `path`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        action: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    Peer {
+        addr: String,
+    },
+    Provenance {
+        addr: String,
+    },
+    Stats {},
+    Report {},
+    Reannounce {},
+    Audit {},
+    Export {
+        path: String,
+    },
+    Publish {
+        topic: String,
+        text: String,
+    },
+    PublishSchema {
+        topic: String,
+        schema: String,
+        version: Number,
+        text: String,
+    },
+    Estimate {
+        /// The topic of a publication, none for a gossip text.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+        text: String,
+    },
+    Limits {},
+    Schedule {
+        delay: Number,
+        /// The topic of a publication, none for a gossip text.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+        text: String,
+    },
+    Scheduled {},
+    Unschedule {
+        id: Number,
+    },
+    Request {
+        addr: String,
+        topic: String,
+        text: String,
+    },
+    Probe {
+        addr: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        count: Option<Number>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval: Option<Number>,
+    },
+    ProbeStatus {},
+    Mute {
+        addr: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secs: Option<Number>,
+    },
+    Unmute {
+        addr: String,
+    },
+    Evict {
+        addr: String,
+        /// How long the address is banned, none to only disconnect it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secs: Option<Number>,
+    },
+    Unban {
+        addr: String,
+    },
+    Bans {},
+    Dials {
+        /// The address whose dials are listed, none for the recent dials of any address.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        addr: Option<String>,
+    },
+    Subscribe {
+        topic: String,
+    },
+    SubscribeOrdered {
+        topic: String,
+    },
+    SubscribeSchema {
+        topic: String,
+        schema: String,
+        versions: String,
+    },
+    SubscribeUnsupported {
+        topic: String,
+    },
+    SchemaSupport {
+        topic: String,
+        schema: String,
+    },
+    Unsubscribe {
+        id: Number,
+    },
+    Subscriptions {},
+    Inbox {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secs: Option<Number>,
+    },
+    Threads {},
+    Channels {},
+    Memory {},
+    Reload {},
+    Help {},
+    Quit {},
+}
+
+/// Parses a peer address as the console does.
+fn address(addr: &str) -> Result<SocketAddr, String> {
+    addr.parse()
+        .map_err(|_| format!("Invalid address \"{}\"", addr))
+}
+
+impl AdminRequest {
+    /// Turns the request into its command, checking each argument as the console does.
+    fn into_command(self) -> Result<Command, String> {
+        let command = match self {
+            AdminRequest::Peers { action, path } => match (action.as_deref(), path) {
+                (None, None) => Command::Peers,
+                (Some("export-csv"), Some(path)) => Command::PeersCsv(PathBuf::from(path)),
+                _ => return Err("Usage: peers export-csv <path>".to_owned()),
+            },
+            AdminRequest::Peer { addr } => Command::Peer(address(&addr)?),
+            AdminRequest::Provenance { addr } => Command::Provenance(address(&addr)?),
+            AdminRequest::Stats {} => Command::Stats,
+            AdminRequest::Report {} => Command::Report,
+            AdminRequest::Reannounce {} => Command::Reannounce,
+            AdminRequest::Audit {} => Command::Audit,
+            AdminRequest::Export { path } => Command::Export(PathBuf::from(path)),
+            AdminRequest::Publish { topic, text } => Command::Publish { topic, text },
+            AdminRequest::PublishSchema {
+                topic,
+                schema,
+                version,
+                text,
+            } => Command::PublishSchema {
+                topic,
+                schema: Schema::new(schema, version.integer("schema version")?),
+                text,
+            },
+            AdminRequest::Estimate { topic, text } => Command::Estimate { topic, text },
+            AdminRequest::Limits {} => Command::Limits,
+            AdminRequest::Schedule { delay, topic, text } => Command::Schedule {
+                delay: delay.duration()?,
+                topic,
+                text,
+            },
+            AdminRequest::Scheduled {} => Command::Scheduled,
+            AdminRequest::Unschedule { id } => {
+                Command::Unschedule(id.integer("scheduled message")?)
+            }
+            AdminRequest::Request { addr, topic, text } => Command::Request {
+                peer: address(&addr)?,
+                topic,
+                text,
+            },
+            AdminRequest::Probe {
+                addr,
+                count,
+                interval,
+            } => {
+                let count = match count {
+                    Some(count) => Some(count.integer::<u32>("probe count")?)
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| format!("Invalid probe count \"{}\"", count))?,
+                    None => DEFAULT_PROBE_COUNT,
+                };
+                let interval = match interval {
+                    Some(interval) => interval.duration()?,
+                    None => DEFAULT_PROBE_INTERVAL,
+                };
+                if interval.is_zero() {
+                    return Err("The probe interval must be positive".to_owned());
+                }
+                Command::Probe {
+                    peer: address(&addr)?,
+                    count,
+                    interval,
+                }
+            }
+            AdminRequest::ProbeStatus {} => Command::ProbeStatus,
+            AdminRequest::Mute { addr, secs } => Command::Mute {
+                peer: address(&addr)?,
+                duration: match secs {
+                    Some(secs) => secs.duration()?,
+                    None => DEFAULT_MUTE_DURATION,
+                },
+            },
+            AdminRequest::Unmute { addr } => Command::Unmute(address(&addr)?),
+            AdminRequest::Evict { addr, secs } => Command::Evict {
+                peer: address(&addr)?,
+                ban: secs.map(|secs| secs.duration()).transpose()?,
+            },
+            AdminRequest::Unban { addr } => Command::Unban(address(&addr)?),
+            AdminRequest::Bans {} => Command::Bans,
+            AdminRequest::Dials { addr } => {
+                Command::Dials(addr.as_deref().map(address).transpose()?)
+            }
+            AdminRequest::Subscribe { topic } => Command::Subscribe(topic),
+            AdminRequest::SubscribeOrdered { topic } => Command::SubscribeOrdered(topic),
+            AdminRequest::SubscribeSchema {
+                topic,
+                schema,
+                versions,
+            } => Command::SubscribeSchema {
+                topic,
+                name: schema,
+                range: versions.parse()?,
+            },
+            AdminRequest::SubscribeUnsupported { topic } => Command::SubscribeUnsupported(topic),
+            AdminRequest::SchemaSupport { topic, schema } => Command::SchemaSupport {
+                topic,
+                name: schema,
+            },
+            AdminRequest::Unsubscribe { id } => Command::Unsubscribe(id.integer("subscription")?),
+            AdminRequest::Subscriptions {} => Command::Subscriptions,
+            AdminRequest::Inbox { secs } => {
+                Command::Inbox(secs.map(|secs| secs.duration()).transpose()?)
+            }
+            AdminRequest::Threads {} => Command::Threads,
+            AdminRequest::Channels {} => Command::Channels,
+            AdminRequest::Memory {} => Command::Memory,
+            AdminRequest::Reload {} => Command::Reload,
+            AdminRequest::Help {} => Command::Help,
+            AdminRequest::Quit {} => Command::Quit,
+        };
+        Ok(command)
+    }
+}
+
+impl From<Command> for AdminRequest {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Peers => AdminRequest::Peers {
+                action: None,
+                path: None,
+            },
+            Command::PeersCsv(path) => AdminRequest::Peers {
+                action: Some("export-csv".to_owned()),
+                path: Some(path.display().to_string()),
+            },
+            Command::Peer(addr) => AdminRequest::Peer {
+                addr: addr.to_string(),
+            },
+            Command::Provenance(addr) => AdminRequest::Provenance {
+                addr: addr.to_string(),
+            },
+            Command::Stats => AdminRequest::Stats {},
+            Command::Report => AdminRequest::Report {},
+            Command::Reannounce => AdminRequest::Reannounce {},
+            Command::Audit => AdminRequest::Audit {},
+            Command::Export(path) => AdminRequest::Export {
+                path: path.display().to_string(),
+            },
+            Command::Publish { topic, text } => AdminRequest::Publish { topic, text },
+            Command::PublishSchema {
+                topic,
+                schema,
+                text,
+            } => AdminRequest::PublishSchema {
+                topic,
+                schema: schema.name,
+                version: Number::of(schema.version),
+                text,
+            },
+            Command::Estimate { topic, text } => AdminRequest::Estimate { topic, text },
+            Command::Limits => AdminRequest::Limits {},
+            Command::Schedule { delay, topic, text } => AdminRequest::Schedule {
+                delay: Number::of_duration(delay),
+                topic,
+                text,
+            },
+            Command::Scheduled => AdminRequest::Scheduled {},
+            Command::Unschedule(id) => AdminRequest::Unschedule { id: Number::of(id) },
+            Command::Request { peer, topic, text } => AdminRequest::Request {
+                addr: peer.to_string(),
+                topic,
+                text,
+            },
+            Command::Probe {
+                peer,
+                count,
+                interval,
+            } => AdminRequest::Probe {
+                addr: peer.to_string(),
+                count: Some(Number::of(count)),
+                interval: Some(Number::of_duration(interval)),
+            },
+            Command::ProbeStatus => AdminRequest::ProbeStatus {},
+            Command::Mute { peer, duration } => AdminRequest::Mute {
+                addr: peer.to_string(),
+                secs: Some(Number::of_duration(duration)),
+            },
+            Command::Unmute(addr) => AdminRequest::Unmute {
+                addr: addr.to_string(),
+            },
+            Command::Evict { peer, ban } => AdminRequest::Evict {
+                addr: peer.to_string(),
+                secs: ban.map(Number::of_duration),
+            },
+            Command::Unban(addr) => AdminRequest::Unban {
+                addr: addr.to_string(),
+            },
+            Command::Bans => AdminRequest::Bans {},
+            Command::Dials(addr) => AdminRequest::Dials {
+                addr: addr.map(|addr| addr.to_string()),
+            },
+            Command::Subscribe(topic) => AdminRequest::Subscribe { topic },
+            Command::SubscribeOrdered(topic) => AdminRequest::SubscribeOrdered { topic },
+            Command::SubscribeSchema { topic, name, range } => AdminRequest::SubscribeSchema {
+                topic,
+                schema: name,
+                versions: range.to_string(),
+            },
+            Command::SubscribeUnsupported(topic) => AdminRequest::SubscribeUnsupported { topic },
+            Command::SchemaSupport { topic, name } => AdminRequest::SchemaSupport {
+                topic,
+                schema: name,
+            },
+            Command::Unsubscribe(id) => AdminRequest::Unsubscribe { id: Number::of(id) },
+            Command::Subscriptions => AdminRequest::Subscriptions {},
+            Command::Inbox(wait) => AdminRequest::Inbox {
+                secs: wait.map(Number::of_duration),
+            },
+            Command::Threads => AdminRequest::Threads {},
+            Command::Channels => AdminRequest::Channels {},
+            Command::Memory => AdminRequest::Memory {},
+            Command::Reload => AdminRequest::Reload {},
+            Command::Help => AdminRequest::Help {},
+            Command::Quit => AdminRequest::Quit {},
+        }
+    }
+}
+
+/// Parses one admin request into the console command it stands for.
+///
+/// # Returns
+///
+/// The `Command`, or an `Err` explaining what is wrong with the request.
+pub fn parse_request(text: &str) -> Result<Command, String> {
+    let request: AdminRequest =
+        serde_json::from_str(text).map_err(|err| format!("Malformed request: {}", err))?;
+    request.into_command()
+}
+
+/// Builds the admin request of a command given as the words of `ctl`, e.g. `mute`,
+/// `127.0.0.1:8081`, `60`, read as the console reads the line of these words.
+///
+/// # Returns
+///
+/// The request as one line of JSON, or the `Err` of the console for a command it refuses.
+pub fn build_request(words: &[String]) -> Result<String, String> {
+    let command = parse_command(&words.join(" "))?;
+    Ok(serde_json::to_string(&AdminRequest::from(command)).expect("a request serializes"))
+}
+
+/// A response, as sent on the socket: the output of a command that ran, or the reason it
+/// was refused.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Answer {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Formats the response to a request.
+fn format_response(outcome: &Result<Vec<String>, String>) -> String {
+    let answer = match outcome {
+        Ok(output) => Answer {
+            ok: true,
+            output: Some(output.clone()),
+            error: None,
+        },
+        Err(err) => Answer {
+            ok: false,
+            output: None,
+            error: Some(err.clone()),
+        },
+    };
+    serde_json::to_string(&answer).expect("a response serializes")
+}
+
+/// A parsed admin response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// The command ran, with this output.
+    Done(Vec<String>),
+    /// The command was refused, for this reason.
+    Refused(String),
+}
+
+/// Parses the response line of a participant.
+pub fn parse_response(text: &str) -> Result<Response, String> {
+    let answer: Result<Answer, _> = serde_json::from_str(text);
+    match answer {
+        Ok(Answer {
+            ok: true,
+            output: Some(output),
+            error: None,
+        }) => Ok(Response::Done(output)),
+        Ok(Answer {
+            ok: false,
+            output: None,
+            error: Some(error),
+        }) => Ok(Response::Refused(error)),
+        _ => Err(format!("Unexpected response {}", text)),
+    }
+}
+
+/// The Unix domain socket a participant serves admin requests on; the socket file is removed
+/// when it is dropped.
+#[derive(Debug)]
+pub struct AdminSocket {
+    path: PathBuf,
+    listener: Option<UnixListener>,
+}
+
+impl AdminSocket {
+    /// Binds the socket at `path`, readable and writable by its owner only.
+    ///
+    /// A socket file left by a participant that stopped is replaced, but one that still
+    /// accepts connections, or a file of another type, is an error.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        Self::check(path)?;
+        if fs::symlink_metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        Ok(Self {
+            path: path.to_owned(),
+            listener: Some(listener),
+        })
+    }
+
+    /// Makes sure a socket could be bound at `path` as `AdminSocket::bind` does, without
+    /// binding it: the file is missing or left by a participant that stopped, in a directory
+    /// that exists.
+    pub fn check(path: &Path) -> io::Result<()> {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => fs::metadata(dir).map(drop),
+                _ => Ok(()),
+            };
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("\"{}\" exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("\"{}\" is served by another participant", path.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Spawns the thread accepting admin connections, each served on a thread of its own.
+    ///
+    /// The accepting thread, `gossip-admin`, stops at shutdown; the connection threads,
+    /// `gossip-admin-io`, wait on their clients and are left behind. A connection refused a
+    /// thread by the cap of `threads` is answered with an error and closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - The registry the threads are spawned through.
+    /// * `descriptors` - The budget the socket and each connection are counted in; they take
+    ///   a permit even when none is left, so the socket still serves a saturated participant.
+    /// * `dispatch` - Runs one command and returns its output, on the thread of the
+    ///   connection that sent it.
+    pub fn serve(
+        &mut self,
+        threads: &ThreadRegistry,
+        descriptors: &DescriptorBudget,
+        dispatch: impl Fn(Command) -> Result<Vec<String>, String> + Clone + Send + 'static,
+    ) -> Result<(), SpawnError> {
+        let Some(listener) = self.listener.take() else {
+            return Ok(());
+        };
+        listener.set_nonblocking(true).map_err(SpawnError::Os)?;
+        let clients = Arc::new(AtomicUsize::new(0));
+        let registry = threads.clone();
+        let descriptors = descriptors.clone();
+        threads.spawn("gossip-admin", Join::Wait, move |stop| {
+            let _listening = descriptors.acquire_unbounded();
+            while !stop.is_raised() {
+                let mut stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_POLL_INTERVAL);
+                        continue;
+                    }
+                    Err(_) => continue,
+                };
+                if stream.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                if clients.fetch_add(1, Ordering::SeqCst) >= MAX_ADMIN_CLIENTS {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    let refusal = Err(format!(
+                        "Too many admin connections, at most {} are served",
+                        MAX_ADMIN_CLIENTS
+                    ));
+                    let _ = writeln!(stream, "{}", format_response(&refusal));
+                    continue;
+                }
+                let permit = descriptors.acquire_unbounded();
+                let dispatch = dispatch.clone();
+                let served = clients.clone();
+                let Ok(mut refused) = stream.try_clone() else {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                };
+                let spawned = registry.spawn("gossip-admin-io", Join::Detach, move |_| {
+                    serve_client(stream, dispatch);
+                    served.fetch_sub(1, Ordering::SeqCst);
+                    drop(permit);
+                });
+                if let Err(err) = spawned {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    let refusal = Err(format!("Can not serve the connection: {}", err));
+                    let _ = writeln!(refused, "{}", format_response(&refusal));
+                }
+            }
+        })
+    }
+}
+
+impl Drop for AdminSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answers the requests of one admin connection until it closes.
+fn serve_client(stream: UnixStream, dispatch: impl Fn(Command) -> Result<Vec<String>, String>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader)
+            .take(MAX_REQUEST_BYTES + 1)
+            .read_line(&mut line);
+        let outcome = match read {
+            Ok(0) => return,
+            Ok(_) if !line.ends_with('\n') && line.len() as u64 > MAX_REQUEST_BYTES => {
+                let refusal = Err(format!(
+                    "Request longer than {} bytes, closing the connection",
+                    MAX_REQUEST_BYTES
+                ));
+                let _ = writeln!(writer, "{}", format_response(&refusal));
+                return;
+            }
+            Ok(_) if line.trim().is_empty() => continue,
+            Ok(_) => parse_request(line.trim()).and_then(&dispatch),
+            Err(err) => {
+                let refusal = Err(format!("Can not read the request: {}", err));
+                let _ = writeln!(writer, "{}", format_response(&refusal));
+                return;
+            }
+        };
+        if writeln!(writer, "{}", format_response(&outcome)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends one request to the participant serving `socket` and reads its response.
+pub fn send_request(socket: &Path, request: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(CTL_TIMEOUT))?;
+    writeln!(stream, "{}", request)?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    if response.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the participant closed the connection without answering",
+        ));
+    }
+    Ok(response.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::{addr, TempDir};
+
+    fn words(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn a_request_is_the_command_the_console_would_read() {
+        assert_eq!(parse_request(r#"{"cmd": "stats"}"#), Ok(Command::Stats));
+        assert_eq!(
+            parse_request(r#" { "cmd" : "mute", "secs": 60, "addr": "127.0.0.1:8081" } "#),
+            Ok(Command::Mute {
+                peer: addr(8081),
+                duration: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(
+            parse_request(r#"{"cmd":"mute","addr":"127.0.0.1:8081","secs":"10m"}"#),
+            Ok(Command::Mute {
+                peer: addr(8081),
+                duration: Duration::from_secs(600),
+            })
+        );
+        assert_eq!(
+            parse_request(r#"{"cmd":"mute","addr":"127.0.0.1:8081"}"#),
+            Ok(Command::Mute {
+                peer: addr(8081),
+                duration: DEFAULT_MUTE_DURATION,
+            })
+        );
+        assert_eq!(
+            parse_request(r#"{"cmd":"publish","topic":"chat","text":"hello \"world\"\n"}"#),
+            Ok(Command::Publish {
+                topic: "chat".to_owned(),
+                text: "hello \"world\"\n".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_request(r#"{"cmd":"publish","topic":"chat","text":"😀"}"#),
+            Ok(Command::Publish {
+                topic: "chat".to_owned(),
+                text: "\u{1f600}".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn a_field_never_spills_into_the_next() {
+        // Read as a console line, the space would have made `60` the duration.
+        assert_eq!(
+            parse_request(r#"{"cmd":"mute","addr":"127.0.0.1:8081 60"}"#),
+            Err("Invalid address \"127.0.0.1:8081 60\"".to_owned())
+        );
+        assert_eq!(
+            parse_request(r#"{"cmd":"publish","topic":"chat room","text":"hi"}"#),
+            Ok(Command::Publish {
+                topic: "chat room".to_owned(),
+                text: "hi".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_requests_are_explained() {
+        assert_eq!(
+            parse_request(r#"{"cmd": "peer"}"#),
+            Err("Malformed request: missing field `addr`".to_owned())
+        );
+        for request in [
+            "",
+            "stats",
+            r#"{}"#,
+            r#"{"cmd": 1}"#,
+            r#"{"cmd": "fly"}"#,
+            r#"{"cmd": "stats", "verbose": true}"#,
+            r#"{"cmd": "mute", "addr": true}"#,
+            r#"{"cmd": "mute", "secs": 60}"#,
+            r#"{"cmd": "mute", "addr": "127.0.0.1:8081", "secs": 1-2e+}"#,
+            r#"{"cmd": "stats"} extra"#,
+            r#"{"cmd": "stats""#,
+        ] {
+            let outcome = parse_request(request);
+            assert!(
+                matches!(&outcome, Err(err) if err.starts_with("Malformed request: ")),
+                "{:?}: {:?}",
+                request,
+                outcome
+            );
+        }
+        for (request, error) in [
+            (
+                r#"{"cmd": "probe", "addr": "127.0.0.1:8081", "count": 0}"#,
+                "Invalid probe count \"0\"",
+            ),
+            (
+                r#"{"cmd": "probe", "addr": "127.0.0.1:8081", "interval": 0}"#,
+                "\"0\" is not a positive duration",
+            ),
+            (
+                r#"{"cmd": "unsubscribe", "id": -1}"#,
+                "Invalid subscription \"-1\"",
+            ),
+            (
+                r#"{"cmd": "peers", "path": "peers.csv"}"#,
+                "Usage: peers export-csv <path>",
+            ),
+        ] {
+            assert_eq!(parse_request(request), Err(error.to_owned()), "{}", request);
+        }
+    }
+
+    #[test]
+    fn every_command_survives_a_request() {
+        for line in [
+            "peers",
+            "peers export-csv /tmp/peers.csv",
+            "peer 127.0.0.1:8080",
+            "provenance 127.0.0.1:8080",
+            "stats",
+            "report",
+            "reannounce",
+            "audit",
+            "export /tmp/snapshot",
+            "publish chat hello to everyone",
+            "publish-schema chat greeting 2 hello",
+            "estimate text hello there",
+            "estimate topic chat hello",
+            "limits",
+            "schedule 5s text hello",
+            "schedule 1500ms topic chat hello",
+            "scheduled",
+            "unschedule 3",
+            "request 127.0.0.1:8080 time now please",
+            "probe 127.0.0.1:8080",
+            "probe 127.0.0.1:8080 3 250ms",
+            "probe-status",
+            "mute 127.0.0.1:8080",
+            "mute 127.0.0.1:8080 90",
+            "unmute 127.0.0.1:8080",
+            "evict 127.0.0.1:8080",
+            "evict 127.0.0.1:8080 1h",
+            "unban 127.0.0.1:8080",
+            "bans",
+            "dials --recent",
+            "dials 127.0.0.1:8080",
+            "subscribe chat",
+            "subscribe-ordered chat",
+            "subscribe-schema chat greeting v1-v3",
+            "subscribe-unsupported chat",
+            "schema-support chat greeting",
+            "unsubscribe 2",
+            "subscriptions",
+            "inbox",
+            "inbox 5s",
+            "threads",
+            "channels",
+            "memory",
+            "reload",
+            "help",
+            "quit",
+        ] {
+            let command = parse_command(line).unwrap_or_else(|err| panic!("{}: {}", line, err));
+            let request = build_request(&words(line)).unwrap();
+            assert_eq!(
+                parse_request(&request),
+                Ok(command),
+                "{}: {}",
+                line,
+                request
+            );
+        }
+    }
+
+    #[test]
+    fn ctl_words_build_the_request_of_their_command() {
+        assert_eq!(
+            build_request(&words("mute 127.0.0.1:8081 60")),
+            Ok(r#"{"cmd":"mute","addr":"127.0.0.1:8081","secs":"60000ms"}"#.to_owned())
+        );
+        assert_eq!(build_request(&[]), Err("Empty command".to_owned()));
+        assert_eq!(
+            build_request(&words("fly away")),
+            Err("Unknown command \"fly away\"".to_owned())
+        );
+        assert_eq!(
+            build_request(&words("stats now")),
+            Err("Unknown command \"stats now\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        for outcome in [
+            Ok(vec![
+                "Muted \"127.0.0.1:8081\"".to_owned(),
+                "line 2".to_owned(),
+            ]),
+            Ok(Vec::new()),
+            Err("Usage: peer <addr>".to_owned()),
+        ] {
+            let expected = match &outcome {
+                Ok(output) => Response::Done(output.clone()),
+                Err(err) => Response::Refused(err.clone()),
+            };
+            assert_eq!(parse_response(&format_response(&outcome)), Ok(expected));
+        }
+        assert!(parse_response(r#"{"ok":true}"#).is_err());
+    }
+
+    #[test]
+    fn a_served_socket_answers_requests_and_refuses_a_second_participant() {
+        let dir = TempDir::new("admin-serve");
+        let path = dir.0.join("gossip.sock");
+        let threads = ThreadRegistry::new(8);
+        let descriptors = DescriptorBudget::new(1024, 64);
+        let mut socket = AdminSocket::bind(&path).unwrap();
+        assert_eq!(socket.path(), path);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        socket
+            .serve(&threads, &descriptors, |command| match command {
+                Command::Stats => Ok(vec!["all good".to_owned()]),
+                _ => Err("not here".to_owned()),
+            })
+            .unwrap();
+
+        let response = send_request(&path, r#"{"cmd":"stats"}"#).unwrap();
+        assert_eq!(
+            parse_response(&response),
+            Ok(Response::Done(vec!["all good".to_owned()]))
+        );
+        let response = send_request(&path, r#"{"cmd":"fly"}"#).unwrap();
+        assert_eq!(
+            parse_response(&response),
+            Ok(Response::Refused(
+                "Malformed request: unknown variant `fly`, expected one of `peers`, `peer`, \
+                 `provenance`, `stats`, `report`, `reannounce`, `audit`, `export`, `publish`, \
+                 `publish-schema`, `estimate`, `limits`, `schedule`, `scheduled`, `unschedule`, \
+                 `request`, `probe`, `probe-status`, `mute`, `unmute`, `evict`, `unban`, `bans`, \
+                 `dials`, `subscribe`, `subscribe-ordered`, `subscribe-schema`, \
+                 `subscribe-unsupported`, `schema-support`, `unsubscribe`, `subscriptions`, \
+                 `inbox`, `threads`, `channels`, `memory`, `reload`, `help`, `quit` at line 1 \
+                 column 12"
+                    .to_owned()
+            ))
+        );
+        let err = AdminSocket::check(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        threads.shutdown(Duration::from_secs(2));
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_stale_socket_is_replaced_but_no_other_file() {
+        let dir = TempDir::new("admin-stale");
+        let path = dir.0.join("gossip.sock");
+        assert!(AdminSocket::check(&path).is_ok());
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(AdminSocket::check(&path).is_ok());
+        let socket = AdminSocket::bind(&path).unwrap();
+        drop(socket);
+
+        fs::write(&path, "not a socket").unwrap();
+        let err = AdminSocket::check(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(AdminSocket::bind(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+
+        assert!(AdminSocket::check(&dir.0.join("missing").join("gossip.sock")).is_err());
+    }
+}
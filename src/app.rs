@@ -0,0 +1,689 @@
+//! The Command-Line Application.
+//!
+//! Everything the `gossip_p2p` binary does, behind its `main`: parses the arguments, builds
+//! the `NodeConfig` of the participant they describe, and runs it, checks it, or runs one of
+//! the `replay`, `analyze`, `ctl`, `probe`, `selftest` and `simulate` commands instead. Each way the application
+//! can fail is an `AppError`, mapped to the exit code documented by the binary.
+//!
+//! The module is public so the binary can reach it, and hidden from the documentation: it is
+//! not part of the library API.
+use crate::admin::{self, Response};
+use crate::cli::{
+    self, AnalyzeArguments, CliArguments, CliError, CtlArguments, Invocation, ProbeArguments,
+    ReplayArguments, SimulateArguments,
+};
+use crate::console;
+use crate::participant::config::validate::{validate, ConfigIssue};
+use crate::participant::config::NodeConfig;
+use crate::participant::error::ParticipantError;
+use crate::participant::history::analyze;
+use crate::participant::model::Participant;
+use crate::participant::period::GossipPeriod;
+use crate::participant::probe::{self, PROBE_TIMEOUT};
+use crate::participant::record::{RecordError, Recording};
+use crate::participant::replay::{replay, ReplayError, ReplayOptions};
+use crate::printer::{print_error, print_line};
+use crate::selftest;
+use crate::signal;
+use crate::simulate;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code of a clean shutdown, including Ctrl-C, and of a successful `--check`.
+const EXIT_OK: i32 = 0;
+/// Exit code for invalid arguments or configuration.
+const EXIT_CONFIG: i32 = 2;
+/// Exit code for a listen address that can not be bound.
+const EXIT_BIND: i32 = 3;
+/// Exit code for an unreachable bootstrap while `--require-bootstrap` is set, and for a join
+/// check that timed out.
+const EXIT_BOOTSTRAP: i32 = 4;
+/// Exit code for a local state file that can not be read or created.
+const EXIT_STATE_FILE: i32 = 5;
+/// Exit code for an admin socket that `ctl` or `probe` can not reach.
+const EXIT_ADMIN_UNREACHABLE: i32 = 6;
+/// Exit code for a `selftest` with a stage that did not pass.
+const EXIT_SELFTEST: i32 = 7;
+/// Exit code for a protocol anomaly while `--strict-protocol` is set.
+const EXIT_ANOMALY: i32 = 8;
+/// Exit code for a bootstrap target that did not present the identity it was expected to.
+const EXIT_UNVERIFIED: i32 = 9;
+/// Exit code for a `simulate` scenario with an assertion that did not hold, or an event that
+/// could not be taken.
+const EXIT_SIMULATE: i32 = 10;
+/// Exit code for a `replay` whose summary differs from the one of `--diff`.
+const EXIT_REPLAY_DIFF: i32 = 11;
+
+/// How often `probe` asks the participant whether its run of probes is done.
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Any error that ends the application.
+#[derive(Debug)]
+enum AppError {
+    Cli(CliError),
+    Config(Vec<ConfigIssue>),
+    Participant(ParticipantError),
+    Replay(ReplayError),
+    /// The summary of a replay has `differences` lines the one of `--diff` does not share.
+    ReplayDiffers {
+        differences: usize,
+    },
+    Analyze(RecordError),
+    /// The admin socket could not be reached, or its response read.
+    CtlUnreachable(io::Error),
+    /// The participant refused the command `ctl` or `probe` sent, or answered something else
+    /// than a response.
+    CtlRefused(String),
+    /// `failed` of the `stages` of the self test did not pass.
+    Selftest {
+        failed: usize,
+        stages: usize,
+    },
+    /// The scenario file can not be read, or does not hold a scenario that can run.
+    Scenario(String),
+    /// `failed` of the `assertions` of the scenario did not hold or were skipped, or an event
+    /// could not be taken.
+    Simulate {
+        failed: usize,
+        assertions: usize,
+    },
+}
+
+impl From<CliError> for AppError {
+    fn from(err: CliError) -> Self {
+        AppError::Cli(err)
+    }
+}
+
+impl From<ParticipantError> for AppError {
+    fn from(err: ParticipantError) -> Self {
+        AppError::Participant(err)
+    }
+}
+
+/// Maps an application error to the documented process exit code.
+fn exit_code(err: &AppError) -> i32 {
+    match err {
+        AppError::Cli(_) => EXIT_CONFIG,
+        AppError::Config(_) => EXIT_CONFIG,
+        AppError::Replay(_) => EXIT_CONFIG,
+        AppError::ReplayDiffers { .. } => EXIT_REPLAY_DIFF,
+        AppError::Analyze(_) => EXIT_CONFIG,
+        AppError::CtlUnreachable(_) => EXIT_ADMIN_UNREACHABLE,
+        AppError::CtlRefused(_) => EXIT_CONFIG,
+        AppError::Selftest { .. } => EXIT_SELFTEST,
+        AppError::Scenario(_) => EXIT_CONFIG,
+        AppError::Simulate { .. } => EXIT_SIMULATE,
+        AppError::Participant(ParticipantError::Resolve(_)) => EXIT_CONFIG,
+        AppError::Participant(ParticipantError::Bind(_)) => EXIT_BIND,
+        AppError::Participant(ParticipantError::BootstrapExhausted) => EXIT_BOOTSTRAP,
+        AppError::Participant(ParticipantError::JoinTimeout) => EXIT_BOOTSTRAP,
+        AppError::Participant(ParticipantError::StateFile(_)) => EXIT_STATE_FILE,
+        AppError::Participant(ParticipantError::Snapshot(_)) => EXIT_CONFIG,
+        AppError::Participant(ParticipantError::Filter(_)) => EXIT_CONFIG,
+        AppError::Participant(ParticipantError::AdminSocket(_)) => EXIT_BIND,
+        AppError::Participant(ParticipantError::Threads(_)) => EXIT_CONFIG,
+        AppError::Participant(ParticipantError::Anomaly(_)) => EXIT_ANOMALY,
+        AppError::Participant(ParticipantError::BootstrapUnverified(_)) => EXIT_UNVERIFIED,
+        AppError::Participant(ParticipantError::Config(_)) => EXIT_CONFIG,
+    }
+}
+
+/// Builds the participant configuration from the command-line arguments `args`, as parsed
+/// into `cli_args`.
+fn node_config(cli_args: CliArguments, args: &[String]) -> Result<NodeConfig, CliError> {
+    Ok(NodeConfig {
+        period: if cli_args.adaptive_period {
+            GossipPeriod::Adaptive {
+                base: cli_args.period,
+                min: cli_args.min_period,
+                max: cli_args.max_period,
+            }
+        } else {
+            GossipPeriod::Fixed(cli_args.period)
+        },
+        rounds: cli_args.rounds,
+        legacy_payload: cli_args.legacy_payload,
+        port: cli_args.port,
+        connect: cli_args.connect,
+        listen: cli_args.listen,
+        advertise: cli_args.advertise,
+        use_peer_cache: !cli_args.no_peer_cache,
+        network: cli_args.network,
+        half_open_threshold: cli_args
+            .half_open_threshold
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Half-open threshold is too large"))?,
+        period_skew_ratio: cli_args
+            .period_skew_ratio
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Period skew ratio is too large"))?,
+        max_clock_offset: cli_args.max_clock_offset,
+        balance_connections: cli_args.balance_connections.then_some(
+            u32::try_from(cli_args.min_outbound_share)
+                .ok()
+                .filter(|share| *share <= 100)
+                .ok_or(CliError::InvalidArgument(
+                    "Min outbound share must be a percentage, from 0 to 100",
+                ))?,
+        ),
+        require_bootstrap: cli_args.require_bootstrap,
+        join_check: cli_args.join_check,
+        expect_network_id: cli_args.expect_network_id,
+        expect_node_id: cli_args.expect_node_id,
+        bootstrap_verification: cli_args.bootstrap_verification,
+        strict_protocol: cli_args.strict_protocol,
+        validate_outbound: cli_args.validate_outbound,
+        hints: !cli_args.no_hints,
+        interactive: cli_args.interactive,
+        exit_on_eof: console::exit_on_eof(
+            cli_args.interactive_exit_on_eof,
+            console::stdin_is_terminal(),
+        ),
+        exec: cli_args.exec,
+        admin_socket: cli_args.admin_socket.map(PathBuf::from),
+        max_observers: cli_args
+            .max_observers
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Max observers is too large"))?,
+        dedup_entries: cli_args
+            .dedup_entries
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Dedup entries is too large"))?,
+        ratelimit_entries: cli_args
+            .ratelimit_entries
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Rate limit entries is too large"))?,
+        blocklist: cli_args.blocklist,
+        filter_file: cli_args.filter_file.map(PathBuf::from),
+        allow_privileged_ports: cli_args.allow_privileged_ports,
+        allow_special_ranges: cli_args.allow_special_ranges,
+        topics: cli_args.topics,
+        topic_broadcast: cli_args.topic_broadcast,
+        reorder_window: Duration::from_millis(cli_args.reorder_window_ms),
+        reorder_buffer: cli_args
+            .reorder_buffer
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Reorder buffer is too large"))?,
+        audit_every: cli_args.audit_every,
+        send_budget: Duration::from_millis(cli_args.per_peer_send_budget_ms),
+        batch_window: Duration::from_millis(cli_args.batch_window_ms),
+        late_tick_factor: cli_args
+            .late_tick_factor
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Late tick factor is too large"))?,
+        frozen_tick_factor: cli_args
+            .frozen_tick_factor
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Frozen tick factor is too large"))?,
+        connect_timeout: cli_args.connect_timeout,
+        dial_memory: cli_args.dial_memory,
+        heal_probe_interval: cli_args.heal_probe_interval,
+        resolve_interval: cli_args.resolve_interval,
+        warmup: cli_args.warmup,
+        busy_lag: cli_args.busy_lag,
+        overload_lag: cli_args.overload_lag,
+        trace_ticks: cli_args.trace_ticks,
+        inbox_capacity: cli_args
+            .inbox_capacity
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Inbox capacity is too large"))?,
+        max_scheduled: cli_args
+            .max_scheduled
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Max scheduled is too large"))?,
+        callback_workers: cli_args
+            .callback_workers
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Callback workers is too large"))?,
+        max_threads: cli_args
+            .max_threads
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Max threads is too large"))?,
+        fd_headroom: cli_args
+            .fd_headroom
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Fd headroom is too large"))?,
+        import_membership: cli_args.import_membership.map(PathBuf::from),
+        max_snapshot_age: cli_args.max_snapshot_age,
+        require_signed_import: cli_args.require_signed_import,
+        trusted_snapshot_key: cli_args.trusted_snapshot_key,
+        state_file: cli_args.state_file.map(PathBuf::from),
+        state_save_interval: cli_args.state_save_interval,
+        max_state_age: cli_args.max_state_age,
+        retention: [
+            cli_args.retain_sessions,
+            cli_args.retain_departed,
+            cli_args.retain_lists,
+        ],
+        identity_file: cli_args.identity_file.map(PathBuf::from),
+        persist_identity: true,
+        node_label: cli_args.node_label,
+        report_file: cli_args.report_file.map(PathBuf::from),
+        report_format: cli_args.report_format,
+        record: cli_args.record.map(PathBuf::from),
+        record_snapshot_every: cli_args.record_snapshot_every,
+        alerts: cli_args.alerts,
+        alert_cooldown: cli_args.alert_cooldown,
+        share_limit: cli_args
+            .share_limit
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Share limit is too large"))?,
+        corroboration: cli_args
+            .corroboration
+            .try_into()
+            .map_err(|_| CliError::InvalidArgument("Corroboration is too large"))?,
+        args: args.to_vec(),
+        seed: None,
+    })
+}
+
+/// Runs the cross-field checks of `config`, printing the warnings that do not stop it.
+fn check_consistency(config: &NodeConfig, strict: bool) -> Result<(), AppError> {
+    let (fatal, warnings): (Vec<ConfigIssue>, Vec<ConfigIssue>) = validate(config)
+        .into_iter()
+        .partition(|issue| issue.is_fatal(strict));
+    for warning in warnings {
+        print_error(&format!("Configuration {}", warning));
+    }
+    if fatal.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Config(fatal))
+    }
+}
+
+/// Reads a recording and replays its events on a sandboxed participant, configured as the
+/// recorded one was.
+fn run_replay(replay_args: ReplayArguments) -> Result<(), AppError> {
+    let recording = Recording::load(&PathBuf::from(replay_args.file))
+        .map_err(|err| AppError::Replay(ReplayError::Record(err)))?;
+    let options = ReplayOptions {
+        fast: replay_args.fast,
+        output: replay_args.output.map(PathBuf::from),
+        diff: replay_args.diff.map(PathBuf::from),
+    };
+    match replay(&recording, &options, config_of).map_err(AppError::Replay)? {
+        0 => Ok(()),
+        differences => Err(AppError::ReplayDiffers { differences }),
+    }
+}
+
+/// Reads a recording and prints the peer set it had at `--at` and the summary of its
+/// membership.
+fn run_analyze(analyze_args: AnalyzeArguments) -> Result<(), AppError> {
+    let recording =
+        Recording::load(&PathBuf::from(analyze_args.file)).map_err(AppError::Analyze)?;
+    analyze(&recording, analyze_args.at);
+    Ok(())
+}
+
+/// Sends a console command to a participant through its admin socket and prints the
+/// response, as JSON or, with `--pretty`, as the lines of its output.
+fn run_ctl(ctl_args: CtlArguments) -> Result<(), AppError> {
+    let request = admin::build_request(&ctl_args.command).map_err(AppError::CtlRefused)?;
+    let line = admin::send_request(&PathBuf::from(ctl_args.socket), &request)
+        .map_err(AppError::CtlUnreachable)?;
+    let response = admin::parse_response(&line).map_err(AppError::CtlRefused)?;
+    if !ctl_args.pretty {
+        print_line(&line);
+    }
+    match response {
+        Response::Done(output) => {
+            if ctl_args.pretty {
+                for line in output {
+                    print_line(&line);
+                }
+            }
+            Ok(())
+        }
+        Response::Refused(err) => Err(AppError::CtlRefused(err)),
+    }
+}
+
+/// Runs the console command of `words` on the participant serving `socket`.
+///
+/// # Returns
+///
+/// The output of the command.
+fn admin_command(socket: &Path, words: &[String]) -> Result<Vec<String>, AppError> {
+    let request = admin::build_request(words).map_err(AppError::CtlRefused)?;
+    let line = admin::send_request(socket, &request).map_err(AppError::CtlUnreachable)?;
+    match admin::parse_response(&line).map_err(AppError::CtlRefused)? {
+        Response::Done(output) => Ok(output),
+        Response::Refused(err) => Err(AppError::CtlRefused(err)),
+    }
+}
+
+/// Starts a run of probes on a participant through its admin socket, waits until it is done
+/// and prints its summary.
+///
+/// The participant runs the probes, so they measure the hop from it to its peer, and the
+/// summary is the one it prints itself: a run cut short by the peer disconnecting is summed up
+/// with the reason, its unanswered probes lost.
+fn run_probe(probe_args: ProbeArguments) -> Result<(), AppError> {
+    let socket = PathBuf::from(probe_args.socket);
+    let started = admin_command(
+        &socket,
+        &[
+            "probe".to_owned(),
+            probe_args.peer.to_string(),
+            probe_args.count.to_string(),
+            format!("{}ms", probe_args.interval.as_millis().max(1)),
+        ],
+    )?;
+    let line = started.first().cloned().unwrap_or_default();
+    let run = probe::run_of(&line).ok_or(AppError::CtlRefused(line.clone()))?;
+    print_line(&line);
+
+    let deadline = Instant::now()
+        + probe_args.interval.saturating_mul(probe_args.count)
+        + PROBE_TIMEOUT
+        + admin::CTL_TIMEOUT;
+    loop {
+        thread::sleep(PROBE_POLL_INTERVAL);
+        let status = admin_command(&socket, &["probe-status".to_owned()])?;
+        let line = status.first().map_or("", String::as_str);
+        if let Some(summary) = probe::done_summary(line, run) {
+            print_line(summary);
+            return Ok(());
+        }
+        if probe::run_of(line) != Some(run) {
+            return Err(AppError::CtlRefused(format!(
+                "probe run {} was replaced by another before it was done",
+                run
+            )));
+        }
+        if Instant::now() > deadline {
+            return Err(AppError::CtlUnreachable(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("probe run {} is not done in time", run),
+            )));
+        }
+    }
+}
+
+/// Builds the configuration of the participant `args` start, for the self test and the
+/// reloads.
+fn config_of(args: &[String]) -> Result<NodeConfig, String> {
+    match cli::parse_arguments(args) {
+        Ok(Invocation::Run(cli_args)) => {
+            node_config(*cli_args, args).map_err(|err| err.to_string())
+        }
+        Ok(_) => Err("the arguments do not start a participant".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+impl NodeConfig {
+    /// Builds the configuration a participant started with the command-line `args` runs with,
+    /// such as `["--period=1", "--port=8080"]`, for an embedder that has no `NodeConfig` of its
+    /// own to start from.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or the reason the arguments do not start a participant.
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<NodeConfig, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.as_ref().to_owned()).collect();
+        config_of(&args)
+    }
+}
+
+/// Runs the self test, each participant configured as if started with the arguments given by
+/// the test.
+fn run_selftest() -> Result<(), AppError> {
+    let (failed, stages) = selftest::run(config_of);
+    match failed {
+        0 => Ok(()),
+        failed => Err(AppError::Selftest { failed, stages }),
+    }
+}
+
+/// Runs the scenario file of the `simulate` command.
+fn run_simulate(simulate_args: SimulateArguments) -> Result<(), AppError> {
+    let outcome = simulate::run(&PathBuf::from(simulate_args.scenario), simulate_args.fast)
+        .map_err(AppError::Scenario)?;
+    if outcome.passed() {
+        return Ok(());
+    }
+    Err(AppError::Simulate {
+        failed: outcome.failures(),
+        assertions: outcome.verdicts.len(),
+    })
+}
+
+/// Parses the arguments and either prints the help or the version, replays or analyzes a
+/// recording, runs a `ctl` command or a `probe`, the self test or a simulation, checks the configuration
+/// or runs the participant.
+fn run(program_name: &str, args: &[String]) -> Result<(), AppError> {
+    let merged = cli::with_config_file(args)?;
+    let cli_args = match cli::parse_arguments(&merged)? {
+        Invocation::Run(cli_args) => *cli_args,
+        Invocation::Help => {
+            print_line(&cli::get_help_message(program_name));
+            return Ok(());
+        }
+        Invocation::Version => {
+            print_line(&cli::get_version_message());
+            return Ok(());
+        }
+        Invocation::Replay(replay_args) => return run_replay(replay_args),
+        Invocation::Analyze(analyze_args) => return run_analyze(analyze_args),
+        Invocation::Ctl(ctl_args) => return run_ctl(ctl_args),
+        Invocation::Probe(probe_args) => return run_probe(probe_args),
+        Invocation::Selftest => return run_selftest(),
+        Invocation::Simulate(simulate_args) => return run_simulate(simulate_args),
+    };
+    let check_only = cli_args.check;
+    let strict_config = cli_args.strict_config;
+    let reloadable = cli_args.config.is_some();
+    let config = node_config(cli_args, &merged)?;
+    check_consistency(&config, strict_config)?;
+
+    if check_only {
+        Participant::check(&config)?;
+        print_line("Configuration is valid");
+        return Ok(());
+    }
+
+    signal::install();
+    let mut participant = Participant::new(config)?;
+    // Answer requests on the echo topic with their payload, so peers can check that this
+    // participant responds, e.g. with the request console command.
+    participant
+        .on_request("echo", |_, payload| Ok(payload))
+        .expect("echo is a valid topic name");
+    if reloadable {
+        // Read the file again with the same command line, whose options keep winning.
+        let args = args.to_vec();
+        participant.on_reload(move || {
+            config_of(&cli::with_config_file(&args).map_err(|err| err.to_string())?)
+        });
+    }
+    participant.run()?;
+    Ok(())
+}
+
+/// Runs the application with the arguments of the process and exits with its exit code.
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    match run(&args[0], &args[1..]) {
+        Ok(()) => std::process::exit(EXIT_OK),
+        Err(err) => {
+            match &err {
+                AppError::Cli(err) => {
+                    print_error(&err.to_string());
+                    print_error(&cli::get_help_message(&args[0]));
+                }
+                AppError::Config(issues) => {
+                    print_error("Invalid configuration:");
+                    for issue in issues {
+                        print_error(&format!("\t{}", issue));
+                    }
+                }
+                AppError::Participant(ParticipantError::Anomaly(anomaly)) => {
+                    print_error(&format!("Stopped on a protocol anomaly: {}", anomaly))
+                }
+                AppError::Participant(err) => {
+                    print_error(&format!("Can not run the instance: {}", err))
+                }
+                AppError::Replay(err) => {
+                    print_error(&format!("Can not replay the recording: {}", err))
+                }
+                AppError::ReplayDiffers { differences } => print_error(&format!(
+                    "The replay differs from the earlier one by {} lines",
+                    differences
+                )),
+                AppError::Analyze(err) => {
+                    print_error(&format!("Can not analyze the recording: {}", err))
+                }
+                AppError::CtlUnreachable(err) => {
+                    print_error(&format!("Can not reach the admin socket: {}", err))
+                }
+                AppError::CtlRefused(err) => {
+                    print_error(&format!("The command was refused: {}", err))
+                }
+                AppError::Selftest { failed, stages } => print_error(&format!(
+                    "{} of {} selftest stages did not pass",
+                    failed, stages
+                )),
+                AppError::Scenario(err) => {
+                    print_error(&format!("Can not run the scenario: {}", err))
+                }
+                AppError::Simulate { failed, assertions } => print_error(&format!(
+                    "The scenario did not pass: {} of {} assertions did not hold",
+                    failed, assertions
+                )),
+            }
+            std::process::exit(exit_code(&err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::anomaly::{Anomaly, AnomalyKind};
+    use crate::participant::config::validate::Severity;
+    use crate::participant::filter::FilterError;
+    use crate::participant::snapshot::SnapshotError;
+    use crate::participant::test_support::addr;
+    use crate::participant::threads::SpawnError;
+    use crate::participant::verify::{Mismatch, VerificationFailure};
+
+    fn io_error() -> io::Error {
+        io::Error::from(io::ErrorKind::AddrInUse)
+    }
+
+    /// One error of each kind, with the exit code the binary documents for it.
+    fn every_error() -> Vec<(AppError, i32)> {
+        vec![
+            (AppError::Cli(CliError::MissingArguments), 2),
+            (
+                AppError::Cli(CliError::UnknownArgument("--x".to_owned())),
+                2,
+            ),
+            (AppError::Cli(CliError::InvalidArgument("bad")), 2),
+            (AppError::Cli(CliError::ConfigFile("missing".to_owned())), 2),
+            (
+                AppError::Config(vec![ConfigIssue {
+                    severity: Severity::Error,
+                    message: "bad".to_owned(),
+                }]),
+                2,
+            ),
+            (AppError::Replay(ReplayError::Sandbox("gone".to_owned())), 2),
+            (AppError::ReplayDiffers { differences: 3 }, 11),
+            (AppError::Analyze(RecordError::NotARecording), 2),
+            (AppError::CtlUnreachable(io_error()), 6),
+            (AppError::CtlRefused("unknown".to_owned()), 2),
+            (
+                AppError::Selftest {
+                    failed: 1,
+                    stages: 4,
+                },
+                7,
+            ),
+            (AppError::Scenario("malformed".to_owned()), 2),
+            (
+                AppError::Simulate {
+                    failed: 1,
+                    assertions: 2,
+                },
+                10,
+            ),
+            (ParticipantError::Resolve("nowhere".to_owned()).into(), 2),
+            (ParticipantError::Bind(io_error()).into(), 3),
+            (ParticipantError::BootstrapExhausted.into(), 4),
+            (ParticipantError::JoinTimeout.into(), 4),
+            (ParticipantError::StateFile(io_error()).into(), 5),
+            (
+                ParticipantError::Snapshot(SnapshotError::Malformed("x".to_owned())).into(),
+                2,
+            ),
+            (
+                ParticipantError::Filter(FilterError::Malformed(3)).into(),
+                2,
+            ),
+            (ParticipantError::AdminSocket(io_error()).into(), 3),
+            (
+                ParticipantError::Threads(SpawnError::Cap {
+                    cap: 1,
+                    live: "loop".to_owned(),
+                })
+                .into(),
+                2,
+            ),
+            (
+                ParticipantError::Anomaly(Anomaly {
+                    kind: AnomalyKind::Undecodable,
+                    peer: addr(8080),
+                    context: "a frame of 4 bytes".to_owned(),
+                })
+                .into(),
+                8,
+            ),
+            (
+                ParticipantError::BootstrapUnverified(VerificationFailure {
+                    addr: addr(8080),
+                    mismatch: Mismatch::Network {
+                        expected: "prod".to_owned(),
+                        presented: Some("test".to_owned()),
+                    },
+                })
+                .into(),
+                9,
+            ),
+            (ParticipantError::Config("no port".to_owned()).into(), 2),
+        ]
+    }
+
+    #[test]
+    fn every_error_maps_to_its_documented_exit_code() {
+        for (err, code) in every_error() {
+            assert_eq!(exit_code(&err), code, "{:?}", err);
+            assert_ne!(exit_code(&err), EXIT_OK, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn the_help_documents_every_exit_code() {
+        let help = cli::get_help_message("gossip_p2p");
+        let (_, documented) = help
+            .split_once("Exit codes:\n")
+            .expect("the help has an exit codes section");
+        for code in
+            std::iter::once(EXIT_OK).chain(every_error().iter().map(|(err, _)| exit_code(err)))
+        {
+            assert!(
+                documented.contains(&format!("\t{} - ", code)),
+                "exit code {} is not documented",
+                code
+            );
+        }
+    }
+}
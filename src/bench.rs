@@ -0,0 +1,345 @@
+//! Benchmark Bodies.
+//!
+//! The operations `benches/hot_paths.rs` measures, on fixtures built here since they reach
+//! into the private modules: the receivers and the participants list of a storage, the
+//! lookup of a participant by address, the encoding of every message, the formatting of long
+//! address lists and the broadcast of a message. Each body is run once by a test of this
+//! module as well, so `cargo test` keeps it working when the benchmarks are not run.
+//!
+//! Where a change removed a cost that grew with the peers, the way it was done before stays
+//! here too, `scan_contains` and `broadcast_per_receiver`, so the benchmarks show both.
+//!
+//! The module is public only for the benchmarks, hidden from the documentation, and not part
+//! of the API.
+
+use crate::participant::compactlist::PackedList;
+use crate::participant::message::{
+    AgeBucket, MembershipChange, Message, MessageKind, ObserverStats, SharedPeer,
+};
+use crate::participant::schema::{SchemaSupport, VersionRange};
+use crate::participant::storage::ParticipantsStorage;
+use crate::participant::transport::{
+    Connection, ListenerId, SendOutcome, Transport, TransportContext,
+};
+use crate::participant::utils::{
+    broadcast_message, format_addrs_capped, format_list_of_addrs, DEFAULT_ADDRS_CAP,
+};
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Instant;
+
+/// The peer counts the storage benchmarks run at.
+pub const PEER_COUNTS: [usize; 3] = [10, 1_000, 10_000];
+
+/// The length of the long participants list among the messages.
+pub const LONG_LIST: usize = 1_000;
+
+/// Returns `count` distinct public addresses, such as participants announce.
+pub fn addresses(count: usize) -> Vec<SocketAddr> {
+    (0..count)
+        .map(|index| {
+            let index = index as u32;
+            SocketAddr::from((Ipv4Addr::from(0x0a00_0000 | index), 9000))
+        })
+        .collect()
+}
+
+/// A storage holding connected participants, each known by its public address.
+pub struct StorageFixture {
+    storage: ParticipantsStorage<Connection>,
+    own: SocketAddr,
+    addrs: Vec<SocketAddr>,
+}
+
+impl StorageFixture {
+    /// Builds a storage of `peers` participants, each connected from a port of its own.
+    pub fn new(peers: usize) -> Self {
+        let now = Instant::now();
+        let mut storage = ParticipantsStorage::new();
+        let addrs = addresses(peers);
+        for (index, addr) in addrs.iter().enumerate() {
+            let from = SocketAddr::new(addr.ip(), 20_000 + (index % 40_000) as u16);
+            storage.add_unknown_participant(Connection::new(index as u64, from), *addr, now);
+        }
+        Self {
+            storage,
+            own: SocketAddr::from((Ipv4Addr::LOCALHOST, 8080)),
+            addrs,
+        }
+    }
+
+    /// The public addresses of the participants, in the order they were added.
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Takes the receivers of a broadcast, returning how many there are.
+    pub fn receivers(&self) -> usize {
+        self.storage.receivers().len()
+    }
+
+    /// Takes the participants list shared with a peer, returning its length.
+    pub fn participants_list(&self) -> usize {
+        self.storage.get_participants_list(self.own, None).len()
+    }
+
+    /// Looks `addr` up in the index by address.
+    pub fn contains(&self, addr: SocketAddr) -> bool {
+        self.storage.contains_addr(addr)
+    }
+
+    /// Looks `addr` up by comparing it with every participant, as the lookup did before the
+    /// index.
+    pub fn scan_contains(&self, addr: SocketAddr) -> bool {
+        self.storage
+            .receivers()
+            .iter()
+            .any(|receiver| receiver.public == addr)
+    }
+}
+
+/// Returns one message of every variant, in the order of `MessageKind::ALL`, for the
+/// benchmarks and the unit tests of the messages, kept out of `message` so the API does not
+/// carry it.
+pub fn one_of_each() -> Vec<Message> {
+    let a: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+    let b: SocketAddr = "[::1]:8081".parse().unwrap();
+    let stats = ObserverStats {
+        peers: 1,
+        sent: vec![0; MessageKind::COUNT],
+        received: vec![0; MessageKind::COUNT],
+    };
+    let peers = vec![SharedPeer {
+        addr: b,
+        age: AgeBucket::Fresh,
+    }];
+    vec![
+        Message::PublicAddress(a),
+        Message::PushParticipantsList,
+        Message::PullParticipantsList(vec![a, b]),
+        Message::Text("hello".to_owned()),
+        Message::ObserverHello,
+        Message::ObserverSnapshot {
+            peers: vec![a],
+            stats: stats.clone(),
+        },
+        Message::ObserverUpdate {
+            changes: vec![MembershipChange {
+                addr: a,
+                joined: true,
+                reason: "handshake".to_owned(),
+            }],
+            stats,
+        },
+        Message::ObserverRejected,
+        Message::PleaseIntroduce {
+            target: a,
+            my_addr: b,
+        },
+        Message::IntroductionRequest { from: a },
+        Message::Topic {
+            topic: "news".to_owned(),
+            payload: vec![1, 2],
+        },
+        Message::SharedPeers(peers.clone()),
+        Message::Request {
+            id: 1,
+            topic: "echo".to_owned(),
+            payload: vec![3],
+        },
+        Message::Response {
+            id: 1,
+            payload: Err("no handler".to_owned()),
+        },
+        Message::Batch(vec![Message::Text("batched".to_owned())]),
+        Message::RoundText {
+            round: 4,
+            text: "round".to_owned(),
+        },
+        Message::SequencedTopic {
+            topic: "news".to_owned(),
+            seq: 5,
+            payload: vec![4],
+        },
+        Message::Probe {
+            id: 6,
+            sent_at_offset: 10,
+        },
+        Message::ProbeReply {
+            id: 6,
+            echoed_at_offset: 20,
+        },
+        Message::InterestUpdate(Some(vec!["news".to_owned()])),
+        Message::ListRequest { id: 7 },
+        Message::ListReply {
+            id: 7,
+            peers: peers.clone(),
+        },
+        Message::ClockRequest { sent_at: 100 },
+        Message::ClockReply {
+            sent_at: 100,
+            received_at: 150,
+            replied_at: 160,
+        },
+        Message::VersionedTopic {
+            topic: "sensors".to_owned(),
+            seq: 8,
+            schema: "reading".to_owned(),
+            version: 2,
+            payload: vec![5],
+        },
+        Message::SchemaUpdate(vec![SchemaSupport {
+            topic: "sensors".to_owned(),
+            name: "reading".to_owned(),
+            range: VersionRange::new(1, 3).unwrap(),
+        }]),
+        Message::CompactListReply {
+            id: 7,
+            list: PackedList::pack(&peers),
+        },
+    ]
+}
+
+/// Returns one message of every variant, named by its kind, and a participants list of
+/// `LONG_LIST` addresses.
+pub fn messages() -> Vec<(String, Message)> {
+    let mut messages: Vec<(String, Message)> = one_of_each()
+        .into_iter()
+        .map(|message| (message.kind().name().to_owned(), message))
+        .collect();
+    messages.push((
+        format!("PullParticipantsList of {}", LONG_LIST),
+        Message::PullParticipantsList(addresses(LONG_LIST)),
+    ));
+    messages
+}
+
+/// Encodes `message` and decodes it back, returning the length of its frame.
+pub fn round_trip(message: &Message) -> usize {
+    let frame = message.encode();
+    let (decoded, _) = Message::decode(&frame).expect("an encoded message decodes");
+    debug_assert_eq!(decoded.kind(), message.kind());
+    frame.len()
+}
+
+/// Formats `addrs` as a log line does, returning the length of the line.
+pub fn format_addrs(addrs: &[SocketAddr]) -> usize {
+    format_addrs_capped(addrs, DEFAULT_ADDRS_CAP).len()
+}
+
+/// Formats every address of `addrs`, without the cap of the log lines, returning the length of
+/// the line.
+pub fn format_all_addrs(addrs: &[SocketAddr]) -> usize {
+    format_list_of_addrs(addrs).len()
+}
+
+/// A transport taking every frame sent and keeping nothing but their total size.
+#[derive(Debug, Default)]
+pub struct SinkTransport {
+    /// The bytes sent so far.
+    pub bytes: usize,
+}
+
+impl Transport for SinkTransport {
+    fn attach(&mut self, _: TransportContext) {}
+
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)> {
+        Ok((ListenerId::new(0), addr))
+    }
+
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)> {
+        Ok((Connection::new(0, addr), addr))
+    }
+
+    fn send(&mut self, _: Connection, frame: &[u8]) -> SendOutcome {
+        self.bytes += frame.len();
+        SendOutcome::Sent
+    }
+
+    fn close(&mut self, _: Connection) -> bool {
+        true
+    }
+
+    fn is_open(&self, _: Connection) -> bool {
+        true
+    }
+
+    fn admit(&mut self, _: Connection) -> bool {
+        true
+    }
+}
+
+/// Returns `count` connections to broadcast to.
+pub fn connections(count: usize) -> Vec<Connection> {
+    addresses(count)
+        .into_iter()
+        .enumerate()
+        .map(|(index, addr)| Connection::new(index as u64, addr))
+        .collect()
+}
+
+/// Broadcasts `message` to `receivers` as a participant does, encoding it once.
+pub fn broadcast(transport: &mut SinkTransport, receivers: &[Connection], message: &Message) {
+    broadcast_message(transport, receivers, message);
+}
+
+/// Broadcasts `message` to `receivers`, encoding it again for each of them as the broadcast
+/// did before it encoded once.
+pub fn broadcast_per_receiver(
+    transport: &mut SinkTransport,
+    receivers: &[Connection],
+    message: &Message,
+) {
+    for to in receivers {
+        transport.send(*to, &message.encode());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::message::MessageKind;
+
+    #[test]
+    fn the_storage_bodies_see_every_participant() {
+        let fixture = StorageFixture::new(PEER_COUNTS[0]);
+        assert_eq!(fixture.receivers(), 10);
+        assert_eq!(fixture.participants_list(), 11);
+        let missing = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        for addr in fixture.addrs() {
+            assert!(fixture.contains(*addr));
+            assert!(fixture.scan_contains(*addr));
+        }
+        assert!(!fixture.contains(missing));
+        assert!(!fixture.scan_contains(missing));
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        let messages = messages();
+        assert_eq!(messages.len(), MessageKind::COUNT + 1);
+        for (name, message) in &messages {
+            assert!(round_trip(message) > 0, "{}", name);
+        }
+        assert_eq!(messages[0].0, "PublicAddress");
+    }
+
+    #[test]
+    fn a_long_address_list_is_formatted_capped() {
+        let short = format_addrs(&addresses(DEFAULT_ADDRS_CAP));
+        let long = format_addrs(&addresses(10_000));
+        assert!(long < short + 32, "{} against {}", long, short);
+    }
+
+    #[test]
+    fn both_broadcasts_send_the_same_bytes() {
+        let receivers = connections(5);
+        let message = Message::Text("hello".to_owned());
+        let (mut once, mut each) = (SinkTransport::default(), SinkTransport::default());
+        broadcast(&mut once, &receivers, &message);
+        broadcast_per_receiver(&mut each, &receivers, &message);
+        assert_eq!(once.bytes, 5 * message.encode().len());
+        assert_eq!(once.bytes, each.bytes);
+    }
+}
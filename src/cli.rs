@@ -11,23 +11,32 @@
 //! ## Features
 //!
 //! - Parse command-line arguments for the application.
+//! - Describe every option once, in `OPTIONS`, for both the parser and the help message.
+//! - Parse the `replay` command, whose options are described in `REPLAY_OPTIONS`, the
+//!   `analyze` command, whose options are described in `ANALYZE_OPTIONS`, the `ctl`
+//!   command, whose options are described in `CTL_OPTIONS`, the `probe` command, whose options
+//!   are described in `PROBE_OPTIONS`, and the `simulate` command, whose options are described
+//!   in `SIMULATE_OPTIONS`.
 //! - Provide detailed help messages and usage examples.
 //! - Support for essential P2P settings: messaging period, connection port, and optional peer connection.
 //!
 //! ## Usage
 //!
 //! To use this module, include it in your main application file and call the `parse_arguments`
-//! function with the command-line arguments. This function will return a `CliArguments` struct
-//! which can be used to configure the P2P application.
+//! function with the command-line arguments. This function will return an `Invocation`, which
+//! either holds a `CliArguments` struct to configure the P2P application or asks for the help
+//! message or the version.
 //!
-//! ```
-//! use gossip_p2p::cli::{parse_arguments, CliArguments, get_help_message};
+//! ```ignore
+//! use gossip_p2p::cli::{get_help_message, get_version_message, parse_arguments, Invocation};
 //!
 //! let args: Vec<String> = std::env::args().collect();
 //! match parse_arguments(&args[1..]) {
-//!     Ok(cli_args) => {
+//!     Ok(Invocation::Run(cli_args)) => {
 //!         // Use cli_args to configure the application
 //!     },
+//!     Ok(Invocation::Help) => println!("{}", get_help_message(&args[0])),
+//!     Ok(Invocation::Version) => println!("{}", get_version_message()),
 //!     Err(e) => {
 //!         eprintln!("{}", e);
 //!         eprintln!("{}", get_help_message(&args[0]));
@@ -39,27 +48,1188 @@
 //! Ensure that you handle the `Result` returned by `parse_arguments` properly, displaying
 //! the help message and terminating the application in case of an error.
 
+use crate::console::{parse_script, Command};
+use crate::participant::alert::AlertRule;
+use crate::participant::config::ListenPort;
+use crate::participant::identity::NodeId;
+use crate::participant::join::JoinCheck;
+use crate::participant::message::PROTOCOL_VERSION;
+use crate::participant::report::ReportFormat;
+use crate::participant::retention::RetentionPolicy;
+use crate::participant::state::unhex;
+use crate::participant::topic::validate_topic;
+use crate::participant::verify::VerificationMode;
+
+use ed25519_dalek::VerifyingKey;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
 // Constants for the application's name and description.
 const APP_NAME: &str = "\t\t\t---{ GOSSIP P2P }---";
 const APP_DESCRIPTION: &str = "\t\tSimple p2p gossiping application in Rust.";
 
+/// The longest duration `parse_duration` accepts, 100 years, far from what an `Instant` can
+/// be moved by.
+pub const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
 /// Structure to hold command-line arguments.
 ///
 /// This structure represents the command-line arguments passed to the
-/// application. It includes the messaging period, the port for connections,
-/// and optionally, the address of a peer to connect to.
+/// application. It includes the messaging period, whether it adapts to the
+/// number of peers and its bounds, whether broadcasts follow synchronized rounds, whether their
+/// texts keep the legacy unstructured form, the port for connections,
+/// optionally, the address of a peer to connect to, the additional listen
+/// addresses with the addresses announced for them, whether the recent
+/// peers cache is used, how many unanswered messages make a connection
+/// look half-open, the ratio between the periods of two peers worth a warning, the clock
+/// offset of a peer worth a warning,
+/// whether inbound-only peers are dialed back and below which outbound share,
+/// whether a failed bootstrap is fatal, the join check probing the network, the node id the
+/// target must present and what a target presenting another one does,
+/// the configuration file whose options add to the command line, whether the
+/// configuration should only be checked, whether its warnings are fatal, whether protocol
+/// anomalies are, whether outbound frames are checked, whether operator hints are logged,
+/// whether the end of the standard
+/// input shuts the participant down, the console commands run at startup, the admin socket,
+/// and the entry budgets of the
+/// duplicate-suppression and rate-limiter maps, the blocked peer
+/// addresses and the filter file, whether learned addresses may use privileged ports and
+/// special ranges, the allowlist of accepted topics, the topics published to every peer
+/// whatever its interest, how long and for how
+/// many messages an ordered subscription waits for a missing publication, how often the storage
+/// is audited, how many addresses a shared participants list holds, how many
+/// peers must list an address before it is dialed,
+/// how long a send of a broadcast round may take, how late a broadcast tick may fire before
+/// it is reported and before it counts as a freeze, how long a dial may
+/// take to be established, how often departed peers are probed, how often a host name target
+/// is resolved again while isolated, the warm-up window spreading
+/// the announcement and first dials, how far the event loop may
+/// fall behind before the participant sheds load, which broadcast ticks are
+/// traced, how many messages an inbox holds, how many scheduled publications may be pending,
+/// how many threads run the subscription
+/// callbacks, how many internal threads may run at once, how many file descriptors are kept
+/// for other uses than connections, the
+/// membership snapshot to bootstrap from along with its oldest accepted age, the node state file with how often it is saved
+/// and its oldest accepted age, how much of the session histories, departed peers and peer
+/// lists is retained, the identity file keeping the node id or the label naming it,
+/// the file receiving the run report at shutdown with its format, and the file
+/// recording the inbound network events and the membership, with how often the participant
+/// notes its own state in it.
 pub struct CliArguments {
-    pub period: u64,
-    pub port: u16,
+    pub period: Duration,
+    pub adaptive_period: bool,
+    pub min_period: Duration,
+    pub max_period: Duration,
+    pub rounds: bool,
+    pub legacy_payload: bool,
+    pub port: ListenPort,
     pub connect: Option<String>,
+    pub listen: Vec<SocketAddr>,
+    pub advertise: Vec<SocketAddr>,
+    pub no_peer_cache: bool,
+    pub network: Option<String>,
+    pub half_open_threshold: u64,
+    pub period_skew_ratio: u64,
+    pub max_clock_offset: Duration,
+    pub balance_connections: bool,
+    pub min_outbound_share: u64,
+    pub require_bootstrap: bool,
+    pub join_check: Option<JoinCheck>,
+    pub expect_network_id: Option<String>,
+    pub expect_node_id: Option<NodeId>,
+    pub bootstrap_verification: VerificationMode,
+    pub config: Option<String>,
+    pub check: bool,
+    pub strict_config: bool,
+    pub strict_protocol: bool,
+    pub validate_outbound: bool,
+    pub no_hints: bool,
+    pub interactive: bool,
+    pub interactive_exit_on_eof: Option<bool>,
+    pub exec: Vec<Command>,
+    pub admin_socket: Option<String>,
+    pub max_observers: u64,
+    pub dedup_entries: u64,
+    pub ratelimit_entries: u64,
+    pub blocklist: Vec<SocketAddr>,
+    pub filter_file: Option<String>,
+    pub allow_privileged_ports: bool,
+    pub allow_special_ranges: bool,
+    pub topics: Option<Vec<String>>,
+    pub topic_broadcast: Vec<String>,
+    pub reorder_window_ms: u64,
+    pub reorder_buffer: u64,
+    pub audit_every: u64,
+    pub share_limit: u64,
+    pub corroboration: u64,
+    pub per_peer_send_budget_ms: u64,
+    pub batch_window_ms: u64,
+    pub late_tick_factor: u64,
+    pub frozen_tick_factor: u64,
+    pub connect_timeout: Duration,
+    pub dial_memory: Duration,
+    pub heal_probe_interval: Duration,
+    pub resolve_interval: Duration,
+    pub warmup: Option<Duration>,
+    pub busy_lag: Duration,
+    pub overload_lag: Duration,
+    pub trace_ticks: u64,
+    pub inbox_capacity: u64,
+    pub max_scheduled: u64,
+    pub callback_workers: u64,
+    pub max_threads: u64,
+    pub fd_headroom: u64,
+    pub import_membership: Option<String>,
+    pub max_snapshot_age: Duration,
+    pub require_signed_import: bool,
+    pub trusted_snapshot_key: Option<VerifyingKey>,
+    pub state_file: Option<String>,
+    pub state_save_interval: Option<Duration>,
+    pub max_state_age: Duration,
+    pub retain_sessions: RetentionPolicy,
+    pub retain_departed: RetentionPolicy,
+    pub retain_lists: RetentionPolicy,
+    pub identity_file: Option<String>,
+    pub node_label: Option<String>,
+    pub report_file: Option<String>,
+    pub report_format: ReportFormat,
+    pub record: Option<String>,
+    pub record_snapshot_every: Duration,
+    pub alerts: Vec<AlertRule>,
+    pub alert_cooldown: Duration,
+}
+
+/// The arguments of the `replay` command: the recording to replay, whether its original
+/// timing is ignored, and the files its summary is written to and compared with.
+pub struct ReplayArguments {
+    pub file: String,
+    pub fast: bool,
+    pub output: Option<String>,
+    pub diff: Option<String>,
+}
+
+/// The arguments of the `analyze` command: the recording to analyze and the time since its
+/// start at which its peer set is rebuilt, its end if not given.
+pub struct AnalyzeArguments {
+    pub file: String,
+    pub at: Option<Duration>,
+}
+
+/// The arguments of the `ctl` command: the admin socket of the participant, whether the
+/// response is rendered for humans, and the console command to run, as its words.
+pub struct CtlArguments {
+    pub socket: String,
+    pub pretty: bool,
+    pub command: Vec<String>,
+}
+
+/// The arguments of the `probe` command: the admin socket of the probing participant, the
+/// peer it probes, and how many probes it sends how far apart.
+pub struct ProbeArguments {
+    pub socket: String,
+    pub peer: SocketAddr,
+    pub count: u32,
+    pub interval: Duration,
+}
+
+/// The arguments of the `simulate` command: the scenario file to run and whether it runs
+/// faster than the real time.
+pub struct SimulateArguments {
+    pub scenario: String,
+    pub fast: bool,
 }
 
+/// Errors produced while parsing the command-line arguments.
+#[derive(Debug)]
+pub enum CliError {
+    /// Fewer arguments than the required ones were given.
+    MissingArguments,
+    /// An argument is not one of the supported options; holds the argument.
+    UnknownArgument(String),
+    /// An argument is absent or its value can not be used; holds the explanation.
+    InvalidArgument(&'static str),
+    /// The `--config` file can not be read; holds the explanation.
+    ConfigFile(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingArguments => write!(f, "Period and port are required"),
+            CliError::UnknownArgument(arg) => write!(f, "Unknown argument \"{}\"", arg),
+            CliError::InvalidArgument(reason) => write!(f, "{}", reason),
+            CliError::ConfigFile(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// What the command line asks the application to do.
+pub enum Invocation {
+    /// Run, or with `--check` only validate, a participant.
+    Run(Box<CliArguments>),
+    /// Replay a recording made with `--record`.
+    Replay(ReplayArguments),
+    /// Rebuild the peer set of a recording made with `--record` and sum its membership up.
+    Analyze(AnalyzeArguments),
+    /// Run a console command on a participant through its admin socket.
+    Ctl(CtlArguments),
+    /// Measure the round trip from a participant to one of its peers through its admin
+    /// socket.
+    Probe(ProbeArguments),
+    /// Run two participants against each other and report on each stage of their lifecycle.
+    Selftest,
+    /// Run the participants of a scenario file through its timeline and check its assertions.
+    Simulate(SimulateArguments),
+    /// Print the help message and exit.
+    Help,
+    /// Print the version and exit.
+    Version,
+}
+
+/// The kind of value a command-line option takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// A flag, given as `--name` without a value.
+    Flag,
+    /// A non-negative integer.
+    Number,
+    /// A duration, see `parse_duration`.
+    Duration,
+    /// A duration, optionally prefixed by `auto:` to make it adaptive.
+    Period,
+    /// A free-form text, such as a host and port.
+    Text,
+    /// A comma-separated list of socket addresses.
+    AddressList,
+    /// A comma-separated list of topic names.
+    TopicList,
+    /// Either `true` or `false`.
+    Boolean,
+    /// Console commands separated by `;`.
+    CommandList,
+    /// An `<event>:<action>` alert rule; unlike other options, every occurrence counts.
+    Alert,
+    /// A `<peers>,<duration>` join check.
+    JoinCheck,
+    /// An `<entries>,<age>[,<bytes>]` retention policy.
+    Retention,
+    /// A TCP port, `0` for one picked by the system.
+    Port,
+    /// Either `json`, `csv` or `both`.
+    ReportFormat,
+    /// A node id, as 32 hexadecimal digits.
+    NodeId,
+    /// An Ed25519 public key, as 64 hexadecimal digits.
+    Key,
+    /// Either `fail` or `warn`.
+    Verification,
+}
+
+/// Describes one command-line option.
+///
+/// `OPTIONS` lists every supported option. The parser only accepts the options of the table
+/// and the help message is generated from it, so the two can not disagree.
+pub struct OptionSpec {
+    /// The name of the option, without the leading `--`.
+    pub name: &'static str,
+    /// The kind of value the option takes.
+    pub value_kind: ValueKind,
+    /// The placeholder shown for the value in the usage line, empty for flags.
+    pub value_name: &'static str,
+    /// Whether the option must be given.
+    pub required: bool,
+    /// One line describing the option.
+    pub help: &'static str,
+    /// The value used when the option is not given, parsed like a given value.
+    pub default: Option<&'static str>,
+    /// The error reported when the value can not be used.
+    pub invalid: &'static str,
+}
+
+/// Every supported command-line option, in the order of the help message.
+pub const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "period",
+        value_kind: ValueKind::Period,
+        value_name: "[auto:]<duration>",
+        required: true,
+        help: "messaging period: bare seconds or a number with ms, s, m or h, e.g. 250ms, 1.5s, 15m; auto:<base> grows it with log2 of the peer count",
+        default: None,
+        invalid: "Period is required and must be a positive duration, such as 5, 250ms, 15m or auto:1s",
+    },
+    OptionSpec {
+        name: "min-period",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "shortest messaging period of an auto period",
+        default: Some("500ms"),
+        invalid: "Min period must be a positive duration, such as 500ms or 1s",
+    },
+    OptionSpec {
+        name: "max-period",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "longest messaging period of an auto period",
+        default: Some("60s"),
+        invalid: "Max period must be a positive duration, such as 30s or 5m",
+    },
+    OptionSpec {
+        name: "rounds",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "number broadcasts in rounds counted from the Unix epoch and align them with the rounds of the peers",
+        default: None,
+        invalid: "Rounds does not take a value",
+    },
+    OptionSpec {
+        name: "legacy-payload",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "broadcast the unstructured \"random message <n>\" texts instead of \"msg tick=<n> from=<node> rnd=<n>\"",
+        default: None,
+        invalid: "Legacy-payload does not take a value",
+    },
+    OptionSpec {
+        name: "port",
+        value_kind: ValueKind::Port,
+        value_name: "<port>",
+        required: true,
+        help: "connection port, 0 for one picked by the system",
+        default: None,
+        invalid: "Port is required and must be a number from 0 to 65535",
+    },
+    OptionSpec {
+        name: "connect",
+        value_kind: ValueKind::Text,
+        value_name: "<peer_address_with_port>",
+        required: false,
+        help: "address of the peer",
+        default: None,
+        invalid: "Connect must be followed by an address",
+    },
+    OptionSpec {
+        name: "listen",
+        value_kind: ValueKind::AddressList,
+        value_name: "<address>[,<address>...]",
+        required: false,
+        help: "comma-separated addresses listened on besides 127.0.0.1:<port>, each sharing only its own peers",
+        default: None,
+        invalid: "Listen must contain comma-separated socket addresses",
+    },
+    OptionSpec {
+        name: "advertise",
+        value_kind: ValueKind::AddressList,
+        value_name: "<address>[,<address>...]",
+        required: false,
+        help: "addresses announced to the peers of each listen address, in the same order; defaults to the listen address",
+        default: None,
+        invalid: "Advertise must contain comma-separated socket addresses",
+    },
+    OptionSpec {
+        name: "no-peer-cache",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "do not read or write the recent peers cache",
+        default: None,
+        invalid: "No-peer-cache does not take a value",
+    },
+    OptionSpec {
+        name: "network",
+        value_kind: ValueKind::Text,
+        value_name: "<name>",
+        required: false,
+        help: "name of the network, which keeps its recent peers cache apart from those of other networks on the same port",
+        default: None,
+        invalid: "Network must be a name",
+    },
+    OptionSpec {
+        name: "half-open-threshold",
+        value_kind: ValueKind::Number,
+        value_name: "<messages>",
+        required: false,
+        help: "unanswered messages before a peer is re-dialed, 0 disables",
+        default: Some("10"),
+        invalid: "Half-open threshold must be a non-negative number",
+    },
+    OptionSpec {
+        name: "period-skew-ratio",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "ratio between a peer's declared period and ours worth a warning, 0 disables",
+        default: Some("20"),
+        invalid: "Period skew ratio must be a non-negative number",
+    },
+    OptionSpec {
+        name: "max-clock-offset",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "estimated offset of a peer's clock from ours worth a warning",
+        default: Some("5s"),
+        invalid: "Max clock offset must be a positive duration, such as 5s or 500ms",
+    },
+    OptionSpec {
+        name: "balance-connections",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "dial inbound-only peers back while too few connections are outbound",
+        default: None,
+        invalid: "Balance-connections does not take a value",
+    },
+    OptionSpec {
+        name: "min-outbound-share",
+        value_kind: ValueKind::Number,
+        value_name: "<percent>",
+        required: false,
+        help: "outbound share of the connections that --balance-connections keeps",
+        default: Some("25"),
+        invalid: "Min outbound share must be a percentage, from 0 to 100",
+    },
+    OptionSpec {
+        name: "require-bootstrap",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "exit instead of running isolated when connect never succeeds",
+        default: None,
+        invalid: "Require-bootstrap does not take a value",
+    },
+    OptionSpec {
+        name: "join-check",
+        value_kind: ValueKind::JoinCheck,
+        value_name: "<k>,<timeout>",
+        required: false,
+        help: "only join the network: print a JSON result and exit once k peers completed their handshake, or exit 4 after the timeout",
+        default: None,
+        invalid: "Join check must be a positive number of peers and a timeout, such as 2,10s",
+    },
+    OptionSpec {
+        name: "expect-network-id",
+        value_kind: ValueKind::Text,
+        value_name: "<name>",
+        required: false,
+        help: "network the connect target must announce with --network before its participants list is trusted",
+        default: None,
+        invalid: "Expect network id must be a name",
+    },
+    OptionSpec {
+        name: "expect-node-id",
+        value_kind: ValueKind::NodeId,
+        value_name: "<hex>",
+        required: false,
+        help: "node id the connect target must present before its participants list is trusted",
+        default: None,
+        invalid: "Expect node id must be followed by a node id of 32 hexadecimal digits",
+    },
+    OptionSpec {
+        name: "bootstrap-verification",
+        value_kind: ValueKind::Verification,
+        value_name: "<fail|warn>",
+        required: false,
+        help: "what a connect target presenting another node id does: fail, dropping it and exiting 9 if no other candidate connects, or warn, trusting it anyway",
+        default: Some("fail"),
+        invalid: "Bootstrap verification must be fail or warn",
+    },
+    OptionSpec {
+        name: "config",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file of further options, one per line such as share-limit=8, read again on SIGHUP or reload",
+        default: None,
+        invalid: "Config must be followed by a path",
+    },
+    OptionSpec {
+        name: "check",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "validate the configuration and exit without starting the peer",
+        default: None,
+        invalid: "Check does not take a value",
+    },
+    OptionSpec {
+        name: "strict-config",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "refuse to start on configuration warnings, not only on errors",
+        default: None,
+        invalid: "Strict-config does not take a value",
+    },
+    OptionSpec {
+        name: "strict-protocol",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "stop with exit code 8 at the first protocol anomaly a peer causes, for fuzzing and CI",
+        default: None,
+        invalid: "Strict-protocol does not take a value",
+    },
+    OptionSpec {
+        name: "validate-outbound",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "check every frame before sending it and never send an invalid one, as debug builds always do",
+        default: None,
+        invalid: "Validate-outbound does not take a value",
+    },
+    OptionSpec {
+        name: "no-hints",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "do not log the hints about common misconfigurations, such as a firewalled peer",
+        default: None,
+        invalid: "No-hints does not take a value",
+    },
+    OptionSpec {
+        name: "interactive",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "read console commands from stdin; type help to list them",
+        default: None,
+        invalid: "Interactive does not take a value",
+    },
+    OptionSpec {
+        name: "interactive-exit-on-eof",
+        value_kind: ValueKind::Boolean,
+        value_name: "<true|false>",
+        required: false,
+        help: "shut down like quit when stdin closes; true by default when stdin is not a terminal",
+        default: None,
+        invalid: "Interactive exit on EOF must be true or false",
+    },
+    OptionSpec {
+        name: "exec",
+        value_kind: ValueKind::CommandList,
+        value_name: "<command>[;<command>...]",
+        required: false,
+        help: "console commands run at startup, separated by semicolons, e.g. \"peers; quit\"",
+        default: None,
+        invalid: "Exec must hold console commands separated by semicolons; type help in the console to list them",
+    },
+    OptionSpec {
+        name: "admin-socket",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "Unix domain socket serving the console commands as JSON, to the ctl command and scripts",
+        default: None,
+        invalid: "Admin socket must be followed by a path",
+    },
+    OptionSpec {
+        name: "max-observers",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "observers watching the network through this peer",
+        default: Some("4"),
+        invalid: "Max observers must be a non-negative number",
+    },
+    OptionSpec {
+        name: "dedup-entries",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "recently dialed addresses remembered to skip duplicate dials",
+        default: Some("1024"),
+        invalid: "Dedup entries must be a non-negative number",
+    },
+    OptionSpec {
+        name: "ratelimit-entries",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "peers whose participants list requests are rate limited at once",
+        default: Some("1024"),
+        invalid: "Rate limit entries must be a non-negative number",
+    },
+    OptionSpec {
+        name: "block",
+        value_kind: ValueKind::AddressList,
+        value_name: "<address>[,<address>...]",
+        required: false,
+        help: "comma-separated peer addresses whose inbound connections are refused, never dialed nor introduced",
+        default: None,
+        invalid: "Block list must contain comma-separated socket addresses",
+    },
+    OptionSpec {
+        name: "filter-file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file of allow and block rules for peer addresses and networks, reloaded when it changes",
+        default: None,
+        invalid: "Filter file must be followed by a path",
+    },
+    OptionSpec {
+        name: "allow-privileged-ports",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "dial the addresses learned from peers even on ports below 1024",
+        default: None,
+        invalid: "Allow-privileged-ports does not take a value",
+    },
+    OptionSpec {
+        name: "allow-special-ranges",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "dial the addresses learned from peers even in reserved, link-local, documentation or multicast ranges",
+        default: None,
+        invalid: "Allow-special-ranges does not take a value",
+    },
+    OptionSpec {
+        name: "topics",
+        value_kind: ValueKind::TopicList,
+        value_name: "<topic>[,<topic>...]",
+        required: false,
+        help: "comma-separated topics accepted from peers; messages on other topics are dropped",
+        default: None,
+        invalid: "Topics must be comma-separated names of 1 to 64 bytes without control characters",
+    },
+    OptionSpec {
+        name: "topic-broadcast",
+        value_kind: ValueKind::TopicList,
+        value_name: "<topic>[,<topic>...]",
+        required: false,
+        help: "comma-separated topics published to every peer, even those that did not ask for them",
+        default: None,
+        invalid: "Topic broadcast must list comma-separated names of 1 to 64 bytes without control characters",
+    },
+    OptionSpec {
+        name: "reorder-window-ms",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "milliseconds an ordered subscription waits for a missing message before reporting a gap",
+        default: Some("500"),
+        invalid: "Reorder window must be a number of milliseconds",
+    },
+    OptionSpec {
+        name: "reorder-buffer",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "messages an ordered subscription holds per publisher and topic behind a missing one",
+        default: Some("64"),
+        invalid: "Reorder buffer must be a positive number of messages",
+    },
+    OptionSpec {
+        name: "audit-every",
+        value_kind: ValueKind::Number,
+        value_name: "<ticks>",
+        required: false,
+        help: "maintenance ticks (one per second) between storage consistency audits, 0 disables",
+        default: Some("60"),
+        invalid: "Audit interval must be a non-negative number of ticks",
+    },
+    OptionSpec {
+        name: "share-limit",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "addresses in a shared participants list, most recently verified first",
+        default: Some("100"),
+        invalid: "Share limit must be a positive number",
+    },
+    OptionSpec {
+        name: "corroboration",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "distinct peers that must list an address before it is dialed",
+        default: Some("1"),
+        invalid: "Corroboration must be a positive number",
+    },
+    OptionSpec {
+        name: "per-peer-send-budget-ms",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "milliseconds a broadcast send may take before the peer skips a round",
+        default: Some("50"),
+        invalid: "Per-peer send budget must be a positive number of milliseconds",
+    },
+    OptionSpec {
+        name: "batch-window-ms",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "milliseconds messages to the same peer wait to share a frame, 0 to send each at once",
+        default: Some("20"),
+        invalid: "Batch window must be a number of milliseconds",
+    },
+    OptionSpec {
+        name: "late-tick-factor",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "times the period after which a late broadcast tick is logged as a scheduling delay",
+        default: Some("2"),
+        invalid: "Late tick factor must be a positive number",
+    },
+    OptionSpec {
+        name: "frozen-tick-factor",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "times the period after which a late tick counts as a freeze, such as a resume from sleep, and the peers are resynchronized",
+        default: Some("10"),
+        invalid: "Frozen tick factor must be a positive number",
+    },
+    OptionSpec {
+        name: "connect-timeout",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time a dialed peer may take to accept the connection before it is given up",
+        default: Some("10s"),
+        invalid: "Connect timeout must be a positive duration, such as 10s or 1500ms",
+    },
+    OptionSpec {
+        name: "dial-memory",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time a dialed address is not dialed again when another participants list shows it",
+        default: Some("30s"),
+        invalid: "Dial memory must be a positive duration, such as 30s or 2m",
+    },
+    OptionSpec {
+        name: "heal-probe-interval",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time between two rounds of dials to remembered peers that departed, to heal partitions",
+        default: Some("5m"),
+        invalid: "Heal probe interval must be a positive duration, such as 30s or 5m",
+    },
+    OptionSpec {
+        name: "resolve-interval",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "shortest time between two resolutions of a --connect host name while no peer is connected",
+        default: Some("30s"),
+        invalid: "Resolve interval must be a positive duration, such as 30s or 2m",
+    },
+    OptionSpec {
+        name: "warmup",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "window over which a node started along with many others spreads its announcement, at a random time in it, and its dials, one per second",
+        default: None,
+        invalid: "Warmup must be a positive duration, such as 2s or 1m",
+    },
+    OptionSpec {
+        name: "busy-lag",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "event loop lag from which audits, heal probes and split-brain bridging are skipped",
+        default: Some("250ms"),
+        invalid: "Busy lag must be a positive duration, such as 250ms or 1s",
+    },
+    OptionSpec {
+        name: "overload-lag",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "event loop lag from which received application payloads are dropped",
+        default: Some("2s"),
+        invalid: "Overload lag must be a positive duration, such as 2s or 1500ms",
+    },
+    OptionSpec {
+        name: "trace-ticks",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "trace every nth broadcast tick, logging where its time went, 0 disables",
+        default: Some("0"),
+        invalid: "Trace ticks must be a non-negative number of ticks",
+    },
+    OptionSpec {
+        name: "inbox-capacity",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "messages an inbox holds before dropping the oldest, per inbox",
+        default: Some("1024"),
+        invalid: "Inbox capacity must be a positive number of messages",
+    },
+    OptionSpec {
+        name: "max-scheduled",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "publications scheduled for later that may be pending at once",
+        default: Some("256"),
+        invalid: "Max scheduled must be a positive number of publications",
+    },
+    OptionSpec {
+        name: "callback-workers",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "threads running the topic subscription callbacks",
+        default: Some("2"),
+        invalid: "Callback workers must be a positive number of threads",
+    },
+    OptionSpec {
+        name: "max-threads",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "internal threads that may run at once, console, admin connections and workers included",
+        default: Some("32"),
+        invalid: "Max threads must be a positive number of threads",
+    },
+    OptionSpec {
+        name: "fd-headroom",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "file descriptors of the soft limit kept for files and pipes, the rest bounding the connections",
+        default: Some("64"),
+        invalid: "Fd headroom must be a non-negative number of file descriptors",
+    },
+    OptionSpec {
+        name: "import-membership",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "membership snapshot written by the export console command whose peers are dialed at startup",
+        default: None,
+        invalid: "Import membership must be followed by a path",
+    },
+    OptionSpec {
+        name: "max-snapshot-age",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "oldest membership snapshot accepted by import-membership",
+        default: Some("24h"),
+        invalid: "Max snapshot age must be a positive duration, such as 30m or 24h",
+    },
+    OptionSpec {
+        name: "require-signed-import",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "reject a membership snapshot given with import-membership that its exporter did not sign",
+        default: None,
+        invalid: "Require-signed-import does not take a value",
+    },
+    OptionSpec {
+        name: "trusted-snapshot-key",
+        value_kind: ValueKind::Key,
+        value_name: "<hex>",
+        required: false,
+        help: "public key whose signature import-membership accepts besides the key of this participant",
+        default: None,
+        invalid: "Trusted snapshot key must be followed by an Ed25519 public key of 64 hexadecimal digits",
+    },
+    OptionSpec {
+        name: "state-file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file keeping the peers and topic sequence numbers of this participant across restarts, saved on shutdown",
+        default: None,
+        invalid: "State file must be followed by a path",
+    },
+    OptionSpec {
+        name: "state-save-interval",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "also save the state file this often, not only on shutdown",
+        default: None,
+        invalid: "State save interval must be a positive duration, such as 30s or 5m",
+    },
+    OptionSpec {
+        name: "max-state-age",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "oldest state file, and oldest peer verification in it, accepted at startup",
+        default: Some("1h"),
+        invalid: "Max state age must be a positive duration, such as 30m or 1h",
+    },
+    OptionSpec {
+        name: "retain-sessions",
+        value_kind: ValueKind::Retention,
+        value_name: "<entries>,<age>[,<bytes>]",
+        required: false,
+        help: "session histories kept, each aging from its departure",
+        default: Some("4096,1h,4194304"),
+        invalid: "Retain sessions must be a number of entries, an age and optionally a byte count, such as 4096,1h,4194304",
+    },
+    OptionSpec {
+        name: "retain-departed",
+        value_kind: ValueKind::Retention,
+        value_name: "<entries>,<age>[,<bytes>]",
+        required: false,
+        help: "departed peers remembered for healing probes and the state file, each aging from when it was last seen",
+        default: Some("256,168h,1048576"),
+        invalid: "Retain departed must be a number of entries, an age and optionally a byte count, such as 256,168h",
+    },
+    OptionSpec {
+        name: "retain-lists",
+        value_kind: ValueKind::Retention,
+        value_name: "<entries>,<age>[,<bytes>]",
+        required: false,
+        help: "participants lists of the peers kept for the split-brain detection, each aging from its arrival",
+        default: Some("1024,24h,16777216"),
+        invalid: "Retain lists must be a number of entries, an age and optionally a byte count, such as 1024,24h",
+    },
+    OptionSpec {
+        name: "identity-file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file keeping the persistent node id, created at the first start; defaults to one in the data directory named after the label or the port",
+        default: None,
+        invalid: "Identity file must be followed by a path",
+    },
+    OptionSpec {
+        name: "node-label",
+        value_kind: ValueKind::Text,
+        value_name: "<label>",
+        required: false,
+        help: "name of the default identity file, so a node on an ephemeral port keeps its node id too",
+        default: None,
+        invalid: "Node label must be followed by a label",
+    },
+    OptionSpec {
+        name: "report-file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file receiving a JSON report of the run, with the final peers and counters, at shutdown",
+        default: None,
+        invalid: "Report file must be followed by a path",
+    },
+    OptionSpec {
+        name: "report-format",
+        value_kind: ValueKind::ReportFormat,
+        value_name: "<json|csv|both>",
+        required: false,
+        help: "what the report file receives: the JSON report, the CSV table of the peers, or both, the table in the same name with the csv extension",
+        default: Some("json"),
+        invalid: "Report format must be json, csv or both",
+    },
+    OptionSpec {
+        name: "record",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file receiving every inbound network event, to be replayed with the replay command",
+        default: None,
+        invalid: "Record must be followed by a path",
+    },
+    OptionSpec {
+        name: "record-snapshot-every",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time between two notes of the connected peer count and the period in the --record file",
+        default: Some("60s"),
+        invalid: "Record snapshot every must be a positive duration, such as 60s or 5m",
+    },
+    OptionSpec {
+        name: "alert",
+        value_kind: ValueKind::Alert,
+        value_name: "<event>:<action>",
+        required: false,
+        help: "run exec:<command> or post to http:<url> on isolated, peer-evicted, bootstrap-failed or conflict-detected; may be repeated",
+        default: None,
+        invalid: "Alert must be <event>:exec:<command> or <event>:http:http://<host>:<port>/<path>, with an event of isolated, peer-evicted, bootstrap-failed or conflict-detected",
+    },
+    OptionSpec {
+        name: "alert-cooldown",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "shortest time between two alerts of the same event",
+        default: Some("60s"),
+        invalid: "Alert cooldown must be a positive duration, such as 60s or 5m",
+    },
+    OptionSpec {
+        name: "help",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "print this message and exit",
+        default: None,
+        invalid: "Help does not take a value",
+    },
+    OptionSpec {
+        name: "version",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "print the application and protocol versions and exit",
+        default: None,
+        invalid: "Version does not take a value",
+    },
+];
+
+/// Every option of the `replay` command, in the order of the help message.
+pub const REPLAY_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: true,
+        help: "recording written by a participant started with --record",
+        default: None,
+        invalid: "File is required and must be followed by a path",
+    },
+    OptionSpec {
+        name: "fast",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "replay the events one after the other instead of with their original timing",
+        default: None,
+        invalid: "Fast does not take a value",
+    },
+    OptionSpec {
+        name: "output",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "file the summary of the replay is written to, to compare a later replay with",
+        default: None,
+        invalid: "Output must be followed by a path",
+    },
+    OptionSpec {
+        name: "diff",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: false,
+        help: "summary written by an earlier replay with --output, printing the lines that differ",
+        default: None,
+        invalid: "Diff must be followed by a path",
+    },
+];
+
+/// Every option of the `analyze` command, in the order of the help message.
+pub const ANALYZE_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "file",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: true,
+        help: "recording written by a participant started with --record",
+        default: None,
+        invalid: "File is required and must be followed by a path",
+    },
+    OptionSpec {
+        name: "at",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time since the start of the recording at which the peer set is shown, its end by default",
+        default: None,
+        invalid: "At must be a positive duration, such as 90s or 14m",
+    },
+];
+
+/// Every option of the `ctl` command, in the order of the help message; the console command
+/// follows them.
+pub const CTL_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "socket",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: true,
+        help: "admin socket of the participant, as given to its --admin-socket",
+        default: None,
+        invalid: "Socket is required and must be followed by a path",
+    },
+    OptionSpec {
+        name: "pretty",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "print the output of the command as text instead of the JSON response",
+        default: None,
+        invalid: "Pretty does not take a value",
+    },
+];
+
+/// Every option of the `probe` command, in the order of the help message; the admin socket
+/// and the probed peer come before them.
+pub const PROBE_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "count",
+        value_kind: ValueKind::Number,
+        value_name: "<n>",
+        required: false,
+        help: "number of probes sent to the peer",
+        default: Some("10"),
+        invalid: "Count must be a positive number of probes",
+    },
+    OptionSpec {
+        name: "interval",
+        value_kind: ValueKind::Duration,
+        value_name: "<duration>",
+        required: false,
+        help: "time between two probes",
+        default: Some("100ms"),
+        invalid: "Interval must be a positive duration, such as 50ms or 1s",
+    },
+];
+
+/// Every option of the `simulate` command, in the order of the help message.
+pub const SIMULATE_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "scenario",
+        value_kind: ValueKind::Text,
+        value_name: "<path>",
+        required: true,
+        help: "JSON file describing the nodes, the timeline and the assertions of the simulation",
+        default: None,
+        invalid: "Scenario is required and must be followed by a path",
+    },
+    OptionSpec {
+        name: "fast",
+        value_kind: ValueKind::Flag,
+        value_name: "",
+        required: false,
+        help: "run the simulation ten times faster than the real time",
+        default: None,
+        invalid: "Fast does not take a value",
+    },
+];
+
 /// Generates a help message for the application.
 ///
 /// This function constructs a help message using the application's name,
 /// description, usage pattern, and examples of how to use the command-line
-/// interface. It formats this information into a readable string that can be
-/// displayed to the user.
+/// interface. The usage line and the Arguments section are generated from
+/// `OPTIONS`; the examples are written by hand.
 ///
 /// # Arguments
 ///
@@ -71,15 +1241,48 @@ pub struct CliArguments {
 ///
 /// A string containing the formatted help message.
 pub fn get_help_message(program_name: &str) -> String {
-    let usage = format!(
-        "Usage:\n\t{} --period=<seconds> --port=<port> [--connect=<peer_address_with_port>]",
-        program_name
+    let mut usage = format!("Usage:\n\t{}", program_name);
+    let mut arguments = "Arguments:".to_owned();
+    describe_options(OPTIONS, &mut usage, &mut arguments);
+
+    usage.push_str(&format!("\n\t{} replay", program_name));
+    arguments.push_str("\n\nReplay arguments:");
+    describe_options(REPLAY_OPTIONS, &mut usage, &mut arguments);
+
+    usage.push_str(&format!("\n\t{} analyze", program_name));
+    arguments.push_str("\n\nAnalyze arguments:");
+    describe_options(ANALYZE_OPTIONS, &mut usage, &mut arguments);
+
+    usage.push_str(&format!("\n\t{} ctl", program_name));
+    arguments.push_str("\n\nCtl arguments:");
+    describe_options(CTL_OPTIONS, &mut usage, &mut arguments);
+    usage.push_str(" <command> [<arguments>]");
+
+    usage.push_str(&format!("\n\t{} probe <socket> <addr>", program_name));
+    arguments.push_str("\n\nProbe arguments:");
+    describe_options(PROBE_OPTIONS, &mut usage, &mut arguments);
+    usage.push_str(&format!("\n\t{} selftest", program_name));
+
+    usage.push_str(&format!("\n\t{} simulate", program_name));
+    arguments.push_str("\n\nSimulate arguments:");
+    describe_options(SIMULATE_OPTIONS, &mut usage, &mut arguments);
+
+    arguments.push_str(
+        "\n\
+        \n\
+        Exit codes:\n\
+        \t0 - clean shutdown (including Ctrl-C), successful check, ctl command or probe\n\
+        \t2 - invalid arguments, configuration, membership snapshot, filter file, recording or scenario, a ctl command or probe refused, or over --max-threads\n\
+        \t3 - the listen address or the admin socket can not be bound\n\
+        \t4 - no bootstrap peer could be reached with --require-bootstrap, or --join-check timed out\n\
+        \t5 - a local state file can not be read or created\n\
+        \t6 - ctl or probe can not reach the admin socket\n\
+        \t7 - a selftest stage failed\n\
+        \t8 - a peer caused a protocol anomaly with --strict-protocol\n\
+        \t9 - the bootstrap peer did not present the network of --expect-network-id or the node id of --expect-node-id\n\
+        \t10 - an assertion of a simulate scenario did not hold, or one of its events could not be taken\n\
+        \t11 - the summary of a replay differs from the one of --diff",
     );
-    let arguments = "\
-        Arguments:\n\
-        \tperiod - messaging period in seconds (required)\n\
-        \tport - connection port (required)\n\
-        \tconnect - address of the peer";
 
     let examples = format!(
         "Examples:\n\
@@ -94,8 +1297,42 @@ pub fn get_help_message(program_name: &str) -> String {
         \t# Starting the second peer which will connect to all the peers through the first\n\
         \t# messaging period - 7 seconds\n\
         \t# port - 8082\n\
-        \t{} --period=7 --port=8082 --connect=\"127.0.0.1:8080\"",
-        program_name, program_name, program_name
+        \t{} --period=7 --port=8082 --connect=\"127.0.0.1:8080\"\n\
+        \n\
+        \t# Starting a load-testing peer which sends a message every 250 milliseconds\n\
+        \t{} --period=250ms --port=8083 --connect=\"127.0.0.1:8080\"\n\
+        \n\
+        \t# Starting a peer which sends every second with two peers, every 3 seconds with eight\n\
+        \t{} --period=auto:1s --port=8084 --connect=\"127.0.0.1:8080\"\n\
+        \n\
+        \t# Replaying, as fast as possible, the events recorded by a peer started with --record\n\
+        \t{} replay --file=peer.record --fast --output=before.txt\n\
+        \n\
+        \t# Replaying them again with another build, and showing what it did differently\n\
+        \t{} replay --file=peer.record --fast --diff=before.txt\n\
+        \n\
+        \t# Showing the peers a recorded peer had 14 minutes into its run, and its churn\n\
+        \t{} analyze --file=peer.record --at=14m\n\
+        \n\
+        \t# Muting a peer for a minute through the admin socket of a peer started with --admin-socket\n\
+        \t{} ctl --socket=/tmp/gossip.sock --pretty mute 127.0.0.1:8081 60\n\
+        \n\
+        \t# Measuring the round trip and loss from that peer to one of its peers with 100 probes\n\
+        \t{} probe /tmp/gossip.sock 127.0.0.1:8081 --count=100 --interval=50ms\n\
+        \n\
+        \t# Running a partition and its heal, ten times faster than the real time\n\
+        \t{} simulate --scenario=examples/scenarios/partition-heal.json --fast",
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name,
+        program_name
     );
 
     format!(
@@ -104,33 +1341,408 @@ pub fn get_help_message(program_name: &str) -> String {
     )
 }
 
-/// Parses a single command-line argument.
+/// Appends the options of `options` to the usage line and to the arguments section of the help
+/// message.
+fn describe_options(options: &[OptionSpec], usage: &mut String, arguments: &mut String) {
+    for spec in options {
+        let option = match spec.value_kind {
+            ValueKind::Flag => format!("--{}", spec.name),
+            _ => format!("--{}={}", spec.name, spec.value_name),
+        };
+        if spec.required {
+            usage.push_str(&format!(" {}", option));
+        } else {
+            usage.push_str(&format!(" [{}]", option));
+        }
+
+        arguments.push_str(&format!("\n\t{} - {}", spec.name, spec.help));
+        if spec.required {
+            arguments.push_str(" (required)");
+        } else if let Some(default) = spec.default {
+            arguments.push_str(&format!(" (default {})", default));
+        }
+    }
+}
+
+/// Returns the version of the application and of the protocol it speaks.
+pub fn get_version_message() -> String {
+    format!(
+        "gossip-p2p {} (protocol version {})",
+        env!("CARGO_PKG_VERSION"),
+        PROTOCOL_VERSION
+    )
+}
+
+/// Parses a duration such as `5`, `250ms`, `1.5s`, `15m` or `2h`.
 ///
-/// This helper function looks for an argument with a specific prefix, extracts
-/// the value after the "=" character, and tries to parse it as a `u64`.
+/// A bare number counts seconds, which keeps the original `--period=<seconds>` form working.
+/// Otherwise the number, which may be fractional, is followed by one of the `ms`, `s`, `m` or
+/// `h` units. Compound values like `1h30m` are not supported; write `90m` instead. Values
+/// longer than `MAX_DURATION` are refused, so no timer built from one overflows.
 ///
 /// # Arguments
 ///
-/// * `args` - A slice of strings representing all command-line arguments.
-/// * `prefix` - The prefix to look for when filtering the arguments.
+/// * `value` - The text to parse.
 ///
 /// # Returns
 ///
-/// An `Option<u64>` which is `Some` if the argument was found and successfully
-/// parsed, or `None` otherwise.
-fn parse_each_arg(args: &[String], prefix: &str) -> Option<u64> {
-    args.iter()
-        .find(|arg| arg.starts_with(prefix))
-        .and_then(|arg| arg.split('=').nth(1))
-        .and_then(|value| value.parse().ok())
+/// The parsed `Duration`, or an explanation of why `value` is not a positive duration.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let seconds_per_unit = match unit {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown duration unit in \"{}\"", value)),
+    };
+    let amount: f64 = number
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a duration", value))?;
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(format!("\"{}\" is not a positive duration", value));
+    }
+
+    let duration = Duration::try_from_secs_f64(amount * seconds_per_unit)
+        .ok()
+        .filter(|duration| *duration <= MAX_DURATION)
+        .ok_or_else(|| format!("\"{}\" is longer than 100 years", value))?;
+    if duration.is_zero() {
+        return Err(format!("\"{}\" is shorter than a nanosecond", value));
+    }
+    Ok(duration)
+}
+
+/// The options found on the command line, checked against a table such as `OPTIONS`.
+struct ParsedOptions<'a> {
+    options: &'static [OptionSpec],
+    values: HashMap<&'static str, Option<&'a str>>,
+    repeated: HashMap<&'static str, Vec<&'a str>>,
+}
+
+impl<'a> ParsedOptions<'a> {
+    /// Matches every argument against `options`.
+    ///
+    /// When an option is given several times, the first occurrence wins, except for
+    /// `ValueKind::Alert` options, which keep every occurrence.
+    fn new(args: &'a [String], options: &'static [OptionSpec]) -> Result<Self, CliError> {
+        let mut values = HashMap::new();
+        let mut repeated: HashMap<&'static str, Vec<&'a str>> = HashMap::new();
+        for arg in args {
+            let option = arg
+                .strip_prefix("--")
+                .ok_or_else(|| CliError::UnknownArgument(arg.clone()))?;
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (option, None),
+            };
+            let spec =
+                find_spec(options, name).ok_or_else(|| CliError::UnknownArgument(arg.clone()))?;
+
+            if (spec.value_kind == ValueKind::Flag) != value.is_none() {
+                return Err(CliError::InvalidArgument(spec.invalid));
+            }
+            if let (ValueKind::Alert, Some(value)) = (spec.value_kind, value) {
+                repeated.entry(spec.name).or_default().push(value);
+            }
+            values.entry(spec.name).or_insert(value);
+        }
+        Ok(Self {
+            options,
+            values,
+            repeated,
+        })
+    }
+
+    /// Returns the specification of an option the parser itself refers to.
+    fn spec(&self, name: &str) -> &'static OptionSpec {
+        find_spec(self.options, name)
+            .expect("every option read by the parser is listed in its table")
+    }
+
+    /// Determines whether a flag was given.
+    fn flag(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Returns the value of an option, its default if it was not given, or `None` for an
+    /// absent option without a default.
+    fn value(&self, spec: &OptionSpec) -> Result<Option<&'a str>, CliError> {
+        match self.values.get(spec.name) {
+            Some(value) => Ok(*value),
+            None if spec.required => Err(CliError::InvalidArgument(spec.invalid)),
+            None => Ok(spec.default),
+        }
+    }
+
+    /// Parses the value of a `ValueKind::Number` option.
+    fn number(&self, name: &str) -> Result<u64, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .and_then(|value| value.parse().ok())
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::Number` option that can not be zero.
+    fn positive_number(&self, name: &str) -> Result<u64, CliError> {
+        match self.number(name)? {
+            0 => Err(CliError::InvalidArgument(self.spec(name).invalid)),
+            number => Ok(number),
+        }
+    }
+
+    /// Parses the value of a `ValueKind::Duration` option.
+    fn duration(&self, name: &str) -> Result<Duration, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .and_then(|value| parse_duration(value).ok())
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::Duration` option without a default, `None` when it
+    /// was not given.
+    fn optional_duration(&self, name: &str) -> Result<Option<Duration>, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .map(|value| parse_duration(value).map_err(|_| CliError::InvalidArgument(spec.invalid)))
+            .transpose()
+    }
+
+    /// Parses the value of a `ValueKind::Period` option.
+    ///
+    /// # Returns
+    ///
+    /// The duration and whether it was prefixed by `auto:`.
+    fn period(&self, name: &str) -> Result<(Duration, bool), CliError> {
+        let spec = self.spec(name);
+        let value = self.value(spec)?.unwrap_or_default();
+        let (value, adaptive) = match value.strip_prefix("auto:") {
+            Some(base) => (base, true),
+            None => (value, false),
+        };
+        parse_duration(value)
+            .map(|period| (period, adaptive))
+            .map_err(|_| CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Returns the value of a `ValueKind::Text` option.
+    fn text(&self, name: &str) -> Result<Option<String>, CliError> {
+        Ok(self.value(self.spec(name))?.map(str::to_owned))
+    }
+
+    /// Parses the value of a `ValueKind::AddressList` option.
+    fn addresses(&self, name: &str) -> Result<Vec<SocketAddr>, CliError> {
+        let spec = self.spec(name);
+        match self.value(spec)? {
+            Some(value) => value
+                .split(',')
+                .map(|addr| addr.parse())
+                .collect::<Result<Vec<SocketAddr>, _>>()
+                .map_err(|_| CliError::InvalidArgument(spec.invalid)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses the value of a `ValueKind::Boolean` option.
+    fn boolean(&self, name: &str) -> Result<Option<bool>, CliError> {
+        let spec = self.spec(name);
+        match self.value(spec)? {
+            Some("true") => Ok(Some(true)),
+            Some("false") => Ok(Some(false)),
+            Some(_) => Err(CliError::InvalidArgument(spec.invalid)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the value of a `ValueKind::CommandList` option.
+    fn commands(&self, name: &str) -> Result<Vec<Command>, CliError> {
+        let spec = self.spec(name);
+        match self.value(spec)? {
+            Some(value) => parse_script(value).map_err(|_| CliError::InvalidArgument(spec.invalid)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses every occurrence of a `ValueKind::Alert` option.
+    fn alerts(&self, name: &str) -> Result<Vec<AlertRule>, CliError> {
+        let spec = self.spec(name);
+        let values = self
+            .repeated
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        values
+            .iter()
+            .map(|value| {
+                AlertRule::parse(value).map_err(|_| CliError::InvalidArgument(spec.invalid))
+            })
+            .collect()
+    }
+
+    /// Parses the value of a `ValueKind::Port` option.
+    fn port(&self, name: &str) -> Result<ListenPort, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .and_then(|value| value.parse().ok())
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::ReportFormat` option.
+    fn report_format(&self, name: &str) -> Result<ReportFormat, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .and_then(|value| value.parse().ok())
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::NodeId` option.
+    fn node_id(&self, name: &str) -> Result<Option<NodeId>, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| CliError::InvalidArgument(spec.invalid))
+            })
+            .transpose()
+    }
+
+    /// Parses the value of a `ValueKind::Key` option.
+    fn key(&self, name: &str) -> Result<Option<VerifyingKey>, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .map(|value| {
+                unhex(value)
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                    .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+                    .ok_or(CliError::InvalidArgument(spec.invalid))
+            })
+            .transpose()
+    }
+
+    /// Parses the value of a `ValueKind::Verification` option.
+    fn verification(&self, name: &str) -> Result<VerificationMode, CliError> {
+        let spec = self.spec(name);
+        self.value(spec)?
+            .and_then(|value| value.parse().ok())
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::JoinCheck` option.
+    fn join_check(&self, name: &str) -> Result<Option<JoinCheck>, CliError> {
+        let spec = self.spec(name);
+        let Some(value) = self.value(spec)? else {
+            return Ok(None);
+        };
+        let check = value.split_once(',').and_then(|(peers, timeout)| {
+            Some(JoinCheck {
+                peers: peers.parse().ok().filter(|peers| *peers > 0)?,
+                timeout: parse_duration(timeout).ok()?,
+            })
+        });
+        check
+            .map(Some)
+            .ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::Retention` option.
+    fn retention(&self, name: &str) -> Result<RetentionPolicy, CliError> {
+        let spec = self.spec(name);
+        let value = self.value(spec)?.unwrap_or_default();
+        let mut parts = value.split(',');
+        let mut policy = || {
+            let max_entries = parts.next()?.parse().ok()?;
+            let max_age = parse_duration(parts.next()?).ok()?;
+            let max_total_bytes = match parts.next() {
+                Some(bytes) => Some(bytes.parse().ok()?),
+                None => None,
+            };
+            parts.next().is_none().then_some(RetentionPolicy {
+                max_entries,
+                max_age,
+                max_total_bytes,
+            })
+        };
+        policy().ok_or(CliError::InvalidArgument(spec.invalid))
+    }
+
+    /// Parses the value of a `ValueKind::TopicList` option.
+    fn topics(&self, name: &str) -> Result<Option<Vec<String>>, CliError> {
+        let spec = self.spec(name);
+        let Some(value) = self.value(spec)? else {
+            return Ok(None);
+        };
+
+        let topics: Vec<String> = value.split(',').map(str::to_owned).collect();
+        if topics.iter().any(|topic| validate_topic(topic).is_err()) {
+            return Err(CliError::InvalidArgument(spec.invalid));
+        }
+        Ok(Some(topics))
+    }
+}
+
+/// Finds the specification of the option called `name` in `options`.
+fn find_spec(options: &'static [OptionSpec], name: &str) -> Option<&'static OptionSpec> {
+    options.iter().find(|spec| spec.name == name)
+}
+
+/// Appends the options of the `--config` file to `args`, if they name one.
+///
+/// The file holds one option per line, written as on the command line with or without its
+/// leading `--`, such as `share-limit=8`; blank lines and lines starting with `#` are skipped.
+/// Its options come after those of the command line, so an option given in both keeps its
+/// command-line value. The `replay`, `analyze`, `ctl`, `probe`, `selftest` and `simulate`
+/// commands read no file.
+///
+/// # Returns
+///
+/// The arguments followed by the options of the file, or a `CliError::ConfigFile` if it can
+/// not be read.
+pub fn with_config_file(args: &[String]) -> Result<Vec<String>, CliError> {
+    let mut merged = args.to_vec();
+    if args.first().is_some_and(|arg| !arg.starts_with("--")) {
+        return Ok(merged);
+    }
+    let Some(path) = args.iter().find_map(|arg| arg.strip_prefix("--config=")) else {
+        return Ok(merged);
+    };
+    let text = fs::read_to_string(path).map_err(|err| {
+        CliError::ConfigFile(format!(
+            "Can not read the config file \"{}\": {}",
+            path, err
+        ))
+    })?;
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for line in lines {
+        let option = line.strip_prefix("--").unwrap_or(line);
+        merged.push(match option.split_once('=') {
+            Some((name, value)) => format!("--{}={}", name.trim(), value.trim()),
+            None => format!("--{}", option),
+        });
+    }
+    Ok(merged)
 }
 
 /// Parses all command-line arguments.
 ///
-/// This function extracts and validates the command-line arguments required by
-/// the application. It ensures that the mandatory arguments `--period` and
-/// `--port` are provided and correctly formatted. It also handles the optional
-/// `--connect` argument.
+/// This function matches every argument against `OPTIONS`, rejecting unknown options,
+/// flags given a value and options missing one. `--help` and `--version` are honored before
+/// anything else is validated. A leading `replay` selects the replay command, whose arguments
+/// are matched against `REPLAY_OPTIONS` instead, a leading `analyze` the analyze command, whose
+/// arguments are matched against `ANALYZE_OPTIONS`, a leading `simulate` the simulate command,
+/// whose arguments are matched against `SIMULATE_OPTIONS`, a leading `ctl` the ctl command,
+/// whose options are matched against `CTL_OPTIONS` up to the words of its console command, and
+/// a leading `probe` the probe command, whose two words name the admin socket and the peer and
+/// whose options are matched against `PROBE_OPTIONS`. Otherwise the mandatory `--period` and `--port` arguments
+/// must be provided and correctly formatted, and the optional ones fall back to the
+/// defaults of the table.
 ///
 /// # Arguments
 ///
@@ -138,22 +1750,617 @@ fn parse_each_arg(args: &[String], prefix: &str) -> Option<u64> {
 ///
 /// # Returns
 ///
-/// A `Result` which is `Ok` with a `CliArguments` struct if the arguments were
-/// successfully parsed, or an `Err` with a static error message otherwise.
-pub fn parse_arguments(args: &[String]) -> Result<CliArguments, &'static str> {
-    let period_arg = parse_each_arg(args, "--period=")
-        .ok_or("Period is required and must be a positive number")?;
-    let port_arg =
-        parse_each_arg(args, "--port=").ok_or("Port is required and must be a positive number")?;
-
-    let connect_arg = args
-        .iter()
-        .find(|arg| arg.starts_with("--connect="))
-        .map(|s| s.split('=').nth(1).unwrap().to_string());
-
-    Ok(CliArguments {
-        period: period_arg,
-        port: port_arg as u16,
-        connect: connect_arg,
-    })
+/// A `Result` which is `Ok` with the `Invocation` requested by the arguments, or an `Err`
+/// with a `CliError` otherwise.
+pub fn parse_arguments(args: &[String]) -> Result<Invocation, CliError> {
+    if args.iter().any(|arg| arg == "--help") {
+        return Ok(Invocation::Help);
+    }
+    if args.iter().any(|arg| arg == "--version") {
+        return Ok(Invocation::Version);
+    }
+    if args.first().is_some_and(|arg| arg == "ctl") {
+        // The options come first; the first other word starts the command, whose text may
+        // hold anything from there on.
+        let split = args[1..]
+            .iter()
+            .position(|arg| !arg.starts_with("--"))
+            .map_or(args.len(), |index| index + 1);
+        let options = ParsedOptions::new(&args[1..split], CTL_OPTIONS)?;
+        let command = args[split..].to_vec();
+        if command.is_empty() {
+            return Err(CliError::InvalidArgument(
+                "Ctl needs a console command, such as peers",
+            ));
+        }
+        return Ok(Invocation::Ctl(CtlArguments {
+            socket: options
+                .text("socket")?
+                .ok_or(CliError::InvalidArgument(options.spec("socket").invalid))?,
+            pretty: options.flag("pretty"),
+            command,
+        }));
+    }
+    if args.first().is_some_and(|arg| arg == "probe") {
+        let (words, options): (Vec<String>, Vec<String>) = args[1..]
+            .iter()
+            .cloned()
+            .partition(|arg| !arg.starts_with("--"));
+        let [socket, peer] = words.as_slice() else {
+            return Err(CliError::InvalidArgument(
+                "Probe needs the admin socket of a participant and the address of its peer",
+            ));
+        };
+        let peer = peer.parse().map_err(|_| {
+            CliError::InvalidArgument("Probe needs the address of a peer, such as 127.0.0.1:8081")
+        })?;
+        let options = ParsedOptions::new(&options, PROBE_OPTIONS)?;
+        let count = options.positive_number("count")?;
+        return Ok(Invocation::Probe(ProbeArguments {
+            socket: socket.clone(),
+            peer,
+            count: u32::try_from(count)
+                .map_err(|_| CliError::InvalidArgument(options.spec("count").invalid))?,
+            interval: options.duration("interval")?,
+        }));
+    }
+    if args.first().is_some_and(|arg| arg == "selftest") {
+        if args.len() > 1 {
+            return Err(CliError::InvalidArgument("Selftest takes no arguments"));
+        }
+        return Ok(Invocation::Selftest);
+    }
+    if args.first().is_some_and(|arg| arg == "simulate") {
+        let options = ParsedOptions::new(&args[1..], SIMULATE_OPTIONS)?;
+        return Ok(Invocation::Simulate(SimulateArguments {
+            scenario: options
+                .text("scenario")?
+                .ok_or(CliError::InvalidArgument(options.spec("scenario").invalid))?,
+            fast: options.flag("fast"),
+        }));
+    }
+    if args.first().is_some_and(|arg| arg == "analyze") {
+        let options = ParsedOptions::new(&args[1..], ANALYZE_OPTIONS)?;
+        return Ok(Invocation::Analyze(AnalyzeArguments {
+            file: options
+                .text("file")?
+                .ok_or(CliError::InvalidArgument(options.spec("file").invalid))?,
+            at: options.optional_duration("at")?,
+        }));
+    }
+    if args.first().is_some_and(|arg| arg == "replay") {
+        let options = ParsedOptions::new(&args[1..], REPLAY_OPTIONS)?;
+        return Ok(Invocation::Replay(ReplayArguments {
+            file: options
+                .text("file")?
+                .ok_or(CliError::InvalidArgument(options.spec("file").invalid))?,
+            fast: options.flag("fast"),
+            output: options.text("output")?,
+            diff: options.text("diff")?,
+        }));
+    }
+    if args.len() < 2 {
+        return Err(CliError::MissingArguments);
+    }
+
+    let options = ParsedOptions::new(args, OPTIONS)?;
+    let (period, adaptive_period) = options.period("period")?;
+
+    Ok(Invocation::Run(Box::new(CliArguments {
+        period,
+        adaptive_period,
+        min_period: options.duration("min-period")?,
+        max_period: options.duration("max-period")?,
+        rounds: options.flag("rounds"),
+        legacy_payload: options.flag("legacy-payload"),
+        port: options.port("port")?,
+        connect: options.text("connect")?,
+        listen: options.addresses("listen")?,
+        advertise: options.addresses("advertise")?,
+        no_peer_cache: options.flag("no-peer-cache"),
+        network: options.text("network")?,
+        half_open_threshold: options.number("half-open-threshold")?,
+        period_skew_ratio: options.number("period-skew-ratio")?,
+        max_clock_offset: options.duration("max-clock-offset")?,
+        balance_connections: options.flag("balance-connections"),
+        min_outbound_share: options.number("min-outbound-share")?,
+        require_bootstrap: options.flag("require-bootstrap"),
+        join_check: options.join_check("join-check")?,
+        expect_network_id: options.text("expect-network-id")?,
+        expect_node_id: options.node_id("expect-node-id")?,
+        bootstrap_verification: options.verification("bootstrap-verification")?,
+        config: options.text("config")?,
+        check: options.flag("check"),
+        strict_config: options.flag("strict-config"),
+        strict_protocol: options.flag("strict-protocol"),
+        validate_outbound: options.flag("validate-outbound"),
+        no_hints: options.flag("no-hints"),
+        interactive: options.flag("interactive"),
+        interactive_exit_on_eof: options.boolean("interactive-exit-on-eof")?,
+        exec: options.commands("exec")?,
+        admin_socket: options.text("admin-socket")?,
+        max_observers: options.number("max-observers")?,
+        dedup_entries: options.number("dedup-entries")?,
+        ratelimit_entries: options.number("ratelimit-entries")?,
+        blocklist: options.addresses("block")?,
+        filter_file: options.text("filter-file")?,
+        allow_privileged_ports: options.flag("allow-privileged-ports"),
+        allow_special_ranges: options.flag("allow-special-ranges"),
+        topics: options.topics("topics")?,
+        topic_broadcast: options.topics("topic-broadcast")?.unwrap_or_default(),
+        reorder_window_ms: options.number("reorder-window-ms")?,
+        reorder_buffer: options.positive_number("reorder-buffer")?,
+        audit_every: options.number("audit-every")?,
+        share_limit: options.positive_number("share-limit")?,
+        corroboration: options.positive_number("corroboration")?,
+        per_peer_send_budget_ms: options.positive_number("per-peer-send-budget-ms")?,
+        batch_window_ms: options.number("batch-window-ms")?,
+        late_tick_factor: options.positive_number("late-tick-factor")?,
+        frozen_tick_factor: options.positive_number("frozen-tick-factor")?,
+        connect_timeout: options.duration("connect-timeout")?,
+        dial_memory: options.duration("dial-memory")?,
+        heal_probe_interval: options.duration("heal-probe-interval")?,
+        resolve_interval: options.duration("resolve-interval")?,
+        warmup: options.optional_duration("warmup")?,
+        busy_lag: options.duration("busy-lag")?,
+        overload_lag: options.duration("overload-lag")?,
+        trace_ticks: options.number("trace-ticks")?,
+        inbox_capacity: options.positive_number("inbox-capacity")?,
+        max_scheduled: options.positive_number("max-scheduled")?,
+        callback_workers: options.positive_number("callback-workers")?,
+        max_threads: options.positive_number("max-threads")?,
+        fd_headroom: options.number("fd-headroom")?,
+        import_membership: options.text("import-membership")?,
+        max_snapshot_age: options.duration("max-snapshot-age")?,
+        require_signed_import: options.flag("require-signed-import"),
+        trusted_snapshot_key: options.key("trusted-snapshot-key")?,
+        state_file: options.text("state-file")?,
+        state_save_interval: options.optional_duration("state-save-interval")?,
+        max_state_age: options.duration("max-state-age")?,
+        retain_sessions: options.retention("retain-sessions")?,
+        retain_departed: options.retention("retain-departed")?,
+        retain_lists: options.retention("retain-lists")?,
+        identity_file: options.text("identity-file")?,
+        node_label: options.text("node-label")?,
+        report_file: options.text("report-file")?,
+        report_format: options.report_format("report-format")?,
+        record: options.text("record")?,
+        record_snapshot_every: options.duration("record-snapshot-every")?,
+        alerts: options.alerts("alert")?,
+        alert_cooldown: options.duration("alert-cooldown")?,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::alert::AlertEvent;
+    use crate::participant::state::hex;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn run(values: &[&str]) -> Result<CliArguments, CliError> {
+        match parse_arguments(&args(values))? {
+            Invocation::Run(arguments) => Ok(*arguments),
+            _ => panic!("{:?} is not a run", values),
+        }
+    }
+
+    fn invalid(values: &[&str]) -> &'static str {
+        match run(values) {
+            Err(CliError::InvalidArgument(reason)) => reason,
+            Err(err) => panic!("{:?} failed with {}", values, err),
+            Ok(_) => panic!("{:?} was accepted", values),
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        let cases = [
+            ("5", Duration::from_secs(5)),
+            ("5s", Duration::from_secs(5)),
+            ("1.5s", Duration::from_millis(1_500)),
+            ("250ms", Duration::from_millis(250)),
+            ("0.5ms", Duration::from_micros(500)),
+            ("15m", Duration::from_secs(900)),
+            ("2h", Duration::from_secs(7_200)),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_duration(value), Ok(expected), "{}", value);
+        }
+    }
+
+    #[test]
+    fn refuses_what_is_not_a_positive_duration() {
+        for value in [
+            "",
+            "s",
+            "0",
+            "0s",
+            "-1s",
+            "1h30m",
+            "5d",
+            "1e3s",
+            "NaN",
+            "inf",
+            "1,5s",
+            " 5s",
+            "0.0000000001ns",
+        ] {
+            assert!(parse_duration(value).is_err(), "{:?} was accepted", value);
+        }
+        assert_eq!(
+            parse_duration("0.0000000001"),
+            Err("\"0.0000000001\" is shorter than a nanosecond".to_owned())
+        );
+        // An exponent reads as a unit.
+        assert_eq!(
+            parse_duration("1e300"),
+            Err("unknown duration unit in \"1e300\"".to_owned())
+        );
+        assert_eq!(
+            parse_duration("1.2.3s"),
+            Err("\"1.2.3s\" is not a duration".to_owned())
+        );
+        assert_eq!(
+            parse_duration("100000000000000000000s"),
+            Err("\"100000000000000000000s\" is longer than 100 years".to_owned())
+        );
+        // Parses as a `Duration`, but would overflow an `Instant`.
+        assert_eq!(
+            parse_duration("3000000000000000h"),
+            Err("\"3000000000000000h\" is longer than 100 years".to_owned())
+        );
+    }
+
+    #[test]
+    fn accepts_durations_up_to_100_years() {
+        let max = format!("{}s", MAX_DURATION.as_secs());
+        assert_eq!(parse_duration(&max), Ok(MAX_DURATION));
+        assert_eq!(parse_duration("876000h"), Ok(MAX_DURATION));
+        assert!(parse_duration(&format!("{}s", MAX_DURATION.as_secs() + 1)).is_err());
+    }
+
+    #[test]
+    fn refuses_overlong_durations_as_argument_errors() {
+        for option in [
+            "--warmup",
+            "--heal-probe-interval",
+            "--record-snapshot-every",
+            "--state-save-interval",
+        ] {
+            invalid(&["--period=1", &format!("{}=3000000000000000h", option)]);
+        }
+    }
+
+    #[test]
+    fn option_tables_are_consistent() {
+        for options in [OPTIONS, REPLAY_OPTIONS, ANALYZE_OPTIONS, CTL_OPTIONS] {
+            for (index, spec) in options.iter().enumerate() {
+                assert!(
+                    options[..index].iter().all(|other| other.name != spec.name),
+                    "--{} is listed twice",
+                    spec.name
+                );
+                assert_eq!(
+                    spec.value_kind == ValueKind::Flag,
+                    spec.value_name.is_empty(),
+                    "--{} has a value name only if it takes a value",
+                    spec.name
+                );
+                assert!(
+                    !(spec.required && spec.default.is_some()),
+                    "--{} is required yet has a default",
+                    spec.name
+                );
+                assert!(!spec.help.is_empty() && !spec.invalid.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn help_lists_every_option() {
+        let help = get_help_message("gossip");
+        for options in [OPTIONS, REPLAY_OPTIONS, ANALYZE_OPTIONS, CTL_OPTIONS] {
+            for spec in options {
+                assert!(
+                    help.contains(&format!("\n\t{} - {}", spec.name, spec.help)),
+                    "--{} is missing from the help",
+                    spec.name
+                );
+            }
+        }
+        assert!(help.contains(" --period=[auto:]<duration> [--min-period=<duration>]"));
+        assert!(help.contains(" [--rounds] "));
+    }
+
+    #[test]
+    fn falls_back_to_the_defaults() {
+        // Every default of the table parses, or this fails.
+        let arguments = run(&["--period=5", "--port=8080"]).unwrap();
+        assert_eq!(arguments.period, Duration::from_secs(5));
+        assert!(!arguments.adaptive_period);
+        assert_eq!(arguments.port, ListenPort::from(8080));
+        assert_eq!(arguments.min_period, Duration::from_millis(500));
+        assert_eq!(arguments.max_period, Duration::from_secs(60));
+        assert_eq!(arguments.half_open_threshold, 10);
+        assert_eq!(arguments.connect, None);
+        assert_eq!(arguments.network, None);
+        assert!(!arguments.no_peer_cache);
+        assert!(arguments.alerts.is_empty());
+        assert!(arguments.exec.is_empty());
+        assert_eq!(arguments.alert_cooldown, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn parses_the_given_values() {
+        let arguments = run(&[
+            "--period=auto:250ms",
+            "--port=0",
+            "--connect=127.0.0.1:8080",
+            "--listen=127.0.0.2:9000,[::1]:9001",
+            "--no-peer-cache",
+            "--network=lab",
+            "--expect-network-id=prod",
+            "--interactive-exit-on-eof=false",
+            "--exec=peers;stats",
+            "--topics=news,weather",
+            "--alert=isolated:exec:true",
+            "--alert=peer-evicted:exec:false",
+        ])
+        .unwrap();
+        assert_eq!(arguments.period, Duration::from_millis(250));
+        assert!(arguments.adaptive_period);
+        assert_eq!(arguments.port, ListenPort::Ephemeral);
+        assert_eq!(arguments.connect.as_deref(), Some("127.0.0.1:8080"));
+        assert_eq!(
+            arguments.listen,
+            [
+                "127.0.0.2:9000".parse().unwrap(),
+                "[::1]:9001".parse().unwrap()
+            ]
+        );
+        assert!(arguments.no_peer_cache);
+        assert_eq!(arguments.network.as_deref(), Some("lab"));
+        assert_eq!(arguments.expect_network_id.as_deref(), Some("prod"));
+        assert_eq!(arguments.interactive_exit_on_eof, Some(false));
+        assert_eq!(arguments.exec, [Command::Peers, Command::Stats]);
+        assert_eq!(
+            arguments.topics,
+            Some(vec!["news".to_owned(), "weather".to_owned()])
+        );
+        // Alerts keep every occurrence, in order.
+        let events: Vec<_> = arguments.alerts.iter().map(|rule| rule.event).collect();
+        assert_eq!(events, [AlertEvent::Isolated, AlertEvent::PeerEvicted]);
+    }
+
+    #[test]
+    fn the_first_occurrence_wins() {
+        let arguments = run(&["--period=5", "--port=8080", "--period=7", "--port=9090"]).unwrap();
+        assert_eq!(arguments.period, Duration::from_secs(5));
+        assert_eq!(arguments.port, ListenPort::from(8080));
+    }
+
+    #[test]
+    fn refuses_malformed_arguments() {
+        assert!(matches!(
+            run(&["--period=5"]),
+            Err(CliError::MissingArguments)
+        ));
+        assert!(matches!(
+            run(&["--period=5", "--port=8080", "--unknown"]),
+            Err(CliError::UnknownArgument(arg)) if arg == "--unknown"
+        ));
+        assert!(matches!(
+            run(&["--period=5", "port=8080"]),
+            Err(CliError::UnknownArgument(_))
+        ));
+        let spec = |name| find_spec(OPTIONS, name).unwrap().invalid;
+        assert_eq!(invalid(&["--period=0", "--port=8080"]), spec("period"));
+        assert_eq!(invalid(&["--port=8080", "--rounds"]), spec("period"));
+        assert_eq!(invalid(&["--period=5", "--port=65536"]), spec("port"));
+        assert_eq!(
+            invalid(&["--period=5", "--port=8080", "--rounds=yes"]),
+            spec("rounds")
+        );
+        assert_eq!(
+            invalid(&["--period=5", "--port=8080", "--connect"]),
+            spec("connect")
+        );
+        assert_eq!(
+            invalid(&["--period=5", "--port=8080", "--share-limit=0"]),
+            spec("share-limit")
+        );
+        assert_eq!(
+            invalid(&[
+                "--period=5",
+                "--port=8080",
+                "--alert=panic-restart:exec:true"
+            ]),
+            spec("alert")
+        );
+    }
+
+    #[test]
+    fn help_and_version_come_first() {
+        assert!(matches!(
+            parse_arguments(&args(&["--unknown", "--help"])),
+            Ok(Invocation::Help)
+        ));
+        assert!(matches!(
+            parse_arguments(&args(&["--port=x", "--version"])),
+            Ok(Invocation::Version)
+        ));
+    }
+
+    #[test]
+    fn parses_the_commands() {
+        match parse_arguments(&args(&["replay", "--file=peer.record", "--fast"])) {
+            Ok(Invocation::Replay(replay)) => {
+                assert_eq!(replay.file, "peer.record");
+                assert!(replay.fast);
+                assert_eq!(replay.output, None);
+                assert_eq!(replay.diff, None);
+            }
+            _ => panic!("not a replay"),
+        }
+        match parse_arguments(&args(&["replay", "--file=r", "--output=new", "--diff=old"])) {
+            Ok(Invocation::Replay(replay)) => {
+                assert!(!replay.fast);
+                assert_eq!(replay.output.as_deref(), Some("new"));
+                assert_eq!(replay.diff.as_deref(), Some("old"));
+            }
+            _ => panic!("not a replay"),
+        }
+        match parse_arguments(&args(&["analyze", "--file=peer.record", "--at=14m"])) {
+            Ok(Invocation::Analyze(analyze)) => {
+                assert_eq!(analyze.at, Some(Duration::from_secs(840)));
+            }
+            _ => panic!("not an analysis"),
+        }
+        // The command starts at the first word that is not an option, and keeps the rest.
+        match parse_arguments(&args(&["ctl", "--socket=/tmp/s", "mute", "--x", "60"])) {
+            Ok(Invocation::Ctl(ctl)) => {
+                assert_eq!(ctl.socket, "/tmp/s");
+                assert!(!ctl.pretty);
+                assert_eq!(ctl.command, ["mute", "--x", "60"]);
+            }
+            _ => panic!("not a ctl"),
+        }
+        assert!(matches!(
+            parse_arguments(&args(&["ctl", "--socket=/tmp/s"])),
+            Err(CliError::InvalidArgument(_))
+        ));
+        match parse_arguments(&args(&["probe", "/tmp/s", "127.0.0.1:8081", "--count=100"])) {
+            Ok(Invocation::Probe(probe)) => {
+                assert_eq!(probe.socket, "/tmp/s");
+                assert_eq!(probe.peer, "127.0.0.1:8081".parse().unwrap());
+                assert_eq!(probe.count, 100);
+                assert_eq!(probe.interval, Duration::from_millis(100));
+            }
+            _ => panic!("not a probe"),
+        }
+        match parse_arguments(&args(&["probe", "--interval=50ms", "/tmp/s", "[::1]:8081"])) {
+            Ok(Invocation::Probe(probe)) => {
+                assert_eq!(probe.count, 10);
+                assert_eq!(probe.interval, Duration::from_millis(50));
+            }
+            _ => panic!("not a probe"),
+        }
+        for wrong in [
+            &["probe", "/tmp/s"][..],
+            &["probe", "/tmp/s", "somewhere"],
+            &["probe", "/tmp/s", "127.0.0.1:8081", "--count=0"],
+            &["probe", "/tmp/s", "127.0.0.1:8081", "--count=5000000000"],
+            &["probe", "/tmp/s", "127.0.0.1:8081", "--pretty"],
+        ] {
+            assert!(parse_arguments(&args(wrong)).is_err(), "{:?}", wrong);
+        }
+        assert!(matches!(
+            parse_arguments(&args(&["selftest"])),
+            Ok(Invocation::Selftest)
+        ));
+        assert!(parse_arguments(&args(&["selftest", "--fast"])).is_err());
+        assert!(parse_arguments(&args(&["replay"])).is_err());
+        match parse_arguments(&args(&["simulate", "--scenario=s.json", "--fast"])) {
+            Ok(Invocation::Simulate(simulate)) => {
+                assert_eq!(simulate.scenario, "s.json");
+                assert!(simulate.fast);
+            }
+            _ => panic!("not a simulate"),
+        }
+        assert!(matches!(
+            parse_arguments(&args(&["simulate", "--fast"])),
+            Err(CliError::InvalidArgument(
+                "Scenario is required and must be followed by a path"
+            ))
+        ));
+    }
+
+    #[test]
+    fn appends_the_config_file_after_the_command_line() {
+        let path = std::env::temp_dir().join(format!("gossip-p2p-cli-{}.conf", std::process::id()));
+        fs::write(
+            &path,
+            "# a participant\n\n--period=7\n  port = 9090 \nrounds\nno-peer-cache\n",
+        )
+        .unwrap();
+        let config = format!("--config={}", path.display());
+        let merged = with_config_file(&args(&["--port=8080", &config]));
+        fs::remove_file(&path).unwrap();
+        let merged = merged.unwrap();
+        assert_eq!(
+            merged[2..],
+            ["--period=7", "--port=9090", "--rounds", "--no-peer-cache"]
+        );
+
+        let arguments = run(&merged.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+        assert_eq!(arguments.port, ListenPort::from(8080));
+        assert_eq!(arguments.period, Duration::from_secs(7));
+        assert!(arguments.rounds);
+
+        // The commands read no file, and a missing one is reported.
+        assert_eq!(
+            with_config_file(&args(&["replay", "--config=/nonexistent"])).unwrap(),
+            ["replay", "--config=/nonexistent"]
+        );
+        assert!(matches!(
+            with_config_file(&args(&["--config=/nonexistent/gossip.conf"])),
+            Err(CliError::ConfigFile(_))
+        ));
+    }
+
+    #[test]
+    fn parses_join_checks() {
+        let check = run(&["--period=5", "--port=8080", "--join-check=2,10s"])
+            .unwrap()
+            .join_check;
+        assert_eq!(
+            check,
+            Some(JoinCheck {
+                peers: 2,
+                timeout: Duration::from_secs(10),
+            })
+        );
+        assert_eq!(
+            run(&["--period=5", "--port=8080"]).unwrap().join_check,
+            None
+        );
+
+        let spec = find_spec(OPTIONS, "join-check").unwrap().invalid;
+        for value in ["0,10s", "2", "2,", ",10s", "2,0s", "-1,10s", "two,10s"] {
+            let arg = format!("--join-check={}", value);
+            assert_eq!(
+                invalid(&["--period=5", "--port=8080", &arg]),
+                spec,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn parses_trusted_snapshot_keys() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]).verifying_key();
+        let arg = format!("--trusted-snapshot-key={}", hex(key.as_bytes()));
+        let arguments = run(&["--period=5", "--port=8080", &arg]).unwrap();
+        assert_eq!(arguments.trusted_snapshot_key, Some(key));
+
+        let spec = find_spec(OPTIONS, "trusted-snapshot-key").unwrap().invalid;
+        let short = format!("--trusted-snapshot-key={}", &hex(key.as_bytes())[2..]);
+        for arg in [
+            short.as_str(),
+            "--trusted-snapshot-key=zz",
+            "--trusted-snapshot-key",
+        ] {
+            assert_eq!(
+                invalid(&["--period=5", "--port=8080", arg]),
+                spec,
+                "{}",
+                arg
+            );
+        }
+    }
 }
@@ -0,0 +1,659 @@
+//! Interactive Console.
+//!
+//! This module provides the optional interactive console enabled with `--interactive`. A
+//! dedicated thread reads commands from the standard input, parses them into `Command`
+//! values and hands them over to the participant, which executes them on its event loop.
+//! The same commands can be given non-interactively with `--exec`, separated by `;`, or sent
+//! to the admin socket of `--admin-socket`, see `admin`.
+//!
+//! When the standard input is closed, the console either stops while the participant keeps
+//! running or shuts the participant down like `quit`, see `exit_on_eof`.
+//!
+//! ## Commands
+//!
+//! - `peers`: Lists every known participant with its uptime, sessions and per-kind message
+//!   counters.
+//! - `peers export-csv <path>`: Writes the peers as a CSV table to a file, see `csv`.
+//! - `peer <addr>`: Shows one participant in detail, with its recent sessions.
+//! - `provenance <addr>`: Shows where an address was first learned and which peers listed it
+//!   since, see `provenance`.
+//! - `stats`: Shows the aggregated message counters of this participant.
+//! - `report`: Prints the JSON run report that `--report-file` writes at shutdown.
+//! - `reannounce`: Re-sends this participant's public address to every connected peer.
+//! - `audit`: Checks the storage for inconsistencies and repairs them right away.
+//! - `export <path>`: Writes a membership snapshot for `--import-membership` to a file.
+//! - `publish <topic> <text>`: Publishes a text on a topic to every connected peer.
+//! - `publish-schema <topic> <schema> <version> <text>`: Publishes a text on a topic declaring
+//!   a schema and its version, see `schema`.
+//! - `estimate text <text>`, `estimate topic <topic> <text>`: Shows the size of the frame a
+//!   gossip text or a publication would be sent in, without sending it.
+//! - `limits`: Shows the size limits this participant sends under.
+//! - `schedule <delay> text <text>`, `schedule <delay> topic <topic> <text>`: Schedules a
+//!   gossip text or a publication to be sent after a delay, see `schedule`.
+//! - `scheduled`: Lists the publications scheduled and not sent yet.
+//! - `unschedule <id>`: Cancels a scheduled publication.
+//! - `request <addr> <topic> <text>`: Asks one peer to answer a text on a topic and waits for
+//!   its response.
+//! - `probe <addr> [count] [interval]`: Measures the round trip to one peer with `count`
+//!   probes sent `interval` apart, `DEFAULT_PROBE_COUNT` every `DEFAULT_PROBE_INTERVAL` unless
+//!   given, then prints the loss and the round trip statistics.
+//! - `probe-status`: Tells how far the running probe has come, or the summary of the last one,
+//!   for the `probe` command of the binary, which polls it through the admin socket.
+//! - `mute <addr> [duration]`: Drops the payloads of one peer without disconnecting it, for
+//!   `DEFAULT_MUTE_DURATION` unless a duration is given.
+//! - `unmute <addr>`: Lifts the mute of a peer before it expires.
+//! - `evict <addr> [duration]`: Disconnects the peer advertising an address, and bans the
+//!   address for a duration when one is given, see `ban`.
+//! - `unban <addr>`: Lifts the ban of an address before it expires.
+//! - `bans`: Lists the banned addresses with the time left on each ban.
+//! - `dials <addr>`, `dials --recent`: Lists the last dials of one address, or of any address,
+//!   with their trigger, outcome and latency, see `dials`.
+//! - `subscribe <topic>`: Prints every message received on a topic.
+//! - `subscribe-ordered <topic>`: Prints the messages received on a topic in the publish order
+//!   of each publisher, along with the gaps of messages that never arrived.
+//! - `subscribe-schema <topic> <schema> <versions>`: Prints the messages received on a topic
+//!   with a schema at one of a version or range of versions, such as `2` or `1-3`.
+//! - `subscribe-unsupported <topic>`: Prints the messages received on a topic with a schema no
+//!   `subscribe-schema` handles.
+//! - `schema-support <topic> <schema>`: Lists the peers handling a schema on a topic with the
+//!   versions they handle.
+//! - `unsubscribe <id>`: Removes a subscription made with `subscribe`, `subscribe-ordered`,
+//!   `subscribe-schema` or `subscribe-unsupported`.
+//! - `subscriptions`: Lists the subscriptions with what the callback workers ran, dropped and
+//!   hold for each, see `callback`.
+//! - `inbox [duration]`: Prints the messages waiting in the inbox of the console, first waiting
+//!   up to a duration for one to arrive when given.
+//! - `threads`: Lists the internal threads running, see `threads`.
+//! - `channels`: Lists the channels feeding the internal threads with the messages waiting in
+//!   each, see `channels`.
+//! - `memory`: Shows how much of each kind of peer history is kept, its estimated size and what
+//!   its retention policy evicted, see `retention`.
+//! - `reload`: Reads the `--config` file again and applies the settings that change while
+//!   running, see `reload`.
+//! - `help`: Lists the available commands.
+//! - `quit`: Shuts the participant down gracefully.
+
+use crate::cli::parse_duration;
+use crate::participant::probe::{DEFAULT_PROBE_COUNT, DEFAULT_PROBE_INTERVAL};
+use crate::participant::schema::{Schema, VersionRange};
+use crate::participant::threads::{Join, SpawnError, ThreadRegistry};
+use crate::printer::print_error;
+
+use std::io::{self, BufRead, IsTerminal};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// The delay before the first retry of a failed console read.
+const MIN_READ_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The longest delay between two retries of a failed console read.
+const MAX_READ_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How long `mute` silences a peer when no duration is given.
+pub const DEFAULT_MUTE_DURATION: Duration = Duration::from_secs(600);
+
+/// A command entered in the interactive console.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Command {
+    Peers,
+    PeersCsv(PathBuf),
+    Peer(SocketAddr),
+    Provenance(SocketAddr),
+    Stats,
+    Report,
+    Reannounce,
+    Audit,
+    Export(PathBuf),
+    Publish {
+        topic: String,
+        text: String,
+    },
+    PublishSchema {
+        topic: String,
+        schema: Schema,
+        text: String,
+    },
+    Estimate {
+        topic: Option<String>,
+        text: String,
+    },
+    Limits,
+    Schedule {
+        delay: Duration,
+        topic: Option<String>,
+        text: String,
+    },
+    Scheduled,
+    Unschedule(u64),
+    Request {
+        peer: SocketAddr,
+        topic: String,
+        text: String,
+    },
+    Probe {
+        peer: SocketAddr,
+        count: u32,
+        interval: Duration,
+    },
+    ProbeStatus,
+    Mute {
+        peer: SocketAddr,
+        duration: Duration,
+    },
+    Unmute(SocketAddr),
+    Evict {
+        peer: SocketAddr,
+        ban: Option<Duration>,
+    },
+    Unban(SocketAddr),
+    Bans,
+    Dials(Option<SocketAddr>),
+    Subscribe(String),
+    SubscribeOrdered(String),
+    SubscribeSchema {
+        topic: String,
+        name: String,
+        range: VersionRange,
+    },
+    SubscribeUnsupported(String),
+    SchemaSupport {
+        topic: String,
+        name: String,
+    },
+    Unsubscribe(u64),
+    Subscriptions,
+    Inbox(Option<Duration>),
+    Threads,
+    Channels,
+    Memory,
+    Reload,
+    Help,
+    Quit,
+}
+
+/// Returns the list of available console commands.
+pub fn get_help_message() -> &'static str {
+    "Commands:\n\
+    \tpeers - list known participants with their uptime\n\
+    \tpeers export-csv <path> - write the known participants as a CSV table\n\
+    \tpeer <addr> - show one participant in detail, with its recent sessions\n\
+    \tprovenance <addr> - show where an address was first learned and who listed it since\n\
+    \tstats - show aggregated message counters\n\
+    \treport - print the JSON run report written by --report-file\n\
+    \treannounce - re-send our address to every peer and refresh the participants lists\n\
+    \taudit - check the storage for inconsistencies now\n\
+    \texport <path> - write a membership snapshot for --import-membership\n\
+    \tpublish <topic> <text> - publish a text on a topic\n\
+    \tpublish-schema <topic> <schema> <version> <text> - publish a text on a topic with a schema version\n\
+    \testimate text <text> | estimate topic <topic> <text> - show the frame size of a message\n\
+    \tlimits - show the size limits of sent messages\n\
+    \tschedule <delay> text <text> | schedule <delay> topic <topic> <text> - send a message after a delay\n\
+    \tscheduled - list the scheduled messages not sent yet\n\
+    \tunschedule <id> - cancel a scheduled message\n\
+    \trequest <addr> <topic> <text> - ask one peer to answer a text on a topic, e.g. echo\n\
+    \tprobe <addr> [count] [interval] - measure the round trip and loss to a peer, 10 probes 100ms apart by default\n\
+    \tprobe-status - show the progress of the running probe, or the summary of the last one\n\
+    \tmute <addr> [duration] - drop the payloads of a peer but keep it connected, 10m by default\n\
+    \tunmute <addr> - deliver the payloads of a muted peer again\n\
+    \tevict <addr> [duration] - disconnect a peer, and refuse its address for a duration if given\n\
+    \tunban <addr> - accept a banned address again\n\
+    \tbans - list the banned addresses and how long their bans last\n\
+    \tdials <addr> | dials --recent - list the last dials of an address, or of any address\n\
+    \tsubscribe <topic> - print the messages received on a topic\n\
+    \tsubscribe-ordered <topic> - print them in publish order, with the missing ones\n\
+    \tsubscribe-schema <topic> <schema> <versions> - print those with a schema at a version or range, e.g. 1-3\n\
+    \tsubscribe-unsupported <topic> - print those with a schema no subscribe-schema handles\n\
+    \tschema-support <topic> <schema> - list the peers handling a schema and their versions\n\
+    \tunsubscribe <id> - remove a subscription\n\
+    \tsubscriptions - list the subscriptions with the deliveries their callbacks ran, dropped and hold\n\
+    \tinbox [duration] - print the messages received since the last inbox, waiting for one if a duration is given\n\
+    \tthreads - list the running internal threads\n\
+    \tchannels - show how many messages wait in each internal channel, and the most that did\n\
+    \tmemory - show the peer history kept, its estimated size and the evictions of its retention\n\
+    \treload - read the --config file again and apply the settings that can change while running\n\
+    \thelp - show this message\n\
+    \tquit - shut down gracefully"
+}
+
+/// Parses one line of console input.
+///
+/// # Arguments
+///
+/// * `line` - The raw line, surrounding whitespace is ignored.
+///
+/// # Returns
+///
+/// The parsed `Command`, or an `Err` with a message explaining what is wrong with the input.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or_else(|| "Empty command".to_owned())?;
+
+    // The text of a publication is the rest of the line, spaces included.
+    if name == "publish" {
+        let rest = line.trim_start()[name.len()..].trim_start();
+        let (topic, text) = rest
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| "Usage: publish <topic> <text>".to_owned())?;
+        return Ok(Command::Publish {
+            topic: topic.to_owned(),
+            text: text.trim().to_owned(),
+        });
+    }
+
+    // And the text of a versioned publication, after its topic and schema.
+    if name == "publish-schema" {
+        let usage = || "Usage: publish-schema <topic> <schema> <version> <text>".to_owned();
+        let rest = line.trim_start()[name.len()..].trim_start();
+        let mut parts = rest.splitn(4, char::is_whitespace);
+        let (Some(topic), Some(schema), Some(version), Some(text)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(usage());
+        };
+        let version = version
+            .parse()
+            .map_err(|_| format!("Invalid schema version \"{}\"", version))?;
+        return Ok(Command::PublishSchema {
+            topic: topic.to_owned(),
+            schema: Schema::new(schema, version),
+            text: text.trim().to_owned(),
+        });
+    }
+
+    // So is the text of an estimate, after the kind of message and its topic.
+    if name == "estimate" {
+        let usage = || "Usage: estimate text <text> | estimate topic <topic> <text>".to_owned();
+        let rest = line.trim_start()[name.len()..].trim_start();
+        let (kind, rest) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+        let rest = rest.trim_start();
+        return match kind {
+            "text" => Ok(Command::Estimate {
+                topic: None,
+                text: rest.trim().to_owned(),
+            }),
+            "topic" => {
+                let (topic, text) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+                Ok(Command::Estimate {
+                    topic: Some(topic.to_owned()),
+                    text: text.trim().to_owned(),
+                })
+            }
+            _ => Err(usage()),
+        };
+    }
+
+    // And the text of a scheduled message, after the delay, the kind and the topic.
+    if name == "schedule" {
+        let usage = || {
+            "Usage: schedule <delay> text <text> | schedule <delay> topic <topic> <text>".to_owned()
+        };
+        let rest = line.trim_start()[name.len()..].trim_start();
+        let (delay, rest) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+        let delay = parse_duration(delay)?;
+        let (kind, rest) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or_else(usage)?;
+        let rest = rest.trim_start();
+        return match kind {
+            "text" => Ok(Command::Schedule {
+                delay,
+                topic: None,
+                text: rest.trim().to_owned(),
+            }),
+            "topic" => {
+                let (topic, text) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+                Ok(Command::Schedule {
+                    delay,
+                    topic: Some(topic.to_owned()),
+                    text: text.trim().to_owned(),
+                })
+            }
+            _ => Err(usage()),
+        };
+    }
+
+    // So is the text of a request, after the address and the topic.
+    if name == "request" {
+        let usage = || "Usage: request <addr> <topic> <text>".to_owned();
+        let rest = line.trim_start()[name.len()..].trim_start();
+        let (addr, rest) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+        let (topic, text) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or_else(usage)?;
+        return Ok(Command::Request {
+            peer: addr
+                .parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+            topic: topic.to_owned(),
+            text: text.trim().to_owned(),
+        });
+    }
+
+    // A probe takes an optional count, then an optional interval, after the address.
+    if name == "probe" {
+        let usage = || "Usage: probe <addr> [count] [interval]".to_owned();
+        let addr = words.next().ok_or_else(usage)?;
+        let count = match words.next() {
+            Some(count) => count
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("Invalid probe count \"{}\"", count))?,
+            None => DEFAULT_PROBE_COUNT,
+        };
+        let interval = match words.next() {
+            Some(interval) => parse_duration(interval)?,
+            None => DEFAULT_PROBE_INTERVAL,
+        };
+        if interval.is_zero() {
+            return Err("The probe interval must be positive".to_owned());
+        }
+        if words.next().is_some() {
+            return Err(usage());
+        }
+        return Ok(Command::Probe {
+            peer: addr
+                .parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+            count,
+            interval,
+        });
+    }
+
+    // A mute takes an optional duration after the address.
+    if name == "mute" {
+        let usage = || "Usage: mute <addr> [duration]".to_owned();
+        let addr = words.next().ok_or_else(usage)?;
+        let duration = match words.next() {
+            Some(duration) => parse_duration(duration)?,
+            None => DEFAULT_MUTE_DURATION,
+        };
+        if words.next().is_some() {
+            return Err(usage());
+        }
+        return Ok(Command::Mute {
+            peer: addr
+                .parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+            duration,
+        });
+    }
+
+    // An eviction bans the address only when a duration follows it.
+    if name == "evict" {
+        let usage = || "Usage: evict <addr> [duration]".to_owned();
+        let addr = words.next().ok_or_else(usage)?;
+        let ban = words.next().map(parse_duration).transpose()?;
+        if words.next().is_some() {
+            return Err(usage());
+        }
+        return Ok(Command::Evict {
+            peer: addr
+                .parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+            ban,
+        });
+    }
+
+    let argument = words.next();
+
+    let command = match (name, argument) {
+        ("peers", None) => Command::Peers,
+        ("peers", Some("export-csv")) => Command::PeersCsv(PathBuf::from(
+            words
+                .next()
+                .ok_or_else(|| "Usage: peers export-csv <path>".to_owned())?,
+        )),
+        ("peer", Some(addr)) => Command::Peer(
+            addr.parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+        ),
+        ("peer", None) => return Err("Usage: peer <addr>".to_owned()),
+        ("provenance", Some(addr)) => Command::Provenance(
+            addr.parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+        ),
+        ("provenance", None) => return Err("Usage: provenance <addr>".to_owned()),
+        ("stats", None) => Command::Stats,
+        ("report", None) => Command::Report,
+        ("reannounce", None) => Command::Reannounce,
+        ("audit", None) => Command::Audit,
+        ("probe-status", None) => Command::ProbeStatus,
+        ("limits", None) => Command::Limits,
+        ("scheduled", None) => Command::Scheduled,
+        ("unschedule", Some(id)) => Command::Unschedule(
+            id.parse()
+                .map_err(|_| format!("Invalid scheduled message \"{}\"", id))?,
+        ),
+        ("unschedule", None) => return Err("Usage: unschedule <id>".to_owned()),
+        ("export", Some(path)) => Command::Export(PathBuf::from(path)),
+        ("export", None) => return Err("Usage: export <path>".to_owned()),
+        ("unmute", Some(addr)) => Command::Unmute(
+            addr.parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+        ),
+        ("unmute", None) => return Err("Usage: unmute <addr>".to_owned()),
+        ("unban", Some(addr)) => Command::Unban(
+            addr.parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+        ),
+        ("unban", None) => return Err("Usage: unban <addr>".to_owned()),
+        ("bans", None) => Command::Bans,
+        ("dials", Some("--recent")) => Command::Dials(None),
+        ("dials", Some(addr)) => Command::Dials(Some(
+            addr.parse()
+                .map_err(|_| format!("Invalid address \"{}\"", addr))?,
+        )),
+        ("dials", None) => return Err("Usage: dials <addr> | dials --recent".to_owned()),
+        ("subscribe", Some(topic)) => Command::Subscribe(topic.to_owned()),
+        ("subscribe", None) => return Err("Usage: subscribe <topic>".to_owned()),
+        ("subscribe-ordered", Some(topic)) => Command::SubscribeOrdered(topic.to_owned()),
+        ("subscribe-ordered", None) => return Err("Usage: subscribe-ordered <topic>".to_owned()),
+        ("subscribe-schema", Some(topic)) => {
+            let usage = || "Usage: subscribe-schema <topic> <schema> <versions>".to_owned();
+            let name = words.next().ok_or_else(usage)?;
+            Command::SubscribeSchema {
+                topic: topic.to_owned(),
+                name: name.to_owned(),
+                range: words.next().ok_or_else(usage)?.parse()?,
+            }
+        }
+        ("subscribe-schema", None) => {
+            return Err("Usage: subscribe-schema <topic> <schema> <versions>".to_owned())
+        }
+        ("subscribe-unsupported", Some(topic)) => Command::SubscribeUnsupported(topic.to_owned()),
+        ("subscribe-unsupported", None) => {
+            return Err("Usage: subscribe-unsupported <topic>".to_owned())
+        }
+        ("schema-support", Some(topic)) => Command::SchemaSupport {
+            topic: topic.to_owned(),
+            name: words
+                .next()
+                .ok_or_else(|| "Usage: schema-support <topic> <schema>".to_owned())?
+                .to_owned(),
+        },
+        ("schema-support", None) => return Err("Usage: schema-support <topic> <schema>".to_owned()),
+        ("unsubscribe", Some(id)) => Command::Unsubscribe(
+            id.parse()
+                .map_err(|_| format!("Invalid subscription \"{}\"", id))?,
+        ),
+        ("unsubscribe", None) => return Err("Usage: unsubscribe <id>".to_owned()),
+        ("subscriptions", None) => Command::Subscriptions,
+        ("inbox", Some(duration)) => Command::Inbox(Some(parse_duration(duration)?)),
+        ("inbox", None) => Command::Inbox(None),
+        ("threads", None) => Command::Threads,
+        ("channels", None) => Command::Channels,
+        ("memory", None) => Command::Memory,
+        ("reload", None) => Command::Reload,
+        ("help", None) => Command::Help,
+        ("quit", None) => Command::Quit,
+        _ => return Err(format!("Unknown command \"{}\"", line.trim())),
+    };
+
+    if words.next().is_some() {
+        return Err(format!("Unexpected arguments in \"{}\"", line.trim()));
+    }
+
+    Ok(command)
+}
+
+/// Parses a script of console commands separated by `;`, as given to `--exec`.
+///
+/// Empty commands, such as the one after a trailing `;`, are skipped. A `;` always separates
+/// commands, so the text of a `publish` or `request` in a script can not contain one.
+///
+/// # Arguments
+///
+/// * `script` - The commands, e.g. `peers; quit`.
+///
+/// # Returns
+///
+/// The parsed commands in order, or the error of the first command that does not parse.
+pub fn parse_script(script: &str) -> Result<Vec<Command>, String> {
+    script
+        .split(';')
+        .filter(|command| !command.trim().is_empty())
+        .map(parse_command)
+        .collect()
+}
+
+/// Decides whether the end of the standard input shuts the participant down.
+///
+/// # Arguments
+///
+/// * `choice` - The value of `--interactive-exit-on-eof`, if given.
+/// * `stdin_is_terminal` - Whether the standard input is a terminal.
+///
+/// # Returns
+///
+/// `choice` when given. Otherwise `true` for piped input, whose end means the commands are
+/// over, and `false` for a terminal, which only closes when it detaches.
+pub fn exit_on_eof(choice: Option<bool>, stdin_is_terminal: bool) -> bool {
+    choice.unwrap_or(!stdin_is_terminal)
+}
+
+/// Determines whether the standard input is a terminal.
+pub fn stdin_is_terminal() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Spawns the console thread running `script`, then reading commands from the standard input
+/// when `read_stdin` is set.
+///
+/// Each successfully parsed command is passed to `dispatch`; invalid input is reported on the
+/// standard error and otherwise ignored. When the standard input is closed, the thread
+/// dispatches `Command::Quit` if `exit_on_eof` is set and stops, leaving the participant
+/// running otherwise. A failed read is retried after a delay that doubles up to
+/// `MAX_READ_BACKOFF`, so a broken input never keeps a core busy.
+///
+/// # Arguments
+///
+/// * `threads` - The registry the console thread is spawned through, as `gossip-console`.
+/// * `script` - The commands given with `--exec`, dispatched first.
+/// * `read_stdin` - Whether commands are then read from the standard input.
+/// * `exit_on_eof` - Whether the end of the standard input shuts the participant down.
+/// * `dispatch` - Receives every command, on the console thread.
+pub fn spawn(
+    threads: &ThreadRegistry,
+    script: Vec<Command>,
+    read_stdin: bool,
+    exit_on_eof: bool,
+    dispatch: impl Fn(Command) + Send + 'static,
+) -> Result<(), SpawnError> {
+    // Blocked reading the standard input, the thread can not notice the shutdown.
+    threads.spawn("gossip-console", Join::Detach, move |_| {
+        for command in script {
+            dispatch(command);
+        }
+        if !read_stdin {
+            return;
+        }
+
+        let stdin = io::stdin();
+        let mut line = String::new();
+        let mut backoff = MIN_READ_BACKOFF;
+        loop {
+            line.clear();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => {
+                    if exit_on_eof {
+                        dispatch(Command::Quit);
+                    }
+                    return;
+                }
+                Ok(_) => backoff = MIN_READ_BACKOFF,
+                Err(err) => {
+                    print_error(&format!(
+                        "Can not read the console, retrying in {:?}: {}",
+                        backoff, err
+                    ));
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_READ_BACKOFF);
+                    continue;
+                }
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_command(&line) {
+                Ok(command) => dispatch(command),
+                Err(err) => print_error(&err.to_string()),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_script_is_split_on_semicolons() {
+        assert_eq!(
+            parse_script("peers; quit"),
+            Ok(vec![Command::Peers, Command::Quit])
+        );
+        assert_eq!(
+            parse_script("publish chat hello there;stats"),
+            Ok(vec![
+                Command::Publish {
+                    topic: "chat".to_owned(),
+                    text: "hello there".to_owned(),
+                },
+                Command::Stats,
+            ])
+        );
+        // Empty commands are skipped, wherever they are.
+        assert_eq!(parse_script(" ; stats;; "), Ok(vec![Command::Stats]));
+        assert_eq!(parse_script(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn a_script_fails_on_its_first_bad_command() {
+        assert_eq!(
+            parse_script("peers; fly; quit"),
+            Err("Unknown command \"fly\"".to_owned())
+        );
+        // A `;` ends the text of a publication.
+        assert_eq!(
+            parse_script("publish chat one; two"),
+            Err("Unknown command \"two\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn the_end_of_piped_input_quits_unless_told_otherwise() {
+        assert!(exit_on_eof(None, false));
+        assert!(!exit_on_eof(None, true));
+        for stdin_is_terminal in [false, true] {
+            assert!(exit_on_eof(Some(true), stdin_is_terminal));
+            assert!(!exit_on_eof(Some(false), stdin_is_terminal));
+        }
+    }
+}
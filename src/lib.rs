@@ -0,0 +1,131 @@
+//! Gossip P2P.
+//!
+//! A participant of a gossip network: it joins the network through a known peer, learns the
+//! other participants from the lists its peers share, and broadcasts a message to all of them
+//! every period. The `gossip_p2p` binary runs one from the command line; an application can
+//! embed one instead, configure it with a `ParticipantBuilder`, and exchange its own topic
+//! messages and requests through the `Participant` and the handles it hands out. Unlike the
+//! binary, a built participant keeps no identity file, uses no recent peers cache and prints
+//! no event unless the builder is asked to.
+//!
+//! ## The Supported API
+//!
+//! The items re-exported at the root of the crate are its API, and only they follow semantic
+//! versioning. The modules they live in are private, so they can move without breaking an
+//! embedder; `prelude` re-exports the ones most embedders import:
+//!
+//! ```no_run
+//! use gossip_p2p::prelude::*;
+//!
+//! fn run() -> Result<(), ParticipantError> {
+//!     let participant = ParticipantBuilder::new(std::time::Duration::from_secs(5))
+//!         .port(8080)
+//!         .build()?;
+//!     let inbox = participant.inbox();
+//!     let handle = participant.handle();
+//!     std::thread::spawn(move || {
+//!         while let Some(message) = inbox.recv_timeout(std::time::Duration::from_secs(60)) {
+//!             println!("{:?}", message);
+//!         }
+//!         handle.shutdown();
+//!     });
+//!     participant.run()
+//! }
+//! ```
+//!
+//! `tests/public-api.txt` lists these items, and `tests/api.rs` fails when they change; after
+//! an intended change, `UPDATE_PUBLIC_API=1 cargo test --test api` regenerates the list. The
+//! same test checks that the modules behind them stay out of reach.
+//!
+//! The enums that grow as the protocol does, the messages, the errors, the departure reasons, the alert
+//! events and the console commands, are `#[non_exhaustive]`, so a new variant is not a
+//! breaking change. A match on them needs a wildcard arm.
+//!
+//! A participant runs on the TCP transport of `message-io`; `Participant::with_transport` runs
+//! it on another implementation of `Transport`, such as the `InMemoryTransport` connecting
+//! the participants of one process without sockets.
+//!
+//! The `app` module holds the command-line application behind the binary, `bench` the bodies
+//! of the benchmarks, and `transcript` the replay of the wire transcripts of the tests. They
+//! are public only for the binary, the benchmarks and the tests to reach them, hidden from the
+//! documentation, and not part of the API.
+//!
+//! ## Modules
+//!
+//! - `admin`: Serves the console commands on a Unix domain socket, for `ctl` and scripts.
+//! - `app`: Runs the command-line application.
+//! - `bench`: Holds the operations the benchmarks measure, on fixtures of their own.
+//! - `cli`: Parses and interprets command-line arguments.
+//! - `console`: Reads and parses interactive console commands.
+//! - `participant`: Manages network participant logic, including message handling and
+//!   participant storage.
+//! - `printer`: Provides utilities for logging and output formatting.
+//! - `selftest`: Runs two participants against each other to check that the binary works.
+//! - `signal`: Turns the termination signals into a graceful shutdown, and `SIGHUP` into a
+//!   configuration reload.
+//! - `simulate`: Runs participants through a timeline of churn and chaos on an in-memory
+//!   network, as a scenario file describes it, and checks what the network holds.
+//! - `transcript`: Records and replays byte-level conversations with a participant, masking
+//!   the fields no two runs share.
+
+mod admin;
+#[doc(hidden)]
+pub mod app;
+#[doc(hidden)]
+pub mod bench;
+mod cli;
+mod console;
+mod participant;
+mod printer;
+mod selftest;
+mod signal;
+mod simulate;
+#[doc(hidden)]
+pub mod transcript;
+
+pub use console::Command;
+pub use participant::alert::{AlertAction, AlertEvent, AlertRule};
+pub use participant::anomaly::{Anomaly, AnomalyKind};
+pub use participant::builder::ParticipantBuilder;
+pub use participant::compactlist::{CompactListError, PackedList};
+pub use participant::config::{ListenPort, NodeConfig};
+pub use participant::dials::{DialOutcome, DialTrigger};
+pub use participant::error::ParticipantError;
+pub use participant::frame::{EstimateError, FrameEstimate, Limits, OutgoingMessage};
+pub use participant::handle::ParticipantHandle;
+pub use participant::identity::NodeId;
+pub use participant::inbox::{Inbox, ReceivedMessage};
+pub use participant::join::JoinCheck;
+pub use participant::message::builder::{
+    read_frame, AddressError, BuildError, FrameError, MessageBuilder,
+};
+pub use participant::message::{
+    AgeBucket, MembershipChange, Message, MessageKind, ObserverStats, SharedPeer, Trailer,
+};
+pub use participant::model::Participant;
+pub use participant::period::GossipPeriod;
+pub use participant::provenance::{LearnedVia, Provenance};
+pub use participant::reorder::Delivery;
+pub use participant::report::{PeerSetChanges, Report, ReportFormat, ReportedPeer};
+pub use participant::request::{RequestClient, RequestError};
+pub use participant::retention::{KindUsage, RetainedKind, RetentionPolicy};
+pub use participant::schedule::{Due, Missed, ScheduleError, ScheduleHandle, ScheduledEntry};
+pub use participant::schema::{Schema, VersionRange};
+pub use participant::session::DepartureReason;
+pub use participant::skew::ClockOffset;
+pub use participant::storage::{Direction, KindCounters};
+pub use participant::topic::{SubscriptionHandle, TopicError};
+pub use participant::transport::{
+    Connection, InMemoryNetwork, InMemoryTransport, LinkConditions, ListenerId, MessageIoTransport,
+    OwnedTransportEvent, SendOutcome, Transport, TransportContext, TransportEvent,
+};
+pub use participant::verify::{Mismatch, VerificationFailure, VerificationMode};
+
+/// The items most embedders import, for a single `use gossip_p2p::prelude::*`.
+pub mod prelude {
+    pub use crate::{
+        Command, Delivery, InMemoryNetwork, Inbox, NodeConfig, Participant, ParticipantBuilder,
+        ParticipantError, ParticipantHandle, ReceivedMessage, RequestClient, RequestError, Schema,
+        SubscriptionHandle, TopicError, VersionRange,
+    };
+}
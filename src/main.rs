@@ -9,21 +9,39 @@
 //!
 //! - Parses command-line arguments to configure the network participant's behavior.
 //! - Supports starting a participant as part of an existing network or as the first node in a new network.
-//! - Utilizes submodules for specific functionalities:
-//!   - `cli`: Parses and interprets command-line arguments.
-//!   - `participant`: Manages network participant logic, including message handling and participant storage.
-//!   - `printer`: Provides utilities for logging and output formatting.
+//! - Runs on the `gossip_p2p` library, whose hidden `app` module holds the whole application.
 //!
 //! ## Usage
 //!
 //! The application requires specific command-line arguments to run, including the period for
-//! sending messages and the port to listen on. Optionally, it can connect to an existing network
+//! sending messages (`5`, `250ms`, `1.5s`, `15m` or `2h`) and the port to listen on. Optionally, it can connect to an existing network
 //! participant to join the network.
 //!
 //! ```plaintext
-//! Usage: my_network_app --period=<period> --port=<port> [--connect=<address>]
+//! Usage: my_network_app --period=[auto:]<duration> [--min-period=<duration>] [--max-period=<duration>] [--rounds] [--legacy-payload] --port=<port> [--connect=<address>] [--listen=<addresses>] [--advertise=<addresses>] [--no-peer-cache] [--network=<name>] [--half-open-threshold=<messages>] [--period-skew-ratio=<n>] [--max-clock-offset=<duration>] [--balance-connections] [--min-outbound-share=<percent>] [--require-bootstrap] [--join-check=<k>,<timeout>] [--expect-network-id=<name>] [--expect-node-id=<hex>] [--bootstrap-verification=<fail|warn>] [--config=<path>] [--check] [--strict-config] [--strict-protocol] [--validate-outbound] [--no-hints] [--interactive] [--interactive-exit-on-eof=<true|false>] [--exec=<commands>] [--admin-socket=<path>] [--max-observers=<n>] [--dedup-entries=<n>] [--ratelimit-entries=<n>] [--block=<addresses>] [--filter-file=<path>] [--allow-privileged-ports] [--allow-special-ranges] [--topics=<topics>] [--topic-broadcast=<topics>] [--reorder-window-ms=<n>] [--reorder-buffer=<n>] [--audit-every=<ticks>] [--share-limit=<n>] [--corroboration=<n>] [--per-peer-send-budget-ms=<n>] [--batch-window-ms=<n>] [--late-tick-factor=<n>] [--frozen-tick-factor=<n>] [--connect-timeout=<duration>] [--dial-memory=<duration>] [--heal-probe-interval=<duration>] [--resolve-interval=<duration>] [--warmup=<duration>] [--busy-lag=<duration>] [--overload-lag=<duration>] [--trace-ticks=<n>] [--inbox-capacity=<n>] [--max-scheduled=<n>] [--callback-workers=<n>] [--max-threads=<n>] [--fd-headroom=<n>] [--import-membership=<path>] [--max-snapshot-age=<duration>] [--require-signed-import] [--trusted-snapshot-key=<hex>] [--state-file=<path>] [--state-save-interval=<duration>] [--max-state-age=<duration>] [--retain-sessions=<entries>,<age>[,<bytes>]] [--retain-departed=<entries>,<age>[,<bytes>]] [--retain-lists=<entries>,<age>[,<bytes>]] [--identity-file=<path>] [--node-label=<label>] [--report-file=<path>] [--report-format=<json|csv|both>] [--record=<path>] [--record-snapshot-every=<duration>] [--alert=<event>:<action>] [--alert-cooldown=<duration>] [--help] [--version]
+//!        my_network_app replay --file=<path> [--fast]
+//!        my_network_app analyze --file=<path> [--at=<duration>]
+//!        my_network_app ctl --socket=<path> [--pretty] <command> [<arguments>]
+//!        my_network_app selftest
 //! ```
 //!
+//! ## Exit Codes
+//!
+//! - `0`: clean shutdown, including Ctrl-C, a successful `--check` or a successful `ctl`
+//!   command.
+//! - `2`: invalid arguments or configuration, including an unusable membership snapshot,
+//!   filter file or recording to replay or analyze, a command refused by the participant `ctl` sent it
+//!   to, or an internal thread the participant can not start, over `--max-threads`.
+//! - `3`: the listen address or the admin socket can not be bound.
+//! - `4`: no bootstrap participant could be reached while `--require-bootstrap` is set, or
+//!   fewer peers than `--join-check` asks for completed their handshake in time.
+//! - `5`: a local state file, such as the recent peers cache, can not be read or created.
+//! - `6`: `ctl` can not reach the admin socket or read the response.
+//! - `7`: a `selftest` stage did not pass.
+//! - `8`: a peer caused a protocol anomaly while `--strict-protocol` is set.
+//! - `9`: the bootstrap target did not present the node id of `--expect-node-id`, and no other
+//!   bootstrap candidate could be reached.
+//!
 //! ## Example
 //!
 //! Starting a new network participant on port 8080 with a message sending period of 5 seconds:
@@ -38,56 +56,6 @@
 //! cargo run -- --period=5 --port=8080 --connect=127.0.0.1:8081
 //! ```
 
-mod cli;
-mod participant;
-mod printer;
-
-pub fn main() {
-    let args: Vec<String> = std::env::args().collect();
-
-    // Ensure that the necessary arguments are provided, otherwise display the help message.
-    if args.len() < 3 {
-        eprintln!("{}", cli::get_help_message(&args[0]));
-        std::process::exit(1);
-    }
-
-    // Parse the command-line arguments and configure the application accordingly.
-    match cli::parse_arguments(&args[1..]) {
-        Ok(cli_args) => {
-            // Determine if the participant is the first in the network or joining an existing one.
-            let not_first_participant = cli_args.connect.is_some();
-
-            if not_first_participant {
-                let participant_or_server = participant::model::Participant::new(
-                    cli_args.period.try_into().unwrap(),
-                    cli_args.port.into(),
-                    cli_args.connect,
-                );
-                match participant_or_server {
-                    Ok(instance) => instance.run(),
-                    Err(e) => {
-                        eprintln!("Failed to create instance: {}", e);
-                        std::process::exit(1);
-                    }
-                }
-            } else {
-                let participant_or_server = participant::model::Participant::new(
-                    cli_args.period.try_into().unwrap(),
-                    cli_args.port.into(),
-                    None,
-                );
-                match participant_or_server {
-                    Ok(instance) => instance.run(),
-                    Err(err) => {
-                        eprintln!("Can not run the instance: {}", err);
-                        std::process::exit(1);
-                    }
-                }
-            }
-        }
-        Err(_) => {
-            eprintln!("{}", cli::get_help_message(&args[0]));
-            std::process::exit(1);
-        }
-    }
+fn main() {
+    gossip_p2p::app::main()
 }
@@ -0,0 +1,592 @@
+//! Alert Hooks.
+//!
+//! An operator can ask to be told about a few significant events without scraping the logs.
+//! Each `--alert=<event>:<action>` flag binds an `AlertEvent` to an `AlertAction`: a shell
+//! command run with the details of the event in `GOSSIP_ALERT_*` environment variables, or an
+//! HTTP `POST` of the same details as JSON. The flag may be repeated to bind several actions.
+//!
+//! Actions run one after the other on a dedicated thread, each within a hard timeout, so a
+//! hanging command or an unreachable web hook never delays the event loop. Every event type
+//! alerts at most once per `--alert-cooldown`; the alerts suppressed in the meantime are
+//! counted and reported with the next one. A failing action is logged and otherwise ignored.
+
+use crate::participant::channels::{ChannelRegistry, MonitoredSender};
+use crate::participant::descriptors::{DescriptorBudget, Exhausted};
+use crate::participant::threads::{Join, SpawnError, ThreadRegistry};
+use crate::printer::print_event;
+
+use serde::{Serialize, Serializer};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The longest time an `exec` action may run before it is killed.
+pub const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest time an `http` action may take to connect, and then to send and be answered.
+pub const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a running `exec` action is checked for completion.
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The prefix of the environment variables given to `exec` actions.
+const ENV_PREFIX: &str = "GOSSIP_ALERT_";
+
+/// A significant event an operator may be alerted about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AlertEvent {
+    /// The last connected participant is gone.
+    Isolated,
+    /// A peer was disconnected for violating the protocol.
+    PeerEvicted,
+    /// Every bootstrap target was tried without success.
+    BootstrapFailed,
+    /// A participant announced a public address already used by another connection, or a node
+    /// id already presented from another address.
+    ConflictDetected,
+}
+
+impl AlertEvent {
+    /// Every event, in the order of the help message.
+    pub const ALL: [AlertEvent; 4] = [
+        AlertEvent::Isolated,
+        AlertEvent::PeerEvicted,
+        AlertEvent::BootstrapFailed,
+        AlertEvent::ConflictDetected,
+    ];
+
+    /// Returns the name of this event on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertEvent::Isolated => "isolated",
+            AlertEvent::PeerEvicted => "peer-evicted",
+            AlertEvent::BootstrapFailed => "bootstrap-failed",
+            AlertEvent::ConflictDetected => "conflict-detected",
+        }
+    }
+
+    /// Returns the event called `name` on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.name() == name)
+    }
+}
+
+impl fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What is done when an event happens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertAction {
+    /// Runs a shell command with the details of the event in its environment.
+    Exec(String),
+    /// Posts the details of the event as JSON to a plain HTTP endpoint.
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+}
+
+impl AlertAction {
+    /// Parses an action: `exec:<command>` or `http:http://<host>[:<port>][/<path>]`.
+    fn parse(value: &str) -> Result<Self, String> {
+        if let Some(command) = value.strip_prefix("exec:") {
+            if command.trim().is_empty() {
+                return Err("an exec action needs a command".to_owned());
+            }
+            return Ok(AlertAction::Exec(command.to_owned()));
+        }
+        let Some(url) = value.strip_prefix("http:") else {
+            return Err(format!(
+                "unknown action \"{}\", expected exec:<command> or http:<url>",
+                value
+            ));
+        };
+        let Some(rest) = url.strip_prefix("http://") else {
+            return Err(format!(
+                "\"{}\" is not an http:// URL; https is not supported",
+                url
+            ));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port in \"{}\"", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("no host in \"{}\"", url));
+        }
+        Ok(AlertAction::Http {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for AlertAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertAction::Exec(command) => write!(f, "exec:{}", command),
+            AlertAction::Http { host, port, path } => {
+                write!(f, "http:http://{}:{}{}", host, port, path)
+            }
+        }
+    }
+}
+
+/// An action bound to an event by an `--alert` flag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertRule {
+    pub event: AlertEvent,
+    pub action: AlertAction,
+}
+
+impl AlertRule {
+    /// Parses the value of an `--alert` flag, `<event>:<action>`.
+    ///
+    /// # Returns
+    ///
+    /// The rule, or an explanation of what is wrong with `value`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (event, action) = value
+            .split_once(':')
+            .ok_or_else(|| format!("\"{}\" is not <event>:<action>", value))?;
+        if event == "panic-restart" {
+            return Err(
+                "panic-restart never happens, since this build has no restart supervisor"
+                    .to_owned(),
+            );
+        }
+        let event = AlertEvent::from_name(event).ok_or_else(|| {
+            let names: Vec<&str> = AlertEvent::ALL.iter().map(|event| event.name()).collect();
+            format!(
+                "unknown event \"{}\", expected one of {}",
+                event,
+                names.join(", ")
+            )
+        })?;
+        Ok(Self {
+            event,
+            action: AlertAction::parse(action)?,
+        })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.event, self.action)
+    }
+}
+
+/// One occurrence of an event, as handed to its actions.
+#[derive(Debug, Clone, Serialize)]
+struct Alert {
+    #[serde(serialize_with = "event_name")]
+    event: AlertEvent,
+    node: SocketAddr,
+    time: u64,
+    suppressed: u64,
+    #[serde(serialize_with = "details_object")]
+    details: Vec<(&'static str, String)>,
+}
+
+impl Alert {
+    /// Returns the environment variables of an `exec` action.
+    fn env(&self) -> Vec<(String, String)> {
+        let mut env = vec![
+            (format!("{}EVENT", ENV_PREFIX), self.event.name().to_owned()),
+            (format!("{}NODE", ENV_PREFIX), self.node.to_string()),
+            (format!("{}TIME", ENV_PREFIX), self.time.to_string()),
+            (
+                format!("{}SUPPRESSED", ENV_PREFIX),
+                self.suppressed.to_string(),
+            ),
+        ];
+        for (key, value) in &self.details {
+            env.push((
+                format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase()),
+                value.clone(),
+            ));
+        }
+        env
+    }
+
+    /// Returns the JSON body of an `http` action.
+    fn json(&self) -> String {
+        serde_json::to_string(self).expect("an alert serializes")
+    }
+}
+
+/// Serializes an event as its name.
+fn event_name<S: Serializer>(event: &AlertEvent, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(event.name())
+}
+
+/// Serializes the details of an alert as a JSON object, in their order.
+fn details_object<S: Serializer>(
+    details: &[(&'static str, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(details.iter().map(|(key, value)| (key, value)))
+}
+
+/// Raises the alerts of a participant, rate limited per event type.
+pub struct Alerter {
+    rules: Vec<AlertRule>,
+    cooldown: Duration,
+    last_raised: HashMap<AlertEvent, Instant>,
+    suppressed: HashMap<AlertEvent, u64>,
+    raised: u64,
+    worker: Option<MonitoredSender<(AlertAction, Alert)>>,
+}
+
+impl Alerter {
+    /// Creates the alerter of `rules`, with its worker thread, `gossip-alert`, if there is any
+    /// rule.
+    ///
+    /// # Parameters
+    ///
+    /// * `rules` - The actions bound to events.
+    /// * `cooldown` - The shortest time between two alerts of the same event type.
+    /// * `time_start` - The start of the participant, for the timestamps of the log lines.
+    /// * `threads` - The registry the worker, and the readers of the commands it runs, are
+    ///   spawned through.
+    /// * `channels` - The registry the queue of the worker, `alerts`, is created through.
+    /// * `descriptors` - The budget the connection of each `http` action takes a permit from.
+    pub fn new(
+        rules: Vec<AlertRule>,
+        cooldown: Duration,
+        time_start: Arc<Instant>,
+        threads: &ThreadRegistry,
+        channels: &ChannelRegistry,
+        descriptors: &DescriptorBudget,
+    ) -> Result<Self, SpawnError> {
+        let mut worker = None;
+        if !rules.is_empty() {
+            let (sender, alerts) = channels.channel::<(AlertAction, Alert)>("alerts");
+            let readers = threads.clone();
+            let descriptors = descriptors.clone();
+            threads.spawn("gossip-alert", Join::Wait, move |_| {
+                while let Ok((action, alert)) = alerts.recv() {
+                    let outcome = match &action {
+                        AlertAction::Exec(command) => run_exec(command, &alert, &readers),
+                        AlertAction::Http { host, port, path } => {
+                            post_http(host, *port, path, &alert, &descriptors)
+                        }
+                    };
+                    let formatted_msg = match outcome {
+                        Ok(report) => format!("Alert {} ran {}: {}", alert.event, action, report),
+                        Err(err) => {
+                            format!("Alert {} failed to run {}: {}", alert.event, action, err)
+                        }
+                    };
+                    print_event(time_start.clone(), &formatted_msg);
+                }
+            })?;
+            worker = Some(sender);
+        }
+
+        Ok(Self {
+            rules,
+            cooldown,
+            last_raised: HashMap::new(),
+            suppressed: HashMap::new(),
+            raised: 0,
+            worker,
+        })
+    }
+
+    /// Sets the shortest time between two alerts of the same event, from the next alert on.
+    pub fn set_cooldown(&mut self, cooldown: Duration) {
+        self.cooldown = cooldown;
+    }
+
+    /// Stops the worker once it ran the alerts already raised; later alerts are dropped.
+    pub fn close(&mut self) {
+        self.worker = None;
+    }
+
+    /// Hands `event` to its actions, unless the same event type alerted less than the
+    /// cooldown ago.
+    ///
+    /// # Parameters
+    ///
+    /// * `event` - The event that happened.
+    /// * `node` - The public address of this participant.
+    /// * `details` - What happened, as lowercase keys and their values.
+    /// * `now` - The current time.
+    pub fn raise(
+        &mut self,
+        event: AlertEvent,
+        node: SocketAddr,
+        details: Vec<(&'static str, String)>,
+        now: Instant,
+    ) {
+        let Some(worker) = &self.worker else {
+            return;
+        };
+        if !self.rules.iter().any(|rule| rule.event == event) {
+            return;
+        }
+        let cooling = self
+            .last_raised
+            .get(&event)
+            .is_some_and(|last| now.saturating_duration_since(*last) < self.cooldown);
+        if cooling {
+            *self.suppressed.entry(event).or_default() += 1;
+            return;
+        }
+        self.last_raised.insert(event, now);
+        self.raised += 1;
+
+        let alert = Alert {
+            event,
+            node,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            suppressed: self.suppressed.remove(&event).unwrap_or_default(),
+            details,
+        };
+        for rule in self.rules.iter().filter(|rule| rule.event == event) {
+            let _ = worker.send((rule.action.clone(), alert.clone()));
+        }
+    }
+
+    /// Returns the number of alerts raised.
+    pub fn raised(&self) -> u64 {
+        self.raised
+    }
+
+    /// Returns the number of alerts suppressed by the cooldown and not reported yet.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.values().sum()
+    }
+}
+
+/// Runs `command` with the shell, killing it after `EXEC_TIMEOUT`.
+///
+/// The output is read by threads of `threads` named `gossip-alert-io`; a command whose
+/// output can not be read is killed at once.
+///
+/// # Returns
+///
+/// The exit status and the output of the command, or why it could not complete.
+fn run_exec(command: &str, alert: &Alert, threads: &ThreadRegistry) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(alert.env())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    // The output is drained while the command runs, so a chatty command never blocks on a
+    // full pipe. A reader left behind by a killed command's children is abandoned.
+    let (output, captured) = mpsc::channel();
+    for mut stream in [
+        child
+            .stdout
+            .take()
+            .map(|out| Box::new(out) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|err| Box::new(err) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let output = output.clone();
+        let reader = threads.spawn("gossip-alert-io", Join::Detach, move |_| {
+            let mut text = Vec::new();
+            let _ = stream.read_to_end(&mut text);
+            let _ = output.send(String::from_utf8_lossy(&text).into_owned());
+        });
+        if let Err(err) = reader {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("can not read its output: {}", err));
+        }
+    }
+    drop(output);
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|err| err.to_string())? {
+            Some(status) => break status,
+            None if started.elapsed() >= EXEC_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("killed after {:?}", EXEC_TIMEOUT));
+            }
+            None => thread::sleep(EXEC_POLL_INTERVAL),
+        }
+    };
+
+    let mut text = String::new();
+    while let Ok(part) = captured.recv_timeout(EXEC_POLL_INTERVAL) {
+        text.push_str(&part);
+    }
+    let text = text.trim();
+    if !status.success() {
+        return Err(format!("{} [{}]", status, text));
+    }
+    Ok(format!("{} [{}]", status, text))
+}
+
+/// Posts the details of `alert` as JSON to `http://<host>:<port><path>`.
+///
+/// # Returns
+///
+/// The status line of the response, or why there is none or it is not a success.
+fn post_http(
+    host: &str,
+    port: u16,
+    path: &str,
+    alert: &Alert,
+    descriptors: &DescriptorBudget,
+) -> Result<String, String> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| format!("\"{}\" has no address", host))?;
+    let _permit = descriptors.acquire().ok_or_else(|| Exhausted.to_string())?;
+    let mut stream =
+        TcpStream::connect_timeout(&addr, HTTP_TIMEOUT).map_err(|err| err.to_string())?;
+    stream
+        .set_write_timeout(Some(HTTP_TIMEOUT))
+        .and_then(|()| stream.set_read_timeout(Some(HTTP_TIMEOUT)))
+        .map_err(|err| err.to_string())?;
+
+    let body = alert.json();
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        port,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|err| err.to_string())?;
+
+    let mut response = [0; 256];
+    let read = stream.read(&mut response).map_err(|err| err.to_string())?;
+    let response = String::from_utf8_lossy(&response[..read]);
+    let status_line = response.lines().next().unwrap_or_default().to_owned();
+    let success = status_line
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|code| code.starts_with('2'));
+    if success {
+        Ok(status_line)
+    } else {
+        Err(format!("answered \"{}\"", status_line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for event in AlertEvent::ALL {
+            assert_eq!(AlertEvent::from_name(event.name()), Some(event));
+        }
+        assert_eq!(AlertEvent::from_name("panic-restart"), None);
+    }
+
+    #[test]
+    fn parses_exec_and_http_rules() {
+        let rule = AlertRule::parse("isolated:exec:notify-send alone").unwrap();
+        assert_eq!(rule.event, AlertEvent::Isolated);
+        assert_eq!(
+            rule.action,
+            AlertAction::Exec("notify-send alone".to_owned())
+        );
+
+        let rule = AlertRule::parse("peer-evicted:http:http://hooks.local:8080/gossip").unwrap();
+        assert_eq!(
+            rule.action,
+            AlertAction::Http {
+                host: "hooks.local".to_owned(),
+                port: 8080,
+                path: "/gossip".to_owned(),
+            }
+        );
+        assert_eq!(
+            rule.to_string(),
+            "peer-evicted:http:http://hooks.local:8080/gossip"
+        );
+
+        let rule = AlertRule::parse("bootstrap-failed:http:http://hooks.local").unwrap();
+        assert_eq!(
+            rule.to_string(),
+            "bootstrap-failed:http:http://hooks.local:80/"
+        );
+    }
+
+    #[test]
+    fn refuses_panic_restart() {
+        let error = AlertRule::parse("panic-restart:exec:true").unwrap_err();
+        assert!(error.contains("no restart supervisor"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_malformed_rules() {
+        for value in [
+            "isolated",
+            "unknown:exec:true",
+            "isolated:exec: ",
+            "isolated:ftp:host",
+            "isolated:http:https://host/",
+            "isolated:http:http://:80/",
+            "isolated:http:http://host:port/",
+        ] {
+            assert!(AlertRule::parse(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn the_http_body_is_one_json_object() {
+        let alert = Alert {
+            event: AlertEvent::PeerEvicted,
+            node: "127.0.0.1:8080".parse().unwrap(),
+            time: 1_760_400_000,
+            suppressed: 2,
+            details: vec![
+                ("peer", "127.0.0.1:8081".to_owned()),
+                ("reason", "said \"hi\"\n\u{1}".to_owned()),
+            ],
+        };
+        assert_eq!(
+            alert.json(),
+            r#"{"event":"peer-evicted","node":"127.0.0.1:8080","time":1760400000,"suppressed":2,"details":{"peer":"127.0.0.1:8081","reason":"said \"hi\"\n\u0001"}}"#
+        );
+    }
+}
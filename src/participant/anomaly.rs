@@ -0,0 +1,207 @@
+//! Protocol Anomalies.
+//!
+//! A participant recovers from whatever a peer gets wrong: a frame that does not decode is
+//! dropped, a first frame of another protocol or a message the state of its sender does not
+//! allow closes the connection, an echo of our own texts is dropped and a topic no publisher
+//! could have used is refused. That keeps a network running, and hides the bug of the peer.
+//! Each of these recoveries is an `AnomalyKind`, counted by the `AnomalyPolicy` of the
+//! participant and shown in `stats` and the report.
+//!
+//! With `--strict-protocol`, meant for fuzzing and CI clusters, the first anomaly is fatal:
+//! the participant logs it, with its kind, its peer and what it was about, stops its event
+//! loop and fails with `ParticipantError::Anomaly`, which the application turns into an exit
+//! code of its own. The anomaly is still recovered from as usual until the loop stops.
+//!
+//! A reply arriving after its request timed out is no anomaly: it is a race any peer loses
+//! under load, so the stray replies keep their own counters.
+
+use std::fmt;
+use std::net::SocketAddr;
+
+/// A recoverable anomaly of the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AnomalyKind {
+    /// A frame of an established connection does not decode.
+    Undecodable,
+    /// The first frame of a connection does not belong to this protocol.
+    ProtocolMismatch,
+    /// A message the state of its sender does not allow, such as a nested batch.
+    Violation,
+    /// A text or handshake of this participant sent back.
+    Echo,
+    /// A topic message whose topic no publisher could have used.
+    InvalidTopic,
+}
+
+impl AnomalyKind {
+    /// The number of kinds, i.e. the length of a per-kind array.
+    pub const COUNT: usize = 5;
+
+    /// Every kind, in array order.
+    pub const ALL: [AnomalyKind; AnomalyKind::COUNT] = [
+        AnomalyKind::Undecodable,
+        AnomalyKind::ProtocolMismatch,
+        AnomalyKind::Violation,
+        AnomalyKind::Echo,
+        AnomalyKind::InvalidTopic,
+    ];
+
+    /// Returns the name of this kind, as in `stats` and the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            AnomalyKind::Undecodable => "undecodable",
+            AnomalyKind::ProtocolMismatch => "protocol_mismatch",
+            AnomalyKind::Violation => "violation",
+            AnomalyKind::Echo => "echo",
+            AnomalyKind::InvalidTopic => "invalid_topic",
+        }
+    }
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An anomaly, as escalated in strict mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    /// What went wrong.
+    pub kind: AnomalyKind,
+    /// The address of the peer at fault, its public one when known.
+    pub peer: SocketAddr,
+    /// What the anomaly was about, such as the size of the frame or the kind of the message.
+    pub context: String,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from \"{}\": {}", self.kind, self.peer, self.context)
+    }
+}
+
+/// Counts the anomalies of a participant, and escalates the first one in strict mode.
+#[derive(Debug)]
+pub struct AnomalyPolicy {
+    strict: bool,
+    counts: [u64; AnomalyKind::COUNT],
+    escalated: bool,
+}
+
+impl AnomalyPolicy {
+    /// Creates a policy that counted nothing yet, escalating when `strict`.
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            counts: [0; AnomalyKind::COUNT],
+            escalated: false,
+        }
+    }
+
+    /// Determines whether anomalies are fatal.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Counts an anomaly of `kind` caused by `peer`; `context` is only built when escalating.
+    ///
+    /// # Returns
+    ///
+    /// The anomaly to fail with, for the first one in strict mode.
+    pub fn record(
+        &mut self,
+        kind: AnomalyKind,
+        peer: SocketAddr,
+        context: impl FnOnce() -> String,
+    ) -> Option<Anomaly> {
+        self.counts[kind as usize] += 1;
+        if !self.strict || self.escalated {
+            return None;
+        }
+        self.escalated = true;
+        Some(Anomaly {
+            kind,
+            peer,
+            context: context(),
+        })
+    }
+
+    /// Returns the number of anomalies of `kind` so far.
+    pub fn count(&self, kind: AnomalyKind) -> u64 {
+        self.counts[kind as usize]
+    }
+
+    /// Returns the number of anomalies of each kind so far, in `AnomalyKind::ALL` order.
+    pub fn counts(&self) -> [u64; AnomalyKind::COUNT] {
+        self.counts
+    }
+
+    /// Returns the number of anomalies of any kind so far.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8080))
+    }
+
+    #[test]
+    fn lists_every_kind_in_array_order() {
+        for (index, kind) in AnomalyKind::ALL.into_iter().enumerate() {
+            // Without a wildcard, a new kind does not build until it is listed here too.
+            let listed = match kind {
+                AnomalyKind::Undecodable
+                | AnomalyKind::ProtocolMismatch
+                | AnomalyKind::Violation
+                | AnomalyKind::Echo
+                | AnomalyKind::InvalidTopic => AnomalyKind::ALL.contains(&kind),
+            };
+            assert!(listed);
+            assert_eq!(kind as usize, index, "{} is out of order", kind);
+        }
+        let mut names: Vec<&str> = AnomalyKind::ALL.iter().map(|kind| kind.name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), AnomalyKind::COUNT);
+    }
+
+    #[test]
+    fn counts_every_kind_without_escalating() {
+        let mut policy = AnomalyPolicy::new(false);
+        for (times, kind) in AnomalyKind::ALL.into_iter().enumerate() {
+            for _ in 0..=times {
+                let escalated = policy.record(kind, peer(), || panic!("context built"));
+                assert_eq!(escalated, None);
+            }
+        }
+        assert_eq!(policy.counts(), [1, 2, 3, 4, 5]);
+        assert_eq!(policy.count(AnomalyKind::Echo), 4);
+        assert_eq!(policy.total(), 15);
+        assert!(!policy.is_strict());
+    }
+
+    #[test]
+    fn escalates_the_first_anomaly_once_when_strict() {
+        let mut policy = AnomalyPolicy::new(true);
+        let anomaly = policy
+            .record(AnomalyKind::Violation, peer(), || {
+                "a nested batch".to_owned()
+            })
+            .unwrap();
+        assert_eq!(
+            anomaly.to_string(),
+            "violation from \"127.0.0.1:8080\": a nested batch"
+        );
+        // The loop is stopping: later anomalies are counted, not escalated again.
+        let later = policy.record(AnomalyKind::Undecodable, peer(), || panic!("context built"));
+        assert_eq!(later, None);
+        assert_eq!(policy.total(), 2);
+    }
+}
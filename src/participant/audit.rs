@@ -0,0 +1,215 @@
+//! Storage Consistency Audit.
+//!
+//! Several features keep state next to `ParticipantsStorage`: the rate limiter buckets, the
+//! list origins remembered for introductions, the public address index. Each is maintained by
+//! its own event handlers, and a missed event (a connection removed without a `Disconnected`,
+//! a race between a re-announcement and a drop) leaves them disagreeing with the storage.
+//!
+//! This module holds the invariant checks run by the participant's periodic audit. Every check
+//! is a plain function over iterators borrowed from the audited collections: it walks them
+//! once, allocates only for the findings it reports, and leaves the repair to the caller.
+
+use crate::participant::utils::same_addr;
+
+use std::fmt;
+use std::net::SocketAddr;
+
+/// The counts reported at the end of an audit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AuditSummary {
+    /// The number of entries checked over every invariant.
+    pub checked: usize,
+    /// The number of inconsistencies fixed.
+    pub repaired: usize,
+    /// The number of inconsistencies left in place.
+    pub unrepairable: usize,
+}
+
+impl fmt::Display for AuditSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Audit checked {} entries: {} repaired, {} unrepairable",
+            self.checked, self.repaired, self.unrepairable
+        )
+    }
+}
+
+/// Collects the keys of an auxiliary map that have no matching participant.
+///
+/// # Parameters
+///
+/// * `keys` - The keys of the auxiliary map, copied out of it.
+/// * `is_stored` - Determines whether the participant a key refers to is stored.
+/// * `orphans` - Receives the keys without a participant.
+///
+/// # Returns
+///
+/// The number of checked keys.
+pub fn orphaned_keys<K>(
+    keys: impl IntoIterator<Item = K>,
+    is_stored: impl Fn(&K) -> bool,
+    orphans: &mut Vec<K>,
+) -> usize {
+    let mut checked = 0;
+    for key in keys {
+        checked += 1;
+        if !is_stored(&key) {
+            orphans.push(key);
+        }
+    }
+    checked
+}
+
+/// Collects the stored endpoints that the network layer no longer knows.
+///
+/// # Parameters
+///
+/// * `endpoints` - The endpoints of the stored participants, copied out of the storage.
+/// * `is_live` - Determines whether the network layer still holds an endpoint's connection.
+/// * `dead` - Receives the endpoints whose connection is gone.
+///
+/// # Returns
+///
+/// The number of checked endpoints.
+pub fn dead_endpoints<T>(
+    endpoints: impl IntoIterator<Item = T>,
+    is_live: impl Fn(&T) -> bool,
+    dead: &mut Vec<T>,
+) -> usize {
+    orphaned_keys(endpoints, is_live, dead)
+}
+
+/// Collects the public addresses announced by more than one stored participant.
+///
+/// # Parameters
+///
+/// * `index` - The public address index, as addresses with the endpoints announcing them.
+/// * `shared` - Receives each shared address with the number of endpoints announcing it.
+///
+/// # Returns
+///
+/// The number of checked addresses.
+pub fn shared_public_addresses<'a, T: 'a>(
+    index: impl IntoIterator<Item = (SocketAddr, &'a [T])>,
+    shared: &mut Vec<(SocketAddr, usize)>,
+) -> usize {
+    let mut checked = 0;
+    for (public, endpoints) in index {
+        checked += 1;
+        if endpoints.len() > 1 {
+            shared.push((public, endpoints.len()));
+        }
+    }
+    checked
+}
+
+/// Determines whether the public address index disagrees with the stored participants.
+///
+/// The index agrees when it lists every stored endpoint exactly once, under the public
+/// address the storage holds for it, compared by canonical form.
+///
+/// # Parameters
+///
+/// * `index` - The public address index, as addresses with the endpoints announcing them.
+/// * `public_of` - Returns the stored public address of an endpoint, if it is stored.
+/// * `stored` - The number of stored participants.
+pub fn index_mismatch<'a, T: 'a>(
+    index: impl IntoIterator<Item = (SocketAddr, &'a [T])>,
+    public_of: impl Fn(&T) -> Option<SocketAddr>,
+    stored: usize,
+) -> bool {
+    let mut indexed = 0;
+    for (public, endpoints) in index {
+        for endpoint in endpoints {
+            indexed += 1;
+            if !public_of(endpoint).is_some_and(|stored| same_addr(stored, public)) {
+                return true;
+            }
+        }
+    }
+    indexed != stored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    use std::collections::HashMap;
+
+    fn mapped(port: u16) -> SocketAddr {
+        format!("[::ffff:127.0.0.1]:{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn collects_the_keys_without_a_participant() {
+        let mut orphans = Vec::new();
+        let checked = orphaned_keys([1, 2, 3, 4], |key| key % 2 == 0, &mut orphans);
+        assert_eq!(checked, 4);
+        assert_eq!(orphans, [1, 3]);
+
+        let mut dead = Vec::new();
+        assert_eq!(dead_endpoints(Vec::<u32>::new(), |_| false, &mut dead), 0);
+        assert_eq!(
+            dead_endpoints([7, 8], |endpoint| *endpoint == 8, &mut dead),
+            2
+        );
+        assert_eq!(dead, [7]);
+    }
+
+    #[test]
+    fn collects_the_addresses_announced_twice() {
+        let index = [
+            (addr(8000), &[1][..]),
+            (addr(8001), &[2, 3][..]),
+            (addr(8002), &[][..]),
+        ];
+        let mut shared = Vec::new();
+        assert_eq!(shared_public_addresses(index, &mut shared), 3);
+        assert_eq!(shared, [(addr(8001), 2)]);
+    }
+
+    #[test]
+    fn an_index_agrees_with_the_storage_by_canonical_form() {
+        let stored: HashMap<u32, SocketAddr> =
+            [(1, addr(8000)), (2, mapped(8001))].into_iter().collect();
+        let public_of = |endpoint: &u32| stored.get(endpoint).copied();
+
+        let index = [(addr(8000), &[1][..]), (addr(8001), &[2][..])];
+        assert!(!index_mismatch(index, public_of, 2));
+    }
+
+    #[test]
+    fn finds_an_index_disagreeing_with_the_storage() {
+        let stored: HashMap<u32, SocketAddr> =
+            [(1, addr(8000)), (2, addr(8001))].into_iter().collect();
+        let public_of = |endpoint: &u32| stored.get(endpoint).copied();
+
+        // An endpoint under another address than the stored one.
+        let wrong = [(addr(8000), &[1][..]), (addr(8002), &[2][..])];
+        assert!(index_mismatch(wrong, public_of, 2));
+        // An endpoint that is not stored.
+        let unknown = [(addr(8000), &[1, 3][..]), (addr(8001), &[2][..])];
+        assert!(index_mismatch(unknown, public_of, 2));
+        // A stored endpoint missing from the index.
+        let missing = [(addr(8000), &[1][..])];
+        assert!(index_mismatch(missing, public_of, 2));
+        // An endpoint listed twice.
+        let twice = [(addr(8000), &[1, 1][..]), (addr(8001), &[2][..])];
+        assert!(index_mismatch(twice, public_of, 2));
+    }
+
+    #[test]
+    fn summarizes_an_audit() {
+        let summary = AuditSummary {
+            checked: 12,
+            repaired: 2,
+            unrepairable: 1,
+        };
+        assert_eq!(
+            summary.to_string(),
+            "Audit checked 12 entries: 2 repaired, 1 unrepairable"
+        );
+    }
+}
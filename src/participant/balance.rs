@@ -0,0 +1,245 @@
+//! Connection Balance.
+//!
+//! A participant that is only dialed by others, such as the bootstrap node every newcomer
+//! connects to, ends up with inbound connections alone, while one behind a NAT dials every
+//! peer itself. With `--balance-connections`, a participant whose outbound connections fall
+//! below `--min-outbound-share` percent of its established ones dials one of its inbound-only
+//! peers back at its public address, and hands the peer over to the new connection, without a
+//! departure nor a join on either side.
+//!
+//! The handover takes two steps. Once the outbound connection is established, the peer's
+//! counters and state move to it, and the inbound connection, no longer stored, only waits.
+//! Once the first frame of the peer arrives through the outbound connection, the peer stored
+//! that connection before answering it, so the inbound one is closed without the peer taking it
+//! for a departure.
+//!
+//! One handover is in flight at a time. A dial that fails leaves the inbound connection as it
+//! was, and a handover whose inbound connection closes before the dial is established is
+//! abandoned, the outbound connection carrying on as an ordinary dial. An outbound connection
+//! lost before the peer answered takes the waiting inbound one with it.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// A handover in flight.
+#[derive(Debug)]
+struct Handover<K> {
+    public: SocketAddr,
+    inbound: K,
+    outbound: K,
+    started_at: Instant,
+    established: bool,
+}
+
+/// Decides when to dial a peer back and follows the handover to the new connection.
+#[derive(Debug)]
+pub struct Balancer<K> {
+    min_outbound: u32,
+    handover: Option<Handover<K>>,
+    completed: u64,
+    failed: u64,
+    abandoned: u64,
+}
+
+impl<K: PartialEq + Clone> Balancer<K> {
+    /// Constructs a balancer keeping at least `min_outbound` percent of the established
+    /// connections outbound.
+    pub fn new(min_outbound: u32) -> Self {
+        Self {
+            min_outbound,
+            handover: None,
+            completed: 0,
+            failed: 0,
+            abandoned: 0,
+        }
+    }
+
+    /// Determines whether an inbound-only peer should be dialed back, given the established
+    /// connections by direction.
+    pub fn wants_outbound(&self, inbound: usize, outbound: usize) -> bool {
+        self.handover.is_none()
+            && inbound > 0
+            && (outbound as u64) * 100 < u64::from(self.min_outbound) * (inbound + outbound) as u64
+    }
+
+    /// Records that the peer at `public`, connected through `inbound`, is dialed back through
+    /// `outbound` at `now`.
+    pub fn start(&mut self, public: SocketAddr, inbound: K, outbound: K, now: Instant) {
+        self.handover = Some(Handover {
+            public,
+            inbound,
+            outbound,
+            started_at: now,
+            established: false,
+        });
+    }
+
+    /// Records that the connection `outbound` was established.
+    ///
+    /// # Returns
+    ///
+    /// The inbound connection the peer moves from, or `None` if `outbound` was not dialed for
+    /// a handover.
+    pub fn established(&mut self, outbound: &K) -> Option<K> {
+        let handover = self
+            .handover
+            .as_mut()
+            .filter(|handover| !handover.established && handover.outbound == *outbound)?;
+        handover.established = true;
+        Some(handover.inbound.clone())
+    }
+
+    /// Completes the handover once the peer answered through `outbound`.
+    ///
+    /// # Returns
+    ///
+    /// The public address of the peer and the inbound connection to close, or `None` if
+    /// `outbound` is not an established handover.
+    pub fn answered(&mut self, outbound: &K) -> Option<(SocketAddr, K)> {
+        let matches = self
+            .handover
+            .as_ref()
+            .is_some_and(|handover| handover.established && handover.outbound == *outbound);
+        if !matches {
+            return None;
+        }
+        let handover = self.handover.take()?;
+        self.completed += 1;
+        Some((handover.public, handover.inbound))
+    }
+
+    /// Gives the handover dialed through `outbound` up after the dial failed.
+    ///
+    /// # Returns
+    ///
+    /// Whether `outbound` was dialed for a handover, the inbound connection then staying as
+    /// it was.
+    pub fn dial_failed(&mut self, outbound: &K) -> bool {
+        let matches = self
+            .handover
+            .as_ref()
+            .is_some_and(|handover| !handover.established && handover.outbound == *outbound);
+        if matches {
+            self.handover = None;
+            self.failed += 1;
+        }
+        matches
+    }
+
+    /// Follows the handover after the connection `endpoint` closed.
+    ///
+    /// The peer closing the inbound connection first completes an established handover, and
+    /// abandons one still dialing.
+    ///
+    /// # Returns
+    ///
+    /// The waiting inbound connection, to be closed as well, when the outbound connection of
+    /// an established handover closed before the peer answered.
+    pub fn closed(&mut self, endpoint: &K) -> Option<K> {
+        let handover = self.handover.as_ref()?;
+        if handover.inbound == *endpoint {
+            if handover.established {
+                self.completed += 1;
+            } else {
+                self.abandoned += 1;
+            }
+            self.handover = None;
+            return None;
+        }
+        if handover.outbound == *endpoint && handover.established {
+            self.abandoned += 1;
+            return self.handover.take().map(|handover| handover.inbound);
+        }
+        None
+    }
+
+    /// Returns the public address of the peer being handed over and when its dial started.
+    pub fn in_flight(&self) -> Option<(SocketAddr, Instant)> {
+        self.handover
+            .as_ref()
+            .map(|handover| (handover.public, handover.started_at))
+    }
+
+    /// Returns the completed, failed and abandoned handovers.
+    pub fn counters(&self) -> (u64, u64, u64) {
+        (self.completed, self.failed, self.abandoned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    /// A balancer wanting half of its connections outbound, with a handover of the peer at
+    /// port 8080 from the connection 9001 to 9002 started.
+    fn handing_over() -> Balancer<u16> {
+        let mut balancer = Balancer::new(50);
+        balancer.start(addr(8080), 9001, 9002, Instant::now());
+        balancer
+    }
+
+    #[test]
+    fn a_peer_is_dialed_back_below_the_outbound_share() {
+        let balancer = Balancer::<u16>::new(50);
+        assert!(balancer.wants_outbound(3, 2));
+        assert!(!balancer.wants_outbound(2, 2));
+        assert!(!balancer.wants_outbound(0, 0));
+        assert!(!Balancer::<u16>::new(0).wants_outbound(5, 0));
+        // One handover at a time.
+        assert!(!handing_over().wants_outbound(5, 0));
+    }
+
+    #[test]
+    fn a_handover_completes_once_the_peer_answers() {
+        let mut balancer = handing_over();
+        assert_eq!(
+            balancer.in_flight().map(|(public, _)| public),
+            Some(addr(8080))
+        );
+        assert_eq!(balancer.answered(&9002), None);
+        assert_eq!(balancer.established(&9003), None);
+        assert_eq!(balancer.established(&9002), Some(9001));
+        assert_eq!(balancer.established(&9002), None);
+        assert!(!balancer.dial_failed(&9002));
+
+        assert_eq!(balancer.answered(&9002), Some((addr(8080), 9001)));
+        assert_eq!(balancer.in_flight(), None);
+        assert_eq!(balancer.counters(), (1, 0, 0));
+    }
+
+    #[test]
+    fn a_failed_dial_leaves_the_inbound_connection() {
+        let mut balancer = handing_over();
+        assert!(!balancer.dial_failed(&9003));
+        assert!(balancer.dial_failed(&9002));
+        assert_eq!(balancer.in_flight(), None);
+        assert_eq!(balancer.counters(), (0, 1, 0));
+    }
+
+    #[test]
+    fn the_inbound_connection_closing_ends_the_handover() {
+        let mut dialing = handing_over();
+        assert_eq!(dialing.closed(&9003), None);
+        // The outbound connection of a dial not established yet is left to `dial_failed`.
+        assert_eq!(dialing.closed(&9002), None);
+        assert!(dialing.in_flight().is_some());
+        assert_eq!(dialing.closed(&9001), None);
+        assert_eq!(dialing.counters(), (0, 0, 1));
+
+        let mut established = handing_over();
+        established.established(&9002);
+        assert_eq!(established.closed(&9001), None);
+        assert_eq!(established.in_flight(), None);
+        assert_eq!(established.counters(), (1, 0, 0));
+    }
+
+    #[test]
+    fn the_outbound_connection_lost_takes_the_inbound_one_along() {
+        let mut balancer = handing_over();
+        balancer.established(&9002);
+        assert_eq!(balancer.closed(&9002), Some(9001));
+        assert_eq!(balancer.in_flight(), None);
+        assert_eq!(balancer.counters(), (0, 0, 1));
+    }
+}
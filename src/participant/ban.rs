@@ -0,0 +1,156 @@
+//! Runtime Bans.
+//!
+//! The `evict <addr> [duration]` console command disconnects the peer advertising an address,
+//! and given a duration also bans the address for that long. A banned address is refused like
+//! a blocked one: its inbound connections are closed once it announces itself, and it is not
+//! dialed, probed nor introduced, whatever participants list names it. A ban may target an
+//! address no peer is connected from, to keep it out before it shows up.
+//!
+//! Bans live in memory only, apart from the filter: they expire on their own, `unban <addr>`
+//! lifts one early, and none outlives the process. An address to keep out for good belongs in
+//! the filter file, see `filter`.
+
+use crate::participant::utils::CanonicalAddr;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The addresses banned at runtime, with the time each ban ends.
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: HashMap<CanonicalAddr, Instant>,
+}
+
+impl BanList {
+    /// Constructs a list without bans.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bans `addr` until `until`, replacing any ban it already had.
+    pub fn ban(&mut self, addr: SocketAddr, until: Instant) {
+        self.bans.insert(addr.into(), until);
+    }
+
+    /// Lifts the ban of `addr`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the address was banned.
+    pub fn unban(&mut self, addr: SocketAddr) -> bool {
+        self.bans.remove(&addr.into()).is_some()
+    }
+
+    /// Determines whether `addr` is banned at `now`.
+    ///
+    /// A ban that ended but was not swept yet no longer counts.
+    pub fn is_banned(&self, addr: SocketAddr, now: Instant) -> bool {
+        self.bans
+            .get(&addr.into())
+            .is_some_and(|until| *until > now)
+    }
+
+    /// Forgets the bans ended at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The addresses whose ban ended.
+    pub fn expire(&mut self, now: Instant) -> Vec<SocketAddr> {
+        let mut expired = Vec::new();
+        self.bans.retain(|addr, until| {
+            if *until > now {
+                return true;
+            }
+            expired.push(addr.original());
+            false
+        });
+        expired
+    }
+
+    /// Lists the banned addresses with the time left on each ban, the soonest to end first.
+    pub fn list(&self, now: Instant) -> Vec<(SocketAddr, Duration)> {
+        let mut bans: Vec<(SocketAddr, Duration)> = self
+            .bans
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(addr, until)| (addr.original(), until.saturating_duration_since(now)))
+            .collect();
+        bans.sort_by_key(|(addr, left)| (*left, *addr));
+        bans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    #[test]
+    fn bans_until_the_end() {
+        let now = Instant::now();
+        let mut bans = BanList::new();
+        bans.ban(addr(8080), now + Duration::from_secs(10));
+
+        assert!(bans.is_banned(addr(8080), now));
+        assert!(bans.is_banned(addr(8080), now + Duration::from_secs(9)));
+        assert!(!bans.is_banned(addr(8080), now + Duration::from_secs(10)));
+        assert!(!bans.is_banned(addr(8081), now));
+        // The IPv4-mapped form is the same address.
+        assert!(bans.is_banned("[::ffff:127.0.0.1]:8080".parse().unwrap(), now));
+    }
+
+    #[test]
+    fn a_new_ban_replaces_the_old_one() {
+        let now = Instant::now();
+        let mut bans = BanList::new();
+        bans.ban(addr(8080), now + Duration::from_secs(60));
+        bans.ban(addr(8080), now + Duration::from_secs(5));
+        assert_eq!(bans.list(now), [(addr(8080), Duration::from_secs(5))]);
+    }
+
+    #[test]
+    fn unbans_early() {
+        let now = Instant::now();
+        let mut bans = BanList::new();
+        bans.ban(addr(8080), now + Duration::from_secs(60));
+        assert!(bans.unban(addr(8080)));
+        assert!(!bans.unban(addr(8080)));
+        assert!(!bans.is_banned(addr(8080), now));
+    }
+
+    #[test]
+    fn expires_the_ended_bans_once() {
+        let now = Instant::now();
+        let mut bans = BanList::new();
+        bans.ban(addr(1), now + Duration::from_secs(1));
+        bans.ban(addr(2), now + Duration::from_secs(2));
+        bans.ban(addr(3), now + Duration::from_secs(3));
+
+        let mut expired = bans.expire(now + Duration::from_secs(2));
+        expired.sort();
+        assert_eq!(expired, [addr(1), addr(2)]);
+        assert!(bans.expire(now + Duration::from_secs(2)).is_empty());
+        assert_eq!(bans.list(now), [(addr(3), Duration::from_secs(3))]);
+    }
+
+    #[test]
+    fn lists_the_soonest_to_end_first() {
+        let now = Instant::now();
+        let mut bans = BanList::new();
+        bans.ban(addr(3), now + Duration::from_secs(30));
+        bans.ban(addr(2), now + Duration::from_secs(10));
+        bans.ban(addr(1), now + Duration::from_secs(30));
+        bans.ban(addr(4), now);
+
+        // Ended bans not swept yet are left out; ties are listed by address.
+        assert_eq!(
+            bans.list(now),
+            [
+                (addr(2), Duration::from_secs(10)),
+                (addr(1), Duration::from_secs(30)),
+                (addr(3), Duration::from_secs(30)),
+            ]
+        );
+    }
+}
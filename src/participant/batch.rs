@@ -0,0 +1,439 @@
+//! Outbound Batching.
+//!
+//! A participant often sends several small messages to the same peer within a few
+//! milliseconds: a participants list request right after a topic message, a list answer next to
+//! a request. Each of them costs a frame of its own, with its framing and its system call. The
+//! `Outbox` holds the messages queued for a peer during `--batch-window-ms`, 20 by default, and then sends them
+//! as a single `Message::Batch` frame, which the receiver unpacks and handles as if each message
+//! had arrived on its own, in order.
+//!
+//! Messages whose latency matters, or which carry a protocol version trailer, are
+//! `Priority::Immediate`: they flush the queue of their peer, so the order of the messages is
+//! kept, and are sent at once. A batch never grows past `MAX_BATCH_BYTES`; the messages that
+//! do not fit go in the next batch, and a message too large for any batch is sent alone.
+
+use crate::participant::message::Message;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The largest encoded size of a batch frame.
+pub const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// The encoded size of an empty `Message::Batch`: the variant index and the length of the list.
+pub const BATCH_HEADER_BYTES: usize = 4 + 8;
+
+/// Whether a message may wait in the outbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// The message waits for the batch window of its peer.
+    Batched,
+    /// The message is sent at once, after the messages already queued for its peer.
+    Immediate,
+}
+
+impl Priority {
+    /// Returns the priority of `message`.
+    ///
+    /// Handshakes, responses, probes and clock exchanges are latency sensitive, the frames
+    /// carrying a protocol version trailer would lose it inside a batch, and observers never
+    /// unpack batches.
+    pub fn of(message: &Message) -> Self {
+        match message {
+            Message::PublicAddress(_)
+            | Message::SharedPeers(_)
+            | Message::Response { .. }
+            | Message::Probe { .. }
+            | Message::ProbeReply { .. }
+            | Message::ClockRequest { .. }
+            | Message::ClockReply { .. }
+            | Message::ObserverHello
+            | Message::ObserverSnapshot { .. }
+            | Message::ObserverUpdate { .. }
+            | Message::ObserverRejected
+            | Message::Batch(_) => Priority::Immediate,
+            Message::PushParticipantsList
+            | Message::PullParticipantsList(_)
+            | Message::Text(_)
+            | Message::RoundText { .. }
+            | Message::PleaseIntroduce { .. }
+            | Message::IntroductionRequest { .. }
+            | Message::Topic { .. }
+            | Message::SequencedTopic { .. }
+            | Message::VersionedTopic { .. }
+            | Message::SchemaUpdate(_)
+            | Message::Request { .. }
+            | Message::InterestUpdate(_)
+            | Message::ListRequest { .. }
+            | Message::ListReply { .. }
+            | Message::CompactListReply { .. } => Priority::Batched,
+        }
+    }
+}
+
+/// The messages waiting for one peer, oldest first.
+struct Queue {
+    opened_at: Instant,
+    messages: Vec<Message>,
+}
+
+/// The messages waiting to be sent in batches, per peer.
+pub struct Outbox<K> {
+    window: Duration,
+    budget: usize,
+    queues: HashMap<K, Queue>,
+    batches: u64,
+    batched: u64,
+}
+
+impl<K: Eq + Hash + Copy> Outbox<K> {
+    /// Creates an empty outbox.
+    ///
+    /// # Parameters
+    ///
+    /// * `window` - How long a queued message waits for others; zero disables batching.
+    /// * `budget` - The largest encoded size of a batch frame.
+    pub fn new(window: Duration, budget: usize) -> Self {
+        Self {
+            window,
+            budget,
+            queues: HashMap::new(),
+            batches: 0,
+            batched: 0,
+        }
+    }
+
+    /// Sets how long a queued message waits for others, the open queues included: they are due
+    /// once the new window elapsed since they opened.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Determines whether messages are batched at all.
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Returns how long a queued message waits for others.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the largest encoded size of a batch frame.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Queues `message` for `to`.
+    ///
+    /// # Parameters
+    ///
+    /// * `to` - The receiving peer.
+    /// * `message` - The message to send.
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// Whether the message opened the queue of `to`, in which case the caller arranges for
+    /// `take_due` to run once the window elapsed.
+    pub fn push(&mut self, to: K, message: Message, now: Instant) -> bool {
+        let mut opened = false;
+        self.queues
+            .entry(to)
+            .or_insert_with(|| {
+                opened = true;
+                Queue {
+                    opened_at: now,
+                    messages: Vec::new(),
+                }
+            })
+            .messages
+            .push(message);
+        opened
+    }
+
+    /// Determines whether messages are waiting for `to`.
+    pub fn has_pending(&self, to: &K) -> bool {
+        self.queues.contains_key(to)
+    }
+
+    /// Empties the queue of `to`, whatever its age.
+    ///
+    /// # Returns
+    ///
+    /// The frames to send to `to`, in order; none if nothing was waiting.
+    pub fn take(&mut self, to: &K) -> Vec<Vec<u8>> {
+        match self.queues.remove(to) {
+            Some(queue) => self.frames(queue.messages),
+            None => Vec::new(),
+        }
+    }
+
+    /// Empties the queues whose window elapsed at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The frames to send, per peer.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(K, Vec<Vec<u8>>)> {
+        let due: Vec<K> = self
+            .queues
+            .iter()
+            .filter(|(_, queue)| now.saturating_duration_since(queue.opened_at) >= self.window)
+            .map(|(to, _)| *to)
+            .collect();
+        due.into_iter()
+            .map(|to| {
+                let frames = self.take(&to);
+                (to, frames)
+            })
+            .collect()
+    }
+
+    /// Drops the messages waiting for a peer that is gone.
+    pub fn discard(&mut self, to: &K) {
+        self.queues.remove(to);
+    }
+
+    /// Returns the number of messages waiting in every queue.
+    pub fn pending(&self) -> usize {
+        self.queues.values().map(|queue| queue.messages.len()).sum()
+    }
+
+    /// Returns the number of batch frames sent.
+    pub fn batches(&self) -> u64 {
+        self.batches
+    }
+
+    /// Returns the number of messages sent inside batch frames.
+    pub fn batched(&self) -> u64 {
+        self.batched
+    }
+
+    /// Encodes `messages` into as few frames as `budget` allows, counting the batches.
+    fn frames(&mut self, messages: Vec<Message>) -> Vec<Vec<u8>> {
+        let groups = pack(messages, self.budget);
+        for group in &groups {
+            if group.len() > 1 {
+                self.batches += 1;
+                self.batched += group.len() as u64;
+            }
+        }
+        groups.into_iter().map(encode_group).collect()
+    }
+}
+
+/// Splits `messages` into groups, in order, each fitting in a batch frame of `budget` bytes.
+///
+/// A group is closed as soon as the next message would overflow it, so a message alone over
+/// the budget forms a group of its own, sent as a plain frame.
+///
+/// # Parameters
+///
+/// * `messages` - The messages to send, oldest first.
+/// * `budget` - The largest encoded size of a batch frame.
+pub fn pack(messages: Vec<Message>, budget: usize) -> Vec<Vec<Message>> {
+    let mut groups = Vec::new();
+    let mut group = Vec::new();
+    let mut size = BATCH_HEADER_BYTES;
+    for message in messages {
+        let message_size = bincode::serialized_size(&message).unwrap_or(u64::MAX) as usize;
+        if !group.is_empty() && size.saturating_add(message_size) > budget {
+            groups.push(std::mem::take(&mut group));
+            size = BATCH_HEADER_BYTES;
+        }
+        size = size.saturating_add(message_size);
+        group.push(message);
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    groups
+}
+
+/// Encodes a group of `pack` into a frame: the message itself when it is alone, a
+/// `Message::Batch` otherwise.
+fn encode_group(mut group: Vec<Message>) -> Vec<u8> {
+    match group.len() {
+        1 => group.remove(0).encode(),
+        _ => Message::Batch(group).encode(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(20);
+
+    fn text(content: &str) -> Message {
+        Message::Text(content.to_owned())
+    }
+
+    /// Returns the texts a frame carries, in order, whether batched or plain.
+    fn texts(frame: &[u8]) -> Vec<String> {
+        match Message::decode(frame).unwrap().0 {
+            Message::Batch(messages) => messages
+                .into_iter()
+                .map(|message| match message {
+                    Message::Text(content) => content,
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect(),
+            Message::Text(content) => vec![content],
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn size(message: &Message) -> usize {
+        bincode::serialized_size(message).unwrap() as usize
+    }
+
+    #[test]
+    fn latency_sensitive_messages_are_immediate() {
+        assert_eq!(
+            Priority::of(&Message::Probe {
+                id: 1,
+                sent_at_offset: 0
+            }),
+            Priority::Immediate
+        );
+        assert_eq!(
+            Priority::of(&Message::Batch(Vec::new())),
+            Priority::Immediate
+        );
+        assert_eq!(Priority::of(&text("hello")), Priority::Batched);
+        assert_eq!(
+            Priority::of(&Message::PushParticipantsList),
+            Priority::Batched
+        );
+    }
+
+    #[test]
+    fn the_first_message_opens_the_queue_of_its_peer() {
+        let now = Instant::now();
+        let mut outbox = Outbox::new(WINDOW, MAX_BATCH_BYTES);
+        assert!(outbox.is_enabled());
+        assert!(outbox.push(1, text("a"), now));
+        assert!(!outbox.push(1, text("b"), now));
+        assert!(outbox.push(2, text("c"), now));
+        assert!(outbox.has_pending(&1));
+        assert_eq!(outbox.pending(), 3);
+
+        outbox.discard(&2);
+        assert!(!outbox.has_pending(&2));
+        assert_eq!(outbox.pending(), 2);
+        assert!(outbox.take(&2).is_empty());
+        assert!(!Outbox::<u32>::new(Duration::ZERO, MAX_BATCH_BYTES).is_enabled());
+    }
+
+    #[test]
+    fn a_queue_is_due_once_its_window_elapsed() {
+        let start = Instant::now();
+        let mut outbox = Outbox::new(WINDOW, MAX_BATCH_BYTES);
+        outbox.push(1, text("a"), start);
+        outbox.push(1, text("b"), start + Duration::from_millis(10));
+        outbox.push(2, text("c"), start + Duration::from_millis(10));
+
+        assert!(outbox
+            .take_due(start + WINDOW - Duration::from_millis(1))
+            .is_empty());
+        let due = outbox.take_due(start + WINDOW);
+        assert_eq!(due.len(), 1);
+        let (to, frames) = &due[0];
+        assert_eq!(*to, 1);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(texts(&frames[0]), ["a", "b"]);
+        assert_eq!((outbox.batches(), outbox.batched()), (1, 2));
+
+        // A message alone is sent as a plain frame, not counted as a batch.
+        let due = outbox.take_due(start + Duration::from_millis(30));
+        assert_eq!(due.len(), 1);
+        assert!(matches!(
+            Message::decode(&due[0].1[0]).unwrap().0,
+            Message::Text(_)
+        ));
+        assert_eq!((outbox.batches(), outbox.batched()), (1, 2));
+        assert_eq!(outbox.pending(), 0);
+    }
+
+    #[test]
+    fn a_new_window_applies_to_the_open_queues() {
+        let start = Instant::now();
+        let mut outbox = Outbox::new(WINDOW, MAX_BATCH_BYTES);
+        outbox.push(1, text("a"), start);
+        outbox.set_window(Duration::from_millis(5));
+        assert_eq!(outbox.window(), Duration::from_millis(5));
+        assert_eq!(outbox.take_due(start + Duration::from_millis(5)).len(), 1);
+    }
+
+    #[test]
+    fn an_immediate_flush_keeps_the_order_of_the_queue() {
+        let now = Instant::now();
+        let mut outbox = Outbox::new(WINDOW, MAX_BATCH_BYTES);
+        outbox.push(1, text("a"), now);
+        outbox.push(1, text("b"), now);
+        let frames = outbox.take(&1);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(texts(&frames[0]), ["a", "b"]);
+        assert!(!outbox.has_pending(&1));
+        assert!(outbox.take_due(now + WINDOW).is_empty());
+    }
+
+    #[test]
+    fn packing_closes_a_group_before_it_overflows() {
+        let message = text("0123456789");
+        let budget = BATCH_HEADER_BYTES + 2 * size(&message);
+        let messages: Vec<Message> = (0..5).map(|_| message.clone()).collect();
+        let groups = pack(messages, budget);
+        let lengths: Vec<usize> = groups.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [2, 2, 1]);
+
+        // One byte short of two messages, every message goes alone.
+        let groups = pack(vec![message.clone(), message.clone()], budget - 1);
+        assert_eq!(groups.len(), 2);
+        assert!(pack(Vec::new(), budget).is_empty());
+    }
+
+    #[test]
+    fn a_message_over_the_remaining_budget_starts_the_next_group() {
+        let small = text("s");
+        let large = text(&"l".repeat(100));
+        // The large message misses the first group by a byte.
+        let budget = BATCH_HEADER_BYTES + 2 * size(&small) + size(&large) - 1;
+        let messages = vec![small.clone(), small.clone(), large.clone(), small.clone()];
+        let groups = pack(messages, budget);
+        let lengths: Vec<usize> = groups.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [2, 2]);
+        let groups = pack(
+            vec![small.clone(), small.clone(), large.clone()],
+            budget + 1,
+        );
+        assert_eq!(groups.len(), 1);
+
+        // A message larger than any batch is sent alone, as a plain frame.
+        let mut outbox = Outbox::new(WINDOW, BATCH_HEADER_BYTES + 10);
+        let now = Instant::now();
+        outbox.push(1, small.clone(), now);
+        outbox.push(1, large, now);
+        outbox.push(1, small, now);
+        let frames = outbox.take(&1);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(texts(&frames[1]), ["l".repeat(100)]);
+        assert_eq!(outbox.batches(), 0);
+    }
+
+    #[test]
+    fn every_frame_fits_in_the_budget() {
+        let now = Instant::now();
+        let mut outbox = Outbox::new(WINDOW, 256);
+        for n in 0..200 {
+            outbox.push(1, text(&"x".repeat(n % 40)), now);
+        }
+        let frames = outbox.take(&1);
+        assert!(frames.iter().all(|frame| frame.len() <= outbox.budget()));
+        let delivered: usize = frames.iter().map(|frame| texts(frame).len()).sum();
+        assert_eq!(delivered, 200);
+        assert_eq!(outbox.batched(), 200);
+    }
+}
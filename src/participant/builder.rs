@@ -0,0 +1,215 @@
+//! Participant Builder.
+//!
+//! `ParticipantBuilder` configures a participant embedded in an application. It starts from
+//! the settings of the command line, so every option of the binary is available through
+//! `arg`, but unlike the binary it leaves the files and the output of its process alone
+//! unless asked:
+//!
+//! - the node id lasts for the run only, rather than being kept in an identity file under the
+//!   data directory, until `persist_identity` or `identity_file`;
+//! - the recent peers cache under the cache directory is neither read nor written, until
+//!   `peer_cache`;
+//! - the events of the participant are not printed on the standard output, until
+//!   `print_events`.
+//!
+//! ```no_run
+//! use gossip_p2p::prelude::*;
+//! use std::time::Duration;
+//!
+//! fn start() -> Result<Participant, ParticipantError> {
+//!     ParticipantBuilder::new(Duration::from_secs(5))
+//!         .port(8080)
+//!         .connect("127.0.0.1:8081")
+//!         .build()
+//! }
+//! ```
+
+use crate::participant::config::NodeConfig;
+use crate::participant::error::ParticipantError;
+use crate::participant::model::Participant;
+use crate::participant::transport::{MessageIoTransport, Transport};
+use crate::printer::silence_events;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where the node id of a built participant is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IdentityStore {
+    /// Nowhere: a new id for every run.
+    None,
+    /// In the identity file of the data directory the binary uses.
+    Default,
+    /// In the given file.
+    File(PathBuf),
+}
+
+/// Configures and builds a participant with no side effect on its process it was not asked
+/// for.
+#[derive(Debug, Clone)]
+pub struct ParticipantBuilder {
+    /// The command-line arguments the configuration is parsed from, but the port.
+    args: Vec<String>,
+    /// The port listened on, `0` for an ephemeral one.
+    port: u16,
+    /// Where the node id is kept.
+    identity: IdentityStore,
+    /// Whether the recent peers cache is used.
+    peer_cache: bool,
+    /// Whether the events are printed.
+    print_events: bool,
+}
+
+impl ParticipantBuilder {
+    /// Starts the settings of a participant broadcasting every `period`, listening on an
+    /// ephemeral port and connecting to no one.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            args: vec![format!("--period={}ms", period.as_millis().max(1))],
+            port: 0,
+            identity: IdentityStore::None,
+            peer_cache: false,
+            print_events: false,
+        }
+    }
+
+    /// Listens on `port` of `127.0.0.1` rather than on an ephemeral one.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Connects to the participant at `addr` at startup, such as `127.0.0.1:8080`.
+    pub fn connect(self, addr: impl Into<String>) -> Self {
+        self.arg(format!("--connect={}", addr.into()))
+    }
+
+    /// Adds a setting given as a command-line argument of the binary, such as
+    /// `--topics=chat` or `--share-limit=4`.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Keeps the node id across runs in the identity file the binary would use, named by the
+    /// port or by `--node-label` under the data directory.
+    pub fn persist_identity(mut self) -> Self {
+        self.identity = IdentityStore::Default;
+        self
+    }
+
+    /// Keeps the node id across runs in `path`.
+    pub fn identity_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity = IdentityStore::File(path.into());
+        self
+    }
+
+    /// Reads the recent peers cache at startup and updates it on every handshake, as the
+    /// binary does.
+    pub fn peer_cache(mut self) -> Self {
+        self.peer_cache = true;
+        self
+    }
+
+    /// Prints the events of the participant on the standard output, as the binary does.
+    ///
+    /// The events are printed or not for the whole process: a participant built without
+    /// this silences them for every other one as well.
+    pub fn print_events(mut self) -> Self {
+        self.print_events = true;
+        self
+    }
+
+    /// Builds the configuration of the participant.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or `ParticipantError::Config` with the reason the settings do not
+    /// configure a participant.
+    pub fn config(&self) -> Result<NodeConfig, ParticipantError> {
+        let mut args = self.args.clone();
+        args.push(format!("--port={}", self.port));
+        let mut config = NodeConfig::from_args(&args).map_err(ParticipantError::Config)?;
+        config.use_peer_cache = self.peer_cache;
+        match &self.identity {
+            IdentityStore::None => config.persist_identity = false,
+            IdentityStore::Default => config.persist_identity = true,
+            IdentityStore::File(path) => {
+                config.persist_identity = true;
+                config.identity_file = Some(path.clone());
+            }
+        }
+        Ok(config)
+    }
+
+    /// Builds the participant on the TCP transport, see `Participant::new`.
+    pub fn build(self) -> Result<Participant, ParticipantError> {
+        self.build_with_transport(MessageIoTransport::new())
+    }
+
+    /// Builds the participant on `transport`, see `Participant::with_transport`.
+    pub fn build_with_transport<T: Transport + 'static>(
+        self,
+        transport: T,
+    ) -> Result<Participant, ParticipantError> {
+        let config = self.config()?;
+        if !self.print_events {
+            silence_events();
+        }
+        Participant::with_transport(config, transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_built_participant_writes_no_file_unless_asked() {
+        let config = ParticipantBuilder::new(Duration::from_secs(5))
+            .port(9100)
+            .config()
+            .unwrap();
+        assert!(!config.use_peer_cache);
+        assert!(!config.persist_identity);
+        assert_eq!(config.identity_file, None);
+        assert_eq!(config.state_file, None);
+        assert_eq!(config.report_file, None);
+        assert_eq!(config.record, None);
+        assert_eq!(config.admin_socket, None);
+        assert_eq!(config.port.get(), 9100);
+    }
+
+    #[test]
+    fn the_files_are_opted_into_one_by_one() {
+        let builder = ParticipantBuilder::new(Duration::from_millis(250)).peer_cache();
+        let config = builder.config().unwrap();
+        assert!(config.use_peer_cache);
+        assert!(!config.persist_identity);
+
+        let config = builder.clone().persist_identity().config().unwrap();
+        assert!(config.persist_identity);
+        assert_eq!(config.identity_file, None);
+
+        let config = builder.identity_file("/tmp/node.id").config().unwrap();
+        assert!(config.persist_identity);
+        assert_eq!(config.identity_file, Some(PathBuf::from("/tmp/node.id")));
+    }
+
+    #[test]
+    fn the_settings_are_those_of_the_command_line() {
+        let config = ParticipantBuilder::new(Duration::from_secs(1))
+            .connect("127.0.0.1:8080")
+            .arg("--topics=chat")
+            .config()
+            .unwrap();
+        assert_eq!(config.connect.as_deref(), Some("127.0.0.1:8080"));
+        assert_eq!(config.topics, Some(vec!["chat".to_owned()]));
+
+        let err = ParticipantBuilder::new(Duration::from_secs(1))
+            .arg("--no-such-setting")
+            .config()
+            .unwrap_err();
+        assert!(matches!(err, ParticipantError::Config(_)), "{}", err);
+    }
+}
@@ -0,0 +1,481 @@
+//! Callback Workers.
+//!
+//! Topic subscribers run application code of unknown cost. Called on the event loop, a slow
+//! one held back everything behind it: handshakes, probe replies, the broadcast tick. So the
+//! event loop only queues each delivery in the `CallbackPool`, and `--callback-workers` threads
+//! named `gossip-callback`, 2 by default, run the callbacks.
+//!
+//! Every subscription has a queue of its own, holding at most `CALLBACK_QUEUE` deliveries.
+//! When it is full the oldest delivery is dropped and counted against the subscription, so a
+//! subscriber falling behind only loses its own messages. The workers take the subscriptions
+//! with queued deliveries in turn, one delivery at a time, so a slow subscriber never delays
+//! another by more than one of its calls, and only when both share the last idle worker.
+//!
+//! A subscription never runs on two workers at once, so its callback is called in the order
+//! its deliveries were queued. An ordered subscription is moreover pinned to the worker its id
+//! hashes to. A panicking callback is caught and counted, and its subscription goes on. At
+//! shutdown the workers run the deliveries already queued, then stop.
+
+use crate::participant::reorder::Delivery;
+use crate::participant::schema::Schema;
+use crate::participant::threads::{Join, SpawnError, ThreadRegistry};
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The number of deliveries queued per subscription before the oldest is dropped.
+pub const CALLBACK_QUEUE: usize = 1024;
+
+/// What a callback is called with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    /// A delivery of the topic of a plain or an ordered subscription.
+    Delivery(Delivery),
+    /// A payload following `Schema`, for the schema handler or the fallback it was routed to.
+    Versioned(Schema, Vec<u8>),
+}
+
+/// A callback run by the workers with the publisher's public address and each call.
+pub type DeliveryCallback = Box<dyn FnMut(SocketAddr, Call) + Send>;
+
+/// What the workers did for one subscription.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallbackStats {
+    /// The deliveries the callback was called with.
+    pub executed: u64,
+    /// The deliveries dropped from its full queue.
+    pub dropped: u64,
+    /// The calls that panicked.
+    pub panicked: u64,
+    /// The longest call.
+    pub slowest: Duration,
+    /// The deliveries waiting in its queue.
+    pub queued: usize,
+    /// Whether a worker is running the callback.
+    pub running: bool,
+}
+
+/// A subscription, as the workers see it.
+struct Slot {
+    /// The callback, taken out while a worker runs it.
+    callback: Option<DeliveryCallback>,
+    queue: VecDeque<(SocketAddr, Call)>,
+    /// The only worker allowed to run the callback, if any.
+    pinned: Option<usize>,
+    stats: CallbackStats,
+    /// Whether the subscription was removed while its callback ran.
+    removed: bool,
+}
+
+/// The state shared by the event loop and the workers.
+#[derive(Default)]
+struct State {
+    slots: HashMap<u64, Slot>,
+    /// The subscriptions with queued deliveries and no running call, in turn order.
+    turn: VecDeque<u64>,
+    /// The counters of every subscription, removed ones included.
+    totals: CallbackStats,
+    closed: bool,
+}
+
+/// The state and the condition the idle workers wait on.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The subscription callbacks of a participant and the workers running them.
+pub struct CallbackPool {
+    shared: Arc<Shared>,
+    workers: usize,
+}
+
+impl CallbackPool {
+    /// Creates a pool without subscriptions, served by `workers` threads named
+    /// `gossip-callback`.
+    ///
+    /// # Parameters
+    ///
+    /// * `workers` - The number of worker threads, at least one.
+    /// * `threads` - The registry the workers are spawned through.
+    pub fn new(workers: usize, threads: &ThreadRegistry) -> Result<Self, SpawnError> {
+        let workers = workers.max(1);
+        let shared = Arc::new(Shared::default());
+        for index in 0..workers {
+            let shared = Arc::clone(&shared);
+            threads.spawn("gossip-callback", Join::Wait, move |_| work(&shared, index))?;
+        }
+        Ok(Self { shared, workers })
+    }
+
+    /// Adds the subscription `id`, whose callback is run by any worker, or by the one `id`
+    /// hashes to if `pinned`.
+    pub fn add(&mut self, id: u64, callback: DeliveryCallback, pinned: bool) {
+        let slot = Slot {
+            callback: Some(callback),
+            queue: VecDeque::new(),
+            pinned: pinned.then_some(id as usize % self.workers),
+            stats: CallbackStats::default(),
+            removed: false,
+        };
+        self.shared.lock().slots.insert(id, slot);
+    }
+
+    /// Removes the subscription `id`, discarding its queued deliveries; a running call
+    /// completes.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `id` was subscribed.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let mut state = self.shared.lock();
+        state.turn.retain(|queued| *queued != id);
+        let Some(slot) = state.slots.get_mut(&id) else {
+            return false;
+        };
+        if slot.removed {
+            return false;
+        }
+        if slot.stats.running {
+            slot.removed = true;
+            slot.queue.clear();
+        } else {
+            state.slots.remove(&id);
+        }
+        true
+    }
+
+    /// Queues `delivery` for the subscription `id`, dropping its oldest queued delivery when
+    /// its queue is full.
+    ///
+    /// # Returns
+    ///
+    /// `false` if `id` is not subscribed.
+    pub fn deliver(&mut self, id: u64, from: SocketAddr, delivery: Call) -> bool {
+        let mut state = self.shared.lock();
+        let State {
+            slots,
+            turn,
+            totals,
+            ..
+        } = &mut *state;
+        let Some(slot) = slots.get_mut(&id).filter(|slot| !slot.removed) else {
+            return false;
+        };
+        if slot.queue.len() >= CALLBACK_QUEUE {
+            slot.queue.pop_front();
+            slot.stats.dropped += 1;
+            totals.dropped += 1;
+        }
+        if slot.queue.is_empty() && !slot.stats.running {
+            turn.push_back(id);
+        }
+        slot.queue.push_back((from, delivery));
+        drop(state);
+        self.shared.wake.notify_all();
+        true
+    }
+
+    /// Lets the workers run the deliveries already queued, then stop; later deliveries are
+    /// still queued but never run.
+    pub fn close(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.wake.notify_all();
+    }
+
+    /// Returns the counters of every subscription, by id.
+    pub fn stats(&self) -> Vec<(u64, CallbackStats)> {
+        let state = self.shared.lock();
+        let mut stats: Vec<(u64, CallbackStats)> = state
+            .slots
+            .iter()
+            .filter(|(_, slot)| !slot.removed)
+            .map(|(id, slot)| {
+                let mut stats = slot.stats;
+                stats.queued = slot.queue.len();
+                (*id, stats)
+            })
+            .collect();
+        stats.sort_by_key(|(id, _)| *id);
+        stats
+    }
+
+    /// Returns the counters summed over every subscription, removed ones included, with the
+    /// deliveries queued now and whether any callback runs.
+    pub fn totals(&self) -> CallbackStats {
+        let state = self.shared.lock();
+        let mut totals = state.totals;
+        totals.queued = state.slots.values().map(|slot| slot.queue.len()).sum();
+        totals.running = state.slots.values().any(|slot| slot.stats.running);
+        totals
+    }
+
+    /// Returns the number of workers.
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+}
+
+/// Runs the callbacks whose turn comes, as the worker `index`, until the pool is closed and
+/// nothing is left for this worker.
+fn work(shared: &Shared, index: usize) {
+    let mut state = shared.lock();
+    loop {
+        let next = state.turn.iter().position(|id| {
+            state
+                .slots
+                .get(id)
+                .is_none_or(|slot| slot.pinned.is_none_or(|pinned| pinned == index))
+        });
+        let Some(position) = next else {
+            if state.closed {
+                return;
+            }
+            state = shared
+                .wake
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        let Some(id) = state.turn.remove(position) else {
+            continue;
+        };
+        let Some(slot) = state.slots.get_mut(&id) else {
+            continue;
+        };
+        let (Some(mut callback), Some((from, delivery))) =
+            (slot.callback.take(), slot.queue.pop_front())
+        else {
+            continue;
+        };
+        slot.stats.running = true;
+        drop(state);
+
+        let started = Instant::now();
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| callback(from, delivery))).is_err();
+        let elapsed = started.elapsed();
+
+        state = shared.lock();
+        let State {
+            slots,
+            turn,
+            totals,
+            ..
+        } = &mut *state;
+        totals.executed += 1;
+        totals.panicked += u64::from(panicked);
+        totals.slowest = totals.slowest.max(elapsed);
+        let Some(slot) = slots.get_mut(&id) else {
+            continue;
+        };
+        if slot.removed {
+            slots.remove(&id);
+            continue;
+        }
+        slot.stats.executed += 1;
+        slot.stats.panicked += u64::from(panicked);
+        slot.stats.slowest = slot.stats.slowest.max(elapsed);
+        slot.stats.running = false;
+        slot.callback = Some(callback);
+        if !slot.queue.is_empty() {
+            turn.push_back(id);
+            // The next delivery may be pinned to another worker.
+            shared.wake.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn message(seq: u64) -> Call {
+        Call::Delivery(Delivery::Message {
+            seq,
+            payload: Vec::new(),
+        })
+    }
+
+    fn seq_of(call: &Call) -> u64 {
+        match call {
+            Call::Delivery(Delivery::Message { seq, .. }) => *seq,
+            other => panic!("unexpected call {:?}", other),
+        }
+    }
+
+    /// A callback sending the sequence number of each call.
+    fn forwarding(calls: Sender<u64>) -> DeliveryCallback {
+        Box::new(move |_, call| {
+            let _ = calls.send(seq_of(&call));
+        })
+    }
+
+    /// A callback announcing each call on `started`, then waiting for `gate` to let it end.
+    fn gated(started: Sender<u64>, gate: Receiver<()>) -> DeliveryCallback {
+        Box::new(move |_, call| {
+            let _ = started.send(seq_of(&call));
+            let _ = gate.recv();
+        })
+    }
+
+    fn received(calls: &Receiver<u64>, count: usize) -> Vec<u64> {
+        (0..count)
+            .map(|_| calls.recv_timeout(WAIT).unwrap())
+            .collect()
+    }
+
+    fn stop(pool: &mut CallbackPool, threads: &ThreadRegistry) {
+        pool.close();
+        assert!(threads.shutdown(WAIT).is_empty());
+    }
+
+    #[test]
+    fn a_slow_callback_does_not_hold_a_fast_one_back() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(2, &threads).unwrap();
+        let (started, slow_calls) = mpsc::channel();
+        let (release, gate) = mpsc::channel();
+        let (calls, fast_calls) = mpsc::channel();
+        pool.add(1, gated(started, gate), false);
+        pool.add(2, forwarding(calls), false);
+
+        pool.deliver(1, addr(8080), message(0));
+        assert_eq!(slow_calls.recv_timeout(WAIT), Ok(0));
+        for seq in 0..20 {
+            assert!(pool.deliver(2, addr(8080), message(seq)));
+        }
+        assert_eq!(received(&fast_calls, 20), (0..20).collect::<Vec<u64>>());
+        let totals = pool.totals();
+        assert!(totals.running);
+        assert_eq!(totals.executed, 20);
+
+        release.send(()).unwrap();
+        stop(&mut pool, &threads);
+    }
+
+    #[test]
+    fn a_full_queue_drops_its_oldest_deliveries() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(1, &threads).unwrap();
+        let (started, calls) = mpsc::channel();
+        let (release, gate) = mpsc::channel();
+        pool.add(1, gated(started, gate), false);
+
+        pool.deliver(1, addr(8080), message(0));
+        assert_eq!(calls.recv_timeout(WAIT), Ok(0));
+        let queued = CALLBACK_QUEUE as u64;
+        for seq in 1..=queued + 3 {
+            pool.deliver(1, addr(8080), message(seq));
+        }
+        let [(1, stats)] = pool.stats()[..] else {
+            panic!("expected one subscription");
+        };
+        assert_eq!((stats.dropped, stats.queued), (3, CALLBACK_QUEUE));
+        assert!(stats.running);
+
+        for _ in 0..=queued {
+            release.send(()).unwrap();
+        }
+        let order = received(&calls, CALLBACK_QUEUE);
+        assert_eq!(order.first(), Some(&4));
+        assert_eq!(order.last(), Some(&(queued + 3)));
+        stop(&mut pool, &threads);
+        assert!(calls.try_recv().is_err());
+    }
+
+    #[test]
+    fn each_subscription_is_called_in_order() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(3, &threads).unwrap();
+        let (first, first_calls) = mpsc::channel();
+        let (second, second_calls) = mpsc::channel();
+        pool.add(1, forwarding(first), true);
+        pool.add(2, forwarding(second), false);
+
+        for seq in 0..200 {
+            pool.deliver(1, addr(8080), message(seq));
+            pool.deliver(2, addr(8081), message(seq));
+        }
+        let expected: Vec<u64> = (0..200).collect();
+        assert_eq!(received(&first_calls, 200), expected);
+        assert_eq!(received(&second_calls, 200), expected);
+        stop(&mut pool, &threads);
+    }
+
+    #[test]
+    fn a_panicking_callback_is_counted_and_called_again() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(1, &threads).unwrap();
+        let (calls, called) = mpsc::channel();
+        pool.add(
+            1,
+            Box::new(move |_, call| {
+                let seq = seq_of(&call);
+                calls.send(seq).unwrap();
+                assert!(seq != 0, "on purpose");
+            }),
+            false,
+        );
+        pool.deliver(1, addr(8080), message(0));
+        pool.deliver(1, addr(8080), message(1));
+        assert_eq!(received(&called, 2), [0, 1]);
+        stop(&mut pool, &threads);
+        let totals = pool.totals();
+        assert_eq!((totals.executed, totals.panicked), (2, 1));
+    }
+
+    #[test]
+    fn a_removed_subscription_takes_no_more_deliveries() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(1, &threads).unwrap();
+        let (started, calls) = mpsc::channel();
+        let (release, gate) = mpsc::channel();
+        pool.add(1, gated(started, gate), false);
+        pool.deliver(1, addr(8080), message(0));
+        assert_eq!(calls.recv_timeout(WAIT), Ok(0));
+        pool.deliver(1, addr(8080), message(1));
+
+        assert!(pool.remove(1));
+        assert!(!pool.remove(1));
+        assert!(!pool.deliver(1, addr(8080), message(2)));
+        assert!(pool.stats().is_empty());
+        release.send(()).unwrap();
+        stop(&mut pool, &threads);
+        assert!(calls.try_recv().is_err());
+    }
+
+    #[test]
+    fn closing_runs_the_queued_deliveries_first() {
+        let threads = ThreadRegistry::new(4);
+        let mut pool = CallbackPool::new(1, &threads).unwrap();
+        let (started, calls) = mpsc::channel();
+        let (release, gate) = mpsc::channel();
+        pool.add(1, gated(started, gate), false);
+        for seq in 0..5 {
+            pool.deliver(1, addr(8080), message(seq));
+        }
+        assert_eq!(calls.recv_timeout(WAIT), Ok(0));
+        pool.close();
+        for _ in 0..5 {
+            release.send(()).unwrap();
+        }
+        assert!(threads.shutdown(WAIT).is_empty());
+        assert_eq!(calls.try_iter().collect::<Vec<u64>>(), [1, 2, 3, 4]);
+        let totals = pool.totals();
+        assert_eq!((totals.executed, totals.queued), (5, 0));
+    }
+}
@@ -0,0 +1,201 @@
+//! Corroborated Dialing.
+//!
+//! An address learned from a participants list is only dialed once enough distinct peers
+//! vouch for it. Until then it is a candidate, stored with the set of peers that reported it,
+//! so a single peer serving fabricated addresses can not make this participant dial arbitrary
+//! hosts. With `--corroboration=<n>`, a candidate becomes dialable once `n` distinct peers
+//! reported it; the default of `1` dials every listed address at once.
+//!
+//! Reporters are told apart by the IP address their connection comes from, not by the public
+//! address they announce, which they choose: a host opening several connections, each
+//! announcing another address, still counts once.
+//!
+//! Some addresses need no corroboration: the explicit bootstrap target, the participants of a
+//! trusted membership snapshot and the addresses this participant reached by dialing them.
+//! Candidates that never gather enough reports expire after `CANDIDATE_TTL`.
+
+use crate::participant::collections::{BoundedMap, Eviction};
+use crate::participant::utils::{canonical, CanonicalAddr};
+
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// How long a candidate waits for more reports, counted from its first report.
+pub const CANDIDATE_TTL: Duration = Duration::from_secs(300);
+
+/// The addresses reported by too few peers to be dialed yet, and those trusted without reports.
+pub struct Candidates {
+    threshold: usize,
+    pending: BoundedMap<CanonicalAddr, HashSet<IpAddr>>,
+    trusted: BoundedMap<CanonicalAddr, ()>,
+    promoted: u64,
+}
+
+impl Candidates {
+    /// Creates an empty table.
+    ///
+    /// # Parameters
+    ///
+    /// * `threshold` - The number of distinct reporters making a candidate dialable.
+    /// * `capacity` - The maximal number of candidates, and of trusted addresses.
+    pub fn new(threshold: usize, capacity: usize) -> Self {
+        Self {
+            threshold,
+            pending: BoundedMap::new(capacity, Eviction::Fifo, Some(CANDIDATE_TTL)),
+            trusted: BoundedMap::new(capacity, Eviction::Lru, None),
+            promoted: 0,
+        }
+    }
+
+    /// Sets the number of distinct reporters making a candidate dialable. The candidates
+    /// pending already are promoted once reported again.
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
+    /// Marks `addr` as dialable whoever reports it, and drops it from the candidates.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The trusted address.
+    /// * `now` - The current time.
+    pub fn trust(&mut self, addr: SocketAddr, now: Instant) {
+        let addr = CanonicalAddr::from(addr);
+        self.pending.remove(&addr);
+        self.trusted.insert(addr, (), now);
+    }
+
+    /// Records that `reporter` listed `addr`.
+    ///
+    /// A reporter listing the same address again, in whatever form, counts once, and so do
+    /// all the connections from one IP address. A candidate that becomes dialable leaves the
+    /// table, so a later failed dial starts collecting reports anew.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The listed address.
+    /// * `reporter` - The remote address of the connection the list came through.
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// Whether `addr` may be dialed now.
+    pub fn report(&mut self, addr: SocketAddr, reporter: SocketAddr, now: Instant) -> bool {
+        let addr = CanonicalAddr::from(addr);
+        if self.threshold <= 1 || self.trusted.contains(&addr, now) {
+            self.pending.remove(&addr);
+            return true;
+        }
+
+        let reporters = match self.pending.get_mut(&addr, now) {
+            Some(reporters) => reporters,
+            None => {
+                self.pending.insert(addr, HashSet::new(), now);
+                match self.pending.get_mut(&addr, now) {
+                    Some(reporters) => reporters,
+                    // A table of capacity 0 keeps nothing, so nothing is ever corroborated.
+                    None => return false,
+                }
+            }
+        };
+        reporters.insert(canonical(reporter).ip());
+        if reporters.len() < self.threshold {
+            return false;
+        }
+
+        self.pending.remove(&addr);
+        self.promoted += 1;
+        true
+    }
+
+    /// Forgets the candidates that outlived `CANDIDATE_TTL`.
+    pub fn sweep(&mut self, now: Instant) {
+        self.pending.sweep(now);
+    }
+
+    /// Returns the number of distinct reporters making a candidate dialable.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Returns the number of candidates waiting for more reports.
+    pub fn count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of candidates that became dialable after enough reports.
+    pub fn promoted(&self) -> u64 {
+        self.promoted
+    }
+
+    /// Returns the number of candidates dropped before becoming dialable, expired or evicted.
+    pub fn dropped(&self) -> u64 {
+        self.pending.evictions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn from(ip: [u8; 4], port: u16) -> SocketAddr {
+        SocketAddr::from((ip, port))
+    }
+
+    #[test]
+    fn a_candidate_is_dialable_once_enough_hosts_reported_it() {
+        let now = Instant::now();
+        let mut candidates = Candidates::new(3, 16);
+        assert!(!candidates.report(addr(9000), from([10, 0, 0, 1], 4000), now));
+        assert!(!candidates.report(addr(9000), from([10, 0, 0, 2], 4000), now));
+        assert_eq!(candidates.count(), 1);
+        assert!(candidates.report(addr(9000), from([10, 0, 0, 3], 4000), now));
+        assert_eq!((candidates.count(), candidates.promoted()), (0, 1));
+
+        // A promoted candidate collects its reports anew.
+        assert!(!candidates.report(addr(9000), from([10, 0, 0, 1], 4000), now));
+    }
+
+    #[test]
+    fn the_connections_of_one_host_count_as_one_reporter() {
+        let now = Instant::now();
+        let mut candidates = Candidates::new(2, 16);
+        for port in 4000..4010 {
+            assert!(!candidates.report(addr(9000), from([10, 0, 0, 1], port), now));
+        }
+        let mapped = "[::ffff:10.0.0.1]:4000".parse().unwrap();
+        assert!(!candidates.report(addr(9000), mapped, now));
+        assert!(candidates.report(addr(9000), from([10, 0, 0, 2], 4000), now));
+    }
+
+    #[test]
+    fn an_uncorroborated_candidate_expires() {
+        let now = Instant::now();
+        let mut candidates = Candidates::new(2, 16);
+        assert!(!candidates.report(addr(9000), from([10, 0, 0, 1], 4000), now));
+        candidates.sweep(now + CANDIDATE_TTL + Duration::from_secs(1));
+        assert_eq!((candidates.count(), candidates.dropped()), (0, 1));
+
+        // The report of the first host is forgotten with the candidate.
+        let later = now + CANDIDATE_TTL + Duration::from_secs(2);
+        assert!(!candidates.report(addr(9000), from([10, 0, 0, 2], 4000), later));
+    }
+
+    #[test]
+    fn trusted_addresses_and_a_threshold_of_one_bypass_the_reports() {
+        let now = Instant::now();
+        let mut candidates = Candidates::new(1, 16);
+        assert!(candidates.report(addr(9000), from([10, 0, 0, 1], 4000), now));
+
+        candidates.set_threshold(3);
+        assert!(!candidates.report(addr(9001), from([10, 0, 0, 1], 4000), now));
+        candidates.trust(addr(9001), now);
+        assert_eq!(candidates.count(), 0);
+        assert!(candidates.report(addr(9001), from([10, 0, 0, 1], 4000), now));
+        let mapped = "[::ffff:127.0.0.1]:9001".parse().unwrap();
+        assert!(candidates.report(mapped, from([10, 0, 0, 1], 4000), now));
+        assert_eq!(candidates.promoted(), 0);
+    }
+}
@@ -0,0 +1,246 @@
+//! Internal Channels.
+//!
+//! The event loop hands work to its threads through channels: the records to the recorder,
+//! the raised alerts to the alert worker and the requests to the request workers. When one of
+//! them falls behind, its channel is where the work piles up. A `ChannelRegistry` creates each
+//! of these channels as a `MonitoredSender` and `MonitoredReceiver` pair keeping, under the
+//! name it was given:
+//!
+//! - the messages waiting in the channel, and the most that ever waited at once;
+//! - the messages sent so far;
+//! - the messages dropped because the receiving end was gone.
+//!
+//! The counters are a few atomic operations per message, so the wrappers take no lock on the
+//! way; only creating a channel and listing them lock the registry. The channels are unbounded,
+//! so a send never blocks. The `channels` console command lists them, and `stats` sums them up.
+//!
+//! The one-shot channels carrying a single reply back to a caller, and the inboxes, which
+//! count their own drops, are not monitored.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, SendError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The counters shared by both ends of a monitored channel and the registry.
+#[derive(Debug, Default)]
+struct Counters {
+    depth: AtomicUsize,
+    high_water: AtomicUsize,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// The counters of a monitored channel at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// The name the channel was created with.
+    pub name: String,
+    /// The messages waiting to be received.
+    pub depth: usize,
+    /// The most messages that ever waited at once.
+    pub high_water: usize,
+    /// The messages sent so far.
+    pub sent: u64,
+    /// The messages dropped because the receiving end was gone.
+    pub dropped: u64,
+    /// Whether both ends of the channel are gone.
+    pub closed: bool,
+}
+
+/// The sending end of a monitored channel.
+#[derive(Debug)]
+pub struct MonitoredSender<T> {
+    inner: Sender<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T> Clone for MonitoredSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<T> MonitoredSender<T> {
+    /// Sends `value`, counting it as waiting until it is received.
+    ///
+    /// # Returns
+    ///
+    /// The value back, counted as dropped, when the receiving end is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        // The depth grows before the send, so that the receiver never takes it below zero.
+        let depth = self.counters.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.counters.high_water.fetch_max(depth, Ordering::Relaxed);
+        match self.inner.send(value) {
+            Ok(()) => {
+                self.counters.sent.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(err) => {
+                self.counters.depth.fetch_sub(1, Ordering::Relaxed);
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// The receiving end of a monitored channel.
+#[derive(Debug)]
+pub struct MonitoredReceiver<T> {
+    inner: Receiver<T>,
+    counters: Arc<Counters>,
+}
+
+impl<T> MonitoredReceiver<T> {
+    /// Waits for the next value, until every sender is gone.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let value = self.inner.recv()?;
+        self.counters.depth.fetch_sub(1, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    /// Takes the values waiting now, without blocking.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.inner.try_iter().inspect(|_| {
+            self.counters.depth.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+}
+
+/// A channel created through the registry.
+#[derive(Debug)]
+struct Registered {
+    name: String,
+    counters: Arc<Counters>,
+}
+
+/// Creates and lists the monitored channels of a participant.
+///
+/// Clones share the same list, so a channel created through any of them is listed by all.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelRegistry(Arc<Mutex<Vec<Registered>>>);
+
+impl ChannelRegistry {
+    /// Constructs a registry without channels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a channel listed as `name`.
+    pub fn channel<T>(&self, name: &str) -> (MonitoredSender<T>, MonitoredReceiver<T>) {
+        let (sender, receiver) = mpsc::channel();
+        let counters = Arc::new(Counters::default());
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Registered {
+                name: name.to_owned(),
+                counters: counters.clone(),
+            });
+        (
+            MonitoredSender {
+                inner: sender,
+                counters: counters.clone(),
+            },
+            MonitoredReceiver {
+                inner: receiver,
+                counters,
+            },
+        )
+    }
+
+    /// Returns the counters of every channel, in the order they were created.
+    pub fn stats(&self) -> Vec<ChannelStats> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|Registered { name, counters }| ChannelStats {
+                name: name.clone(),
+                depth: counters.depth.load(Ordering::Relaxed),
+                high_water: counters.high_water.load(Ordering::Relaxed),
+                sent: counters.sent.load(Ordering::Relaxed),
+                dropped: counters.dropped.load(Ordering::Relaxed),
+                // Only the registry still holds the counters once both ends are dropped.
+                closed: Arc::strong_count(counters) == 1,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The counters of the channel listed as `name`.
+    fn stats_of(registry: &ChannelRegistry, name: &str) -> ChannelStats {
+        registry
+            .stats()
+            .into_iter()
+            .find(|stats| stats.name == name)
+            .expect("the channel is listed")
+    }
+
+    #[test]
+    fn channels_are_listed_in_creation_order_by_every_clone() {
+        let registry = ChannelRegistry::new();
+        let clone = registry.clone();
+        let _recorder = registry.channel::<u32>("recorder");
+        let _alerts = clone.channel::<u32>("alerts");
+
+        let names: Vec<_> = registry
+            .stats()
+            .into_iter()
+            .map(|stats| stats.name)
+            .collect();
+        assert_eq!(names, ["recorder", "alerts"]);
+        assert_eq!(clone.stats(), registry.stats());
+    }
+
+    #[test]
+    fn the_depth_follows_the_messages_waiting() {
+        let registry = ChannelRegistry::new();
+        let (sender, receiver) = registry.channel("requests-1");
+        for value in 0..3 {
+            sender.send(value).unwrap();
+        }
+        assert_eq!(receiver.recv(), Ok(0));
+
+        let stats = stats_of(&registry, "requests-1");
+        assert_eq!((stats.depth, stats.high_water, stats.sent), (2, 3, 3));
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [1, 2]);
+        sender.send(3).unwrap();
+        let stats = stats_of(&registry, "requests-1");
+        assert_eq!((stats.depth, stats.high_water, stats.sent), (1, 3, 4));
+        assert!(!stats.closed);
+    }
+
+    #[test]
+    fn a_send_without_a_receiver_is_dropped() {
+        let registry = ChannelRegistry::new();
+        let (sender, receiver) = registry.channel("alerts");
+        drop(receiver);
+        assert_eq!(sender.send(7), Err(SendError(7)));
+
+        let stats = stats_of(&registry, "alerts");
+        assert_eq!((stats.depth, stats.sent, stats.dropped), (0, 0, 1));
+        assert!(!stats.closed);
+    }
+
+    #[test]
+    fn a_channel_is_closed_once_both_ends_are_gone() {
+        let registry = ChannelRegistry::new();
+        let (sender, receiver) = registry.channel::<u32>("recorder");
+        let other = sender.clone();
+        drop(sender);
+        drop(receiver);
+        assert!(!stats_of(&registry, "recorder").closed);
+
+        drop(other);
+        assert!(stats_of(&registry, "recorder").closed);
+    }
+}
@@ -0,0 +1,405 @@
+//! Bounded Collections.
+//!
+//! This module provides `BoundedMap`, the map used by every piece of per-peer or per-address
+//! state that would otherwise grow without limit as peers come and go. The map enforces a hard
+//! entry cap, evicting either the oldest inserted (FIFO) or the least recently used (LRU) entry,
+//! and optionally expires entries after a time-to-live. Expired entries are swept lazily on
+//! access, when an insertion finds the map full, and explicitly by the participant's
+//! maintenance tick.
+//!
+//! Time is always passed in by the caller, which keeps the map free of hidden clock reads. Every
+//! touch of an entry moves it to the back of the eviction order and restarts its time-to-live,
+//! so the eviction order is also the expiry order and a sweep stops at the first live entry.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The order in which entries are evicted when the map is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evict the entry inserted first; reads do not change the order.
+    Fifo,
+    /// Evict the entry used least recently; reads and updates move an entry to the back.
+    Lru,
+}
+
+/// Why an entry left the map without being removed explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionCause {
+    /// The map was full and the entry made room for a new one.
+    Capacity,
+    /// The entry outlived the time-to-live.
+    Expired,
+}
+
+/// Callback invoked for every evicted entry.
+type EvictionCallback<K, V> = Box<dyn FnMut(&K, &V, EvictionCause) + Send>;
+
+/// A single stored value with its bookkeeping.
+struct Entry<V> {
+    value: V,
+    touched_at: Instant,
+    order: u64,
+}
+
+/// A hash map with a hard entry cap and an optional time-to-live.
+pub struct BoundedMap<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<u64, K>,
+    next_order: u64,
+    capacity: usize,
+    ttl: Option<Duration>,
+    eviction: Eviction,
+    evictions: u64,
+    on_evict: Option<EvictionCallback<K, V>>,
+}
+
+impl<K: Hash + Eq + Clone, V> BoundedMap<K, V> {
+    /// Constructs an empty `BoundedMap`.
+    ///
+    /// # Parameters
+    ///
+    /// * `capacity` - The maximal number of entries; a capacity of `0` keeps nothing.
+    /// * `eviction` - Which entry makes room when the map is full.
+    /// * `ttl` - How long an entry lives after its last insertion (or use, with LRU).
+    pub fn new(capacity: usize, eviction: Eviction, ttl: Option<Duration>) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_order: 0,
+            capacity,
+            ttl,
+            eviction,
+            evictions: 0,
+            on_evict: None,
+        }
+    }
+
+    /// Registers a callback invoked for every evicted entry, replacing the previous one.
+    pub fn with_eviction_callback(
+        mut self,
+        callback: impl FnMut(&K, &V, EvictionCause) + Send + 'static,
+    ) -> Self {
+        self.on_evict = Some(Box::new(callback));
+        self
+    }
+
+    /// Inserts or replaces the value stored under `key`.
+    ///
+    /// Replacing a value moves the entry to the back of the eviction order. Inserting into a
+    /// full map evicts one entry first.
+    pub fn insert(&mut self, key: K, value: V, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let order = self.bump_order();
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.order.remove(&entry.order);
+            entry.value = value;
+            entry.touched_at = now;
+            entry.order = order;
+            self.order.insert(order, key);
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.sweep(now);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.evicted(&oldest, &entry.value, EvictionCause::Capacity);
+            }
+        }
+
+        self.entries.insert(
+            key.clone(),
+            Entry {
+                value,
+                touched_at: now,
+                order,
+            },
+        );
+        self.order.insert(order, key);
+    }
+
+    /// Returns a mutable reference to the value stored under `key`, if it has not expired.
+    ///
+    /// With LRU eviction, a successful lookup moves the entry to the back of the order and
+    /// restarts its time-to-live.
+    pub fn get_mut(&mut self, key: &K, now: Instant) -> Option<&mut V> {
+        if self.is_expired(key, now) {
+            if let Some(entry) = self.entries.remove(key) {
+                self.order.remove(&entry.order);
+                self.evicted(key, &entry.value, EvictionCause::Expired);
+            }
+            return None;
+        }
+
+        if self.eviction == Eviction::Lru && self.entries.contains_key(key) {
+            let order = self.bump_order();
+            let entry = self.entries.get_mut(key)?;
+            self.order.remove(&entry.order);
+            entry.order = order;
+            entry.touched_at = now;
+            self.order.insert(order, key.clone());
+        }
+
+        self.entries.get_mut(key).map(|entry| &mut entry.value)
+    }
+
+    /// Returns the value stored under `key` without touching it, including an expired entry
+    /// not swept yet.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    /// Determines whether a live entry exists under `key`, with the same effects as `get_mut`.
+    pub fn contains(&mut self, key: &K, now: Instant) -> bool {
+        self.get_mut(key, now).is_some()
+    }
+
+    /// Removes the entry stored under `key` without counting it as an eviction.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.order);
+        Some(entry.value)
+    }
+
+    /// Removes every entry that outlived the time-to-live, oldest first.
+    pub fn sweep(&mut self, now: Instant) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+
+        while let Some((_, key)) = self.order.first_key_value() {
+            let expired = self
+                .entries
+                .get(key)
+                .is_some_and(|entry| now.duration_since(entry.touched_at) > ttl);
+            if !expired {
+                break;
+            }
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.evicted(&key, &entry.value, EvictionCause::Expired);
+            }
+        }
+    }
+
+    /// Iterates over the stored keys and values, including expired entries not swept yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// Returns the number of stored entries, including expired ones not swept yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determines whether the map holds no entry at all.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the maximal number of entries.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries evicted so far, for any cause.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    fn bump_order(&mut self) -> u64 {
+        let order = self.next_order;
+        self.next_order += 1;
+        order
+    }
+
+    fn is_expired(&self, key: &K, now: Instant) -> bool {
+        match (self.ttl, self.entries.get(key)) {
+            (Some(ttl), Some(entry)) => now.duration_since(entry.touched_at) > ttl,
+            _ => false,
+        }
+    }
+
+    fn evicted(&mut self, key: &K, value: &V, cause: EvictionCause) {
+        self.evictions += 1;
+        if let Some(callback) = self.on_evict.as_mut() {
+            callback(key, value, cause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::{Arc, Mutex};
+
+    fn filled(eviction: Eviction, ttl: Option<Duration>, now: Instant) -> BoundedMap<u32, u32> {
+        let mut map = BoundedMap::new(3, eviction, ttl);
+        for key in 1..=3 {
+            map.insert(key, key * 10, now);
+        }
+        map
+    }
+
+    fn keys(map: &BoundedMap<u32, u32>) -> Vec<u32> {
+        map.order.values().copied().collect()
+    }
+
+    #[test]
+    fn fifo_evicts_the_first_inserted_whatever_is_read() {
+        let now = Instant::now();
+        let mut map = filled(Eviction::Fifo, None, now);
+        assert_eq!(map.get_mut(&1, now), Some(&mut 10));
+        map.insert(4, 40, now);
+        assert_eq!(keys(&map), [2, 3, 4]);
+        assert_eq!(map.get(&1), None);
+
+        map.insert(2, 21, now);
+        map.insert(5, 50, now);
+        assert_eq!(keys(&map), [4, 2, 5]);
+        assert_eq!(map.evictions(), 2);
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used() {
+        let now = Instant::now();
+        let mut map = filled(Eviction::Lru, None, now);
+        assert!(map.contains(&1, now));
+        map.insert(4, 40, now);
+        assert_eq!(keys(&map), [3, 1, 4]);
+
+        // Reading without touching leaves the order alone.
+        assert_eq!(map.get(&3), Some(&30));
+        map.insert(5, 50, now);
+        assert_eq!(keys(&map), [1, 4, 5]);
+    }
+
+    #[test]
+    fn expires_entries_after_their_ttl() {
+        let start = Instant::now();
+        let ttl = Duration::from_secs(10);
+        let mut map = filled(Eviction::Fifo, Some(ttl), start);
+        map.insert(2, 21, start + Duration::from_secs(5));
+
+        assert!(map.contains(&1, start + ttl));
+        assert!(!map.contains(&1, start + ttl + Duration::from_millis(1)));
+        assert_eq!(map.len(), 2);
+
+        map.sweep(start + ttl + Duration::from_secs(1));
+        assert_eq!(keys(&map), [2]);
+        map.sweep(start + ttl + Duration::from_secs(6));
+        assert!(map.is_empty());
+        assert_eq!(map.evictions(), 3);
+    }
+
+    #[test]
+    fn an_lru_touch_restarts_the_ttl() {
+        let start = Instant::now();
+        let ttl = Duration::from_secs(10);
+        let mut lru = filled(Eviction::Lru, Some(ttl), start);
+        let mut fifo = filled(Eviction::Fifo, Some(ttl), start);
+        let touched = start + Duration::from_secs(8);
+        assert!(lru.contains(&1, touched));
+        assert!(fifo.contains(&1, touched));
+
+        let later = start + Duration::from_secs(15);
+        lru.sweep(later);
+        fifo.sweep(later);
+        assert_eq!(keys(&lru), [1]);
+        assert!(fifo.is_empty());
+    }
+
+    #[test]
+    fn a_full_map_makes_room_with_expired_entries_first() {
+        let start = Instant::now();
+        let mut map = filled(Eviction::Fifo, Some(Duration::from_secs(10)), start);
+        map.insert(3, 31, start + Duration::from_secs(5));
+        map.insert(4, 40, start + Duration::from_secs(11));
+        assert_eq!(keys(&map), [3, 4]);
+
+        // An insertion under the cap leaves the expired entries to a later sweep.
+        let mut map = BoundedMap::new(3, Eviction::Fifo, Some(Duration::from_secs(10)));
+        map.insert(1, 10, start);
+        map.insert(2, 20, start + Duration::from_secs(11));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn reports_every_eviction_with_its_cause() {
+        let start = Instant::now();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&evicted);
+        let mut map = BoundedMap::new(2, Eviction::Fifo, Some(Duration::from_secs(10)))
+            .with_eviction_callback(move |key: &u32, value: &u32, cause| {
+                log.lock().unwrap().push((*key, *value, cause));
+            });
+        map.insert(1, 10, start);
+        map.insert(2, 20, start + Duration::from_secs(5));
+        map.insert(3, 30, start + Duration::from_secs(6));
+        assert_eq!(map.remove(&2), Some(20));
+        map.sweep(start + Duration::from_secs(20));
+
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            [
+                (1, 10, EvictionCause::Capacity),
+                (3, 30, EvictionCause::Expired)
+            ]
+        );
+        assert_eq!(map.evictions(), 2);
+    }
+
+    #[test]
+    fn a_map_without_capacity_keeps_nothing() {
+        let now = Instant::now();
+        let mut map = BoundedMap::new(0, Eviction::Lru, None);
+        map.insert(1, 10, now);
+        assert!(map.is_empty());
+        assert_eq!(map.evictions(), 0);
+    }
+
+    #[test]
+    fn stays_within_its_capacity_under_churn() {
+        let mut rng = StdRng::seed_from_u64(0x626f756e);
+        let start = Instant::now();
+        for eviction in [Eviction::Fifo, Eviction::Lru] {
+            let mut map = BoundedMap::new(64, eviction, Some(Duration::from_millis(500)));
+            let mut now = start;
+            for _ in 0..50_000 {
+                now += Duration::from_millis(rng.gen_range(0..5));
+                let key = rng.gen_range(0..256u32);
+                match rng.gen_range(0..4) {
+                    0 => {
+                        map.remove(&key);
+                    }
+                    1 => {
+                        map.contains(&key, now);
+                    }
+                    2 if rng.gen_ratio(1, 50) => map.sweep(now),
+                    _ => map.insert(key, key, now),
+                }
+                assert!(map.len() <= map.capacity());
+                assert_eq!(map.order.len(), map.entries.len());
+            }
+            assert!(map
+                .entries
+                .iter()
+                .all(|(key, entry)| map.order.get(&entry.order) == Some(key)));
+            map.sweep(now + Duration::from_secs(1));
+            assert!(map.is_empty());
+        }
+    }
+}
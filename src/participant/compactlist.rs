@@ -0,0 +1,755 @@
+//! Compact Participants Lists.
+//!
+//! A participants list encodes each address on its own, ten bytes for an IPv4 address and its
+//! age bucket taking four more, although the addresses of a large network mostly share their
+//! prefixes, and an orchestrator hands out ports in sequence. A peer speaking
+//! `COMPACT_LISTS_VERSION` is answered with a `Message::CompactListReply` instead of a
+//! `ListReply`, a `PackedList` carrying each age bucket of the list as addresses sorted in the
+//! order of `SocketAddr` and encoded by `encode`.
+//!
+//! The encoding starts with a form byte. The compact form holds the IPv4 addresses and then
+//! the IPv6 ones, each section as a varint count, the first address in full, and for every
+//! other address the difference between its IP and the previous one, as a varint, followed by
+//! the difference between their ports, zigzagged into a varint. The plain form holds a varint
+//! count and then every address in full, a family byte `4` or `6`, the octets of the IP and
+//! the port, big-endian. `encode` writes the compact form only when it is smaller than the
+//! plain one, which it is not for a handful of scattered addresses. Three addresses of
+//! neighbouring hosts take 13 bytes instead of 23:
+//!
+//! ```plaintext
+//! 127.0.0.1:8000, 127.0.0.1:8001, 127.0.0.3:8000
+//!
+//! 01                compact form
+//! 03                three IPv4 addresses
+//! 7f 00 00 01 1f 40 127.0.0.1:8000
+//! 00 02             same IP, port + 1
+//! 02 01             IP + 2, port - 1
+//! 00                no IPv6 address
+//! ```
+//!
+//! Only the IP and the port of an address are encoded, as with the other lists: an IPv6
+//! address comes back without its flow information and scope id. `decode` refuses an
+//! encoding whose addresses are not strictly increasing, so an encoding stands for a single
+//! sorted set and a list decodes to no duplicate.
+//!
+//! The age buckets each hold the addresses in canonical order instead of the order the sender
+//! ranked them in, which only decided the dial order within a bucket. The address the sender
+//! advertises, which a list names first, is carried apart in `PackedList::lister`.
+//!
+//! The protocol announces what a peer understands by its version alone, with no list of
+//! capabilities to hold a `peerlist-compact` flag, so a peer of `COMPACT_LISTS_VERSION` or later
+//! is the one able to decode a packed list, and an older one keeps getting `ListReply`.
+
+use crate::participant::message::{AgeBucket, SharedPeer};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The form byte of an encoding holding every address in full.
+pub const PLAIN_FORM: u8 = 0;
+
+/// The form byte of an encoding holding differences between consecutive addresses.
+pub const COMPACT_FORM: u8 = 1;
+
+/// The most bytes a varint of a `u128` takes.
+const MAX_VARINT_LEN: usize = 19;
+
+/// Enumerates the reasons an encoded list can not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompactListError {
+    /// The bytes end before the list does.
+    Truncated,
+    /// The bytes go on after the list.
+    TrailingBytes(usize),
+    /// The form byte is neither `PLAIN_FORM` nor `COMPACT_FORM`.
+    UnknownForm(u8),
+    /// A family byte of the plain form is neither `4` nor `6`.
+    UnknownFamily(u8),
+    /// A varint does not fit the value it stands for.
+    Overflow,
+    /// An address is not greater than the one before it.
+    Unordered(SocketAddr),
+}
+
+impl fmt::Display for CompactListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactListError::Truncated => write!(f, "the list is truncated"),
+            CompactListError::TrailingBytes(len) => {
+                write!(f, "{} bytes follow the list", len)
+            }
+            CompactListError::UnknownForm(form) => write!(f, "unknown list form {}", form),
+            CompactListError::UnknownFamily(family) => {
+                write!(f, "unknown address family {}", family)
+            }
+            CompactListError::Overflow => write!(f, "a difference overflows its address"),
+            CompactListError::Unordered(addr) => {
+                write!(f, "the address {} does not follow the one before it", addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompactListError {}
+
+/// A participants list as a `Message::CompactListReply` carries it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackedList {
+    /// The first address of the list, the one its sender advertises; `None` for an empty list.
+    pub lister: Option<SocketAddr>,
+    /// The other addresses verified less than `FRESH_AGE` ago, as `encode` writes them.
+    pub fresh: Vec<u8>,
+    /// The other addresses verified less than `RECENT_AGE` ago.
+    pub recent: Vec<u8>,
+    /// The other addresses, verified longer ago or never.
+    pub stale: Vec<u8>,
+}
+
+/// The packed lists a participant sent, with the bytes they took and would have taken as
+/// `ListReply` messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackingStats {
+    /// The number of packed lists sent.
+    pub lists: u64,
+    /// The bytes of the packed lists.
+    pub packed_bytes: u64,
+    /// The bytes of the `ListReply` messages they replaced.
+    pub plain_bytes: u64,
+}
+
+impl PackingStats {
+    /// Counts a packed list of `packed` bytes, replacing a reply of `plain` bytes.
+    pub fn record(&mut self, packed: usize, plain: usize) {
+        self.lists += 1;
+        self.packed_bytes += packed as u64;
+        self.plain_bytes += plain as u64;
+    }
+}
+
+/// Encodes `addrs` in the smaller of the two forms.
+///
+/// The addresses are sorted and their duplicates dropped first, so the order they are given
+/// in does not matter.
+pub fn encode(addrs: &[SocketAddr]) -> Vec<u8> {
+    let mut sorted: Vec<SocketAddr> = addrs.iter().map(|addr| stripped(*addr)).collect();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let compact = encode_compact(&sorted);
+    if compact.len() < plain_len(&sorted) {
+        compact
+    } else {
+        encode_plain(&sorted)
+    }
+}
+
+/// Decodes an encoding written by `encode`.
+///
+/// # Returns
+///
+/// The addresses, sorted and without duplicates.
+pub fn decode(bytes: &[u8]) -> Result<Vec<SocketAddr>, CompactListError> {
+    let mut reader = Reader { bytes, at: 0 };
+    let mut addrs = Vec::new();
+    match reader.byte()? {
+        PLAIN_FORM => {
+            let count = reader.count()?;
+            for _ in 0..count {
+                let ip = match reader.byte()? {
+                    4 => IpAddr::V4(Ipv4Addr::from(reader.array::<4>()?)),
+                    6 => IpAddr::V6(Ipv6Addr::from(reader.array::<16>()?)),
+                    family => return Err(CompactListError::UnknownFamily(family)),
+                };
+                let addr = SocketAddr::new(ip, u16::from_be_bytes(reader.array()?));
+                push_ordered(&mut addrs, addr)?;
+            }
+        }
+        COMPACT_FORM => {
+            decode_section(&mut reader, &mut addrs, true)?;
+            decode_section(&mut reader, &mut addrs, false)?;
+        }
+        form => return Err(CompactListError::UnknownForm(form)),
+    }
+    match bytes.len() - reader.at {
+        0 => Ok(addrs),
+        trailing => Err(CompactListError::TrailingBytes(trailing)),
+    }
+}
+
+impl PackedList {
+    /// Packs a participants list, its first entry as the lister and the others by age bucket.
+    ///
+    /// The age of the first entry is not kept: it is the sender itself, always fresh.
+    pub fn pack(peers: &[SharedPeer]) -> Self {
+        let bucket = |age: AgeBucket| {
+            let addrs: Vec<SocketAddr> = peers
+                .iter()
+                .skip(1)
+                .filter(|peer| peer.age == age)
+                .map(|peer| peer.addr)
+                .collect();
+            encode(&addrs)
+        };
+        Self {
+            lister: peers.first().map(|peer| peer.addr),
+            fresh: bucket(AgeBucket::Fresh),
+            recent: bucket(AgeBucket::Recent),
+            stale: bucket(AgeBucket::Stale),
+        }
+    }
+
+    /// Unpacks the list: the lister first, then the fresher addresses first.
+    pub fn unpack(&self) -> Result<Vec<SharedPeer>, CompactListError> {
+        let mut peers: Vec<SharedPeer> = self
+            .lister
+            .map(|addr| SharedPeer {
+                addr,
+                age: AgeBucket::Fresh,
+            })
+            .into_iter()
+            .collect();
+        for (bytes, age) in [
+            (&self.fresh, AgeBucket::Fresh),
+            (&self.recent, AgeBucket::Recent),
+            (&self.stale, AgeBucket::Stale),
+        ] {
+            peers.extend(
+                decode(bytes)?
+                    .into_iter()
+                    .map(|addr| SharedPeer { addr, age }),
+            );
+        }
+        Ok(peers)
+    }
+}
+
+/// Returns `addr` without what the encoding does not carry.
+fn stripped(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip(), addr.port())
+}
+
+/// Returns the length of the plain form of the sorted `addrs`.
+fn plain_len(addrs: &[SocketAddr]) -> usize {
+    let entries: usize = addrs
+        .iter()
+        .map(|addr| match addr {
+            SocketAddr::V4(_) => 1 + 4 + 2,
+            SocketAddr::V6(_) => 1 + 16 + 2,
+        })
+        .sum();
+    1 + varint_len(addrs.len() as u128) + entries
+}
+
+/// Encodes the sorted `addrs` in the plain form.
+fn encode_plain(addrs: &[SocketAddr]) -> Vec<u8> {
+    let mut bytes = vec![PLAIN_FORM];
+    write_varint(&mut bytes, addrs.len() as u128);
+    for addr in addrs {
+        match addr.ip() {
+            IpAddr::V4(ip) => {
+                bytes.push(4);
+                bytes.extend(ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                bytes.push(6);
+                bytes.extend(ip.octets());
+            }
+        }
+        bytes.extend(addr.port().to_be_bytes());
+    }
+    bytes
+}
+
+/// Encodes the sorted `addrs` in the compact form.
+fn encode_compact(addrs: &[SocketAddr]) -> Vec<u8> {
+    let mut bytes = vec![COMPACT_FORM];
+    let split = addrs.partition_point(SocketAddr::is_ipv4);
+    let (v4, v6) = addrs.split_at(split);
+    encode_section(&mut bytes, v4);
+    encode_section(&mut bytes, v6);
+    bytes
+}
+
+/// Writes the section of the sorted `addrs`, all of the same family.
+fn encode_section(bytes: &mut Vec<u8>, addrs: &[SocketAddr]) {
+    write_varint(bytes, addrs.len() as u128);
+    let mut previous: Option<(u128, u16)> = None;
+    for addr in addrs {
+        let ip = ip_bits(addr.ip());
+        match previous {
+            None => {
+                match addr.ip() {
+                    IpAddr::V4(ip) => bytes.extend(ip.octets()),
+                    IpAddr::V6(ip) => bytes.extend(ip.octets()),
+                }
+                bytes.extend(addr.port().to_be_bytes());
+            }
+            Some((previous_ip, previous_port)) => {
+                write_varint(bytes, ip - previous_ip);
+                let port_delta = i32::from(addr.port()) - i32::from(previous_port);
+                write_varint(bytes, zigzag(port_delta));
+            }
+        }
+        previous = Some((ip, addr.port()));
+    }
+}
+
+/// Reads the section of the IPv4 addresses, or of the IPv6 ones, into `addrs`.
+fn decode_section(
+    reader: &mut Reader<'_>,
+    addrs: &mut Vec<SocketAddr>,
+    v4: bool,
+) -> Result<(), CompactListError> {
+    let count = reader.count()?;
+    let mut previous: Option<(u128, u16)> = None;
+    for _ in 0..count {
+        let (ip, port) = match previous {
+            None => {
+                let ip = match v4 {
+                    true => u128::from(u32::from_be_bytes(reader.array()?)),
+                    false => u128::from_be_bytes(reader.array()?),
+                };
+                (ip, u16::from_be_bytes(reader.array()?))
+            }
+            Some((previous_ip, previous_port)) => {
+                let ip = previous_ip
+                    .checked_add(reader.varint()?)
+                    .ok_or(CompactListError::Overflow)?;
+                let port = i32::from(previous_port)
+                    .checked_add(unzigzag(reader.varint()?)?)
+                    .and_then(|port| u16::try_from(port).ok())
+                    .ok_or(CompactListError::Overflow)?;
+                (ip, port)
+            }
+        };
+        let ip = if v4 {
+            IpAddr::V4(Ipv4Addr::from(
+                u32::try_from(ip).map_err(|_| CompactListError::Overflow)?,
+            ))
+        } else {
+            IpAddr::V6(Ipv6Addr::from(ip))
+        };
+        push_ordered(addrs, SocketAddr::new(ip, port))?;
+        previous = Some((ip_bits(ip), port));
+    }
+    Ok(())
+}
+
+/// Appends `addr` to the decoded `addrs`, unless it does not follow the last of them.
+fn push_ordered(addrs: &mut Vec<SocketAddr>, addr: SocketAddr) -> Result<(), CompactListError> {
+    if addrs.last().is_some_and(|last| *last >= addr) {
+        return Err(CompactListError::Unordered(addr));
+    }
+    addrs.push(addr);
+    Ok(())
+}
+
+/// Returns the bits of `ip` as a number.
+fn ip_bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(u32::from(ip)),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Maps a signed difference to an unsigned one, small either way.
+fn zigzag(value: i32) -> u128 {
+    ((value << 1) ^ (value >> 31)) as u32 as u128
+}
+
+/// Maps back a difference mapped by `zigzag`.
+fn unzigzag(value: u128) -> Result<i32, CompactListError> {
+    let value = u32::try_from(value).map_err(|_| CompactListError::Overflow)?;
+    Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+}
+
+/// Appends `value` as a varint, seven bits per byte, least significant first.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Returns the number of bytes the varint of `value` takes.
+fn varint_len(value: u128) -> usize {
+    let bits = (128 - value.leading_zeros() as usize).max(1);
+    bits.div_ceil(7)
+}
+
+/// Reads an encoding from its start.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, CompactListError> {
+        let byte = *self.bytes.get(self.at).ok_or(CompactListError::Truncated)?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CompactListError> {
+        let array = self
+            .bytes
+            .get(self.at..self.at + N)
+            .ok_or(CompactListError::Truncated)?
+            .try_into()
+            .map_err(|_| CompactListError::Truncated)?;
+        self.at += N;
+        Ok(array)
+    }
+
+    fn varint(&mut self) -> Result<u128, CompactListError> {
+        let mut value = 0u128;
+        for shift in 0..MAX_VARINT_LEN {
+            let byte = self.byte()?;
+            let bits = u128::from(byte & 0x7f);
+            if shift * 7 > 128 - 7 && bits >> (128 - shift * 7) != 0 {
+                return Err(CompactListError::Overflow);
+            }
+            value |= bits << (shift * 7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(CompactListError::Overflow)
+    }
+
+    /// Reads a count of addresses, which can not exceed the bytes left.
+    fn count(&mut self) -> Result<usize, CompactListError> {
+        let count = usize::try_from(self.varint()?).map_err(|_| CompactListError::Overflow)?;
+        if count > self.bytes.len() - self.at {
+            return Err(CompactListError::Truncated);
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::net::SocketAddrV6;
+
+    fn addr(value: &str) -> SocketAddr {
+        value.parse().unwrap()
+    }
+
+    /// Returns `addrs` as `decode(encode(addrs))` has to: sorted, without duplicates.
+    fn canonical(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+        let mut sorted: Vec<SocketAddr> = addrs.iter().map(|addr| stripped(*addr)).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+        sorted
+    }
+
+    fn round_trips(addrs: &[SocketAddr]) {
+        let bytes = encode(addrs);
+        assert_eq!(decode(&bytes), Ok(canonical(addrs)), "{:?}", addrs);
+        assert!(bytes.len() <= plain_len(&canonical(addrs)));
+    }
+
+    fn random_addr(rng: &mut StdRng) -> SocketAddr {
+        let ip = match rng.gen_bool(0.5) {
+            true => IpAddr::V4(Ipv4Addr::from(rng.gen::<u32>())),
+            false => IpAddr::V6(Ipv6Addr::from(rng.gen::<u128>())),
+        };
+        SocketAddr::new(ip, rng.gen())
+    }
+
+    /// A network handed out by an orchestrator: a few /24s, ports in sequence on each host.
+    fn orchestrated(hosts: u8, ports: u16) -> Vec<SocketAddr> {
+        let mut addrs = Vec::new();
+        for subnet in 0..4u8 {
+            for host in 1..=hosts {
+                for port in 0..ports {
+                    addrs.push(SocketAddr::from(([10, 0, subnet, host], 8000 + port)));
+                }
+            }
+        }
+        addrs
+    }
+
+    #[test]
+    fn round_trips_random_sets() {
+        let mut rng = StdRng::seed_from_u64(0x6c697374);
+        for len in [0, 1, 2, 3, 10, 100, 1000] {
+            for _ in 0..20 {
+                let addrs: Vec<SocketAddr> = (0..len).map(|_| random_addr(&mut rng)).collect();
+                round_trips(&addrs);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_sorted_and_dense_sets() {
+        let mut addrs = orchestrated(50, 5);
+        round_trips(&addrs);
+        addrs.reverse();
+        round_trips(&addrs);
+        // Duplicates and the IPv4-mapped form are distinct addresses to the encoding.
+        let mut duplicated = orchestrated(2, 2);
+        duplicated.extend(orchestrated(2, 2));
+        duplicated.push(addr("[::ffff:10.0.0.1]:8000"));
+        round_trips(&duplicated);
+        round_trips(&[addr("127.0.0.1:8000"); 50]);
+    }
+
+    #[test]
+    fn round_trips_port_and_address_edges() {
+        round_trips(&[
+            addr("0.0.0.0:0"),
+            addr("0.0.0.0:65535"),
+            addr("0.0.0.1:0"),
+            addr("255.255.255.255:0"),
+            addr("255.255.255.255:65535"),
+        ]);
+        round_trips(&[
+            addr("[::]:0"),
+            addr("[::]:65535"),
+            addr("[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:65535"),
+            addr("127.0.0.1:1"),
+            addr("[::1]:1"),
+        ]);
+        let ports: Vec<SocketAddr> = [0, 65535, 1, 65534, 32768]
+            .into_iter()
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect();
+        round_trips(&ports);
+    }
+
+    #[test]
+    fn drops_what_the_encoding_does_not_carry() {
+        let scoped = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 80, 7, 3));
+        assert_eq!(decode(&encode(&[scoped])), Ok(vec![addr("[fe80::1]:80")]));
+    }
+
+    #[test]
+    fn encodes_the_documented_example() {
+        let addrs = [
+            addr("127.0.0.3:8000"),
+            addr("127.0.0.1:8001"),
+            addr("127.0.0.1:8000"),
+        ];
+        let golden = [COMPACT_FORM, 3, 0x7f, 0, 0, 1, 0x1f, 0x40, 0, 2, 2, 1, 0];
+        assert_eq!(encode(&addrs), golden);
+        assert_eq!(plain_len(&canonical(&addrs)), 23);
+    }
+
+    #[test]
+    fn picks_the_smaller_form() {
+        assert_eq!(encode(&[]), [PLAIN_FORM, 0]);
+        assert_eq!(
+            encode(&[addr("10.0.0.1:80")]),
+            [PLAIN_FORM, 1, 4, 10, 0, 0, 1, 0, 80]
+        );
+        // Scattered addresses take as many bytes either way.
+        assert_eq!(
+            encode(&[addr("200.1.1.1:80"), addr("1.2.3.4:8080")]),
+            [PLAIN_FORM, 2, 4, 1, 2, 3, 4, 0x1f, 0x90, 4, 200, 1, 1, 1, 0, 80]
+        );
+        // One address of each family still saves their family bytes.
+        let mut golden = vec![COMPACT_FORM, 1, 1, 2, 3, 4, 0x1f, 0x90, 1];
+        golden.extend(Ipv6Addr::LOCALHOST.octets());
+        golden.extend([0x01, 0xbb]);
+        assert_eq!(encode(&[addr("[::1]:443"), addr("1.2.3.4:8080")]), golden);
+    }
+
+    #[test]
+    fn encodes_ipv6_differences() {
+        let addrs = [addr("[2001:db8::1]:9000"), addr("[2001:db8::101]:8999")];
+        let mut golden = vec![COMPACT_FORM, 0, 2];
+        golden.extend("2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        // 0x100 apart, as a varint, then port - 1 zigzagged.
+        golden.extend([0x23, 0x28, 0x80, 0x02, 0x01]);
+        assert_eq!(encode(&addrs), golden);
+    }
+
+    #[test]
+    fn refuses_truncated_encodings() {
+        for addrs in [orchestrated(2, 3), vec![addr("10.0.0.1:80")]] {
+            let bytes = encode(&addrs);
+            for len in 0..bytes.len() {
+                assert_eq!(
+                    decode(&bytes[..len]),
+                    Err(CompactListError::Truncated),
+                    "{:?}",
+                    &bytes[..len]
+                );
+            }
+            let mut longer = bytes.clone();
+            longer.extend([0, 0]);
+            assert_eq!(decode(&longer), Err(CompactListError::TrailingBytes(2)));
+        }
+    }
+
+    #[test]
+    fn refuses_overlong_varints() {
+        // A count whose continuation bits never stop.
+        let mut bytes = vec![COMPACT_FORM];
+        bytes.extend([0x80; MAX_VARINT_LEN]);
+        bytes.push(0);
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+        // The last byte of a varint with bits beyond 128.
+        let mut bytes = vec![COMPACT_FORM];
+        bytes.extend([0x80; MAX_VARINT_LEN - 1]);
+        bytes.push(0x7f);
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+        // A count above the bytes left.
+        assert_eq!(
+            decode(&[COMPACT_FORM, 0xff, 0x01]),
+            Err(CompactListError::Truncated)
+        );
+    }
+
+    #[test]
+    fn refuses_differences_past_the_family() {
+        // 255.255.255.255 plus one.
+        let bytes = [COMPACT_FORM, 2, 255, 255, 255, 255, 0, 80, 1, 0, 0];
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+        // Port 65535 plus one.
+        let bytes = [COMPACT_FORM, 2, 10, 0, 0, 1, 0xff, 0xff, 1, 2, 0];
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+        // A port difference beyond 32 bits.
+        let bytes = [
+            COMPACT_FORM,
+            2,
+            10,
+            0,
+            0,
+            1,
+            0,
+            80,
+            1,
+            0x80,
+            0x80,
+            0x80,
+            0x80,
+            0x10,
+            0,
+        ];
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+        // Port 65535 plus the largest difference, past `i32` itself.
+        let bytes = [
+            COMPACT_FORM,
+            2,
+            10,
+            0,
+            0,
+            1,
+            0xff,
+            0xff,
+            0,
+            0xfe,
+            0xff,
+            0xff,
+            0xff,
+            0x0f,
+            0,
+        ];
+        assert_eq!(decode(&bytes), Err(CompactListError::Overflow));
+    }
+
+    #[test]
+    fn refuses_unordered_and_unknown_encodings() {
+        // Same IP, same port: a duplicate.
+        let bytes = [COMPACT_FORM, 2, 10, 0, 0, 1, 0, 80, 0, 0, 0];
+        assert_eq!(
+            decode(&bytes),
+            Err(CompactListError::Unordered(addr("10.0.0.1:80")))
+        );
+        // Same IP, lower port.
+        let bytes = [COMPACT_FORM, 2, 10, 0, 0, 1, 0, 80, 0, 1, 0];
+        assert_eq!(
+            decode(&bytes),
+            Err(CompactListError::Unordered(addr("10.0.0.1:79")))
+        );
+        let bytes = [PLAIN_FORM, 2, 4, 10, 0, 0, 2, 0, 80, 4, 10, 0, 0, 1, 0, 80];
+        assert_eq!(
+            decode(&bytes),
+            Err(CompactListError::Unordered(addr("10.0.0.1:80")))
+        );
+        assert_eq!(decode(&[7]), Err(CompactListError::UnknownForm(7)));
+        assert_eq!(
+            decode(&[PLAIN_FORM, 1, 5, 0, 0, 0, 0, 0, 0]),
+            Err(CompactListError::UnknownFamily(5))
+        );
+    }
+
+    #[test]
+    fn never_panics_on_random_bytes() {
+        let mut rng = StdRng::seed_from_u64(0x62797465);
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..40);
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            if let Some(form) = bytes.first_mut() {
+                *form %= 2;
+            }
+            if let Ok(addrs) = decode(&bytes) {
+                assert_eq!(addrs, canonical(&addrs));
+            }
+        }
+    }
+
+    #[test]
+    fn packs_and_unpacks_the_buckets() {
+        let peers: Vec<SharedPeer> = [
+            ("10.0.0.9:8000", AgeBucket::Recent),
+            ("10.0.0.2:8001", AgeBucket::Fresh),
+            ("10.0.0.1:8000", AgeBucket::Stale),
+            ("10.0.0.2:8000", AgeBucket::Fresh),
+            ("[::1]:8000", AgeBucket::Stale),
+        ]
+        .into_iter()
+        .map(|(value, age)| SharedPeer {
+            addr: addr(value),
+            age,
+        })
+        .collect();
+        let packed = PackedList::pack(&peers);
+        assert_eq!(packed.lister, Some(addr("10.0.0.9:8000")));
+
+        let unpacked: Vec<(SocketAddr, AgeBucket)> = packed
+            .unpack()
+            .unwrap()
+            .into_iter()
+            .map(|peer| (peer.addr, peer.age))
+            .collect();
+        assert_eq!(
+            unpacked,
+            [
+                (addr("10.0.0.9:8000"), AgeBucket::Fresh),
+                (addr("10.0.0.2:8000"), AgeBucket::Fresh),
+                (addr("10.0.0.2:8001"), AgeBucket::Fresh),
+                (addr("10.0.0.1:8000"), AgeBucket::Stale),
+                (addr("[::1]:8000"), AgeBucket::Stale),
+            ]
+        );
+        assert_eq!(PackedList::pack(&[]).unpack(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn is_at_least_three_times_smaller_than_bincode() {
+        let peers: Vec<SharedPeer> = orchestrated(50, 10)
+            .into_iter()
+            .map(|addr| SharedPeer {
+                addr,
+                age: AgeBucket::Fresh,
+            })
+            .collect();
+        let plain = bincode::serialize(&peers).unwrap().len();
+        let packed = bincode::serialize(&PackedList::pack(&peers)).unwrap().len();
+        assert!(
+            packed * 3 <= plain,
+            "{} packed bytes against {} plain ones",
+            packed,
+            plain
+        );
+    }
+}
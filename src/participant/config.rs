@@ -0,0 +1,236 @@
+//! Participant Configuration.
+//!
+//! This module gathers every setting a participant needs into a single `NodeConfig`
+//! structure, so the entry point can assemble it once from the command line and hand it
+//! over to `Participant::new` or `Participant::check`. The `validate` submodule checks that
+//! the settings of a `NodeConfig` work together.
+
+use crate::console::Command;
+use crate::participant::alert::AlertRule;
+use crate::participant::identity::NodeId;
+use crate::participant::join::JoinCheck;
+use crate::participant::period::GossipPeriod;
+use crate::participant::report::ReportFormat;
+use crate::participant::retention::{RetainedKind, RetentionPolicy};
+use crate::participant::verify::VerificationMode;
+
+use ed25519_dalek::VerifyingKey;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::num::{NonZeroU16, ParseIntError};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod validate;
+
+/// Settings used to create and run a participant.
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// The interval between each random message broadcast, fixed or following the number of
+    /// peers.
+    pub period: GossipPeriod,
+    /// Whether broadcasts are numbered in rounds aligned with those of the peers.
+    pub rounds: bool,
+    /// Whether the broadcast texts keep the unstructured `random message <n>` form.
+    pub legacy_payload: bool,
+    /// The port on which the participant listens for incoming connections.
+    pub port: ListenPort,
+    /// The addresses listened on besides `127.0.0.1:<port>`, each an interface of its own.
+    pub listen: Vec<SocketAddr>,
+    /// The public addresses announced to the peers of each `listen` address, in the same
+    /// order; a `listen` address without one is announced as is.
+    pub advertise: Vec<SocketAddr>,
+    /// An optional address of another participant to initially connect to.
+    pub connect: Option<String>,
+    /// Whether the recent peers cache is read at startup and updated on every handshake.
+    pub use_peer_cache: bool,
+    /// An optional name of the network, keeping its recent peers cache apart from those of
+    /// other networks run on the same port.
+    pub network: Option<String>,
+    /// The number of unanswered messages after which a connection is re-dialed; `0` disables it.
+    pub half_open_threshold: u32,
+    /// How many times longer or shorter than ours the period a peer declares may be before its
+    /// skew is logged; `0` disables the warning.
+    pub period_skew_ratio: u32,
+    /// The estimated offset of the clock of a peer from ours beyond which it is logged.
+    pub max_clock_offset: Duration,
+    /// The outbound share of the established connections, in percent, below which an
+    /// inbound-only peer is dialed back and handed over; `None` leaves the directions as they
+    /// are.
+    pub balance_connections: Option<u32>,
+    /// Whether the participant exits instead of running isolated when no bootstrap succeeds.
+    pub require_bootstrap: bool,
+    /// An optional join check: the participant only joins, reports and exits.
+    pub join_check: Option<JoinCheck>,
+    /// The network the `connect` target must announce before its list is trusted.
+    pub expect_network_id: Option<String>,
+    /// The node id the `connect` target must present before its list is trusted.
+    pub expect_node_id: Option<NodeId>,
+    /// What a target presenting another network or node id does, see `verify`.
+    pub bootstrap_verification: VerificationMode,
+    /// Whether the first protocol anomaly a peer causes stops the participant.
+    pub strict_protocol: bool,
+    /// Whether every frame is checked before it is sent; debug builds always check.
+    pub validate_outbound: bool,
+    /// Whether the operator hints of `hints` are logged.
+    pub hints: bool,
+    /// Whether console commands are read from the standard input.
+    pub interactive: bool,
+    /// Whether the end of the standard input shuts the participant down like `quit`.
+    pub exit_on_eof: bool,
+    /// The console commands run once the participant starts.
+    pub exec: Vec<Command>,
+    /// An optional Unix domain socket serving the console commands to scripts and `ctl`.
+    pub admin_socket: Option<PathBuf>,
+    /// The number of observers served at the same time.
+    pub max_observers: usize,
+    /// The number of recently dialed addresses remembered to suppress duplicate dials.
+    pub dedup_entries: usize,
+    /// The number of peers whose participants list requests are rate limited at the same time.
+    pub ratelimit_entries: usize,
+    /// The participants whose inbound connections are refused and who are never dialed nor
+    /// introduced.
+    pub blocklist: Vec<SocketAddr>,
+    /// An optional file of allow and block rules, applied on top of `blocklist` and reloaded
+    /// whenever it changes.
+    pub filter_file: Option<PathBuf>,
+    /// Whether the addresses learned from peers are dialed even on ports below 1024.
+    pub allow_privileged_ports: bool,
+    /// Whether the addresses learned from peers are dialed even in reserved or special ranges.
+    pub allow_special_ranges: bool,
+    /// The only topics accepted from peers, or `None` to accept every topic.
+    pub topics: Option<Vec<String>>,
+    /// The topics published to every peer, whether or not its interest covers them.
+    pub topic_broadcast: Vec<String>,
+    /// The time an ordered subscription waits for a missing message.
+    pub reorder_window: Duration,
+    /// The number of messages an ordered subscription holds per publisher and topic while it
+    /// waits.
+    pub reorder_buffer: usize,
+    /// The number of maintenance ticks between two storage audits; `0` disables the audit.
+    pub audit_every: u64,
+    /// The maximal number of addresses in a shared participants list.
+    pub share_limit: usize,
+    /// The number of distinct peers that must list an address before it is dialed.
+    pub corroboration: usize,
+    /// The time a send of a broadcast round may take before its receiver skips a round.
+    pub send_budget: Duration,
+    /// The time messages to the same peer wait to be sent in a single frame; zero sends each
+    /// at once.
+    pub batch_window: Duration,
+    /// The overshoot of the period making a broadcast tick late, which is logged and counted.
+    pub late_tick_factor: u32,
+    /// The overshoot of the period making a broadcast tick frozen, after which the participant
+    /// resynchronizes with its peers at once.
+    pub frozen_tick_factor: u32,
+    /// The time a dialed connection may take to be established before it is given up.
+    pub connect_timeout: Duration,
+    /// The time a dialed address is not dialed again when another participants list shows it.
+    pub dial_memory: Duration,
+    /// The time between two rounds of dials to remembered peers that departed.
+    pub heal_probe_interval: Duration,
+    /// The shortest time between two resolutions of a `connect` host name while isolated.
+    pub resolve_interval: Duration,
+    /// The window over which the announcement and the first dials are spread, if any.
+    pub warmup: Option<Duration>,
+    /// The lag of the event loop from which deferrable housekeeping is skipped.
+    pub busy_lag: Duration,
+    /// The lag of the event loop from which inbound application payloads are dropped.
+    pub overload_lag: Duration,
+    /// Every how many broadcast ticks one is traced, 0 tracing none.
+    pub trace_ticks: u64,
+    /// The number of messages an inbox holds before dropping the oldest.
+    pub inbox_capacity: usize,
+    /// The number of scheduled publications that may be pending at once.
+    pub max_scheduled: usize,
+    /// The number of threads running the callbacks of the topic subscriptions.
+    pub callback_workers: usize,
+    /// The number of internal threads allowed to run at once.
+    pub max_threads: usize,
+    /// The number of file descriptors kept for other uses than connections.
+    pub fd_headroom: usize,
+    /// An optional membership snapshot whose participants are dialed at startup.
+    pub import_membership: Option<PathBuf>,
+    /// The oldest membership snapshot accepted by `import_membership`.
+    pub max_snapshot_age: Duration,
+    /// Whether `import_membership` rejects a snapshot its exporter did not sign.
+    pub require_signed_import: bool,
+    /// A key whose signature `import_membership` accepts, besides the own one.
+    pub trusted_snapshot_key: Option<VerifyingKey>,
+    /// An optional file keeping the peers and topic sequence numbers of the participant across
+    /// restarts.
+    pub state_file: Option<PathBuf>,
+    /// How often `state_file` is saved besides on shutdown, if at all.
+    pub state_save_interval: Option<Duration>,
+    /// The oldest state file, and the oldest peer verification in it, accepted at startup.
+    pub max_state_age: Duration,
+    /// The retention policy of each kind of peer history, in `RetainedKind::ALL` order.
+    pub retention: [RetentionPolicy; RetainedKind::COUNT],
+    /// An optional file keeping the persistent node id, instead of the default one.
+    pub identity_file: Option<PathBuf>,
+    /// Whether the node id is kept in an identity file across runs; without, a new id lasts
+    /// for this run only.
+    pub persist_identity: bool,
+    /// An optional label naming the default identity file instead of the listen port.
+    pub node_label: Option<String>,
+    /// An optional file receiving a JSON report of the run at shutdown.
+    pub report_file: Option<PathBuf>,
+    /// What the report file receives, see `ReportFormat`.
+    pub report_format: ReportFormat,
+    /// An optional file receiving every inbound network event and membership change, for an
+    /// offline replay or analysis.
+    pub record: Option<PathBuf>,
+    /// The time between two notes of the participant's own state in `record`.
+    pub record_snapshot_every: Duration,
+    /// The actions run when significant events happen.
+    pub alerts: Vec<AlertRule>,
+    /// The shortest time between two alerts of the same event.
+    pub alert_cooldown: Duration,
+    /// The command-line arguments the configuration was built from, kept in `record` so a
+    /// replay starts with the same settings; empty when it was built otherwise.
+    pub args: Vec<String>,
+    /// The seed of the random choices of the participant, drawn at the start when `None`.
+    pub seed: Option<u64>,
+}
+
+/// The port a participant listens on at `127.0.0.1`.
+///
+/// Parsed from the decimal text of the `--port` option, where `0` stands for a port the
+/// system picks; anything out of the range of a TCP port is rejected instead of wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenPort {
+    /// A free port, picked by the system when binding.
+    Ephemeral,
+    /// The given port.
+    Fixed(NonZeroU16),
+}
+
+impl ListenPort {
+    /// Returns the port to bind, `0` for an ephemeral one.
+    pub fn get(self) -> u16 {
+        match self {
+            ListenPort::Ephemeral => 0,
+            ListenPort::Fixed(port) => port.get(),
+        }
+    }
+
+    /// Returns the primary listen address, `127.0.0.1:<port>`.
+    pub fn listen_addr(self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, self.get()))
+    }
+}
+
+impl From<u16> for ListenPort {
+    fn from(port: u16) -> Self {
+        NonZeroU16::new(port).map_or(ListenPort::Ephemeral, ListenPort::Fixed)
+    }
+}
+
+impl FromStr for ListenPort {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u16>().map(ListenPort::from)
+    }
+}
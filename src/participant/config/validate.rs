@@ -0,0 +1,835 @@
+//! Cross-Field Configuration Checks.
+//!
+//! Each option is checked on its own while the command line is parsed, but some combinations
+//! of valid options can not work together: a send budget longer than the period, a recording
+//! written over the filter file. This module checks the assembled `NodeConfig` against
+//! `RULES`, a list of functions that each look at one interaction and explain the problem in
+//! terms of the options involved.
+//!
+//! A rule reports an error for a combination that can not work, which stops the participant,
+//! or a warning for one that works badly, which is printed and only stops the participant with
+//! `--strict-config`. Adding a check is adding a function to `RULES`.
+
+use crate::participant::alert::AlertAction;
+use crate::participant::config::NodeConfig;
+use crate::participant::descriptors;
+use crate::participant::identity::is_valid_label;
+use crate::participant::observer::UPDATE_INTERVAL;
+use crate::participant::period::GossipPeriod;
+use crate::participant::report::{csv_path, ReportFormat};
+use crate::participant::request::REQUEST_WORKERS;
+use crate::participant::retention::RetainedKind;
+
+use std::fmt;
+
+/// How serious a configuration issue is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The participant works, though probably not as intended.
+    Warning,
+    /// The participant can not work as configured.
+    Error,
+}
+
+/// A problem found in a configuration, explained with the options involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn warning(message: String) -> Option<Self> {
+        Some(Self {
+            severity: Severity::Warning,
+            message,
+        })
+    }
+
+    fn error(message: String) -> Option<Self> {
+        Some(Self {
+            severity: Severity::Error,
+            message,
+        })
+    }
+
+    /// Determines whether the issue stops the participant, warnings counting with `strict`.
+    ///
+    /// # Parameters
+    ///
+    /// * `strict` - Whether warnings are treated as errors.
+    pub fn is_fatal(&self, strict: bool) -> bool {
+        self.severity == Severity::Error || strict
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// A check of one interaction between options.
+pub type Rule = fn(&NodeConfig) -> Option<ConfigIssue>;
+
+/// Every check run by `validate`, in the order of their reports.
+pub const RULES: &[Rule] = &[
+    advertise_within_listen,
+    min_period_within_max,
+    adaptive_base_within_bounds,
+    send_budget_within_period,
+    batch_window_within_period,
+    frozen_tick_above_late_tick,
+    rounds_need_fixed_period,
+    connect_timeout_above_tick,
+    busy_lag_below_overload_lag,
+    balance_has_a_share,
+    departed_outlive_heal_probes,
+    require_bootstrap_has_source,
+    join_check_has_source,
+    join_check_outlasts_warmup,
+    expectations_have_target,
+    distinct_state_files,
+    report_format_has_a_file,
+    report_csv_beside_json,
+    node_label_names_a_file,
+    network_names_a_file,
+    node_label_needs_default_identity,
+    max_threads_fit_startup,
+    fd_headroom_below_limit,
+];
+
+/// Checks `config` against every rule of `RULES`.
+///
+/// # Returns
+///
+/// The issues found, in the order of `RULES`.
+pub fn validate(config: &NodeConfig) -> Vec<ConfigIssue> {
+    RULES.iter().filter_map(|rule| rule(config)).collect()
+}
+
+/// Every advertise address belongs to the listen address at the same position.
+fn advertise_within_listen(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.advertise.len() <= config.listen.len() {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "advertise ({} addresses) can not list more addresses than listen ({} addresses)",
+        config.advertise.len(),
+        config.listen.len()
+    ))
+}
+
+/// An adaptive period needs bounds in the right order.
+fn min_period_within_max(config: &NodeConfig) -> Option<ConfigIssue> {
+    let GossipPeriod::Adaptive { min, max, .. } = config.period else {
+        return None;
+    };
+    if min <= max {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "min-period ({:?}) can not be longer than max-period ({:?})",
+        min, max
+    ))
+}
+
+/// An adaptive base outside of the bounds is never used as is.
+fn adaptive_base_within_bounds(config: &NodeConfig) -> Option<ConfigIssue> {
+    let GossipPeriod::Adaptive { base, min, max } = config.period else {
+        return None;
+    };
+    if base < min {
+        ConfigIssue::warning(format!(
+            "period base ({:?}) is shorter than min-period ({:?}), so the period starts at min-period",
+            base, min
+        ))
+    } else if base > max {
+        ConfigIssue::warning(format!(
+            "period base ({:?}) is longer than max-period ({:?}), so the period never adapts",
+            base, max
+        ))
+    } else {
+        None
+    }
+}
+
+/// A broadcast round must fit in a period, sends included.
+fn send_budget_within_period(config: &NodeConfig) -> Option<ConfigIssue> {
+    let period = config.period.shortest();
+    if config.send_budget >= period {
+        ConfigIssue::error(format!(
+            "per-peer-send-budget-ms ({:?}) must be shorter than period ({:?}), or a single slow send fills a whole round",
+            config.send_budget, period
+        ))
+    } else if config.send_budget * 2 > period {
+        ConfigIssue::warning(format!(
+            "per-peer-send-budget-ms ({:?}) is more than half of period ({:?}), so slow peers delay the next round",
+            config.send_budget, period
+        ))
+    } else {
+        None
+    }
+}
+
+/// Batching should delay a message by a fraction of a round at most.
+fn batch_window_within_period(config: &NodeConfig) -> Option<ConfigIssue> {
+    let period = config.period.shortest();
+    if config.batch_window * 2 <= period {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "batch-window-ms ({:?}) is more than half of period ({:?}), so batched messages lag a whole round behind",
+        config.batch_window, period
+    ))
+}
+
+/// A freeze is a tick later than a late one.
+fn frozen_tick_above_late_tick(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.frozen_tick_factor > config.late_tick_factor {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "frozen-tick-factor ({}) is not larger than late-tick-factor ({}), so every late tick resynchronizes the peers",
+        config.frozen_tick_factor, config.late_tick_factor
+    ))
+}
+
+/// Rounds only line up between participants sharing the same period.
+fn rounds_need_fixed_period(config: &NodeConfig) -> Option<ConfigIssue> {
+    if !config.rounds || matches!(config.period, GossipPeriod::Fixed(_)) {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "rounds with an auto period ({}) drift apart, since peers with different peer counts use different periods",
+        config.period
+    ))
+}
+
+/// Connect timeouts are only enforced on maintenance ticks.
+fn connect_timeout_above_tick(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.connect_timeout >= UPDATE_INTERVAL {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "connect-timeout ({:?}) is shorter than the maintenance tick ({:?}) enforcing it, so dials may take up to {:?}",
+        config.connect_timeout, UPDATE_INTERVAL, UPDATE_INTERVAL
+    ))
+}
+
+/// Overload is the harder of the two load states.
+fn busy_lag_below_overload_lag(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.busy_lag < config.overload_lag {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "busy-lag ({:?}) must be shorter than overload-lag ({:?}), or the participant drops payloads before it defers any housekeeping",
+        config.busy_lag, config.overload_lag
+    ))
+}
+
+/// No outbound share is ever below 0 percent.
+fn balance_has_a_share(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.balance_connections != Some(0) {
+        return None;
+    }
+    ConfigIssue::warning(
+        "balance-connections with a min-outbound-share of 0 never dials a peer back".to_owned(),
+    )
+}
+
+/// Partition healing probes the remembered departed peers.
+fn departed_outlive_heal_probes(config: &NodeConfig) -> Option<ConfigIssue> {
+    let departed = config.retention[RetainedKind::Departed as usize];
+    if departed.max_age >= config.heal_probe_interval {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "retain-departed forgets departed peers after {:?}, before a heal-probe-interval ({:?}) comes round to probe them",
+        departed.max_age, config.heal_probe_interval
+    ))
+}
+
+/// A required bootstrap needs a peer to bootstrap from.
+fn require_bootstrap_has_source(config: &NodeConfig) -> Option<ConfigIssue> {
+    if !config.require_bootstrap
+        || config.connect.is_some()
+        || config.import_membership.is_some()
+        || config.use_peer_cache
+    {
+        return None;
+    }
+    ConfigIssue::error(
+        "require-bootstrap needs connect or import-membership when no-peer-cache is set".to_owned(),
+    )
+}
+
+/// A join check needs a network to join.
+fn join_check_has_source(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.join_check.is_none()
+        || config.connect.is_some()
+        || config.import_membership.is_some()
+        || config.use_peer_cache
+    {
+        return None;
+    }
+    ConfigIssue::error(
+        "join-check needs connect or import-membership when no-peer-cache is set".to_owned(),
+    )
+}
+
+/// A join check only counts from the start, warm-up included.
+fn join_check_outlasts_warmup(config: &NodeConfig) -> Option<ConfigIssue> {
+    let (check, warmup) = (config.join_check?, config.warmup?);
+    if check.timeout > warmup {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "join-check timeout ({:?}) is not longer than warmup ({:?}), so the check may time out before the bootstrap dial",
+        check.timeout, warmup
+    ))
+}
+
+/// Only the connect target is verified.
+fn expectations_have_target(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.connect.is_some() {
+        return None;
+    }
+    let option = if config.expect_network_id.is_some() {
+        "expect-network-id"
+    } else if config.expect_node_id.is_some() {
+        "expect-node-id"
+    } else {
+        return None;
+    };
+    ConfigIssue::warning(format!("{} has no effect without connect", option))
+}
+
+/// The recording is created anew, so it must not replace a file read at startup.
+fn distinct_state_files(config: &NodeConfig) -> Option<ConfigIssue> {
+    let record = config.record.as_ref()?;
+    let read = [
+        ("filter-file", &config.filter_file),
+        ("import-membership", &config.import_membership),
+        ("state-file", &config.state_file),
+    ];
+    let (option, _) = read
+        .iter()
+        .find(|(_, path)| path.as_ref() == Some(record))?;
+    ConfigIssue::error(format!(
+        "record ({}) can not be the same file as {}, which it would overwrite",
+        record.display(),
+        option
+    ))
+}
+
+/// Only the report file follows the report format.
+fn report_format_has_a_file(config: &NodeConfig) -> Option<ConfigIssue> {
+    if config.report_format == ReportFormat::Json || config.report_file.is_some() {
+        return None;
+    }
+    ConfigIssue::warning(format!(
+        "report-format ({}) has no effect without report-file",
+        config.report_format
+    ))
+}
+
+/// Both reports need a file each.
+fn report_csv_beside_json(config: &NodeConfig) -> Option<ConfigIssue> {
+    let path = config.report_file.as_ref()?;
+    if config.report_format != ReportFormat::Both || csv_path(path) != *path {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "report-file ({}) already has the csv extension, so with report-format both the CSV table would overwrite the JSON report",
+        path.display()
+    ))
+}
+
+/// The label becomes part of a file name in the data directory.
+fn node_label_names_a_file(config: &NodeConfig) -> Option<ConfigIssue> {
+    let label = config.node_label.as_ref()?;
+    if is_valid_label(label) {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "node-label (\"{}\") may only hold letters, digits, '-', '_' and '.', and not start with '.'",
+        label
+    ))
+}
+
+/// The network name becomes part of a file name in the cache directory.
+fn network_names_a_file(config: &NodeConfig) -> Option<ConfigIssue> {
+    let network = config.network.as_ref()?;
+    if is_valid_label(network) {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "network (\"{}\") may only hold letters, digits, '-', '_' and '.', and not start with '.'",
+        network
+    ))
+}
+
+/// The label only names the default identity file.
+fn node_label_needs_default_identity(config: &NodeConfig) -> Option<ConfigIssue> {
+    let (label, file) = (config.node_label.as_ref()?, config.identity_file.as_ref()?);
+    ConfigIssue::warning(format!(
+        "node-label (\"{}\") is ignored, since identity-file ({}) names the identity file",
+        label,
+        file.display()
+    ))
+}
+
+/// The threads started with the participant have to fit in the cap, along with one admin
+/// connection and the output readers of an exec alert.
+fn max_threads_fit_startup(config: &NodeConfig) -> Option<ConfigIssue> {
+    let mut needed = 1 + REQUEST_WORKERS + config.callback_workers;
+    if config.interactive || !config.exec.is_empty() {
+        needed += 1;
+    }
+    if config.admin_socket.is_some() {
+        needed += 2;
+    }
+    if !config.alerts.is_empty() {
+        needed += 1;
+    }
+    if config
+        .alerts
+        .iter()
+        .any(|rule| matches!(rule.action, AlertAction::Exec(_)))
+    {
+        needed += 2;
+    }
+    if config.record.is_some() {
+        needed += 1;
+    }
+    if config.max_threads >= needed {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "max-threads ({}) is below the {} internal threads this configuration runs",
+        config.max_threads, needed
+    ))
+}
+
+/// The headroom leaves file descriptors for the sockets, the listener at least.
+fn fd_headroom_below_limit(config: &NodeConfig) -> Option<ConfigIssue> {
+    fd_headroom_within(config.fd_headroom, descriptors::soft_limit())
+}
+
+/// Checks `headroom` against the soft `limit` of file descriptors of the process.
+fn fd_headroom_within(headroom: usize, limit: usize) -> Option<ConfigIssue> {
+    if headroom < limit {
+        return None;
+    }
+    ConfigIssue::error(format!(
+        "fd-headroom ({}) keeps every one of the {} file descriptors of the soft limit, leaving none for the sockets",
+        headroom, limit
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::alert::AlertRule;
+    use crate::participant::identity::NodeId;
+    use crate::participant::join::JoinCheck;
+    use crate::participant::test_support::addr;
+
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// A configuration every rule accepts, to break one option at a time.
+    fn config() -> NodeConfig {
+        NodeConfig::from_args(&["--period=1", "--port=8080"]).unwrap()
+    }
+
+    /// Returns the severity of the issue `rule` finds in `config`, if any.
+    fn severity(rule: Rule, config: &NodeConfig) -> Option<Severity> {
+        rule(config).map(|issue| issue.severity)
+    }
+
+    fn adaptive(base: u64, min: u64, max: u64) -> GossipPeriod {
+        GossipPeriod::Adaptive {
+            base: Duration::from_millis(base),
+            min: Duration::from_millis(min),
+            max: Duration::from_millis(max),
+        }
+    }
+
+    #[test]
+    fn the_default_configuration_passes_every_rule() {
+        assert_eq!(validate(&config()), Vec::new());
+    }
+
+    #[test]
+    fn each_advertise_address_needs_a_listen_address() {
+        let mut config = config();
+        config.listen = vec![addr(9000)];
+        config.advertise = vec![addr(9100)];
+        assert_eq!(severity(advertise_within_listen, &config), None);
+        config.advertise.push(addr(9101));
+        assert_eq!(
+            severity(advertise_within_listen, &config),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn adaptive_bounds_are_ordered_and_hold_the_base() {
+        let mut config = config();
+        assert_eq!(severity(min_period_within_max, &config), None);
+        assert_eq!(severity(adaptive_base_within_bounds, &config), None);
+
+        for (period, order, base) in [
+            (adaptive(500, 100, 1000), None, None),
+            (adaptive(100, 100, 100), None, None),
+            (adaptive(50, 100, 1000), None, Some(Severity::Warning)),
+            (adaptive(2000, 100, 1000), None, Some(Severity::Warning)),
+            (
+                adaptive(500, 1000, 100),
+                Some(Severity::Error),
+                Some(Severity::Warning),
+            ),
+        ] {
+            config.period = period;
+            assert_eq!(
+                severity(min_period_within_max, &config),
+                order,
+                "{}",
+                period
+            );
+            assert_eq!(
+                severity(adaptive_base_within_bounds, &config),
+                base,
+                "{}",
+                period
+            );
+        }
+    }
+
+    #[test]
+    fn the_send_budget_fits_in_the_shortest_period() {
+        let mut config = config();
+        for (budget, expected) in [
+            (500, None),
+            (501, Some(Severity::Warning)),
+            (999, Some(Severity::Warning)),
+            (1000, Some(Severity::Error)),
+        ] {
+            config.send_budget = Duration::from_millis(budget);
+            assert_eq!(
+                severity(send_budget_within_period, &config),
+                expected,
+                "{}ms",
+                budget
+            );
+        }
+        let message = send_budget_within_period(&config).unwrap().message;
+        assert!(
+            message.contains("per-peer-send-budget-ms (1s)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("period (1s)"), "{}", message);
+
+        // An adaptive period is checked at its base, the period with the fewest peers.
+        config.period = adaptive(200, 100, 1000);
+        config.send_budget = Duration::from_millis(150);
+        assert_eq!(
+            severity(send_budget_within_period, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn the_batch_window_stays_within_half_a_period() {
+        let mut config = config();
+        config.batch_window = Duration::from_millis(500);
+        assert_eq!(severity(batch_window_within_period, &config), None);
+        config.batch_window = Duration::from_millis(501);
+        assert_eq!(
+            severity(batch_window_within_period, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn a_frozen_tick_is_later_than_a_late_one() {
+        let mut config = config();
+        config.late_tick_factor = 3;
+        config.frozen_tick_factor = 4;
+        assert_eq!(severity(frozen_tick_above_late_tick, &config), None);
+        config.frozen_tick_factor = 3;
+        assert_eq!(
+            severity(frozen_tick_above_late_tick, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn rounds_warn_with_an_auto_period_only() {
+        let mut config = config();
+        config.rounds = true;
+        assert_eq!(severity(rounds_need_fixed_period, &config), None);
+        config.period = adaptive(500, 100, 1000);
+        assert_eq!(
+            severity(rounds_need_fixed_period, &config),
+            Some(Severity::Warning)
+        );
+        config.rounds = false;
+        assert_eq!(severity(rounds_need_fixed_period, &config), None);
+    }
+
+    #[test]
+    fn the_connect_timeout_spans_a_maintenance_tick() {
+        let mut config = config();
+        config.connect_timeout = UPDATE_INTERVAL;
+        assert_eq!(severity(connect_timeout_above_tick, &config), None);
+        config.connect_timeout = UPDATE_INTERVAL - Duration::from_millis(1);
+        assert_eq!(
+            severity(connect_timeout_above_tick, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn the_busy_lag_is_shorter_than_the_overload_lag() {
+        let mut config = config();
+        config.busy_lag = Duration::from_millis(99);
+        config.overload_lag = Duration::from_millis(100);
+        assert_eq!(severity(busy_lag_below_overload_lag, &config), None);
+        config.busy_lag = config.overload_lag;
+        assert_eq!(
+            severity(busy_lag_below_overload_lag, &config),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn balancing_needs_an_outbound_share() {
+        let mut config = config();
+        for (share, expected) in [
+            (None, None),
+            (Some(1), None),
+            (Some(0), Some(Severity::Warning)),
+        ] {
+            config.balance_connections = share;
+            assert_eq!(
+                severity(balance_has_a_share, &config),
+                expected,
+                "{:?}",
+                share
+            );
+        }
+    }
+
+    #[test]
+    fn departed_peers_are_kept_until_a_heal_probe() {
+        let mut config = config();
+        config.heal_probe_interval = Duration::from_secs(60);
+        config.retention[RetainedKind::Departed as usize].max_age = Duration::from_secs(60);
+        assert_eq!(severity(departed_outlive_heal_probes, &config), None);
+        config.retention[RetainedKind::Departed as usize].max_age = Duration::from_secs(59);
+        assert_eq!(
+            severity(departed_outlive_heal_probes, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn a_required_bootstrap_and_a_join_check_need_a_source() {
+        let mut config = config();
+        config.require_bootstrap = true;
+        config.join_check = Some(JoinCheck {
+            peers: 1,
+            timeout: Duration::from_secs(10),
+        });
+        config.use_peer_cache = true;
+        for rule in [require_bootstrap_has_source, join_check_has_source] {
+            assert_eq!(severity(rule, &config), None);
+        }
+
+        config.use_peer_cache = false;
+        for rule in [require_bootstrap_has_source, join_check_has_source] {
+            assert_eq!(severity(rule, &config), Some(Severity::Error));
+        }
+
+        config.import_membership = Some(PathBuf::from("members.json"));
+        for rule in [require_bootstrap_has_source, join_check_has_source] {
+            assert_eq!(severity(rule, &config), None);
+        }
+        config.import_membership = None;
+        config.connect = Some("127.0.0.1:8090".to_owned());
+        for rule in [require_bootstrap_has_source, join_check_has_source] {
+            assert_eq!(severity(rule, &config), None);
+        }
+    }
+
+    #[test]
+    fn a_join_check_outlasts_the_warmup() {
+        let mut config = config();
+        config.join_check = Some(JoinCheck {
+            peers: 1,
+            timeout: Duration::from_secs(10),
+        });
+        assert_eq!(severity(join_check_outlasts_warmup, &config), None);
+        config.warmup = Some(Duration::from_secs(9));
+        assert_eq!(severity(join_check_outlasts_warmup, &config), None);
+        config.warmup = Some(Duration::from_secs(10));
+        assert_eq!(
+            severity(join_check_outlasts_warmup, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn an_expected_identity_needs_a_connect_target() {
+        let mut config = config();
+        config.expect_node_id = Some(NodeId(7));
+        assert_eq!(
+            severity(expectations_have_target, &config),
+            Some(Severity::Warning)
+        );
+        config.expect_node_id = None;
+        config.expect_network_id = Some("prod".to_owned());
+        assert_eq!(
+            expectations_have_target(&config).map(|issue| issue.message),
+            Some("expect-network-id has no effect without connect".to_owned())
+        );
+        config.connect = Some("127.0.0.1:8090".to_owned());
+        assert_eq!(severity(expectations_have_target, &config), None);
+    }
+
+    #[test]
+    fn the_recording_overwrites_no_file_read_at_startup() {
+        let mut config = config();
+        config.record = Some(PathBuf::from("run.rec"));
+        config.filter_file = Some(PathBuf::from("filter.txt"));
+        assert_eq!(severity(distinct_state_files, &config), None);
+
+        config.state_file = Some(PathBuf::from("run.rec"));
+        let issue = distinct_state_files(&config).unwrap();
+        assert_eq!(issue.severity, Severity::Error);
+        assert!(issue.message.contains("state-file"), "{}", issue);
+        assert!(issue.message.contains("record (run.rec)"), "{}", issue);
+    }
+
+    #[test]
+    fn the_report_format_needs_distinct_files() {
+        let mut config = config();
+        config.report_format = ReportFormat::Csv;
+        assert_eq!(
+            severity(report_format_has_a_file, &config),
+            Some(Severity::Warning)
+        );
+        config.report_file = Some(PathBuf::from("report.json"));
+        assert_eq!(severity(report_format_has_a_file, &config), None);
+
+        config.report_format = ReportFormat::Both;
+        assert_eq!(severity(report_csv_beside_json, &config), None);
+        config.report_file = Some(PathBuf::from("report.csv"));
+        assert_eq!(
+            severity(report_csv_beside_json, &config),
+            Some(Severity::Error)
+        );
+        config.report_format = ReportFormat::Csv;
+        assert_eq!(severity(report_csv_beside_json, &config), None);
+    }
+
+    #[test]
+    fn labels_and_network_names_must_name_a_file() {
+        let mut config = config();
+        for (label, expected) in [
+            ("node-1.a_b", None),
+            ("", Some(Severity::Error)),
+            (".hidden", Some(Severity::Error)),
+            ("a/b", Some(Severity::Error)),
+        ] {
+            config.node_label = Some(label.to_owned());
+            config.network = Some(label.to_owned());
+            assert_eq!(
+                severity(node_label_names_a_file, &config),
+                expected,
+                "{:?}",
+                label
+            );
+            assert_eq!(
+                severity(network_names_a_file, &config),
+                expected,
+                "{:?}",
+                label
+            );
+        }
+    }
+
+    #[test]
+    fn a_label_is_ignored_beside_an_identity_file() {
+        let mut config = config();
+        config.node_label = Some("node-1".to_owned());
+        assert_eq!(severity(node_label_needs_default_identity, &config), None);
+        config.identity_file = Some(PathBuf::from("node.id"));
+        assert_eq!(
+            severity(node_label_needs_default_identity, &config),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn the_thread_cap_fits_the_threads_of_the_configuration() {
+        let mut config = config();
+        config.interactive = false;
+        config.exec.clear();
+        config.callback_workers = 2;
+        let needed = 1 + REQUEST_WORKERS + 2;
+        config.max_threads = needed;
+        assert_eq!(severity(max_threads_fit_startup, &config), None);
+        config.max_threads = needed - 1;
+        assert_eq!(
+            severity(max_threads_fit_startup, &config),
+            Some(Severity::Error)
+        );
+
+        config.max_threads = needed;
+        config.admin_socket = Some(PathBuf::from("gossip.sock"));
+        assert_eq!(
+            severity(max_threads_fit_startup, &config),
+            Some(Severity::Error)
+        );
+        config.max_threads = needed + 2;
+        assert_eq!(severity(max_threads_fit_startup, &config), None);
+        config.alerts = vec![AlertRule::parse("isolated:exec:true").unwrap()];
+        config.max_threads = needed + 2 + 3;
+        assert_eq!(severity(max_threads_fit_startup, &config), None);
+        config.max_threads -= 1;
+        assert_eq!(
+            severity(max_threads_fit_startup, &config),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn warnings_are_only_fatal_when_strict() {
+        let warning = ConfigIssue::warning("w".to_owned()).unwrap();
+        let error = ConfigIssue::error("e".to_owned()).unwrap();
+        assert!(!warning.is_fatal(false));
+        assert!(warning.is_fatal(true));
+        assert!(error.is_fatal(false));
+        assert_eq!(warning.to_string(), "warning: w");
+        assert_eq!(error.to_string(), "error: e");
+    }
+
+    #[test]
+    fn the_headroom_must_leave_a_descriptor() {
+        assert_eq!(fd_headroom_within(64, 1024), None);
+        assert_eq!(fd_headroom_within(1023, 1024), None);
+        for headroom in [1024, 100_000] {
+            let issue = fd_headroom_within(headroom, 1024).expect("the headroom is refused");
+            assert_eq!(issue.severity, Severity::Error);
+            assert!(issue.message.starts_with("fd-headroom"), "{}", issue);
+        }
+    }
+}
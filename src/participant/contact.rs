@@ -0,0 +1,336 @@
+//! First Contact.
+//!
+//! bincode frames carry no type information, so a frame written by a build with another
+//! `Message` layout may well decode into a valid but meaningless message of this build. Left
+//! unchecked, such a peer half-works: its frames are handled as texts or lists of garbage
+//! addresses, and those addresses end up in the storage and the participants lists.
+//!
+//! A new connection is therefore unverified until its first frame passes
+//! `validate_first_contact`: an announcement of a plausible public address, an observer
+//! subscription, or the participants list answering this participant's request. Anything else
+//! means the peer does not speak this protocol, and the connection is closed before its
+//! content is acted on. `PendingContacts` keeps the unverified connections, which are closed
+//! when they stay silent for `FIRST_CONTACT_TIMEOUT`.
+
+use crate::participant::message::builder::{
+    validate_address, validate_list, AddressError, BuildError, MAX_LIST_LEN,
+};
+use crate::participant::message::{Message, MessageKind};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// How long a new connection may stay silent before it is closed.
+pub const FIRST_CONTACT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Enumerates the reasons a first frame shows that a peer speaks another protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MismatchReason {
+    /// The frame does not decode into a message of this build.
+    Undecodable,
+    /// A message of this kind never opens a connection.
+    UnexpectedKind(MessageKind),
+    /// An announced or listed address has port 0.
+    ZeroPort(SocketAddr),
+    /// An announced or listed address is a multicast or broadcast address.
+    NotUnicast(SocketAddr),
+    /// A participants list without any entry, not even its sender.
+    EmptyList,
+    /// A participants list longer than `MAX_LIST_LEN`.
+    OversizedList(usize),
+    /// A batch opening a connection, whose first message is implausible.
+    Batch(Box<MismatchReason>),
+}
+
+impl fmt::Display for MismatchReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MismatchReason::Undecodable => write!(f, "its first frame does not decode"),
+            MismatchReason::UnexpectedKind(kind) => {
+                write!(f, "it opened with a {} message", kind.name())
+            }
+            MismatchReason::ZeroPort(addr) => write!(f, "it sent the address {} of port 0", addr),
+            MismatchReason::NotUnicast(addr) => {
+                write!(f, "it sent the address {}, which is not unicast", addr)
+            }
+            MismatchReason::EmptyList => write!(f, "it sent an empty participants list"),
+            MismatchReason::OversizedList(len) => write!(
+                f,
+                "it sent a participants list of {} addresses, over {}",
+                len, MAX_LIST_LEN
+            ),
+            MismatchReason::Batch(reason) => write!(f, "in a batch, {}", reason),
+        }
+    }
+}
+
+/// Checks whether `message` can be the first frame of a peer speaking this protocol.
+///
+/// A dialing peer opens with `PublicAddress` and an observer with `ObserverHello`; a dialed
+/// peer answers the `PushParticipantsList` of this participant with `SharedPeers` or, before
+/// protocol version 2, `PullParticipantsList`, possibly in a `Batch`. Every address must be a
+/// unicast address with a port, and a list must hold at least its sender and at most
+/// `MAX_LIST_LEN` addresses.
+///
+/// # Parameters
+///
+/// * `message` - The first message decoded from the connection.
+///
+/// # Returns
+///
+/// `Ok(())` for a plausible first frame, or the `MismatchReason` of an implausible one.
+pub fn validate_first_contact(message: &Message) -> Result<(), MismatchReason> {
+    match message {
+        Message::PublicAddress(addr) => plausible_addr(*addr),
+        Message::ObserverHello => Ok(()),
+        Message::PullParticipantsList(addrs) => plausible_list(addrs.iter().copied()),
+        Message::SharedPeers(peers) => plausible_list(peers.iter().map(|peer| peer.addr)),
+        Message::Batch(messages) => match messages.first() {
+            Some(Message::Batch(_)) | None => {
+                Err(MismatchReason::UnexpectedKind(MessageKind::Batch))
+            }
+            Some(first) => {
+                validate_first_contact(first).map_err(|reason| MismatchReason::Batch(reason.into()))
+            }
+        },
+        _ => Err(MismatchReason::UnexpectedKind(message.kind())),
+    }
+}
+
+/// Checks that every address of a participants list is plausible, and its length.
+fn plausible_list(addrs: impl ExactSizeIterator<Item = SocketAddr>) -> Result<(), MismatchReason> {
+    if addrs.len() == 0 {
+        return Err(MismatchReason::EmptyList);
+    }
+    validate_list(addrs).map_err(mismatch)
+}
+
+/// Checks that a peer could be dialed at `addr`, with the validator of the send path.
+fn plausible_addr(addr: SocketAddr) -> Result<(), MismatchReason> {
+    validate_address(addr).map_err(mismatch)
+}
+
+/// Translates the refusal of a validator into the reason of a mismatch.
+fn mismatch(err: BuildError) -> MismatchReason {
+    match err {
+        BuildError::Address(addr, AddressError::ZeroPort) => MismatchReason::ZeroPort(addr),
+        BuildError::Address(addr, AddressError::NotUnicast) => MismatchReason::NotUnicast(addr),
+        BuildError::ListTooLong(len) => MismatchReason::OversizedList(len),
+        _ => MismatchReason::Undecodable,
+    }
+}
+
+/// The connections whose first frame is still awaited, with the time they were opened.
+pub struct PendingContacts<K> {
+    opened: HashMap<K, Instant>,
+}
+
+impl<K: Hash + Eq + Clone> Default for PendingContacts<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone> PendingContacts<K> {
+    /// Constructs an empty `PendingContacts`.
+    pub fn new() -> Self {
+        Self {
+            opened: HashMap::new(),
+        }
+    }
+
+    /// Awaits the first frame of a connection opened at `now`.
+    pub fn expect(&mut self, key: K, now: Instant) {
+        self.opened.insert(key, now);
+    }
+
+    /// Determines whether the first frame of a connection is still awaited.
+    pub fn is_pending(&self, key: &K) -> bool {
+        self.opened.contains_key(key)
+    }
+
+    /// Stops awaiting the first frame of a connection, once received or once it is closed.
+    pub fn forget(&mut self, key: &K) {
+        self.opened.remove(key);
+    }
+
+    /// Removes the connections opened longer than `timeout` ago.
+    ///
+    /// # Returns
+    ///
+    /// The removed connections, to be closed.
+    pub fn expired(&mut self, now: Instant, timeout: Duration) -> Vec<K> {
+        let expired: Vec<K> = self
+            .opened
+            .iter()
+            .filter(|(_, opened)| now.saturating_duration_since(**opened) > timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.opened.remove(key);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::one_of_each;
+    use crate::participant::message::{AgeBucket, SharedPeer};
+    use crate::participant::test_support::addr;
+
+    fn shared(addrs: &[SocketAddr]) -> Message {
+        Message::SharedPeers(
+            addrs
+                .iter()
+                .map(|addr| SharedPeer {
+                    addr: *addr,
+                    age: AgeBucket::Fresh,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn accepts_the_frames_opening_a_connection() {
+        for message in [
+            Message::PublicAddress(addr(8080)),
+            Message::PublicAddress("[::1]:8080".parse().unwrap()),
+            Message::ObserverHello,
+            Message::PullParticipantsList(vec![addr(8080)]),
+            shared(&[addr(8080), addr(8090)]),
+            Message::Batch(vec![shared(&[addr(8080)]), Message::Text("x".to_owned())]),
+        ] {
+            assert_eq!(validate_first_contact(&message), Ok(()), "{:?}", message);
+        }
+        let full: Vec<SocketAddr> = (1..=MAX_LIST_LEN as u16).map(addr).collect();
+        assert_eq!(
+            validate_first_contact(&Message::PullParticipantsList(full)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn every_other_kind_is_a_mismatch() {
+        for message in one_of_each() {
+            let kind = message.kind();
+            if matches!(
+                kind,
+                MessageKind::PublicAddress
+                    | MessageKind::ObserverHello
+                    | MessageKind::PullParticipantsList
+                    | MessageKind::SharedPeers
+                    | MessageKind::Batch
+            ) {
+                continue;
+            }
+            assert_eq!(
+                validate_first_contact(&message),
+                Err(MismatchReason::UnexpectedKind(kind)),
+                "{}",
+                kind.name()
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_implausible_addresses_and_lists() {
+        let zero = addr(0);
+        let multicast: SocketAddr = "224.0.0.1:8080".parse().unwrap();
+        let broadcast: SocketAddr = "255.255.255.255:8080".parse().unwrap();
+        let oversized: Vec<SocketAddr> = (1..=MAX_LIST_LEN as u16 + 1).map(addr).collect();
+        for (message, reason) in [
+            (Message::PublicAddress(zero), MismatchReason::ZeroPort(zero)),
+            (
+                Message::PublicAddress(multicast),
+                MismatchReason::NotUnicast(multicast),
+            ),
+            (
+                Message::PullParticipantsList(vec![addr(8080), broadcast]),
+                MismatchReason::NotUnicast(broadcast),
+            ),
+            (shared(&[addr(8080), zero]), MismatchReason::ZeroPort(zero)),
+            (
+                Message::PullParticipantsList(Vec::new()),
+                MismatchReason::EmptyList,
+            ),
+            (shared(&[]), MismatchReason::EmptyList),
+            (
+                Message::PullParticipantsList(oversized),
+                MismatchReason::OversizedList(MAX_LIST_LEN + 1),
+            ),
+        ] {
+            assert_eq!(
+                validate_first_contact(&message),
+                Err(reason),
+                "{:?}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn a_batch_is_judged_by_its_first_message() {
+        assert_eq!(
+            validate_first_contact(&Message::Batch(Vec::new())),
+            Err(MismatchReason::UnexpectedKind(MessageKind::Batch))
+        );
+        assert_eq!(
+            validate_first_contact(&Message::Batch(vec![Message::Batch(Vec::new())])),
+            Err(MismatchReason::UnexpectedKind(MessageKind::Batch))
+        );
+        let reason = validate_first_contact(&Message::Batch(vec![
+            Message::Text("random message 1".to_owned()),
+            shared(&[addr(8080)]),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            reason,
+            MismatchReason::Batch(MismatchReason::UnexpectedKind(MessageKind::Text).into())
+        );
+        assert_eq!(
+            reason.to_string(),
+            "in a batch, it opened with a Text message"
+        );
+    }
+
+    #[test]
+    fn reasons_explain_the_mismatch() {
+        assert_eq!(
+            MismatchReason::ZeroPort(addr(0)).to_string(),
+            "it sent the address 127.0.0.1:0 of port 0"
+        );
+        assert_eq!(
+            MismatchReason::OversizedList(300).to_string(),
+            format!(
+                "it sent a participants list of 300 addresses, over {}",
+                MAX_LIST_LEN
+            )
+        );
+    }
+
+    #[test]
+    fn silent_connections_expire_after_the_timeout() {
+        let start = Instant::now();
+        let mut pending = PendingContacts::new();
+        pending.expect(1, start);
+        pending.expect(2, start + Duration::from_secs(5));
+        pending.expect(3, start);
+        pending.forget(&3);
+        assert!(pending.is_pending(&1));
+        assert!(!pending.is_pending(&3));
+
+        assert!(pending
+            .expired(start + FIRST_CONTACT_TIMEOUT, FIRST_CONTACT_TIMEOUT)
+            .is_empty());
+        let later = start + FIRST_CONTACT_TIMEOUT + Duration::from_millis(1);
+        assert_eq!(pending.expired(later, FIRST_CONTACT_TIMEOUT), vec![1]);
+        assert!(!pending.is_pending(&1));
+        assert!(pending.is_pending(&2));
+        assert!(pending.expired(later, FIRST_CONTACT_TIMEOUT).is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+//! Crossed Connections.
+//!
+//! Two participants that learn of each other at the same time, typically from participants
+//! lists exchanged at once, dial each other and end up with two connections. Left alone, both
+//! are kept, every message goes out twice, and whichever one closes first looks like a loss.
+//!
+//! Each side breaks the tie on its own, as soon as it sees both connections, from the same pair
+//! of addresses: the one it announces through its outbound connection and the one the peer
+//! announced through its inbound connection, which the peer also sees, reversed. The connection
+//! dialed by the smaller address, compared canonically by IP then port, survives. The side
+//! with the larger address closes its own dial, aborting it if it is still connecting, after
+//! moving the counters and the state it gathered to the surviving connection. The side with
+//! the smaller address keeps both and waits for the peer to close its inbound connection:
+//! `CrossedConnections` remembers that connection, so its close is no departure and its state
+//! folds into the survivor.
+//!
+//! Neither side ends a session or records a membership change for the closed connection. A
+//! peer announcing the very address of this participant breaks no tie, and both connections
+//! are kept.
+
+use crate::participant::utils::canonical;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::SocketAddr;
+
+/// The connection kept out of two crossed connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Survivor {
+    /// The connection this participant dialed.
+    Outbound,
+    /// The connection the peer dialed.
+    Inbound,
+}
+
+/// Decides which of the crossed connections to a peer survives.
+///
+/// # Parameters
+///
+/// * `local` - The address this participant announces to the peer.
+/// * `remote` - The address the peer announced.
+///
+/// # Returns
+///
+/// `None` when both addresses are the same, which breaks no tie: each side would keep the
+/// connection of the other and both would be closed.
+pub fn survivor(local: SocketAddr, remote: SocketAddr) -> Option<Survivor> {
+    match canonical(local).cmp(&canonical(remote)) {
+        Ordering::Less => Some(Survivor::Outbound),
+        Ordering::Greater => Some(Survivor::Inbound),
+        Ordering::Equal => None,
+    }
+}
+
+/// The crossed connections the peers are expected to close.
+#[derive(Debug)]
+pub struct CrossedConnections<K> {
+    /// The connection each peer closes, and the one that survives it.
+    awaited: HashMap<K, K>,
+    /// The crossed connections closed by this participant.
+    closed_here: u64,
+    /// The crossed connections closed by the peers, as their state folded.
+    closed_by_peer: u64,
+}
+
+impl<K: Hash + Eq + Copy> Default for CrossedConnections<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Copy> CrossedConnections<K> {
+    /// Creates a table without crossed connections.
+    pub fn new() -> Self {
+        Self {
+            awaited: HashMap::new(),
+            closed_here: 0,
+            closed_by_peer: 0,
+        }
+    }
+
+    /// Waits for the peer to close `loser`, whose state then folds into `survivor`.
+    pub fn await_close(&mut self, loser: K, survivor: K) {
+        self.awaited.insert(loser, survivor);
+    }
+
+    /// Counts a crossed connection this participant closed.
+    pub fn closed_here(&mut self) {
+        self.closed_here += 1;
+    }
+
+    /// Handles the close of `endpoint`.
+    ///
+    /// # Returns
+    ///
+    /// The surviving connection `endpoint` folds into, if the peer was expected to close it.
+    /// The connections awaiting the close of a surviving `endpoint` are forgotten: they are a
+    /// peer's only connection from now on.
+    pub fn closed(&mut self, endpoint: &K) -> Option<K> {
+        let survivor = self.awaited.remove(endpoint);
+        if survivor.is_some() {
+            self.closed_by_peer += 1;
+        } else {
+            self.awaited.retain(|_, survivor| survivor != endpoint);
+        }
+        survivor
+    }
+
+    /// Returns the number of crossed connections closed by this participant, closed by the
+    /// peers, and still awaited.
+    pub fn counters(&self) -> (u64, u64, usize) {
+        (self.closed_here, self.closed_by_peer, self.awaited.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn parse(text: &str) -> SocketAddr {
+        text.parse().unwrap()
+    }
+
+    /// Determines whether both sides keep the same connection: the one a side dialed is the
+    /// one the other side accepted.
+    fn agree(a: SocketAddr, b: SocketAddr) -> bool {
+        matches!(
+            (survivor(a, b), survivor(b, a)),
+            (Some(Survivor::Outbound), Some(Survivor::Inbound))
+                | (Some(Survivor::Inbound), Some(Survivor::Outbound))
+        )
+    }
+
+    #[test]
+    fn the_smaller_address_keeps_its_dial() {
+        assert_eq!(survivor(addr(8000), addr(8001)), Some(Survivor::Outbound));
+        assert_eq!(survivor(addr(8001), addr(8000)), Some(Survivor::Inbound));
+        // The IP is compared before the port.
+        assert_eq!(
+            survivor(parse("10.0.0.1:9000"), parse("10.0.0.2:8000")),
+            Some(Survivor::Outbound)
+        );
+    }
+
+    #[test]
+    fn both_sides_keep_the_same_connection_in_either_order() {
+        let addrs = [
+            addr(8000),
+            addr(8001),
+            parse("10.0.0.1:8000"),
+            parse("[::ffff:10.0.0.2]:8000"),
+            parse("[fd00::1]:8000"),
+            parse("[fd00::1]:8001"),
+        ];
+        for a in addrs {
+            for b in addrs.into_iter().filter(|b| *b != a) {
+                assert!(agree(a, b), "{} and {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn compares_mapped_addresses_as_ipv4() {
+        let mapped = parse("[::ffff:127.0.0.1]:8001");
+        assert_eq!(survivor(addr(8000), mapped), Some(Survivor::Outbound));
+        assert_eq!(survivor(mapped, addr(8000)), Some(Survivor::Inbound));
+        // An IPv4 address sorts before every IPv6 one, whatever its form.
+        assert_eq!(
+            survivor(parse("[::ffff:255.0.0.1]:8000"), parse("[::1]:8000")),
+            Some(Survivor::Outbound)
+        );
+    }
+
+    #[test]
+    fn the_same_address_breaks_no_tie() {
+        assert_eq!(survivor(addr(8000), addr(8000)), None);
+        assert_eq!(survivor(addr(8000), parse("[::ffff:127.0.0.1]:8000")), None);
+    }
+
+    #[test]
+    fn folds_the_connections_the_peers_close() {
+        let mut crossed = CrossedConnections::new();
+        crossed.await_close(1, 2);
+        crossed.await_close(3, 4);
+        crossed.closed_here();
+
+        assert_eq!(crossed.closed(&1), Some(2));
+        assert_eq!(crossed.closed(&1), None);
+        // A survivor closing first leaves its peer a single connection.
+        assert_eq!(crossed.closed(&4), None);
+        assert_eq!(crossed.closed(&3), None);
+        assert_eq!(crossed.counters(), (1, 1, 0));
+    }
+}
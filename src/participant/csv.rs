@@ -0,0 +1,363 @@
+//! CSV Export.
+//!
+//! The peers of a soak run are analyzed in spreadsheets, which read CSV rather than the JSON
+//! report. `peers export-csv <path>` writes the summaries of the known peers as a table, a
+//! header row then one row per peer, and `--report-format=csv` or `both` writes the same table
+//! as the report at shutdown.
+//!
+//! The columns are `PEER_COLUMNS`, always all of them and in that order; a column is only ever
+//! added at the end, so scripts may address them by position. A value the participant does not
+//! know is an empty cell, never a missing one: the node id of a peer that presented none, the
+//! time of a session still connecting, the clock offset before its first sample, the mute of a
+//! peer that is not muted, the provenance of an address learned before the last restart or
+//! forgotten since. Durations are in milliseconds and the clock offset in microseconds, as
+//! integers.
+//!
+//! Fields follow RFC 4180: a field holding a comma, a double quote, a carriage return or a line
+//! feed is enclosed in double quotes, with its double quotes doubled, and every row ends with
+//! CRLF. The tables are written by hand, since they need nothing more.
+
+use crate::participant::provenance::LearnedVia;
+use crate::participant::storage::PeerSummary;
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// The number of columns of a peer row.
+pub const PEER_COLUMN_COUNT: usize = 25;
+
+/// The header of the peer table, in column order.
+pub const PEER_COLUMNS: [&str; PEER_COLUMN_COUNT] = [
+    "address",
+    "node_id",
+    "direction",
+    "state",
+    "connected_ms",
+    "sessions",
+    "flaps_10m",
+    "longest_session_ms",
+    "last_verified_age_ms",
+    "declared_period_ms",
+    "clock_offset_us",
+    "clock_bound_us",
+    "clock_samples",
+    "unanswered_sends",
+    "deferred_sends",
+    "muted_remaining_ms",
+    "muted_drops",
+    "msgs_sent",
+    "msgs_received",
+    "former_addresses",
+    "learned_via",
+    "learned_from",
+    "learned_request",
+    "learned_uptime_ms",
+    "corroborators",
+];
+
+/// Quotes `field` as RFC 4180 asks, when it holds a separator, a quote or a line break.
+pub fn quote(field: &str) -> Cow<'_, str> {
+    if !field.contains([',', '"', '\r', '\n']) {
+        return Cow::Borrowed(field);
+    }
+    Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+}
+
+/// Formats a table of `header` and `rows`, each row ending with CRLF.
+pub fn table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) -> String {
+    let mut out = String::new();
+    let mut push_row = |fields: &mut dyn Iterator<Item = &str>| {
+        let quoted: Vec<Cow<'_, str>> = fields.map(quote).collect();
+        out.push_str(&quoted.join(","));
+        out.push_str("\r\n");
+    };
+    push_row(&mut header.iter().copied());
+    for row in rows {
+        push_row(&mut row.iter().map(String::as_str));
+    }
+    out
+}
+
+/// Returns the row of one peer, in `PEER_COLUMNS` order.
+pub fn peer_row(summary: &PeerSummary) -> [String; PEER_COLUMN_COUNT] {
+    let millis = |duration: Option<Duration>| {
+        duration.map_or(String::new(), |duration| duration.as_millis().to_string())
+    };
+    let offset = summary.clock_offset;
+    let former: Vec<String> = summary
+        .former_addrs
+        .iter()
+        .map(|addr| addr.to_string())
+        .collect();
+    let provenance = summary.provenance.as_ref();
+    [
+        summary.public.to_string(),
+        summary.node_id.map_or(String::new(), |id| id.to_string()),
+        summary.direction.name().to_owned(),
+        match summary.sessions.current {
+            Some(_) => "connected".to_owned(),
+            None => "connecting".to_owned(),
+        },
+        millis(summary.sessions.current),
+        summary.sessions.total.to_string(),
+        summary.sessions.flaps.to_string(),
+        summary.sessions.longest.as_millis().to_string(),
+        millis(summary.verified_ago),
+        millis(summary.declared_period),
+        offset.map_or(String::new(), |offset| offset.offset_us.to_string()),
+        offset.map_or(String::new(), |offset| offset.bound.as_micros().to_string()),
+        offset.map_or(String::new(), |offset| offset.samples.to_string()),
+        summary.sends_since_receive.to_string(),
+        summary.deferred_sends.to_string(),
+        millis(summary.muted_for),
+        summary.muted_drops.to_string(),
+        summary.sent.iter().sum::<u64>().to_string(),
+        summary.received.iter().sum::<u64>().to_string(),
+        former.join(" "),
+        provenance.map_or(String::new(), |record| record.source.name().to_owned()),
+        provenance
+            .and_then(|record| record.source.peer())
+            .map_or(String::new(), |peer| peer.to_string()),
+        match provenance.map(|record| record.source) {
+            Some(LearnedVia::List {
+                request: Some(request),
+                ..
+            }) => request.to_string(),
+            _ => String::new(),
+        },
+        millis(provenance.and_then(|record| record.uptime)),
+        provenance.map_or(String::new(), |record| {
+            record.corroborators.len().to_string()
+        }),
+    ]
+}
+
+/// Formats the table of `summaries`, one row per peer.
+pub fn peers_table(summaries: &[PeerSummary]) -> String {
+    let rows: Vec<[String; PEER_COLUMN_COUNT]> = summaries.iter().map(peer_row).collect();
+    table(&PEER_COLUMNS, &rows)
+}
+
+/// Writes `table` to `path` through a temporary file, so a reader never sees half a table.
+pub fn save(path: &Path, table: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, table)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::identity::NodeId;
+    use crate::participant::message::MessageKind;
+    use crate::participant::provenance::Provenance;
+    use crate::participant::session::SessionStats;
+    use crate::participant::skew::ClockOffset;
+    use crate::participant::storage::Direction;
+
+    /// Splits `table` back into rows of fields, undoing the quoting of `quote`.
+    fn parse(table: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let (mut row, mut field) = (Vec::new(), String::new());
+        let mut chars = table.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\r') => assert_eq!(chars.next(), Some('\n'), "a bare CR"),
+                (false, '\n') => panic!("a row ended without CR"),
+                (false, c) => field.push(c),
+            }
+            if !quoted && c == '\r' {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+        }
+        assert!(
+            !quoted && field.is_empty() && row.is_empty(),
+            "a partial row"
+        );
+        rows
+    }
+
+    fn summary() -> PeerSummary {
+        PeerSummary {
+            public: "127.0.0.1:8080".parse().unwrap(),
+            direction: Direction::Outbound,
+            node_id: None,
+            former_addrs: Vec::new(),
+            sent: [0; MessageKind::COUNT],
+            received: [0; MessageKind::COUNT],
+            sends_since_receive: 0,
+            deferred_sends: 0,
+            sessions: SessionStats {
+                current: None,
+                total: 0,
+                flaps: 0,
+                longest: Duration::ZERO,
+            },
+            verified_ago: None,
+            muted_for: None,
+            muted_drops: 0,
+            declared_period: None,
+            clock_offset: None,
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn quotes_only_what_needs_it() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote(""), "");
+        assert_eq!(quote("ünïcødé ✓"), "ünïcødé ✓");
+        assert_eq!(quote("a,b"), "\"a,b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote("\""), "\"\"\"\"");
+        assert_eq!(quote("two\nlines"), "\"two\nlines\"");
+        assert_eq!(quote("cr\r"), "\"cr\r\"");
+        assert!(matches!(quote("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn quoted_fields_read_back() {
+        let fields = ["a,b", "say \"hi\"", "two\r\nlines", "ünïcødé", "", "\"\""];
+        let rows = [fields.map(str::to_owned)];
+        let table = table(&["1", "2", "3", "4", "5", "6"], &rows);
+        assert!(table.ends_with("\r\n"));
+        assert_eq!(
+            parse(&table),
+            [vec!["1", "2", "3", "4", "5", "6"], fields.to_vec()]
+        );
+    }
+
+    #[test]
+    fn the_header_is_stable() {
+        // Columns are only ever added at the end: scripts address them by position.
+        let header = "address,node_id,direction,state,connected_ms,sessions,flaps_10m,\
+            longest_session_ms,last_verified_age_ms,declared_period_ms,clock_offset_us,\
+            clock_bound_us,clock_samples,unanswered_sends,deferred_sends,muted_remaining_ms,\
+            muted_drops,msgs_sent,msgs_received,former_addresses,learned_via,learned_from,\
+            learned_request,learned_uptime_ms,corroborators\r\n";
+        assert_eq!(peers_table(&[]), header);
+    }
+
+    #[test]
+    fn unknown_values_are_empty_cells() {
+        let rows = parse(&peers_table(&[summary(), summary()]));
+        assert_eq!(rows.len(), 3);
+        let row = &rows[1];
+        assert_eq!(row.len(), PEER_COLUMN_COUNT);
+        let cell = |name| {
+            &row[PEER_COLUMNS
+                .iter()
+                .position(|column| *column == name)
+                .unwrap()]
+        };
+        assert_eq!(cell("address"), "127.0.0.1:8080");
+        assert_eq!(cell("direction"), "outbound");
+        assert_eq!(cell("state"), "connecting");
+        assert_eq!(cell("sessions"), "0");
+        for empty in [
+            "node_id",
+            "connected_ms",
+            "last_verified_age_ms",
+            "declared_period_ms",
+            "clock_offset_us",
+            "clock_bound_us",
+            "clock_samples",
+            "muted_remaining_ms",
+            "former_addresses",
+            "learned_via",
+            "learned_from",
+            "learned_request",
+            "learned_uptime_ms",
+            "corroborators",
+        ] {
+            assert_eq!(cell(empty), "", "{}", empty);
+        }
+    }
+
+    #[test]
+    fn known_values_are_numbers_in_their_units() {
+        let mut summary = summary();
+        let lister = "127.0.0.2:9000".parse().unwrap();
+        summary.node_id = Some(NodeId::random());
+        summary.former_addrs = vec!["10.0.0.1:1".parse().unwrap(), "[::1]:2".parse().unwrap()];
+        summary.sent[0] = 3;
+        summary.sent[1] = 4;
+        summary.received[2] = 5;
+        summary.sessions.current = Some(Duration::from_millis(1_500));
+        summary.sessions.total = 2;
+        summary.verified_ago = Some(Duration::from_secs(2));
+        summary.muted_for = Some(Duration::from_secs(30));
+        summary.clock_offset = Some(ClockOffset {
+            offset_us: -1_250,
+            bound: Duration::from_micros(300),
+            samples: 4,
+        });
+        summary.provenance = Some(Provenance {
+            source: LearnedVia::List {
+                lister,
+                request: Some(7),
+            },
+            learned_at: 0,
+            uptime: Some(Duration::from_millis(250)),
+            corroborators: vec![lister],
+        });
+
+        let row = peer_row(&summary);
+        let cell = |name| {
+            &row[PEER_COLUMNS
+                .iter()
+                .position(|column| *column == name)
+                .unwrap()]
+        };
+        assert_eq!(cell("node_id").len(), 32);
+        assert_eq!(cell("state"), "connected");
+        assert_eq!(cell("connected_ms"), "1500");
+        assert_eq!(cell("last_verified_age_ms"), "2000");
+        assert_eq!(cell("muted_remaining_ms"), "30000");
+        assert_eq!(cell("clock_offset_us"), "-1250");
+        assert_eq!(cell("clock_bound_us"), "300");
+        assert_eq!(cell("msgs_sent"), "7");
+        assert_eq!(cell("msgs_received"), "5");
+        assert_eq!(cell("former_addresses"), "10.0.0.1:1 [::1]:2");
+        assert_eq!(
+            cell("learned_via"),
+            LearnedVia::List {
+                lister,
+                request: None
+            }
+            .name()
+        );
+        assert_eq!(cell("learned_from"), "127.0.0.2:9000");
+        assert_eq!(cell("learned_request"), "7");
+        assert_eq!(cell("learned_uptime_ms"), "250");
+        assert_eq!(cell("corroborators"), "1");
+    }
+
+    #[test]
+    fn saves_in_one_piece() {
+        let dir = std::env::temp_dir().join(format!("gossip-p2p-csv-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("peers.csv");
+        fs::write(&path, "old").unwrap();
+        save(&path, "new\r\n").unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let leftover = dir.join("peers.csv.tmp").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(saved, "new\r\n");
+        assert!(!leftover);
+    }
+}
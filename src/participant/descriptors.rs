@@ -0,0 +1,505 @@
+//! File Descriptors.
+//!
+//! Every connection holds a file descriptor, and the process only gets so many: 1024 by
+//! default on Linux. A participant that ran out stopped accepting connections while it looked
+//! alive, since `message-io` fails the accepts it can not complete. So the participant reads
+//! its soft limit at startup, keeps `--fd-headroom` descriptors, 64 by default, for the files
+//! it writes, the pipes of the commands it runs and the event loop itself, and hands the rest
+//! out as permits of a `DescriptorBudget`.
+//!
+//! Whatever opens a socket takes a permit first, and gives it back when the socket closes:
+//!
+//! - the transport, for its listeners, the dials it starts and the connections it accepts, so
+//!   the peers and the observers count alike;
+//! - the admin socket and its connections;
+//! - the alert worker, for each webhook it posts to.
+//!
+//! Without a permit, a dial is put off in the `DialBacklog` and started once permits free
+//! up, and an accepted connection is closed at once, with a line logged at most every
+//! `REFUSAL_LOG_INTERVAL`. Each refusal counts in `fd_budget_exhausted_total`. The admin
+//! connections, at most `MAX_ADMIN_CLIENTS`, are counted but never refused: once every permit
+//! is held they come out of the headroom, so an operator can still look into a saturated
+//! participant. Crossing `PRESSURE_PERCENT` of the permits logs a warning, once until the use
+//! falls back under `RELIEF_PERCENT`.
+
+use crate::participant::warmup::DeferredDial;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The soft limit assumed where the system does not tell one.
+pub const FALLBACK_FD_LIMIT: usize = 8192;
+
+/// The share of the permits in use, in percent, that logs a warning.
+pub const PRESSURE_PERCENT: usize = 80;
+
+/// The share of the permits in use, in percent, under which the warning is armed again.
+pub const RELIEF_PERCENT: usize = 70;
+
+/// The shortest time between two lines about refused connections.
+pub const REFUSAL_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The number of dials the backlog holds; the ones over it are dropped.
+pub const DIAL_BACKLOG: usize = 256;
+
+/// Returns the soft limit of file descriptors of the process.
+#[cfg(unix)]
+pub fn soft_limit() -> usize {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `getrlimit` only writes the `rlimit` it is given.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return FALLBACK_FD_LIMIT;
+    }
+    usize::try_from(limit.rlim_cur).unwrap_or(usize::MAX)
+}
+
+/// Returns the soft limit of file descriptors of the process.
+#[cfg(not(unix))]
+pub fn soft_limit() -> usize {
+    FALLBACK_FD_LIMIT
+}
+
+/// The error of a socket that was not opened for want of a permit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exhausted;
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the file descriptor budget is exhausted")
+    }
+}
+
+impl std::error::Error for Exhausted {}
+
+impl From<Exhausted> for io::Error {
+    fn from(err: Exhausted) -> Self {
+        io::Error::other(err)
+    }
+}
+
+/// Determines whether `err` tells that a socket was not opened for want of a permit.
+pub fn is_exhausted(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Exhausted>())
+}
+
+/// A change of the share of the permits in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pressure {
+    /// The use crossed `PRESSURE_PERCENT`.
+    High,
+    /// The use fell back under `RELIEF_PERCENT`.
+    Relieved,
+}
+
+/// The counters of a `DescriptorBudget`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorStats {
+    /// The soft limit of the process.
+    pub limit: usize,
+    /// The permits handed out at most.
+    pub ceiling: usize,
+    /// The permits held now.
+    pub in_use: usize,
+    /// The most permits held at once.
+    pub peak: usize,
+    /// The permits refused so far, `fd_budget_exhausted_total`.
+    pub exhausted: u64,
+}
+
+#[derive(Debug)]
+struct Counters {
+    limit: usize,
+    ceiling: usize,
+    /// The use that raises the pressure, and the one under which it is relieved.
+    high: usize,
+    relief: usize,
+    in_use: AtomicUsize,
+    peak: AtomicUsize,
+    exhausted: AtomicU64,
+    /// Whether the use crossed `high` and did not fall under `relief` since.
+    pressured: AtomicBool,
+    /// Whether the use crossed `high` since the last `DescriptorBudget::pressure`.
+    raised: AtomicBool,
+    /// Whether the last change taken by `DescriptorBudget::pressure` was `Pressure::High`.
+    reported: AtomicBool,
+}
+
+/// The permits to open sockets, shared by every thread that opens one.
+///
+/// Clones share the same permits.
+#[derive(Debug, Clone)]
+pub struct DescriptorBudget {
+    counters: Arc<Counters>,
+}
+
+impl DescriptorBudget {
+    /// Creates the budget of a process allowed `limit` descriptors, `headroom` of which are
+    /// kept for other uses than sockets.
+    pub fn new(limit: usize, headroom: usize) -> Self {
+        let ceiling = limit.saturating_sub(headroom);
+        let share = |percent: usize| (ceiling as u128 * percent as u128).div_ceil(100) as usize;
+        Self {
+            counters: Arc::new(Counters {
+                limit,
+                ceiling,
+                high: share(PRESSURE_PERCENT).max(1),
+                relief: share(RELIEF_PERCENT),
+                in_use: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                exhausted: AtomicU64::new(0),
+                pressured: AtomicBool::new(false),
+                raised: AtomicBool::new(false),
+                reported: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Takes a permit, held until the returned `Permit` is dropped.
+    ///
+    /// # Returns
+    ///
+    /// `None` when every permit is held; the refusal is counted.
+    pub fn acquire(&self) -> Option<Permit> {
+        let counters = &self.counters;
+        let taken = counters
+            .in_use
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |in_use| {
+                (in_use < counters.ceiling).then_some(in_use + 1)
+            });
+        let Ok(previous) = taken else {
+            counters.exhausted.fetch_add(1, Ordering::SeqCst);
+            return None;
+        };
+        let in_use = previous + 1;
+        counters.peak.fetch_max(in_use, Ordering::SeqCst);
+        if in_use >= counters.high && !counters.pressured.swap(true, Ordering::SeqCst) {
+            counters.raised.store(true, Ordering::SeqCst);
+        }
+        Some(Permit {
+            counters: Arc::clone(counters),
+        })
+    }
+
+    /// Takes a permit even when every one is held, eating into the headroom.
+    pub fn acquire_unbounded(&self) -> Permit {
+        let counters = &self.counters;
+        let in_use = counters.in_use.fetch_add(1, Ordering::SeqCst) + 1;
+        counters.peak.fetch_max(in_use, Ordering::SeqCst);
+        Permit {
+            counters: Arc::clone(counters),
+        }
+    }
+
+    /// Takes the change of pressure since the last call, if any.
+    ///
+    /// A crossing is reported even when the use fell back before the call; the relief is
+    /// then reported by the next one.
+    pub fn pressure(&self) -> Option<Pressure> {
+        let counters = &self.counters;
+        if counters.raised.swap(false, Ordering::SeqCst)
+            && !counters.reported.swap(true, Ordering::SeqCst)
+        {
+            return Some(Pressure::High);
+        }
+        let relieved = !counters.pressured.load(Ordering::SeqCst)
+            && counters.reported.swap(false, Ordering::SeqCst);
+        relieved.then_some(Pressure::Relieved)
+    }
+
+    /// Returns the number of permits left.
+    pub fn available(&self) -> usize {
+        let counters = &self.counters;
+        counters
+            .ceiling
+            .saturating_sub(counters.in_use.load(Ordering::SeqCst))
+    }
+
+    /// Returns the counters of the budget.
+    pub fn stats(&self) -> DescriptorStats {
+        let counters = &self.counters;
+        DescriptorStats {
+            limit: counters.limit,
+            ceiling: counters.ceiling,
+            in_use: counters.in_use.load(Ordering::SeqCst),
+            peak: counters.peak.load(Ordering::SeqCst),
+            exhausted: counters.exhausted.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// The right to hold one socket open, given back when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    counters: Arc<Counters>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let counters = &self.counters;
+        let in_use = counters.in_use.fetch_sub(1, Ordering::SeqCst) - 1;
+        if in_use < counters.relief {
+            counters.pressured.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The dials put off for want of a permit, started again in their order.
+#[derive(Debug, Default)]
+pub struct DialBacklog {
+    dials: VecDeque<DeferredDial>,
+    deferred: u64,
+    dropped: u64,
+}
+
+impl DialBacklog {
+    /// Creates an empty backlog.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts off `dial`, unless its address already waits; the dial is dropped when the
+    /// backlog is full.
+    pub fn defer(&mut self, dial: DeferredDial) {
+        if self.dials.iter().any(|queued| queued.addr == dial.addr) {
+            return;
+        }
+        if self.dials.len() >= DIAL_BACKLOG {
+            self.dropped += 1;
+            return;
+        }
+        self.dials.push_back(dial);
+        self.deferred += 1;
+    }
+
+    /// Puts back first a dial that still found no permit; the dial is dropped when the backlog
+    /// is full.
+    pub fn requeue(&mut self, dial: DeferredDial) {
+        if self.dials.len() >= DIAL_BACKLOG {
+            self.dropped += 1;
+            return;
+        }
+        self.dials.push_front(dial);
+    }
+
+    /// Takes the oldest dial put off.
+    pub fn next(&mut self) -> Option<DeferredDial> {
+        self.dials.pop_front()
+    }
+
+    /// Returns the number of dials waiting.
+    pub fn len(&self) -> usize {
+        self.dials.len()
+    }
+
+    /// Returns the number of dials put off so far, and dropped from a full backlog.
+    pub fn counters(&self) -> (u64, u64) {
+        (self.deferred, self.dropped)
+    }
+}
+
+/// Rate-limits the lines about connections refused for want of a permit.
+#[derive(Debug, Default)]
+pub struct RefusalLog {
+    last: Option<Instant>,
+    unlogged: u64,
+}
+
+impl RefusalLog {
+    /// Creates a log that logged nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a refused connection at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The number of refusals not logged since the last line, when this one is to be logged.
+    pub fn refused(&mut self, now: Instant) -> Option<u64> {
+        let quiet = self
+            .last
+            .is_some_and(|last| now.saturating_duration_since(last) < REFUSAL_LOG_INTERVAL);
+        if quiet {
+            self.unlogged += 1;
+            return None;
+        }
+        self.last = Some(now);
+        Some(std::mem::take(&mut self.unlogged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::dials::DialTrigger;
+    use crate::participant::message::AgeBucket;
+    use crate::participant::test_support::addr;
+
+    use std::thread;
+
+    fn dial(port: u16) -> DeferredDial {
+        DeferredDial {
+            addr: addr(port),
+            age: AgeBucket::Fresh,
+            interface: 0,
+            origin: None,
+            trigger: DialTrigger::Learned,
+        }
+    }
+
+    #[test]
+    fn keeps_the_headroom_out_of_the_permits() {
+        let budget = DescriptorBudget::new(10, 7);
+        let permits: Vec<Permit> = (0..3).filter_map(|_| budget.acquire()).collect();
+        assert_eq!(permits.len(), 3);
+        assert!(budget.acquire().is_none());
+        assert_eq!(budget.available(), 0);
+        assert_eq!(
+            budget.stats(),
+            DescriptorStats {
+                limit: 10,
+                ceiling: 3,
+                in_use: 3,
+                peak: 3,
+                exhausted: 1,
+            }
+        );
+
+        drop(permits);
+        assert_eq!(budget.available(), 3);
+        assert_eq!(budget.stats().peak, 3);
+        assert!(DescriptorBudget::new(10, 10).acquire().is_none());
+        assert!(DescriptorBudget::new(10, 100).acquire().is_none());
+    }
+
+    #[test]
+    fn counts_the_permits_of_concurrent_threads() {
+        let budget = DescriptorBudget::new(64, 0);
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                thread::spawn(move || {
+                    let mut granted = 0;
+                    for _ in 0..1_000 {
+                        let permits: Vec<Permit> =
+                            (0..16).filter_map(|_| budget.acquire()).collect();
+                        granted += permits.len();
+                    }
+                    granted
+                })
+            })
+            .collect();
+        let granted: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+
+        let stats = budget.stats();
+        assert_eq!(stats.in_use, 0);
+        assert_eq!(budget.available(), 64);
+        assert!(stats.peak <= stats.ceiling);
+        assert_eq!(granted as u64 + stats.exhausted, 8 * 1_000 * 16);
+    }
+
+    #[test]
+    fn unbounded_permits_eat_into_the_headroom() {
+        let budget = DescriptorBudget::new(4, 2);
+        let bounded: Vec<Permit> = (0..2).filter_map(|_| budget.acquire()).collect();
+        let unbounded = budget.acquire_unbounded();
+        assert_eq!(budget.stats().in_use, 3);
+        assert_eq!(budget.stats().peak, 3);
+        assert_eq!(budget.available(), 0);
+
+        drop(bounded);
+        assert!(budget.acquire().is_some());
+        drop(unbounded);
+        assert_eq!(budget.stats().in_use, 0);
+    }
+
+    #[test]
+    fn warns_once_until_the_use_falls_under_the_relief() {
+        let budget = DescriptorBudget::new(10, 0);
+        let mut permits: Vec<Permit> = (0..7).filter_map(|_| budget.acquire()).collect();
+        assert_eq!(budget.pressure(), None);
+
+        permits.push(budget.acquire().unwrap());
+        assert_eq!(budget.pressure(), Some(Pressure::High));
+        assert_eq!(budget.pressure(), None);
+
+        // Between the relief and the warning, the pressure holds.
+        permits.truncate(7);
+        permits.push(budget.acquire().unwrap());
+        assert_eq!(budget.pressure(), None);
+        permits.truncate(7);
+        assert_eq!(budget.pressure(), None);
+
+        permits.truncate(6);
+        assert_eq!(budget.pressure(), Some(Pressure::Relieved));
+        assert_eq!(budget.pressure(), None);
+
+        permits.extend((0..2).filter_map(|_| budget.acquire()));
+        assert_eq!(budget.pressure(), Some(Pressure::High));
+    }
+
+    #[test]
+    fn reports_a_crossing_the_use_already_fell_back_from() {
+        let budget = DescriptorBudget::new(10, 0);
+        let permits: Vec<Permit> = (0..8).filter_map(|_| budget.acquire()).collect();
+        drop(permits);
+        assert_eq!(budget.pressure(), Some(Pressure::High));
+        assert_eq!(budget.pressure(), Some(Pressure::Relieved));
+        assert_eq!(budget.pressure(), None);
+    }
+
+    #[test]
+    fn tells_an_exhausted_budget_from_other_errors() {
+        assert!(is_exhausted(&io::Error::from(Exhausted)));
+        assert!(!is_exhausted(&io::Error::other("refused")));
+        assert!(!is_exhausted(&io::Error::from(io::ErrorKind::AddrInUse)));
+    }
+
+    #[test]
+    fn resumes_the_deferred_dials_in_their_order() {
+        let mut backlog = DialBacklog::new();
+        backlog.defer(dial(1));
+        backlog.defer(dial(2));
+        backlog.defer(dial(1));
+        assert_eq!(backlog.len(), 2);
+        assert_eq!(backlog.counters(), (2, 0));
+
+        let first = backlog.next().unwrap();
+        assert_eq!(first, dial(1));
+        backlog.requeue(first);
+        assert_eq!(backlog.next(), Some(dial(1)));
+        assert_eq!(backlog.next(), Some(dial(2)));
+        assert_eq!(backlog.next(), None);
+    }
+
+    #[test]
+    fn drops_the_dials_of_a_full_backlog() {
+        let mut backlog = DialBacklog::new();
+        for port in 0..DIAL_BACKLOG as u16 {
+            backlog.defer(dial(port));
+        }
+        backlog.defer(dial(DIAL_BACKLOG as u16));
+        backlog.requeue(dial(DIAL_BACKLOG as u16 + 1));
+        assert_eq!(backlog.len(), DIAL_BACKLOG);
+        assert_eq!(backlog.counters(), (DIAL_BACKLOG as u64, 2));
+        assert_eq!(backlog.next(), Some(dial(0)));
+    }
+
+    #[test]
+    fn logs_one_refusal_per_interval() {
+        let now = Instant::now();
+        let mut log = RefusalLog::new();
+        assert_eq!(log.refused(now), Some(0));
+        assert_eq!(log.refused(now + Duration::from_secs(1)), None);
+        assert_eq!(log.refused(now + Duration::from_secs(9)), None);
+        assert_eq!(log.refused(now + REFUSAL_LOG_INTERVAL), Some(2));
+        assert_eq!(log.refused(now + REFUSAL_LOG_INTERVAL), None);
+    }
+}
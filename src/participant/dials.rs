@@ -0,0 +1,537 @@
+//! Dial Trail.
+//!
+//! "Why is this participant not connected to that address" is a question the log answers
+//! badly: the dial may have been skipped for one of half a dozen reasons, each logged
+//! differently or not at all, or started and lost long ago among the gossip lines. Every
+//! outbound dial the participant starts or decides against is an entry of its `DialTrail`,
+//! with the address, its `DialTrigger`, when it happened and its `DialOutcome`, and for a dial
+//! that completed the time it took.
+//!
+//! The entries are kept twice: the last `DIALS_PER_ADDR` of each address, for `dials <addr>`,
+//! and the last `RECENT_DIALS` of any address, for `dials --recent`. A skip repeating the
+//! previous entry of its address, such as a learned address still awaiting corroboration
+//! every time a list names it, updates that entry instead of adding one, so the trail keeps
+//! the attempts rather than the repetitions. The totals by trigger and outcome count every
+//! one of them, and are what `stats` and the report show, the partition heal probes
+//! included.
+//!
+//! `message-io` only tells that a dial did not connect, not why, so a refused connection and
+//! an unreachable host are both `DialOutcome::Failed`.
+
+use crate::participant::collections::{BoundedMap, Eviction};
+use crate::participant::utils::CanonicalAddr;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The number of attempts kept per address.
+pub const DIALS_PER_ADDR: usize = 8;
+
+/// The number of attempts kept across all addresses.
+pub const RECENT_DIALS: usize = 512;
+
+/// What made the participant dial an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DialTrigger {
+    /// A `--connect` target or an address of the recent peers cache.
+    Bootstrap,
+    /// An address named by a participants list.
+    Learned,
+    /// A departed peer probed to heal a partition.
+    HealProbe,
+    /// An inbound-only peer dialed back to balance the connections.
+    Rebalance,
+    /// A peer dialed on the introduction of a neighbor.
+    Introduction,
+    /// A half-open peer dialed again.
+    Redial,
+    /// A peer that left while this participant was frozen.
+    Rejoin,
+}
+
+impl DialTrigger {
+    /// The number of triggers, i.e. the length of a per-trigger array.
+    pub const COUNT: usize = 7;
+
+    /// Every trigger, in array order.
+    pub const ALL: [DialTrigger; DialTrigger::COUNT] = [
+        DialTrigger::Bootstrap,
+        DialTrigger::Learned,
+        DialTrigger::HealProbe,
+        DialTrigger::Rebalance,
+        DialTrigger::Introduction,
+        DialTrigger::Redial,
+        DialTrigger::Rejoin,
+    ];
+
+    /// Returns the name of this trigger, as in `stats` and the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            DialTrigger::Bootstrap => "bootstrap",
+            DialTrigger::Learned => "learned",
+            DialTrigger::HealProbe => "heal_probe",
+            DialTrigger::Rebalance => "rebalance",
+            DialTrigger::Introduction => "introduction",
+            DialTrigger::Redial => "redial",
+            DialTrigger::Rejoin => "rejoin",
+        }
+    }
+}
+
+impl fmt::Display for DialTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a dial ended, or why it was not started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DialOutcome {
+    /// The connection was established.
+    Connected,
+    /// The connection was refused or the address unreachable.
+    Failed,
+    /// The connection did not complete within `--connect-timeout`.
+    TimedOut,
+    /// Not started: the address is blocked or banned.
+    Filtered,
+    /// Put off by the warm-up or for want of a file descriptor.
+    Deferred,
+    /// Not started: the dial hygiene refused the address.
+    HygieneRejected,
+    /// Not started: too few peers listed the address yet.
+    CorroborationPending,
+}
+
+impl DialOutcome {
+    /// The number of outcomes, i.e. the length of a per-outcome array.
+    pub const COUNT: usize = 7;
+
+    /// Every outcome, in array order.
+    pub const ALL: [DialOutcome; DialOutcome::COUNT] = [
+        DialOutcome::Connected,
+        DialOutcome::Failed,
+        DialOutcome::TimedOut,
+        DialOutcome::Filtered,
+        DialOutcome::Deferred,
+        DialOutcome::HygieneRejected,
+        DialOutcome::CorroborationPending,
+    ];
+
+    /// Returns the name of this outcome, as in `stats` and the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            DialOutcome::Connected => "connected",
+            DialOutcome::Failed => "failed",
+            DialOutcome::TimedOut => "timed_out",
+            DialOutcome::Filtered => "filtered",
+            DialOutcome::Deferred => "deferred",
+            DialOutcome::HygieneRejected => "hygiene_rejected",
+            DialOutcome::CorroborationPending => "corroboration_pending",
+        }
+    }
+}
+
+impl fmt::Display for DialOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One entry of the trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialAttempt {
+    /// The number of the attempt, counting from 1 since the start.
+    pub id: u64,
+    /// The address dialed.
+    pub addr: SocketAddr,
+    /// What made the participant dial it.
+    pub trigger: DialTrigger,
+    /// When the dial started, or when the last repetition of a skip happened.
+    pub at: Instant,
+    /// How the dial ended; `None` while it is in flight.
+    pub outcome: Option<DialOutcome>,
+    /// How long a started dial took to end.
+    pub latency: Option<Duration>,
+    /// How many times in a row the outcome happened, above 1 for a repeated skip.
+    pub repeats: u32,
+}
+
+impl DialAttempt {
+    /// Describes the attempt as of `now`, as `dials` shows it.
+    pub fn describe(&self, now: Instant) -> String {
+        let ago = now.saturating_duration_since(self.at);
+        let outcome = match (self.outcome, self.latency) {
+            (None, _) => "in flight".to_owned(),
+            (Some(outcome), Some(latency)) => format!("{} in {:.1?}", outcome, latency),
+            (Some(outcome), None) if self.repeats > 1 => {
+                format!("{} {} times", outcome, self.repeats)
+            }
+            (Some(outcome), None) => outcome.to_string(),
+        };
+        format!(
+            "#{} \"{}\" {} {:.1?} ago: {}",
+            self.id, self.addr, self.trigger, ago, outcome
+        )
+    }
+}
+
+/// A dial in flight.
+#[derive(Debug, Clone, Copy)]
+struct InFlight {
+    id: u64,
+    addr: SocketAddr,
+    trigger: DialTrigger,
+    started: Instant,
+}
+
+/// The trail of the dials of a participant, the dials in flight keyed by connection.
+pub struct DialTrail<K> {
+    by_addr: BoundedMap<CanonicalAddr, VecDeque<DialAttempt>>,
+    recent: VecDeque<DialAttempt>,
+    in_flight: HashMap<K, InFlight>,
+    next_id: u64,
+    counts: [[u64; DialOutcome::COUNT]; DialTrigger::COUNT],
+}
+
+impl<K: Hash + Eq> DialTrail<K> {
+    /// Creates an empty trail keeping the attempts of at most `addresses` addresses.
+    pub fn new(addresses: usize) -> Self {
+        Self {
+            by_addr: BoundedMap::new(addresses, Eviction::Lru, None),
+            recent: VecDeque::with_capacity(RECENT_DIALS),
+            in_flight: HashMap::new(),
+            next_id: 1,
+            counts: [[0; DialOutcome::COUNT]; DialTrigger::COUNT],
+        }
+    }
+
+    /// Records that a dial of `addr` started through `key`, its outcome following later.
+    pub fn started(&mut self, key: K, addr: SocketAddr, trigger: DialTrigger, now: Instant) {
+        let id = self.push(addr, trigger, None, now);
+        self.in_flight.insert(
+            key,
+            InFlight {
+                id,
+                addr,
+                trigger,
+                started: now,
+            },
+        );
+    }
+
+    /// Records how the dial through `key` ended, if it is in flight.
+    pub fn finished(&mut self, key: &K, outcome: DialOutcome, now: Instant) {
+        let Some(dial) = self.in_flight.remove(key) else {
+            return;
+        };
+        self.counts[dial.trigger as usize][outcome as usize] += 1;
+        let latency = now.saturating_duration_since(dial.started);
+        let update = |attempt: &mut DialAttempt| {
+            attempt.outcome = Some(outcome);
+            attempt.latency = Some(latency);
+        };
+        if let Some(attempt) = self
+            .recent
+            .iter_mut()
+            .rev()
+            .find(|attempt| attempt.id == dial.id)
+        {
+            update(attempt);
+        }
+        if let Some(attempts) = self.by_addr.get_mut(&dial.addr.into(), now) {
+            if let Some(attempt) = attempts.iter_mut().find(|attempt| attempt.id == dial.id) {
+                update(attempt);
+            }
+        }
+    }
+
+    /// Records a dial of `addr` that ended, or was decided against, at once.
+    pub fn settled(
+        &mut self,
+        addr: SocketAddr,
+        trigger: DialTrigger,
+        outcome: DialOutcome,
+        now: Instant,
+    ) {
+        self.counts[trigger as usize][outcome as usize] += 1;
+        let repeated = self
+            .by_addr
+            .get_mut(&addr.into(), now)
+            .and_then(|attempts| attempts.back_mut())
+            .filter(|last| {
+                last.trigger == trigger && last.outcome == Some(outcome) && last.latency.is_none()
+            })
+            .map(|last| {
+                last.at = now;
+                last.repeats += 1;
+                *last
+            });
+        let Some(repeated) = repeated else {
+            self.push(addr, trigger, Some(outcome), now);
+            return;
+        };
+        // The repetition moves to the back of the recent attempts, as a new one would.
+        if let Some(index) = self
+            .recent
+            .iter()
+            .position(|attempt| attempt.id == repeated.id)
+        {
+            self.recent.remove(index);
+        }
+        self.push_recent(repeated);
+    }
+
+    /// Returns the attempts kept for `addr`, oldest first.
+    pub fn of(&mut self, addr: SocketAddr, now: Instant) -> Vec<DialAttempt> {
+        self.by_addr
+            .get_mut(&addr.into(), now)
+            .map_or(Vec::new(), |attempts| attempts.iter().copied().collect())
+    }
+
+    /// Returns the last attempts of any address, oldest first.
+    pub fn recent(&self) -> Vec<DialAttempt> {
+        self.recent.iter().copied().collect()
+    }
+
+    /// Returns the number of dials of `trigger` that ended with `outcome`.
+    pub fn count(&self, trigger: DialTrigger, outcome: DialOutcome) -> u64 {
+        self.counts[trigger as usize][outcome as usize]
+    }
+
+    /// Returns the number of dials of `trigger` in flight.
+    pub fn in_flight(&self, trigger: DialTrigger) -> u64 {
+        self.in_flight
+            .values()
+            .filter(|dial| dial.trigger == trigger)
+            .count() as u64
+    }
+
+    /// Returns the number of dials that ended with each outcome, in `DialOutcome::ALL` order.
+    pub fn by_outcome(&self) -> [u64; DialOutcome::COUNT] {
+        DialOutcome::ALL.map(|outcome| {
+            DialTrigger::ALL
+                .iter()
+                .map(|&trigger| self.count(trigger, outcome))
+                .sum()
+        })
+    }
+
+    /// Returns the number of dials of each trigger, in flight or not, in `DialTrigger::ALL`
+    /// order.
+    pub fn by_trigger(&self) -> [u64; DialTrigger::COUNT] {
+        DialTrigger::ALL.map(|trigger| {
+            self.counts[trigger as usize].iter().sum::<u64>() + self.in_flight(trigger)
+        })
+    }
+
+    /// Adds a new attempt to both rings.
+    ///
+    /// # Returns
+    ///
+    /// The id of the attempt.
+    fn push(
+        &mut self,
+        addr: SocketAddr,
+        trigger: DialTrigger,
+        outcome: Option<DialOutcome>,
+        now: Instant,
+    ) -> u64 {
+        let attempt = DialAttempt {
+            id: self.next_id,
+            addr,
+            trigger,
+            at: now,
+            outcome,
+            latency: None,
+            repeats: 1,
+        };
+        self.next_id += 1;
+        match self.by_addr.get_mut(&addr.into(), now) {
+            Some(attempts) => {
+                if attempts.len() == DIALS_PER_ADDR {
+                    attempts.pop_front();
+                }
+                attempts.push_back(attempt);
+            }
+            None => self
+                .by_addr
+                .insert(addr.into(), VecDeque::from([attempt]), now),
+        }
+        self.push_recent(attempt);
+        attempt.id
+    }
+
+    /// Adds `attempt` at the back of the recent attempts, dropping the oldest when full.
+    fn push_recent(&mut self, attempt: DialAttempt) {
+        if self.recent.len() == RECENT_DIALS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(attempt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn a_started_dial_gets_its_outcome_and_latency() {
+        let mut trail = DialTrail::new(16);
+        let now = Instant::now();
+        trail.started(1u16, addr(8080), DialTrigger::Bootstrap, now);
+        assert_eq!(trail.in_flight(DialTrigger::Bootstrap), 1);
+        assert_eq!(trail.of(addr(8080), now)[0].outcome, None);
+
+        trail.finished(&1, DialOutcome::Connected, now + 12 * MS);
+        // A dial no longer in flight is not finished twice.
+        trail.finished(&1, DialOutcome::Failed, now + 20 * MS);
+        let attempts = trail.of(addr(8080), now);
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].outcome, Some(DialOutcome::Connected));
+        assert_eq!(attempts[0].latency, Some(12 * MS));
+        assert_eq!(trail.recent(), attempts);
+        assert_eq!(trail.in_flight(DialTrigger::Bootstrap), 0);
+        assert_eq!(
+            trail.count(DialTrigger::Bootstrap, DialOutcome::Connected),
+            1
+        );
+        assert_eq!(trail.count(DialTrigger::Bootstrap, DialOutcome::Failed), 0);
+    }
+
+    #[test]
+    fn a_repeated_skip_updates_its_entry() {
+        let mut trail = DialTrail::<u16>::new(16);
+        let now = Instant::now();
+        let pending = DialOutcome::CorroborationPending;
+        trail.settled(addr(8080), DialTrigger::Learned, pending, now);
+        trail.settled(addr(8090), DialTrigger::Learned, pending, now);
+        trail.settled(addr(8080), DialTrigger::Learned, pending, now + MS);
+
+        let attempts = trail.of(addr(8080), now);
+        assert_eq!(attempts.len(), 1);
+        assert_eq!((attempts[0].repeats, attempts[0].at), (2, now + MS));
+        // The repetition moved to the back of the recent attempts.
+        let recent: Vec<SocketAddr> = trail.recent().iter().map(|a| a.addr).collect();
+        assert_eq!(recent, [addr(8090), addr(8080)]);
+        assert_eq!(trail.count(DialTrigger::Learned, pending), 3);
+
+        // Another outcome, or another trigger, is an entry of its own.
+        trail.settled(addr(8080), DialTrigger::Learned, DialOutcome::Filtered, now);
+        trail.settled(
+            addr(8080),
+            DialTrigger::HealProbe,
+            DialOutcome::Filtered,
+            now,
+        );
+        assert_eq!(trail.of(addr(8080), now).len(), 3);
+    }
+
+    #[test]
+    fn the_rings_keep_the_last_attempts() {
+        let mut trail = DialTrail::<u16>::new(RECENT_DIALS);
+        let now = Instant::now();
+        for index in 0..DIALS_PER_ADDR + 2 {
+            let outcome = DialOutcome::ALL[index % 2];
+            trail.settled(addr(8080), DialTrigger::Learned, outcome, now);
+        }
+        let attempts = trail.of(addr(8080), now);
+        assert_eq!(attempts.len(), DIALS_PER_ADDR);
+        assert_eq!(attempts[0].id, 3);
+
+        for port in 0..RECENT_DIALS as u16 {
+            trail.settled(
+                addr(9000 + port),
+                DialTrigger::Learned,
+                DialOutcome::Failed,
+                now,
+            );
+        }
+        let recent = trail.recent();
+        assert_eq!(recent.len(), RECENT_DIALS);
+        assert_eq!(recent[0].addr, addr(9000));
+        // The address dialed least recently made room for the last one.
+        assert!(trail.of(addr(8080), now).is_empty());
+    }
+
+    #[test]
+    fn the_totals_add_up_per_trigger_and_outcome() {
+        let mut trail = DialTrail::new(16);
+        let now = Instant::now();
+        trail.started(1u16, addr(8080), DialTrigger::Learned, now);
+        trail.started(2, addr(8081), DialTrigger::Learned, now);
+        trail.started(3, addr(8082), DialTrigger::HealProbe, now);
+        trail.finished(&1, DialOutcome::TimedOut, now);
+        trail.finished(&3, DialOutcome::Failed, now);
+        trail.settled(
+            addr(8083),
+            DialTrigger::Rebalance,
+            DialOutcome::Deferred,
+            now,
+        );
+
+        assert_eq!(trail.by_outcome(), [0, 1, 1, 0, 1, 0, 0]);
+        assert_eq!(trail.by_trigger(), [0, 2, 1, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn attempts_are_described_by_their_state() {
+        let now = Instant::now();
+        let mut attempt = DialAttempt {
+            id: 4,
+            addr: addr(8080),
+            trigger: DialTrigger::Redial,
+            at: now,
+            outcome: None,
+            latency: None,
+            repeats: 1,
+        };
+        let later = now + 1500 * MS;
+        assert_eq!(
+            attempt.describe(later),
+            "#4 \"127.0.0.1:8080\" redial 1.5s ago: in flight"
+        );
+        attempt.outcome = Some(DialOutcome::Connected);
+        attempt.latency = Some(3 * MS);
+        assert_eq!(
+            attempt.describe(later),
+            "#4 \"127.0.0.1:8080\" redial 1.5s ago: connected in 3.0ms"
+        );
+        attempt.outcome = Some(DialOutcome::HygieneRejected);
+        attempt.latency = None;
+        assert!(attempt.describe(later).ends_with("ago: hygiene_rejected"));
+        attempt.repeats = 3;
+        assert!(attempt
+            .describe(later)
+            .ends_with("ago: hygiene_rejected 3 times"));
+    }
+
+    #[test]
+    fn names_follow_the_array_order() {
+        let triggers: Vec<&str> = DialTrigger::ALL.iter().map(|t| t.name()).collect();
+        assert_eq!(
+            triggers,
+            [
+                "bootstrap",
+                "learned",
+                "heal_probe",
+                "rebalance",
+                "introduction",
+                "redial",
+                "rejoin",
+            ]
+        );
+        for (index, outcome) in DialOutcome::ALL.into_iter().enumerate() {
+            assert_eq!(outcome as usize, index);
+            assert_eq!(outcome.to_string(), outcome.name());
+        }
+    }
+}
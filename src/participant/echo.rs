@@ -0,0 +1,235 @@
+//! Echo Detection.
+//!
+//! A peer reflecting every frame back to its sender, such as a naive echo server, would have a
+//! participant log its own gossip texts as received, since a `Text` carries no origin to tell
+//! them apart. An `EchoGuard` keeps the hashes of the last `ECHO_RING` texts sent to each peer
+//! for `ECHO_WINDOW`, and a text the peer sends back within that time is taken for an echo:
+//! it is dropped instead of being logged and delivered, and counted as an `AnomalyKind::Echo`.
+//!
+//! A peer may also send a text equal to one it was sent. The structured texts name their
+//! sender and can not, but the legacy ones are drawn from a thousand values, so such a text is
+//! dropped about once in a thousand per text remembered. One echo therefore only warns; a peer
+//! whose last `ECHO_STRIKES` texts were all echoes, without a text of its own in between, is
+//! closed as a protocol violation.
+//!
+//! Handshake frames need no such care: their trailer carries the node id of their sender, and
+//! a frame presenting the id of this participant and one of its addresses is its own, closed
+//! at once and counted alike.
+
+use super::collections::{BoundedMap, Eviction};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// How long a text sent to a peer is remembered, to recognize its echo; an echo comes back
+/// within a round trip.
+pub const ECHO_WINDOW: Duration = Duration::from_secs(2);
+
+/// The most texts remembered per peer.
+pub const ECHO_RING: usize = 4;
+
+/// The number of echoes in a row that close the connection of the echoing peer.
+pub const ECHO_STRIKES: u32 = 3;
+
+/// What a text received from a peer turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoVerdict {
+    /// A text of the peer's own.
+    Genuine,
+    /// The echo of a text sent to the peer; `first` for the first of a row.
+    Echo { first: bool },
+    /// The echo completing `ECHO_STRIKES` in a row.
+    Violation,
+}
+
+/// The texts recently sent to one peer and its echoes in a row.
+#[derive(Debug, Default)]
+struct Sent {
+    hashes: VecDeque<(u64, Instant)>,
+    strikes: u32,
+}
+
+/// Recognizes the peers echoing the gossip texts of this participant back.
+pub struct EchoGuard<K> {
+    peers: BoundedMap<K, Sent>,
+    violations: u64,
+}
+
+impl<K: Hash + Eq + Clone> EchoGuard<K> {
+    /// Constructs a guard remembering the texts sent to at most `max_peers` peers.
+    pub fn new(max_peers: usize) -> Self {
+        Self {
+            // The echoes in a row outlive the texts, which may be sent further apart.
+            peers: BoundedMap::new(max_peers, Eviction::Lru, None),
+            violations: 0,
+        }
+    }
+
+    /// Remembers that `text` was sent to `peer` at `now`.
+    pub fn sent(&mut self, peer: K, text: &str, now: Instant) {
+        let hash = text_hash(text);
+        match self.peers.get_mut(&peer, now) {
+            Some(sent) => {
+                if sent.hashes.len() == ECHO_RING {
+                    sent.hashes.pop_front();
+                }
+                sent.hashes.push_back((hash, now));
+            }
+            None => {
+                let sent = Sent {
+                    hashes: VecDeque::from([(hash, now)]),
+                    strikes: 0,
+                };
+                self.peers.insert(peer, sent, now);
+            }
+        }
+    }
+
+    /// Checks whether `text`, received from `peer` at `now`, echoes a text sent to it.
+    pub fn received(&mut self, peer: &K, text: &str, now: Instant) -> EchoVerdict {
+        let Some(sent) = self.peers.get_mut(peer, now) else {
+            return EchoVerdict::Genuine;
+        };
+        sent.hashes
+            .retain(|(_, at)| now.saturating_duration_since(*at) <= ECHO_WINDOW);
+        let hash = text_hash(text);
+        let Some(index) = sent.hashes.iter().position(|(sent, _)| *sent == hash) else {
+            sent.strikes = 0;
+            return EchoVerdict::Genuine;
+        };
+        // Each text sent can only come back once.
+        sent.hashes.remove(index);
+        sent.strikes += 1;
+        if sent.strikes < ECHO_STRIKES {
+            return EchoVerdict::Echo {
+                first: sent.strikes == 1,
+            };
+        }
+        sent.strikes = 0;
+        self.violations += 1;
+        EchoVerdict::Violation
+    }
+
+    /// Forgets the texts sent to a peer that went away.
+    pub fn forget(&mut self, peer: &K) {
+        self.peers.remove(peer);
+    }
+
+    /// Counts the connection closed for sending a handshake of this participant back.
+    pub fn handshake_echoed(&mut self) {
+        self.violations += 1;
+    }
+
+    /// Returns the number of connections closed for echoing.
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+}
+
+/// Hashes a gossip text.
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    #[test]
+    fn a_text_sent_back_is_an_echo() {
+        let mut guard = EchoGuard::new(8);
+        let now = Instant::now();
+        guard.sent(addr(8080), "a", now);
+        guard.sent(addr(8080), "b", now);
+
+        assert_eq!(
+            guard.received(&addr(8080), "b", now),
+            EchoVerdict::Echo { first: true }
+        );
+        assert_eq!(
+            guard.received(&addr(8080), "a", now),
+            EchoVerdict::Echo { first: false }
+        );
+        // Each text sent comes back once, and only from the peer it was sent to.
+        assert_eq!(guard.received(&addr(8080), "a", now), EchoVerdict::Genuine);
+        guard.sent(addr(8080), "c", now);
+        assert_eq!(guard.received(&addr(8081), "c", now), EchoVerdict::Genuine);
+    }
+
+    #[test]
+    fn texts_are_forgotten_after_the_window_and_past_the_ring() {
+        let mut guard = EchoGuard::new(8);
+        let now = Instant::now();
+        guard.sent(addr(8080), "old", now);
+        let later = now + ECHO_WINDOW + Duration::from_millis(1);
+        assert_eq!(
+            guard.received(&addr(8080), "old", later),
+            EchoVerdict::Genuine
+        );
+
+        for index in 0..=ECHO_RING {
+            guard.sent(addr(8080), &index.to_string(), later);
+        }
+        assert_eq!(
+            guard.received(&addr(8080), "0", later),
+            EchoVerdict::Genuine
+        );
+        assert_eq!(
+            guard.received(&addr(8080), &ECHO_RING.to_string(), later),
+            EchoVerdict::Echo { first: true }
+        );
+    }
+
+    #[test]
+    fn echoes_in_a_row_are_a_violation() {
+        let mut guard = EchoGuard::new(8);
+        let now = Instant::now();
+        for index in 0..ECHO_STRIKES {
+            let text = index.to_string();
+            guard.sent(addr(8080), &text, now);
+            let verdict = guard.received(&addr(8080), &text, now);
+            if index + 1 < ECHO_STRIKES {
+                assert_eq!(verdict, EchoVerdict::Echo { first: index == 0 });
+            } else {
+                assert_eq!(verdict, EchoVerdict::Violation);
+            }
+        }
+        assert_eq!(guard.violations(), 1);
+        guard.handshake_echoed();
+        assert_eq!(guard.violations(), 2);
+    }
+
+    #[test]
+    fn a_text_of_the_peers_own_breaks_the_row() {
+        let mut guard = EchoGuard::new(8);
+        let now = Instant::now();
+        for round in 0..ECHO_STRIKES * 2 {
+            let text = round.to_string();
+            guard.sent(addr(8080), &text, now);
+            // A legitimate peer happening to send the same text now and then is only warned.
+            assert_ne!(
+                guard.received(&addr(8080), &text, now),
+                EchoVerdict::Violation
+            );
+            assert_eq!(
+                guard.received(&addr(8080), "its own", now),
+                EchoVerdict::Genuine
+            );
+        }
+        assert_eq!(guard.violations(), 0);
+    }
+
+    #[test]
+    fn a_forgotten_peer_starts_over() {
+        let mut guard = EchoGuard::new(8);
+        let now = Instant::now();
+        guard.sent(addr(8080), "a", now);
+        guard.forget(&addr(8080));
+        assert_eq!(guard.received(&addr(8080), "a", now), EchoVerdict::Genuine);
+    }
+}
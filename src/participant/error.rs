@@ -0,0 +1,93 @@
+//! Participant Error Types.
+//!
+//! This module defines the errors a participant can fail with while starting up or running.
+//! Keeping them structured, rather than collapsing everything into `io::Error`, lets the entry
+//! point map each failure to its own process exit code.
+
+use crate::participant::anomaly::Anomaly;
+use crate::participant::filter::FilterError;
+use crate::participant::snapshot::SnapshotError;
+use crate::participant::threads::SpawnError;
+use crate::participant::verify::VerificationFailure;
+
+use std::fmt;
+use std::io;
+
+/// Enumerates the ways a participant can fail.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParticipantError {
+    /// The listen address could not be bound.
+    Bind(io::Error),
+
+    /// A bootstrap target could not be resolved to a socket address.
+    Resolve(String),
+
+    /// None of the bootstrap candidates could be reached while `--require-bootstrap` was set.
+    BootstrapExhausted,
+
+    /// Fewer peers than `--join-check` asks for completed their handshake before its timeout.
+    JoinTimeout,
+
+    /// A local state file (such as the recent peers cache) can not be read or created.
+    StateFile(io::Error),
+
+    /// The membership snapshot given with `--import-membership` can not be used.
+    Snapshot(SnapshotError),
+
+    /// The filter file given with `--filter-file` can not be used.
+    Filter(FilterError),
+
+    /// The socket given with `--admin-socket` could not be bound.
+    AdminSocket(io::Error),
+
+    /// An internal thread could not be started, usually because of `--max-threads`.
+    Threads(SpawnError),
+
+    /// A peer caused a protocol anomaly while `--strict-protocol` was set.
+    Anomaly(Anomaly),
+
+    /// The bootstrap target did not present the identity it was expected to, and no other
+    /// candidate could be reached.
+    BootstrapUnverified(VerificationFailure),
+
+    /// The settings given to a `ParticipantBuilder` do not configure a participant; holds why.
+    Config(String),
+}
+
+impl fmt::Display for ParticipantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParticipantError::Bind(err) => write!(f, "can not bind the listen address: {}", err),
+            ParticipantError::Resolve(addr) => write!(f, "can not resolve \"{}\"", addr),
+            ParticipantError::BootstrapExhausted => {
+                write!(f, "can not connect to any bootstrap participant")
+            }
+            ParticipantError::JoinTimeout => {
+                write!(f, "not enough peers completed their handshake in time")
+            }
+            ParticipantError::StateFile(err) => write!(f, "can not use a state file: {}", err),
+            ParticipantError::Snapshot(err) => {
+                write!(f, "can not import the membership snapshot: {}", err)
+            }
+            ParticipantError::Filter(err) => write!(f, "can not use the filter file: {}", err),
+            ParticipantError::AdminSocket(err) => {
+                write!(f, "can not bind the admin socket: {}", err)
+            }
+            ParticipantError::Threads(err) => {
+                write!(f, "can not start an internal thread: {}", err)
+            }
+            ParticipantError::Anomaly(anomaly) => write!(f, "protocol anomaly: {}", anomaly),
+            ParticipantError::BootstrapUnverified(failure) => {
+                write!(
+                    f,
+                    "the bootstrap target failed its verification: {}",
+                    failure
+                )
+            }
+            ParticipantError::Config(reason) => write!(f, "invalid settings: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ParticipantError {}
@@ -0,0 +1,100 @@
+//! Internal Participant Events.
+//!
+//! This module defines the events that other threads of the application deliver to the
+//! participant's event loop through the `message-io` signal channel. The channel is the
+//! participant's internal event bus: timers, the console reader and the shutdown watcher only
+//! ever send events, and the event loop, which owns all of the participant's state, is the one
+//! place where they are handled, in order with network events and without any locking.
+
+use crate::console::Command;
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::report::Report;
+use crate::participant::request::RequestResult;
+use crate::participant::schedule::{Due, Missed, ScheduleError, ScheduleHandle};
+use crate::participant::transport::{Connection, OwnedTransportEvent};
+
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// An event delivered to the participant's event loop from inside the application.
+#[derive(Debug)]
+pub enum InternalEvent {
+    /// Something happened on a connection of a transport that delivers its events through
+    /// the event loop rather than the `message-io` network, see `TransportContext::deliver`.
+    Transport(OwnedTransportEvent),
+
+    /// A command entered in the interactive console, or sent through the admin socket. Its
+    /// output goes to `reply` when given, and to the standard output otherwise.
+    Command {
+        command: Command,
+        reply: Option<Sender<Vec<String>>>,
+    },
+
+    /// Time to broadcast a random message and look for half-open connections.
+    BroadcastTick,
+
+    /// Time for periodic housekeeping: flush coalesced updates to observers, expire idle
+    /// observers and sweep the bounded maps.
+    MaintenanceTick,
+
+    /// The batch window of a queue of the outbox elapsed: send the batches that are due.
+    FlushOutbox,
+
+    /// An ordered subscription waited long enough for a missing message: give up on it.
+    ExpireReorder,
+
+    /// The earliest scheduled publication is due: publish the ones that are.
+    ScheduleTick,
+
+    /// A `ParticipantHandle` schedules `message` at `due`; the handle of the publication, or
+    /// the reason it was refused, goes to `reply`.
+    Schedule {
+        due: Due,
+        message: OutgoingMessage,
+        missed: Missed,
+        reply: Sender<Result<ScheduleHandle, ScheduleError>>,
+    },
+
+    /// Time to save the state file, every `--state-save-interval`.
+    SaveState,
+
+    /// The random share of `--warmup` to wait before dialing the bootstrap targets elapsed.
+    WarmupAnnounce,
+
+    /// The subscriptions changed `INTEREST_DEBOUNCE` ago: announce them to the peers.
+    AnnounceInterest,
+
+    /// The timeout of `--join-check` elapsed.
+    JoinTimeout,
+
+    /// Time to send the next probe of the running `probe` command, and to expire the
+    /// unanswered ones.
+    ProbeTick,
+
+    /// A `RequestClient` asks the participant at `peer` to answer `payload` on `topic`; the
+    /// outcome goes to `reply`.
+    Request {
+        peer: SocketAddr,
+        topic: String,
+        payload: Vec<u8>,
+        timeout: Duration,
+        reply: Sender<RequestResult>,
+    },
+
+    /// A request handler finished answering the request `id` received through `endpoint`.
+    RequestHandled {
+        endpoint: Connection,
+        id: u64,
+        result: Result<Vec<u8>, String>,
+    },
+
+    /// A `ParticipantHandle` asks for the report of the run so far.
+    Report { reply: Sender<Report> },
+
+    /// A `SIGHUP` asks for the configuration to be reloaded, as the `reload` command does.
+    Reload,
+
+    /// Stop the event loop once the events queued before this one have been handled.
+    Shutdown,
+}
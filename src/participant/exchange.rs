@@ -0,0 +1,465 @@
+//! List Exchanges.
+//!
+//! A participant asks its peers for their participants lists for several reasons: right after
+//! dialing one, when the dial was a partition heal probe, on `reannounce`, and to learn the
+//! list of a peer that connected by itself, which the split-brain detection needs. Once
+//! several requests to the same peer are outstanding, a list arriving from it said nothing of
+//! which one it answered. `ListExchanges` keeps every outstanding request with its
+//! `ListPurpose` and the time it was sent, and matches the lists received to them.
+//!
+//! A peer speaking protocol version 9 is asked with a `ListRequest` carrying an id, which its
+//! `ListReply` echoes, or its `CompactListReply` from version 12 on, so the match is exact: a reply with an id that is not outstanding, such
+//! as a second reply to the same request, is dropped and counted. Older peers, and any peer
+//! whose version is not known yet, which is always the case right after dialing it, are asked
+//! with a `PushParticipantsList`; their lists carry no id and answer the oldest request
+//! outstanding to them, as a connection delivers them in order. A list without an id and
+//! without any request outstanding is unsolicited. Such lists have a use, split-brain bridging
+//! sends them, so they are counted and still merged.
+//!
+//! A request unanswered within `LIST_REQUEST_TIMEOUT` is forgotten and counted as timed out
+//! for its purpose; `stats` shows the counters with the mean latency of each purpose.
+
+use super::collections::{BoundedMap, Eviction};
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// How long a participants list request waits for its answer.
+pub const LIST_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a participants list was asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListPurpose {
+    /// A peer this participant dialed was asked right after connecting.
+    InitialSync,
+    /// A departed peer reconnected by a partition heal probe was asked.
+    HealProbe,
+    /// Every peer was asked again by `reannounce`.
+    Reannounce,
+    /// A peer that connected by itself was asked for the split-brain detection.
+    SplitCheck,
+}
+
+impl ListPurpose {
+    /// The number of purposes, i.e. the length of a per-purpose counter array.
+    pub const COUNT: usize = 4;
+
+    /// Every purpose, in counter array order.
+    pub const ALL: [ListPurpose; ListPurpose::COUNT] = [
+        ListPurpose::InitialSync,
+        ListPurpose::HealProbe,
+        ListPurpose::Reannounce,
+        ListPurpose::SplitCheck,
+    ];
+
+    /// Returns the human-readable name of this purpose.
+    pub fn name(self) -> &'static str {
+        match self {
+            ListPurpose::InitialSync => "initial-sync",
+            ListPurpose::HealProbe => "heal-probe",
+            ListPurpose::Reannounce => "reannounce",
+            ListPurpose::SplitCheck => "split-check",
+        }
+    }
+}
+
+/// What a received participants list turned out to answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMatch {
+    /// The request sent for `purpose`, `latency` before.
+    Answered {
+        purpose: ListPurpose,
+        latency: Duration,
+    },
+    /// A request that is not outstanding; the list is dropped.
+    Unknown,
+    /// No request; the list was shared unasked.
+    Unsolicited,
+}
+
+/// The counters of one purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PurposeStats {
+    /// The requests answered.
+    pub answered: u64,
+    /// The requests unanswered within `LIST_REQUEST_TIMEOUT`.
+    pub timed_out: u64,
+    /// The time the answered requests took, all together.
+    pub latency: Duration,
+}
+
+impl PurposeStats {
+    /// Returns the mean time a request took to be answered, `None` before any was.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.answered > 0).then(|| self.latency / self.answered as u32)
+    }
+}
+
+/// A request sent with an id.
+#[derive(Debug)]
+struct Outstanding<K> {
+    peer: K,
+    purpose: ListPurpose,
+    sent_at: Instant,
+}
+
+/// The participants list requests outstanding to the peers of a participant.
+pub struct ListExchanges<K> {
+    next_id: u64,
+    correlated: BoundedMap<u64, Outstanding<K>>,
+    legacy: HashMap<K, VecDeque<(ListPurpose, Instant)>>,
+    purposes: [PurposeStats; ListPurpose::COUNT],
+    unknown: u64,
+    unsolicited: u64,
+}
+
+impl<K: Hash + Eq + Clone> ListExchanges<K> {
+    /// Constructs a table keeping at most `capacity` requests sent with an id; the oldest
+    /// makes room for a new one and counts as timed out.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            next_id: 1,
+            correlated: BoundedMap::new(capacity, Eviction::Fifo, None),
+            legacy: HashMap::new(),
+            purposes: [PurposeStats::default(); ListPurpose::COUNT],
+            unknown: 0,
+            unsolicited: 0,
+        }
+    }
+
+    /// Records a request sent to `peer` at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `correlated` - Whether the peer echoes an id, in which case the request gets one.
+    ///
+    /// # Returns
+    ///
+    /// The id to send the request with, `None` for a request without one.
+    pub fn request(
+        &mut self,
+        peer: K,
+        purpose: ListPurpose,
+        correlated: bool,
+        now: Instant,
+    ) -> Option<u64> {
+        if !correlated {
+            self.legacy
+                .entry(peer)
+                .or_default()
+                .push_back((purpose, now));
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.correlated.len() >= self.correlated.capacity() {
+            // The oldest request makes room, counted here since the map would not tell its
+            // purpose.
+            let oldest = self
+                .correlated
+                .iter()
+                .min_by_key(|(_, outstanding)| outstanding.sent_at)
+                .map(|(id, _)| *id);
+            if let Some(outstanding) = oldest.and_then(|id| self.correlated.remove(&id)) {
+                self.purposes[outstanding.purpose as usize].timed_out += 1;
+            }
+        }
+        self.correlated.insert(
+            id,
+            Outstanding {
+                peer,
+                purpose,
+                sent_at: now,
+            },
+            now,
+        );
+        Some(id)
+    }
+
+    /// Matches a list received from `peer` at `now`, carrying `id` when it is a reply.
+    pub fn answered(&mut self, peer: &K, id: Option<u64>, now: Instant) -> ListMatch {
+        let found = match id {
+            // Another peer may not answer for the one asked.
+            Some(id) => match self.correlated.get_mut(&id, now) {
+                Some(outstanding) if outstanding.peer == *peer => self
+                    .correlated
+                    .remove(&id)
+                    .map(|outstanding| (outstanding.purpose, outstanding.sent_at)),
+                _ => None,
+            },
+            // The queues left empty are removed, so a queue always holds a request.
+            None => {
+                let Some(queue) = self.legacy.get_mut(peer) else {
+                    self.unsolicited += 1;
+                    return ListMatch::Unsolicited;
+                };
+                let found = queue.pop_front();
+                if queue.is_empty() {
+                    self.legacy.remove(peer);
+                }
+                found
+            }
+        };
+        let Some((purpose, sent_at)) = found else {
+            self.unknown += 1;
+            return ListMatch::Unknown;
+        };
+        let latency = now.saturating_duration_since(sent_at);
+        let stats = &mut self.purposes[purpose as usize];
+        stats.answered += 1;
+        stats.latency += latency;
+        ListMatch::Answered { purpose, latency }
+    }
+
+    /// Forgets the requests outstanding to a peer that went away, without counting them.
+    pub fn forget(&mut self, peer: &K) {
+        self.legacy.remove(peer);
+        let ids: Vec<u64> = self
+            .correlated
+            .iter()
+            .filter(|(_, outstanding)| outstanding.peer == *peer)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.correlated.remove(&id);
+        }
+    }
+
+    /// Forgets the requests unanswered within `LIST_REQUEST_TIMEOUT` at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The peers and purposes of the requests that timed out.
+    pub fn expire(&mut self, now: Instant) -> Vec<(K, ListPurpose)> {
+        let late = |sent_at: Instant| now.saturating_duration_since(sent_at) > LIST_REQUEST_TIMEOUT;
+        let mut expired = Vec::new();
+        let ids: Vec<u64> = self
+            .correlated
+            .iter()
+            .filter(|(_, outstanding)| late(outstanding.sent_at))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if let Some(outstanding) = self.correlated.remove(&id) {
+                expired.push((outstanding.peer, outstanding.purpose));
+            }
+        }
+        self.legacy.retain(|peer, queue| {
+            while let Some(&(purpose, sent_at)) = queue.front() {
+                if !late(sent_at) {
+                    break;
+                }
+                queue.pop_front();
+                expired.push((peer.clone(), purpose));
+            }
+            !queue.is_empty()
+        });
+        for (_, purpose) in &expired {
+            self.purposes[*purpose as usize].timed_out += 1;
+        }
+        expired
+    }
+
+    /// Returns the counters of `purpose`.
+    pub fn stats(&self, purpose: ListPurpose) -> PurposeStats {
+        self.purposes[purpose as usize]
+    }
+
+    /// Returns the number of replies dropped because their request was not outstanding.
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Returns the number of lists received without a request.
+    pub fn unsolicited(&self) -> u64 {
+        self.unsolicited
+    }
+
+    /// Returns the number of requests outstanding.
+    pub fn outstanding(&self) -> usize {
+        self.correlated.len() + self.legacy.values().map(VecDeque::len).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn replies_match_their_request_by_id() {
+        let mut exchanges = ListExchanges::new(8);
+        let now = Instant::now();
+        let sync = exchanges
+            .request(addr(8080), ListPurpose::InitialSync, true, now)
+            .unwrap();
+        let split = exchanges
+            .request(addr(8080), ListPurpose::SplitCheck, true, now)
+            .unwrap();
+        assert_ne!(sync, split);
+        assert_eq!(exchanges.outstanding(), 2);
+
+        // Answered out of order, each reply still finds its purpose.
+        assert_eq!(
+            exchanges.answered(&addr(8080), Some(split), now + 3 * MS),
+            ListMatch::Answered {
+                purpose: ListPurpose::SplitCheck,
+                latency: 3 * MS,
+            }
+        );
+        assert_eq!(
+            exchanges.answered(&addr(8080), Some(sync), now + 5 * MS),
+            ListMatch::Answered {
+                purpose: ListPurpose::InitialSync,
+                latency: 5 * MS,
+            }
+        );
+        assert_eq!(exchanges.outstanding(), 0);
+        let stats = exchanges.stats(ListPurpose::InitialSync);
+        assert_eq!((stats.answered, stats.mean_latency()), (1, Some(5 * MS)));
+        assert_eq!(exchanges.stats(ListPurpose::HealProbe).mean_latency(), None);
+    }
+
+    #[test]
+    fn duplicate_foreign_and_unknown_replies_are_dropped() {
+        let mut exchanges = ListExchanges::new(8);
+        let now = Instant::now();
+        let id = exchanges
+            .request(addr(8080), ListPurpose::Reannounce, true, now)
+            .unwrap();
+
+        assert_eq!(
+            exchanges.answered(&addr(8081), Some(id), now),
+            ListMatch::Unknown
+        );
+        assert!(matches!(
+            exchanges.answered(&addr(8080), Some(id), now),
+            ListMatch::Answered { .. }
+        ));
+        assert_eq!(
+            exchanges.answered(&addr(8080), Some(id), now),
+            ListMatch::Unknown
+        );
+        assert_eq!(
+            exchanges.answered(&addr(8080), Some(id + 100), now),
+            ListMatch::Unknown
+        );
+        assert_eq!((exchanges.unknown(), exchanges.unsolicited()), (3, 0));
+    }
+
+    #[test]
+    fn lists_without_an_id_answer_the_oldest_request() {
+        let mut exchanges = ListExchanges::new(8);
+        let now = Instant::now();
+        assert_eq!(
+            exchanges.request(addr(8080), ListPurpose::InitialSync, false, now),
+            None
+        );
+        exchanges.request(addr(8080), ListPurpose::HealProbe, false, now + MS);
+
+        let purposes: Vec<ListMatch> = (0..3)
+            .map(|_| exchanges.answered(&addr(8080), None, now + 2 * MS))
+            .collect();
+        assert_eq!(
+            purposes,
+            [
+                ListMatch::Answered {
+                    purpose: ListPurpose::InitialSync,
+                    latency: 2 * MS,
+                },
+                ListMatch::Answered {
+                    purpose: ListPurpose::HealProbe,
+                    latency: MS,
+                },
+                ListMatch::Unsolicited,
+            ]
+        );
+        assert_eq!((exchanges.unknown(), exchanges.unsolicited()), (0, 1));
+    }
+
+    #[test]
+    fn unanswered_requests_time_out_per_purpose() {
+        let mut exchanges = ListExchanges::new(8);
+        let now = Instant::now();
+        exchanges.request(addr(8080), ListPurpose::InitialSync, true, now);
+        exchanges.request(addr(8081), ListPurpose::SplitCheck, false, now);
+        exchanges.request(addr(8081), ListPurpose::Reannounce, false, now + MS);
+
+        assert!(exchanges.expire(now + LIST_REQUEST_TIMEOUT).is_empty());
+        let mut expired = exchanges.expire(now + LIST_REQUEST_TIMEOUT + MS / 2);
+        expired.sort_by_key(|(peer, _)| *peer);
+        assert_eq!(
+            expired,
+            [
+                (addr(8080), ListPurpose::InitialSync),
+                (addr(8081), ListPurpose::SplitCheck),
+            ]
+        );
+        assert_eq!(exchanges.outstanding(), 1);
+        assert_eq!(exchanges.stats(ListPurpose::InitialSync).timed_out, 1);
+        assert_eq!(exchanges.stats(ListPurpose::SplitCheck).timed_out, 1);
+        assert_eq!(exchanges.stats(ListPurpose::Reannounce).timed_out, 0);
+    }
+
+    #[test]
+    fn a_full_table_drops_the_oldest_request() {
+        let mut exchanges = ListExchanges::new(2);
+        let now = Instant::now();
+        let oldest = exchanges
+            .request(addr(8080), ListPurpose::HealProbe, true, now)
+            .unwrap();
+        exchanges.request(addr(8080), ListPurpose::InitialSync, true, now + MS);
+        exchanges.request(addr(8080), ListPurpose::InitialSync, true, now + 2 * MS);
+
+        assert_eq!(exchanges.outstanding(), 2);
+        assert_eq!(exchanges.stats(ListPurpose::HealProbe).timed_out, 1);
+        assert_eq!(
+            exchanges.answered(&addr(8080), Some(oldest), now + 3 * MS),
+            ListMatch::Unknown
+        );
+    }
+
+    #[test]
+    fn a_departed_peer_takes_its_requests_along_uncounted() {
+        let mut exchanges = ListExchanges::new(8);
+        let now = Instant::now();
+        exchanges.request(addr(8080), ListPurpose::InitialSync, true, now);
+        exchanges.request(addr(8080), ListPurpose::SplitCheck, false, now);
+        exchanges.request(addr(8081), ListPurpose::SplitCheck, true, now);
+
+        exchanges.forget(&addr(8080));
+        assert_eq!(exchanges.outstanding(), 1);
+        let later = now + LIST_REQUEST_TIMEOUT * 2;
+        assert_eq!(
+            exchanges.expire(later),
+            [(addr(8081), ListPurpose::SplitCheck)]
+        );
+        for purpose in ListPurpose::ALL {
+            let expected = u64::from(purpose == ListPurpose::SplitCheck);
+            assert_eq!(
+                exchanges.stats(purpose).timed_out,
+                expected,
+                "{:?}",
+                purpose
+            );
+        }
+    }
+
+    #[test]
+    fn purposes_are_named_in_counter_order() {
+        let names: Vec<&str> = ListPurpose::ALL
+            .iter()
+            .map(|purpose| purpose.name())
+            .collect();
+        assert_eq!(
+            names,
+            ["initial-sync", "heal-probe", "reannounce", "split-check"]
+        );
+        for (index, purpose) in ListPurpose::ALL.into_iter().enumerate() {
+            assert_eq!(purpose as usize, index);
+        }
+    }
+}
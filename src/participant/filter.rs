@@ -0,0 +1,878 @@
+//! Peer Address Filtering.
+//!
+//! A participant refuses the peers its filter blocks: their inbound connections are closed as
+//! soon as they announce a public address, and they are never dialed nor introduced. The filter
+//! combines the `--block` addresses with the rules of an optional filter file, which is polled
+//! for changes and can be replaced while the participant runs.
+//!
+//! A filter file holds one rule per line, an action followed by a target. The target is a
+//! socket address, which matches that address only, an IP address, which matches every port of
+//! it, or a network in CIDR notation. Empty lines and lines starting with `#` are skipped:
+//!
+//! ```plaintext
+//! # Refuse a whole network, except for one host.
+//! block 10.0.0.0/8
+//! allow 10.1.2.3
+//! block 192.168.1.20:8080
+//! ```
+//!
+//! An address is blocked when a `block` rule matches it and no `allow` rule does, so an
+//! allowlist is written as `block 0.0.0.0/0` and `block ::/0` followed by `allow` rules. The
+//! `--block` addresses are blocked whatever the file says.
+//!
+//! Apart from the filter, `DialHygiene` keeps the addresses learned from participants lists and
+//! introductions from pointing the dialer at services no peer runs: a learned address is only
+//! dialed when its port is not below `FIRST_UNPRIVILEGED_PORT` and its IP is not in a
+//! reserved or special-purpose range, see `is_special_ip`. Loopback addresses are only dialed
+//! by participants listening on loopback themselves. `--allow-privileged-ports` and
+//! `--allow-special-ranges` lift the checks for lab setups; a `--connect` target is never
+//! checked, since the operator gave it.
+
+use crate::participant::collections::{BoundedMap, Eviction};
+use crate::participant::utils::{canonical, CanonicalAddr};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// Enumerates the reasons a filter file can not be used.
+#[derive(Debug)]
+pub enum FilterError {
+    /// The file can not be read.
+    Io(io::Error),
+    /// A line of the file is not a rule; holds the line number, starting at 1.
+    Malformed(usize),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Io(err) => write!(f, "{}", err),
+            FilterError::Malformed(line) => write!(f, "invalid rule on line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// What a matching rule does to an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Allow,
+    Block,
+}
+
+/// The addresses a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterTarget {
+    /// A single socket address.
+    Socket(SocketAddr),
+    /// Every address of a network, whatever the port.
+    Network { ip: IpAddr, prefix: u8 },
+}
+
+impl FilterTarget {
+    /// Parses a socket address, an IP address or a network in CIDR notation.
+    ///
+    /// Targets are kept in canonical form: an IPv4-mapped address or network becomes the IPv4
+    /// one, which is how the addresses it is matched against are compared.
+    fn parse(target: &str) -> Option<Self> {
+        if let Ok(addr) = target.parse() {
+            return Some(FilterTarget::Socket(canonical(addr)));
+        }
+
+        let (ip, prefix) = match target.split_once('/') {
+            Some((ip, prefix)) => (ip.parse::<IpAddr>().ok()?, prefix.parse().ok()?),
+            None => {
+                let ip = target.parse::<IpAddr>().ok()?;
+                (ip, address_bits(ip))
+            }
+        };
+        if prefix > address_bits(ip) {
+            return None;
+        }
+        let mapped = match ip {
+            IpAddr::V6(v6) if prefix >= 96 => v6.to_ipv4_mapped(),
+            _ => None,
+        };
+        match mapped {
+            Some(v4) => Some(FilterTarget::Network {
+                ip: v4.into(),
+                prefix: prefix - 96,
+            }),
+            None => Some(FilterTarget::Network { ip, prefix }),
+        }
+    }
+
+    /// Determines whether the target covers `addr`, compared by its canonical form.
+    fn matches(&self, addr: SocketAddr) -> bool {
+        let addr = canonical(addr);
+        match *self {
+            FilterTarget::Socket(target) => target == addr,
+            FilterTarget::Network { ip, prefix } => match (ip, addr.ip()) {
+                (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                    same_prefix(u32::from(net).into(), u32::from(ip).into(), prefix, 32)
+                }
+                (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                    same_prefix(u128::from(net), u128::from(ip), prefix, 128)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Returns the number of bits of an IP address.
+fn address_bits(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Determines whether two addresses of `bits` bits share their first `prefix` bits.
+fn same_prefix(net: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = u32::from(bits - prefix);
+    (net ^ ip) >> shift == 0
+}
+
+/// One line of a filter file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterRule {
+    action: FilterAction,
+    target: FilterTarget,
+}
+
+/// Parses the content of a filter file.
+///
+/// # Parameters
+///
+/// * `content` - The content of the file.
+///
+/// # Returns
+///
+/// Every rule of the file, or `FilterError::Malformed` with the first line that is not one.
+pub fn parse_rules(content: &str) -> Result<Vec<FilterRule>, FilterError> {
+    let mut rules = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let action = match words.next() {
+            Some("allow") => Some(FilterAction::Allow),
+            Some("block") => Some(FilterAction::Block),
+            _ => None,
+        };
+        let target = words.next().and_then(FilterTarget::parse);
+        match (action, target, words.next()) {
+            (Some(action), Some(target), None) => rules.push(FilterRule { action, target }),
+            _ => return Err(FilterError::Malformed(index + 1)),
+        }
+    }
+    Ok(rules)
+}
+
+/// The addresses a participant refuses.
+#[derive(Debug)]
+pub struct FilterSet {
+    blocklist: HashSet<CanonicalAddr>,
+    rules: Vec<FilterRule>,
+}
+
+impl FilterSet {
+    /// Creates a filter blocking the `--block` addresses, without file rules.
+    ///
+    /// # Parameters
+    ///
+    /// * `blocklist` - The addresses blocked whatever the rules say.
+    pub fn new(blocklist: Vec<SocketAddr>) -> Self {
+        Self {
+            blocklist: blocklist.into_iter().map(CanonicalAddr::from).collect(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Replaces the file rules, keeping the `--block` addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `rules` - The new rules, as returned by `parse_rules`.
+    pub fn set_rules(&mut self, rules: Vec<FilterRule>) {
+        self.rules = rules;
+    }
+
+    /// Returns the number of file rules.
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Determines whether `addr` is refused.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of a peer.
+    pub fn is_blocked(&self, addr: SocketAddr) -> bool {
+        if self.blocklist.contains(&addr.into()) {
+            return true;
+        }
+
+        let matching = |action| {
+            self.rules
+                .iter()
+                .any(|rule| rule.action == action && rule.target.matches(addr))
+        };
+        matching(FilterAction::Block) && !matching(FilterAction::Allow)
+    }
+}
+
+/// The lowest port a learned address may point at without `--allow-privileged-ports`.
+pub const FIRST_UNPRIVILEGED_PORT: u16 = 1024;
+
+/// Why a learned address is not dialed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HygieneViolation {
+    /// The port is below `FIRST_UNPRIVILEGED_PORT`.
+    PrivilegedPort,
+    /// The IP is in a reserved or special-purpose range, or a loopback IP of a participant
+    /// that does not listen on loopback.
+    SpecialRange,
+}
+
+/// Determines whether `port` is below `FIRST_UNPRIVILEGED_PORT`, port 0 included.
+pub fn is_privileged_port(port: u16) -> bool {
+    port < FIRST_UNPRIVILEGED_PORT
+}
+
+/// Determines whether `ip` is in a range no peer is dialed at, loopback aside.
+///
+/// For IPv4: `0.0.0.0/8`, the link-local `169.254.0.0/16`, the documentation networks
+/// `192.0.2.0/24`, `198.51.100.0/24` and `203.0.113.0/24`, the multicast `224.0.0.0/4` and the
+/// reserved `240.0.0.0/4`, which holds the broadcast address. For IPv6: the unspecified
+/// address, the link-local `fe80::/10`, the documentation `2001:db8::/32` and the multicast
+/// `ff00::/8`. An IPv4-mapped IPv6 address is checked as its IPv4 address.
+pub fn is_special_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_special_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_special_v4(ip),
+            None => is_special_v6(ip),
+        },
+    }
+}
+
+/// Determines whether `ip` is in one of the IPv4 ranges of `is_special_ip`.
+fn is_special_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    a == 0
+        || (a == 169 && b == 254)
+        || (a == 192 && b == 0 && c == 2)
+        || (a == 198 && b == 51 && c == 100)
+        || (a == 203 && b == 0 && c == 113)
+        || a >= 224
+}
+
+/// Determines whether `ip` is in one of the IPv6 ranges of `is_special_ip`.
+fn is_special_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    ip.is_unspecified()
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        || (segments[0] & 0xff00) == 0xff00
+}
+
+/// Determines whether `ip` is a loopback address, IPv4-mapped ones included.
+pub fn is_loopback_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        IpAddr::V6(ip) => ip
+            .to_ipv4_mapped()
+            .map_or(ip.is_loopback(), |ip| ip.is_loopback()),
+    }
+}
+
+/// The checks an address learned from a peer passes before it is dialed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialHygiene {
+    /// Whether learned addresses may use privileged ports.
+    pub allow_privileged_ports: bool,
+    /// Whether learned addresses may be in special ranges, loopback included.
+    pub allow_special_ranges: bool,
+    /// Whether learned loopback addresses are dialed, as by a participant on loopback.
+    pub allow_loopback: bool,
+}
+
+impl DialHygiene {
+    /// Checks a learned address.
+    ///
+    /// # Returns
+    ///
+    /// The first check `addr` fails, the port being checked before the IP.
+    pub fn check(&self, addr: SocketAddr) -> Result<(), HygieneViolation> {
+        if !self.allow_privileged_ports && is_privileged_port(addr.port()) {
+            return Err(HygieneViolation::PrivilegedPort);
+        }
+        let special =
+            is_special_ip(addr.ip()) || (!self.allow_loopback && is_loopback_ip(addr.ip()));
+        if !self.allow_special_ranges && special {
+            return Err(HygieneViolation::SpecialRange);
+        }
+        Ok(())
+    }
+}
+
+/// The number of peers with the most strikes named by `HygieneLog::worst`.
+pub const WORST_REPORTERS: usize = 3;
+
+/// Counts the learned addresses refused by `DialHygiene`, and the strikes of the peers that
+/// listed them: one per participants list or introduction holding any.
+pub struct HygieneLog {
+    strikes: BoundedMap<CanonicalAddr, u64>,
+    privileged: u64,
+    special: u64,
+    unreported: u64,
+}
+
+impl HygieneLog {
+    /// Creates an empty log remembering the strikes of up to `capacity` peers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            strikes: BoundedMap::new(capacity, Eviction::Lru, None),
+            privileged: 0,
+            special: 0,
+            unreported: 0,
+        }
+    }
+
+    /// Records the addresses refused from one list or introduction of `reporter`.
+    ///
+    /// # Parameters
+    ///
+    /// * `reporter` - The public address of the peer that sent them.
+    /// * `violations` - The check each refused address failed; nothing is recorded when empty.
+    /// * `now` - The current time.
+    pub fn record(&mut self, reporter: SocketAddr, violations: &[HygieneViolation], now: Instant) {
+        if violations.is_empty() {
+            return;
+        }
+        for violation in violations {
+            match violation {
+                HygieneViolation::PrivilegedPort => self.privileged += 1,
+                HygieneViolation::SpecialRange => self.special += 1,
+            }
+        }
+        self.unreported += violations.len() as u64;
+
+        let reporter = CanonicalAddr::from(reporter);
+        let strikes = self
+            .strikes
+            .get_mut(&reporter, now)
+            .map_or(0, |strikes| *strikes);
+        self.strikes.insert(reporter, strikes + 1, now);
+    }
+
+    /// Returns the number of addresses refused since the previous call.
+    pub fn take_unreported(&mut self) -> u64 {
+        std::mem::take(&mut self.unreported)
+    }
+
+    /// Returns the number of addresses refused for their port and for their IP.
+    pub fn refused(&self) -> (u64, u64) {
+        (self.privileged, self.special)
+    }
+
+    /// Returns the `WORST_REPORTERS` peers with the most strikes, most first.
+    pub fn worst(&self) -> Vec<(SocketAddr, u64)> {
+        let mut strikes: Vec<(SocketAddr, u64)> = self
+            .strikes
+            .iter()
+            .map(|(reporter, strikes)| (reporter.original(), *strikes))
+            .collect();
+        strikes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        strikes.truncate(WORST_REPORTERS);
+        strikes
+    }
+}
+
+/// A filter file, re-read whenever its modification time changes.
+#[derive(Debug)]
+pub struct FilterFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FilterFile {
+    /// Reads the filter file at `path`.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the filter file.
+    ///
+    /// # Returns
+    ///
+    /// The file, to be polled for changes, and its rules.
+    pub fn load(path: &Path) -> Result<(Self, Vec<FilterRule>), FilterError> {
+        let mut file = Self {
+            path: path.to_owned(),
+            modified: None,
+        };
+        let rules = file.read()?;
+        Ok((file, rules))
+    }
+
+    /// Returns the path of the filter file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Re-reads the file if it was modified since the last read.
+    ///
+    /// # Returns
+    ///
+    /// `None` when the modification time did not change, otherwise the new rules or the error
+    /// that prevented reading them. A file that can not be parsed is read again only once it
+    /// changes, while a file that can not be read at all is tried again on every poll.
+    pub fn poll(&mut self) -> Option<Result<Vec<FilterRule>, FilterError>> {
+        let modified = fs::metadata(&self.path).and_then(|meta| meta.modified());
+        if let (Ok(modified), Some(previous)) = (&modified, self.modified) {
+            if *modified == previous {
+                return None;
+            }
+        }
+        Some(self.read())
+    }
+
+    /// Reads and parses the file, recording its modification time once it could be read.
+    fn read(&mut self) -> Result<Vec<FilterRule>, FilterError> {
+        self.modified = None;
+        let modified = fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .map_err(FilterError::Io)?;
+        let content = fs::read_to_string(&self.path).map_err(FilterError::Io)?;
+        self.modified = Some(modified);
+        parse_rules(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn addr(text: &str) -> SocketAddr {
+        text.parse().unwrap()
+    }
+
+    fn filter(rules: &str) -> FilterSet {
+        let mut filter = FilterSet::new(Vec::new());
+        filter.set_rules(parse_rules(rules).expect("the rules parse"));
+        filter
+    }
+
+    fn malformed_line(rules: &str) -> Option<usize> {
+        match parse_rules(rules) {
+            Err(FilterError::Malformed(line)) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_rules_skipping_comments_and_empty_lines() {
+        let rules = parse_rules(
+            "# Refuse a whole network, except for one host.\n\n  block 10.0.0.0/8\nallow 10.1.2.3\n\tblock 192.168.1.20:8080  \n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            [
+                FilterRule {
+                    action: FilterAction::Block,
+                    target: FilterTarget::Network {
+                        ip: "10.0.0.0".parse().unwrap(),
+                        prefix: 8,
+                    },
+                },
+                FilterRule {
+                    action: FilterAction::Allow,
+                    target: FilterTarget::Network {
+                        ip: "10.1.2.3".parse().unwrap(),
+                        prefix: 32,
+                    },
+                },
+                FilterRule {
+                    action: FilterAction::Block,
+                    target: FilterTarget::Socket(addr("192.168.1.20:8080")),
+                },
+            ]
+        );
+        assert!(parse_rules("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn names_the_first_line_that_is_not_a_rule() {
+        assert_eq!(malformed_line("drop 10.0.0.1"), Some(1));
+        assert_eq!(malformed_line("# comment\nblock"), Some(2));
+        assert_eq!(
+            malformed_line("block 10.0.0.1\nallow 10.0.0.2 10.0.0.3"),
+            Some(2)
+        );
+        assert_eq!(malformed_line("block 10.0.0.0/33"), Some(1));
+        assert_eq!(malformed_line("block ::/129"), Some(1));
+        assert_eq!(malformed_line("block 10.0.0.0/x"), Some(1));
+        assert_eq!(malformed_line("block example.com"), Some(1));
+        assert_eq!(malformed_line("Block 10.0.0.1"), Some(1));
+    }
+
+    #[test]
+    fn matches_a_socket_address_on_its_port_only() {
+        let filter = filter("block 10.0.0.1:8080");
+        assert!(filter.is_blocked(addr("10.0.0.1:8080")));
+        assert!(!filter.is_blocked(addr("10.0.0.1:8081")));
+        assert!(!filter.is_blocked(addr("10.0.0.2:8080")));
+    }
+
+    #[test]
+    fn matches_an_ip_address_on_every_port() {
+        let filter = filter("block 10.0.0.1\nblock fd00::1");
+        assert!(filter.is_blocked(addr("10.0.0.1:1")));
+        assert!(filter.is_blocked(addr("10.0.0.1:65535")));
+        assert!(filter.is_blocked(addr("[fd00::1]:8080")));
+        assert!(!filter.is_blocked(addr("10.0.0.2:8080")));
+        assert!(!filter.is_blocked(addr("[fd00::2]:8080")));
+    }
+
+    #[test]
+    fn matches_a_network_up_to_its_edges() {
+        let filter = filter("block 10.0.0.0/8\nblock 192.168.1.128/25\nblock fd00::/16");
+        assert!(filter.is_blocked(addr("10.0.0.0:8080")));
+        assert!(filter.is_blocked(addr("10.255.255.255:8080")));
+        assert!(!filter.is_blocked(addr("9.255.255.255:8080")));
+        assert!(!filter.is_blocked(addr("11.0.0.0:8080")));
+        assert!(filter.is_blocked(addr("192.168.1.128:8080")));
+        assert!(filter.is_blocked(addr("192.168.1.255:8080")));
+        assert!(!filter.is_blocked(addr("192.168.1.127:8080")));
+        assert!(filter.is_blocked(addr("[fd00:ffff::1]:8080")));
+        assert!(!filter.is_blocked(addr("[fd01::1]:8080")));
+    }
+
+    #[test]
+    fn an_empty_prefix_matches_its_own_family_only() {
+        let filter = filter("block 0.0.0.0/0");
+        assert!(filter.is_blocked(addr("1.2.3.4:8080")));
+        assert!(filter.is_blocked(addr("255.255.255.255:8080")));
+        assert!(!filter.is_blocked(addr("[fd00::1]:8080")));
+    }
+
+    #[test]
+    fn matches_mapped_addresses_and_networks_as_ipv4() {
+        let filter = filter("block ::ffff:10.0.0.0/104\nblock [::ffff:192.168.1.1]:8080");
+        assert_eq!(
+            filter.rules[0].target,
+            FilterTarget::Network {
+                ip: "10.0.0.0".parse().unwrap(),
+                prefix: 8,
+            }
+        );
+        assert!(filter.is_blocked(addr("10.1.2.3:8080")));
+        assert!(filter.is_blocked(addr("[::ffff:10.1.2.3]:8080")));
+        assert!(filter.is_blocked(addr("192.168.1.1:8080")));
+
+        let filter = self::filter("block 10.0.0.0/8");
+        assert!(filter.is_blocked(addr("[::ffff:10.1.2.3]:8080")));
+    }
+
+    #[test]
+    fn an_allow_rule_wins_over_block_rules_in_any_order() {
+        let filter = filter("allow 10.1.2.3\nblock 0.0.0.0/0\nblock ::/0\nallow fd00::/16");
+        assert!(filter.is_blocked(addr("10.1.2.4:8080")));
+        assert!(!filter.is_blocked(addr("10.1.2.3:8080")));
+        assert!(filter.is_blocked(addr("[fe80::1]:8080")));
+        assert!(!filter.is_blocked(addr("[fd00::1]:8080")));
+        // An allow rule alone blocks nothing else.
+        assert!(!self::filter("allow 10.1.2.3").is_blocked(addr("10.1.2.4:8080")));
+    }
+
+    #[test]
+    fn the_block_option_wins_over_the_rules() {
+        let mut filter = FilterSet::new(vec![addr("10.1.2.3:8080")]);
+        filter.set_rules(parse_rules("allow 10.1.2.3").unwrap());
+        assert!(filter.is_blocked(addr("10.1.2.3:8080")));
+        assert!(filter.is_blocked(addr("[::ffff:10.1.2.3]:8080")));
+        assert!(!filter.is_blocked(addr("10.1.2.3:8081")));
+
+        filter.set_rules(Vec::new());
+        assert_eq!(filter.rule_count(), 0);
+        assert!(filter.is_blocked(addr("10.1.2.3:8080")));
+    }
+
+    #[test]
+    fn reads_a_filter_file_again_once_it_changes() {
+        let path = std::env::temp_dir().join(format!(
+            "gossip-p2p-filter-file-{}.rules",
+            std::process::id()
+        ));
+        let write = |content: &str, age: u64| {
+            fs::write(&path, content).unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .unwrap();
+        };
+
+        write("block 10.0.0.1", 60);
+        let (mut file, rules) = FilterFile::load(&path).unwrap();
+        assert_eq!(file.path(), path);
+        assert_eq!(rules.len(), 1);
+        assert!(file.poll().is_none());
+
+        write("block 10.0.0.1\nblock 10.0.0.2", 30);
+        assert_eq!(file.poll().unwrap().unwrap().len(), 2);
+        assert!(file.poll().is_none());
+
+        // A malformed file is read again only once it changes.
+        write("block 10.0.0.1\nnonsense", 20);
+        assert!(matches!(file.poll(), Some(Err(FilterError::Malformed(2)))));
+        assert!(file.poll().is_none());
+
+        // A missing file is tried again on every poll.
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(file.poll(), Some(Err(FilterError::Io(_)))));
+        assert!(matches!(file.poll(), Some(Err(FilterError::Io(_)))));
+        write("allow 10.0.0.1", 10);
+        assert_eq!(file.poll().unwrap().unwrap().len(), 1);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(FilterFile::load(&path), Err(FilterError::Io(_))));
+    }
+
+    fn v4(text: &str) -> Ipv4Addr {
+        text.parse().unwrap()
+    }
+
+    fn v6(text: &str) -> Ipv6Addr {
+        text.parse().unwrap()
+    }
+
+    const STRICT: DialHygiene = DialHygiene {
+        allow_privileged_ports: false,
+        allow_special_ranges: false,
+        allow_loopback: false,
+    };
+
+    #[test]
+    fn ports_below_1024_are_privileged() {
+        assert!(is_privileged_port(0));
+        assert!(is_privileged_port(1));
+        assert!(is_privileged_port(1023));
+        assert!(!is_privileged_port(1024));
+        assert!(!is_privileged_port(65535));
+    }
+
+    #[test]
+    fn special_v4_ranges_end_at_their_edges() {
+        for (first, last, before, after) in [
+            ("0.0.0.0", "0.255.255.255", None, Some("1.0.0.0")),
+            (
+                "169.254.0.0",
+                "169.254.255.255",
+                Some("169.253.255.255"),
+                Some("169.255.0.0"),
+            ),
+            (
+                "192.0.2.0",
+                "192.0.2.255",
+                Some("192.0.1.255"),
+                Some("192.0.3.0"),
+            ),
+            (
+                "198.51.100.0",
+                "198.51.100.255",
+                Some("198.51.99.255"),
+                Some("198.51.101.0"),
+            ),
+            (
+                "203.0.113.0",
+                "203.0.113.255",
+                Some("203.0.112.255"),
+                Some("203.0.114.0"),
+            ),
+            (
+                "224.0.0.0",
+                "239.255.255.255",
+                Some("223.255.255.255"),
+                None,
+            ),
+            ("240.0.0.0", "255.255.255.255", None, None),
+        ] {
+            assert!(is_special_v4(v4(first)), "{}", first);
+            assert!(is_special_v4(v4(last)), "{}", last);
+            for outside in [before, after].into_iter().flatten() {
+                assert!(!is_special_v4(v4(outside)), "{}", outside);
+            }
+        }
+        for ordinary in ["10.0.0.1", "127.0.0.1", "192.168.1.1", "8.8.8.8"] {
+            assert!(!is_special_v4(v4(ordinary)), "{}", ordinary);
+        }
+    }
+
+    #[test]
+    fn special_v6_ranges_end_at_their_edges() {
+        for special in [
+            "::",
+            "fe80::",
+            "febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+            "2001:db8::",
+            "2001:db8:ffff:ffff:ffff:ffff:ffff:ffff",
+            "ff00::",
+            "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+        ] {
+            assert!(is_special_v6(v6(special)), "{}", special);
+        }
+        for ordinary in [
+            "::1",
+            "::2",
+            "fe7f:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+            "fec0::",
+            "2001:db7:ffff:ffff:ffff:ffff:ffff:ffff",
+            "2001:db9::",
+            "feff:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+            "fd00::1",
+            "2a00::1",
+        ] {
+            assert!(!is_special_v6(v6(ordinary)), "{}", ordinary);
+        }
+    }
+
+    #[test]
+    fn checks_a_mapped_address_as_its_ipv4_address() {
+        assert!(is_special_ip(v6("::ffff:169.254.1.1").into()));
+        assert!(is_special_ip(v6("::ffff:0.0.0.0").into()));
+        assert!(!is_special_ip(v6("::ffff:10.0.0.1").into()));
+        assert!(is_loopback_ip(v6("::ffff:127.0.0.2").into()));
+        assert!(is_loopback_ip(v6("::1").into()));
+        assert!(is_loopback_ip(v4("127.255.255.255").into()));
+        assert!(!is_loopback_ip(v6("::ffff:10.0.0.1").into()));
+    }
+
+    #[test]
+    fn checks_the_port_before_the_ip() {
+        assert_eq!(STRICT.check(addr("10.0.0.1:1024")), Ok(()));
+        assert_eq!(STRICT.check(addr("[fd00::1]:8080")), Ok(()));
+        assert_eq!(
+            STRICT.check(addr("10.0.0.1:1023")),
+            Err(HygieneViolation::PrivilegedPort)
+        );
+        assert_eq!(
+            STRICT.check(addr("224.0.0.1:80")),
+            Err(HygieneViolation::PrivilegedPort)
+        );
+        assert_eq!(
+            STRICT.check(addr("224.0.0.1:8080")),
+            Err(HygieneViolation::SpecialRange)
+        );
+        assert_eq!(
+            STRICT.check(addr("[::ffff:192.0.2.1]:8080")),
+            Err(HygieneViolation::SpecialRange)
+        );
+    }
+
+    #[test]
+    fn the_options_lift_their_own_check_only() {
+        let ports = DialHygiene {
+            allow_privileged_ports: true,
+            ..STRICT
+        };
+        assert_eq!(ports.check(addr("10.0.0.1:0")), Ok(()));
+        assert_eq!(
+            ports.check(addr("169.254.0.1:80")),
+            Err(HygieneViolation::SpecialRange)
+        );
+
+        let ranges = DialHygiene {
+            allow_special_ranges: true,
+            ..STRICT
+        };
+        assert_eq!(ranges.check(addr("169.254.0.1:8080")), Ok(()));
+        assert_eq!(ranges.check(addr("127.0.0.1:8080")), Ok(()));
+        assert_eq!(
+            ranges.check(addr("169.254.0.1:80")),
+            Err(HygieneViolation::PrivilegedPort)
+        );
+    }
+
+    #[test]
+    fn loopback_is_dialed_by_participants_on_loopback_only() {
+        assert_eq!(
+            STRICT.check(addr("127.0.0.1:8080")),
+            Err(HygieneViolation::SpecialRange)
+        );
+        assert_eq!(
+            STRICT.check(addr("[::1]:8080")),
+            Err(HygieneViolation::SpecialRange)
+        );
+        let loopback = DialHygiene {
+            allow_loopback: true,
+            ..STRICT
+        };
+        assert_eq!(loopback.check(addr("127.0.0.1:8080")), Ok(()));
+        assert_eq!(loopback.check(addr("[::ffff:127.0.0.1]:8080")), Ok(()));
+        assert_eq!(
+            loopback.check(addr("0.0.0.0:8080")),
+            Err(HygieneViolation::SpecialRange)
+        );
+    }
+
+    #[test]
+    fn counts_the_strikes_of_the_peers_listing_refused_addresses() {
+        let now = Instant::now();
+        let mut log = HygieneLog::new(16);
+        let privileged = HygieneViolation::PrivilegedPort;
+        let special = HygieneViolation::SpecialRange;
+        log.record(addr("10.0.0.1:8000"), &[privileged, special, special], now);
+        log.record(addr("10.0.0.2:8000"), &[special], now);
+        log.record(addr("[::ffff:10.0.0.2]:8000"), &[special], now);
+        log.record(addr("10.0.0.3:8000"), &[], now);
+
+        assert_eq!(log.refused(), (1, 4));
+        assert_eq!(log.take_unreported(), 5);
+        assert_eq!(log.take_unreported(), 0);
+        assert_eq!(
+            log.worst(),
+            [(addr("10.0.0.2:8000"), 2), (addr("10.0.0.1:8000"), 1)]
+        );
+    }
+
+    #[test]
+    fn names_the_worst_reporters_only() {
+        let now = Instant::now();
+        let mut log = HygieneLog::new(16);
+        for (port, strikes) in [(8001, 1), (8002, 4), (8003, 2), (8004, 2)] {
+            for _ in 0..strikes {
+                log.record(
+                    addr(&format!("10.0.0.1:{}", port)),
+                    &[HygieneViolation::SpecialRange],
+                    now,
+                );
+            }
+        }
+        assert_eq!(
+            log.worst(),
+            [
+                (addr("10.0.0.1:8002"), 4),
+                (addr("10.0.0.1:8003"), 2),
+                (addr("10.0.0.1:8004"), 2),
+            ]
+        );
+    }
+}
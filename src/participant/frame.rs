@@ -0,0 +1,247 @@
+//! Frame Size Estimation.
+//!
+//! An application building its payloads dynamically may want to know what a message costs
+//! before publishing it. `estimate` encodes an `OutgoingMessage` with `Message::encode`, the
+//! function every send goes through, and adds the length prefix FramedTcp writes before each
+//! frame, so the estimate is the exact number of bytes the message takes on the wire and can
+//! not drift from the send path.
+//!
+//! FramedTcp puts no limit on the size of a frame, so no message is ever too large to be sent.
+//! The limit that matters is the batch budget, `MAX_BATCH_BYTES`: a message that leaves no room
+//! in a batch frame is still sent, but always alone in a frame of its own.
+
+use crate::participant::batch::BATCH_HEADER_BYTES;
+use crate::participant::message::Message;
+use crate::participant::topic::{validate_topic, TopicError};
+
+use std::fmt;
+
+/// A message an application could publish, built for `estimate`.
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    message: Message,
+}
+
+impl OutgoingMessage {
+    /// Builds a gossip text, as broadcast every period.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            message: Message::Text(text.into()),
+        }
+    }
+
+    /// Builds a publication of `payload` on `topic`; the topic name is checked by `estimate`.
+    ///
+    /// The publication is numbered like the ones sent to current peers, whose sequence number
+    /// takes the same room whatever its value.
+    pub fn topic(topic: impl Into<String>, payload: Vec<u8>) -> Self {
+        Self {
+            message: Message::SequencedTopic {
+                topic: topic.into(),
+                seq: 0,
+                payload,
+            },
+        }
+    }
+
+    /// Returns the message as it is encoded.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    /// Returns the size of the frame of the message on the wire, length prefix included.
+    pub fn frame_len(&self) -> usize {
+        let size = self.message.encode().len();
+        length_prefix(size) + size
+    }
+}
+
+/// Enumerates the reasons a message can not be estimated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EstimateError {
+    /// The topic name would be refused by `publish`.
+    Topic(TopicError),
+}
+
+impl fmt::Display for EstimateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EstimateError::Topic(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for EstimateError {}
+
+/// The size limits a participant sends under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest encoded size of a batch frame.
+    pub batch_budget: usize,
+    /// The longest accepted topic name, in bytes.
+    pub max_topic_len: usize,
+}
+
+/// What a message costs once encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameEstimate {
+    /// The size of the encoded message, as returned by `Message::encode`.
+    pub message: usize,
+    /// The size of the frame on the wire, length prefix included.
+    pub frame: usize,
+    /// Whether the message fits in a batch frame along with the batch header.
+    pub fits_batch: bool,
+    /// The bytes left in a batch frame holding only this message; zero when it does not fit.
+    pub headroom: usize,
+}
+
+/// Estimates the frame `message` would be sent in.
+///
+/// # Parameters
+///
+/// * `message` - The message to estimate.
+/// * `limits` - The limits of the sending participant.
+///
+/// # Returns
+///
+/// The estimate, or the `EstimateError` explaining why the message could not be published.
+pub fn estimate(
+    message: &OutgoingMessage,
+    limits: &Limits,
+) -> Result<FrameEstimate, EstimateError> {
+    if let Message::SequencedTopic { topic, .. } = &message.message {
+        validate_topic(topic).map_err(EstimateError::Topic)?;
+    }
+
+    let size = message.message.encode().len();
+    let room = limits.batch_budget.checked_sub(BATCH_HEADER_BYTES + size);
+    Ok(FrameEstimate {
+        message: size,
+        frame: length_prefix(size) + size,
+        fits_batch: room.is_some(),
+        headroom: room.unwrap_or(0),
+    })
+}
+
+/// Returns the size of the varint length prefix FramedTcp writes before a frame of `len` bytes.
+fn length_prefix(len: usize) -> usize {
+    let bits = usize::BITS - len.leading_zeros();
+    bits.div_ceil(7).max(1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::batch::MAX_BATCH_BYTES;
+    use crate::participant::topic::MAX_TOPIC_LEN;
+
+    use message_io::util::encoding::{self, MAX_ENCODED_SIZE};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const LIMITS: Limits = Limits {
+        batch_budget: MAX_BATCH_BYTES,
+        max_topic_len: MAX_TOPIC_LEN,
+    };
+
+    /// Returns the length of the frame FramedTcp sends for `message`.
+    fn wire_len(message: &Message) -> usize {
+        let body = message.encode();
+        let mut buf = [0; MAX_ENCODED_SIZE];
+        encoding::encode_size(&body, &mut buf).len() + body.len()
+    }
+
+    #[test]
+    fn each_kind_is_estimated_as_encoded() {
+        for message in [
+            OutgoingMessage::text("random message 1"),
+            OutgoingMessage::topic("chat", b"hello".to_vec()),
+        ] {
+            let estimate = estimate(&message, &LIMITS).unwrap();
+            assert_eq!(estimate.message, message.message().encode().len());
+            assert_eq!(estimate.frame, wire_len(message.message()));
+            assert_eq!(estimate.frame, message.frame_len());
+            assert!(estimate.fits_batch);
+        }
+    }
+
+    #[test]
+    fn a_topic_publish_would_refuse_is_not_estimated() {
+        for (topic, err) in [
+            ("", TopicError::Empty),
+            (&"t".repeat(MAX_TOPIC_LEN + 1)[..], TopicError::TooLong),
+            ("a\nb", TopicError::ControlCharacter),
+        ] {
+            assert_eq!(
+                estimate(&OutgoingMessage::topic(topic, Vec::new()), &LIMITS),
+                Err(EstimateError::Topic(err))
+            );
+        }
+        assert!(estimate(&OutgoingMessage::text(""), &LIMITS).is_ok());
+    }
+
+    #[test]
+    fn the_headroom_reaches_zero_at_the_exact_budget() {
+        let message = OutgoingMessage::topic("chat", vec![7; 100]);
+        let size = message.message().encode().len();
+        let exact = Limits {
+            batch_budget: BATCH_HEADER_BYTES + size,
+            ..LIMITS
+        };
+        let estimate_at = |limits: Limits| estimate(&message, &limits).unwrap();
+
+        let at = estimate_at(exact);
+        assert!(at.fits_batch);
+        assert_eq!(at.headroom, 0);
+        let above = estimate_at(Limits {
+            batch_budget: exact.batch_budget + 5,
+            ..LIMITS
+        });
+        assert!(above.fits_batch);
+        assert_eq!(above.headroom, 5);
+        let below = estimate_at(Limits {
+            batch_budget: exact.batch_budget - 1,
+            ..LIMITS
+        });
+        assert!(!below.fits_batch);
+        assert_eq!(below.headroom, 0);
+    }
+
+    #[test]
+    fn the_length_prefix_grows_every_seven_bits() {
+        for (len, prefix) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16_383, 2),
+            (16_384, 3),
+            (2_097_151, 3),
+            (2_097_152, 4),
+        ] {
+            assert_eq!(length_prefix(len), prefix, "{}", len);
+        }
+    }
+
+    #[test]
+    fn the_estimate_is_the_length_of_the_sent_frame() {
+        let mut rng = StdRng::seed_from_u64(140);
+        let mut lengths: Vec<usize> = vec![0, 100, 101, 16_360, 16_361, 70_000];
+        lengths.extend((0..100).map(|_| rng.gen_range(0..20_000)));
+        for len in lengths {
+            let payload: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let text: String = (0..len).map(|_| rng.gen_range('a'..='z')).collect();
+            for message in [
+                OutgoingMessage::topic("bench", payload),
+                OutgoingMessage::text(text),
+            ] {
+                let estimate = estimate(&message, &LIMITS).unwrap();
+                assert_eq!(estimate.frame, wire_len(message.message()), "{}", len);
+                assert_eq!(
+                    estimate.fits_batch,
+                    estimate.message + BATCH_HEADER_BYTES <= MAX_BATCH_BYTES
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Participant Handles.
+//!
+//! Once `Participant::run` is called, the participant belongs to its event loop. A
+//! `ParticipantHandle`, taken before, lets another thread of the same process drive it the way
+//! the console and the admin socket do, through the events of its loop: run a console command
+//! and read its output, take the `Report` of the run so far as a value instead of its JSON
+//! text, schedule a publication, or shut it down gracefully. The `selftest` command watches its two participants
+//! through it.
+
+use crate::console::Command;
+use crate::participant::event::InternalEvent;
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::report::Report;
+use crate::participant::schedule::{Due, Missed, ScheduleError, ScheduleHandle};
+
+use message_io::node::NodeHandler;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Drives a running participant from another thread.
+#[derive(Clone)]
+pub struct ParticipantHandle {
+    handler: NodeHandler<InternalEvent>,
+}
+
+impl ParticipantHandle {
+    /// Creates a handle handing its events to the event loop of `handler`.
+    pub fn new(handler: NodeHandler<InternalEvent>) -> Self {
+        Self { handler }
+    }
+
+    /// Runs `command` as if entered in the console, waiting up to `timeout` for its output.
+    ///
+    /// Must not be called from the event loop, which would wait for itself.
+    ///
+    /// # Returns
+    ///
+    /// The output lines, or `None` if the participant did not answer in time or stopped.
+    pub fn command(&self, command: Command, timeout: Duration) -> Option<Vec<String>> {
+        let (reply, waiter) = mpsc::channel();
+        self.handler.signals().send(InternalEvent::Command {
+            command,
+            reply: Some(reply),
+        });
+        waiter.recv_timeout(timeout).ok()
+    }
+
+    /// Takes the report of the run so far, waiting up to `timeout` for it.
+    ///
+    /// # Returns
+    ///
+    /// The report, or `None` if the participant did not answer in time or stopped.
+    pub fn report(&self, timeout: Duration) -> Option<Report> {
+        let (reply, waiter) = mpsc::channel();
+        self.handler.signals().send(InternalEvent::Report { reply });
+        waiter.recv_timeout(timeout).ok()
+    }
+
+    /// Schedules `message` to be published at `due`, as `Participant::schedule` does, waiting up
+    /// to `timeout` for the participant to take it.
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the scheduling, or `None` if the participant did not answer in time or
+    /// stopped.
+    pub fn schedule(
+        &self,
+        due: Due,
+        message: OutgoingMessage,
+        missed: Missed,
+        timeout: Duration,
+    ) -> Option<Result<ScheduleHandle, ScheduleError>> {
+        let (reply, waiter) = mpsc::channel();
+        self.handler.signals().send(InternalEvent::Schedule {
+            due,
+            message,
+            missed,
+            reply,
+        });
+        waiter.recv_timeout(timeout).ok()
+    }
+
+    /// Asks the participant to shut down once the events queued before are handled.
+    pub fn shutdown(&self) {
+        self.handler.signals().send(InternalEvent::Shutdown);
+    }
+}
@@ -0,0 +1,247 @@
+//! Partition Healing.
+//!
+//! Anti-entropy only talks to connected peers. Once the two halves of a network lost each
+//! other, say during a switch outage, neither half lists the other anymore, and they stay apart
+//! after the network is back although a dial across the boundary would work. The storage keeps
+//! remembering the peers that departed, and every `--heal-probe-interval` a participant dials a
+//! few of them that are not connected: `HealProber` picks them, the least recently probed and,
+//! among those, the most recently departed first.
+//!
+//! A probe that connects is handled like any dial, so the peers exchange their participants
+//! lists at once and the rest of the other half follows through normal anti-entropy. A probe
+//! that fails is only recorded in the dial trail: it asks nobody for an introduction and does
+//! not try the bootstrap candidates.
+
+use crate::participant::utils::CanonicalAddr;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The number of probes in flight at once, which bounds the probes started per round.
+pub const HEAL_PROBES_PER_ROUND: usize = 3;
+
+/// A departed peer being probed.
+#[derive(Debug, Clone, Copy)]
+pub struct Probe {
+    /// The address dialed.
+    pub addr: SocketAddr,
+    /// When the peer departed, or was last heard from when the departure is unknown.
+    pub departed_at: Option<Instant>,
+}
+
+/// Picks the departed peers to probe and keeps the probes in flight, keyed by connection.
+#[derive(Debug)]
+pub struct HealProber<K> {
+    interval: Duration,
+    /// The time of the next round, `None` when it is further than an `Instant` can tell.
+    next_round: Option<Instant>,
+    probed: HashMap<CanonicalAddr, Instant>,
+    pending: HashMap<K, Probe>,
+}
+
+impl<K: Hash + Eq> HealProber<K> {
+    /// Creates a prober whose first round is one interval away.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval` - The time between two probe rounds.
+    /// * `now` - The current time.
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            next_round: now.checked_add(interval),
+            probed: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Sets the time between two probe rounds, the round already scheduled keeping its time.
+    ///
+    /// When no round was scheduled, because the previous interval was too long for the clock,
+    /// the next one is one new interval from `now`.
+    pub fn set_interval(&mut self, interval: Duration, now: Instant) {
+        self.interval = interval;
+        if self.next_round.is_none() {
+            self.next_round = now.checked_add(interval);
+        }
+    }
+
+    /// Determines whether a probe round is due at `now`, starting the next interval if so.
+    pub fn round_due(&mut self, now: Instant) -> bool {
+        if self.next_round.is_none_or(|next_round| now < next_round) {
+            return false;
+        }
+        self.next_round = now.checked_add(self.interval);
+        true
+    }
+
+    /// Picks the departed peers to probe in this round, as many as there are free slots.
+    ///
+    /// The peers never probed come first, then the least recently probed; between equally
+    /// probed peers, the most recent departure wins. The picked peers count as probed at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `departed` - The departed peers that may be dialed, with their departure time.
+    /// * `now` - The current time.
+    pub fn select(&mut self, departed: Vec<Probe>, now: Instant) -> Vec<Probe> {
+        let slots = HEAL_PROBES_PER_ROUND.saturating_sub(self.pending.len());
+        let mut departed = departed;
+        departed.sort_by_key(|probe| {
+            (
+                self.probed.get(&probe.addr.into()).copied(),
+                Reverse(probe.departed_at),
+            )
+        });
+        departed.truncate(slots);
+
+        for probe in &departed {
+            self.probed.insert(probe.addr.into(), now);
+        }
+        departed
+    }
+
+    /// Forgets the probe times of the peers that are no longer probe candidates.
+    ///
+    /// # Parameters
+    ///
+    /// * `keep` - Whether an address is still remembered as departed.
+    pub fn retain(&mut self, keep: impl Fn(SocketAddr) -> bool) {
+        self.probed.retain(|addr, _| keep(addr.original()));
+    }
+
+    /// Records that the dial of a probe started through `key`.
+    pub fn started(&mut self, key: K, probe: Probe) {
+        self.pending.insert(key, probe);
+    }
+
+    /// Records that the dial through `key` failed.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the dial was a probe, which calls for no other handling.
+    pub fn failed(&mut self, key: &K) -> bool {
+        self.pending.remove(key).is_some()
+    }
+
+    /// Records that the dial through `key` connected.
+    ///
+    /// # Returns
+    ///
+    /// The probe, if the dial was one.
+    pub fn connected(&mut self, key: &K) -> Option<Probe> {
+        self.pending.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn probe(port: u16, departed_at: Option<Instant>) -> Probe {
+        Probe {
+            addr: addr(port),
+            departed_at,
+        }
+    }
+
+    fn ports(probes: &[Probe]) -> Vec<u16> {
+        probes.iter().map(|probe| probe.addr.port()).collect()
+    }
+
+    #[test]
+    fn rounds_are_one_interval_apart() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(30);
+        let mut prober = HealProber::<u64>::new(interval, now);
+
+        assert!(!prober.round_due(now));
+        assert!(prober.round_due(now + interval));
+        assert!(!prober.round_due(now + interval));
+        assert!(prober.round_due(now + interval * 2));
+
+        // A new interval applies from the next round on.
+        prober.set_interval(Duration::from_secs(5), now);
+        assert!(!prober.round_due(now + interval * 2 + Duration::from_secs(4)));
+        assert!(prober.round_due(now + interval * 3));
+        assert!(prober.round_due(now + interval * 3 + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn an_interval_past_the_clock_never_probes() {
+        let now = Instant::now();
+        let mut prober = HealProber::<u64>::new(Duration::MAX, now);
+        assert!(!prober.round_due(now + Duration::from_secs(100 * 365 * 24 * 60 * 60)));
+
+        // A shorter interval set later schedules the next round.
+        prober.set_interval(Duration::from_secs(1), now);
+        assert!(prober.round_due(now + Duration::from_secs(1)));
+
+        prober.set_interval(Duration::MAX, now);
+        assert!(prober.round_due(now + Duration::from_secs(2)));
+        assert!(!prober.round_due(now + Duration::from_secs(100 * 365 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn picks_the_least_recently_probed_and_most_recently_departed_first() {
+        let now = Instant::now();
+        let mut prober = HealProber::<u64>::new(Duration::from_secs(30), now);
+        let departed = |port: u16, secs: u64| probe(port, Some(now - Duration::from_secs(secs)));
+
+        let first = prober.select(
+            vec![
+                departed(1, 50),
+                departed(2, 10),
+                departed(3, 30),
+                probe(4, None),
+            ],
+            now,
+        );
+        assert_eq!(ports(&first), [2, 3, 1]);
+
+        // The one left out has never been probed, so it comes before the others.
+        let second = prober.select(
+            vec![
+                departed(1, 50),
+                departed(2, 10),
+                departed(3, 30),
+                probe(4, None),
+            ],
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(ports(&second), [4, 2, 3]);
+    }
+
+    #[test]
+    fn probes_in_flight_take_the_slots() {
+        let now = Instant::now();
+        let mut prober = HealProber::new(Duration::from_secs(30), now);
+        prober.started(1, probe(1, None));
+        prober.started(2, probe(2, None));
+
+        let picked = prober.select(vec![probe(3, None), probe(4, None)], now);
+        assert_eq!(picked.len(), HEAL_PROBES_PER_ROUND - 2);
+
+        assert!(prober.failed(&1));
+        assert!(!prober.failed(&1));
+        assert_eq!(prober.connected(&2).map(|probe| probe.addr), Some(addr(2)));
+        assert!(prober.connected(&2).is_none());
+        assert_eq!(prober.select(vec![probe(5, None)], now).len(), 1);
+    }
+
+    #[test]
+    fn forgets_the_probe_times_of_peers_no_longer_departed() {
+        let now = Instant::now();
+        let mut prober = HealProber::<u64>::new(Duration::from_secs(30), now);
+        prober.select(vec![probe(1, None)], now);
+        prober.retain(|addr| addr.port() != 1);
+
+        // Forgotten, the peer counts as never probed again.
+        let picked = prober.select(vec![probe(2, None), probe(1, None)], now);
+        assert_eq!(ports(&picked), [2, 1]);
+    }
+}
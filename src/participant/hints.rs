@@ -0,0 +1,597 @@
+//! Operator Hints.
+//!
+//! The first runs of a new user fail in a handful of ways, and the logs only show the symptom:
+//! a node that receives nothing, a node announcing the address of another, a target that never
+//! answers, a demo that looks frozen. Each detector of this module recognizes one of them from
+//! a `Signals` snapshot the participant takes on its maintenance tick, and names the setting to
+//! check in a `Hint`:
+//!
+//! - `silent`: peers are connected, yet no frame arrived from any of them for
+//!   `SILENT_PERIODS` gossip periods, which a firewall dropping their frames or frozen peers
+//!   cause;
+//! - `undecodable`: every frame from a host failed to decode, at least `UNDECODABLE_FRAMES` of
+//!   them, as from another service or an incompatible build on that port;
+//! - `foreign_self`: a peer announces one of the addresses of this participant under another
+//!   node id, which a copied `--advertise` on either of them causes;
+//! - `isolated`: no peer connected for `ISOLATION_GRACE` although `--connect` was given;
+//! - `long_period`: peers have been connected for `LONG_PERIOD_WAIT` and the first gossip text
+//!   is still to go out, under a period long enough to pass for a hang.
+//!
+//! The detectors are pure functions of the snapshot. `Hints` debounces them: a condition must
+//! be found on `HINT_CONFIRM` maintenance ticks in a row before its hint is given, and absent
+//! on as many before it can be given again, so a transient condition never fires and a
+//! lasting one fires once. The participant logs each hint given as a warning prefixed with
+//! `hint:` and counts them in `stats`; `--no-hints` turns the whole engine off.
+
+use crate::participant::collections::{BoundedMap, Eviction};
+use crate::participant::identity::NodeId;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// The maintenance ticks in a row a condition must be found, or absent, to change its state.
+pub const HINT_CONFIRM: u32 = 3;
+
+/// The gossip periods without any frame from a connected peer that make it silent.
+pub const SILENT_PERIODS: u32 = 5;
+
+/// The failed frames from a host, none decoding, that make it undecodable.
+pub const UNDECODABLE_FRAMES: u64 = 3;
+
+/// How long a participant given `--connect` may stay without peers before it is isolated.
+pub const ISOLATION_GRACE: Duration = Duration::from_secs(30);
+
+/// How long the first gossip text may keep the connected peers waiting.
+pub const LONG_PERIOD_WAIT: Duration = Duration::from_secs(10);
+
+/// The most hosts whose frames are counted.
+const FRAME_HOSTS: usize = 256;
+
+/// How long the frames of a host are remembered after its last one.
+const FRAME_HOSTS_TTL: Duration = Duration::from_secs(600);
+
+/// Enumerates the misconfigurations a hint is given for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintKind {
+    Silent,
+    Undecodable,
+    ForeignSelf,
+    Isolated,
+    LongPeriod,
+}
+
+/// A condition a detector found, and what to check about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    /// The detector that found the condition.
+    pub kind: HintKind,
+    /// The host or peer the condition is about, as displayed, for the detectors finding one
+    /// per host or peer.
+    pub subject: Option<String>,
+    /// The hint, for the operator.
+    pub text: String,
+}
+
+/// The frames received from a host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCounts {
+    /// The frames that decoded.
+    pub decoded: u64,
+    /// The frames that did not.
+    pub failed: u64,
+}
+
+/// A peer announcing an address of this participant under another node id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Impostor {
+    /// The address the peer is connected from.
+    pub peer: SocketAddr,
+    /// The address of this participant it announced.
+    pub announced: SocketAddr,
+    /// The node id it presented.
+    pub node_id: NodeId,
+}
+
+/// What the detectors look at.
+#[derive(Debug, Clone)]
+pub struct Signals<'a> {
+    /// When the snapshot was taken.
+    pub now: Instant,
+    /// The gossip period in effect.
+    pub period: Duration,
+    /// The peers connected.
+    pub peers: usize,
+    /// Since when peers have been connected without a pause, if they are.
+    pub peers_since: Option<Instant>,
+    /// Since when no peer has been connected, if none is.
+    pub alone_since: Option<Instant>,
+    /// When the last frame arrived from a peer, or the peers connected if later.
+    pub last_frame: Instant,
+    /// The broadcast ticks run so far.
+    pub broadcasts: u64,
+    /// The `--connect` target, if any.
+    pub connect: Option<&'a str>,
+    /// The frames received from each host.
+    pub frames: Vec<(IpAddr, FrameCounts)>,
+    /// The connected peers announcing an address of this participant.
+    pub impostors: Vec<Impostor>,
+}
+
+/// Detects connected peers none of which sent anything for `SILENT_PERIODS` periods.
+pub fn silent(signals: &Signals) -> Option<Hint> {
+    let quiet = signals.now.saturating_duration_since(signals.last_frame);
+    if signals.peers == 0 || quiet < signals.period * SILENT_PERIODS {
+        return None;
+    }
+    Some(Hint {
+        kind: HintKind::Silent,
+        subject: None,
+        text: format!(
+            "nothing arrived for {}s from the {} connected participants; check that no firewall drops their frames and that they are not frozen",
+            quiet.as_secs(),
+            signals.peers
+        ),
+    })
+}
+
+/// Detects the hosts whose frames all failed to decode, at least `UNDECODABLE_FRAMES` of them.
+pub fn undecodable(signals: &Signals) -> Vec<Hint> {
+    signals
+        .frames
+        .iter()
+        .filter(|(_, counts)| counts.decoded == 0 && counts.failed >= UNDECODABLE_FRAMES)
+        .map(|(host, counts)| Hint {
+            kind: HintKind::Undecodable,
+            subject: Some(host.to_string()),
+            text: format!(
+                "all {} frames from {} failed to decode; check that it runs a compatible build of this participant and not another service on that port",
+                counts.failed, host
+            ),
+        })
+        .collect()
+}
+
+/// Detects the peers announcing an address of this participant under another node id.
+pub fn foreign_self(signals: &Signals) -> Vec<Hint> {
+    signals
+        .impostors
+        .iter()
+        .map(|impostor| Hint {
+            kind: HintKind::ForeignSelf,
+            subject: Some(impostor.peer.to_string()),
+            text: format!(
+                "\"{}\" announces \"{}\", an address of this participant, as node {}; check the --advertise of both",
+                impostor.peer, impostor.announced, impostor.node_id
+            ),
+        })
+        .collect()
+}
+
+/// Detects a participant given `--connect` that stayed without peers for `ISOLATION_GRACE`.
+pub fn isolated(signals: &Signals) -> Option<Hint> {
+    let connect = signals.connect?;
+    let alone = signals.now.saturating_duration_since(signals.alone_since?);
+    if alone < ISOLATION_GRACE {
+        return None;
+    }
+    Some(Hint {
+        kind: HintKind::Isolated,
+        subject: None,
+        text: format!(
+            "no participant connected for {}s although --connect={} was given; check that the target runs and that its port is reachable from here",
+            alone.as_secs(),
+            connect
+        ),
+    })
+}
+
+/// Detects peers kept waiting `LONG_PERIOD_WAIT` for the first gossip text.
+pub fn long_period(signals: &Signals) -> Option<Hint> {
+    let waiting = signals.now.saturating_duration_since(signals.peers_since?);
+    if signals.broadcasts > 0 || waiting < LONG_PERIOD_WAIT {
+        return None;
+    }
+    Some(Hint {
+        kind: HintKind::LongPeriod,
+        subject: None,
+        text: format!(
+            "the first gossip text only goes out after the period of {:?}; a shorter --period shows the gossip sooner",
+            signals.period
+        ),
+    })
+}
+
+/// Runs every detector over `signals`.
+///
+/// # Returns
+///
+/// The conditions found, at most one per kind and subject.
+pub fn detect(signals: &Signals) -> Vec<Hint> {
+    let mut hints: Vec<Hint> = Vec::new();
+    hints.extend(silent(signals));
+    hints.extend(undecodable(signals));
+    hints.extend(foreign_self(signals));
+    hints.extend(isolated(signals));
+    hints.extend(long_period(signals));
+    hints
+}
+
+/// The state of a condition between two maintenance ticks.
+#[derive(Debug, Clone, Copy, Default)]
+struct Condition {
+    /// The ticks in a row the condition was found.
+    found: u32,
+    /// The ticks in a row it was not, since it was last found.
+    absent: u32,
+    /// Whether its hint was given for this occurrence.
+    given: bool,
+}
+
+/// The observations the signals are taken from, and the debounced conditions.
+pub struct Hints {
+    enabled: bool,
+    connect: Option<String>,
+    peers_since: Option<Instant>,
+    alone_since: Option<Instant>,
+    last_frame: Instant,
+    frames: BoundedMap<IpAddr, FrameCounts>,
+    impostors: HashMap<SocketAddr, Impostor>,
+    conditions: HashMap<(HintKind, Option<String>), Condition>,
+    given: u64,
+}
+
+impl Hints {
+    /// Creates the engine of a participant starting at `now` without peers.
+    ///
+    /// # Parameters
+    ///
+    /// * `enabled` - Whether hints are given, `false` with `--no-hints`.
+    /// * `connect` - The `--connect` target, if any.
+    /// * `now` - The start of the participant.
+    pub fn new(enabled: bool, connect: Option<String>, now: Instant) -> Self {
+        Self {
+            enabled,
+            connect,
+            peers_since: None,
+            alone_since: Some(now),
+            last_frame: now,
+            frames: BoundedMap::new(FRAME_HOSTS, Eviction::Lru, Some(FRAME_HOSTS_TTL)),
+            impostors: HashMap::new(),
+            conditions: HashMap::new(),
+            given: 0,
+        }
+    }
+
+    /// Turns the engine on or off, forgetting the conditions found so far when turned off.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.conditions.clear();
+        }
+    }
+
+    /// Records a frame from `peer` at `now`, and whether it decoded.
+    pub fn frame(&mut self, peer: SocketAddr, decoded: bool, now: Instant) {
+        if decoded {
+            self.last_frame = now;
+        }
+        let host = peer.ip();
+        let mut counts = self
+            .frames
+            .get_mut(&host, now)
+            .map(|counts| *counts)
+            .unwrap_or_default();
+        if decoded {
+            counts.decoded += 1;
+        } else {
+            counts.failed += 1;
+        }
+        self.frames.insert(host, counts, now);
+    }
+
+    /// Records the peer connected from `peer` announcing `announced`, an address of this
+    /// participant, as node `node_id`.
+    pub fn impostor(&mut self, peer: SocketAddr, announced: SocketAddr, node_id: NodeId) {
+        self.impostors.insert(
+            peer,
+            Impostor {
+                peer,
+                announced,
+                node_id,
+            },
+        );
+    }
+
+    /// Forgets what the connection from `peer` announced, once it is closed.
+    pub fn closed(&mut self, peer: SocketAddr) {
+        self.impostors.remove(&peer);
+    }
+
+    /// Takes the snapshot of the signals at `now`, with `peers` connected.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `period` - The gossip period in effect.
+    /// * `peers` - The peers connected.
+    /// * `broadcasts` - The broadcast ticks run so far.
+    pub fn signals(
+        &mut self,
+        now: Instant,
+        period: Duration,
+        peers: usize,
+        broadcasts: u64,
+    ) -> Signals<'_> {
+        if peers == 0 {
+            self.peers_since = None;
+            self.alone_since.get_or_insert(now);
+        } else if self.peers_since.is_none() {
+            // Silence is counted from the connection of the peers, not from the last frame
+            // of the ones before.
+            self.peers_since = Some(now);
+            self.alone_since = None;
+            self.last_frame = self.last_frame.max(now);
+        }
+        self.frames.sweep(now);
+        Signals {
+            now,
+            period,
+            peers,
+            peers_since: self.peers_since,
+            alone_since: self.alone_since,
+            last_frame: self.last_frame,
+            broadcasts,
+            connect: self.connect.as_deref(),
+            frames: self
+                .frames
+                .iter()
+                .map(|(host, counts)| (*host, *counts))
+                .collect(),
+            impostors: self.impostors.values().copied().collect(),
+        }
+    }
+
+    /// Debounces the conditions `found` on this maintenance tick.
+    ///
+    /// # Returns
+    ///
+    /// The hints to give now, each for a condition found `HINT_CONFIRM` ticks in a row and
+    /// not given since it last cleared; none while the engine is off.
+    pub fn evaluate(&mut self, found: Vec<Hint>) -> Vec<Hint> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let mut seen = Vec::with_capacity(found.len());
+        let mut due = Vec::new();
+        for hint in found {
+            let key = (hint.kind, hint.subject.clone());
+            let condition = self.conditions.entry(key.clone()).or_default();
+            seen.push(key);
+            condition.found += 1;
+            condition.absent = 0;
+            if !condition.given && condition.found >= HINT_CONFIRM {
+                condition.given = true;
+                due.push(hint);
+            }
+        }
+        self.conditions.retain(|key, condition| {
+            if seen.contains(key) {
+                return true;
+            }
+            condition.found = 0;
+            condition.absent += 1;
+            condition.absent < HINT_CONFIRM
+        });
+        self.given += due.len() as u64;
+        due
+    }
+
+    /// Returns the number of hints given so far.
+    pub fn given(&self) -> u64 {
+        self.given
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    const PERIOD: Duration = Duration::from_secs(1);
+    const MS: Duration = Duration::from_millis(1);
+
+    /// The signals of a participant with `peers` connected since `start`, whose last frame
+    /// arrived then too, `elapsed` after `start`.
+    fn signals(start: Instant, elapsed: Duration, peers: usize) -> Signals<'static> {
+        Signals {
+            now: start + elapsed,
+            period: PERIOD,
+            peers,
+            peers_since: (peers > 0).then_some(start),
+            alone_since: (peers == 0).then_some(start),
+            last_frame: start,
+            broadcasts: 0,
+            connect: None,
+            frames: Vec::new(),
+            impostors: Vec::new(),
+        }
+    }
+
+    fn kinds(hints: &[Hint]) -> Vec<HintKind> {
+        hints.iter().map(|hint| hint.kind).collect()
+    }
+
+    #[test]
+    fn silent_peers_are_found_after_their_periods() {
+        let start = Instant::now();
+        let quiet = PERIOD * SILENT_PERIODS;
+        assert_eq!(silent(&signals(start, quiet - MS, 2)), None);
+        assert_eq!(silent(&signals(start, quiet, 0)), None);
+        let hint = silent(&signals(start, quiet, 2)).unwrap();
+        assert_eq!(hint.subject, None);
+        assert!(hint
+            .text
+            .starts_with("nothing arrived for 5s from the 2 connected participants"));
+    }
+
+    #[test]
+    fn hosts_are_undecodable_only_without_a_decoded_frame() {
+        let start = Instant::now();
+        let mut signals = signals(start, Duration::ZERO, 1);
+        let failing = |failed| FrameCounts { decoded: 0, failed };
+        signals.frames = vec![
+            (addr(8080).ip(), failing(UNDECODABLE_FRAMES)),
+            ("10.0.0.5".parse().unwrap(), failing(UNDECODABLE_FRAMES - 1)),
+            (
+                "10.0.0.6".parse().unwrap(),
+                FrameCounts {
+                    decoded: 1,
+                    failed: 100,
+                },
+            ),
+        ];
+        let hints = undecodable(&signals);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].subject.as_deref(), Some("127.0.0.1"));
+        assert!(hints[0]
+            .text
+            .starts_with("all 3 frames from 127.0.0.1 failed"));
+    }
+
+    #[test]
+    fn an_impostor_is_named_with_the_address_it_announced() {
+        let mut signals = signals(Instant::now(), Duration::ZERO, 1);
+        signals.impostors = vec![Impostor {
+            peer: addr(8081),
+            announced: addr(8080),
+            node_id: NodeId(1),
+        }];
+        let hints = foreign_self(&signals);
+        assert_eq!(hints[0].subject.as_deref(), Some("127.0.0.1:8081"));
+        assert!(hints[0]
+            .text
+            .starts_with("\"127.0.0.1:8081\" announces \"127.0.0.1:8080\""));
+    }
+
+    #[test]
+    fn isolation_needs_a_connect_target_and_the_grace() {
+        let start = Instant::now();
+        let mut alone = signals(start, ISOLATION_GRACE, 0);
+        assert_eq!(isolated(&alone), None);
+        alone.connect = Some("seed:8080");
+        assert!(isolated(&alone)
+            .unwrap()
+            .text
+            .contains("although --connect=seed:8080 was given"));
+        let mut early = signals(start, ISOLATION_GRACE - MS, 0);
+        early.connect = Some("seed:8080");
+        assert_eq!(isolated(&early), None);
+    }
+
+    #[test]
+    fn a_long_period_is_found_before_the_first_text() {
+        let start = Instant::now();
+        let mut waiting = signals(start, LONG_PERIOD_WAIT, 1);
+        waiting.last_frame = waiting.now;
+        assert_eq!(kinds(&detect(&waiting)), [HintKind::LongPeriod]);
+        waiting.broadcasts = 1;
+        assert!(detect(&waiting).is_empty());
+        assert_eq!(long_period(&signals(start, LONG_PERIOD_WAIT - MS, 1)), None);
+    }
+
+    #[test]
+    fn a_condition_is_given_once_after_confirmation() {
+        let start = Instant::now();
+        let mut hints = Hints::new(true, None, start);
+        let found = silent(&signals(start, PERIOD * SILENT_PERIODS, 1)).unwrap();
+        for _ in 1..HINT_CONFIRM {
+            assert!(hints.evaluate(vec![found.clone()]).is_empty());
+        }
+        assert_eq!(
+            kinds(&hints.evaluate(vec![found.clone()])),
+            [HintKind::Silent]
+        );
+        assert!(hints.evaluate(vec![found.clone()]).is_empty());
+
+        // A short absence does not clear the condition, a long one does.
+        for _ in 1..HINT_CONFIRM {
+            hints.evaluate(Vec::new());
+        }
+        for _ in 0..HINT_CONFIRM {
+            assert!(hints.evaluate(vec![found.clone()]).is_empty());
+        }
+        for _ in 0..HINT_CONFIRM {
+            hints.evaluate(Vec::new());
+        }
+        let given: usize = (0..HINT_CONFIRM)
+            .map(|_| hints.evaluate(vec![found.clone()]).len())
+            .sum();
+        assert_eq!(given, 1);
+        assert_eq!(hints.given(), 2);
+    }
+
+    #[test]
+    fn a_transient_condition_is_never_given() {
+        let start = Instant::now();
+        let mut hints = Hints::new(true, None, start);
+        let found = silent(&signals(start, PERIOD * SILENT_PERIODS, 1)).unwrap();
+        for _ in 0..10 {
+            for _ in 1..HINT_CONFIRM {
+                assert!(hints.evaluate(vec![found.clone()]).is_empty());
+            }
+            assert!(hints.evaluate(Vec::new()).is_empty());
+        }
+        assert_eq!(hints.given(), 0);
+    }
+
+    #[test]
+    fn no_hint_is_given_while_disabled() {
+        let start = Instant::now();
+        let mut hints = Hints::new(false, None, start);
+        let found = silent(&signals(start, PERIOD * SILENT_PERIODS, 1)).unwrap();
+        for _ in 0..HINT_CONFIRM {
+            assert!(hints.evaluate(vec![found.clone()]).is_empty());
+        }
+        hints.set_enabled(true);
+        let given: usize = (0..HINT_CONFIRM)
+            .map(|_| hints.evaluate(vec![found.clone()]).len())
+            .sum();
+        assert_eq!(given, 1);
+    }
+
+    #[test]
+    fn the_snapshot_follows_the_observations() {
+        let start = Instant::now();
+        let mut hints = Hints::new(true, Some("seed:8080".to_owned()), start);
+        let alone = hints.signals(start + PERIOD, PERIOD, 0, 0);
+        assert_eq!(alone.alone_since, Some(start));
+        assert_eq!(alone.connect, Some("seed:8080"));
+
+        hints.frame(addr(8080), false, start + PERIOD);
+        hints.frame(addr(8081), true, start + PERIOD);
+        hints.impostor(addr(8081), addr(7000), NodeId(1));
+        let connected = hints.signals(start + 2 * PERIOD, PERIOD, 1, 0);
+        assert_eq!(connected.peers_since, Some(start + 2 * PERIOD));
+        assert_eq!(connected.alone_since, None);
+        // Silence is counted from the connection.
+        assert_eq!(connected.last_frame, start + 2 * PERIOD);
+        assert_eq!(
+            connected.frames,
+            [(
+                addr(8080).ip(),
+                FrameCounts {
+                    decoded: 1,
+                    failed: 1,
+                },
+            )]
+        );
+        assert_eq!(connected.impostors.len(), 1);
+
+        hints.closed(addr(8081));
+        assert!(hints
+            .signals(start + 3 * PERIOD, PERIOD, 1, 0)
+            .impostors
+            .is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+//! Membership History.
+//!
+//! `gossip-p2p analyze --file=<path> [--at=<duration>]` reads the membership records of a
+//! recording made with `--record` and replays them to rebuild the peer set of the recording
+//! participant at a given time since its start, the end of the recording by default. It also
+//! sums the membership up: the joins and departures, the churn per hour and the longest
+//! interval over which the peer set did not change.
+//!
+//! The analysis works on the record stream alone, so it can be fed any history. The records
+//! are ordered by their elapsed time first, keeping the written order among equal times, since
+//! the writer may stamp a record a little before one handed earlier. A join of a peer already
+//! in the set, such as the reconnection of a redialed peer, and a departure of a peer not in
+//! it change nothing and are not counted. A partial record at the end of the file is skipped
+//! by `Recording`.
+
+use crate::participant::record::{MembershipRecord, Record, RecordedEvent, Recording};
+use crate::printer::{format_elapsed, print_line};
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// A membership record with the time it was written at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MembershipEntry {
+    /// The time between the start of the recording and the record.
+    pub elapsed: Duration,
+    /// The record.
+    pub change: MembershipRecord,
+}
+
+/// A peer of the rebuilt peer set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAt {
+    /// The time the peer joined, as a time since the start of the recording.
+    pub since: Duration,
+    /// Whether the peer was suspected of a half-open connection and not recovered yet.
+    pub suspect: bool,
+}
+
+/// The periodic snapshot of the participant's own state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfState {
+    /// The time of the snapshot since the start of the recording.
+    pub elapsed: Duration,
+    /// The number of connected peers.
+    pub peers: u64,
+    /// The effective broadcast period.
+    pub period: Duration,
+}
+
+/// The summary of the membership records of a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySummary {
+    /// The number of peers that joined the peer set.
+    pub joins: u64,
+    /// The number of peers that left it.
+    pub leaves: u64,
+    /// The time covered by the recording, up to its last record.
+    pub span: Duration,
+    /// The joins and departures per hour of `span`, 0 for an empty span.
+    pub churn_per_hour: f64,
+    /// The longest interval without joins nor departures, as its start and end times.
+    pub longest_stable: (Duration, Duration),
+}
+
+/// Extracts the membership records of `records`, ordered by their elapsed time.
+pub fn membership(records: &[Record]) -> Vec<MembershipEntry> {
+    let mut entries: Vec<MembershipEntry> = records
+        .iter()
+        .filter_map(|record| match &record.event {
+            RecordedEvent::Membership { change, .. } => Some(MembershipEntry {
+                elapsed: Duration::from_millis(record.elapsed_ms),
+                change: change.clone(),
+            }),
+            _ => None,
+        })
+        .collect();
+    // Stable, so the records of the same millisecond keep the order they were written in.
+    entries.sort_by_key(|entry| entry.elapsed);
+    entries
+}
+
+/// Rebuilds the peer set after the entries written up to `at`.
+///
+/// # Parameters
+///
+/// * `entries` - The membership records, as ordered by `membership`.
+/// * `at` - The time since the start of the recording.
+pub fn peers_at(entries: &[MembershipEntry], at: Duration) -> BTreeMap<SocketAddr, PeerAt> {
+    let mut peers = BTreeMap::new();
+    for entry in entries.iter().take_while(|entry| entry.elapsed <= at) {
+        apply(&mut peers, entry);
+    }
+    peers
+}
+
+/// Returns the last snapshot of the participant's own state written up to `at`.
+pub fn self_state_at(entries: &[MembershipEntry], at: Duration) -> Option<SelfState> {
+    entries
+        .iter()
+        .take_while(|entry| entry.elapsed <= at)
+        .filter_map(|entry| match entry.change {
+            MembershipRecord::SelfState { peers, period_ms } => Some(SelfState {
+                elapsed: entry.elapsed,
+                peers,
+                period: Duration::from_millis(period_ms),
+            }),
+            _ => None,
+        })
+        .last()
+}
+
+/// Sums up the membership records of a recording whose last record was written at `end`.
+pub fn summarize(entries: &[MembershipEntry], end: Duration) -> HistorySummary {
+    let mut peers = BTreeMap::new();
+    let (mut joins, mut leaves) = (0, 0);
+    let mut stable_since = Duration::ZERO;
+    let mut longest_stable = (Duration::ZERO, Duration::ZERO);
+    for entry in entries {
+        let before = peers.len();
+        let changed = apply(&mut peers, entry);
+        if !changed {
+            continue;
+        }
+        if peers.len() > before {
+            joins += 1;
+        } else {
+            leaves += 1;
+        }
+        if entry.elapsed - stable_since > longest_stable.1 - longest_stable.0 {
+            longest_stable = (stable_since, entry.elapsed);
+        }
+        stable_since = entry.elapsed;
+    }
+    let span = end.max(stable_since);
+    if span - stable_since > longest_stable.1 - longest_stable.0 {
+        longest_stable = (stable_since, span);
+    }
+    let hours = span.as_secs_f64() / 3600.0;
+    HistorySummary {
+        joins,
+        leaves,
+        span,
+        churn_per_hour: if hours > 0.0 {
+            (joins + leaves) as f64 / hours
+        } else {
+            0.0
+        },
+        longest_stable,
+    }
+}
+
+/// Applies one membership record to `peers`.
+///
+/// # Returns
+///
+/// Whether a peer joined or left the set.
+fn apply(peers: &mut BTreeMap<SocketAddr, PeerAt>, entry: &MembershipEntry) -> bool {
+    match &entry.change {
+        MembershipRecord::Joined { addr } => {
+            if peers.contains_key(addr) {
+                return false;
+            }
+            peers.insert(
+                *addr,
+                PeerAt {
+                    since: entry.elapsed,
+                    suspect: false,
+                },
+            );
+            true
+        }
+        MembershipRecord::Left { addr, .. } => peers.remove(addr).is_some(),
+        MembershipRecord::Suspect { addr } | MembershipRecord::Recovered { addr } => {
+            let suspect = matches!(entry.change, MembershipRecord::Suspect { .. });
+            if let Some(peer) = peers.get_mut(addr) {
+                peer.suspect = suspect;
+            }
+            false
+        }
+        MembershipRecord::SelfState { .. } => false,
+    }
+}
+
+/// Prints the peer set of `recording` at `at`, the end of the recording if `None`, and the
+/// summary of its membership.
+pub fn analyze(recording: &Recording, at: Option<Duration>) {
+    let entries = membership(&recording.records);
+    let end = recording
+        .records
+        .iter()
+        .map(|record| Duration::from_millis(record.elapsed_ms))
+        .max()
+        .unwrap_or_default();
+    let at = at.unwrap_or(end);
+
+    if recording.dropped_bytes > 0 {
+        print_line(&format!(
+            "Dropped {} bytes after the last complete record",
+            recording.dropped_bytes
+        ));
+    }
+    if entries.is_empty() {
+        print_line("The recording holds no membership records");
+        return;
+    }
+
+    let peers = peers_at(&entries, at);
+    print_line(&format!(
+        "Peer set at {}: {} peers",
+        format_elapsed(at),
+        peers.len()
+    ));
+    for (addr, peer) in &peers {
+        print_line(&format!(
+            "\t{} since {}{}",
+            addr,
+            format_elapsed(peer.since),
+            if peer.suspect { ", suspected" } else { "" }
+        ));
+    }
+    if let Some(state) = self_state_at(&entries, at) {
+        print_line(&format!(
+            "Last snapshot at {}: {} peers connected, period {:?}",
+            format_elapsed(state.elapsed),
+            state.peers,
+            state.period
+        ));
+    }
+
+    let summary = summarize(&entries, end);
+    print_line(&format!(
+        "Over {}: {} joins, {} departures, {:.1} changes per hour",
+        format_elapsed(summary.span),
+        summary.joins,
+        summary.leaves,
+        summary.churn_per_hour
+    ));
+    let (from, to) = summary.longest_stable;
+    print_line(&format!(
+        "Longest stable interval: {}, from {} to {}",
+        format_elapsed(to - from),
+        format_elapsed(from),
+        format_elapsed(to)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn record(elapsed_ms: u64, change: MembershipRecord) -> Record {
+        Record {
+            elapsed_ms,
+            event: RecordedEvent::Membership { wall_ms: 0, change },
+        }
+    }
+
+    fn joined(port: u16) -> MembershipRecord {
+        MembershipRecord::Joined { addr: addr(port) }
+    }
+
+    fn left(port: u16) -> MembershipRecord {
+        MembershipRecord::Left {
+            addr: addr(port),
+            reason: "disconnected".to_owned(),
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn membership_keeps_the_written_order_among_equal_times() {
+        let records = [
+            Record {
+                elapsed_ms: 0,
+                event: RecordedEvent::Accepted { endpoint: addr(1) },
+            },
+            record(2000, left(1)),
+            record(1000, joined(1)),
+            record(2000, joined(2)),
+        ];
+        let changes: Vec<_> = membership(&records)
+            .into_iter()
+            .map(|entry| entry.change)
+            .collect();
+        assert_eq!(changes, [joined(1), left(1), joined(2)]);
+    }
+
+    #[test]
+    fn the_peer_set_holds_the_records_up_to_the_time_asked() {
+        let entries = membership(&[
+            record(1000, joined(1)),
+            record(2000, joined(2)),
+            record(3000, MembershipRecord::Suspect { addr: addr(2) }),
+            record(4000, left(1)),
+        ]);
+
+        assert!(peers_at(&entries, secs(0)).is_empty());
+        let at_2s = peers_at(&entries, secs(2));
+        assert_eq!(
+            at_2s.keys().copied().collect::<Vec<_>>(),
+            [addr(1), addr(2)]
+        );
+        assert!(!at_2s[&addr(2)].suspect);
+
+        let at_end = peers_at(&entries, secs(10));
+        assert_eq!(at_end.len(), 1);
+        assert_eq!(
+            at_end[&addr(2)],
+            PeerAt {
+                since: secs(2),
+                suspect: true,
+            }
+        );
+    }
+
+    #[test]
+    fn a_rejoin_keeps_the_first_join_and_a_recovery_clears_the_suspicion() {
+        let entries = membership(&[
+            record(1000, joined(1)),
+            record(2000, MembershipRecord::Suspect { addr: addr(1) }),
+            record(3000, joined(1)),
+            record(4000, MembershipRecord::Recovered { addr: addr(1) }),
+        ]);
+        assert_eq!(
+            peers_at(&entries, secs(4))[&addr(1)],
+            PeerAt {
+                since: secs(1),
+                suspect: false,
+            }
+        );
+    }
+
+    #[test]
+    fn the_last_snapshot_is_the_one_written_before_the_time_asked() {
+        let snapshot = |peers| MembershipRecord::SelfState {
+            peers,
+            period_ms: 500,
+        };
+        let entries = membership(&[
+            record(1000, snapshot(1)),
+            record(1500, joined(1)),
+            record(2000, snapshot(2)),
+        ]);
+        assert_eq!(self_state_at(&entries, secs(0)), None);
+        assert_eq!(
+            self_state_at(&entries, secs(1)).map(|state| state.peers),
+            Some(1)
+        );
+        assert_eq!(
+            self_state_at(&entries, secs(3)),
+            Some(SelfState {
+                elapsed: secs(2),
+                peers: 2,
+                period: Duration::from_millis(500),
+            })
+        );
+    }
+
+    #[test]
+    fn the_summary_counts_only_the_changes_of_the_peer_set() {
+        let entries = membership(&[
+            record(0, joined(1)),
+            record(0, joined(1)),
+            record(600_000, joined(2)),
+            record(900_000, left(3)),
+            record(1_200_000, left(1)),
+        ]);
+        let summary = summarize(&entries, Duration::from_secs(3600));
+        assert_eq!((summary.joins, summary.leaves), (2, 1));
+        assert_eq!(summary.span, secs(3600));
+        assert_eq!(summary.churn_per_hour, 3.0);
+        // The peer set stays the same from the last departure to the end of the recording.
+        assert_eq!(summary.longest_stable, (secs(1200), secs(3600)));
+    }
+
+    #[test]
+    fn the_longest_stable_interval_may_lie_between_two_changes() {
+        let entries = membership(&[
+            record(1000, joined(1)),
+            record(9000, joined(2)),
+            record(10_000, left(2)),
+        ]);
+        let summary = summarize(&entries, secs(10));
+        assert_eq!(summary.longest_stable, (secs(1), secs(9)));
+    }
+
+    #[test]
+    fn an_empty_history_has_no_churn() {
+        let summary = summarize(&[], Duration::ZERO);
+        assert_eq!((summary.joins, summary.leaves), (0, 0));
+        assert_eq!(summary.churn_per_hour, 0.0);
+        assert_eq!(summary.longest_stable, (Duration::ZERO, Duration::ZERO));
+    }
+}
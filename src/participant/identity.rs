@@ -0,0 +1,466 @@
+//! Persistent Node Identity.
+//!
+//! Addresses change from one start to the next, with an ephemeral port, a new DHCP lease or
+//! another NAT mapping, so they can not tie the logs, reports and state files of a logical
+//! node together. A `NodeId` is a random 128-bit id generated at the first start and kept in
+//! an identity file, read again at every later start:
+//!
+//! ```plaintext
+//! gossip-p2p identity 1
+//! 5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e
+//! ```
+//!
+//! The file is `--identity-file` when given. Otherwise it lives in the platform data
+//! directory, named after `--node-label` or, failing that, the listen port; a participant on
+//! an ephemeral port without a label has no stable name to find its file by, so its id only
+//! lasts for the run. A file that does not parse is replaced by a new id, with a warning,
+//! rather than keeping the participant from starting.
+//!
+//! The id travels in the trailer of the handshake frames, after the protocol version, where
+//! older builds never look.
+//!
+//! A participant with an identity file also has a signing key, an Ed25519 key pair kept next
+//! to it with `.key` appended to its name and readable by its owner only. The key signs the
+//! membership snapshots the participant exports; the protocol does not use it. It is
+//! generated at the first start with an identity file, or when the key file does not parse:
+//!
+//! ```plaintext
+//! gossip-p2p signing key 1
+//! <the 32-byte secret key, as 64 hexadecimal digits>
+//! ```
+
+use crate::participant::state::{hex, unhex};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::Rng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The version of the identity file format written by this build.
+pub const IDENTITY_VERSION: u32 = 1;
+
+/// The first words of an identity file, followed by the format version.
+const IDENTITY_HEADER: &str = "gossip-p2p identity";
+
+/// The version of the signing key file format written by this build.
+pub const KEY_VERSION: u32 = 1;
+
+/// The first words of a signing key file, followed by the format version.
+const KEY_HEADER: &str = "gossip-p2p signing key";
+
+/// The persistent id of a logical node, the same across restarts and addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u128);
+
+impl NodeId {
+    /// Generates a new random id.
+    pub fn random() -> Self {
+        Self(rand::thread_rng().gen())
+    }
+}
+
+/// Formats the id as 32 lowercase hexadecimal digits.
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Serializes the id as its 32 hexadecimal digits, as in the files that keep it.
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse()
+            .map_err(|()| de::Error::custom(format!("\"{}\" is not a node id", text)))
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 {
+            return Err(());
+        }
+        u128::from_str_radix(s, 16).map(NodeId).map_err(|_| ())
+    }
+}
+
+/// How the id of this run was obtained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityOrigin {
+    /// Read from the identity file.
+    Loaded,
+    /// Generated because there was no identity file yet, and saved.
+    Created,
+    /// Generated because the identity file did not parse, and saved over it.
+    Regenerated,
+    /// Generated for this run only, since there is no identity file to keep it in.
+    Ephemeral,
+}
+
+/// The id of this participant and where it is kept.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// The id.
+    pub id: NodeId,
+    /// The identity file, `None` for an ephemeral id.
+    pub path: Option<PathBuf>,
+    /// How the id was obtained.
+    pub origin: IdentityOrigin,
+    /// The key signing the exports of the participant, `None` for an ephemeral id.
+    pub key: Option<SigningKey>,
+}
+
+impl Identity {
+    /// Reads the id from `path`, generating and saving a new one when the file is missing or
+    /// does not parse, and the signing key kept next to it the same way.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The identity file, `None` for an id lasting only for this run.
+    ///
+    /// # Returns
+    ///
+    /// The identity, or the error of a file that can neither be read nor written.
+    pub fn load_or_create(path: Option<PathBuf>) -> io::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self {
+                id: NodeId::random(),
+                path: None,
+                origin: IdentityOrigin::Ephemeral,
+                key: None,
+            });
+        };
+        let loaded = match fs::read_to_string(&path) {
+            Ok(content) => parse_identity(&content).ok_or(IdentityOrigin::Regenerated),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(IdentityOrigin::Created),
+            Err(err) => return Err(err),
+        };
+        let (id, origin) = match loaded {
+            Ok(id) => (id, IdentityOrigin::Loaded),
+            Err(origin) => {
+                let id = NodeId::random();
+                save_identity(&path, id)?;
+                (id, origin)
+            }
+        };
+        let key = load_or_create_key(&key_path(&path))?;
+        Ok(Self {
+            id,
+            path: Some(path),
+            origin,
+            key: Some(key),
+        })
+    }
+
+    /// Verifies that the identity file at `path` can be read, or that its directory can be
+    /// created when there is no file yet, without creating the file itself.
+    pub fn check(path: &Path) -> io::Result<()> {
+        match fs::read_to_string(path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+                _ => Ok(()),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Derives the identity file of a participant.
+///
+/// # Parameters
+///
+/// * `file` - The file given with `--identity-file`, used as is.
+/// * `label` - The `--node-label`, naming the file in the data directory.
+/// * `port` - The configured listen port, naming the file when there is no label; `0` names
+///   none.
+pub fn identity_path(file: Option<&Path>, label: Option<&str>, port: u16) -> Option<PathBuf> {
+    if let Some(file) = file {
+        return Some(file.to_path_buf());
+    }
+    let name = match label {
+        Some(label) => format!("node-{}.id", label),
+        None if port != 0 => format!("node-{}.id", port),
+        None => return None,
+    };
+    Some(data_dir().join("gossip-p2p").join(name))
+}
+
+/// Reads the public half of the signing key kept next to the identity file at `path`,
+/// without creating it.
+pub fn stored_key(path: &Path) -> Option<VerifyingKey> {
+    let content = fs::read_to_string(key_path(path)).ok()?;
+    Some(parse_key(&content)?.verifying_key())
+}
+
+/// Returns the signing key file kept next to the identity file at `path`.
+pub fn key_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".key");
+    PathBuf::from(name)
+}
+
+/// Determines whether `label` can name an identity file: letters, digits, `-`, `_` and `.`,
+/// not starting with a dot.
+pub fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && !label.starts_with('.')
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Reads the id from the content of an identity file.
+fn parse_identity(content: &str) -> Option<NodeId> {
+    let mut lines = content.lines().map(str::trim);
+    let version = lines
+        .next()?
+        .strip_prefix(IDENTITY_HEADER)?
+        .trim()
+        .parse::<u32>()
+        .ok()?;
+    if !(1..=IDENTITY_VERSION).contains(&version) {
+        return None;
+    }
+    lines.next()?.parse().ok()
+}
+
+/// Writes `id` to `path` through a temporary file, creating its directory if needed.
+fn save_identity(path: &Path, id: NodeId) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(
+        &temporary,
+        format!("{} {}\n{}\n", IDENTITY_HEADER, IDENTITY_VERSION, id),
+    )?;
+    fs::rename(&temporary, path)
+}
+
+/// Reads the signing key at `path`, generating and saving a new one when the file is missing
+/// or does not parse.
+fn load_or_create_key(path: &Path) -> io::Result<SigningKey> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            if let Some(key) = parse_key(&content) {
+                return Ok(key);
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let key = SigningKey::from_bytes(&rand::thread_rng().gen());
+    save_key(path, &key)?;
+    Ok(key)
+}
+
+/// Reads the signing key from the content of a key file.
+fn parse_key(content: &str) -> Option<SigningKey> {
+    let mut lines = content.lines().map(str::trim);
+    let version = lines
+        .next()?
+        .strip_prefix(KEY_HEADER)?
+        .trim()
+        .parse::<u32>()
+        .ok()?;
+    if !(1..=KEY_VERSION).contains(&version) {
+        return None;
+    }
+    let secret: [u8; 32] = unhex(lines.next()?)?.try_into().ok()?;
+    Some(SigningKey::from_bytes(&secret))
+}
+
+/// Writes `key` to `path` through a temporary file readable by its owner only.
+fn save_key(path: &Path, key: &SigningKey) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temporary)?;
+    io::Write::write_all(
+        &mut file,
+        format!("{} {}\n{}\n", KEY_HEADER, KEY_VERSION, hex(&key.to_bytes())).as_bytes(),
+    )?;
+    drop(file);
+    fs::rename(&temporary, path)
+}
+
+/// Returns the platform data directory.
+///
+/// Follows `$XDG_DATA_HOME`, then `$HOME/.local/share`, then the system temporary directory.
+fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::TempDir;
+
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn ids_are_32_hexadecimal_digits() {
+        let id = NodeId(0x5f0c_3a9e_1d2b_4c6f_8a7e_9d0b_1c2a_3f4e);
+        assert_eq!(id.to_string(), "5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e");
+        assert_eq!(NodeId(1).to_string(), "00000000000000000000000000000001");
+        assert_eq!(id.to_string().parse(), Ok(id));
+        assert_eq!("5F0C3A9E1D2B4C6F8A7E9D0B1C2A3F4E".parse(), Ok(id));
+        for text in [
+            "",
+            "1",
+            "5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4",
+            "5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4eg",
+        ] {
+            assert_eq!(text.parse::<NodeId>(), Err(()), "{}", text);
+        }
+        assert_ne!(NodeId::random(), NodeId::random());
+    }
+
+    #[test]
+    fn ids_serialize_as_their_digits() {
+        let id = NodeId(0xa41d);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, "\"0000000000000000000000000000a41d\"");
+        assert_eq!(serde_json::from_str::<NodeId>(&json).unwrap(), id);
+        assert!(serde_json::from_str::<NodeId>("\"a41d\"").is_err());
+    }
+
+    #[test]
+    fn an_identity_is_created_then_loaded() {
+        let dir = TempDir::new("identity-create");
+        let path = dir.0.join("nested").join("node.id");
+        let created = Identity::load_or_create(Some(path.clone())).unwrap();
+        assert_eq!(created.origin, IdentityOrigin::Created);
+        assert_eq!(created.path.as_deref(), Some(path.as_path()));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("gossip-p2p identity 1\n{}\n", created.id)
+        );
+
+        let loaded = Identity::load_or_create(Some(path.clone())).unwrap();
+        assert_eq!(loaded.origin, IdentityOrigin::Loaded);
+        assert_eq!(loaded.id, created.id);
+        assert_eq!(
+            loaded.key.unwrap().to_bytes(),
+            created.key.unwrap().to_bytes()
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(key_path(&path)).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    #[test]
+    fn a_corrupt_identity_or_key_is_regenerated() {
+        let dir = TempDir::new("identity-corrupt");
+        let path = dir.0.join("node.id");
+        let first = Identity::load_or_create(Some(path.clone())).unwrap();
+
+        fs::write(&path, "gossip-p2p identity 1\nnot an id\n").unwrap();
+        let regenerated = Identity::load_or_create(Some(path.clone())).unwrap();
+        assert_eq!(regenerated.origin, IdentityOrigin::Regenerated);
+        assert_ne!(regenerated.id, first.id);
+        // The key is kept, since its own file still parses.
+        assert_eq!(
+            regenerated.key.unwrap().to_bytes(),
+            first.key.as_ref().unwrap().to_bytes()
+        );
+
+        fs::write(key_path(&path), "garbage").unwrap();
+        let rekeyed = Identity::load_or_create(Some(path.clone())).unwrap();
+        assert_eq!(rekeyed.origin, IdentityOrigin::Loaded);
+        assert_ne!(
+            rekeyed.key.unwrap().to_bytes(),
+            first.key.unwrap().to_bytes()
+        );
+    }
+
+    #[test]
+    fn only_known_versions_of_the_files_parse() {
+        let id = "5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e";
+        assert!(parse_identity(&format!("gossip-p2p identity 1\n{}\n", id)).is_some());
+        assert!(parse_identity(&format!("  gossip-p2p identity 1  \n {} ", id)).is_some());
+        for content in [
+            format!("gossip-p2p identity 2\n{}\n", id),
+            format!("gossip-p2p identity 0\n{}\n", id),
+            format!("gossip-p2p identity\n{}\n", id),
+            format!("gossip-p2p signing key 1\n{}\n", id),
+            "gossip-p2p identity 1\n".to_owned(),
+        ] {
+            assert_eq!(parse_identity(&content), None, "{:?}", content);
+        }
+        let secret = hex(&[7; 32]);
+        assert!(parse_key(&format!("gossip-p2p signing key 1\n{}\n", secret)).is_some());
+        assert!(parse_key(&format!("gossip-p2p signing key 2\n{}\n", secret)).is_none());
+        assert!(parse_key(&format!("gossip-p2p signing key 1\n{}\n", hex(&[7; 31]))).is_none());
+    }
+
+    #[test]
+    fn an_identity_without_a_file_lasts_for_the_run() {
+        let identity = Identity::load_or_create(None).unwrap();
+        assert_eq!(identity.origin, IdentityOrigin::Ephemeral);
+        assert_eq!(identity.path, None);
+        assert!(identity.key.is_none());
+    }
+
+    #[test]
+    fn the_file_is_named_by_the_option_the_label_or_the_port() {
+        let given = Path::new("/srv/node.id");
+        assert_eq!(
+            identity_path(Some(given), Some("a"), 8080),
+            Some(given.to_path_buf())
+        );
+        let labeled = identity_path(None, Some("edge-1"), 8080).unwrap();
+        assert!(
+            labeled.ends_with("gossip-p2p/node-edge-1.id"),
+            "{:?}",
+            labeled
+        );
+        let numbered = identity_path(None, None, 8080).unwrap();
+        assert!(
+            numbered.ends_with("gossip-p2p/node-8080.id"),
+            "{:?}",
+            numbered
+        );
+        assert_eq!(identity_path(None, None, 0), None);
+        assert_eq!(key_path(given), PathBuf::from("/srv/node.id.key"));
+    }
+
+    #[test]
+    fn checking_creates_the_directory_but_not_the_file() {
+        let dir = TempDir::new("identity-check");
+        let path = dir.0.join("data").join("node.id");
+        Identity::check(&path).unwrap();
+        assert!(dir.0.join("data").is_dir());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,357 @@
+//! Message Inboxes.
+//!
+//! Subscriptions call back on the event loop, which suits code reacting to each message but not
+//! code that would rather poll. An `Inbox` keeps the application messages a participant
+//! receives, gossip texts and topic payloads, until its owner takes them with `try_recv` or
+//! waits for one with `recv_timeout`, from any thread.
+//!
+//! An inbox is a bounded ring of `--inbox-capacity` messages, 1024 by default: once it is
+//! full, a new message pushes the oldest one out and counts it as dropped, so a consumer that
+//! stops polling costs memory but never stalls the event loop, which only takes a short lock to
+//! push. Every open inbox gets its own copy of each message, so memory grows with the number of
+//! inboxes times their capacity. Inboxes can be opened at any time through
+//! `InboxRegistry::open`; one that is dropped is forgotten at the next delivery.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+/// An application message received from a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedMessage {
+    /// The public address of the sender.
+    pub sender: SocketAddr,
+    /// The topic of a topic message, `None` for a gossip text.
+    pub topic: Option<String>,
+    /// The time the message was received.
+    pub received_at: SystemTime,
+    /// The payload of the message, the bytes of the text for a gossip text.
+    pub payload: Vec<u8>,
+}
+
+/// The messages of one inbox, oldest first.
+#[derive(Debug)]
+struct Ring {
+    messages: VecDeque<ReceivedMessage>,
+    dropped: u64,
+}
+
+/// The state an inbox shares with the registry delivering to it.
+#[derive(Debug)]
+struct Shared {
+    ring: Mutex<Ring>,
+    arrived: Condvar,
+    capacity: usize,
+}
+
+impl Shared {
+    /// Appends a message, dropping the oldest one when the ring is full.
+    fn push(&self, message: ReceivedMessage) {
+        let mut ring = self.lock();
+        if ring.messages.len() == self.capacity {
+            ring.messages.pop_front();
+            ring.dropped += 1;
+        }
+        ring.messages.push_back(message);
+        drop(ring);
+        self.arrived.notify_one();
+    }
+
+    /// Locks the ring, even if a thread panicked while holding it.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Ring> {
+        self.ring
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A bounded queue of the messages received since it was opened.
+#[derive(Debug)]
+pub struct Inbox {
+    shared: Arc<Shared>,
+}
+
+impl Inbox {
+    /// Takes the oldest message, if any.
+    pub fn try_recv(&self) -> Option<ReceivedMessage> {
+        self.shared.lock().messages.pop_front()
+    }
+
+    /// Takes the oldest message, waiting up to `timeout` for one to arrive.
+    ///
+    /// A `timeout` further away than an `Instant` can tell waits until a message arrives.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ReceivedMessage> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut ring = self.shared.lock();
+        loop {
+            if let Some(message) = ring.messages.pop_front() {
+                return Some(message);
+            }
+            let Some(deadline) = deadline else {
+                ring = self
+                    .shared
+                    .arrived
+                    .wait(ring)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            ring = self
+                .shared
+                .arrived
+                .wait_timeout(ring, remaining)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    /// Returns the number of messages waiting.
+    pub fn len(&self) -> usize {
+        self.shared.lock().messages.len()
+    }
+
+    /// Determines whether no message is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().messages.is_empty()
+    }
+
+    /// Returns the number of messages the inbox holds before dropping the oldest.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of messages dropped because the inbox was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+}
+
+/// The open inboxes of a participant, delivered to by its event loop.
+#[derive(Debug, Clone)]
+pub struct InboxRegistry {
+    inboxes: Arc<Mutex<Vec<Weak<Shared>>>>,
+    capacity: usize,
+}
+
+impl InboxRegistry {
+    /// Constructs a registry opening inboxes of `capacity` messages, at least one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inboxes: Arc::new(Mutex::new(Vec::new())),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Opens an inbox receiving every message delivered from now on.
+    pub fn open(&self) -> Inbox {
+        let shared = Arc::new(Shared {
+            ring: Mutex::new(Ring {
+                messages: VecDeque::new(),
+                dropped: 0,
+            }),
+            arrived: Condvar::new(),
+            capacity: self.capacity,
+        });
+        self.lock().push(Arc::downgrade(&shared));
+        Inbox { shared }
+    }
+
+    /// Determines whether any inbox is open, so a message is worth copying for them.
+    pub fn is_active(&self) -> bool {
+        self.lock().iter().any(|inbox| inbox.strong_count() > 0)
+    }
+
+    /// Pushes a copy of `message` into every open inbox, forgetting the dropped ones.
+    pub fn deliver(&self, message: ReceivedMessage) {
+        let mut inboxes = self.lock();
+        inboxes.retain(|inbox| inbox.strong_count() > 0);
+        for inbox in inboxes.iter().filter_map(Weak::upgrade) {
+            inbox.push(message.clone());
+        }
+    }
+
+    /// Returns the number of open inboxes and the messages they dropped.
+    pub fn stats(&self) -> (usize, u64) {
+        let inboxes: Vec<Arc<Shared>> = self.lock().iter().filter_map(Weak::upgrade).collect();
+        let dropped = inboxes.iter().map(|inbox| inbox.lock().dropped).sum();
+        (inboxes.len(), dropped)
+    }
+
+    /// Locks the list of inboxes, even if a thread panicked while holding it.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Weak<Shared>>> {
+        self.inboxes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    use std::thread;
+
+    fn message(port: u16, payload: &[u8]) -> ReceivedMessage {
+        ReceivedMessage {
+            sender: addr(port),
+            topic: None,
+            received_at: SystemTime::now(),
+            payload: payload.to_vec(),
+        }
+    }
+
+    fn payloads(inbox: &Inbox) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| inbox.try_recv())
+            .map(|message| message.payload)
+            .collect()
+    }
+
+    #[test]
+    fn a_full_inbox_drops_the_oldest_message() {
+        let registry = InboxRegistry::new(3);
+        let inbox = registry.open();
+        for index in 0..5u8 {
+            registry.deliver(message(8080, &[index]));
+        }
+
+        assert_eq!(inbox.capacity(), 3);
+        assert_eq!(inbox.len(), 3);
+        assert_eq!(inbox.dropped(), 2);
+        assert_eq!(registry.stats(), (1, 2));
+        assert_eq!(payloads(&inbox), [[2], [3], [4]]);
+        assert!(inbox.is_empty());
+        // Taking messages does not undo the drops.
+        assert_eq!(inbox.dropped(), 2);
+    }
+
+    #[test]
+    fn the_capacity_is_at_least_one() {
+        let registry = InboxRegistry::new(0);
+        let inbox = registry.open();
+        registry.deliver(message(8080, b"a"));
+        registry.deliver(message(8080, b"b"));
+        assert_eq!(inbox.capacity(), 1);
+        assert_eq!(payloads(&inbox), [b"b"]);
+    }
+
+    #[test]
+    fn keeps_the_sender_topic_and_time_of_each_message() {
+        let registry = InboxRegistry::new(4);
+        let inbox = registry.open();
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let sent = ReceivedMessage {
+            sender: addr(8081),
+            topic: Some("news".to_owned()),
+            received_at,
+            payload: b"hello".to_vec(),
+        };
+        registry.deliver(sent.clone());
+        assert_eq!(inbox.try_recv(), Some(sent));
+        assert_eq!(inbox.try_recv(), None);
+    }
+
+    #[test]
+    fn every_inbox_gets_its_own_copy() {
+        let registry = InboxRegistry::new(4);
+        assert!(!registry.is_active());
+        let first = registry.open();
+        registry.deliver(message(8080, b"before"));
+        let second = registry.open();
+        registry.deliver(message(8080, b"after"));
+
+        assert!(registry.is_active());
+        assert_eq!(payloads(&first), [b"before".to_vec(), b"after".to_vec()]);
+        // An inbox only gets what is delivered once it is open.
+        assert_eq!(payloads(&second), [b"after".to_vec()]);
+    }
+
+    #[test]
+    fn a_dropped_inbox_is_forgotten() {
+        let registry = InboxRegistry::new(4);
+        let kept = registry.open();
+        drop(registry.open());
+        assert_eq!(registry.stats().0, 1);
+
+        registry.deliver(message(8080, b"a"));
+        assert_eq!(registry.lock().len(), 1);
+        drop(kept);
+        assert!(!registry.is_active());
+        registry.deliver(message(8080, b"b"));
+        assert!(registry.lock().is_empty());
+    }
+
+    #[test]
+    fn waits_for_a_message_until_the_timeout() {
+        let registry = InboxRegistry::new(4);
+        let inbox = registry.open();
+        let started = Instant::now();
+        assert_eq!(inbox.recv_timeout(Duration::from_millis(50)), None);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let delivering = registry.clone();
+        let pusher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            delivering.deliver(message(8080, b"late"));
+        });
+        let received = inbox.recv_timeout(Duration::from_secs(5));
+        assert_eq!(
+            received.map(|message| message.payload),
+            Some(b"late".to_vec())
+        );
+        pusher.join().unwrap();
+    }
+
+    #[test]
+    fn a_timeout_past_the_clock_waits_for_the_next_message() {
+        let registry = InboxRegistry::new(4);
+        let inbox = registry.open();
+        registry.deliver(message(8080, b"now"));
+        let received = inbox.recv_timeout(Duration::MAX - Duration::from_secs(1));
+        assert_eq!(
+            received.map(|message| message.payload),
+            Some(b"now".to_vec())
+        );
+
+        let delivering = registry.clone();
+        let pusher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            delivering.deliver(message(8080, b"later"));
+        });
+        let received = inbox.recv_timeout(Duration::MAX);
+        assert_eq!(
+            received.map(|message| message.payload),
+            Some(b"later".to_vec())
+        );
+        pusher.join().unwrap();
+    }
+
+    #[test]
+    fn concurrent_pushes_and_pops_lose_nothing_below_the_capacity() {
+        const SENT: u32 = 10_000;
+        let registry = InboxRegistry::new(SENT as usize);
+        let inbox = registry.open();
+
+        let delivering = registry.clone();
+        let pusher = thread::spawn(move || {
+            for index in 0..SENT {
+                delivering.deliver(message(8080, &index.to_be_bytes()));
+            }
+        });
+        let mut received = Vec::new();
+        while received.len() < SENT as usize {
+            let message = inbox
+                .recv_timeout(Duration::from_secs(5))
+                .expect("every message arrives");
+            received.push(u32::from_be_bytes(message.payload.try_into().unwrap()));
+        }
+        pusher.join().unwrap();
+
+        assert_eq!(received, (0..SENT).collect::<Vec<u32>>());
+        assert_eq!(inbox.dropped(), 0);
+    }
+}
@@ -0,0 +1,203 @@
+//! Topic Interest.
+//!
+//! Without it every publication goes to every peer, so a topic consumed by a single node costs
+//! the whole network. A participant therefore tells its peers which topics it subscribes to:
+//! in the trailer of its handshake frames, and in a `Message::InterestUpdate` to every peer
+//! speaking `INTERESTS_VERSION` whenever its subscriptions change, at most once per
+//! `INTEREST_DEBOUNCE` so that a burst of subscriptions is announced once.
+//!
+//! A publisher sends a topic payload to a peer when the peer's `Interest` covers the topic. A
+//! peer that never sent an update, an older build among them, has an unknown interest and is
+//! sent everything, as before; so is a peer subscribed to more than `MAX_INTEREST_TOPICS`
+//! topics, which announces every topic instead of its list. The topics given with
+//! `--topic-broadcast` skip the check and go to every peer.
+
+use crate::participant::message::builder::validate_interest;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// The most topics an interest update lists; a participant subscribed to more announces every
+/// topic instead.
+pub const MAX_INTEREST_TOPICS: usize = 32;
+
+/// The shortest time between two interest updates announcing a change of subscriptions.
+pub const INTEREST_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// The topics a peer wants to be sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Interest {
+    /// The peer never sent an interest update.
+    #[default]
+    Unknown,
+    /// The peer asked for every topic.
+    Everything,
+    /// The peer asked for these topics only.
+    Topics(HashSet<String>),
+}
+
+impl Interest {
+    /// Reads the interest carried by an interest update.
+    ///
+    /// A list longer than `MAX_INTEREST_TOPICS` breaks the cap its sender should have applied,
+    /// and is taken as a request for every topic rather than trusted. So is a list naming a
+    /// topic `validate_topic` refuses, which no participant can publish on anyway.
+    pub fn from_update(topics: Option<Vec<String>>) -> Self {
+        match topics {
+            Some(topics) if validate_interest(&topics).is_ok() => {
+                Interest::Topics(topics.into_iter().collect())
+            }
+            _ => Interest::Everything,
+        }
+    }
+
+    /// Determines whether a payload published on `topic` is to be sent to the peer.
+    pub fn wants(&self, topic: &str) -> bool {
+        match self {
+            Interest::Unknown | Interest::Everything => true,
+            Interest::Topics(topics) => topics.contains(topic),
+        }
+    }
+}
+
+/// Keeps the interest of this participant as announced to its peers, and the updates
+/// announcing its changes to one per `INTEREST_DEBOUNCE`.
+#[derive(Debug)]
+pub struct InterestAnnouncer {
+    current: Option<Vec<String>>,
+    armed: bool,
+    updates: u64,
+}
+
+impl InterestAnnouncer {
+    /// Constructs the announcer of a participant without subscriptions.
+    pub fn new() -> Self {
+        Self {
+            current: Some(Vec::new()),
+            armed: false,
+            updates: 0,
+        }
+    }
+
+    /// Returns the interest announced from now on, `None` standing for every topic.
+    pub fn current(&self) -> &Option<Vec<String>> {
+        &self.current
+    }
+
+    /// Records a change of subscriptions.
+    ///
+    /// # Parameters
+    ///
+    /// * `topics` - The topics subscribed to after the change, sorted.
+    ///
+    /// # Returns
+    ///
+    /// `true` when the announcement timer has to be armed, `false` when it already is.
+    pub fn changed(&mut self, topics: Vec<String>) -> bool {
+        self.current = validate_interest(&topics).is_ok().then_some(topics);
+        !std::mem::replace(&mut self.armed, true)
+    }
+
+    /// Takes the content of the update to send once the announcement timer fired.
+    ///
+    /// The update is sent even when the subscriptions are back to what was announced before,
+    /// since a peer may have shaken hands in between.
+    pub fn due(&mut self) -> Option<Vec<String>> {
+        self.armed = false;
+        self.updates += 1;
+        self.current.clone()
+    }
+
+    /// Returns the number of updates announcing a change of subscriptions.
+    pub fn updates(&self) -> u64 {
+        self.updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topics(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn many(count: usize) -> Vec<String> {
+        (0..count).map(|n| format!("topic-{}", n)).collect()
+    }
+
+    #[test]
+    fn an_update_lists_the_topics_wanted() {
+        let interest = Interest::from_update(Some(topics(&["metrics", "chat"])));
+        assert_eq!(
+            interest,
+            Interest::Topics(HashSet::from(["metrics".to_owned(), "chat".to_owned()]))
+        );
+        assert_eq!(
+            Interest::from_update(Some(Vec::new())),
+            Interest::Topics(HashSet::new())
+        );
+        assert_eq!(Interest::from_update(None), Interest::Everything);
+    }
+
+    #[test]
+    fn an_update_over_the_cap_or_with_a_bad_topic_asks_for_everything() {
+        assert!(matches!(
+            Interest::from_update(Some(many(MAX_INTEREST_TOPICS))),
+            Interest::Topics(_)
+        ));
+        assert_eq!(
+            Interest::from_update(Some(many(MAX_INTEREST_TOPICS + 1))),
+            Interest::Everything
+        );
+        assert_eq!(
+            Interest::from_update(Some(topics(&["chat", ""]))),
+            Interest::Everything
+        );
+        assert_eq!(
+            Interest::from_update(Some(topics(&["bad\ntopic"]))),
+            Interest::Everything
+        );
+    }
+
+    #[test]
+    fn a_payload_goes_to_interested_and_unknown_peers() {
+        let selective = Interest::from_update(Some(topics(&["metrics"])));
+        for (interest, topic, wanted) in [
+            (&selective, "metrics", true),
+            (&selective, "chat", false),
+            (&Interest::Topics(HashSet::new()), "metrics", false),
+            (&Interest::Unknown, "metrics", true),
+            (&Interest::Everything, "chat", true),
+        ] {
+            assert_eq!(interest.wants(topic), wanted, "{:?} {}", interest, topic);
+        }
+        assert_eq!(Interest::default(), Interest::Unknown);
+    }
+
+    #[test]
+    fn a_burst_of_changes_is_announced_once() {
+        let mut announcer = InterestAnnouncer::new();
+        assert_eq!(announcer.current(), &Some(Vec::new()));
+        assert!(announcer.changed(topics(&["chat"])));
+        assert!(!announcer.changed(topics(&["chat", "metrics"])));
+        assert!(!announcer.changed(topics(&["metrics"])));
+        assert_eq!(announcer.due(), Some(topics(&["metrics"])));
+        assert_eq!(announcer.updates(), 1);
+
+        // The next change arms the timer again.
+        assert!(announcer.changed(topics(&["metrics", "news"])));
+        assert_eq!(announcer.due(), Some(topics(&["metrics", "news"])));
+        assert_eq!(announcer.updates(), 2);
+    }
+
+    #[test]
+    fn subscriptions_over_the_cap_announce_every_topic() {
+        let mut announcer = InterestAnnouncer::new();
+        announcer.changed(many(MAX_INTEREST_TOPICS + 1));
+        assert_eq!(announcer.current(), &None);
+        assert_eq!(announcer.due(), None);
+        announcer.changed(many(MAX_INTEREST_TOPICS));
+        assert_eq!(announcer.due(), Some(many(MAX_INTEREST_TOPICS)));
+    }
+}
@@ -0,0 +1,230 @@
+//! Listen Interfaces.
+//!
+//! A participant listens on `127.0.0.1:<port>` and on every `--listen` address, so a host
+//! attached to several networks runs a single participant reachable from all of them. Each
+//! listener is an interface with its own advertise address: the public address announced to
+//! the peers of that interface, which defaults to the listen address itself.
+//!
+//! Peers belong to the interface their connection came through, and the participants dialed
+//! from a list belong to the interface of the peer that sent the list. The participants list
+//! shared with a peer only holds the participants of its own interface, since a peer on one
+//! network can not be expected to reach the addresses of another. Gossip still flows across:
+//! this participant broadcasts to the peers of every interface.
+
+use crate::participant::utils::canonical;
+
+use crate::participant::transport::ListenerId;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// The interface of the `127.0.0.1:<port>` listener, and of every peer whose interface is not
+/// otherwise known.
+pub const PRIMARY_INTERFACE: usize = 0;
+
+/// One listener and the address announced to the peers connected through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interface {
+    /// The address the listener is bound to.
+    pub listen: SocketAddr,
+    /// The public address announced to the peers of this interface.
+    pub advertise: SocketAddr,
+}
+
+/// The interfaces of a participant, indexed by the order their listeners were opened in.
+#[derive(Debug, Default)]
+pub struct Interfaces {
+    interfaces: Vec<Interface>,
+    listeners: HashMap<ListenerId, usize>,
+}
+
+impl Interfaces {
+    /// Creates a table without interfaces; the first one added is `PRIMARY_INTERFACE`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the interface of a listener.
+    ///
+    /// # Parameters
+    ///
+    /// * `listener` - The resource id of the listener.
+    /// * `listen` - The address the listener is bound to.
+    /// * `advertise` - The address announced to its peers, `listen` if `None`.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new interface.
+    pub fn add(
+        &mut self,
+        listener: ListenerId,
+        listen: SocketAddr,
+        advertise: Option<SocketAddr>,
+    ) -> usize {
+        let index = self.interfaces.len();
+        self.interfaces.push(Interface {
+            listen,
+            advertise: advertise.unwrap_or(listen),
+        });
+        self.listeners.insert(listener, index);
+        index
+    }
+
+    /// Returns the advertise address of `PRIMARY_INTERFACE`, the address this participant is
+    /// known by in logs, snapshots and recordings.
+    pub fn primary(&self) -> SocketAddr {
+        self.advertise(PRIMARY_INTERFACE)
+    }
+
+    /// Returns the address announced to the peers of `interface`.
+    ///
+    /// # Parameters
+    ///
+    /// * `interface` - The index of the interface; an unknown index falls back to the primary.
+    pub fn advertise(&self, interface: usize) -> SocketAddr {
+        self.interfaces
+            .get(interface)
+            .unwrap_or(&self.interfaces[PRIMARY_INTERFACE])
+            .advertise
+    }
+
+    /// Returns the interface of the connections accepted by `listener`.
+    ///
+    /// # Parameters
+    ///
+    /// * `listener` - The resource id of the listener, as given by `NetEvent::Accepted`.
+    pub fn of_listener(&self, listener: ListenerId) -> usize {
+        self.listeners
+            .get(&listener)
+            .copied()
+            .unwrap_or(PRIMARY_INTERFACE)
+    }
+
+    /// Returns the interface of an outbound connection from its local address: the first
+    /// interface listening on the same IP address, or `PRIMARY_INTERFACE`.
+    ///
+    /// # Parameters
+    ///
+    /// * `local` - The local address of the connection.
+    pub fn of_local_addr(&self, local: SocketAddr) -> usize {
+        self.interfaces
+            .iter()
+            .position(|interface| interface.listen.ip() == local.ip())
+            .unwrap_or(PRIMARY_INTERFACE)
+    }
+
+    /// Determines whether `addr` is one of the listen or advertise addresses of this
+    /// participant.
+    ///
+    /// Addresses are compared by their canonical form, so the IPv4-mapped form of an own
+    /// address is own too. A listener bound to an unspecified address also owns the loopback
+    /// and unspecified addresses of its port.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The address to check.
+    pub fn is_own(&self, addr: SocketAddr) -> bool {
+        let addr = canonical(addr);
+        self.interfaces.iter().any(|interface| {
+            let listen = canonical(interface.listen);
+            listen == addr
+                || canonical(interface.advertise) == addr
+                || (listen.ip().is_unspecified()
+                    && listen.port() == addr.port()
+                    && (addr.ip().is_loopback() || addr.ip().is_unspecified()))
+        })
+    }
+
+    /// Iterates over the interfaces after the primary one.
+    pub fn secondary(&self) -> impl Iterator<Item = &Interface> {
+        self.interfaces.iter().skip(PRIMARY_INTERFACE + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    /// The interfaces of a participant on port 8080 that also listens on `127.0.0.2:8080`,
+    /// advertised as `10.0.0.2:8080`, and on every address of port 9090.
+    fn dual_homed() -> Interfaces {
+        let mut interfaces = Interfaces::new();
+        assert_eq!(
+            interfaces.add(ListenerId::new(1), addr(8080), None),
+            PRIMARY_INTERFACE
+        );
+        let data = "127.0.0.2:8080".parse().unwrap();
+        let advertised = "10.0.0.2:8080".parse().unwrap();
+        assert_eq!(
+            interfaces.add(ListenerId::new(2), data, Some(advertised)),
+            1
+        );
+        let any = "0.0.0.0:9090".parse().unwrap();
+        assert_eq!(interfaces.add(ListenerId::new(3), any, None), 2);
+        interfaces
+    }
+
+    #[test]
+    fn each_listener_advertises_its_own_address() {
+        let interfaces = dual_homed();
+        assert_eq!(interfaces.primary(), addr(8080));
+        assert_eq!(interfaces.advertise(PRIMARY_INTERFACE), addr(8080));
+        assert_eq!(
+            interfaces.advertise(interfaces.of_listener(ListenerId::new(2))),
+            "10.0.0.2:8080".parse().unwrap()
+        );
+        assert_eq!(
+            interfaces.advertise(interfaces.of_listener(ListenerId::new(3))),
+            "0.0.0.0:9090".parse().unwrap()
+        );
+        // An unknown listener or index falls back to the primary interface.
+        assert_eq!(
+            interfaces.of_listener(ListenerId::new(4)),
+            PRIMARY_INTERFACE
+        );
+        assert_eq!(interfaces.advertise(7), addr(8080));
+        assert_eq!(
+            interfaces
+                .secondary()
+                .map(|interface| interface.listen)
+                .collect::<Vec<_>>(),
+            [
+                "127.0.0.2:8080".parse().unwrap(),
+                "0.0.0.0:9090".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn an_outbound_connection_belongs_to_the_interface_of_its_local_address() {
+        let interfaces = dual_homed();
+        assert_eq!(
+            interfaces.of_local_addr("127.0.0.2:40000".parse().unwrap()),
+            1
+        );
+        assert_eq!(interfaces.of_local_addr(addr(40000)), PRIMARY_INTERFACE);
+        assert_eq!(
+            interfaces.of_local_addr("192.168.1.5:40000".parse().unwrap()),
+            PRIMARY_INTERFACE
+        );
+    }
+
+    #[test]
+    fn listen_and_advertise_addresses_are_own() {
+        let interfaces = dual_homed();
+        for own in [
+            "127.0.0.1:8080",
+            "[::ffff:127.0.0.1]:8080",
+            "127.0.0.2:8080",
+            "10.0.0.2:8080",
+            "0.0.0.0:9090",
+            "127.0.0.1:9090",
+            "[::1]:9090",
+        ] {
+            assert!(interfaces.is_own(own.parse().unwrap()), "{}", own);
+        }
+        for other in ["127.0.0.1:8081", "10.0.0.2:9090", "192.168.1.5:9090"] {
+            assert!(!interfaces.is_own(other.parse().unwrap()), "{}", other);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Join Checks.
+//!
+//! With `--join-check=<k>,<timeout>`, a participant is a probe answering one question: can a
+//! new node join this network and handshake with `k` peers within `timeout`? It bootstraps as
+//! usual but does not gossip, and each time a peer completes its handshake it checks
+//! `JoinCheck::is_complete`. It then prints a `JoinResult` as one JSON line and shuts down
+//! gracefully, closing its connections so its peers drop it at once:
+//!
+//! ```plaintext
+//! {"joined":true,"peers":["127.0.0.1:8080","127.0.0.1:8081"],"elapsed_ms":412}
+//! ```
+//!
+//! When the timeout elapses first, the line tells `"joined":false` with the peers reached so
+//! far, and the process exits with the code of an unreachable bootstrap.
+
+use serde::{Serialize, Serializer};
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How many peers a join check waits for, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinCheck {
+    /// The number of distinct peers to handshake with, at least 1.
+    pub peers: usize,
+    /// The time the peers have to complete their handshakes, from the start.
+    pub timeout: Duration,
+}
+
+impl JoinCheck {
+    /// Determines whether handshakes with `handshaked` distinct peers complete the check.
+    pub fn is_complete(&self, handshaked: usize) -> bool {
+        handshaked >= self.peers
+    }
+}
+
+/// The outcome of a join check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JoinResult {
+    /// Whether enough peers completed their handshakes in time.
+    pub joined: bool,
+    /// The public addresses of the peers that completed their handshakes, sorted.
+    pub peers: Vec<SocketAddr>,
+    /// The time from the start to the outcome.
+    #[serde(rename = "elapsed_ms", serialize_with = "millis")]
+    pub elapsed: Duration,
+}
+
+impl JoinResult {
+    /// Formats the result as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a join result serializes")
+    }
+}
+
+/// Serializes a duration as its milliseconds.
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    #[test]
+    fn the_check_completes_at_k_peers() {
+        let check = JoinCheck {
+            peers: 2,
+            timeout: Duration::from_secs(10),
+        };
+        assert!(!check.is_complete(0));
+        assert!(!check.is_complete(1));
+        assert!(check.is_complete(2));
+        assert!(check.is_complete(5));
+    }
+
+    #[test]
+    fn the_result_is_one_line_of_json() {
+        let joined = JoinResult {
+            joined: true,
+            peers: vec![addr(8080), addr(8081)],
+            elapsed: Duration::from_millis(412),
+        };
+        assert_eq!(
+            joined.to_json(),
+            "{\"joined\":true,\"peers\":[\"127.0.0.1:8080\",\"127.0.0.1:8081\"],\"elapsed_ms\":412}"
+        );
+
+        let timed_out = JoinResult {
+            joined: false,
+            peers: Vec::new(),
+            elapsed: Duration::from_secs(10),
+        };
+        let parsed: serde_json::Value = serde_json::from_str(&timed_out.to_json()).unwrap();
+        assert_eq!(parsed["joined"], false);
+        assert_eq!(parsed["peers"].as_array().unwrap().len(), 0);
+        assert_eq!(parsed["elapsed_ms"], 10_000);
+    }
+}
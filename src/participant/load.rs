@@ -0,0 +1,375 @@
+//! Load Shedding.
+//!
+//! When a participant receives more than its event loop can handle, every event waits longer
+//! in the queue, timers included. The maintenance tick is armed for `UPDATE_INTERVAL` after the
+//! previous one ended, so how late it fires is the time the events queued ahead of it waited:
+//! the lag of the event loop, sampled once per tick. `LoadShedder` turns the lag into a
+//! `LoadState`:
+//!
+//! - `Busy` from `--busy-lag` on, 250ms by default: the housekeeping that can wait does, so
+//!   audits are deferred and neither heal probes nor split-brain bridging run;
+//! - `Overloaded` from `--overload-lag` on, 2s by default: the application payloads received
+//!   are dropped as well, counted, while the protocol messages keeping the membership are still
+//!   handled.
+//!
+//! A state is entered at the first sample over its threshold but only left once the lag stayed
+//! under half of it for `LOAD_RECOVERY`, so a lag hovering around a threshold does not switch
+//! the state at every tick.
+
+use crate::participant::message::MessageKind;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How long the lag has to stay under half of the threshold of a state before it is left.
+pub const LOAD_RECOVERY: Duration = Duration::from_secs(5);
+
+/// The shortest time between two warnings about dropped payloads.
+pub const SHED_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How far the event loop of a participant falls behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoadState {
+    /// The events are handled in time.
+    Normal,
+    /// The lag reached the soft threshold; deferrable housekeeping is skipped.
+    Busy,
+    /// The lag reached the hard threshold; inbound application payloads are dropped too.
+    Overloaded,
+}
+
+impl LoadState {
+    /// Returns the human-readable name of this state.
+    pub fn name(self) -> &'static str {
+        match self {
+            LoadState::Normal => "normal",
+            LoadState::Busy => "busy",
+            LoadState::Overloaded => "overloaded",
+        }
+    }
+
+    /// Determines whether the deferrable housekeeping is skipped in this state.
+    pub fn defers_housekeeping(self) -> bool {
+        self >= LoadState::Busy
+    }
+
+    /// Determines whether an inbound message of `kind` is dropped in this state: only the
+    /// application payloads and requests of an overloaded participant are.
+    pub fn sheds(self, kind: MessageKind) -> bool {
+        use MessageKind::*;
+
+        self == LoadState::Overloaded
+            && matches!(
+                kind,
+                Text | RoundText | Topic | SequencedTopic | VersionedTopic | Request
+            )
+    }
+}
+
+impl fmt::Display for LoadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A change of `LoadState`, with the lag that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadChange {
+    /// The state left.
+    pub from: LoadState,
+    /// The state entered.
+    pub to: LoadState,
+    /// The lag of the sample that changed the state.
+    pub lag: Duration,
+}
+
+/// Tracks the lag of the event loop and the state it puts the participant in.
+#[derive(Debug)]
+pub struct LoadShedder {
+    busy_lag: Duration,
+    overload_lag: Duration,
+    state: LoadState,
+    calm_since: Option<Instant>,
+    last_lag: Duration,
+    worst_lag: Duration,
+    changes: u64,
+    shed: u64,
+    unreported: u64,
+    last_warning: Option<Instant>,
+}
+
+impl LoadShedder {
+    /// Constructs a shedder in the `Normal` state.
+    ///
+    /// # Parameters
+    ///
+    /// * `busy_lag` - The lag making the participant busy.
+    /// * `overload_lag` - The lag making it overloaded, longer than `busy_lag`.
+    pub fn new(busy_lag: Duration, overload_lag: Duration) -> Self {
+        Self {
+            busy_lag,
+            overload_lag,
+            state: LoadState::Normal,
+            calm_since: None,
+            last_lag: Duration::ZERO,
+            worst_lag: Duration::ZERO,
+            changes: 0,
+            shed: 0,
+            unreported: 0,
+            last_warning: None,
+        }
+    }
+
+    /// Sets the lags of the `Busy` and `Overloaded` states, applied from the next observed lag.
+    pub fn set_lags(&mut self, busy_lag: Duration, overload_lag: Duration) {
+        self.busy_lag = busy_lag;
+        self.overload_lag = overload_lag;
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> LoadState {
+        self.state
+    }
+
+    /// Records the lag measured at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The change of state the sample caused, if any.
+    pub fn observe(&mut self, lag: Duration, now: Instant) -> Option<LoadChange> {
+        self.last_lag = lag;
+        self.worst_lag = self.worst_lag.max(lag);
+
+        let target = self.classify(lag);
+        if target > self.state {
+            self.calm_since = None;
+            return Some(self.enter(target, lag));
+        }
+        let threshold = match self.state {
+            LoadState::Normal => return None,
+            LoadState::Busy => self.busy_lag,
+            LoadState::Overloaded => self.overload_lag,
+        };
+        if lag >= threshold / 2 {
+            self.calm_since = None;
+            return None;
+        }
+        let calm_since = *self.calm_since.get_or_insert(now);
+        if now.saturating_duration_since(calm_since) < LOAD_RECOVERY {
+            return None;
+        }
+        self.calm_since = None;
+        Some(self.enter(target, lag))
+    }
+
+    /// Counts an inbound payload dropped because the participant is overloaded.
+    pub fn record_shed(&mut self) {
+        self.shed += 1;
+        self.unreported += 1;
+    }
+
+    /// Takes the number of payloads dropped since the last warning, if one is due at `now`.
+    pub fn take_warning(&mut self, now: Instant) -> Option<u64> {
+        if self.unreported == 0 {
+            return None;
+        }
+        if let Some(last) = self.last_warning {
+            if now.saturating_duration_since(last) < SHED_WARNING_INTERVAL {
+                return None;
+            }
+        }
+        self.last_warning = Some(now);
+        Some(std::mem::take(&mut self.unreported))
+    }
+
+    /// Returns the lag of the last sample.
+    pub fn last_lag(&self) -> Duration {
+        self.last_lag
+    }
+
+    /// Returns the longest lag sampled.
+    pub fn worst_lag(&self) -> Duration {
+        self.worst_lag
+    }
+
+    /// Returns the number of state changes.
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// Returns the number of inbound payloads dropped.
+    pub fn shed(&self) -> u64 {
+        self.shed
+    }
+
+    /// Returns the state a lag of `lag` reaches at once.
+    fn classify(&self, lag: Duration) -> LoadState {
+        if lag >= self.overload_lag {
+            LoadState::Overloaded
+        } else if lag >= self.busy_lag {
+            LoadState::Busy
+        } else {
+            LoadState::Normal
+        }
+    }
+
+    /// Moves to `to` because of a sample of `lag`.
+    fn enter(&mut self, to: LoadState, lag: Duration) -> LoadChange {
+        let from = std::mem::replace(&mut self.state, to);
+        self.changes += 1;
+        LoadChange { from, to, lag }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUSY: Duration = Duration::from_millis(250);
+    const OVERLOAD: Duration = Duration::from_secs(2);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn a_state_is_entered_at_the_first_sample_over_its_threshold() {
+        let now = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        assert_eq!(shedder.observe(ms(249), now), None);
+        assert_eq!(
+            shedder.observe(BUSY, now),
+            Some(LoadChange {
+                from: LoadState::Normal,
+                to: LoadState::Busy,
+                lag: BUSY,
+            })
+        );
+        assert_eq!(shedder.observe(ms(1999), now), None);
+        let change = shedder.observe(OVERLOAD, now).unwrap();
+        assert_eq!(
+            (change.from, change.to),
+            (LoadState::Busy, LoadState::Overloaded)
+        );
+        assert_eq!(shedder.changes(), 2);
+
+        // Straight from normal to overloaded.
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        let change = shedder.observe(ms(5000), now).unwrap();
+        assert_eq!(
+            (change.from, change.to),
+            (LoadState::Normal, LoadState::Overloaded)
+        );
+    }
+
+    #[test]
+    fn a_state_is_left_after_a_calm_recovery() {
+        let start = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        shedder.observe(BUSY, start);
+
+        // Under the threshold, but not under half of it.
+        assert_eq!(shedder.observe(ms(125), start + LOAD_RECOVERY * 2), None);
+        assert_eq!(shedder.state(), LoadState::Busy);
+
+        let calm = start + LOAD_RECOVERY * 3;
+        assert_eq!(shedder.observe(ms(124), calm), None);
+        assert_eq!(shedder.observe(ms(10), calm + LOAD_RECOVERY - ms(1)), None);
+        let change = shedder.observe(ms(10), calm + LOAD_RECOVERY).unwrap();
+        assert_eq!(
+            (change.from, change.to),
+            (LoadState::Busy, LoadState::Normal)
+        );
+        assert_eq!(change.lag, ms(10));
+    }
+
+    #[test]
+    fn a_spike_restarts_the_recovery() {
+        let start = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        shedder.observe(BUSY, start);
+        shedder.observe(ms(10), start);
+        shedder.observe(ms(200), start + ms(4000));
+        assert_eq!(shedder.observe(ms(10), start + LOAD_RECOVERY), None);
+        assert_eq!(shedder.observe(ms(10), start + ms(9999)), None);
+        assert!(shedder.observe(ms(10), start + ms(10_000)).is_some());
+    }
+
+    #[test]
+    fn an_overloaded_participant_recovers_to_the_state_of_its_lag() {
+        let start = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        shedder.observe(OVERLOAD, start);
+        shedder.observe(ms(500), start);
+        let change = shedder.observe(ms(500), start + LOAD_RECOVERY).unwrap();
+        assert_eq!(
+            (change.from, change.to),
+            (LoadState::Overloaded, LoadState::Busy)
+        );
+
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        shedder.observe(OVERLOAD, start);
+        shedder.observe(ms(5), start);
+        let change = shedder.observe(ms(5), start + LOAD_RECOVERY).unwrap();
+        assert_eq!(change.to, LoadState::Normal);
+        assert_eq!(shedder.worst_lag(), OVERLOAD);
+        assert_eq!(shedder.last_lag(), ms(5));
+    }
+
+    #[test]
+    fn only_an_overloaded_participant_sheds_payloads() {
+        for kind in MessageKind::ALL {
+            assert!(!LoadState::Normal.sheds(kind));
+            assert!(!LoadState::Busy.sheds(kind));
+        }
+        assert!(LoadState::Overloaded.sheds(MessageKind::Text));
+        assert!(LoadState::Overloaded.sheds(MessageKind::Request));
+        for kind in [
+            MessageKind::PublicAddress,
+            MessageKind::PushParticipantsList,
+            MessageKind::SharedPeers,
+            MessageKind::Probe,
+            MessageKind::Response,
+        ] {
+            assert!(!LoadState::Overloaded.sheds(kind), "{}", kind.name());
+        }
+        assert!(!LoadState::Normal.defers_housekeeping());
+        assert!(LoadState::Busy.defers_housekeeping());
+        assert!(LoadState::Overloaded.defers_housekeeping());
+    }
+
+    #[test]
+    fn shed_warnings_are_rate_limited() {
+        let start = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        assert_eq!(shedder.take_warning(start), None);
+        shedder.record_shed();
+        shedder.record_shed();
+        assert_eq!(shedder.take_warning(start), Some(2));
+        shedder.record_shed();
+        assert_eq!(
+            shedder.take_warning(start + SHED_WARNING_INTERVAL - ms(1)),
+            None
+        );
+        assert_eq!(shedder.take_warning(start + SHED_WARNING_INTERVAL), Some(1));
+        assert_eq!(
+            shedder.take_warning(start + SHED_WARNING_INTERVAL * 3),
+            None
+        );
+        assert_eq!(shedder.shed(), 3);
+    }
+
+    #[test]
+    fn new_lags_apply_from_the_next_sample() {
+        let now = Instant::now();
+        let mut shedder = LoadShedder::new(BUSY, OVERLOAD);
+        shedder.observe(ms(300), now);
+        assert_eq!(shedder.state(), LoadState::Busy);
+        shedder.set_lags(ms(100), ms(300));
+        assert_eq!(shedder.state(), LoadState::Busy);
+        assert_eq!(
+            shedder.observe(ms(300), now).unwrap().to,
+            LoadState::Overloaded
+        );
+    }
+}
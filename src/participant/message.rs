@@ -23,20 +23,200 @@
 //! - `PushParticipantsList`: Requests the receiver to share its list of known participants.
 //! - `PullParticipantsList`: Shares a list of known participants with the receiver.
 //! - `Text`: Sends a free-form text message, allowing for versatile communication.
+//! - `ObserverHello`: Subscribes a non-member observer to membership updates.
+//! - `ObserverSnapshot`: Gives a new observer the current peer list and statistics.
+//! - `ObserverUpdate`: Streams coalesced membership changes and statistics to observers.
+//! - `ObserverRejected`: Refuses an observer because the observer cap is reached.
+//! - `PleaseIntroduce`: Asks a common neighbor to make a participant that can not be dialed
+//!   dial the sender instead.
+//! - `IntroductionRequest`: Relays such a request to the participant that should dial back.
+//! - `Topic`: Carries an application payload published on a named topic.
+//! - `SharedPeers`: Shares the best known participants, each tagged with how recently it was
+//!   verified; sent instead of `PullParticipantsList` to peers speaking protocol version 2.
+//! - `Request`: Asks one peer to answer a payload on a topic; only sent to peers speaking
+//!   protocol version 3.
+//! - `Response`: Answers a `Request`, with the handler's payload or an error.
+//! - `Batch`: Carries several of the messages above in a single frame; only sent to peers
+//!   speaking protocol version 4.
+//! - `RoundText`: Sends a gossip text along with the round it was broadcast in, with
+//!   `--rounds`; only sent to peers speaking protocol version 5.
+//! - `SequencedTopic`: Carries a topic payload along with the publisher's sequence number for
+//!   the topic; sent instead of `Topic` to peers speaking protocol version 6.
+//! - `Probe`: Asks a peer to echo an id back at once, to measure the round trip; only sent to
+//!   peers speaking protocol version 7.
+//! - `ProbeReply`: Echoes a `Probe`.
+//! - `InterestUpdate`: Tells which topics the sender subscribes to, so publishers can leave it
+//!   out of the others; only sent to peers speaking protocol version 8.
+//! - `ListRequest`: Asks for the participants list like `PushParticipantsList`, with an id the
+//!   answer echoes; sent instead of it to peers speaking protocol version 9.
+//! - `ListReply`: Answers a `ListRequest` with the list `SharedPeers` would carry.
+//! - `ClockRequest`: Asks a peer for its wall-clock time, to estimate how far its clock is
+//!   from ours; only sent to peers speaking protocol version 10.
+//! - `ClockReply`: Answers a `ClockRequest` with the times it was received and answered.
+//! - `VersionedTopic`: Carries a topic payload like `SequencedTopic`, along with the schema
+//!   it follows; sent instead of it for a payload published with a schema to peers speaking
+//!   protocol version 11.
+//! - `SchemaUpdate`: Tells which payload schemas the sender handles on which topics, see the
+//!   `schema` module; only sent to peers speaking protocol version 11.
+//! - `CompactListReply`: Answers a `ListRequest` like `ListReply`, with the list packed by
+//!   the `compactlist` module; sent instead of it to peers speaking protocol version 12.
+//!
+//! ## Protocol Versions
+//!
+//! `PublicAddress` frames carry the sender's `PROTOCOL_VERSION` as a trailer after the bincode
+//! body. Older builds decode the body and ignore the trailing bytes, so the version can be
+//! announced to anyone, while a peer that announces none is assumed to speak
+//! `BASE_PROTOCOL_VERSION` and is never sent a message it does not know. `SharedPeers` frames
+//! carry the same trailer, which tells a dialer the version of the participant it dialed.
+//! The trailer may go on with the persistent `NodeId` of the sender, which older builds never
+//! read; a frame without it comes from a peer whose id is unknown. After the id comes the
+//! interest of the sender, the topics it subscribes to, which a frame from an older build
+//! lacks as well, after the interest its effective period, in milliseconds, and after the
+//! period the `--network` name of the sender, which only a sender given one writes.
 //!
 //! Each message type is designed to fulfill specific roles within the network's communication
 //! protocol, ensuring that participants can effectively discover each other, establish connections,
 //! and exchange information.
+//!
+//! ## Building Messages
+//!
+//! The `builder` submodule builds each message with the checks the participant applies to
+//! the ones it sends, and writes and reads the frames of the wire, for harnesses and tools
+//! talking to a network from outside.
 
-use serde::{Deserialize, Serialize};
+pub mod builder;
+
+use crate::participant::compactlist::PackedList;
+use crate::participant::identity::NodeId;
+use crate::participant::schema::SchemaSupport;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cell::Cell;
 use std::net::SocketAddr;
+use std::time::Duration;
+
+/// The version of the protocol spoken by this build; version 2 adds `SharedPeers`, version 3
+/// `Request` and `Response`, version 4 `Batch`, version 5 `RoundText`, version 6
+/// `SequencedTopic`, version 7 `Probe` and `ProbeReply`, version 8 `InterestUpdate`, version 9
+/// `ListRequest` and `ListReply`, version 10 `ClockRequest` and `ClockReply`, version 11
+/// `VersionedTopic` and `SchemaUpdate`, version 12 `CompactListReply`.
+pub const PROTOCOL_VERSION: u16 = 12;
+
+/// How deep a batch may sit within other batches of a frame that still decodes: deep enough
+/// for a batch holding a batch to be reported as such, shallow enough that a crafted frame of
+/// batches nested thousands of times can not exhaust the stack of the decoder.
+const MAX_BATCH_DEPTH: usize = 2;
+
+thread_local! {
+    /// The batches the decoder of this thread is within.
+    static BATCH_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The first version of the protocol understanding `SharedPeers`.
+pub const SHARED_PEERS_VERSION: u16 = 2;
+
+/// The first version of the protocol understanding `Request` and `Response`.
+pub const REQUESTS_VERSION: u16 = 3;
+
+/// The first version of the protocol understanding `Batch`.
+pub const BATCH_VERSION: u16 = 4;
+
+/// The first version of the protocol understanding `RoundText`.
+pub const ROUNDS_VERSION: u16 = 5;
+
+/// The first version of the protocol understanding `SequencedTopic`.
+pub const SEQUENCED_TOPICS_VERSION: u16 = 6;
+
+/// The first version of the protocol understanding `Probe` and `ProbeReply`.
+pub const PROBES_VERSION: u16 = 7;
+
+/// The first version of the protocol understanding `InterestUpdate`.
+pub const INTERESTS_VERSION: u16 = 8;
+
+/// The first version of the protocol understanding `ListRequest` and `ListReply`.
+pub const LIST_EXCHANGES_VERSION: u16 = 9;
+
+/// The first version of the protocol understanding `ClockRequest` and `ClockReply`.
+pub const CLOCKS_VERSION: u16 = 10;
+
+/// The first version of the protocol understanding `VersionedTopic` and `SchemaUpdate`.
+pub const SCHEMAS_VERSION: u16 = 11;
+
+/// The first version of the protocol understanding `CompactListReply`.
+pub const COMPACT_LISTS_VERSION: u16 = 12;
+
+/// The version of the protocol assumed for a peer that never announced one.
+pub const BASE_PROTOCOL_VERSION: u16 = 1;
+
+/// The longest time since verification for a shared peer to count as fresh.
+pub const FRESH_AGE: Duration = Duration::from_secs(60);
+
+/// The longest time since verification for a shared peer to count as recent.
+pub const RECENT_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// How long ago a shared participant was last verified, rounded to a coarse bucket.
+///
+/// The order of the variants is the order in which a receiver dials learned addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AgeBucket {
+    /// Verified less than `FRESH_AGE` ago.
+    Fresh,
+    /// Verified less than `RECENT_AGE` ago.
+    Recent,
+    /// Verified longer ago, or never.
+    Stale,
+}
+
+impl AgeBucket {
+    /// Returns the bucket of a participant verified `age` ago, or never verified for `None`.
+    pub fn of(age: Option<Duration>) -> Self {
+        match age {
+            Some(age) if age < FRESH_AGE => AgeBucket::Fresh,
+            Some(age) if age < RECENT_AGE => AgeBucket::Recent,
+            _ => AgeBucket::Stale,
+        }
+    }
+}
+
+/// A participant address shared in a `SharedPeers` list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedPeer {
+    /// The public address of the participant.
+    pub addr: SocketAddr,
+    /// How recently the sender verified the participant.
+    pub age: AgeBucket,
+}
+
+/// Aggregate counters of a participant, as reported to observers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObserverStats {
+    /// The number of participants currently known.
+    pub peers: u32,
+    /// The total number of messages sent, per `MessageKind`.
+    pub sent: Vec<u64>,
+    /// The total number of messages received, per `MessageKind`.
+    pub received: Vec<u64>,
+}
+
+/// A change in the set of participants, as reported to observers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MembershipChange {
+    /// The public address of the participant that joined or left.
+    pub addr: SocketAddr,
+    /// `true` when the participant joined, `false` when it left.
+    pub joined: bool,
+    /// A short explanation of the change, such as `"handshake"` or `"disconnected"`.
+    pub reason: String,
+}
 
 /// Defines the types of messages that can be sent between network participants.
 ///
 /// This enum is used for serializing and deserializing message content for network communication.
 /// Each variant represents a specific kind of message that can be exchanged in the network,
 /// facilitating various aspects of network interaction and management.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Message {
     /// Represents a message containing the public address of a participant.
     ///
@@ -63,4 +243,485 @@ pub enum Message {
     /// This variant is used for exchanging arbitrary text messages, supporting a wide range of
     /// communication needs, from simple notifications to complex data payloads encoded as strings.
     Text(String),
+
+    /// Identifies the sender as an observer instead of a participant.
+    ///
+    /// Sent in place of `PublicAddress` right after connecting. The receiver never adds an
+    /// observer to its participants, answers with an `ObserverSnapshot` and then streams
+    /// `ObserverUpdate` messages. Sending it again keeps an idle subscription alive.
+    ObserverHello,
+
+    /// Describes the receiving observer's view of the network right after it subscribed.
+    ObserverSnapshot {
+        peers: Vec<SocketAddr>,
+        stats: ObserverStats,
+    },
+
+    /// Carries the membership changes coalesced since the previous update, along with the
+    /// current statistics.
+    ObserverUpdate {
+        changes: Vec<MembershipChange>,
+        stats: ObserverStats,
+    },
+
+    /// Tells an observer that the participant already serves as many observers as allowed.
+    ObserverRejected,
+
+    /// Asks the receiver, a neighbor that listed `target`, to have `target` dial `my_addr`.
+    ///
+    /// Sent after a dial to `target` failed. The receiver relays it as an
+    /// `IntroductionRequest` when it is connected to `target` and has not introduced the same
+    /// pair recently.
+    PleaseIntroduce {
+        target: SocketAddr,
+        my_addr: SocketAddr,
+    },
+
+    /// Asks the receiver to dial `from`, which could not dial the receiver itself.
+    IntroductionRequest { from: SocketAddr },
+
+    /// Carries an application payload published on `topic`, delivered to the receiver's
+    /// subscribers of that topic.
+    Topic { topic: String, payload: Vec<u8> },
+
+    /// Contains the sender's best known participants, most recently verified first.
+    ///
+    /// Sent in response to a `PushParticipantsList` from a peer that announced protocol
+    /// version 2 or later. The list is limited to the sender's share budget and each entry
+    /// tells how recently the sender heard from the participant.
+    SharedPeers(Vec<SharedPeer>),
+
+    /// Asks the receiver to answer `payload` with its handler for `topic`.
+    ///
+    /// `id` is unique among the requests of the sender, which matches the `Response` carrying
+    /// the same id to the request.
+    Request {
+        id: u64,
+        topic: String,
+        payload: Vec<u8>,
+    },
+
+    /// Answers the `Request` with the same `id`, with the payload returned by the handler or
+    /// the reason there is none.
+    Response {
+        id: u64,
+        payload: Result<Vec<u8>, String>,
+    },
+
+    /// Carries messages queued for the receiver within a short window, to be handled one after
+    /// the other as if each had arrived on its own.
+    ///
+    /// A batch never holds another batch; a peer sending one violates the protocol, and a
+    /// frame nesting them deeper than `MAX_BATCH_DEPTH` does not decode.
+    Batch(#[serde(deserialize_with = "deserialize_batch")] Vec<Message>),
+
+    /// Represents a gossip text broadcast in `round`, see the `round` module.
+    ///
+    /// Sent instead of `Text` by a participant running in rounds; a receiver that does not
+    /// run in rounds handles it as a `Text`.
+    RoundText { round: u64, text: String },
+
+    /// Carries an application payload published on `topic`, numbered `seq` among the
+    /// sender's publications on that topic.
+    ///
+    /// Handled as a `Topic` by plain subscribers; ordered subscribers use `seq` to put the
+    /// sender's messages back in publish order, see the `reorder` module.
+    SequencedTopic {
+        topic: String,
+        seq: u64,
+        payload: Vec<u8>,
+    },
+
+    /// Asks the receiver to echo `id` back at once, see the `probe` module.
+    ///
+    /// `sent_at_offset` is the time the probe was sent, in microseconds from the start of the
+    /// sender's run of probes.
+    Probe { id: u64, sent_at_offset: u64 },
+
+    /// Echoes the `Probe` with the same `id`; `echoed_at_offset` is the uptime of the sender
+    /// when it echoed, in microseconds.
+    ProbeReply { id: u64, echoed_at_offset: u64 },
+
+    /// Lists the topics the sender subscribes to, replacing the list of its last handshake
+    /// frame or update.
+    ///
+    /// `None` stands for every topic, sent by a participant subscribed to more topics than an
+    /// update may list; publishers then send it everything, as they do to a peer that never
+    /// sent an update. See the `interest` module.
+    InterestUpdate(Option<Vec<String>>),
+
+    /// Asks the receiver for its participants list, answered with a `ListReply` carrying the
+    /// same `id`; see the `exchange` module.
+    ListRequest { id: u64 },
+
+    /// Answers the `ListRequest` with the same `id`, with the entries a `SharedPeers` would
+    /// carry. It has no trailer, since the requester already knows the version of the sender.
+    ListReply { id: u64, peers: Vec<SharedPeer> },
+
+    /// Asks the receiver to answer at once with its wall-clock time, see the `skew` module.
+    ///
+    /// `sent_at` is the wall-clock time of the sender when it sent the request, in
+    /// microseconds since the Unix epoch.
+    ClockRequest { sent_at: u64 },
+
+    /// Answers the `ClockRequest` sent at `sent_at`, with the wall-clock times the sender of
+    /// the reply received the request and replied, in microseconds since the Unix epoch on its
+    /// own clock.
+    ClockReply {
+        sent_at: u64,
+        received_at: u64,
+        replied_at: u64,
+    },
+
+    /// Carries an application payload published on `topic` like a `SequencedTopic`, following
+    /// version `version` of the schema `schema`.
+    ///
+    /// Handled as a `SequencedTopic` by plain and ordered subscribers; the schema handlers of
+    /// the topic get it by version, see the `schema` module.
+    VersionedTopic {
+        topic: String,
+        seq: u64,
+        schema: String,
+        version: u32,
+        payload: Vec<u8>,
+    },
+
+    /// Lists the payload schemas the sender handles, replacing the list of its last update.
+    SchemaUpdate(Vec<SchemaSupport>),
+
+    /// Answers the `ListRequest` with the same `id` like a `ListReply`, with the list packed
+    /// into fewer bytes, see the `compactlist` module.
+    CompactListReply { id: u64, list: PackedList },
+}
+
+/// What the trailer of a frame tells about its sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trailer {
+    /// The protocol version the sender speaks, if announced.
+    pub version: Option<u16>,
+    /// The persistent id of the sender, if announced.
+    pub node_id: Option<NodeId>,
+    /// The topics the sender subscribes to as in an `InterestUpdate`, if announced.
+    pub interest: Option<Option<Vec<String>>>,
+    /// The effective period of the sender, if announced.
+    pub period: Option<Duration>,
+    /// The `--network` name of the sender, if announced.
+    pub network: Option<String>,
+}
+
+impl Message {
+    /// Returns the lightweight kind of this message, used for per-kind statistics.
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::PublicAddress(_) => MessageKind::PublicAddress,
+            Message::PushParticipantsList => MessageKind::PushParticipantsList,
+            Message::PullParticipantsList(_) => MessageKind::PullParticipantsList,
+            Message::Text(_) => MessageKind::Text,
+            Message::ObserverHello => MessageKind::ObserverHello,
+            Message::ObserverSnapshot { .. } => MessageKind::ObserverSnapshot,
+            Message::ObserverUpdate { .. } => MessageKind::ObserverUpdate,
+            Message::ObserverRejected => MessageKind::ObserverRejected,
+            Message::PleaseIntroduce { .. } => MessageKind::PleaseIntroduce,
+            Message::IntroductionRequest { .. } => MessageKind::IntroductionRequest,
+            Message::Topic { .. } => MessageKind::Topic,
+            Message::SharedPeers(_) => MessageKind::SharedPeers,
+            Message::Request { .. } => MessageKind::Request,
+            Message::Response { .. } => MessageKind::Response,
+            Message::Batch(_) => MessageKind::Batch,
+            Message::RoundText { .. } => MessageKind::RoundText,
+            Message::SequencedTopic { .. } => MessageKind::SequencedTopic,
+            Message::Probe { .. } => MessageKind::Probe,
+            Message::ProbeReply { .. } => MessageKind::ProbeReply,
+            Message::InterestUpdate(_) => MessageKind::InterestUpdate,
+            Message::ListRequest { .. } => MessageKind::ListRequest,
+            Message::ListReply { .. } => MessageKind::ListReply,
+            Message::ClockRequest { .. } => MessageKind::ClockRequest,
+            Message::ClockReply { .. } => MessageKind::ClockReply,
+            Message::VersionedTopic { .. } => MessageKind::VersionedTopic,
+            Message::SchemaUpdate(_) => MessageKind::SchemaUpdate,
+            Message::CompactListReply { .. } => MessageKind::CompactListReply,
+        }
+    }
+
+    /// Serializes this message into a frame.
+    ///
+    /// A `PublicAddress` or `SharedPeers` frame is followed by this build's `PROTOCOL_VERSION`.
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_trailer(None)
+    }
+
+    /// Serializes this message into a frame whose trailer, if it has one, also carries
+    /// `node_id`, the `interest`, the effective `period` and the `network` of the sender.
+    pub fn encode_from(
+        &self,
+        node_id: NodeId,
+        interest: &Option<Vec<String>>,
+        period: Duration,
+        network: Option<&str>,
+    ) -> Vec<u8> {
+        self.encode_trailer(Some(TrailerSender {
+            node_id,
+            interest,
+            period,
+            network,
+        }))
+    }
+
+    /// Deserializes a frame.
+    ///
+    /// # Returns
+    ///
+    /// The message along with what its trailer announces, or the error of a frame that is not
+    /// a valid message.
+    pub fn decode(input_data: &[u8]) -> bincode::Result<(Message, Trailer)> {
+        let message: Message = bincode::deserialize(input_data)?;
+        let trailer = match message {
+            Message::PublicAddress(_) | Message::SharedPeers(_) => {
+                let body_len = bincode::serialized_size(&message)? as usize;
+                let version_len = bincode::serialized_size(&PROTOCOL_VERSION)? as usize;
+                let node_id_len = bincode::serialized_size(&0u128)? as usize;
+                let period_len = bincode::serialized_size(&0u64)? as usize;
+                let node_id = input_data
+                    .get(body_len + version_len..)
+                    .and_then(|trailer| bincode::deserialize(trailer).ok())
+                    .map(NodeId);
+                let interest_at = body_len + version_len + node_id_len;
+                let interest: Option<Option<Vec<String>>> = node_id.and_then(|_| {
+                    input_data
+                        .get(interest_at..)
+                        .and_then(|trailer| bincode::deserialize(trailer).ok())
+                });
+                let period_at = match &interest {
+                    Some(topics) => Some(interest_at + bincode::serialized_size(topics)? as usize),
+                    None => None,
+                };
+                let period_ms: Option<u64> = period_at.and_then(|period_at| {
+                    input_data
+                        .get(period_at..)
+                        .and_then(|trailer| bincode::deserialize(trailer).ok())
+                });
+                let network = period_ms.and(period_at).and_then(|period_at| {
+                    input_data
+                        .get(period_at + period_len..)
+                        .and_then(|trailer| bincode::deserialize(trailer).ok())
+                });
+                Trailer {
+                    version: input_data
+                        .get(body_len..)
+                        .and_then(|trailer| bincode::deserialize(trailer).ok()),
+                    node_id,
+                    interest,
+                    period: period_ms.map(Duration::from_millis),
+                    network,
+                }
+            }
+            _ => Trailer::default(),
+        };
+        Ok((message, trailer))
+    }
+
+    /// Serializes this message, followed by the trailer of the frames that carry one.
+    fn encode_trailer(&self, sender: Option<TrailerSender>) -> Vec<u8> {
+        let mut output_data = bincode::serialize(self).unwrap();
+        if let Message::PublicAddress(_) | Message::SharedPeers(_) = self {
+            output_data.extend(bincode::serialize(&PROTOCOL_VERSION).unwrap());
+            if let Some(sender) = sender {
+                output_data.extend(bincode::serialize(&sender.node_id.0).unwrap());
+                output_data.extend(bincode::serialize(sender.interest).unwrap());
+                let period_ms = u64::try_from(sender.period.as_millis()).unwrap_or(u64::MAX);
+                output_data.extend(bincode::serialize(&period_ms).unwrap());
+                if let Some(network) = sender.network {
+                    output_data.extend(bincode::serialize(network).unwrap());
+                }
+            }
+        }
+        output_data
+    }
+}
+
+/// What a sender announces in the trailer of its frames, after the protocol version.
+struct TrailerSender<'a> {
+    node_id: NodeId,
+    interest: &'a Option<Vec<String>>,
+    period: Duration,
+    network: Option<&'a str>,
+}
+
+/// Identifies the variant of a `Message` without carrying its content.
+///
+/// Each kind doubles as an index into fixed-size counter arrays, so counting messages per
+/// kind never allocates. The match in `Message::kind` is exhaustive, which means a new
+/// `Message` variant does not compile until it is given a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    PublicAddress,
+    PushParticipantsList,
+    PullParticipantsList,
+    Text,
+    ObserverHello,
+    ObserverSnapshot,
+    ObserverUpdate,
+    ObserverRejected,
+    PleaseIntroduce,
+    IntroductionRequest,
+    Topic,
+    SharedPeers,
+    Request,
+    Response,
+    Batch,
+    RoundText,
+    SequencedTopic,
+    Probe,
+    ProbeReply,
+    InterestUpdate,
+    ListRequest,
+    ListReply,
+    ClockRequest,
+    ClockReply,
+    VersionedTopic,
+    SchemaUpdate,
+    CompactListReply,
+}
+
+impl MessageKind {
+    /// The number of message kinds, i.e. the length of a per-kind counter array.
+    pub const COUNT: usize = 27;
+
+    /// Every message kind, in counter array order.
+    pub const ALL: [MessageKind; MessageKind::COUNT] = [
+        MessageKind::PublicAddress,
+        MessageKind::PushParticipantsList,
+        MessageKind::PullParticipantsList,
+        MessageKind::Text,
+        MessageKind::ObserverHello,
+        MessageKind::ObserverSnapshot,
+        MessageKind::ObserverUpdate,
+        MessageKind::ObserverRejected,
+        MessageKind::PleaseIntroduce,
+        MessageKind::IntroductionRequest,
+        MessageKind::Topic,
+        MessageKind::SharedPeers,
+        MessageKind::Request,
+        MessageKind::Response,
+        MessageKind::Batch,
+        MessageKind::RoundText,
+        MessageKind::SequencedTopic,
+        MessageKind::Probe,
+        MessageKind::ProbeReply,
+        MessageKind::InterestUpdate,
+        MessageKind::ListRequest,
+        MessageKind::ListReply,
+        MessageKind::ClockRequest,
+        MessageKind::ClockReply,
+        MessageKind::VersionedTopic,
+        MessageKind::SchemaUpdate,
+        MessageKind::CompactListReply,
+    ];
+
+    /// Returns the position of this kind in a per-kind counter array.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the human-readable name of this kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageKind::PublicAddress => "PublicAddress",
+            MessageKind::PushParticipantsList => "PushParticipantsList",
+            MessageKind::PullParticipantsList => "PullParticipantsList",
+            MessageKind::Text => "Text",
+            MessageKind::ObserverHello => "ObserverHello",
+            MessageKind::ObserverSnapshot => "ObserverSnapshot",
+            MessageKind::ObserverUpdate => "ObserverUpdate",
+            MessageKind::ObserverRejected => "ObserverRejected",
+            MessageKind::PleaseIntroduce => "PleaseIntroduce",
+            MessageKind::IntroductionRequest => "IntroductionRequest",
+            MessageKind::Topic => "Topic",
+            MessageKind::SharedPeers => "SharedPeers",
+            MessageKind::Request => "Request",
+            MessageKind::Response => "Response",
+            MessageKind::Batch => "Batch",
+            MessageKind::RoundText => "RoundText",
+            MessageKind::SequencedTopic => "SequencedTopic",
+            MessageKind::Probe => "Probe",
+            MessageKind::ProbeReply => "ProbeReply",
+            MessageKind::InterestUpdate => "InterestUpdate",
+            MessageKind::ListRequest => "ListRequest",
+            MessageKind::ListReply => "ListReply",
+            MessageKind::ClockRequest => "ClockRequest",
+            MessageKind::ClockReply => "ClockReply",
+            MessageKind::VersionedTopic => "VersionedTopic",
+            MessageKind::SchemaUpdate => "SchemaUpdate",
+            MessageKind::CompactListReply => "CompactListReply",
+        }
+    }
+}
+
+/// Deserializes the messages of a batch, refusing a batch nested deeper than
+/// `MAX_BATCH_DEPTH` before it reads the messages within.
+fn deserialize_batch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Message>, D::Error> {
+    let depth = BATCH_DEPTH.with(Cell::get);
+    if depth >= MAX_BATCH_DEPTH {
+        return Err(D::Error::custom("batches nested too deep"));
+    }
+    BATCH_DEPTH.with(|cell| cell.set(depth + 1));
+    let messages = Vec::<Message>::deserialize(deserializer);
+    BATCH_DEPTH.with(|cell| cell.set(depth));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::one_of_each;
+
+    #[test]
+    fn every_variant_has_its_own_kind_in_counter_order() {
+        let kinds: Vec<MessageKind> = one_of_each().iter().map(Message::kind).collect();
+        assert_eq!(kinds, MessageKind::ALL.to_vec());
+    }
+
+    #[test]
+    fn kinds_index_their_place_in_the_counter_arrays() {
+        for (position, kind) in MessageKind::ALL.into_iter().enumerate() {
+            assert_eq!(kind.index(), position);
+        }
+        assert_eq!(MessageKind::ALL.len(), MessageKind::COUNT);
+    }
+
+    #[test]
+    fn kind_names_are_the_variant_names() {
+        for kind in MessageKind::ALL {
+            assert_eq!(kind.name(), format!("{:?}", kind));
+        }
+    }
+
+    #[test]
+    fn a_decoded_message_keeps_its_kind() {
+        for message in one_of_each() {
+            let (decoded, _) = Message::decode(&message.encode()).unwrap();
+            assert_eq!(decoded.kind(), message.kind());
+        }
+    }
+
+    #[test]
+    fn a_frame_of_deeply_nested_batches_does_not_decode() {
+        let batch = bincode::serialize(&(MessageKind::Batch.index() as u32)).unwrap();
+        let mut frame = Vec::new();
+        for _ in 0..100_000 {
+            frame.extend_from_slice(&batch);
+            frame.extend_from_slice(&1u64.to_le_bytes());
+        }
+        frame.extend_from_slice(&batch);
+        frame.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Message::decode(&frame).is_err());
+
+        let nested = Message::Batch(vec![Message::Batch(Vec::new())]);
+        let (decoded, _) = Message::decode(&nested.encode()).unwrap();
+        assert_eq!(decoded.encode(), nested.encode());
+        let deeper = Message::Batch(vec![nested]);
+        assert!(Message::decode(&deeper.encode()).is_err());
+        let (decoded, _) = Message::decode(&Message::Text("after".to_owned()).encode()).unwrap();
+        assert_eq!(decoded.kind(), MessageKind::Text);
+    }
 }
@@ -0,0 +1,991 @@
+//! Message Builder.
+//!
+//! The harnesses driving a network from outside wrote their frames by hand, bincode field by
+//! field, and every change of `Message` broke them without a word. A `MessageBuilder` builds
+//! each message a participant sends, and `build` checks it with the validators the participant
+//! applies on its own send and receive paths, so a tool can not produce a message a participant
+//! would drop, or close the connection over:
+//!
+//! - topic names, with `validate_topic`, in topic payloads, requests and interest updates;
+//! - interest lists, with `validate_interest`: at most `MAX_INTEREST_TOPICS` valid topics;
+//! - addresses, with `validate_address`: a port, and a unicast address;
+//! - participants lists, with `validate_list`: at most `MAX_LIST_LEN` addresses;
+//! - packed participants lists, with `validate_packed`: unpacked, then like the others;
+//! - schema updates, with `validate_schemas`: at most `MAX_SCHEMA_ENTRIES` valid entries;
+//! - batches, with `validate_batch`: no batch inside.
+//!
+//! `MessageBuilder::frame` goes one step further and returns the bytes a participant writes on
+//! the wire: the encoded message with the trailer of its sender, after the varint length prefix
+//! of FramedTcp. `read_frame` takes such bytes back apart.
+//!
+//! ```plaintext
+//! let frame = MessageBuilder::public_address(addr).sender(node_id, None, period, None).frame()?;
+//! let (message, trailer, used) = read_frame(&frame)?;
+//! ```
+
+use crate::participant::compactlist::{CompactListError, PackedList};
+use crate::participant::identity::NodeId;
+use crate::participant::interest::MAX_INTEREST_TOPICS;
+use crate::participant::message::{
+    MembershipChange, Message, MessageKind, ObserverStats, SharedPeer, Trailer,
+};
+use crate::participant::schema::{Schema, SchemaSupport, VersionRange, MAX_SCHEMA_ENTRIES};
+use crate::participant::topic::{validate_topic, TopicError};
+
+use message_io::util::encoding::{self, MAX_ENCODED_SIZE};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// The longest participants list a participant sends, and accepts as a first frame; well
+/// above the default share limit of 100.
+pub const MAX_LIST_LEN: usize = 256;
+
+/// Enumerates the reasons an address can not be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AddressError {
+    /// The address has port 0.
+    ZeroPort,
+    /// The address is a multicast or broadcast address.
+    NotUnicast,
+}
+
+/// Enumerates the reasons a message can not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A topic name would be refused by `publish`.
+    Topic(TopicError),
+    /// An interest list names more than `MAX_INTEREST_TOPICS` topics.
+    TooManyTopics(usize),
+    /// An address no peer could be dialed at.
+    Address(SocketAddr, AddressError),
+    /// A participants list holds more than `MAX_LIST_LEN` addresses.
+    ListTooLong(usize),
+    /// A batch holds another batch.
+    NestedBatch,
+    /// The trailer of a sender was given for a message of this kind, which carries none.
+    NoTrailer(MessageKind),
+    /// A schema name would be refused like a topic name.
+    SchemaName(TopicError),
+    /// A schema update lists more than `MAX_SCHEMA_ENTRIES` schemas.
+    TooManySchemas(usize),
+    /// A schema update lists a range holding no version.
+    EmptyRange(VersionRange),
+    /// A packed participants list does not unpack.
+    PackedList(CompactListError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Topic(err) => write!(f, "{}", err),
+            BuildError::TooManyTopics(len) => write!(
+                f,
+                "an interest list names {} topics, over {}",
+                len, MAX_INTEREST_TOPICS
+            ),
+            BuildError::Address(addr, AddressError::ZeroPort) => {
+                write!(f, "the address {} has port 0", addr)
+            }
+            BuildError::Address(addr, AddressError::NotUnicast) => {
+                write!(f, "the address {} is not unicast", addr)
+            }
+            BuildError::ListTooLong(len) => write!(
+                f,
+                "a participants list holds {} addresses, over {}",
+                len, MAX_LIST_LEN
+            ),
+            BuildError::NestedBatch => write!(f, "a batch can not hold another batch"),
+            BuildError::NoTrailer(kind) => {
+                write!(f, "a {} frame carries no trailer", kind.name())
+            }
+            BuildError::SchemaName(err) => {
+                write!(f, "a schema name breaks the rules of topic names: {}", err)
+            }
+            BuildError::TooManySchemas(len) => write!(
+                f,
+                "a schema update lists {} schemas, over {}",
+                len, MAX_SCHEMA_ENTRIES
+            ),
+            BuildError::EmptyRange(range) => {
+                write!(f, "the version range {} holds no version", range)
+            }
+            BuildError::PackedList(err) => {
+                write!(f, "a packed participants list does not unpack: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<TopicError> for BuildError {
+    fn from(err: TopicError) -> Self {
+        BuildError::Topic(err)
+    }
+}
+
+/// Enumerates the reasons `read_frame` can not read a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameError {
+    /// The bytes end before the frame does.
+    Incomplete,
+    /// The frame is not a message of this build, as explained.
+    Undecodable(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Incomplete => write!(f, "the frame is incomplete"),
+            FrameError::Undecodable(reason) => write!(f, "the frame does not decode: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Checks that a peer could be dialed at `addr`.
+///
+/// Unspecified addresses are accepted, since a participant listening on every interface may
+/// announce one.
+pub fn validate_address(addr: SocketAddr) -> Result<(), BuildError> {
+    if addr.port() == 0 {
+        return Err(BuildError::Address(addr, AddressError::ZeroPort));
+    }
+    let unicast = match addr.ip() {
+        IpAddr::V4(ip) => !ip.is_multicast() && !ip.is_broadcast(),
+        IpAddr::V6(ip) => !ip.is_multicast(),
+    };
+    if !unicast {
+        return Err(BuildError::Address(addr, AddressError::NotUnicast));
+    }
+    Ok(())
+}
+
+/// Checks the length of a participants list and each of its addresses.
+pub fn validate_list(
+    mut addrs: impl ExactSizeIterator<Item = SocketAddr>,
+) -> Result<(), BuildError> {
+    if addrs.len() > MAX_LIST_LEN {
+        return Err(BuildError::ListTooLong(addrs.len()));
+    }
+    addrs.try_for_each(validate_address)
+}
+
+/// Checks that a packed participants list unpacks to a list `validate_list` accepts.
+pub fn validate_packed(list: &PackedList) -> Result<(), BuildError> {
+    let peers = list.unpack().map_err(BuildError::PackedList)?;
+    validate_list(peers.iter().map(|peer| peer.addr))
+}
+
+/// Checks that an interest list can be announced: at most `MAX_INTEREST_TOPICS` topics, each
+/// one a valid topic name.
+pub fn validate_interest(topics: &[String]) -> Result<(), BuildError> {
+    if topics.len() > MAX_INTEREST_TOPICS {
+        return Err(BuildError::TooManyTopics(topics.len()));
+    }
+    topics
+        .iter()
+        .try_for_each(|topic| validate_topic(topic).map_err(BuildError::Topic))
+}
+
+/// Checks that a schema update lists at most `MAX_SCHEMA_ENTRIES` schemas, on valid topics,
+/// with valid names and ranges.
+pub fn validate_schemas(schemas: &[SchemaSupport]) -> Result<(), BuildError> {
+    if schemas.len() > MAX_SCHEMA_ENTRIES {
+        return Err(BuildError::TooManySchemas(schemas.len()));
+    }
+    schemas.iter().try_for_each(|schema| {
+        validate_topic(&schema.topic)?;
+        validate_topic(&schema.name).map_err(BuildError::SchemaName)?;
+        if !schema.range.is_valid() {
+            return Err(BuildError::EmptyRange(schema.range));
+        }
+        Ok(())
+    })
+}
+
+/// Checks that a batch holds no other batch.
+pub fn validate_batch(messages: &[Message]) -> Result<(), BuildError> {
+    if messages
+        .iter()
+        .any(|inner| inner.kind() == MessageKind::Batch)
+    {
+        return Err(BuildError::NestedBatch);
+    }
+    Ok(())
+}
+
+/// Checks `message` with the validator of each of its parts.
+pub fn validate_message(message: &Message) -> Result<(), BuildError> {
+    match message {
+        Message::PublicAddress(addr) | Message::IntroductionRequest { from: addr } => {
+            validate_address(*addr)
+        }
+        Message::PleaseIntroduce { target, my_addr } => {
+            validate_address(*target)?;
+            validate_address(*my_addr)
+        }
+        Message::PullParticipantsList(addrs) => validate_list(addrs.iter().copied()),
+        Message::SharedPeers(peers) | Message::ListReply { peers, .. } => {
+            validate_list(peers.iter().map(|peer| peer.addr))
+        }
+        Message::CompactListReply { list, .. } => validate_packed(list),
+        Message::Topic { topic, .. }
+        | Message::SequencedTopic { topic, .. }
+        | Message::Request { topic, .. } => Ok(validate_topic(topic)?),
+        Message::InterestUpdate(Some(topics)) => validate_interest(topics),
+        Message::VersionedTopic { topic, schema, .. } => {
+            validate_topic(topic)?;
+            validate_topic(schema).map_err(BuildError::SchemaName)
+        }
+        Message::SchemaUpdate(schemas) => validate_schemas(schemas),
+        Message::Batch(messages) => {
+            validate_batch(messages)?;
+            messages.iter().try_for_each(validate_message)
+        }
+        Message::PushParticipantsList
+        | Message::Text(_)
+        | Message::ObserverHello
+        | Message::ObserverSnapshot { .. }
+        | Message::ObserverUpdate { .. }
+        | Message::ObserverRejected
+        | Message::Response { .. }
+        | Message::RoundText { .. }
+        | Message::Probe { .. }
+        | Message::ProbeReply { .. }
+        | Message::InterestUpdate(None)
+        | Message::ListRequest { .. }
+        | Message::ClockRequest { .. }
+        | Message::ClockReply { .. } => Ok(()),
+    }
+}
+
+/// Reads the frame at the start of `wire`, as written by FramedTcp.
+///
+/// # Returns
+///
+/// The message, what its trailer announces, and the number of bytes the frame took, length
+/// prefix included.
+pub fn read_frame(wire: &[u8]) -> Result<(Message, Trailer, usize), FrameError> {
+    let (len, prefix) = encoding::decode_size(wire).ok_or(FrameError::Incomplete)?;
+    let end = prefix.checked_add(len).ok_or(FrameError::Incomplete)?;
+    let body = wire.get(prefix..end).ok_or(FrameError::Incomplete)?;
+    let (message, trailer) =
+        Message::decode(body).map_err(|err| FrameError::Undecodable(err.to_string()))?;
+    Ok((message, trailer, end))
+}
+
+/// The sender announced in the trailer of a frame.
+#[derive(Debug, Clone)]
+struct Sender {
+    node_id: NodeId,
+    interest: Option<Vec<String>>,
+    period: Duration,
+    network: Option<String>,
+}
+
+/// Builds a message checked like the participant checks the messages it sends.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct MessageBuilder {
+    message: Message,
+    sender: Option<Sender>,
+}
+
+impl MessageBuilder {
+    fn new(message: Message) -> Self {
+        Self {
+            message,
+            sender: None,
+        }
+    }
+
+    /// Builds the announcement of the public address of the sender.
+    pub fn public_address(addr: SocketAddr) -> Self {
+        Self::new(Message::PublicAddress(addr))
+    }
+
+    /// Builds a request for the participants list of the receiver.
+    pub fn push_participants_list() -> Self {
+        Self::new(Message::PushParticipantsList)
+    }
+
+    /// Builds a participants list, as sent to peers older than protocol version 2.
+    pub fn participants_list(addrs: Vec<SocketAddr>) -> Self {
+        Self::new(Message::PullParticipantsList(addrs))
+    }
+
+    /// Builds a participants list whose entries tell how recently each was verified.
+    pub fn shared_peers(peers: Vec<SharedPeer>) -> Self {
+        Self::new(Message::SharedPeers(peers))
+    }
+
+    /// Builds a request for the participants list, answered with a reply echoing `id`.
+    pub fn list_request(id: u64) -> Self {
+        Self::new(Message::ListRequest { id })
+    }
+
+    /// Builds the answer to the list request `id`.
+    pub fn list_reply(id: u64, peers: Vec<SharedPeer>) -> Self {
+        Self::new(Message::ListReply { id, peers })
+    }
+
+    /// Builds the answer to the list request `id`, packed for a peer speaking
+    /// `COMPACT_LISTS_VERSION`.
+    pub fn compact_list_reply(id: u64, peers: &[SharedPeer]) -> Self {
+        Self::new(Message::CompactListReply {
+            id,
+            list: PackedList::pack(peers),
+        })
+    }
+
+    /// Builds a gossip text.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(Message::Text(text.into()))
+    }
+
+    /// Builds a gossip text broadcast in `round`.
+    pub fn round_text(round: u64, text: impl Into<String>) -> Self {
+        Self::new(Message::RoundText {
+            round,
+            text: text.into(),
+        })
+    }
+
+    /// Builds a publication of `payload` on `topic`, as sent to peers older than protocol
+    /// version 6.
+    pub fn topic(topic: impl Into<String>, payload: Vec<u8>) -> Self {
+        Self::new(Message::Topic {
+            topic: topic.into(),
+            payload,
+        })
+    }
+
+    /// Builds the publication numbered `seq` among the publications of the sender on `topic`.
+    pub fn sequenced_topic(topic: impl Into<String>, seq: u64, payload: Vec<u8>) -> Self {
+        Self::new(Message::SequencedTopic {
+            topic: topic.into(),
+            seq,
+            payload,
+        })
+    }
+
+    /// Builds the publication numbered `seq` on `topic` of a payload following `schema`.
+    pub fn versioned_topic(
+        topic: impl Into<String>,
+        seq: u64,
+        schema: &Schema,
+        payload: Vec<u8>,
+    ) -> Self {
+        Self::new(Message::VersionedTopic {
+            topic: topic.into(),
+            seq,
+            schema: schema.name.clone(),
+            version: schema.version,
+            payload,
+        })
+    }
+
+    /// Builds the list of the payload schemas the sender handles.
+    pub fn schema_update(schemas: Vec<SchemaSupport>) -> Self {
+        Self::new(Message::SchemaUpdate(schemas))
+    }
+
+    /// Builds the request `id`, answered by the handler of `topic` of the receiver.
+    pub fn request(id: u64, topic: impl Into<String>, payload: Vec<u8>) -> Self {
+        Self::new(Message::Request {
+            id,
+            topic: topic.into(),
+            payload,
+        })
+    }
+
+    /// Builds the response to the request `id`.
+    pub fn response(id: u64, payload: Result<Vec<u8>, String>) -> Self {
+        Self::new(Message::Response { id, payload })
+    }
+
+    /// Builds a batch of `messages`, handled one after the other.
+    pub fn batch(messages: Vec<Message>) -> Self {
+        Self::new(Message::Batch(messages))
+    }
+
+    /// Builds the probe `id`, sent `sent_at_offset` microseconds into its run.
+    pub fn probe(id: u64, sent_at_offset: u64) -> Self {
+        Self::new(Message::Probe { id, sent_at_offset })
+    }
+
+    /// Builds the echo of the probe `id`, at an uptime of `echoed_at_offset` microseconds.
+    pub fn probe_reply(id: u64, echoed_at_offset: u64) -> Self {
+        Self::new(Message::ProbeReply {
+            id,
+            echoed_at_offset,
+        })
+    }
+
+    /// Builds the list of the topics the sender subscribes to, `None` for every topic.
+    pub fn interest_update(topics: Option<Vec<String>>) -> Self {
+        Self::new(Message::InterestUpdate(topics))
+    }
+
+    /// Builds a request to have `target` dial `my_addr`.
+    pub fn please_introduce(target: SocketAddr, my_addr: SocketAddr) -> Self {
+        Self::new(Message::PleaseIntroduce { target, my_addr })
+    }
+
+    /// Builds a request to dial `from`.
+    pub fn introduction_request(from: SocketAddr) -> Self {
+        Self::new(Message::IntroductionRequest { from })
+    }
+
+    /// Builds a clock request sent at `sent_at`, in microseconds since the Unix epoch.
+    pub fn clock_request(sent_at: u64) -> Self {
+        Self::new(Message::ClockRequest { sent_at })
+    }
+
+    /// Builds the answer to the clock request sent at `sent_at`.
+    pub fn clock_reply(sent_at: u64, received_at: u64, replied_at: u64) -> Self {
+        Self::new(Message::ClockReply {
+            sent_at,
+            received_at,
+            replied_at,
+        })
+    }
+
+    /// Builds the subscription of an observer.
+    pub fn observer_hello() -> Self {
+        Self::new(Message::ObserverHello)
+    }
+
+    /// Builds the view of the network a new observer is sent.
+    pub fn observer_snapshot(peers: Vec<SocketAddr>, stats: ObserverStats) -> Self {
+        Self::new(Message::ObserverSnapshot { peers, stats })
+    }
+
+    /// Builds the membership changes streamed to an observer.
+    pub fn observer_update(changes: Vec<MembershipChange>, stats: ObserverStats) -> Self {
+        Self::new(Message::ObserverUpdate { changes, stats })
+    }
+
+    /// Builds the refusal of an observer over the cap.
+    pub fn observer_rejected() -> Self {
+        Self::new(Message::ObserverRejected)
+    }
+
+    /// Announces the sender in the trailer of the frame, after the protocol version: its node
+    /// id, its interest, `None` for every topic, its effective period, and its `--network`
+    /// name, if it has one.
+    ///
+    /// Only `PublicAddress` and `SharedPeers` frames carry a trailer.
+    pub fn sender(
+        mut self,
+        node_id: NodeId,
+        interest: Option<Vec<String>>,
+        period: Duration,
+        network: Option<String>,
+    ) -> Self {
+        self.sender = Some(Sender {
+            node_id,
+            interest,
+            period,
+            network,
+        });
+        self
+    }
+
+    /// Checks the message and returns it.
+    pub fn build(self) -> Result<Message, BuildError> {
+        self.checked().map(|(message, _)| message)
+    }
+
+    /// Checks the message and returns the frame a participant would write on the wire for
+    /// it, length prefix included.
+    pub fn frame(self) -> Result<Vec<u8>, BuildError> {
+        let (message, sender) = self.checked()?;
+        let body = match &sender {
+            Some(sender) => message.encode_from(
+                sender.node_id,
+                &sender.interest,
+                sender.period,
+                sender.network.as_deref(),
+            ),
+            None => message.encode(),
+        };
+        let mut buf = [0; MAX_ENCODED_SIZE];
+        let mut frame = encoding::encode_size(&body, &mut buf).to_vec();
+        frame.extend(body);
+        Ok(frame)
+    }
+
+    /// Checks the message and the trailer of its sender.
+    fn checked(self) -> Result<(Message, Option<Sender>), BuildError> {
+        validate_message(&self.message)?;
+        if let Some(sender) = &self.sender {
+            if !matches!(
+                self.message,
+                Message::PublicAddress(_) | Message::SharedPeers(_)
+            ) {
+                return Err(BuildError::NoTrailer(self.message.kind()));
+            }
+            if let Some(topics) = &sender.interest {
+                validate_interest(topics)?;
+            }
+        }
+        Ok((self.message, self.sender))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::message::{AgeBucket, PROTOCOL_VERSION};
+    use crate::participant::topic::MAX_TOPIC_LEN;
+
+    fn addr(value: &str) -> SocketAddr {
+        value.parse().unwrap()
+    }
+
+    fn peers(len: usize) -> Vec<SharedPeer> {
+        (0..len)
+            .map(|index| SharedPeer {
+                addr: SocketAddr::from(([10, 0, (index / 250) as u8, 1], 8000 + index as u16)),
+                age: AgeBucket::Fresh,
+            })
+            .collect()
+    }
+
+    fn stats() -> ObserverStats {
+        ObserverStats {
+            peers: 2,
+            sent: vec![1; MessageKind::COUNT],
+            received: vec![2; MessageKind::COUNT],
+        }
+    }
+
+    /// Every builder, with the message the participant constructs for it.
+    fn every_builder() -> Vec<(MessageBuilder, Message)> {
+        let a = addr("127.0.0.1:8080");
+        let b = addr("[::1]:8081");
+        let schema = Schema::new("reading", 2);
+        let support = SchemaSupport {
+            topic: "sensors".to_owned(),
+            name: "reading".to_owned(),
+            range: VersionRange::new(1, 3).unwrap(),
+        };
+        vec![
+            (MessageBuilder::public_address(a), Message::PublicAddress(a)),
+            (
+                MessageBuilder::push_participants_list(),
+                Message::PushParticipantsList,
+            ),
+            (
+                MessageBuilder::participants_list(vec![a, b]),
+                Message::PullParticipantsList(vec![a, b]),
+            ),
+            (
+                MessageBuilder::shared_peers(peers(3)),
+                Message::SharedPeers(peers(3)),
+            ),
+            (
+                MessageBuilder::list_request(7),
+                Message::ListRequest { id: 7 },
+            ),
+            (
+                MessageBuilder::list_reply(7, peers(3)),
+                Message::ListReply {
+                    id: 7,
+                    peers: peers(3),
+                },
+            ),
+            (
+                MessageBuilder::compact_list_reply(7, &peers(3)),
+                Message::CompactListReply {
+                    id: 7,
+                    list: PackedList::pack(&peers(3)),
+                },
+            ),
+            (MessageBuilder::text("hi"), Message::Text("hi".to_owned())),
+            (
+                MessageBuilder::round_text(4, "hi"),
+                Message::RoundText {
+                    round: 4,
+                    text: "hi".to_owned(),
+                },
+            ),
+            (
+                MessageBuilder::topic("news", vec![1]),
+                Message::Topic {
+                    topic: "news".to_owned(),
+                    payload: vec![1],
+                },
+            ),
+            (
+                MessageBuilder::sequenced_topic("news", 3, vec![1]),
+                Message::SequencedTopic {
+                    topic: "news".to_owned(),
+                    seq: 3,
+                    payload: vec![1],
+                },
+            ),
+            (
+                MessageBuilder::versioned_topic("sensors", 3, &schema, vec![1]),
+                Message::VersionedTopic {
+                    topic: "sensors".to_owned(),
+                    seq: 3,
+                    schema: "reading".to_owned(),
+                    version: 2,
+                    payload: vec![1],
+                },
+            ),
+            (
+                MessageBuilder::schema_update(vec![support.clone()]),
+                Message::SchemaUpdate(vec![support]),
+            ),
+            (
+                MessageBuilder::request(5, "echo", vec![1]),
+                Message::Request {
+                    id: 5,
+                    topic: "echo".to_owned(),
+                    payload: vec![1],
+                },
+            ),
+            (
+                MessageBuilder::response(5, Err("no handler".to_owned())),
+                Message::Response {
+                    id: 5,
+                    payload: Err("no handler".to_owned()),
+                },
+            ),
+            (
+                MessageBuilder::batch(vec![Message::Text("hi".to_owned())]),
+                Message::Batch(vec![Message::Text("hi".to_owned())]),
+            ),
+            (
+                MessageBuilder::probe(1, 2),
+                Message::Probe {
+                    id: 1,
+                    sent_at_offset: 2,
+                },
+            ),
+            (
+                MessageBuilder::probe_reply(1, 2),
+                Message::ProbeReply {
+                    id: 1,
+                    echoed_at_offset: 2,
+                },
+            ),
+            (
+                MessageBuilder::interest_update(Some(vec!["news".to_owned()])),
+                Message::InterestUpdate(Some(vec!["news".to_owned()])),
+            ),
+            (
+                MessageBuilder::interest_update(None),
+                Message::InterestUpdate(None),
+            ),
+            (
+                MessageBuilder::please_introduce(a, b),
+                Message::PleaseIntroduce {
+                    target: a,
+                    my_addr: b,
+                },
+            ),
+            (
+                MessageBuilder::introduction_request(a),
+                Message::IntroductionRequest { from: a },
+            ),
+            (
+                MessageBuilder::clock_request(1),
+                Message::ClockRequest { sent_at: 1 },
+            ),
+            (
+                MessageBuilder::clock_reply(1, 2, 3),
+                Message::ClockReply {
+                    sent_at: 1,
+                    received_at: 2,
+                    replied_at: 3,
+                },
+            ),
+            (MessageBuilder::observer_hello(), Message::ObserverHello),
+            (
+                MessageBuilder::observer_snapshot(vec![a], stats()),
+                Message::ObserverSnapshot {
+                    peers: vec![a],
+                    stats: stats(),
+                },
+            ),
+            (
+                MessageBuilder::observer_update(Vec::new(), stats()),
+                Message::ObserverUpdate {
+                    changes: Vec::new(),
+                    stats: stats(),
+                },
+            ),
+            (
+                MessageBuilder::observer_rejected(),
+                Message::ObserverRejected,
+            ),
+        ]
+    }
+
+    #[test]
+    fn builds_what_the_participant_constructs() {
+        let builders = every_builder();
+        let mut kinds: Vec<MessageKind> = Vec::new();
+        for (builder, expected) in builders {
+            let message = builder.build().unwrap();
+            assert_eq!(message.kind(), expected.kind());
+            assert_eq!(message.encode(), expected.encode(), "{:?}", expected);
+            if !kinds.contains(&message.kind()) {
+                kinds.push(message.kind());
+            }
+        }
+        assert_eq!(kinds.len(), MessageKind::COUNT, "a kind has no builder");
+    }
+
+    #[test]
+    fn frames_read_back() {
+        for (builder, expected) in every_builder() {
+            let frame = builder.frame().unwrap();
+            let (message, trailer, used) = read_frame(&frame).unwrap();
+            assert_eq!(used, frame.len());
+            assert_eq!(message.encode(), expected.encode());
+            let version = matches!(
+                expected,
+                Message::PublicAddress(_) | Message::SharedPeers(_)
+            )
+            .then_some(PROTOCOL_VERSION);
+            assert_eq!(trailer.version, version, "{:?}", expected);
+            assert_eq!(trailer.node_id, None);
+        }
+    }
+
+    #[test]
+    fn frames_carry_the_trailer_of_their_sender() {
+        let node_id = NodeId::random();
+        let interest = Some(vec!["news".to_owned()]);
+        for network in [None, Some("lab".to_owned())] {
+            let frame = MessageBuilder::public_address(addr("127.0.0.1:8080"))
+                .sender(
+                    node_id,
+                    interest.clone(),
+                    Duration::from_millis(1_500),
+                    network.clone(),
+                )
+                .frame()
+                .unwrap();
+            let (_, trailer, _) = read_frame(&frame).unwrap();
+            assert_eq!(
+                trailer,
+                Trailer {
+                    version: Some(PROTOCOL_VERSION),
+                    node_id: Some(node_id),
+                    interest: Some(interest.clone()),
+                    period: Some(Duration::from_millis(1_500)),
+                    network,
+                }
+            );
+        }
+
+        let result = MessageBuilder::text("hi")
+            .sender(node_id, None, Duration::from_secs(1), None)
+            .build();
+        assert_eq!(
+            result.unwrap_err(),
+            BuildError::NoTrailer(MessageKind::Text)
+        );
+        let too_many = Some((0..=MAX_INTEREST_TOPICS).map(|i| i.to_string()).collect());
+        let result = MessageBuilder::shared_peers(peers(1))
+            .sender(node_id, too_many, Duration::from_secs(1), None)
+            .build();
+        assert_eq!(
+            result.unwrap_err(),
+            BuildError::TooManyTopics(MAX_INTEREST_TOPICS + 1)
+        );
+    }
+
+    #[test]
+    fn reads_two_frames_in_a_row() {
+        let mut wire = MessageBuilder::text("one").frame().unwrap();
+        let first = wire.len();
+        wire.extend(MessageBuilder::list_request(2).frame().unwrap());
+        let (_, _, used) = read_frame(&wire).unwrap();
+        assert_eq!(used, first);
+        let (message, _, _) = read_frame(&wire[used..]).unwrap();
+        assert_eq!(message.kind(), MessageKind::ListRequest);
+    }
+
+    #[test]
+    fn refuses_incomplete_and_foreign_frames() {
+        let frame = MessageBuilder::text("hello").frame().unwrap();
+        for len in 0..frame.len() {
+            assert_eq!(
+                read_frame(&frame[..len]).unwrap_err(),
+                FrameError::Incomplete
+            );
+        }
+        // A length prefix followed by a variant this build does not know.
+        let garbage = [4, 0xff, 0xff, 0xff, 0x7f];
+        assert!(matches!(
+            read_frame(&garbage),
+            Err(FrameError::Undecodable(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_addresses_no_peer_could_be_dialed_at() {
+        for (value, reason) in [
+            ("127.0.0.1:0", AddressError::ZeroPort),
+            ("224.0.0.1:80", AddressError::NotUnicast),
+            ("255.255.255.255:80", AddressError::NotUnicast),
+            ("[ff02::1]:80", AddressError::NotUnicast),
+        ] {
+            let bad = addr(value);
+            let expected = BuildError::Address(bad, reason);
+            assert_eq!(validate_address(bad), Err(expected.clone()));
+            let builders = [
+                MessageBuilder::public_address(bad),
+                MessageBuilder::introduction_request(bad),
+                MessageBuilder::please_introduce(addr("127.0.0.1:1"), bad),
+                MessageBuilder::participants_list(vec![addr("127.0.0.1:1"), bad]),
+                MessageBuilder::list_reply(
+                    1,
+                    vec![SharedPeer {
+                        addr: bad,
+                        age: AgeBucket::Stale,
+                    }],
+                ),
+            ];
+            for builder in builders {
+                assert_eq!(builder.build().unwrap_err(), expected);
+            }
+        }
+        assert_eq!(validate_address(addr("0.0.0.0:80")), Ok(()));
+    }
+
+    #[test]
+    fn refuses_lists_over_the_cap() {
+        assert!(MessageBuilder::shared_peers(peers(MAX_LIST_LEN))
+            .build()
+            .is_ok());
+        for builder in [
+            MessageBuilder::shared_peers(peers(MAX_LIST_LEN + 1)),
+            MessageBuilder::list_reply(1, peers(MAX_LIST_LEN + 1)),
+            MessageBuilder::compact_list_reply(1, &peers(MAX_LIST_LEN + 1)),
+            MessageBuilder::participants_list(
+                peers(MAX_LIST_LEN + 1)
+                    .into_iter()
+                    .map(|peer| peer.addr)
+                    .collect(),
+            ),
+        ] {
+            assert_eq!(
+                builder.build().unwrap_err(),
+                BuildError::ListTooLong(MAX_LIST_LEN + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_packed_lists_that_do_not_unpack() {
+        let list = PackedList {
+            lister: None,
+            fresh: vec![9],
+            recent: Vec::new(),
+            stale: Vec::new(),
+        };
+        assert_eq!(
+            validate_packed(&list),
+            Err(BuildError::PackedList(CompactListError::UnknownForm(9)))
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_topics() {
+        let long = "t".repeat(MAX_TOPIC_LEN + 1);
+        for (topic, reason) in [
+            ("", TopicError::Empty),
+            (long.as_str(), TopicError::TooLong),
+            ("new\ns", TopicError::ControlCharacter),
+        ] {
+            let expected = BuildError::Topic(reason.clone());
+            for builder in [
+                MessageBuilder::topic(topic, Vec::new()),
+                MessageBuilder::sequenced_topic(topic, 1, Vec::new()),
+                MessageBuilder::request(1, topic, Vec::new()),
+                MessageBuilder::versioned_topic(topic, 1, &Schema::new("s", 1), Vec::new()),
+                MessageBuilder::interest_update(Some(vec![topic.to_owned()])),
+            ] {
+                assert_eq!(builder.build().unwrap_err(), expected);
+            }
+            assert_eq!(
+                MessageBuilder::versioned_topic("news", 1, &Schema::new(topic, 1), Vec::new())
+                    .build()
+                    .unwrap_err(),
+                BuildError::SchemaName(reason)
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_oversized_interest_and_schema_lists() {
+        let topics: Vec<String> = (0..=MAX_INTEREST_TOPICS)
+            .map(|i| format!("t{}", i))
+            .collect();
+        assert_eq!(
+            MessageBuilder::interest_update(Some(topics))
+                .build()
+                .unwrap_err(),
+            BuildError::TooManyTopics(MAX_INTEREST_TOPICS + 1)
+        );
+
+        let support = |topic: &str, name: &str, range| SchemaSupport {
+            topic: topic.to_owned(),
+            name: name.to_owned(),
+            range,
+        };
+        let range = VersionRange::exactly(1);
+        let schemas = vec![support("t", "s", range); MAX_SCHEMA_ENTRIES + 1];
+        assert_eq!(
+            validate_schemas(&schemas),
+            Err(BuildError::TooManySchemas(MAX_SCHEMA_ENTRIES + 1))
+        );
+        assert_eq!(
+            validate_schemas(&[support("", "s", range)]),
+            Err(BuildError::Topic(TopicError::Empty))
+        );
+        assert_eq!(
+            validate_schemas(&[support("t", "", range)]),
+            Err(BuildError::SchemaName(TopicError::Empty))
+        );
+        // An empty range only comes off the wire.
+        let empty: VersionRange =
+            bincode::deserialize(&bincode::serialize(&(3u32, 1u32)).unwrap()).unwrap();
+        assert_eq!(
+            validate_schemas(&[support("t", "s", empty)]),
+            Err(BuildError::EmptyRange(empty))
+        );
+    }
+
+    #[test]
+    fn refuses_nested_batches() {
+        let inner = Message::Batch(vec![Message::Text("hi".to_owned())]);
+        assert_eq!(
+            MessageBuilder::batch(vec![inner]).build().unwrap_err(),
+            BuildError::NestedBatch
+        );
+        // The messages of a batch are checked like the others.
+        let bad = Message::Topic {
+            topic: String::new(),
+            payload: Vec::new(),
+        };
+        assert_eq!(
+            MessageBuilder::batch(vec![bad]).build().unwrap_err(),
+            BuildError::Topic(TopicError::Empty)
+        );
+    }
+}
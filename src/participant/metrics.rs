@@ -0,0 +1,130 @@
+//! Duration Histograms.
+//!
+//! A `Histogram` counts durations in a few fixed buckets, enough to tell a participant whose
+//! sends take microseconds from one whose sends block for tens of milliseconds without keeping
+//! every sample. The buckets are bounded by `DURATION_BOUNDS_MS`; a duration lands in the first
+//! bucket whose bound is above it, and the durations at or above the last bound in the last
+//! bucket.
+
+use std::fmt;
+use std::time::Duration;
+
+/// The upper bounds of the buckets but the last, in milliseconds, exclusive.
+pub const DURATION_BOUNDS_MS: [u64; 3] = [1, 10, 50];
+
+/// The number of buckets of a `Histogram`.
+pub const BUCKETS: usize = DURATION_BOUNDS_MS.len() + 1;
+
+/// Counts durations in the buckets bounded by `DURATION_BOUNDS_MS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+}
+
+impl Histogram {
+    /// Constructs an empty `Histogram`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the bucket counting `duration`.
+    pub fn bucket(duration: Duration) -> usize {
+        DURATION_BOUNDS_MS
+            .iter()
+            .position(|bound| duration < Duration::from_millis(*bound))
+            .unwrap_or(BUCKETS - 1)
+    }
+
+    /// Counts one duration.
+    pub fn record(&mut self, duration: Duration) {
+        self.counts[Self::bucket(duration)] += 1;
+    }
+
+    /// Adds the counts of `other` to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+
+    /// Returns the number of durations counted.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Formats the buckets as `<1ms:4 1-10ms:1 10-50ms:0 >50ms:0`.
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, count) in self.counts.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            match index {
+                0 => write!(f, "<{}ms", DURATION_BOUNDS_MS[0])?,
+                _ if index == BUCKETS - 1 => write!(f, ">{}ms", DURATION_BOUNDS_MS[index - 1])?,
+                _ => write!(
+                    f,
+                    "{}-{}ms",
+                    DURATION_BOUNDS_MS[index - 1],
+                    DURATION_BOUNDS_MS[index]
+                )?,
+            }
+            write!(f, ":{}", count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn a_duration_lands_below_the_first_bound_above_it() {
+        for (duration, bucket) in [
+            (Duration::ZERO, 0),
+            (ms(1) - Duration::from_nanos(1), 0),
+            (ms(1), 1),
+            (ms(9), 1),
+            (ms(10), 2),
+            (ms(49), 2),
+            (ms(50), 3),
+            (Duration::from_secs(3600), 3),
+        ] {
+            assert_eq!(Histogram::bucket(duration), bucket, "{:?}", duration);
+        }
+    }
+
+    #[test]
+    fn merging_adds_the_counts_bucket_by_bucket() {
+        let mut first = Histogram::new();
+        for millis in [0, 0, 5, 60] {
+            first.record(ms(millis));
+        }
+        let mut second = Histogram::new();
+        for millis in [20, 60, 70] {
+            second.record(ms(millis));
+        }
+        first.merge(&second);
+        assert_eq!(first.total(), 7);
+        assert_eq!(first.to_string(), "<1ms:2 1-10ms:1 10-50ms:1 >50ms:3");
+
+        let before = first;
+        first.merge(&Histogram::new());
+        assert_eq!(first, before);
+    }
+
+    #[test]
+    fn an_empty_histogram_shows_every_bucket() {
+        assert_eq!(Histogram::new().total(), 0);
+        assert_eq!(
+            Histogram::new().to_string(),
+            "<1ms:0 1-10ms:0 10-50ms:0 >50ms:0"
+        );
+    }
+}
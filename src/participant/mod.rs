@@ -7,12 +7,212 @@
 //!
 //! ## Submodules
 //!
+//! - `alert`: Runs the commands and web hooks bound to significant events, rate limited per
+//!   event type.
+//!
+//! - `anomaly`: Counts the recoverable anomalies of the protocol, and makes the first one fatal
+//!   with `--strict-protocol`.
+//!
+//! - `audit`: Holds the invariant checks of the periodic storage consistency audit.
+//!
+//! - `balance`: Decides when a participant with too few outbound connections dials one of its
+//!   inbound-only peers back, and hands the peer over to the new connection.
+//!
+//! - `ban`: Keeps the addresses banned at runtime by `evict`, until their bans expire.
+//!
+//! - `batch`: Queues the messages sent to a peer within a short window and packs them into
+//!   shared frames.
+//!
+//! - `builder`: Configures an embedded participant, with no identity file, peer cache or
+//!   printed events unless asked for.
+//!
+//! - `callback`: Runs the callbacks of the topic subscriptions on worker threads, off the event
+//!   loop, from a bounded queue per subscription.
+//!
+//! - `candidate`: Holds the listed addresses until enough distinct peers corroborate them to
+//!   be dialed.
+//!
+//! - `channels`: Creates the channels handing work to the internal threads, keeping how many
+//!   messages wait in each of them.
+//!
+//! - `collections`: Provides `BoundedMap`, the capped and expiring map used for per-peer and
+//!   per-address state.
+//!
+//! - `compactlist`: Packs the participants lists sent to current peers, as differences
+//!   between sorted addresses.
+//!
+//! - `config`: Gathers the settings of a participant into a single `NodeConfig` structure.
+//!
+//! - `contact`: Checks that the first frame of a new connection belongs to this protocol, so
+//!   a peer running an incompatible build is closed before anything it sent is used.
+//!
+//! - `crossed`: Breaks the tie between the two connections of peers that dialed each other at
+//!   once, the same way on both sides.
+//!
+//! - `csv`: Formats the summaries of the peers as an RFC 4180 table with a fixed set of
+//!   columns, for `peers export-csv` and `--report-format`.
+//!
+//! - `descriptors`: Bounds the sockets a participant opens by its soft limit of file
+//!   descriptors, putting dials off and refusing connections past it.
+//!
+//! - `dials`: Keeps the trail of the dials started or decided against, by address and overall.
+//!
+//! - `echo`: Recognizes the peers sending the gossip texts of this participant back to it.
+//!
+//! - `error`: Defines the structured `ParticipantError` type returned when a participant can
+//!   not start or has to stop.
+//!
+//! - `exchange`: Matches the participants lists received to the requests they answer, and
+//!   the purpose each was sent for.
+//!
+//! - `event`: Defines the internal events delivered to the participant's event loop, such as
+//!   console commands.
+//!
+//! - `frame`: Estimates the size of a message on the wire before it is published, from the
+//!   encoding the send path uses.
+//!
+//! - `filter`: Decides which peer addresses are refused, from the `--block` addresses and a
+//!   filter file reloaded when it changes.
+//!
+//! - `handle`: Drives a running participant from another thread: runs console commands, takes
+//!   its report and shuts it down.
+//!
+//! - `heal`: Probes the remembered peers that departed, so the halves of a split network find
+//!   each other again once it heals.
+//!
+//! - `hints`: Recognizes the common misconfigurations from what the participant observes, and
+//!   tells the operator what to check.
+//!
+//! - `history`: Rebuilds the peer set of a recorded participant at any time of its run from
+//!   the membership records of its recording, and sums its churn up.
+//!
+//! - `identity`: Keeps the persistent node id of a participant in its identity file, the same
+//!   across restarts and addresses.
+//!
+//! - `inbox`: Keeps bounded queues of the received application messages, polled instead of
+//!   called back.
+//!
+//! - `interest`: Tells peers which topics to send and decides which peers a publication goes
+//!   to.
+//!
+//! - `interface`: Keeps the listen interfaces of a participant and the address announced to
+//!   the peers of each.
+//!
+//! - `join`: Decides when a participant probing the network with `--join-check` joined it,
+//!   and formats the outcome.
+//!
+//! - `load`: Measures how far the event loop falls behind and decides what a busy or
+//!   overloaded participant stops doing.
+//!
+//! - `metrics`: Counts durations in fixed-bucket histograms.
+//!
 //! - `message`: Defines the message formats used for communication between network participants.
 //!   Includes serialization and deserialization functionalities for efficient network transmission.
 //!
+//! - `observer`: Tracks the observers watching the network without joining it and coalesces
+//!   the membership updates streamed to them.
+//!
+//! - `outbound`: Checks each frame right before it is sent, with `--validate-outbound` or in a
+//!   debug build, so no invalid frame this participant built reaches a peer.
+//!
+//! - `payload`: Generates the gossip texts broadcast every period and reads them back as a
+//!   compact form for the logs.
+//!
+//! - `period`: Computes the period between two broadcasts, fixed or growing with the number of
+//!   connected peers.
+//!
+//! - `policy`: Decides what happens to an inbound message depending on the state of its
+//!   sender.
+//!
+//! - `probe`: Measures the round trip and loss to one peer with echoed probes.
+//!
+//! - `provenance`: Records where each address was first learned and which peers listed it
+//!   since.
+//!
+//! - `record`: Writes the inbound network events of a participant to a recording and reads
+//!   them back.
+//!
+//! - `reorder`: Puts the messages of a publisher on a topic back in publish order for ordered
+//!   subscriptions, reporting the ones that never arrive.
+//!
+//! - `replay`: Feeds the events of a recording to a participant whose sends go nowhere, with
+//!   their original timing or as fast as possible, and summarizes the state they lead it to
+//!   and what it sent.
+//!
+//! - `round`: Numbers the broadcasts of a participant in rounds and aligns them with the
+//!   rounds heard from its peers.
+//!
+//! - `reload`: Classifies the configuration fields that change while running and merges a
+//!   reloaded configuration into the running one.
+//!
+//! - `report`: Summarizes the run of a participant as JSON, for scripts checking whether the
+//!   network converged.
+//!
+//! - `request`: Correlates requests sent to one peer with their responses and runs the
+//!   handlers answering the requests of others on worker threads.
+//!
+//! - `resolve`: Resolves a `--connect` target given as a host name again while the participant
+//!   is isolated, so it follows a seed whose address changed.
+//!
+//! - `retention`: Holds the session histories, the remembered departed peers and the peer lists
+//!   to their retention policies, and estimates how much each takes.
+//!
 //! - `storage`: Implements storage mechanisms for tracking known participants within the network.
 //!   Provides functionalities for adding, removing, and querying participant information.
 //!
+//! - `rate_limit`: Implements the per-peer token-bucket rate limiter guarding participants
+//!   list requests.
+//!
+//! - `threads`: Spawns the named internal threads of a participant under a cap, and stops
+//!   them at shutdown.
+//!
+//! - `tick`: Watches the time between broadcast ticks and tells a participant that was not
+//!   scheduled, or that was suspended, from a slow network.
+//!
+//! - `timing`: Scales what a participant expects of each peer to the period the peer
+//!   declared, and tells which periods are too far from its own.
+//!
+//! - `topic`: Keeps the subscriptions to application topics and dispatches the topic messages
+//!   received from peers.
+//!
+//! - `schedule`: Keeps the publications scheduled for later and tells which are due.
+//!
+//! - `schema`: Matches versioned payloads to the handlers of their schema, and counts the
+//!   peers handling each version.
+//!
+//! - `session`: Records the sessions of every participant address and derives uptime and flap
+//!   metrics from them.
+//!
+//! - `skew`: Estimates how far the clock of each peer is from ours, from the timestamps of
+//!   the clock requests exchanged with it.
+//!
+//! - `snapshot`: Reads and writes the membership snapshots used to bootstrap a participant
+//!   from a file instead of a live peer.
+//!
+//! - `split`: Groups the peers by the participants lists they sent, to find and bridge the
+//!   groups that do not know each other.
+//!
+//! - `state`: Saves the peers, the topic sequence numbers and the scheduled publications of a
+//!   participant, so a planned restart resumes with them.
+//!
+//! - `peer_cache`: Persists the most recently handshaked peer addresses so a restarted
+//!   participant can rejoin the network without an explicit bootstrap target.
+//!
+//! - `trace`: Times the phases of the traced broadcast ticks, for performance debugging.
+//!
+//! - `transport`: Defines the network backend a participant listens, dials and sends
+//!   through, and implements it over `message-io` and over an in-process network without
+//!   sockets.
+//!
+//! - `test_support`: Holds the values the unit tests of the crate build their addresses and
+//!   scratch directories from.
+//!
+//! - `verify`: Checks the identity of the `--connect` target before its participants list is
+//!   trusted.
+//!
+//! - `warmup`: Spreads the announcement and the first dials of a participant started along
+//!   with many others over a warm-up window.
+//!
 //! - `utils`: Contains utility functions that support various operations within the participant
 //!   management system, including address formatting and message sending.
 //!
@@ -24,7 +224,77 @@
 //! distributed network, ensuring modular design and ease of integration into broader network
 //! application architectures.
 
+pub mod alert;
+pub mod anomaly;
+pub mod audit;
+pub mod balance;
+pub mod ban;
+pub mod batch;
+pub mod builder;
+pub mod callback;
+pub mod candidate;
+pub mod channels;
+pub mod collections;
+pub mod compactlist;
+pub mod config;
+pub mod contact;
+pub mod crossed;
+pub mod csv;
+pub mod descriptors;
+pub mod dials;
+pub mod echo;
+pub mod error;
+pub mod event;
+pub mod exchange;
+pub mod filter;
+pub mod frame;
+pub mod handle;
+pub mod heal;
+pub mod hints;
+pub mod history;
+pub mod identity;
+pub mod inbox;
+pub mod interest;
+pub mod interface;
+pub mod join;
+pub mod load;
 pub mod message;
+pub mod metrics;
 pub mod model;
+pub mod observer;
+pub mod outbound;
+pub mod payload;
+pub mod peer_cache;
+pub mod period;
+pub mod policy;
+pub mod probe;
+pub mod provenance;
+pub mod rate_limit;
+pub mod record;
+pub mod reload;
+pub mod reorder;
+pub mod replay;
+pub mod report;
+pub mod request;
+pub mod resolve;
+pub mod retention;
+pub mod round;
+pub mod schedule;
+pub mod schema;
+pub mod session;
+pub mod skew;
+pub mod snapshot;
+pub mod split;
+pub mod state;
 pub mod storage;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod threads;
+pub mod tick;
+pub mod timing;
+pub mod topic;
+pub mod trace;
+pub mod transport;
 pub mod utils;
+pub mod verify;
+pub mod warmup;
@@ -13,25 +13,148 @@
 //! - Dynamically updating the list of known participants based on network interactions.
 //!
 //! This module leverages `message-io` for network communication, providing an asynchronous,
-//! event-driven architecture that facilitates efficient message handling. The use of
-//! `Arc<Mutex<...>>` for shared state management ensures thread-safe operations across
-//! the different components of the network system.
+//! event-driven architecture that facilitates efficient message handling. All of the
+//! participant's state is owned by its event loop: timers, the console and the shutdown
+//! watcher only send `InternalEvent`s to it, so no state is shared between threads and no
+//! mutex is needed.
+//!
+//! `Participant` is defined here with its event loop, while its methods are spread over the
+//! submodules by concern: `startup` for the steps of `new`, `protocol` for the messages peers
+//! send, `connections` for dials and their outcome, `gossip` for the tick, `topics`,
+//! `scheduling`, `requests` and `probes` for what the application and the console ask for,
+//! `maintenance` for the periodic upkeep, `reporting` for the state file and the report, and
+//! `commands` and `stats` for the console commands.
+
+mod commands;
+mod connections;
+mod gossip;
+mod maintenance;
+mod probes;
+mod protocol;
+mod reporting;
+mod requests;
+mod scheduling;
+mod startup;
+mod stats;
+mod topics;
 
-use crate::printer::{init as logger_init, print_event};
+use crate::admin::AdminSocket;
+use crate::console::{self, Command};
+use crate::printer::{
+    format_elapsed, init as logger_init, print_event, print_line, sanitize_for_log,
+};
+use crate::signal;
 
-use super::message::Message;
-use super::storage::{ParticipantAddress, ParticipantsStorage};
-use super::utils::{format_list_of_addrs, send_message};
+use super::alert::Alerter;
+use super::anomaly::{AnomalyKind, AnomalyPolicy};
+use super::balance::Balancer;
+use super::ban::BanList;
+use super::batch::{Outbox, Priority, MAX_BATCH_BYTES};
+use super::candidate::Candidates;
+use super::channels::ChannelRegistry;
+use super::collections::{BoundedMap, Eviction, EvictionCause};
+use super::compactlist::PackingStats;
+use super::config::NodeConfig;
+use super::contact::{validate_first_contact, MismatchReason, PendingContacts};
+use super::crossed::CrossedConnections;
+use super::descriptors::{self, DescriptorBudget, DialBacklog, RefusalLog};
+use super::dials::{DialOutcome, DialTrail};
+use super::echo::EchoGuard;
+use super::error::ParticipantError;
+use super::event::InternalEvent;
+use super::exchange::ListExchanges;
+use super::filter::{is_loopback_ip, DialHygiene, FilterFile, FilterSet, HygieneLog};
+use super::heal::HealProber;
+use super::hints::Hints;
+use super::identity::{identity_path, stored_key, Identity};
+use super::inbox::{Inbox, InboxRegistry};
+use super::interest::{Interest, InterestAnnouncer};
+use super::interface::Interfaces;
+use super::join::JoinCheck;
+use super::load::LoadShedder;
+use super::message::{Message, BATCH_VERSION, SCHEMAS_VERSION};
+use super::observer::ObserverRegistry;
+use super::outbound::OutboundCheck;
+use super::peer_cache::PeerCache;
+use super::period::GossipPeriod;
+use super::probe::{ProbeSession, ProbeSummary};
+use super::provenance::ProvenanceBook;
+use super::rate_limit::RateLimiter;
+use super::record::{RecordedEvent, Recorder};
+use super::reload::ConfigLoader;
+use super::report::{config_digest, PeerSetChanges, ReportFormat};
+use super::request::{PendingRequests, RequestClient, RequestHandlers, REQUEST_WORKERS};
+use super::resolve::ConnectTarget;
+use super::retention::{RetainedKind, Retention};
+use super::round::RoundClock;
+use super::schedule::Schedule;
+use super::schema::SchemaCensus;
+use super::session::DepartureReason;
+use super::skew::CLOCK_SAMPLE_INTERVAL;
+use super::snapshot::Snapshot;
+use super::split::{PeerLists, SPLIT_BRIDGE_INTERVAL};
+use super::storage::ParticipantsStorage;
+use super::threads::{Join, SpawnError, ThreadRegistry, THREAD_JOIN_TIMEOUT};
+use super::tick::TickMonitor;
+use super::topic::TopicRegistry;
+use super::trace::TickTracer;
+use super::transport::{
+    CheckedTransport, Connection, MessageIoTransport, Transport, TransportContext, TransportEvent,
+};
+use super::utils::{broadcast_message, CanonicalAddr};
+use super::verify::{BootstrapCheck, Expected};
+use super::warmup::Warmup;
+use startup::{
+    bind_interfaces, bootstrap_plan, import_membership, load_filter, load_identity, restore_state,
+    trusted_snapshot_keys,
+};
 
-use message_io::network::{Endpoint, NetEvent, Transport};
-use message_io::node::{self, NodeHandler, NodeListener};
-use rand::Rng;
+use message_io::node::{self, NodeEvent, NodeHandler, NodeListener};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use std::io::{self};
-use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{self};
+use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the shutdown watcher checks whether a shutdown signal has arrived.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the participant that listed an address is remembered, in case dialing it fails.
+const LIST_ORIGIN_TTL: Duration = Duration::from_secs(60);
+
+/// How long a neighbor refuses to introduce the same pair of participants again.
+const INTRODUCTION_PAIR_TTL: Duration = Duration::from_secs(60);
+
+/// Minimal time between two re-announcements of this participant.
+const REANNOUNCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the interface of an accepted connection is remembered until its public address
+/// is announced.
+const ACCEPTED_INTERFACE_TTL: Duration = Duration::from_secs(60);
+
+/// How long a request sent from the console waits for its response.
+const CONSOLE_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a command sent through the admin socket waits for the event loop to run it.
+const ADMIN_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the addresses refused by the dial hygiene are summed up in the log.
+const HYGIENE_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the filter file is checked for changes.
+const FILTER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The number of participants list requests a peer can send at once.
+const PUSH_LIST_BURST: f64 = 5.0;
+
+/// The sustained number of participants list requests per second allowed per peer.
+const PUSH_LIST_PER_SECOND: f64 = 1.0;
 
 /// Represents a participant in the network.
 ///
@@ -39,13 +162,125 @@ use std::time::{Duration, Instant};
 /// for a participant within the network, including its node handler for network
 /// operations, its public address, and the storage for other participants.
 pub struct Participant {
-    node_handler: Arc<Mutex<NodeHandler<()>>>,
-    node_listener: Option<NodeListener<()>>,
-    public_addr: SocketAddr,
-    period: u32,
-    connect: Option<String>,
-    participants: Arc<Mutex<ParticipantsStorage<Endpoint>>>,
+    node_handler: NodeHandler<InternalEvent>,
+    transport: CheckedTransport,
+    node_listener: Option<NodeListener<InternalEvent>>,
+    interfaces: Interfaces,
+    accepted_interfaces: BoundedMap<Connection, usize>,
+    contacts: PendingContacts<Connection>,
+    period: GossipPeriod,
+    effective_period: Duration,
+    network: Option<String>,
+    logged_period: Duration,
+    rounds: Option<RoundClock>,
+    broadcast_ticks: u64,
+    legacy_payload: bool,
+    node_label: Option<String>,
+    tick_monitor: TickMonitor,
+    freeze_grace_until: Option<Instant>,
+    participants: ParticipantsStorage<Connection>,
     time_start: Arc<Instant>,
+    peer_cache: PeerCache,
+    bootstrap_candidates: VecDeque<String>,
+    connect_target: Option<ConnectTarget>,
+    hints: Hints,
+    packing: PackingStats,
+    provenances: ProvenanceBook,
+    bootstrap_check: BootstrapCheck<Connection>,
+    bootstrapping: bool,
+    was_connected: bool,
+    alerter: Alerter,
+    half_open_threshold: u32,
+    period_skew_ratio: u32,
+    max_clock_offset: Duration,
+    next_clock_sample: Instant,
+    require_bootstrap: bool,
+    join_check: Option<JoinCheck>,
+    interactive: bool,
+    exit_on_eof: bool,
+    exec: Vec<Command>,
+    admin_socket: Option<PathBuf>,
+    observers: ObserverRegistry<Connection>,
+    recent_dials: BoundedMap<CanonicalAddr, ()>,
+    push_list_limiter: RateLimiter<Connection>,
+    echoes: EchoGuard<Connection>,
+    anomalies: AnomalyPolicy,
+    list_exchanges: ListExchanges<Connection>,
+    filter: FilterSet,
+    bans: BanList,
+    filter_file: Option<FilterFile>,
+    last_filter_poll: Instant,
+    dial_hygiene: DialHygiene,
+    hygiene_log: HygieneLog,
+    last_hygiene_report: Instant,
+    list_origins: BoundedMap<CanonicalAddr, SocketAddr>,
+    candidates: Candidates,
+    introduced_pairs: BoundedMap<(CanonicalAddr, CanonicalAddr), ()>,
+    last_reannounce: Option<Instant>,
+    topics: TopicRegistry,
+    schema_census: SchemaCensus,
+    topic_sequences: HashMap<String, u64>,
+    topic_broadcast: HashSet<String>,
+    interest: InterestAnnouncer,
+    interest_spared: u64,
+    reorder_timer_armed: bool,
+    schedule: Schedule,
+    /// The due time the timer of the schedule is armed for, if any.
+    schedule_armed: Option<Instant>,
+    pending_requests: PendingRequests<Connection>,
+    request_handlers: RequestHandlers,
+    threads: ThreadRegistry,
+    channels: ChannelRegistry,
+    recorder: Option<Recorder>,
+    record_snapshot_every: Duration,
+    /// When the next self-state record is due, `None` when further than an `Instant` can tell.
+    next_self_state: Option<Instant>,
+    /// The public addresses of the suspects redialed, until their redial ends.
+    redialed: HashSet<SocketAddr>,
+    balancer: Option<Balancer<Connection>>,
+    crossed: CrossedConnections<Connection>,
+    descriptors: DescriptorBudget,
+    /// The dials put off until a file descriptor is free.
+    dial_backlog: DialBacklog,
+    refusals: RefusalLog,
+    state_file: Option<PathBuf>,
+    state_save_interval: Option<Duration>,
+    report_file: Option<PathBuf>,
+    report_format: ReportFormat,
+    config_digest: u64,
+    peer_set: PeerSetChanges,
+    audit_every: u64,
+    ticks_since_audit: u64,
+    share_limit: usize,
+    send_budget: Duration,
+    outbox: Outbox<Connection>,
+    connect_timeout: Duration,
+    heal: HealProber<Connection>,
+    dials: DialTrail<Connection>,
+    peer_lists: PeerLists,
+    retention: Retention,
+    bridged_splits: BoundedMap<Vec<Vec<SocketAddr>>, ()>,
+    split_brains: u64,
+    identity: Identity,
+    load: LoadShedder,
+    warmup: Option<Warmup>,
+    /// Draws the random choices of the participant, from the seed of `NodeConfig::seed` or
+    /// one of its own, which `record` keeps so a replay draws the same.
+    rng: StdRng,
+    maintenance_due: Instant,
+    tracer: TickTracer,
+    inboxes: InboxRegistry,
+    probe: Option<(Connection, ProbeSession)>,
+    probe_timer_armed: bool,
+    probe_runs: u64,
+    last_probe: Option<ProbeSummary>,
+    slowest_send: Option<(SocketAddr, Duration)>,
+    failure: Option<ParticipantError>,
+    /// The configuration running, with the hot fields of the last reload.
+    config: NodeConfig,
+    reloader: Option<ConfigLoader>,
+    /// The number of reloads applied, and of those rejected.
+    reloads: (u64, u64),
 }
 
 impl Participant {
@@ -56,34 +291,373 @@ impl Participant {
     ///
     /// # Parameters
     ///
-    /// - `period`: The interval in seconds between each random message broadcast.
-    /// - `port`: The port number on which this node will listen for incoming connections.
-    /// - `connect`: An optional address of another node to initially connect to.
+    /// - `config`: The settings of the participant, see `NodeConfig`.
     ///
     /// # Returns
     ///
-    /// An `io::Result<Self>` indicating success or failure.
-    pub fn new(period: u32, port: u32, connect: Option<String>) -> io::Result<Self> {
-        let (handler, listener) = node::split::<()>();
+    /// A `Result<Self, ParticipantError>` which fails with `ParticipantError::Bind` when the
+    /// listen address is not available, with `ParticipantError::Snapshot` when the
+    /// membership snapshot to import can not be used, with `ParticipantError::Filter` when
+    /// the filter file can not be read or parsed, with `ParticipantError::StateFile` when
+    /// the identity file or the recording can not be created, or with
+    /// `ParticipantError::Threads` when `--max-threads` leaves no room for the internal threads.
+    pub fn new(config: NodeConfig) -> Result<Self, ParticipantError> {
+        Self::with_transport(config, MessageIoTransport::new())
+    }
 
-        let listen_addr = format!("127.0.0.1:{}", port);
-        let (_, public_addr) = handler
-            .network()
-            .listen(Transport::FramedTcp, listen_addr)?;
+    /// Constructs a new `Participant` running on `transport` instead of the TCP transport of
+    /// `message-io`, such as an `InMemoryTransport`.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: The settings of the participant, see `NodeConfig`.
+    /// - `transport`: The backend carrying the frames of the participant, not attached yet.
+    ///
+    /// # Returns
+    ///
+    /// The participant, or the errors of `Participant::new`.
+    pub fn with_transport<T: Transport + 'static>(
+        config: NodeConfig,
+        transport: T,
+    ) -> Result<Self, ParticipantError> {
+        let running_config = config.clone();
+        let (handler, listener) = node::split::<InternalEvent>();
+        let descriptors = DescriptorBudget::new(descriptors::soft_limit(), config.fd_headroom);
+        let mut transport = CheckedTransport::new(Box::new(transport));
+        transport.attach(TransportContext::new(handler.clone(), descriptors.clone()));
+
+        let interfaces = bind_interfaces(&mut transport, &config)?;
+        let public_addr = interfaces.primary();
 
         let time_start = logger_init(&public_addr);
+        if config.validate_outbound || cfg!(debug_assertions) {
+            let fatal = config.strict_protocol || cfg!(debug_assertions);
+            transport.validate_outbound(OutboundCheck::new(fatal, time_start.clone()));
+        }
+        let budget = descriptors.stats();
+        let formatted_msg = format!(
+            "File descriptors: soft limit {}, {} kept for files, at most {} connections",
+            budget.limit,
+            budget.limit - budget.ceiling,
+            budget.ceiling
+        );
+        print_event(time_start.clone(), &formatted_msg);
+        for interface in interfaces.secondary() {
+            let formatted_msg = format!(
+                "Also listening on \"{}\", announced as \"{}\"",
+                interface.listen, interface.advertise
+            );
+            print_event(time_start.clone(), &formatted_msg);
+        }
+
+        let identity = load_identity(&config, &time_start)?;
+
+        let peer_cache = if config.use_peer_cache {
+            PeerCache::load(public_addr.port(), config.network.as_deref())
+        } else {
+            PeerCache::disabled()
+        };
+
+        let imported = import_membership(&config, public_addr, &identity, &time_start)?;
+
+        let (remembered, topic_sequences, schedule) = restore_state(&config, &time_start);
+        let mut participants = ParticipantsStorage::new();
+        for peer in &remembered {
+            participants.remember(peer.clone(), Instant::now(), SystemTime::now());
+        }
+
+        let config_digest = config_digest(&format!("{:?}", config));
+        let threads = ThreadRegistry::new(config.max_threads);
+        let channels = ChannelRegistry::new();
+        let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let recorder = match &config.record {
+            Some(path) => Some(
+                Recorder::create(
+                    path,
+                    public_addr,
+                    format!("{:?}", config),
+                    (config.args.clone(), seed),
+                    &threads,
+                    &channels,
+                )
+                .map_err(ParticipantError::StateFile)?,
+            ),
+            None => None,
+        };
+
+        let (filter, filter_file) = load_filter(&config)?;
+
+        let (candidates, provenances, bootstrap_candidates) = bootstrap_plan(
+            &config,
+            &remembered,
+            imported,
+            &peer_cache,
+            &interfaces,
+            &time_start,
+        );
+
+        let eviction_start = time_start.clone();
+        let recent_dials = BoundedMap::new(
+            config.dedup_entries,
+            Eviction::Fifo,
+            Some(config.dial_memory),
+        )
+        .with_eviction_callback(move |addr, _, cause| {
+            if cause == EvictionCause::Capacity {
+                let formatted_msg = format!(
+                    "Dedup budget exhausted, forgetting the dial to \"{}\" early",
+                    addr
+                );
+                print_event(eviction_start.clone(), &formatted_msg);
+            }
+        });
 
         Ok(Self {
-            public_addr,
-            node_handler: Arc::new(Mutex::new(handler)),
+            interfaces,
+            contacts: PendingContacts::new(),
+            accepted_interfaces: BoundedMap::new(
+                config.dedup_entries,
+                Eviction::Fifo,
+                Some(ACCEPTED_INTERFACE_TTL),
+            ),
+            node_handler: handler,
+            transport,
             node_listener: Some(listener),
-            connect,
-            period,
-            participants: Arc::new(Mutex::new(ParticipantsStorage::new(public_addr))),
+            period: config.period,
+            effective_period: config.period.for_peers(0),
+            network: config.network.clone(),
+            logged_period: config.period.for_peers(0),
+            rounds: config
+                .rounds
+                .then(|| RoundClock::new(config.period.for_peers(0), Instant::now())),
+            broadcast_ticks: 0,
+            legacy_payload: config.legacy_payload,
+            node_label: config.node_label.clone(),
+            tick_monitor: TickMonitor::new(config.late_tick_factor, config.frozen_tick_factor),
+            freeze_grace_until: None,
+            participants,
+            bootstrapping: !bootstrap_candidates.is_empty(),
+            was_connected: false,
+            alerter: Alerter::new(
+                config.alerts,
+                config.alert_cooldown,
+                time_start.clone(),
+                &threads,
+                &channels,
+                &descriptors,
+            )
+            .map_err(ParticipantError::Threads)?,
             time_start,
+            peer_cache,
+            bootstrap_candidates,
+            hints: Hints::new(config.hints, config.connect.clone(), Instant::now()),
+            packing: PackingStats::default(),
+            provenances,
+            connect_target: config
+                .connect
+                .as_deref()
+                .and_then(|connect| ConnectTarget::named(connect, config.resolve_interval)),
+            bootstrap_check: BootstrapCheck::new(
+                config.connect.clone(),
+                Expected {
+                    network: config.expect_network_id.clone(),
+                    node_id: config.expect_node_id,
+                },
+                config.bootstrap_verification,
+            ),
+            half_open_threshold: config.half_open_threshold,
+            period_skew_ratio: config.period_skew_ratio,
+            max_clock_offset: config.max_clock_offset,
+            next_clock_sample: Instant::now() + CLOCK_SAMPLE_INTERVAL,
+            require_bootstrap: config.require_bootstrap,
+            join_check: config.join_check,
+            interactive: config.interactive,
+            exit_on_eof: config.exit_on_eof,
+            exec: config.exec,
+            admin_socket: config.admin_socket,
+            observers: ObserverRegistry::new(config.max_observers, Instant::now()),
+            recent_dials,
+            push_list_limiter: RateLimiter::new(
+                config.ratelimit_entries,
+                PUSH_LIST_BURST,
+                PUSH_LIST_PER_SECOND,
+            ),
+            echoes: EchoGuard::new(config.dedup_entries),
+            anomalies: AnomalyPolicy::new(config.strict_protocol),
+            list_exchanges: ListExchanges::new(config.dedup_entries),
+            filter,
+            bans: BanList::new(),
+            filter_file,
+            last_filter_poll: Instant::now(),
+            dial_hygiene: DialHygiene {
+                allow_privileged_ports: config.allow_privileged_ports,
+                allow_special_ranges: config.allow_special_ranges,
+                allow_loopback: is_loopback_ip(public_addr.ip()),
+            },
+            hygiene_log: HygieneLog::new(config.dedup_entries),
+            last_hygiene_report: Instant::now(),
+            list_origins: BoundedMap::new(
+                config.dedup_entries,
+                Eviction::Fifo,
+                Some(LIST_ORIGIN_TTL),
+            ),
+            candidates,
+            introduced_pairs: BoundedMap::new(
+                config.dedup_entries,
+                Eviction::Fifo,
+                Some(INTRODUCTION_PAIR_TTL),
+            ),
+            last_reannounce: None,
+            topics: TopicRegistry::new(
+                config.topics,
+                config.reorder_buffer,
+                config.reorder_window,
+                config.callback_workers,
+                &threads,
+            )
+            .map_err(ParticipantError::Threads)?,
+            schema_census: SchemaCensus::new(),
+            topic_sequences,
+            topic_broadcast: config.topic_broadcast.into_iter().collect(),
+            interest: InterestAnnouncer::new(),
+            interest_spared: 0,
+            reorder_timer_armed: false,
+            schedule,
+            schedule_armed: None,
+            pending_requests: PendingRequests::new(),
+            request_handlers: RequestHandlers::new(REQUEST_WORKERS, &threads, &channels)
+                .map_err(ParticipantError::Threads)?,
+            threads,
+            channels,
+            recorder,
+            record_snapshot_every: config.record_snapshot_every,
+            next_self_state: Instant::now().checked_add(config.record_snapshot_every),
+            redialed: HashSet::new(),
+            balancer: config.balance_connections.map(Balancer::new),
+            crossed: CrossedConnections::new(),
+            descriptors,
+            dial_backlog: DialBacklog::new(),
+            refusals: RefusalLog::new(),
+            state_file: config.state_file,
+            state_save_interval: config.state_save_interval,
+            report_file: config.report_file,
+            report_format: config.report_format,
+            config_digest,
+            peer_set: PeerSetChanges::default(),
+            audit_every: config.audit_every,
+            ticks_since_audit: 0,
+            share_limit: config.share_limit,
+            send_budget: config.send_budget,
+            outbox: Outbox::new(config.batch_window, MAX_BATCH_BYTES),
+            connect_timeout: config.connect_timeout,
+            heal: HealProber::new(config.heal_probe_interval, Instant::now()),
+            dials: DialTrail::new(config.dedup_entries),
+            peer_lists: PeerLists::new(config.retention[RetainedKind::Lists as usize].max_entries),
+            retention: Retention::new(config.retention),
+            bridged_splits: BoundedMap::new(
+                config.dedup_entries,
+                Eviction::Fifo,
+                Some(SPLIT_BRIDGE_INTERVAL),
+            ),
+            split_brains: 0,
+            identity,
+            load: LoadShedder::new(config.busy_lag, config.overload_lag),
+            warmup: config
+                .warmup
+                .map(|window| Warmup::new(window, rng.gen(), Instant::now())),
+            rng,
+            maintenance_due: Instant::now(),
+            tracer: TickTracer::new(config.trace_ticks),
+            inboxes: InboxRegistry::new(config.inbox_capacity),
+            probe: None,
+            probe_timer_armed: false,
+            probe_runs: 0,
+            last_probe: None,
+            slowest_send: None,
+            failure: None,
+            config: running_config,
+            reloader: None,
+            reloads: (0, 0),
         })
     }
 
+    /// Validates a configuration without starting a participant.
+    ///
+    /// Resolves the bootstrap target, binds the listen address and releases it immediately,
+    /// makes sure the admin socket could be bound and the recent peers cache file and the
+    /// identity file can be read or created, and reads the membership snapshot to import and the
+    /// filter file, and makes sure the recording can be created.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: The settings to validate.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` when a participant with this configuration could be started, or the
+    /// `ParticipantError` describing the first problem found.
+    pub fn check(config: &NodeConfig) -> Result<(), ParticipantError> {
+        if let Some(addr) = &config.connect {
+            addr.to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or_else(|| ParticipantError::Resolve(addr.clone()))?;
+        }
+
+        let listener =
+            TcpListener::bind(config.port.listen_addr()).map_err(ParticipantError::Bind)?;
+        let port = listener
+            .local_addr()
+            .map_err(ParticipantError::Bind)?
+            .port();
+        drop(listener);
+        for listen_addr in &config.listen {
+            TcpListener::bind(listen_addr).map_err(ParticipantError::Bind)?;
+        }
+
+        if let Some(path) = &config.admin_socket {
+            AdminSocket::check(path).map_err(ParticipantError::AdminSocket)?;
+        }
+
+        if config.use_peer_cache {
+            PeerCache::check(port, config.network.as_deref())
+                .map_err(ParticipantError::StateFile)?;
+        }
+
+        let identity_file = identity_path(
+            config.identity_file.as_deref(),
+            config.node_label.as_deref(),
+            config.port.get(),
+        );
+        let identity_file = identity_file.filter(|_| config.persist_identity);
+        if let Some(path) = &identity_file {
+            Identity::check(path).map_err(ParticipantError::StateFile)?;
+        }
+
+        if let Some(path) = &config.import_membership {
+            let own = identity_file.as_deref().and_then(stored_key);
+            Snapshot::load(
+                path,
+                SystemTime::now(),
+                config.max_snapshot_age,
+                config.require_signed_import,
+                &trusted_snapshot_keys(config, own),
+            )
+            .map_err(ParticipantError::Snapshot)?;
+        }
+
+        if let Some(path) = &config.filter_file {
+            FilterFile::load(path).map_err(ParticipantError::Filter)?;
+        }
+
+        if let Some(path) = &config.record {
+            Recorder::check(path).map_err(ParticipantError::StateFile)?;
+        }
+
+        Ok(())
+    }
+
     /// Starts the participant's network operations.
     ///
     /// This method initiates the participant's network activities by optionally connecting to another
@@ -93,318 +667,638 @@ impl Participant {
     ///
     /// # Behavior
     ///
-    /// 1. **Initial Connection**: If an initial connection address is provided (`self.connect`),
-    ///    attempts to connect to it and register the connection.
+    /// 1. **Initial Connection**: If an initial connection address is provided (`--connect`),
+    ///    attempts to connect to it and register the connection. When it cannot be reached, the
+    ///    addresses from the recent peers cache are tried one by one.
     ///
-    /// 2. **Periodic Messaging**: Launches a separate thread to send random messages at regular intervals
-    ///    defined by `self.period`.
+    /// 2. **Periodic Messaging**: Arms the `InternalEvent::BroadcastTick` timer, which sends a random
+    ///    message every `self.effective_period`, which may be as short as a few milliseconds.
+    ///    A participant probing the network with `--join-check` arms its timeout instead and
+    ///    never gossips.
     ///
     /// 3. **Event Listening**: Enters a loop to listen for and handle `NetEvent` occurrences, such as
-    ///    accepting new connections, receiving messages, and handling disconnections.
+    ///    accepting new connections, receiving messages, and handling disconnections, along with the
+    ///    `InternalEvent`s sent by timers, the console and the shutdown watcher. The loop owns all
+    ///    of the participant's state; other threads only ever send events to it.
     ///
     /// # Event Handling
     ///
     /// - **NetEvent::Accepted**: Triggered when a new incoming connection is accepted.
     ///
     /// - **NetEvent::Connected**: Triggered when a connection attempt is either successful or fails.
-    ///    On success, registers the new participant and sends initial synchronization messages.
+    ///   On success, registers the new participant and sends initial synchronization messages.
+    ///   On failure, the next bootstrap candidate is tried.
     ///
     /// - **NetEvent::Message**: Triggered upon receiving a message. It deserializes the message
-    ///    and processes it according to its type.
+    ///   and processes it according to its type.
     ///
     /// - **NetEvent::Disconnected**: Triggered when a connection is lost. Removes the disconnected
-    ///    participant from the list of known participants.
+    ///   participant from the list of known participants.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the event loop stops after a shutdown signal, or the `ParticipantError`
+    /// that stopped it.
     ///
     /// # Note
     ///
     /// This method constitutes the participant's main event loop, where all network activities are
     /// centralized. It leverages `message-io` for asynchronous event-driven communication.
-    pub fn run(mut self) {
-        // Attempt initial connection if an address is provided.
-        if let Some(addr) = &self.connect {
-            let handler = self.node_handler.lock().unwrap();
-
-            match handler.network().connect(Transport::FramedTcp, addr) {
-                Ok((endpoint, _)) => {
-                    let mut participants = self.participants.lock().unwrap();
-                    participants.add_known_participant(endpoint);
-                }
-                Err(_) => {
-                    println!("Failed to connect to {}", &addr);
+    pub fn run(mut self) -> Result<(), ParticipantError> {
+        // Attempt initial connection to the explicit target or, failing that, a cached peer,
+        // once the random share of the warm-up elapsed.
+        match self
+            .warmup
+            .as_ref()
+            .map(Warmup::announce_delay)
+            .filter(|delay| !delay.is_zero())
+        {
+            Some(delay) => {
+                let formatted_msg = format!("Warming up: announcing in {:.1?}", delay);
+                print_event(self.time_start.clone(), &formatted_msg);
+                // An announcement further away than an `Instant` can tell never comes.
+                if Instant::now().checked_add(delay).is_some() {
+                    self.node_handler
+                        .signals()
+                        .send_with_timer(InternalEvent::WarmupAnnounce, delay);
                 }
             }
+            None => self.dial_next_bootstrap(),
         }
 
-        // Start sending random messages at the specified periodic interval.
-        self.sending_random_message();
+        // Start sending random messages at the specified periodic interval, from the next epoch
+        // round in rounds.
+        if let Some(check) = &self.join_check {
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::JoinTimeout, check.timeout);
+        } else {
+            let first_tick = match &self.rounds {
+                Some(clock) => clock.remaining(self.effective_period, Instant::now()),
+                None => self.effective_period,
+            };
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::BroadcastTick, first_tick);
+            self.tick_monitor
+                .armed(first_tick, Instant::now(), SystemTime::now());
+        }
 
-        // Listen for and handle network events.
-        if let Some(node_listener) = self.node_listener.take() {
-            node_listener.for_each(move |event| match event.network() {
-                NetEvent::Accepted(_, _) => {}
-                NetEvent::Connected(endpoint, established) => {
-                    if established {
-                        self.connected(endpoint)
-                    } else {
-                        println!("Can not connect to {}", endpoint.addr());
-                        std::process::exit(1);
+        // Stop the event loop once a shutdown signal arrives, reload on a `SIGHUP`.
+        self.watching_signals().map_err(ParticipantError::Threads)?;
+
+        // Start the periodic housekeeping: observer updates and bounded map sweeps.
+        self.arm_maintenance_tick();
+        self.arm_schedule_timer();
+        self.arm_state_save();
+
+        // Forward the console commands and those of `--exec` to the event loop. A request is
+        // sent from the console thread itself, which waits for the response while the event
+        // loop keeps running.
+        if self.interactive || !self.exec.is_empty() {
+            let handler_clone = self.node_handler.clone();
+            let client = self.request_client();
+            let time_start = self.time_start.clone();
+            let inbox = self.inbox();
+            let script = std::mem::take(&mut self.exec);
+            console::spawn(
+                &self.threads,
+                script,
+                self.interactive,
+                self.exit_on_eof,
+                move |command| match command {
+                    Command::Request { peer, topic, text } => {
+                        let formatted_msg = request_outcome(&client, peer, &topic, text);
+                        print_event(time_start.clone(), &formatted_msg);
                     }
-                }
-                NetEvent::Message(message_sender, input_data) => {
-                    let message: Message = bincode::deserialize(input_data).unwrap();
-                    self.network_messages(message_sender, message)
-                }
+                    Command::Inbox(wait) => {
+                        let (messages, summary) = take_inbox(&inbox, wait);
+                        for formatted_msg in messages {
+                            print_event(time_start.clone(), &formatted_msg);
+                        }
+                        print_line(&summary);
+                    }
+                    command => handler_clone.signals().send(InternalEvent::Command {
+                        command,
+                        reply: None,
+                    }),
+                },
+            )
+            .map_err(ParticipantError::Threads)?;
+        }
 
-                NetEvent::Disconnected(endpoint) => {
-                    let mut participants = self.participants.lock().unwrap();
-                    ParticipantsStorage::drop(&mut participants, endpoint);
+        // Serve the same commands on the admin socket, each connection on a thread of its own
+        // waiting for the output from the event loop. The socket has an inbox of its own.
+        let mut admin_socket = None;
+        if let Some(path) = &self.admin_socket {
+            let mut socket = AdminSocket::bind(path).map_err(ParticipantError::AdminSocket)?;
+            let handler_clone = self.node_handler.clone();
+            let client = self.request_client();
+            let inbox = Arc::new(self.inbox());
+            socket
+                .serve(
+                    &self.threads,
+                    &self.descriptors,
+                    move |command| match command {
+                        Command::Request { peer, topic, text } => {
+                            Ok(vec![request_outcome(&client, peer, &topic, text)])
+                        }
+                        Command::Inbox(wait) => {
+                            let (mut output, summary) = take_inbox(&inbox, wait);
+                            output.push(summary);
+                            Ok(output)
+                        }
+                        command => {
+                            let (reply, output) = mpsc::channel();
+                            handler_clone.signals().send(InternalEvent::Command {
+                                command,
+                                reply: Some(reply),
+                            });
+                            output.recv_timeout(ADMIN_REPLY_TIMEOUT).map_err(|_| {
+                                "The participant did not run the command in time".to_owned()
+                            })
+                        }
+                    },
+                )
+                .map_err(ParticipantError::Threads)?;
+            let formatted_msg =
+                format!("Serving admin commands on \"{}\"", socket.path().display());
+            print_event(self.time_start.clone(), &formatted_msg);
+            admin_socket = Some(socket);
+        }
+
+        // Listen for and handle network and internal events.
+        if let Some(node_listener) = self.node_listener.take() {
+            node_listener.for_each(|event| match event {
+                NodeEvent::Network(net_event) => {
+                    if let Some(event) = self.transport.translate(net_event) {
+                        self.network_event(event)
+                    }
                 }
+                NodeEvent::Signal(internal_event) => self.internal_event(internal_event),
             });
         }
+
+        // The event loop is over: persist what is worth keeping for the next start.
+        drop(admin_socket);
+        self.peer_cache.save();
+        self.save_state();
+        self.write_report();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.finish();
+        }
+        self.stop_threads();
+
+        match self.failure.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 
-    /// Handles incoming network messages directed to this participant.
+    /// Handles one network event of the event loop, after handing it to the recorder when
+    /// `--record` is set.
     ///
-    /// This method decodes and processes various types of messages that can be received from other
-    /// participants in the network. It acts on the message based on its type, performing actions
-    /// such as updating the participants list, responding with a list of known participants,
-    /// or logging received text messages.
+    /// A connection accepted while no file descriptor is left is closed at once, unrecorded.
+    /// A connection that closed, or a dial that failed, frees a descriptor for the dials put
+    /// off, see `descriptors`.
     ///
     /// # Parameters
     ///
-    /// - `message_sender`: The `Endpoint` representing the source of the message. This is used
-    ///   for identifying the sender and potentially responding.
-    /// - `message`: The `Message` enum containing the message received. This enum encapsulates
-    ///   different types of messages that can be processed by this function.
-    ///
-    /// # Supported Message Types
-    ///
-    /// - `Message::PublicAddress`: Adds the sender's public address to the list of unknown participants
-    ///   if it is not already known.
-    /// - `Message::PushParticipantsList`: Responds to the sender with a list of known participant
-    ///   addresses.
-    /// - `Message::PullParticipantsList`: Updates the local list of participants with the addresses
-    ///   received in the message.
-    /// - `Message::Text`: Logs a received text message along with the sender's address.
-    fn network_messages(&self, message_sender: Endpoint, message: Message) {
-        match message {
-            // A public address message contains the sender's address.
-            // This address is added to the list of participants if it is not already known.
-            Message::PublicAddress(pub_addr) => {
-                let mut participants = self.participants.lock().unwrap();
-                participants.add_unknown_participant(message_sender, pub_addr);
+    /// - `event`: The event, translated from the `message-io` network of the node or
+    ///   delivered by the transport.
+    fn network_event(&mut self, event: TransportEvent<'_>) {
+        self.log_descriptor_pressure();
+        if let TransportEvent::Accepted(endpoint, _) = event {
+            if !self.transport.admit(endpoint) {
+                return self.refused_connection(endpoint);
             }
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record(RecordedEvent::from_transport_event(&event));
+        }
 
-            // A request to push the participants list triggers a response with the known participant
-            // addresses. This helps newly joined participants to learn about existing ones.
-            Message::PushParticipantsList => {
-                let list = {
-                    let participants = self.participants.lock().unwrap();
-                    participants.get_participants_list()
+        let freed = matches!(
+            event,
+            TransportEvent::Closed(_) | TransportEvent::Connected(_, false)
+        );
+        self.transport_event(event);
+        if freed {
+            self.resume_dials(Instant::now());
+        }
+    }
+
+    /// Handles one event of the transport.
+    fn transport_event(&mut self, event: TransportEvent<'_>) {
+        match event {
+            TransportEvent::Accepted(endpoint, listener) => {
+                // Remembered for every listener: it also tells the handshake that the peer
+                // connected by itself.
+                let interface = self.interfaces.of_listener(listener);
+                self.accepted_interfaces
+                    .insert(endpoint, interface, Instant::now());
+                self.contacts.expect(endpoint, Instant::now());
+            }
+            TransportEvent::Connected(endpoint, established) => {
+                let outcome = if established {
+                    DialOutcome::Connected
+                } else {
+                    DialOutcome::Failed
                 };
-                let msg = Message::PullParticipantsList(list);
-                send_message(&mut self.node_handler.lock().unwrap(), message_sender, &msg);
+                self.dials.finished(&endpoint, outcome, Instant::now());
+                if established {
+                    self.connected(endpoint)
+                } else {
+                    let formatted_msg = format!("Can not connect to \"{}\"", endpoint.addr());
+                    print_event(self.time_start.clone(), &formatted_msg);
+                    self.dial_failed(endpoint);
+                }
             }
+            TransportEvent::Frame(message_sender, input_data) => {
+                let decoded = Message::decode(input_data);
+                self.hints
+                    .frame(message_sender.addr(), decoded.is_ok(), Instant::now());
+                if self.contacts.is_pending(&message_sender) {
+                    let first = match &decoded {
+                        Ok((message, _)) => validate_first_contact(message),
+                        Err(_) => Err(MismatchReason::Undecodable),
+                    };
+                    if let Err(reason) = first {
+                        return self.protocol_mismatch(message_sender, reason);
+                    }
+                    let (network, node_id) = match &decoded {
+                        Ok((_, trailer)) => (trailer.network.as_deref(), trailer.node_id),
+                        Err(_) => (None, None),
+                    };
+                    if !self.verify_bootstrap(message_sender, network, node_id) {
+                        return;
+                    }
+                    self.first_contact(message_sender);
+                }
 
-            // When a list of participants is received, update the local storage to include any new
-            // addresses. This ensures the participant is aware of other peers in the network.
-            Message::PullParticipantsList(addrs) => {
-                self.pull_participants_list(message_sender, addrs)
+                let Ok((message, trailer)) = decoded else {
+                    let formatted_msg = format!(
+                        "Dropping a frame of {} bytes from \"{}\" that does not decode",
+                        input_data.len(),
+                        message_sender.addr()
+                    );
+                    print_event(self.time_start.clone(), &formatted_msg);
+                    let len = input_data.len();
+                    return self.anomaly(AnomalyKind::Undecodable, message_sender, || {
+                        format!("a frame of {} bytes", len)
+                    });
+                };
+                let kind = message.kind();
+                self.network_messages(message_sender, message);
+                if let Some(version) = trailer.version {
+                    let before = self.participants.protocol_version(&message_sender);
+                    self.participants
+                        .set_protocol_version(&message_sender, version);
+                    if before < SCHEMAS_VERSION && version >= SCHEMAS_VERSION {
+                        self.announce_schemas_to(message_sender);
+                    }
+                }
+                if let Some(node_id) = trailer.node_id {
+                    self.node_id_presented(message_sender, node_id, kind);
+                }
+                if let Some(topics) = trailer.interest {
+                    self.participants
+                        .set_interest(&message_sender, Interest::from_update(topics));
+                }
+                if let Some(period) = trailer.period {
+                    self.period_declared(message_sender, period);
+                }
             }
 
-            // For text messages, log the received message along with the sender's address.
-            // This is useful for debugging and monitoring the flow of messages.
-            Message::Text(text) => {
+            TransportEvent::Closed(endpoint) => {
+                self.contacts.forget(&endpoint);
+                self.hints.closed(endpoint.addr());
+                if self.observers.remove(&endpoint) {
+                    return;
+                }
+                // The peer closed its side of a crossed connection, which departs nothing.
+                if let Some(survivor) = self.crossed.closed(&endpoint) {
+                    self.hand_over(endpoint, survivor);
+                    return;
+                }
+
+                self.forget_connection(endpoint);
+                self.close_handover_orphan(&endpoint);
+                // The target hung up before presenting its node id, and is not trusted.
+                if self.bootstrap_check.closed(&endpoint) {
+                    self.dial_next_bootstrap();
+                }
+
+                let rejected = self.participants.never_answered(&endpoint);
+                // A peer still connected through another connection, such as one handed over
+                // to this participant's dial, did not leave.
                 let pub_addr = self
                     .participants
-                    .lock()
-                    .unwrap()
-                    .get_pub_addr(&message_sender)
-                    .unwrap();
+                    .get_pub_addr(&endpoint)
+                    .filter(|pub_addr| {
+                        !self.participants.connected_elsewhere(&endpoint, *pub_addr)
+                    });
+                if let Some(pub_addr) = pub_addr {
+                    self.record_membership_change(pub_addr, false, "disconnected");
+                    self.topics.forget_publisher(pub_addr);
+                    self.schema_census.forget(pub_addr);
+                }
+                let retry = self
+                    .freeze_grace_until
+                    .is_some_and(|until| Instant::now() < until)
+                    && !self.participants.is_connecting(&endpoint);
+                let interface = self.participants.interface(&endpoint);
+                ParticipantsStorage::drop(
+                    &mut self.participants,
+                    endpoint,
+                    DepartureReason::Disconnected,
+                    Instant::now(),
+                );
 
-                let formatted_msg = format!("Received message [{}] from \"{}\"", &text, &pub_addr);
-                print_event(self.time_start.clone(), &formatted_msg);
+                // A dialed peer that hung up without a word most likely refused us.
+                if rejected {
+                    self.request_introduction(endpoint.addr());
+                }
+
+                // A peer leaving right after a freeze probably gave up on us while we did not
+                // run, so it is dialed again rather than forgotten.
+                if let Some(pub_addr) = pub_addr.filter(|_| retry) {
+                    self.dial_after_freeze(pub_addr, interface);
+                }
             }
         }
     }
 
-    /// Periodically sends a random text message to all known participants.
+    /// Handles one internal event of the event loop.
     ///
-    /// This method spawns a new thread that wakes up at regular intervals specified by `self.period`.
-    /// Each time it wakes up, it constructs a random message and sends it to all participants
-    /// currently known to this instance. The message includes a randomized number to demonstrate
-    /// variability and potential for custom message content.
-    ///
-    /// # Notes
+    /// # Parameters
     ///
-    /// - The method clones several `Arc`-wrapped resources to move them safely into the thread.
-    /// - Uses a `Mutex` to safely access shared resources across threads.
-    /// - Demonstrates the use of `thread::sleep` for periodic execution within a spawned thread.
-    fn sending_random_message(&self) {
-        // Convert the period into a `Duration` for the sleep call.
-        let tick_duration = Duration::from_secs(self.period as u64);
-
-        // Clone `Arc`-wrapped resources to move into the thread. This increases the reference count
-        // safely without violating Rust's ownership rules.
-        let participants_clone = Arc::clone(&self.participants);
-        let handler_clone = Arc::clone(&self.node_handler);
-        let clone_start_time = self.time_start.clone();
-
-        // Spawn a new thread to handle the periodic sending of messages.
-        thread::spawn(move || loop {
-            // Sleep for the specified period.
-            thread::sleep(tick_duration);
-
-            // Lock the mutex to access participants. This ensures safe access across threads.
-            let participants = participants_clone.lock().unwrap();
-
-            // Retrieve the list of receivers (participants) to send the message to.
-            let receivers = participants.receivers();
-
-            // If there are no participants to send to, continue the loop.
-            if receivers.is_empty() {
-                continue;
+    /// - `event`: The `InternalEvent` sent by another thread of the application.
+    fn internal_event(&mut self, event: InternalEvent) {
+        self.log_descriptor_pressure();
+        match event {
+            InternalEvent::Transport(event) => {
+                let event = event.as_event();
+                self.transport.observe(&event);
+                self.network_event(event);
             }
-
-            // Lock the mutex to access the network handler for sending messages.
-            let mut network = handler_clone.lock().unwrap();
-
-            // Generate a random message text.
-            let msg_text = format!("random message {}", rand::thread_rng().gen_range(0..1000));
-            let msg = Message::Text(msg_text.clone());
-
-            // Log the message being sent for debugging or monitoring purposes.
-            let formatted_msg = format!(
-                "Sending message [{}] to {}",
-                &msg_text,
-                format_list_of_addrs(
-                    &receivers
-                        .iter()
-                        .map(|ParticipantAddress { public, .. }| public)
-                        .collect::<Vec<&SocketAddr>>(),
-                )
-            );
-            print_event(clone_start_time.clone(), &formatted_msg);
-
-            // Iterate through the list of receivers and send the message to each.
-            for ParticipantAddress { endpoint, .. } in &receivers {
-                send_message(&mut network, *endpoint, &msg);
+            InternalEvent::Command { command, reply } => {
+                let output = self.execute_command(command);
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.send(output);
+                    }
+                    None => {
+                        for line in output {
+                            print_line(&line);
+                        }
+                    }
+                }
             }
-        });
+            InternalEvent::Reload => {
+                self.reload();
+            }
+            InternalEvent::BroadcastTick => self.broadcast_tick(),
+            InternalEvent::MaintenanceTick => self.maintenance_tick(),
+            InternalEvent::FlushOutbox => self.flush_outbox(),
+            InternalEvent::ScheduleTick => self.schedule_tick(),
+            InternalEvent::Schedule {
+                due,
+                message,
+                missed,
+                reply,
+            } => {
+                let _ = reply.send(self.schedule(due, message, missed));
+            }
+            InternalEvent::ExpireReorder => {
+                self.reorder_timer_armed = false;
+                self.topics.expire(Instant::now());
+                self.arm_reorder_timer();
+            }
+            InternalEvent::WarmupAnnounce => self.dial_next_bootstrap(),
+            InternalEvent::AnnounceInterest => self.announce_interest(),
+            InternalEvent::JoinTimeout => self.finish_join(false),
+            InternalEvent::ProbeTick => {
+                self.probe_timer_armed = false;
+                self.probe_tick();
+            }
+            InternalEvent::SaveState => {
+                self.save_state();
+                self.arm_state_save();
+            }
+            InternalEvent::Request {
+                peer,
+                topic,
+                payload,
+                timeout,
+                reply,
+            } => self.send_request(peer, topic, payload, timeout, reply),
+            InternalEvent::RequestHandled {
+                endpoint,
+                id,
+                result,
+            } => self.send(
+                endpoint,
+                Message::Response {
+                    id,
+                    payload: result,
+                },
+            ),
+            InternalEvent::Report { reply } => {
+                let _ = reply.send(self.report());
+            }
+            InternalEvent::Shutdown => {
+                if let Some((_, session)) = &mut self.probe {
+                    session.abort("the participant stopped");
+                    self.finish_probe();
+                }
+                // A `ParticipantHandle` keeps the network alive past the loop, so the
+                // connections are closed here for the peers to see this participant leave.
+                let endpoints: Vec<Connection> = self
+                    .participants
+                    .endpoints()
+                    .copied()
+                    .chain(self.observers.endpoints())
+                    .collect();
+                for endpoint in endpoints {
+                    self.transport.close(endpoint);
+                }
+                self.node_handler.stop()
+            }
+        }
     }
 
-    /// Handles the event of a successful connection to another network participant.
+    /// Sends `msg` to the participant at `endpoint`, queued in the outbox when it may wait and
+    /// the participant unpacks batches.
     ///
-    /// Upon establishing a connection, this method performs two primary actions:
-    /// 1. Registers the newly connected participant in the local storage of known participants.
-    /// 2. Sends initial messages to the new participant, including the public address of this participant
-    ///    and a request to push the list of known participants.
-    ///
-    /// This setup ensures that every new participant is immediately aware of the network's topology
-    /// and can start communicating with other nodes without further manual intervention.
+    /// An immediate message first flushes the queue of its participant, which then receives the
+    /// messages in the order they were sent. A queued message counts as sent right away.
     ///
     /// # Parameters
     ///
-    /// - `endpoint`: The `Endpoint` representing the network connection to the new participant.
-    ///   This value is used both to register the participant and to target the initial messages.
-    fn connected(&self, endpoint: Endpoint) {
-        // Lock the mutex to safely access the participants storage. This is necessary
-        // because the network operation could be accessed from multiple threads.
-        let mut participants = self.participants.lock().unwrap();
-
-        // Add the endpoint of the newly connected participant to the known participants list.
-        // This is critical for maintaining an up-to-date view of the network topology.
-        participants.add_known_participant(endpoint);
-
-        // Lock the mutex to safely access the node handler. This handler is responsible for
-        // network communication and thus needs to be accessed in a thread-safe manner.
-        let mut network = self.node_handler.lock().unwrap();
-
-        // Send a message back to the newly connected participant containing this participant's
-        // public address. This helps the new participant learn about the existence and address
-        // of this node.
-        send_message(
-            &mut network,
-            endpoint,
-            &Message::PublicAddress(self.public_addr),
-        );
+    /// - `endpoint`: The `Connection` of the receiving participant.
+    /// - `msg`: The message to send.
+    fn send(&mut self, endpoint: Connection, msg: Message) {
+        if Priority::of(&msg) == Priority::Immediate || !self.batches_to(&endpoint) {
+            self.flush_to(endpoint);
+            self.transport.send(
+                endpoint,
+                &msg.encode_from(
+                    self.identity.id,
+                    self.interest.current(),
+                    self.effective_period,
+                    self.network.as_deref(),
+                ),
+            );
+            self.participants.record_sent_kind(&endpoint, msg.kind());
+            return;
+        }
 
-        // Send another message to the newly connected participant requesting it to push
-        // its list of known participants. This step is crucial for syncing the view of the
-        // network topology with the new participant, enabling it to communicate with other nodes.
-        send_message(&mut network, endpoint, &Message::PushParticipantsList);
+        self.participants.record_sent_kind(&endpoint, msg.kind());
+        if self.outbox.push(endpoint, msg, Instant::now()) {
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::FlushOutbox, self.outbox.window());
+        }
     }
 
-    /// Attempts to connect to a list of participant addresses received from another participant.
-    ///
-    /// This method iteratively checks each received address against the current list of known
-    /// participants. If the address is not known and is not the address of this participant or
-    /// the message sender, it attempts to establish a new connection. Successful new connections
-    /// result in the address being added to the list of known participants.
+    /// Sends `msg` to several participants: serialized once for those it can not be batched
+    /// for, and queued for the others.
     ///
     /// # Parameters
     ///
-    /// - `message_sender`: The `Endpoint` of the participant that sent this list of addresses.
-    ///   This is used to avoid trying to reconnect to the sender or to self.
-    /// - `addrs`: A `Vec<SocketAddr>` containing the addresses of potential new participants to connect to.
-    ///
-    /// # Behavior
+    /// - `endpoints`: The endpoints of the receiving participants.
+    /// - `msg`: The message to send.
+    fn send_to_all(&mut self, endpoints: &[Connection], msg: Message) {
+        let batchable = Priority::of(&msg) == Priority::Batched;
+        let (batched, direct): (Vec<Connection>, Vec<Connection>) = endpoints
+            .iter()
+            .partition(|endpoint| batchable && self.batches_to(endpoint));
+
+        broadcast_message(&mut self.transport, &direct, &msg);
+        for endpoint in &direct {
+            self.participants.record_sent_kind(endpoint, msg.kind());
+        }
+        for endpoint in batched {
+            self.send(endpoint, msg.clone());
+        }
+    }
+
+    /// Determines whether the messages to `endpoint` may wait in the outbox.
+    fn batches_to(&self, endpoint: &Connection) -> bool {
+        self.outbox.is_enabled() && self.participants.protocol_version(endpoint) >= BATCH_VERSION
+    }
+
+    /// Sends the messages queued for `endpoint` at once.
+    fn flush_to(&mut self, endpoint: Connection) {
+        for frame in self.outbox.take(&endpoint) {
+            self.transport.send(endpoint, &frame);
+        }
+    }
+
+    /// Sends the queues of the outbox whose batch window elapsed.
+    fn flush_outbox(&mut self) {
+        for (endpoint, frames) in self.outbox.take_due(Instant::now()) {
+            for frame in frames {
+                self.transport.send(endpoint, &frame);
+            }
+        }
+    }
+
+    /// Sends `InternalEvent::Shutdown` to the event loop as soon as a shutdown signal (Ctrl-C or
+    /// `SIGTERM`) is received, and `InternalEvent::Reload` on every `SIGHUP`.
     ///
-    /// For each address in `addrs` that is not already a known participant, this function tries
-    /// to establish a new connection. If at least one new connection is successfully established,
-    /// a message is logged indicating the successful connection to new participants.
-    ///
-    /// This approach allows the network to self-organize and expand as new participants join
-    /// and share their lists of known connections.
-    ///
-    /// # Errors
-    ///
-    /// Connection attempts that fail will not stop the method from attempting to connect to the
-    /// next address in the list. Each failure is logged with a message indicating the address
-    /// of the failed connection attempt.
-    fn pull_participants_list(&self, message_sender: Endpoint, addrs: Vec<SocketAddr>) {
-        // Lock the node handler and participants storage to ensure thread-safe access.
-        let network = self.node_handler.lock().unwrap();
-        let mut participants = self.participants.lock().unwrap();
-
-        // Track whether any new connections have been made to log this event later.
-        let mut new_connections = false;
-
-        // Iterate through each received participant address.
-        for &participant_address in addrs.iter() {
-            // Check if the address is not the current participant's, not the sender's,
-            // and not already known.
-            if participant_address != self.public_addr
-                && participant_address != message_sender.addr()
-                && !participants.is_known_participant(participant_address)
-            {
-                // Attempt to connect to the new participant address.
-                match network
-                    .network()
-                    .connect(Transport::FramedTcp, participant_address)
-                {
-                    Ok((endpoint, _)) => {
-                        // If successful, add the endpoint to the known participants.
-                        participants.add_known_participant(endpoint);
-                        new_connections = true;
+    /// A lightweight thread, `gossip-signal`, polls the flags raised by the signal handlers, so
+    /// the handlers themselves stay async-signal-safe.
+    fn watching_signals(&self) -> Result<(), SpawnError> {
+        let handler_clone = self.node_handler.clone();
+
+        self.threads
+            .spawn("gossip-signal", Join::Wait, move |stop| {
+                while !stop.is_raised() {
+                    thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                    if signal::shutdown_requested() {
+                        handler_clone.signals().send(InternalEvent::Shutdown);
+                        break;
+                    }
+                    if signal::reload_requested() {
+                        handler_clone.signals().send(InternalEvent::Reload);
                     }
-                    Err(_) => println!("Failed to connect to {}", participant_address),
                 }
-            }
-        }
+            })
+    }
 
-        // If any new connections were made, log an event with the list of newly connected addresses.
-        if new_connections {
+    /// Stops the internal threads once the event loop is over, warning about those that do not
+    /// stop within `THREAD_JOIN_TIMEOUT`.
+    fn stop_threads(&mut self) {
+        self.alerter.close();
+        self.request_handlers.close();
+        self.topics.close();
+        for name in self.threads.shutdown(THREAD_JOIN_TIMEOUT) {
             let formatted_msg = format!(
-                "Connected to new participants: {}",
-                format_list_of_addrs(
-                    &addrs
-                        .iter()
-                        .filter(|&&addr| addr != self.public_addr)
-                        .collect::<Vec<_>>()
-                )
+                "WARNING: Thread \"{}\" did not stop within {}, leaving it behind",
+                name,
+                format_elapsed(THREAD_JOIN_TIMEOUT)
             );
             print_event(self.time_start.clone(), &formatted_msg);
         }
     }
 }
+
+/// Sends a console request to the participant at `peer` and waits for its response.
+///
+/// # Returns
+///
+/// The line telling the response, or why there is none.
+fn request_outcome(client: &RequestClient, peer: SocketAddr, topic: &str, text: String) -> String {
+    match client.request(peer, topic, text.into_bytes(), CONSOLE_REQUEST_TIMEOUT) {
+        Ok(payload) => format!(
+            "Response [{}] on \"{}\" from \"{}\"",
+            sanitize_for_log(&String::from_utf8_lossy(&payload)),
+            topic,
+            peer
+        ),
+        // The error may quote the reason the peer answered with.
+        Err(err) => format!(
+            "Request on \"{}\" to \"{}\" failed: {}",
+            topic,
+            peer,
+            sanitize_for_log(&err.to_string())
+        ),
+    }
+}
+
+/// Takes the messages waiting in `inbox`, first waiting up to `wait` for one when given.
+///
+/// # Returns
+///
+/// One line per message taken, and the line summing up the inbox.
+fn take_inbox(inbox: &Inbox, wait: Option<Duration>) -> (Vec<String>, String) {
+    let first = match wait {
+        Some(timeout) => inbox.recv_timeout(timeout),
+        None => inbox.try_recv(),
+    };
+    let mut messages = Vec::new();
+    for message in first.into_iter().chain(iter::from_fn(|| inbox.try_recv())) {
+        let text = String::from_utf8_lossy(&message.payload);
+        let text = sanitize_for_log(&text);
+        let age = message.received_at.elapsed().unwrap_or_default();
+        messages.push(match &message.topic {
+            Some(topic) => format!(
+                "Inbox [{}] on \"{}\" from \"{}\", received {:.1?} ago",
+                text, topic, message.sender, age
+            ),
+            None => format!(
+                "Inbox [{}] from \"{}\", received {:.1?} ago",
+                text, message.sender, age
+            ),
+        });
+    }
+    let summary = format!(
+        "Inbox: {} taken, {}/{} waiting, {} dropped",
+        messages.len(),
+        inbox.len(),
+        inbox.capacity(),
+        inbox.dropped()
+    );
+    (messages, summary)
+}
@@ -0,0 +1,681 @@
+//! Running the commands of the console and of the admin socket.
+
+use crate::console::{self, Command};
+use crate::printer::{format_elapsed, print_event, sanitize_for_log};
+
+use crate::participant::csv;
+use crate::participant::dials::{DIALS_PER_ADDR, RECENT_DIALS};
+use crate::participant::event::InternalEvent;
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::message::MessageKind;
+use crate::participant::probe;
+use crate::participant::provenance::MAX_CORROBORATORS;
+use crate::participant::reorder::Delivery;
+use crate::participant::schedule::{Due, Missed};
+use crate::participant::schema::VersionRange;
+use crate::participant::session::{DepartureReason, SessionStats, FLAP_WINDOW};
+use crate::participant::snapshot::{unix_seconds, Exporter, Snapshot, SnapshotPeer};
+use crate::participant::state::RememberedPeer;
+use crate::participant::storage::ParticipantsStorage;
+use crate::participant::topic::SubscriptionHandle;
+use crate::participant::transport::Transport;
+use crate::participant::utils::CanonicalAddr;
+
+use crate::participant::transport::Connection;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Disconnects the participants advertising `peer`, and bans the address for `ban` when
+    /// given.
+    ///
+    /// An address no participant advertises can still be banned, so it is refused once it
+    /// shows up.
+    pub(super) fn evict(&mut self, peer: SocketAddr, ban: Option<Duration>, out: &mut Vec<String>) {
+        let now = Instant::now();
+        // A ban ending past what the clock can tell refuses the whole command, eviction too.
+        let ban = match ban.map(|duration| (duration, now.checked_add(duration))) {
+            Some((duration, None)) => {
+                out.push(format!(
+                    "Can not ban \"{}\" for {}: that is too long",
+                    peer,
+                    format_elapsed(duration)
+                ));
+                return;
+            }
+            Some((duration, Some(until))) => Some((duration, until)),
+            None => None,
+        };
+        let target = CanonicalAddr::from(peer);
+        let evicted: Vec<Connection> = self
+            .participants
+            .endpoints()
+            .filter(|endpoint| {
+                self.participants
+                    .get_pub_addr(endpoint)
+                    .is_some_and(|public| CanonicalAddr::from(public) == target)
+            })
+            .copied()
+            .collect();
+        for endpoint in &evicted {
+            self.push_list_limiter.forget(endpoint);
+            self.echoes.forget(endpoint);
+            self.list_exchanges.forget(endpoint);
+            ParticipantsStorage::drop(
+                &mut self.participants,
+                *endpoint,
+                DepartureReason::Evicted,
+                now,
+            );
+            self.transport.close(*endpoint);
+        }
+        if !evicted.is_empty() {
+            self.record_membership_change(peer, false, "evicted");
+            let formatted_msg = format!("Evicted \"{}\" from the console", peer);
+            print_event(self.time_start.clone(), &formatted_msg);
+            out.push(format!(
+                "Disconnected {} connections of \"{}\"",
+                evicted.len(),
+                peer
+            ));
+        } else if ban.is_none() {
+            out.push(format!("Unknown participant \"{}\"", peer));
+        }
+        if let Some((duration, until)) = ban {
+            self.bans.ban(peer, until);
+            out.push(format!(
+                "Banned \"{}\" for {}",
+                peer,
+                format_elapsed(duration)
+            ));
+        }
+    }
+
+    /// Describes the session metrics of a participant and its remembered sessions, oldest
+    /// first.
+    ///
+    /// # Parameters
+    ///
+    /// - `addr`: The public address of the participant.
+    /// - `stats`: The metrics derived from its session history.
+    /// - `out`: The output lines of the command, which the description is appended to.
+    pub(super) fn describe_sessions(
+        &self,
+        addr: SocketAddr,
+        stats: SessionStats,
+        out: &mut Vec<String>,
+    ) {
+        match stats.current {
+            Some(current) => out.push(format!("\tcurrent session: {}", format_elapsed(current))),
+            None => out.push("\tcurrent session: none".to_owned()),
+        }
+        out.push(format!("\tsessions seen: {}", stats.total));
+        out.push(format!(
+            "\tflaps in the last {}: {}",
+            format_elapsed(FLAP_WINDOW),
+            stats.flaps
+        ));
+        out.push(format!(
+            "\tlongest session: {}",
+            format_elapsed(stats.longest)
+        ));
+
+        let Some(history) = self.participants.session_history(addr) else {
+            return;
+        };
+        for session in history.sessions() {
+            let started = session
+                .connected_at
+                .saturating_duration_since(*self.time_start);
+            match (session.disconnected_at, session.reason) {
+                (Some(ended), Some(reason)) => out.push(format!(
+                    "\t\tsession from {} to {}, {}",
+                    format_elapsed(started),
+                    format_elapsed(ended.saturating_duration_since(*self.time_start)),
+                    reason
+                )),
+                _ => out.push(format!(
+                    "\t\tsession from {}, ongoing",
+                    format_elapsed(started)
+                )),
+            }
+        }
+    }
+
+    /// Executes a console command.
+    ///
+    /// # Parameters
+    ///
+    /// - `command`: The parsed console `Command`.
+    ///
+    /// # Returns
+    ///
+    /// The output of the command, one line per entry. What the command logs later, such as
+    /// the outcome of a probe, is printed as it happens instead.
+    pub(super) fn execute_command(&mut self, command: Command) -> Vec<String> {
+        let mut out = Vec::new();
+        match command {
+            Command::Peers => {
+                let summaries = self.participants.summaries(Instant::now());
+                if summaries.is_empty() {
+                    out.push("No known participants".to_owned());
+                }
+                for summary in summaries {
+                    out.push(summary.to_string());
+                }
+            }
+            Command::Peer(addr) => self.describe_peer(addr, &mut out),
+            Command::Provenance(addr) => self.describe_provenance(addr, &mut out),
+            Command::Stats => self.describe_stats(&mut out),
+            Command::Report => out.push(self.report().to_json().to_string()),
+            // Requests are sent from the console thread, which waits for their response, and
+            // the console polls its inbox there as well.
+            Command::Request { .. } | Command::Inbox(_) => {}
+            Command::Probe {
+                peer,
+                count,
+                interval,
+            } => match self.start_probe(peer, count, interval) {
+                Ok(run) => out.push(probe::started_line(run, peer, count, interval)),
+                Err(reason) => out.push(reason),
+            },
+            Command::ProbeStatus => out.push(self.probe_status()),
+            Command::Reannounce => self.reannounce(),
+            Command::Audit => out.push(self.audit().to_string()),
+            Command::PeersCsv(path) => {
+                let summaries = self.peer_summaries(Instant::now());
+                match csv::save(&path, &csv::peers_table(&summaries)) {
+                    Ok(()) => out.push(format!(
+                        "Exported {} participants as CSV to \"{}\"",
+                        summaries.len(),
+                        path.display()
+                    )),
+                    Err(err) => {
+                        out.push(format!("Can not export to \"{}\": {}", path.display(), err))
+                    }
+                }
+            }
+            Command::Export(path) => {
+                let snapshot = self.membership_snapshot();
+                let signed = match &snapshot.signature {
+                    Some(signature) => format!("signed by key {}", signature.key),
+                    None => "unsigned: this participant has no identity file".to_owned(),
+                };
+                match snapshot.save(&path) {
+                    Ok(()) => out.push(format!(
+                        "Exported {} participants to \"{}\", {}",
+                        snapshot.peers.len(),
+                        path.display(),
+                        signed
+                    )),
+                    Err(err) => {
+                        out.push(format!("Can not export to \"{}\": {}", path.display(), err))
+                    }
+                }
+            }
+            Command::Publish { topic, text } => {
+                if let Err(err) = self.publish(&topic, text.into_bytes()) {
+                    out.push(format!("Can not publish on \"{}\": {}", topic, err));
+                }
+            }
+            Command::PublishSchema {
+                topic,
+                schema,
+                text,
+            } => {
+                if let Err(err) = self.publish_with_schema(&topic, &schema, text.into_bytes()) {
+                    out.push(format!("Can not publish on \"{}\": {}", topic, err));
+                }
+            }
+            Command::Estimate { topic, text } => {
+                let message = match topic {
+                    Some(topic) => OutgoingMessage::topic(topic, text.into_bytes()),
+                    None => OutgoingMessage::text(text),
+                };
+                match self.estimate_frame(&message) {
+                    Ok(estimate) if estimate.fits_batch => out.push(format!(
+                        "Frame of {} bytes ({} of message), {} bytes left in a batch",
+                        estimate.frame, estimate.message, estimate.headroom
+                    )),
+                    Ok(estimate) => out.push(format!(
+                        "Frame of {} bytes ({} of message), too large for a batch so always sent alone",
+                        estimate.frame, estimate.message
+                    )),
+                    Err(err) => out.push(format!("Can not estimate: {}", err)),
+                }
+            }
+            Command::Schedule { delay, topic, text } => {
+                let message = match topic {
+                    Some(topic) => OutgoingMessage::topic(topic, text.into_bytes()),
+                    None => OutgoingMessage::text(text),
+                };
+                match self.schedule(Due::After(delay), message, Missed::Send) {
+                    Ok(handle) => out.push(format!(
+                        "Scheduled publication {} in {:.1?}",
+                        handle.id(),
+                        delay
+                    )),
+                    Err(err) => out.push(format!("Can not schedule: {}", err)),
+                }
+            }
+            Command::Scheduled => self.describe_scheduled(&mut out),
+            Command::Unschedule(id) => {
+                if self.schedule.cancel(id) {
+                    out.push(format!("Cancelled scheduled publication {}", id));
+                } else {
+                    out.push(format!("No pending scheduled publication {}", id));
+                }
+            }
+            Command::Limits => {
+                let limits = self.limits();
+                out.push("Frame size: unlimited".to_owned());
+                out.push(format!("Batch budget: {} bytes", limits.batch_budget));
+                out.push(format!("Topic names: {} bytes", limits.max_topic_len));
+            }
+            Command::Subscribe(topic) => self.log_topic(topic, &mut out),
+            Command::SubscribeOrdered(topic) => self.log_topic_ordered(topic, &mut out),
+            Command::SubscribeSchema { topic, name, range } => {
+                self.log_topic_schema(topic, name, range, &mut out)
+            }
+            Command::SubscribeUnsupported(topic) => self.log_topic_unsupported(topic, &mut out),
+            Command::SchemaSupport { topic, name } => {
+                let support = self.schema_support(&topic, &name);
+                if support.is_empty() {
+                    out.push(format!("No peer handles {} on \"{}\"", name, topic));
+                }
+                for (peer, range) in support {
+                    out.push(format!("\"{}\" handles {} {}", peer, name, range));
+                }
+            }
+            Command::Unsubscribe(id) => {
+                if self.topics.unsubscribe(SubscriptionHandle::from_id(id)) {
+                    self.interest_changed();
+                    out.push(format!("Removed subscription {}", id));
+                } else {
+                    out.push(format!("Unknown subscription {}", id));
+                }
+            }
+            Command::Subscriptions => self.describe_subscriptions(&mut out),
+            Command::Mute { peer, duration } => match Instant::now().checked_add(duration) {
+                None => out.push(format!(
+                    "Can not mute \"{}\" for {}: that is too long",
+                    peer,
+                    format_elapsed(duration)
+                )),
+                Some(until) if self.participants.mute(peer, until) => out.push(format!(
+                    "Muted \"{}\" for {}",
+                    peer,
+                    format_elapsed(duration)
+                )),
+                Some(_) => out.push(format!("Unknown participant \"{}\"", peer)),
+            },
+            Command::Unmute(peer) => {
+                if self.participants.unmute(peer) {
+                    out.push(format!("Unmuted \"{}\"", peer));
+                } else {
+                    out.push(format!("Unknown participant \"{}\"", peer));
+                }
+            }
+            Command::Evict { peer, ban } => self.evict(peer, ban, &mut out),
+            Command::Unban(peer) => {
+                if self.bans.unban(peer) {
+                    out.push(format!("Lifted the ban of \"{}\"", peer));
+                } else {
+                    out.push(format!("\"{}\" is not banned", peer));
+                }
+            }
+            Command::Bans => {
+                let bans = self.bans.list(Instant::now());
+                for (addr, left) in &bans {
+                    out.push(format!("{}: {} left", addr, format_elapsed(*left)));
+                }
+                out.push(format!("{} addresses banned", bans.len()));
+            }
+            Command::Dials(addr) => self.describe_dials(addr, &mut out),
+            Command::Threads => {
+                for (name, count) in self.threads.live() {
+                    out.push(format!("{}: {}", name, count));
+                }
+                let threads = self.threads.stats();
+                out.push(format!(
+                    "{} internal threads running, at most {}",
+                    threads.live, threads.cap
+                ));
+            }
+            Command::Channels => {
+                for channel in self.channels.stats() {
+                    out.push(format!(
+                        "{}: {} waiting, {} at most, {} sent, {} dropped{}",
+                        channel.name,
+                        channel.depth,
+                        channel.high_water,
+                        channel.sent,
+                        channel.dropped,
+                        if channel.closed { ", closed" } else { "" }
+                    ));
+                }
+            }
+            Command::Memory => out.extend(self.retention.lines()),
+            Command::Reload => out.extend(self.reload()),
+            Command::Help => out.push(console::get_help_message().to_string()),
+            Command::Quit => self.node_handler.signals().send(InternalEvent::Shutdown),
+        }
+        out
+    }
+
+    /// Takes the membership snapshot of the `export` command: the participants this one would
+    /// share, itself first, with the node id and last contact of each, signed with the key of
+    /// the identity file if there is one.
+    fn membership_snapshot(&self) -> Snapshot {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let own = self.interfaces.primary();
+        let remembered: HashMap<CanonicalAddr, RememberedPeer> = self
+            .participants
+            .remembered_peers(now, wall)
+            .into_iter()
+            .map(|peer| (peer.addr.into(), peer))
+            .collect();
+        let peers = self
+            .participants
+            .shared_peers(own, now, usize::MAX, None)
+            .into_iter()
+            .map(|peer| {
+                let known = remembered.get(&peer.addr.into());
+                let (node_id, verified_at) = match known {
+                    _ if CanonicalAddr::from(own) == peer.addr.into() => {
+                        (Some(self.identity.id), Some(unix_seconds(wall)))
+                    }
+                    Some(known) => (known.node_id, known.verified_at),
+                    None => (None, None),
+                };
+                SnapshotPeer {
+                    addr: peer.addr,
+                    node_id,
+                    age: peer.age,
+                    last_verified_secs: verified_at.map(|at| unix_seconds(wall).saturating_sub(at)),
+                }
+            })
+            .collect();
+        let exporter = Exporter {
+            addr: own,
+            node_id: Some(self.identity.id),
+            label: self.node_label.clone(),
+        };
+        let mut snapshot = Snapshot::new(exporter, peers, wall);
+        if let Some(key) = &self.identity.key {
+            snapshot.sign(key);
+        }
+        snapshot
+    }
+
+    /// Describes the participant advertising `addr`: its messages of each kind, its sends, mute,
+    /// clock offset, provenance and sessions.
+    fn describe_peer(&self, addr: SocketAddr, out: &mut Vec<String>) {
+        match self.participants.summary(addr, Instant::now()) {
+            Some(summary) => {
+                out.push(format!("Participant \"{}\"", summary.public));
+                for kind in MessageKind::ALL {
+                    out.push(format!(
+                        "\t{}: sent {}, received {}",
+                        kind.name(),
+                        summary.sent[kind.index()],
+                        summary.received[kind.index()]
+                    ));
+                }
+                out.push(format!(
+                    "\tunanswered sends: {}",
+                    summary.sends_since_receive
+                ));
+                out.push(format!("\tdeferred sends: {}", summary.deferred_sends));
+                match summary.muted_for {
+                    Some(remaining) => out.push(format!(
+                        "\tmuted for {}, {} messages dropped",
+                        format_elapsed(remaining),
+                        summary.muted_drops
+                    )),
+                    None => out.push(format!("\tmuted drops: {}", summary.muted_drops)),
+                }
+                match summary.clock_offset {
+                    Some(offset) => out.push(format!(
+                        "\tclock offset: {}, from {} samples",
+                        offset, offset.samples
+                    )),
+                    None => out.push("\tclock offset: not estimated".to_owned()),
+                }
+                match self.provenances.get(summary.public) {
+                    Some(provenance) => out.push(format!(
+                        "\tlearned from {}, corroborators: {}",
+                        provenance.source,
+                        provenance.corroborators.len()
+                    )),
+                    None => out.push("\tprovenance: not kept".to_owned()),
+                }
+                self.describe_sessions(addr, summary.sessions, out);
+            }
+            None => out.push(format!("Unknown participant \"{}\"", addr)),
+        }
+    }
+
+    /// Describes how and when this participant learned `addr`, and who corroborated it.
+    fn describe_provenance(&self, addr: SocketAddr, out: &mut Vec<String>) {
+        match self.provenances.get(addr) {
+            Some(provenance) => {
+                out.push(format!("Provenance of \"{}\"", addr));
+                out.push(format!("\tlearned from {}", provenance.source));
+                let ago = Duration::from_secs(
+                    unix_seconds(SystemTime::now()).saturating_sub(provenance.learned_at),
+                );
+                match provenance.uptime {
+                    Some(uptime) => out.push(format!(
+                        "\tat {} into this run, {} ago",
+                        format_elapsed(uptime),
+                        format_elapsed(ago)
+                    )),
+                    None => out.push(format!("\tin an earlier run, {} ago", format_elapsed(ago))),
+                }
+                let corroborators: Vec<String> = provenance
+                    .corroborators
+                    .iter()
+                    .map(|peer| format!("\"{}\"", peer))
+                    .collect();
+                match corroborators.len() {
+                    0 => out.push("\tcorroborated by no other peer".to_owned()),
+                    count if count >= MAX_CORROBORATORS => out.push(format!(
+                        "\tcorroborated by {}, and maybe more",
+                        corroborators.join(", ")
+                    )),
+                    _ => out.push(format!("\tcorroborated by {}", corroborators.join(", "))),
+                }
+            }
+            None => out.push(format!("No provenance for \"{}\"", addr)),
+        }
+    }
+
+    /// Lists the pending scheduled publications, with the time left until each.
+    fn describe_scheduled(&self, out: &mut Vec<String>) {
+        let scheduled = self.scheduled();
+        if scheduled.is_empty() {
+            out.push("No scheduled publications".to_owned());
+        }
+        let now = SystemTime::now();
+        for entry in scheduled {
+            let due = match entry.due.duration_since(now) {
+                Ok(left) => format!("in {:.1?}", left),
+                Err(_) => "overdue".to_owned(),
+            };
+            let what = match &entry.topic {
+                Some(topic) => format!("on \"{}\"", topic),
+                None => entry.kind.name().to_owned(),
+            };
+            out.push(format!(
+                "{}: {} {}, {} bytes",
+                entry.id, due, what, entry.bytes
+            ));
+        }
+    }
+
+    /// Subscribes to `topic`, logging what arrives on it.
+    fn log_topic(&mut self, topic: String, out: &mut Vec<String>) {
+        let time_start = self.time_start.clone();
+        let subscribed_topic = topic.clone();
+        let subscription = self.subscribe(&topic, move |from, payload| {
+            let formatted_msg = format!(
+                "Received [{}] on \"{}\" from \"{}\"",
+                sanitize_for_log(&String::from_utf8_lossy(&payload)),
+                subscribed_topic,
+                from
+            );
+            print_event(time_start.clone(), &formatted_msg);
+        });
+        match subscription {
+            Ok(handle) => out.push(format!("Subscribed to \"{}\" as {}", topic, handle.id())),
+            Err(err) => out.push(format!("Can not subscribe to \"{}\": {}", topic, err)),
+        }
+    }
+
+    /// Subscribes to `topic` in publish order, logging what arrives on it and the gaps.
+    fn log_topic_ordered(&mut self, topic: String, out: &mut Vec<String>) {
+        let time_start = self.time_start.clone();
+        let subscribed_topic = topic.clone();
+        let subscription = self.subscribe_ordered(&topic, move |from, delivery| {
+            let formatted_msg = match delivery {
+                Delivery::Message { seq, payload } => format!(
+                    "Received [{}] #{} on \"{}\" from \"{}\"",
+                    sanitize_for_log(&String::from_utf8_lossy(&payload)),
+                    seq,
+                    subscribed_topic,
+                    from
+                ),
+                Delivery::Unsequenced(payload) => format!(
+                    "Received [{}] on \"{}\" from \"{}\"",
+                    sanitize_for_log(&String::from_utf8_lossy(&payload)),
+                    subscribed_topic,
+                    from
+                ),
+                Delivery::Gap { from: first, to } => format!(
+                    "Missed #{} to #{} on \"{}\" from \"{}\"",
+                    first, to, subscribed_topic, from
+                ),
+            };
+            print_event(time_start.clone(), &formatted_msg);
+        });
+        match subscription {
+            Ok(handle) => out.push(format!(
+                "Subscribed to \"{}\" in publish order as {}",
+                topic,
+                handle.id()
+            )),
+            Err(err) => out.push(format!("Can not subscribe to \"{}\": {}", topic, err)),
+        }
+    }
+
+    /// Subscribes to the `name` schema of `topic` for the versions of `range`, logging what
+    /// arrives.
+    fn log_topic_schema(
+        &mut self,
+        topic: String,
+        name: String,
+        range: VersionRange,
+        out: &mut Vec<String>,
+    ) {
+        let time_start = self.time_start.clone();
+        let subscribed_topic = topic.clone();
+        let schema = name.clone();
+        let subscription =
+            self.subscribe_schema(&topic, &name, range, move |from, version, payload| {
+                let formatted_msg = format!(
+                    "Received [{}] as {} v{} on \"{}\" from \"{}\"",
+                    sanitize_for_log(&String::from_utf8_lossy(&payload)),
+                    schema,
+                    version,
+                    subscribed_topic,
+                    from
+                );
+                print_event(time_start.clone(), &formatted_msg);
+            });
+        match subscription {
+            Ok(handle) => out.push(format!(
+                "Subscribed to \"{}\" for {} {} as {}",
+                topic,
+                name,
+                range,
+                handle.id()
+            )),
+            Err(err) => out.push(format!("Can not subscribe to \"{}\": {}", topic, err)),
+        }
+    }
+
+    /// Subscribes to what arrives on `topic` in a schema no subscription handles, logging it.
+    fn log_topic_unsupported(&mut self, topic: String, out: &mut Vec<String>) {
+        let time_start = self.time_start.clone();
+        let subscribed_topic = topic.clone();
+        let subscription = self.on_unsupported_schema(&topic, move |from, schema, payload| {
+            let formatted_msg = format!(
+                "Received [{}] of unsupported {} on \"{}\" from \"{}\"",
+                sanitize_for_log(&String::from_utf8_lossy(&payload)),
+                sanitize_for_log(&schema.to_string()),
+                subscribed_topic,
+                from
+            );
+            print_event(time_start.clone(), &formatted_msg);
+        });
+        match subscription {
+            Ok(handle) => out.push(format!(
+                "Subscribed to the unsupported schemas of \"{}\" as {}",
+                topic,
+                handle.id()
+            )),
+            Err(err) => out.push(format!("Can not subscribe to \"{}\": {}", topic, err)),
+        }
+    }
+
+    /// Lists the subscriptions, with the statistics of their callbacks.
+    fn describe_subscriptions(&self, out: &mut Vec<String>) {
+        let subscriptions = self.topics.callback_stats();
+        if subscriptions.is_empty() {
+            out.push("No subscriptions".to_owned());
+        }
+        for (handle, topic, stats) in subscriptions {
+            out.push(format!(
+                "{} on \"{}\": {} run, {} dropped, {} panicked, slowest {:?}, {} queued{}",
+                handle.id(),
+                topic,
+                stats.executed,
+                stats.dropped,
+                stats.panicked,
+                stats.slowest,
+                stats.queued,
+                if stats.running { ", running" } else { "" }
+            ));
+        }
+    }
+
+    /// Lists the dials kept, of `addr` only when given.
+    fn describe_dials(&mut self, addr: Option<SocketAddr>, out: &mut Vec<String>) {
+        let now = Instant::now();
+        let attempts = match addr {
+            Some(addr) => self.dials.of(addr, now),
+            None => self.dials.recent(),
+        };
+        for attempt in &attempts {
+            out.push(attempt.describe(now));
+        }
+        match addr {
+            Some(addr) => out.push(format!(
+                "{} dials of \"{}\" kept, at most {}",
+                attempts.len(),
+                addr,
+                DIALS_PER_ADDR
+            )),
+            None => out.push(format!(
+                "{} recent dials kept, at most {}",
+                attempts.len(),
+                RECENT_DIALS
+            )),
+        }
+    }
+}
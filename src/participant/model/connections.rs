@@ -0,0 +1,731 @@
+//! Dialing peers, from the bootstrap to the redials, and the connections that result.
+
+use crate::printer::{format_elapsed, print_event};
+
+use crate::participant::alert::AlertEvent;
+use crate::participant::balance::Balancer;
+use crate::participant::crossed::{self, Survivor};
+use crate::participant::descriptors::{self};
+use crate::participant::dials::{DialOutcome, DialTrigger};
+use crate::participant::error::ParticipantError;
+use crate::participant::event::InternalEvent;
+use crate::participant::exchange::ListPurpose;
+use crate::participant::identity::NodeId;
+use crate::participant::message::{AgeBucket, Message};
+use crate::participant::record::MembershipRecord;
+use crate::participant::resolve::Resolution;
+use crate::participant::session::DepartureReason;
+use crate::participant::storage::{ParticipantAddress, ParticipantsStorage};
+use crate::participant::tick::FREEZE_GRACE;
+use crate::participant::transport::Transport;
+use crate::participant::utils::{send_message, CanonicalAddr};
+use crate::participant::verify::{Verdict, VerificationMode};
+use crate::participant::warmup::DeferredDial;
+
+use crate::participant::transport::Connection;
+
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Moves a peer from the connection `from` to its other connection `to`, which becomes its
+    /// only stored connection, without a membership change.
+    ///
+    /// `from` is not closed here. A peer dialed back keeps its inbound connection open until it
+    /// answers through the outbound one, see `balance`; what it still carries is ignored, as
+    /// from a peer that never announced itself. A crossed connection is already closed, or
+    /// about to be, see `crossed`.
+    pub(super) fn hand_over(&mut self, from: Connection, to: Connection) {
+        self.participants.hand_over(&from, &to);
+        self.contacts.forget(&from);
+        self.forget_connection(from);
+    }
+
+    /// Breaks the tie between the crossed connections of the peer at `pub_addr`, if `endpoint`
+    /// is one of them, see `crossed`.
+    ///
+    /// # Returns
+    ///
+    /// Whether `endpoint` itself was closed.
+    pub(super) fn resolve_crossed(&mut self, endpoint: Connection, pub_addr: SocketAddr) -> bool {
+        let Some((outbound, inbound)) = self.participants.crossing(&endpoint, pub_addr) else {
+            return false;
+        };
+        // The dial back of a handover crosses the inbound connection on purpose.
+        let handing_over = self
+            .balancer
+            .as_ref()
+            .and_then(Balancer::in_flight)
+            .is_some_and(|(public, _)| CanonicalAddr::from(public) == pub_addr.into());
+        if handing_over {
+            return false;
+        }
+        let local = self
+            .interfaces
+            .advertise(self.participants.interface(&outbound));
+        let remote = self.participants.get_pub_addr(&inbound).unwrap_or(pub_addr);
+        let Some(survivor) = crossed::survivor(local, remote) else {
+            return false;
+        };
+        match survivor {
+            Survivor::Outbound => {
+                self.crossed.await_close(inbound, outbound);
+                let formatted_msg = format!(
+                    "Crossed connections with \"{}\": keeping ours, the peer closes its own",
+                    remote
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                false
+            }
+            Survivor::Inbound => {
+                let connecting = self.participants.is_connecting(&outbound);
+                if connecting {
+                    // A connecting participant has no open session, so the reason is never
+                    // recorded.
+                    ParticipantsStorage::drop(
+                        &mut self.participants,
+                        outbound,
+                        DepartureReason::Disconnected,
+                        Instant::now(),
+                    );
+                } else {
+                    self.hand_over(outbound, inbound);
+                }
+                self.transport.close(outbound);
+                self.crossed.closed_here();
+                let formatted_msg = format!(
+                    "Crossed connections with \"{}\": {} ours, the peer's survives",
+                    remote,
+                    if connecting { "aborting" } else { "closing" }
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                outbound == endpoint
+            }
+        }
+    }
+
+    /// Closes the connection left waiting by a handover whose outbound connection went away.
+    pub(super) fn close_handover_orphan(&mut self, endpoint: &Connection) {
+        if let Some(orphan) = self
+            .balancer
+            .as_mut()
+            .and_then(|balancer| balancer.closed(endpoint))
+        {
+            self.transport.close(orphan);
+        }
+    }
+
+    /// Forgets the per-connection state kept for a participant whose connection is gone.
+    pub(super) fn forget_connection(&mut self, endpoint: Connection) {
+        self.push_list_limiter.forget(&endpoint);
+        self.echoes.forget(&endpoint);
+        self.list_exchanges.forget(&endpoint);
+        self.outbox.discard(&endpoint);
+        self.pending_requests
+            .peer_gone(|pending| *pending == endpoint);
+        if let Some((_, session)) = self
+            .probe
+            .as_mut()
+            .filter(|(probed, _)| *probed == endpoint)
+        {
+            session.abort("the peer disconnected, its unanswered probes count as lost");
+            self.finish_probe();
+        }
+    }
+
+    /// Dials a learned address the warm-up or the file descriptor budget put off, unless it got
+    /// known, blocked or dialed in the meantime.
+    ///
+    /// # Returns
+    ///
+    /// `false` when no file descriptor is left for the dial, which is put off again.
+    pub(super) fn dial_deferred(&mut self, dial: DeferredDial, now: Instant) -> bool {
+        if self.participants.contains_addr(dial.addr)
+            || self.recent_dials.contains(&dial.addr.into(), now)
+        {
+            return true;
+        }
+        if self.filter.is_blocked(dial.addr) || self.bans.is_banned(dial.addr, now) {
+            self.dials
+                .settled(dial.addr, dial.trigger, DialOutcome::Filtered, now);
+            return true;
+        }
+        let dialed = self.transport.dial(dial.addr);
+        if dialed.as_ref().is_err_and(descriptors::is_exhausted) {
+            self.dials
+                .settled(dial.addr, dial.trigger, DialOutcome::Deferred, now);
+            self.dial_backlog.requeue(dial);
+            return false;
+        }
+        self.recent_dials.insert(dial.addr.into(), (), now);
+        if let Some(origin) = dial.origin {
+            self.list_origins.insert(dial.addr.into(), origin, now);
+        }
+        match dialed {
+            Ok((endpoint, _)) => {
+                let suspicion = if dial.age == AgeBucket::Stale {
+                    self.half_open_threshold / 2
+                } else {
+                    0
+                };
+                self.participants
+                    .add_connecting_participant(endpoint, now, suspicion);
+                self.participants.set_interface(&endpoint, dial.interface);
+                self.dials.started(endpoint, dial.addr, dial.trigger, now);
+                let formatted_msg = format!("Connected to new participant: \"{}\"", dial.addr);
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            Err(_) => {
+                self.dials
+                    .settled(dial.addr, dial.trigger, DialOutcome::Failed, now);
+                self.dial_refused(dial.addr)
+            }
+        }
+        true
+    }
+
+    /// Starts the dials put off for want of a file descriptor, oldest first, while descriptors
+    /// are left.
+    pub(super) fn resume_dials(&mut self, now: Instant) {
+        while self.descriptors.available() > 0 {
+            let Some(dial) = self.dial_backlog.next() else {
+                return;
+            };
+            if !self.dial_deferred(dial, now) {
+                return;
+            }
+        }
+    }
+
+    /// Closes at once a connection accepted while no file descriptor is left, logging it at
+    /// most every `REFUSAL_LOG_INTERVAL`.
+    pub(super) fn refused_connection(&mut self, endpoint: Connection) {
+        let Some(unlogged) = self.refusals.refused(Instant::now()) else {
+            return;
+        };
+        let budget = self.descriptors.stats();
+        let mut formatted_msg = format!(
+            "Refused the connection from {}: {} of {} file descriptors in use",
+            endpoint.addr(),
+            budget.in_use,
+            budget.ceiling
+        );
+        if unlogged > 0 {
+            formatted_msg.push_str(&format!(
+                ", {} more refused since the last report",
+                unlogged
+            ));
+        }
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Catches up with the peers after a time this participant did not run.
+    ///
+    /// The peers are spared the half-open detection and the connect timeout for the answers
+    /// this participant could not read, re-announced to and asked for their participants lists
+    /// at once, and the peers disconnecting within `FREEZE_GRACE` are dialed again. The
+    /// scheduled publications whose wall-clock time passed are sent.
+    pub(super) fn recover_from_freeze(&mut self) {
+        let now = Instant::now();
+        self.participants.forgive_gap(now);
+        self.freeze_grace_until = Some(now + FREEZE_GRACE);
+        if !self.participants.receivers().is_empty() {
+            self.reannounce();
+        }
+        self.schedule.catch_up(now, SystemTime::now());
+        self.schedule_tick();
+    }
+
+    /// Dials again a participant that disconnected right after this participant was frozen.
+    ///
+    /// # Parameters
+    ///
+    /// - `public`: The public address of the participant.
+    /// - `interface`: The interface the participant was connected through.
+    pub(super) fn dial_after_freeze(&mut self, public: SocketAddr, interface: usize) {
+        let now = Instant::now();
+        if self.interfaces.is_own(public) || self.participants.contains_addr(public) {
+            return;
+        }
+        if self.filter.is_blocked(public) || self.bans.is_banned(public, now) {
+            self.dials
+                .settled(public, DialTrigger::Rejoin, DialOutcome::Filtered, now);
+            return;
+        }
+        match self.transport.dial(public) {
+            Err(err) if descriptors::is_exhausted(&err) => {
+                self.dials
+                    .settled(public, DialTrigger::Rejoin, DialOutcome::Deferred, now);
+                self.dial_backlog.defer(DeferredDial {
+                    addr: public,
+                    age: AgeBucket::Fresh,
+                    interface,
+                    origin: None,
+                    trigger: DialTrigger::Rejoin,
+                });
+            }
+            Ok((endpoint, _)) => {
+                self.participants
+                    .add_connecting_participant(endpoint, now, 0);
+                self.participants.set_interface(&endpoint, interface);
+                self.dials
+                    .started(endpoint, public, DialTrigger::Rejoin, now);
+                let formatted_msg = format!(
+                    "Dialing \"{}\" again, it left while this participant was frozen",
+                    public
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            Err(_) => {
+                self.dials
+                    .settled(public, DialTrigger::Rejoin, DialOutcome::Failed, now);
+                self.dial_refused(public)
+            }
+        }
+    }
+
+    /// Closes the connection to a participant and dials its public address again.
+    ///
+    /// The participant keeps its counters under the new endpoint, where it is connecting until
+    /// the dial succeeds; it is dropped if the dial can not even be started.
+    ///
+    /// # Parameters
+    ///
+    /// - `suspect`: The public address and current endpoint of the participant.
+    pub(super) fn redial(&mut self, suspect: ParticipantAddress<Connection>) {
+        if let Some(recorder) = &self.recorder {
+            recorder.membership(MembershipRecord::Suspect {
+                addr: suspect.public,
+            });
+        }
+        self.transport.close(suspect.endpoint);
+        match self.transport.dial(suspect.public) {
+            Ok((endpoint, _)) => {
+                self.redialed.insert(endpoint.addr());
+                self.dials.started(
+                    endpoint,
+                    suspect.public,
+                    DialTrigger::Redial,
+                    Instant::now(),
+                );
+                self.participants
+                    .replace_endpoint(&suspect.endpoint, endpoint, Instant::now())
+            }
+            Err(_) => {
+                self.dials.settled(
+                    suspect.public,
+                    DialTrigger::Redial,
+                    DialOutcome::Failed,
+                    Instant::now(),
+                );
+                self.dial_refused(suspect.public);
+                self.record_membership_change(suspect.public, false, "half-open");
+                ParticipantsStorage::drop(
+                    &mut self.participants,
+                    suspect.endpoint,
+                    DepartureReason::HalfOpen,
+                    Instant::now(),
+                );
+            }
+        }
+    }
+
+    /// Logs a dial to `addr` that the transport refused to start.
+    pub(super) fn dial_refused(&self, addr: impl fmt::Display) {
+        let formatted_msg = format!("Failed to connect to \"{}\"", addr);
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Drops a participant whose dial failed or timed out and tries the alternatives.
+    ///
+    /// The neighbor that listed the participant is asked for an introduction, and the next
+    /// bootstrap candidate is dialed if the participant was one. A failed partition healing
+    /// probe is only counted.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the failed connection.
+    pub(super) fn dial_failed(&mut self, endpoint: Connection) {
+        self.crossed.closed(&endpoint);
+        // A failed dial back leaves the peer on its inbound connection.
+        let dialed_back = self
+            .balancer
+            .as_mut()
+            .is_some_and(|balancer| balancer.dial_failed(&endpoint));
+        if dialed_back {
+            ParticipantsStorage::drop(
+                &mut self.participants,
+                endpoint,
+                DepartureReason::Disconnected,
+                Instant::now(),
+            );
+            let formatted_msg = format!(
+                "Can not dial \"{}\" back, keeping its inbound connection",
+                endpoint.addr()
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            return;
+        }
+        if self.redialed.remove(&endpoint.addr()) {
+            self.record_membership_change(endpoint.addr(), false, "half-open");
+        }
+        // A connecting participant has no open session, so the reason is never recorded.
+        ParticipantsStorage::drop(
+            &mut self.participants,
+            endpoint,
+            DepartureReason::Disconnected,
+            Instant::now(),
+        );
+        if self.heal.failed(&endpoint) {
+            return;
+        }
+        self.request_introduction(endpoint.addr());
+        self.dial_next_bootstrap();
+    }
+
+    /// Handles the event of a successful connection to another network participant.
+    ///
+    /// Upon establishing a connection, this method performs two primary actions:
+    /// 1. Promotes the dialed participant from connecting to known in the local storage.
+    /// 2. Sends initial messages to the new participant, including the public address of this participant
+    ///    and a request to push the list of known participants.
+    ///
+    /// This setup ensures that every new participant is immediately aware of the network's topology
+    /// and can start communicating with other nodes without further manual intervention.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` representing the network connection to the new participant.
+    ///   This value is used both to register the participant and to target the initial messages.
+    pub(super) fn connected(&mut self, endpoint: Connection) {
+        // Promote the endpoint of the newly connected participant to the known participants,
+        // keeping what was recorded while connecting. This is critical for maintaining an
+        // up-to-date view of the network topology.
+        // A dial losing the tie-break with the peer's own dial is closed before its handshake.
+        if self.resolve_crossed(endpoint, endpoint.addr()) {
+            return;
+        }
+        // A peer still in session through another connection, such as a crossed one, rejoins
+        // nothing.
+        let in_session = self
+            .participants
+            .session_history(endpoint.addr())
+            .is_some_and(|history| history.is_open());
+        let now = Instant::now();
+        if !self.participants.promote_connected(&endpoint, now) {
+            self.participants.add_known_participant(endpoint, now);
+        }
+        self.participants.mark_verified(&endpoint, now);
+        // An address reached by dialing it needs no corroboration to be dialed again.
+        self.candidates.trust(endpoint.addr(), now);
+        // The peer is remembered by `first_contact` once it answers in this protocol.
+        self.contacts.expect(endpoint, now);
+        if self.redialed.remove(&endpoint.addr()) {
+            if let Some(recorder) = &self.recorder {
+                recorder.membership(MembershipRecord::Recovered {
+                    addr: endpoint.addr(),
+                });
+            }
+        }
+        match self
+            .balancer
+            .as_mut()
+            .and_then(|balancer| balancer.established(&endpoint))
+        {
+            Some(inbound) => self.hand_over(inbound, endpoint),
+            None if in_session => {}
+            None => self.record_membership_change(endpoint.addr(), true, "connected"),
+        }
+        let healed = self.heal.connected(&endpoint);
+        if let Some(probe) = &healed {
+            let apart = probe
+                .departed_at
+                .map_or("an unknown time".to_owned(), |departed_at| {
+                    format_elapsed(now.saturating_duration_since(departed_at))
+                });
+            let formatted_msg = format!(
+                "Partition heal: reconnected to \"{}\" after {}",
+                probe.addr, apart
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+
+        // Drop the remaining bootstrap candidates once any connection is up, unless it is to
+        // a target still to be verified.
+        if !self.bootstrap_check.is_pending(&endpoint) {
+            self.bootstrap_trusted();
+        }
+
+        // Send a message back to the newly connected participant containing this participant's
+        // public address. This helps the new participant learn about the existence and address
+        // of this node.
+        let advertise = self
+            .interfaces
+            .advertise(self.participants.interface(&endpoint));
+        self.send(endpoint, Message::PublicAddress(advertise));
+
+        // Send another message to the newly connected participant requesting it to push
+        // its list of known participants. This step is crucial for syncing the view of the
+        // network topology with the new participant, enabling it to communicate with other nodes.
+        // Its version is not known yet, so the request carries no id.
+        let purpose = if healed.is_some() {
+            ListPurpose::HealProbe
+        } else {
+            ListPurpose::InitialSync
+        };
+        self.list_exchanges.request(endpoint, purpose, false, now);
+        send_message(
+            &mut self.transport,
+            &mut self.participants,
+            endpoint,
+            &Message::PushParticipantsList,
+        );
+    }
+
+    /// Stops trying the bootstrap candidates, a connection being up and trusted.
+    pub(super) fn bootstrap_trusted(&mut self) {
+        self.bootstrap_candidates.clear();
+        self.bootstrapping = false;
+    }
+
+    /// Checks the first frame of `endpoint` against `--expect-network-id` and
+    /// `--expect-node-id`, see `verify`.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the peer.
+    /// - `network`: The network the trailer of the frame carries.
+    /// - `node_id`: The node id the trailer of the frame carries.
+    ///
+    /// # Returns
+    ///
+    /// Whether the frame is handled: `false` once a target failing its verification is closed.
+    pub(super) fn verify_bootstrap(
+        &mut self,
+        endpoint: Connection,
+        network: Option<&str>,
+        node_id: Option<NodeId>,
+    ) -> bool {
+        let failure = match self.bootstrap_check.verify(&endpoint, network, node_id) {
+            Verdict::Unchecked => return true,
+            Verdict::Passed => {
+                let formatted_msg = format!(
+                    "Bootstrap target \"{}\" presented the expected identity",
+                    endpoint.addr()
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.bootstrap_trusted();
+                return true;
+            }
+            Verdict::Failed(failure) => failure,
+        };
+        if self.bootstrap_check.mode() == VerificationMode::Warn {
+            let formatted_msg = format!(
+                "WARNING: Bootstrap verification failed: {}, trusting it anyway",
+                failure
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.bootstrap_trusted();
+            return true;
+        }
+        let formatted_msg = format!(
+            "Bootstrap verification failed: {}, closing the connection",
+            failure
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+        self.close_unverified(endpoint, DepartureReason::Unverified);
+        self.dial_next_bootstrap();
+        false
+    }
+
+    /// Dials the next bootstrap candidate.
+    ///
+    /// Candidates are the explicit `--connect` target followed by the addresses of the recent
+    /// peers cache. Each call pops candidates until a dial can be started; the outcome arrives
+    /// later as a `NetEvent::Connected` event, and a failed attempt calls this method again.
+    ///
+    /// # Behavior
+    ///
+    /// When every candidate has been tried without success and no other participant is
+    /// connected, the `bootstrap-failed` alert is raised, and a participant started with
+    /// `--require-bootstrap` stops its event loop with
+    /// `ParticipantError::BootstrapExhausted`, while any other participant keeps running
+    /// isolated until someone connects to it. A target that failed its verification stops
+    /// the event loop with `ParticipantError::BootstrapUnverified` either way.
+    pub(super) fn dial_next_bootstrap(&mut self) {
+        let mut dialed = None;
+        while let Some(addr) = self.bootstrap_candidates.pop_front() {
+            let resolved = addr
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next());
+            match resolved.map(|resolved| (resolved, self.transport.dial(resolved))) {
+                Some((resolved, Ok((endpoint, local_addr)))) => {
+                    if let Some(target) = &mut self.connect_target {
+                        if target.name() == addr {
+                            target.resolved(resolved, Instant::now());
+                        }
+                    }
+                    self.bootstrap_check.dialed(endpoint, &addr, resolved);
+                    dialed = Some((endpoint, resolved, local_addr));
+                    break;
+                }
+                Some((resolved, Err(_))) => {
+                    self.dials.settled(
+                        resolved,
+                        DialTrigger::Bootstrap,
+                        DialOutcome::Failed,
+                        Instant::now(),
+                    );
+                    self.dial_refused(&addr)
+                }
+                // An address that does not resolve has no place in the trail.
+                None => self.dial_refused(&addr),
+            }
+        }
+
+        match dialed {
+            Some((endpoint, resolved, local_addr)) => {
+                self.dials
+                    .started(endpoint, resolved, DialTrigger::Bootstrap, Instant::now());
+                self.participants
+                    .add_connecting_participant(endpoint, Instant::now(), 0);
+                self.participants
+                    .set_interface(&endpoint, self.interfaces.of_local_addr(local_addr));
+            }
+            None if self.participants.receivers().is_empty() => {
+                let mut unverified = None;
+                if self.bootstrapping {
+                    self.bootstrapping = false;
+                    unverified = self.bootstrap_check.failure();
+                    self.alerter.raise(
+                        AlertEvent::BootstrapFailed,
+                        self.interfaces.primary(),
+                        vec![("fatal", self.require_bootstrap.to_string())],
+                        Instant::now(),
+                    );
+                }
+                if let Some(failure) = unverified {
+                    self.failure = Some(ParticipantError::BootstrapUnverified(failure));
+                    self.node_handler.signals().send(InternalEvent::Shutdown);
+                } else if self.require_bootstrap {
+                    self.failure = Some(ParticipantError::BootstrapExhausted);
+                    self.node_handler.signals().send(InternalEvent::Shutdown);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Resolves the `--connect` host name again and dials what it resolves to, while no
+    /// participant is connected, see `resolve`.
+    ///
+    /// Nothing is resolved while the bootstrap candidates are still being tried or a dial of
+    /// one is in flight, nor twice within `--resolve-interval`.
+    pub(super) fn dial_connect_target(&mut self, now: Instant) {
+        if self.bootstrapping || self.dials.in_flight(DialTrigger::Bootstrap) > 0 {
+            return;
+        }
+        let Some(target) = &mut self.connect_target else {
+            return;
+        };
+        if !target.is_due(now) {
+            return;
+        }
+        let name = target.name().to_owned();
+        let addr = match target.resolve(now) {
+            Resolution::Unresolved => {
+                let formatted_msg = format!("Can not resolve \"{}\"", name);
+                print_event(self.time_start.clone(), &formatted_msg);
+                return;
+            }
+            Resolution::Same(addr) => addr,
+            Resolution::Changed { from, to } => {
+                let formatted_msg = match from {
+                    Some(from) => format!(
+                        "\"{}\" now resolves to \"{}\" instead of \"{}\"",
+                        name, to, from
+                    ),
+                    None => format!("\"{}\" now resolves to \"{}\"", name, to),
+                };
+                print_event(self.time_start.clone(), &formatted_msg);
+                to
+            }
+        };
+        if self.interfaces.is_own(addr) {
+            return;
+        }
+        if self.filter.is_blocked(addr) || self.bans.is_banned(addr, now) {
+            self.dials
+                .settled(addr, DialTrigger::Bootstrap, DialOutcome::Filtered, now);
+            return;
+        }
+        match self.transport.dial(addr) {
+            Ok((endpoint, local_addr)) => {
+                self.dials
+                    .started(endpoint, addr, DialTrigger::Bootstrap, now);
+                self.bootstrap_check.dialed(endpoint, &name, addr);
+                self.participants
+                    .add_connecting_participant(endpoint, now, 0);
+                self.participants
+                    .set_interface(&endpoint, self.interfaces.of_local_addr(local_addr));
+                let formatted_msg = format!(
+                    "Dialing \"{}\" again at \"{}\", no participant is connected",
+                    name, addr
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            Err(err) => {
+                let outcome = if descriptors::is_exhausted(&err) {
+                    DialOutcome::Deferred
+                } else {
+                    DialOutcome::Failed
+                };
+                self.dials
+                    .settled(addr, DialTrigger::Bootstrap, outcome, now);
+                self.dial_refused(addr);
+            }
+        }
+    }
+
+    /// Asks the neighbor that listed `target` to introduce this participant to it.
+    ///
+    /// Called after a dial to `target` failed or was refused. Only addresses learned from a
+    /// participants list have a known origin, so bootstrap targets and participants dialed on
+    /// an introduction never cause a request. The origin is forgotten once asked: a second
+    /// failure does not ask again.
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: The public address that could not be reached.
+    pub(super) fn request_introduction(&mut self, target: SocketAddr) {
+        let Some(origin) = self.list_origins.remove(&target.into()) else {
+            return;
+        };
+
+        let participants = &mut self.participants;
+        let Some(origin_endpoint) = participants.endpoint_of(origin) else {
+            return;
+        };
+        let interface = participants.interface(&origin_endpoint);
+
+        let formatted_msg = format!(
+            "Asking \"{}\" to introduce us to unreachable \"{}\"",
+            origin, target
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+
+        send_message(
+            &mut self.transport,
+            participants,
+            origin_endpoint,
+            &Message::PleaseIntroduce {
+                target,
+                my_addr: self.interfaces.advertise(interface),
+            },
+        );
+    }
+}
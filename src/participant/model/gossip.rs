@@ -0,0 +1,342 @@
+//! The gossip tick and the announcements of this participant.
+
+use crate::printer::print_event;
+
+use crate::participant::event::InternalEvent;
+use crate::participant::exchange::ListPurpose;
+use crate::participant::message::{Message, MessageKind, ROUNDS_VERSION};
+use crate::participant::payload::default_payload;
+use crate::participant::period::differs_notably;
+use crate::participant::round::RoundClock;
+use crate::participant::storage::ParticipantAddress;
+use crate::participant::tick::{LateTick, TickGap};
+use crate::participant::trace::TickTrace;
+use crate::participant::transport::SendOutcome;
+use crate::participant::utils::{
+    broadcast_timed, format_addrs_capped, send_frames_timed, TimedSend, DEFAULT_ADDRS_CAP,
+};
+
+use crate::participant::transport::Connection;
+use rand::Rng;
+
+use std::time::{Instant, SystemTime};
+
+use super::{Participant, REANNOUNCE_INTERVAL};
+
+impl Participant {
+    /// Re-sends this participant's public address to every connected peer.
+    ///
+    /// Each peer is also asked for its participants list, an immediate anti-entropy round that
+    /// spreads the refreshed address without waiting for new connections. Re-announcements are
+    /// limited to one per `REANNOUNCE_INTERVAL`; extra calls only log how long to wait.
+    pub fn reannounce(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_reannounce {
+            let elapsed = now.duration_since(last);
+            if elapsed < REANNOUNCE_INTERVAL {
+                let formatted_msg = format!(
+                    "Already re-announced, try again in {:.1}s",
+                    (REANNOUNCE_INTERVAL - elapsed).as_secs_f64()
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                return;
+            }
+        }
+        self.last_reannounce = Some(now);
+
+        let endpoints: Vec<Connection> = self
+            .participants
+            .receivers()
+            .into_iter()
+            .map(|ParticipantAddress { endpoint, .. }| endpoint)
+            .collect();
+
+        // Each participant is told the address of its own interface.
+        for endpoint in &endpoints {
+            let advertise = self
+                .interfaces
+                .advertise(self.participants.interface(endpoint));
+            self.send(*endpoint, Message::PublicAddress(advertise));
+        }
+        for endpoint in &endpoints {
+            self.request_list(*endpoint, ListPurpose::Reannounce);
+        }
+
+        let formatted_msg = format!(
+            "Re-announced \"{}\" to {} participants",
+            self.interfaces.primary(),
+            endpoints.len()
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Sends the gossip message `msg` to `endpoints`, each send timed.
+    ///
+    /// The receivers with queued messages get them along with the gossip message, in one send
+    /// timed as a whole. A traced tick also records how long serializing the message took.
+    ///
+    /// # Returns
+    ///
+    /// Every send, with the kind of the gossip message.
+    pub(super) fn gossip(
+        &mut self,
+        endpoints: &[Connection],
+        msg: Message,
+        trace: Option<&mut TickTrace>,
+    ) -> Vec<(TimedSend, MessageKind)> {
+        let kind = msg.kind();
+        let (piggybacked, endpoints): (Vec<Connection>, Vec<Connection>) = endpoints
+            .iter()
+            .partition(|endpoint| self.outbox.has_pending(endpoint));
+        let output_data = match trace {
+            Some(trace) => {
+                let started = Instant::now();
+                let output_data = msg.encode();
+                trace.serialize += started.elapsed();
+                output_data
+            }
+            None => msg.encode(),
+        };
+        let mut sends = broadcast_timed(&mut self.transport, &endpoints, &output_data);
+        for endpoint in piggybacked {
+            self.outbox.push(endpoint, msg.clone(), Instant::now());
+            let frames = self.outbox.take(&endpoint);
+            sends.push(send_frames_timed(&mut self.transport, endpoint, &frames));
+        }
+        sends.into_iter().map(|send| (send, kind)).collect()
+    }
+
+    /// Sends a generated text message to all known participants.
+    ///
+    /// Runs on every `InternalEvent::BroadcastTick` and re-arms the tick, so a message goes out
+    /// every period for the whole life of the participant. The message tells the number of the
+    /// tick and the label or address of this participant, along with a random number, in the
+    /// form described by the `payload` module.
+    ///
+    /// # Notes
+    ///
+    /// - The tick runs on the event loop, with plain access to the storage: nothing is locked,
+    ///   however short the period.
+    /// - An adaptive period is recomputed from the number of receivers before the tick is
+    ///   re-armed, and logged whenever it moved notably since the last logged value.
+    /// - The gossip message is never queued in the outbox, since the send budget times it;
+    ///   instead it takes along the messages already queued for its receiver.
+    /// - Every send is timed. A receiver whose send took longer than `send_budget` skips the
+    ///   next round, its message counted as deferred, so one stuck peer can delay at most every
+    ///   other round. Sends the network layer refuses are counted as deferred too.
+    /// - After each round, connections that look half-open are closed and dialed again.
+    /// - With `--trace-ticks`, the phases of every nth tick are timed and logged as one line.
+    pub(super) fn broadcast_tick(&mut self) {
+        self.broadcast_ticks += 1;
+        let mut trace = self
+            .tracer
+            .should_trace()
+            .then(|| TickTrace::start(Instant::now(), SystemTime::now()));
+        if let Some(late) = self.tick_monitor.fired(Instant::now(), SystemTime::now()) {
+            self.late_tick(late);
+        }
+
+        // Retrieve the list of receivers (participants) to send the message to.
+        let snapshot_started = Instant::now();
+        let receivers = self.participants.receivers();
+        if let Some(trace) = &mut trace {
+            trace.snapshot = snapshot_started.elapsed();
+        }
+
+        self.effective_period = self.period.for_peers(receivers.len());
+        if differs_notably(self.logged_period, self.effective_period) {
+            let formatted_msg = format!(
+                "Gossip period is now {:?} with {} peers",
+                self.effective_period,
+                receivers.len()
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.logged_period = self.effective_period;
+        }
+        let delay = match &mut self.rounds {
+            Some(clock) => {
+                let (skipped, delay) = clock.tick(self.effective_period, Instant::now());
+                if skipped > 0 {
+                    let formatted_msg = format!(
+                        "Jumped to round {}, skipping {} rounds to catch up with the peers",
+                        clock.round(),
+                        skipped
+                    );
+                    print_event(self.time_start.clone(), &formatted_msg);
+                }
+                delay
+            }
+            None => self.effective_period,
+        };
+        self.node_handler
+            .signals()
+            .send_with_timer(InternalEvent::BroadcastTick, delay);
+        self.tick_monitor
+            .armed(delay, Instant::now(), SystemTime::now());
+
+        // If there are no participants to send to, wait for the next tick.
+        if receivers.is_empty() {
+            if let Some(trace) = trace {
+                self.finish_trace(trace);
+            }
+            return;
+        }
+
+        // Generate the message text, numbered by the tick even when it is not sent.
+        let from = match &self.node_label {
+            Some(label) => label.clone(),
+            None => self.interfaces.primary().to_string(),
+        };
+        let msg_text = default_payload(
+            self.broadcast_ticks,
+            &from,
+            self.rng.gen_range(0..1000),
+            self.legacy_payload,
+        );
+        let round = self.rounds.as_ref().map(RoundClock::round);
+
+        // Log the message being sent for debugging or monitoring purposes.
+        let formatted_msg = match round {
+            Some(round) => format!(
+                "Sending message [{}] in round {} to {}",
+                &msg_text,
+                round,
+                format_addrs_capped(&receivers, DEFAULT_ADDRS_CAP)
+            ),
+            None => format!(
+                "Sending message [{}] to {}",
+                &msg_text,
+                format_addrs_capped(&receivers, DEFAULT_ADDRS_CAP)
+            ),
+        };
+        let logging_started = Instant::now();
+        print_event(self.time_start.clone(), &formatted_msg);
+        if let Some(trace) = &mut trace {
+            trace.logging = logging_started.elapsed();
+        }
+
+        // Send the message to every receiver, serializing it only once. A receiver whose
+        // previous send overran the budget sits this round out.
+        let mut endpoints = Vec::with_capacity(receivers.len());
+        for ParticipantAddress { endpoint, .. } in &receivers {
+            if self.participants.take_congested(endpoint) {
+                self.participants.record_deferred_send(endpoint);
+            } else {
+                endpoints.push(*endpoint);
+            }
+        }
+
+        // The text is remembered per receiver, to recognize a peer sending it back.
+        for endpoint in &endpoints {
+            self.echoes.sent(*endpoint, &msg_text, Instant::now());
+        }
+
+        // In rounds, the peers that know rounds are told the round, the others get a plain text.
+        let (round_endpoints, text_endpoints): (Vec<Connection>, Vec<Connection>) =
+            endpoints.into_iter().partition(|endpoint| {
+                round.is_some() && self.participants.protocol_version(endpoint) >= ROUNDS_VERSION
+            });
+        let mut sends = self.gossip(
+            &text_endpoints,
+            Message::Text(msg_text.clone()),
+            trace.as_mut(),
+        );
+        if let Some(round) = round {
+            sends.extend(self.gossip(
+                &round_endpoints,
+                Message::RoundText {
+                    round,
+                    text: msg_text,
+                },
+                trace.as_mut(),
+            ));
+        }
+
+        let mut slowest: Option<TimedSend> = None;
+        for (send, kind) in sends {
+            if send.status == SendOutcome::Sent {
+                self.participants.record_sent_kind(&send.endpoint, kind);
+            } else {
+                self.participants.record_deferred_send(&send.endpoint);
+            }
+            if send.elapsed > self.send_budget {
+                self.participants.mark_congested(&send.endpoint);
+            }
+            if slowest.is_none_or(|slowest| send.elapsed > slowest.elapsed) {
+                slowest = Some(send);
+            }
+            if let Some(trace) = &mut trace {
+                trace.send += send.elapsed;
+                let public = self.participants.get_pub_addr(&send.endpoint);
+                trace.record_send(public.unwrap_or(send.endpoint.addr()), send.elapsed);
+            }
+        }
+        self.slowest_send = slowest.and_then(|send| {
+            let public = self.participants.get_pub_addr(&send.endpoint)?;
+            Some((public, send.elapsed))
+        });
+
+        if let Some((public, elapsed)) = self.slowest_send {
+            if elapsed > self.send_budget {
+                let formatted_msg = format!(
+                    "Sending to \"{}\" took {:.1}ms, over the {}ms budget; its next message is deferred",
+                    public,
+                    elapsed.as_secs_f64() * 1000.0,
+                    self.send_budget.as_millis()
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+        }
+
+        // Re-dial the participants that stopped answering while others still do.
+        for suspect in self
+            .participants
+            .half_open_suspects(self.half_open_threshold, self.effective_period)
+        {
+            let unanswered = self
+                .participants
+                .sends_since_receive(&suspect.endpoint)
+                .unwrap_or_default();
+            let formatted_msg = format!(
+                "Reconnecting to \"{}\" due to suspected half-open connection ({} messages without reply)",
+                suspect.public, unanswered
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+
+            self.redial(suspect);
+        }
+
+        if let Some(trace) = trace {
+            self.finish_trace(trace);
+        }
+    }
+
+    /// Logs the trace of a broadcast tick and adds it to the tick histograms.
+    pub(super) fn finish_trace(&mut self, trace: TickTrace) {
+        let (line, total) = trace.finish(Instant::now());
+        self.tracer.record(&trace, total);
+        print_event(self.time_start.clone(), &line);
+    }
+
+    /// Reports a broadcast tick that fired late, and resynchronizes after a frozen one.
+    ///
+    /// # Parameters
+    ///
+    /// - `late`: The gap between the tick and the previous one.
+    pub(super) fn late_tick(&mut self, late: LateTick) {
+        let formatted_msg = match late.gap {
+            TickGap::Frozen => format!(
+                "Broadcast tick fired after {:.1?} instead of {:.1?}, this participant was frozen; resynchronizing with the peers",
+                late.actual, late.expected
+            ),
+            _ => format!(
+                "Broadcast tick fired after {:.1?} instead of {:.1?}, this participant was not scheduled in time",
+                late.actual, late.expected
+            ),
+        };
+        print_event(self.time_start.clone(), &formatted_msg);
+        if late.gap == TickGap::Frozen {
+            self.recover_from_freeze();
+        }
+    }
+}
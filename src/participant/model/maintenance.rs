@@ -0,0 +1,721 @@
+//! The periodic upkeep of a participant: rebalancing, audits, split detection and reloads.
+
+use crate::printer::{format_elapsed, print_event};
+
+use crate::participant::alert::AlertEvent;
+use crate::participant::audit::{
+    dead_endpoints, index_mismatch, orphaned_keys, shared_public_addresses, AuditSummary,
+};
+use crate::participant::config::validate::{validate, Severity};
+use crate::participant::config::NodeConfig;
+use crate::participant::contact::FIRST_CONTACT_TIMEOUT;
+use crate::participant::descriptors::{self, Pressure};
+use crate::participant::dials::{DialOutcome, DialTrigger};
+use crate::participant::event::InternalEvent;
+use crate::participant::exchange::LIST_REQUEST_TIMEOUT;
+use crate::participant::heal::Probe;
+use crate::participant::hints::{self};
+use crate::participant::load::LoadState;
+use crate::participant::message::{AgeBucket, Message, SharedPeer, SHARED_PEERS_VERSION};
+use crate::participant::observer::{OBSERVER_IDLE_TIMEOUT, UPDATE_INTERVAL};
+use crate::participant::record::MembershipRecord;
+use crate::participant::reload::{self, Reload};
+use crate::participant::session::DepartureReason;
+use crate::participant::skew::CLOCK_SAMPLE_INTERVAL;
+use crate::participant::split::SPLIT_REPRESENTATIVES;
+use crate::participant::storage::{ParticipantAddress, ParticipantsStorage};
+use crate::participant::transport::Transport;
+use crate::participant::utils::{broadcast_message, CanonicalAddr};
+
+use crate::participant::transport::Connection;
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use super::{Participant, FILTER_POLL_INTERVAL, HYGIENE_REPORT_INTERVAL};
+
+impl Participant {
+    /// Flushes the coalesced observer update, if one is due, disconnects idle observers,
+    /// sweeps the expired entries of the bounded maps, gives up the dials that took longer than
+    /// `connect_timeout` and the requests left unanswered past their deadline or by peers that
+    /// are gone, raises the `isolated` alert when the last peer left, notes its own state in
+    /// the recording every `--record-snapshot-every`, lifts the expired mutes, reloads the filter file if it changed, probes departed peers every
+    /// `--heal-probe-interval` and, every `audit_every` ticks, audits the storage.
+    ///
+    /// How late the tick fired is first sampled as the lag of the event loop: while it makes
+    /// the participant busy, the heal probes and the split-brain detection are skipped and the
+    /// audit waits for the first tick that is not.
+    ///
+    /// Re-arms itself so that it runs every `UPDATE_INTERVAL` for the whole life of the
+    /// participant.
+    pub(super) fn maintenance_tick(&mut self) {
+        let now = Instant::now();
+        self.sample_load(now);
+        self.recent_dials.sweep(now);
+        self.list_origins.sweep(now);
+        self.candidates.sweep(now);
+        self.participants.sweep_sessions(now, &mut self.retention);
+        self.peer_lists.sweep(now, &mut self.retention);
+        self.introduced_pairs.sweep(now);
+        // The participant is isolated once its last peer is gone, however it left.
+        let connected = !self.participants.receivers().is_empty();
+        if self.was_connected && !connected {
+            print_event(
+                self.time_start.clone(),
+                "No participant is connected anymore",
+            );
+            self.alerter.raise(
+                AlertEvent::Isolated,
+                self.interfaces.primary(),
+                Vec::new(),
+                now,
+            );
+        }
+        self.was_connected = connected;
+        let peers = self.participants.receivers().len();
+        let signals = self
+            .hints
+            .signals(now, self.effective_period, peers, self.broadcast_ticks);
+        let found = hints::detect(&signals);
+        for hint in self.hints.evaluate(found) {
+            let formatted_msg = format!("WARNING: hint: {}", hint.text);
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        if self.next_self_state.is_some_and(|next| now >= next) {
+            self.next_self_state = now.checked_add(self.record_snapshot_every);
+            if let Some(recorder) = &self.recorder {
+                recorder.membership(MembershipRecord::SelfState {
+                    peers: self.handshaked_peers().len() as u64,
+                    period_ms: self.effective_period.as_millis() as u64,
+                });
+            }
+        }
+
+        for (public, dropped) in self.participants.expire_mutes(now) {
+            let formatted_msg = format!(
+                "The mute of \"{}\" expired after dropping {} messages",
+                public, dropped
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        for (endpoint, purpose) in self.list_exchanges.expire(now) {
+            let formatted_msg = format!(
+                "\"{}\" did not answer the participants list request of {} within {}",
+                self.participants
+                    .get_pub_addr(&endpoint)
+                    .unwrap_or(endpoint.addr()),
+                purpose.name(),
+                format_elapsed(LIST_REQUEST_TIMEOUT)
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        for addr in self.bans.expire(now) {
+            let formatted_msg = format!("The ban of \"{}\" expired", addr);
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        self.push_list_limiter.sweep(now);
+
+        for endpoint in self.observers.expire_idle(now, OBSERVER_IDLE_TIMEOUT) {
+            self.transport.close(endpoint);
+        }
+
+        for endpoint in self
+            .participants
+            .expired_connecting(now, self.connect_timeout)
+        {
+            self.transport.close(endpoint);
+            self.dials.finished(&endpoint, DialOutcome::TimedOut, now);
+            let formatted_msg = format!(
+                "Can not connect to \"{}\" within {:?}",
+                endpoint.addr(),
+                self.connect_timeout
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.dial_failed(endpoint);
+        }
+
+        for endpoint in self.contacts.expired(now, FIRST_CONTACT_TIMEOUT) {
+            let formatted_msg = format!(
+                "Closing the connection of \"{}\", which sent nothing within {:?}",
+                endpoint.addr(),
+                FIRST_CONTACT_TIMEOUT
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.close_unverified(endpoint, DepartureReason::ProtocolMismatch);
+        }
+
+        if let Some(changes) = self.observers.take_update(now) {
+            let stats = self.observer_stats();
+            let msg = Message::ObserverUpdate { changes, stats };
+            broadcast_message(&mut self.transport, &self.observers.endpoints(), &msg);
+        }
+
+        self.pending_requests.expire(now);
+        let participants = &self.participants;
+        self.pending_requests
+            .peer_gone(|endpoint| participants.get_pub_addr(endpoint).is_none());
+
+        if now.duration_since(self.last_filter_poll) >= FILTER_POLL_INTERVAL {
+            self.last_filter_poll = now;
+            self.reload_filter();
+        }
+
+        if now.duration_since(self.last_hygiene_report) >= HYGIENE_REPORT_INTERVAL {
+            self.last_hygiene_report = now;
+            let refused = self.hygiene_log.take_unreported();
+            if refused > 0 {
+                let formatted_msg = format!(
+                    "Refused to dial {} learned addresses on privileged ports or in special ranges in the last {}",
+                    refused,
+                    format_elapsed(HYGIENE_REPORT_INTERVAL)
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+        }
+
+        if let Some(dropped) = self.load.take_warning(now) {
+            let formatted_msg = format!(
+                "Overloaded: dropped {} received application payloads, the event loop is {:?} behind",
+                dropped,
+                self.load.last_lag()
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+
+        self.warmup_tick(now);
+        self.resume_dials(now);
+        if now >= self.next_clock_sample {
+            self.next_clock_sample = now + CLOCK_SAMPLE_INTERVAL;
+            self.request_clocks();
+        }
+
+        // A busy participant keeps its housekeeping to what the membership needs, and audits
+        // once it caught up.
+        let busy = self.load.state().defers_housekeeping();
+        self.bridged_splits.sweep(now);
+        if !busy {
+            if self.heal.round_due(now) {
+                self.probe_departed(now);
+            }
+            self.detect_split(now);
+            self.rebalance(now);
+        }
+        if !connected {
+            self.dial_connect_target(now);
+        }
+
+        if self.audit_every > 0 {
+            self.ticks_since_audit += 1;
+            if self.ticks_since_audit >= self.audit_every && !busy {
+                self.audit();
+            }
+        }
+
+        self.arm_maintenance_tick();
+    }
+
+    /// Dials an inbound-only peer back while too few connections are outbound, one at a time.
+    ///
+    /// A peer dialed back recently is skipped, so one that can not be reached, such as a peer
+    /// behind a NAT, does not keep being dialed every tick.
+    pub(super) fn rebalance(&mut self, now: Instant) {
+        let Some(balancer) = &self.balancer else {
+            return;
+        };
+        let (inbound, outbound) = self.participants.direction_counts();
+        if !balancer.wants_outbound(inbound, outbound) {
+            return;
+        }
+        let Some(peer) = self.participants.inbound_only().into_iter().find(|peer| {
+            !self.interfaces.is_own(peer.public)
+                && !self.filter.is_blocked(peer.public)
+                && !self.bans.is_banned(peer.public, now)
+                && !self.recent_dials.contains(&peer.public.into(), now)
+        }) else {
+            return;
+        };
+        self.recent_dials.insert(peer.public.into(), (), now);
+        match self.transport.dial(peer.public) {
+            Ok((endpoint, _)) => {
+                self.dials
+                    .started(endpoint, peer.public, DialTrigger::Rebalance, now);
+                let interface = self.participants.interface(&peer.endpoint);
+                self.participants
+                    .add_connecting_participant(endpoint, now, 0);
+                self.participants.set_interface(&endpoint, interface);
+                if let Some(balancer) = &mut self.balancer {
+                    balancer.start(peer.public, peer.endpoint, endpoint, now);
+                }
+                let formatted_msg = format!(
+                    "Dialing \"{}\" back, {} of {} connections are outbound",
+                    peer.public,
+                    outbound,
+                    inbound + outbound
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            // Tried again on a later tick.
+            Err(err) if descriptors::is_exhausted(&err) => {
+                self.dials.settled(
+                    peer.public,
+                    DialTrigger::Rebalance,
+                    DialOutcome::Deferred,
+                    now,
+                );
+            }
+            Err(_) => {
+                self.dials.settled(
+                    peer.public,
+                    DialTrigger::Rebalance,
+                    DialOutcome::Failed,
+                    now,
+                );
+                self.dial_refused(peer.public)
+            }
+        }
+    }
+
+    /// Dials the learned addresses the warm-up put off and are due, and ends the warm-up once
+    /// its window is over.
+    pub(super) fn warmup_tick(&mut self, now: Instant) {
+        let Some(warmup) = &mut self.warmup else {
+            return;
+        };
+        let due = warmup.due(now);
+        let over = !warmup.is_warming(now);
+        let deferred = warmup.deferred_total();
+        for dial in due {
+            self.dial_deferred(dial, now);
+        }
+        if over {
+            self.warmup = None;
+            let formatted_msg = format!("Warm-up over, {} dials were put off", deferred);
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+    }
+
+    /// Logs the file descriptors in use crossing `PRESSURE_PERCENT` of the budget, or falling
+    /// back under `RELIEF_PERCENT`.
+    pub(super) fn log_descriptor_pressure(&mut self) {
+        let Some(pressure) = self.descriptors.pressure() else {
+            return;
+        };
+        let budget = self.descriptors.stats();
+        let formatted_msg = match pressure {
+            Pressure::High => format!(
+                "WARNING: {} of {} file descriptors in use, over {}%; new dials are put off and connections refused once none is left",
+                budget.in_use,
+                budget.ceiling,
+                descriptors::PRESSURE_PERCENT
+            ),
+            Pressure::Relieved => format!(
+                "File descriptors back under {}%: {} of {} in use",
+                descriptors::RELIEF_PERCENT,
+                budget.in_use,
+                budget.ceiling
+            ),
+        };
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Arms the next maintenance tick, due `UPDATE_INTERVAL` from now.
+    pub(super) fn arm_maintenance_tick(&mut self) {
+        self.maintenance_due = Instant::now() + UPDATE_INTERVAL;
+        self.node_handler
+            .signals()
+            .send_with_timer(InternalEvent::MaintenanceTick, UPDATE_INTERVAL);
+    }
+
+    /// Samples the lag of the event loop from how late the maintenance tick fired, and logs
+    /// the change of load state it causes.
+    pub(super) fn sample_load(&mut self, now: Instant) {
+        let lag = now.saturating_duration_since(self.maintenance_due);
+        let Some(change) = self.load.observe(lag, now) else {
+            return;
+        };
+        let formatted_msg = if change.to > change.from {
+            let shedding = match change.to {
+                LoadState::Overloaded => {
+                    "deferring housekeeping and dropping received application payloads"
+                }
+                _ => "deferring audits, heal probes and split-brain bridging",
+            };
+            format!(
+                "The event loop is {:?} behind: {}, {}",
+                change.lag, change.to, shedding
+            )
+        } else {
+            format!(
+                "The event loop caught up ({:?} behind): {} again after {}",
+                change.lag, change.to, change.from
+            )
+        };
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Dials a few remembered peers that departed and are not connected, in case the network
+    /// split and healed since.
+    ///
+    /// The probes come after any other dial: none is started while the participant is still
+    /// bootstrapping, and the addresses dialed recently, own or blocked are skipped. A probe
+    /// is not recorded among the recent dials, so it never delays the dial of a listed peer.
+    pub(super) fn probe_departed(&mut self, now: Instant) {
+        let remembered = self.participants.departed();
+        let known: HashSet<CanonicalAddr> =
+            remembered.iter().map(|probe| probe.addr.into()).collect();
+        self.heal.retain(|addr| known.contains(&addr.into()));
+        if self.bootstrapping {
+            return;
+        }
+
+        let departed: Vec<Probe> = remembered
+            .into_iter()
+            .filter(|probe| {
+                !self.interfaces.is_own(probe.addr)
+                    && !self.filter.is_blocked(probe.addr)
+                    && !self.bans.is_banned(probe.addr, now)
+                    && !self.recent_dials.contains(&probe.addr.into(), now)
+            })
+            .collect();
+        for probe in self.heal.select(departed, now) {
+            match self.transport.dial(probe.addr) {
+                Ok((endpoint, local_addr)) => {
+                    self.participants
+                        .add_connecting_participant(endpoint, now, 0);
+                    self.participants
+                        .set_interface(&endpoint, self.interfaces.of_local_addr(local_addr));
+                    self.heal.started(endpoint, probe);
+                    self.dials
+                        .started(endpoint, probe.addr, DialTrigger::HealProbe, now);
+                }
+                Err(err) if descriptors::is_exhausted(&err) => {
+                    self.dials.settled(
+                        probe.addr,
+                        DialTrigger::HealProbe,
+                        DialOutcome::Deferred,
+                        now,
+                    );
+                }
+                Err(_) => {
+                    self.dials.settled(
+                        probe.addr,
+                        DialTrigger::HealProbe,
+                        DialOutcome::Failed,
+                        now,
+                    );
+                    let formatted_msg = format!("Failed to probe \"{}\"", probe.addr);
+                    print_event(self.time_start.clone(), &formatted_msg);
+                }
+            }
+        }
+    }
+
+    /// Looks for groups of peers that do not know each other, and bridges them.
+    ///
+    /// The peers are grouped by the participants lists they last sent; when they form two
+    /// components or more, each member is pushed a participants list naming representatives of
+    /// the other components, this participant first. A split bridged within the last
+    /// `SPLIT_BRIDGE_INTERVAL` is not logged nor bridged again.
+    pub(super) fn detect_split(&mut self, now: Instant) {
+        let receivers = self.participants.receivers();
+        let connected: HashSet<CanonicalAddr> = receivers
+            .iter()
+            .map(|receiver| receiver.public.into())
+            .collect();
+        self.peer_lists
+            .retain(|peer| connected.contains(&peer.into()));
+        let interfaces = &self.interfaces;
+        let components = self.peer_lists.components(|addr| interfaces.is_own(addr));
+        if components.len() < 2 || self.bridged_splits.contains(&components, now) {
+            return;
+        }
+
+        self.split_brains += 1;
+        let sizes: Vec<String> = components
+            .iter()
+            .map(|component| component.len().to_string())
+            .collect();
+        let formatted_msg = format!(
+            "Possible split-brain: {} components of sizes [{}]",
+            components.len(),
+            sizes.join(", ")
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+
+        for (index, component) in components.iter().enumerate() {
+            let representatives: Vec<SharedPeer> = components
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .flat_map(|(_, other)| other.iter().take(SPLIT_REPRESENTATIVES))
+                .map(|&addr| SharedPeer {
+                    addr,
+                    age: AgeBucket::Fresh,
+                })
+                .collect();
+            for member in component {
+                let Some(endpoint) = self.participants.endpoint_of(*member) else {
+                    continue;
+                };
+                let own = SharedPeer {
+                    addr: self
+                        .interfaces
+                        .advertise(self.participants.interface(&endpoint)),
+                    age: AgeBucket::Fresh,
+                };
+                let peers: Vec<SharedPeer> = std::iter::once(own)
+                    .chain(representatives.iter().copied())
+                    .collect();
+                let msg = if self.participants.protocol_version(&endpoint) >= SHARED_PEERS_VERSION {
+                    Message::SharedPeers(peers)
+                } else {
+                    Message::PullParticipantsList(peers.into_iter().map(|peer| peer.addr).collect())
+                };
+                self.send(endpoint, msg);
+            }
+        }
+        self.bridged_splits.insert(components, (), now);
+    }
+
+    /// Re-reads the filter file if it changed and applies the new rules to the current peers.
+    ///
+    /// A file that can not be read or parsed leaves the previous rules in place. With the new
+    /// rules, every stored participant whose public address is now blocked is disconnected,
+    /// which also leaves it out of the participants lists served from then on.
+    pub(super) fn reload_filter(&mut self) {
+        let Some(file) = &mut self.filter_file else {
+            return;
+        };
+        let path = file.path().display().to_string();
+        let rules = match file.poll() {
+            None => return,
+            Some(Ok(rules)) => rules,
+            Some(Err(err)) => {
+                let formatted_msg = format!(
+                    "Keeping the previous filter rules, can not use \"{}\": {}",
+                    path, err
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                return;
+            }
+        };
+
+        self.filter.set_rules(rules);
+        let formatted_msg = format!(
+            "Loaded {} filter rules from \"{}\"",
+            self.filter.rule_count(),
+            path
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+
+        let blocked: Vec<(Connection, SocketAddr)> = self
+            .participants
+            .endpoints()
+            .filter_map(|endpoint| {
+                let public = self.participants.get_pub_addr(endpoint)?;
+                self.filter
+                    .is_blocked(public)
+                    .then_some((*endpoint, public))
+            })
+            .collect();
+        for (endpoint, public) in blocked {
+            let formatted_msg = format!("Disconnecting \"{}\", now blocked", public);
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.record_membership_change(public, false, "blocklisted");
+            self.push_list_limiter.forget(&endpoint);
+            ParticipantsStorage::drop(
+                &mut self.participants,
+                endpoint,
+                DepartureReason::Blocked,
+                Instant::now(),
+            );
+            self.transport.close(endpoint);
+        }
+    }
+
+    /// Checks the storage against the state kept next to it and repairs what can be repaired.
+    ///
+    /// The public address index is rebuilt when it disagrees with the storage, rate limiter
+    /// buckets and list origins left over by participants that are gone are dropped, and
+    /// participants whose connection the network layer no longer holds are re-dialed, like
+    /// half-open suspects. Public addresses announced by several connections are reported but
+    /// kept, since either connection may be the live one. Prints a one-line summary, preceded
+    /// by a warning for every finding left in place.
+    ///
+    /// # Returns
+    ///
+    /// The summary printed.
+    pub(super) fn audit(&mut self) -> AuditSummary {
+        self.ticks_since_audit = 0;
+        let mut summary = AuditSummary::default();
+
+        let participants = &mut self.participants;
+        summary.checked += participants.count();
+        if index_mismatch(
+            participants.public_index(),
+            |endpoint| participants.get_pub_addr(endpoint),
+            participants.count(),
+        ) {
+            participants.rebuild_index();
+            summary.repaired += 1;
+        }
+
+        let mut shared = Vec::new();
+        summary.checked += shared_public_addresses(participants.public_index(), &mut shared);
+        for (public, connections) in shared {
+            let formatted_msg = format!(
+                "Audit warning: \"{}\" is announced by {} connections",
+                public, connections
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            summary.unrepairable += 1;
+        }
+
+        let mut orphaned_buckets = Vec::new();
+        summary.checked += orphaned_keys(
+            self.push_list_limiter.peers().copied(),
+            |endpoint| participants.get_pub_addr(endpoint).is_some(),
+            &mut orphaned_buckets,
+        );
+        for endpoint in &orphaned_buckets {
+            self.push_list_limiter.forget(endpoint);
+        }
+        summary.repaired += orphaned_buckets.len();
+
+        let mut orphaned_origins = Vec::new();
+        summary.checked += orphaned_keys(
+            self.list_origins
+                .iter()
+                .map(|(target, origin)| (*target, *origin)),
+            |(_, origin)| participants.contains_addr(*origin),
+            &mut orphaned_origins,
+        );
+        for (target, _) in &orphaned_origins {
+            self.list_origins.remove(target);
+        }
+        summary.repaired += orphaned_origins.len();
+
+        let transport = &self.transport;
+        let mut dead = Vec::new();
+        summary.checked += dead_endpoints(
+            participants.endpoints().copied(),
+            |endpoint| transport.is_open(*endpoint),
+            &mut dead,
+        );
+        for endpoint in dead {
+            let Some(public) = self.participants.get_pub_addr(&endpoint) else {
+                continue;
+            };
+            let formatted_msg = format!(
+                "Audit found no connection behind \"{}\", reconnecting",
+                public
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.redial(ParticipantAddress { public, endpoint });
+            summary.repaired += 1;
+        }
+
+        print_event(self.time_start.clone(), &summary.to_string());
+        summary
+    }
+
+    /// Reads the configuration again through the loader and applies its hot fields, logging the
+    /// outcome.
+    ///
+    /// A configuration that can not be loaded, or whose merge with the running one has errors,
+    /// is rejected and leaves every value as it was. Otherwise the changed hot fields are
+    /// applied together, and the changed cold ones are listed as ignored.
+    ///
+    /// # Returns
+    ///
+    /// The lines logged.
+    pub(super) fn reload(&mut self) -> Vec<String> {
+        let Some(loader) = &mut self.reloader else {
+            let formatted_msg =
+                "Nothing to reload: the participant was not started with --config".to_owned();
+            print_event(self.time_start.clone(), &formatted_msg);
+            return vec![formatted_msg];
+        };
+        let mut lines = Vec::new();
+        let outcome = loader().and_then(|loaded| {
+            let (merged, changes) = reload::merge(&self.config, loaded);
+            let (errors, warnings): (Vec<_>, Vec<_>) = validate(&merged)
+                .into_iter()
+                .partition(|issue| issue.severity == Severity::Error);
+            lines.extend(warnings.iter().map(|issue| format!("\t{}", issue)));
+            if errors.is_empty() {
+                Ok((merged, changes))
+            } else {
+                lines.extend(errors.iter().map(|issue| format!("\t{}", issue)));
+                Err(format!("{} configuration errors", errors.len()))
+            }
+        });
+        let header = match outcome {
+            Ok((merged, changes)) => {
+                self.apply_config(&merged);
+                self.config = merged;
+                self.reloads.0 += 1;
+                let applied = changes
+                    .iter()
+                    .filter(|change| change.reload == Reload::Hot)
+                    .count();
+                lines.splice(0..0, changes.iter().map(|change| format!("\t{}", change)));
+                format!(
+                    "Reloaded the configuration, generation {}: {} changes applied, {} ignored",
+                    self.reloads.0,
+                    applied,
+                    changes.len() - applied
+                )
+            }
+            Err(err) => {
+                self.reloads.1 += 1;
+                format!(
+                    "Rejected the reloaded configuration, keeping generation {}: {}",
+                    self.reloads.0, err
+                )
+            }
+        };
+        lines.insert(0, header);
+        for line in &lines {
+            print_event(self.time_start.clone(), line);
+        }
+        lines
+    }
+
+    /// Applies the hot fields of `config`, as classified by `reload::merge`, to the state that
+    /// reads them.
+    pub(super) fn apply_config(&mut self, config: &NodeConfig) {
+        self.legacy_payload = config.legacy_payload;
+        self.half_open_threshold = config.half_open_threshold;
+        self.period_skew_ratio = config.period_skew_ratio;
+        self.max_clock_offset = config.max_clock_offset;
+        self.schedule.set_max(config.max_scheduled);
+        self.dial_hygiene.allow_privileged_ports = config.allow_privileged_ports;
+        self.dial_hygiene.allow_special_ranges = config.allow_special_ranges;
+        self.topic_broadcast = config.topic_broadcast.iter().cloned().collect();
+        self.audit_every = config.audit_every;
+        self.share_limit = config.share_limit;
+        self.candidates.set_threshold(config.corroboration);
+        self.send_budget = config.send_budget;
+        self.outbox.set_window(config.batch_window);
+        self.tick_monitor
+            .set_factors(config.late_tick_factor, config.frozen_tick_factor);
+        self.connect_timeout = config.connect_timeout;
+        self.heal
+            .set_interval(config.heal_probe_interval, Instant::now());
+        if let Some(target) = &mut self.connect_target {
+            target.set_interval(config.resolve_interval);
+        }
+        self.load.set_lags(config.busy_lag, config.overload_lag);
+        self.tracer.set_every(config.trace_ticks);
+        self.report_file = config.report_file.clone();
+        self.report_format = config.report_format;
+        self.record_snapshot_every = config.record_snapshot_every;
+        if self.next_self_state.is_none() {
+            self.next_self_state = Instant::now().checked_add(self.record_snapshot_every);
+        }
+        self.alerter.set_cooldown(config.alert_cooldown);
+        self.hints.set_enabled(config.hints);
+    }
+}
@@ -0,0 +1,193 @@
+//! Probing peers, sampling their clocks and comparing their periods and rounds.
+
+use crate::printer::{print_event, print_line};
+
+use crate::participant::event::InternalEvent;
+use crate::participant::message::{Message, CLOCKS_VERSION, PROBES_VERSION};
+use crate::participant::probe::{self, ProbeSession};
+use crate::participant::skew::{self, ClockSample};
+use crate::participant::timing;
+
+use crate::participant::transport::Connection;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Starts a run of `count` probes to the participant at `peer`, `interval` apart.
+    ///
+    /// Nothing is sent when another run is in progress, or when `peer` is not a connected
+    /// participant speaking `PROBES_VERSION`.
+    ///
+    /// # Returns
+    ///
+    /// The number of the run, or the reason it did not start, for the console.
+    pub(super) fn start_probe(
+        &mut self,
+        peer: SocketAddr,
+        count: u32,
+        interval: Duration,
+    ) -> Result<u64, String> {
+        if let Some((_, session)) = &self.probe {
+            return Err(format!(
+                "A probe of \"{}\" is already running",
+                session.peer()
+            ));
+        }
+        let participants = &self.participants;
+        let endpoint = match participants.endpoint_of(peer) {
+            Some(endpoint) if !participants.is_connecting(&endpoint) => endpoint,
+            _ => return Err(format!("\"{}\" is not a connected participant", peer)),
+        };
+        if participants.protocol_version(&endpoint) < PROBES_VERSION {
+            return Err(format!("\"{}\" does not answer probes", peer));
+        }
+
+        let session = ProbeSession::new(peer, count, interval, Instant::now());
+        self.probe = Some((endpoint, session));
+        self.probe_runs += 1;
+        let run = self.probe_runs;
+        // A tick left over from a previous run starts this one.
+        if !self.probe_timer_armed {
+            self.probe_tick();
+        }
+        Ok(run)
+    }
+
+    /// Tells how far the running run of probes has come, or sums up the last one.
+    pub(super) fn probe_status(&self) -> String {
+        match (&self.probe, &self.last_probe) {
+            (Some((_, session)), _) => probe::running_line(self.probe_runs, &session.summary()),
+            (None, Some(summary)) => probe::done_line(self.probe_runs, summary),
+            (None, None) => "No probe has run".to_owned(),
+        }
+    }
+
+    /// Sends the next probe of the running run, gives up on the unanswered probes that timed
+    /// out, and prints the summary once the run is done.
+    pub(super) fn probe_tick(&mut self) {
+        let Some((endpoint, session)) = &mut self.probe else {
+            return;
+        };
+        let now = Instant::now();
+        session.expire(now);
+        let endpoint = *endpoint;
+        let interval = session.interval();
+        if let Some((id, sent_at_offset)) = session.next_probe(now) {
+            self.send(endpoint, Message::Probe { id, sent_at_offset });
+        }
+
+        if self
+            .probe
+            .as_ref()
+            .is_some_and(|(_, session)| session.is_done())
+        {
+            self.finish_probe();
+        } else {
+            self.probe_timer_armed = true;
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::ProbeTick, interval);
+        }
+    }
+
+    /// Prints the summary of the run of probes, if any, and ends it, keeping the summary for
+    /// `probe-status`.
+    pub(super) fn finish_probe(&mut self) {
+        if let Some((_, session)) = self.probe.take() {
+            let summary = session.summary();
+            print_line(&summary.to_string());
+            self.last_probe = Some(summary);
+        }
+    }
+
+    /// Sends a clock request to every handshaked peer that answers them.
+    pub(super) fn request_clocks(&mut self) {
+        for receiver in self.participants.receivers() {
+            let endpoint = receiver.endpoint;
+            if self.contacts.is_pending(&endpoint)
+                || self.participants.protocol_version(&endpoint) < CLOCKS_VERSION
+            {
+                continue;
+            }
+            let sent_at = skew::unix_micros(SystemTime::now());
+            self.participants.clock_requested(&endpoint, sent_at);
+            self.send(endpoint, Message::ClockRequest { sent_at });
+        }
+    }
+
+    /// Adds the sample of a clock reply to the estimate of its sender, and warns when the
+    /// estimate crosses `--max-clock-offset`.
+    pub(super) fn clock_replied(&mut self, endpoint: Connection, sample: ClockSample) {
+        let Some((offset, true)) =
+            self.participants
+                .clock_replied(&endpoint, sample, self.max_clock_offset)
+        else {
+            return;
+        };
+        let public = self
+            .participants
+            .get_pub_addr(&endpoint)
+            .unwrap_or(endpoint.addr());
+        let formatted_msg = format!(
+            "WARNING: the clock of \"{}\" is {} from ours, beyond {:?}; it is likely set wrong",
+            public, offset, self.max_clock_offset
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+
+    /// Describes the one-way delay of a text sent in `round`: the time since the round
+    /// started, on the clock of the sender when its offset is estimated.
+    ///
+    /// A participant in rounds broadcasts as its round starts, so the delay is that of the
+    /// network and of both event loops, and of the phase of a sender pulled along by a peer.
+    pub(super) fn round_delay(&self, endpoint: Connection, round: u64) -> String {
+        let period = u64::try_from(self.effective_period.as_micros()).unwrap_or(u64::MAX);
+        let started = i128::from(round.saturating_mul(period));
+        let elapsed = i128::from(skew::unix_micros(SystemTime::now())) - started;
+        let format = |micros: i128| format!("{:.3} ms", micros as f64 / 1000.0);
+        match self.participants.clock_offset(&endpoint) {
+            Some(offset) => format!(
+                "one-way delay {} corrected for a clock offset of {}",
+                format(elapsed + i128::from(offset.offset_us)),
+                skew::format_offset(offset.offset_us)
+            ),
+            None => format!("one-way delay {} uncorrected", format(elapsed)),
+        }
+    }
+
+    /// Records the effective period a participant declared in the trailer of a handshake frame,
+    /// and warns once per connection when it is too far from ours.
+    pub(super) fn period_declared(&mut self, endpoint: Connection, period: Duration) {
+        self.participants.set_declared_period(&endpoint, period);
+        let ours = self.effective_period;
+        if !timing::skewed(
+            ours,
+            period,
+            self.half_open_threshold,
+            self.period_skew_ratio,
+        ) || !self.participants.note_period_skew(&endpoint)
+        {
+            return;
+        }
+        let public = self
+            .participants
+            .get_pub_addr(&endpoint)
+            .unwrap_or(endpoint.addr());
+        let tolerated =
+            match timing::half_open_threshold(self.half_open_threshold, ours, Some(period)) {
+                0 => String::new(),
+                threshold => format!(
+                    "; its half-open threshold is scaled to {} messages",
+                    threshold
+                ),
+            };
+        let formatted_msg = format!(
+            "WARNING: \"{}\" declares a gossip period of {:?}, ours is {:?}{}",
+            public, period, ours, tolerated
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+}
@@ -0,0 +1,1192 @@
+//! Handling the messages peers send, from the first contact on.
+
+use crate::printer::print_event;
+
+use crate::participant::alert::AlertEvent;
+use crate::participant::anomaly::AnomalyKind;
+use crate::participant::compactlist::PackedList;
+use crate::participant::contact::MismatchReason;
+use crate::participant::descriptors::{self};
+use crate::participant::dials::{DialOutcome, DialTrigger};
+use crate::participant::echo::EchoVerdict;
+use crate::participant::error::ParticipantError;
+use crate::participant::event::InternalEvent;
+use crate::participant::exchange::{ListMatch, ListPurpose};
+use crate::participant::identity::NodeId;
+use crate::participant::interest::Interest;
+use crate::participant::message::builder::{validate_batch, MAX_LIST_LEN};
+use crate::participant::message::{
+    AgeBucket, Message, MessageKind, SharedPeer, COMPACT_LISTS_VERSION, LIST_EXCHANGES_VERSION,
+    SHARED_PEERS_VERSION,
+};
+use crate::participant::observer::Subscription;
+use crate::participant::payload::describe_payload;
+use crate::participant::policy::{inbound_policy, InboundPolicy, PeerState};
+use crate::participant::provenance::{LearnedVia, Provenance};
+use crate::participant::request::RequestError;
+use crate::participant::schema::Schema;
+use crate::participant::session::DepartureReason;
+use crate::participant::skew::{self, ClockSample};
+use crate::participant::snapshot::unix_seconds;
+use crate::participant::storage::{AddressUpdate, NodeIdUpdate, ParticipantsStorage};
+use crate::participant::timing;
+use crate::participant::transport::Transport;
+use crate::participant::utils::{
+    format_addrs_capped, send_message, CanonicalAddr, DEFAULT_ADDRS_CAP,
+};
+use crate::participant::warmup::DeferredDial;
+
+use crate::participant::transport::Connection;
+
+use std::net::SocketAddr;
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Handles an `ObserverHello` from `endpoint`.
+    ///
+    /// A new observer receives a snapshot of the current participants and statistics; an
+    /// observer over the cap receives `ObserverRejected` instead.
+    pub(super) fn observer_hello(&mut self, endpoint: Connection) {
+        let subscription = self.observers.subscribe(endpoint, Instant::now());
+
+        let msg = match subscription {
+            Subscription::New => {
+                let stats = self.observer_stats();
+                let peers = self
+                    .participants
+                    .get_participants_list(self.interfaces.primary(), None);
+                Message::ObserverSnapshot { peers, stats }
+            }
+            Subscription::Renewed => return,
+            Subscription::Rejected => Message::ObserverRejected,
+        };
+
+        send_message(&mut self.transport, &mut self.participants, endpoint, &msg);
+    }
+
+    /// Applies the outcome of a `PublicAddress` announcement.
+    ///
+    /// # Parameters
+    ///
+    /// - `pub_addr`: The announced public address.
+    /// - `update`: What the storage did with the announcement.
+    pub(super) fn address_announced(
+        &mut self,
+        pub_addr: SocketAddr,
+        update: AddressUpdate<Connection>,
+    ) {
+        match update {
+            AddressUpdate::Added => {
+                self.peer_cache.record(pub_addr);
+                self.record_membership_change(pub_addr, true, "handshake");
+            }
+            AddressUpdate::Unchanged => {}
+            AddressUpdate::Updated { previous } => {
+                let formatted_msg = format!("Participant \"{}\" is now \"{}\"", previous, pub_addr);
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.peer_cache.record(pub_addr);
+                self.record_membership_change(previous, false, "re-announced");
+                self.record_membership_change(pub_addr, true, "re-announced");
+            }
+            AddressUpdate::Conflict { previous, other } => {
+                let formatted_msg = format!(
+                    "Participant \"{}\" re-announced as \"{}\", already used by another connection to {}; keeping both",
+                    previous,
+                    pub_addr,
+                    other.addr()
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.record_membership_change(previous, false, "re-announced");
+                self.alerter.raise(
+                    AlertEvent::ConflictDetected,
+                    self.interfaces.primary(),
+                    vec![
+                        ("peer", previous.to_string()),
+                        ("address", pub_addr.to_string()),
+                        ("other", other.addr().to_string()),
+                    ],
+                    Instant::now(),
+                );
+            }
+        }
+    }
+
+    /// Records the persistent node id a participant presented in the trailer of a handshake
+    /// frame, and warns about a peer presenting the id of another connection or of this
+    /// participant.
+    ///
+    /// The id of this participant along with one of its own addresses is its own handshake
+    /// coming back, from a peer echoing frames or a connection to itself, which is closed.
+    pub(super) fn node_id_presented(
+        &mut self,
+        endpoint: Connection,
+        node_id: NodeId,
+        kind: MessageKind,
+    ) {
+        let Some(pub_addr) = self.participants.get_pub_addr(&endpoint) else {
+            return;
+        };
+        if node_id == self.identity.id && self.interfaces.is_own(pub_addr) {
+            let formatted_msg = format!(
+                "WARNING: \"{}\" sent our own handshake back",
+                endpoint.addr()
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.echoes.handshake_echoed();
+            self.anomaly(AnomalyKind::Echo, endpoint, || {
+                "our own handshake sent back".to_owned()
+            });
+            let state = self.peer_state(&endpoint);
+            return self.close_violating(endpoint, state, kind);
+        }
+        if node_id == self.identity.id {
+            let formatted_msg = format!(
+                "WARNING: \"{}\" presents the node id of this participant, {}; one of the identity files was probably copied",
+                pub_addr, node_id
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            self.node_id_conflict(pub_addr, node_id, self.interfaces.primary());
+            return;
+        }
+        if self.interfaces.is_own(pub_addr) {
+            self.hints.impostor(endpoint.addr(), pub_addr, node_id);
+        }
+        match self.participants.set_node_id(&endpoint, node_id) {
+            NodeIdUpdate::Unchanged | NodeIdUpdate::Added => {}
+            NodeIdUpdate::Moved { previous } => {
+                let formatted_msg = format!(
+                    "Participant \"{}\" is node {}, formerly {}",
+                    pub_addr,
+                    node_id,
+                    format_addrs_capped(&previous, DEFAULT_ADDRS_CAP)
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            NodeIdUpdate::Conflict { other_addr, .. } => {
+                let formatted_msg = format!(
+                    "WARNING: \"{}\" and \"{}\" both present node id {}; keeping both",
+                    pub_addr, other_addr, node_id
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.node_id_conflict(pub_addr, node_id, other_addr);
+            }
+        }
+    }
+
+    /// Raises the conflict alert for a node id presented from two addresses at once.
+    pub(super) fn node_id_conflict(
+        &mut self,
+        pub_addr: SocketAddr,
+        node_id: NodeId,
+        other: SocketAddr,
+    ) {
+        self.alerter.raise(
+            AlertEvent::ConflictDetected,
+            self.interfaces.primary(),
+            vec![
+                ("peer", pub_addr.to_string()),
+                ("node_id", node_id.to_string()),
+                ("other", other.to_string()),
+            ],
+            Instant::now(),
+        );
+    }
+
+    /// Handles incoming network messages directed to this participant.
+    ///
+    /// This method decodes and processes various types of messages that can be received from other
+    /// participants in the network. It acts on the message based on its type, performing actions
+    /// such as updating the participants list, responding with a list of known participants,
+    /// or logging received text messages.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` representing the source of the message. This is used
+    ///   for identifying the sender and potentially responding.
+    /// - `message`: The `Message` enum containing the message received. This enum encapsulates
+    ///   different types of messages that can be processed by this function.
+    ///
+    /// # Supported Message Types
+    ///
+    /// - `Message::PublicAddress`: Adds the sender's public address to the list of unknown participants
+    ///   if it is not already known.
+    /// - `Message::PushParticipantsList`: Responds to the sender with a list of known participant
+    ///   addresses, unless the sender exceeded its request rate.
+    /// - `Message::PullParticipantsList`: Updates the local list of participants with the addresses
+    ///   received in the message.
+    /// - `Message::SharedPeers`: Does the same with an age-tagged list, dialing the fresher
+    ///   addresses first.
+    /// - `Message::ListRequest`: Responds like `PushParticipantsList`, with a `ListReply`.
+    /// - `Message::ListReply`: Handled as a `SharedPeers` if it answers an outstanding request,
+    ///   dropped otherwise.
+    /// - `Message::CompactListReply`: Unpacked and handled as a `ListReply`.
+    /// - `Message::Text`: Logs a received text message along with the sender's address.
+    /// - `Message::ObserverHello`: Subscribes the sender as an observer.
+    /// - `Message::PleaseIntroduce`: Relays an introduction to the requested target.
+    /// - `Message::IntroductionRequest`: Dials back the participant that could not dial us.
+    /// - `Message::Topic`: Delivers the payload to the subscribers of its topic.
+    /// - `Message::VersionedTopic`: Delivers the payload to the subscribers of its topic and
+    ///   the schema handler of its version.
+    /// - `Message::SchemaUpdate`: Records the schemas the peer handles.
+    /// - `Message::Request`: Runs the handler of the request's topic and responds with its outcome.
+    /// - `Message::Response`: Completes the pending request it answers.
+    /// - `Message::Batch`: Handles each of its messages in order; a nested batch is a protocol
+    ///   violation.
+    ///
+    /// A `Message::PublicAddress` announcing a blocked address closes the connection instead.
+    ///
+    /// Before anything else, the message is checked against the `inbound_policy` of its
+    /// sender's `PeerState`: an ignored message is dropped uncounted, and a violation closes the
+    /// connection. In particular, messages from observers other than `ObserverHello` only reset
+    /// their idle timer, a half-open suspect's text and topic messages are dropped, and a muted
+    /// peer's payloads only count towards its muted drops.
+    ///
+    /// An overloaded participant then drops application payloads and requests, counted by
+    /// its `LoadShedder`, and only handles the protocol messages.
+    pub(super) fn network_messages(&mut self, message_sender: Connection, message: Message) {
+        let state = self.peer_state(&message_sender);
+        match inbound_policy(state, message.kind()) {
+            InboundPolicy::Accept => {}
+            InboundPolicy::Ignore => return,
+            InboundPolicy::Mute => {
+                self.participants.record_muted_drop(&message_sender);
+                self.participants
+                    .mark_verified(&message_sender, Instant::now());
+                return;
+            }
+            InboundPolicy::Violation => {
+                let kind = message.kind();
+                self.anomaly(AnomalyKind::Violation, message_sender, || {
+                    format!("a {} message from a {} peer", kind.name(), state)
+                });
+                return self.close_violating(message_sender, state, kind);
+            }
+        }
+
+        // An overloaded participant keeps up with the membership before the applications.
+        if self.load.state().sheds(message.kind()) {
+            self.load.record_shed();
+            return;
+        }
+
+        self.participants
+            .record_received_kind(&message_sender, message.kind());
+        self.participants
+            .mark_verified(&message_sender, Instant::now());
+
+        match message {
+            // A public address message contains the sender's address.
+            // This address is added to the list of participants if it is not already known.
+            Message::PublicAddress(pub_addr) => {
+                let banned = self.bans.is_banned(pub_addr, Instant::now());
+                if banned || self.filter.is_blocked(pub_addr) {
+                    let formatted_msg = format!(
+                        "Refusing the connection of {} \"{}\"",
+                        if banned { "banned" } else { "blocked" },
+                        pub_addr
+                    );
+                    print_event(self.time_start.clone(), &formatted_msg);
+                    ParticipantsStorage::drop(
+                        &mut self.participants,
+                        message_sender,
+                        DepartureReason::Blocked,
+                        Instant::now(),
+                    );
+                    self.transport.close(message_sender);
+                    return;
+                }
+
+                let rejoined = self
+                    .participants
+                    .connected_elsewhere(&message_sender, pub_addr);
+                let update =
+                    self.participants
+                        .update_in_place(message_sender, pub_addr, Instant::now());
+                if let Some(interface) = self.accepted_interfaces.remove(&message_sender) {
+                    // The address a peer that connected by itself announces is its own claim,
+                    // so it is corroborated like a listed one before it is dialed.
+                    self.participants.set_interface(&message_sender, interface);
+                    let provenance = self.first_sight(LearnedVia::Inbound);
+                    self.provenances
+                        .learned(pub_addr, provenance, Instant::now());
+                }
+                if update == AddressUpdate::Added {
+                    // The announcement arrived before its sender was stored, so it counts now.
+                    self.participants
+                        .record_received_kind(&message_sender, MessageKind::PublicAddress);
+                    self.resolve_crossed(message_sender, pub_addr);
+                }
+                // A second connection of a connected peer, such as its dial back, is no join.
+                if rejoined && update == AddressUpdate::Added {
+                    self.peer_cache.record(pub_addr);
+                } else {
+                    self.address_announced(pub_addr, update);
+                }
+            }
+
+            // A request to push the participants list triggers a response with the known participant
+            // addresses. This helps newly joined participants to learn about existing ones.
+            Message::PushParticipantsList => self.answer_list_request(message_sender, None),
+            Message::ListRequest { id } => self.answer_list_request(message_sender, Some(id)),
+
+            // When a list of participants is received, update the local storage to include any new
+            // addresses. This ensures the participant is aware of other peers in the network.
+            // Peers of older versions share bare addresses, of no known age.
+            Message::PullParticipantsList(addrs) => {
+                self.list_answered(message_sender, None);
+                let peers = addrs
+                    .into_iter()
+                    .map(|addr| SharedPeer {
+                        addr,
+                        age: AgeBucket::Recent,
+                    })
+                    .collect();
+                self.pull_participants_list(message_sender, peers, None)
+            }
+
+            Message::SharedPeers(peers) => {
+                self.list_answered(message_sender, None);
+                self.pull_participants_list(message_sender, peers, None)
+            }
+
+            // A reply to a request that is not outstanding is dropped, see `exchange`.
+            Message::ListReply { id, peers } => {
+                if self.list_answered(message_sender, Some(id)) {
+                    self.pull_participants_list(message_sender, peers, Some(id))
+                }
+            }
+
+            // A list that does not unpack is dropped like a frame that does not decode.
+            Message::CompactListReply { id, list } => {
+                if !self.list_answered(message_sender, Some(id)) {
+                    return;
+                }
+                match list.unpack() {
+                    Ok(peers) => self.pull_participants_list(message_sender, peers, Some(id)),
+                    Err(err) => {
+                        let formatted_msg = format!(
+                            "Dropping a packed participants list from \"{}\" that does not unpack: {}",
+                            message_sender.addr(),
+                            err
+                        );
+                        print_event(self.time_start.clone(), &formatted_msg);
+                        self.anomaly(AnomalyKind::Undecodable, message_sender, || {
+                            format!("a packed participants list: {}", err)
+                        })
+                    }
+                }
+            }
+
+            // For text messages, log the received message along with the sender's address.
+            // This is useful for debugging and monitoring the flow of messages.
+            Message::Text(text) => {
+                let Some(pub_addr) = self.participants.get_pub_addr(&message_sender) else {
+                    return;
+                };
+                if self.echoed(message_sender, pub_addr, &text, MessageKind::Text) {
+                    return;
+                }
+
+                let formatted_msg = format!(
+                    "Received message [{}] from \"{}\"",
+                    describe_payload(&text),
+                    &pub_addr
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.deliver_to_inboxes(pub_addr, None, text.into_bytes());
+            }
+
+            // A text sent in a round also tells how far the sender's rounds went.
+            Message::RoundText { round, text } => {
+                let Some(pub_addr) = self.participants.get_pub_addr(&message_sender) else {
+                    return;
+                };
+                if self.echoed(message_sender, pub_addr, &text, MessageKind::RoundText) {
+                    return;
+                }
+                if let Some(clock) = &mut self.rounds {
+                    clock.observe(
+                        round,
+                        self.effective_period,
+                        Instant::now(),
+                        SystemTime::now(),
+                    );
+                }
+
+                let formatted_msg = format!(
+                    "Received message [{}] in round {} from \"{}\", {}",
+                    describe_payload(&text),
+                    round,
+                    &pub_addr,
+                    self.round_delay(message_sender, round)
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                self.deliver_to_inboxes(pub_addr, None, text.into_bytes());
+            }
+
+            // An observer subscribes instead of announcing a public address.
+            Message::ObserverHello => self.observer_hello(message_sender),
+
+            // A neighbor that can not reach us directly asks us to make the target dial it.
+            Message::PleaseIntroduce { target, my_addr } => {
+                self.please_introduce(message_sender, target, my_addr)
+            }
+
+            // Someone could not dial us: dial it instead.
+            Message::IntroductionRequest { from } => {
+                self.introduction_request(message_sender, from)
+            }
+
+            // Topic messages go to the subscribers, unless the allowlist drops them.
+            Message::Topic { topic, payload } => {
+                self.deliver_topic(message_sender, &topic, None, None, payload)
+            }
+            Message::SequencedTopic {
+                topic,
+                seq,
+                payload,
+            } => self.deliver_topic(message_sender, &topic, Some(seq), None, payload),
+            Message::VersionedTopic {
+                topic,
+                seq,
+                schema,
+                version,
+                payload,
+            } => {
+                let schema = Some(Schema::new(schema, version));
+                self.deliver_topic(message_sender, &topic, Some(seq), schema, payload)
+            }
+
+            // A request is answered by the handler of its topic, on a worker thread.
+            Message::Request { id, topic, payload } => {
+                self.answer_request(message_sender, id, topic, payload)
+            }
+
+            // The messages of a batch are handled in order, each as if it had arrived alone.
+            Message::Batch(messages) => {
+                if validate_batch(&messages).is_err() {
+                    self.anomaly(AnomalyKind::Violation, message_sender, || {
+                        "a batch nesting another batch".to_owned()
+                    });
+                    return self.close_violating(message_sender, state, MessageKind::Batch);
+                }
+                for inner in messages {
+                    self.network_messages(message_sender, inner);
+                }
+            }
+
+            // A probe is echoed at once, ahead of any batched message.
+            Message::Probe { id, .. } => {
+                let uptime = self.time_start.elapsed().as_micros();
+                let echoed_at_offset = u64::try_from(uptime).unwrap_or(u64::MAX);
+                self.send(
+                    message_sender,
+                    Message::ProbeReply {
+                        id,
+                        echoed_at_offset,
+                    },
+                );
+            }
+
+            // A reply completes its probe, unless the probe timed out or belongs to another run.
+            Message::ProbeReply { id, .. } => {
+                let now = Instant::now();
+                let done = self.probe.as_mut().is_some_and(|(endpoint, session)| {
+                    *endpoint == message_sender && session.reply(id, now) && session.is_done()
+                });
+                if done {
+                    self.finish_probe();
+                }
+            }
+
+            // A clock request is answered at once, with the time it was received and answered.
+            Message::ClockRequest { sent_at } => {
+                let received_at = skew::unix_micros(SystemTime::now());
+                self.send(
+                    message_sender,
+                    Message::ClockReply {
+                        sent_at,
+                        received_at,
+                        replied_at: skew::unix_micros(SystemTime::now()),
+                    },
+                );
+            }
+
+            // A clock reply completes a sample of the offset of the peer's clock.
+            Message::ClockReply {
+                sent_at,
+                received_at,
+                replied_at,
+            } => {
+                let sample = ClockSample {
+                    sent: sent_at,
+                    received: received_at,
+                    replied: replied_at,
+                    arrived: skew::unix_micros(SystemTime::now()),
+                };
+                self.clock_replied(message_sender, sample);
+            }
+
+            // An interest update replaces the topics the peer was sent so far.
+            Message::InterestUpdate(topics) => {
+                self.participants
+                    .set_interest(&message_sender, Interest::from_update(topics));
+            }
+
+            // A schema update replaces the schemas the peer announced so far.
+            Message::SchemaUpdate(schemas) => {
+                if let Some(pub_addr) = self.participants.get_pub_addr(&message_sender) {
+                    self.schema_census.announced(pub_addr, schemas);
+                }
+            }
+
+            // A response completes the pending request with its id, unless it timed out.
+            Message::Response { id, payload } => {
+                self.pending_requests.complete(
+                    id,
+                    &message_sender,
+                    payload.map_err(RequestError::Remote),
+                );
+            }
+
+            // Observer messages are meant for observers; the inbound policy closes the
+            // connection of any peer sending them before they get here.
+            Message::ObserverSnapshot { .. }
+            | Message::ObserverUpdate { .. }
+            | Message::ObserverRejected => {}
+        }
+    }
+
+    /// Returns the state of the peer behind `endpoint`, as seen by the receive path.
+    ///
+    /// An observer is also kept alive by the lookup, whatever it sent.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` a message was received from.
+    pub(super) fn peer_state(&mut self, endpoint: &Connection) -> PeerState {
+        if self.observers.touch(endpoint, Instant::now()) {
+            return PeerState::Observer;
+        }
+        if self.participants.is_connecting(endpoint) {
+            return PeerState::Connecting;
+        }
+
+        let threshold = timing::half_open_threshold(
+            self.half_open_threshold,
+            self.effective_period,
+            self.participants.declared_period(endpoint),
+        );
+        match self.participants.sends_since_receive(endpoint) {
+            None => PeerState::Unannounced,
+            Some(unanswered) if threshold > 0 && unanswered > threshold => PeerState::Suspect,
+            Some(_) if self.participants.is_muted(endpoint, Instant::now()) => PeerState::Muted,
+            Some(_) => PeerState::Established,
+        }
+    }
+
+    /// Marks a connection as verified once its first frame passed `validate_first_contact`.
+    ///
+    /// A dialed peer is only remembered in the recent peers cache from then on, so a restart
+    /// never rejoins through a peer speaking another protocol.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the peer.
+    pub(super) fn first_contact(&mut self, endpoint: Connection) {
+        self.contacts.forget(&endpoint);
+        // The peer stored the outbound connection of a handover before answering through it.
+        if let Some((public, inbound)) = self
+            .balancer
+            .as_mut()
+            .and_then(|balancer| balancer.answered(&endpoint))
+        {
+            self.transport.close(inbound);
+            let formatted_msg = format!("Handed \"{}\" over to an outbound connection", public);
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        if self.participants.never_answered(&endpoint) {
+            self.peer_cache.record(endpoint.addr());
+        }
+        self.check_join();
+    }
+
+    /// Closes the connection of a peer whose first frame does not belong to this protocol,
+    /// without handling the frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the peer.
+    /// - `reason`: What the first frame got wrong.
+    pub(super) fn protocol_mismatch(&mut self, endpoint: Connection, reason: MismatchReason) {
+        let formatted_msg = format!(
+            "Peer at {} does not appear to speak this protocol version ({}), closing the connection",
+            endpoint.addr(),
+            reason
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+        self.anomaly(AnomalyKind::ProtocolMismatch, endpoint, || {
+            reason.to_string()
+        });
+        self.close_unverified(endpoint, DepartureReason::ProtocolMismatch);
+    }
+
+    /// Closes a connection whose first frame never arrived, showed another protocol or
+    /// another bootstrap target.
+    ///
+    /// Nothing was learned from such a peer, so only the entry of a dial is dropped.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the peer.
+    /// - `reason`: Why the connection is closed.
+    pub(super) fn close_unverified(&mut self, endpoint: Connection, reason: DepartureReason) {
+        self.contacts.forget(&endpoint);
+        self.accepted_interfaces.remove(&endpoint);
+        self.outbox.discard(&endpoint);
+        self.close_handover_orphan(&endpoint);
+        ParticipantsStorage::drop(&mut self.participants, endpoint, reason, Instant::now());
+        self.transport.close(endpoint);
+        if self.bootstrap_check.closed(&endpoint) {
+            self.dial_next_bootstrap();
+        }
+    }
+
+    /// Checks whether a gossip text received from `endpoint` echoes one this participant sent
+    /// it, warning at the first echo of a row and closing the connection after
+    /// `ECHO_STRIKES` in a row.
+    ///
+    /// # Returns
+    ///
+    /// `true` when the text is an echo, to be dropped.
+    pub(super) fn echoed(
+        &mut self,
+        endpoint: Connection,
+        pub_addr: SocketAddr,
+        text: &str,
+        kind: MessageKind,
+    ) -> bool {
+        let verdict = self.echoes.received(&endpoint, text, Instant::now());
+        if verdict != EchoVerdict::Genuine {
+            self.anomaly(AnomalyKind::Echo, endpoint, || {
+                format!("our own {} message sent back", kind.name())
+            });
+        }
+        match verdict {
+            EchoVerdict::Genuine => false,
+            EchoVerdict::Echo { first } => {
+                if first {
+                    let formatted_msg = format!(
+                        "WARNING: \"{}\" sent our own message back, dropping its echoes",
+                        pub_addr
+                    );
+                    print_event(self.time_start.clone(), &formatted_msg);
+                }
+                true
+            }
+            EchoVerdict::Violation => {
+                let state = self.peer_state(&endpoint);
+                self.close_violating(endpoint, state, kind);
+                true
+            }
+        }
+    }
+
+    /// Counts a protocol anomaly caused by the peer behind `endpoint`, see `anomaly`.
+    ///
+    /// The first one of a strict participant stops it, failing with
+    /// `ParticipantError::Anomaly`; the caller still recovers from it meanwhile.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: What went wrong.
+    /// - `endpoint`: The `Connection` of the peer at fault.
+    /// - `context`: Builds what the anomaly was about, only when it is escalated.
+    pub(super) fn anomaly(
+        &mut self,
+        kind: AnomalyKind,
+        endpoint: Connection,
+        context: impl FnOnce() -> String,
+    ) {
+        let peer = self
+            .participants
+            .get_pub_addr(&endpoint)
+            .unwrap_or(endpoint.addr());
+        let Some(anomaly) = self.anomalies.record(kind, peer, context) else {
+            return;
+        };
+        let formatted_msg = format!("Strict protocol: stopping on {}", anomaly);
+        print_event(self.time_start.clone(), &formatted_msg);
+        self.failure = Some(ParticipantError::Anomaly(anomaly));
+        self.node_handler.signals().send(InternalEvent::Shutdown);
+    }
+
+    /// Closes the connection of a peer that sent a message its state does not allow.
+    ///
+    /// # Parameters
+    ///
+    /// - `endpoint`: The `Connection` of the offending peer.
+    /// - `state`: The state of the peer when the message arrived.
+    /// - `kind`: The kind of the offending message.
+    pub(super) fn close_violating(
+        &mut self,
+        endpoint: Connection,
+        state: PeerState,
+        kind: MessageKind,
+    ) {
+        let formatted_msg = format!(
+            "Closing the connection of {} \"{}\" after an unexpected {} message",
+            state,
+            endpoint.addr(),
+            kind.name()
+        );
+        print_event(self.time_start.clone(), &formatted_msg);
+
+        self.observers.remove(&endpoint);
+        self.push_list_limiter.forget(&endpoint);
+        self.echoes.forget(&endpoint);
+        self.list_exchanges.forget(&endpoint);
+        let pub_addr = self.participants.get_pub_addr(&endpoint);
+        if let Some(pub_addr) = pub_addr {
+            self.record_membership_change(pub_addr, false, "protocol violation");
+        }
+        self.alerter.raise(
+            AlertEvent::PeerEvicted,
+            self.interfaces.primary(),
+            vec![
+                ("peer", pub_addr.unwrap_or(endpoint.addr()).to_string()),
+                ("state", state.to_string()),
+                ("message", kind.name().to_owned()),
+            ],
+            Instant::now(),
+        );
+        ParticipantsStorage::drop(
+            &mut self.participants,
+            endpoint,
+            DepartureReason::Violation,
+            Instant::now(),
+        );
+        self.transport.close(endpoint);
+    }
+
+    /// Relays a `PleaseIntroduce` from `message_sender` to `target`.
+    ///
+    /// The introduction is refused when either side is blocked, when `my_addr` is not the
+    /// public address the sender announced, when `target` is not connected, or when the same
+    /// pair was introduced within the last `INTRODUCTION_PAIR_TTL`. A peer can thus only have
+    /// itself introduced, never send `target` to dial a third host.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` of the participant asking for the introduction.
+    /// - `target`: The public address of the participant that should dial back.
+    /// - `my_addr`: The public address `target` should dial.
+    pub(super) fn please_introduce(
+        &mut self,
+        message_sender: Connection,
+        target: SocketAddr,
+        my_addr: SocketAddr,
+    ) {
+        let now = Instant::now();
+        let refused = |addr| self.filter.is_blocked(addr) || self.bans.is_banned(addr, now);
+        if refused(target) || refused(my_addr) {
+            return;
+        }
+
+        let participants = &mut self.participants;
+        let Some(sender_addr) = participants.get_pub_addr(&message_sender) else {
+            return;
+        };
+        if CanonicalAddr::from(sender_addr) != CanonicalAddr::from(my_addr) {
+            return;
+        }
+        let Some(target_endpoint) = participants.endpoint_of(target) else {
+            return;
+        };
+
+        let introduced_pairs = &mut self.introduced_pairs;
+        let pair = (my_addr.into(), target.into());
+        if introduced_pairs.contains(&pair, now) {
+            return;
+        }
+        introduced_pairs.insert(pair, (), now);
+
+        let formatted_msg = format!("Introducing \"{}\" to \"{}\"", my_addr, target);
+        print_event(self.time_start.clone(), &formatted_msg);
+
+        send_message(
+            &mut self.transport,
+            participants,
+            target_endpoint,
+            &Message::IntroductionRequest { from: my_addr },
+        );
+    }
+
+    /// Dials `from` on behalf of the neighbor that relayed its introduction.
+    ///
+    /// The dial records no list origin, so its failure never turns into a new introduction
+    /// request and the exchange can not loop. The neighbor vouches for `from` as a list
+    /// would, so with a corroboration above one the address waits in `self.candidates`.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` of the neighbor that relayed the introduction.
+    /// - `from`: The public address of the participant to dial.
+    pub(super) fn introduction_request(&mut self, message_sender: Connection, from: SocketAddr) {
+        let participants = &mut self.participants;
+        let Some(neighbor) = participants.get_pub_addr(&message_sender) else {
+            return;
+        };
+        let now = Instant::now();
+        let trigger = DialTrigger::Introduction;
+        if self.interfaces.is_own(from) || participants.contains_addr(from) {
+            return;
+        }
+        if self.filter.is_blocked(from) || self.bans.is_banned(from, now) {
+            self.dials
+                .settled(from, trigger, DialOutcome::Filtered, now);
+            return;
+        }
+        if let Err(violation) = self.dial_hygiene.check(from) {
+            self.dials
+                .settled(from, trigger, DialOutcome::HygieneRejected, now);
+            self.hygiene_log.record(neighbor, &[violation], now);
+            return;
+        }
+        if !self.candidates.report(from, message_sender.addr(), now) {
+            self.dials
+                .settled(from, trigger, DialOutcome::CorroborationPending, now);
+            return;
+        }
+
+        let provenance = Provenance::new(
+            LearnedVia::Introduction { via: neighbor },
+            unix_seconds(SystemTime::now()),
+            now.saturating_duration_since(*self.time_start),
+        );
+        self.provenances.learned(from, provenance, now);
+        self.list_origins.remove(&from.into());
+        let connection = self.transport.dial(from);
+        if connection.as_ref().is_err_and(descriptors::is_exhausted) {
+            self.dials
+                .settled(from, trigger, DialOutcome::Deferred, now);
+            self.dial_backlog.defer(DeferredDial {
+                addr: from,
+                age: AgeBucket::Fresh,
+                interface: participants.interface(&message_sender),
+                origin: None,
+                trigger,
+            });
+            return;
+        }
+        self.recent_dials.insert(from.into(), (), now);
+        match connection {
+            Ok((endpoint, _)) => {
+                participants.add_connecting_participant(endpoint, now, 0);
+                let interface = participants.interface(&message_sender);
+                participants.set_interface(&endpoint, interface);
+                self.dials.started(endpoint, from, trigger, now);
+                let formatted_msg = format!(
+                    "Dialing \"{}\" on the introduction of \"{}\"",
+                    from, neighbor
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+            Err(_) => {
+                self.dials.settled(from, trigger, DialOutcome::Failed, now);
+                self.dial_refused(from)
+            }
+        }
+    }
+
+    /// Answers a request for the participants list, with a `ListReply` echoing `id` when the
+    /// request carried one, packed into a `CompactListReply` for a peer speaking
+    /// `COMPACT_LISTS_VERSION`.
+    ///
+    /// Only the participants of the requester's interface are shared, since those of another
+    /// network may not be reachable from it. A peer that connected by itself was never asked
+    /// for its list, which the split-brain detection needs. It is asked once answered, as its
+    /// first contact has to be the list.
+    pub(super) fn answer_list_request(&mut self, message_sender: Connection, id: Option<u64>) {
+        let allowed = self.push_list_limiter.allow(message_sender, Instant::now());
+        if !allowed {
+            return;
+        }
+
+        let interface = self.participants.interface(&message_sender);
+        let peers = self.participants.shared_peers(
+            self.interfaces.advertise(interface),
+            Instant::now(),
+            self.share_limit.min(MAX_LIST_LEN),
+            Some(interface),
+        );
+        let msg = match id {
+            Some(id)
+                if self.participants.protocol_version(&message_sender) >= COMPACT_LISTS_VERSION =>
+            {
+                let msg = Message::CompactListReply {
+                    id,
+                    list: PackedList::pack(&peers),
+                };
+                let plain = Message::ListReply { id, peers }.encode().len();
+                self.packing.record(msg.encode().len(), plain);
+                msg
+            }
+            Some(id) => Message::ListReply { id, peers },
+            None if self.participants.protocol_version(&message_sender) >= SHARED_PEERS_VERSION => {
+                Message::SharedPeers(peers)
+            }
+            None => {
+                Message::PullParticipantsList(peers.into_iter().map(|peer| peer.addr).collect())
+            }
+        };
+        self.send(message_sender, msg);
+
+        let lister = self
+            .participants
+            .get_pub_addr(&message_sender)
+            .unwrap_or(message_sender.addr());
+        if !self.peer_lists.contains(lister) {
+            self.request_list(message_sender, ListPurpose::SplitCheck);
+        }
+    }
+
+    /// Asks `endpoint` for its participants list, with an id its answer echoes if it speaks
+    /// `LIST_EXCHANGES_VERSION`.
+    pub(super) fn request_list(&mut self, endpoint: Connection, purpose: ListPurpose) {
+        let correlated = self.participants.protocol_version(&endpoint) >= LIST_EXCHANGES_VERSION;
+        let msg = match self
+            .list_exchanges
+            .request(endpoint, purpose, correlated, Instant::now())
+        {
+            Some(id) => Message::ListRequest { id },
+            None => Message::PushParticipantsList,
+        };
+        self.send(endpoint, msg);
+    }
+
+    /// Matches a participants list received from `endpoint` to the request it answers, `id`
+    /// for a `ListReply`.
+    ///
+    /// # Returns
+    ///
+    /// `false` for a reply to no outstanding request, which is dropped.
+    pub(super) fn list_answered(&mut self, endpoint: Connection, id: Option<u64>) -> bool {
+        match self.list_exchanges.answered(&endpoint, id, Instant::now()) {
+            ListMatch::Unknown => {
+                let formatted_msg = format!(
+                    "Dropped a participants list from \"{}\" answering no outstanding request",
+                    self.participants
+                        .get_pub_addr(&endpoint)
+                        .unwrap_or(endpoint.addr())
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+                false
+            }
+            ListMatch::Answered { .. } | ListMatch::Unsolicited => true,
+        }
+    }
+
+    /// Attempts to connect to a list of participant addresses received from another participant.
+    ///
+    /// This method iteratively checks each received address against the current list of known
+    /// participants. If the address is not known and is not the address of this participant or
+    /// the message sender, it attempts to establish a new connection. The sender lists itself
+    /// first, under the address it advertises, which is recorded so neither this nor the
+    /// address it was dialed at is dialed again. Successful new connections
+    /// result in the address being added to the list of known participants. Addresses dialed
+    /// within the last `--dial-memory` are skipped, so several lists naming the same peer do
+    /// not trigger several dials. The sender is remembered as the origin of every dialed
+    /// address, so it can be asked for an introduction if the dial fails. With a corroboration
+    /// above one, an untrusted address is only dialed once enough distinct peers listed it;
+    /// until then it waits in `self.candidates`.
+    ///
+    /// Fresher addresses are dialed first, and a participant dialed from a stale address starts
+    /// half-way to the half-open threshold, so it is given up sooner if it never answers.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` of the participant that sent this list of addresses.
+    ///   This is used to avoid trying to reconnect to the sender or to self.
+    /// - `peers`: The addresses of potential new participants to connect to, with the sender's
+    ///   idea of how recently each was verified.
+    /// - `request`: The id of the list request this list answers, if it carried one, kept in
+    ///   the provenance of the addresses first learned from it.
+    ///
+    /// # Behavior
+    ///
+    /// For each address in `peers` that is not already a known participant, this function tries
+    /// to establish a new connection. If at least one new connection is successfully established,
+    /// a message is logged indicating the successful connection to new participants.
+    ///
+    /// This approach allows the network to self-organize and expand as new participants join
+    /// and share their lists of known connections.
+    ///
+    /// # Errors
+    ///
+    /// Connection attempts that fail will not stop the method from attempting to connect to the
+    /// next address in the list. Each failure is logged with a message indicating the address
+    /// of the failed connection attempt.
+    pub(super) fn pull_participants_list(
+        &mut self,
+        message_sender: Connection,
+        mut peers: Vec<SharedPeer>,
+        request: Option<u64>,
+    ) {
+        if let Some(advertised) = peers.first() {
+            self.participants
+                .set_advertised(&message_sender, advertised.addr);
+        }
+        let lister = self
+            .participants
+            .get_pub_addr(&message_sender)
+            .unwrap_or(message_sender.addr());
+        self.peer_lists.record(
+            lister,
+            peers.iter().map(|peer| peer.addr).collect(),
+            Instant::now(),
+        );
+        peers.sort_by_key(|peer| peer.age);
+
+        let transport = &mut self.transport;
+        let participants = &mut self.participants;
+        let recent_dials = &mut self.recent_dials;
+        let list_origins = &mut self.list_origins;
+        let candidates = &mut self.candidates;
+        let dial_backlog = &mut self.dial_backlog;
+        let dials = &mut self.dials;
+        let trigger = DialTrigger::Learned;
+        let origin = participants.get_pub_addr(&message_sender);
+        let reporter = origin.unwrap_or(message_sender.addr());
+        let interface = participants.interface(&message_sender);
+        let now = Instant::now();
+        let first_sight = Provenance::new(
+            LearnedVia::List {
+                lister: reporter,
+                request,
+            },
+            unix_seconds(SystemTime::now()),
+            now.saturating_duration_since(*self.time_start),
+        );
+
+        // Track whether any new connections have been made to log this event later.
+        let mut new_connections = false;
+        let mut refused = Vec::new();
+
+        // Iterate through each received participant address.
+        for &SharedPeer {
+            addr: participant_address,
+            age,
+        } in peers.iter()
+        {
+            // Every address listed counts toward its provenance, even one not dialed.
+            if self.interfaces.is_own(participant_address)
+                || participants.is_address_of(&message_sender, participant_address)
+            {
+                continue;
+            }
+            self.provenances
+                .listed(participant_address, first_sight.clone(), reporter, now);
+            // Skip the address if it is already known, and refuse it if it points where no
+            // peer should be.
+            if participants.contains_addr(participant_address) {
+                continue;
+            }
+            if let Err(violation) = self.dial_hygiene.check(participant_address) {
+                dials.settled(
+                    participant_address,
+                    trigger,
+                    DialOutcome::HygieneRejected,
+                    now,
+                );
+                refused.push(violation);
+                continue;
+            }
+            if self.filter.is_blocked(participant_address)
+                || self.bans.is_banned(participant_address, now)
+            {
+                dials.settled(participant_address, trigger, DialOutcome::Filtered, now);
+                continue;
+            }
+            if recent_dials.contains(&participant_address.into(), now) {
+                continue;
+            }
+            if !candidates.report(participant_address, message_sender.addr(), now) {
+                dials.settled(
+                    participant_address,
+                    trigger,
+                    DialOutcome::CorroborationPending,
+                    now,
+                );
+                continue;
+            }
+            // A warming participant dials at its own pace and queues the rest.
+            let dial = DeferredDial {
+                addr: participant_address,
+                age,
+                interface,
+                origin,
+                trigger,
+            };
+            if let Some(warmup) = &mut self.warmup {
+                if !warmup.admit(dial, now) {
+                    dials.settled(participant_address, trigger, DialOutcome::Deferred, now);
+                    continue;
+                }
+            }
+
+            // Attempt to connect to the new participant address, or put the dial off
+            // until a file descriptor is free.
+            let dialed = transport.dial(participant_address);
+            if dialed.as_ref().is_err_and(descriptors::is_exhausted) {
+                dials.settled(participant_address, trigger, DialOutcome::Deferred, now);
+                dial_backlog.defer(dial);
+                continue;
+            }
+            recent_dials.insert(participant_address.into(), (), now);
+            if let Some(origin) = origin {
+                list_origins.insert(participant_address.into(), origin, now);
+            }
+            match dialed {
+                Ok((endpoint, _)) => {
+                    // If the dial started, store the endpoint as connecting until the
+                    // connection is established.
+                    let suspicion = if age == AgeBucket::Stale {
+                        self.half_open_threshold / 2
+                    } else {
+                        0
+                    };
+                    participants.add_connecting_participant(endpoint, now, suspicion);
+                    participants.set_interface(&endpoint, interface);
+                    dials.started(endpoint, participant_address, trigger, now);
+                    new_connections = true;
+                }
+                Err(_) => {
+                    dials.settled(participant_address, trigger, DialOutcome::Failed, now);
+                    let formatted_msg = format!("Failed to connect to \"{}\"", participant_address);
+                    print_event(self.time_start.clone(), &formatted_msg);
+                }
+            }
+        }
+
+        self.hygiene_log.record(reporter, &refused, now);
+
+        // If any new connections were made, log an event with the list of newly connected addresses.
+        if new_connections {
+            let formatted_msg = format!(
+                "Connected to new participants: {}",
+                format_addrs_capped(
+                    &peers
+                        .iter()
+                        .map(|peer| peer.addr)
+                        .filter(|&addr| !self.interfaces.is_own(addr))
+                        .collect::<Vec<_>>(),
+                    DEFAULT_ADDRS_CAP
+                )
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! The state file, the report and the join check of a participant.
+
+use crate::printer::{print_event, print_line};
+
+use crate::participant::csv;
+use crate::participant::error::ParticipantError;
+use crate::participant::event::InternalEvent;
+use crate::participant::join::JoinResult;
+use crate::participant::message::{MembershipChange, ObserverStats, PROTOCOL_VERSION};
+use crate::participant::provenance::{LearnedVia, Provenance};
+use crate::participant::record::MembershipRecord;
+use crate::participant::report::{Report, ReportedPeer};
+use crate::participant::retention::RetainedKind;
+use crate::participant::snapshot::unix_seconds;
+use crate::participant::state::NodeState;
+use crate::participant::storage::PeerSummary;
+use crate::participant::utils::CanonicalAddr;
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Arms the timer of the next periodic save of the state file, with
+    /// `--state-save-interval`.
+    ///
+    /// A save due further away than an `Instant` can tell never comes, rather than overflowing
+    /// the timer.
+    pub(super) fn arm_state_save(&self) {
+        let Some(interval) = self.state_save_interval else {
+            return;
+        };
+        if Instant::now().checked_add(interval).is_some() {
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::SaveState, interval);
+        }
+    }
+
+    /// Saves the peers and the topic sequence numbers to the state file, if one is set.
+    ///
+    /// A failed save is logged: the previous state stays in place, and the participant keeps
+    /// running.
+    pub(super) fn save_state(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let now = SystemTime::now();
+        let mut topic_sequences: Vec<(String, u64)> = self
+            .topic_sequences
+            .iter()
+            .map(|(topic, seq)| (topic.clone(), *seq))
+            .collect();
+        topic_sequences.sort();
+        let state = NodeState {
+            saved_at: unix_seconds(now),
+            node: self.interfaces.primary(),
+            topic_sequences,
+            peers: self
+                .participants
+                .remembered_peers(Instant::now(), now)
+                .into_iter()
+                .filter(|peer| !self.interfaces.is_own(peer.addr))
+                .map(|mut peer| {
+                    peer.provenance = self.provenances.get(peer.addr).cloned();
+                    peer
+                })
+                .collect(),
+            scheduled: self.schedule.saved(),
+        };
+        if let Err(err) = state.save(path) {
+            let formatted_msg = format!(
+                "Can not save the state file \"{}\": {}",
+                path.display(),
+                err
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+    }
+
+    /// Gathers the report of the run so far.
+    pub(super) fn report(&self) -> Report {
+        let now = Instant::now();
+        let (sent, received) = self.participants.totals();
+        Report {
+            node: self.interfaces.primary(),
+            node_id: self.identity.id,
+            protocol_version: PROTOCOL_VERSION,
+            config_digest: self.config_digest,
+            uptime: now.saturating_duration_since(*self.time_start),
+            peers: self
+                .peer_summaries(now)
+                .into_iter()
+                .map(|summary| ReportedPeer {
+                    addr: summary.public,
+                    node_id: summary.node_id,
+                    former_addrs: summary.former_addrs,
+                    sessions: summary.sessions.total,
+                    connected_for: summary.sessions.current,
+                    verified_ago: summary.verified_ago,
+                    direction: summary.direction,
+                    clock_offset: summary.clock_offset,
+                    provenance: summary.provenance,
+                })
+                .collect(),
+            sent,
+            received,
+            ordered_gaps: self.topics.gaps(),
+            ordered_duplicates: self.topics.duplicates(),
+            departures: self.participants.departures(),
+            dedup_evictions: self.recent_dials.evictions(),
+            ratelimit_evictions: self.push_list_limiter.evictions(),
+            peer_set: self.peer_set,
+            bans: self.bans.list(now),
+            retention: RetainedKind::ALL.map(|kind| self.retention.usage(kind)),
+            scheduled: self.schedule.pending(),
+            anomalies: self.anomalies.counts(),
+            dial_outcomes: self.dials.by_outcome(),
+            dial_triggers: self.dials.by_trigger(),
+        }
+    }
+
+    /// Writes the report of the run to the report file, if one is set, in the JSON report, the
+    /// peer table or both, as `--report-format` asks.
+    pub(super) fn write_report(&self) {
+        let Some(path) = &self.report_file else {
+            return;
+        };
+        let (json, table) = self.report_format.paths(path);
+        let written = [
+            json.map(|path| (self.report().save(&path), path)),
+            table.map(|path| (csv::save(&path, &self.peers_table()), path)),
+        ];
+        for (result, path) in written.into_iter().flatten() {
+            if let Err(err) = result {
+                let formatted_msg = format!(
+                    "Can not write the report file \"{}\": {}",
+                    path.display(),
+                    err
+                );
+                print_event(self.time_start.clone(), &formatted_msg);
+            }
+        }
+    }
+
+    /// Returns the CSV table of the known peers, see `csv`.
+    pub(super) fn peers_table(&self) -> String {
+        csv::peers_table(&self.peer_summaries(Instant::now()))
+    }
+
+    /// Returns the summaries of the known peers, with the provenance of their addresses.
+    pub(super) fn peer_summaries(&self, now: Instant) -> Vec<PeerSummary> {
+        let mut summaries = self.participants.summaries(now);
+        for summary in &mut summaries {
+            summary.provenance = self.provenances.get(summary.public).cloned();
+        }
+        summaries
+    }
+
+    /// Returns the record of an address learned from `source` now.
+    pub(super) fn first_sight(&self, source: LearnedVia) -> Provenance {
+        Provenance::new(
+            source,
+            unix_seconds(SystemTime::now()),
+            self.time_start.elapsed(),
+        )
+    }
+
+    /// Returns the aggregate counters reported to observers.
+    pub(super) fn observer_stats(&self) -> ObserverStats {
+        let (sent, received) = self.participants.totals();
+        ObserverStats {
+            peers: self.participants.count() as u32,
+            sent: sent.to_vec(),
+            received: received.to_vec(),
+        }
+    }
+
+    /// Queues a membership change for the observers.
+    ///
+    /// # Parameters
+    ///
+    /// - `addr`: The public address of the participant that joined or left.
+    /// - `joined`: `true` for a join, `false` for a departure.
+    /// - `reason`: A short explanation of the change.
+    pub(super) fn record_membership_change(
+        &mut self,
+        addr: SocketAddr,
+        joined: bool,
+        reason: &str,
+    ) {
+        self.peer_set.record(SystemTime::now());
+        if joined && self.schedule.release(Instant::now()) {
+            self.arm_schedule_timer();
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.membership(if joined {
+                MembershipRecord::Joined { addr }
+            } else {
+                MembershipRecord::Left {
+                    addr,
+                    reason: reason.to_owned(),
+                }
+            });
+        }
+        self.observers.record_change(MembershipChange {
+            addr,
+            joined,
+            reason: reason.to_owned(),
+        });
+        if joined {
+            self.check_join();
+        }
+    }
+
+    /// Returns the public addresses of the peers whose handshake completed, each once, sorted.
+    pub(super) fn handshaked_peers(&self) -> Vec<SocketAddr> {
+        let mut seen = HashSet::new();
+        let mut peers: Vec<SocketAddr> = self
+            .participants
+            .receivers()
+            .into_iter()
+            .filter(|receiver| !self.contacts.is_pending(&receiver.endpoint))
+            .map(|receiver| receiver.public)
+            .filter(|public| seen.insert(CanonicalAddr::from(*public)))
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    /// Finishes the join check successfully once enough peers completed their handshake.
+    pub(super) fn check_join(&mut self) {
+        let Some(check) = &self.join_check else {
+            return;
+        };
+        if check.is_complete(self.handshaked_peers().len()) {
+            self.finish_join(true);
+        }
+    }
+
+    /// Prints the outcome of the join check and shuts the participant down gracefully.
+    ///
+    /// A check that timed out makes the participant fail with `ParticipantError::JoinTimeout`.
+    /// Only the first outcome counts; the check is over afterwards.
+    ///
+    /// # Parameters
+    ///
+    /// - `joined`: Whether enough peers completed their handshake.
+    pub(super) fn finish_join(&mut self, joined: bool) {
+        if self.join_check.take().is_none() {
+            return;
+        }
+        let result = JoinResult {
+            joined,
+            peers: self.handshaked_peers(),
+            elapsed: self.time_start.elapsed(),
+        };
+        print_line(&result.to_json());
+        if !joined {
+            self.failure = Some(ParticipantError::JoinTimeout);
+        }
+        self.node_handler.signals().send(InternalEvent::Shutdown);
+    }
+}
@@ -0,0 +1,145 @@
+//! Requests to peers and the handlers answering theirs.
+
+use crate::participant::config::NodeConfig;
+use crate::participant::event::InternalEvent;
+use crate::participant::handle::ParticipantHandle;
+use crate::participant::message::{Message, REQUESTS_VERSION};
+use crate::participant::request::{
+    RequestClient, RequestError, RequestResult, MAX_REQUEST_TIMEOUT,
+};
+use crate::participant::topic::TopicError;
+
+use crate::participant::transport::Connection;
+
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use super::Participant;
+
+impl Participant {
+    /// Registers `handler` to answer the requests peers send on `topic`.
+    ///
+    /// The handler runs on a worker thread with the requester's public address and the
+    /// payload, and its return value is sent back as the `Response`. A panicking handler is
+    /// answered with an error. Requests on a topic without a handler are answered with an
+    /// error too.
+    ///
+    /// # Returns
+    ///
+    /// The `TopicError` explaining why `topic` is not a valid topic name, if it is not.
+    pub fn on_request(
+        &mut self,
+        topic: &str,
+        handler: impl Fn(SocketAddr, Vec<u8>) -> Result<Vec<u8>, String> + Send + Sync + 'static,
+    ) -> Result<(), TopicError> {
+        self.request_handlers.register(topic, handler)
+    }
+
+    /// Registers `loader` to read the configuration again on a `SIGHUP` or the `reload` command.
+    ///
+    /// The loaded configuration is validated as a whole, and only its hot fields are applied,
+    /// see `reload`. Without a loader, a reload only reports that there is nothing to read.
+    pub fn on_reload(
+        &mut self,
+        loader: impl FnMut() -> Result<NodeConfig, String> + Send + 'static,
+    ) {
+        self.reloader = Some(Box::new(loader));
+    }
+
+    /// Returns a `RequestClient` sending requests through this participant from other threads.
+    pub fn request_client(&self) -> RequestClient {
+        RequestClient::new(self.node_handler.clone())
+    }
+
+    /// Returns a `ParticipantHandle` driving this participant from other threads once it runs.
+    pub fn handle(&self) -> ParticipantHandle {
+        ParticipantHandle::new(self.node_handler.clone())
+    }
+
+    /// Sends the request of a `RequestClient` to the participant at `peer`.
+    ///
+    /// The outcome goes to `reply` once the response arrives, or immediately when `peer` is
+    /// not a connected participant or does not speak `REQUESTS_VERSION`.
+    ///
+    /// # Parameters
+    ///
+    /// - `peer`: The public address of the requested participant.
+    /// - `topic`: The topic of the request.
+    /// - `payload`: The payload of the request.
+    /// - `timeout`: How long the request is kept waiting for its response, at most
+    ///   `MAX_REQUEST_TIMEOUT`.
+    /// - `reply`: Receives the outcome of the request.
+    pub(super) fn send_request(
+        &mut self,
+        peer: SocketAddr,
+        topic: String,
+        payload: Vec<u8>,
+        timeout: Duration,
+        reply: Sender<RequestResult>,
+    ) {
+        let participants = &mut self.participants;
+        let endpoint = match participants.endpoint_of(peer) {
+            Some(endpoint) if !participants.is_connecting(&endpoint) => endpoint,
+            _ => {
+                let _ = reply.send(Err(RequestError::UnknownPeer));
+                return;
+            }
+        };
+        if participants.protocol_version(&endpoint) < REQUESTS_VERSION {
+            let _ = reply.send(Err(RequestError::Unsupported));
+            return;
+        }
+
+        let now = Instant::now();
+        let deadline = now
+            .checked_add(timeout.min(MAX_REQUEST_TIMEOUT))
+            .unwrap_or(now);
+        let id = self.pending_requests.start(endpoint, deadline, reply);
+        self.send(endpoint, Message::Request { id, topic, payload });
+    }
+
+    /// Answers a `Request` from `message_sender` with the handler of its topic.
+    ///
+    /// The handler runs on a worker thread, which hands its outcome back to the event loop as
+    /// an `InternalEvent::RequestHandled`. A topic without a handler is answered right away
+    /// with an error.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` the request came from.
+    /// - `id`: The id of the request, repeated in the response.
+    /// - `topic`: The topic of the request.
+    /// - `payload`: The payload of the request.
+    pub(super) fn answer_request(
+        &mut self,
+        message_sender: Connection,
+        id: u64,
+        topic: String,
+        payload: Vec<u8>,
+    ) {
+        let Some(pub_addr) = self.participants.get_pub_addr(&message_sender) else {
+            return;
+        };
+
+        let handler_clone = self.node_handler.clone();
+        let dispatched = self
+            .request_handlers
+            .dispatch(pub_addr, &topic, payload, move |result| {
+                handler_clone.signals().send(InternalEvent::RequestHandled {
+                    endpoint: message_sender,
+                    id,
+                    result,
+                })
+            });
+        if !dispatched {
+            self.send(
+                message_sender,
+                Message::Response {
+                    id,
+                    payload: Err(format!("no handler for topic \"{}\"", topic.escape_debug())),
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! The messages scheduled for later.
+
+use crate::printer::print_event;
+
+use crate::participant::event::InternalEvent;
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::message::Message;
+use crate::participant::schedule::{Due, Missed, ScheduleError, ScheduleHandle, ScheduledEntry};
+use crate::participant::storage::ParticipantAddress;
+use crate::participant::utils::{format_addrs_capped, DEFAULT_ADDRS_CAP};
+
+use crate::participant::transport::Connection;
+
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Schedules `message` to be published at `due`, as a text to every peer or on its topic
+    /// like `publish`.
+    ///
+    /// # Parameters
+    ///
+    /// - `due`: When the message is due.
+    /// - `message`: The message to publish.
+    /// - `missed`: What becomes of it if its time passes while this participant is down, with
+    ///   `--state-file`.
+    ///
+    /// # Returns
+    ///
+    /// The `ScheduleHandle` cancelling the publication, or the `ScheduleError` explaining why
+    /// it can not be scheduled.
+    pub fn schedule(
+        &mut self,
+        due: Due,
+        message: OutgoingMessage,
+        missed: Missed,
+    ) -> Result<ScheduleHandle, ScheduleError> {
+        let handle = self
+            .schedule
+            .add(due, message, missed, Instant::now(), SystemTime::now())?;
+        self.arm_schedule_timer();
+        Ok(handle)
+    }
+
+    /// Returns the publications scheduled and not sent yet, by due time.
+    pub fn scheduled(&self) -> Vec<ScheduledEntry> {
+        self.schedule.pending()
+    }
+
+    /// Arms the timer of the earliest scheduled publication, unless one is armed for it or an
+    /// earlier one already.
+    pub(super) fn arm_schedule_timer(&mut self) {
+        let Some(due) = self.schedule.next_due() else {
+            return;
+        };
+        if self.schedule_armed.is_some_and(|armed| armed <= due) {
+            return;
+        }
+        self.schedule_armed = Some(due);
+        self.node_handler.signals().send_with_timer(
+            InternalEvent::ScheduleTick,
+            due.saturating_duration_since(Instant::now()),
+        );
+    }
+
+    /// Publishes the scheduled messages that are due, in the order they were due.
+    pub(super) fn schedule_tick(&mut self) {
+        let now = Instant::now();
+        // A timer armed before an earlier publication was scheduled fires after its own.
+        if self.schedule_armed.is_some_and(|armed| armed <= now) {
+            self.schedule_armed = None;
+        }
+        for (id, message) in self.schedule.take_due(now) {
+            self.publish_scheduled(id, message);
+        }
+        self.arm_schedule_timer();
+    }
+
+    /// Publishes the scheduled publication `id`.
+    pub(super) fn publish_scheduled(&mut self, id: u64, message: OutgoingMessage) {
+        let formatted_msg = match message.message().clone() {
+            Message::SequencedTopic { topic, payload, .. } => match self.publish(&topic, payload) {
+                Ok(()) => format!("Published scheduled publication {} on \"{}\"", id, topic),
+                Err(err) => format!("Dropped scheduled publication {}: {}", id, err),
+            },
+            Message::Text(text) => {
+                let receivers = self.participants.receivers();
+                let endpoints: Vec<Connection> = receivers
+                    .iter()
+                    .map(|ParticipantAddress { endpoint, .. }| *endpoint)
+                    .collect();
+                self.send_to_all(&endpoints, Message::Text(text.clone()));
+                format!(
+                    "Sending scheduled publication {} [{}] to {}",
+                    id,
+                    text,
+                    format_addrs_capped(&receivers, DEFAULT_ADDRS_CAP)
+                )
+            }
+            _ => return,
+        };
+        print_event(self.time_start.clone(), &formatted_msg);
+    }
+}
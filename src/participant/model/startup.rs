@@ -0,0 +1,296 @@
+//! The steps of starting a participant: binding its interfaces, loading its identity and what
+//! it remembers, and choosing whom to bootstrap from.
+
+use crate::printer::print_event;
+
+use crate::participant::candidate::Candidates;
+use crate::participant::config::NodeConfig;
+use crate::participant::error::ParticipantError;
+use crate::participant::filter::{FilterFile, FilterSet};
+use crate::participant::identity::{identity_path, Identity, IdentityOrigin};
+use crate::participant::interface::Interfaces;
+use crate::participant::peer_cache::PeerCache;
+use crate::participant::provenance::{LearnedVia, Provenance, ProvenanceBook};
+use crate::participant::schedule::Schedule;
+use crate::participant::snapshot::{unix_seconds, Snapshot};
+use crate::participant::state::{hex, NodeState, RememberedPeer};
+use crate::participant::transport::{CheckedTransport, Transport};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+/// Binds the listen address of `config` and its additional ones, announced as their
+/// matching `--advertise` address when given.
+pub(super) fn bind_interfaces(
+    transport: &mut CheckedTransport,
+    config: &NodeConfig,
+) -> Result<Interfaces, ParticipantError> {
+    let mut interfaces = Interfaces::new();
+    let (listener_id, bound_addr) = transport
+        .listen(config.port.listen_addr())
+        .map_err(ParticipantError::Bind)?;
+    interfaces.add(listener_id, bound_addr, None);
+    for (index, listen_addr) in config.listen.iter().enumerate() {
+        let (listener_id, bound_addr) = transport
+            .listen(*listen_addr)
+            .map_err(ParticipantError::Bind)?;
+        interfaces.add(
+            listener_id,
+            bound_addr,
+            config.advertise.get(index).copied(),
+        );
+    }
+    Ok(interfaces)
+}
+
+/// Loads the identity of the participant, or creates it, and logs where it comes from.
+pub(super) fn load_identity(
+    config: &NodeConfig,
+    time_start: &Arc<Instant>,
+) -> Result<Identity, ParticipantError> {
+    let path = identity_path(
+        config.identity_file.as_deref(),
+        config.node_label.as_deref(),
+        config.port.get(),
+    );
+    let identity = Identity::load_or_create(path.filter(|_| config.persist_identity))
+        .map_err(ParticipantError::StateFile)?;
+    let formatted_msg = match (&identity.origin, &identity.path) {
+        (IdentityOrigin::Ephemeral, _) if !config.persist_identity => {
+            format!("Node id is {}, for this run only", identity.id)
+        }
+        (IdentityOrigin::Ephemeral, _) | (_, None) => format!(
+            "Node id is {}, for this run only: an ephemeral port without --node-label has no identity file",
+            identity.id
+        ),
+        (IdentityOrigin::Loaded, Some(path)) => {
+            format!("Node id is {}, from \"{}\"", identity.id, path.display())
+        }
+        (IdentityOrigin::Created, Some(path)) => {
+            format!("Node id is {}, new in \"{}\"", identity.id, path.display())
+        }
+        (IdentityOrigin::Regenerated, Some(path)) => format!(
+            "WARNING: the identity file \"{}\" is corrupt; node id is now {}, so peers will see a new node",
+            path.display(),
+            identity.id
+        ),
+    };
+    print_event(time_start.clone(), &formatted_msg);
+    Ok(identity)
+}
+
+/// Returns the keys whose signatures an imported snapshot may carry: the one of
+/// `--trusted-snapshot-key` and the own one, if any.
+pub(super) fn trusted_snapshot_keys(
+    config: &NodeConfig,
+    own: Option<VerifyingKey>,
+) -> Vec<VerifyingKey> {
+    config.trusted_snapshot_key.into_iter().chain(own).collect()
+}
+
+/// Reads the membership snapshot of `--import-membership`, if any.
+///
+/// # Returns
+///
+/// The addresses of the fresh participants of the snapshot in dial order, or none without a
+/// snapshot to import, and whether a trusted key signed them.
+pub(super) fn import_membership(
+    config: &NodeConfig,
+    public_addr: SocketAddr,
+    identity: &Identity,
+    time_start: &Arc<Instant>,
+) -> Result<(Vec<SocketAddr>, bool), ParticipantError> {
+    let imported = match &config.import_membership {
+        Some(path) => {
+            let own = identity.key.as_ref().map(SigningKey::verifying_key);
+            let (snapshot, key) = Snapshot::load(
+                path,
+                SystemTime::now(),
+                config.max_snapshot_age,
+                config.require_signed_import,
+                &trusted_snapshot_keys(config, own),
+            )
+            .map_err(ParticipantError::Snapshot)?;
+            let (addrs, stale) = snapshot.dial_order(public_addr);
+            let signed = match key {
+                Some(key) => format!("signed by trusted key {}", hex(key.as_bytes())),
+                None => "unsigned".to_owned(),
+            };
+            let formatted_msg = format!(
+                "Imported {} participants from the snapshot of \"{}\" made {}s ago, {}, skipped {} stale",
+                addrs.len(),
+                snapshot.exporter.addr,
+                snapshot.age(SystemTime::now()).as_secs(),
+                signed,
+                stale
+            );
+            print_event(time_start.clone(), &formatted_msg);
+            (addrs, key.is_some())
+        }
+        None => (Vec::new(), false),
+    };
+    Ok(imported)
+}
+
+/// Reads the state file of `--state-file`, if any, starting fresh when it can not be used.
+///
+/// # Returns
+///
+/// The fresh peers remembered, the last sequence number of each topic and the schedule with
+/// the publications still due restored.
+pub(super) fn restore_state(
+    config: &NodeConfig,
+    time_start: &Arc<Instant>,
+) -> (Vec<RememberedPeer>, HashMap<String, u64>, Schedule) {
+    let state = config.state_file.as_ref().and_then(|path| {
+        match NodeState::load(path, SystemTime::now(), config.max_state_age) {
+            Ok(state) => state,
+            Err(err) => {
+                let formatted_msg = format!(
+                    "Ignoring the state file \"{}\" and starting fresh: {}",
+                    path.display(),
+                    err
+                );
+                print_event(time_start.clone(), &formatted_msg);
+                None
+            }
+        }
+    });
+    let (remembered, topic_sequences, scheduled) = match state {
+        Some(state) => {
+            let (peers, stale) = state.fresh_peers(SystemTime::now(), config.max_state_age);
+            let formatted_msg = format!(
+                "Restored {} peers and {} topic sequences from the state saved {}s ago, skipped {} stale peers",
+                peers.len(),
+                state.topic_sequences.len(),
+                unix_seconds(SystemTime::now()).saturating_sub(state.saved_at),
+                stale
+            );
+            print_event(time_start.clone(), &formatted_msg);
+            (
+                peers,
+                state.topic_sequences.into_iter().collect(),
+                state.scheduled,
+            )
+        }
+        None => (Vec::new(), HashMap::new(), Vec::new()),
+    };
+    let mut schedule = Schedule::new(config.max_scheduled);
+    if !scheduled.is_empty() {
+        let saved = scheduled.len();
+        let restored = scheduled
+            .into_iter()
+            .map(|publication| schedule.restore(publication, Instant::now(), SystemTime::now()))
+            .filter(|restored| *restored)
+            .count();
+        let formatted_msg = format!(
+            "Restored {} scheduled publications, dropped {} whose time passed while this participant was down",
+            restored,
+            saved - restored
+        );
+        print_event(time_start.clone(), &formatted_msg);
+    }
+    (remembered, topic_sequences, schedule)
+}
+
+/// Builds the filter of the blocklist of `config` and of the rules of its filter file, if any.
+pub(super) fn load_filter(
+    config: &NodeConfig,
+) -> Result<(FilterSet, Option<FilterFile>), ParticipantError> {
+    let mut filter = FilterSet::new(config.blocklist.clone());
+    let filter_file = match &config.filter_file {
+        Some(path) => {
+            let (file, rules) = FilterFile::load(path).map_err(ParticipantError::Filter)?;
+            filter.set_rules(rules);
+            Some(file)
+        }
+        None => None,
+    };
+    Ok((filter, filter_file))
+}
+
+/// Chooses whom to bootstrap from, and records how each of them was learned.
+///
+/// # Parameters
+///
+/// * `imported` - The participants of the imported snapshot, and whether a trusted key
+///   signed them.
+///
+/// # Returns
+///
+/// The candidates trusted without corroboration, the provenances of the addresses known
+/// from the start and the bootstrap candidates in dial order.
+pub(super) fn bootstrap_plan(
+    config: &NodeConfig,
+    remembered: &[RememberedPeer],
+    (imported, signed): (Vec<SocketAddr>, bool),
+    peer_cache: &PeerCache,
+    interfaces: &Interfaces,
+    time_start: &Arc<Instant>,
+) -> (Candidates, ProvenanceBook, VecDeque<String>) {
+    // The explicit target, the restored peers and the participants of a trusted snapshot
+    // are dialed whoever lists them; those of an unsigned one are only dialed at startup.
+    let mut candidates = Candidates::new(config.corroboration, config.dedup_entries);
+    let resolved_target = config
+        .connect
+        .iter()
+        .filter_map(|addr| addr.to_socket_addrs().ok()?.next());
+    let restored = remembered.iter().map(|peer| peer.addr);
+    let trusted_imports = imported.iter().copied().filter(|_| signed);
+    for addr in resolved_target
+        .clone()
+        .chain(restored.clone())
+        .chain(trusted_imports)
+    {
+        candidates.trust(addr, Instant::now());
+    }
+
+    // A restored peer keeps the provenance saved with it, even if it is the target again.
+    let mut provenances = ProvenanceBook::new(config.dedup_entries);
+    let first_sight = |source| {
+        Provenance::new(
+            source,
+            unix_seconds(SystemTime::now()),
+            time_start.elapsed(),
+        )
+    };
+    for peer in remembered {
+        if let Some(provenance) = &peer.provenance {
+            provenances.learned(peer.addr, provenance.clone(), Instant::now());
+        }
+    }
+    let sources = resolved_target
+        .map(|addr| (addr, LearnedVia::Connect))
+        .chain(restored.clone().map(|addr| (addr, LearnedVia::StateFile)))
+        .chain(imported.iter().map(|addr| (*addr, LearnedVia::Snapshot)))
+        .chain(
+            peer_cache
+                .addresses()
+                .into_iter()
+                .map(|addr| (addr, LearnedVia::PeerCache)),
+        );
+    for (addr, source) in sources {
+        provenances.learned(addr, first_sight(source), Instant::now());
+    }
+
+    // The explicit target goes first, the restored peers and then the imported
+    // participants follow from the most recently verified, then the cached peers in
+    // most-recently-seen order.
+    let bootstrap_candidates: VecDeque<String> = config
+        .connect
+        .iter()
+        .cloned()
+        .chain(
+            restored
+                .chain(imported)
+                .chain(peer_cache.addresses())
+                .filter(|addr| !interfaces.is_own(*addr))
+                .map(|addr| addr.to_string()),
+        )
+        .collect();
+    (candidates, provenances, bootstrap_candidates)
+}
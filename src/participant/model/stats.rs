@@ -0,0 +1,406 @@
+//! The `stats` command, summing up the whole state of a participant.
+
+use crate::printer::format_elapsed;
+
+use crate::participant::anomaly::AnomalyKind;
+use crate::participant::dials::{DialOutcome, DialTrigger};
+use crate::participant::exchange::ListPurpose;
+use crate::participant::retention::{format_bytes, RetainedKind};
+use crate::participant::session::FLAP_WINDOW;
+use crate::participant::skew::ClockOffset;
+use crate::participant::storage::format_kind_counters;
+
+use std::net::SocketAddr;
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Sums up the whole state of the participant, one line per part of it, for the `stats`
+    /// command.
+    pub(super) fn describe_stats(&self, out: &mut Vec<String>) {
+        let (sent, received) = self.participants.totals();
+        out.push(format!("Participants: {}", self.participants.count()));
+        let (inbound, outbound) = self.participants.direction_counts();
+        out.push(format!(
+            "Connections: {} inbound, {} outbound",
+            inbound, outbound
+        ));
+        if let Some(balancer) = &self.balancer {
+            let (completed, failed, abandoned) = balancer.counters();
+            let in_flight = balancer
+                .in_flight()
+                .map_or(String::new(), |(public, since)| {
+                    format!(
+                        ", dialing \"{}\" back for {}",
+                        public,
+                        format_elapsed(Instant::now().saturating_duration_since(since))
+                    )
+                });
+            out.push(format!(
+                "Handovers: {} completed, {} failed, {} abandoned{}",
+                completed, failed, abandoned, in_flight
+            ));
+        }
+        let (closed_here, closed_by_peer, awaited) = self.crossed.counters();
+        out.push(format!(
+            "Crossed connections: {} closed here, {} closed by the peer, {} awaited",
+            closed_here, closed_by_peer, awaited
+        ));
+        out.push(format!("Observers: {}", self.observers.count()));
+        let flapping: Vec<String> = self
+            .participants
+            .flapping(Instant::now())
+            .iter()
+            .map(|(public, flaps)| format!("\"{}\" ({} flaps)", public, flaps))
+            .collect();
+        out.push(format!(
+            "Flapping in the last {}: [{}]",
+            format_elapsed(FLAP_WINDOW),
+            flapping.join(", ")
+        ));
+        out.push(format!(
+            "Gossip period: {:?} ({})",
+            self.effective_period, self.period
+        ));
+        out.push(format!("Sent: {}", format_kind_counters(&sent)));
+        out.push(format!("Received: {}", format_kind_counters(&received)));
+
+        let recent_dials = &self.recent_dials;
+        out.push(format!(
+            "Dedup entries: {}/{} ({} evicted)",
+            recent_dials.len(),
+            recent_dials.capacity(),
+            recent_dials.evictions()
+        ));
+        let limiter = &self.push_list_limiter;
+        out.push(format!(
+            "Rate limit entries: {}/{} ({} evicted, {} requests throttled)",
+            limiter.buckets(),
+            limiter.capacity(),
+            limiter.evictions(),
+            limiter.throttled()
+        ));
+        match self.slowest_send {
+            Some((public, elapsed)) => out.push(format!(
+                "Slowest send of the last round: \"{}\" in {:.3}ms (budget {}ms)",
+                public,
+                elapsed.as_secs_f64() * 1000.0,
+                self.send_budget.as_millis()
+            )),
+            None => out.push("Slowest send of the last round: none".to_owned()),
+        }
+        out.push(format!(
+            "Requests: {} pending",
+            self.pending_requests.count()
+        ));
+        let dials = &self.dials;
+        let probe = |outcome| dials.count(DialTrigger::HealProbe, outcome);
+        let (failed, timed_out) = (probe(DialOutcome::Failed), probe(DialOutcome::TimedOut));
+        let reconnected = probe(DialOutcome::Connected);
+        out.push(format!(
+            "Partition heal probes: {} sent, {} failed, {} reconnected",
+            dials.in_flight(DialTrigger::HealProbe) + failed + timed_out + reconnected,
+            failed + timed_out,
+            reconnected
+        ));
+        let outcomes: Vec<String> = DialOutcome::ALL
+            .iter()
+            .zip(dials.by_outcome())
+            .map(|(outcome, count)| format!("{} {}", count, outcome))
+            .collect();
+        let triggers: Vec<String> = DialTrigger::ALL
+            .iter()
+            .zip(dials.by_trigger())
+            .map(|(trigger, count)| format!("{} {}", count, trigger))
+            .collect();
+        let in_flight: u64 = DialTrigger::ALL
+            .iter()
+            .map(|&trigger| dials.in_flight(trigger))
+            .sum();
+        out.push(format!(
+            "Dials: {} in flight, {}; by trigger: {}",
+            in_flight,
+            outcomes.join(", "),
+            triggers.join(", ")
+        ));
+        let (privileged, special) = self.hygiene_log.refused();
+        let worst: Vec<String> = self
+            .hygiene_log
+            .worst()
+            .into_iter()
+            .map(|(reporter, strikes)| format!("\"{}\": {}", reporter, strikes))
+            .collect();
+        out.push(format!(
+            "Dial hygiene: {} learned addresses refused for their port, {} for their range, most strikes: [{}]",
+            privileged,
+            special,
+            worst.join(", ")
+        ));
+        out.push(format!(
+            "Split-brains: {} detected, {} participants lists kept",
+            self.split_brains,
+            self.peer_lists.len()
+        ));
+        out.push(format!(
+            "Load: {}, event loop {:?} behind (worst {:?}), {} state changes, {} received payloads dropped",
+            self.load.state(),
+            self.load.last_lag(),
+            self.load.worst_lag(),
+            self.load.changes(),
+            self.load.shed()
+        ));
+        if let Some(warmup) = &self.warmup {
+            out.push(format!(
+                "Warm-up: {} dials put off so far",
+                warmup.deferred_total()
+            ));
+        }
+        out.push(format!(
+            "Alerts: {} raised, {} suppressed by the cooldown",
+            self.alerter.raised(),
+            self.alerter.suppressed()
+        ));
+        let outbox = &self.outbox;
+        if outbox.is_enabled() {
+            out.push(format!(
+                "Batches: {} sent carrying {} messages, {} messages queued (window {:?})",
+                outbox.batches(),
+                outbox.batched(),
+                outbox.pending(),
+                outbox.window()
+            ));
+        } else {
+            out.push("Batches: disabled".to_owned());
+        }
+        let tracer = &self.tracer;
+        if tracer.is_enabled() {
+            out.push(format!(
+                "tick_duration_ms: {} ({} ticks traced)",
+                tracer.tick_durations(),
+                tracer.tick_durations().total()
+            ));
+            out.push(format!(
+                "send_duration_ms: {} ({} sends traced)",
+                tracer.send_durations(),
+                tracer.send_durations().total()
+            ));
+        }
+        let tick_monitor = &self.tick_monitor;
+        out.push(format!(
+            "Late ticks: {} ({} frozen, longest gap {:.1?})",
+            tick_monitor.late_ticks(),
+            tick_monitor.frozen_ticks(),
+            tick_monitor.longest_gap()
+        ));
+        if let Some(clock) = &self.rounds {
+            let highest = clock
+                .highest_observed()
+                .map_or("none".to_owned(), |round| round.to_string());
+            out.push(format!(
+                "Round: {} (highest heard {}, {} jumps, {} rejected)",
+                clock.round(),
+                highest,
+                clock.jumps(),
+                clock.rejected()
+            ));
+        }
+        let candidates = &self.candidates;
+        out.push(format!(
+            "Candidates: {} awaiting {} reports ({} corroborated, {} dropped)",
+            candidates.count(),
+            candidates.threshold(),
+            candidates.promoted(),
+            candidates.dropped()
+        ));
+        out.push(format!("Hints: {} given", self.hints.given()));
+        let topics = &self.topics;
+        out.push(format!(
+            "Topics: {} subscriptions, {} delivered, {} unsubscribed, {} filtered, {} unsupported, {} callback panics",
+            topics.subscriptions(),
+            topics.delivered(),
+            topics.unsubscribed(),
+            topics.filtered(),
+            topics.unsupported(),
+            topics.panicked()
+        ));
+        let callbacks = topics.callback_totals();
+        let running = topics
+            .callback_stats()
+            .iter()
+            .filter(|(_, _, stats)| stats.running)
+            .count();
+        out.push(format!(
+            "Callbacks: {} workers, {} running, {} run, {} dropped, {} queued, slowest {:?}",
+            topics.callback_workers(),
+            running,
+            callbacks.executed,
+            callbacks.dropped,
+            callbacks.queued,
+            callbacks.slowest
+        ));
+        let scheduled = self.schedule.stats();
+        let next = self
+            .schedule
+            .pending()
+            .first()
+            .map_or("none".to_owned(), |entry| {
+                match entry.due.duration_since(SystemTime::now()) {
+                    Ok(left) => format!("in {:.1?}", left),
+                    Err(_) => "overdue".to_owned(),
+                }
+            });
+        out.push(format!(
+            "Scheduled: {} pending, next {}, {} sent, {} cancelled, {} missed while down",
+            scheduled.pending, next, scheduled.sent, scheduled.cancelled, scheduled.missed
+        ));
+        out.push(format!(
+            "Topic interest: {} peers listed their topics, {} publications spared, {} updates announced",
+            self.participants.selective_count(),
+            self.interest_spared,
+            self.interest.updates()
+        ));
+        out.push(format!(
+            "Echoes: {} of our own messages sent back and dropped, {} echoing peers closed",
+            self.anomalies.count(AnomalyKind::Echo),
+            self.echoes.violations()
+        ));
+        let counts = self.anomalies.counts();
+        let anomalies: Vec<String> = AnomalyKind::ALL
+            .iter()
+            .map(|kind| format!("{} {}", counts[*kind as usize], kind.name()))
+            .collect();
+        out.push(format!(
+            "Anomalies: {} in all, {}{}",
+            self.anomalies.total(),
+            anomalies.join(", "),
+            if self.anomalies.is_strict() {
+                ", the first one fatal"
+            } else {
+                ""
+            }
+        ));
+        match self.transport.outbound_refused() {
+            Some(refused) => out.push(format!(
+                "Outbound validation: {} invalid frames not sent",
+                refused
+            )),
+            None => out.push("Outbound validation: off".to_owned()),
+        }
+        let purposes: Vec<String> = ListPurpose::ALL
+            .iter()
+            .map(|purpose| {
+                let stats = self.list_exchanges.stats(*purpose);
+                format!(
+                    "{} {} answered ({} mean), {} timed out",
+                    purpose.name(),
+                    stats.answered,
+                    stats.mean_latency().map_or("-".to_owned(), |latency| {
+                        format!("{:.3}ms", latency.as_secs_f64() * 1000.0)
+                    }),
+                    stats.timed_out
+                )
+            })
+            .collect();
+        out.push(format!(
+            "List exchanges: {} outstanding, {}; {} stray replies dropped, {} lists unasked",
+            self.list_exchanges.outstanding(),
+            purposes.join(", "),
+            self.list_exchanges.unknown(),
+            self.list_exchanges.unsolicited()
+        ));
+        let packing = &self.packing;
+        out.push(format!(
+            "Compact lists: {} sent, {} bytes instead of {}",
+            packing.lists, packing.packed_bytes, packing.plain_bytes
+        ));
+        out.push(format!(
+            "Ordered delivery: {} held, {} gaps, {} duplicates dropped",
+            topics.held(),
+            topics.gaps(),
+            topics.duplicates()
+        ));
+        let (inboxes, dropped) = self.inboxes.stats();
+        out.push(format!(
+            "Inboxes: {} open, {} messages dropped",
+            inboxes, dropped
+        ));
+        let threads = self.threads.stats();
+        out.push(format!(
+            "Threads: {} running of at most {}, {} spawned, {} refused, {} panicked",
+            threads.live, threads.cap, threads.spawned, threads.refused, threads.panicked
+        ));
+        let offsets: Vec<(SocketAddr, ClockOffset)> = self
+            .participants
+            .summaries(Instant::now())
+            .into_iter()
+            .filter_map(|summary| Some((summary.public, summary.clock_offset?)))
+            .collect();
+        let beyond = offsets
+            .iter()
+            .filter(|(_, offset)| offset.exceeds(self.max_clock_offset))
+            .count();
+        let largest = match offsets.iter().max_by_key(|(_, offset)| offset.magnitude()) {
+            Some((public, offset)) => format!(", largest {} for \"{}\"", offset, public),
+            None => String::new(),
+        };
+        out.push(format!(
+            "Clock offsets: {} peers estimated{}, {} beyond {:?}",
+            offsets.len(),
+            largest,
+            beyond,
+            self.max_clock_offset
+        ));
+        let budget = self.descriptors.stats();
+        let (deferred, dropped) = self.dial_backlog.counters();
+        out.push(format!(
+            "File descriptors: {} of {} in use (soft limit {}), peak {}, fd_budget_exhausted_total {}, {} dials put off ({} so far, {} dropped)",
+            budget.in_use,
+            budget.ceiling,
+            budget.limit,
+            budget.peak,
+            budget.exhausted,
+            self.dial_backlog.len(),
+            deferred,
+            dropped
+        ));
+        let kept: Vec<String> = RetainedKind::ALL
+            .iter()
+            .map(|kind| {
+                let usage = self.retention.usage(*kind);
+                format!(
+                    "{} {} (~{})",
+                    kind.name(),
+                    usage.entries,
+                    format_bytes(usage.bytes)
+                )
+            })
+            .collect();
+        let evicted: u64 = RetainedKind::ALL
+            .iter()
+            .flat_map(|kind| self.retention.usage(*kind).evicted)
+            .sum();
+        out.push(format!(
+            "Retention: {}, ~{} in all, {} evicted",
+            kept.join(", "),
+            format_bytes(self.retention.total_bytes()),
+            evicted
+        ));
+        out.push(format!(
+            "Configuration: generation {}, {} reloads rejected",
+            self.reloads.0, self.reloads.1
+        ));
+        let channels = self.channels.stats();
+        let deepest = channels.iter().max_by_key(|channel| channel.high_water);
+        out.push(format!(
+            "Channels: {} open, {} messages waiting, {} dropped, deepest {}",
+            channels.iter().filter(|channel| !channel.closed).count(),
+            channels.iter().map(|channel| channel.depth).sum::<usize>(),
+            channels.iter().map(|channel| channel.dropped).sum::<u64>(),
+            deepest.map_or("none".to_owned(), |channel| format!(
+                "{} with {} at once",
+                channel.name, channel.high_water
+            ))
+        ));
+    }
+}
@@ -0,0 +1,362 @@
+//! Publishing and subscribing to topics, and delivering what arrives on them.
+
+use crate::printer::{print_event, sanitize_for_log};
+
+use crate::participant::anomaly::AnomalyKind;
+use crate::participant::event::InternalEvent;
+use crate::participant::frame::{self, EstimateError, FrameEstimate, Limits, OutgoingMessage};
+use crate::participant::inbox::{Inbox, ReceivedMessage};
+use crate::participant::interest::INTEREST_DEBOUNCE;
+use crate::participant::message::{
+    Message, INTERESTS_VERSION, SCHEMAS_VERSION, SEQUENCED_TOPICS_VERSION,
+};
+use crate::participant::reorder::Delivery;
+use crate::participant::schema::{Schema, VersionRange};
+use crate::participant::storage::ParticipantAddress;
+use crate::participant::topic::{
+    validate_topic, Dispatch, SubscriptionHandle, TopicError, MAX_TOPIC_LEN,
+};
+
+use crate::participant::transport::Connection;
+
+use std::net::SocketAddr;
+use std::time::{Instant, SystemTime};
+
+use super::Participant;
+
+impl Participant {
+    /// Publishes `payload` on `topic` to every connected participant that may want it.
+    ///
+    /// The message is serialized once for all the receivers it is not batched for. Publishing
+    /// does not deliver to this participant's own subscribers. The peers whose interest update
+    /// leaves `topic` out are skipped, unless `topic` is one of `--topic-broadcast`. The
+    /// publications on a topic are numbered for the ordered subscribers of the peers that
+    /// understand sequence numbers; older peers are sent them without one.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the message is sent, or the `TopicError` explaining why `topic` is not a
+    /// valid topic name.
+    pub fn publish(&mut self, topic: &str, payload: Vec<u8>) -> Result<(), TopicError> {
+        self.publish_versioned(topic, None, payload)
+    }
+
+    /// Publishes `payload` on `topic` like `publish`, declaring it follows `schema`.
+    ///
+    /// The peers speaking schemas receive it with the schema, for the handler of its version;
+    /// older peers are sent the bare payload, as by `publish`. The publication is numbered
+    /// along with the unversioned ones of the topic. `schema_support` tells how many peers
+    /// announced a handler of a version before publishing it.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the message is sent, or the `TopicError` explaining why `topic` or the
+    /// name of `schema` is not a valid name.
+    pub fn publish_with_schema(
+        &mut self,
+        topic: &str,
+        schema: &Schema,
+        payload: Vec<u8>,
+    ) -> Result<(), TopicError> {
+        validate_topic(&schema.name)?;
+        self.publish_versioned(topic, Some(schema), payload)
+    }
+
+    /// Publishes `payload` on `topic`, with `schema` to the peers understanding one.
+    pub(super) fn publish_versioned(
+        &mut self,
+        topic: &str,
+        schema: Option<&Schema>,
+        payload: Vec<u8>,
+    ) -> Result<(), TopicError> {
+        validate_topic(topic)?;
+
+        let broadcast = self.topic_broadcast.contains(topic);
+        let (endpoints, spared): (Vec<Connection>, Vec<Connection>) = self
+            .participants
+            .receivers()
+            .into_iter()
+            .map(|ParticipantAddress { endpoint, .. }| endpoint)
+            .partition(|endpoint| broadcast || self.participants.wants_topic(endpoint, topic));
+        self.interest_spared += spared.len() as u64;
+
+        let seq = self.topic_sequences.entry(topic.to_owned()).or_insert(0);
+        *seq += 1;
+        let seq = *seq;
+
+        let (versioned, endpoints): (Vec<Connection>, Vec<Connection>) =
+            endpoints.into_iter().partition(|endpoint| {
+                schema.is_some() && self.participants.protocol_version(endpoint) >= SCHEMAS_VERSION
+            });
+        if let Some(schema) = schema.filter(|_| !versioned.is_empty()) {
+            let msg = Message::VersionedTopic {
+                topic: topic.to_owned(),
+                seq,
+                schema: schema.name.clone(),
+                version: schema.version,
+                payload: payload.clone(),
+            };
+            self.send_to_all(&versioned, msg);
+        }
+        let (sequenced, plain): (Vec<Connection>, Vec<Connection>) =
+            endpoints.into_iter().partition(|endpoint| {
+                self.participants.protocol_version(endpoint) >= SEQUENCED_TOPICS_VERSION
+            });
+        if !plain.is_empty() {
+            let msg = Message::Topic {
+                topic: topic.to_owned(),
+                payload: payload.clone(),
+            };
+            self.send_to_all(&plain, msg);
+        }
+        let msg = Message::SequencedTopic {
+            topic: topic.to_owned(),
+            seq,
+            payload,
+        };
+        self.send_to_all(&sequenced, msg);
+        Ok(())
+    }
+
+    /// Estimates the frame `message` would be sent in, without sending anything.
+    ///
+    /// # Returns
+    ///
+    /// The `FrameEstimate` of the message against the current `limits`, or the
+    /// `EstimateError` explaining why it could not be published.
+    pub fn estimate_frame(
+        &self,
+        message: &OutgoingMessage,
+    ) -> Result<FrameEstimate, EstimateError> {
+        frame::estimate(message, &self.limits())
+    }
+
+    /// Returns the size limits this participant sends under.
+    pub fn limits(&self) -> Limits {
+        Limits {
+            batch_budget: self.outbox.budget(),
+            max_topic_len: MAX_TOPIC_LEN,
+        }
+    }
+
+    /// Subscribes `callback` to the messages peers publish on `topic`.
+    ///
+    /// The callback runs on a callback worker with the publisher's public address and the
+    /// payload, never on two workers at once. A panicking callback is counted and does not
+    /// affect the other subscribers, and one falling too far behind loses its oldest
+    /// deliveries.
+    ///
+    /// # Returns
+    ///
+    /// The `SubscriptionHandle` removing the subscription, or the `TopicError` explaining why
+    /// `topic` is not a valid topic name.
+    pub fn subscribe(
+        &mut self,
+        topic: &str,
+        callback: impl FnMut(SocketAddr, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.topics.subscribe(topic, callback)?;
+        self.interest_changed();
+        Ok(handle)
+    }
+
+    /// Subscribes `callback` to the messages peers publish on `topic`, in the order each peer
+    /// published them.
+    ///
+    /// The callback runs on the callback worker the subscription is pinned to, with the
+    /// publisher's public address and each `Delivery`: a message, or a gap of messages given
+    /// up after `--reorder-window-ms`.
+    ///
+    /// # Returns
+    ///
+    /// The `SubscriptionHandle` removing the subscription, or the `TopicError` explaining why
+    /// `topic` is not a valid topic name.
+    pub fn subscribe_ordered(
+        &mut self,
+        topic: &str,
+        callback: impl FnMut(SocketAddr, Delivery) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.topics.subscribe_ordered(topic, callback)?;
+        self.interest_changed();
+        Ok(handle)
+    }
+
+    /// Subscribes `callback` to the payloads peers publish on `topic` with the schema `name`,
+    /// at a version of `range`.
+    ///
+    /// The callback runs on a callback worker with the publisher's public address, the
+    /// version of the payload and the payload. A payload whose version several handlers of
+    /// the topic support goes to the one with the narrowest range, see `schema`. The handled
+    /// schemas are announced to the peers along with the interest.
+    ///
+    /// # Returns
+    ///
+    /// The `SubscriptionHandle` removing the handler, or the `TopicError` explaining why
+    /// `topic` or `name` is not a valid name.
+    pub fn subscribe_schema(
+        &mut self,
+        topic: &str,
+        name: &str,
+        range: VersionRange,
+        callback: impl FnMut(SocketAddr, u32, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.topics.subscribe_schema(topic, name, range, callback)?;
+        self.interest_changed();
+        Ok(handle)
+    }
+
+    /// Registers `callback` for the versioned payloads on `topic` that no schema handler
+    /// supports, with the publisher's public address, the schema they declared and the
+    /// payload.
+    ///
+    /// # Returns
+    ///
+    /// The `SubscriptionHandle` removing the fallback, or the `TopicError` explaining why
+    /// `topic` is not a valid topic name.
+    pub fn on_unsupported_schema(
+        &mut self,
+        topic: &str,
+        callback: impl FnMut(SocketAddr, Schema, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.topics.on_unsupported_schema(topic, callback)?;
+        self.interest_changed();
+        Ok(handle)
+    }
+
+    /// Returns the connected peers that announced a handler of schema `name` on `topic`, with
+    /// the versions each handles, by address.
+    pub fn schema_support(&self, topic: &str, name: &str) -> Vec<(SocketAddr, VersionRange)> {
+        self.schema_census.support(topic, name)
+    }
+
+    /// Arms the announcement of the subscriptions to the peers, unless it already is.
+    pub(super) fn interest_changed(&mut self) {
+        if self.interest.changed(self.topics.subscribed_topics()) {
+            self.node_handler
+                .signals()
+                .send_with_timer(InternalEvent::AnnounceInterest, INTEREST_DEBOUNCE);
+        }
+    }
+
+    /// Sends the current subscriptions in an interest update to every peer understanding
+    /// one.
+    pub(super) fn announce_interest(&mut self) {
+        let update = self.interest.due();
+        let endpoints: Vec<Connection> = self
+            .participants
+            .receivers()
+            .into_iter()
+            .map(|ParticipantAddress { endpoint, .. }| endpoint)
+            .filter(|endpoint| self.participants.protocol_version(endpoint) >= INTERESTS_VERSION)
+            .collect();
+        self.send_to_all(&endpoints, Message::InterestUpdate(update));
+
+        let schemas: Vec<Connection> = endpoints
+            .into_iter()
+            .filter(|endpoint| self.participants.protocol_version(endpoint) >= SCHEMAS_VERSION)
+            .collect();
+        let update = Message::SchemaUpdate(self.topics.schema_support());
+        self.send_to_all(&schemas, update);
+    }
+
+    /// Sends the handled schemas to the peer at `endpoint`, which just turned out to speak
+    /// them, unless there are none; unlike the interest, they are not in every trailer.
+    pub(super) fn announce_schemas_to(&mut self, endpoint: Connection) {
+        let schemas = self.topics.schema_support();
+        if !schemas.is_empty() {
+            self.send(endpoint, Message::SchemaUpdate(schemas));
+        }
+    }
+
+    /// Opens an `Inbox` receiving a copy of every gossip text and topic payload delivered from
+    /// now on, to be polled from any thread.
+    pub fn inbox(&self) -> Inbox {
+        self.inboxes.open()
+    }
+
+    /// Hands a received application message to the open inboxes.
+    pub(super) fn deliver_to_inboxes(
+        &self,
+        sender: SocketAddr,
+        topic: Option<&str>,
+        payload: Vec<u8>,
+    ) {
+        if !self.inboxes.is_active() {
+            return;
+        }
+        self.inboxes.deliver(ReceivedMessage {
+            sender,
+            topic: topic.map(str::to_owned),
+            received_at: SystemTime::now(),
+            payload,
+        });
+    }
+
+    /// Hands a topic message from `message_sender` to the subscribers of its topic, unless
+    /// `validate_topic` refuses its name.
+    ///
+    /// # Parameters
+    ///
+    /// - `message_sender`: The `Connection` the message came from.
+    /// - `topic`: The topic of the message.
+    /// - `seq`: The sequence number of a `SequencedTopic`, `None` for a `Topic`.
+    /// - `schema`: The schema of a `VersionedTopic`, `None` otherwise.
+    /// - `payload`: The payload of the message.
+    pub(super) fn deliver_topic(
+        &mut self,
+        message_sender: Connection,
+        topic: &str,
+        seq: Option<u64>,
+        schema: Option<Schema>,
+        payload: Vec<u8>,
+    ) {
+        let Some(pub_addr) = self.participants.get_pub_addr(&message_sender) else {
+            return;
+        };
+        // A name no publisher could have used is refused before it reaches the subscribers,
+        // the inboxes or the logs.
+        if validate_topic(topic).is_err() {
+            let formatted_msg = format!(
+                "Dropped message on invalid topic \"{}\" from \"{}\"",
+                sanitize_for_log(topic),
+                pub_addr
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+            return self.anomaly(AnomalyKind::InvalidTopic, message_sender, || {
+                format!("a message on topic \"{}\"", sanitize_for_log(topic))
+            });
+        }
+
+        let copy = self.inboxes.is_active().then(|| payload.clone());
+        let dispatch = self
+            .topics
+            .dispatch(pub_addr, topic, seq, schema, payload, Instant::now());
+        if let Some(payload) = copy.filter(|_| dispatch != Dispatch::Filtered) {
+            self.deliver_to_inboxes(pub_addr, Some(topic), payload);
+        }
+        if dispatch == Dispatch::Filtered {
+            let formatted_msg = format!(
+                "Dropped message on filtered topic \"{}\"",
+                topic.escape_debug()
+            );
+            print_event(self.time_start.clone(), &formatted_msg);
+        }
+        self.arm_reorder_timer();
+    }
+
+    /// Arms the timer giving up on the next missing message of an ordered subscription, unless
+    /// it is armed already or nothing is waited for.
+    pub(super) fn arm_reorder_timer(&mut self) {
+        if self.reorder_timer_armed {
+            return;
+        }
+        let Some(deadline) = self.topics.next_deadline() else {
+            return;
+        };
+        self.reorder_timer_armed = true;
+        self.node_handler.signals().send_with_timer(
+            InternalEvent::ExpireReorder,
+            deadline.saturating_duration_since(Instant::now()),
+        );
+    }
+}
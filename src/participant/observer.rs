@@ -0,0 +1,257 @@
+//! Observer Registry.
+//!
+//! This module keeps track of observers: clients that connect to a participant to watch the
+//! network without joining it. Observers live in their own registry, separate from
+//! `ParticipantsStorage`, so they are never gossiped to, never listed in the participant
+//! lists served to real peers, and never counted as participants.
+//!
+//! Membership changes are not forwarded one by one. They are queued and flushed as a single
+//! `ObserverUpdate` at most once per `UPDATE_INTERVAL`; when nothing changed, a counters-only
+//! update is still sent every `IDLE_UPDATE_INTERVAL` so observers see the message rates move.
+
+use super::message::MembershipChange;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Minimal time between two updates sent to observers.
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after which a counters-only update is sent when no membership change happened.
+pub const IDLE_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time without any message from an observer after which it is disconnected.
+pub const OBSERVER_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The outcome of an observer subscription attempt.
+#[derive(Debug, PartialEq)]
+pub enum Subscription {
+    /// The endpoint was not an observer before and has just been registered.
+    New,
+    /// The endpoint was already an observer; its idle timer has been reset.
+    Renewed,
+    /// The observer cap is reached.
+    Rejected,
+}
+
+/// Tracks observers and coalesces the membership changes reported to them.
+#[derive(Debug)]
+pub struct ObserverRegistry<T> {
+    observers: HashMap<T, Instant>,
+    max_observers: usize,
+    pending: Vec<MembershipChange>,
+    last_update: Instant,
+}
+
+impl<T: Hash + Eq + Clone> ObserverRegistry<T> {
+    /// Constructs an empty `ObserverRegistry`.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_observers` - The number of observers served at the same time.
+    /// * `now` - The current time, used as the reference of the first update window.
+    pub fn new(max_observers: usize, now: Instant) -> Self {
+        Self {
+            observers: HashMap::new(),
+            max_observers,
+            pending: Vec::new(),
+            last_update: now,
+        }
+    }
+
+    /// Registers `endpoint` as an observer, or renews its subscription.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint that sent `ObserverHello`.
+    /// * `now` - The current time.
+    pub fn subscribe(&mut self, endpoint: T, now: Instant) -> Subscription {
+        if let Some(last_seen) = self.observers.get_mut(&endpoint) {
+            *last_seen = now;
+            return Subscription::Renewed;
+        }
+        if self.observers.len() >= self.max_observers {
+            return Subscription::Rejected;
+        }
+
+        self.observers.insert(endpoint, now);
+        Subscription::New
+    }
+
+    /// Records activity from an observer, resetting its idle timer.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `endpoint` is an observer.
+    pub fn touch(&mut self, endpoint: &T, now: Instant) -> bool {
+        match self.observers.get_mut(endpoint) {
+            Some(last_seen) => {
+                *last_seen = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an observer.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `endpoint` was an observer.
+    pub fn remove(&mut self, endpoint: &T) -> bool {
+        self.observers.remove(endpoint).is_some()
+    }
+
+    /// Returns the number of observers.
+    pub fn count(&self) -> usize {
+        self.observers.len()
+    }
+
+    /// Returns the endpoints of every observer.
+    pub fn endpoints(&self) -> Vec<T> {
+        self.observers.keys().cloned().collect()
+    }
+
+    /// Queues a membership change for the next update.
+    ///
+    /// Changes are only queued while at least one observer is subscribed.
+    pub fn record_change(&mut self, change: MembershipChange) {
+        if !self.observers.is_empty() {
+            self.pending.push(change);
+        }
+    }
+
+    /// Takes the changes due for an update, if an update should be sent now.
+    ///
+    /// # Returns
+    ///
+    /// `Some` with the coalesced changes (possibly empty for a counters-only update) when an
+    /// update is due, or `None` when observers should not hear anything yet.
+    pub fn take_update(&mut self, now: Instant) -> Option<Vec<MembershipChange>> {
+        if self.observers.is_empty() {
+            return None;
+        }
+
+        let elapsed = now.duration_since(self.last_update);
+        let due = if self.pending.is_empty() {
+            elapsed >= IDLE_UPDATE_INTERVAL
+        } else {
+            elapsed >= UPDATE_INTERVAL
+        };
+        if !due {
+            return None;
+        }
+
+        self.last_update = now;
+        Some(std::mem::take(&mut self.pending))
+    }
+
+    /// Removes and returns the observers that stayed silent for longer than `timeout`.
+    pub fn expire_idle(&mut self, now: Instant, timeout: Duration) -> Vec<T> {
+        let expired: Vec<T> = self
+            .observers
+            .iter()
+            .filter(|(_, last_seen)| now.duration_since(**last_seen) > timeout)
+            .map(|(endpoint, _)| endpoint.clone())
+            .collect();
+        for endpoint in &expired {
+            self.observers.remove(endpoint);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn change(port: u16, joined: bool) -> MembershipChange {
+        MembershipChange {
+            addr: addr(port),
+            joined,
+            reason: "handshake".to_owned(),
+        }
+    }
+
+    #[test]
+    fn serves_observers_up_to_the_cap() {
+        let now = Instant::now();
+        let mut observers = ObserverRegistry::new(2, now);
+        assert_eq!(observers.subscribe(1, now), Subscription::New);
+        assert_eq!(observers.subscribe(2, now), Subscription::New);
+        assert_eq!(observers.subscribe(1, now), Subscription::Renewed);
+        assert_eq!(observers.subscribe(3, now), Subscription::Rejected);
+        assert_eq!(observers.count(), 2);
+
+        assert!(observers.remove(&1));
+        assert!(!observers.remove(&1));
+        assert_eq!(observers.subscribe(3, now), Subscription::New);
+        let mut endpoints = observers.endpoints();
+        endpoints.sort();
+        assert_eq!(endpoints, [2, 3]);
+    }
+
+    #[test]
+    fn coalesces_the_changes_of_an_update_interval() {
+        let start = Instant::now();
+        let mut observers = ObserverRegistry::new(4, start);
+        // Nothing is queued while nobody watches.
+        observers.record_change(change(8000, true));
+        observers.subscribe(1, start);
+        observers.record_change(change(8001, true));
+        observers.record_change(change(8001, false));
+
+        let early = start + UPDATE_INTERVAL - Duration::from_millis(1);
+        assert_eq!(observers.take_update(early), None);
+        let due = start + UPDATE_INTERVAL;
+        assert_eq!(
+            observers.take_update(due),
+            Some(vec![change(8001, true), change(8001, false)])
+        );
+
+        observers.record_change(change(8002, true));
+        assert_eq!(
+            observers.take_update(due + Duration::from_millis(999)),
+            None
+        );
+        assert_eq!(
+            observers.take_update(due + UPDATE_INTERVAL),
+            Some(vec![change(8002, true)])
+        );
+    }
+
+    #[test]
+    fn sends_a_counters_only_update_when_idle() {
+        let start = Instant::now();
+        let mut observers = ObserverRegistry::new(4, start);
+        assert_eq!(observers.take_update(start + IDLE_UPDATE_INTERVAL), None);
+
+        observers.subscribe(1, start);
+        assert_eq!(observers.take_update(start + UPDATE_INTERVAL), None);
+        let idle = start + IDLE_UPDATE_INTERVAL;
+        assert_eq!(observers.take_update(idle), Some(Vec::new()));
+        assert_eq!(observers.take_update(idle + UPDATE_INTERVAL), None);
+    }
+
+    #[test]
+    fn expires_the_silent_observers() {
+        let start = Instant::now();
+        let mut observers = ObserverRegistry::new(4, start);
+        observers.subscribe(1, start);
+        observers.subscribe(2, start);
+        let later = start + Duration::from_secs(30);
+        assert!(observers.touch(&2, later));
+        assert!(!observers.touch(&3, later));
+
+        let timeout = OBSERVER_IDLE_TIMEOUT;
+        assert!(observers.expire_idle(start + timeout, timeout).is_empty());
+        assert_eq!(
+            observers.expire_idle(start + timeout + Duration::from_millis(1), timeout),
+            [1]
+        );
+        assert_eq!(observers.expire_idle(later + timeout * 2, timeout), [2]);
+        assert_eq!(observers.count(), 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Outbound Validation.
+//!
+//! `MessageBuilder` checks the messages a tool builds with the validators of `builder`, but
+//! most frames a participant sends never go through it: the handshakes, the participants lists
+//! and replies, the batches the outbox packs and the relayed introductions are built where they
+//! are sent. A refactor that breaks one of them goes unnoticed until a peer drops the frame or
+//! closes the connection over it.
+//!
+//! With `--validate-outbound`, and always in a debug build, the transport checks every frame
+//! right before writing it: the frame is decoded, the message checked with `validate_message`,
+//! and encoded again from what was decoded, trailer included, which has to give back the same
+//! bytes. A frame failing the check is never written. The participant logs a warning with the
+//! kind of the message and refuses the send with `SendOutcome::Invalid`, except under
+//! `--strict-protocol` or in a debug build, where it panics with the details, so a CI run or a
+//! test stops at the first one.
+//!
+//! Without the flag, a release build only pays the branch deciding there is nothing to check.
+
+use crate::participant::message::builder::{validate_message, BuildError};
+use crate::participant::message::{Message, MessageKind};
+use crate::printer::print_event;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Enumerates the reasons a frame fails the outbound check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutboundError {
+    /// The frame does not decode, as explained.
+    Undecodable(String),
+    /// The message would be refused by a peer.
+    Invalid(MessageKind, BuildError),
+    /// The message does not encode back into the same frame.
+    RoundTrip(MessageKind),
+}
+
+impl fmt::Display for OutboundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboundError::Undecodable(reason) => {
+                write!(f, "the frame does not decode: {}", reason)
+            }
+            OutboundError::Invalid(kind, err) => {
+                write!(f, "a {} frame is invalid: {}", kind.name(), err)
+            }
+            OutboundError::RoundTrip(kind) => write!(
+                f,
+                "a {} frame does not encode back into the same bytes",
+                kind.name()
+            ),
+        }
+    }
+}
+
+/// Checks a frame about to be sent.
+pub fn check_frame(frame: &[u8]) -> Result<(), OutboundError> {
+    let (message, trailer) =
+        Message::decode(frame).map_err(|err| OutboundError::Undecodable(err.to_string()))?;
+    let kind = message.kind();
+    validate_message(&message).map_err(|err| OutboundError::Invalid(kind, err))?;
+    let encoded = match (trailer.node_id, trailer.interest, trailer.period) {
+        (Some(node_id), Some(interest), Some(period)) => {
+            message.encode_from(node_id, &interest, period, trailer.network.as_deref())
+        }
+        _ => message.encode(),
+    };
+    if encoded != frame {
+        return Err(OutboundError::RoundTrip(kind));
+    }
+    Ok(())
+}
+
+/// The outbound check of a transport, and the frames it refused.
+#[derive(Debug)]
+pub struct OutboundCheck {
+    fatal: bool,
+    time_start: Arc<Instant>,
+    refused: u64,
+}
+
+impl OutboundCheck {
+    /// Creates a check logging against `time_start`, panicking on a failure when `fatal`.
+    pub fn new(fatal: bool, time_start: Arc<Instant>) -> Self {
+        Self {
+            fatal,
+            time_start,
+            refused: 0,
+        }
+    }
+
+    /// Determines whether `frame` may be sent to `to`, logging why not.
+    ///
+    /// # Panics
+    ///
+    /// When the frame fails the check and the check is fatal.
+    pub fn admit(&mut self, to: SocketAddr, frame: &[u8]) -> bool {
+        let Err(err) = check_frame(frame) else {
+            return true;
+        };
+        if self.fatal {
+            panic!(
+                "Outbound validation: refusing to send to \"{}\": {}",
+                to, err
+            );
+        }
+        self.refused += 1;
+        let formatted_msg = format!("WARNING: Not sending to \"{}\": {}", to, err);
+        print_event(self.time_start.clone(), &formatted_msg);
+        false
+    }
+
+    /// Returns the number of frames refused so far.
+    pub fn refused(&self) -> u64 {
+        self.refused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::one_of_each;
+    use crate::participant::identity::NodeId;
+    use crate::participant::test_support::addr;
+    use std::time::Duration;
+
+    #[test]
+    fn every_well_formed_frame_passes() {
+        for message in one_of_each() {
+            assert_eq!(check_frame(&message.encode()), Ok(()), "{:?}", message);
+        }
+        let handshake = Message::PublicAddress(addr(8080)).encode_from(
+            NodeId(7),
+            &Some(vec!["news".to_owned()]),
+            Duration::from_secs(1),
+            Some("lab"),
+        );
+        assert_eq!(check_frame(&handshake), Ok(()));
+    }
+
+    #[test]
+    fn a_broken_frame_is_refused_with_its_reason() {
+        assert!(matches!(
+            check_frame(&[0xff; 4]),
+            Err(OutboundError::Undecodable(_))
+        ));
+
+        let nested = Message::Batch(vec![Message::Batch(Vec::new())]);
+        let err = check_frame(&nested.encode()).unwrap_err();
+        assert_eq!(
+            err,
+            OutboundError::Invalid(MessageKind::Batch, BuildError::NestedBatch)
+        );
+        assert_eq!(
+            err.to_string(),
+            "a Batch frame is invalid: a batch can not hold another batch"
+        );
+
+        let mut padded = Message::Text("hello".to_owned()).encode();
+        padded.push(0);
+        let err = check_frame(&padded).unwrap_err();
+        assert_eq!(err, OutboundError::RoundTrip(MessageKind::Text));
+        assert_eq!(
+            err.to_string(),
+            "a Text frame does not encode back into the same bytes"
+        );
+    }
+
+    #[test]
+    fn a_refused_frame_is_counted_and_not_sent() {
+        let mut check = OutboundCheck::new(false, Arc::new(Instant::now()));
+        assert!(check.admit(addr(8080), &Message::PushParticipantsList.encode()));
+        assert!(!check.admit(addr(8080), &[0xff; 4]));
+        assert_eq!(check.refused(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Outbound validation: refusing to send to \"127.0.0.1:8080\"")]
+    fn a_fatal_check_panics_on_a_refused_frame() {
+        let mut check = OutboundCheck::new(true, Arc::new(Instant::now()));
+        check.admit(addr(8080), &[0xff; 4]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Gossip Payloads.
+//!
+//! Every period a participant broadcasts a generated text. The old `random message <n>` said
+//! nothing of its sender once the logs of several nodes were interleaved, so the text is now
+//! structured:
+//!
+//! ```plaintext
+//! msg tick=42 from=alice rnd=517
+//! ```
+//!
+//! `tick` counts the broadcast ticks of the sender since it started, `from` is its
+//! `--node-label` or, failing that, its advertised address, and `rnd` is the random part the
+//! old text had. A receiver recognizing the form logs it as `tick 42 from alice`; any other
+//! text, such as one from an older build, is logged as it came. `--legacy-payload` sends the
+//! old text instead.
+
+use crate::printer::sanitize_for_log;
+
+use std::fmt;
+
+/// The first word of a structured gossip text.
+const PAYLOAD_TAG: &str = "msg";
+
+/// The fields of a structured gossip text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedPayload {
+    /// The broadcast tick of the sender the text went out on.
+    pub tick: u64,
+    /// The label or advertised address of the sender.
+    pub from: String,
+    /// The random part, if the text has one.
+    pub rnd: Option<u32>,
+}
+
+/// Formats the compact form logged by receivers, such as `tick 42 from alice`.
+impl fmt::Display for ParsedPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tick {} from {}", self.tick, self.from)
+    }
+}
+
+/// Generates the gossip text of a broadcast tick.
+///
+/// # Parameters
+///
+/// * `tick` - The number of the tick, counted from 1 since the start of the participant.
+/// * `from` - The label or advertised address of the participant.
+/// * `rnd` - The random part of the text.
+/// * `legacy` - Whether to generate the unstructured `random message <n>` instead.
+pub fn default_payload(tick: u64, from: &str, rnd: u32, legacy: bool) -> String {
+    if legacy {
+        return format!("random message {}", rnd);
+    }
+    format!("{} tick={} from={} rnd={}", PAYLOAD_TAG, tick, from, rnd)
+}
+
+/// Reads the fields of a structured gossip text.
+///
+/// The fields may come in any order and `rnd` may be missing; fields this build does not know
+/// are skipped, so a later build can add some. A text that does not start with `msg`, lacks
+/// `tick` or `from`, or has a word that is not a `key=value` pair or a known field given twice
+/// or with an unreadable value is not one, and gives `None`.
+pub fn parse_default_payload(text: &str) -> Option<ParsedPayload> {
+    let mut words = text.split_whitespace();
+    if words.next()? != PAYLOAD_TAG {
+        return None;
+    }
+    let (mut tick, mut from, mut rnd) = (None, None, None);
+    for word in words {
+        let (key, value) = word.split_once('=')?;
+        match key {
+            "tick" if tick.is_none() => tick = Some(value.parse().ok()?),
+            "from" if from.is_none() && !value.is_empty() => from = Some(value.to_owned()),
+            "rnd" if rnd.is_none() => rnd = Some(value.parse().ok()?),
+            "tick" | "from" | "rnd" => return None,
+            _ => {}
+        }
+    }
+    Some(ParsedPayload {
+        tick: tick?,
+        from: from?,
+        rnd,
+    })
+}
+
+/// Renders a received gossip text for the logs: the compact form of a structured text, and
+/// any other text as it came, escaped by `sanitize_for_log` either way.
+pub fn describe_payload(text: &str) -> String {
+    match parse_default_payload(text) {
+        Some(parsed) => sanitize_for_log(&parsed.to_string()).into_owned(),
+        None => sanitize_for_log(text).into_owned(),
+    }
+}
@@ -0,0 +1,303 @@
+//! Recent Peers Cache.
+//!
+//! This module keeps a small, persistent list of the most recently handshaked peer addresses
+//! so that a restarted participant can find its way back into the network even when its
+//! explicit `--connect` target is gone (or when no target was given at all).
+//!
+//! The cache is a plain text file with one socket address per line, most recently seen first.
+//! It lives in the platform cache directory, in a file named after the listen port and the
+//! `--network` name, so several local participants, of one network or of several, never
+//! overwrite each other's cache. Lines that fail to parse are skipped, which makes a partially
+//! written or hand-edited file harmless.
+
+use crate::participant::utils::same_addr;
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Default number of peer addresses remembered by the cache.
+pub const DEFAULT_CAPACITY: usize = 10;
+
+/// A bounded, most-recently-seen-first list of peer addresses backed by a file.
+///
+/// A cache created with [`PeerCache::disabled`] never reads from or writes to disk, which
+/// keeps call sites free of `Option` juggling when the cache is turned off.
+#[derive(Debug)]
+pub struct PeerCache {
+    path: Option<PathBuf>,
+    addrs: VecDeque<SocketAddr>,
+    capacity: usize,
+}
+
+impl PeerCache {
+    /// Loads the cache belonging to the participant listening on `port` in `network`.
+    ///
+    /// A missing or unreadable file results in an empty cache; invalid lines are ignored.
+    ///
+    /// # Parameters
+    ///
+    /// * `port` - The listen port of the participant owning the cache.
+    /// * `network` - The `--network` name of the participant, if any.
+    pub fn load(port: u16, network: Option<&str>) -> Self {
+        Self::at(cache_path(port, network))
+    }
+
+    /// Loads the cache kept in the file at `path`.
+    fn at(path: PathBuf) -> Self {
+        let addrs = fs::read_to_string(&path)
+            .map(|content| parse_cache(&content, DEFAULT_CAPACITY))
+            .unwrap_or_default();
+
+        Self {
+            path: Some(path),
+            addrs,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    /// Verifies that the cache file of the participant listening on `port` in `network` can be
+    /// read and written, or created when there is none yet, without creating anything.
+    ///
+    /// # Parameters
+    ///
+    /// * `port` - The listen port of the participant owning the cache.
+    /// * `network` - The `--network` name of the participant, if any.
+    pub fn check(port: u16, network: Option<&str>) -> io::Result<()> {
+        check_file(&cache_path(port, network))
+    }
+
+    /// Creates a cache that neither reads nor writes anything.
+    pub fn disabled() -> Self {
+        Self {
+            path: None,
+            addrs: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    /// Records a successful handshake with the peer at `addr` and persists the cache.
+    ///
+    /// The address moves to the front of the list; the oldest entry is evicted when the
+    /// capacity is exceeded.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the peer.
+    pub fn record(&mut self, addr: SocketAddr) {
+        if self.path.is_none() {
+            return;
+        }
+
+        self.addrs.retain(|known| !same_addr(*known, addr));
+        self.addrs.push_front(addr);
+        self.addrs.truncate(self.capacity);
+        self.save();
+    }
+
+    /// Returns the cached addresses, most recently seen first.
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        self.addrs.iter().copied().collect()
+    }
+
+    /// Writes the cache to disk, silently giving up if the file cannot be written.
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+
+        let content = self
+            .addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let _ = fs::write(path, content);
+    }
+}
+
+/// Parses the cache file content, keeping at most `capacity` valid addresses in file order.
+fn parse_cache(content: &str, capacity: usize) -> VecDeque<SocketAddr> {
+    let mut addrs = VecDeque::with_capacity(capacity);
+    for addr in content
+        .lines()
+        .filter_map(|line| line.trim().parse::<SocketAddr>().ok())
+    {
+        if addrs.len() == capacity {
+            break;
+        }
+        if !addrs.iter().any(|known| same_addr(*known, addr)) {
+            addrs.push_back(addr);
+        }
+    }
+    addrs
+}
+
+/// Verifies that the file at `path` can be read and written if it exists, or that the
+/// directories up to it could be created along with it otherwise.
+fn check_file(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(path)
+            .map(|_| ());
+    }
+    // The closest directory that exists has to let the rest be created in it.
+    let mut dir = path.parent();
+    while let Some(candidate) = dir {
+        if candidate.as_os_str().is_empty() {
+            return writable_dir(Path::new("."));
+        }
+        if candidate.exists() {
+            return writable_dir(candidate);
+        }
+        dir = candidate.parent();
+    }
+    Ok(())
+}
+
+/// Verifies that `dir` is a directory files can be created in.
+fn writable_dir(dir: &Path) -> io::Result<()> {
+    if !fs::metadata(dir)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+    let dir = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: `dir` is a valid NUL-terminated string that outlives the call.
+    if unsafe { libc::access(dir.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Derives the cache file location for the participant listening on `port` in `network`.
+///
+/// Follows `$XDG_CACHE_HOME`, then `$HOME/.cache`, then the system temporary directory.
+pub fn cache_path(port: u16, network: Option<&str>) -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache_path_in(&base, port, network)
+}
+
+/// Derives the cache file location in the cache directory `base`, see `cache_path`.
+fn cache_path_in(base: &Path, port: u16, network: Option<&str>) -> PathBuf {
+    let name = match network {
+        Some(network) => format!("peers-{}-{}.cache", network, port),
+        None => format!("peers-{}.cache", port),
+    };
+    base.join("gossip-p2p").join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::{addr, TempDir};
+
+    #[test]
+    fn keeps_the_most_recently_seen_first() {
+        let dir = TempDir::new("peer-cache-order");
+        let path = dir.0.join("peers.cache");
+        let mut cache = PeerCache::at(path.clone());
+        for port in [8080, 8081, 8082] {
+            cache.record(addr(port));
+        }
+        assert_eq!(cache.addresses(), [addr(8082), addr(8081), addr(8080)]);
+
+        // Seeing a peer again moves it to the front, once.
+        cache.record(addr(8080));
+        assert_eq!(cache.addresses(), [addr(8080), addr(8082), addr(8081)]);
+        // The IPv4-mapped form is the same peer.
+        cache.record("[::ffff:127.0.0.1]:8081".parse().unwrap());
+        assert_eq!(cache.addresses().len(), 3);
+
+        assert_eq!(PeerCache::at(path).addresses(), cache.addresses());
+    }
+
+    #[test]
+    fn evicts_the_oldest_beyond_the_capacity() {
+        let dir = TempDir::new("peer-cache-capacity");
+        let mut cache = PeerCache::at(dir.0.join("peers.cache"));
+        for port in 0..DEFAULT_CAPACITY as u16 + 3 {
+            cache.record(addr(9000 + port));
+        }
+        let addrs = cache.addresses();
+        assert_eq!(addrs.len(), DEFAULT_CAPACITY);
+        assert_eq!(addrs[0], addr(9000 + DEFAULT_CAPACITY as u16 + 2));
+        assert!(!addrs.contains(&addr(9002)));
+        assert!(addrs.contains(&addr(9003)));
+    }
+
+    #[test]
+    fn tolerates_a_corrupt_file() {
+        let content = "127.0.0.1:1\n\ngarbage\n  127.0.0.1:2  \n127.0.0.1:1\n[::1]:3\n\u{0}\u{ff}";
+        assert_eq!(
+            parse_cache(content, DEFAULT_CAPACITY),
+            [addr(1), addr(2), "[::1]:3".parse().unwrap()]
+        );
+        assert_eq!(parse_cache(content, 1), [addr(1)]);
+
+        let dir = TempDir::new("peer-cache-corrupt");
+        let path = dir.0.join("peers.cache");
+        fs::write(&path, [0xff, 0xfe, 0x00, 0x80]).unwrap();
+        assert!(PeerCache::at(path).addresses().is_empty());
+    }
+
+    #[test]
+    fn a_disabled_cache_writes_nothing() {
+        let mut cache = PeerCache::disabled();
+        cache.record(addr(8080));
+        cache.save();
+        assert!(cache.addresses().is_empty());
+    }
+
+    #[test]
+    fn derives_the_path_from_the_port_and_network() {
+        let base = Path::new("/cache");
+        assert_eq!(
+            cache_path_in(base, 8080, None),
+            Path::new("/cache/gossip-p2p/peers-8080.cache")
+        );
+        assert_eq!(
+            cache_path_in(base, 8080, Some("lab")),
+            Path::new("/cache/gossip-p2p/peers-lab-8080.cache")
+        );
+        assert_ne!(
+            cache_path_in(base, 8080, Some("lab")),
+            cache_path_in(base, 8081, Some("lab"))
+        );
+        assert_ne!(
+            cache_path_in(base, 8080, Some("lab")),
+            cache_path_in(base, 8080, Some("prod"))
+        );
+    }
+
+    #[test]
+    fn checks_without_creating_anything() {
+        let dir = TempDir::new("peer-cache-check");
+        let path = dir.0.join("nested").join("gossip-p2p").join("peers.cache");
+        check_file(&path).unwrap();
+        assert!(!dir.0.join("nested").exists());
+
+        fs::write(dir.0.join("peers.cache"), "127.0.0.1:1").unwrap();
+        check_file(&dir.0.join("peers.cache")).unwrap();
+
+        // A file where a directory should be can not hold the cache.
+        let blocked = dir.0.join("peers.cache").join("peers.cache");
+        assert!(check_file(&blocked).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Gossip Period.
+//!
+//! A participant broadcasts a random message every period. The period is either fixed, or
+//! adaptive with `--period=auto:<base>`: the more peers a participant has, the longer it waits
+//! between two broadcasts, so the messages each participant processes per second stay roughly
+//! the same as the network grows. The adaptive period is recomputed on every broadcast tick from
+//! the current number of connected peers.
+
+use std::fmt;
+use std::time::Duration;
+
+/// How the period between two broadcasts is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GossipPeriod {
+    /// The same period whatever the size of the network.
+    Fixed(Duration),
+    /// A period following the number of peers, see `effective_period`.
+    Adaptive {
+        base: Duration,
+        min: Duration,
+        max: Duration,
+    },
+}
+
+impl GossipPeriod {
+    /// Returns the period to wait before the next broadcast.
+    ///
+    /// # Parameters
+    ///
+    /// * `peers` - The number of connected peers.
+    pub fn for_peers(&self, peers: usize) -> Duration {
+        match *self {
+            GossipPeriod::Fixed(period) => period,
+            GossipPeriod::Adaptive { base, min, max } => effective_period(base, peers, min, max),
+        }
+    }
+
+    /// Returns the shortest period this setting can lead to, with two peers or fewer.
+    pub fn shortest(&self) -> Duration {
+        match *self {
+            GossipPeriod::Fixed(period) => period,
+            // Not `clamp`, which panics on bounds the configuration checks have yet to reject.
+            GossipPeriod::Adaptive { base, min, max } => base.max(min).min(max),
+        }
+    }
+}
+
+impl fmt::Display for GossipPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GossipPeriod::Fixed(period) => write!(f, "fixed {:?}", period),
+            GossipPeriod::Adaptive { base, min, max } => {
+                write!(
+                    f,
+                    "adaptive from {:?}, between {:?} and {:?}",
+                    base, min, max
+                )
+            }
+        }
+    }
+}
+
+/// Computes the adaptive period: `base × log2(max(peers, 2))`, clamped between `min` and `max`.
+///
+/// Up to two peers the period is `base` itself; it then grows by `base` every time the number
+/// of peers doubles.
+///
+/// # Parameters
+///
+/// * `base` - The period with two peers or fewer.
+/// * `peers` - The number of connected peers.
+/// * `min` - The shortest period returned.
+/// * `max` - The longest period returned, at least `min`.
+pub fn effective_period(base: Duration, peers: usize, min: Duration, max: Duration) -> Duration {
+    let factor = (peers.max(2) as f64).log2();
+    Duration::try_from_secs_f64(base.as_secs_f64() * factor)
+        .unwrap_or(max)
+        .clamp(min, max)
+}
+
+/// The relative change of the effective period that is worth a log line.
+pub const LOGGED_PERIOD_CHANGE: f64 = 0.2;
+
+/// Determines whether `current` differs from the last logged period by more than
+/// `LOGGED_PERIOD_CHANGE`, so the adaptation is visible without a line on every tick.
+///
+/// # Parameters
+///
+/// * `logged` - The period logged last.
+/// * `current` - The period just computed.
+pub fn differs_notably(logged: Duration, current: Duration) -> bool {
+    let logged = logged.as_secs_f64();
+    (current.as_secs_f64() - logged).abs() > logged * LOGGED_PERIOD_CHANGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_secs(1);
+    const MIN: Duration = Duration::from_millis(500);
+    const MAX: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn is_the_base_up_to_two_peers() {
+        for peers in [0, 1, 2] {
+            assert_eq!(effective_period(BASE, peers, MIN, MAX), BASE, "{}", peers);
+        }
+    }
+
+    #[test]
+    fn grows_by_the_base_every_time_the_peers_double() {
+        assert_eq!(effective_period(BASE, 4, MIN, MAX), Duration::from_secs(2));
+        assert_eq!(effective_period(BASE, 8, MIN, MAX), Duration::from_secs(3));
+        let thousand = effective_period(BASE, 1000, MIN, MAX);
+        assert!(
+            (thousand.as_secs_f64() - 1000f64.log2()).abs() < 1e-6,
+            "{:?}",
+            thousand
+        );
+    }
+
+    #[test]
+    fn stays_between_the_bounds() {
+        // Just below and at the bounds nothing changes; beyond them the bound wins.
+        assert_eq!(effective_period(MIN, 2, MIN, MAX), MIN);
+        assert_eq!(
+            effective_period(Duration::from_millis(100), 2, MIN, MAX),
+            MIN
+        );
+        assert_eq!(effective_period(Duration::from_secs(30), 4, MIN, MAX), MAX);
+        assert_eq!(
+            effective_period(Duration::from_secs(30), 1000, MIN, MAX),
+            MAX
+        );
+        assert_eq!(
+            effective_period(Duration::from_secs(29), 4, MIN, MAX),
+            Duration::from_secs(58)
+        );
+        assert_eq!(effective_period(Duration::MAX, 1000, MIN, MAX), MAX);
+        assert_eq!(effective_period(BASE, usize::MAX, MIN, MAX), MAX);
+    }
+
+    #[test]
+    fn fixed_ignores_the_peers() {
+        let period = GossipPeriod::Fixed(Duration::from_secs(5));
+        assert_eq!(period.for_peers(0), Duration::from_secs(5));
+        assert_eq!(period.for_peers(1000), Duration::from_secs(5));
+        assert_eq!(period.shortest(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn adaptive_follows_the_peers() {
+        let period = GossipPeriod::Adaptive {
+            base: BASE,
+            min: MIN,
+            max: MAX,
+        };
+        assert_eq!(period.for_peers(4), Duration::from_secs(2));
+        assert_eq!(period.shortest(), BASE);
+
+        // Bounds the configuration checks will reject do not panic.
+        let inverted = GossipPeriod::Adaptive {
+            base: BASE,
+            min: MAX,
+            max: MIN,
+        };
+        assert_eq!(inverted.shortest(), MIN);
+    }
+
+    #[test]
+    fn logs_changes_beyond_a_fifth() {
+        let logged = Duration::from_secs(10);
+        assert!(!differs_notably(logged, logged));
+        assert!(!differs_notably(logged, Duration::from_secs(12)));
+        assert!(!differs_notably(logged, Duration::from_secs(8)));
+        assert!(differs_notably(logged, Duration::from_millis(12_001)));
+        assert!(differs_notably(logged, Duration::from_millis(7_999)));
+        assert!(differs_notably(Duration::ZERO, Duration::from_nanos(1)));
+    }
+}
@@ -0,0 +1,262 @@
+//! Inbound Message Policy.
+//!
+//! A participant talks to peers in several states: inbound connections that have not announced
+//! a public address yet, dials that are still connecting, established participants, half-open
+//! suspects and observers. This module decides, in one table, what happens to a message of each
+//! `MessageKind` depending on the state of its sender, so the receive path consults the table
+//! once instead of repeating state checks in every handler.
+
+use crate::participant::message::MessageKind;
+
+use std::fmt;
+
+/// The state of a peer, as seen by the receive path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerState {
+    /// An inbound connection that has not announced its public address yet.
+    Unannounced,
+    /// A dial of this participant whose connection is not established yet.
+    Connecting,
+    /// A participant with an established connection.
+    Established,
+    /// An established participant that left too many messages unanswered.
+    Suspect,
+    /// An established participant whose payloads are muted from the console.
+    Muted,
+    /// An observer watching the network through this participant.
+    Observer,
+}
+
+impl PeerState {
+    /// Returns the human-readable name of this state.
+    pub fn name(self) -> &'static str {
+        match self {
+            PeerState::Unannounced => "unannounced",
+            PeerState::Connecting => "connecting",
+            PeerState::Established => "established",
+            PeerState::Suspect => "suspect",
+            PeerState::Muted => "muted",
+            PeerState::Observer => "observer",
+        }
+    }
+}
+
+impl fmt::Display for PeerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What the receive path does with a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboundPolicy {
+    /// The message is counted and handled.
+    Accept,
+    /// The message is dropped without being counted, and the peer stays in its state.
+    Ignore,
+    /// The message is dropped and only counted as a muted drop of the peer.
+    Mute,
+    /// The message is never sent by a well-behaved peer in this state; the connection is closed.
+    Violation,
+}
+
+/// Returns the policy for a message of `kind` sent by a peer in `state`.
+///
+/// - Unannounced and connecting peers may only complete the handshake, by announcing their
+///   public address or subscribing as an observer; the rest of their messages is ignored until
+///   then.
+/// - Established participants may send every message meant for a participant. Messages meant
+///   for observers are violations, since no participant ever sends them.
+/// - Suspects may be recovering, so their protocol messages and responses are accepted and
+///   bring them back to established, but their application payloads and requests are ignored
+///   until then.
+/// - Muted participants keep their connection and their part in discovery, so their protocol
+///   messages and responses are accepted, but their application payloads and requests are
+///   muted: never logged, relayed nor handed to callbacks.
+/// - Observers only ever subscribe; anything else they send is ignored and merely keeps them
+///   alive.
+///
+/// # Parameters
+///
+/// * `state` - The state of the sender.
+/// * `kind` - The kind of the received message.
+pub fn inbound_policy(state: PeerState, kind: MessageKind) -> InboundPolicy {
+    use InboundPolicy::*;
+    use MessageKind::*;
+
+    match (state, kind) {
+        (PeerState::Unannounced, PublicAddress | ObserverHello) => Accept,
+        (PeerState::Unannounced, ObserverSnapshot | ObserverUpdate | ObserverRejected) => Violation,
+        (PeerState::Unannounced, _) => Ignore,
+
+        (PeerState::Connecting, PublicAddress) => Accept,
+        (PeerState::Connecting, ObserverSnapshot | ObserverUpdate | ObserverRejected) => Violation,
+        (PeerState::Connecting, _) => Ignore,
+
+        (PeerState::Established, ObserverSnapshot | ObserverUpdate | ObserverRejected) => Violation,
+        (PeerState::Established, ObserverHello) => Ignore,
+        (PeerState::Established, _) => Accept,
+
+        (PeerState::Suspect, ObserverSnapshot | ObserverUpdate | ObserverRejected) => Violation,
+        (
+            PeerState::Suspect,
+            Text | RoundText | Topic | SequencedTopic | VersionedTopic | Request | ObserverHello,
+        ) => Ignore,
+        (PeerState::Suspect, _) => Accept,
+
+        (PeerState::Muted, ObserverSnapshot | ObserverUpdate | ObserverRejected) => Violation,
+        (
+            PeerState::Muted,
+            Text | RoundText | Topic | SequencedTopic | VersionedTopic | Request,
+        ) => Mute,
+        (PeerState::Muted, ObserverHello) => Ignore,
+        (PeerState::Muted, _) => Accept,
+
+        (PeerState::Observer, ObserverHello) => Accept,
+        (PeerState::Observer, _) => Ignore,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: [PeerState; 6] = [
+        PeerState::Unannounced,
+        PeerState::Connecting,
+        PeerState::Established,
+        PeerState::Suspect,
+        PeerState::Muted,
+        PeerState::Observer,
+    ];
+
+    /// The expected policy of every kind, one letter per state in the order of `STATES`:
+    /// `A`ccept, `I`gnore, `M`ute or `V`iolation.
+    const EXPECTED: [(MessageKind, &str); MessageKind::COUNT] = [
+        (MessageKind::PublicAddress, "AAAAAI"),
+        (MessageKind::PushParticipantsList, "IIAAAI"),
+        (MessageKind::PullParticipantsList, "IIAAAI"),
+        (MessageKind::Text, "IIAIMI"),
+        (MessageKind::ObserverHello, "AIIIIA"),
+        (MessageKind::ObserverSnapshot, "VVVVVI"),
+        (MessageKind::ObserverUpdate, "VVVVVI"),
+        (MessageKind::ObserverRejected, "VVVVVI"),
+        (MessageKind::PleaseIntroduce, "IIAAAI"),
+        (MessageKind::IntroductionRequest, "IIAAAI"),
+        (MessageKind::Topic, "IIAIMI"),
+        (MessageKind::SharedPeers, "IIAAAI"),
+        (MessageKind::Request, "IIAIMI"),
+        (MessageKind::Response, "IIAAAI"),
+        (MessageKind::Batch, "IIAAAI"),
+        (MessageKind::RoundText, "IIAIMI"),
+        (MessageKind::SequencedTopic, "IIAIMI"),
+        (MessageKind::Probe, "IIAAAI"),
+        (MessageKind::ProbeReply, "IIAAAI"),
+        (MessageKind::InterestUpdate, "IIAAAI"),
+        (MessageKind::ListRequest, "IIAAAI"),
+        (MessageKind::ListReply, "IIAAAI"),
+        (MessageKind::ClockRequest, "IIAAAI"),
+        (MessageKind::ClockReply, "IIAAAI"),
+        (MessageKind::VersionedTopic, "IIAIMI"),
+        (MessageKind::SchemaUpdate, "IIAAAI"),
+        (MessageKind::CompactListReply, "IIAAAI"),
+    ];
+
+    fn letter(policy: InboundPolicy) -> char {
+        match policy {
+            InboundPolicy::Accept => 'A',
+            InboundPolicy::Ignore => 'I',
+            InboundPolicy::Mute => 'M',
+            InboundPolicy::Violation => 'V',
+        }
+    }
+
+    #[test]
+    fn every_state_and_kind_follows_the_expectation_matrix() {
+        for (index, (kind, expected)) in EXPECTED.iter().enumerate() {
+            assert_eq!(*kind, MessageKind::ALL[index], "the matrix follows ALL");
+            let actual: String = STATES
+                .iter()
+                .map(|state| letter(inbound_policy(*state, *kind)))
+                .collect();
+            assert_eq!(&actual, expected, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn unannounced_and_connecting_peers_may_only_complete_the_handshake() {
+        for state in [PeerState::Unannounced, PeerState::Connecting] {
+            let accepted: Vec<MessageKind> = MessageKind::ALL
+                .into_iter()
+                .filter(|kind| inbound_policy(state, *kind) == InboundPolicy::Accept)
+                .collect();
+            assert!(accepted.contains(&MessageKind::PublicAddress), "{}", state);
+            assert!(
+                accepted.iter().all(|kind| matches!(
+                    kind,
+                    MessageKind::PublicAddress | MessageKind::ObserverHello
+                )),
+                "{}: {:?}",
+                state,
+                accepted
+            );
+        }
+    }
+
+    #[test]
+    fn a_suspect_may_recover_but_its_payloads_are_ignored() {
+        for kind in [
+            MessageKind::Probe,
+            MessageKind::ProbeReply,
+            MessageKind::Response,
+        ] {
+            assert_eq!(
+                inbound_policy(PeerState::Suspect, kind),
+                InboundPolicy::Accept
+            );
+        }
+        for kind in [MessageKind::Text, MessageKind::Topic, MessageKind::Request] {
+            assert_eq!(
+                inbound_policy(PeerState::Suspect, kind),
+                InboundPolicy::Ignore
+            );
+            assert_eq!(inbound_policy(PeerState::Muted, kind), InboundPolicy::Mute);
+            assert_eq!(
+                inbound_policy(PeerState::Established, kind),
+                InboundPolicy::Accept
+            );
+        }
+    }
+
+    #[test]
+    fn no_participant_sends_observer_messages() {
+        for state in &STATES[..5] {
+            for kind in [
+                MessageKind::ObserverSnapshot,
+                MessageKind::ObserverUpdate,
+                MessageKind::ObserverRejected,
+            ] {
+                assert_eq!(inbound_policy(*state, kind), InboundPolicy::Violation);
+            }
+        }
+        assert!(MessageKind::ALL
+            .into_iter()
+            .all(|kind| inbound_policy(PeerState::Observer, kind) != InboundPolicy::Violation));
+    }
+
+    #[test]
+    fn states_are_displayed_by_name() {
+        let names: Vec<String> = STATES.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            names,
+            [
+                "unannounced",
+                "connecting",
+                "established",
+                "suspect",
+                "muted",
+                "observer"
+            ]
+        );
+    }
+}
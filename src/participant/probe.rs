@@ -0,0 +1,390 @@
+//! Latency Probes.
+//!
+//! The `probe` console command measures the one-hop round trip to a connected peer without
+//! touching application payloads. A `ProbeSession` sends one `Message::Probe` per interval,
+//! each with an id of its own, and the peer echoes it back at once as a `Message::ProbeReply`;
+//! neither waits for a batch window. A reply gives the round trip of its probe, and a probe
+//! unanswered within `PROBE_TIMEOUT` counts as lost.
+//!
+//! Once every probe is answered or lost, or when the run ends early because the peer
+//! disconnected or the participant stops, the session is summed up as a `ProbeSummary`:
+//!
+//! ```plaintext
+//! Probe of "127.0.0.1:8080": 20 sent, 19 received, 5.0% loss, rtt min/avg/p95/max = 0.081/0.140/0.322/0.410 ms
+//! ```
+//!
+//! A participant runs one session at a time. It numbers its runs, and `probe-status` tells
+//! how far the running one has come or sums up the last one, so the `probe` command of the
+//! binary can start a run through the admin socket and wait for its summary:
+//!
+//! ```plaintext
+//! Probe run 3 of "127.0.0.1:8080" is running: 12 sent, 11 received
+//! Probe run 3 is done: Probe of "127.0.0.1:8080": 20 sent, 19 received, 5.0% loss, ...
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// How long a probe waits for its reply before it counts as lost.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The number of probes of a `probe` command without a count.
+pub const DEFAULT_PROBE_COUNT: u32 = 10;
+
+/// The time between two probes of a `probe` command without an interval.
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A run of probes to one peer.
+#[derive(Debug)]
+pub struct ProbeSession {
+    peer: SocketAddr,
+    count: u32,
+    interval: Duration,
+    started: Instant,
+    sent: u32,
+    outstanding: HashMap<u64, Instant>,
+    rtts: Vec<Duration>,
+    note: Option<String>,
+}
+
+impl ProbeSession {
+    /// Starts a run of probes.
+    ///
+    /// # Parameters
+    ///
+    /// * `peer` - The public address of the probed peer.
+    /// * `count` - The number of probes to send.
+    /// * `interval` - The time between two probes.
+    /// * `now` - The current time.
+    pub fn new(peer: SocketAddr, count: u32, interval: Duration, now: Instant) -> Self {
+        Self {
+            peer,
+            count,
+            interval,
+            started: now,
+            sent: 0,
+            outstanding: HashMap::new(),
+            rtts: Vec::new(),
+            note: None,
+        }
+    }
+
+    /// Returns the public address of the probed peer.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Returns the time between two probes.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Records the send of the next probe at `now`, if any is left to send.
+    ///
+    /// # Returns
+    ///
+    /// The id of the probe and the time since the start of the run, in microseconds.
+    pub fn next_probe(&mut self, now: Instant) -> Option<(u64, u64)> {
+        if self.sent >= self.count || self.note.is_some() {
+            return None;
+        }
+        let id = u64::from(self.sent);
+        self.sent += 1;
+        self.outstanding.insert(id, now);
+        let offset = now.saturating_duration_since(self.started).as_micros();
+        Some((id, u64::try_from(offset).unwrap_or(u64::MAX)))
+    }
+
+    /// Records the reply to the probe `id` arriving at `now`.
+    ///
+    /// # Returns
+    ///
+    /// `false` when no probe with that id is waiting, because it was never sent or already
+    /// answered or lost.
+    pub fn reply(&mut self, id: u64, now: Instant) -> bool {
+        let Some(sent_at) = self.outstanding.remove(&id) else {
+            return false;
+        };
+        self.rtts.push(now.saturating_duration_since(sent_at));
+        true
+    }
+
+    /// Gives up on the probes sent `PROBE_TIMEOUT` or longer before `now`, which count as lost.
+    pub fn expire(&mut self, now: Instant) {
+        self.outstanding
+            .retain(|_, sent_at| now.saturating_duration_since(*sent_at) < PROBE_TIMEOUT);
+    }
+
+    /// Ends the run early: no probe is sent anymore and the unanswered ones count as lost.
+    ///
+    /// # Parameters
+    ///
+    /// * `note` - Why the run ended, shown with the summary.
+    pub fn abort(&mut self, note: &str) {
+        self.outstanding.clear();
+        self.note = Some(note.to_owned());
+    }
+
+    /// Determines whether every probe was sent and answered or lost.
+    pub fn is_done(&self) -> bool {
+        (self.sent >= self.count || self.note.is_some()) && self.outstanding.is_empty()
+    }
+
+    /// Sums up the run so far; the probes still waiting for their reply count as lost.
+    pub fn summary(&self) -> ProbeSummary {
+        let mut rtts = self.rtts.clone();
+        rtts.sort();
+        ProbeSummary {
+            peer: self.peer,
+            sent: self.sent,
+            rtts,
+            note: self.note.clone(),
+        }
+    }
+}
+
+/// The outcome of a run of probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeSummary {
+    /// The public address of the probed peer.
+    pub peer: SocketAddr,
+    /// The number of probes sent.
+    pub sent: u32,
+    /// The round trips of the answered probes, shortest first.
+    pub rtts: Vec<Duration>,
+    /// Why the run ended early, if it did.
+    pub note: Option<String>,
+}
+
+impl ProbeSummary {
+    /// Returns the number of answered probes.
+    pub fn received(&self) -> usize {
+        self.rtts.len()
+    }
+
+    /// Returns the share of the sent probes that were lost, in percent; 0 when none was sent.
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        let lost = f64::from(self.sent) - self.received() as f64;
+        lost * 100.0 / f64::from(self.sent)
+    }
+
+    /// Returns the average round trip, `None` when no probe was answered.
+    pub fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.rtts.len())
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(self.rtts.iter().sum::<Duration>() / count)
+    }
+
+    /// Returns the nearest-rank `percentile` of the round trips, `None` when no probe was
+    /// answered.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.rtts.is_empty() {
+            return None;
+        }
+        let rank = (percentile / 100.0 * self.rtts.len() as f64).ceil() as usize;
+        self.rtts.get(rank.clamp(1, self.rtts.len()) - 1).copied()
+    }
+}
+
+/// Formats the answer of the `probe` command to the run `run` it started.
+pub fn started_line(run: u64, peer: SocketAddr, count: u32, interval: Duration) -> String {
+    format!(
+        "Probe run {} of \"{}\" started: {} probes {:?} apart",
+        run, peer, count, interval
+    )
+}
+
+/// Formats the answer of `probe-status` while the run `run` is in progress.
+pub fn running_line(run: u64, summary: &ProbeSummary) -> String {
+    format!(
+        "Probe run {} of \"{}\" is running: {} sent, {} received",
+        run,
+        summary.peer,
+        summary.sent,
+        summary.received()
+    )
+}
+
+/// Formats the answer of `probe-status` once the run `run` is done.
+pub fn done_line(run: u64, summary: &ProbeSummary) -> String {
+    format!("Probe run {} is done: {}", run, summary)
+}
+
+/// Reads the number of the run a line of `started_line`, `running_line` or `done_line` is
+/// about.
+pub fn run_of(line: &str) -> Option<u64> {
+    line.strip_prefix("Probe run ")?
+        .split(' ')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Returns the summary in a line of `done_line`, if it is about the run `run`.
+pub fn done_summary(line: &str, run: u64) -> Option<&str> {
+    line.strip_prefix(&format!("Probe run {} is done: ", run))
+}
+
+/// Formats the summary as one line, with the round trips in milliseconds.
+impl fmt::Display for ProbeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Probe of \"{}\": {} sent, {} received, {:.1}% loss",
+            self.peer,
+            self.sent,
+            self.received(),
+            self.loss_percent()
+        )?;
+        let millis = |rtt: Duration| rtt.as_secs_f64() * 1000.0;
+        if let (Some(min), Some(average), Some(p95), Some(max)) = (
+            self.rtts.first(),
+            self.average(),
+            self.percentile(95.0),
+            self.rtts.last(),
+        ) {
+            write!(
+                f,
+                ", rtt min/avg/p95/max = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                millis(*min),
+                millis(average),
+                millis(p95),
+                millis(*max)
+            )?;
+        }
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8081))
+    }
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// Runs `count` probes 10ms apart, each answered after the round trip `rtts` gives it, or
+    /// lost when it gives none.
+    fn run(count: u32, rtts: impl Fn(u64) -> Option<u64>) -> ProbeSummary {
+        let start = Instant::now();
+        let mut session = ProbeSession::new(peer(), count, millis(10), start);
+        let mut replies = Vec::new();
+        for index in 0..u64::from(count) {
+            let sent = start + millis(10 * index);
+            let (id, offset) = session.next_probe(sent).expect("a probe is left");
+            assert_eq!((id, offset), (index, 10_000 * index));
+            if let Some(rtt) = rtts(index) {
+                replies.push((id, sent + millis(rtt)));
+            }
+        }
+        assert!(session.next_probe(start + millis(10_000)).is_none());
+        for (id, at) in replies {
+            assert!(session.reply(id, at));
+        }
+        session.expire(start + millis(10 * u64::from(count)) + PROBE_TIMEOUT);
+        assert!(session.is_done());
+        session.summary()
+    }
+
+    #[test]
+    fn every_answered_probe_gives_its_round_trip() {
+        let summary = run(20, |index| Some(index + 1));
+        assert_eq!((summary.sent, summary.received()), (20, 20));
+        assert_eq!(summary.loss_percent(), 0.0);
+        assert_eq!(summary.rtts.first(), Some(&millis(1)));
+        assert_eq!(summary.rtts.last(), Some(&millis(20)));
+        assert_eq!(summary.average(), Some(Duration::from_micros(10_500)));
+        assert_eq!(summary.percentile(95.0), Some(millis(19)));
+        assert_eq!(summary.percentile(50.0), Some(millis(10)));
+        assert_eq!(summary.percentile(0.0), Some(millis(1)));
+        assert_eq!(summary.percentile(100.0), Some(millis(20)));
+        assert_eq!(
+            summary.to_string(),
+            "Probe of \"127.0.0.1:8081\": 20 sent, 20 received, 0.0% loss, \
+             rtt min/avg/p95/max = 1.000/10.500/19.000/20.000 ms"
+        );
+    }
+
+    #[test]
+    fn unanswered_probes_are_lost_once_they_time_out() {
+        let summary = run(10, |index| (index % 2 == 0).then_some(5));
+        assert_eq!((summary.sent, summary.received()), (10, 5));
+        assert_eq!(summary.loss_percent(), 50.0);
+
+        let summary = run(4, |_| None);
+        assert_eq!(summary.received(), 0);
+        assert_eq!(summary.loss_percent(), 100.0);
+        assert_eq!(summary.average(), None);
+        assert_eq!(summary.percentile(95.0), None);
+        assert_eq!(
+            summary.to_string(),
+            "Probe of \"127.0.0.1:8081\": 4 sent, 0 received, 100.0% loss"
+        );
+    }
+
+    #[test]
+    fn a_late_duplicate_or_unknown_reply_is_not_counted() {
+        let start = Instant::now();
+        let mut session = ProbeSession::new(peer(), 2, millis(10), start);
+        let (first, _) = session.next_probe(start).unwrap();
+        assert!(!session.is_done());
+        session.expire(start + PROBE_TIMEOUT);
+        assert!(!session.reply(first, start + PROBE_TIMEOUT + millis(1)));
+        let (second, _) = session.next_probe(start + PROBE_TIMEOUT).unwrap();
+        assert!(session.reply(second, start + PROBE_TIMEOUT + millis(3)));
+        assert!(!session.reply(second, start + PROBE_TIMEOUT + millis(4)));
+        assert!(!session.reply(7, start + PROBE_TIMEOUT + millis(4)));
+        assert!(session.is_done());
+        let summary = session.summary();
+        assert_eq!((summary.sent, summary.rtts.clone()), (2, vec![millis(3)]));
+    }
+
+    #[test]
+    fn an_aborted_run_sends_no_more_and_loses_the_outstanding_probes() {
+        let start = Instant::now();
+        let mut session = ProbeSession::new(peer(), 10, millis(10), start);
+        let (id, _) = session.next_probe(start).unwrap();
+        assert!(session.reply(id, start + millis(2)));
+        session.next_probe(start + millis(10)).unwrap();
+        session.abort("the peer disconnected");
+        assert!(session.is_done());
+        assert!(session.next_probe(start + millis(20)).is_none());
+        let summary = session.summary();
+        assert_eq!((summary.sent, summary.received()), (2, 1));
+        assert!(summary.to_string().ends_with(" (the peer disconnected)"));
+    }
+
+    #[test]
+    fn the_status_lines_name_their_run() {
+        let summary = run(2, |_| Some(1));
+        let lines = [
+            started_line(3, peer(), 2, millis(10)),
+            running_line(3, &summary),
+            done_line(3, &summary),
+        ];
+        for line in &lines {
+            assert_eq!(run_of(line), Some(3), "{}", line);
+        }
+        assert_eq!(
+            done_summary(&lines[2], 3),
+            Some(summary.to_string().as_str())
+        );
+        assert_eq!(done_summary(&lines[2], 4), None);
+        assert_eq!(done_summary(&lines[1], 3), None);
+        assert_eq!(run_of("No probe has run"), None);
+    }
+}
@@ -0,0 +1,383 @@
+//! Address Provenance.
+//!
+//! An address that turns up in the storage of a participant may have been given on the command
+//! line, read from a file or heard from a peer, and the origin kept for introductions only
+//! remembers the last peer that listed it, until the dial settles. `ProvenanceBook` keeps a
+//! `Provenance` for every address the participant learns: the way it was first learned,
+//! when, and the distinct peers that listed it since.
+//!
+//! The record of an address is set the first time the address is seen and never replaced,
+//! however the address is learned again: a peer listing it later only adds to its
+//! corroborators. An address first seen in a participants list names the advertised address
+//! of the peer that listed it and the id of the list request it answered, as in `exchange`,
+//! when the list carried one. At most `MAX_CORROBORATORS` corroborators are kept, which is
+//! plenty to tell a lone rumor from a well-known peer.
+//!
+//! The records of the remembered peers are saved in the `--state-file` with them and restored
+//! as they were, so a restart does not claim to have learned every peer from the state file.
+//! A restored record keeps the wall-clock time it was learned at, but no longer the time into
+//! the run, which belonged to the previous one.
+
+use crate::participant::collections::{BoundedMap, Eviction};
+use crate::participant::utils::{same_addr, CanonicalAddr};
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The most corroborators kept in a record.
+pub const MAX_CORROBORATORS: usize = 16;
+
+/// Where an address was first learned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LearnedVia {
+    /// The `--connect` target.
+    Connect,
+    /// The recent peers cache.
+    PeerCache,
+    /// The peers restored from `--state-file` by a run that did not know where they came from.
+    StateFile,
+    /// A membership snapshot given with `--import-membership`.
+    Snapshot,
+    /// The participants list of the peer advertising `lister`, answering the list request
+    /// `request` if it carried an id.
+    List {
+        lister: SocketAddr,
+        request: Option<u64>,
+    },
+    /// The announcement of a peer that connected to this participant.
+    Inbound,
+    /// The introduction of the neighbor `via`.
+    Introduction { via: SocketAddr },
+}
+
+impl LearnedVia {
+    /// Returns the name of this source, as in the CSV export, the report and the state file.
+    pub fn name(self) -> &'static str {
+        match self {
+            LearnedVia::Connect => "connect",
+            LearnedVia::PeerCache => "peer_cache",
+            LearnedVia::StateFile => "state_file",
+            LearnedVia::Snapshot => "snapshot",
+            LearnedVia::List { .. } => "list",
+            LearnedVia::Inbound => "inbound",
+            LearnedVia::Introduction { .. } => "introduction",
+        }
+    }
+
+    /// Returns the peer the address was heard from, if it was heard from a peer.
+    pub fn peer(self) -> Option<SocketAddr> {
+        match self {
+            LearnedVia::List { lister, .. } => Some(lister),
+            LearnedVia::Introduction { via } => Some(via),
+            _ => None,
+        }
+    }
+
+    /// Returns the source named `name`, heard from `peer` in the answer to `request`.
+    pub fn parse(name: &str, peer: Option<SocketAddr>, request: Option<u64>) -> Option<Self> {
+        Some(match name {
+            "connect" => LearnedVia::Connect,
+            "peer_cache" => LearnedVia::PeerCache,
+            "state_file" => LearnedVia::StateFile,
+            "snapshot" => LearnedVia::Snapshot,
+            "list" => LearnedVia::List {
+                lister: peer?,
+                request,
+            },
+            "inbound" => LearnedVia::Inbound,
+            "introduction" => LearnedVia::Introduction { via: peer? },
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for LearnedVia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LearnedVia::Connect => write!(f, "the --connect target"),
+            LearnedVia::PeerCache => write!(f, "the recent peers cache"),
+            LearnedVia::StateFile => write!(f, "the state file"),
+            LearnedVia::Snapshot => write!(f, "the imported membership snapshot"),
+            LearnedVia::List {
+                lister,
+                request: Some(request),
+            } => write!(
+                f,
+                "the participants list of \"{}\", answering request {}",
+                lister, request
+            ),
+            LearnedVia::List {
+                lister,
+                request: None,
+            } => write!(f, "the participants list of \"{}\"", lister),
+            LearnedVia::Inbound => write!(f, "its own inbound connection"),
+            LearnedVia::Introduction { via } => write!(f, "the introduction of \"{}\"", via),
+        }
+    }
+}
+
+/// How an address was first learned, and who vouched for it since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Where the address was first learned from.
+    pub source: LearnedVia,
+    /// The time the address was first learned, in seconds since the Unix epoch.
+    pub learned_at: u64,
+    /// How long after the start of this run the address was first learned; `None` for a
+    /// record restored from an earlier run.
+    pub uptime: Option<Duration>,
+    /// The distinct peers that listed the address since, other than the one it came from.
+    pub corroborators: Vec<SocketAddr>,
+}
+
+impl Provenance {
+    /// Creates the record of an address learned from `source` now.
+    ///
+    /// # Parameters
+    ///
+    /// * `source` - Where the address came from.
+    /// * `learned_at` - The current time, in seconds since the Unix epoch.
+    /// * `uptime` - The time since the start of this run.
+    pub fn new(source: LearnedVia, learned_at: u64, uptime: Duration) -> Self {
+        Self {
+            source,
+            learned_at,
+            uptime: Some(uptime),
+            corroborators: Vec::new(),
+        }
+    }
+
+    /// Counts `peer` among the corroborators, unless it is the source or already counted.
+    fn corroborate(&mut self, peer: SocketAddr) {
+        let known = self
+            .source
+            .peer()
+            .is_some_and(|source| same_addr(source, peer))
+            || self
+                .corroborators
+                .iter()
+                .any(|corroborator| same_addr(*corroborator, peer));
+        if !known && self.corroborators.len() < MAX_CORROBORATORS {
+            self.corroborators.push(peer);
+        }
+    }
+}
+
+/// The provenance of the addresses a participant learned, by address.
+pub struct ProvenanceBook {
+    records: BoundedMap<CanonicalAddr, Provenance>,
+}
+
+impl ProvenanceBook {
+    /// Creates a book of at most `capacity` records; the one used least recently makes room
+    /// for a new one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: BoundedMap::new(capacity, Eviction::Lru, None),
+        }
+    }
+
+    /// Records that `addr` was learned as `provenance` tells, unless it was learned before.
+    ///
+    /// # Returns
+    ///
+    /// Whether this is the first sight of `addr`.
+    pub fn learned(&mut self, addr: SocketAddr, provenance: Provenance, now: Instant) -> bool {
+        let key = CanonicalAddr::from(addr);
+        if self.records.contains(&key, now) {
+            return false;
+        }
+        self.records.insert(key, provenance, now);
+        true
+    }
+
+    /// Records that the peer advertising `lister` listed `addr`: its first sight, or one more
+    /// corroborator.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The listed address.
+    /// * `first` - The record of the address if this is its first sight, naming `lister`.
+    /// * `lister` - The advertised address of the peer that sent the list.
+    /// * `now` - The current time.
+    pub fn listed(
+        &mut self,
+        addr: SocketAddr,
+        first: Provenance,
+        lister: SocketAddr,
+        now: Instant,
+    ) {
+        match self.records.get_mut(&addr.into(), now) {
+            Some(record) => record.corroborate(lister),
+            None => self.records.insert(addr.into(), first, now),
+        }
+    }
+
+    /// Returns the record of `addr`, if it was learned and not forgotten since.
+    pub fn get(&self, addr: SocketAddr) -> Option<&Provenance> {
+        self.records.get(&addr.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::state::{NodeState, RememberedPeer};
+    use crate::participant::test_support::addr;
+
+    fn every_source() -> [LearnedVia; 8] {
+        [
+            LearnedVia::Connect,
+            LearnedVia::PeerCache,
+            LearnedVia::StateFile,
+            LearnedVia::Snapshot,
+            LearnedVia::List {
+                lister: addr(9000),
+                request: Some(7),
+            },
+            LearnedVia::List {
+                lister: addr(9000),
+                request: None,
+            },
+            LearnedVia::Inbound,
+            LearnedVia::Introduction { via: addr(9001) },
+        ]
+    }
+
+    fn listed_by(lister: SocketAddr) -> Provenance {
+        Provenance::new(
+            LearnedVia::List {
+                lister,
+                request: None,
+            },
+            1_000,
+            Duration::from_secs(5),
+        )
+    }
+
+    #[test]
+    fn the_first_source_is_never_replaced() {
+        let now = Instant::now();
+        let mut book = ProvenanceBook::new(8);
+        let first = Provenance::new(LearnedVia::Connect, 1_000, Duration::ZERO);
+        assert!(book.learned(addr(8080), first.clone(), now));
+        assert!(!book.learned(
+            addr(8080),
+            Provenance::new(LearnedVia::Inbound, 2_000, Duration::from_secs(1)),
+            now
+        ));
+        book.listed(addr(8080), listed_by(addr(9000)), addr(9000), now);
+
+        let record = book.get(addr(8080)).unwrap();
+        assert_eq!(record.source, LearnedVia::Connect);
+        assert_eq!(record.learned_at, 1_000);
+        assert_eq!(record.corroborators, [addr(9000)]);
+        assert_eq!(book.get(addr(8090)), None);
+    }
+
+    #[test]
+    fn corroborators_are_distinct_and_bounded() {
+        let now = Instant::now();
+        let mut book = ProvenanceBook::new(8);
+        book.listed(addr(8080), listed_by(addr(9000)), addr(9000), now);
+        // The source, and a peer already counted, are not counted again.
+        book.listed(addr(8080), listed_by(addr(9000)), addr(9000), now);
+        book.listed(addr(8080), listed_by(addr(9001)), addr(9001), now);
+        book.listed(addr(8080), listed_by(addr(9001)), addr(9001), now);
+        let record = book.get(addr(8080)).unwrap();
+        assert_eq!(record.source.peer(), Some(addr(9000)));
+        assert_eq!(record.corroborators, [addr(9001)]);
+
+        for port in 0..MAX_CORROBORATORS as u16 * 2 {
+            book.listed(
+                addr(8080),
+                listed_by(addr(7000 + port)),
+                addr(7000 + port),
+                now,
+            );
+        }
+        assert_eq!(
+            book.get(addr(8080)).unwrap().corroborators.len(),
+            MAX_CORROBORATORS
+        );
+    }
+
+    #[test]
+    fn the_least_recently_used_record_makes_room() {
+        let now = Instant::now();
+        let mut book = ProvenanceBook::new(2);
+        let record = || Provenance::new(LearnedVia::PeerCache, 1_000, Duration::ZERO);
+        book.learned(addr(8080), record(), now);
+        book.learned(addr(8081), record(), now);
+        book.listed(addr(8080), listed_by(addr(9000)), addr(9000), now);
+        book.learned(addr(8082), record(), now);
+        assert!(book.get(addr(8080)).is_some());
+        assert!(book.get(addr(8081)).is_none());
+        assert!(book.get(addr(8082)).is_some());
+    }
+
+    #[test]
+    fn sources_parse_back_from_their_name() {
+        for source in every_source() {
+            let request = match source {
+                LearnedVia::List { request, .. } => request,
+                _ => None,
+            };
+            assert_eq!(
+                LearnedVia::parse(source.name(), source.peer(), request),
+                Some(source)
+            );
+        }
+        assert_eq!(LearnedVia::parse("list", None, Some(1)), None);
+        assert_eq!(LearnedVia::parse("introduction", None, None), None);
+        assert_eq!(LearnedVia::parse("rumor", None, None), None);
+    }
+
+    #[test]
+    fn sources_are_displayed_with_their_peer() {
+        let shown: Vec<String> = every_source().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            shown,
+            [
+                "the --connect target",
+                "the recent peers cache",
+                "the state file",
+                "the imported membership snapshot",
+                "the participants list of \"127.0.0.1:9000\", answering request 7",
+                "the participants list of \"127.0.0.1:9000\"",
+                "its own inbound connection",
+                "the introduction of \"127.0.0.1:9001\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_survive_the_state_file() {
+        let peers: Vec<RememberedPeer> = every_source()
+            .into_iter()
+            .enumerate()
+            .map(|(index, source)| RememberedPeer {
+                addr: addr(8080 + index as u16),
+                verified_at: Some(1_500),
+                sessions: 1,
+                node_id: None,
+                provenance: Some(Provenance {
+                    source,
+                    learned_at: 1_000 + index as u64,
+                    // The time into the run belongs to the run that saved the record.
+                    uptime: None,
+                    corroborators: vec![addr(7000), addr(7001)],
+                }),
+            })
+            .collect();
+        let state = NodeState {
+            saved_at: 2_000,
+            node: addr(7999),
+            topic_sequences: Vec::new(),
+            peers,
+            scheduled: Vec::new(),
+        };
+        assert_eq!(NodeState::decode(&state.encode()).unwrap(), state);
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-Peer Rate Limiting.
+//!
+//! This module implements a token-bucket rate limiter keyed by peer. Each peer owns a bucket
+//! holding up to `burst` tokens, refilled at `per_second` tokens per second; every limited
+//! request spends one token and is refused when the bucket is empty.
+//!
+//! Buckets are kept in a `BoundedMap`, so the limiter never tracks more peers than its budget.
+//! A bucket left alone long enough to refill completely expires, since forgetting it is the
+//! same as keeping a full one.
+
+use super::collections::{BoundedMap, Eviction};
+
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The tokens left to one peer.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token-bucket rate limiter with a bounded number of buckets.
+pub struct RateLimiter<K> {
+    buckets: BoundedMap<K, TokenBucket>,
+    burst: f64,
+    per_second: f64,
+    throttled: u64,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Constructs a new `RateLimiter`.
+    ///
+    /// # Parameters
+    ///
+    /// * `max_buckets` - The number of peers tracked at the same time.
+    /// * `burst` - The number of requests a peer can make at once.
+    /// * `per_second` - The sustained number of requests per second allowed per peer.
+    pub fn new(max_buckets: usize, burst: f64, per_second: f64) -> Self {
+        let refill_time = Duration::from_secs_f64(burst / per_second);
+        Self {
+            buckets: BoundedMap::new(max_buckets, Eviction::Lru, Some(refill_time)),
+            burst,
+            per_second,
+            throttled: 0,
+        }
+    }
+
+    /// Spends a token of `key`, if any is left.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is allowed, `false` if it must be refused.
+    pub fn allow(&mut self, key: K, now: Instant) -> bool {
+        let (burst, per_second) = (self.burst, self.per_second);
+        let allowed = match self.buckets.get_mut(&key, now) {
+            Some(bucket) => {
+                let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * per_second;
+                bucket.tokens = (bucket.tokens + refill).min(burst);
+                bucket.refilled_at = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    true
+                } else {
+                    false
+                }
+            }
+            None => {
+                let bucket = TokenBucket {
+                    tokens: burst - 1.0,
+                    refilled_at: now,
+                };
+                self.buckets.insert(key, bucket, now);
+                true
+            }
+        };
+
+        if !allowed {
+            self.throttled += 1;
+        }
+        allowed
+    }
+
+    /// Drops the bucket of a peer that went away.
+    pub fn forget(&mut self, key: &K) {
+        self.buckets.remove(key);
+    }
+
+    /// Removes the buckets that refilled completely.
+    pub fn sweep(&mut self, now: Instant) {
+        if !self.buckets.is_empty() {
+            self.buckets.sweep(now);
+        }
+    }
+
+    /// Iterates over the peers that own a bucket.
+    pub fn peers(&self) -> impl Iterator<Item = &K> {
+        self.buckets.iter().map(|(key, _)| key)
+    }
+
+    /// Returns the number of tracked buckets.
+    pub fn buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns the maximal number of tracked buckets.
+    pub fn capacity(&self) -> usize {
+        self.buckets.capacity()
+    }
+
+    /// Returns the number of buckets evicted so far.
+    pub fn evictions(&self) -> u64 {
+        self.buckets.evictions()
+    }
+
+    /// Returns the number of refused requests so far.
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+}
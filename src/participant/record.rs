@@ -0,0 +1,503 @@
+//! Recordings of Inbound Network Events.
+//!
+//! With `--record`, a participant appends every network event it receives to a file: accepted,
+//! established, failed and lost connections with the address of their endpoint, and the raw
+//! bytes of every received frame with its sender. The first record holds the public address and
+//! the configuration of the participant, so a recording handed over with a bug report tells
+//! how the participant was started; the second holds its command-line arguments and the seed
+//! of its random choices, so `gossip-p2p replay` can start a participant just like it.
+//!
+//! Alongside the network events, the recording keeps the membership of the participant as it
+//! saw it: every peer joining or leaving its peer set with the reason, every peer suspected of
+//! a half-open connection and redialed, and whether the redial recovered it. Every
+//! `--record-snapshot-every` it also notes its own connected peer count and effective period.
+//! These records carry the wall-clock time too, so `gossip-p2p analyze` can tell the peer set
+//! at any point of a long run; see `history`.
+//!
+//! The file starts with `RECORD_MAGIC` and the little-endian `RECORD_VERSION`, followed by the
+//! records. Each record is its bincode length as a little-endian `u32` and the bincode encoding
+//! of a `Record`. A participant that crashes can leave a partial record at the end; the reader
+//! drops everything after the last complete record instead of rejecting the whole file.
+//!
+//! The event loop never writes to the file itself: it hands the records to a writer thread over
+//! a channel, so a slow disk never delays the handling of events.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::participant::channels::{ChannelRegistry, MonitoredSender};
+use crate::participant::threads::{Join, ThreadRegistry};
+use crate::participant::transport::TransportEvent;
+use crate::printer::print_error;
+
+/// The bytes every recording starts with.
+pub const RECORD_MAGIC: &[u8; 8] = b"GOSSREC\n";
+
+/// The version of the record format written by this build; version 2 had no settings record.
+pub const RECORD_VERSION: u16 = 3;
+
+/// The oldest record format read by this build; version 1 had no membership records.
+pub const OLDEST_RECORD_VERSION: u16 = 1;
+
+/// The largest record accepted by the reader; a longer length prefix means a corrupted file.
+const MAX_RECORD_LEN: u32 = 16 * 1024 * 1024;
+
+/// Enumerates the reasons a recording can not be read.
+#[derive(Debug)]
+pub enum RecordError {
+    /// The file can not be read.
+    Io(io::Error),
+    /// The file does not start with `RECORD_MAGIC`.
+    NotARecording,
+    /// The file was written in a record format this build does not read; holds the version.
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Io(err) => write!(f, "{}", err),
+            RecordError::NotARecording => write!(f, "not a gossip-p2p recording"),
+            RecordError::UnsupportedVersion(version) => {
+                write!(f, "unsupported record format version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// One recorded event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    /// The participant started; always the first record.
+    Started {
+        public_addr: SocketAddr,
+        config: String,
+    },
+    /// A peer connected to the participant.
+    Accepted { endpoint: SocketAddr },
+    /// A dial of the participant was established or failed.
+    Connected {
+        endpoint: SocketAddr,
+        established: bool,
+    },
+    /// A frame was received.
+    Message { sender: SocketAddr, frame: Vec<u8> },
+    /// A connection was lost.
+    Disconnected { endpoint: SocketAddr },
+    /// The membership of the participant changed, at `wall_ms` milliseconds since the Unix
+    /// epoch.
+    Membership {
+        wall_ms: u64,
+        change: MembershipRecord,
+    },
+    /// The command-line arguments the participant was configured with and the seed of its
+    /// random choices; right after `Started`.
+    Settings { args: Vec<String>, seed: u64 },
+}
+
+/// A change of the peer set of the recording participant, or a snapshot of its own state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MembershipRecord {
+    /// A peer joined the peer set.
+    Joined { addr: SocketAddr },
+    /// A peer left the peer set, for the given reason.
+    Left { addr: SocketAddr, reason: String },
+    /// A peer left too many messages unanswered and is redialed.
+    Suspect { addr: SocketAddr },
+    /// The redial of a suspected peer was established.
+    Recovered { addr: SocketAddr },
+    /// The number of connected peers and the effective period, noted periodically.
+    SelfState { peers: u64, period_ms: u64 },
+}
+
+impl fmt::Display for MembershipRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MembershipRecord::Joined { addr } => write!(f, "\"{}\" joined", addr),
+            MembershipRecord::Left { addr, reason } => write!(f, "\"{}\" left ({})", addr, reason),
+            MembershipRecord::Suspect { addr } => {
+                write!(f, "\"{}\" is suspected of a half-open connection", addr)
+            }
+            MembershipRecord::Recovered { addr } => write!(f, "\"{}\" recovered", addr),
+            MembershipRecord::SelfState { peers, period_ms } => {
+                write!(f, "{} peers connected, period {}ms", peers, period_ms)
+            }
+        }
+    }
+}
+
+impl RecordedEvent {
+    /// Captures a network event of the transport.
+    ///
+    /// # Parameters
+    ///
+    /// * `event` - The event, whose frame is copied.
+    pub fn from_transport_event(event: &TransportEvent<'_>) -> Self {
+        match event {
+            TransportEvent::Accepted(endpoint, _) => RecordedEvent::Accepted {
+                endpoint: endpoint.addr(),
+            },
+            TransportEvent::Connected(endpoint, established) => RecordedEvent::Connected {
+                endpoint: endpoint.addr(),
+                established: *established,
+            },
+            TransportEvent::Frame(endpoint, frame) => RecordedEvent::Message {
+                sender: endpoint.addr(),
+                frame: frame.to_vec(),
+            },
+            TransportEvent::Closed(endpoint) => RecordedEvent::Disconnected {
+                endpoint: endpoint.addr(),
+            },
+        }
+    }
+}
+
+/// A recorded event with the time it happened at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// The milliseconds between the start of the recording and the event.
+    pub elapsed_ms: u64,
+    /// The event.
+    pub event: RecordedEvent,
+}
+
+/// Appends the records of a participant to a file from a writer thread.
+#[derive(Debug)]
+pub struct Recorder {
+    started_at: Instant,
+    records: Option<MonitoredSender<Record>>,
+    written: Option<Receiver<()>>,
+}
+
+impl Recorder {
+    /// Creates the recording at `path`, replacing any previous file, and writes its header,
+    /// the `RecordedEvent::Started` record and the `RecordedEvent::Settings` one.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the recording.
+    /// * `public_addr` - The public address of the recording participant.
+    /// * `config` - The configuration of the participant, as shown in the recording.
+    /// * `settings` - The command-line arguments of the participant and its seed.
+    /// * `threads` - The registry the writer thread, `gossip-recorder`, is spawned through.
+    /// * `channels` - The registry the queue of the writer, `recorder`, is created through.
+    pub fn create(
+        path: &Path,
+        public_addr: SocketAddr,
+        config: String,
+        (args, seed): (Vec<String>, u64),
+        threads: &ThreadRegistry,
+        channels: &ChannelRegistry,
+    ) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(RECORD_MAGIC)?;
+        file.write_all(&RECORD_VERSION.to_le_bytes())?;
+        file.flush()?;
+
+        let (records, pending) = channels.channel::<Record>("recorder");
+        // The writer drops `done` as it ends, which tells `finish` that the file is complete.
+        let (done, written) = mpsc::channel::<()>();
+        threads.spawn("gossip-recorder", Join::Wait, move |_| {
+            let _done = done;
+            while let Ok(record) = pending.recv() {
+                // Write whatever piled up before flushing, so a burst costs a single flush.
+                let mut result = write_record(&mut file, &record);
+                for record in pending.try_iter() {
+                    result = result.and_then(|_| write_record(&mut file, &record));
+                }
+                if let Err(err) = result.and_then(|_| file.flush()) {
+                    print_error(&format!("Stopped recording: {}", err));
+                    return;
+                }
+            }
+        })?;
+
+        let recorder = Self {
+            started_at: Instant::now(),
+            records: Some(records),
+            written: Some(written),
+        };
+        recorder.record(RecordedEvent::Started {
+            public_addr,
+            config,
+        });
+        recorder.record(RecordedEvent::Settings { args, seed });
+        Ok(recorder)
+    }
+
+    /// Verifies that a recording can be created at `path`, without truncating an existing file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the recording.
+    pub fn check(path: &Path) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map(|_| ())
+    }
+
+    /// Hands `event` to the writer thread, stamped with the time elapsed since the start.
+    ///
+    /// # Parameters
+    ///
+    /// * `event` - The event to record.
+    pub fn record(&self, event: RecordedEvent) {
+        let record = Record {
+            elapsed_ms: self.started_at.elapsed().as_millis() as u64,
+            event,
+        };
+        if let Some(records) = &self.records {
+            // A writer that stopped has already reported why.
+            let _ = records.send(record);
+        }
+    }
+
+    /// Hands a membership change to the writer thread, stamped with the wall-clock time as well.
+    ///
+    /// # Parameters
+    ///
+    /// * `change` - The change to record.
+    pub fn membership(&self, change: MembershipRecord) {
+        let wall_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        self.record(RecordedEvent::Membership { wall_ms, change });
+    }
+
+    /// Waits until every handed record is written.
+    pub fn finish(&mut self) {
+        self.records = None;
+        if let Some(written) = self.written.take() {
+            let _ = written.recv();
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Writes one length-prefixed record.
+fn write_record(file: &mut impl Write, record: &Record) -> io::Result<()> {
+    let body = bincode::serialize(record).map_err(io::Error::other)?;
+    file.write_all(&(body.len() as u32).to_le_bytes())?;
+    file.write_all(&body)
+}
+
+/// The records read back from a recording.
+#[derive(Debug)]
+pub struct Recording {
+    /// Every complete record, in the order they were written.
+    pub records: Vec<Record>,
+    /// The number of bytes after the last complete record, dropped as a partial record.
+    pub dropped_bytes: usize,
+}
+
+impl Recording {
+    /// Reads the recording at `path`.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the recording.
+    pub fn load(path: &Path) -> Result<Self, RecordError> {
+        let content = fs::read(path).map_err(RecordError::Io)?;
+        Self::decode(&content)
+    }
+
+    /// Decodes the content of a recording.
+    ///
+    /// A record whose length prefix or body is cut short, or whose body does not decode, ends
+    /// the recording: it and everything after it are counted in `dropped_bytes`.
+    ///
+    /// # Parameters
+    ///
+    /// * `content` - The content of the file.
+    pub fn decode(content: &[u8]) -> Result<Self, RecordError> {
+        let header_len = RECORD_MAGIC.len() + 2;
+        if content.len() < header_len || &content[..RECORD_MAGIC.len()] != RECORD_MAGIC {
+            return Err(RecordError::NotARecording);
+        }
+        let version = u16::from_le_bytes([content[8], content[9]]);
+        // The newer formats only add records, so an older recording decodes the same.
+        if !(OLDEST_RECORD_VERSION..=RECORD_VERSION).contains(&version) {
+            return Err(RecordError::UnsupportedVersion(version));
+        }
+
+        let mut records = Vec::new();
+        let mut offset = header_len;
+        while let Some((record, record_len)) = decode_record(&content[offset..]) {
+            records.push(record);
+            offset += record_len;
+        }
+
+        Ok(Self {
+            records,
+            dropped_bytes: content.len() - offset,
+        })
+    }
+}
+
+/// Decodes the record at the start of `content`.
+///
+/// # Returns
+///
+/// The record and the number of bytes it takes, length prefix included, or `None` when
+/// `content` does not start with a complete record.
+fn decode_record(content: &[u8]) -> Option<(Record, usize)> {
+    let prefix: [u8; 4] = content.get(..4)?.try_into().ok()?;
+    let body_len = u32::from_le_bytes(prefix);
+    if body_len > MAX_RECORD_LEN {
+        return None;
+    }
+    let body = content.get(4..4 + body_len as usize)?;
+    let record = bincode::deserialize(body).ok()?;
+    Some((record, 4 + body.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn header(version: u16) -> Vec<u8> {
+        let mut content = RECORD_MAGIC.to_vec();
+        content.extend_from_slice(&version.to_le_bytes());
+        content
+    }
+
+    fn encoded(events: &[RecordedEvent]) -> Vec<u8> {
+        let mut content = header(RECORD_VERSION);
+        for (elapsed_ms, event) in events.iter().enumerate() {
+            let record = Record {
+                elapsed_ms: elapsed_ms as u64,
+                event: event.clone(),
+            };
+            write_record(&mut content, &record).unwrap();
+        }
+        content
+    }
+
+    #[test]
+    fn a_recording_reads_back_what_the_recorder_wrote() {
+        let path = std::env::temp_dir().join(format!("gossip-p2p-record-{}", std::process::id()));
+        let threads = ThreadRegistry::new(4);
+        let channels = ChannelRegistry::new();
+        let args = vec!["--port=9000".to_owned()];
+        let mut recorder = Recorder::create(
+            &path,
+            addr(9000),
+            "config".to_owned(),
+            (args.clone(), 7),
+            &threads,
+            &channels,
+        )
+        .unwrap();
+        recorder.record(RecordedEvent::Message {
+            sender: addr(9001),
+            frame: vec![1, 2, 3],
+        });
+        recorder.membership(MembershipRecord::Joined { addr: addr(9001) });
+        recorder.finish();
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let events: Vec<&RecordedEvent> = recording.records.iter().map(|r| &r.event).collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[0],
+            &RecordedEvent::Started {
+                public_addr: addr(9000),
+                config: "config".to_owned()
+            }
+        );
+        assert_eq!(events[1], &RecordedEvent::Settings { args, seed: 7 });
+        assert_eq!(
+            events[2],
+            &RecordedEvent::Message {
+                sender: addr(9001),
+                frame: vec![1, 2, 3]
+            }
+        );
+        assert!(matches!(
+            events[3],
+            RecordedEvent::Membership {
+                change: MembershipRecord::Joined { .. },
+                ..
+            }
+        ));
+        assert_eq!(recording.dropped_bytes, 0);
+    }
+
+    #[test]
+    fn a_partial_record_at_the_end_is_dropped() {
+        let events = [
+            RecordedEvent::Accepted {
+                endpoint: addr(9001),
+            },
+            RecordedEvent::Disconnected {
+                endpoint: addr(9001),
+            },
+        ];
+        let content = encoded(&events);
+        let complete = encoded(&events[..1]).len();
+        for cut in [complete + 2, content.len() - 1] {
+            let recording = Recording::decode(&content[..cut]).unwrap();
+            assert_eq!(recording.records.len(), 1, "cut at {}", cut);
+            assert_eq!(recording.records[0].event, events[0]);
+            assert_eq!(recording.dropped_bytes, cut - complete);
+        }
+    }
+
+    #[test]
+    fn a_corrupted_length_ends_the_recording() {
+        let mut content = encoded(&[]);
+        content.extend_from_slice(&(MAX_RECORD_LEN + 1).to_le_bytes());
+        content.extend_from_slice(&[0; 16]);
+        let recording = Recording::decode(&content).unwrap();
+        assert!(recording.records.is_empty());
+        assert_eq!(recording.dropped_bytes, 20);
+    }
+
+    #[test]
+    fn a_file_without_the_magic_is_not_a_recording() {
+        assert!(matches!(
+            Recording::decode(b"GOSSIP\n\0\x03\0"),
+            Err(RecordError::NotARecording)
+        ));
+        assert!(matches!(
+            Recording::decode(&RECORD_MAGIC[..]),
+            Err(RecordError::NotARecording)
+        ));
+    }
+
+    #[test]
+    fn only_the_versions_this_build_reads_are_accepted() {
+        for version in [0, RECORD_VERSION + 1] {
+            assert!(matches!(
+                Recording::decode(&header(version)),
+                Err(RecordError::UnsupportedVersion(v)) if v == version
+            ));
+        }
+        // A recording of an older format decodes the same, only without the newer records.
+        let mut content = header(OLDEST_RECORD_VERSION);
+        content.extend_from_slice(
+            &encoded(&[RecordedEvent::Accepted {
+                endpoint: addr(9001),
+            }])[10..],
+        );
+        let recording = Recording::decode(&content).unwrap();
+        assert_eq!(recording.records.len(), 1);
+    }
+}
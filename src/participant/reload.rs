@@ -0,0 +1,436 @@
+//! Configuration Reload.
+//!
+//! A soak test should not be restarted to turn a knob: `SIGHUP` or the `reload` command read
+//! the configuration again, through the loader registered with `Participant::on_reload`, which
+//! the application builds from its `--config` file and command line. The new configuration is
+//! checked like the one of the start, and rejected as a whole on any error.
+//!
+//! Each field of `NodeConfig` is either `Reload::Hot`, read by the participant where it is
+//! used and so changed at once, or `Reload::Cold`, fixed at the start: the listen addresses,
+//! the identity, the thread and map budgets, the startup-only files. `merge` classifies every
+//! field, taking the hot ones from the new configuration and keeping the cold ones. A changed
+//! cold field is reported and ignored until the next start.
+//!
+//! The participant applies the merged configuration on its event loop, between two events, so
+//! nothing it runs ever sees some of the fields of a reload and not the others.
+
+use crate::participant::config::NodeConfig;
+
+use std::fmt;
+
+/// Loads the configuration to reload, or explains why it can not be read.
+pub type ConfigLoader = Box<dyn FnMut() -> Result<NodeConfig, String> + Send>;
+
+/// Whether a field of `NodeConfig` can change while the participant runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reload {
+    /// The new value is applied at once.
+    Hot,
+    /// The value of the start is kept until the next one.
+    Cold,
+}
+
+/// A field whose value differs in the reloaded configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    /// The name of the field in `NodeConfig`.
+    pub field: &'static str,
+    /// Whether the new value was applied.
+    pub reload: Reload,
+    /// The running value, as debug-formatted.
+    pub old: String,
+    /// The reloaded value, as debug-formatted.
+    pub new: String,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reload {
+            Reload::Hot => write!(f, "{}: {} -> {}", self.field, self.old, self.new),
+            Reload::Cold => write!(
+                f,
+                "{}: {} -> {} ignored, it only changes on a restart",
+                self.field, self.old, self.new
+            ),
+        }
+    }
+}
+
+/// The changes found by `merge`.
+struct Changes(Vec<ConfigChange>);
+
+impl Changes {
+    /// Records the change of `field`, if its value differs.
+    fn note(
+        &mut self,
+        field: &'static str,
+        reload: Reload,
+        old: &dyn fmt::Debug,
+        new: &dyn fmt::Debug,
+    ) {
+        let (old, new) = (format!("{:?}", old), format!("{:?}", new));
+        if old != new {
+            self.0.push(ConfigChange {
+                field,
+                reload,
+                old,
+                new,
+            });
+        }
+    }
+
+    /// Records the change of a hot field.
+    fn hot(&mut self, field: &'static str, old: &dyn fmt::Debug, new: &dyn fmt::Debug) {
+        self.note(field, Reload::Hot, old, new);
+    }
+
+    /// Records the change of a cold field.
+    fn cold(&mut self, field: &'static str, old: &dyn fmt::Debug, new: &dyn fmt::Debug) {
+        self.note(field, Reload::Cold, old, new);
+    }
+}
+
+/// Merges a reloaded configuration into the running one.
+///
+/// # Returns
+///
+/// The running configuration with the hot fields of `loaded`, and the fields that differ,
+/// applied or not.
+pub fn merge(current: &NodeConfig, loaded: NodeConfig) -> (NodeConfig, Vec<ConfigChange>) {
+    // Destructured without `..`, so a new field does not build until it is classified here.
+    let NodeConfig {
+        period,
+        rounds,
+        legacy_payload,
+        port,
+        listen,
+        advertise,
+        connect,
+        use_peer_cache,
+        network,
+        half_open_threshold,
+        period_skew_ratio,
+        max_clock_offset,
+        balance_connections,
+        require_bootstrap,
+        join_check,
+        expect_network_id,
+        expect_node_id,
+        bootstrap_verification,
+        strict_protocol,
+        validate_outbound,
+        hints,
+        interactive,
+        exit_on_eof,
+        exec,
+        admin_socket,
+        max_observers,
+        dedup_entries,
+        ratelimit_entries,
+        blocklist,
+        filter_file,
+        allow_privileged_ports,
+        allow_special_ranges,
+        topics,
+        topic_broadcast,
+        reorder_window,
+        reorder_buffer,
+        audit_every,
+        share_limit,
+        corroboration,
+        send_budget,
+        batch_window,
+        late_tick_factor,
+        frozen_tick_factor,
+        connect_timeout,
+        dial_memory,
+        heal_probe_interval,
+        resolve_interval,
+        warmup,
+        busy_lag,
+        overload_lag,
+        trace_ticks,
+        inbox_capacity,
+        max_scheduled,
+        callback_workers,
+        max_threads,
+        fd_headroom,
+        import_membership,
+        max_snapshot_age,
+        require_signed_import,
+        trusted_snapshot_key,
+        state_file,
+        state_save_interval,
+        max_state_age,
+        retention,
+        identity_file,
+        persist_identity,
+        node_label,
+        report_file,
+        report_format,
+        record,
+        record_snapshot_every,
+        alerts,
+        alert_cooldown,
+        // What the participant was started with, which a reload does not change.
+        args: _,
+        seed: _,
+    } = loaded;
+    let mut changes = Changes(Vec::new());
+    let c = current;
+
+    // Fixed at the start: the sockets, the identity, the budgets the maps and threads were
+    // sized with, the timers and workers already running, and the files only read at startup.
+    changes.cold("period", &c.period, &period);
+    changes.cold("rounds", &c.rounds, &rounds);
+    changes.cold("port", &c.port, &port);
+    changes.cold("listen", &c.listen, &listen);
+    changes.cold("advertise", &c.advertise, &advertise);
+    changes.cold("connect", &c.connect, &connect);
+    changes.cold("use_peer_cache", &c.use_peer_cache, &use_peer_cache);
+    changes.cold("network", &c.network, &network);
+    changes.cold(
+        "balance_connections",
+        &c.balance_connections,
+        &balance_connections,
+    );
+    changes.cold(
+        "require_bootstrap",
+        &c.require_bootstrap,
+        &require_bootstrap,
+    );
+    changes.cold("join_check", &c.join_check, &join_check);
+    changes.cold(
+        "expect_network_id",
+        &c.expect_network_id,
+        &expect_network_id,
+    );
+    changes.cold("expect_node_id", &c.expect_node_id, &expect_node_id);
+    changes.cold(
+        "bootstrap_verification",
+        &c.bootstrap_verification,
+        &bootstrap_verification,
+    );
+    changes.cold("strict_protocol", &c.strict_protocol, &strict_protocol);
+    changes.cold(
+        "validate_outbound",
+        &c.validate_outbound,
+        &validate_outbound,
+    );
+    changes.cold("interactive", &c.interactive, &interactive);
+    changes.cold("exit_on_eof", &c.exit_on_eof, &exit_on_eof);
+    changes.cold("exec", &c.exec, &exec);
+    changes.cold("admin_socket", &c.admin_socket, &admin_socket);
+    changes.cold("max_observers", &c.max_observers, &max_observers);
+    changes.cold("dedup_entries", &c.dedup_entries, &dedup_entries);
+    changes.cold(
+        "ratelimit_entries",
+        &c.ratelimit_entries,
+        &ratelimit_entries,
+    );
+    changes.cold("blocklist", &c.blocklist, &blocklist);
+    changes.cold("filter_file", &c.filter_file, &filter_file);
+    changes.cold("topics", &c.topics, &topics);
+    changes.cold("reorder_window", &c.reorder_window, &reorder_window);
+    changes.cold("reorder_buffer", &c.reorder_buffer, &reorder_buffer);
+    changes.cold("warmup", &c.warmup, &warmup);
+    changes.cold("inbox_capacity", &c.inbox_capacity, &inbox_capacity);
+    changes.cold("callback_workers", &c.callback_workers, &callback_workers);
+    changes.cold("max_threads", &c.max_threads, &max_threads);
+    changes.cold("fd_headroom", &c.fd_headroom, &fd_headroom);
+    changes.cold(
+        "import_membership",
+        &c.import_membership,
+        &import_membership,
+    );
+    changes.cold("max_snapshot_age", &c.max_snapshot_age, &max_snapshot_age);
+    changes.cold(
+        "require_signed_import",
+        &c.require_signed_import,
+        &require_signed_import,
+    );
+    changes.cold(
+        "trusted_snapshot_key",
+        &c.trusted_snapshot_key,
+        &trusted_snapshot_key,
+    );
+    changes.cold("state_file", &c.state_file, &state_file);
+    changes.cold(
+        "state_save_interval",
+        &c.state_save_interval,
+        &state_save_interval,
+    );
+    changes.cold("max_state_age", &c.max_state_age, &max_state_age);
+    changes.cold("retention", &c.retention, &retention);
+    changes.cold("identity_file", &c.identity_file, &identity_file);
+    changes.cold("persist_identity", &c.persist_identity, &persist_identity);
+    changes.cold("node_label", &c.node_label, &node_label);
+    changes.cold("record", &c.record, &record);
+    changes.cold("alerts", &c.alerts, &alerts);
+
+    let mut merged = current.clone();
+    changes.hot("legacy_payload", &c.legacy_payload, &legacy_payload);
+    merged.legacy_payload = legacy_payload;
+    changes.hot(
+        "half_open_threshold",
+        &c.half_open_threshold,
+        &half_open_threshold,
+    );
+    merged.half_open_threshold = half_open_threshold;
+    changes.hot(
+        "period_skew_ratio",
+        &c.period_skew_ratio,
+        &period_skew_ratio,
+    );
+    merged.period_skew_ratio = period_skew_ratio;
+    changes.hot("max_clock_offset", &c.max_clock_offset, &max_clock_offset);
+    merged.max_clock_offset = max_clock_offset;
+    changes.hot(
+        "allow_privileged_ports",
+        &c.allow_privileged_ports,
+        &allow_privileged_ports,
+    );
+    merged.allow_privileged_ports = allow_privileged_ports;
+    changes.hot(
+        "allow_special_ranges",
+        &c.allow_special_ranges,
+        &allow_special_ranges,
+    );
+    merged.allow_special_ranges = allow_special_ranges;
+    changes.hot("hints", &c.hints, &hints);
+    merged.hints = hints;
+    changes.hot("topic_broadcast", &c.topic_broadcast, &topic_broadcast);
+    merged.topic_broadcast = topic_broadcast;
+    changes.hot("audit_every", &c.audit_every, &audit_every);
+    merged.audit_every = audit_every;
+    changes.hot("share_limit", &c.share_limit, &share_limit);
+    merged.share_limit = share_limit;
+    changes.hot("corroboration", &c.corroboration, &corroboration);
+    merged.corroboration = corroboration;
+    changes.hot("send_budget", &c.send_budget, &send_budget);
+    merged.send_budget = send_budget;
+    changes.hot("batch_window", &c.batch_window, &batch_window);
+    merged.batch_window = batch_window;
+    changes.hot("late_tick_factor", &c.late_tick_factor, &late_tick_factor);
+    merged.late_tick_factor = late_tick_factor;
+    changes.hot(
+        "frozen_tick_factor",
+        &c.frozen_tick_factor,
+        &frozen_tick_factor,
+    );
+    merged.frozen_tick_factor = frozen_tick_factor;
+    changes.hot("connect_timeout", &c.connect_timeout, &connect_timeout);
+    merged.connect_timeout = connect_timeout;
+    changes.cold("dial_memory", &c.dial_memory, &dial_memory);
+    changes.hot(
+        "heal_probe_interval",
+        &c.heal_probe_interval,
+        &heal_probe_interval,
+    );
+    merged.heal_probe_interval = heal_probe_interval;
+    changes.hot("resolve_interval", &c.resolve_interval, &resolve_interval);
+    merged.resolve_interval = resolve_interval;
+    changes.hot("busy_lag", &c.busy_lag, &busy_lag);
+    merged.busy_lag = busy_lag;
+    changes.hot("overload_lag", &c.overload_lag, &overload_lag);
+    merged.overload_lag = overload_lag;
+    changes.hot("trace_ticks", &c.trace_ticks, &trace_ticks);
+    merged.trace_ticks = trace_ticks;
+    changes.hot("max_scheduled", &c.max_scheduled, &max_scheduled);
+    merged.max_scheduled = max_scheduled;
+    changes.hot("report_file", &c.report_file, &report_file);
+    merged.report_file = report_file;
+    changes.hot("report_format", &c.report_format, &report_format);
+    merged.report_format = report_format;
+    changes.hot(
+        "record_snapshot_every",
+        &c.record_snapshot_every,
+        &record_snapshot_every,
+    );
+    merged.record_snapshot_every = record_snapshot_every;
+    changes.hot("alert_cooldown", &c.alert_cooldown, &alert_cooldown);
+    merged.alert_cooldown = alert_cooldown;
+
+    (merged, changes.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(args: &[&str]) -> NodeConfig {
+        NodeConfig::from_args(args).unwrap()
+    }
+
+    #[test]
+    fn the_same_configuration_changes_nothing() {
+        let current = config(&["--period=1", "--port=8080"]);
+        let (merged, changes) = merge(&current, current.clone());
+        assert!(changes.is_empty());
+        assert_eq!(format!("{:?}", merged), format!("{:?}", current));
+    }
+
+    #[test]
+    fn hot_fields_are_applied_and_cold_ones_kept() {
+        let current = config(&["--period=1", "--port=8080"]);
+        let loaded = config(&[
+            "--period=1",
+            "--port=9090",
+            "--share-limit=8",
+            "--connect-timeout=3s",
+            "--node-label=renamed",
+        ]);
+        let (merged, changes) = merge(&current, loaded);
+
+        assert_eq!(merged.share_limit, 8);
+        assert_eq!(merged.connect_timeout, Duration::from_secs(3));
+        assert_eq!(format!("{:?}", merged.port), format!("{:?}", current.port));
+        assert_eq!(merged.node_label, None);
+
+        let classified: Vec<(&str, Reload)> = changes
+            .iter()
+            .map(|change| (change.field, change.reload))
+            .collect();
+        assert_eq!(
+            classified,
+            [
+                ("port", Reload::Cold),
+                ("node_label", Reload::Cold),
+                ("share_limit", Reload::Hot),
+                ("connect_timeout", Reload::Hot),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_arguments_and_seed_of_the_start_stay() {
+        let mut current = config(&["--period=1", "--port=8080"]);
+        current.seed = Some(7);
+        let mut loaded = config(&["--period=1", "--port=8080", "--share-limit=8"]);
+        loaded.seed = Some(8);
+        let (merged, changes) = merge(&current, loaded);
+        assert_eq!(merged.seed, Some(7));
+        assert_eq!(merged.args, current.args);
+        assert_eq!(changes.len(), 1);
+    }
+
+    #[test]
+    fn changes_show_both_values() {
+        let hot = ConfigChange {
+            field: "share_limit",
+            reload: Reload::Hot,
+            old: "100".to_owned(),
+            new: "8".to_owned(),
+        };
+        assert_eq!(hot.to_string(), "share_limit: 100 -> 8");
+        let cold = ConfigChange {
+            reload: Reload::Cold,
+            ..hot
+        };
+        assert_eq!(
+            cold.to_string(),
+            "share_limit: 100 -> 8 ignored, it only changes on a restart"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Ordered Delivery.
+//!
+//! Topic messages reach plain subscribers in the order they arrive, which is not always the
+//! order they were published in once a publisher reconnects or its messages are batched. A
+//! participant numbers its publications on each topic, and a subscription made with
+//! `subscribe_ordered` reads them through one `ReorderBuffer` per publisher and topic.
+//!
+//! A message carrying the expected sequence number is delivered at once. A message after a
+//! gap is held until the gap is filled, for the reorder window at most; the buffer then gives
+//! up on the missing messages, reports them with `Delivery::Gap` and delivers what it held in
+//! sequence order. A buffer holding more messages than its capacity gives up on its oldest gap
+//! right away, and a message older than the expected one is a duplicate and is dropped.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// What an ordered subscriber is handed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// The message published with sequence number `seq`.
+    Message { seq: u64, payload: Vec<u8> },
+    /// A message from a publisher too old to number its publications, delivered as it
+    /// arrives.
+    Unsequenced(Vec<u8>),
+    /// The messages numbered `from` to `to`, both included, never arrived in time.
+    Gap { from: u64, to: u64 },
+}
+
+/// The messages received from one publisher on one topic, put back in sequence order.
+#[derive(Debug, Clone)]
+pub struct ReorderBuffer {
+    next: Option<u64>,
+    held: BTreeMap<u64, (Vec<u8>, Instant)>,
+    capacity: usize,
+    window: Duration,
+}
+
+impl ReorderBuffer {
+    /// Constructs a buffer expecting the first message it receives.
+    ///
+    /// # Parameters
+    ///
+    /// * `capacity` - The number of messages held behind a gap before the gap is given up.
+    /// * `window` - How long a gap is waited for.
+    pub fn new(capacity: usize, window: Duration) -> Self {
+        Self {
+            next: None,
+            held: BTreeMap::new(),
+            capacity,
+            window,
+        }
+    }
+
+    /// Receives the message numbered `seq`.
+    ///
+    /// # Returns
+    ///
+    /// The deliveries released by the message, in order, or `None` for a duplicate.
+    pub fn push(&mut self, seq: u64, payload: Vec<u8>, now: Instant) -> Option<Vec<Delivery>> {
+        let next = *self.next.get_or_insert(seq);
+        if seq < next || self.held.contains_key(&seq) {
+            return None;
+        }
+
+        self.held.insert(seq, (payload, now));
+        let mut deliveries = self.release();
+        while self.held.len() > self.capacity {
+            deliveries.extend(self.skip_gap());
+        }
+        Some(deliveries)
+    }
+
+    /// Gives up on the gaps waited for longer than the reorder window.
+    ///
+    /// # Returns
+    ///
+    /// The deliveries released, in order.
+    pub fn expire(&mut self, now: Instant) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+        while self.deadline().is_some_and(|deadline| deadline <= now) {
+            deliveries.extend(self.skip_gap());
+        }
+        deliveries
+    }
+
+    /// Gives up on every gap, as when the publisher is gone.
+    ///
+    /// # Returns
+    ///
+    /// Every held message, in order, with the gaps between them.
+    pub fn flush(&mut self) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+        while !self.held.is_empty() {
+            deliveries.extend(self.skip_gap());
+        }
+        deliveries
+    }
+
+    /// Returns the time the oldest gap is given up at, if a message is held.
+    ///
+    /// A gap exists since the first message after it arrived, so it is waited for from the
+    /// earliest arrival among the held messages.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held
+            .values()
+            .map(|(_, arrived_at)| *arrived_at + self.window)
+            .min()
+    }
+
+    /// Returns the number of messages held behind a gap.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Reports the gap before the first held message and delivers from there.
+    fn skip_gap(&mut self) -> Vec<Delivery> {
+        let Some((&first, _)) = self.held.first_key_value() else {
+            return Vec::new();
+        };
+        let mut deliveries = Vec::new();
+        if let Some(next) = self.next.filter(|next| *next < first) {
+            deliveries.push(Delivery::Gap {
+                from: next,
+                to: first - 1,
+            });
+        }
+        self.next = Some(first);
+        deliveries.extend(self.release());
+        deliveries
+    }
+
+    /// Delivers the held messages following the expected one without a gap.
+    fn release(&mut self) -> Vec<Delivery> {
+        let mut deliveries = Vec::new();
+        while let Some(next) = self.next {
+            let Some((payload, _)) = self.held.remove(&next) else {
+                break;
+            };
+            deliveries.push(Delivery::Message { seq: next, payload });
+            self.next = next.checked_add(1);
+        }
+        deliveries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(500);
+
+    fn message(seq: u64) -> Delivery {
+        Delivery::Message {
+            seq,
+            payload: vec![seq as u8],
+        }
+    }
+
+    fn push(buffer: &mut ReorderBuffer, seq: u64, now: Instant) -> Option<Vec<Delivery>> {
+        buffer.push(seq, vec![seq as u8], now)
+    }
+
+    #[test]
+    fn delivers_messages_in_order_at_once() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(4, WINDOW);
+        // The first message received sets the expected sequence.
+        assert_eq!(push(&mut buffer, 7, now), Some(vec![message(7)]));
+        assert_eq!(push(&mut buffer, 8, now), Some(vec![message(8)]));
+        assert_eq!(buffer.held(), 0);
+        assert_eq!(buffer.deadline(), None);
+    }
+
+    #[test]
+    fn holds_the_messages_after_a_gap_until_it_fills() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(4, WINDOW);
+        push(&mut buffer, 1, now);
+        assert_eq!(push(&mut buffer, 4, now), Some(Vec::new()));
+        assert_eq!(push(&mut buffer, 3, now), Some(Vec::new()));
+        assert_eq!(buffer.held(), 2);
+        assert_eq!(buffer.deadline(), Some(now + WINDOW));
+
+        assert_eq!(
+            push(&mut buffer, 2, now),
+            Some(vec![message(2), message(3), message(4)])
+        );
+        assert_eq!(buffer.held(), 0);
+    }
+
+    #[test]
+    fn drops_duplicates() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(4, WINDOW);
+        push(&mut buffer, 1, now);
+        push(&mut buffer, 3, now);
+        assert_eq!(push(&mut buffer, 1, now), None);
+        assert_eq!(push(&mut buffer, 0, now), None);
+        assert_eq!(push(&mut buffer, 3, now), None);
+        assert_eq!(buffer.held(), 1);
+    }
+
+    #[test]
+    fn gives_up_on_a_gap_after_the_window() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(4, WINDOW);
+        push(&mut buffer, 1, now);
+        push(&mut buffer, 4, now);
+        push(&mut buffer, 7, now + Duration::from_millis(300));
+
+        assert!(buffer
+            .expire(now + WINDOW - Duration::from_millis(1))
+            .is_empty());
+        assert_eq!(
+            buffer.expire(now + WINDOW),
+            [Delivery::Gap { from: 2, to: 3 }, message(4)]
+        );
+        // The second gap is waited for since the message after it arrived.
+        assert_eq!(
+            buffer.deadline(),
+            Some(now + Duration::from_millis(300) + WINDOW)
+        );
+        assert_eq!(
+            buffer.expire(now + Duration::from_secs(1)),
+            [Delivery::Gap { from: 5, to: 6 }, message(7)]
+        );
+        // A message of a gap given up on is a duplicate.
+        assert_eq!(push(&mut buffer, 5, now + Duration::from_secs(1)), None);
+        assert_eq!(
+            push(&mut buffer, 8, now + Duration::from_secs(1)),
+            Some(vec![message(8)])
+        );
+    }
+
+    #[test]
+    fn a_full_buffer_gives_up_on_its_oldest_gap() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(2, WINDOW);
+        push(&mut buffer, 1, now);
+        push(&mut buffer, 3, now);
+        push(&mut buffer, 5, now);
+        assert_eq!(
+            push(&mut buffer, 6, now),
+            Some(vec![Delivery::Gap { from: 2, to: 2 }, message(3)])
+        );
+        assert_eq!(buffer.held(), 2);
+    }
+
+    #[test]
+    fn flushes_every_held_message_with_its_gaps() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(8, WINDOW);
+        push(&mut buffer, 1, now);
+        push(&mut buffer, 3, now);
+        push(&mut buffer, 4, now);
+        push(&mut buffer, 9, now);
+        assert_eq!(
+            buffer.flush(),
+            [
+                Delivery::Gap { from: 2, to: 2 },
+                message(3),
+                message(4),
+                Delivery::Gap { from: 5, to: 8 },
+                message(9),
+            ]
+        );
+        assert!(buffer.flush().is_empty());
+        assert_eq!(buffer.deadline(), None);
+    }
+}
@@ -0,0 +1,805 @@
+//! Offline Replay of Recordings.
+//!
+//! `gossip-p2p replay --file=<path>` reads a recording made with `--record` and feeds its events
+//! to a participant started in a sandbox: configured with the recorded command-line arguments
+//! and seed, it runs on a `SandboxTransport`, which opens no socket and delivers the recorded
+//! accepts, dials, frames and disconnections instead. What the participant sends, dials and
+//! closes in response is captured in order, tagged with the number of the last recorded event
+//! it had handled, and nothing leaves the process.
+//!
+//! The events are fed with their original relative timing or, with `--fast`, one after the
+//! other, with every message sent at once rather than batched; either way each is printed as it is fed, with its frame decoded again. The replay
+//! ends with the summary of the participant: its connected participants, its message counters,
+//! the recorded events it had no connection for, and the outbound actions captured.
+//!
+//! The sandbox writes none of the files of the recorded run: it uses no peer cache, state file,
+//! report, recording or admin socket, keeps its identity in a temporary directory, and neither
+//! imports a snapshot nor runs the `--exec` commands and `--alert-on` actions. A `--filter-file`
+//! is read as the participant would read it.
+//!
+//! `--output=<path>` writes the summary to a file, and `--diff=<path>` compares the summary
+//! with one written before, printing the lines each has and the other lacks, so a change of
+//! behavior shows as a difference between two builds replaying the same recording.
+
+use crate::participant::config::{ListenPort, NodeConfig};
+use crate::participant::message::Message;
+use crate::participant::model::Participant;
+use crate::participant::record::{Record, RecordError, RecordedEvent, Recording};
+use crate::participant::storage::format_kind_counters;
+use crate::participant::transport::{
+    Connection, ListenerId, OwnedTransportEvent, SendOutcome, Transport, TransportContext,
+    TransportEvent,
+};
+use crate::printer::{print_at, print_line, silence_events};
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the sandboxed participant is given to start and to hand over its report.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest number of lines `diff` aligns; the lines told apart by a longer difference are
+/// shown as removed and added as a whole.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// Keeps the directories of the replays of one process apart.
+static REPLAYS: AtomicU32 = AtomicU32::new(0);
+
+/// Enumerates the reasons a replay can not run to its end.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The recording can not be read.
+    Record(RecordError),
+    /// The sandboxed participant can not be started, or stopped before the end of the
+    /// recording; holds why.
+    Sandbox(String),
+    /// The summary can not be written to, or read back from, the file.
+    Summary(PathBuf, io::Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Record(err) => write!(f, "{}", err),
+            ReplayError::Sandbox(reason) => write!(f, "the sandboxed participant {}", reason),
+            ReplayError::Summary(path, err) => write!(f, "\"{}\": {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// How to replay a recording.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// Whether the events follow each other without waiting for their original timing.
+    pub fast: bool,
+    /// The file the summary is written to, if any.
+    pub output: Option<PathBuf>,
+    /// The file holding the summary of an earlier replay to compare with, if any.
+    pub diff: Option<PathBuf>,
+}
+
+/// What the sandboxed participant ended with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The public addresses of its connected participants, sorted.
+    pub participants: Vec<SocketAddr>,
+    /// Its received message counters, formatted.
+    pub received: String,
+    /// Its sent message counters, formatted.
+    pub sent: String,
+    /// The recorded frames and disconnections of a connection the participant did not have
+    /// open, which a participant behaving as the recorded one never leaves.
+    pub unmatched: u64,
+    /// What it sent, dialed and closed, in order.
+    pub actions: Vec<String>,
+}
+
+impl Summary {
+    /// Returns the lines the summary prints as, and is written and compared as.
+    pub fn lines(&self) -> Vec<String> {
+        let participants: Vec<String> = self
+            .participants
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect();
+        let mut lines = vec![
+            format!("Connected participants: [{}]", participants.join(", ")),
+            format!("Received: {}", self.received),
+            format!("Sent: {}", self.sent),
+            format!("Events without a connection: {}", self.unmatched),
+            format!("Outbound actions: {}", self.actions.len()),
+        ];
+        lines.extend(self.actions.iter().map(|action| format!("\t{}", action)));
+        lines
+    }
+}
+
+/// Replays `recording` in a sandbox, printing its events and the summary of the participant.
+///
+/// # Parameters
+///
+/// * `recording` - The recording to replay.
+/// * `options` - The timing, and the files the summary is written to and compared with.
+/// * `config` - Builds the configuration of a participant from command-line arguments, as
+///   the recorded participant was started with.
+///
+/// # Returns
+///
+/// The number of lines the summary and the one of `options.diff` do not share, `0` without
+/// one to compare with.
+pub fn replay(
+    recording: &Recording,
+    options: &ReplayOptions,
+    config: impl Fn(&[String]) -> Result<NodeConfig, String>,
+) -> Result<usize, ReplayError> {
+    let summary = run_sandboxed(recording, options.fast, config, &mut |record, line| {
+        print_at(Duration::from_millis(record.elapsed_ms), &line)
+    })?;
+
+    print_line(&format!("Replayed {} events", recording.records.len()));
+    if recording.dropped_bytes > 0 {
+        print_line(&format!(
+            "Dropped {} bytes after the last complete record",
+            recording.dropped_bytes
+        ));
+    }
+    let lines = summary.lines();
+    for line in &lines {
+        print_line(line);
+    }
+
+    if let Some(path) = &options.output {
+        let mut content = lines.join("\n");
+        content.push('\n');
+        fs::write(path, content).map_err(|err| ReplayError::Summary(path.clone(), err))?;
+    }
+    let Some(path) = &options.diff else {
+        return Ok(0);
+    };
+    let earlier =
+        fs::read_to_string(path).map_err(|err| ReplayError::Summary(path.clone(), err))?;
+    let earlier: Vec<String> = earlier.lines().map(str::to_owned).collect();
+    let differences = diff(&earlier, &lines);
+    if differences.is_empty() {
+        print_line(&format!("No difference from \"{}\"", path.display()));
+    } else {
+        print_line(&format!("Differences from \"{}\":", path.display()));
+        for line in &differences {
+            print_line(line);
+        }
+    }
+    Ok(differences.len())
+}
+
+/// Feeds the events of `recording` to a sandboxed participant and takes its summary.
+///
+/// # Parameters
+///
+/// * `recording` - The recording to replay.
+/// * `fast` - Whether the events follow each other without waiting for their original timing.
+/// * `config` - Builds the configuration of a participant from command-line arguments.
+/// * `log` - Receives each record, along with its description, as it is fed.
+pub fn run_sandboxed(
+    recording: &Recording,
+    fast: bool,
+    config: impl Fn(&[String]) -> Result<NodeConfig, String>,
+    log: &mut dyn FnMut(&Record, String),
+) -> Result<Summary, ReplayError> {
+    let (public_addr, args, seed) = settings_of(recording)?;
+    let dir = std::env::temp_dir().join(format!(
+        "gossip-p2p-replay-{}-{}",
+        std::process::id(),
+        REPLAYS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let config = config(&args).map_err(|err| {
+        ReplayError::Sandbox(format!("can not be configured as recorded: {}", err))
+    })?;
+    let config = sandbox(config, public_addr.port(), seed, fast, &dir);
+
+    silence_events();
+    let state = Arc::new(Mutex::new(SandboxState::new(public_addr)));
+    let transport = SandboxTransport {
+        state: state.clone(),
+    };
+    let (started, waiter) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("gossip-replay".to_owned())
+        .spawn(move || {
+            let participant = match Participant::with_transport(config, transport) {
+                Ok(participant) => participant,
+                Err(err) => {
+                    let _ = started.send(None);
+                    return Err(err.to_string());
+                }
+            };
+            let _ = started.send(Some(participant.handle()));
+            participant.run().map_err(|err| err.to_string())
+        })
+        .map_err(|err| ReplayError::Sandbox(format!("can not be spawned: {}", err)))?;
+    let stopped = |thread: thread::JoinHandle<Result<(), String>>, when: &str| {
+        let _ = fs::remove_dir_all(&dir);
+        let reason = match thread.join() {
+            Ok(Err(err)) => err,
+            Ok(Ok(())) => "stopped".to_owned(),
+            Err(_) => "panicked".to_owned(),
+        };
+        ReplayError::Sandbox(format!("{}: {}", when, reason))
+    };
+
+    // Once the participant answers, it dialed its bootstrap targets and its loop runs, so the
+    // recorded outcome of these dials finds them.
+    let handle = waiter.recv_timeout(REPLY_TIMEOUT).ok().flatten();
+    let Some(handle) = handle.filter(|handle| handle.report(REPLY_TIMEOUT).is_some()) else {
+        return Err(stopped(thread, "did not start"));
+    };
+    let started_at = Instant::now();
+    for (index, record) in recording.records.iter().enumerate() {
+        if !fast {
+            let elapsed = Duration::from_millis(record.elapsed_ms);
+            if let Some(wait) = elapsed.checked_sub(started_at.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        lock(&state).feed(index, &record.event);
+        log(record, format!("#{} {}", index, describe(&record.event)));
+    }
+
+    // The report is handled after every event fed before it.
+    let Some(report) = handle.report(REPLY_TIMEOUT) else {
+        return Err(stopped(thread, "stopped before the end of the recording"));
+    };
+    // What the participant does as it shuts down is no response to the recording.
+    let (unmatched, actions) = {
+        let state = lock(&state);
+        (state.unmatched, state.actions.clone())
+    };
+    handle.shutdown();
+    let _ = thread.join();
+    let _ = fs::remove_dir_all(&dir);
+
+    Ok(Summary {
+        participants: report
+            .peers
+            .iter()
+            .filter(|peer| peer.connected_for.is_some())
+            .map(|peer| peer.addr)
+            .collect(),
+        received: format_kind_counters(&report.received),
+        sent: format_kind_counters(&report.sent),
+        unmatched,
+        actions,
+    })
+}
+
+/// Reads the public address, the command-line arguments and the seed of the participant from
+/// the first records of `recording`.
+///
+/// A recording made before the settings were recorded replays with the default settings on
+/// the recorded port, and a seed of its own.
+fn settings_of(
+    recording: &Recording,
+) -> Result<(SocketAddr, Vec<String>, Option<u64>), ReplayError> {
+    let mut records = recording.records.iter().map(|record| &record.event);
+    let Some(RecordedEvent::Started { public_addr, .. }) = records.next() else {
+        return Err(ReplayError::Sandbox(
+            "can not be configured: the recording does not start with its participant".to_owned(),
+        ));
+    };
+    match records.next() {
+        Some(RecordedEvent::Settings { args, seed }) => {
+            Ok((*public_addr, args.clone(), Some(*seed)))
+        }
+        _ => {
+            print_line("The recording holds no settings, so it replays with the default ones");
+            Ok((
+                *public_addr,
+                vec![format!("--port={}", public_addr.port())],
+                None,
+            ))
+        }
+    }
+}
+
+/// Takes the files, the sockets and the commands of the recorded run out of `config`, so the
+/// replay neither reads the state of the recorded run nor leaves anything behind.
+///
+/// # Parameters
+///
+/// * `port` - The port the recorded participant listened on, even when it was given as `0`.
+/// * `seed` - The seed of the recorded participant, if known.
+/// * `fast` - Whether the replay is fast, which sends every message at once: what a batch
+///   holds depends on the timing a fast replay drops.
+/// * `dir` - The temporary directory the identity of the sandbox is kept in.
+fn sandbox(
+    mut config: NodeConfig,
+    port: u16,
+    seed: Option<u64>,
+    fast: bool,
+    dir: &Path,
+) -> NodeConfig {
+    config.port = ListenPort::from(port);
+    config.seed = seed;
+    if fast {
+        config.batch_window = Duration::ZERO;
+    }
+    config.use_peer_cache = false;
+    config.identity_file = Some(dir.join("node.id"));
+    config.import_membership = None;
+    config.state_file = None;
+    config.state_save_interval = None;
+    config.report_file = None;
+    config.record = None;
+    config.admin_socket = None;
+    config.interactive = false;
+    config.exec = Vec::new();
+    config.alerts = Vec::new();
+    config
+}
+
+/// Describes one recorded event, decoding its frame again.
+fn describe(event: &RecordedEvent) -> String {
+    match event {
+        RecordedEvent::Started {
+            public_addr,
+            config,
+        } => format!("Recording of \"{}\" started with {}", public_addr, config),
+        RecordedEvent::Settings { args, seed } => {
+            format!("Started with [{}] and seed {}", args.join(" "), seed)
+        }
+        RecordedEvent::Accepted { endpoint } => format!("Accepted a connection from {}", endpoint),
+        RecordedEvent::Connected {
+            endpoint,
+            established: true,
+        } => format!("Connected to \"{}\"", endpoint),
+        RecordedEvent::Connected {
+            endpoint,
+            established: false,
+        } => format!("Can not connect to {}", endpoint),
+        RecordedEvent::Message { sender, frame } => {
+            format!("Received {} from {}", describe_frame(frame), sender)
+        }
+        RecordedEvent::Disconnected { endpoint } => format!("Disconnected from {}", endpoint),
+        // The connections already tell the peers; `analyze` follows the membership.
+        RecordedEvent::Membership { change, .. } => format!("Membership: {}", change),
+    }
+}
+
+/// Describes a frame by the kind of its message.
+fn describe_frame(frame: &[u8]) -> String {
+    match Message::decode(frame) {
+        Ok((Message::Batch(messages), _)) => {
+            format!("Batch of {} messages", messages.len())
+        }
+        Ok((message, _)) => message.kind().name().to_owned(),
+        Err(_) => format!("an undecodable frame of {} bytes", frame.len()),
+    }
+}
+
+/// Aligns `new` on `old` and returns the lines told apart, in order: `- ` before a line only
+/// `old` has, `+ ` before one only `new` has.
+pub fn diff(old: &[String], new: &[String]) -> Vec<String> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let removed = |line: &String| format!("- {}", line);
+    let added = |line: &String| format!("+ {}", line);
+    if (old.len() + 1) * (new.len() + 1) > MAX_DIFF_CELLS {
+        return old
+            .iter()
+            .map(removed)
+            .chain(new.iter().map(added))
+            .collect();
+    }
+
+    // The longest common subsequence of the tails, `common[i][j]` for `old[i..]` and `new[j..]`.
+    let width = new.len() + 1;
+    let mut common = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            lines.push(removed(&old[i]));
+            i += 1;
+        } else {
+            lines.push(added(&new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(removed));
+    lines.extend(new[j..].iter().map(added));
+    lines
+}
+
+/// What the sandbox knows of the connections of its participant, and what it captured.
+struct SandboxState {
+    /// The context of the participant, once attached.
+    context: Option<TransportContext>,
+    /// The public address of the recorded participant.
+    public_addr: SocketAddr,
+    /// The listener the recorded connections are accepted on.
+    listener: Option<ListenerId>,
+    /// The id of the next connection or listener.
+    next_id: u64,
+    /// The dials of the participant the recording did not tell the outcome of yet, by address.
+    dialing: HashMap<SocketAddr, Connection>,
+    /// The open connections, by the endpoint address the recording knows them by.
+    open: HashMap<SocketAddr, Connection>,
+    /// The numbers of the records fed and not handled yet by the participant, in order.
+    in_flight: VecDeque<usize>,
+    /// The number of the last record the participant handled.
+    handled: usize,
+    /// The recorded events of a connection that was not open.
+    unmatched: u64,
+    /// The actions captured, in order.
+    actions: Vec<String>,
+}
+
+impl SandboxState {
+    fn new(public_addr: SocketAddr) -> Self {
+        Self {
+            context: None,
+            public_addr,
+            listener: None,
+            next_id: 0,
+            dialing: HashMap::new(),
+            open: HashMap::new(),
+            in_flight: VecDeque::new(),
+            handled: 0,
+            unmatched: 0,
+            actions: Vec::new(),
+        }
+    }
+
+    fn next_connection(&mut self, addr: SocketAddr) -> Connection {
+        self.next_id += 1;
+        Connection::new(self.next_id, addr)
+    }
+
+    /// Notes an action of the participant, after the last record it handled.
+    fn capture(&mut self, action: String) {
+        self.actions.push(format!("#{} {}", self.handled, action));
+    }
+
+    /// Hands the network event of the record `index` to the participant, on the connection it
+    /// refers to.
+    fn feed(&mut self, index: usize, event: &RecordedEvent) {
+        let event = match event {
+            RecordedEvent::Accepted { endpoint } => {
+                let conn = self.next_connection(*endpoint);
+                self.open.insert(*endpoint, conn);
+                let listener = self.listener.unwrap_or(ListenerId::new(0));
+                Some(OwnedTransportEvent::Accepted(conn, listener))
+            }
+            RecordedEvent::Connected {
+                endpoint,
+                established,
+            } => {
+                let conn = match self.dialing.remove(endpoint) {
+                    Some(conn) => conn,
+                    None => self.next_connection(*endpoint),
+                };
+                if *established {
+                    self.open.insert(*endpoint, conn);
+                }
+                Some(OwnedTransportEvent::Connected(conn, *established))
+            }
+            RecordedEvent::Message { sender, frame } => self
+                .open
+                .get(sender)
+                .map(|conn| OwnedTransportEvent::Frame(*conn, frame.clone())),
+            RecordedEvent::Disconnected { endpoint } => {
+                self.open.remove(endpoint).map(OwnedTransportEvent::Closed)
+            }
+            RecordedEvent::Started { .. }
+            | RecordedEvent::Settings { .. }
+            | RecordedEvent::Membership { .. } => return,
+        };
+        match (event, &self.context) {
+            (Some(event), Some(context)) => {
+                self.in_flight.push_back(index);
+                context.deliver(event);
+            }
+            _ => self.unmatched += 1,
+        }
+    }
+
+    /// Forgets `conn`, open or dialing.
+    ///
+    /// # Returns
+    ///
+    /// Whether it was known.
+    fn forget(&mut self, conn: Connection) -> bool {
+        let addr = conn.addr();
+        if self.open.get(&addr) == Some(&conn) {
+            self.open.remove(&addr);
+            return true;
+        }
+        if self.dialing.get(&addr) == Some(&conn) {
+            self.dialing.remove(&addr);
+            return true;
+        }
+        false
+    }
+}
+
+/// A transport whose connections are those of a recording: its events come from the records
+/// fed by the replay, and what the participant sends through it is captured.
+struct SandboxTransport {
+    state: Arc<Mutex<SandboxState>>,
+}
+
+impl Transport for SandboxTransport {
+    fn attach(&mut self, context: TransportContext) {
+        lock(&self.state).context = Some(context);
+    }
+
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)> {
+        let mut state = lock(&self.state);
+        let mut bound = addr;
+        if bound.port() == 0 {
+            bound.set_port(state.public_addr.port());
+        }
+        state.next_id += 1;
+        let listener = ListenerId::new(state.next_id);
+        state.listener.get_or_insert(listener);
+        Ok((listener, bound))
+    }
+
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)> {
+        let mut state = lock(&self.state);
+        let conn = state.next_connection(addr);
+        state.dialing.insert(addr, conn);
+        state.capture(format!("dial {}", addr));
+        Ok((conn, SocketAddr::from((Ipv4Addr::LOCALHOST, 0))))
+    }
+
+    fn send(&mut self, conn: Connection, frame: &[u8]) -> SendOutcome {
+        let mut state = lock(&self.state);
+        if state.open.get(&conn.addr()) == Some(&conn) {
+            state.capture(format!("send {} to {}", describe_frame(frame), conn.addr()));
+            SendOutcome::Sent
+        } else if state.dialing.get(&conn.addr()) == Some(&conn) {
+            SendOutcome::NotReady
+        } else {
+            SendOutcome::Closed
+        }
+    }
+
+    fn close(&mut self, conn: Connection) -> bool {
+        let mut state = lock(&self.state);
+        let known = state.forget(conn);
+        if known {
+            state.capture(format!("close {}", conn.addr()));
+        }
+        known
+    }
+
+    fn is_open(&self, conn: Connection) -> bool {
+        let state = lock(&self.state);
+        state.open.get(&conn.addr()) == Some(&conn)
+            || state.dialing.get(&conn.addr()) == Some(&conn)
+    }
+
+    fn admit(&mut self, _: Connection) -> bool {
+        true
+    }
+
+    /// Moves the actions captured from then on after the record the event was fed from.
+    fn observe(&mut self, _: &TransportEvent<'_>) {
+        let mut state = lock(&self.state);
+        if let Some(index) = state.in_flight.pop_front() {
+            state.handled = index;
+        }
+    }
+}
+
+/// Locks the state of a sandbox, whose updates leave it consistent even if one panicked.
+fn lock(state: &Mutex<SandboxState>) -> MutexGuard<'_, SandboxState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn recording(events: Vec<RecordedEvent>) -> Recording {
+        Recording {
+            records: events
+                .into_iter()
+                .map(|event| Record {
+                    elapsed_ms: 0,
+                    event,
+                })
+                .collect(),
+            dropped_bytes: 0,
+        }
+    }
+
+    fn started(port: u16) -> RecordedEvent {
+        RecordedEvent::Started {
+            public_addr: addr(port),
+            config: "config".to_owned(),
+        }
+    }
+
+    #[test]
+    fn the_same_lines_do_not_differ() {
+        let same = lines(&["a", "b", "c"]);
+        assert!(diff(&same, &same).is_empty());
+        assert!(diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn the_lines_only_one_side_has_are_told_apart_in_order() {
+        let old = lines(&["a", "b", "c", "d"]);
+        let new = lines(&["a", "x", "c", "d", "e"]);
+        assert_eq!(diff(&old, &new), ["- b", "+ x", "+ e"]);
+        assert_eq!(diff(&old, &[]), ["- a", "- b", "- c", "- d"]);
+        assert_eq!(diff(&[], &lines(&["a"])), ["+ a"]);
+        // A line moved elsewhere is removed at one place and added at the other.
+        let moved = lines(&["b", "c", "d", "a"]);
+        assert_eq!(diff(&old, &moved), ["- a", "+ a"]);
+    }
+
+    #[test]
+    fn a_summary_prints_one_line_per_action() {
+        let summary = Summary {
+            participants: vec![addr(9001), addr(9002)],
+            received: "[Text: 1]".to_owned(),
+            sent: "[Text: 0]".to_owned(),
+            unmatched: 2,
+            actions: lines(&["#1 dial 127.0.0.1:9001", "#3 close 127.0.0.1:9001"]),
+        };
+        assert_eq!(
+            summary.lines(),
+            [
+                "Connected participants: [127.0.0.1:9001, 127.0.0.1:9002]",
+                "Received: [Text: 1]",
+                "Sent: [Text: 0]",
+                "Events without a connection: 2",
+                "Outbound actions: 2",
+                "\t#1 dial 127.0.0.1:9001",
+                "\t#3 close 127.0.0.1:9001",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_recorded_frame_is_described_by_its_message() {
+        let frame = Message::Text("hello".to_owned()).encode();
+        assert_eq!(
+            describe(&RecordedEvent::Message {
+                sender: addr(20001),
+                frame
+            }),
+            "Received Text from 127.0.0.1:20001"
+        );
+        let batch = Message::Batch(vec![
+            Message::PushParticipantsList,
+            Message::PushParticipantsList,
+        ]);
+        assert_eq!(describe_frame(&batch.encode()), "Batch of 2 messages");
+        assert_eq!(
+            describe_frame(&[0xff; 3]),
+            "an undecodable frame of 3 bytes"
+        );
+        assert_eq!(
+            describe(&RecordedEvent::Connected {
+                endpoint: addr(9001),
+                established: false
+            }),
+            "Can not connect to 127.0.0.1:9001"
+        );
+    }
+
+    #[test]
+    fn the_settings_are_read_from_the_first_records() {
+        let args = lines(&["--port=9000", "--period=1s"]);
+        let settings = RecordedEvent::Settings {
+            args: args.clone(),
+            seed: 42,
+        };
+        let (public_addr, read, seed) =
+            settings_of(&recording(vec![started(9000), settings])).unwrap();
+        assert_eq!((public_addr, read, seed), (addr(9000), args, Some(42)));
+
+        // An older recording replays on its port with the default settings.
+        let (_, read, seed) = settings_of(&recording(vec![started(9000)])).unwrap();
+        assert_eq!((read, seed), (lines(&["--port=9000"]), None));
+
+        let accepted = RecordedEvent::Accepted {
+            endpoint: addr(20001),
+        };
+        assert!(matches!(
+            settings_of(&recording(vec![accepted])),
+            Err(ReplayError::Sandbox(_))
+        ));
+    }
+
+    #[test]
+    fn a_fast_replay_captures_the_same_actions_every_time() {
+        let peer = addr(20001);
+        let recording = recording(vec![
+            started(9500),
+            RecordedEvent::Settings {
+                args: lines(&["--port=9500", "--period=1h"]),
+                seed: 7,
+            },
+            RecordedEvent::Accepted { endpoint: peer },
+            RecordedEvent::Message {
+                sender: peer,
+                frame: Message::PublicAddress(addr(9501)).encode(),
+            },
+            RecordedEvent::Message {
+                sender: addr(20002),
+                frame: Message::Text("lost".to_owned()).encode(),
+            },
+            RecordedEvent::Message {
+                sender: peer,
+                frame: Message::PushParticipantsList.encode(),
+            },
+        ]);
+        let run = || {
+            let mut fed = Vec::new();
+            let summary = run_sandboxed(&recording, true, NodeConfig::from_args, &mut |_, line| {
+                fed.push(line)
+            })
+            .unwrap();
+            (summary, fed)
+        };
+        let (summary, fed) = run();
+        assert_eq!(fed.len(), 6);
+        assert_eq!(fed[2], "#2 Accepted a connection from 127.0.0.1:20001");
+        assert_eq!(summary.participants, [addr(9501)]);
+        assert_eq!(summary.unmatched, 1);
+        assert!(
+            summary
+                .actions
+                .iter()
+                .any(|action| action.starts_with("#5 send ")
+                    && action.ends_with(" to 127.0.0.1:20001")),
+            "{:?}",
+            summary.actions
+        );
+        assert_eq!(run().0, summary);
+    }
+}
@@ -0,0 +1,769 @@
+//! Run Reports.
+//!
+//! Telling from the logs whether a scripted run converged means grepping every node. With
+//! `--report-file`, a participant writes a `Report` of its run as one JSON object when it shuts
+//! down gracefully, and the `report` console command prints the same object at any time:
+//!
+//! ```plaintext
+//! {"node":"127.0.0.1:8080","node_id":"5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e","protocol_version":6,
+//!  "config_digest":"9c1f0e3a5b7d2481","uptime_ms":5012,"peers":[{"addr":"127.0.0.1:8081",
+//!  "node_id":"a41d07c28e5b9f3066d2c1e47b8a9053","former_addrs":["127.0.0.1:8091"],"sessions":1,"connected_ms":4990,
+//!  "verified_ago_ms":12,"direction":"outbound","clock_offset":{"offset_us":-152,"bound_us":61,
+//!  "samples":4},"provenance":{"source":"list","learned_at":1760399995,"uptime_ms":310,
+//!  "from":"127.0.0.1:8082","request":17,"corroborators":2}}],"sent":{"PublicAddress":1,...},
+//!  "received":{...},"ordered_delivery":{"gaps":0,"duplicates":0},"departures":{"disconnected":1},
+//!  "evictions":{"dedup":0,"rate_limit":0},"peer_set":{"changes":2,
+//!  "first_change_unix_ms":1760400000123,"last_change_unix_ms":1760400000456},...,
+//!  "scheduled":[{"id":7,"due_unix_ms":1760403600000,"kind":"SequencedTopic","topic":"news",
+//!  "bytes":19}],"anomalies":{"undecodable":0,"protocol_mismatch":1,...},
+//!  "dials":{"outcomes":{"connected":3,"failed":1,...},"triggers":{"bootstrap":1,...}}}
+//! ```
+//!
+//! The time of the last change of the peer set is a proxy for the time the network took to
+//! converge. A peer that came back from another address is listed once, under its current
+//! address, with the addresses its node id was seen at before, and with the `provenance` of its
+//! address, null when it was not kept. The field names are part of the format: scripts read
+//! them, so a field is only ever added.
+//!
+//! `--report-format=csv` writes the peer table of `csv` instead, and `both` writes the JSON
+//! report and the table beside it, in the same file name with the `csv` extension.
+
+use crate::participant::anomaly::AnomalyKind;
+use crate::participant::dials::{DialOutcome, DialTrigger};
+use crate::participant::identity::NodeId;
+use crate::participant::message::MessageKind;
+use crate::participant::provenance::{LearnedVia, Provenance};
+use crate::participant::retention::{KindUsage, RetainedKind};
+use crate::participant::schedule::ScheduledEntry;
+use crate::participant::session::DepartureReason;
+use crate::participant::skew::ClockOffset;
+use crate::participant::storage::{Direction, KindCounters};
+
+use serde::{Serialize, Serializer};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What `--report-file` receives at shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// The JSON `Report`.
+    #[default]
+    Json,
+    /// The peer table of `csv`.
+    Csv,
+    /// The JSON report, and the peer table beside it, see `csv_path`.
+    Both,
+}
+
+impl ReportFormat {
+    /// Returns the name of this format, as in `--report-format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Both => "both",
+        }
+    }
+
+    /// Returns the files written for the report file `path`: the JSON one, then the CSV one.
+    pub fn paths(self, path: &Path) -> (Option<PathBuf>, Option<PathBuf>) {
+        match self {
+            ReportFormat::Json => (Some(path.to_owned()), None),
+            ReportFormat::Csv => (None, Some(path.to_owned())),
+            ReportFormat::Both => (Some(path.to_owned()), Some(csv_path(path))),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "both" => Ok(ReportFormat::Both),
+            _ => Err(format!("unknown report format \"{}\"", text)),
+        }
+    }
+}
+
+/// Returns the file receiving the peer table beside the JSON report `path`, which has the same
+/// name with the `csv` extension.
+pub fn csv_path(path: &Path) -> PathBuf {
+    path.with_extension("csv")
+}
+
+/// Digests the `Debug` form of a configuration with 64-bit FNV-1a, which stays the same from
+/// one run and one build to the next, so reports of identically configured nodes compare.
+///
+/// # Parameters
+///
+/// * `config` - The `Debug` form of the configuration.
+pub fn config_digest(config: &str) -> u64 {
+    config.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// When the set of connected peers changed, first and last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerSetChanges {
+    /// The number of joins and departures.
+    pub count: u64,
+    /// The time of the first change.
+    pub first: Option<SystemTime>,
+    /// The time of the last change.
+    pub last: Option<SystemTime>,
+}
+
+impl PeerSetChanges {
+    /// Records a join or a departure at `now`.
+    pub fn record(&mut self, now: SystemTime) {
+        self.count += 1;
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+    }
+}
+
+/// A peer as listed in a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportedPeer {
+    /// The public address of the peer.
+    pub addr: SocketAddr,
+    /// The persistent id the peer presented, if any.
+    pub node_id: Option<NodeId>,
+    /// The other addresses its node id was seen at, the most recent first.
+    pub former_addrs: Vec<SocketAddr>,
+    /// The number of sessions of the peer.
+    pub sessions: u64,
+    /// How long the current session lasts, `None` while the peer is being dialed.
+    pub connected_for: Option<Duration>,
+    /// How long ago the peer was last heard from, `None` if never.
+    pub verified_ago: Option<Duration>,
+    /// Which side opened the connection to the peer.
+    pub direction: Direction,
+    /// The estimated offset of the clock of the peer from ours, `None` before the first sample.
+    pub clock_offset: Option<ClockOffset>,
+    /// Where the address was first learned, `None` if that is not known.
+    pub provenance: Option<Provenance>,
+}
+
+/// The summary of a run of a participant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The public address of the participant.
+    pub node: SocketAddr,
+    /// The persistent id of the participant.
+    pub node_id: NodeId,
+    /// The highest protocol version the participant speaks.
+    pub protocol_version: u16,
+    /// The `config_digest` of the configuration of the participant.
+    pub config_digest: u64,
+    /// How long the participant ran.
+    pub uptime: Duration,
+    /// The peers, sorted by address.
+    pub peers: Vec<ReportedPeer>,
+    /// The messages sent to participants, per kind.
+    pub sent: KindCounters,
+    /// The messages received from participants, per kind.
+    pub received: KindCounters,
+    /// The gaps reported to ordered subscriptions.
+    pub ordered_gaps: u64,
+    /// The duplicates dropped by ordered subscriptions.
+    pub ordered_duplicates: u64,
+    /// The number of sessions ended for each reason.
+    pub departures: Vec<(DepartureReason, u64)>,
+    /// The dials forgotten early because the dedup budget was exhausted.
+    pub dedup_evictions: u64,
+    /// The rate limiter buckets forgotten early because its budget was exhausted.
+    pub ratelimit_evictions: u64,
+    /// The changes of the peer set.
+    pub peer_set: PeerSetChanges,
+    /// The addresses banned at runtime, with the time left on each ban.
+    pub bans: Vec<(SocketAddr, Duration)>,
+    /// The peer history kept and evicted, per `RetainedKind`.
+    pub retention: [KindUsage; RetainedKind::COUNT],
+    /// The publications scheduled and not sent yet, by due time.
+    pub scheduled: Vec<ScheduledEntry>,
+    /// The protocol anomalies caused by peers, per `AnomalyKind`.
+    pub anomalies: [u64; AnomalyKind::COUNT],
+    /// The dials that ended, per `DialOutcome`.
+    pub dial_outcomes: [u64; DialOutcome::COUNT],
+    /// The dials started or decided against, per `DialTrigger`.
+    pub dial_triggers: [u64; DialTrigger::COUNT],
+}
+
+impl Report {
+    /// Formats the report as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let retained_bytes: usize = self.retention.iter().map(|usage| usage.bytes).sum();
+        let json = ReportJson {
+            node: self.node,
+            node_id: self.node_id,
+            protocol_version: self.protocol_version,
+            config_digest: format!("{:016x}", self.config_digest),
+            uptime_ms: self.uptime.as_millis(),
+            peers: self.peers.iter().map(PeerJson::of).collect(),
+            sent: kind_counters(&self.sent),
+            received: kind_counters(&self.received),
+            ordered_delivery: OrderedDeliveryJson {
+                gaps: self.ordered_gaps,
+                duplicates: self.ordered_duplicates,
+            },
+            departures: Named(
+                self.departures
+                    .iter()
+                    .map(|(reason, count)| (reason.name(), *count))
+                    .collect(),
+            ),
+            evictions: EvictionsJson {
+                dedup: self.dedup_evictions,
+                rate_limit: self.ratelimit_evictions,
+            },
+            peer_set: PeerSetJson {
+                changes: self.peer_set.count,
+                first_change_unix_ms: unix_millis(self.peer_set.first),
+                last_change_unix_ms: unix_millis(self.peer_set.last),
+            },
+            bans: self
+                .bans
+                .iter()
+                .map(|(addr, left)| BanJson {
+                    addr: *addr,
+                    remaining_ms: left.as_millis(),
+                })
+                .collect(),
+            retention: RetentionJson {
+                kinds: Named(
+                    RetainedKind::ALL
+                        .iter()
+                        .map(|kind| {
+                            let usage = self.retention[*kind as usize];
+                            let [age, count, bytes] = usage.evicted;
+                            let retained = RetainedJson {
+                                entries: usage.entries,
+                                bytes: usage.bytes,
+                                evicted: EvictedJson { age, count, bytes },
+                            };
+                            (kind.name(), retained)
+                        })
+                        .collect(),
+                ),
+                total_bytes: retained_bytes,
+            },
+            scheduled: self
+                .scheduled
+                .iter()
+                .map(|entry| ScheduledJson {
+                    id: entry.id,
+                    due_unix_ms: unix_millis(Some(entry.due)),
+                    kind: entry.kind.name(),
+                    topic: entry.topic.as_deref(),
+                    bytes: entry.bytes,
+                })
+                .collect(),
+            anomalies: Named(
+                AnomalyKind::ALL
+                    .iter()
+                    .map(|kind| (kind.name(), self.anomalies[*kind as usize]))
+                    .collect(),
+            ),
+            dials: DialsJson {
+                outcomes: Named(
+                    DialOutcome::ALL
+                        .iter()
+                        .map(|outcome| (outcome.name(), self.dial_outcomes[*outcome as usize]))
+                        .collect(),
+                ),
+                triggers: Named(
+                    DialTrigger::ALL
+                        .iter()
+                        .map(|trigger| (trigger.name(), self.dial_triggers[*trigger as usize]))
+                        .collect(),
+                ),
+            },
+        };
+        serde_json::to_string(&json).expect("a report serializes")
+    }
+
+    /// Writes the report to `path` through a temporary file, so a reader never sees half a
+    /// report.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the report file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_json() + "\n")?;
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Values keyed by name, serialized as a JSON object in the order given.
+struct Named<V>(Vec<(&'static str, V)>);
+
+impl<V: Serialize> Serialize for Named<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+/// The JSON layout of a `Report`, whose field names are part of the format.
+#[derive(Serialize)]
+struct ReportJson<'a> {
+    node: SocketAddr,
+    node_id: NodeId,
+    protocol_version: u16,
+    config_digest: String,
+    uptime_ms: u128,
+    peers: Vec<PeerJson<'a>>,
+    sent: Named<u64>,
+    received: Named<u64>,
+    ordered_delivery: OrderedDeliveryJson,
+    departures: Named<u64>,
+    evictions: EvictionsJson,
+    peer_set: PeerSetJson,
+    bans: Vec<BanJson>,
+    retention: RetentionJson,
+    scheduled: Vec<ScheduledJson<'a>>,
+    anomalies: Named<u64>,
+    dials: DialsJson,
+}
+
+#[derive(Serialize)]
+struct PeerJson<'a> {
+    addr: SocketAddr,
+    node_id: Option<NodeId>,
+    former_addrs: &'a [SocketAddr],
+    sessions: u64,
+    connected_ms: Option<u128>,
+    verified_ago_ms: Option<u128>,
+    direction: &'static str,
+    clock_offset: Option<ClockOffsetJson>,
+    provenance: Option<ProvenanceJson>,
+}
+
+impl<'a> PeerJson<'a> {
+    fn of(peer: &'a ReportedPeer) -> Self {
+        Self {
+            addr: peer.addr,
+            node_id: peer.node_id,
+            former_addrs: &peer.former_addrs,
+            sessions: peer.sessions,
+            connected_ms: peer.connected_for.map(|duration| duration.as_millis()),
+            verified_ago_ms: peer.verified_ago.map(|duration| duration.as_millis()),
+            direction: peer.direction.name(),
+            clock_offset: peer.clock_offset.map(|offset| ClockOffsetJson {
+                offset_us: offset.offset_us,
+                bound_us: offset.bound.as_micros(),
+                samples: offset.samples,
+            }),
+            provenance: peer.provenance.as_ref().map(ProvenanceJson::of),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ClockOffsetJson {
+    offset_us: i64,
+    bound_us: u128,
+    samples: u64,
+}
+
+#[derive(Serialize)]
+struct ProvenanceJson {
+    source: &'static str,
+    learned_at: u64,
+    uptime_ms: Option<u128>,
+    from: Option<SocketAddr>,
+    request: Option<u64>,
+    corroborators: usize,
+}
+
+impl ProvenanceJson {
+    fn of(provenance: &Provenance) -> Self {
+        let request = match provenance.source {
+            LearnedVia::List { request, .. } => request,
+            _ => None,
+        };
+        Self {
+            source: provenance.source.name(),
+            learned_at: provenance.learned_at,
+            uptime_ms: provenance.uptime.map(|uptime| uptime.as_millis()),
+            from: provenance.source.peer(),
+            request,
+            corroborators: provenance.corroborators.len(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderedDeliveryJson {
+    gaps: u64,
+    duplicates: u64,
+}
+
+#[derive(Serialize)]
+struct EvictionsJson {
+    dedup: u64,
+    rate_limit: u64,
+}
+
+#[derive(Serialize)]
+struct PeerSetJson {
+    changes: u64,
+    first_change_unix_ms: Option<u128>,
+    last_change_unix_ms: Option<u128>,
+}
+
+#[derive(Serialize)]
+struct BanJson {
+    addr: SocketAddr,
+    remaining_ms: u128,
+}
+
+#[derive(Serialize)]
+struct RetentionJson {
+    #[serde(flatten)]
+    kinds: Named<RetainedJson>,
+    total_bytes: usize,
+}
+
+#[derive(Serialize)]
+struct RetainedJson {
+    entries: usize,
+    bytes: usize,
+    evicted: EvictedJson,
+}
+
+#[derive(Serialize)]
+struct EvictedJson {
+    age: u64,
+    count: u64,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct ScheduledJson<'a> {
+    id: u64,
+    due_unix_ms: Option<u128>,
+    kind: &'static str,
+    topic: Option<&'a str>,
+    bytes: usize,
+}
+
+#[derive(Serialize)]
+struct DialsJson {
+    outcomes: Named<u64>,
+    triggers: Named<u64>,
+}
+
+/// Returns per-kind counters keyed by kind name.
+fn kind_counters(counters: &KindCounters) -> Named<u64> {
+    Named(
+        MessageKind::ALL
+            .iter()
+            .map(|kind| (kind.name(), counters[kind.index()]))
+            .collect(),
+    )
+}
+
+/// Returns a time in milliseconds since the Unix epoch.
+fn unix_millis(time: Option<SystemTime>) -> Option<u128> {
+    time.map(|time| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::retention::EvictedBy;
+    use crate::participant::test_support::{addr, TempDir};
+
+    use serde_json::Value;
+
+    fn at(unix_ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(unix_ms)
+    }
+
+    /// A report with every field set, and a peer of each shape.
+    fn populated() -> Report {
+        let mut sent = [0; MessageKind::COUNT];
+        sent[MessageKind::Text.index()] = 12;
+        let mut received = [0; MessageKind::COUNT];
+        received[MessageKind::PublicAddress.index()] = 2;
+        let mut retention = [KindUsage::default(); RetainedKind::COUNT];
+        retention[RetainedKind::Departed as usize] = KindUsage {
+            entries: 3,
+            bytes: 300,
+            evicted: [1; EvictedBy::COUNT],
+        };
+        let mut anomalies = [0; AnomalyKind::COUNT];
+        anomalies[AnomalyKind::ALL[0] as usize] = 4;
+        let mut dial_outcomes = [0; DialOutcome::COUNT];
+        dial_outcomes[DialOutcome::ALL[0] as usize] = 5;
+        let mut dial_triggers = [0; DialTrigger::COUNT];
+        dial_triggers[DialTrigger::ALL[0] as usize] = 6;
+        Report {
+            node: addr(8080),
+            node_id: NodeId(0x5f0c),
+            protocol_version: 6,
+            config_digest: config_digest("config"),
+            uptime: Duration::from_millis(5012),
+            peers: vec![
+                ReportedPeer {
+                    addr: addr(8081),
+                    node_id: Some(NodeId(0xa41d)),
+                    former_addrs: vec![addr(8091)],
+                    sessions: 2,
+                    connected_for: Some(Duration::from_millis(4990)),
+                    verified_ago: Some(Duration::from_millis(12)),
+                    direction: Direction::Outbound,
+                    clock_offset: Some(ClockOffset {
+                        offset_us: -152,
+                        bound: Duration::from_micros(61),
+                        samples: 4,
+                    }),
+                    provenance: Some(Provenance {
+                        source: LearnedVia::List {
+                            lister: addr(8082),
+                            request: Some(17),
+                        },
+                        learned_at: 1_760_399_995,
+                        uptime: Some(Duration::from_millis(310)),
+                        corroborators: vec![addr(8083), addr(8084)],
+                    }),
+                },
+                ReportedPeer {
+                    addr: addr(8082),
+                    node_id: None,
+                    former_addrs: Vec::new(),
+                    sessions: 0,
+                    connected_for: None,
+                    verified_ago: None,
+                    direction: Direction::Inbound,
+                    clock_offset: None,
+                    provenance: None,
+                },
+            ],
+            sent,
+            received,
+            ordered_gaps: 1,
+            ordered_duplicates: 2,
+            departures: vec![(DepartureReason::Disconnected, 1)],
+            dedup_evictions: 3,
+            ratelimit_evictions: 4,
+            peer_set: PeerSetChanges {
+                count: 2,
+                first: Some(at(1_760_400_000_123)),
+                last: Some(at(1_760_400_000_456)),
+            },
+            bans: vec![(addr(9000), Duration::from_secs(30))],
+            retention,
+            scheduled: vec![ScheduledEntry {
+                id: 7,
+                due: at(1_760_403_600_000),
+                kind: MessageKind::SequencedTopic,
+                topic: Some("news".to_owned()),
+                bytes: 19,
+            }],
+            anomalies,
+            dial_outcomes,
+            dial_triggers,
+        }
+    }
+
+    /// Asserts that the object `value` has exactly the fields `expected`, in any order.
+    fn assert_fields(value: &Value, expected: &[&str]) {
+        let mut fields: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut expected = expected.to_vec();
+        fields.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(fields, expected);
+    }
+
+    #[test]
+    fn a_populated_report_is_one_line_of_json() {
+        let json = populated().to_json();
+        assert!(!json.contains('\n'));
+        let report: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(report["node"], "127.0.0.1:8080");
+        assert_eq!(report["config_digest"].as_str().unwrap().len(), 16);
+        assert_eq!(report["uptime_ms"], 5012);
+        let peer = &report["peers"][0];
+        assert_eq!(peer["former_addrs"][0], "127.0.0.1:8091");
+        assert_eq!(peer["connected_ms"], 4990);
+        assert_eq!(peer["direction"], "outbound");
+        assert_eq!(peer["clock_offset"]["offset_us"], -152);
+        assert_eq!(peer["provenance"]["source"], "list");
+        assert_eq!(peer["provenance"]["request"], 17);
+        assert_eq!(peer["provenance"]["corroborators"], 2);
+        let unknown = &report["peers"][1];
+        for field in [
+            "node_id",
+            "connected_ms",
+            "verified_ago_ms",
+            "clock_offset",
+            "provenance",
+        ] {
+            assert!(unknown[field].is_null(), "{}", field);
+        }
+        assert_eq!(report["sent"]["Text"], 12);
+        assert_eq!(report["received"]["PublicAddress"], 2);
+        assert_eq!(report["departures"]["disconnected"], 1);
+        assert_eq!(
+            report["peer_set"]["first_change_unix_ms"],
+            1_760_400_000_123u64
+        );
+        assert_eq!(
+            report["peer_set"]["last_change_unix_ms"],
+            1_760_400_000_456u64
+        );
+        assert_eq!(report["bans"][0]["remaining_ms"], 30_000);
+        assert_eq!(report["retention"]["total_bytes"], 300);
+        assert_eq!(report["scheduled"][0]["topic"], "news");
+        assert_eq!(report["scheduled"][0]["due_unix_ms"], 1_760_403_600_000u64);
+    }
+
+    #[test]
+    fn the_field_names_stay_the_same() {
+        let report: Value = serde_json::from_str(&populated().to_json()).unwrap();
+        assert_fields(
+            &report,
+            &[
+                "node",
+                "node_id",
+                "protocol_version",
+                "config_digest",
+                "uptime_ms",
+                "peers",
+                "sent",
+                "received",
+                "ordered_delivery",
+                "departures",
+                "evictions",
+                "peer_set",
+                "bans",
+                "retention",
+                "scheduled",
+                "anomalies",
+                "dials",
+            ],
+        );
+        assert_fields(
+            &report["peers"][0],
+            &[
+                "addr",
+                "node_id",
+                "former_addrs",
+                "sessions",
+                "connected_ms",
+                "verified_ago_ms",
+                "direction",
+                "clock_offset",
+                "provenance",
+            ],
+        );
+        assert_fields(
+            &report["peers"][0]["provenance"],
+            &[
+                "source",
+                "learned_at",
+                "uptime_ms",
+                "from",
+                "request",
+                "corroborators",
+            ],
+        );
+        assert_fields(&report["ordered_delivery"], &["gaps", "duplicates"]);
+        assert_fields(&report["evictions"], &["dedup", "rate_limit"]);
+        assert_fields(
+            &report["peer_set"],
+            &["changes", "first_change_unix_ms", "last_change_unix_ms"],
+        );
+        assert_fields(&report["dials"], &["outcomes", "triggers"]);
+        assert_eq!(
+            report["sent"].as_object().unwrap().len(),
+            MessageKind::COUNT
+        );
+        assert_eq!(
+            report["anomalies"].as_object().unwrap().len(),
+            AnomalyKind::COUNT
+        );
+    }
+
+    #[test]
+    fn saving_replaces_the_report_at_once() {
+        let dir = TempDir::new("report-save");
+        let path = dir.0.join("report.json");
+        fs::write(&path, "stale").unwrap();
+        let report = populated();
+        report.save(&path).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, report.to_json() + "\n");
+        assert!(!dir.0.join("report.json.tmp").exists());
+        let missing = dir.0.join("missing").join("report.json");
+        assert!(report.save(&missing).is_err());
+    }
+
+    #[test]
+    fn the_peer_set_keeps_its_first_and_last_change() {
+        let mut changes = PeerSetChanges::default();
+        assert_eq!(changes.first, None);
+        for unix_ms in [100, 200, 300] {
+            changes.record(at(unix_ms));
+        }
+        assert_eq!(changes.count, 3);
+        assert_eq!(changes.first, Some(at(100)));
+        assert_eq!(changes.last, Some(at(300)));
+    }
+
+    #[test]
+    fn the_config_digest_is_fnv_1a() {
+        assert_eq!(config_digest(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(config_digest("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(config_digest("period: 1s"), config_digest("period: 2s"));
+    }
+
+    #[test]
+    fn formats_name_the_files_they_write() {
+        let path = Path::new("run/report.json");
+        for format in [ReportFormat::Json, ReportFormat::Csv, ReportFormat::Both] {
+            assert_eq!(format.name().parse(), Ok(format));
+        }
+        assert!("xml".parse::<ReportFormat>().is_err());
+        assert_eq!(
+            ReportFormat::Json.paths(path),
+            (Some(path.to_owned()), None)
+        );
+        assert_eq!(ReportFormat::Csv.paths(path), (None, Some(path.to_owned())));
+        assert_eq!(
+            ReportFormat::Both.paths(path),
+            (Some(path.to_owned()), Some(PathBuf::from("run/report.csv")))
+        );
+    }
+}
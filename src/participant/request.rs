@@ -0,0 +1,518 @@
+//! Requests and Responses Between Peers.
+//!
+//! Besides fire-and-forget gossip, a participant can ask one peer a question and wait for the
+//! answer. A `Message::Request` carries an id unique to the requesting participant, a topic and
+//! a payload; the peer answers with a `Message::Response` holding the same id and either the
+//! payload returned by its handler for the topic or an error.
+//!
+//! The waiting happens outside of the event loop. A `RequestClient`, which any thread can own,
+//! hands the request to the event loop and blocks its caller on a channel until the event loop
+//! sends the outcome back. The event loop keeps the unanswered requests in `PendingRequests`
+//! and gives up on them on timeout or when the peer goes away. Handlers run on a small pool of
+//! worker threads, so a slow handler never stalls the event loop.
+
+use crate::participant::channels::{ChannelRegistry, MonitoredSender};
+use crate::participant::event::InternalEvent;
+use crate::participant::threads::{Join, SpawnError, ThreadRegistry};
+use crate::participant::topic::{validate_topic, TopicError};
+
+use message_io::node::NodeHandler;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The number of worker threads running request handlers.
+pub const REQUEST_WORKERS: usize = 2;
+
+/// The longest a request waits for its response; a longer timeout is shortened to it.
+pub const MAX_REQUEST_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Enumerates the ways a request can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestError {
+    /// The topic is not a valid topic name.
+    InvalidTopic(TopicError),
+    /// No connected participant has the requested address.
+    UnknownPeer,
+    /// The peer speaks a protocol version without requests.
+    Unsupported,
+    /// No response arrived in time.
+    Timeout,
+    /// The connection to the peer was lost before it responded.
+    PeerGone,
+    /// The peer responded with an error, such as an unhandled topic or a failed handler.
+    Remote(String),
+    /// The participant stopped before the request completed.
+    Stopped,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::InvalidTopic(err) => write!(f, "invalid topic: {}", err),
+            RequestError::UnknownPeer => write!(f, "not connected to this participant"),
+            RequestError::Unsupported => write!(f, "the participant does not support requests"),
+            RequestError::Timeout => write!(f, "no response in time"),
+            RequestError::PeerGone => write!(f, "the participant disconnected"),
+            RequestError::Remote(reason) => write!(f, "the participant answered: {}", reason),
+            RequestError::Stopped => write!(f, "the participant stopped"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// The outcome of a request, as delivered to the waiting caller.
+pub type RequestResult = Result<Vec<u8>, RequestError>;
+
+/// A handler answering the requests on a topic, given the requester's public address and the
+/// payload.
+pub type RequestHandler = dyn Fn(SocketAddr, Vec<u8>) -> Result<Vec<u8>, String> + Send + Sync;
+
+/// Sends requests to peers from any thread and waits for their responses.
+#[derive(Clone)]
+pub struct RequestClient {
+    handler: NodeHandler<InternalEvent>,
+}
+
+impl RequestClient {
+    /// Creates a client handing its requests to the event loop of `handler`.
+    ///
+    /// # Parameters
+    ///
+    /// * `handler` - The node handler of the participant.
+    pub fn new(handler: NodeHandler<InternalEvent>) -> Self {
+        Self { handler }
+    }
+
+    /// Asks the participant at `peer` to answer `payload` on `topic`, blocking until the
+    /// response arrives or `timeout` elapses.
+    ///
+    /// Must not be called from the event loop, which would wait for itself.
+    ///
+    /// # Parameters
+    ///
+    /// * `peer` - The public address of a connected participant.
+    /// * `topic` - The topic of the request, which selects the peer's handler.
+    /// * `payload` - The payload handed to the handler.
+    /// * `timeout` - How long to wait for the response, at most `MAX_REQUEST_TIMEOUT`.
+    pub fn request(
+        &self,
+        peer: SocketAddr,
+        topic: &str,
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> RequestResult {
+        validate_topic(topic).map_err(RequestError::InvalidTopic)?;
+
+        let timeout = timeout.min(MAX_REQUEST_TIMEOUT);
+        let (reply, waiter) = mpsc::channel();
+        self.handler.signals().send(InternalEvent::Request {
+            peer,
+            topic: topic.to_owned(),
+            payload,
+            timeout,
+            reply,
+        });
+
+        match waiter.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(RequestError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(RequestError::Stopped),
+        }
+    }
+}
+
+/// One request waiting for its response.
+#[derive(Debug)]
+struct PendingRequest<T> {
+    endpoint: T,
+    deadline: Instant,
+    reply: Sender<RequestResult>,
+}
+
+/// The requests of a participant that have not been answered yet.
+#[derive(Debug)]
+pub struct PendingRequests<T> {
+    next_id: u64,
+    pending: HashMap<u64, PendingRequest<T>>,
+}
+
+impl<T: PartialEq> Default for PendingRequests<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq> PendingRequests<T> {
+    /// Creates an empty set of pending requests.
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Records a request sent to `endpoint`.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the request is sent to.
+    /// * `deadline` - The time after which the request is given up.
+    /// * `reply` - Receives the outcome of the request.
+    ///
+    /// # Returns
+    ///
+    /// The id of the request, never returned again by this participant.
+    pub fn start(&mut self, endpoint: T, deadline: Instant, reply: Sender<RequestResult>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(
+            id,
+            PendingRequest {
+                endpoint,
+                deadline,
+                reply,
+            },
+        );
+        id
+    }
+
+    /// Delivers the response to a pending request.
+    ///
+    /// A response with an unknown id, such as the late response to a request that timed out,
+    /// or coming from another endpoint than the request went to, is ignored.
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - The id of the answered request.
+    /// * `from` - The endpoint the response came from.
+    /// * `result` - The outcome of the request.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the response completed a pending request.
+    pub fn complete(&mut self, id: u64, from: &T, result: RequestResult) -> bool {
+        match self.pending.get(&id) {
+            Some(request) if request.endpoint == *from => {}
+            _ => return false,
+        }
+        if let Some(request) = self.pending.remove(&id) {
+            let _ = request.reply.send(result);
+        }
+        true
+    }
+
+    /// Fails every pending request whose deadline passed with `RequestError::Timeout`.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    pub fn expire(&mut self, now: Instant) {
+        self.fail_where(|request| request.deadline <= now, RequestError::Timeout);
+    }
+
+    /// Fails every pending request sent to an endpoint matching `gone` with
+    /// `RequestError::PeerGone`.
+    ///
+    /// # Parameters
+    ///
+    /// * `gone` - Determines whether the connection behind an endpoint is lost.
+    pub fn peer_gone(&mut self, gone: impl Fn(&T) -> bool) {
+        self.fail_where(|request| gone(&request.endpoint), RequestError::PeerGone);
+    }
+
+    /// Returns the number of pending requests.
+    pub fn count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Removes the requests matching `failed` and sends `error` to their callers.
+    fn fail_where(&mut self, failed: impl Fn(&PendingRequest<T>) -> bool, error: RequestError) {
+        let ids: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, request)| failed(request))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if let Some(request) = self.pending.remove(&id) {
+                let _ = request.reply.send(Err(error.clone()));
+            }
+        }
+    }
+}
+
+/// A job run by a worker thread.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The request handlers of a participant and the worker threads running them.
+pub struct RequestHandlers {
+    handlers: HashMap<String, Arc<RequestHandler>>,
+    workers: Vec<MonitoredSender<Job>>,
+    next_worker: usize,
+}
+
+impl RequestHandlers {
+    /// Creates an empty registry, served by `workers` threads named `gossip-request`.
+    ///
+    /// # Parameters
+    ///
+    /// * `workers` - The number of worker threads, at least one.
+    /// * `threads` - The registry the workers are spawned through.
+    /// * `channels` - The registry the job queue of each worker, `requests-<n>`, is created
+    ///   through.
+    pub fn new(
+        workers: usize,
+        threads: &ThreadRegistry,
+        channels: &ChannelRegistry,
+    ) -> Result<Self, SpawnError> {
+        let workers = (1..=workers.max(1))
+            .map(|index| {
+                let (sender, jobs) = channels.channel::<Job>(&format!("requests-{}", index));
+                threads.spawn("gossip-request", Join::Wait, move |_| {
+                    while let Ok(job) = jobs.recv() {
+                        job();
+                    }
+                })?;
+                Ok(sender)
+            })
+            .collect::<Result<Vec<MonitoredSender<Job>>, SpawnError>>()?;
+
+        Ok(Self {
+            handlers: HashMap::new(),
+            workers,
+            next_worker: 0,
+        })
+    }
+
+    /// Stops the workers once they ran the jobs already handed to them; no handler runs
+    /// afterwards.
+    pub fn close(&mut self) {
+        self.workers.clear();
+    }
+
+    /// Registers `handler` for the requests on `topic`, replacing any previous handler.
+    ///
+    /// # Parameters
+    ///
+    /// * `topic` - The topic answered by the handler.
+    /// * `handler` - Returns the response payload, or an error sent back to the requester.
+    pub fn register(
+        &mut self,
+        topic: &str,
+        handler: impl Fn(SocketAddr, Vec<u8>) -> Result<Vec<u8>, String> + Send + Sync + 'static,
+    ) -> Result<(), TopicError> {
+        validate_topic(topic)?;
+        self.handlers.insert(topic.to_owned(), Arc::new(handler));
+        Ok(())
+    }
+
+    /// Runs the handler of `topic` on a worker thread.
+    ///
+    /// A panicking handler is answered with an error, like a handler returning one.
+    ///
+    /// # Parameters
+    ///
+    /// * `from` - The public address of the requester.
+    /// * `topic` - The topic of the request.
+    /// * `payload` - The payload of the request.
+    /// * `done` - Receives the outcome of the handler, on the worker thread.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no handler is registered for `topic` or the workers are closed, in which
+    /// case `done` is not called.
+    pub fn dispatch(
+        &mut self,
+        from: SocketAddr,
+        topic: &str,
+        payload: Vec<u8>,
+        done: impl FnOnce(Result<Vec<u8>, String>) + Send + 'static,
+    ) -> bool {
+        let Some(handler) = self.handlers.get(topic).cloned() else {
+            return false;
+        };
+        if self.workers.is_empty() {
+            return false;
+        }
+
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| handler(from, payload)))
+                .unwrap_or_else(|_| Err("the request handler panicked".to_owned()));
+            done(result);
+        });
+        self.next_worker = (self.next_worker + 1) % self.workers.len();
+        let _ = self.workers[self.next_worker].send(job);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    /// How long a test waits for a worker to answer.
+    const WAIT: Duration = Duration::from_secs(2);
+
+    /// Returns a registry served by its workers, and the registry of the workers.
+    fn handlers() -> (RequestHandlers, ThreadRegistry) {
+        let threads = ThreadRegistry::new(REQUEST_WORKERS);
+        let handlers = RequestHandlers::new(REQUEST_WORKERS, &threads, &ChannelRegistry::new())
+            .expect("the workers can be spawned");
+        (handlers, threads)
+    }
+
+    /// Dispatches a request on `topic` and waits for the outcome of its handler.
+    fn answer(
+        handlers: &mut RequestHandlers,
+        topic: &str,
+        payload: &[u8],
+    ) -> Option<Result<Vec<u8>, String>> {
+        let (done, outcome) = mpsc::channel();
+        if !handlers.dispatch(addr(8080), topic, payload.to_vec(), move |result| {
+            let _ = done.send(result);
+        }) {
+            return None;
+        }
+        Some(outcome.recv_timeout(WAIT).expect("the handler answers"))
+    }
+
+    #[test]
+    fn ids_are_never_reused() {
+        let mut pending = PendingRequests::new();
+        let now = Instant::now();
+        let (reply, _waiter) = mpsc::channel();
+        let first = pending.start(1, now, reply.clone());
+        let second = pending.start(1, now, reply.clone());
+        assert!(pending.complete(first, &1, Ok(Vec::new())));
+        let third = pending.start(1, now, reply);
+        assert_ne!(first, second);
+        assert_ne!(first, third);
+        assert_ne!(second, third);
+    }
+
+    #[test]
+    fn a_response_completes_its_request_once() {
+        let mut pending = PendingRequests::new();
+        let (reply, waiter) = mpsc::channel();
+        let id = pending.start(1, Instant::now() + WAIT, reply);
+
+        // From another endpoint than the request went to, the response is ignored.
+        assert!(!pending.complete(id, &2, Ok(vec![9])));
+        assert!(pending.complete(id, &1, Ok(vec![1])));
+        assert_eq!(waiter.try_recv(), Ok(Ok(vec![1])));
+        assert!(!pending.complete(id, &1, Ok(vec![2])));
+        assert_eq!(pending.count(), 0);
+    }
+
+    #[test]
+    fn a_late_response_after_the_timeout_is_ignored() {
+        let mut pending = PendingRequests::new();
+        let now = Instant::now();
+        let (reply, waiter) = mpsc::channel();
+        let id = pending.start(1, now + Duration::from_secs(5), reply);
+
+        pending.expire(now + Duration::from_secs(4));
+        assert_eq!(pending.count(), 1);
+        pending.expire(now + Duration::from_secs(5));
+        assert_eq!(pending.count(), 0);
+        assert_eq!(waiter.try_recv(), Ok(Err(RequestError::Timeout)));
+
+        assert!(!pending.complete(id, &1, Ok(vec![1])));
+        assert!(waiter.try_recv().is_err());
+    }
+
+    #[test]
+    fn the_requests_to_a_lost_peer_fail() {
+        let mut pending = PendingRequests::new();
+        let deadline = Instant::now() + WAIT;
+        let (lost, lost_waiter) = mpsc::channel();
+        let (kept, kept_waiter) = mpsc::channel();
+        pending.start(1, deadline, lost.clone());
+        pending.start(1, deadline, lost);
+        let kept_id = pending.start(2, deadline, kept);
+
+        pending.peer_gone(|endpoint| *endpoint == 1);
+        assert_eq!(pending.count(), 1);
+        assert_eq!(
+            lost_waiter.try_iter().collect::<Vec<_>>(),
+            [Err(RequestError::PeerGone), Err(RequestError::PeerGone)]
+        );
+        assert!(kept_waiter.try_recv().is_err());
+        assert!(pending.complete(kept_id, &2, Ok(Vec::new())));
+    }
+
+    #[test]
+    fn handlers_answer_on_a_worker() {
+        let (mut handlers, threads) = handlers();
+        handlers
+            .register("echo", |from, payload| {
+                assert_eq!(from, addr(8080));
+                assert!(thread_is_worker());
+                Ok(payload)
+            })
+            .unwrap();
+        handlers
+            .register("refuse", |_, _| Err("no".to_owned()))
+            .unwrap();
+
+        assert_eq!(
+            answer(&mut handlers, "echo", b"ping"),
+            Some(Ok(b"ping".to_vec()))
+        );
+        assert_eq!(
+            answer(&mut handlers, "refuse", b""),
+            Some(Err("no".to_owned()))
+        );
+        assert_eq!(answer(&mut handlers, "missing", b""), None);
+
+        handlers.close();
+        assert_eq!(answer(&mut handlers, "echo", b"ping"), None);
+        assert!(threads.shutdown(WAIT).is_empty());
+    }
+
+    #[test]
+    fn a_panicking_handler_is_answered_with_an_error() {
+        let (mut handlers, threads) = handlers();
+        handlers
+            .register("panic", |_, _| panic!("the handler fails"))
+            .unwrap();
+        handlers.register("echo", |_, payload| Ok(payload)).unwrap();
+
+        for _ in 0..REQUEST_WORKERS {
+            assert_eq!(
+                answer(&mut handlers, "panic", b""),
+                Some(Err("the request handler panicked".to_owned()))
+            );
+        }
+        // Every worker survived its panic.
+        for _ in 0..REQUEST_WORKERS {
+            assert_eq!(
+                answer(&mut handlers, "echo", b"ok"),
+                Some(Ok(b"ok".to_vec()))
+            );
+        }
+        handlers.close();
+        assert!(threads.shutdown(WAIT).is_empty());
+    }
+
+    #[test]
+    fn invalid_topics_are_refused() {
+        let (mut handlers, threads) = handlers();
+        assert!(handlers.register("", |_, payload| Ok(payload)).is_err());
+        handlers.close();
+        assert!(threads.shutdown(WAIT).is_empty());
+    }
+
+    /// Determines whether the current thread is a request worker.
+    fn thread_is_worker() -> bool {
+        std::thread::current().name() == Some("gossip-request")
+    }
+}
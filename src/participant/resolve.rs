@@ -0,0 +1,163 @@
+//! Connect Target Re-Resolution.
+//!
+//! A `--connect` target given as a host name, such as the service of a seed in Kubernetes, is
+//! resolved when it is dialed at the start. The address behind the name changes when the seed
+//! is rescheduled, and a participant that lost its peers would otherwise never find its way
+//! back: the heal probes dial the departed peers at the addresses they had, and the bootstrap
+//! candidates are only tried once.
+//!
+//! So while no participant is connected and no bootstrap dial is in flight, a participant
+//! resolves a named target again and dials what it resolves to, at most once per
+//! `--resolve-interval`, which keeps a flapping network from hammering the resolver. An
+//! address that differs from the one resolved before is logged and dialed like any new one;
+//! nothing recorded about the old address, its bans, its recent dials or its trail, holds the
+//! new one back. A target given as an address is never resolved again.
+//!
+//! The resolution is the blocking one of the standard library, run on the event loop like the
+//! one of the start, and only ever while the participant has nobody to gossip with.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// What resolving the target again gave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The name does not resolve, or to no address.
+    Unresolved,
+    /// The name resolves to the address it resolved to before.
+    Same(SocketAddr),
+    /// The name resolves to a new address, `from` being the previous one if it ever resolved.
+    Changed {
+        from: Option<SocketAddr>,
+        to: SocketAddr,
+    },
+}
+
+/// A `--connect` target given as a host name, and how it last resolved.
+#[derive(Debug)]
+pub struct ConnectTarget {
+    name: String,
+    interval: Duration,
+    resolved_at: Option<Instant>,
+    addr: Option<SocketAddr>,
+}
+
+impl ConnectTarget {
+    /// Creates the target of `connect`, unless it is an address rather than a name.
+    ///
+    /// # Parameters
+    ///
+    /// * `connect` - The `--connect` target.
+    /// * `interval` - The shortest time between two resolutions.
+    pub fn named(connect: &str, interval: Duration) -> Option<Self> {
+        if connect.parse::<SocketAddr>().is_ok() {
+            return None;
+        }
+        Some(Self {
+            name: connect.to_owned(),
+            interval,
+            resolved_at: None,
+            addr: None,
+        })
+    }
+
+    /// Returns the name of the target, with its port.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the shortest time between two resolutions.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Records that the target resolved to `addr` at `now`, when it was dialed at the start.
+    pub fn resolved(&mut self, addr: SocketAddr, now: Instant) {
+        self.resolved_at = Some(now);
+        self.addr = Some(addr);
+    }
+
+    /// Determines whether the target may be resolved again at `now`.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.resolved_at
+            .is_none_or(|at| now.saturating_duration_since(at) >= self.interval)
+    }
+
+    /// Resolves the target again at `now`, keeping the address it resolves to.
+    pub fn resolve(&mut self, now: Instant) -> Resolution {
+        self.resolved_at = Some(now);
+        let Some(to) = self
+            .name
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+        else {
+            return Resolution::Unresolved;
+        };
+        let from = self.addr.replace(to);
+        if from == Some(to) {
+            Resolution::Same(to)
+        } else {
+            Resolution::Changed { from, to }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    const INTERVAL: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn only_names_are_resolved_again() {
+        assert!(ConnectTarget::named("127.0.0.1:8080", INTERVAL).is_none());
+        assert!(ConnectTarget::named("[::1]:8080", INTERVAL).is_none());
+        let target = ConnectTarget::named("localhost:8080", INTERVAL).unwrap();
+        assert_eq!(target.name(), "localhost:8080");
+    }
+
+    #[test]
+    fn a_name_is_resolved_at_most_once_per_interval() {
+        let now = Instant::now();
+        let mut target = ConnectTarget::named("localhost:8080", INTERVAL).unwrap();
+        assert!(target.is_due(now));
+        target.resolved(addr(8080), now);
+        assert!(!target.is_due(now + INTERVAL - Duration::from_millis(1)));
+        assert!(target.is_due(now + INTERVAL));
+
+        target.set_interval(Duration::from_secs(5));
+        assert!(target.is_due(now + Duration::from_secs(5)));
+        // A failed resolution counts towards the interval as well.
+        let mut unresolvable = ConnectTarget::named("localhost", INTERVAL).unwrap();
+        assert_eq!(unresolvable.resolve(now), Resolution::Unresolved);
+        assert!(!unresolvable.is_due(now));
+    }
+
+    #[test]
+    fn a_new_address_is_reported_once() {
+        let now = Instant::now();
+        let mut target = ConnectTarget::named("localhost:8080", INTERVAL).unwrap();
+        target.resolved(addr(9000), now);
+        let to = match target.resolve(now) {
+            Resolution::Changed { from, to } => {
+                assert_eq!(from, Some(addr(9000)));
+                to
+            }
+            other => panic!("expected a changed address, got {:?}", other),
+        };
+        assert_eq!(to.port(), 8080);
+        assert!(to.ip().is_loopback());
+        assert_eq!(target.resolve(now), Resolution::Same(to));
+    }
+
+    #[test]
+    fn a_first_resolution_changes_from_nothing() {
+        let mut target = ConnectTarget::named("localhost:8080", INTERVAL).unwrap();
+        assert!(matches!(
+            target.resolve(Instant::now()),
+            Resolution::Changed { from: None, .. }
+        ));
+    }
+}
@@ -0,0 +1,393 @@
+//! History Retention.
+//!
+//! A participant keeps some history of its peers beyond their connections: the session
+//! history of every public address, the departed peers it remembers for partition healing and
+//! the state file, and the last participants list of every peer for the split-brain detection.
+//! On a long-running node with heavy churn these grow with every peer ever seen, so each
+//! `RetainedKind` is held to a `RetentionPolicy`, set with `--retain-<kind>`, by the
+//! maintenance sweep.
+//!
+//! The sweep hands `Retention::enforce` every entry of a kind with its age and its estimated
+//! size, and removes the entries it returns through the kind's own removal path, so what the
+//! storage derives from them, such as the session totals carried into the remembered peers,
+//! stays consistent. Entries are evicted by age first, those older than `max_age`; then by
+//! count, the oldest first until at most `max_entries` are left; then by size, the oldest
+//! first until their estimated bytes fit `max_total_bytes`. An entry that must stay, such as the
+//! history of a connected peer, counts towards the bounds without ever being evicted.
+//!
+//! The sizes are estimates: the `size_of` of the entry and its key, plus the length of what it
+//! holds on the heap. They leave the allocator and the maps overhead out, so they tell which
+//! kind grows rather than how much memory the process takes.
+
+use crate::participant::utils::CanonicalAddr;
+use crate::printer::format_elapsed;
+
+use std::fmt;
+use std::mem::size_of;
+use std::time::Duration;
+
+/// A kind of retained history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainedKind {
+    /// The session histories, by public address.
+    Sessions,
+    /// The departed peers remembered for partition healing and the state file.
+    Departed,
+    /// The last participants list of each peer, for the split-brain detection.
+    Lists,
+}
+
+impl RetainedKind {
+    /// The number of kinds, i.e. the length of a per-kind array.
+    pub const COUNT: usize = 3;
+
+    /// Every kind, in array order.
+    pub const ALL: [RetainedKind; RetainedKind::COUNT] = [
+        RetainedKind::Sessions,
+        RetainedKind::Departed,
+        RetainedKind::Lists,
+    ];
+
+    /// Returns the name of this kind, as in its `--retain-<kind>` option.
+    pub fn name(self) -> &'static str {
+        match self {
+            RetainedKind::Sessions => "sessions",
+            RetainedKind::Departed => "departed",
+            RetainedKind::Lists => "lists",
+        }
+    }
+}
+
+/// The bounds of one kind of retained history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// The number of entries kept.
+    pub max_entries: usize,
+    /// The age beyond which an entry is evicted.
+    pub max_age: Duration,
+    /// The estimated bytes the entries may take together, `None` for no bound.
+    pub max_total_bytes: Option<usize>,
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at most {} entries, {} old",
+            self.max_entries,
+            format_elapsed(self.max_age)
+        )?;
+        match self.max_total_bytes {
+            Some(bytes) => write!(f, ", {}", format_bytes(bytes)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Why an entry was evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictedBy {
+    /// The entry was older than `max_age`.
+    Age,
+    /// More than `max_entries` were kept.
+    Count,
+    /// The entries took more than `max_total_bytes`.
+    Bytes,
+}
+
+impl EvictedBy {
+    /// The number of causes, i.e. the length of a per-cause counter array.
+    pub const COUNT: usize = 3;
+}
+
+/// An entry of a retained kind, as handed to `Retention::enforce`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetainedEntry<K> {
+    /// The key the kind removes the entry by.
+    pub key: K,
+    /// The age of the entry, `None` for an entry that must stay.
+    pub age: Option<Duration>,
+    /// The estimated size of the entry.
+    pub bytes: usize,
+}
+
+/// What one kind holds after the last sweep, and what the sweeps evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KindUsage {
+    /// The entries kept.
+    pub entries: usize,
+    /// Their estimated size.
+    pub bytes: usize,
+    /// The entries evicted, per `EvictedBy`.
+    pub evicted: [u64; EvictedBy::COUNT],
+}
+
+/// The policies of every kind, and their usage.
+#[derive(Debug)]
+pub struct Retention {
+    policies: [RetentionPolicy; RetainedKind::COUNT],
+    usage: [KindUsage; RetainedKind::COUNT],
+}
+
+impl Retention {
+    /// Constructs the retention of the given policies, in `RetainedKind::ALL` order.
+    pub fn new(policies: [RetentionPolicy; RetainedKind::COUNT]) -> Self {
+        Self {
+            policies,
+            usage: [KindUsage::default(); RetainedKind::COUNT],
+        }
+    }
+
+    /// Returns the policy of `kind`.
+    pub fn policy(&self, kind: RetainedKind) -> RetentionPolicy {
+        self.policies[kind as usize]
+    }
+
+    /// Applies the policy of `kind` to its entries and records what is left.
+    ///
+    /// # Returns
+    ///
+    /// The keys of the entries to remove, which the caller removes.
+    pub fn enforce<K>(&mut self, kind: RetainedKind, entries: Vec<RetainedEntry<K>>) -> Vec<K> {
+        let (evicted, entries, bytes) = select(&self.policies[kind as usize], entries);
+        let usage = &mut self.usage[kind as usize];
+        usage.entries = entries;
+        usage.bytes = bytes;
+        evicted
+            .into_iter()
+            .map(|(key, cause)| {
+                usage.evicted[cause as usize] += 1;
+                key
+            })
+            .collect()
+    }
+
+    /// Returns the usage of `kind` after the last sweep.
+    pub fn usage(&self, kind: RetainedKind) -> KindUsage {
+        self.usage[kind as usize]
+    }
+
+    /// Returns the estimated size of every kind together.
+    pub fn total_bytes(&self) -> usize {
+        self.usage.iter().map(|usage| usage.bytes).sum()
+    }
+
+    /// Formats one line per kind and the total, for the `memory` command and `stats`.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = RetainedKind::ALL
+            .iter()
+            .map(|kind| {
+                let usage = self.usage(*kind);
+                let [age, count, bytes] = usage.evicted;
+                format!(
+                    "{}: {} entries, ~{} ({}); evicted {} by age, {} by count, {} by size",
+                    kind.name(),
+                    usage.entries,
+                    format_bytes(usage.bytes),
+                    self.policy(*kind),
+                    age,
+                    count,
+                    bytes
+                )
+            })
+            .collect();
+        lines.push(format!("Total: ~{}", format_bytes(self.total_bytes())));
+        lines
+    }
+}
+
+/// Selects the entries `policy` evicts: by age, then by count, then by size, the oldest first.
+///
+/// # Returns
+///
+/// The evicted keys with their cause, then the number and the estimated size of the entries
+/// left.
+pub fn select<K>(
+    policy: &RetentionPolicy,
+    entries: Vec<RetainedEntry<K>>,
+) -> (Vec<(K, EvictedBy)>, usize, usize) {
+    let mut count = entries.len();
+    let mut bytes: usize = entries.iter().map(|entry| entry.bytes).sum();
+    let mut evictable: Vec<(Duration, usize, K)> = entries
+        .into_iter()
+        .filter_map(|entry| entry.age.map(|age| (age, entry.bytes, entry.key)))
+        .collect();
+    // The oldest last, so they pop first.
+    evictable.sort_by_key(|(age, _, _)| *age);
+
+    let mut evicted = Vec::new();
+    while let Some((age, size, key)) = evictable.pop() {
+        let cause = if age > policy.max_age {
+            EvictedBy::Age
+        } else if count > policy.max_entries {
+            EvictedBy::Count
+        } else if policy.max_total_bytes.is_some_and(|max| bytes > max) {
+            EvictedBy::Bytes
+        } else {
+            break;
+        };
+        count -= 1;
+        bytes -= size;
+        evicted.push((key, cause));
+    }
+    (evicted, count, bytes)
+}
+
+/// Estimates the size of an entry of type `V` keyed by a public address, holding `items` items
+/// of type `I` on the heap.
+pub fn estimate<V, I>(items: usize) -> usize {
+    size_of::<CanonicalAddr>() + size_of::<V>() + items * size_of::<I>()
+}
+
+/// Formats a byte count in the largest binary unit it has one of.
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn policy(
+        max_entries: usize,
+        max_age: Duration,
+        max_total_bytes: Option<usize>,
+    ) -> RetentionPolicy {
+        RetentionPolicy {
+            max_entries,
+            max_age,
+            max_total_bytes,
+        }
+    }
+
+    /// An entry keyed by `key`, `age` seconds old or pinned, taking `bytes`.
+    fn entry(key: u16, age: Option<u64>, bytes: usize) -> RetainedEntry<u16> {
+        RetainedEntry {
+            key,
+            age: age.map(Duration::from_secs),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn entries_are_evicted_by_age_then_count_then_size() {
+        let entries = vec![
+            entry(1, Some(100), 10),
+            entry(2, Some(5), 10),
+            entry(3, Some(4), 10),
+            entry(4, Some(3), 50),
+            entry(5, Some(2), 10),
+            entry(6, Some(1), 10),
+        ];
+        let (evicted, count, bytes) = select(&policy(4, 60 * SECOND, Some(40)), entries);
+        assert_eq!(
+            evicted,
+            [
+                (1, EvictedBy::Age),
+                (2, EvictedBy::Count),
+                (3, EvictedBy::Bytes),
+                (4, EvictedBy::Bytes),
+            ]
+        );
+        assert_eq!((count, bytes), (2, 20));
+    }
+
+    #[test]
+    fn entries_within_every_bound_stay() {
+        let entries = vec![entry(1, Some(1), 10), entry(2, Some(2), 10)];
+        let (evicted, count, bytes) = select(&policy(2, 60 * SECOND, None), entries);
+        assert!(evicted.is_empty());
+        assert_eq!((count, bytes), (2, 20));
+        // The bound on age is exclusive.
+        let (evicted, _, _) = select(&policy(2, 2 * SECOND, None), vec![entry(1, Some(2), 1)]);
+        assert!(evicted.is_empty());
+    }
+
+    #[test]
+    fn pinned_entries_count_but_are_never_evicted() {
+        let entries = vec![
+            entry(1, None, 100),
+            entry(2, None, 100),
+            entry(3, Some(1), 10),
+            entry(4, Some(1000), 10),
+        ];
+        let (evicted, count, bytes) = select(&policy(1, 60 * SECOND, Some(50)), entries);
+        assert_eq!(evicted, [(4, EvictedBy::Age), (3, EvictedBy::Count)]);
+        assert_eq!((count, bytes), (2, 200));
+    }
+
+    #[test]
+    fn usage_is_recorded_per_kind() {
+        let mut retention = Retention::new([
+            policy(1, 60 * SECOND, None),
+            policy(10, SECOND, None),
+            policy(10, 60 * SECOND, Some(2048)),
+        ]);
+        let evicted = retention.enforce(
+            RetainedKind::Sessions,
+            vec![entry(1, Some(2), 10), entry(2, Some(1), 10)],
+        );
+        assert_eq!(evicted, [1]);
+        let evicted = retention.enforce(RetainedKind::Departed, vec![entry(3, Some(2), 30)]);
+        assert_eq!(evicted, [3]);
+        retention.enforce(RetainedKind::Lists, vec![entry(4, None, 1536)]);
+
+        assert_eq!(
+            retention.usage(RetainedKind::Sessions),
+            KindUsage {
+                entries: 1,
+                bytes: 10,
+                evicted: [0, 1, 0],
+            }
+        );
+        assert_eq!(retention.usage(RetainedKind::Departed).evicted, [1, 0, 0]);
+        assert_eq!(retention.total_bytes(), 1546);
+        assert_eq!(
+            retention.lines(),
+            [
+                "sessions: 1 entries, ~10 B (at most 1 entries, 1m 00s old); \
+                 evicted 0 by age, 1 by count, 0 by size",
+                "departed: 0 entries, ~0 B (at most 10 entries, 1s old); \
+                 evicted 1 by age, 0 by count, 0 by size",
+                "lists: 1 entries, ~1.5 KiB (at most 10 entries, 1m 00s old, 2.0 KiB); \
+                 evicted 0 by age, 0 by count, 0 by size",
+                "Total: ~1.5 KiB",
+            ]
+        );
+    }
+
+    #[test]
+    fn sizes_are_formatted_in_the_largest_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 + 512 * 1024), "5.5 MiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+        assert_eq!(
+            estimate::<u64, u32>(3),
+            size_of::<CanonicalAddr>() + size_of::<u64>() + 3 * size_of::<u32>()
+        );
+    }
+
+    #[test]
+    fn kinds_are_named_as_their_options() {
+        let names: Vec<&str> = RetainedKind::ALL.iter().map(|kind| kind.name()).collect();
+        assert_eq!(names, ["sessions", "departed", "lists"]);
+    }
+}
@@ -0,0 +1,417 @@
+//! Synchronized Rounds.
+//!
+//! With `--rounds`, broadcasts are numbered: round `n` is the `n`-th period since the Unix
+//! epoch, so participants whose clocks agree start in the same round at the same time. Every
+//! gossip message carries the round it was sent in, and a participant keeps the highest round
+//! heard from its peers along with the time it was first heard.
+//!
+//! On every broadcast tick the participant picks its next round with `next_round_and_delay`:
+//! when a peer is ahead it joins the round that peer is in and ends it one period after the
+//! peer started it, otherwise it moves on to its own next round. There is no master clock; the
+//! participants furthest ahead pull the others along, which aligns free-running timers within
+//! a few periods as long as every participant uses the same period.
+//!
+//! A round further ahead of the local epoch round than `ROUND_TOLERANCE` is not believed: it
+//! comes from a clock far off or a peer lying, and following it would drag every participant
+//! along. Such rounds are counted and otherwise ignored.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The number of rounds a peer may be ahead of the local epoch round and still be followed.
+pub const ROUND_TOLERANCE: u64 = 2;
+
+/// The round in progress at `now` counting periods from the Unix epoch, and the time left
+/// until the next one starts.
+///
+/// # Parameters
+///
+/// * `now` - The current wall-clock time.
+/// * `period` - The period of a round.
+pub fn epoch_round(now: SystemTime, period: Duration) -> (u64, Duration) {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    project(0, since_epoch, period)
+}
+
+/// Picks the round a participant moves to on a broadcast tick, and the delay of the tick
+/// after it.
+///
+/// The round a peer was seen in is projected to the present: a round first heard `k` periods
+/// ago has been followed by `k` more. When that projection is ahead of this participant's next
+/// round, or equal to it, the participant joins it and its next tick fires when the peer's
+/// round ends. Otherwise the participant moves to its next round, further if the tick was
+/// late by whole periods, and keeps its own phase.
+///
+/// # Parameters
+///
+/// * `own_round` - The round this participant is in.
+/// * `own_elapsed_in_round` - The time since this participant's round started.
+/// * `observed_round` - The highest round heard from a peer.
+/// * `observed_age` - The time since `observed_round` was first heard.
+/// * `period` - The period of a round.
+///
+/// # Returns
+///
+/// The round to broadcast in now, and the delay until the next tick.
+pub fn next_round_and_delay(
+    own_round: u64,
+    own_elapsed_in_round: Duration,
+    observed_round: u64,
+    observed_age: Duration,
+    period: Duration,
+) -> (u64, Duration) {
+    let skipped = divide(own_elapsed_in_round.saturating_sub(period), period);
+    let own_next = own_round.saturating_add(1).saturating_add(skipped);
+    if observed_round <= own_round {
+        return (own_next, period);
+    }
+
+    let (observed_now, remaining) = project(observed_round, observed_age, period);
+    if observed_now >= own_next {
+        (observed_now, remaining)
+    } else {
+        (own_next, period)
+    }
+}
+
+/// The round in progress `elapsed` after `round` started, and the time left in it.
+fn project(round: u64, elapsed: Duration, period: Duration) -> (u64, Duration) {
+    if period.is_zero() {
+        return (round, period);
+    }
+    let passed = divide(elapsed, period);
+    // The remainder is below the period, which fits in nanoseconds as a `Duration` does.
+    let into_round = u64::try_from(elapsed.as_nanos() % period.as_nanos())
+        .map_or(Duration::ZERO, Duration::from_nanos);
+    (round.saturating_add(passed), period - into_round)
+}
+
+/// Returns the number of whole `period`s in `elapsed`, zero for a zero period.
+fn divide(elapsed: Duration, period: Duration) -> u64 {
+    match period.as_nanos() {
+        0 => 0,
+        period => (elapsed.as_nanos() / period).try_into().unwrap_or(u64::MAX),
+    }
+}
+
+/// The round of a participant and the highest round heard from its peers.
+#[derive(Debug, Clone)]
+pub struct RoundClock {
+    round: u64,
+    started_at: Instant,
+    observed: Option<(u64, Instant)>,
+    jumps: u64,
+    rejected: u64,
+}
+
+impl RoundClock {
+    /// Starts in the round of the epoch in progress.
+    ///
+    /// # Parameters
+    ///
+    /// * `period` - The period of a round.
+    /// * `now` - The current time.
+    pub fn new(period: Duration, now: Instant) -> Self {
+        let (round, remaining) = epoch_round(SystemTime::now(), period);
+        Self {
+            round,
+            started_at: now.checked_sub(period - remaining).unwrap_or(now),
+            observed: None,
+            jumps: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Returns the time left until the round in progress ends.
+    ///
+    /// # Parameters
+    ///
+    /// * `period` - The period of a round.
+    /// * `now` - The current time.
+    pub fn remaining(&self, period: Duration, now: Instant) -> Duration {
+        period.saturating_sub(now.saturating_duration_since(self.started_at))
+    }
+
+    /// Returns the round this participant is in.
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Returns the number of ticks that skipped rounds, to catch up with a peer or after a
+    /// late tick.
+    pub fn jumps(&self) -> u64 {
+        self.jumps
+    }
+
+    /// Returns the number of rounds heard too far ahead of the local epoch round to follow.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Returns the highest round heard from a peer, if any.
+    pub fn highest_observed(&self) -> Option<u64> {
+        self.observed.map(|(round, _)| round)
+    }
+
+    /// Records that a peer sent a message in `round`.
+    ///
+    /// Only a round higher than every round heard before is kept, with the time it was first
+    /// heard. A round more than `ROUND_TOLERANCE` ahead of the epoch round at `wall` is
+    /// rejected instead.
+    ///
+    /// # Parameters
+    ///
+    /// * `round` - The round the message was sent in.
+    /// * `period` - The period of a round.
+    /// * `now` - The current time.
+    /// * `wall` - The current wall-clock time.
+    ///
+    /// # Returns
+    ///
+    /// Whether the round was believed.
+    pub fn observe(
+        &mut self,
+        round: u64,
+        period: Duration,
+        now: Instant,
+        wall: SystemTime,
+    ) -> bool {
+        let (epoch, _) = epoch_round(wall, period);
+        if round > epoch.saturating_add(ROUND_TOLERANCE) {
+            self.rejected += 1;
+            return false;
+        }
+        if self.observed.is_none_or(|(highest, _)| round > highest) {
+            self.observed = Some((round, now));
+        }
+        true
+    }
+
+    /// Moves to the next round on a broadcast tick, see `next_round_and_delay`.
+    ///
+    /// # Parameters
+    ///
+    /// * `period` - The period of a round.
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    ///
+    /// The number of rounds skipped, and the delay until the next tick.
+    pub fn tick(&mut self, period: Duration, now: Instant) -> (u64, Duration) {
+        let (observed_round, observed_age) = match self.observed {
+            Some((round, at)) => (round, now.saturating_duration_since(at)),
+            None => (0, Duration::ZERO),
+        };
+        let elapsed = now.saturating_duration_since(self.started_at);
+        let (round, delay) =
+            next_round_and_delay(self.round, elapsed, observed_round, observed_age, period);
+        let skipped = round.saturating_sub(self.round.saturating_add(1));
+        if skipped > 0 {
+            self.jumps += 1;
+        }
+        self.round = round;
+        self.started_at = now.checked_sub(period - delay).unwrap_or(now);
+        (skipped, delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_secs(1);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn counts_rounds_from_the_epoch() {
+        let now = UNIX_EPOCH + Duration::from_millis(10_250);
+        assert_eq!(epoch_round(now, PERIOD), (10, ms(750)));
+        assert_eq!(epoch_round(UNIX_EPOCH, PERIOD), (0, PERIOD));
+        assert_eq!(epoch_round(now, Duration::ZERO), (0, Duration::ZERO));
+    }
+
+    #[test]
+    fn moves_on_when_no_peer_is_ahead() {
+        // Equal: the peer is in our round, we keep our own phase.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 7, ms(300), PERIOD),
+            (8, PERIOD)
+        );
+        // Behind: nothing to catch up with.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 3, ms(300), PERIOD),
+            (8, PERIOD)
+        );
+        // Nothing heard yet.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 0, Duration::ZERO, PERIOD),
+            (8, PERIOD)
+        );
+    }
+
+    #[test]
+    fn skips_the_rounds_a_late_tick_missed() {
+        assert_eq!(
+            next_round_and_delay(7, ms(3_400), 0, Duration::ZERO, PERIOD),
+            (10, PERIOD)
+        );
+    }
+
+    #[test]
+    fn joins_a_peer_ahead_and_ends_with_its_round() {
+        // The peer started round 8 400ms ago: we join it and tick when it ends.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 8, ms(400), PERIOD),
+            (8, ms(600))
+        );
+        // Heard 2.4 periods ago, round 8 has been followed by two more.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 8, ms(2_400), PERIOD),
+            (10, ms(600))
+        );
+        // Far ahead: we jump all the way.
+        assert_eq!(
+            next_round_and_delay(7, PERIOD, 500, ms(100), PERIOD),
+            (500, ms(900))
+        );
+    }
+
+    #[test]
+    fn a_peer_ahead_only_of_our_round_does_not_pull_us_back() {
+        // Our tick is late by two periods, which takes us past the peer's projected round.
+        assert_eq!(
+            next_round_and_delay(7, ms(3_100), 8, ms(100), PERIOD),
+            (10, PERIOD)
+        );
+    }
+
+    #[test]
+    fn saturates_on_huge_skew() {
+        assert_eq!(
+            next_round_and_delay(u64::MAX - 1, PERIOD, u64::MAX, ms(5_000), PERIOD),
+            (u64::MAX, PERIOD)
+        );
+        assert_eq!(
+            next_round_and_delay(u64::MAX, Duration::MAX, 0, Duration::ZERO, PERIOD),
+            (u64::MAX, PERIOD)
+        );
+        assert_eq!(
+            next_round_and_delay(0, PERIOD, u64::MAX, Duration::MAX, PERIOD).0,
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn rejects_rounds_too_far_ahead_of_the_epoch() {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let mut clock = RoundClock::new(PERIOD, now);
+        let (epoch, _) = epoch_round(wall, PERIOD);
+
+        assert!(clock.observe(epoch + ROUND_TOLERANCE, PERIOD, now, wall));
+        assert!(!clock.observe(epoch + ROUND_TOLERANCE + 1, PERIOD, now, wall));
+        assert!(!clock.observe(u64::MAX, PERIOD, now, wall));
+        assert_eq!(clock.highest_observed(), Some(epoch + ROUND_TOLERANCE));
+        assert_eq!(clock.rejected(), 2);
+    }
+
+    #[test]
+    fn keeps_the_first_time_the_highest_round_was_heard() {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let mut clock = RoundClock::new(PERIOD, now);
+        let (epoch, _) = epoch_round(wall, PERIOD);
+
+        clock.observe(epoch + 1, PERIOD, now, wall);
+        clock.observe(epoch + 1, PERIOD, now + ms(500), wall);
+        clock.observe(epoch, PERIOD, now + ms(600), wall);
+        assert_eq!(clock.observed, Some((epoch + 1, now)));
+    }
+
+    /// A virtual participant of `converges_within_a_few_rounds`, its times in nanoseconds.
+    struct Node {
+        round: u64,
+        started_at: u64,
+        next_tick: u64,
+        observed: Option<(u64, u64)>,
+    }
+
+    #[test]
+    fn converges_within_a_few_rounds() {
+        let period = PERIOD.as_nanos() as u64;
+        // Started at different times, with clocks that put them in different rounds.
+        let mut nodes: Vec<Node> = [(0, 100), (130, 104), (470, 99), (810, 101), (990, 100)]
+            .into_iter()
+            .map(|(offset_ms, round)| {
+                let started_at = offset_ms * 1_000_000;
+                Node {
+                    round,
+                    started_at,
+                    next_tick: started_at + period,
+                    observed: None,
+                }
+            })
+            .collect();
+
+        let mut aligned_at = None;
+        for _ in 0..50 {
+            // Every participant whose tick is due now ticks, one after the other.
+            let now = nodes.iter().map(|node| node.next_tick).min().unwrap();
+            for index in 0..nodes.len() {
+                if nodes[index].next_tick == now {
+                    tick(&mut nodes, index, now, period);
+                }
+            }
+
+            let first = &nodes[0];
+            let aligned = nodes
+                .iter()
+                .all(|node| node.next_tick == first.next_tick && node.round == first.round);
+            match aligned_at {
+                None if aligned => aligned_at = Some(now),
+                Some(_) => assert!(aligned, "the participants drifted apart again"),
+                None => {}
+            }
+        }
+
+        let aligned_at = aligned_at.expect("the participants never aligned");
+        assert!(
+            aligned_at <= 3 * period,
+            "aligned only after {}ns",
+            aligned_at
+        );
+        // They are in the rounds of the participant that was furthest ahead.
+        assert!(nodes[0].round > 104);
+    }
+
+    /// Ticks the participant `index` of a `converges_within_a_few_rounds` network at `now`.
+    fn tick(nodes: &mut [Node], index: usize, now: u64, period: u64) {
+        {
+            let node = &mut nodes[index];
+            let (observed_round, observed_age) = node
+                .observed
+                .map_or((0, 0), |(round, at)| (round, now - at));
+            let (round, delay) = next_round_and_delay(
+                node.round,
+                Duration::from_nanos(now - node.started_at),
+                observed_round,
+                Duration::from_nanos(observed_age),
+                PERIOD,
+            );
+            let delay = delay.as_nanos() as u64;
+            node.round = round;
+            node.started_at = now + delay - period;
+            node.next_tick = now + delay;
+        }
+
+        // Every other participant hears the broadcast at once.
+        let round = nodes[index].round;
+        for (other, peer) in nodes.iter_mut().enumerate() {
+            if other != index && peer.observed.is_none_or(|(highest, _)| round > highest) {
+                peer.observed = Some((round, now));
+            }
+        }
+    }
+}
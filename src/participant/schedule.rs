@@ -0,0 +1,734 @@
+//! Scheduled Publications.
+//!
+//! An application announcing a change at the top of the hour had to run a timer of its own
+//! next to the participant. `Participant::schedule` takes the `OutgoingMessage` and the time it
+//! is due at instead, and the participant publishes it then through the path of any other
+//! message: a text to every peer, a topic payload to the peers that want it.
+//!
+//! The `Schedule` keeps the pending publications in a min-heap of their due times, and the
+//! participant arms one timer for the earliest. At most `--max-scheduled` publications, 256 by
+//! default, are pending at once; scheduling one more is refused with `ScheduleError::Full`,
+//! and one due later than the clocks can tell with `ScheduleError::TooFar`. The
+//! `ScheduleHandle` of a publication cancels it from any thread. Whichever of the
+//! cancellation and the publication comes first wins, so `ScheduleHandle::cancel` returning
+//! `true` means the message is never sent.
+//!
+//! Due times are kept on the monotonic clock, which a wall-clock adjustment does not move, and
+//! on the wall clock, which is what survives a restart. Monotonic time does not advance while
+//! the system is suspended on every platform, so after a frozen broadcast tick the publications
+//! whose wall-clock time passed are sent at once, once each and in the order they were due.
+//!
+//! With `--state-file`, the pending publications are saved with the state. A publication whose
+//! time passed while the participant was down follows the `Missed` policy it was scheduled
+//! with: sent once the restarted participant connects to its first peer, or dropped.
+
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::message::{Message, MessageKind};
+use crate::participant::topic::{validate_topic, TopicError};
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The state of a publication not yet sent nor cancelled, that of a new `AtomicU8`.
+const PENDING: u8 = 0;
+
+/// The state of a publication handed to the send path.
+const SENT: u8 = 1;
+
+/// The state of a publication cancelled before it was due.
+const CANCELLED: u8 = 2;
+
+/// When a scheduled publication is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Due {
+    /// At an instant of the monotonic clock.
+    At(Instant),
+    /// After a delay from now.
+    After(Duration),
+    /// At a time of the wall clock, such as the top of the hour.
+    AtTime(SystemTime),
+}
+
+/// What becomes of a publication whose time passed while the participant was down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Missed {
+    /// It is sent once the restarted participant connects to a peer.
+    Send,
+    /// It is dropped.
+    Drop,
+}
+
+impl Missed {
+    /// Returns the name of the policy, as written in the state file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Missed::Send => "send",
+            Missed::Drop => "drop",
+        }
+    }
+
+    /// Parses a policy written by `name`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "send" => Some(Missed::Send),
+            "drop" => Some(Missed::Drop),
+            _ => None,
+        }
+    }
+}
+
+/// Enumerates the reasons a publication can not be scheduled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScheduleError {
+    /// `--max-scheduled` publications are pending already.
+    Full(usize),
+    /// The topic name would be refused by `publish`.
+    Topic(TopicError),
+    /// The due time is further away than the monotonic or the wall clock can tell.
+    TooFar,
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Full(max) => {
+                write!(
+                    f,
+                    "{} publications are scheduled already, the most allowed",
+                    max
+                )
+            }
+            ScheduleError::Topic(err) => write!(f, "{}", err),
+            ScheduleError::TooFar => write!(f, "the due time is too far in the future"),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// Cancels a scheduled publication, from any thread.
+#[derive(Debug, Clone)]
+pub struct ScheduleHandle {
+    id: u64,
+    state: Arc<AtomicU8>,
+}
+
+impl ScheduleHandle {
+    /// Returns the id of the publication, as listed by `Participant::scheduled`.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Cancels the publication, unless it was sent already.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the publication was pending and will never be sent.
+    pub fn cancel(&self) -> bool {
+        self.state
+            .compare_exchange(PENDING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Determines whether the publication is still pending.
+    pub fn is_pending(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == PENDING
+    }
+}
+
+/// A pending publication, as listed by `Participant::scheduled` and the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledEntry {
+    /// The id of the publication.
+    pub id: u64,
+    /// The wall-clock time it is due at.
+    pub due: SystemTime,
+    /// The kind of the message.
+    pub kind: MessageKind,
+    /// The topic of a topic payload.
+    pub topic: Option<String>,
+    /// The size of its frame on the wire, length prefix included.
+    pub bytes: usize,
+}
+
+/// A pending publication, as saved in the state file.
+#[derive(Debug, Clone)]
+pub struct SavedPublication {
+    /// The id of the publication.
+    pub id: u64,
+    /// The wall-clock time it is due at, in milliseconds since the Unix epoch.
+    pub due_unix_ms: u64,
+    /// What becomes of it if its time passed while the participant was down.
+    pub missed: Missed,
+    /// The message to publish.
+    pub message: OutgoingMessage,
+}
+
+impl PartialEq for SavedPublication {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.due_unix_ms == other.due_unix_ms
+            && self.missed == other.missed
+            && self.message.message().encode() == other.message.message().encode()
+    }
+}
+
+impl Eq for SavedPublication {}
+
+/// A publication of the heap.
+#[derive(Debug)]
+struct Scheduled {
+    due: Instant,
+    due_wall: SystemTime,
+    missed: Missed,
+    message: OutgoingMessage,
+    bytes: usize,
+    state: Arc<AtomicU8>,
+    /// Whether the publication waits for the first peer, its time having passed while the
+    /// participant was down.
+    held: bool,
+}
+
+impl Scheduled {
+    fn is_pending(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == PENDING
+    }
+}
+
+/// The counters of a `Schedule`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScheduleStats {
+    /// The publications pending now.
+    pub pending: usize,
+    /// The publications sent so far.
+    pub sent: u64,
+    /// The publications cancelled so far.
+    pub cancelled: u64,
+    /// The restored publications dropped, their time having passed while the participant was
+    /// down.
+    pub missed: u64,
+}
+
+/// The pending publications of a participant, by due time.
+#[derive(Debug)]
+pub struct Schedule {
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    entries: HashMap<u64, Scheduled>,
+    next_id: u64,
+    max: usize,
+    stats: ScheduleStats,
+}
+
+impl Schedule {
+    /// Creates a schedule holding at most `max` pending publications.
+    pub fn new(max: usize) -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            entries: HashMap::new(),
+            next_id: 1,
+            max,
+            stats: ScheduleStats::default(),
+        }
+    }
+
+    /// Changes the number of pending publications allowed; the ones over it stay scheduled.
+    pub fn set_max(&mut self, max: usize) {
+        self.max = max;
+    }
+
+    /// Schedules `message` at `due`.
+    ///
+    /// # Parameters
+    ///
+    /// * `due` - When the message is due.
+    /// * `message` - The message to publish.
+    /// * `missed` - What becomes of it if its time passes while the participant is down.
+    /// * `now` - The current time, on the monotonic clock.
+    /// * `wall` - The current time, on the wall clock.
+    pub fn add(
+        &mut self,
+        due: Due,
+        message: OutgoingMessage,
+        missed: Missed,
+        now: Instant,
+        wall: SystemTime,
+    ) -> Result<ScheduleHandle, ScheduleError> {
+        if let Message::SequencedTopic { topic, .. } = message.message() {
+            validate_topic(topic).map_err(ScheduleError::Topic)?;
+        }
+        self.forget_settled();
+        if self.entries.len() >= self.max {
+            return Err(ScheduleError::Full(self.max));
+        }
+        let (due, due_wall) = match due {
+            Due::At(at) => (Some(at), shift(wall, at, now)),
+            Due::After(delay) => (now.checked_add(delay), wall.checked_add(delay)),
+            Due::AtTime(at) => (
+                now.checked_add(at.duration_since(wall).unwrap_or_default()),
+                Some(at.max(wall)),
+            ),
+        };
+        let (Some(due), Some(due_wall)) = (due, due_wall) else {
+            return Err(ScheduleError::TooFar);
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        let entry = Scheduled {
+            due,
+            due_wall,
+            missed,
+            bytes: message.frame_len(),
+            message,
+            state: Arc::default(),
+            held: false,
+        };
+        Ok(self.insert(id, entry))
+    }
+
+    /// Schedules again a publication saved in the state file.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the publication is pending, `false` if it was dropped, its time having
+    /// passed under `Missed::Drop` or being too far for the clocks.
+    pub fn restore(&mut self, saved: SavedPublication, now: Instant, wall: SystemTime) -> bool {
+        let Some(due_wall) =
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(saved.due_unix_ms))
+        else {
+            return false;
+        };
+        let late = due_wall <= wall;
+        if late && saved.missed == Missed::Drop {
+            self.stats.missed += 1;
+            return false;
+        }
+        let Some(due) = now.checked_add(due_wall.duration_since(wall).unwrap_or_default()) else {
+            return false;
+        };
+        self.next_id = self.next_id.max(saved.id + 1);
+        let entry = Scheduled {
+            due,
+            due_wall,
+            missed: saved.missed,
+            bytes: saved.message.frame_len(),
+            message: saved.message,
+            state: Arc::default(),
+            held: late,
+        };
+        self.insert(saved.id, entry);
+        true
+    }
+
+    /// Adds `entry` to the heap, unless it is held, and returns its handle.
+    fn insert(&mut self, id: u64, entry: Scheduled) -> ScheduleHandle {
+        if !entry.held {
+            self.heap.push(Reverse((entry.due, id)));
+        }
+        let state = Arc::clone(&entry.state);
+        self.entries.insert(id, entry);
+        ScheduleHandle { id, state }
+    }
+
+    /// Cancels the publication `id`, like its `ScheduleHandle` does.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the publication was pending and will never be sent.
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let cancelled = self.entries.get(&id).is_some_and(|entry| {
+            entry
+                .state
+                .compare_exchange(PENDING, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        if cancelled {
+            self.entries.remove(&id);
+            self.stats.cancelled += 1;
+        }
+        cancelled
+    }
+
+    /// Makes the publications held for the first peer due at `now`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if any was held.
+    pub fn release(&mut self, now: Instant) -> bool {
+        let mut released = false;
+        for (id, entry) in &mut self.entries {
+            if entry.held {
+                entry.held = false;
+                entry.due = now;
+                self.heap.push(Reverse((now, *id)));
+                released = true;
+            }
+        }
+        released
+    }
+
+    /// Makes due at `now` the publications whose wall-clock time passed at `wall`, after a
+    /// time the monotonic clock may not have counted.
+    pub fn catch_up(&mut self, now: Instant, wall: SystemTime) {
+        for (id, entry) in &mut self.entries {
+            if !entry.held && entry.due > now && entry.due_wall <= wall {
+                entry.due = now;
+                self.heap.push(Reverse((now, *id)));
+            }
+        }
+    }
+
+    /// Takes the publications due at `now`, in the order they were due, skipping the
+    /// cancelled ones.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(u64, OutgoingMessage)> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, id))) = self.heap.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.heap.pop();
+            // A publication made due earlier by `catch_up` left its former slot behind.
+            if self.entries.get(&id).is_none_or(|entry| entry.due != at) {
+                continue;
+            }
+            let Some(entry) = self.entries.remove(&id) else {
+                continue;
+            };
+            let taken = entry
+                .state
+                .compare_exchange(PENDING, SENT, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok();
+            if taken {
+                self.stats.sent += 1;
+                due.push((entry.due_wall, id, entry.message));
+            } else {
+                self.stats.cancelled += 1;
+            }
+        }
+        // Those `catch_up` made due together share a slot, so the slot order is not theirs.
+        due.sort_by_key(|(due_wall, _, _)| *due_wall);
+        due.into_iter()
+            .map(|(_, id, message)| (id, message))
+            .collect()
+    }
+
+    /// Returns the time the earliest pending publication is due at, if any.
+    pub fn next_due(&mut self) -> Option<Instant> {
+        while let Some(Reverse((at, id))) = self.heap.peek().copied() {
+            match self.entries.get(&id) {
+                Some(entry) if entry.due == at && entry.is_pending() => return Some(at),
+                Some(entry) if entry.due == at => {
+                    self.entries.remove(&id);
+                    self.stats.cancelled += 1;
+                }
+                _ => {}
+            }
+            self.heap.pop();
+        }
+        None
+    }
+
+    /// Forgets the cancelled publications, so they no longer count against the bound.
+    fn forget_settled(&mut self) {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.is_pending());
+        self.stats.cancelled += (before - self.entries.len()) as u64;
+    }
+
+    /// Returns the pending publications, by due time.
+    pub fn pending(&self) -> Vec<ScheduledEntry> {
+        let mut entries: Vec<ScheduledEntry> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_pending())
+            .map(|(id, entry)| {
+                let message = entry.message.message();
+                ScheduledEntry {
+                    id: *id,
+                    due: entry.due_wall,
+                    kind: message.kind(),
+                    topic: match message {
+                        Message::SequencedTopic { topic, .. } => Some(topic.clone()),
+                        _ => None,
+                    },
+                    bytes: entry.bytes,
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.due, entry.id));
+        entries
+    }
+
+    /// Returns the pending publications as saved in the state file, by due time.
+    pub fn saved(&self) -> Vec<SavedPublication> {
+        let mut saved: Vec<SavedPublication> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_pending())
+            .map(|(id, entry)| SavedPublication {
+                id: *id,
+                due_unix_ms: entry
+                    .due_wall
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64),
+                missed: entry.missed,
+                message: entry.message.clone(),
+            })
+            .collect();
+        saved.sort_by_key(|saved| (saved.due_unix_ms, saved.id));
+        saved
+    }
+
+    /// Returns the counters of the schedule.
+    pub fn stats(&self) -> ScheduleStats {
+        ScheduleStats {
+            pending: self
+                .entries
+                .values()
+                .filter(|entry| entry.is_pending())
+                .count(),
+            ..self.stats
+        }
+    }
+}
+
+/// Returns the wall-clock time of the instant `at`, `wall` being the time of `now`, or `None`
+/// past the end of the wall clock.
+fn shift(wall: SystemTime, at: Instant, now: Instant) -> Option<SystemTime> {
+    match at.checked_duration_since(now) {
+        Some(ahead) => wall.checked_add(ahead),
+        None => Some(
+            wall.checked_sub(now.saturating_duration_since(at))
+                .unwrap_or(wall),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule_after(schedule: &mut Schedule, delay_ms: u64, now: Instant, wall: SystemTime) {
+        schedule
+            .add(
+                Due::After(Duration::from_millis(delay_ms)),
+                OutgoingMessage::text(format!("after {}", delay_ms)),
+                Missed::Send,
+                now,
+                wall,
+            )
+            .unwrap();
+    }
+
+    fn texts(due: Vec<(u64, OutgoingMessage)>) -> Vec<String> {
+        due.into_iter()
+            .map(|(_, message)| match message.message() {
+                Message::Text(text) => text.clone(),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn takes_due_publications_in_due_order() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        for delay in [300, 100, 200] {
+            schedule_after(&mut schedule, delay, now, wall);
+        }
+        assert_eq!(schedule.next_due(), Some(now + Duration::from_millis(100)));
+        assert!(schedule.take_due(now).is_empty());
+        let due = schedule.take_due(now + Duration::from_millis(250));
+        assert_eq!(texts(due), ["after 100", "after 200"]);
+        let due = schedule.take_due(now + Duration::from_secs(1));
+        assert_eq!(texts(due), ["after 300"]);
+        assert_eq!(schedule.stats().sent, 3);
+        assert_eq!(schedule.next_due(), None);
+    }
+
+    #[test]
+    fn cancel_and_fire_exclude_each_other() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        let cancelled = schedule
+            .add(
+                Due::After(Duration::from_millis(10)),
+                OutgoingMessage::text("cancelled"),
+                Missed::Send,
+                now,
+                wall,
+            )
+            .unwrap();
+        let fired = schedule
+            .add(
+                Due::After(Duration::from_millis(10)),
+                OutgoingMessage::text("fired"),
+                Missed::Send,
+                now,
+                wall,
+            )
+            .unwrap();
+
+        assert!(cancelled.cancel());
+        assert!(!cancelled.cancel());
+        let due = schedule.take_due(now + Duration::from_secs(1));
+        assert_eq!(texts(due), ["fired"]);
+        assert!(!fired.cancel());
+        assert!(!fired.is_pending());
+        assert!(!schedule.cancel(fired.id()));
+        let stats = schedule.stats();
+        assert_eq!((stats.sent, stats.cancelled, stats.pending), (1, 1, 0));
+    }
+
+    #[test]
+    fn refuses_beyond_the_bound_until_one_is_cancelled() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(2);
+        schedule_after(&mut schedule, 10, now, wall);
+        let second = schedule
+            .add(
+                Due::After(Duration::from_millis(20)),
+                OutgoingMessage::text("second"),
+                Missed::Send,
+                now,
+                wall,
+            )
+            .unwrap();
+        let third = schedule.add(
+            Due::After(Duration::from_millis(30)),
+            OutgoingMessage::text("third"),
+            Missed::Send,
+            now,
+            wall,
+        );
+        assert_eq!(third.unwrap_err(), ScheduleError::Full(2));
+
+        assert!(second.cancel());
+        schedule_after(&mut schedule, 30, now, wall);
+        assert_eq!(schedule.stats().pending, 2);
+    }
+
+    #[test]
+    fn refuses_due_times_past_the_clocks() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        for due in [
+            Due::After(Duration::MAX),
+            Due::After(Duration::from_secs(u64::MAX / 2)),
+        ] {
+            let result = schedule.add(due, OutgoingMessage::text("never"), Missed::Send, now, wall);
+            assert_eq!(result.unwrap_err(), ScheduleError::TooFar);
+        }
+        assert_eq!(schedule.stats().pending, 0);
+    }
+
+    #[test]
+    fn refuses_an_invalid_topic() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        let result = schedule.add(
+            Due::After(Duration::from_secs(1)),
+            OutgoingMessage::topic("", b"payload".to_vec()),
+            Missed::Send,
+            now,
+            wall,
+        );
+        assert!(matches!(result, Err(ScheduleError::Topic(_))));
+    }
+
+    #[test]
+    fn saved_publications_follow_their_missed_policy() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        for (delay, missed) in [
+            (1_000, Missed::Send),
+            (2_000, Missed::Drop),
+            (60_000, Missed::Drop),
+        ] {
+            schedule
+                .add(
+                    Due::After(Duration::from_millis(delay)),
+                    OutgoingMessage::text(format!("after {}", delay)),
+                    missed,
+                    now,
+                    wall,
+                )
+                .unwrap();
+        }
+        let saved = schedule.saved();
+        assert_eq!(saved.len(), 3);
+
+        // The participant comes back ten seconds later: the first two are late.
+        let (later, later_wall) = (Instant::now(), wall + Duration::from_secs(10));
+        let mut restored = Schedule::new(8);
+        let kept: Vec<bool> = saved
+            .into_iter()
+            .map(|saved| restored.restore(saved, later, later_wall))
+            .collect();
+        assert_eq!(kept, [true, false, true]);
+        assert_eq!(restored.stats().missed, 1);
+
+        // The late one waits for the first peer, then goes at once.
+        assert!(restored.take_due(later + Duration::from_secs(5)).is_empty());
+        assert!(restored.release(later));
+        assert_eq!(texts(restored.take_due(later)), ["after 1000"]);
+        // Saved times keep milliseconds, so the rest may be due a little earlier.
+        let next = restored.next_due().unwrap();
+        assert!(next <= later + Duration::from_secs(50));
+        assert!(next > later + Duration::from_millis(49_990));
+    }
+
+    #[test]
+    fn restored_ids_do_not_collide_with_new_ones() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        let saved = SavedPublication {
+            id: 41,
+            due_unix_ms: (wall + Duration::from_secs(60))
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            missed: Missed::Send,
+            message: OutgoingMessage::text("restored"),
+        };
+        assert!(schedule.restore(saved, now, wall));
+        let handle = schedule
+            .add(
+                Due::After(Duration::from_secs(1)),
+                OutgoingMessage::text("new"),
+                Missed::Send,
+                now,
+                wall,
+            )
+            .unwrap();
+        assert_eq!(handle.id(), 42);
+    }
+
+    #[test]
+    fn catch_up_fires_the_overdue_once_and_in_order() {
+        let (now, wall) = (Instant::now(), SystemTime::now());
+        let mut schedule = Schedule::new(8);
+        for delay in [20_000, 10_000, 90_000] {
+            schedule_after(&mut schedule, delay, now, wall);
+        }
+
+        // The monotonic clock stood still for a minute while the system slept.
+        let resumed_wall = wall + Duration::from_secs(60);
+        schedule.catch_up(now, resumed_wall);
+        assert_eq!(
+            texts(schedule.take_due(now)),
+            ["after 10000", "after 20000"]
+        );
+        schedule.catch_up(now, resumed_wall);
+        assert!(schedule.take_due(now + Duration::from_secs(20)).is_empty());
+        assert_eq!(
+            texts(schedule.take_due(now + Duration::from_secs(90))),
+            ["after 90000"]
+        );
+        assert_eq!(schedule.stats().sent, 3);
+    }
+}
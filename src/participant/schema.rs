@@ -0,0 +1,289 @@
+//! Payload Schemas.
+//!
+//! Application payloads change while a fleet is rolled over, and a node handed a payload of a
+//! layout it predates mis-parses it or crashes its deserializer. A publisher may therefore
+//! declare the `Schema` of a payload, a name and a version, carried with the topic in a
+//! `Message::VersionedTopic` to the peers speaking `SCHEMAS_VERSION`; older peers are sent the
+//! payload without it, as before.
+//!
+//! A subscriber registers a handler per topic and schema name with the `VersionRange` it
+//! parses. A versioned payload goes to the one handler of its topic and schema name whose
+//! range holds its version, the narrowest range if several do, then the one starting at the
+//! highest version, then the earliest subscription, so the choice never depends on the order
+//! of a map. A payload no handler supports goes to the fallbacks registered for its topic
+//! with `on_unsupported_schema`, along with the schema it declared, and is counted either
+//! way. The plain subscribers of the topic receive every payload, versioned or not;
+//! an unversioned payload never reaches a schema handler or a fallback.
+//!
+//! A participant announces the schemas it handles with its interest, in a
+//! `Message::SchemaUpdate` listing at most `MAX_SCHEMA_ENTRIES` of them. `SchemaCensus` keeps
+//! what each peer announced, so a publisher can count the peers able to read a new version
+//! before flipping over to it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// The most schemas a schema update lists; the others are not announced.
+pub const MAX_SCHEMA_ENTRIES: usize = 32;
+
+/// The schema a payload declares.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Schema {
+    /// The name of the schema, checked like a topic name.
+    pub name: String,
+    /// The version of the schema the payload follows.
+    pub version: u32,
+}
+
+impl Schema {
+    /// Creates the schema `name` at `version`.
+    pub fn new(name: impl Into<String>, version: u32) -> Self {
+        Self {
+            name: name.into(),
+            version,
+        }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} v{}", self.name, self.version)
+    }
+}
+
+/// The versions of a schema a handler parses, both bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct VersionRange {
+    min: u32,
+    max: u32,
+}
+
+impl VersionRange {
+    /// Creates the range from `min` to `max`, unless it is empty.
+    pub fn new(min: u32, max: u32) -> Option<Self> {
+        (min <= max).then_some(Self { min, max })
+    }
+
+    /// Creates the range holding `version` only.
+    pub fn exactly(version: u32) -> Self {
+        Self {
+            min: version,
+            max: version,
+        }
+    }
+
+    /// Returns the lowest version of the range.
+    pub fn min(self) -> u32 {
+        self.min
+    }
+
+    /// Returns the highest version of the range.
+    pub fn max(self) -> u32 {
+        self.max
+    }
+
+    /// Determines whether the range holds `version`.
+    pub fn contains(self, version: u32) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+
+    /// Determines whether the range holds a version, which a decoded one may not.
+    pub fn is_valid(self) -> bool {
+        self.min <= self.max
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "v{}", self.min)
+        } else {
+            write!(f, "v{}-v{}", self.min, self.max)
+        }
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = String;
+
+    /// Parses a version, or two separated by `-`, each with an optional `v` as displayed.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid version range \"{}\"", text);
+        let version = |bound: &str| {
+            let bound = bound.strip_prefix('v').unwrap_or(bound);
+            bound.parse::<u32>().map_err(|_| invalid())
+        };
+        match text.split_once('-') {
+            Some((min, max)) => Self::new(version(min)?, version(max)?).ok_or_else(invalid),
+            None => version(text).map(Self::exactly),
+        }
+    }
+}
+
+/// A schema a participant handles on a topic, as announced to its peers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SchemaSupport {
+    /// The topic the payloads are published on.
+    pub topic: String,
+    /// The name of the schema.
+    pub name: String,
+    /// The versions handled.
+    pub range: VersionRange,
+}
+
+/// Picks the handler of `version` among `candidates`, keyed by subscription order.
+///
+/// # Returns
+///
+/// The key of the narrowest range holding `version`, the one starting at the highest version
+/// among equally narrow ones, then the lowest key; `None` if no range holds it.
+pub fn most_specific<K: Ord + Copy>(
+    candidates: impl IntoIterator<Item = (K, VersionRange)>,
+    version: u32,
+) -> Option<K> {
+    candidates
+        .into_iter()
+        .filter(|(_, range)| range.contains(version))
+        .min_by_key(|(key, range)| (range.max - range.min, u32::MAX - range.min, *key))
+        .map(|(key, _)| key)
+}
+
+/// The schemas each peer announced, by public address.
+#[derive(Debug, Default)]
+pub struct SchemaCensus {
+    peers: HashMap<SocketAddr, Vec<SchemaSupport>>,
+}
+
+impl SchemaCensus {
+    /// Creates a census of no peer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces what `peer` announced with `schemas`.
+    pub fn announced(&mut self, peer: SocketAddr, schemas: Vec<SchemaSupport>) {
+        if schemas.is_empty() {
+            self.peers.remove(&peer);
+        } else {
+            self.peers.insert(peer, schemas);
+        }
+    }
+
+    /// Forgets what `peer` announced, once it is gone.
+    pub fn forget(&mut self, peer: SocketAddr) {
+        self.peers.remove(&peer);
+    }
+
+    /// Returns the peers handling schema `name` on `topic` with the ranges they handle, by
+    /// address then range; a peer with several handlers of the schema is listed once per
+    /// range.
+    pub fn support(&self, topic: &str, name: &str) -> Vec<(SocketAddr, VersionRange)> {
+        let mut support: Vec<(SocketAddr, VersionRange)> = self
+            .peers
+            .iter()
+            .flat_map(|(peer, schemas)| {
+                schemas
+                    .iter()
+                    .filter(|schema| schema.topic == topic && schema.name == name)
+                    .map(|schema| (*peer, schema.range))
+            })
+            .collect();
+        support.sort();
+        support.dedup();
+        support
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn range(min: u32, max: u32) -> VersionRange {
+        VersionRange::new(min, max).unwrap()
+    }
+
+    fn support(topic: &str, name: &str, range: VersionRange) -> SchemaSupport {
+        SchemaSupport {
+            topic: topic.to_owned(),
+            name: name.to_owned(),
+            range,
+        }
+    }
+
+    #[test]
+    fn ranges_hold_both_bounds() {
+        assert_eq!(VersionRange::new(3, 2), None);
+        let range = range(1, 3);
+        assert!(!range.contains(0));
+        assert!(range.contains(1) && range.contains(3));
+        assert!(!range.contains(4));
+        assert_eq!((range.min(), range.max()), (1, 3));
+        assert!(VersionRange::exactly(5).contains(5));
+        assert!(!VersionRange { min: 2, max: 1 }.is_valid());
+    }
+
+    #[test]
+    fn ranges_parse_as_displayed() {
+        for text in ["v2", "v1-v3", "v0-v4294967295"] {
+            let parsed: VersionRange = text.parse().unwrap();
+            assert_eq!(parsed.to_string(), text);
+        }
+        assert_eq!("1-3".parse(), Ok(range(1, 3)));
+        assert_eq!("7".parse(), Ok(VersionRange::exactly(7)));
+        for text in ["", "v", "3-1", "1-", "x1", "1-2-3"] {
+            assert_eq!(
+                text.parse::<VersionRange>(),
+                Err(format!("Invalid version range \"{}\"", text)),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(Schema::new("order", 2).to_string(), "order v2");
+    }
+
+    #[test]
+    fn the_most_specific_range_wins() {
+        let candidates = [(1, range(1, 10)), (2, range(2, 3)), (3, range(3, 4))];
+        assert_eq!(most_specific(candidates, 1), Some(1));
+        assert_eq!(most_specific(candidates, 2), Some(2));
+        // Equally narrow, the range starting higher wins.
+        assert_eq!(most_specific(candidates, 3), Some(3));
+        assert_eq!(most_specific(candidates, 11), None);
+
+        // Equal ranges go to the earliest subscription, whatever the order given.
+        let tied = [(5, range(1, 2)), (4, range(1, 2))];
+        assert_eq!(most_specific(tied, 1), Some(4));
+    }
+
+    #[test]
+    fn the_census_lists_the_peers_handling_a_schema() {
+        let mut census = SchemaCensus::new();
+        census.announced(
+            addr(8081),
+            vec![
+                support("orders", "order", range(2, 3)),
+                support("orders", "order", VersionRange::exactly(1)),
+                support("orders", "invoice", range(1, 5)),
+            ],
+        );
+        census.announced(addr(8080), vec![support("orders", "order", range(1, 2))]);
+        census.announced(addr(8090), vec![support("news", "order", range(1, 2))]);
+
+        assert_eq!(
+            census.support("orders", "order"),
+            [
+                (addr(8080), range(1, 2)),
+                (addr(8081), VersionRange::exactly(1)),
+                (addr(8081), range(2, 3)),
+            ]
+        );
+        census.forget(addr(8080));
+        census.announced(addr(8081), Vec::new());
+        assert!(census.support("orders", "order").is_empty());
+        assert_eq!(census.support("news", "order").len(), 1);
+    }
+}
@@ -0,0 +1,315 @@
+//! Peer Session History.
+//!
+//! A session is the time a participant stays connected under its public address, from its
+//! handshake to its departure. The storage keeps the last `SESSION_HISTORY_LEN` sessions of each
+//! public address, across endpoints: a peer that reconnects, or that is re-dialed as half-open,
+//! adds a session to the same history instead of starting a new one. The history tells a
+//! flapping peer, which comes and goes every few seconds, from a peer that is merely connected.
+//! A peer presenting a known node id from a new address takes the history of its former address
+//! along.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The number of sessions remembered per public address.
+pub const SESSION_HISTORY_LEN: usize = 8;
+
+/// The window over which departures count towards the flap rate.
+pub const FLAP_WINDOW: Duration = Duration::from_secs(600);
+
+/// Why a session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DepartureReason {
+    /// The connection was lost or closed by the peer.
+    Disconnected,
+    /// The connection looked half-open and was closed to dial the peer again.
+    HalfOpen,
+    /// The peer sent a message it should never send and was disconnected.
+    Violation,
+    /// The peer became blocked and was disconnected.
+    Blocked,
+    /// The peer announced another public address through the same connection.
+    Reannounced,
+    /// The first frame of the peer showed that it speaks another protocol.
+    ProtocolMismatch,
+    /// The peer was evicted from the console.
+    Evicted,
+    /// The peer was the bootstrap target and did not present the expected identity.
+    Unverified,
+}
+
+impl DepartureReason {
+    /// Returns the human-readable name of this reason.
+    pub fn name(self) -> &'static str {
+        match self {
+            DepartureReason::Disconnected => "disconnected",
+            DepartureReason::HalfOpen => "half-open",
+            DepartureReason::Violation => "protocol violation",
+            DepartureReason::Blocked => "blocked",
+            DepartureReason::Reannounced => "re-announced",
+            DepartureReason::ProtocolMismatch => "protocol mismatch",
+            DepartureReason::Evicted => "evicted",
+            DepartureReason::Unverified => "unverified",
+        }
+    }
+}
+
+impl fmt::Display for DepartureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One connection of a participant under its public address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    /// The time the participant connected.
+    pub connected_at: Instant,
+    /// The time the participant departed, `None` while it is connected.
+    pub disconnected_at: Option<Instant>,
+    /// Why the participant departed, `None` while it is connected.
+    pub reason: Option<DepartureReason>,
+}
+
+impl Session {
+    /// Returns how long the session lasted, or has lasted so far.
+    fn duration(&self, now: Instant) -> Duration {
+        self.disconnected_at
+            .unwrap_or(now)
+            .saturating_duration_since(self.connected_at)
+    }
+}
+
+/// The metrics derived from a session history, used for reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionStats {
+    /// The duration of the current session, `None` if the participant is not connected.
+    pub current: Option<Duration>,
+    /// The number of sessions seen, including those no longer in the history.
+    pub total: u64,
+    /// The number of departures within the last `FLAP_WINDOW`.
+    pub flaps: usize,
+    /// The longest session in the history.
+    pub longest: Duration,
+}
+
+/// The last sessions of one public address, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct SessionHistory {
+    sessions: VecDeque<Session>,
+    total: u64,
+}
+
+impl SessionHistory {
+    /// Starts a session, unless one is already open; the oldest session makes room when the
+    /// history is full.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The time the participant connected.
+    pub fn open(&mut self, now: Instant) {
+        if self.is_open() {
+            return;
+        }
+        if self.sessions.len() == SESSION_HISTORY_LEN {
+            self.sessions.pop_front();
+        }
+        self.sessions.push_back(Session {
+            connected_at: now,
+            disconnected_at: None,
+            reason: None,
+        });
+        self.total += 1;
+    }
+
+    /// Counts `sessions` earlier sessions, such as those of a previous run of this
+    /// participant, in the total.
+    pub fn carry(&mut self, sessions: u64) {
+        self.total += sessions;
+    }
+
+    /// Takes in the history of another address of the same node, whose sessions all came
+    /// before those of this one; the oldest make room when the history is full.
+    pub fn absorb(&mut self, earlier: SessionHistory) {
+        let mut sessions = earlier.sessions;
+        sessions.extend(self.sessions.drain(..));
+        let excess = sessions.len().saturating_sub(SESSION_HISTORY_LEN);
+        sessions.drain(..excess);
+        self.sessions = sessions;
+        self.total += earlier.total;
+    }
+
+    /// Ends the open session, if any.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The time the participant departed.
+    /// * `reason` - Why it departed.
+    pub fn close(&mut self, now: Instant, reason: DepartureReason) {
+        let open = self
+            .sessions
+            .back_mut()
+            .filter(|session| session.disconnected_at.is_none());
+        if let Some(session) = open {
+            session.disconnected_at = Some(now);
+            session.reason = Some(reason);
+        }
+    }
+
+    /// Determines whether the participant is connected.
+    pub fn is_open(&self) -> bool {
+        self.sessions
+            .back()
+            .is_some_and(|session| session.disconnected_at.is_none())
+    }
+
+    /// Returns how long ago the participant departed, `None` while it is connected.
+    ///
+    /// An empty history, which no session ever opened, departed as long ago as can be.
+    pub fn departed_for(&self, now: Instant) -> Option<Duration> {
+        match self.sessions.back() {
+            Some(Session {
+                disconnected_at: Some(at),
+                ..
+            }) => Some(now.saturating_duration_since(*at)),
+            Some(_) => None,
+            None => Some(Duration::MAX),
+        }
+    }
+
+    /// Returns the number of sessions remembered.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Iterates over the remembered sessions, oldest first.
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.iter()
+    }
+
+    /// Derives the reported metrics.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    pub fn stats(&self, now: Instant) -> SessionStats {
+        SessionStats {
+            current: self
+                .sessions
+                .back()
+                .filter(|session| session.disconnected_at.is_none())
+                .map(|session| session.duration(now)),
+            total: self.total,
+            flaps: self
+                .sessions
+                .iter()
+                .filter_map(|session| session.disconnected_at)
+                .filter(|at| now.saturating_duration_since(*at) <= FLAP_WINDOW)
+                .count(),
+            longest: self
+                .sessions
+                .iter()
+                .map(|session| session.duration(now))
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    /// A history of sessions of `length` seconds each, starting every 10 seconds after `start`.
+    fn history(start: Instant, sessions: u64, length: u64) -> SessionHistory {
+        let mut history = SessionHistory::default();
+        for i in 0..sessions {
+            history.open(start + secs(i * 10));
+            history.close(start + secs(i * 10 + length), DepartureReason::Disconnected);
+        }
+        history
+    }
+
+    #[test]
+    fn a_session_opens_once_and_closes_with_its_reason() {
+        let start = Instant::now();
+        let mut history = SessionHistory::default();
+        assert!(!history.is_open());
+        assert_eq!(history.departed_for(start), Some(Duration::MAX));
+
+        history.open(start);
+        history.open(start + secs(1));
+        assert!(history.is_open());
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.departed_for(start + secs(2)), None);
+
+        history.close(start + secs(3), DepartureReason::HalfOpen);
+        history.close(start + secs(4), DepartureReason::Evicted);
+        assert!(!history.is_open());
+        assert_eq!(
+            history.sessions().copied().collect::<Vec<_>>(),
+            [Session {
+                connected_at: start,
+                disconnected_at: Some(start + secs(3)),
+                reason: Some(DepartureReason::HalfOpen),
+            }]
+        );
+        assert_eq!(history.departed_for(start + secs(5)), Some(secs(2)));
+    }
+
+    #[test]
+    fn the_oldest_session_makes_room_but_stays_counted() {
+        let start = Instant::now();
+        let history = history(start, SESSION_HISTORY_LEN as u64 + 2, 1);
+        assert_eq!(history.len(), SESSION_HISTORY_LEN);
+        assert_eq!(
+            history
+                .sessions()
+                .next()
+                .map(|session| session.connected_at),
+            Some(start + secs(20))
+        );
+        let stats = history.stats(start + secs(100));
+        assert_eq!(stats.total, SESSION_HISTORY_LEN as u64 + 2);
+        assert_eq!(stats.current, None);
+    }
+
+    #[test]
+    fn departures_stop_counting_as_flaps_after_the_window() {
+        let start = Instant::now();
+        let mut history = history(start, 3, 5);
+        history.open(start + secs(30));
+
+        let stats = history.stats(start + secs(40));
+        assert_eq!(stats.flaps, 3);
+        assert_eq!(stats.current, Some(secs(10)));
+        assert_eq!(stats.longest, secs(10));
+
+        // The first departure, at 5 seconds, leaves the window first.
+        let stats = history.stats(start + secs(5) + FLAP_WINDOW + secs(1));
+        assert_eq!(stats.flaps, 2);
+    }
+
+    #[test]
+    fn an_absorbed_history_comes_first_and_is_trimmed_from_the_oldest() {
+        let start = Instant::now();
+        let earlier = history(start, 6, 1);
+        let mut later = history(start + secs(100), 4, 1);
+        later.carry(5);
+        later.absorb(earlier);
+
+        assert_eq!(later.len(), SESSION_HISTORY_LEN);
+        let connected: Vec<_> = later
+            .sessions()
+            .map(|session| session.connected_at.duration_since(start))
+            .collect();
+        assert_eq!(connected, [20, 30, 40, 50, 100, 110, 120, 130].map(secs));
+        assert_eq!(later.stats(start + secs(200)).total, 6 + 4 + 5);
+    }
+}
@@ -0,0 +1,378 @@
+//! Clock Skew.
+//!
+//! A wall-clock time read on one machine and compared on another, such as the start of the
+//! round a text was sent in, is only as good as the agreement of both clocks. Rather than
+//! requiring NTP everywhere, each participant estimates how far the clock of every peer is from
+//! its own, with the four timestamps of NTP. Every `CLOCK_SAMPLE_INTERVAL` it sends each peer
+//! speaking protocol version 10 a `Message::ClockRequest` carrying its wall-clock time `t1`,
+//! and the peer answers at once with a `Message::ClockReply` echoing `t1` with the times it
+//! received the request, `t2`, and replied, `t3`, read on its clock. The reply arriving at `t4`
+//! makes a `ClockSample`:
+//!
+//! ```plaintext
+//! rtt    = (t4 - t1) - (t3 - t2)
+//! offset = ((t2 - t1) + (t3 - t4)) / 2
+//! ```
+//!
+//! The offset, the clock of the peer minus ours, is exact when both legs of the round trip
+//! take as long, and off by half their difference otherwise, so never by more than half the
+//! round trip. A `ClockEstimate` averages the offsets of the last `RECENT_SAMPLES` samples, each
+//! weighted by the square of the ratio between the shortest of their round trips and its own:
+//! a sample taken while a queue held one leg back barely counts. Its confidence bound is half
+//! the shortest round trip plus the standard deviation of the round trips, under the same
+//! weights.
+//!
+//! The estimates show in `peers`, `stats` and the report, and correct the delay logged for the
+//! round texts. An offset beyond `--max-clock-offset`, 5s by default, even at the near end of
+//! its bound, is logged as a warning once, until it falls back under: that far off, the clock
+//! of the peer is usually broken.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The time between two clock requests to a peer.
+pub const CLOCK_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The longest round trip of a sample kept; a later reply is dropped.
+pub const MAX_SAMPLE_RTT: Duration = Duration::from_secs(10);
+
+/// The number of samples an estimate is made of, the most recent ones.
+pub const RECENT_SAMPLES: usize = 8;
+
+/// Returns `time` in microseconds since the Unix epoch, `0` before it.
+pub fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| {
+        u64::try_from(since.as_micros()).unwrap_or(u64::MAX)
+    })
+}
+
+/// The four timestamps of a clock exchange, in microseconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    /// `t1`, when we sent the request, on our clock.
+    pub sent: u64,
+    /// `t2`, when the peer received it, on its clock.
+    pub received: u64,
+    /// `t3`, when the peer replied, on its clock.
+    pub replied: u64,
+    /// `t4`, when the reply arrived, on our clock.
+    pub arrived: u64,
+}
+
+impl ClockSample {
+    /// Returns the round trip of the exchange, without the time the peer took to reply.
+    ///
+    /// # Returns
+    ///
+    /// `None` when the timestamps contradict each other: a reply arriving before its request
+    /// was sent, or sent before the request was received.
+    pub fn rtt(&self) -> Option<Duration> {
+        let elapsed = self.arrived.checked_sub(self.sent)?;
+        let held = self.replied.checked_sub(self.received)?;
+        Some(Duration::from_micros(elapsed.saturating_sub(held)))
+    }
+
+    /// Returns the offset of the clock of the peer from ours, in microseconds.
+    pub fn offset(&self) -> i64 {
+        let span = |from: u64, to: u64| i128::from(to) - i128::from(from);
+        let offset = (span(self.sent, self.received) + span(self.arrived, self.replied)) / 2;
+        i64::try_from(offset).unwrap_or(if offset < 0 { i64::MIN } else { i64::MAX })
+    }
+}
+
+/// The estimated offset of the clock of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockOffset {
+    /// The clock of the peer minus ours, in microseconds.
+    pub offset_us: i64,
+    /// How far the actual offset may be from `offset_us`.
+    pub bound: Duration,
+    /// The number of samples taken so far.
+    pub samples: u64,
+}
+
+impl ClockOffset {
+    /// Returns the magnitude of the offset.
+    pub fn magnitude(&self) -> Duration {
+        Duration::from_micros(self.offset_us.unsigned_abs())
+    }
+
+    /// Determines whether the offset exceeds `max` even at the near end of its bound.
+    pub fn exceeds(&self, max: Duration) -> bool {
+        self.magnitude().saturating_sub(self.bound) > max
+    }
+}
+
+impl fmt::Display for ClockOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ± {:.3} ms",
+            format_offset(self.offset_us),
+            self.bound.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Formats an offset in microseconds as signed milliseconds, such as `+1.250 ms`.
+pub fn format_offset(offset_us: i64) -> String {
+    format!("{:+.3} ms", offset_us as f64 / 1000.0)
+}
+
+/// The recent samples of the offset of the clock of one peer, and the request awaiting its
+/// reply.
+#[derive(Debug, Clone, Default)]
+pub struct ClockEstimate {
+    /// `t1` of the request awaiting its reply, if any.
+    pending: Option<u64>,
+    /// The offset and the round trip of each recent sample, in microseconds, oldest first.
+    recent: VecDeque<(i64, u64)>,
+    samples: u64,
+    /// Whether the offset exceeds the threshold since it was last warned about.
+    warned: bool,
+}
+
+impl ClockEstimate {
+    /// Creates an estimate without samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the request sent at `sent`, in microseconds since the Unix epoch; a reply to an
+    /// earlier one is dropped from now on.
+    pub fn requested(&mut self, sent: u64) {
+        self.pending = Some(sent);
+    }
+
+    /// Takes the reply to the pending request.
+    ///
+    /// # Returns
+    ///
+    /// `false` when `sample` answers no pending request, contradicts itself or took longer
+    /// than `MAX_SAMPLE_RTT`; it is then dropped.
+    pub fn reply(&mut self, sample: ClockSample) -> bool {
+        if self.pending != Some(sample.sent) {
+            return false;
+        }
+        self.pending = None;
+        match sample.rtt() {
+            Some(rtt) if rtt <= MAX_SAMPLE_RTT => {
+                self.add(sample.offset(), rtt);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a sample of offset `offset_us` taken with a round trip of `rtt`, forgetting the
+    /// oldest one beyond `RECENT_SAMPLES`.
+    pub fn add(&mut self, offset_us: i64, rtt: Duration) {
+        if self.recent.len() == RECENT_SAMPLES {
+            self.recent.pop_front();
+        }
+        let rtt = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX);
+        self.recent.push_back((offset_us, rtt));
+        self.samples += 1;
+    }
+
+    /// Returns the estimated offset, `None` before the first sample.
+    pub fn offset(&self) -> Option<ClockOffset> {
+        let shortest = self.recent.iter().map(|(_, rtt)| *rtt).min()? as f64;
+        let weight = |rtt: u64| (shortest.max(1.0) / (rtt as f64).max(1.0)).powi(2);
+        let total: f64 = self.recent.iter().map(|(_, rtt)| weight(*rtt)).sum();
+        let mean = |value: &dyn Fn(i64, f64) -> f64| {
+            let sum: f64 = self
+                .recent
+                .iter()
+                .map(|(offset, rtt)| weight(*rtt) * value(*offset, *rtt as f64))
+                .sum();
+            sum / total
+        };
+        let offset = mean(&|offset, _| offset as f64);
+        let rtt = mean(&|_, rtt| rtt);
+        let deviation = mean(&|_, sample| (sample - rtt).powi(2)).sqrt();
+        Some(ClockOffset {
+            offset_us: offset.round() as i64,
+            bound: Duration::from_micros((shortest / 2.0 + deviation).round() as u64),
+            samples: self.samples,
+        })
+    }
+
+    /// Determines whether the offset is to be warned about, exceeding `max` for the first
+    /// time since it was last under it.
+    pub fn warn(&mut self, max: Duration) -> bool {
+        let exceeds = self.offset().is_some_and(|offset| offset.exceeds(max));
+        let first = exceeds && !self.warned;
+        self.warned = exceeds;
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sample of an exchange started at `sent` with a peer whose clock is `offset`
+    /// microseconds ahead of ours, taking `forward` and `back` microseconds each way and
+    /// `held` to reply.
+    fn exchange(sent: u64, offset: i64, forward: u64, held: u64, back: u64) -> ClockSample {
+        let received = sent.checked_add_signed(offset).unwrap() + forward;
+        let replied = received + held;
+        ClockSample {
+            sent,
+            received,
+            replied,
+            arrived: sent + forward + held + back,
+        }
+    }
+
+    #[test]
+    fn recovers_the_offset_of_a_symmetric_exchange() {
+        let ahead = exchange(1_000_000, 500_000, 10_000, 2_000, 10_000);
+        assert_eq!(
+            ahead,
+            ClockSample {
+                sent: 1_000_000,
+                received: 1_510_000,
+                replied: 1_512_000,
+                arrived: 1_022_000,
+            }
+        );
+        assert_eq!(ahead.offset(), 500_000);
+        assert_eq!(ahead.rtt(), Some(Duration::from_millis(20)));
+
+        let behind = exchange(1_000_000, -300_000, 10_000, 0, 10_000);
+        assert_eq!(behind.offset(), -300_000);
+        assert_eq!(behind.rtt(), Some(Duration::from_millis(20)));
+
+        let same = exchange(1_000_000, 0, 1, 5_000, 1);
+        assert_eq!(same.offset(), 0);
+        assert_eq!(same.rtt(), Some(Duration::from_micros(2)));
+    }
+
+    #[test]
+    fn an_asymmetric_exchange_is_off_by_half_the_difference() {
+        let sample = exchange(1_000_000, 0, 30_000, 1_000, 10_000);
+        assert_eq!(sample.offset(), 10_000);
+        assert_eq!(sample.rtt(), Some(Duration::from_millis(40)));
+
+        let sample = exchange(1_000_000, -200_000, 5_000, 0, 45_000);
+        assert_eq!(sample.offset(), -220_000);
+        let error = (sample.offset() + 200_000).unsigned_abs();
+        assert!(Duration::from_micros(error) <= sample.rtt().unwrap() / 2);
+    }
+
+    #[test]
+    fn refuses_contradicting_timestamps() {
+        let mut sample = exchange(1_000_000, 0, 10, 10, 10);
+        sample.arrived = 999_999;
+        assert_eq!(sample.rtt(), None);
+
+        let mut sample = exchange(1_000_000, 0, 10, 10, 10);
+        sample.replied = sample.received - 1;
+        assert_eq!(sample.rtt(), None);
+
+        // The peer holding the request longer than the whole exchange took leaves no round
+        // trip at all.
+        let mut sample = exchange(1_000_000, 0, 10, 10, 10);
+        sample.replied += 1_000;
+        assert_eq!(sample.rtt(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn clamps_an_offset_beyond_the_range() {
+        let far = ClockSample {
+            sent: 0,
+            received: u64::MAX,
+            replied: u64::MAX,
+            arrived: 0,
+        };
+        assert_eq!(far.offset(), i64::MAX);
+        assert_eq!(unix_micros(UNIX_EPOCH - Duration::from_secs(1)), 0);
+        assert_eq!(unix_micros(UNIX_EPOCH + Duration::from_millis(3)), 3_000);
+    }
+
+    #[test]
+    fn takes_the_reply_to_the_pending_request_only() {
+        let mut estimate = ClockEstimate::new();
+        assert!(!estimate.reply(exchange(1_000, 0, 10, 0, 10)));
+
+        estimate.requested(1_000);
+        estimate.requested(2_000);
+        assert!(!estimate.reply(exchange(1_000, 0, 10, 0, 10)));
+        assert!(estimate.reply(exchange(2_000, 400, 10, 0, 10)));
+        assert!(!estimate.reply(exchange(2_000, 400, 10, 0, 10)));
+
+        let late = MAX_SAMPLE_RTT.as_micros() as u64;
+        estimate.requested(3_000);
+        assert!(!estimate.reply(exchange(3_000, 0, late, 0, 1)));
+        assert_eq!(estimate.offset().unwrap().offset_us, 400);
+        assert_eq!(estimate.offset().unwrap().samples, 1);
+    }
+
+    #[test]
+    fn weighs_the_samples_by_their_round_trip() {
+        let mut estimate = ClockEstimate::new();
+        assert_eq!(estimate.offset(), None);
+        estimate.add(1_000, Duration::from_micros(1_000));
+        estimate.add(5_000, Duration::from_micros(2_000));
+        assert_eq!(
+            estimate.offset(),
+            Some(ClockOffset {
+                offset_us: 1_800,
+                bound: Duration::from_micros(900),
+                samples: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn forgets_the_samples_past_the_recent_ones() {
+        let mut estimate = ClockEstimate::new();
+        estimate.add(-1_000_000, Duration::from_micros(100));
+        for _ in 0..RECENT_SAMPLES {
+            estimate.add(-2_000, Duration::from_micros(100));
+        }
+        let offset = estimate.offset().unwrap();
+        assert_eq!(offset.offset_us, -2_000);
+        assert_eq!(offset.bound, Duration::from_micros(50));
+        assert_eq!(offset.samples, RECENT_SAMPLES as u64 + 1);
+    }
+
+    #[test]
+    fn warns_once_until_the_offset_falls_back_under() {
+        let max = Duration::from_secs(5);
+        let mut estimate = ClockEstimate::new();
+        assert!(!estimate.warn(max));
+
+        // Within its bound of the threshold, the offset may be fine.
+        estimate.add(-5_500_000, Duration::from_secs(2));
+        assert!(!estimate.warn(max));
+
+        let mut estimate = ClockEstimate::new();
+        estimate.add(-6_000_000, Duration::from_millis(2));
+        assert!(estimate.warn(max));
+        assert!(!estimate.warn(max));
+        for _ in 0..RECENT_SAMPLES {
+            estimate.add(0, Duration::from_millis(2));
+        }
+        assert!(!estimate.warn(max));
+        estimate.add(-60_000_000, Duration::from_millis(2));
+        assert!(estimate.warn(max));
+    }
+
+    #[test]
+    fn formats_the_offset_with_its_sign_and_bound() {
+        assert_eq!(format_offset(1_250), "+1.250 ms");
+        assert_eq!(format_offset(-300), "-0.300 ms");
+        assert_eq!(format_offset(0), "+0.000 ms");
+        let offset = ClockOffset {
+            offset_us: -2_500,
+            bound: Duration::from_micros(750),
+            samples: 3,
+        };
+        assert_eq!(offset.to_string(), "-2.500 ms ± 0.750 ms");
+        assert_eq!(offset.magnitude(), Duration::from_micros(2_500));
+    }
+}
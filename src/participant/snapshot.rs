@@ -0,0 +1,581 @@
+//! Membership Snapshots.
+//!
+//! A membership snapshot is a file written by a running participant with the `export` console
+//! command and read by a new participant started with `--import-membership`. It lets a node
+//! bootstrap from a file carried into an air-gapped or firewalled environment instead of a
+//! live `--connect` target.
+//!
+//! A snapshot is a JSON document naming its format and version, the time of the export, the
+//! exporting participant and the participants it knew, each with the node id it presented
+//! and how long ago the exporter last heard from it:
+//!
+//! ```json
+//! {
+//!   "format": "gossip-p2p membership",
+//!   "version": 1,
+//!   "exported_at": 1760400000,
+//!   "exporter": { "addr": "127.0.0.1:8080", "node_id": "5f0c…", "label": "gateway" },
+//!   "peers": [
+//!     { "addr": "127.0.0.1:8080", "node_id": "5f0c…", "age": "fresh", "last_verified_secs": 0 },
+//!     { "addr": "127.0.0.1:8081", "node_id": null, "age": "recent", "last_verified_secs": 12 }
+//!   ],
+//!   "signature": { "key": "<32 bytes in hex>", "value": "<64 bytes in hex>" }
+//! }
+//! ```
+//!
+//! The protocol carries no name of the other participants, so only the exporter is named, by
+//! its `--node-label`.
+//!
+//! An exporter with a signing key, which every participant with an identity file has, signs
+//! the snapshot with it: the Ed25519 signature covers the whole document but the signature
+//! itself, the export time included, so neither the peers nor the age can be changed without
+//! breaking it. The key a snapshot names is only trusted when the importer knows it already:
+//! its own key, for a snapshot it exported itself, or the one of `--trusted-snapshot-key`.
+//! Anybody can sign a snapshot with a key of their own, so a snapshot signed by another key is
+//! rejected as surely as one whose signature does not verify; an unsigned one is rejected
+//! with `--require-signed-import`. The participants of a snapshot signed by a trusted key are
+//! dialed without corroboration, those of an unsigned one only at startup.
+//!
+//! A snapshot is read as a whole: a field that fails to parse rejects the file, since a
+//! truncated snapshot could silently seed a participant with part of the network.
+
+use crate::participant::identity::NodeId;
+use crate::participant::message::AgeBucket;
+use crate::participant::state::{hex, unhex};
+use crate::participant::utils::same_addr;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the snapshot format written by this build.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The format named by every snapshot.
+const SNAPSHOT_FORMAT: &str = "gossip-p2p membership";
+
+/// Enumerates the reasons a snapshot can not be used.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The file can not be read or written.
+    Io(io::Error),
+    /// The file is not a snapshot of a supported format and version; holds what it names.
+    UnsupportedVersion(String),
+    /// The file is not a well-formed snapshot; holds the reason.
+    Malformed(String),
+    /// The snapshot is older than the accepted age; holds its age.
+    Stale(Duration),
+    /// The snapshot is not signed, and `--require-signed-import` requires it to be.
+    Unsigned,
+    /// The signature of the snapshot does not verify: the snapshot was changed after it was
+    /// signed, or the signature is broken.
+    BadSignature,
+    /// The snapshot is signed by a key the importer does not trust; holds the key, in
+    /// hexadecimal.
+    UntrustedKey(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{}", err),
+            SnapshotError::UnsupportedVersion(format) => {
+                write!(f, "unsupported snapshot format {}", format)
+            }
+            SnapshotError::Malformed(reason) => write!(f, "malformed snapshot: {}", reason),
+            SnapshotError::Stale(age) => {
+                write!(f, "the snapshot is {}s old", age.as_secs())
+            }
+            SnapshotError::Unsigned => {
+                write!(f, "the snapshot is not signed, and a signature is required")
+            }
+            SnapshotError::BadSignature => write!(f, "the signature of the snapshot is invalid"),
+            SnapshotError::UntrustedKey(key) => write!(
+                f,
+                "the snapshot is signed by key {}, which is not trusted; trust it with --trusted-snapshot-key",
+                key
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// The participant that exported a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exporter {
+    /// The public address of the exporter.
+    pub addr: SocketAddr,
+    /// The node id of the exporter.
+    pub node_id: Option<NodeId>,
+    /// The `--node-label` of the exporter, if any.
+    pub label: Option<String>,
+}
+
+/// A participant listed in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotPeer {
+    /// The public address of the participant.
+    pub addr: SocketAddr,
+    /// The node id the participant presented, if any.
+    pub node_id: Option<NodeId>,
+    /// How recently the exporter verified the participant, relative to its period.
+    #[serde(with = "age_name")]
+    pub age: AgeBucket,
+    /// How long before the export the exporter last heard from the participant, in seconds,
+    /// if it ever did.
+    pub last_verified_secs: Option<u64>,
+}
+
+/// The signature of a snapshot by its exporter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    /// The public key of the exporter, in hexadecimal.
+    pub key: String,
+    /// The Ed25519 signature of the rest of the snapshot, in hexadecimal.
+    pub value: String,
+}
+
+/// The participants known to a participant at the time of an export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The format of the document, always `SNAPSHOT_FORMAT`.
+    format: String,
+    /// The version of the format, always `SNAPSHOT_VERSION` once decoded.
+    version: u32,
+    /// The time of the export, in seconds since the Unix epoch.
+    pub exported_at: u64,
+    /// The exporting participant.
+    pub exporter: Exporter,
+    /// The exported participants.
+    pub peers: Vec<SnapshotPeer>,
+    /// The signature of the exporter, if it has a signing key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl Snapshot {
+    /// Creates an unsigned snapshot of `peers` taken by `exporter` at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `exporter` - The exporting participant.
+    /// * `peers` - The exported participants.
+    /// * `now` - The time of the export.
+    pub fn new(exporter: Exporter, peers: Vec<SnapshotPeer>, now: SystemTime) -> Self {
+        Self {
+            format: SNAPSHOT_FORMAT.to_owned(),
+            version: SNAPSHOT_VERSION,
+            exported_at: unix_seconds(now),
+            exporter,
+            peers,
+            signature: None,
+        }
+    }
+
+    /// Signs the snapshot with `key`, replacing any previous signature.
+    pub fn sign(&mut self, key: &SigningKey) {
+        let signature = key.sign(&self.signed_content());
+        self.signature = Some(Signature {
+            key: hex(key.verifying_key().as_bytes()),
+            value: hex(&signature.to_bytes()),
+        });
+    }
+
+    /// Verifies the signature of the snapshot, if it has one, with the trusted key it names.
+    ///
+    /// # Parameters
+    ///
+    /// * `trusted` - The keys whose signatures are accepted.
+    ///
+    /// # Returns
+    ///
+    /// The key of a valid signature, `None` for an unsigned snapshot,
+    /// `SnapshotError::UntrustedKey` for a key not in `trusted`, or
+    /// `SnapshotError::BadSignature` for a signature that does not verify.
+    pub fn verify(&self, trusted: &[VerifyingKey]) -> Result<Option<VerifyingKey>, SnapshotError> {
+        let Some(signature) = &self.signature else {
+            return Ok(None);
+        };
+        let named = unhex(&signature.key)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or(SnapshotError::BadSignature)?;
+        let key = trusted
+            .iter()
+            .find(|key| key.as_bytes() == &named)
+            .ok_or_else(|| SnapshotError::UntrustedKey(signature.key.clone()))?;
+        let value = unhex(&signature.value)
+            .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+            .map(|bytes| ed25519_dalek::Signature::from_bytes(&bytes))
+            .ok_or(SnapshotError::BadSignature)?;
+        key.verify_strict(&self.signed_content(), &value)
+            .map_err(|_| SnapshotError::BadSignature)?;
+        Ok(Some(*key))
+    }
+
+    /// Returns the bytes a signature covers: the snapshot without its signature.
+    fn signed_content(&self) -> Vec<u8> {
+        let unsigned = Self {
+            signature: None,
+            ..self.clone()
+        };
+        serde_json::to_vec(&unsigned).expect("a snapshot serializes")
+    }
+
+    /// Formats the snapshot as the content of a snapshot file.
+    pub fn encode(&self) -> String {
+        let mut content = serde_json::to_string_pretty(self).expect("a snapshot serializes");
+        content.push('\n');
+        content
+    }
+
+    /// Parses the content of a snapshot file.
+    ///
+    /// # Parameters
+    ///
+    /// * `content` - The content of the file, as written by `encode`.
+    pub fn decode(content: &str) -> Result<Self, SnapshotError> {
+        let document: serde_json::Value = serde_json::from_str(content)
+            .map_err(|err| SnapshotError::Malformed(err.to_string()))?;
+        let format = &document["format"];
+        let version = &document["version"];
+        if format.as_str() != Some(SNAPSHOT_FORMAT)
+            || version.as_u64() != Some(u64::from(SNAPSHOT_VERSION))
+        {
+            return Err(SnapshotError::UnsupportedVersion(format!(
+                "{} version {}",
+                format, version
+            )));
+        }
+        serde_json::from_value(document).map_err(|err| SnapshotError::Malformed(err.to_string()))
+    }
+
+    /// Writes the snapshot to `path`, replacing any previous file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the snapshot file.
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        fs::write(path, self.encode()).map_err(SnapshotError::Io)
+    }
+
+    /// Reads the snapshot at `path` and checks it, see `check`.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the snapshot file.
+    /// * `now` - The current time, against which the age of the snapshot is computed.
+    /// * `max_age` - The oldest accepted snapshot.
+    /// * `require_signed` - Whether an unsigned snapshot is rejected.
+    /// * `trusted` - The keys whose signatures are accepted.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot and the key of its signature, `None` for an unsigned snapshot.
+    pub fn load(
+        path: &Path,
+        now: SystemTime,
+        max_age: Duration,
+        require_signed: bool,
+        trusted: &[VerifyingKey],
+    ) -> Result<(Self, Option<VerifyingKey>), SnapshotError> {
+        let content = fs::read_to_string(path).map_err(SnapshotError::Io)?;
+        let snapshot = Self::decode(&content)?;
+        let key = snapshot.check(now, max_age, require_signed, trusted)?;
+        Ok((snapshot, key))
+    }
+
+    /// Checks that the snapshot can be imported: that its signature is by one of `trusted`
+    /// and verifies, that it is signed at all when `require_signed`, and that it is not older
+    /// than `max_age`.
+    ///
+    /// # Returns
+    ///
+    /// The key of the signature, `None` for an unsigned snapshot.
+    pub fn check(
+        &self,
+        now: SystemTime,
+        max_age: Duration,
+        require_signed: bool,
+        trusted: &[VerifyingKey],
+    ) -> Result<Option<VerifyingKey>, SnapshotError> {
+        let key = self.verify(trusted)?;
+        if key.is_none() && require_signed {
+            return Err(SnapshotError::Unsigned);
+        }
+        let age = self.age(now);
+        if age > max_age {
+            return Err(SnapshotError::Stale(age));
+        }
+        Ok(key)
+    }
+
+    /// Returns the time elapsed since the export, zero for a snapshot from the future.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        Duration::from_secs(unix_seconds(now).saturating_sub(self.exported_at))
+    }
+
+    /// Splits the exported participants into the addresses worth dialing and the number of
+    /// stale ones.
+    ///
+    /// The addresses are ordered from the most recently verified; `own` is left out, so a
+    /// participant importing a snapshot it exported itself does not dial itself.
+    ///
+    /// # Parameters
+    ///
+    /// * `own` - The public address of the importing participant.
+    pub fn dial_order(&self, own: SocketAddr) -> (Vec<SocketAddr>, usize) {
+        let mut peers: Vec<&SnapshotPeer> = self
+            .peers
+            .iter()
+            .filter(|peer| !same_addr(peer.addr, own))
+            .collect();
+        peers.sort_by_key(|peer| (peer.age, peer.last_verified_secs.unwrap_or(u64::MAX)));
+
+        let stale = peers
+            .iter()
+            .filter(|peer| peer.age == AgeBucket::Stale)
+            .count();
+        let addrs = peers
+            .into_iter()
+            .filter(|peer| peer.age != AgeBucket::Stale)
+            .map(|peer| peer.addr)
+            .collect();
+        (addrs, stale)
+    }
+}
+
+/// Returns the number of whole seconds between the Unix epoch and `time`.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Writes an age bucket in a snapshot as its lowercase name.
+mod age_name {
+    use crate::participant::message::AgeBucket;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(age: &AgeBucket, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match age {
+            AgeBucket::Fresh => "fresh",
+            AgeBucket::Recent => "recent",
+            AgeBucket::Stale => "stale",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AgeBucket, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "fresh" => Ok(AgeBucket::Fresh),
+            "recent" => Ok(AgeBucket::Recent),
+            "stale" => Ok(AgeBucket::Stale),
+            other => Err(de::Error::custom(format!("unknown age \"{}\"", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn peer(port: u16, age: AgeBucket, last_verified_secs: u64) -> SnapshotPeer {
+        SnapshotPeer {
+            addr: addr(port),
+            node_id: Some(NodeId(u128::from(port))),
+            age,
+            last_verified_secs: Some(last_verified_secs),
+        }
+    }
+
+    fn snapshot(now: SystemTime) -> Snapshot {
+        let exporter = Exporter {
+            addr: addr(8080),
+            node_id: Some(NodeId(8080)),
+            label: Some("gateway".to_owned()),
+        };
+        let peers = vec![
+            peer(8080, AgeBucket::Fresh, 0),
+            peer(8082, AgeBucket::Recent, 12),
+            peer(8081, AgeBucket::Fresh, 3),
+            peer(8083, AgeBucket::Stale, 90),
+        ];
+        Snapshot::new(exporter, peers, now)
+    }
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn trusted() -> [VerifyingKey; 1] {
+        [key().verifying_key()]
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn a_snapshot_round_trips_as_versioned_json() {
+        let mut exported = snapshot(SystemTime::now());
+        exported.sign(&key());
+        let content = exported.encode();
+        let document: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(document["format"], "gossip-p2p membership");
+        assert_eq!(document["version"], 1);
+        assert_eq!(document["peers"][1]["age"], "recent");
+        assert_eq!(
+            document["exporter"]["node_id"],
+            "00000000000000000000000000001f90"
+        );
+        assert_eq!(Snapshot::decode(&content).unwrap(), exported);
+
+        let unsigned = snapshot(SystemTime::now());
+        assert!(!unsigned.encode().contains("signature"));
+        assert_eq!(Snapshot::decode(&unsigned.encode()).unwrap(), unsigned);
+    }
+
+    #[test]
+    fn another_format_or_version_is_unsupported() {
+        let content = snapshot(SystemTime::now()).encode();
+        let newer = content.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            Snapshot::decode(&newer),
+            Err(SnapshotError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            Snapshot::decode("gossip-p2p membership 1\nexported_at 1\n"),
+            Err(SnapshotError::Malformed(_))
+        ));
+        let truncated = &content[..content.len() / 2];
+        assert!(matches!(
+            Snapshot::decode(truncated),
+            Err(SnapshotError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn a_signature_verifies_with_the_key_of_the_exporter() {
+        let now = SystemTime::now();
+        let mut exported = snapshot(now);
+        exported.sign(&key());
+        let imported = Snapshot::decode(&exported.encode()).unwrap();
+        assert_eq!(
+            imported.verify(&trusted()).unwrap(),
+            Some(key().verifying_key())
+        );
+        assert_eq!(
+            imported.check(now, DAY, true, &trusted()).unwrap(),
+            Some(key().verifying_key())
+        );
+
+        let unsigned = snapshot(now);
+        assert_eq!(unsigned.check(now, DAY, false, &trusted()).unwrap(), None);
+        assert!(matches!(
+            unsigned.check(now, DAY, true, &trusted()),
+            Err(SnapshotError::Unsigned)
+        ));
+    }
+
+    #[test]
+    fn a_tampered_snapshot_is_rejected_even_when_no_signature_is_required() {
+        let now = SystemTime::now();
+        let mut exported = snapshot(now);
+        exported.sign(&key());
+        let content = exported.encode();
+
+        let tampered = [
+            content.replace("127.0.0.1:8081", "10.0.0.1:8081"),
+            content.replace("\"stale\"", "\"fresh\""),
+            content.replace(
+                &format!("\"exported_at\": {}", exported.exported_at),
+                &format!("\"exported_at\": {}", exported.exported_at + 3600),
+            ),
+        ];
+        for content in &tampered {
+            let imported = Snapshot::decode(content).unwrap();
+            assert_ne!(&imported, &exported);
+            assert!(matches!(
+                imported.verify(&trusted()),
+                Err(SnapshotError::BadSignature)
+            ));
+            assert!(matches!(
+                imported.check(now, DAY, false, &trusted()),
+                Err(SnapshotError::BadSignature)
+            ));
+        }
+
+        let mut broken = exported.clone();
+        broken.signature.as_mut().unwrap().value = "zz".to_owned();
+        assert!(matches!(
+            broken.verify(&trusted()),
+            Err(SnapshotError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn a_snapshot_signed_by_an_untrusted_key_is_rejected() {
+        let now = SystemTime::now();
+        let foreign = SigningKey::from_bytes(&[8; 32]);
+        let mut exported = snapshot(now);
+        exported.sign(&key());
+        let content = exported.encode();
+
+        // A changed snapshot signed again by whoever changed it verifies with the key it names.
+        let mut resigned =
+            Snapshot::decode(&content.replace("127.0.0.1:8081", "10.0.0.1:8081")).unwrap();
+        resigned.sign(&foreign);
+        let imported = Snapshot::decode(&resigned.encode()).unwrap();
+        let named = hex(foreign.verifying_key().as_bytes());
+        for require_signed in [false, true] {
+            assert!(matches!(
+                imported.check(now, DAY, require_signed, &trusted()),
+                Err(SnapshotError::UntrustedKey(key)) if key == named
+            ));
+        }
+        assert!(matches!(
+            imported.check(now, DAY, false, &[]),
+            Err(SnapshotError::UntrustedKey(_))
+        ));
+
+        // It is accepted once its key is trusted too.
+        let both = [key().verifying_key(), foreign.verifying_key()];
+        assert_eq!(
+            imported.check(now, DAY, true, &both).unwrap(),
+            Some(foreign.verifying_key())
+        );
+    }
+
+    #[test]
+    fn a_snapshot_older_than_the_accepted_age_is_stale() {
+        let now = SystemTime::now();
+        let mut exported = snapshot(now - DAY - Duration::from_secs(60));
+        exported.sign(&key());
+        assert!(matches!(
+            exported.check(now, DAY, false, &trusted()),
+            Err(SnapshotError::Stale(age)) if age.as_secs() == DAY.as_secs() + 60
+        ));
+        assert!(exported.check(now, 2 * DAY, true, &trusted()).is_ok());
+        assert_eq!(snapshot(now + DAY).age(now), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_dial_order_is_by_freshness_without_the_stale_peers_and_self() {
+        let exported = snapshot(SystemTime::now());
+        let (addrs, stale) = exported.dial_order(addr(8084));
+        assert_eq!(addrs, vec![addr(8080), addr(8081), addr(8082)]);
+        assert_eq!(stale, 1);
+
+        let (addrs, _) = exported.dial_order(addr(8080));
+        assert_eq!(addrs, vec![addr(8081), addr(8082)]);
+    }
+}
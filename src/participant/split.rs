@@ -0,0 +1,309 @@
+//! Split-Brain Detection.
+//!
+//! A participant can end up the only link between two groups of peers that do not know each
+//! other, for instance when it joined one group while a member of another dialed it, and its
+//! budgeted participants lists never carried the cross links. `PeerLists` keeps the last
+//! participants list received from each connected peer, and at each maintenance tick
+//! `components` groups the peers by the addresses their lists mention, this participant left
+//! out: two peers whose lists mention each other, or a common third address, belong to the
+//! same component.
+//!
+//! Two components or more make a possible split-brain. The participant then pushes to the
+//! members of each component a participants list naming a few `SPLIT_REPRESENTATIVES` of every
+//! other component, outside of the share budget, so the receivers dial across the split. The
+//! same split is bridged at most once per `SPLIT_BRIDGE_INTERVAL`; only the peers whose list is
+//! known are grouped, so a peer that just connected is not taken for a component of its own.
+//!
+//! The lists are held to the `lists` retention policy; a peer whose list was forgotten is asked
+//! for it again the next time it asks for this participant's list, like a peer that just
+//! connected.
+
+use crate::participant::retention::{estimate, RetainedEntry, RetainedKind, Retention};
+use crate::participant::utils::CanonicalAddr;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The number of members of a component named to the other components.
+pub const SPLIT_REPRESENTATIVES: usize = 3;
+
+/// How long the same split is not bridged again.
+pub const SPLIT_BRIDGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of entries kept of a received participants list.
+pub const MAX_LISTED: usize = 256;
+
+/// The last participants list received from each peer, keyed by its public address, with the
+/// time it was received.
+#[derive(Debug)]
+pub struct PeerLists {
+    lists: HashMap<CanonicalAddr, (Vec<SocketAddr>, Instant)>,
+    capacity: usize,
+}
+
+impl PeerLists {
+    /// Constructs an empty `PeerLists` keeping the lists of up to `capacity` peers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lists: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Keeps `list`, received at `now`, as the last participants list of the peer at `peer`,
+    /// truncated to `MAX_LISTED` entries. The list of a new peer is dropped once `capacity`
+    /// lists are kept.
+    pub fn record(&mut self, peer: SocketAddr, mut list: Vec<SocketAddr>, now: Instant) {
+        let peer = CanonicalAddr::from(peer);
+        if !self.lists.contains_key(&peer) && self.lists.len() >= self.capacity {
+            return;
+        }
+        list.truncate(MAX_LISTED);
+        self.lists.insert(peer, (list, now));
+    }
+
+    /// Holds the lists to their retention policy, each aging from the time it was received.
+    pub fn sweep(&mut self, now: Instant, retention: &mut Retention) {
+        let lists = self
+            .lists
+            .iter()
+            .map(|(peer, (list, received_at))| RetainedEntry {
+                key: *peer,
+                age: Some(now.saturating_duration_since(*received_at)),
+                bytes: estimate::<(Vec<SocketAddr>, Instant), SocketAddr>(list.len()),
+            })
+            .collect();
+        for peer in retention.enforce(RetainedKind::Lists, lists) {
+            self.lists.remove(&peer);
+        }
+    }
+
+    /// Forgets the lists of the peers that are no longer connected.
+    pub fn retain(&mut self, connected: impl Fn(SocketAddr) -> bool) {
+        self.lists.retain(|peer, _| connected(peer.original()));
+    }
+
+    /// Determines whether the list of the peer at `peer` is kept.
+    pub fn contains(&self, peer: SocketAddr) -> bool {
+        self.lists.contains_key(&peer.into())
+    }
+
+    /// Returns the number of lists kept.
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    /// Groups the peers whose list is known into components, see `components`.
+    pub fn components(&self, own: impl Fn(SocketAddr) -> bool) -> Vec<Vec<SocketAddr>> {
+        let lists: Vec<(SocketAddr, &[SocketAddr])> = self
+            .lists
+            .iter()
+            .map(|(peer, (list, _))| (peer.original(), list.as_slice()))
+            .collect();
+        components(&lists, own)
+    }
+}
+
+/// Groups peers into the components their participants lists connect.
+///
+/// # Parameters
+///
+/// * `lists` - Each peer with the addresses its participants list mentions.
+/// * `own` - Whether an address is one of this participant, which connects nothing.
+///
+/// # Returns
+///
+/// The components, each sorted, largest first and then by their first member.
+pub fn components(
+    lists: &[(SocketAddr, &[SocketAddr])],
+    own: impl Fn(SocketAddr) -> bool,
+) -> Vec<Vec<SocketAddr>> {
+    let mut sets = DisjointSets::default();
+    for &(peer, list) in lists {
+        let root = sets.index(peer);
+        for &addr in list.iter().filter(|addr| !own(**addr)) {
+            let other = sets.index(addr);
+            sets.union(root, other);
+        }
+    }
+
+    let mut grouped: HashMap<usize, Vec<SocketAddr>> = HashMap::new();
+    for &(peer, _) in lists {
+        let index = sets.index(peer);
+        let root = sets.find(index);
+        grouped.entry(root).or_default().push(peer);
+    }
+    let mut components: Vec<Vec<SocketAddr>> = grouped.into_values().collect();
+    for component in &mut components {
+        component.sort();
+        component.dedup();
+    }
+    components.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    components
+}
+
+/// A union-find over addresses, compared in their canonical form.
+#[derive(Debug, Default)]
+struct DisjointSets {
+    indices: HashMap<CanonicalAddr, usize>,
+    parents: Vec<usize>,
+}
+
+impl DisjointSets {
+    /// Returns the index of `addr`, adding it as a set of its own if it is new.
+    fn index(&mut self, addr: SocketAddr) -> usize {
+        let next = self.parents.len();
+        let index = *self.indices.entry(addr.into()).or_insert(next);
+        if index == next {
+            self.parents.push(next);
+        }
+        index
+    }
+
+    /// Returns the representative of the set of `index`, compressing the path to it.
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut current = index;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    /// Merges the sets of `a` and `b`.
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b] = a;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::retention::RetentionPolicy;
+    use crate::participant::test_support::addr;
+
+    const OWN: u16 = 7000;
+
+    fn own(candidate: SocketAddr) -> bool {
+        candidate == addr(OWN)
+    }
+
+    fn ports(ports: &[u16]) -> Vec<SocketAddr> {
+        ports.iter().copied().map(addr).collect()
+    }
+
+    /// Groups the peers of `lists`, each a port with the ports its list mentions.
+    fn grouped(lists: &[(u16, &[u16])]) -> Vec<Vec<SocketAddr>> {
+        let lists: Vec<(SocketAddr, Vec<SocketAddr>)> = lists
+            .iter()
+            .map(|(peer, list)| (addr(*peer), ports(list)))
+            .collect();
+        let borrowed: Vec<(SocketAddr, &[SocketAddr])> = lists
+            .iter()
+            .map(|(peer, list)| (*peer, list.as_slice()))
+            .collect();
+        components(&borrowed, own)
+    }
+
+    #[test]
+    fn peers_listing_each_other_form_one_component() {
+        let components = grouped(&[
+            (8080, &[OWN, 8081, 8082]),
+            (8081, &[OWN, 8080]),
+            (8082, &[8080]),
+        ]);
+        assert_eq!(components, vec![ports(&[8080, 8081, 8082])]);
+        assert!(grouped(&[]).is_empty());
+    }
+
+    #[test]
+    fn disjoint_lists_form_a_component_each() {
+        let components = grouped(&[
+            (8080, &[OWN, 8081]),
+            (8081, &[OWN, 8080]),
+            (8082, &[OWN, 9000]),
+            (9000, &[OWN, 8082]),
+            (9100, &[OWN]),
+            (9200, &[OWN, 8081, 8080]),
+        ]);
+        assert_eq!(
+            components,
+            vec![
+                ports(&[8080, 8081, 9200]),
+                ports(&[8082, 9000]),
+                ports(&[9100])
+            ]
+        );
+    }
+
+    #[test]
+    fn a_common_third_address_joins_two_groups() {
+        // 8080 and 8090 never list each other, but both know 9999, which is not connected.
+        let components = grouped(&[(8080, &[OWN, 9999]), (8090, &[OWN, 9999]), (8100, &[OWN])]);
+        assert_eq!(components, vec![ports(&[8080, 8090]), ports(&[8100])]);
+    }
+
+    #[test]
+    fn this_participant_connects_nothing() {
+        let components = grouped(&[(8080, &[OWN]), (8081, &[OWN])]);
+        assert_eq!(components, vec![ports(&[8080]), ports(&[8081])]);
+    }
+
+    #[test]
+    fn mapped_addresses_are_the_same_peer() {
+        let mapped: SocketAddr = "[::ffff:127.0.0.1]:8081".parse().unwrap();
+        let (first, second) = (ports(&[OWN]), vec![mapped]);
+        let components = components(&[(addr(8080), &second), (addr(8081), &first)], own);
+        assert_eq!(components, vec![ports(&[8080, 8081])]);
+    }
+
+    #[test]
+    fn lists_are_kept_up_to_the_capacity() {
+        let now = Instant::now();
+        let mut lists = PeerLists::new(2);
+        lists.record(addr(8080), ports(&[8081]), now);
+        lists.record(addr(8081), ports(&[8080]), now);
+        lists.record(addr(8082), ports(&[8080]), now);
+        assert_eq!(lists.len(), 2);
+        assert!(!lists.contains(addr(8082)));
+        // A kept peer still replaces its list.
+        lists.record(addr(8081), ports(&[9000]), now);
+        assert_eq!(lists.lists[&addr(8081).into()].0, ports(&[9000]));
+        assert_eq!(lists.components(own), vec![ports(&[8080, 8081])]);
+
+        let long: Vec<SocketAddr> = (1..=MAX_LISTED as u16 + 10).map(addr).collect();
+        lists.record(addr(8080), long, now);
+        assert_eq!(lists.lists[&addr(8080).into()].0.len(), MAX_LISTED);
+
+        lists.retain(|peer| peer == addr(8081));
+        assert!(!lists.contains(addr(8080)));
+        assert!(lists.contains(addr(8081)));
+    }
+
+    #[test]
+    fn old_lists_are_swept_by_the_retention_policy() {
+        let start = Instant::now();
+        let policy = RetentionPolicy {
+            max_entries: 100,
+            max_age: Duration::from_secs(60),
+            max_total_bytes: None,
+        };
+        let mut retention = Retention::new([policy; RetainedKind::COUNT]);
+        let mut lists = PeerLists::new(10);
+        lists.record(addr(8080), ports(&[8081]), start);
+        lists.record(addr(8081), ports(&[8080]), start + Duration::from_secs(30));
+
+        lists.sweep(start + Duration::from_secs(61), &mut retention);
+        assert!(!lists.contains(addr(8080)));
+        assert!(lists.contains(addr(8081)));
+    }
+}
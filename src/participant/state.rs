@@ -0,0 +1,576 @@
+//! Node State Files.
+//!
+//! The recent peers cache only remembers addresses. With `--state-file`, a participant also
+//! saves what it knows about its peers and its own numbering when it shuts down gracefully,
+//! and every `--state-save-interval` when given, so a planned restart picks up where the
+//! previous run stopped: the peers are dialed from the most recently verified, their session
+//! counts carry on once they reconnect, and the publications on each topic continue their
+//! sequence instead of starting over. The publications scheduled and not sent yet are saved
+//! too, and scheduled again.
+//!
+//! Like a membership snapshot, a state file is a plain text file whose first line names the
+//! format and its version:
+//!
+//! ```plaintext
+//! gossip-p2p state 2
+//! saved_at 1760400000
+//! node 127.0.0.1:8080
+//! topic 42 news
+//! peer 127.0.0.1:8081 verified=1760399990 sessions=3 node=5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e
+//! schedule 7 due=1760403600000 missed=send topic=6e657773 payload=6f6e
+//! ```
+//!
+//! The fields of a peer are `key=value` pairs, so a later version can add some: a missing
+//! field takes its default and an unknown one is skipped. A scheduled publication holds either
+//! a `text` or a `topic` with its `payload`, all three hex-encoded. Version 2 added them, and
+//! version 1 files are still read. The `node` of a peer lets its state
+//! follow it when it comes back from another address, and its `learned` fields keep the
+//! `Provenance` of the address: the source name, `learned_at`, the `learned_from` peer and
+//! `learned_request` id of a list, and the comma-separated `corroborated_by`. A file that
+//! does not parse is not fatal, the participant starts without it.
+
+use crate::participant::frame::OutgoingMessage;
+use crate::participant::identity::NodeId;
+use crate::participant::message::Message;
+use crate::participant::provenance::{LearnedVia, Provenance, MAX_CORROBORATORS};
+use crate::participant::schedule::{Missed, SavedPublication};
+use crate::participant::snapshot::unix_seconds;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The version of the state file format written by this build.
+pub const STATE_VERSION: u32 = 2;
+
+/// The first words of a state file, followed by the format version.
+const STATE_HEADER: &str = "gossip-p2p state";
+
+/// Enumerates the reasons a state file can not be used.
+#[derive(Debug)]
+pub enum StateError {
+    /// The file can not be read or written.
+    Io(io::Error),
+    /// The file is not a state file of a supported version.
+    UnsupportedVersion(String),
+    /// A line of the file can not be parsed; holds the line number, starting at 1.
+    Malformed(usize),
+    /// The file was saved longer ago than the accepted age; holds its age.
+    Stale(Duration),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Io(err) => write!(f, "{}", err),
+            StateError::UnsupportedVersion(header) => {
+                write!(f, "unsupported state header \"{}\"", header)
+            }
+            StateError::Malformed(line) => write!(f, "malformed state line {}", line),
+            StateError::Stale(age) => write!(f, "the state is {}s old", age.as_secs()),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// What a participant remembers about one peer across restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RememberedPeer {
+    /// The public address of the peer.
+    pub addr: SocketAddr,
+    /// The last time the peer was heard from, in seconds since the Unix epoch.
+    pub verified_at: Option<u64>,
+    /// The number of sessions of the peer seen so far.
+    pub sessions: u64,
+    /// The persistent id the peer presented, if any.
+    pub node_id: Option<NodeId>,
+    /// Where the address was first learned, if known.
+    pub provenance: Option<Provenance>,
+}
+
+impl RememberedPeer {
+    /// Returns a peer known only by its address, as a field-less line of an older file reads.
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            verified_at: None,
+            sessions: 0,
+            node_id: None,
+            provenance: None,
+        }
+    }
+}
+
+/// The state saved by a participant for its next start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeState {
+    /// The time the state was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+    /// The public address of the participant.
+    pub node: SocketAddr,
+    /// The last sequence number published on each topic.
+    pub topic_sequences: Vec<(String, u64)>,
+    /// The peers of the participant.
+    pub peers: Vec<RememberedPeer>,
+    /// The publications scheduled and not sent yet.
+    pub scheduled: Vec<SavedPublication>,
+}
+
+impl NodeState {
+    /// Formats the state as the content of a state file.
+    pub fn encode(&self) -> String {
+        let mut content = format!(
+            "{} {}\nsaved_at {}\nnode {}\n",
+            STATE_HEADER, STATE_VERSION, self.saved_at, self.node
+        );
+        for (topic, seq) in &self.topic_sequences {
+            content.push_str(&format!("topic {} {}\n", seq, topic));
+        }
+        for peer in &self.peers {
+            content.push_str(&format!("peer {}", peer.addr));
+            if let Some(verified_at) = peer.verified_at {
+                content.push_str(&format!(" verified={}", verified_at));
+            }
+            content.push_str(&format!(" sessions={}", peer.sessions));
+            if let Some(node_id) = peer.node_id {
+                content.push_str(&format!(" node={}", node_id));
+            }
+            if let Some(provenance) = &peer.provenance {
+                content.push_str(&format!(
+                    " learned={} learned_at={}",
+                    provenance.source.name(),
+                    provenance.learned_at
+                ));
+                if let Some(from) = provenance.source.peer() {
+                    content.push_str(&format!(" learned_from={}", from));
+                }
+                if let LearnedVia::List {
+                    request: Some(request),
+                    ..
+                } = provenance.source
+                {
+                    content.push_str(&format!(" learned_request={}", request));
+                }
+                if !provenance.corroborators.is_empty() {
+                    let corroborators: Vec<String> = provenance
+                        .corroborators
+                        .iter()
+                        .map(|addr| addr.to_string())
+                        .collect();
+                    content.push_str(&format!(" corroborated_by={}", corroborators.join(",")));
+                }
+            }
+            content.push('\n');
+        }
+        for saved in &self.scheduled {
+            content.push_str(&format!(
+                "schedule {} due={} missed={}",
+                saved.id,
+                saved.due_unix_ms,
+                saved.missed.name()
+            ));
+            match saved.message.message() {
+                Message::SequencedTopic { topic, payload, .. } => content.push_str(&format!(
+                    " topic={} payload={}",
+                    hex(topic.as_bytes()),
+                    hex(payload)
+                )),
+                Message::Text(text) => content.push_str(&format!(" text={}", hex(text.as_bytes()))),
+                _ => {}
+            }
+            content.push('\n');
+        }
+        content
+    }
+
+    /// Parses the content of a state file.
+    ///
+    /// # Parameters
+    ///
+    /// * `content` - The content of the file, as written by `encode`.
+    pub fn decode(content: &str) -> Result<Self, StateError> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+
+        let header = lines.next().map_or("", |(_, line)| line);
+        let supported = header
+            .strip_prefix(STATE_HEADER)
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .is_some_and(|version| (1..=STATE_VERSION).contains(&version));
+        if !supported {
+            return Err(StateError::UnsupportedVersion(header.to_owned()));
+        }
+
+        let mut saved_at = None;
+        let mut node = None;
+        let mut topic_sequences = Vec::new();
+        let mut peers = Vec::new();
+        let mut scheduled = Vec::new();
+        for (number, line) in lines {
+            let (key, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let parsed = match key {
+                "saved_at" => rest.parse().ok().map(|secs| saved_at = Some(secs)),
+                "node" => rest.parse().ok().map(|addr| node = Some(addr)),
+                "topic" => rest.split_once(' ').and_then(|(seq, topic)| {
+                    let seq = seq.parse().ok()?;
+                    topic_sequences.push((topic.to_owned(), seq));
+                    Some(())
+                }),
+                "peer" => parse_peer(rest).map(|peer| peers.push(peer)),
+                "schedule" => parse_schedule(rest).map(|saved| scheduled.push(saved)),
+                "" => Some(()),
+                _ => None,
+            };
+            parsed.ok_or(StateError::Malformed(number))?;
+        }
+
+        let last_line = content.lines().count();
+        Ok(Self {
+            saved_at: saved_at.ok_or(StateError::Malformed(last_line))?,
+            node: node.ok_or(StateError::Malformed(last_line))?,
+            topic_sequences,
+            peers,
+            scheduled,
+        })
+    }
+
+    /// Writes the state to `path` through a temporary file, so a crash while saving leaves
+    /// the previous state in place.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the state file.
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.encode()).map_err(StateError::Io)?;
+        fs::rename(&temporary, path).map_err(StateError::Io)
+    }
+
+    /// Reads the state at `path`, rejecting it if it was saved longer than `max_age` ago.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the state file.
+    /// * `now` - The current time, against which the age of the state is computed.
+    /// * `max_age` - The oldest accepted state.
+    ///
+    /// # Returns
+    ///
+    /// The state, `None` when there is no file yet, or the `StateError` explaining why the
+    /// file can not be used.
+    pub fn load(
+        path: &Path,
+        now: SystemTime,
+        max_age: Duration,
+    ) -> Result<Option<Self>, StateError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(StateError::Io(err)),
+        };
+        let state = Self::decode(&content)?;
+
+        let age = Duration::from_secs(unix_seconds(now).saturating_sub(state.saved_at));
+        if age > max_age {
+            return Err(StateError::Stale(age));
+        }
+        Ok(Some(state))
+    }
+
+    /// Splits the remembered peers into those worth dialing and the number of stale ones.
+    ///
+    /// A peer is stale when it was last heard from longer than `max_age` before `now`, or
+    /// never. The others are ordered from the most recently verified.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `max_age` - The longest accepted time since a peer was heard from.
+    pub fn fresh_peers(&self, now: SystemTime, max_age: Duration) -> (Vec<RememberedPeer>, usize) {
+        let now = unix_seconds(now);
+        let (mut fresh, stale): (Vec<RememberedPeer>, Vec<RememberedPeer>) =
+            self.peers.iter().cloned().partition(|peer| {
+                peer.verified_at
+                    .is_some_and(|at| now.saturating_sub(at) <= max_age.as_secs())
+            });
+        fresh.sort_by_key(|peer| std::cmp::Reverse(peer.verified_at));
+        (fresh, stale.len())
+    }
+}
+
+/// Parses the address and the `key=value` fields of a peer line.
+///
+/// A `learned` source that this build does not know leaves the peer without provenance.
+fn parse_peer(line: &str) -> Option<RememberedPeer> {
+    let mut fields = line.split_whitespace();
+    let mut peer = RememberedPeer::new(fields.next()?.parse().ok()?);
+    let (mut learned, mut learned_at, mut from, mut request) = (None, None, None, None);
+    let mut corroborators = Vec::new();
+    for field in fields {
+        match field.split_once('=')? {
+            ("verified", secs) => peer.verified_at = Some(secs.parse().ok()?),
+            ("sessions", count) => peer.sessions = count.parse().ok()?,
+            ("node", id) => peer.node_id = Some(id.parse().ok()?),
+            ("learned", name) => learned = Some(name),
+            ("learned_at", secs) => learned_at = Some(secs.parse().ok()?),
+            ("learned_from", addr) => from = Some(addr.parse().ok()?),
+            ("learned_request", id) => request = Some(id.parse().ok()?),
+            ("corroborated_by", addrs) => {
+                corroborators = addrs
+                    .split(',')
+                    .map(|addr| addr.parse().ok())
+                    .collect::<Option<Vec<SocketAddr>>>()?;
+                corroborators.truncate(MAX_CORROBORATORS);
+            }
+            _ => {}
+        }
+    }
+    peer.provenance = learned
+        .and_then(|name| LearnedVia::parse(name, from, request))
+        .map(|source| Provenance {
+            source,
+            learned_at: learned_at.unwrap_or(0),
+            uptime: None,
+            corroborators,
+        });
+    Some(peer)
+}
+
+/// Parses the id and the `key=value` fields of a scheduled publication.
+fn parse_schedule(line: &str) -> Option<SavedPublication> {
+    let mut fields = line.split_whitespace();
+    let id = fields.next()?.parse().ok()?;
+    let (mut due, mut missed, mut text, mut topic, mut payload) = (None, None, None, None, None);
+    for field in fields {
+        match field.split_once('=')? {
+            ("due", millis) => due = Some(millis.parse().ok()?),
+            ("missed", policy) => missed = Some(Missed::parse(policy)?),
+            ("text", bytes) => text = Some(String::from_utf8(unhex(bytes)?).ok()?),
+            ("topic", bytes) => topic = Some(String::from_utf8(unhex(bytes)?).ok()?),
+            ("payload", bytes) => payload = Some(unhex(bytes)?),
+            _ => {}
+        }
+    }
+    let message = match (text, topic) {
+        (Some(text), None) => OutgoingMessage::text(text),
+        (None, Some(topic)) => OutgoingMessage::topic(topic, payload.unwrap_or_default()),
+        _ => return None,
+    };
+    Some(SavedPublication {
+        id,
+        due_unix_ms: due?,
+        missed: missed.unwrap_or(Missed::Send),
+        message,
+    })
+}
+
+/// Encodes `bytes` as lowercase hexadecimal.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hexadecimal written by `hex`.
+pub fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::{addr, TempDir};
+
+    use std::time::UNIX_EPOCH;
+
+    /// A state with a peer of each shape and a publication of each kind.
+    fn populated() -> NodeState {
+        NodeState {
+            saved_at: 1_760_400_000,
+            node: addr(8080),
+            topic_sequences: vec![("news".to_owned(), 42), ("two words".to_owned(), 1)],
+            peers: vec![
+                RememberedPeer::new(addr(8081)),
+                RememberedPeer {
+                    addr: addr(8082),
+                    verified_at: Some(1_760_399_990),
+                    sessions: 3,
+                    node_id: Some(NodeId(0x5f0c3a9e1d2b4c6f8a7e9d0b1c2a3f4e)),
+                    provenance: Some(Provenance {
+                        source: LearnedVia::List {
+                            lister: addr(8081),
+                            request: Some(7),
+                        },
+                        learned_at: 1_760_399_000,
+                        uptime: None,
+                        corroborators: vec![addr(8083), addr(8084)],
+                    }),
+                },
+                RememberedPeer {
+                    provenance: Some(Provenance {
+                        source: LearnedVia::Connect,
+                        learned_at: 1_760_398_000,
+                        uptime: None,
+                        corroborators: Vec::new(),
+                    }),
+                    ..RememberedPeer::new(addr(8085))
+                },
+            ],
+            scheduled: vec![
+                SavedPublication {
+                    id: 7,
+                    due_unix_ms: 1_760_403_600_000,
+                    missed: Missed::Send,
+                    message: OutgoingMessage::topic("news", b"on".to_vec()),
+                },
+                SavedPublication {
+                    id: 8,
+                    due_unix_ms: 1_760_403_700_000,
+                    missed: Missed::Drop,
+                    message: OutgoingMessage::text("hello world"),
+                },
+            ],
+        }
+    }
+
+    fn at(unix_secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(unix_secs)
+    }
+
+    #[test]
+    fn a_state_reads_back_as_it_was_written() {
+        let state = populated();
+        assert_eq!(NodeState::decode(&state.encode()).unwrap(), state);
+    }
+
+    #[test]
+    fn a_saved_state_loads_back_while_fresh() {
+        let dir = TempDir::new("state-save");
+        let path = dir.0.join("node.state");
+        assert!(NodeState::load(&path, at(1_760_400_000), Duration::MAX)
+            .unwrap()
+            .is_none());
+
+        let state = populated();
+        state.save(&path).unwrap();
+        assert!(!dir.0.join("node.state.tmp").exists());
+        let loaded = NodeState::load(&path, at(1_760_400_060), Duration::from_secs(60)).unwrap();
+        assert_eq!(loaded, Some(state));
+
+        let stale = NodeState::load(&path, at(1_760_400_061), Duration::from_secs(60));
+        assert!(matches!(stale, Err(StateError::Stale(age)) if age.as_secs() == 61));
+    }
+
+    #[test]
+    fn a_corrupt_file_is_refused_with_its_line() {
+        let dir = TempDir::new("state-corrupt");
+        let path = dir.0.join("node.state");
+        fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+        assert!(matches!(
+            NodeState::load(&path, at(0), Duration::MAX),
+            Err(StateError::Io(_))
+        ));
+
+        let valid = populated().encode();
+        let truncated = &valid[..valid.find("peer 127.0.0.1:8082").unwrap() + 10];
+        let error = NodeState::decode(truncated).unwrap_err();
+        assert!(matches!(error, StateError::Malformed(7)), "{}", error);
+
+        let miscounted = valid.replace("sessions=3", "sessions=three");
+        assert!(matches!(
+            NodeState::decode(&miscounted),
+            Err(StateError::Malformed(7))
+        ));
+        let unknown = format!("{}neighbor 127.0.0.1:1\n", valid);
+        assert!(matches!(
+            NodeState::decode(&unknown),
+            Err(StateError::Malformed(11))
+        ));
+        let bad_hex = valid.replace("payload=6f6e", "payload=6f6");
+        assert!(matches!(
+            NodeState::decode(&bad_hex),
+            Err(StateError::Malformed(9))
+        ));
+    }
+
+    #[test]
+    fn a_file_of_another_format_or_without_its_node_is_refused() {
+        assert_eq!(
+            NodeState::decode("gossip-p2p snapshot 1\n")
+                .unwrap_err()
+                .to_string(),
+            "unsupported state header \"gossip-p2p snapshot 1\""
+        );
+        assert!(matches!(
+            NodeState::decode("gossip-p2p state 3\nsaved_at 1\nnode 127.0.0.1:1\n"),
+            Err(StateError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            NodeState::decode(""),
+            Err(StateError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            NodeState::decode("gossip-p2p state 2\nsaved_at 1\n"),
+            Err(StateError::Malformed(2))
+        ));
+    }
+
+    #[test]
+    fn a_version_1_file_reads_with_unknown_fields_skipped() {
+        let state = NodeState::decode(
+            "gossip-p2p state 1\nsaved_at 5\nnode 127.0.0.1:8080\n\
+             peer 127.0.0.1:8081\npeer 127.0.0.1:8082 sessions=2 rtt=15 learned=gossip\n",
+        )
+        .unwrap();
+        assert_eq!(state.saved_at, 5);
+        assert_eq!(
+            state.peers,
+            [
+                RememberedPeer::new(addr(8081)),
+                RememberedPeer {
+                    sessions: 2,
+                    ..RememberedPeer::new(addr(8082))
+                },
+            ]
+        );
+        assert!(state.scheduled.is_empty());
+    }
+
+    #[test]
+    fn only_recently_verified_peers_are_dialed_most_recent_first() {
+        let mut state = populated();
+        state.peers.push(RememberedPeer {
+            verified_at: Some(1_760_399_999),
+            ..RememberedPeer::new(addr(8086))
+        });
+        state.peers.push(RememberedPeer {
+            verified_at: Some(1_760_300_000),
+            ..RememberedPeer::new(addr(8087))
+        });
+
+        let (fresh, stale) = state.fresh_peers(at(1_760_400_000), Duration::from_secs(3600));
+        let fresh: Vec<_> = fresh.into_iter().map(|peer| peer.addr).collect();
+        assert_eq!(fresh, [addr(8086), addr(8082)]);
+        assert_eq!(stale, 3);
+    }
+
+    #[test]
+    fn hex_round_trips_and_refuses_odd_or_foreign_digits() {
+        assert_eq!(hex(b"on\x00\xff"), "6f6e00ff");
+        assert_eq!(unhex("6f6E00ff"), Some(b"on\x00\xff".to_vec()));
+        assert_eq!(unhex(""), Some(Vec::new()));
+        assert_eq!(unhex("6f6"), None);
+        assert_eq!(unhex("zz"), None);
+    }
+}
@@ -3,20 +3,94 @@
 //! This module provides functionalities for managing network participants, including storing
 //! and querying participant addresses and endpoints. It supports distinguishing between known
 //! and unknown participants to facilitate network communication and discovery processes.
+//!
+//! A participant this node dials is stored as connecting until the network layer confirms the
+//! connection. Connecting participants are not yet receivers of any message and are left out of
+//! the participants lists shared with others.
+//!
+//! Every participant belongs to one of the listen interfaces of this node, and the participants
+//! lists can be scoped to the participants of one interface.
+//!
+//! A participant dialed by this node is known by the address it was dialed at, which is not
+//! always the address it advertises behind NAT or with `--advertise`. The advertised address,
+//! learned from the participants list it sends, is indexed as an alias, so a list naming it
+//! does not dial the same participant twice.
+//!
+//! Every connection keeps its direction: outbound when this node dialed it, redials included,
+//! and inbound when the peer connected by itself. A node only ever dialed, or only ever dialed
+//! by others, shows in the direction counts; see `balance` for evening them out.
+//!
+//! The sessions of every public address are recorded apart from its endpoints, so they survive
+//! reconnections and reveal the participants that keep coming and going.
+//!
+//! The peers that are not connected are remembered with their last verification time and their
+//! session count, whether they departed during this run or were restored from a state file, so
+//! a state file saved later still knows them, and with the time they departed, so the
+//! partition healing probes try the most recent departures first.
+//!
+//! A participant presenting a persistent node id is also indexed by it, along with the last
+//! addresses the id was seen at. When a node comes back from a new address, its session
+//! history and remembered state move to the new address, and its former address is no longer
+//! probed: the node id, when known, is what ties a peer together across restarts.
+
+use crate::participant::heal::Probe;
+use crate::participant::identity::NodeId;
+use crate::participant::interest::Interest;
+use crate::participant::interface::PRIMARY_INTERFACE;
+use crate::participant::message::{AgeBucket, MessageKind, SharedPeer, BASE_PROTOCOL_VERSION};
+use crate::participant::provenance::Provenance;
+use crate::participant::retention::{estimate, RetainedEntry, RetainedKind, Retention};
+use crate::participant::session::{DepartureReason, Session, SessionHistory, SessionStats};
+use crate::participant::skew::{ClockEstimate, ClockOffset, ClockSample};
+use crate::participant::snapshot::unix_seconds;
+use crate::participant::state::RememberedPeer;
+use crate::participant::timing;
+use crate::participant::utils::{same_addr, CanonicalAddr};
+use crate::printer::format_elapsed;
 
-use message_io::network::Endpoint;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// One counter per `MessageKind`, indexed by `MessageKind::index`.
+pub type KindCounters = [u64; MessageKind::COUNT];
 
 /// Represents a storage mechanism for network participants.
 ///
 /// This struct manages a collection of network participants, tracking their known state
 /// and associated network endpoints. It allows for efficient querying and updating of
-/// participant information.
+/// participant information. Participants are also indexed by public address, so address
+/// lookups do not scan the whole map, and their session histories are kept by public address.
 #[derive(Debug)]
 pub struct ParticipantsStorage<T: ParticipantEndpoint> {
     map: HashMap<T, ParticipantInfo>,
-    self_pub_addr: SocketAddr,
+    by_public: HashMap<CanonicalAddr, Vec<T>>,
+    aliases: HashMap<CanonicalAddr, T>,
+    sessions: HashMap<CanonicalAddr, SessionHistory>,
+    remembered: HashMap<CanonicalAddr, Remembered>,
+    departures: HashMap<DepartureReason, u64>,
+    nodes: HashMap<NodeId, Vec<CanonicalAddr>>,
+}
+
+/// The number of addresses remembered per node id, the most recent first.
+pub const MAX_NODE_ADDRESSES: usize = 8;
+
+/// What is kept about a public address that is not connected.
+#[derive(Debug, Default)]
+struct Remembered {
+    verified_at: Option<Instant>,
+    departed_at: Option<Instant>,
+    sessions: u64,
+    node_id: Option<NodeId>,
+}
+
+impl Remembered {
+    /// Returns the last time the address was seen, by its departure or its verification.
+    fn last_seen(&self) -> Option<Instant> {
+        self.departed_at.max(self.verified_at)
+    }
 }
 
 /// Defines behavior for types that can be used as network endpoints.
@@ -25,12 +99,6 @@ pub trait ParticipantEndpoint {
     fn addr(&self) -> SocketAddr;
 }
 
-impl ParticipantEndpoint for Endpoint {
-    fn addr(&self) -> SocketAddr {
-        self.addr()
-    }
-}
-
 /// Represents an address associated with a network participant.
 ///
 /// This struct encapsulates both the public socket address and the specific endpoint
@@ -41,79 +109,519 @@ pub struct ParticipantAddress<T: ParticipantEndpoint> {
     pub endpoint: T,
 }
 
+/// The outcome of `ParticipantsStorage::update_in_place`.
+#[derive(Debug, PartialEq)]
+pub enum AddressUpdate<T> {
+    /// The endpoint was not stored yet and has been added.
+    Added,
+    /// The endpoint already announced this address.
+    Unchanged,
+    /// The endpoint announced a new address, which replaced the previous one.
+    Updated { previous: SocketAddr },
+    /// The new address is already announced by another endpoint; both are kept.
+    Conflict { previous: SocketAddr, other: T },
+}
+
+/// The outcome of `ParticipantsStorage::set_node_id`.
+#[derive(Debug, PartialEq)]
+pub enum NodeIdUpdate<T> {
+    /// The endpoint is not stored, or already presented this id.
+    Unchanged,
+    /// The id is new to this address.
+    Added,
+    /// The id was seen at other addresses, not connected anymore, whose state moved to the
+    /// address of the endpoint.
+    Moved { previous: Vec<SocketAddr> },
+    /// Another connected endpoint presents the same id from another address; both are kept.
+    Conflict { other: T, other_addr: SocketAddr },
+}
+
+/// Which side opened a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// The peer connected to this node.
+    Inbound,
+    /// This node dialed the peer.
+    Outbound,
+}
+
+impl Direction {
+    /// Returns the human-readable name of this direction.
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+/// A snapshot of what the storage knows about one participant, used for reporting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerSummary {
+    pub public: SocketAddr,
+    pub direction: Direction,
+    pub node_id: Option<NodeId>,
+    pub former_addrs: Vec<SocketAddr>,
+    pub sent: KindCounters,
+    pub received: KindCounters,
+    pub sends_since_receive: u32,
+    pub deferred_sends: u64,
+    pub sessions: SessionStats,
+    pub verified_ago: Option<Duration>,
+    pub muted_for: Option<Duration>,
+    pub muted_drops: u64,
+    pub declared_period: Option<Duration>,
+    pub clock_offset: Option<ClockOffset>,
+    /// Where the address was first learned; the storage does not know, the participant fills
+    /// it in.
+    pub provenance: Option<Provenance>,
+}
+
+impl fmt::Display for PeerSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uptime = match self.sessions.current {
+            Some(current) => format!("up {}", format_elapsed(current)),
+            None => "connecting".to_owned(),
+        };
+        let muted = match self.muted_for {
+            Some(remaining) => format!(", muted for {}", format_elapsed(remaining)),
+            None => String::new(),
+        };
+        let node = match self.node_id {
+            Some(node_id) => format!(" node {}", node_id),
+            None => String::new(),
+        };
+        let period = match self.declared_period {
+            Some(period) => format!(", declares {:?}", period),
+            None => String::new(),
+        };
+        let clock = match self.clock_offset {
+            Some(offset) => format!(", clock {}", offset),
+            None => String::new(),
+        };
+        let former = match self.former_addrs.as_slice() {
+            [] => String::new(),
+            addrs => format!(
+                ", formerly {}",
+                addrs
+                    .iter()
+                    .map(|addr| format!("\"{}\"", addr))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        };
+        write!(
+            f,
+            "\"{}\"{} {} {}{}, {} sessions, {} flaps{}{}{}, sent {} received {}",
+            self.public,
+            node,
+            self.direction.name(),
+            uptime,
+            former,
+            self.sessions.total,
+            self.sessions.flaps,
+            period,
+            clock,
+            muted,
+            format_kind_counters(&self.sent),
+            format_kind_counters(&self.received)
+        )
+    }
+}
+
+/// Formats per-kind counters as `[Kind: count, ...]`.
+pub fn format_kind_counters(counters: &KindCounters) -> String {
+    let joined = MessageKind::ALL
+        .iter()
+        .map(|kind| format!("{}: {}", kind.name(), counters[kind.index()]))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    format!("[{}]", joined)
+}
+
+/// Holds everything the storage tracks about a single network participant.
+#[derive(Debug)]
+struct ParticipantInfo {
+    address: AddressInfo,
+    direction: Direction,
+    sends_since_receive: u32,
+    sent: KindCounters,
+    received: KindCounters,
+    verified_at: Option<Instant>,
+    protocol_version: u16,
+    deferred_sends: u64,
+    congested: bool,
+    interface: usize,
+    muted_until: Option<Instant>,
+    muted_drops: u64,
+    advertised: Option<SocketAddr>,
+    node_id: Option<NodeId>,
+    interest: Interest,
+    declared_period: Option<Duration>,
+    period_skew_noted: bool,
+    clock: ClockEstimate,
+}
+
+impl ParticipantInfo {
+    fn new(address: AddressInfo, direction: Direction) -> Self {
+        Self {
+            address,
+            direction,
+            sends_since_receive: 0,
+            sent: [0; MessageKind::COUNT],
+            received: [0; MessageKind::COUNT],
+            verified_at: None,
+            protocol_version: BASE_PROTOCOL_VERSION,
+            deferred_sends: 0,
+            congested: false,
+            interface: PRIMARY_INTERFACE,
+            muted_until: None,
+            muted_drops: 0,
+            advertised: None,
+            node_id: None,
+            interest: Interest::Unknown,
+            declared_period: None,
+            period_skew_noted: false,
+            clock: ClockEstimate::new(),
+        }
+    }
+
+    /// Determines whether the participant belongs to the interface `scope`, `None` covering
+    /// every interface.
+    fn in_scope(&self, scope: Option<usize>) -> bool {
+        scope.is_none_or(|interface| self.interface == interface)
+    }
+
+    fn summary<T: ParticipantEndpoint>(
+        &self,
+        endpoint: &T,
+        sessions: SessionStats,
+        former_addrs: Vec<SocketAddr>,
+        now: Instant,
+    ) -> PeerSummary {
+        PeerSummary {
+            public: self.address.public(endpoint),
+            direction: self.direction,
+            node_id: self.node_id,
+            former_addrs,
+            sent: self.sent,
+            received: self.received,
+            sends_since_receive: self.sends_since_receive,
+            deferred_sends: self.deferred_sends,
+            sessions,
+            verified_ago: self.verified_at.map(|at| now.saturating_duration_since(at)),
+            muted_for: self
+                .muted_until
+                .filter(|until| *until > now)
+                .map(|until| until.duration_since(now)),
+            muted_drops: self.muted_drops,
+            declared_period: self.declared_period,
+            clock_offset: self.clock.offset(),
+            provenance: None,
+        }
+    }
+}
+
 /// Enumerates the possible information states of a network participant.
 #[derive(Debug)]
-enum ParticipantInfo {
+enum AddressInfo {
+    /// Dialed by this node, waiting for the network layer to establish the connection.
+    Connecting {
+        started_at: Instant,
+    },
     KnownParticipant,
     UnknownParticipant(SocketAddr),
 }
 
+impl AddressInfo {
+    /// Resolves the public address of the participant reachable through `endpoint`.
+    fn public<T: ParticipantEndpoint>(&self, endpoint: &T) -> SocketAddr {
+        match self {
+            AddressInfo::Connecting { .. } | AddressInfo::KnownParticipant => endpoint.addr(),
+            AddressInfo::UnknownParticipant(public_addr) => *public_addr,
+        }
+    }
+
+    /// Determines whether the connection to the participant is not established yet.
+    fn is_connecting(&self) -> bool {
+        matches!(self, AddressInfo::Connecting { .. })
+    }
+}
+
+impl<T: ParticipantEndpoint + std::hash::Hash + std::cmp::Eq + Clone> Default
+    for ParticipantsStorage<T>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: ParticipantEndpoint + std::hash::Hash + std::cmp::Eq + Clone> ParticipantsStorage<T> {
     /// Constructs a new `ParticipantsStorage`.
     ///
     /// Initializes an empty storage for managing network participants.
-    ///
-    /// # Parameters
-    ///
-    /// * `self_pub_addr` - The public address of the node owning this storage.
-    pub fn new(self_pub_addr: SocketAddr) -> Self {
+    pub fn new() -> Self {
         Self {
             map: HashMap::new(),
-            self_pub_addr,
+            by_public: HashMap::new(),
+            aliases: HashMap::new(),
+            sessions: HashMap::new(),
+            remembered: HashMap::new(),
+            departures: HashMap::new(),
+            nodes: HashMap::new(),
         }
     }
 
-    /// Determines whether a participant with the given address is known.
+    /// Determines whether a participant with the given address is known, by its public
+    /// address or the address it advertises.
+    ///
+    /// Both are looked up in the indexes by address, in any form of the address, so the cost
+    /// does not grow with the number of participants.
     ///
     /// # Parameters
     ///
     /// * `addr` - The socket address to query.
-    pub fn is_known_participant(&self, addr: SocketAddr) -> bool {
-        self.map.iter().any(|(endpoint, info)| match info {
-            ParticipantInfo::KnownParticipant => endpoint.addr() == addr,
-            ParticipantInfo::UnknownParticipant(public_addr) => *public_addr == addr,
-        })
+    pub fn contains_addr(&self, addr: SocketAddr) -> bool {
+        let addr = addr.into();
+        self.by_public.contains_key(&addr) || self.aliases.contains_key(&addr)
+    }
+
+    /// Determines whether `addr` is an address of the participant reachable through
+    /// `endpoint`: the address of the endpoint, its public address or its advertised one.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `addr` - The socket address to compare.
+    pub fn is_address_of(&self, endpoint: &T, addr: SocketAddr) -> bool {
+        let Some(info) = self.map.get(endpoint) else {
+            return same_addr(addr, endpoint.addr());
+        };
+        same_addr(addr, endpoint.addr())
+            || same_addr(addr, info.address.public(endpoint))
+            || info
+                .advertised
+                .is_some_and(|advertised| same_addr(addr, advertised))
+    }
+
+    /// Records the address a participant advertises for itself, when it differs from its
+    /// public address.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `addr` - The address the participant lists first in its participants list.
+    pub fn set_advertised(&mut self, endpoint: &T, addr: SocketAddr) {
+        let Some(info) = self.map.get_mut(endpoint) else {
+            return;
+        };
+        let advertised = (!same_addr(addr, info.address.public(endpoint))).then_some(addr);
+        if let Some(previous) = std::mem::replace(&mut info.advertised, advertised) {
+            let previous = previous.into();
+            if self.aliases.get(&previous) == Some(endpoint) {
+                self.aliases.remove(&previous);
+            }
+        }
+        if let Some(advertised) = advertised {
+            self.aliases.insert(advertised.into(), endpoint.clone());
+        }
     }
 
-    /// Adds a participant as known in the storage.
+    /// Adds a participant as known in the storage, starting a session of its address.
     ///
     /// # Parameters
     ///
     /// * `endpoint` - The endpoint associated with the participant to add.
-    pub fn add_known_participant(&mut self, endpoint: T) {
-        self.map.insert(endpoint, ParticipantInfo::KnownParticipant);
+    /// * `now` - The time the participant connected.
+    pub fn add_known_participant(&mut self, endpoint: T, now: Instant) {
+        self.attach(
+            endpoint,
+            ParticipantInfo::new(AddressInfo::KnownParticipant, Direction::Outbound),
+            now,
+        );
+    }
+
+    /// Adds a participant this node is dialing.
+    ///
+    /// The participant becomes known once `promote_connected` is called for it. A non-zero
+    /// `suspicion` is the number of unanswered sends it starts with, so the half-open detection
+    /// gives up on it sooner than on a participant that is not suspected.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the pending connection.
+    /// * `started_at` - The time the dial was started.
+    /// * `suspicion` - The number of unanswered sends the participant starts with.
+    pub fn add_connecting_participant(&mut self, endpoint: T, started_at: Instant, suspicion: u32) {
+        let mut info =
+            ParticipantInfo::new(AddressInfo::Connecting { started_at }, Direction::Outbound);
+        info.sends_since_receive = suspicion;
+        self.insert(endpoint, info);
+    }
+
+    /// Records that the connection to a connecting participant was established.
+    ///
+    /// The participant keeps the state gathered while connecting, and a session of its address
+    /// starts.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the established connection.
+    /// * `now` - The time the connection was established.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the endpoint was not stored as connecting, in which case nothing changes.
+    pub fn promote_connected(&mut self, endpoint: &T, now: Instant) -> bool {
+        match self.map.get_mut(endpoint) {
+            Some(info) if info.address.is_connecting() => {
+                info.address = AddressInfo::KnownParticipant;
+                self.sessions
+                    .entry(endpoint.addr().into())
+                    .or_default()
+                    .open(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Determines whether a participant is stored as connecting.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn is_connecting(&self, endpoint: &T) -> bool {
+        self.map
+            .get(endpoint)
+            .is_some_and(|info| info.address.is_connecting())
+    }
+
+    /// Lists the connecting participants whose dial started at least `timeout` ago.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `timeout` - The time a connection may take to be established.
+    pub fn expired_connecting(&self, now: Instant, timeout: Duration) -> Vec<T> {
+        self.map
+            .iter()
+            .filter(|(_, info)| match info.address {
+                AddressInfo::Connecting { started_at } => {
+                    now.saturating_duration_since(started_at) >= timeout
+                }
+                _ => false,
+            })
+            .map(|(endpoint, _)| endpoint.clone())
+            .collect()
+    }
+
+    /// Gives every participant a fresh start after a time this node did not run.
+    ///
+    /// The answers to the messages sent before the gap could not be read during it, so the
+    /// unanswered sends are forgotten, and the dials in progress restart their timeout, sparing
+    /// the participants the half-open detection and the connect timeout.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The time this node runs again.
+    pub fn forgive_gap(&mut self, now: Instant) {
+        for info in self.map.values_mut() {
+            info.sends_since_receive = 0;
+            if let AddressInfo::Connecting { started_at } = &mut info.address {
+                *started_at = now;
+            }
+        }
     }
 
     /// Removes a participant from the storage.
     ///
+    /// The session of its address ends, unless another endpoint still announces the address.
+    ///
     /// # Parameters
     ///
     /// * `endpoint` - The endpoint associated with the participant to remove.
-    pub fn drop(&mut self, endpoint: T) {
-        self.map.remove(&endpoint);
+    /// * `reason` - Why the participant departed.
+    /// * `now` - The time the participant departed.
+    pub fn drop(&mut self, endpoint: T, reason: DepartureReason, now: Instant) {
+        self.detach(&endpoint, reason, now);
     }
 
-    /// Adds a participant as unknown in the storage.
+    /// Adds a participant as unknown in the storage, starting a session of its address.
     ///
     /// # Parameters
     ///
     /// * `endpoint` - The endpoint associated with the participant to add.
     /// * `pub_addr` - The public address of the participant.
-    pub fn add_unknown_participant(&mut self, endpoint: T, pub_addr: SocketAddr) {
-        self.map
-            .insert(endpoint, ParticipantInfo::UnknownParticipant(pub_addr));
+    /// * `now` - The time the participant announced its address.
+    pub fn add_unknown_participant(&mut self, endpoint: T, pub_addr: SocketAddr, now: Instant) {
+        self.attach(
+            endpoint,
+            ParticipantInfo::new(
+                AddressInfo::UnknownParticipant(pub_addr),
+                Direction::Inbound,
+            ),
+            now,
+        );
+    }
+
+    /// Records the public address announced through `endpoint`, keeping its counters.
+    ///
+    /// A re-announcement from a stored endpoint updates the address in place instead of
+    /// starting over. When another endpoint already announced the same address, both entries
+    /// are kept, since either connection may be the live one.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the announcement came through.
+    /// * `pub_addr` - The announced public address.
+    /// * `now` - The time of the announcement, which ends the session of the previous address.
+    pub fn update_in_place(
+        &mut self,
+        endpoint: T,
+        pub_addr: SocketAddr,
+        now: Instant,
+    ) -> AddressUpdate<T> {
+        let Some(previous) = self.get_pub_addr(&endpoint) else {
+            self.add_unknown_participant(endpoint, pub_addr, now);
+            return AddressUpdate::Added;
+        };
+        if same_addr(previous, pub_addr) {
+            return AddressUpdate::Unchanged;
+        }
+
+        let other = self.endpoint_of(pub_addr);
+        if let Some(mut info) = self.detach(&endpoint, DepartureReason::Reannounced, now) {
+            info.address = AddressInfo::UnknownParticipant(pub_addr);
+            self.attach(endpoint, info, now);
+        }
+
+        match other {
+            Some(other) => AddressUpdate::Conflict { previous, other },
+            None => AddressUpdate::Updated { previous },
+        }
     }
 
-    /// Retrieves a list of all participant addresses, including the self address.
-    pub fn get_participants_list(&self) -> Vec<SocketAddr> {
+    /// Retrieves a list of all connected participant addresses, including the self address.
+    ///
+    /// An address announced through several endpoints, or in its IPv4-mapped form next to its
+    /// IPv4 form, is listed once.
+    ///
+    /// # Parameters
+    ///
+    /// * `own` - The address of this node, first in the list.
+    /// * `scope` - The only interface whose participants are listed, or `None` for all.
+    pub fn get_participants_list(&self, own: SocketAddr, scope: Option<usize>) -> Vec<SocketAddr> {
         let mut list: Vec<SocketAddr> = Vec::with_capacity(self.map.len() + 1);
-        list.push(self.self_pub_addr);
+        let mut seen: HashSet<CanonicalAddr> = HashSet::with_capacity(self.map.len() + 1);
+        seen.insert(own.into());
+        list.push(own);
         self.map
             .iter()
-            .map(|(endpoint, info)| match info {
-                ParticipantInfo::KnownParticipant => endpoint.addr(),
-                ParticipantInfo::UnknownParticipant(public_addr) => *public_addr,
-            })
+            .filter(|(_, info)| !info.address.is_connecting() && info.in_scope(scope))
+            .map(|(endpoint, info)| info.address.public(endpoint))
+            .filter(|addr| seen.insert((*addr).into()))
             .for_each(|addr| {
                 list.push(addr);
             });
@@ -121,32 +629,1540 @@ impl<T: ParticipantEndpoint + std::hash::Hash + std::cmp::Eq + Clone> Participan
         list
     }
 
+    /// Builds the participants list shared with a peer, best entries first.
+    ///
+    /// The self address comes first, then the participants ordered from the least stale to the
+    /// never verified, each tagged with the `AgeBucket` of its staleness, the time since it was
+    /// verified beyond the period it declared. Connecting participants
+    /// and the participants of other interfaces than `scope` are left out. The list holds at
+    /// most `limit` entries, self address included, and each address, compared by its
+    /// canonical form, appears once.
+    ///
+    /// # Parameters
+    ///
+    /// * `own` - The address of this node, as announced to the receiver of the list.
+    /// * `now` - The current time, against which ages are computed.
+    /// * `limit` - The maximal number of entries.
+    /// * `scope` - The only interface whose participants are shared, or `None` for all.
+    pub fn shared_peers(
+        &self,
+        own: SocketAddr,
+        now: Instant,
+        limit: usize,
+        scope: Option<usize>,
+    ) -> Vec<SharedPeer> {
+        let mut ranked: Vec<(Option<Duration>, SocketAddr)> = self
+            .map
+            .iter()
+            .filter(|(_, info)| !info.address.is_connecting() && info.in_scope(scope))
+            .map(|(endpoint, info)| {
+                let staleness = info.verified_at.map(|at| {
+                    timing::staleness(now.saturating_duration_since(at), info.declared_period)
+                });
+                (staleness, info.address.public(endpoint))
+            })
+            .collect();
+        ranked.sort_by_key(|(staleness, _)| (staleness.is_none(), *staleness));
+        let mut seen: HashSet<CanonicalAddr> = HashSet::from([own.into()]);
+        ranked.retain(|(_, addr)| seen.insert((*addr).into()));
+
+        let own = SharedPeer {
+            addr: own,
+            age: AgeBucket::Fresh,
+        };
+        std::iter::once(own)
+            .chain(ranked.into_iter().map(|(staleness, addr)| SharedPeer {
+                addr,
+                age: AgeBucket::of(staleness),
+            }))
+            .take(limit)
+            .collect()
+    }
+
     /// Retrieves a list of `ParticipantAddress` instances for communication purposes.
+    ///
+    /// Connecting participants are not receivers until their connection is established.
     pub fn receivers(&self) -> Vec<ParticipantAddress<T>> {
         self.map
             .iter()
-            .map(|(endpoint, info)| {
-                let public = match info {
-                    ParticipantInfo::KnownParticipant => endpoint.addr(),
-                    ParticipantInfo::UnknownParticipant(public_addr) => *public_addr,
-                };
-                ParticipantAddress {
-                    endpoint: endpoint.clone(),
-                    public,
-                }
+            .filter(|(_, info)| !info.address.is_connecting())
+            .map(|(endpoint, info)| ParticipantAddress {
+                endpoint: endpoint.clone(),
+                public: info.address.public(endpoint),
             })
             .collect()
     }
 
+    /// Finds the endpoint of the participant with the given public address.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the participant.
+    pub fn endpoint_of(&self, addr: SocketAddr) -> Option<T> {
+        self.by_public
+            .get(&addr.into())
+            .and_then(|endpoints| endpoints.first())
+            .cloned()
+    }
+
+    /// Determines whether a participant this node dialed never sent anything back.
+    ///
+    /// Such a connection reached the participant's address without reaching the participant,
+    /// which is how a rejected inbound connection looks from the dialing side.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn never_answered(&self, endpoint: &T) -> bool {
+        self.map.get(endpoint).is_some_and(|info| {
+            matches!(info.address, AddressInfo::KnownParticipant)
+                && info.received.iter().all(|count| *count == 0)
+        })
+    }
+
+    /// Iterates over the endpoints of every participant.
+    pub fn endpoints(&self) -> impl Iterator<Item = &T> {
+        self.map.keys()
+    }
+
+    /// Iterates over the public address index, as addresses with the endpoints announcing them.
+    pub fn public_index(&self) -> impl Iterator<Item = (SocketAddr, &[T])> {
+        self.by_public
+            .iter()
+            .map(|(public, endpoints)| (public.original(), endpoints.as_slice()))
+    }
+
+    /// Rebuilds the public address index from the stored participants.
+    pub fn rebuild_index(&mut self) {
+        self.by_public.clear();
+        self.aliases.clear();
+        for (endpoint, info) in &self.map {
+            self.by_public
+                .entry(info.address.public(endpoint).into())
+                .or_default()
+                .push(endpoint.clone());
+            if let Some(advertised) = info.advertised {
+                self.aliases.insert(advertised.into(), endpoint.clone());
+            }
+        }
+    }
+
     /// Gets the public address for a given participant endpoint.
     ///
     /// # Parameters
     ///
     /// * `endpoint` - The endpoint of the participant whose address is being queried.
     pub fn get_pub_addr(&self, endpoint: &T) -> Option<SocketAddr> {
-        self.map.get(endpoint).map(|founded| match founded {
-            ParticipantInfo::KnownParticipant => endpoint.addr(),
-            ParticipantInfo::UnknownParticipant(addr) => *addr,
-        })
+        self.map
+            .get(endpoint)
+            .map(|founded| founded.address.public(endpoint))
+    }
+
+    /// Records that a participant was just heard from or successfully dialed.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the verified participant.
+    /// * `now` - The time of the verification.
+    pub fn mark_verified(&mut self, endpoint: &T, now: Instant) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.verified_at = Some(now);
+        }
+    }
+
+    /// Records the listen interface a participant belongs to.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `interface` - The index of the interface.
+    pub fn set_interface(&mut self, endpoint: &T, interface: usize) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.interface = interface;
+        }
+    }
+
+    /// Returns the listen interface of a participant, `PRIMARY_INTERFACE` if it is not stored.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn interface(&self, endpoint: &T) -> usize {
+        self.map
+            .get(endpoint)
+            .map_or(PRIMARY_INTERFACE, |info| info.interface)
+    }
+
+    /// Records the protocol version a participant announced.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the announcement came through.
+    /// * `version` - The announced version.
+    pub fn set_protocol_version(&mut self, endpoint: &T, version: u16) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.protocol_version = version;
+        }
+    }
+
+    /// Returns the protocol version of a participant, `BASE_PROTOCOL_VERSION` if it never
+    /// announced one or is not stored.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn protocol_version(&self, endpoint: &T) -> u16 {
+        self.map
+            .get(endpoint)
+            .map_or(BASE_PROTOCOL_VERSION, |info| info.protocol_version)
+    }
+
+    /// Records the topics a participant asked for in its last handshake frame or interest
+    /// update.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `interest` - The interest read from the update.
+    pub fn set_interest(&mut self, endpoint: &T, interest: Interest) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.interest = interest;
+        }
+    }
+
+    /// Records the effective period a participant declared in its last handshake frame.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `period` - The declared period.
+    pub fn set_declared_period(&mut self, endpoint: &T, period: Duration) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.declared_period = Some(period);
+        }
+    }
+
+    /// Retrieves the effective period a participant declared, `None` if it never did.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    pub fn declared_period(&self, endpoint: &T) -> Option<Duration> {
+        self.map.get(endpoint).and_then(|info| info.declared_period)
+    }
+
+    /// Marks the period skew of a participant as logged.
+    ///
+    /// # Returns
+    ///
+    /// `false` if it already was, or the participant is not stored.
+    pub fn note_period_skew(&mut self, endpoint: &T) -> bool {
+        self.map
+            .get_mut(endpoint)
+            .is_some_and(|info| !std::mem::replace(&mut info.period_skew_noted, true))
+    }
+
+    /// Records the clock request sent to a participant at `sent`, in microseconds since the
+    /// Unix epoch.
+    pub fn clock_requested(&mut self, endpoint: &T, sent: u64) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.clock.requested(sent);
+        }
+    }
+
+    /// Adds the sample of a clock reply to the estimate of a participant.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `sample` - The timestamps of the exchange.
+    /// * `max` - The offset worth a warning.
+    ///
+    /// # Returns
+    ///
+    /// The updated estimate and whether it is to be warned about, or `None` if the reply was
+    /// dropped.
+    pub fn clock_replied(
+        &mut self,
+        endpoint: &T,
+        sample: ClockSample,
+        max: Duration,
+    ) -> Option<(ClockOffset, bool)> {
+        let info = self.map.get_mut(endpoint)?;
+        if !info.clock.reply(sample) {
+            return None;
+        }
+        let warn = info.clock.warn(max);
+        info.clock.offset().map(|offset| (offset, warn))
+    }
+
+    /// Retrieves the estimated clock offset of a participant, `None` before its first sample.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    pub fn clock_offset(&self, endpoint: &T) -> Option<ClockOffset> {
+        self.map.get(endpoint).and_then(|info| info.clock.offset())
+    }
+
+    /// Determines whether a payload published on `topic` is to be sent to a participant;
+    /// `true` for a participant that never said what it wants.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant.
+    /// * `topic` - The topic of the payload.
+    pub fn wants_topic(&self, endpoint: &T, topic: &str) -> bool {
+        self.map
+            .get(endpoint)
+            .is_none_or(|info| info.interest.wants(topic))
+    }
+
+    /// Counts the connected participants that sent an interest update listing their topics.
+    pub fn selective_count(&self) -> usize {
+        self.map
+            .values()
+            .filter(|info| matches!(info.interest, Interest::Topics(_)))
+            .count()
+    }
+
+    /// Records the persistent node id a participant presented.
+    ///
+    /// When the id was seen at other addresses that are no longer connected, the participant
+    /// takes over their session history and remembered state, and those addresses are no
+    /// longer probed. When another connected endpoint presents the same id from another
+    /// address, both are kept, since either may be the genuine node.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the id came through.
+    /// * `node_id` - The presented id.
+    pub fn set_node_id(&mut self, endpoint: &T, node_id: NodeId) -> NodeIdUpdate<T> {
+        let Some(info) = self.map.get_mut(endpoint) else {
+            return NodeIdUpdate::Unchanged;
+        };
+        if info.node_id == Some(node_id) {
+            return NodeIdUpdate::Unchanged;
+        }
+        info.node_id = Some(node_id);
+        let public: CanonicalAddr = info.address.public(endpoint).into();
+
+        let conflict = self.map.iter().find_map(|(other, other_info)| {
+            let other_addr = other_info.address.public(other);
+            (other != endpoint
+                && other_info.node_id == Some(node_id)
+                && CanonicalAddr::from(other_addr) != public)
+                .then(|| (other.clone(), other_addr))
+        });
+        if let Some((other, other_addr)) = conflict {
+            self.index_node(node_id, public);
+            return NodeIdUpdate::Conflict { other, other_addr };
+        }
+
+        let previous: Vec<CanonicalAddr> = self
+            .nodes
+            .get(&node_id)
+            .into_iter()
+            .flatten()
+            .filter(|addr| **addr != public && !self.by_public.contains_key(addr))
+            .copied()
+            .collect();
+        for addr in &previous {
+            self.move_state(*addr, public);
+        }
+        self.index_node(node_id, public);
+        if previous.is_empty() {
+            NodeIdUpdate::Added
+        } else {
+            NodeIdUpdate::Moved {
+                previous: previous.iter().map(CanonicalAddr::original).collect(),
+            }
+        }
+    }
+
+    /// Returns the addresses the node `node_id` was seen at, the most recent first.
+    ///
+    /// # Parameters
+    ///
+    /// * `node_id` - The persistent id of the node.
+    pub fn node_addresses(&self, node_id: NodeId) -> Vec<SocketAddr> {
+        self.nodes
+            .get(&node_id)
+            .map(|addrs| addrs.iter().map(CanonicalAddr::original).collect())
+            .unwrap_or_default()
+    }
+
+    /// Counts a message of the given kind sent to a participant.
+    ///
+    /// The message also counts as unanswered until anything comes back from the participant.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the message was sent to.
+    /// * `kind` - The kind of the sent message.
+    pub fn record_sent_kind(&mut self, endpoint: &T, kind: MessageKind) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.sent[kind.index()] += 1;
+            info.sends_since_receive = info.sends_since_receive.saturating_add(1);
+        }
+    }
+
+    /// Counts a broadcast message that was not handed to the network for a participant.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the message was meant for.
+    pub fn record_deferred_send(&mut self, endpoint: &T) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.deferred_sends += 1;
+        }
+    }
+
+    /// Marks a participant whose last send took longer than the send budget.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the slow participant.
+    pub fn mark_congested(&mut self, endpoint: &T) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.congested = true;
+        }
+    }
+
+    /// Determines whether a participant was marked congested, clearing the mark.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn take_congested(&mut self, endpoint: &T) -> bool {
+        self.map
+            .get_mut(endpoint)
+            .is_some_and(|info| std::mem::take(&mut info.congested))
+    }
+
+    /// Counts a message of the given kind received from a participant.
+    ///
+    /// Any received message resets the unanswered sends counter of the participant.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the message was received from.
+    /// * `kind` - The kind of the received message.
+    pub fn record_received_kind(&mut self, endpoint: &T, kind: MessageKind) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.received[kind.index()] += 1;
+            info.sends_since_receive = 0;
+        }
+    }
+
+    /// Mutes the payloads of the participant with the given public address until `until`.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the participant.
+    /// * `until` - The time the mute expires.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no participant announces `addr`.
+    pub fn mute(&mut self, addr: SocketAddr, until: Instant) -> bool {
+        self.set_muted_until(addr, Some(until))
+    }
+
+    /// Lifts the mute of the participant with the given public address.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the participant.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no participant announces `addr`.
+    pub fn unmute(&mut self, addr: SocketAddr) -> bool {
+        self.set_muted_until(addr, None)
+    }
+
+    /// Determines whether the payloads of a participant are muted at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    /// * `now` - The current time.
+    pub fn is_muted(&self, endpoint: &T, now: Instant) -> bool {
+        self.map
+            .get(endpoint)
+            .and_then(|info| info.muted_until)
+            .is_some_and(|until| until > now)
+    }
+
+    /// Counts a payload dropped because its sender is muted.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint the payload was received from.
+    pub fn record_muted_drop(&mut self, endpoint: &T) {
+        if let Some(info) = self.map.get_mut(endpoint) {
+            info.muted_drops += 1;
+        }
+    }
+
+    /// Lifts the mutes that expired at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The public addresses of the participants whose mute was lifted, with the number of
+    /// payloads dropped while they were muted.
+    pub fn expire_mutes(&mut self, now: Instant) -> Vec<(SocketAddr, u64)> {
+        self.map
+            .iter_mut()
+            .filter(|(_, info)| info.muted_until.is_some_and(|until| until <= now))
+            .map(|(endpoint, info)| {
+                info.muted_until = None;
+                (info.address.public(endpoint), info.muted_drops)
+            })
+            .collect()
+    }
+
+    /// Returns the number of messages sent to and received from all participants, per kind.
+    pub fn totals(&self) -> (KindCounters, KindCounters) {
+        let mut sent = [0; MessageKind::COUNT];
+        let mut received = [0; MessageKind::COUNT];
+        for info in self.map.values() {
+            for kind in MessageKind::ALL {
+                sent[kind.index()] += info.sent[kind.index()];
+                received[kind.index()] += info.received[kind.index()];
+            }
+        }
+        (sent, received)
+    }
+
+    /// Returns the number of participants.
+    pub fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns a summary of every participant, sorted by public address.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time, against which session durations are computed.
+    pub fn summaries(&self, now: Instant) -> Vec<PeerSummary> {
+        let mut summaries: Vec<PeerSummary> = self
+            .map
+            .iter()
+            .map(|(endpoint, info)| {
+                let former = self.former_addrs(endpoint, info);
+                info.summary(endpoint, self.session_stats(endpoint, now), former, now)
+            })
+            .collect();
+        summaries.sort_by_key(|summary| summary.public);
+        summaries
+    }
+
+    /// Returns the summary of the participant with the given public address.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the participant.
+    /// * `now` - The current time, against which session durations are computed.
+    pub fn summary(&self, addr: SocketAddr, now: Instant) -> Option<PeerSummary> {
+        let endpoint = self.endpoint_of(addr)?;
+        self.map.get(&endpoint).map(|info| {
+            let former = self.former_addrs(&endpoint, info);
+            info.summary(&endpoint, self.session_stats(&endpoint, now), former, now)
+        })
+    }
+
+    /// Remembers a peer of a previous run, restored from a state file.
+    ///
+    /// The peer is not stored as a participant, it still has to be dialed; its session count
+    /// carries on once it connects again.
+    ///
+    /// # Parameters
+    ///
+    /// * `peer` - The restored peer.
+    /// * `now` - The current time.
+    /// * `wall` - The current wall-clock time, against which `peer.verified_at` is read.
+    pub fn remember(&mut self, peer: RememberedPeer, now: Instant, wall: SystemTime) {
+        let verified_at = peer.verified_at.and_then(|at| {
+            let age = unix_seconds(wall).saturating_sub(at);
+            now.checked_sub(Duration::from_secs(age))
+        });
+        let remembered = self.remembered.entry(peer.addr.into()).or_default();
+        remembered.verified_at = remembered.verified_at.max(verified_at);
+        remembered.sessions += peer.sessions;
+        if let Some(node_id) = peer.node_id {
+            remembered.node_id = Some(node_id);
+            self.index_node(node_id, peer.addr.into());
+        }
+    }
+
+    /// Returns every peer worth saving in a state file: the connected participants and the
+    /// remembered peers, each once, sorted by address.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `wall` - The current wall-clock time, against which verification times are written.
+    pub fn remembered_peers(&self, now: Instant, wall: SystemTime) -> Vec<RememberedPeer> {
+        let mut peers: HashMap<CanonicalAddr, (Option<Instant>, Option<NodeId>)> = self
+            .remembered
+            .iter()
+            .map(|(public, remembered)| (*public, (remembered.verified_at, remembered.node_id)))
+            .collect();
+        for (endpoint, info) in &self.map {
+            if info.address.is_connecting() {
+                continue;
+            }
+            let (verified_at, node_id) = peers
+                .entry(info.address.public(endpoint).into())
+                .or_default();
+            *verified_at = (*verified_at).max(info.verified_at);
+            *node_id = info.node_id.or(*node_id);
+        }
+
+        let wall = unix_seconds(wall);
+        let mut peers: Vec<RememberedPeer> = peers
+            .into_iter()
+            .map(|(public, (verified_at, node_id))| RememberedPeer {
+                addr: public.original(),
+                verified_at: verified_at
+                    .map(|at| wall.saturating_sub(now.saturating_duration_since(at).as_secs())),
+                sessions: self.sessions.get(&public).map_or(0, |h| h.stats(now).total)
+                    + self.remembered.get(&public).map_or(0, |r| r.sessions),
+                node_id,
+                provenance: None,
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.addr);
+        peers
+    }
+
+    /// Lists the remembered public addresses that are not connected nor being dialed, as
+    /// partition healing probes.
+    pub fn departed(&self) -> Vec<Probe> {
+        self.remembered
+            .iter()
+            .filter(|(public, _)| !self.contains_addr(public.original()))
+            .map(|(public, remembered)| Probe {
+                addr: public.original(),
+                departed_at: remembered.last_seen(),
+            })
+            .collect()
+    }
+
+    /// Returns the number of sessions ended for each reason, sorted by reason name.
+    pub fn departures(&self) -> Vec<(DepartureReason, u64)> {
+        let mut departures: Vec<(DepartureReason, u64)> = self
+            .departures
+            .iter()
+            .map(|(reason, count)| (*reason, *count))
+            .collect();
+        departures.sort_by_key(|(reason, _)| reason.name());
+        departures
+    }
+
+    /// Returns the session history of a public address, also after the participant departed.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - The public address of the participant.
+    pub fn session_history(&self, addr: SocketAddr) -> Option<&SessionHistory> {
+        self.sessions.get(&addr.into())
+    }
+
+    /// Lists the public addresses that departed within the last `FLAP_WINDOW`, with their
+    /// number of departures, most flapping first.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    pub fn flapping(&self, now: Instant) -> Vec<(SocketAddr, usize)> {
+        let mut flapping: Vec<(SocketAddr, usize)> = self
+            .sessions
+            .iter()
+            .map(|(public, history)| (public.original(), history.stats(now).flaps))
+            .filter(|(_, flaps)| *flaps > 0)
+            .collect();
+        flapping.sort_by_key(|(public, flaps)| (Reverse(*flaps), *public));
+        flapping
+    }
+
+    /// Holds the session histories and the remembered addresses to their retention policies.
+    ///
+    /// The history of a connected participant is never forgotten; the others age from their
+    /// departure. A forgotten history leaves its session total to the remembered address,
+    /// which ages from the last time it was seen. An address never seen, such as one restored
+    /// without a verification, is the first forgotten by count, though never by age.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `retention` - The policies, which record what is left.
+    pub fn sweep_sessions(&mut self, now: Instant, retention: &mut Retention) {
+        let histories = self
+            .sessions
+            .iter()
+            .map(|(public, history)| RetainedEntry {
+                key: *public,
+                age: history.departed_for(now),
+                bytes: estimate::<SessionHistory, Session>(history.len()),
+            })
+            .collect();
+        for public in retention.enforce(RetainedKind::Sessions, histories) {
+            if let Some(history) = self.sessions.remove(&public) {
+                self.remembered.entry(public).or_default().sessions += history.stats(now).total;
+            }
+        }
+
+        let unseen = retention.policy(RetainedKind::Departed).max_age;
+        let remembered = self
+            .remembered
+            .iter()
+            .map(|(public, remembered)| RetainedEntry {
+                key: *public,
+                age: Some(
+                    remembered
+                        .last_seen()
+                        .map_or(unseen, |at| now.saturating_duration_since(at)),
+                ),
+                bytes: estimate::<Remembered, ()>(0),
+            })
+            .collect();
+        for public in retention.enforce(RetainedKind::Departed, remembered) {
+            self.remembered.remove(&public);
+        }
+    }
+
+    /// Returns the number of messages sent to a participant since it last sent anything.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint` - The endpoint of the participant being queried.
+    pub fn sends_since_receive(&self, endpoint: &T) -> Option<u32> {
+        self.map.get(endpoint).map(|info| info.sends_since_receive)
+    }
+
+    /// Lists the participants whose connection looks half-open.
+    ///
+    /// A participant is suspected when more than `threshold` messages were sent to it without
+    /// anything coming back, scaled to the period it declared, while at least one other
+    /// participant is still answering. When every participant is silent the problem is more
+    /// likely local, so nobody is suspected. Connecting participants are neither suspected nor
+    /// counted as answering.
+    ///
+    /// # Parameters
+    ///
+    /// * `threshold` - The number of unanswered sends tolerated from a participant of `period`;
+    ///   `0` disables the detection.
+    /// * `period` - The effective period of this node.
+    pub fn half_open_suspects(
+        &self,
+        threshold: u32,
+        period: Duration,
+    ) -> Vec<ParticipantAddress<T>> {
+        if threshold == 0 {
+            return Vec::new();
+        }
+        let silent = |info: &ParticipantInfo| {
+            info.sends_since_receive
+                > timing::half_open_threshold(threshold, period, info.declared_period)
+        };
+
+        let healthy_peer_exists = self
+            .map
+            .values()
+            .any(|info| !info.address.is_connecting() && !silent(info));
+        if !healthy_peer_exists {
+            return Vec::new();
+        }
+
+        self.map
+            .iter()
+            .filter(|(_, info)| !info.address.is_connecting() && silent(info))
+            .map(|(endpoint, info)| ParticipantAddress {
+                endpoint: endpoint.clone(),
+                public: info.address.public(endpoint),
+            })
+            .collect()
+    }
+
+    /// Counts the established connections by direction.
+    ///
+    /// # Returns
+    ///
+    /// The number of inbound connections, then the number of outbound ones.
+    pub fn direction_counts(&self) -> (usize, usize) {
+        let established = self
+            .map
+            .values()
+            .filter(|info| !info.address.is_connecting());
+        established.fold((0, 0), |(inbound, outbound), info| match info.direction {
+            Direction::Inbound => (inbound + 1, outbound),
+            Direction::Outbound => (inbound, outbound + 1),
+        })
+    }
+
+    /// Lists the participants reached only through connections they opened, by public address.
+    ///
+    /// A participant with a connection being dialed, or with any outbound one, is not listed.
+    pub fn inbound_only(&self) -> Vec<ParticipantAddress<T>> {
+        let mut peers: Vec<ParticipantAddress<T>> = self
+            .by_public
+            .iter()
+            .filter(|(_, endpoints)| {
+                endpoints.iter().all(|endpoint| {
+                    self.map.get(endpoint).is_some_and(|info| {
+                        !info.address.is_connecting() && info.direction == Direction::Inbound
+                    })
+                })
+            })
+            .filter_map(|(public, endpoints)| {
+                endpoints.first().map(|endpoint| ParticipantAddress {
+                    public: public.original(),
+                    endpoint: endpoint.clone(),
+                })
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.public);
+        peers
+    }
+
+    /// Determines whether an established connection other than `endpoint` announces `addr`.
+    ///
+    /// The peer behind `addr` then stays connected whatever happens to `endpoint`, so the
+    /// arrival or the loss of `endpoint` is not a membership change.
+    pub fn connected_elsewhere(&self, endpoint: &T, addr: SocketAddr) -> bool {
+        self.by_public.get(&addr.into()).is_some_and(|endpoints| {
+            endpoints.iter().any(|other| {
+                other != endpoint
+                    && self
+                        .map
+                        .get(other)
+                        .is_some_and(|info| !info.address.is_connecting())
+            })
+        })
+    }
+
+    /// Finds the crossed connections of `addr`: `endpoint` and another stored connection
+    /// announcing the same address in the other direction, established or being dialed.
+    ///
+    /// # Returns
+    ///
+    /// The outbound connection and the inbound one.
+    pub fn crossing(&self, endpoint: &T, addr: SocketAddr) -> Option<(T, T)> {
+        let direction = self.map.get(endpoint)?.direction;
+        let other = self
+            .by_public
+            .get(&addr.into())?
+            .iter()
+            .find(|other| {
+                *other != endpoint
+                    && self
+                        .map
+                        .get(other)
+                        .is_some_and(|info| info.direction != direction)
+            })?
+            .clone();
+        Some(match direction {
+            Direction::Outbound => (endpoint.clone(), other),
+            Direction::Inbound => (other, endpoint.clone()),
+        })
+    }
+
+    /// Moves what was gathered through the connection `from` to the connection `to` of the
+    /// same participant, and removes `from` without ending the session of its address.
+    ///
+    /// The counters of both connections add up, and what only `from` learned, such as the
+    /// node id or the topic interest, is kept. `to` keeps its own direction and unanswered
+    /// sends counter.
+    ///
+    /// # Returns
+    ///
+    /// `false` if either endpoint is not stored, in which case nothing changes.
+    pub fn hand_over(&mut self, from: &T, to: &T) -> bool {
+        if from == to || !self.map.contains_key(to) {
+            return false;
+        }
+        let Some(old) = self.remove(from) else {
+            return false;
+        };
+        let Some(info) = self.map.get_mut(to) else {
+            return false;
+        };
+        for (mine, theirs) in info.sent.iter_mut().zip(old.sent) {
+            *mine += theirs;
+        }
+        for (mine, theirs) in info.received.iter_mut().zip(old.received) {
+            *mine += theirs;
+        }
+        info.deferred_sends += old.deferred_sends;
+        info.muted_drops += old.muted_drops;
+        info.verified_at = info.verified_at.max(old.verified_at);
+        info.muted_until = info.muted_until.max(old.muted_until);
+        info.protocol_version = info.protocol_version.max(old.protocol_version);
+        info.node_id = info.node_id.or(old.node_id);
+        if matches!(info.interest, Interest::Unknown) {
+            info.interest = old.interest;
+        }
+        info.declared_period = info.declared_period.or(old.declared_period);
+        info.period_skew_noted |= old.period_skew_noted;
+        if info.clock.offset().is_none() {
+            info.clock = old.clock;
+        }
+        let advertised = info
+            .advertised
+            .is_none()
+            .then_some(old.advertised)
+            .flatten();
+        if let Some(advertised) = advertised {
+            self.set_advertised(to, advertised);
+        }
+        true
+    }
+
+    /// Moves a participant to a fresh endpoint being dialed, keeping its accumulated state.
+    ///
+    /// The participant is connecting again, and its unanswered sends counter starts over, since
+    /// nothing has been sent through the new connection yet. Its session ends as half-open, and
+    /// the next one starts once the new connection is established. The new connection is
+    /// dialed, so it is outbound whichever side opened the old one.
+    ///
+    /// # Parameters
+    ///
+    /// * `old` - The endpoint the participant is currently stored under.
+    /// * `new` - The endpoint of the replacement connection.
+    /// * `started_at` - The time the replacement connection was dialed.
+    pub fn replace_endpoint(&mut self, old: &T, new: T, started_at: Instant) {
+        if let Some(mut info) = self.detach(old, DepartureReason::HalfOpen, started_at) {
+            info.address = AddressInfo::Connecting { started_at };
+            info.direction = Direction::Outbound;
+            info.sends_since_receive = 0;
+            self.insert(new, info);
+        }
+    }
+
+    /// Stores a participant and indexes it by public address, replacing any previous entry
+    /// of the same endpoint.
+    fn insert(&mut self, endpoint: T, info: ParticipantInfo) {
+        self.remove(&endpoint);
+        self.by_public
+            .entry(info.address.public(&endpoint).into())
+            .or_default()
+            .push(endpoint.clone());
+        if let Some(advertised) = info.advertised {
+            self.aliases.insert(advertised.into(), endpoint.clone());
+        }
+        self.map.insert(endpoint, info);
+    }
+
+    /// Sets the mute expiry of every endpoint announcing `addr`.
+    fn set_muted_until(&mut self, addr: SocketAddr, until: Option<Instant>) -> bool {
+        let Some(endpoints) = self.by_public.get(&addr.into()) else {
+            return false;
+        };
+        for endpoint in endpoints {
+            if let Some(info) = self.map.get_mut(endpoint) {
+                info.muted_until = until;
+            }
+        }
+        true
+    }
+
+    /// Stores a participant and starts a session of its address, unless it is connecting.
+    fn attach(&mut self, endpoint: T, info: ParticipantInfo, now: Instant) {
+        let public: CanonicalAddr = info.address.public(&endpoint).into();
+        let connected = !info.address.is_connecting();
+        self.insert(endpoint, info);
+        if connected {
+            let history = self.sessions.entry(public).or_default();
+            if let Some(remembered) = self.remembered.get_mut(&public) {
+                history.carry(std::mem::take(&mut remembered.sessions));
+            }
+            history.open(now);
+        }
+    }
+
+    /// Removes a participant and ends the session of its address once no other endpoint
+    /// announces it.
+    fn detach(
+        &mut self,
+        endpoint: &T,
+        reason: DepartureReason,
+        now: Instant,
+    ) -> Option<ParticipantInfo> {
+        let info = self.remove(endpoint)?;
+        let public: CanonicalAddr = info.address.public(endpoint).into();
+        if !info.address.is_connecting() && !self.by_public.contains_key(&public) {
+            if let Some(history) = self.sessions.get_mut(&public) {
+                history.close(now, reason);
+            }
+            *self.departures.entry(reason).or_default() += 1;
+            let remembered = self.remembered.entry(public).or_default();
+            remembered.verified_at = remembered.verified_at.max(info.verified_at);
+            remembered.departed_at = Some(now);
+            remembered.node_id = info.node_id.or(remembered.node_id);
+        }
+        Some(info)
+    }
+
+    /// Indexes `addr` as the most recent address of the node `node_id`.
+    fn index_node(&mut self, node_id: NodeId, addr: CanonicalAddr) {
+        let addrs = self.nodes.entry(node_id).or_default();
+        addrs.retain(|known| *known != addr);
+        addrs.insert(0, addr);
+        addrs.truncate(MAX_NODE_ADDRESSES);
+    }
+
+    /// Moves the session history and the remembered state of a former address of a node to
+    /// its current address.
+    fn move_state(&mut self, former: CanonicalAddr, current: CanonicalAddr) {
+        if let Some(earlier) = self.sessions.remove(&former) {
+            self.sessions.entry(current).or_default().absorb(earlier);
+        }
+        if let Some(remembered) = self.remembered.remove(&former) {
+            let history = self.sessions.entry(current).or_default();
+            history.carry(remembered.sessions);
+        }
+    }
+
+    /// Returns the other addresses the node behind `endpoint` was seen at.
+    fn former_addrs(&self, endpoint: &T, info: &ParticipantInfo) -> Vec<SocketAddr> {
+        let public = info.address.public(endpoint);
+        info.node_id
+            .map(|node_id| self.node_addresses(node_id))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|addr| !same_addr(*addr, public))
+            .collect()
+    }
+
+    /// Returns the session metrics of the address announced through `endpoint`.
+    fn session_stats(&self, endpoint: &T, now: Instant) -> SessionStats {
+        let Some(public) = self.get_pub_addr(endpoint) else {
+            return SessionStats::default();
+        };
+        self.sessions
+            .get(&public.into())
+            .map(|history| history.stats(now))
+            .unwrap_or_default()
+    }
+
+    /// Removes a participant and its index entry.
+    fn remove(&mut self, endpoint: &T) -> Option<ParticipantInfo> {
+        let info = self.map.remove(endpoint)?;
+        let public: CanonicalAddr = info.address.public(endpoint).into();
+        if let Some(endpoints) = self.by_public.get_mut(&public) {
+            endpoints.retain(|indexed| indexed != endpoint);
+            if endpoints.is_empty() {
+                self.by_public.remove(&public);
+            }
+        }
+        if let Some(advertised) = info.advertised {
+            let advertised = advertised.into();
+            if self.aliases.get(&advertised) == Some(endpoint) {
+                self.aliases.remove(&advertised);
+            }
+        }
+        Some(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    /// An endpoint standing for a connection from `addr`, with no socket behind it.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct TestEndpoint(SocketAddr);
+
+    impl ParticipantEndpoint for TestEndpoint {
+        fn addr(&self) -> SocketAddr {
+            self.0
+        }
+    }
+
+    /// A storage holding the participants announcing the ports of `ports`, each connected
+    /// from the port above.
+    fn storage_of(ports: &[u16], now: Instant) -> ParticipantsStorage<TestEndpoint> {
+        let mut storage = ParticipantsStorage::new();
+        for port in ports {
+            storage.add_unknown_participant(TestEndpoint(addr(port + 1)), addr(*port), now);
+        }
+        storage
+    }
+
+    #[test]
+    fn lists_only_hold_the_participants_of_their_interface() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090, 8100], now);
+        storage.set_interface(&TestEndpoint(addr(8091)), 1);
+        storage.set_interface(&TestEndpoint(addr(8101)), 1);
+        assert_eq!(storage.interface(&TestEndpoint(addr(8091))), 1);
+        assert_eq!(
+            storage.interface(&TestEndpoint(addr(8081))),
+            PRIMARY_INTERFACE
+        );
+        assert_eq!(
+            storage.interface(&TestEndpoint(addr(9999))),
+            PRIMARY_INTERFACE
+        );
+
+        let scoped = |scope| {
+            let mut list = storage.get_participants_list(addr(7000), scope);
+            list[1..].sort();
+            list
+        };
+        assert_eq!(scoped(Some(PRIMARY_INTERFACE)), [addr(7000), addr(8080)]);
+        assert_eq!(scoped(Some(1)), [addr(7000), addr(8090), addr(8100)]);
+        assert_eq!(scoped(Some(2)), [addr(7000)]);
+        assert_eq!(
+            scoped(None),
+            [addr(7000), addr(8080), addr(8090), addr(8100)]
+        );
+
+        let shared: Vec<SocketAddr> = storage
+            .shared_peers(addr(7000), now, 10, Some(PRIMARY_INTERFACE))
+            .iter()
+            .map(|peer| peer.addr)
+            .collect();
+        assert_eq!(shared, [addr(7000), addr(8080)]);
+    }
+
+    #[test]
+    fn contains_addr_follows_the_index_of_public_and_advertised_addresses() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090], now);
+        assert!(storage.contains_addr(addr(8080)));
+        assert!(storage.contains_addr(addr(8090)));
+        // The address of the connection is not the one of the participant.
+        assert!(!storage.contains_addr(addr(8081)));
+        let mapped: SocketAddr = "[::ffff:127.0.0.1]:8080".parse().unwrap();
+        assert!(storage.contains_addr(mapped));
+
+        let first = TestEndpoint(addr(8081));
+        storage.set_advertised(&first, addr(7000));
+        assert!(storage.contains_addr(addr(7000)));
+        storage.set_advertised(&first, addr(7001));
+        assert!(!storage.contains_addr(addr(7000)));
+        assert!(storage.contains_addr(addr(7001)));
+
+        storage.drop(first, DepartureReason::Disconnected, now);
+        assert!(!storage.contains_addr(addr(8080)));
+        assert!(!storage.contains_addr(addr(7001)));
+        assert!(storage.contains_addr(addr(8090)));
+    }
+
+    #[test]
+    fn kinds_sent_and_received_are_counted_per_participant() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090], now);
+        let first = TestEndpoint(addr(8081));
+        storage.record_sent_kind(&first, MessageKind::PublicAddress);
+        storage.record_sent_kind(&first, MessageKind::Text);
+        storage.record_sent_kind(&first, MessageKind::Text);
+        storage.record_received_kind(&first, MessageKind::PushParticipantsList);
+
+        let summary = storage.summary(addr(8080), now).unwrap();
+        assert_eq!(summary.sent[MessageKind::PublicAddress.index()], 1);
+        assert_eq!(summary.sent[MessageKind::Text.index()], 2);
+        assert_eq!(summary.sent.iter().sum::<u64>(), 3);
+        assert_eq!(
+            summary.received[MessageKind::PushParticipantsList.index()],
+            1
+        );
+        assert_eq!(summary.received.iter().sum::<u64>(), 1);
+
+        let untouched = storage.summary(addr(8090), now).unwrap();
+        assert_eq!(untouched.sent, [0; MessageKind::COUNT]);
+        assert_eq!(untouched.received, [0; MessageKind::COUNT]);
+    }
+
+    #[test]
+    fn a_received_message_answers_the_sends_before_it() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let endpoint = TestEndpoint(addr(8081));
+        for _ in 0..3 {
+            storage.record_sent_kind(&endpoint, MessageKind::Text);
+        }
+        assert_eq!(
+            storage
+                .summary(addr(8080), now)
+                .unwrap()
+                .sends_since_receive,
+            3
+        );
+        storage.record_received_kind(&endpoint, MessageKind::Text);
+        assert_eq!(
+            storage
+                .summary(addr(8080), now)
+                .unwrap()
+                .sends_since_receive,
+            0
+        );
+    }
+
+    #[test]
+    fn messages_of_an_unknown_endpoint_are_not_counted() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let stranger = TestEndpoint(addr(9000));
+        storage.record_sent_kind(&stranger, MessageKind::Text);
+        storage.record_received_kind(&stranger, MessageKind::Text);
+        assert_eq!(
+            storage.totals(),
+            ([0; MessageKind::COUNT], [0; MessageKind::COUNT])
+        );
+    }
+
+    #[test]
+    fn totals_add_up_every_participant() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090, 8100], now);
+        for (port, count) in [(8081, 1), (8091, 2), (8101, 4)] {
+            for _ in 0..count {
+                storage.record_sent_kind(&TestEndpoint(addr(port)), MessageKind::Text);
+                storage.record_received_kind(&TestEndpoint(addr(port)), MessageKind::Probe);
+            }
+        }
+        let (sent, received) = storage.totals();
+        assert_eq!(sent[MessageKind::Text.index()], 7);
+        assert_eq!(received[MessageKind::Probe.index()], 7);
+        assert_eq!(sent.iter().sum::<u64>() + received.iter().sum::<u64>(), 14);
+    }
+
+    #[test]
+    fn kind_counters_are_formatted_by_name_in_counter_order() {
+        let mut counters = [0; MessageKind::COUNT];
+        counters[MessageKind::Text.index()] = 5;
+        let formatted = format_kind_counters(&counters);
+        assert!(formatted.starts_with("[PublicAddress: 0, PushParticipantsList: 0, "));
+        assert!(formatted.contains(", Text: 5, "));
+        assert!(formatted.ends_with(", CompactListReply: 0]"));
+        assert_eq!(formatted.matches(": ").count(), MessageKind::COUNT);
+    }
+
+    #[test]
+    fn a_summary_shows_both_directions_of_counters() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let endpoint = TestEndpoint(addr(8081));
+        storage.record_sent_kind(&endpoint, MessageKind::SharedPeers);
+        storage.record_received_kind(&endpoint, MessageKind::PushParticipantsList);
+
+        let summary = storage.summary(addr(8080), now).unwrap();
+        let line = summary.to_string();
+        assert!(line.starts_with("\"127.0.0.1:8080\" inbound "), "{}", line);
+        let (sent, received) = line.split_once(" received ").unwrap();
+        assert!(sent.contains("SharedPeers: 1"), "{}", line);
+        assert!(sent.contains("PushParticipantsList: 0"), "{}", line);
+        assert!(received.contains("PushParticipantsList: 1"), "{}", line);
+        assert!(received.contains("SharedPeers: 0"), "{}", line);
+    }
+
+    #[test]
+    fn any_received_message_resets_the_unanswered_sends() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let endpoint = TestEndpoint(addr(8081));
+        for kind in MessageKind::ALL {
+            storage.record_sent_kind(&endpoint, MessageKind::Text);
+            storage.record_sent_kind(&endpoint, MessageKind::Text);
+            assert_eq!(storage.sends_since_receive(&endpoint), Some(2));
+            storage.record_received_kind(&endpoint, kind);
+            assert_eq!(
+                storage.sends_since_receive(&endpoint),
+                Some(0),
+                "{}",
+                kind.name()
+            );
+        }
+
+        storage.record_sent_kind(&endpoint, MessageKind::Text);
+        storage.forgive_gap(now);
+        assert_eq!(storage.sends_since_receive(&endpoint), Some(0));
+    }
+
+    #[test]
+    fn a_participant_is_suspected_past_the_threshold_while_another_answers() {
+        let now = Instant::now();
+        let period = Duration::from_millis(100);
+        let mut storage = storage_of(&[8080, 8090], now);
+        let (silent, answering) = (TestEndpoint(addr(8081)), TestEndpoint(addr(8091)));
+        for _ in 0..3 {
+            storage.record_sent_kind(&silent, MessageKind::Text);
+        }
+        assert!(storage.half_open_suspects(3, period).is_empty());
+
+        storage.record_sent_kind(&silent, MessageKind::Text);
+        let suspects = storage.half_open_suspects(3, period);
+        assert_eq!(suspects.len(), 1);
+        assert_eq!(suspects[0].endpoint, silent);
+        assert_eq!(suspects[0].public, addr(8080));
+        assert!(storage.half_open_suspects(0, period).is_empty());
+
+        // With every participant silent, the problem is more likely ours.
+        for _ in 0..4 {
+            storage.record_sent_kind(&answering, MessageKind::Text);
+        }
+        assert!(storage.half_open_suspects(3, period).is_empty());
+        storage.record_received_kind(&answering, MessageKind::Text);
+        assert_eq!(storage.half_open_suspects(3, period).len(), 1);
+    }
+
+    #[test]
+    fn the_threshold_of_a_participant_scales_with_its_declared_period() {
+        let now = Instant::now();
+        let period = Duration::from_millis(100);
+        let mut storage = storage_of(&[8080, 8090], now);
+        let slow = TestEndpoint(addr(8081));
+        storage.set_declared_period(&slow, Duration::from_secs(1));
+        for _ in 0..30 {
+            storage.record_sent_kind(&slow, MessageKind::Text);
+        }
+        assert!(storage.half_open_suspects(3, period).is_empty());
+        storage.record_sent_kind(&slow, MessageKind::Text);
+        assert_eq!(storage.half_open_suspects(3, period).len(), 1);
+    }
+
+    #[test]
+    fn a_redialed_participant_keeps_its_counters_and_starts_unsuspected() {
+        let now = Instant::now();
+        let period = Duration::from_millis(100);
+        let mut storage = storage_of(&[8080, 8090], now);
+        // The replacement connection dials the public address of the participant.
+        let (old, new) = (TestEndpoint(addr(8081)), TestEndpoint(addr(8080)));
+        for _ in 0..4 {
+            storage.record_sent_kind(&old, MessageKind::Text);
+        }
+        assert_eq!(storage.half_open_suspects(3, period).len(), 1);
+
+        storage.replace_endpoint(&old, new.clone(), now);
+        assert!(storage.is_connecting(&new));
+        assert_eq!(storage.sends_since_receive(&new), Some(0));
+        assert_eq!(storage.sends_since_receive(&old), None);
+        // A connecting participant is neither suspected nor counted as answering.
+        for _ in 0..4 {
+            storage.record_sent_kind(&new, MessageKind::Text);
+        }
+        assert!(storage.half_open_suspects(3, period).is_empty());
+
+        assert!(storage.promote_connected(&new, now));
+        let summary = storage.summary(addr(8080), now).unwrap();
+        assert_eq!(summary.sent[MessageKind::Text.index()], 8);
+        assert_eq!(storage.half_open_suspects(3, period).len(), 1);
+    }
+
+    #[test]
+    fn a_dialed_participant_is_connecting_until_promoted() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let dialed = TestEndpoint(addr(9000));
+        storage.add_connecting_participant(dialed.clone(), now, 0);
+        assert!(storage.is_connecting(&dialed));
+        assert!(!storage.is_connecting(&TestEndpoint(addr(8081))));
+        assert!(storage.summary(addr(9000), now).is_some());
+
+        assert!(storage.promote_connected(&dialed, now));
+        assert!(!storage.is_connecting(&dialed));
+        // A participant already known is not promoted again.
+        assert!(!storage.promote_connected(&dialed, now));
+        assert!(!storage.promote_connected(&TestEndpoint(addr(9100)), now));
+    }
+
+    #[test]
+    fn connecting_participants_are_neither_receivers_nor_shared() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080], now);
+        let dialed = TestEndpoint(addr(9000));
+        storage.add_connecting_participant(dialed.clone(), now, 0);
+
+        let receivers: Vec<SocketAddr> = storage.receivers().iter().map(|r| r.public).collect();
+        assert_eq!(receivers, vec![addr(8080)]);
+        assert_eq!(
+            storage.get_participants_list(addr(7000), None),
+            vec![addr(7000), addr(8080)]
+        );
+        let shared: Vec<SocketAddr> = storage
+            .shared_peers(addr(7000), now, 10, None)
+            .iter()
+            .map(|peer| peer.addr)
+            .collect();
+        assert_eq!(shared, vec![addr(7000), addr(8080)]);
+        assert_eq!(storage.direction_counts(), (1, 0));
+
+        storage.promote_connected(&dialed, now);
+        assert_eq!(storage.receivers().len(), 2);
+        assert_eq!(storage.get_participants_list(addr(7000), None).len(), 3);
+        assert_eq!(storage.shared_peers(addr(7000), now, 10, None).len(), 3);
+        assert_eq!(storage.direction_counts(), (1, 1));
+    }
+
+    #[test]
+    fn a_dial_expires_after_the_connect_timeout_unless_a_gap_restarts_it() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(5);
+        let mut storage = storage_of(&[8080], start);
+        let dialed = TestEndpoint(addr(9000));
+        storage.add_connecting_participant(dialed.clone(), start, 0);
+
+        assert!(storage
+            .expired_connecting(start + Duration::from_secs(4), timeout)
+            .is_empty());
+        assert_eq!(
+            storage.expired_connecting(start + timeout, timeout),
+            vec![dialed.clone()]
+        );
+
+        storage.forgive_gap(start + Duration::from_secs(4));
+        assert!(storage
+            .expired_connecting(start + timeout, timeout)
+            .is_empty());
+        assert_eq!(
+            storage.expired_connecting(start + Duration::from_secs(9), timeout),
+            vec![dialed.clone()]
+        );
+
+        storage.drop(
+            dialed.clone(),
+            DepartureReason::Disconnected,
+            start + timeout,
+        );
+        assert!(!storage.is_connecting(&dialed));
+        assert!(storage
+            .expired_connecting(start + Duration::from_secs(9), timeout)
+            .is_empty());
+        // A promoted participant no longer expires.
+        let promoted = TestEndpoint(addr(9100));
+        storage.add_connecting_participant(promoted.clone(), start, 0);
+        storage.promote_connected(&promoted, start);
+        assert!(storage
+            .expired_connecting(start + Duration::from_secs(60), timeout)
+            .is_empty());
+    }
+
+    #[test]
+    fn a_suspected_dial_starts_with_its_unanswered_sends() {
+        let now = Instant::now();
+        let period = Duration::from_millis(100);
+        let mut storage = storage_of(&[8080], now);
+        let suspected = TestEndpoint(addr(9000));
+        storage.add_connecting_participant(suspected.clone(), now, 3);
+        assert_eq!(storage.sends_since_receive(&suspected), Some(3));
+        assert!(storage.half_open_suspects(3, period).is_empty());
+
+        storage.promote_connected(&suspected, now);
+        storage.record_sent_kind(&suspected, MessageKind::Text);
+        let suspects = storage.half_open_suspects(3, period);
+        assert_eq!(suspects.len(), 1);
+        assert_eq!(suspects[0].endpoint, suspected);
+    }
+
+    #[test]
+    fn a_node_id_follows_its_node_across_addresses() {
+        let now = Instant::now();
+        let id = NodeId(7);
+        let mut storage = storage_of(&[8080], now);
+        let first = TestEndpoint(addr(8081));
+        assert_eq!(storage.set_node_id(&first, id), NodeIdUpdate::Added);
+        assert_eq!(storage.set_node_id(&first, id), NodeIdUpdate::Unchanged);
+        assert_eq!(
+            storage.set_node_id(&TestEndpoint(addr(9999)), id),
+            NodeIdUpdate::Unchanged
+        );
+
+        // The node restarts on another port: the new address takes over.
+        storage.drop(first, DepartureReason::Disconnected, now);
+        storage.add_unknown_participant(TestEndpoint(addr(8091)), addr(8090), now);
+        assert_eq!(
+            storage.set_node_id(&TestEndpoint(addr(8091)), id),
+            NodeIdUpdate::Moved {
+                previous: vec![addr(8080)],
+            }
+        );
+        assert_eq!(storage.node_addresses(id), vec![addr(8090), addr(8080)]);
+        assert_eq!(storage.node_addresses(NodeId(8)), Vec::new());
+        assert_eq!(
+            storage
+                .session_history(addr(8090))
+                .unwrap()
+                .sessions()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn two_connected_addresses_presenting_one_id_conflict() {
+        let now = Instant::now();
+        let id = NodeId(7);
+        let mut storage = storage_of(&[8080, 8090], now);
+        storage.set_node_id(&TestEndpoint(addr(8081)), id);
+        assert_eq!(
+            storage.set_node_id(&TestEndpoint(addr(8091)), id),
+            NodeIdUpdate::Conflict {
+                other: TestEndpoint(addr(8081)),
+                other_addr: addr(8080),
+            }
+        );
+        assert!(storage.contains_addr(addr(8080)));
+        assert!(storage.contains_addr(addr(8090)));
+    }
+
+    #[test]
+    fn topics_go_to_the_peers_wanting_them() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090, 8100], now);
+        let (selective, everything, unknown) = (
+            TestEndpoint(addr(8081)),
+            TestEndpoint(addr(8091)),
+            TestEndpoint(addr(8101)),
+        );
+        storage.set_interest(
+            &selective,
+            Interest::from_update(Some(vec!["metrics".to_owned()])),
+        );
+        storage.set_interest(&everything, Interest::from_update(None));
+        assert_eq!(storage.selective_count(), 1);
+
+        for endpoint in [&selective, &everything, &unknown] {
+            assert!(storage.wants_topic(endpoint, "metrics"));
+        }
+        assert!(!storage.wants_topic(&selective, "chat"));
+        assert!(storage.wants_topic(&everything, "chat"));
+        assert!(storage.wants_topic(&unknown, "chat"));
+        // A stranger is sent everything, like a peer of unknown interest.
+        assert!(storage.wants_topic(&TestEndpoint(addr(9999)), "chat"));
+    }
+
+    #[test]
+    fn a_peer_dialed_back_moves_to_its_outbound_connection() {
+        let now = Instant::now();
+        let mut storage = storage_of(&[8080, 8090], now);
+        let inbound = TestEndpoint(addr(8081));
+        storage.record_sent_kind(&inbound, MessageKind::Text);
+        let only: Vec<SocketAddr> = storage.inbound_only().iter().map(|p| p.public).collect();
+        assert_eq!(only, vec![addr(8080), addr(8090)]);
+
+        let outbound = TestEndpoint(addr(8080));
+        storage.add_connecting_participant(outbound.clone(), now, 0);
+        let only: Vec<SocketAddr> = storage.inbound_only().iter().map(|p| p.public).collect();
+        assert_eq!(only, vec![addr(8090)]);
+        assert_eq!(
+            storage.crossing(&outbound, addr(8080)),
+            Some((outbound.clone(), inbound.clone()))
+        );
+        storage.promote_connected(&outbound, now);
+        assert!(storage.connected_elsewhere(&inbound, addr(8080)));
+        assert_eq!(storage.direction_counts(), (2, 1));
+
+        assert!(storage.hand_over(&inbound, &outbound));
+        assert!(!storage.hand_over(&inbound, &outbound));
+        assert_eq!(storage.direction_counts(), (1, 1));
+        assert_eq!(storage.endpoint_of(addr(8080)), Some(outbound));
+        let summary = storage.summary(addr(8080), now).unwrap();
+        assert_eq!(summary.sent[MessageKind::Text.index()], 1);
     }
 }
@@ -0,0 +1,33 @@
+//! Test Support.
+//!
+//! The values the unit tests of the crate build their addresses, messages and entries from,
+//! and the scratch directories they write files to.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Returns the loopback address on `port`.
+pub(crate) fn addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// A fresh directory under the system temporary directory, removed with the value.
+///
+/// `name` must be unique across the crate, since the unit tests run in one process.
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("gossip-p2p-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
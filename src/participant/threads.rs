@@ -0,0 +1,437 @@
+//! Internal Threads.
+//!
+//! The event loop owns the state of a participant, but a few jobs block and run beside it:
+//! the shutdown signal watcher, the console, the admin socket and its connections, the
+//! request workers, the callback workers, the alert worker and the readers of the commands it
+//! runs, and the writer of the recording. `ThreadRegistry` spawns every one of them, so that:
+//!
+//! - each thread carries a name, such as `gossip-console`, shown by `top -H` and debuggers;
+//! - no more than `--max-threads` of them run at once, 32 by default; a spawn over the cap is
+//!   refused with a `SpawnError` naming the threads that hold it;
+//! - shutdown can wait for them: each thread is handed a `StopFlag`, and the workers reading
+//!   from a channel stop once it closes. After raising the flags and closing the channels,
+//!   the participant joins the threads, waiting at most `THREAD_JOIN_TIMEOUT`, and warns about
+//!   those still running.
+//!
+//! A thread blocked reading the standard input or an admin connection notices neither, so it
+//! is spawned with `Join::Detach` and left to the end of the process instead.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long shutdown waits for the internal threads to stop.
+pub const THREAD_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often shutdown checks whether the threads it waits for stopped.
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Tells a thread that the participant is shutting down.
+#[derive(Debug, Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    /// Determines whether the thread is asked to stop.
+    pub fn is_raised(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn raise(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// How shutdown treats a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Join {
+    /// Shutdown waits for the thread to stop.
+    Wait,
+    /// The thread may be blocked where it can not notice the shutdown, and is left behind.
+    Detach,
+}
+
+/// Enumerates the reasons a thread could not be spawned.
+#[derive(Debug)]
+pub enum SpawnError {
+    /// `cap` internal threads already run, listed in `live`.
+    Cap { cap: usize, live: String },
+    /// The system refused to create the thread.
+    Os(io::Error),
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::Cap { cap, live } => {
+                write!(
+                    f,
+                    "{} internal threads already run, the most allowed: {}",
+                    cap, live
+                )
+            }
+            SpawnError::Os(err) => write!(f, "the system refused a thread: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+impl From<SpawnError> for io::Error {
+    fn from(err: SpawnError) -> Self {
+        match err {
+            SpawnError::Os(err) => err,
+            err => io::Error::other(err.to_string()),
+        }
+    }
+}
+
+/// The counters of a `ThreadRegistry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// The threads running now.
+    pub live: usize,
+    /// The most threads allowed to run at once.
+    pub cap: usize,
+    /// The threads spawned so far.
+    pub spawned: u64,
+    /// The spawns refused because of the cap.
+    pub refused: u64,
+    /// The threads that ended with a panic.
+    pub panicked: u64,
+}
+
+/// A thread spawned through the registry.
+struct Tracked {
+    handle: JoinHandle<()>,
+    stop: StopFlag,
+    join: Join,
+}
+
+struct Registry {
+    cap: usize,
+    threads: Vec<Tracked>,
+    spawned: u64,
+    refused: u64,
+    panicked: u64,
+}
+
+impl Registry {
+    /// Forgets the threads that ended, counting those that panicked.
+    fn reap(&mut self) {
+        let (finished, running): (Vec<Tracked>, Vec<Tracked>) = std::mem::take(&mut self.threads)
+            .into_iter()
+            .partition(|tracked| tracked.handle.is_finished());
+        self.threads = running;
+        for tracked in finished {
+            if tracked.handle.join().is_err() {
+                self.panicked += 1;
+            }
+        }
+    }
+
+    /// Counts the running threads per name.
+    fn names(&self) -> BTreeMap<String, usize> {
+        let mut names = BTreeMap::new();
+        for tracked in &self.threads {
+            let name = tracked.handle.thread().name().unwrap_or("unnamed");
+            *names.entry(name.to_owned()).or_insert(0) += 1;
+        }
+        names
+    }
+}
+
+/// Spawns and keeps track of the internal threads of a participant.
+///
+/// Clones share the same threads and cap, so a thread can spawn others through its own
+/// clone.
+#[derive(Clone)]
+pub struct ThreadRegistry(Arc<Mutex<Registry>>);
+
+impl ThreadRegistry {
+    /// Constructs a registry allowing `cap` threads at once.
+    pub fn new(cap: usize) -> Self {
+        Self(Arc::new(Mutex::new(Registry {
+            cap,
+            threads: Vec::new(),
+            spawned: 0,
+            refused: 0,
+            panicked: 0,
+        })))
+    }
+
+    /// Spawns a thread named `name` running `body`, if the cap allows it.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the thread, at most 15 bytes to be shown whole by the system.
+    /// * `join` - Whether shutdown waits for the thread.
+    /// * `body` - The job of the thread, handed the flag raised at shutdown.
+    pub fn spawn(
+        &self,
+        name: &str,
+        join: Join,
+        body: impl FnOnce(StopFlag) + Send + 'static,
+    ) -> Result<(), SpawnError> {
+        let mut registry = self.lock();
+        registry.reap();
+        if registry.threads.len() >= registry.cap {
+            registry.refused += 1;
+            return Err(SpawnError::Cap {
+                cap: registry.cap,
+                live: format_names(&registry.names()),
+            });
+        }
+        let stop = StopFlag::default();
+        let flag = stop.clone();
+        let handle = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || body(flag))
+            .map_err(SpawnError::Os)?;
+        registry.spawned += 1;
+        registry.threads.push(Tracked { handle, stop, join });
+        Ok(())
+    }
+
+    /// Returns the running threads with their count per name, sorted by name.
+    pub fn live(&self) -> Vec<(String, usize)> {
+        let mut registry = self.lock();
+        registry.reap();
+        registry.names().into_iter().collect()
+    }
+
+    /// Returns the counters of the registry.
+    pub fn stats(&self) -> ThreadStats {
+        let mut registry = self.lock();
+        registry.reap();
+        ThreadStats {
+            live: registry.threads.len(),
+            cap: registry.cap,
+            spawned: registry.spawned,
+            refused: registry.refused,
+            panicked: registry.panicked,
+        }
+    }
+
+    /// Asks every thread to stop, and waits up to `timeout` for those spawned with
+    /// `Join::Wait`.
+    ///
+    /// # Returns
+    ///
+    /// The names of the waited for threads still running after `timeout`, which are left
+    /// behind like the detached ones.
+    pub fn shutdown(&self, timeout: Duration) -> Vec<String> {
+        let threads = std::mem::take(&mut self.lock().threads);
+        for tracked in &threads {
+            tracked.stop.raise();
+        }
+        let mut waiting: Vec<Tracked> = threads
+            .into_iter()
+            .filter(|tracked| tracked.join == Join::Wait)
+            .collect();
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let (finished, running): (Vec<Tracked>, Vec<Tracked>) = waiting
+                .into_iter()
+                .partition(|tracked| tracked.handle.is_finished());
+            for tracked in finished {
+                if tracked.handle.join().is_err() {
+                    self.lock().panicked += 1;
+                }
+            }
+            waiting = running;
+            if waiting.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        waiting
+            .iter()
+            .map(|tracked| {
+                tracked
+                    .handle
+                    .thread()
+                    .name()
+                    .unwrap_or("unnamed")
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Formats per-name thread counts as `name x2, other`.
+fn format_names(names: &BTreeMap<String, usize>) -> String {
+    if names.is_empty() {
+        return "none".to_owned();
+    }
+    names
+        .iter()
+        .map(|(name, count)| match count {
+            1 => name.clone(),
+            count => format!("{} x{}", name, count),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn threads_carry_their_names_and_stop_at_shutdown() {
+        let threads = ThreadRegistry::new(4);
+        let (sender, receiver) = mpsc::channel();
+        for name in ["gossip-test", "gossip-test", "gossip-other"] {
+            let sender = sender.clone();
+            threads
+                .spawn(name, Join::Wait, move |stop| {
+                    sender
+                        .send(thread::current().name().map(str::to_owned))
+                        .unwrap();
+                    while !stop.is_raised() {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+                .unwrap();
+        }
+        let mut names: Vec<Option<String>> = (0..3)
+            .map(|_| receiver.recv_timeout(WAIT).unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                Some("gossip-other".to_owned()),
+                Some("gossip-test".to_owned()),
+                Some("gossip-test".to_owned()),
+            ]
+        );
+        assert_eq!(
+            threads.live(),
+            [
+                ("gossip-other".to_owned(), 1),
+                ("gossip-test".to_owned(), 2)
+            ]
+        );
+
+        assert!(threads.shutdown(WAIT).is_empty());
+        let stats = threads.stats();
+        assert_eq!((stats.live, stats.spawned, stats.panicked), (0, 3, 0));
+        assert!(threads.live().is_empty());
+    }
+
+    #[test]
+    fn a_spawn_over_the_cap_is_refused_until_a_thread_ends() {
+        let threads = ThreadRegistry::new(2);
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        for _ in 0..2 {
+            let released = Arc::clone(&released);
+            threads
+                .spawn("gossip-busy", Join::Wait, move |_| {
+                    let _ = released.lock().unwrap().recv();
+                })
+                .unwrap();
+        }
+
+        match threads.spawn("gossip-late", Join::Wait, |_| ()) {
+            Err(SpawnError::Cap { cap, live }) => {
+                assert_eq!(cap, 2);
+                assert_eq!(live, "gossip-busy x2");
+            }
+            other => panic!("expected the cap to refuse, got {:?}", other.map(|_| ())),
+        }
+        let stats = threads.stats();
+        assert_eq!((stats.live, stats.cap, stats.refused), (2, 2, 1));
+
+        drop(release);
+        let deadline = Instant::now() + WAIT;
+        while threads.stats().live > 0 && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        threads.spawn("gossip-late", Join::Wait, |_| ()).unwrap();
+        assert!(threads.shutdown(WAIT).is_empty());
+        assert_eq!(threads.stats().spawned, 3);
+    }
+
+    #[test]
+    fn shutdown_names_the_threads_that_do_not_stop() {
+        let threads = ThreadRegistry::new(4);
+        let (release, released) = mpsc::channel::<()>();
+        threads
+            .spawn("gossip-stuck", Join::Wait, move |_| {
+                let _ = released.recv();
+            })
+            .unwrap();
+        threads
+            .spawn("gossip-polite", Join::Wait, |stop| {
+                while !stop.is_raised() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+            .unwrap();
+
+        let started = Instant::now();
+        let stuck = threads.shutdown(Duration::from_millis(50));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(stuck, ["gossip-stuck"]);
+        assert_eq!(threads.stats().live, 0);
+        drop(release);
+    }
+
+    #[test]
+    fn shutdown_leaves_detached_threads_behind() {
+        let threads = ThreadRegistry::new(4);
+        let (release, released) = mpsc::channel::<()>();
+        let (sender, stopped) = mpsc::channel();
+        threads
+            .spawn("gossip-stdin", Join::Detach, move |stop| {
+                let _ = released.recv();
+                sender.send(stop.is_raised()).unwrap();
+            })
+            .unwrap();
+
+        assert!(threads.shutdown(Duration::ZERO).is_empty());
+        drop(release);
+        assert_eq!(stopped.recv_timeout(WAIT), Ok(true));
+    }
+
+    #[test]
+    fn panicking_threads_are_counted() {
+        let threads = ThreadRegistry::new(4);
+        threads
+            .spawn("gossip-panic", Join::Wait, |_| panic!("on purpose"))
+            .unwrap();
+        assert!(threads.shutdown(WAIT).is_empty());
+        assert_eq!(threads.stats().panicked, 1);
+    }
+
+    #[test]
+    fn errors_describe_the_refusal() {
+        let cap = SpawnError::Cap {
+            cap: 2,
+            live: "gossip-console, gossip-request x2".to_owned(),
+        };
+        assert_eq!(
+            cap.to_string(),
+            "2 internal threads already run, the most allowed: gossip-console, gossip-request x2"
+        );
+        assert_eq!(io::Error::from(cap).kind(), io::ErrorKind::Other);
+
+        let os = SpawnError::Os(io::Error::new(io::ErrorKind::OutOfMemory, "no memory"));
+        assert_eq!(io::Error::from(os).kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(format_names(&BTreeMap::new()), "none");
+    }
+}
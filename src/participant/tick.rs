@@ -0,0 +1,260 @@
+//! Tick Health.
+//!
+//! A broadcast tick firing long after it was due most likely means the process did not run,
+//! not that the network failed: an overloaded machine left it unscheduled, or a laptop slept.
+//! `TickMonitor` compares the time between two broadcast ticks with the delay the first one
+//! armed and tells a late tick, worth a warning, from a frozen one, after which the participant
+//! resynchronizes at once (see `Participant::recover_from_freeze`).
+//!
+//! Monotonic time does not advance while the system is suspended on every platform, so the
+//! monitor also measures wall-clock time and keeps the longer of the two.
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long after a frozen tick the peers that disconnect are dialed again instead of being
+/// forgotten, since their departure probably comes from the freeze.
+pub const FREEZE_GRACE: Duration = Duration::from_secs(30);
+
+/// How a broadcast tick compares with the delay it was armed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickGap {
+    /// The tick fired within `late_factor` times its delay.
+    OnTime,
+    /// The tick fired more than `late_factor` times its delay after the previous one.
+    Late,
+    /// The tick fired more than `frozen_factor` times its delay after the previous one.
+    Frozen,
+}
+
+/// Classifies the `actual` time between two ticks armed `expected` apart.
+///
+/// # Parameters
+///
+/// * `expected` - The delay the previous tick armed.
+/// * `actual` - The time that elapsed until the tick fired.
+/// * `late_factor` - The overshoot making a tick late.
+/// * `frozen_factor` - The overshoot making a tick frozen, larger than `late_factor`.
+pub fn classify(
+    expected: Duration,
+    actual: Duration,
+    late_factor: u32,
+    frozen_factor: u32,
+) -> TickGap {
+    if actual > expected.saturating_mul(frozen_factor) {
+        TickGap::Frozen
+    } else if actual > expected.saturating_mul(late_factor) {
+        TickGap::Late
+    } else {
+        TickGap::OnTime
+    }
+}
+
+/// A tick that fired later than `late_factor` allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LateTick {
+    /// `TickGap::Late` or `TickGap::Frozen`.
+    pub gap: TickGap,
+    /// The delay the previous tick armed.
+    pub expected: Duration,
+    /// The time that elapsed until the tick fired.
+    pub actual: Duration,
+}
+
+/// The delay armed by the previous tick, and when it was armed.
+#[derive(Debug, Clone, Copy)]
+struct Armed {
+    delay: Duration,
+    at: Instant,
+    wall: SystemTime,
+}
+
+/// Watches the time between broadcast ticks.
+#[derive(Debug, Clone)]
+pub struct TickMonitor {
+    late_factor: u32,
+    frozen_factor: u32,
+    armed: Option<Armed>,
+    late_ticks: u64,
+    frozen_ticks: u64,
+    longest_gap: Duration,
+}
+
+impl TickMonitor {
+    /// Creates a monitor that saw no tick yet.
+    ///
+    /// # Parameters
+    ///
+    /// * `late_factor` - The overshoot making a tick late.
+    /// * `frozen_factor` - The overshoot making a tick frozen.
+    pub fn new(late_factor: u32, frozen_factor: u32) -> Self {
+        Self {
+            late_factor,
+            frozen_factor,
+            armed: None,
+            late_ticks: 0,
+            frozen_ticks: 0,
+            longest_gap: Duration::ZERO,
+        }
+    }
+
+    /// Sets the overshoots making a tick late and frozen, from the next armed tick on.
+    pub fn set_factors(&mut self, late_factor: u32, frozen_factor: u32) {
+        self.late_factor = late_factor;
+        self.frozen_factor = frozen_factor;
+    }
+
+    /// Records that the next tick was armed to fire after `delay`.
+    ///
+    /// # Parameters
+    ///
+    /// * `delay` - The delay of the timer.
+    /// * `now` - The current time.
+    /// * `wall` - The current wall-clock time.
+    pub fn armed(&mut self, delay: Duration, now: Instant, wall: SystemTime) {
+        self.armed = Some(Armed {
+            delay,
+            at: now,
+            wall,
+        });
+    }
+
+    /// Records that the armed tick fired.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The current time.
+    /// * `wall` - The current wall-clock time.
+    ///
+    /// # Returns
+    ///
+    /// The `LateTick` of a tick that fired late or frozen, counted; `None` otherwise.
+    pub fn fired(&mut self, now: Instant, wall: SystemTime) -> Option<LateTick> {
+        let armed = self.armed.take()?;
+        let monotonic = now.saturating_duration_since(armed.at);
+        let actual = wall
+            .duration_since(armed.wall)
+            .map_or(monotonic, |elapsed| elapsed.max(monotonic));
+        let gap = classify(armed.delay, actual, self.late_factor, self.frozen_factor);
+        if gap == TickGap::OnTime {
+            return None;
+        }
+
+        self.late_ticks += 1;
+        if gap == TickGap::Frozen {
+            self.frozen_ticks += 1;
+        }
+        self.longest_gap = self.longest_gap.max(actual);
+        Some(LateTick {
+            gap,
+            expected: armed.delay,
+            actual,
+        })
+    }
+
+    /// Returns the number of late ticks, frozen ones included.
+    pub fn late_ticks(&self) -> u64 {
+        self.late_ticks
+    }
+
+    /// Returns the number of frozen ticks.
+    pub fn frozen_ticks(&self) -> u64 {
+        self.frozen_ticks
+    }
+
+    /// Returns the longest time between two ticks that fired late.
+    pub fn longest_gap(&self) -> Duration {
+        self.longest_gap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn a_tick_is_late_past_one_factor_and_frozen_past_the_other() {
+        let classify = |actual| classify(ms(100), ms(actual), 2, 10);
+        assert_eq!(classify(0), TickGap::OnTime);
+        assert_eq!(classify(200), TickGap::OnTime);
+        assert_eq!(classify(201), TickGap::Late);
+        assert_eq!(classify(1000), TickGap::Late);
+        assert_eq!(classify(1001), TickGap::Frozen);
+        assert_eq!(
+            super::classify(Duration::MAX, Duration::MAX, 2, 10),
+            TickGap::OnTime
+        );
+    }
+
+    #[test]
+    fn only_an_armed_tick_is_measured() {
+        let (now, wall) = (Instant::now(), UNIX_EPOCH + Duration::from_secs(1_000));
+        let mut monitor = TickMonitor::new(2, 10);
+        assert_eq!(monitor.fired(now + ms(5000), wall), None);
+
+        monitor.armed(ms(100), now, wall);
+        assert_eq!(monitor.fired(now + ms(150), wall + ms(150)), None);
+        // The tick was measured, so firing again without arming it measures nothing.
+        assert_eq!(monitor.fired(now + ms(5000), wall + ms(5000)), None);
+        assert_eq!(monitor.late_ticks(), 0);
+    }
+
+    #[test]
+    fn late_and_frozen_ticks_are_counted_with_the_longest_gap() {
+        let (now, wall) = (Instant::now(), UNIX_EPOCH + Duration::from_secs(1_000));
+        let mut monitor = TickMonitor::new(2, 10);
+        monitor.armed(ms(100), now, wall);
+        assert_eq!(
+            monitor.fired(now + ms(300), wall + ms(300)),
+            Some(LateTick {
+                gap: TickGap::Late,
+                expected: ms(100),
+                actual: ms(300),
+            })
+        );
+
+        monitor.armed(ms(100), now + ms(300), wall + ms(300));
+        let frozen = monitor.fired(now + ms(2300), wall + ms(2300)).unwrap();
+        assert_eq!((frozen.gap, frozen.actual), (TickGap::Frozen, ms(2000)));
+
+        monitor.armed(ms(100), now + ms(2300), wall + ms(2300));
+        assert_eq!(
+            monitor.fired(now + ms(2700), wall + ms(2700)).unwrap().gap,
+            TickGap::Late
+        );
+        assert_eq!((monitor.late_ticks(), monitor.frozen_ticks()), (3, 1));
+        assert_eq!(monitor.longest_gap(), ms(2000));
+    }
+
+    #[test]
+    fn a_suspend_seen_only_by_the_wall_clock_is_a_freeze() {
+        let (now, wall) = (Instant::now(), UNIX_EPOCH + Duration::from_secs(1_000));
+        let mut monitor = TickMonitor::new(2, 10);
+        monitor.armed(ms(100), now, wall);
+        let tick = monitor.fired(now + ms(100), wall + Duration::from_secs(60));
+        assert_eq!(tick.map(|tick| tick.gap), Some(TickGap::Frozen));
+
+        // A wall clock set back falls back to the monotonic time.
+        monitor.armed(ms(100), now, wall);
+        let tick = monitor.fired(now + ms(300), wall - Duration::from_secs(60));
+        assert_eq!(tick.map(|tick| tick.actual), Some(ms(300)));
+    }
+
+    #[test]
+    fn new_factors_apply_to_the_next_tick() {
+        let (now, wall) = (Instant::now(), UNIX_EPOCH + Duration::from_secs(1_000));
+        let mut monitor = TickMonitor::new(2, 10);
+        monitor.set_factors(5, 20);
+        monitor.armed(ms(100), now, wall);
+        assert_eq!(monitor.fired(now + ms(300), wall + ms(300)), None);
+
+        monitor.armed(ms(100), now, wall);
+        let tick = monitor.fired(now + ms(1500), wall + ms(1500));
+        assert_eq!(tick.map(|tick| tick.gap), Some(TickGap::Late));
+    }
+}
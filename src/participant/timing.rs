@@ -0,0 +1,111 @@
+//! Peer Timing.
+//!
+//! Participants of the same network need not share a period: one may broadcast every second
+//! and another every ten minutes. Measured against our own period, a slow peer looks dead: it
+//! leaves dozens of our messages unanswered between two of its own, and the time since we last
+//! heard from it ranks it behind every fast peer in the lists we share. So each participant
+//! declares its effective period in the trailer of its handshake frames, after its interest,
+//! and what we expect of a peer is scaled by the period it declared:
+//!
+//! - the half-open threshold, which is also when a peer turns suspect, tolerates as many more
+//!   unanswered sends as we broadcast in one of its periods;
+//! - the staleness that orders and ages the shared peers leaves out one of its periods, the
+//!   silence expected of it.
+//!
+//! A peer that declares no period, an older build, is held to our own. An adaptive period
+//! travels in the next handshake frame, like the answer to a list exchange or a
+//! re-announcement. The broadcast doubles as the heartbeat, so the period is the only interval
+//! declared.
+//!
+//! When a declared period is longer than we let a peer stay silent before scaling, or more
+//! than `--period-skew-ratio` times ours or as many times shorter, the skew is logged once per
+//! connection, naming both periods, so the operators notice the configuration mismatch.
+
+use std::time::Duration;
+
+/// The number of our broadcasts that fit in one period of the peer, rounded up, at least one.
+///
+/// # Parameters
+///
+/// * `ours` - Our effective period.
+/// * `theirs` - The period the peer declared.
+pub fn broadcasts_per_period(ours: Duration, theirs: Duration) -> u32 {
+    let ours = ours.as_nanos().max(1);
+    let ratio = theirs.as_nanos().div_ceil(ours).max(1);
+    u32::try_from(ratio).unwrap_or(u32::MAX)
+}
+
+/// Scales the half-open threshold to the period of a peer.
+///
+/// # Parameters
+///
+/// * `threshold` - The unanswered sends tolerated from a peer of our period, `0` disabling the
+///   detection.
+/// * `ours` - Our effective period.
+/// * `theirs` - The period the peer declared, if any.
+pub fn half_open_threshold(threshold: u32, ours: Duration, theirs: Option<Duration>) -> u32 {
+    match theirs {
+        Some(theirs) => threshold.saturating_mul(broadcasts_per_period(ours, theirs)),
+        None => threshold,
+    }
+}
+
+/// Returns how stale a peer verified `age` ago is, beyond the silence expected of it.
+///
+/// # Parameters
+///
+/// * `age` - The time since the peer was last verified.
+/// * `theirs` - The period the peer declared, if any.
+pub fn staleness(age: Duration, theirs: Option<Duration>) -> Duration {
+    age.saturating_sub(theirs.unwrap_or_default())
+}
+
+/// Determines whether the period a peer declared is worth a warning.
+///
+/// # Parameters
+///
+/// * `ours` - Our effective period.
+/// * `theirs` - The period the peer declared.
+/// * `threshold` - The unscaled half-open threshold, `0` when the detection is disabled.
+/// * `ratio` - The tolerated ratio between both periods, `0` disabling the warning.
+pub fn skewed(ours: Duration, theirs: Duration, threshold: u32, ratio: u32) -> bool {
+    if ratio == 0 {
+        return false;
+    }
+    let silence = ours.saturating_mul(threshold);
+    let (shorter, longer) = (ours.min(theirs), ours.max(theirs));
+    (threshold > 0 && theirs > silence) || longer > shorter.saturating_mul(ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn counts_our_broadcasts_in_a_period_of_the_peer_rounded_up() {
+        assert_eq!(broadcasts_per_period(100 * MS, 100 * MS), 1);
+        assert_eq!(broadcasts_per_period(100 * MS, 1000 * MS), 10);
+        assert_eq!(broadcasts_per_period(100 * MS, 1001 * MS), 11);
+        // A faster peer still answers at least once per broadcast of ours.
+        assert_eq!(broadcasts_per_period(1000 * MS, 10 * MS), 1);
+        assert_eq!(broadcasts_per_period(Duration::ZERO, Duration::ZERO), 1);
+        assert_eq!(
+            broadcasts_per_period(Duration::from_nanos(1), Duration::MAX),
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn scales_the_half_open_threshold_to_the_declared_period() {
+        assert_eq!(half_open_threshold(10, 100 * MS, None), 10);
+        assert_eq!(half_open_threshold(10, 100 * MS, Some(100 * MS)), 10);
+        assert_eq!(half_open_threshold(10, 100 * MS, Some(1000 * MS)), 100);
+        assert_eq!(half_open_threshold(0, 100 * MS, Some(1000 * MS)), 0);
+        assert_eq!(
+            half_open_threshold(u32::MAX, 100 * MS, Some(1000 * MS)),
+            u32::MAX
+        );
+    }
+}
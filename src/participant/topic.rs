@@ -0,0 +1,917 @@
+//! Application Topics.
+//!
+//! This module lets several subsystems share one participant by gossiping on named topics
+//! instead of stuffing everything into `Text` messages. `TopicRegistry` keeps the subscriptions
+//! to each topic and dispatches every received `Message::Topic` to their callbacks, which the
+//! workers of its `CallbackPool` run off the event loop (see the `callback` module).
+//!
+//! A topic received without any subscriber is counted but not delivered. With an allowlist
+//! (`--topics`), topics outside of it are dropped before any callback is even looked up. A
+//! callback that panics is isolated: the panic is caught and counted, and the other callbacks
+//! of the topic still run.
+//!
+//! An ordered subscription receives the messages of each publisher in publish order instead
+//! of arrival order, through the `ReorderBuffer`s the registry keeps per publisher and topic
+//! (see the `reorder` module). The buffers only exist for topics with an ordered subscriber.
+//!
+//! The schema handlers of a topic and its fallbacks receive the versioned payloads, each by
+//! the rules of the `schema` module.
+
+use crate::participant::callback::{Call, CallbackPool, CallbackStats};
+use crate::participant::reorder::{Delivery, ReorderBuffer};
+use crate::participant::schema::{
+    most_specific, Schema, SchemaSupport, VersionRange, MAX_SCHEMA_ENTRIES,
+};
+use crate::participant::threads::{SpawnError, ThreadRegistry};
+use crate::printer::is_unprintable;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The longest accepted topic name, in bytes.
+pub const MAX_TOPIC_LEN: usize = 64;
+
+/// Enumerates the reasons a topic name is refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TopicError {
+    /// The topic name is empty.
+    Empty,
+    /// The topic name is longer than `MAX_TOPIC_LEN` bytes.
+    TooLong,
+    /// The topic name contains a control character, or another character `is_unprintable`
+    /// refuses.
+    ControlCharacter,
+}
+
+impl fmt::Display for TopicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopicError::Empty => write!(f, "topic names can not be empty"),
+            TopicError::TooLong => {
+                write!(f, "topic names are limited to {} bytes", MAX_TOPIC_LEN)
+            }
+            TopicError::ControlCharacter => {
+                write!(
+                    f,
+                    "topic names can not contain control or formatting characters"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TopicError {}
+
+/// Checks that `topic` can be published or subscribed to.
+pub fn validate_topic(topic: &str) -> Result<(), TopicError> {
+    if topic.is_empty() {
+        return Err(TopicError::Empty);
+    }
+    if topic.len() > MAX_TOPIC_LEN {
+        return Err(TopicError::TooLong);
+    }
+    if topic.chars().any(is_unprintable) {
+        return Err(TopicError::ControlCharacter);
+    }
+    Ok(())
+}
+
+/// Identifies one subscription, so it can be removed later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionHandle(u64);
+
+impl SubscriptionHandle {
+    /// Returns the number shown to console users for this subscription.
+    pub fn id(self) -> u64 {
+        self.0
+    }
+
+    /// Rebuilds a handle from the number shown to console users.
+    pub fn from_id(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// The order a subscription receives the messages of a topic in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    /// On arrival.
+    Arrival,
+    /// In the publish order of each publisher.
+    Publish,
+}
+
+/// What happened to a received topic message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// The message was queued for this many callbacks.
+    Delivered(usize),
+    /// Nobody is subscribed to the topic.
+    Unsubscribed,
+    /// The topic is not in the allowlist.
+    Filtered,
+    /// The topic has schema handlers, none of which supports the schema of the payload, and
+    /// neither a fallback nor a plain subscriber.
+    Unsupported,
+}
+
+/// The subscriptions of a participant and the counters of received topic messages.
+pub struct TopicRegistry {
+    subscribers: HashMap<String, Vec<(SubscriptionHandle, Order)>>,
+    schemas: HashMap<String, Vec<(SubscriptionHandle, String, VersionRange)>>,
+    fallbacks: HashMap<String, Vec<SubscriptionHandle>>,
+    callbacks: CallbackPool,
+    allowlist: Option<HashSet<String>>,
+    buffers: HashMap<(SocketAddr, String), ReorderBuffer>,
+    reorder_capacity: usize,
+    reorder_window: Duration,
+    next_id: u64,
+    delivered: u64,
+    unsubscribed: u64,
+    filtered: u64,
+    unsupported: u64,
+    gaps: u64,
+    duplicates: u64,
+}
+
+impl TopicRegistry {
+    /// Constructs an empty `TopicRegistry`.
+    ///
+    /// # Parameters
+    ///
+    /// * `allowlist` - The only topics accepted from peers, or `None` to accept every topic.
+    /// * `reorder_capacity` - The number of messages an ordered subscription holds per
+    ///   publisher while it waits for a missing one.
+    /// * `reorder_window` - How long an ordered subscription waits for a missing message.
+    /// * `workers` - The number of threads running the callbacks.
+    /// * `threads` - The registry the workers are spawned through.
+    pub fn new(
+        allowlist: Option<Vec<String>>,
+        reorder_capacity: usize,
+        reorder_window: Duration,
+        workers: usize,
+        threads: &ThreadRegistry,
+    ) -> Result<Self, SpawnError> {
+        Ok(Self {
+            subscribers: HashMap::new(),
+            schemas: HashMap::new(),
+            fallbacks: HashMap::new(),
+            callbacks: CallbackPool::new(workers, threads)?,
+            allowlist: allowlist.map(|topics| topics.into_iter().collect()),
+            buffers: HashMap::new(),
+            reorder_capacity,
+            reorder_window,
+            next_id: 1,
+            delivered: 0,
+            unsubscribed: 0,
+            filtered: 0,
+            unsupported: 0,
+            gaps: 0,
+            duplicates: 0,
+        })
+    }
+
+    /// Subscribes `callback` to `topic`; a topic can have any number of callbacks.
+    ///
+    /// # Returns
+    ///
+    /// The handle removing this subscription, or the reason `topic` is not a valid name.
+    pub fn subscribe(
+        &mut self,
+        topic: &str,
+        mut callback: impl FnMut(SocketAddr, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.add(topic, Order::Arrival)?;
+        // Only the payloads of the arrivals are queued for an unordered subscription.
+        let callback = move |from, call| match call {
+            Call::Delivery(Delivery::Message { payload, .. })
+            | Call::Delivery(Delivery::Unsequenced(payload)) => callback(from, payload),
+            Call::Delivery(Delivery::Gap { .. }) | Call::Versioned(..) => {}
+        };
+        self.callbacks.add(handle.0, Box::new(callback), false);
+        Ok(handle)
+    }
+
+    /// Subscribes `callback` to `topic`, in the publish order of each publisher.
+    ///
+    /// # Returns
+    ///
+    /// The handle removing this subscription, or the reason `topic` is not a valid name.
+    pub fn subscribe_ordered(
+        &mut self,
+        topic: &str,
+        mut callback: impl FnMut(SocketAddr, Delivery) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        let handle = self.add(topic, Order::Publish)?;
+        let callback = move |from, call| {
+            if let Call::Delivery(delivery) = call {
+                callback(from, delivery)
+            }
+        };
+        self.callbacks.add(handle.0, Box::new(callback), true);
+        Ok(handle)
+    }
+
+    /// Subscribes `callback` to the payloads published on `topic` with the schema `name`, at
+    /// a version of `range`; the callback gets the version along with the payload.
+    ///
+    /// # Returns
+    ///
+    /// The handle removing this subscription, or the reason `topic` or `name` is not a valid
+    /// name.
+    pub fn subscribe_schema(
+        &mut self,
+        topic: &str,
+        name: &str,
+        range: VersionRange,
+        mut callback: impl FnMut(SocketAddr, u32, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        validate_topic(topic)?;
+        validate_topic(name)?;
+        let handle = self.next_handle();
+        self.schemas
+            .entry(topic.to_owned())
+            .or_default()
+            .push((handle, name.to_owned(), range));
+        let callback = move |from, call| {
+            if let Call::Versioned(schema, payload) = call {
+                callback(from, schema.version, payload)
+            }
+        };
+        self.callbacks.add(handle.0, Box::new(callback), false);
+        Ok(handle)
+    }
+
+    /// Registers `callback` for the versioned payloads of `topic` no schema handler supports,
+    /// with the schema they declared.
+    ///
+    /// # Returns
+    ///
+    /// The handle removing this fallback, or the reason `topic` is not a valid name.
+    pub fn on_unsupported_schema(
+        &mut self,
+        topic: &str,
+        mut callback: impl FnMut(SocketAddr, Schema, Vec<u8>) + Send + 'static,
+    ) -> Result<SubscriptionHandle, TopicError> {
+        validate_topic(topic)?;
+        let handle = self.next_handle();
+        self.fallbacks
+            .entry(topic.to_owned())
+            .or_default()
+            .push(handle);
+        let callback = move |from, call| {
+            if let Call::Versioned(schema, payload) = call {
+                callback(from, schema, payload)
+            }
+        };
+        self.callbacks.add(handle.0, Box::new(callback), false);
+        Ok(handle)
+    }
+
+    fn next_handle(&mut self) -> SubscriptionHandle {
+        let handle = SubscriptionHandle(self.next_id);
+        self.next_id += 1;
+        handle
+    }
+
+    fn add(&mut self, topic: &str, order: Order) -> Result<SubscriptionHandle, TopicError> {
+        validate_topic(topic)?;
+
+        let handle = self.next_handle();
+        self.subscribers
+            .entry(topic.to_owned())
+            .or_default()
+            .push((handle, order));
+        Ok(handle)
+    }
+
+    /// Removes a subscription.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `handle` was subscribed.
+    pub fn unsubscribe(&mut self, handle: SubscriptionHandle) -> bool {
+        let mut removed = false;
+        self.subscribers.retain(|_, callbacks| {
+            let before = callbacks.len();
+            callbacks.retain(|(subscribed, _)| *subscribed != handle);
+            removed |= callbacks.len() != before;
+            !callbacks.is_empty()
+        });
+        self.schemas.retain(|_, handlers| {
+            let before = handlers.len();
+            handlers.retain(|(subscribed, ..)| *subscribed != handle);
+            removed |= handlers.len() != before;
+            !handlers.is_empty()
+        });
+        self.fallbacks.retain(|_, fallbacks| {
+            let before = fallbacks.len();
+            fallbacks.retain(|subscribed| *subscribed != handle);
+            removed |= fallbacks.len() != before;
+            !fallbacks.is_empty()
+        });
+        let subscribers = &self.subscribers;
+        self.buffers
+            .retain(|(_, topic), _| has_ordered(subscribers, topic));
+        self.callbacks.remove(handle.0);
+        removed
+    }
+
+    /// Queues a received topic message for the callbacks subscribed to its topic.
+    ///
+    /// The unordered callbacks get it at once. The ordered ones get what the reorder buffer of
+    /// the publisher releases, which may be nothing until a missing message arrives or
+    /// `expire` gives up on it.
+    ///
+    /// # Parameters
+    ///
+    /// * `from` - The public address of the participant that published the message.
+    /// * `topic` - The topic of the message.
+    /// * `seq` - The sequence number of the message, `None` from a publisher too old to send
+    ///   one.
+    /// * `schema` - The schema the payload declared, if any.
+    /// * `payload` - The content of the message.
+    /// * `now` - The current time.
+    pub fn dispatch(
+        &mut self,
+        from: SocketAddr,
+        topic: &str,
+        seq: Option<u64>,
+        schema: Option<Schema>,
+        payload: Vec<u8>,
+        now: Instant,
+    ) -> Dispatch {
+        if let Some(allowlist) = &self.allowlist {
+            if !allowlist.contains(topic) {
+                self.filtered += 1;
+                return Dispatch::Filtered;
+            }
+        }
+
+        let routed = schema.map(|schema| self.dispatch_schema(from, topic, schema, &payload));
+        let Some(callbacks) = self.subscribers.get_mut(topic) else {
+            return match routed {
+                Some(0) => Dispatch::Unsupported,
+                Some(reached) => {
+                    self.delivered += 1;
+                    Dispatch::Delivered(reached)
+                }
+                None => {
+                    self.unsubscribed += 1;
+                    Dispatch::Unsubscribed
+                }
+            };
+        };
+
+        for (handle, order) in callbacks.iter() {
+            if *order == Order::Arrival {
+                let delivery = Delivery::Unsequenced(payload.clone());
+                self.callbacks
+                    .deliver(handle.0, from, Call::Delivery(delivery));
+            }
+        }
+        let reached = callbacks.len() + routed.unwrap_or(0);
+        self.delivered += 1;
+
+        if has_ordered(&self.subscribers, topic) {
+            let deliveries = match seq {
+                Some(seq) => {
+                    let (capacity, window) = (self.reorder_capacity, self.reorder_window);
+                    self.buffers
+                        .entry((from, topic.to_owned()))
+                        .or_insert_with(|| ReorderBuffer::new(capacity, window))
+                        .push(seq, payload, now)
+                }
+                None => Some(vec![Delivery::Unsequenced(payload)]),
+            };
+            match deliveries {
+                Some(deliveries) => self.deliver_ordered(from, topic, deliveries),
+                None => self.duplicates += 1,
+            }
+        }
+        Dispatch::Delivered(reached)
+    }
+
+    /// Queues a versioned payload for the schema handler of its version on `topic`, or for
+    /// the fallbacks of the topic when no handler supports it.
+    ///
+    /// # Returns
+    ///
+    /// The number of callbacks the payload was queued for.
+    fn dispatch_schema(
+        &mut self,
+        from: SocketAddr,
+        topic: &str,
+        schema: Schema,
+        payload: &[u8],
+    ) -> usize {
+        let handlers = self.schemas.get(topic).into_iter().flatten();
+        let candidates = handlers
+            .filter(|(_, name, _)| *name == schema.name)
+            .map(|(handle, _, range)| (handle.0, *range));
+        if let Some(id) = most_specific(candidates, schema.version) {
+            self.callbacks
+                .deliver(id, from, Call::Versioned(schema, payload.to_vec()));
+            return 1;
+        }
+        self.unsupported += 1;
+        let fallbacks = self.fallbacks.get(topic).map_or(&[][..], Vec::as_slice);
+        for handle in fallbacks {
+            let call = Call::Versioned(schema.clone(), payload.to_vec());
+            self.callbacks.deliver(handle.0, from, call);
+        }
+        fallbacks.len()
+    }
+
+    /// Gives up on the missing messages waited for longer than the reorder window, and hands
+    /// the messages held behind them to the ordered subscribers.
+    pub fn expire(&mut self, now: Instant) {
+        let released: Vec<_> = self
+            .buffers
+            .iter_mut()
+            .map(|((from, topic), buffer)| (*from, topic.clone(), buffer.expire(now)))
+            .collect();
+        for (from, topic, deliveries) in released {
+            self.deliver_ordered(from, &topic, deliveries);
+        }
+    }
+
+    /// Hands the messages held for a publisher that is gone to the ordered subscribers, and
+    /// forgets its sequence numbers, which start over when it comes back.
+    ///
+    /// # Parameters
+    ///
+    /// * `from` - The public address of the publisher.
+    pub fn forget_publisher(&mut self, from: SocketAddr) {
+        let topics: Vec<String> = self
+            .buffers
+            .keys()
+            .filter(|(publisher, _)| *publisher == from)
+            .map(|(_, topic)| topic.clone())
+            .collect();
+        for topic in topics {
+            if let Some(mut buffer) = self.buffers.remove(&(from, topic.clone())) {
+                let deliveries = buffer.flush();
+                self.deliver_ordered(from, &topic, deliveries);
+            }
+        }
+    }
+
+    /// Returns the time the next missing message is given up at, if any is waited for.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.buffers
+            .values()
+            .filter_map(ReorderBuffer::deadline)
+            .min()
+    }
+
+    /// Queues every delivery for the ordered callbacks of `topic`, in order.
+    fn deliver_ordered(&mut self, from: SocketAddr, topic: &str, deliveries: Vec<Delivery>) {
+        let Some(callbacks) = self.subscribers.get(topic) else {
+            return;
+        };
+        for delivery in deliveries {
+            if matches!(delivery, Delivery::Gap { .. }) {
+                self.gaps += 1;
+            }
+            for (handle, order) in callbacks.iter() {
+                if *order == Order::Publish {
+                    let call = Call::Delivery(delivery.clone());
+                    self.callbacks.deliver(handle.0, from, call);
+                }
+            }
+        }
+    }
+
+    /// Lets the callback workers run the deliveries already queued, then stop.
+    pub fn close(&mut self) {
+        self.callbacks.close();
+    }
+
+    /// Returns the topic and the callback counters of every subscription, by id.
+    pub fn callback_stats(&self) -> Vec<(SubscriptionHandle, String, CallbackStats)> {
+        let plain = self.subscribers.iter().flat_map(|(topic, callbacks)| {
+            callbacks.iter().map(move |(handle, _)| (*handle, topic))
+        });
+        let schemas = self.schemas.iter().flat_map(|(topic, handlers)| {
+            handlers.iter().map(move |(handle, ..)| (*handle, topic))
+        });
+        let fallbacks = self
+            .fallbacks
+            .iter()
+            .flat_map(|(topic, fallbacks)| fallbacks.iter().map(move |handle| (*handle, topic)));
+        let topics: HashMap<SubscriptionHandle, &String> =
+            plain.chain(schemas).chain(fallbacks).collect();
+        self.callbacks
+            .stats()
+            .into_iter()
+            .filter_map(|(id, stats)| {
+                let handle = SubscriptionHandle(id);
+                topics
+                    .get(&handle)
+                    .map(|topic| (handle, (*topic).clone(), stats))
+            })
+            .collect()
+    }
+
+    /// Returns the callback counters summed over every subscription, removed ones included.
+    pub fn callback_totals(&self) -> CallbackStats {
+        self.callbacks.totals()
+    }
+
+    /// Returns the number of threads running the callbacks.
+    pub fn callback_workers(&self) -> usize {
+        self.callbacks.workers()
+    }
+
+    /// Returns the topics with at least one subscriber, schema handler or fallback that the
+    /// allowlist accepts, sorted.
+    pub fn subscribed_topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self
+            .subscribers
+            .keys()
+            .chain(self.schemas.keys())
+            .chain(self.fallbacks.keys())
+            .filter(|topic| self.allows(topic))
+            .cloned()
+            .collect();
+        topics.sort();
+        topics.dedup();
+        topics
+    }
+
+    /// Returns the schemas handled on the topics the allowlist accepts, sorted, at most
+    /// `MAX_SCHEMA_ENTRIES` of them.
+    pub fn schema_support(&self) -> Vec<SchemaSupport> {
+        let mut schemas: Vec<SchemaSupport> = self
+            .schemas
+            .iter()
+            .filter(|(topic, _)| self.allows(topic))
+            .flat_map(|(topic, handlers)| {
+                handlers.iter().map(|(_, name, range)| SchemaSupport {
+                    topic: topic.clone(),
+                    name: name.clone(),
+                    range: *range,
+                })
+            })
+            .collect();
+        schemas.sort();
+        schemas.dedup();
+        schemas.truncate(MAX_SCHEMA_ENTRIES);
+        schemas
+    }
+
+    /// Determines whether the allowlist, if any, accepts `topic`.
+    fn allows(&self, topic: &str) -> bool {
+        self.allowlist
+            .as_ref()
+            .is_none_or(|allowlist| allowlist.contains(topic))
+    }
+
+    /// Returns the number of subscriptions, schema handlers and fallbacks, over all topics.
+    pub fn subscriptions(&self) -> usize {
+        self.subscribers.values().map(Vec::len).sum::<usize>()
+            + self.schemas.values().map(Vec::len).sum::<usize>()
+            + self.fallbacks.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Returns the number of received messages delivered to at least one callback.
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Returns the number of received messages nobody was subscribed to.
+    pub fn unsubscribed(&self) -> u64 {
+        self.unsubscribed
+    }
+
+    /// Returns the number of received messages dropped by the allowlist.
+    pub fn filtered(&self) -> u64 {
+        self.filtered
+    }
+
+    /// Returns the number of received versioned payloads no schema handler supported,
+    /// whether a fallback got them or not.
+    pub fn unsupported(&self) -> u64 {
+        self.unsupported
+    }
+
+    /// Returns the number of callback invocations that panicked.
+    pub fn panicked(&self) -> u64 {
+        self.callbacks.totals().panicked
+    }
+
+    /// Returns the number of messages held for ordered subscribers behind a missing one.
+    pub fn held(&self) -> usize {
+        self.buffers.values().map(ReorderBuffer::held).sum()
+    }
+
+    /// Returns the number of gaps reported to ordered subscribers.
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+
+    /// Returns the number of messages an ordered subscription already had, and dropped.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
+
+/// Determines whether `topic` has an ordered subscriber.
+fn has_ordered(
+    subscribers: &HashMap<String, Vec<(SubscriptionHandle, Order)>>,
+    topic: &str,
+) -> bool {
+    subscribers
+        .get(topic)
+        .is_some_and(|callbacks| callbacks.iter().any(|(_, order)| *order == Order::Publish))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    use std::sync::mpsc::{self, Receiver};
+
+    /// How long a test waits for a worker to run a callback.
+    const WAIT: Duration = Duration::from_secs(2);
+
+    /// Returns a registry accepting `allowlist`, if any, served by its workers, and the
+    /// registry of the workers.
+    fn registry(allowlist: Option<&[&str]>) -> (TopicRegistry, ThreadRegistry) {
+        let threads = ThreadRegistry::new(2);
+        let allowlist = allowlist.map(|topics| topics.iter().map(|t| t.to_string()).collect());
+        let topics = TopicRegistry::new(allowlist, 4, Duration::from_millis(500), 2, &threads)
+            .expect("the workers can be spawned");
+        (topics, threads)
+    }
+
+    /// Subscribes to `topic` with a callback sending what it receives.
+    fn subscribe(
+        topics: &mut TopicRegistry,
+        topic: &str,
+    ) -> (SubscriptionHandle, Receiver<(SocketAddr, Vec<u8>)>) {
+        let (sender, received) = mpsc::channel();
+        let handle = topics
+            .subscribe(topic, move |from, payload| {
+                let _ = sender.send((from, payload));
+            })
+            .expect("the topic is valid");
+        (handle, received)
+    }
+
+    fn publish(topics: &mut TopicRegistry, topic: &str, payload: &[u8]) -> Dispatch {
+        topics.dispatch(
+            addr(8000),
+            topic,
+            None,
+            None,
+            payload.to_vec(),
+            Instant::now(),
+        )
+    }
+
+    #[test]
+    fn refuses_invalid_topic_names() {
+        assert_eq!(validate_topic("config"), Ok(()));
+        assert_eq!(validate_topic("ünïcode/with spaces"), Ok(()));
+        assert_eq!(validate_topic(&"t".repeat(MAX_TOPIC_LEN)), Ok(()));
+        assert_eq!(validate_topic(""), Err(TopicError::Empty));
+        assert_eq!(
+            validate_topic(&"t".repeat(MAX_TOPIC_LEN + 1)),
+            Err(TopicError::TooLong)
+        );
+        assert_eq!(validate_topic("a\nb"), Err(TopicError::ControlCharacter));
+        assert_eq!(
+            validate_topic("a\u{202e}b"),
+            Err(TopicError::ControlCharacter)
+        );
+
+        let (mut topics, _threads) = registry(None);
+        assert_eq!(
+            topics.subscribe("", |_, _| {}).err(),
+            Some(TopicError::Empty)
+        );
+        assert_eq!(topics.subscriptions(), 0);
+    }
+
+    #[test]
+    fn delivers_a_topic_to_each_of_its_subscribers() {
+        let (mut topics, _threads) = registry(None);
+        let (_, first) = subscribe(&mut topics, "config");
+        let (_, second) = subscribe(&mut topics, "config");
+        let (_, other) = subscribe(&mut topics, "presence");
+
+        assert_eq!(
+            publish(&mut topics, "config", b"v2"),
+            Dispatch::Delivered(2)
+        );
+        assert_eq!(first.recv_timeout(WAIT), Ok((addr(8000), b"v2".to_vec())));
+        assert_eq!(second.recv_timeout(WAIT), Ok((addr(8000), b"v2".to_vec())));
+        assert!(other.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(topics.delivered(), 1);
+        assert_eq!(topics.subscriptions(), 3);
+        assert_eq!(topics.subscribed_topics(), ["config", "presence"]);
+    }
+
+    #[test]
+    fn counts_the_topics_nobody_subscribed_to() {
+        let (mut topics, _threads) = registry(None);
+        assert_eq!(
+            publish(&mut topics, "config", b"v2"),
+            Dispatch::Unsubscribed
+        );
+        assert_eq!(topics.unsubscribed(), 1);
+        assert_eq!(topics.delivered(), 0);
+    }
+
+    #[test]
+    fn an_unsubscribed_callback_gets_nothing_more() {
+        let (mut topics, _threads) = registry(None);
+        let (handle, received) = subscribe(&mut topics, "config");
+        let (_, kept) = subscribe(&mut topics, "config");
+
+        assert!(topics.unsubscribe(handle));
+        assert!(!topics.unsubscribe(handle));
+        assert!(!topics.unsubscribe(SubscriptionHandle::from_id(999)));
+        assert_eq!(
+            publish(&mut topics, "config", b"v3"),
+            Dispatch::Delivered(1)
+        );
+        assert_eq!(kept.recv_timeout(WAIT), Ok((addr(8000), b"v3".to_vec())));
+        assert!(received.try_recv().is_err());
+
+        drop(kept);
+        let (handle, _) = subscribe(&mut topics, "presence");
+        assert!(topics.unsubscribe(handle));
+        assert_eq!(topics.subscribed_topics(), ["config"]);
+        assert_eq!(
+            publish(&mut topics, "presence", b"up"),
+            Dispatch::Unsubscribed
+        );
+    }
+
+    #[test]
+    fn drops_the_topics_outside_the_allowlist() {
+        let (mut topics, _threads) = registry(Some(&["config"]));
+        let (_, received) = subscribe(&mut topics, "presence");
+        let (_, config) = subscribe(&mut topics, "config");
+
+        assert_eq!(publish(&mut topics, "presence", b"up"), Dispatch::Filtered);
+        assert_eq!(publish(&mut topics, "other", b"x"), Dispatch::Filtered);
+        assert_eq!(
+            publish(&mut topics, "config", b"v2"),
+            Dispatch::Delivered(1)
+        );
+        assert_eq!(config.recv_timeout(WAIT), Ok((addr(8000), b"v2".to_vec())));
+        assert!(received.try_recv().is_err());
+        assert_eq!(topics.filtered(), 2);
+        // A subscription the allowlist refuses is not announced to the peers.
+        assert_eq!(topics.subscribed_topics(), ["config"]);
+    }
+
+    #[test]
+    fn a_panicking_callback_spares_the_others() {
+        let (mut topics, _threads) = registry(None);
+        topics
+            .subscribe("config", |_, _| panic!("the subscriber is broken"))
+            .unwrap();
+        let (_, received) = subscribe(&mut topics, "config");
+
+        for payload in [b"v1", b"v2"] {
+            assert_eq!(
+                publish(&mut topics, "config", payload),
+                Dispatch::Delivered(2)
+            );
+            assert_eq!(
+                received.recv_timeout(WAIT),
+                Ok((addr(8000), payload.to_vec()))
+            );
+        }
+        let deadline = Instant::now() + WAIT;
+        while topics.panicked() < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(topics.panicked(), 2);
+    }
+
+    /// Subscribes to `topic` in publish order with a callback sending what it receives.
+    fn subscribe_ordered(
+        topics: &mut TopicRegistry,
+        topic: &str,
+    ) -> (SubscriptionHandle, Receiver<Delivery>) {
+        let (sender, received) = mpsc::channel();
+        let handle = topics
+            .subscribe_ordered(topic, move |_, delivery| {
+                let _ = sender.send(delivery);
+            })
+            .expect("the topic is valid");
+        (handle, received)
+    }
+
+    fn publish_seq(topics: &mut TopicRegistry, seq: u64, now: Instant) -> Dispatch {
+        topics.dispatch(addr(8000), "log", Some(seq), None, vec![seq as u8], now)
+    }
+
+    fn message(seq: u64) -> Delivery {
+        Delivery::Message {
+            seq,
+            payload: vec![seq as u8],
+        }
+    }
+
+    fn next(received: &Receiver<Delivery>) -> Delivery {
+        received.recv_timeout(WAIT).expect("a delivery is made")
+    }
+
+    #[test]
+    fn an_ordered_subscription_gets_the_publish_order() {
+        let now = Instant::now();
+        let (mut topics, _threads) = registry(None);
+        let (_, ordered) = subscribe_ordered(&mut topics, "log");
+        let (_, arrival) = subscribe(&mut topics, "log");
+
+        for seq in [1, 3, 2] {
+            assert_eq!(publish_seq(&mut topics, seq, now), Dispatch::Delivered(2));
+        }
+        for seq in [1, 3, 2] {
+            assert_eq!(
+                arrival.recv_timeout(WAIT),
+                Ok((addr(8000), vec![seq as u8]))
+            );
+        }
+        for seq in [1, 2, 3] {
+            assert_eq!(next(&ordered), message(seq));
+        }
+        assert_eq!(topics.held(), 0);
+
+        publish_seq(&mut topics, 2, now);
+        assert_eq!(topics.duplicates(), 1);
+        topics.dispatch(addr(8000), "log", None, None, b"old".to_vec(), now);
+        assert_eq!(next(&ordered), Delivery::Unsequenced(b"old".to_vec()));
+    }
+
+    #[test]
+    fn reports_a_gap_once_the_window_is_over() {
+        let now = Instant::now();
+        let (mut topics, _threads) = registry(None);
+        let (_, ordered) = subscribe_ordered(&mut topics, "log");
+
+        publish_seq(&mut topics, 1, now);
+        publish_seq(&mut topics, 4, now);
+        assert_eq!(next(&ordered), message(1));
+        assert_eq!(topics.held(), 1);
+        assert_eq!(
+            topics.next_deadline(),
+            Some(now + Duration::from_millis(500))
+        );
+
+        topics.expire(now + Duration::from_millis(499));
+        assert!(ordered.recv_timeout(Duration::from_millis(50)).is_err());
+        topics.expire(now + Duration::from_millis(500));
+        assert_eq!(next(&ordered), Delivery::Gap { from: 2, to: 3 });
+        assert_eq!(next(&ordered), message(4));
+        assert_eq!(topics.gaps(), 1);
+        assert_eq!(topics.next_deadline(), None);
+    }
+
+    #[test]
+    fn flushes_the_messages_of_a_publisher_that_is_gone() {
+        let now = Instant::now();
+        let (mut topics, _threads) = registry(None);
+        let (_, ordered) = subscribe_ordered(&mut topics, "log");
+
+        publish_seq(&mut topics, 5, now);
+        publish_seq(&mut topics, 7, now);
+        assert_eq!(next(&ordered), message(5));
+        topics.forget_publisher(addr(8000));
+        assert_eq!(next(&ordered), Delivery::Gap { from: 6, to: 6 });
+        assert_eq!(next(&ordered), message(7));
+
+        // Its sequence numbers start over when it comes back.
+        publish_seq(&mut topics, 1, now);
+        assert_eq!(next(&ordered), message(1));
+    }
+
+    #[test]
+    fn keeps_no_buffer_without_an_ordered_subscriber() {
+        let now = Instant::now();
+        let (mut topics, _threads) = registry(None);
+        let (_, _arrival) = subscribe(&mut topics, "log");
+        publish_seq(&mut topics, 1, now);
+        publish_seq(&mut topics, 3, now);
+        assert_eq!(topics.held(), 0);
+
+        let (handle, _ordered) = subscribe_ordered(&mut topics, "log");
+        publish_seq(&mut topics, 5, now);
+        publish_seq(&mut topics, 7, now);
+        assert_eq!(topics.held(), 1);
+        assert!(topics.unsubscribe(handle));
+        assert_eq!(topics.held(), 0);
+        assert_eq!(topics.next_deadline(), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Tick Traces.
+//!
+//! When messages arrive late, the time of a broadcast tick has to be split to tell where it
+//! went. With `--trace-ticks=<n>`, every nth broadcast tick is traced: a `TickTrace` times
+//! taking the receivers from the storage, serializing the gossip message, each send and the
+//! logging of the tick, and the participant logs it as one line:
+//!
+//! ```plaintext
+//! Tick trace: started_unix_ms=1760400000123 snapshot_us=4 serialize_us=2 send_us=61
+//!  sends=[<1ms:3 1-10ms:0 10-50ms:0 >50ms:0] slowest=[127.0.0.1:8082=31us,...]
+//!  logging_us=18 total_us=97
+//! ```
+//!
+//! `TickTracer` decides which ticks are traced, at the cost of one branch on the others, and
+//! sums the traced ticks into the `tick_duration_ms` and `send_duration_ms` histograms shown by
+//! `stats`.
+
+use crate::participant::metrics::Histogram;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The number of slowest receivers named by a trace.
+pub const SLOWEST_NAMED: usize = 3;
+
+/// Where the time of one traced broadcast tick went.
+#[derive(Debug, Clone)]
+pub struct TickTrace {
+    started: Instant,
+    started_at: SystemTime,
+    /// The time taken to list the receivers.
+    pub snapshot: Duration,
+    /// The time taken to serialize the gossip messages.
+    pub serialize: Duration,
+    /// The time taken by the sends, all together.
+    pub send: Duration,
+    /// The time taken to log the tick.
+    pub logging: Duration,
+    sends: Histogram,
+    slowest: Vec<(SocketAddr, Duration)>,
+}
+
+impl TickTrace {
+    /// Starts the trace of a tick starting at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `now` - The time the tick started.
+    /// * `wall` - The wall-clock time the tick started, which the log line shows.
+    pub fn start(now: Instant, wall: SystemTime) -> Self {
+        Self {
+            started: now,
+            started_at: wall,
+            snapshot: Duration::ZERO,
+            serialize: Duration::ZERO,
+            send: Duration::ZERO,
+            logging: Duration::ZERO,
+            sends: Histogram::new(),
+            slowest: Vec::new(),
+        }
+    }
+
+    /// Records the send to the receiver at `addr`, which took `elapsed`.
+    pub fn record_send(&mut self, addr: SocketAddr, elapsed: Duration) {
+        self.sends.record(elapsed);
+        self.slowest.push((addr, elapsed));
+        self.slowest
+            .sort_by_key(|(_, elapsed)| std::cmp::Reverse(*elapsed));
+        self.slowest.truncate(SLOWEST_NAMED);
+    }
+
+    /// Ends the trace of the tick at `now`.
+    ///
+    /// # Returns
+    ///
+    /// The trace log line and the total time of the tick.
+    pub fn finish(&self, now: Instant) -> (String, Duration) {
+        let total = now.saturating_duration_since(self.started);
+        let slowest: Vec<String> = self
+            .slowest
+            .iter()
+            .map(|(addr, elapsed)| format!("{}={}us", addr, elapsed.as_micros()))
+            .collect();
+        let line = format!(
+            "Tick trace: started_unix_ms={} snapshot_us={} serialize_us={} send_us={} sends=[{}] slowest=[{}] logging_us={} total_us={}",
+            self.started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            self.snapshot.as_micros(),
+            self.serialize.as_micros(),
+            self.send.as_micros(),
+            self.sends,
+            slowest.join(","),
+            self.logging.as_micros(),
+            total.as_micros()
+        );
+        (line, total)
+    }
+}
+
+/// Picks the traced ticks and sums their traces.
+#[derive(Debug)]
+pub struct TickTracer {
+    every: u64,
+    countdown: u64,
+    tick_durations: Histogram,
+    send_durations: Histogram,
+}
+
+impl TickTracer {
+    /// Constructs a tracer of one tick in `every`, none when `every` is 0.
+    pub fn new(every: u64) -> Self {
+        Self {
+            every,
+            countdown: every,
+            tick_durations: Histogram::new(),
+            send_durations: Histogram::new(),
+        }
+    }
+
+    /// Traces one tick in `every` from now on, none when `every` is 0.
+    pub fn set_every(&mut self, every: u64) {
+        self.every = every;
+        self.countdown = every;
+    }
+
+    /// Determines whether the tick starting now is traced.
+    pub fn should_trace(&mut self) -> bool {
+        if self.countdown == 0 {
+            return false;
+        }
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return false;
+        }
+        self.countdown = self.every;
+        true
+    }
+
+    /// Adds a finished trace, whose tick took `total`, to the histograms.
+    pub fn record(&mut self, trace: &TickTrace, total: Duration) {
+        self.tick_durations.record(total);
+        self.send_durations.merge(&trace.sends);
+    }
+
+    /// Returns the durations of the traced ticks.
+    pub fn tick_durations(&self) -> &Histogram {
+        &self.tick_durations
+    }
+
+    /// Returns the durations of the sends of the traced ticks.
+    pub fn send_durations(&self) -> &Histogram {
+        &self.send_durations
+    }
+
+    /// Determines whether any tick is traced.
+    pub fn is_enabled(&self) -> bool {
+        self.every > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn us(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn one_tick_in_every_is_traced() {
+        let mut tracer = TickTracer::new(3);
+        let traced: Vec<bool> = (0..7).map(|_| tracer.should_trace()).collect();
+        assert_eq!(traced, [false, false, true, false, false, true, false]);
+
+        let mut every = TickTracer::new(1);
+        assert!((0..5).all(|_| every.should_trace()));
+        every.set_every(0);
+        assert!(!every.is_enabled());
+        assert!((0..5).all(|_| !every.should_trace()));
+        assert!(!TickTracer::new(0).is_enabled());
+    }
+
+    #[test]
+    fn a_trace_names_the_slowest_receivers() {
+        let start = Instant::now();
+        let mut trace = TickTrace::start(start, UNIX_EPOCH + Duration::from_millis(1234));
+        for (port, micros) in [
+            (8081, 40),
+            (8082, 31_000),
+            (8083, 5),
+            (8084, 900),
+            (8085, 60),
+        ] {
+            trace.record_send(addr(port), us(micros));
+        }
+        trace.snapshot = us(4);
+        trace.serialize = us(2);
+        trace.send = us(32_005);
+        trace.logging = us(18);
+
+        let (line, total) = trace.finish(start + us(32_100));
+        assert_eq!(total, us(32_100));
+        assert_eq!(
+            line,
+            "Tick trace: started_unix_ms=1234 snapshot_us=4 serialize_us=2 send_us=32005 \
+             sends=[<1ms:4 1-10ms:0 10-50ms:1 >50ms:0] \
+             slowest=[127.0.0.1:8082=31000us,127.0.0.1:8084=900us,127.0.0.1:8085=60us] \
+             logging_us=18 total_us=32100"
+        );
+    }
+
+    #[test]
+    fn the_histograms_sum_the_traced_ticks() {
+        let start = Instant::now();
+        let mut tracer = TickTracer::new(1);
+        for tick in 0..4 {
+            let mut trace = TickTrace::start(start, SystemTime::now());
+            trace.record_send(addr(8081), us(100));
+            trace.record_send(addr(8082), Duration::from_millis(20));
+            let (_, total) = trace.finish(start + Duration::from_millis(tick * 20));
+            tracer.record(&trace, total);
+        }
+        assert_eq!(tracer.tick_durations().total(), 4);
+        assert_eq!(
+            tracer.tick_durations().to_string(),
+            "<1ms:1 1-10ms:0 10-50ms:2 >50ms:1"
+        );
+        assert_eq!(tracer.send_durations().total(), 8);
+        assert_eq!(
+            tracer.send_durations().to_string(),
+            "<1ms:4 1-10ms:0 10-50ms:4 >50ms:0"
+        );
+    }
+}
@@ -0,0 +1,1254 @@
+//! Network Transport.
+//!
+//! The participant reaches the network through the `Transport` trait instead of calling
+//! `message-io` directly: it listens, dials, sends frames and closes connections through it,
+//! and reads what happened on the connections as `TransportEvent`s. Connections and listeners
+//! are known by the `Connection` and `ListenerId` handles of this module, whichever backend
+//! carries them, so the participant holds its transport as a `dyn Transport` and nothing else
+//! of it depends on the backend.
+//!
+//! Two backends implement the trait. `MessageIoTransport` is the framed TCP transport of
+//! `message-io`, whose events the node's event loop delivers and the transport translates.
+//! `InMemoryTransport` connects the participants of one process through an `InMemoryNetwork`
+//! without any socket, delivering its events through the `TransportContext` the participant
+//! attaches it to; tests and simulations run many participants on it.
+//!
+//! Every socket `MessageIoTransport` opens holds a permit of the `DescriptorBudget`, see
+//! `descriptors`: a listener or a dial is refused with `Exhausted` when none is left, and an
+//! accepted connection has to be admitted. The permit of a connection is given back when the
+//! participant closes it, when the peer does, and when its dial fails, so no path that opens
+//! or closes a connection bypasses the count.
+//!
+//! `CheckedTransport` wraps the backend of a participant to pass every outbound frame through
+//! the outbound check first, see `outbound`. Timers and the event loop itself still come from
+//! the `message-io` node, whichever the backend.
+
+use crate::participant::descriptors::{DescriptorBudget, Exhausted, Permit};
+use crate::participant::event::InternalEvent;
+use crate::participant::outbound::OutboundCheck;
+use crate::participant::storage::ParticipantEndpoint;
+
+use message_io::network::{Endpoint, NetEvent, SendStatus, Transport as Protocol};
+use message_io::node::NodeHandler;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What a transport did with a frame handed to `Transport::send`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// The frame was written, or queued to be written, to the connection.
+    Sent,
+    /// The frame is larger than the transport can carry.
+    TooLarge,
+    /// The connection does not exist anymore.
+    Closed,
+    /// The connection is not established yet.
+    NotReady,
+    /// The frame failed the outbound check and was not sent, see `outbound`.
+    Invalid,
+}
+
+impl From<SendStatus> for SendOutcome {
+    fn from(status: SendStatus) -> Self {
+        match status {
+            SendStatus::Sent => SendOutcome::Sent,
+            SendStatus::MaxPacketSizeExceeded => SendOutcome::TooLarge,
+            SendStatus::ResourceNotFound => SendOutcome::Closed,
+            SendStatus::ResourceNotAvailable => SendOutcome::NotReady,
+        }
+    }
+}
+
+/// The handle of a connection, along with the address of the peer at its other end.
+///
+/// The id is unique among the connections of a transport, so two connections from the same
+/// address stay apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Connection {
+    id: u64,
+    addr: SocketAddr,
+}
+
+impl Connection {
+    /// Creates the handle of the connection `id` of a transport, with the peer at `addr`.
+    pub fn new(id: u64, addr: SocketAddr) -> Self {
+        Self { id, addr }
+    }
+
+    /// Returns the id of the connection among those of its transport.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the address of the peer at the other end of the connection.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.id, self.addr)
+    }
+}
+
+impl ParticipantEndpoint for Connection {
+    fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// The handle of a listener of a transport, reported with the connections it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+impl ListenerId {
+    /// Creates the handle of the listener `id` of a transport.
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// Something that happened on the connections of a transport.
+#[derive(Debug, Clone, Copy)]
+pub enum TransportEvent<'a> {
+    /// A peer connected to the listener.
+    Accepted(Connection, ListenerId),
+    /// A dial finished; `true` when the connection is established.
+    Connected(Connection, bool),
+    /// A whole frame arrived.
+    Frame(Connection, &'a [u8]),
+    /// The connection was closed, by either side.
+    Closed(Connection),
+}
+
+/// A `TransportEvent` owning its frame, for a backend that hands its events to the event
+/// loop through `TransportContext::deliver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedTransportEvent {
+    /// A peer connected to the listener.
+    Accepted(Connection, ListenerId),
+    /// A dial finished; `true` when the connection is established.
+    Connected(Connection, bool),
+    /// A whole frame arrived.
+    Frame(Connection, Vec<u8>),
+    /// The connection was closed, by either side.
+    Closed(Connection),
+}
+
+impl OwnedTransportEvent {
+    /// Borrows the event as the participant handles it.
+    pub fn as_event(&self) -> TransportEvent<'_> {
+        match self {
+            OwnedTransportEvent::Accepted(conn, listener) => {
+                TransportEvent::Accepted(*conn, *listener)
+            }
+            OwnedTransportEvent::Connected(conn, established) => {
+                TransportEvent::Connected(*conn, *established)
+            }
+            OwnedTransportEvent::Frame(conn, frame) => TransportEvent::Frame(*conn, frame),
+            OwnedTransportEvent::Closed(conn) => TransportEvent::Closed(*conn),
+        }
+    }
+}
+
+/// What a participant gives the transport it runs on: its event loop and its file
+/// descriptor budget.
+#[derive(Clone)]
+pub struct TransportContext {
+    handler: NodeHandler<InternalEvent>,
+    descriptors: DescriptorBudget,
+}
+
+impl TransportContext {
+    /// Creates the context of the participant whose node has `handler`.
+    pub(crate) fn new(handler: NodeHandler<InternalEvent>, descriptors: DescriptorBudget) -> Self {
+        Self {
+            handler,
+            descriptors,
+        }
+    }
+
+    /// Hands `event` to the event loop of the participant, in order with the events delivered
+    /// before it.
+    pub fn deliver(&self, event: OwnedTransportEvent) {
+        self.handler.signals().send(InternalEvent::Transport(event));
+    }
+
+    /// Hands `event` to the event loop of the participant once `delay` elapsed.
+    pub fn deliver_after(&self, event: OwnedTransportEvent, delay: Duration) {
+        self.handler
+            .signals()
+            .send_with_timer(InternalEvent::Transport(event), delay);
+    }
+
+    /// Returns the budget every socket of the participant takes a permit from.
+    pub fn descriptors(&self) -> &DescriptorBudget {
+        &self.descriptors
+    }
+}
+
+/// A network backend carrying the frames of a participant.
+pub trait Transport: Send {
+    /// Attaches the transport to the participant running on it, before anything else is
+    /// called.
+    fn attach(&mut self, context: TransportContext);
+
+    /// Starts accepting connections on `addr`.
+    ///
+    /// # Returns
+    ///
+    /// The listener and the address it is bound to, which tells the port given as 0.
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)>;
+
+    /// Starts dialing `addr`; `TransportEvent::Connected` tells how the dial ended.
+    ///
+    /// A dial refused for want of a file descriptor fails with `Exhausted`, see
+    /// `descriptors::is_exhausted`.
+    ///
+    /// # Returns
+    ///
+    /// The connection and its local address.
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)>;
+
+    /// Sends one frame through `conn`.
+    fn send(&mut self, conn: Connection, frame: &[u8]) -> SendOutcome;
+
+    /// Closes `conn` without a `TransportEvent::Closed` for it.
+    ///
+    /// # Returns
+    ///
+    /// `false` when the connection did not exist anymore.
+    fn close(&mut self, conn: Connection) -> bool;
+
+    /// Determines whether `conn` is still open, established or not.
+    fn is_open(&self, conn: Connection) -> bool;
+
+    /// Admits the connection `conn` reported by `TransportEvent::Accepted`.
+    ///
+    /// # Returns
+    ///
+    /// `false` when no file descriptor is left for it; it is then closed.
+    fn admit(&mut self, conn: Connection) -> bool;
+
+    /// Translates an event of the `message-io` network of the node.
+    ///
+    /// Only a backend whose connections live in that network gets any; the others deliver
+    /// their events through `TransportContext::deliver` instead.
+    #[doc(hidden)]
+    fn translate<'a>(&mut self, event: NetEvent<'a>) -> Option<TransportEvent<'a>> {
+        let _ = event;
+        None
+    }
+
+    /// Takes note of `event`, delivered through `TransportContext::deliver`, before the
+    /// participant handles it.
+    fn observe(&mut self, event: &TransportEvent<'_>) {
+        let _ = event;
+    }
+}
+
+/// The framed TCP transport of `message-io`.
+///
+/// The connections are the endpoints of the node, known to the participant by the
+/// `Connection` carrying the id of their resource.
+#[derive(Default)]
+pub struct MessageIoTransport {
+    context: Option<TransportContext>,
+    /// The permits of the listeners, held as long as the node.
+    listeners: Vec<Permit>,
+    /// The endpoint of each connection, dialing, dialed or accepted.
+    endpoints: HashMap<Connection, Endpoint>,
+    /// The permits of the connections, dialing, dialed or admitted.
+    permits: HashMap<Connection, Permit>,
+}
+
+impl MessageIoTransport {
+    /// Creates the transport, which opens nothing until it is attached to a participant.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the context the transport is attached to.
+    ///
+    /// # Panics
+    ///
+    /// When the transport is not attached yet.
+    fn context(&self) -> &TransportContext {
+        self.context
+            .as_ref()
+            .expect("the transport is attached before it is used")
+    }
+
+    /// Returns the handle of `endpoint`, remembering its endpoint.
+    fn connection(&mut self, endpoint: Endpoint) -> Connection {
+        let conn = Connection::new(endpoint.resource_id().raw() as u64, endpoint.addr());
+        self.endpoints.insert(conn, endpoint);
+        conn
+    }
+
+    /// Forgets `conn`, giving back its permit.
+    fn forget(&mut self, conn: &Connection) -> Option<Endpoint> {
+        self.permits.remove(conn);
+        self.endpoints.remove(conn)
+    }
+}
+
+impl Transport for MessageIoTransport {
+    fn attach(&mut self, context: TransportContext) {
+        self.context = Some(context);
+    }
+
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)> {
+        let context = self.context();
+        let permit = context.descriptors.acquire().ok_or(Exhausted)?;
+        let (listener, bound) = context
+            .handler
+            .network()
+            .listen(Protocol::FramedTcp, addr)?;
+        self.listeners.push(permit);
+        Ok((ListenerId::new(listener.raw() as u64), bound))
+    }
+
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)> {
+        let context = self.context();
+        let permit = context.descriptors.acquire().ok_or(Exhausted)?;
+        let (endpoint, local_addr) = context
+            .handler
+            .network()
+            .connect(Protocol::FramedTcp, addr)?;
+        let conn = self.connection(endpoint);
+        self.permits.insert(conn, permit);
+        Ok((conn, local_addr))
+    }
+
+    fn send(&mut self, conn: Connection, frame: &[u8]) -> SendOutcome {
+        match self.endpoints.get(&conn) {
+            Some(endpoint) => self
+                .context()
+                .handler
+                .network()
+                .send(*endpoint, frame)
+                .into(),
+            None => SendOutcome::Closed,
+        }
+    }
+
+    fn close(&mut self, conn: Connection) -> bool {
+        match self.forget(&conn) {
+            Some(endpoint) => self
+                .context()
+                .handler
+                .network()
+                .remove(endpoint.resource_id()),
+            None => false,
+        }
+    }
+
+    fn is_open(&self, conn: Connection) -> bool {
+        self.endpoints.get(&conn).is_some_and(|endpoint| {
+            self.context()
+                .handler
+                .network()
+                .is_ready(endpoint.resource_id())
+                .is_some()
+        })
+    }
+
+    fn admit(&mut self, conn: Connection) -> bool {
+        let Some(permit) = self.context().descriptors.acquire() else {
+            self.close(conn);
+            return false;
+        };
+        self.permits.insert(conn, permit);
+        true
+    }
+
+    /// Translates a network event of the node, giving back the permit of a connection that
+    /// closed or could not be dialed.
+    fn translate<'a>(&mut self, event: NetEvent<'a>) -> Option<TransportEvent<'a>> {
+        Some(match event {
+            NetEvent::Accepted(endpoint, listener) => TransportEvent::Accepted(
+                self.connection(endpoint),
+                ListenerId::new(listener.raw() as u64),
+            ),
+            NetEvent::Connected(endpoint, established) => {
+                let conn = self.connection(endpoint);
+                if !established {
+                    self.forget(&conn);
+                }
+                TransportEvent::Connected(conn, established)
+            }
+            NetEvent::Message(endpoint, frame) => {
+                TransportEvent::Frame(self.connection(endpoint), frame)
+            }
+            NetEvent::Disconnected(endpoint) => {
+                let conn = self.connection(endpoint);
+                self.forget(&conn);
+                TransportEvent::Closed(conn)
+            }
+        })
+    }
+}
+
+/// The transport of a participant: its backend, with the outbound check in front of it.
+pub(crate) struct CheckedTransport {
+    inner: Box<dyn Transport>,
+    /// The check every frame passes before it is sent, if outbound validation is on.
+    outbound: Option<OutboundCheck>,
+}
+
+impl CheckedTransport {
+    /// Wraps `inner`, with no outbound check yet.
+    pub(crate) fn new(inner: Box<dyn Transport>) -> Self {
+        Self {
+            inner,
+            outbound: None,
+        }
+    }
+
+    /// Checks every frame sent from now on with `check`.
+    pub(crate) fn validate_outbound(&mut self, check: OutboundCheck) {
+        self.outbound = Some(check);
+    }
+
+    /// Returns the number of frames the outbound check refused, if it is on.
+    pub(crate) fn outbound_refused(&self) -> Option<u64> {
+        self.outbound.as_ref().map(OutboundCheck::refused)
+    }
+}
+
+impl Transport for CheckedTransport {
+    fn attach(&mut self, context: TransportContext) {
+        self.inner.attach(context);
+    }
+
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)> {
+        self.inner.listen(addr)
+    }
+
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)> {
+        self.inner.dial(addr)
+    }
+
+    fn send(&mut self, conn: Connection, frame: &[u8]) -> SendOutcome {
+        if let Some(check) = &mut self.outbound {
+            if !check.admit(conn.addr(), frame) {
+                return SendOutcome::Invalid;
+            }
+        }
+        self.inner.send(conn, frame)
+    }
+
+    fn close(&mut self, conn: Connection) -> bool {
+        self.inner.close(conn)
+    }
+
+    fn is_open(&self, conn: Connection) -> bool {
+        self.inner.is_open(conn)
+    }
+
+    fn admit(&mut self, conn: Connection) -> bool {
+        self.inner.admit(conn)
+    }
+
+    fn translate<'a>(&mut self, event: NetEvent<'a>) -> Option<TransportEvent<'a>> {
+        self.inner.translate(event)
+    }
+
+    fn observe(&mut self, event: &TransportEvent<'_>) {
+        self.inner.observe(event);
+    }
+}
+
+/// The first port `InMemoryNetwork` gives a listener bound to port 0, or a dialing connection.
+const FIRST_VIRTUAL_PORT: u16 = 20000;
+
+/// How a direction of the links between two hosts of an `InMemoryNetwork` misbehaves.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkConditions {
+    /// How late the events of the direction are delivered.
+    pub latency: Duration,
+    /// The share of the frames of the direction that are lost, from 0 to 1.
+    pub loss: f64,
+}
+
+/// One end of a connection of an `InMemoryNetwork`.
+struct Link {
+    /// The transport owning this end.
+    node: u64,
+    /// The other end, as its own transport knows it.
+    peer: Connection,
+}
+
+/// Everything the transports of an `InMemoryNetwork` share.
+struct NetworkState {
+    next_id: u64,
+    next_port: u16,
+    /// The context of each attached transport.
+    nodes: HashMap<u64, TransportContext>,
+    /// The address of each transport that listens, the first it listened on.
+    hosts: HashMap<u64, SocketAddr>,
+    /// The listeners, by the address they are bound to.
+    listeners: HashMap<SocketAddr, (u64, ListenerId)>,
+    /// The ends of the connections, by their id.
+    links: HashMap<u64, Link>,
+    /// The hosts whose listeners refuse every dial.
+    refusing: HashSet<SocketAddr>,
+    /// The pairs of hosts a partition keeps apart, in both orders.
+    cuts: HashSet<(SocketAddr, SocketAddr)>,
+    /// The conditions of the directions that misbehave, from a host to another.
+    conditions: HashMap<(SocketAddr, SocketAddr), LinkConditions>,
+    /// Draws the lost frames.
+    rng: StdRng,
+    /// The frames lost so far.
+    dropped: u64,
+}
+
+impl NetworkState {
+    fn new(seed: u64) -> Self {
+        Self {
+            next_id: 0,
+            next_port: FIRST_VIRTUAL_PORT,
+            nodes: HashMap::new(),
+            hosts: HashMap::new(),
+            listeners: HashMap::new(),
+            links: HashMap::new(),
+            refusing: HashSet::new(),
+            cuts: HashSet::new(),
+            conditions: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed),
+            dropped: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn next_port(&mut self) -> u16 {
+        self.next_port += 1;
+        self.next_port
+    }
+
+    /// Returns the listener a dial to `addr` reaches: one bound to that address, or to the
+    /// unspecified address of its port.
+    fn listener(&self, addr: SocketAddr) -> Option<(u64, ListenerId)> {
+        let unspecified = match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        self.listeners
+            .get(&addr)
+            .or_else(|| {
+                self.listeners
+                    .get(&SocketAddr::new(unspecified, addr.port()))
+            })
+            .copied()
+    }
+
+    /// Returns the host addresses of the transports `from` and `to`, when both listen.
+    fn hosts_of(&self, from: u64, to: u64) -> Option<(SocketAddr, SocketAddr)> {
+        Some((*self.hosts.get(&from)?, *self.hosts.get(&to)?))
+    }
+
+    /// Determines whether a partition keeps the transports `from` and `to` apart.
+    fn is_cut(&self, from: u64, to: u64) -> bool {
+        self.hosts_of(from, to)
+            .is_some_and(|pair| self.cuts.contains(&pair))
+    }
+
+    /// Returns the conditions of the direction from the transport `from` to `to`.
+    fn conditions(&self, from: u64, to: u64) -> LinkConditions {
+        self.hosts_of(from, to)
+            .and_then(|pair| self.conditions.get(&pair).copied())
+            .unwrap_or_default()
+    }
+
+    /// Hands `event` from the transport `from` to the transport `to`, unless it is gone, as
+    /// late as the latency of the direction.
+    fn deliver(&self, from: u64, to: u64, event: OwnedTransportEvent) {
+        let Some(context) = self.nodes.get(&to) else {
+            return;
+        };
+        match self.conditions(from, to).latency {
+            Duration::ZERO => context.deliver(event),
+            latency => context.deliver_after(event, latency),
+        }
+    }
+
+    /// Removes the connection `id`, telling the other end it closed.
+    fn close(&mut self, id: u64) -> bool {
+        let Some(link) = self.links.remove(&id) else {
+            return false;
+        };
+        if let Some(peer) = self.links.remove(&link.peer.id()) {
+            self.deliver(link.node, peer.node, OwnedTransportEvent::Closed(link.peer));
+        }
+        true
+    }
+}
+
+/// A network of participants running in one process, without any socket.
+///
+/// Every transport made by `transport` is a host of the network, known by the first address
+/// it listens on; a dial reaches the listener of another transport by the address it is bound
+/// to, and the frames sent on a connection are delivered to the other end in order, through
+/// the event loop of its participant. A dial to an address nobody listens on is refused as a
+/// TCP one would be.
+///
+/// The network can also misbehave as a real one does, between the hosts it is told about: a
+/// host can refuse its dials, a partition keeps groups of hosts apart, `half_close` leaves a
+/// connection half-open, and the directions of the links between two hosts can be given a
+/// latency and a loss with `set_conditions`. The lost frames are drawn from the seed of the
+/// network, so a run can be repeated.
+#[derive(Clone)]
+pub struct InMemoryNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl Default for InMemoryNetwork {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl InMemoryNetwork {
+    /// Creates an empty network.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty network drawing its lost frames from `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetworkState::new(seed))),
+        }
+    }
+
+    /// Creates a transport connected to the network.
+    pub fn transport(&self) -> InMemoryTransport {
+        self.transport_from(Ipv4Addr::LOCALHOST.into())
+    }
+
+    /// Creates a transport connected to the network, whose dials come from `ip`, so the
+    /// peers it reaches see it as a host of its own.
+    pub fn transport_from(&self, ip: IpAddr) -> InMemoryTransport {
+        let node = self.lock().next_id();
+        InMemoryTransport {
+            network: self.clone(),
+            node,
+            source: ip,
+            descriptors: None,
+            listeners: HashMap::new(),
+            permits: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of connections currently open on the network, a half-open one
+    /// included.
+    pub fn connections(&self) -> usize {
+        let state = self.lock();
+        let half_open = state
+            .links
+            .values()
+            .filter(|link| !state.links.contains_key(&link.peer.id()))
+            .count();
+        (state.links.len() + half_open) / 2
+    }
+
+    /// Returns the number of frames lost so far, to a partition, to the loss of a direction or
+    /// to the other end of a half-open connection.
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    /// Makes the listeners of `host` refuse every dial from now on, or accept them again.
+    pub fn refuse(&self, host: SocketAddr, refusing: bool) {
+        let mut state = self.lock();
+        if refusing {
+            state.refusing.insert(host);
+        } else {
+            state.refusing.remove(&host);
+        }
+    }
+
+    /// Keeps the hosts of `side` and those of `other` apart: the dials between them are
+    /// refused and the frames between them lost, until `heal`.
+    pub fn partition(&self, side: &[SocketAddr], other: &[SocketAddr]) {
+        let mut state = self.lock();
+        for first in side {
+            for second in other {
+                state.cuts.insert((*first, *second));
+                state.cuts.insert((*second, *first));
+            }
+        }
+    }
+
+    /// Ends every partition.
+    pub fn heal(&self) {
+        self.lock().cuts.clear();
+    }
+
+    /// Sets the conditions of the direction from the host `from` to the host `to`.
+    pub fn set_conditions(&self, from: SocketAddr, to: SocketAddr, conditions: LinkConditions) {
+        let mut state = self.lock();
+        if conditions == LinkConditions::default() {
+            state.conditions.remove(&(from, to));
+        } else {
+            state.conditions.insert((from, to), conditions);
+        }
+    }
+
+    /// Closes the sending side of each connection of `host` to `peer`: `peer` sees them close,
+    /// while `host` keeps them open, its frames lost, as a host that crashed and came back
+    /// without a word leaves its peers.
+    ///
+    /// # Returns
+    ///
+    /// The number of connections left half-open.
+    pub fn half_close(&self, host: SocketAddr, peer: SocketAddr) -> usize {
+        let mut state = self.lock();
+        let half_open: Vec<(u64, Connection)> = state
+            .links
+            .values()
+            .filter(|link| {
+                state.hosts.get(&link.node) == Some(&host)
+                    && state
+                        .links
+                        .get(&link.peer.id())
+                        .and_then(|end| state.hosts.get(&end.node))
+                        == Some(&peer)
+            })
+            .map(|link| (link.node, link.peer))
+            .collect();
+        for (node, end) in &half_open {
+            if let Some(removed) = state.links.remove(&end.id()) {
+                state.deliver(*node, removed.node, OwnedTransportEvent::Closed(*end));
+            }
+        }
+        half_open.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, NetworkState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A transport of an `InMemoryNetwork`, see `InMemoryNetwork::transport`.
+///
+/// Its sockets take permits of the descriptor budget as those of `MessageIoTransport` do, so
+/// a participant on it runs out of descriptors the same way.
+pub struct InMemoryTransport {
+    network: InMemoryNetwork,
+    node: u64,
+    /// The IP address the connections it dials come from.
+    source: IpAddr,
+    descriptors: Option<DescriptorBudget>,
+    /// The listeners, with their addresses and permits.
+    listeners: HashMap<ListenerId, (SocketAddr, Permit)>,
+    /// The permits of the connections, dialed or admitted.
+    permits: HashMap<Connection, Permit>,
+}
+
+impl InMemoryTransport {
+    fn acquire(&self) -> io::Result<Permit> {
+        let descriptors = self
+            .descriptors
+            .as_ref()
+            .expect("the transport is attached before it is used");
+        Ok(descriptors.acquire().ok_or(Exhausted)?)
+    }
+}
+
+impl Transport for InMemoryTransport {
+    fn attach(&mut self, context: TransportContext) {
+        self.descriptors = Some(context.descriptors.clone());
+        self.network.lock().nodes.insert(self.node, context);
+    }
+
+    fn listen(&mut self, addr: SocketAddr) -> io::Result<(ListenerId, SocketAddr)> {
+        let permit = self.acquire()?;
+        let mut state = self.network.lock();
+        let mut bound = addr;
+        if bound.port() == 0 {
+            bound.set_port(state.next_port());
+        }
+        if state.listeners.contains_key(&bound) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already listened on", bound),
+            ));
+        }
+        let listener = ListenerId::new(state.next_id());
+        state.listeners.insert(bound, (self.node, listener));
+        let host = if bound.ip().is_unspecified() {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), bound.port())
+        } else {
+            bound
+        };
+        state.hosts.entry(self.node).or_insert(host);
+        self.listeners.insert(listener, (bound, permit));
+        Ok((listener, bound))
+    }
+
+    fn dial(&mut self, addr: SocketAddr) -> io::Result<(Connection, SocketAddr)> {
+        let permit = self.acquire()?;
+        let mut state = self.network.lock();
+        let local_addr = SocketAddr::new(self.source, state.next_port());
+        let conn = Connection::new(state.next_id(), addr);
+        let reached = state.listener(addr).filter(|(node, _)| {
+            !state.is_cut(self.node, *node)
+                && !state
+                    .hosts
+                    .get(node)
+                    .is_some_and(|host| state.refusing.contains(host))
+        });
+        match reached {
+            Some((node, listener)) => {
+                let accepted = Connection::new(state.next_id(), local_addr);
+                state.links.insert(
+                    conn.id(),
+                    Link {
+                        node: self.node,
+                        peer: accepted,
+                    },
+                );
+                state.links.insert(accepted.id(), Link { node, peer: conn });
+                state.deliver(
+                    self.node,
+                    node,
+                    OwnedTransportEvent::Accepted(accepted, listener),
+                );
+                // The dialer learns the connection is up a round trip later.
+                let round_trip = state.conditions(self.node, node).latency
+                    + state.conditions(node, self.node).latency;
+                let connected = OwnedTransportEvent::Connected(conn, true);
+                match (state.nodes.get(&self.node), round_trip) {
+                    (Some(context), Duration::ZERO) => context.deliver(connected),
+                    (Some(context), round_trip) => context.deliver_after(connected, round_trip),
+                    (None, _) => {}
+                }
+                self.permits.insert(conn, permit);
+            }
+            None => state.deliver(
+                self.node,
+                self.node,
+                OwnedTransportEvent::Connected(conn, false),
+            ),
+        }
+        Ok((conn, local_addr))
+    }
+
+    fn send(&mut self, conn: Connection, frame: &[u8]) -> SendOutcome {
+        let mut state = self.network.lock();
+        let Some(link) = state.links.get(&conn.id()) else {
+            return SendOutcome::Closed;
+        };
+        let peer = link.peer;
+        // The other end of a half-open connection is gone, and the frame with it.
+        let Some(node) = state.links.get(&peer.id()).map(|end| end.node) else {
+            state.dropped += 1;
+            return SendOutcome::Sent;
+        };
+        let loss = state.conditions(self.node, node).loss;
+        let lost =
+            state.is_cut(self.node, node) || (loss > 0.0 && state.rng.gen_bool(loss.min(1.0)));
+        if lost {
+            state.dropped += 1;
+        } else {
+            state.deliver(
+                self.node,
+                node,
+                OwnedTransportEvent::Frame(peer, frame.to_vec()),
+            );
+        }
+        SendOutcome::Sent
+    }
+
+    fn close(&mut self, conn: Connection) -> bool {
+        self.permits.remove(&conn);
+        self.network.lock().close(conn.id())
+    }
+
+    fn is_open(&self, conn: Connection) -> bool {
+        self.network.lock().links.contains_key(&conn.id())
+    }
+
+    fn admit(&mut self, conn: Connection) -> bool {
+        match self.acquire() {
+            Ok(permit) => {
+                self.permits.insert(conn, permit);
+                true
+            }
+            Err(_) => {
+                self.close(conn);
+                false
+            }
+        }
+    }
+
+    /// Gives back the permit of a connection the other end closed.
+    fn observe(&mut self, event: &TransportEvent<'_>) {
+        if let TransportEvent::Closed(conn) = event {
+            self.permits.remove(conn);
+        }
+    }
+}
+
+impl Drop for InMemoryTransport {
+    /// Takes the host off the network: its listeners stop and its connections close.
+    fn drop(&mut self) {
+        let mut state = self.network.lock();
+        for (addr, _) in self.listeners.values() {
+            state.listeners.remove(addr);
+        }
+        let owned: HashSet<u64> = state
+            .links
+            .iter()
+            .filter(|(_, link)| link.node == self.node)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in owned {
+            state.close(id);
+        }
+        state.nodes.remove(&self.node);
+        state.hosts.remove(&self.node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    use message_io::events::EventReceiver;
+    use message_io::node::{self, NodeTask, StoredNodeEvent};
+
+    use std::time::Duration;
+
+    /// A transport of `network` attached to a node whose events the test reads.
+    struct Host {
+        transport: InMemoryTransport,
+        handler: NodeHandler<InternalEvent>,
+        events: EventReceiver<StoredNodeEvent<InternalEvent>>,
+        _task: NodeTask,
+    }
+
+    impl Host {
+        fn new(network: &InMemoryNetwork, descriptors: usize) -> Self {
+            let (handler, listener) = node::split::<InternalEvent>();
+            let (task, events) = listener.enqueue();
+            let mut transport = network.transport();
+            transport.attach(TransportContext::new(
+                handler.clone(),
+                DescriptorBudget::new(descriptors, 0),
+            ));
+            Self {
+                transport,
+                handler,
+                events,
+                _task: task,
+            }
+        }
+
+        /// Returns the next event delivered to the host.
+        fn next(&mut self) -> Option<OwnedTransportEvent> {
+            self.next_within(Duration::from_millis(100))
+        }
+
+        /// Returns the next event delivered to the host within `timeout`.
+        fn next_within(&mut self, timeout: Duration) -> Option<OwnedTransportEvent> {
+            match self.events.receive_timeout(timeout)? {
+                StoredNodeEvent::Signal(InternalEvent::Transport(event)) => Some(event),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+    }
+
+    impl Drop for Host {
+        fn drop(&mut self) {
+            self.handler.stop();
+        }
+    }
+
+    /// Connects `dialer` to the listener of `listener` on port 9000, with the handles of
+    /// both ends.
+    fn connect(listener: &mut Host, dialer: &mut Host) -> (Connection, Connection) {
+        let (listener_id, _) = listener.transport.listen(addr(9000)).unwrap();
+        let (dialed, _) = dialer.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            dialer.next(),
+            Some(OwnedTransportEvent::Connected(dialed, true))
+        );
+        let Some(OwnedTransportEvent::Accepted(accepted, id)) = listener.next() else {
+            panic!("the listener did not accept the connection");
+        };
+        assert_eq!(id, listener_id);
+        assert!(listener.transport.admit(accepted));
+        (dialed, accepted)
+    }
+
+    #[test]
+    fn a_dial_reaches_the_listener_and_carries_frames_both_ways() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        let (dialed, accepted) = connect(&mut first, &mut second);
+        assert_eq!(dialed.addr(), addr(9000));
+        assert_ne!(dialed.id(), accepted.id());
+        assert_eq!(network.connections(), 1);
+
+        assert_eq!(second.transport.send(dialed, b"ping"), SendOutcome::Sent);
+        assert_eq!(
+            first.next(),
+            Some(OwnedTransportEvent::Frame(accepted, b"ping".to_vec()))
+        );
+        assert_eq!(first.transport.send(accepted, b"pong"), SendOutcome::Sent);
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Frame(dialed, b"pong".to_vec()))
+        );
+    }
+
+    #[test]
+    fn a_dial_to_an_address_nobody_listens_on_is_refused() {
+        let network = InMemoryNetwork::new();
+        let mut host = Host::new(&network, 8);
+        let (conn, _) = host.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            host.next(),
+            Some(OwnedTransportEvent::Connected(conn, false))
+        );
+        assert!(!host.transport.is_open(conn));
+        assert_eq!(host.transport.send(conn, b"lost"), SendOutcome::Closed);
+    }
+
+    #[test]
+    fn a_listener_on_the_unspecified_address_is_reached_through_its_port() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        let (_, bound) = first
+            .transport
+            .listen(SocketAddr::from(([0, 0, 0, 0], 0)))
+            .unwrap();
+        assert_ne!(bound.port(), 0);
+        let (conn, _) = second.transport.dial(addr(bound.port())).unwrap();
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Connected(conn, true))
+        );
+    }
+
+    #[test]
+    fn an_address_is_listened_on_once() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        first.transport.listen(addr(9000)).unwrap();
+        let err = second.transport.listen(addr(9000)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn closing_a_connection_tells_only_the_other_end() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        let (dialed, accepted) = connect(&mut first, &mut second);
+        assert!(second.transport.close(dialed));
+        assert!(!second.transport.close(dialed));
+        assert_eq!(first.next(), Some(OwnedTransportEvent::Closed(accepted)));
+        assert_eq!(second.next(), None);
+        assert!(!first.transport.is_open(accepted));
+        assert_eq!(network.connections(), 0);
+    }
+
+    #[test]
+    fn a_dropped_transport_closes_its_connections_and_listeners() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        let (dialed, _) = connect(&mut first, &mut second);
+        drop(first);
+        assert_eq!(second.next(), Some(OwnedTransportEvent::Closed(dialed)));
+        let (conn, _) = second.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Connected(conn, false))
+        );
+    }
+
+    #[test]
+    fn sockets_take_permits_of_the_descriptor_budget() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 2), Host::new(&network, 1));
+        let (dialed, _) = connect(&mut first, &mut second);
+        let err = second.transport.dial(addr(9000)).unwrap_err();
+        assert!(crate::participant::descriptors::is_exhausted(&err));
+
+        // The listener and the admitted connection hold both permits of the first host, until
+        // it takes note that the connection closed.
+        second.transport.close(dialed);
+        let closed = first
+            .next()
+            .expect("the first host is told the connection closed");
+        let mut third = Host::new(&network, 2);
+        let (refused, _) = third.transport.dial(addr(9000)).unwrap();
+        third.next();
+        let Some(OwnedTransportEvent::Accepted(accepted, _)) = first.next() else {
+            panic!("the listener did not accept the connection");
+        };
+        assert!(!first.transport.admit(accepted));
+        assert_eq!(third.next(), Some(OwnedTransportEvent::Closed(refused)));
+
+        first.transport.observe(&closed.as_event());
+        third.transport.dial(addr(9000)).unwrap();
+        let Some(OwnedTransportEvent::Accepted(accepted, _)) = first.next() else {
+            panic!("the listener did not accept the connection");
+        };
+        assert!(first.transport.admit(accepted));
+    }
+
+    #[test]
+    fn a_refusing_host_refuses_dials_until_it_accepts_them_again() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        first.transport.listen(addr(9000)).unwrap();
+        network.refuse(addr(9000), true);
+        let (conn, _) = second.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Connected(conn, false))
+        );
+        assert_eq!(first.next(), None);
+
+        network.refuse(addr(9000), false);
+        let (conn, _) = second.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Connected(conn, true))
+        );
+    }
+
+    #[test]
+    fn a_partition_refuses_dials_and_loses_frames_until_it_heals() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        second.transport.listen(addr(9001)).unwrap();
+        let (dialed, accepted) = connect(&mut first, &mut second);
+
+        network.partition(&[addr(9000)], &[addr(9001)]);
+        assert_eq!(second.transport.send(dialed, b"lost"), SendOutcome::Sent);
+        assert_eq!(first.next(), None);
+        assert_eq!(network.dropped(), 1);
+        let (conn, _) = second.transport.dial(addr(9000)).unwrap();
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Connected(conn, false))
+        );
+
+        network.heal();
+        assert_eq!(first.transport.send(accepted, b"back"), SendOutcome::Sent);
+        assert_eq!(
+            second.next(),
+            Some(OwnedTransportEvent::Frame(dialed, b"back".to_vec()))
+        );
+    }
+
+    #[test]
+    fn the_latency_of_a_direction_delays_its_events() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        second.transport.listen(addr(9001)).unwrap();
+        let (dialed, accepted) = connect(&mut first, &mut second);
+        let latency = Duration::from_millis(150);
+        network.set_conditions(
+            addr(9001),
+            addr(9000),
+            LinkConditions { latency, loss: 0.0 },
+        );
+
+        let sent = std::time::Instant::now();
+        second.transport.send(dialed, b"late");
+        assert_eq!(
+            first.next_within(Duration::from_secs(1)),
+            Some(OwnedTransportEvent::Frame(accepted, b"late".to_vec()))
+        );
+        assert!(sent.elapsed() >= latency);
+
+        // The other direction is not delayed.
+        let sent = std::time::Instant::now();
+        first.transport.send(accepted, b"early");
+        assert!(second.next().is_some());
+        assert!(sent.elapsed() < latency);
+    }
+
+    #[test]
+    fn a_lossy_direction_loses_a_share_of_its_frames_drawn_from_the_seed() {
+        let lost = |seed| {
+            let network = InMemoryNetwork::with_seed(seed);
+            let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+            second.transport.listen(addr(9001)).unwrap();
+            let (dialed, _) = connect(&mut first, &mut second);
+            network.set_conditions(
+                addr(9001),
+                addr(9000),
+                LinkConditions {
+                    latency: Duration::ZERO,
+                    loss: 0.5,
+                },
+            );
+            let mut received = Vec::new();
+            for index in 0..64u8 {
+                second.transport.send(dialed, &[index]);
+            }
+            while let Some(OwnedTransportEvent::Frame(_, frame)) = first.next() {
+                received.push(frame[0]);
+            }
+            assert_eq!(network.dropped(), 64 - received.len() as u64);
+            received
+        };
+        let received = lost(7);
+        assert!(received.len() > 8 && received.len() < 56, "{:?}", received);
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(lost(7), received);
+    }
+
+    #[test]
+    fn a_half_closed_connection_stays_open_on_the_side_that_closed_it() {
+        let network = InMemoryNetwork::new();
+        let (mut first, mut second) = (Host::new(&network, 8), Host::new(&network, 8));
+        second.transport.listen(addr(9001)).unwrap();
+        let (dialed, accepted) = connect(&mut first, &mut second);
+
+        assert_eq!(network.half_close(addr(9001), addr(9000)), 1);
+        assert_eq!(first.next(), Some(OwnedTransportEvent::Closed(accepted)));
+        assert!(second.transport.is_open(dialed));
+        assert_eq!(second.transport.send(dialed, b"void"), SendOutcome::Sent);
+        assert_eq!(first.next(), None);
+        assert_eq!(network.connections(), 1);
+        assert_eq!(network.dropped(), 1);
+
+        assert!(second.transport.close(dialed));
+        assert_eq!(first.next(), None);
+        assert_eq!(network.connections(), 0);
+    }
+
+    #[test]
+    fn an_owned_event_borrows_back_as_the_same_event() {
+        let conn = Connection::new(7, addr(9000));
+        let owned = OwnedTransportEvent::Frame(conn, b"frame".to_vec());
+        let TransportEvent::Frame(borrowed, frame) = owned.as_event() else {
+            panic!("the frame changed kind");
+        };
+        assert_eq!((borrowed, frame), (conn, &b"frame"[..]));
+        assert_eq!(conn.to_string(), "[7] 127.0.0.1:9000");
+    }
+}
@@ -11,22 +11,31 @@
 //! - **Address Conversion**: A trait `ToSocketAddr` and its implementations allow for flexible
 //!   conversion from various types to `SocketAddr`, streamlining operations that require
 //!   network addresses.
+//! - **Address Identity**: `CanonicalAddr` compares and hashes addresses by their canonical
+//!   form, so the IPv4-mapped IPv6 form of an address is the same peer as the address itself,
+//!   while logs and the wire keep the form the address was given in.
 //! - **Address Formatting**: `format_list_of_addrs` function for generating human-readable
-//!   strings from lists of addresses, aiding in logging and diagnostics.
+//!   strings from lists of addresses, aiding in logging and diagnostics, and
+//!   `format_addrs_capped`, with a cap that keeps log lines short however large the network
+//!   grows.
 //! - **Message Sending**: `send_message` function encapsulates the serialization of message
-//!   content and network transmission, leveraging `message-io` for efficient asynchronous
-//!   communication.
+//!   content and network transmission through any `Transport`. `broadcast_message` serializes
+//!   once for any number of receivers.
 //!
-//! These utilities are designed to work with the `message-io` library, providing a high-level
-//! abstraction for network message handling that can be easily integrated into applications
-//! requiring network communication capabilities.
+//! These utilities are written against the `Transport` trait, so they work with whichever
+//! backend carries the frames of the participant.
 
-use std::net::SocketAddr;
+use std::cmp::Ordering;
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
+use std::net::{SocketAddr, SocketAddrV6};
+use std::time::{Duration, Instant};
 
-use message_io::network::Endpoint;
-use message_io::node::NodeHandler;
+use crate::participant::transport::Connection;
 
 use crate::participant::message::Message;
+use crate::participant::storage::{ParticipantAddress, ParticipantsStorage};
+use crate::participant::transport::{SendOutcome, Transport};
 
 /// Trait for obtaining a `SocketAddr` from various types.
 ///
@@ -37,15 +46,15 @@ pub trait ToSocketAddr {
     fn get_addr(&self) -> SocketAddr;
 }
 
-/// Implementation of `ToSocketAddr` for `Endpoint`.
-impl ToSocketAddr for Endpoint {
+/// Implementation of `ToSocketAddr` for `Connection`.
+impl ToSocketAddr for Connection {
     fn get_addr(&self) -> SocketAddr {
         self.addr()
     }
 }
 
-/// Implementation of `ToSocketAddr` for a reference to `Endpoint`.
-impl ToSocketAddr for &Endpoint {
+/// Implementation of `ToSocketAddr` for a reference to `Connection`.
+impl ToSocketAddr for &Connection {
     fn get_addr(&self) -> SocketAddr {
         self.addr()
     }
@@ -65,11 +74,98 @@ impl ToSocketAddr for &SocketAddr {
     }
 }
 
+/// Implementation of `ToSocketAddr` for a participant, which resolves to its public address.
+impl ToSocketAddr for ParticipantAddress<Connection> {
+    fn get_addr(&self) -> SocketAddr {
+        self.public
+    }
+}
+
+/// Returns the canonical form of `addr`, the one address identity comparisons use.
+///
+/// An IPv4-mapped IPv6 address such as `[::ffff:127.0.0.1]:8080` becomes the IPv4 address
+/// `127.0.0.1:8080`, and the flow information of other IPv6 addresses, which does not tell
+/// peers apart, is cleared. Ports always matter.
+pub fn canonical(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => addr,
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), v6.port()),
+            None => SocketAddrV6::new(*v6.ip(), v6.port(), 0, v6.scope_id()).into(),
+        },
+    }
+}
+
+/// A peer address compared and hashed by its `canonical` form.
+///
+/// Used as the key of every map and set holding peer identities: public addresses, dialed
+/// addresses, blocked addresses. It is ordered by the canonical form too, so sorted sets agree
+/// with hashed ones. It remembers the address as it was given, which is what it
+/// displays, so logs show the address the peer announced.
+#[derive(Debug, Clone, Copy)]
+pub struct CanonicalAddr {
+    original: SocketAddr,
+    canonical: SocketAddr,
+}
+
+impl CanonicalAddr {
+    /// Returns the address as it was given.
+    pub fn original(&self) -> SocketAddr {
+        self.original
+    }
+}
+
+impl From<SocketAddr> for CanonicalAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            original: addr,
+            canonical: canonical(addr),
+        }
+    }
+}
+
+impl PartialEq for CanonicalAddr {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical == other.canonical
+    }
+}
+
+impl Eq for CanonicalAddr {}
+
+impl Hash for CanonicalAddr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical.hash(state);
+    }
+}
+
+impl PartialOrd for CanonicalAddr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CanonicalAddr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.canonical.cmp(&other.canonical)
+    }
+}
+
+impl fmt::Display for CanonicalAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.original)
+    }
+}
+
+/// Determines whether two addresses are the same peer, see `canonical`.
+pub fn same_addr(a: SocketAddr, b: SocketAddr) -> bool {
+    canonical(a) == canonical(b)
+}
+
 /// Formats a list of items that implement `ToSocketAddr` into a string.
 ///
 /// This function takes a slice of items that can be converted to `SocketAddr` and formats
-/// them into a human-readable string representation. It is particularly useful for logging
-/// and displaying participant addresses in the network.
+/// them into a human-readable string representation, every one of them. Log lines, which
+/// should not grow with the network, use `format_addrs_capped` instead.
 ///
 /// # Parameters
 ///
@@ -79,31 +175,322 @@ impl ToSocketAddr for &SocketAddr {
 ///
 /// A string representation of the list of addresses.
 pub fn format_list_of_addrs<T: ToSocketAddr>(items: &[T]) -> String {
+    format_addrs_capped(items, items.len())
+}
+
+/// The number of addresses shown by `format_addrs_capped` in log lines.
+pub const DEFAULT_ADDRS_CAP: usize = 16;
+
+/// Formats a list of items that implement `ToSocketAddr` into a string, showing at most
+/// `max_items` of them.
+///
+/// The addresses past the cap are summarized as `… and N more`, so logging a list of
+/// thousands of participants costs no more than logging a short one. The output is written
+/// into a single preallocated `String`.
+///
+/// # Parameters
+///
+/// - `items`: A slice of items implementing `ToSocketAddr`.
+/// - `max_items`: The number of addresses shown before the summary.
+///
+/// # Returns
+///
+/// A string representation of the list of addresses.
+pub fn format_addrs_capped<T: ToSocketAddr>(items: &[T], max_items: usize) -> String {
     if items.is_empty() {
-        "[no one]".to_owned()
-    } else {
-        let joined = items
-            .iter()
-            .map(|x| format!("\"{}\"", ToSocketAddr::get_addr(x)))
-            .collect::<Vec<String>>()
-            .join(", ");
+        return "[no one]".to_owned();
+    }
 
-        format!("[{}]", joined)
+    // A quoted IPv4 address with its port and separator takes at most 25 bytes.
+    let shown = items.len().min(max_items);
+    let mut output = String::with_capacity(shown * 25 + 24);
+    output.push('[');
+    for (position, item) in items.iter().take(shown).enumerate() {
+        if position > 0 {
+            output.push_str(", ");
+        }
+        let _ = write!(output, "\"{}\"", ToSocketAddr::get_addr(item));
     }
+    if items.len() > shown {
+        if shown > 0 {
+            output.push(' ');
+        }
+        let _ = write!(output, "… and {} more", items.len() - shown);
+    }
+    output.push(']');
+    output
 }
 
 /// Sends a serialized message to a specified endpoint using a `NodeHandler`.
 ///
 /// This function serializes a given message and sends it to the specified endpoint
-/// via the network managed by the `NodeHandler`. It encapsulates the serialization
-/// and network sending steps, streamlining message dispatch.
+/// through the given transport. It encapsulates the serialization
+/// and network sending steps, streamlining message dispatch, and counts the message
+/// in the per-kind statistics of the receiving participant.
 ///
 /// # Parameters
 ///
-/// - `handler`: A mutable reference to a `NodeHandler` for managing network operations.
-/// - `to`: The target `Endpoint` to send the message to.
+/// - `transport`: The transport carrying the message.
+/// - `participants`: The storage in which the sent message is counted.
+/// - `to`: The target connection to send the message to.
 /// - `msg`: A reference to the message to be sent.
-pub fn send_message(handler: &mut NodeHandler<()>, to: Endpoint, msg: &Message) {
-    let output_data = bincode::serialize(msg).unwrap();
-    handler.network().send(to, &output_data);
+pub fn send_message<T: Transport + ?Sized>(
+    transport: &mut T,
+    participants: &mut ParticipantsStorage<Connection>,
+    to: Connection,
+    msg: &Message,
+) {
+    let output_data = msg.encode();
+    transport.send(to, &output_data);
+    participants.record_sent_kind(&to, msg.kind());
+}
+
+/// Sends the same message to several endpoints, serializing it only once.
+///
+/// Unlike `send_message`, this does not count the messages, since observers, which are not
+/// participants, receive broadcasts too. Callers count participant receivers themselves with
+/// `ParticipantsStorage::record_sent_kind`.
+///
+/// # Parameters
+///
+/// - `transport`: The transport carrying the message.
+/// - `receivers`: The target connections.
+/// - `msg`: A reference to the message to be sent.
+pub fn broadcast_message<T: Transport + ?Sized>(
+    transport: &mut T,
+    receivers: &[Connection],
+    msg: &Message,
+) {
+    let output_data = msg.encode();
+    for to in receivers {
+        transport.send(*to, &output_data);
+    }
+}
+
+/// The outcome of sending a message to one receiver of `broadcast_timed`.
+#[derive(Debug, Clone, Copy)]
+pub struct TimedSend<C = Connection> {
+    /// The receiver.
+    pub endpoint: C,
+    /// What the transport did with the message.
+    pub status: SendOutcome,
+    /// How long the send call took.
+    pub elapsed: Duration,
+}
+
+/// Sends the same encoded message to several endpoints and times every send.
+///
+/// Like `broadcast_message`, this does not count the messages. A send that blocks on a full
+/// connection still delays the following ones, since `message-io` retries it until the data
+/// is written; the timings tell the caller which receiver did it.
+///
+/// # Parameters
+///
+/// - `transport`: The transport carrying the message.
+/// - `receivers`: The target connections.
+/// - `output_data`: The message, encoded once for all receivers.
+///
+/// # Returns
+///
+/// The outcome of every send, in the order of `receivers`.
+pub fn broadcast_timed<T: Transport + ?Sized>(
+    transport: &mut T,
+    receivers: &[Connection],
+    output_data: &[u8],
+) -> Vec<TimedSend> {
+    receivers
+        .iter()
+        .map(|endpoint| {
+            let started = Instant::now();
+            let status = transport.send(*endpoint, output_data);
+            TimedSend {
+                endpoint: *endpoint,
+                status,
+                elapsed: started.elapsed(),
+            }
+        })
+        .collect()
+}
+
+/// Sends several frames to one endpoint, timing them as a single send.
+///
+/// # Parameters
+///
+/// - `transport`: The transport carrying the frames.
+/// - `endpoint`: The receiver.
+/// - `frames`: The encoded frames, in sending order.
+///
+/// # Returns
+///
+/// The outcome of the sends: the first status other than `SendOutcome::Sent`, if any, and the
+/// time all of them took.
+pub fn send_frames_timed<T: Transport + ?Sized>(
+    transport: &mut T,
+    endpoint: Connection,
+    frames: &[Vec<u8>],
+) -> TimedSend {
+    let started = Instant::now();
+    let mut status = SendOutcome::Sent;
+    for frame in frames {
+        let frame_status = transport.send(endpoint, frame);
+        if status == SendOutcome::Sent {
+            status = frame_status;
+        }
+    }
+    TimedSend {
+        endpoint,
+        status,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn addr_of(text: &str) -> SocketAddr {
+        text.parse().unwrap()
+    }
+
+    fn addrs(count: u16) -> Vec<SocketAddr> {
+        (1..=count).map(addr).collect()
+    }
+
+    fn mapped(v4: &str) -> SocketAddr {
+        let v4: SocketAddr = v4.parse().unwrap();
+        let SocketAddr::V4(v4) = v4 else {
+            unreachable!()
+        };
+        SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+    }
+
+    fn hash_of(addr: CanonicalAddr) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        addr.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn maps_ipv4_mapped_addresses_to_ipv4() {
+        assert_eq!(canonical(mapped("10.0.0.1:8000")), addr_of("10.0.0.1:8000"));
+        assert_eq!(
+            canonical(addr_of("10.0.0.1:8000")),
+            addr_of("10.0.0.1:8000")
+        );
+        assert_eq!(canonical(addr_of("[::1]:8000")), addr_of("[::1]:8000"));
+        // The IPv4-compatible form is another address.
+        assert_eq!(
+            canonical(addr_of("[::10.0.0.1]:8000")),
+            addr_of("[::a00:1]:8000")
+        );
+
+        let flowing = SocketAddrV6::new("fe80::1".parse().unwrap(), 8000, 7, 2);
+        let SocketAddr::V6(plain) = canonical(flowing.into()) else {
+            panic!("an IPv6 address stays IPv6")
+        };
+        assert_eq!((plain.flowinfo(), plain.scope_id()), (0, 2));
+    }
+
+    #[test]
+    fn a_mapped_address_is_the_same_peer() {
+        assert!(same_addr(mapped("10.0.0.1:8000"), addr_of("10.0.0.1:8000")));
+        assert!(!same_addr(
+            mapped("10.0.0.1:8000"),
+            addr_of("10.0.0.1:8001")
+        ));
+        assert!(!same_addr(
+            mapped("10.0.0.1:8000"),
+            addr_of("10.0.0.2:8000")
+        ));
+
+        let given = CanonicalAddr::from(mapped("10.0.0.1:8000"));
+        let plain = CanonicalAddr::from(addr_of("10.0.0.1:8000"));
+        assert_eq!(given, plain);
+        assert_eq!(hash_of(given), hash_of(plain));
+        assert_eq!(given.original(), mapped("10.0.0.1:8000"));
+        assert_eq!(given.to_string(), "[::ffff:10.0.0.1]:8000");
+    }
+
+    #[test]
+    fn keys_sets_by_the_canonical_form() {
+        let forms = [
+            mapped("10.0.0.1:8000"),
+            addr_of("10.0.0.1:8000"),
+            addr_of("10.0.0.1:8001"),
+        ];
+        let hashed: std::collections::HashSet<CanonicalAddr> =
+            forms.iter().copied().map(CanonicalAddr::from).collect();
+        let sorted: std::collections::BTreeSet<CanonicalAddr> =
+            forms.iter().copied().map(CanonicalAddr::from).collect();
+        assert_eq!(hashed.len(), 2);
+        assert_eq!(sorted.len(), 2);
+        assert!(sorted.contains(&CanonicalAddr::from(mapped("10.0.0.1:8001"))));
+    }
+
+    #[test]
+    fn orders_mapped_addresses_among_ipv4_ones() {
+        let mut addrs: Vec<CanonicalAddr> = [
+            addr_of("[::1]:8000"),
+            mapped("10.0.0.2:8000"),
+            addr_of("10.0.0.1:8000"),
+            mapped("10.0.0.1:7000"),
+        ]
+        .into_iter()
+        .map(CanonicalAddr::from)
+        .collect();
+        addrs.sort();
+        let originals: Vec<SocketAddr> = addrs.iter().map(CanonicalAddr::original).collect();
+        assert_eq!(
+            originals,
+            [
+                mapped("10.0.0.1:7000"),
+                addr_of("10.0.0.1:8000"),
+                mapped("10.0.0.2:8000"),
+                addr_of("[::1]:8000"),
+            ]
+        );
+        assert_eq!(
+            CanonicalAddr::from(mapped("10.0.0.1:8000"))
+                .cmp(&CanonicalAddr::from(addr_of("10.0.0.1:8000"))),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn formats_no_address_as_no_one() {
+        let none: &[SocketAddr] = &[];
+        assert_eq!(format_list_of_addrs(none), "[no one]");
+        assert_eq!(format_addrs_capped(none, DEFAULT_ADDRS_CAP), "[no one]");
+        assert_eq!(format_addrs_capped(none, 0), "[no one]");
+    }
+
+    #[test]
+    fn formats_every_address_under_the_cap() {
+        let expected = "[\"127.0.0.1:1\", \"127.0.0.1:2\", \"127.0.0.1:3\"]";
+        assert_eq!(format_addrs_capped(&addrs(3), DEFAULT_ADDRS_CAP), expected);
+        assert_eq!(format_addrs_capped(&addrs(3), 3), expected);
+        assert_eq!(format_list_of_addrs(&addrs(3)), expected);
+    }
+
+    #[test]
+    fn summarizes_the_addresses_past_the_cap() {
+        assert_eq!(
+            format_addrs_capped(&addrs(5), 2),
+            "[\"127.0.0.1:1\", \"127.0.0.1:2\" … and 3 more]"
+        );
+        assert_eq!(format_addrs_capped(&addrs(5), 0), "[… and 5 more]");
+
+        let line = format_addrs_capped(&addrs(1000), DEFAULT_ADDRS_CAP);
+        assert_eq!(line.matches("127.0.0.1").count(), DEFAULT_ADDRS_CAP);
+        assert!(line.ends_with(" … and 984 more]"), "{}", line);
+        assert!(line.len() < 25 * (DEFAULT_ADDRS_CAP + 1));
+    }
+
+    #[test]
+    fn formats_every_address_without_a_cap() {
+        let line = format_list_of_addrs(&addrs(1000));
+        assert_eq!(line.matches("127.0.0.1").count(), 1000);
+        assert!(!line.contains("more"), "{}", line);
+    }
 }
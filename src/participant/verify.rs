@@ -0,0 +1,480 @@
+//! Bootstrap Verification.
+//!
+//! The `--connect` target is trusted like no other peer: its participants list is imported
+//! whoever else lists its addresses, and its participants are dialed at once. A typo in the
+//! target that happens to reach the gossip network of someone else imports all of it.
+//!
+//! With `--expect-network-id` and `--expect-node-id`, the participant checks who answers
+//! before trusting it. A handshake presents the `--network` name and the node id of the
+//! sender, in the trailer of the participants list the target sends back; the ed25519 key of
+//! an identity only signs the snapshots it exports and is never presented, and the protocol
+//! carries no peer name, so there is no fingerprint to check and the request for that list
+//! still goes out first. The first frame of the target, which `contact` already requires to
+//! be that list, is checked before anything else is done with it: no address it lists is
+//! stored or dialed, the target is not remembered in the peer cache, and the remaining
+//! bootstrap candidates are kept until it passes. A first frame without a network or a node
+//! id fails the check like a wrong one, and a target that stays silent is closed after
+//! `FIRST_CONTACT_TIMEOUT` like any other connection.
+//!
+//! A failed check closes the connection and moves to the next bootstrap candidate, logging
+//! which expectation failed, with what was expected and what was presented; the network is
+//! checked first. If no other candidate connects, the participant exits with the code of an
+//! unverified bootstrap rather than running isolated. `--bootstrap-verification=warn` only
+//! logs the mismatch and trusts the target anyway.
+
+use crate::participant::identity::NodeId;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// What a failed bootstrap verification does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerificationMode {
+    /// The target is dropped, and the participant exits if no other candidate connects.
+    #[default]
+    Fail,
+    /// The mismatch is logged and the target trusted anyway.
+    Warn,
+}
+
+impl VerificationMode {
+    /// Returns the name of this mode, as in `--bootstrap-verification`.
+    pub fn name(self) -> &'static str {
+        match self {
+            VerificationMode::Fail => "fail",
+            VerificationMode::Warn => "warn",
+        }
+    }
+}
+
+impl fmt::Display for VerificationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for VerificationMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "fail" => Ok(VerificationMode::Fail),
+            "warn" => Ok(VerificationMode::Warn),
+            _ => Err(format!("unknown bootstrap verification mode \"{}\"", text)),
+        }
+    }
+}
+
+/// An expectation a bootstrap target did not meet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The target announced another network than the one of `--expect-network-id`, or none.
+    Network {
+        expected: String,
+        presented: Option<String>,
+    },
+    /// The target presented another node id than the one of `--expect-node-id`, or none.
+    NodeId {
+        expected: NodeId,
+        presented: Option<NodeId>,
+    },
+}
+
+/// A bootstrap target that did not present the expected identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationFailure {
+    /// The address the target was dialed at.
+    pub addr: SocketAddr,
+    /// The expectation it did not meet.
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for VerificationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mismatch {
+            Mismatch::Network {
+                expected,
+                presented: Some(presented),
+            } => write!(
+                f,
+                "\"{}\" announced network \"{}\", expected \"{}\"",
+                self.addr, presented, expected
+            ),
+            Mismatch::Network {
+                expected,
+                presented: None,
+            } => write!(
+                f,
+                "\"{}\" announced no network, expected \"{}\"",
+                self.addr, expected
+            ),
+            Mismatch::NodeId {
+                expected,
+                presented: Some(presented),
+            } => write!(
+                f,
+                "\"{}\" presented node id {}, expected {}",
+                self.addr, presented, expected
+            ),
+            Mismatch::NodeId {
+                expected,
+                presented: None,
+            } => write!(
+                f,
+                "\"{}\" presented no node id, expected {}",
+                self.addr, expected
+            ),
+        }
+    }
+}
+
+/// What the first frame of a connection decides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The connection is not to a target being verified.
+    Unchecked,
+    /// The target presented the expected network and node id.
+    Passed,
+    /// The target presented another network or node id, or none.
+    Failed(VerificationFailure),
+}
+
+/// What a bootstrap target is expected to present.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expected {
+    /// The network given with `--expect-network-id`.
+    pub network: Option<String>,
+    /// The node id given with `--expect-node-id`.
+    pub node_id: Option<NodeId>,
+}
+
+impl Expected {
+    /// Determines whether anything is expected at all.
+    fn is_empty(&self) -> bool {
+        self.network.is_none() && self.node_id.is_none()
+    }
+
+    /// Returns the first expectation that what was presented does not meet, if any.
+    fn mismatch(&self, network: Option<&str>, node_id: Option<NodeId>) -> Option<Mismatch> {
+        if let Some(expected) = &self.network {
+            if network != Some(expected.as_str()) {
+                return Some(Mismatch::Network {
+                    expected: expected.clone(),
+                    presented: network.map(str::to_owned),
+                });
+            }
+        }
+        match self.node_id {
+            Some(expected) if node_id != Some(expected) => Some(Mismatch::NodeId {
+                expected,
+                presented: node_id,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The verification of the `--connect` target, keyed by connection.
+#[derive(Debug)]
+pub struct BootstrapCheck<K> {
+    target: Option<String>,
+    expected: Expected,
+    mode: VerificationMode,
+    pending: Option<(K, SocketAddr)>,
+    failure: Option<VerificationFailure>,
+}
+
+impl<K: PartialEq> BootstrapCheck<K> {
+    /// Creates the check of `target`, which verifies nothing when nothing is `expected`.
+    ///
+    /// # Parameters
+    ///
+    /// * `target` - The `--connect` target, as given.
+    /// * `expected` - The network and node id given with `--expect-network-id` and
+    ///   `--expect-node-id`.
+    /// * `mode` - What a failed verification does.
+    pub fn new(target: Option<String>, expected: Expected, mode: VerificationMode) -> Self {
+        Self {
+            target,
+            expected,
+            mode,
+            pending: None,
+            failure: None,
+        }
+    }
+
+    /// Returns what a failed verification does.
+    pub fn mode(&self) -> VerificationMode {
+        self.mode
+    }
+
+    /// Records the dial of bootstrap candidate `candidate` at `addr` through `key`, to be
+    /// verified if it is the target.
+    pub fn dialed(&mut self, key: K, candidate: &str, addr: SocketAddr) {
+        if !self.expected.is_empty() && self.target.as_deref() == Some(candidate) {
+            self.pending = Some((key, addr));
+        }
+    }
+
+    /// Determines whether the connection `key` waits for its verification.
+    pub fn is_pending(&self, key: &K) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|(pending, _)| pending == key)
+    }
+
+    /// Checks the first frame received through `key`, with the `network` and the `node_id`
+    /// its trailer presented.
+    pub fn verify(&mut self, key: &K, network: Option<&str>, node_id: Option<NodeId>) -> Verdict {
+        if self.expected.is_empty() {
+            return Verdict::Unchecked;
+        }
+        let addr = match self.pending.take() {
+            Some((pending, addr)) if pending == *key => addr,
+            other => {
+                self.pending = other;
+                return Verdict::Unchecked;
+            }
+        };
+        let Some(mismatch) = self.expected.mismatch(network, node_id) else {
+            self.failure = None;
+            return Verdict::Passed;
+        };
+        let failure = VerificationFailure { addr, mismatch };
+        if self.mode == VerificationMode::Fail {
+            self.failure = Some(failure.clone());
+        }
+        Verdict::Failed(failure)
+    }
+
+    /// Forgets the verification of the connection `key`, closed before it ended.
+    ///
+    /// # Returns
+    ///
+    /// Whether the connection was waiting for its verification.
+    pub fn closed(&mut self, key: &K) -> bool {
+        if !self.is_pending(key) {
+            return false;
+        }
+        self.pending = None;
+        true
+    }
+
+    /// Returns the failure to exit with, left by the target while `VerificationMode::Fail` is
+    /// set and no verification passed since.
+    pub fn failure(&self) -> Option<VerificationFailure> {
+        self.failure.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    const EXPECTED: NodeId = NodeId(0xfeed);
+
+    fn check(mode: VerificationMode) -> BootstrapCheck<u16> {
+        let expected = Expected {
+            network: None,
+            node_id: Some(EXPECTED),
+        };
+        BootstrapCheck::new(Some("seed:8080".to_owned()), expected, mode)
+    }
+
+    fn node_id_failure(presented: Option<NodeId>) -> VerificationFailure {
+        VerificationFailure {
+            addr: addr(8080),
+            mismatch: Mismatch::NodeId {
+                expected: EXPECTED,
+                presented,
+            },
+        }
+    }
+
+    #[test]
+    fn only_the_target_is_verified() {
+        let mut check = check(VerificationMode::Fail);
+        check.dialed(1, "other:8080", addr(8081));
+        assert!(!check.is_pending(&1));
+        assert_eq!(check.verify(&1, None, None), Verdict::Unchecked);
+
+        check.dialed(2, "seed:8080", addr(8080));
+        assert!(check.is_pending(&2));
+        assert_eq!(check.verify(&1, None, Some(EXPECTED)), Verdict::Unchecked);
+        assert!(check.is_pending(&2));
+        assert_eq!(check.verify(&2, None, Some(EXPECTED)), Verdict::Passed);
+        // The first frame alone is checked.
+        assert_eq!(check.verify(&2, None, None), Verdict::Unchecked);
+        assert_eq!(check.failure(), None);
+    }
+
+    #[test]
+    fn nothing_is_verified_without_an_expectation() {
+        let mut check = BootstrapCheck::new(
+            Some("seed:8080".to_owned()),
+            Expected::default(),
+            Default::default(),
+        );
+        check.dialed(1u16, "seed:8080", addr(8080));
+        assert!(!check.is_pending(&1));
+        assert_eq!(
+            check.verify(&1, Some("lab"), Some(NodeId(1))),
+            Verdict::Unchecked
+        );
+    }
+
+    #[test]
+    fn a_wrong_or_missing_id_fails_until_a_candidate_passes() {
+        let mut check = check(VerificationMode::Fail);
+        for (key, presented) in [(1, Some(NodeId(1))), (2, None)] {
+            check.dialed(key, "seed:8080", addr(8080));
+            let failure = node_id_failure(presented);
+            assert_eq!(
+                check.verify(&key, None, presented),
+                Verdict::Failed(failure.clone())
+            );
+            assert_eq!(check.failure(), Some(failure));
+        }
+        check.dialed(3, "seed:8080", addr(8090));
+        check.verify(&3, None, Some(EXPECTED));
+        assert_eq!(check.failure(), None);
+    }
+
+    #[test]
+    fn the_network_is_checked_before_the_node_id() {
+        let expected = Expected {
+            network: Some("prod".to_owned()),
+            node_id: Some(EXPECTED),
+        };
+        let mut check = BootstrapCheck::new(
+            Some("seed:8080".to_owned()),
+            expected,
+            VerificationMode::Fail,
+        );
+        for (key, network, node_id, mismatch) in [
+            (
+                1,
+                Some("test"),
+                Some(NodeId(1)),
+                Some(Mismatch::Network {
+                    expected: "prod".to_owned(),
+                    presented: Some("test".to_owned()),
+                }),
+            ),
+            (
+                2,
+                None,
+                Some(EXPECTED),
+                Some(Mismatch::Network {
+                    expected: "prod".to_owned(),
+                    presented: None,
+                }),
+            ),
+            (
+                3,
+                Some("prod"),
+                Some(NodeId(1)),
+                Some(Mismatch::NodeId {
+                    expected: EXPECTED,
+                    presented: Some(NodeId(1)),
+                }),
+            ),
+            (4, Some("prod"), Some(EXPECTED), None),
+        ] {
+            check.dialed(key, "seed:8080", addr(8080));
+            let verdict = match mismatch {
+                Some(mismatch) => Verdict::Failed(VerificationFailure {
+                    addr: addr(8080),
+                    mismatch,
+                }),
+                None => Verdict::Passed,
+            };
+            assert_eq!(check.verify(&key, network, node_id), verdict, "{}", key);
+        }
+    }
+
+    #[test]
+    fn a_network_alone_can_be_expected() {
+        let expected = Expected {
+            network: Some("prod".to_owned()),
+            node_id: None,
+        };
+        let mut check = BootstrapCheck::new(
+            Some("seed:8080".to_owned()),
+            expected,
+            VerificationMode::Fail,
+        );
+        check.dialed(1u16, "seed:8080", addr(8080));
+        assert_eq!(check.verify(&1, Some("prod"), None), Verdict::Passed);
+    }
+
+    #[test]
+    fn warn_mode_reports_the_mismatch_without_failing() {
+        let mut check = check(VerificationMode::Warn);
+        assert_eq!(check.mode(), VerificationMode::Warn);
+        check.dialed(1, "seed:8080", addr(8080));
+        assert!(matches!(
+            check.verify(&1, None, Some(NodeId(1))),
+            Verdict::Failed(_)
+        ));
+        assert_eq!(check.failure(), None);
+    }
+
+    #[test]
+    fn a_closed_target_is_no_longer_pending() {
+        let mut check = check(VerificationMode::Fail);
+        check.dialed(1, "seed:8080", addr(8080));
+        assert!(!check.closed(&2));
+        assert!(check.closed(&1));
+        assert!(!check.closed(&1));
+        assert_eq!(check.verify(&1, None, None), Verdict::Unchecked);
+    }
+
+    #[test]
+    fn modes_and_failures_are_displayed() {
+        for mode in [VerificationMode::Fail, VerificationMode::Warn] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        assert_eq!(
+            "strict".parse::<VerificationMode>(),
+            Err("unknown bootstrap verification mode \"strict\"".to_owned())
+        );
+
+        assert_eq!(
+            node_id_failure(Some(NodeId(1))).to_string(),
+            format!(
+                "\"127.0.0.1:8080\" presented node id {}, expected {}",
+                NodeId(1),
+                EXPECTED
+            )
+        );
+        assert_eq!(
+            node_id_failure(None).to_string(),
+            format!(
+                "\"127.0.0.1:8080\" presented no node id, expected {}",
+                EXPECTED
+            )
+        );
+        let mut failure = VerificationFailure {
+            addr: addr(8080),
+            mismatch: Mismatch::Network {
+                expected: "prod".to_owned(),
+                presented: Some("test".to_owned()),
+            },
+        };
+        assert_eq!(
+            failure.to_string(),
+            "\"127.0.0.1:8080\" announced network \"test\", expected \"prod\""
+        );
+        failure.mismatch = Mismatch::Network {
+            expected: "prod".to_owned(),
+            presented: None,
+        };
+        assert_eq!(
+            failure.to_string(),
+            "\"127.0.0.1:8080\" announced no network, expected \"prod\""
+        );
+    }
+}
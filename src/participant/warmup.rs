@@ -0,0 +1,196 @@
+//! Warm-Up.
+//!
+//! Nodes brought up together by a deployment tool all dial the same seed in the same second,
+//! and the dials their participants lists then trigger flood the network at once. With
+//! `--warmup=<window>`, a participant spreads both over the window:
+//!
+//! - it waits a random fraction of the window before it dials its bootstrap targets and so
+//!   announces itself, so the seed sees the nodes arrive one after the other;
+//! - until the window is over, it dials at most one learned address per `WARMUP_DIAL_PACE`,
+//!   queuing the others in a `Warmup` that the maintenance tick drains, and dials what is
+//!   still queued once the window is over.
+//!
+//! Inbound connections, the participants lists it serves and gossip are not held back. Its own
+//! address still opens those lists, since that entry is how their receiver learns the address
+//! it advertises; the receiver never dials it from there.
+
+use crate::participant::dials::DialTrigger;
+use crate::participant::message::AgeBucket;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The shortest time between two dials of learned addresses during the warm-up.
+pub const WARMUP_DIAL_PACE: Duration = Duration::from_secs(1);
+
+/// A dial of a learned address put off by the warm-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeferredDial {
+    /// The learned address.
+    pub addr: SocketAddr,
+    /// How fresh the listing peer said the address is.
+    pub age: AgeBucket,
+    /// The interface the address was learned on.
+    pub interface: usize,
+    /// The public address of the peer that listed it, if known.
+    pub origin: Option<SocketAddr>,
+    /// What made the participant dial it.
+    pub trigger: DialTrigger,
+}
+
+/// The warm-up window of a participant and the dials it put off.
+#[derive(Debug)]
+pub struct Warmup {
+    /// The end of the window, `None` when it lasts longer than an `Instant` can tell.
+    until: Option<Instant>,
+    announce_delay: Duration,
+    last_dial: Option<Instant>,
+    deferred: VecDeque<DeferredDial>,
+    deferred_total: u64,
+}
+
+impl Warmup {
+    /// Starts a warm-up of `window` at `now`.
+    ///
+    /// # Parameters
+    ///
+    /// * `window` - The length of the warm-up.
+    /// * `fraction` - The share of the window to wait before announcing, from 0 to 1.
+    /// * `now` - The start of the participant.
+    pub fn new(window: Duration, fraction: f64, now: Instant) -> Self {
+        Self {
+            until: now.checked_add(window),
+            announce_delay: window.mul_f64(fraction.clamp(0.0, 1.0)),
+            last_dial: None,
+            deferred: VecDeque::new(),
+            deferred_total: 0,
+        }
+    }
+
+    /// Returns how long the participant waits before dialing its bootstrap targets.
+    pub fn announce_delay(&self) -> Duration {
+        self.announce_delay
+    }
+
+    /// Determines whether the warm-up is still running at `now`.
+    pub fn is_warming(&self, now: Instant) -> bool {
+        self.until.is_none_or(|until| now < until)
+    }
+
+    /// Decides whether `dial` may start at `now`, queuing it when the pace does not allow it.
+    ///
+    /// # Returns
+    ///
+    /// `true` when the dial may start now; the dial is then counted against the pace.
+    pub fn admit(&mut self, dial: DeferredDial, now: Instant) -> bool {
+        if !self.is_warming(now) {
+            return true;
+        }
+        let paced = self
+            .last_dial
+            .is_some_and(|last| now.saturating_duration_since(last) < WARMUP_DIAL_PACE);
+        if paced {
+            self.deferred.push_back(dial);
+            self.deferred_total += 1;
+            return false;
+        }
+        self.last_dial = Some(now);
+        true
+    }
+
+    /// Takes the put-off dials due at `now`: the oldest one when the pace allows it during the
+    /// warm-up, and every one left once it is over.
+    pub fn due(&mut self, now: Instant) -> Vec<DeferredDial> {
+        if !self.is_warming(now) {
+            return self.deferred.drain(..).collect();
+        }
+        let paced = self
+            .last_dial
+            .is_some_and(|last| now.saturating_duration_since(last) < WARMUP_DIAL_PACE);
+        if paced {
+            return Vec::new();
+        }
+        let Some(dial) = self.deferred.pop_front() else {
+            return Vec::new();
+        };
+        self.last_dial = Some(now);
+        vec![dial]
+    }
+
+    /// Returns the number of dials put off so far.
+    pub fn deferred_total(&self) -> u64 {
+        self.deferred_total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+
+    fn dial(port: u16) -> DeferredDial {
+        DeferredDial {
+            addr: addr(port),
+            age: AgeBucket::Fresh,
+            interface: 0,
+            origin: None,
+            trigger: DialTrigger::Learned,
+        }
+    }
+
+    #[test]
+    fn announces_after_its_share_of_the_window() {
+        let now = Instant::now();
+        let window = Duration::from_secs(10);
+        assert_eq!(
+            Warmup::new(window, 0.25, now).announce_delay(),
+            Duration::from_millis(2_500)
+        );
+        assert_eq!(
+            Warmup::new(window, -1.0, now).announce_delay(),
+            Duration::ZERO
+        );
+        assert_eq!(Warmup::new(window, 2.0, now).announce_delay(), window);
+    }
+
+    #[test]
+    fn paces_the_dials_during_the_window() {
+        let now = Instant::now();
+        let mut warmup = Warmup::new(Duration::from_secs(10), 0.0, now);
+
+        assert!(warmup.admit(dial(1), now));
+        assert!(!warmup.admit(dial(2), now));
+        assert!(!warmup.admit(dial(3), now + Duration::from_millis(500)));
+        assert_eq!(warmup.deferred_total(), 2);
+        assert!(warmup.due(now + Duration::from_millis(999)).is_empty());
+
+        let later = now + WARMUP_DIAL_PACE;
+        assert_eq!(warmup.due(later), [dial(2)]);
+        assert!(warmup.due(later).is_empty());
+        assert!(!warmup.admit(dial(4), later));
+    }
+
+    #[test]
+    fn dials_everything_once_the_window_is_over() {
+        let now = Instant::now();
+        let mut warmup = Warmup::new(Duration::from_secs(10), 0.0, now);
+        assert!(warmup.admit(dial(1), now));
+        assert!(!warmup.admit(dial(2), now));
+        assert!(!warmup.admit(dial(3), now));
+
+        let over = now + Duration::from_secs(10);
+        assert!(!warmup.is_warming(over));
+        assert_eq!(warmup.due(over), [dial(2), dial(3)]);
+        assert!(warmup.admit(dial(4), over));
+        assert!(warmup.admit(dial(5), over));
+        assert_eq!(warmup.deferred_total(), 2);
+    }
+
+    #[test]
+    fn a_window_past_the_clock_never_ends() {
+        let now = Instant::now();
+        let warmup = Warmup::new(Duration::MAX, 0.0, now);
+        assert!(warmup.is_warming(now + Duration::from_secs(100 * 365 * 24 * 60 * 60)));
+    }
+}
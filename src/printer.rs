@@ -8,10 +8,28 @@
 //! be utilized directly to log messages with their elapsed time from a given `Instant`.
 //! The `init` and `print_event` functions facilitate easy tracking of events relative
 //! to an application-defined starting point.
+//!
+//! Events often quote what a peer sent, such as a gossip text or a topic payload. Such strings
+//! go through `sanitize_for_log` first, so that a peer can neither forge an event by embedding
+//! a newline and a fake `# 00:00:00 - ` prefix, nor send escape sequences to the terminal.
+//!
+//! `silence_events` keeps every event of the participants off the standard output from then on,
+//! for commands that run participants only to report on them.
+//!
+//! Everything else the crate shows goes through here too: `print_line` for command output and
+//! reports, `print_error` for errors and warnings. Nothing outside `main.rs` uses `println!` or
+//! `eprintln!`, which a test of this module checks.
 
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::io::{self, Write as _};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Raised by `silence_events`.
+static SILENCED: AtomicBool = AtomicBool::new(false);
 
 /// A simple printer for logging events with time elapsed since an `Instant`.
 pub struct SimplePrinter;
@@ -29,21 +47,35 @@ impl SimplePrinter {
     ///
     /// Basic usage:
     ///
-    /// ```
+    /// ```ignore
     /// let start_time = std::time::Instant::now();
     /// let start_time = std::sync::Arc::new(start_time);
     /// simple_printer::SimplePrinter::time(start_time, "Hello, world!");
     /// ```
     fn time(start_time: Arc<Instant>, msg: &str) {
-        let elapsed = Instant::now().duration_since(*start_time);
+        if SILENCED.load(Ordering::Relaxed) {
+            return;
+        }
+        Self::elapsed(Instant::now().duration_since(*start_time), msg);
+    }
 
+    /// Prints a message with a given elapsed time.
+    ///
+    /// # Parameters
+    ///
+    /// * `elapsed`: The time shown before the message.
+    /// * `msg`: The message to print along with the elapsed time.
+    fn elapsed(elapsed: Duration, msg: &str) {
         // Calculate hours, minutes, and seconds from elapsed time
         let hours = elapsed.as_secs() / 3600;
         let minutes = (elapsed.as_secs() % 3600) / 60;
         let seconds = elapsed.as_secs() % 60;
 
         // Print the formatted message with elapsed time
-        println!("# {:02}:{:02}:{:02} - {}", hours, minutes, seconds, msg);
+        print_line(&format!(
+            "# {:02}:{:02}:{:02} - {}",
+            hours, minutes, seconds, msg
+        ));
     }
 }
 
@@ -65,7 +97,7 @@ impl SimplePrinter {
 ///
 /// Basic usage:
 ///
-/// ```
+/// ```ignore
 /// let addr = "127.0.0.1:8080".parse().unwrap();
 /// let start_time = simple_printer::init(&addr);
 /// ```
@@ -94,10 +126,217 @@ pub fn init(addr: &SocketAddr) -> Arc<Instant> {
 ///
 /// Basic usage:
 ///
-/// ```
+/// ```ignore
 /// // Assuming `start_time` has been initialized using `init` function
 /// simple_printer::print_event(start_time, "Event occurred");
 /// ```
 pub fn print_event(start_time: Arc<Instant>, msg: &str) {
     SimplePrinter::time(start_time, msg);
 }
+
+/// Stops printing events, for every participant of the process.
+pub fn silence_events() {
+    SILENCED.store(true, Ordering::Relaxed);
+}
+
+/// Prints a line of command output or of a report, as is, on the standard output.
+///
+/// Unlike events, such lines are printed even after `silence_events`, since they are what the
+/// silenced commands report. A closed standard output drops the line rather than panicking.
+///
+/// # Parameters
+///
+/// * `line`: The line to print, without its line feed.
+pub fn print_line(line: &str) {
+    let _ = writeln!(io::stdout().lock(), "{}", line);
+}
+
+/// Prints an error or a warning that belongs to no event on the standard error.
+///
+/// # Parameters
+///
+/// * `line`: The line to print, without its line feed.
+pub fn print_error(line: &str) {
+    let _ = writeln!(io::stderr().lock(), "{}", line);
+}
+
+/// Formats a duration for humans, such as `42s`, `3m 05s` or `2h 07m 13s`.
+///
+/// # Parameters
+///
+/// * `elapsed`: The duration to format, shown to the second.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let hours = elapsed.as_secs() / 3600;
+    let minutes = (elapsed.as_secs() % 3600) / 60;
+    let seconds = elapsed.as_secs() % 60;
+
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Prints an event with an elapsed time known in advance, such as the time of a recorded event.
+///
+/// Such events are what `replay` reports, so they are printed even after `silence_events`.
+///
+/// # Parameters
+///
+/// * `elapsed`: The time shown before the message.
+/// * `msg`: The message to print along with the elapsed time.
+pub fn print_at(elapsed: Duration, msg: &str) {
+    SimplePrinter::elapsed(elapsed, msg);
+}
+
+/// Determines whether `c` would change how a line reads rather than show as itself: the
+/// control characters, the line and paragraph separators, and the bidirectional overrides
+/// and isolates, which reorder the text around them.
+pub fn is_unprintable(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{2028}' | '\u{2029}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+        )
+}
+
+/// Makes a string received from a peer safe to print within an event.
+///
+/// Every unprintable character is replaced by a visible escape, `\n`, `\r` and `\t` for the
+/// usual ones and `\x1b` or `\u{202e}` for the others, and backslashes are doubled so that an
+/// escape can not be faked. The rest, multi-byte characters included, is kept as is.
+///
+/// # Parameters
+///
+/// * `text`: The string received from a peer.
+///
+/// # Returns
+///
+/// `text` itself when it has nothing to escape, or its escaped copy.
+pub fn sanitize_for_log(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| c == '\\' || is_unprintable(c)) {
+        return Cow::Borrowed(text);
+    }
+    let mut sanitized = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '\\' => sanitized.push_str("\\\\"),
+            '\n' => sanitized.push_str("\\n"),
+            '\r' => sanitized.push_str("\\r"),
+            '\t' => sanitized.push_str("\\t"),
+            c if is_unprintable(c) && (c as u32) < 0x100 => {
+                let _ = write!(sanitized, "\\x{:02x}", c as u32);
+            }
+            c if is_unprintable(c) => {
+                let _ = write!(sanitized, "\\u{{{:x}}}", c as u32);
+            }
+            c => sanitized.push(c),
+        }
+    }
+    Cow::Owned(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    /// The macros that write to the standard output or error behind the printer's back.
+    const RAW_PRINTS: [&str; 4] = ["println!(", "eprintln!(", "print!(", "eprint!("];
+
+    /// Finds the lines of `source` that print without the printer, as their 1-based numbers.
+    ///
+    /// Comments are skipped, doc examples included, and so is everything from the first
+    /// `#[cfg(test)]` on, where tests may print as they like.
+    fn stray_prints(source: &str) -> Vec<usize> {
+        source
+            .lines()
+            .take_while(|line| !line.trim_start().starts_with("#[cfg(test)]"))
+            .enumerate()
+            .filter(|(_, line)| !line.trim_start().starts_with("//"))
+            .filter(|(_, line)| {
+                RAW_PRINTS.iter().any(|raw| {
+                    line.match_indices(raw).any(|(at, _)| {
+                        // `print!(` is also the end of `eprint!(`, which is matched on its own.
+                        at == 0 || !line.as_bytes()[at - 1].is_ascii_alphanumeric()
+                    })
+                })
+            })
+            .map(|(index, _)| index + 1)
+            .collect()
+    }
+
+    /// Collects the Rust sources under `dir`.
+    fn sources(dir: &Path, found: &mut Vec<std::path::PathBuf>) {
+        for entry in fs::read_dir(dir).expect("the source tree is readable") {
+            let path = entry.expect("the source tree is readable").path();
+            if path.is_dir() {
+                sources(&path, found);
+            } else if path.extension().is_some_and(|extension| extension == "rs") {
+                found.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn the_stray_print_scan_catches_an_offender() {
+        let source = "fn report(peers: usize) {\n    print_line(\"fine\");\n    println!(\"{} peers\", peers);\n    eprint!(\"partial\");\n}\n";
+        assert_eq!(stray_prints(source), vec![3, 4]);
+    }
+
+    #[test]
+    fn the_stray_print_scan_skips_comments_and_tests() {
+        let source = "//! println!(\"doc\");\n/// eprintln!(\"doc\");\nfn quiet() {\n    // println!(\"commented out\");\n}\n\n#[cfg(test)]\nmod tests {\n    fn noisy() { println!(\"test output\"); }\n}\n";
+        assert!(stray_prints(source).is_empty());
+    }
+
+    #[test]
+    fn nothing_but_main_prints_without_the_printer() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut files = Vec::new();
+        sources(&root, &mut files);
+        files.sort();
+        assert!(files.len() > 1, "no sources found under {}", root.display());
+
+        let offenders: Vec<String> = files
+            .iter()
+            .filter(|path| *path != &root.join("main.rs"))
+            .flat_map(|path| {
+                let source = fs::read_to_string(path).expect("the source is readable");
+                stray_prints(&source)
+                    .into_iter()
+                    .map(move |line| format!("{}:{}", path.display(), line))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert!(
+            offenders.is_empty(),
+            "print through the printer instead: {}",
+            offenders.join(", ")
+        );
+    }
+
+    #[test]
+    fn format_elapsed_shows_only_the_units_needed() {
+        assert_eq!(format_elapsed(Duration::from_secs(42)), "42s");
+        assert_eq!(format_elapsed(Duration::from_secs(185)), "3m 05s");
+        assert_eq!(format_elapsed(Duration::from_secs(7633)), "2h 07m 13s");
+    }
+
+    #[test]
+    fn sanitize_for_log_escapes_what_could_forge_an_event() {
+        assert!(matches!(
+            sanitize_for_log("plain ü"),
+            Cow::Borrowed("plain ü")
+        ));
+        assert_eq!(
+            sanitize_for_log("a\n# 00:00:00 - fake"),
+            "a\\n# 00:00:00 - fake"
+        );
+        assert_eq!(sanitize_for_log("\x1b[2J\\n"), "\\x1b[2J\\\\n");
+        assert_eq!(sanitize_for_log("\u{202e}txt"), "\\u{202e}txt");
+    }
+}
@@ -0,0 +1,514 @@
+//! Self Test.
+//!
+//! `selftest` proves that the binary works on a host with a single command, without a second
+//! terminal or a pair of free ports. It starts two participants inside the process, connected
+//! through an `InMemoryNetwork` so that no socket is opened, the second dialing the first, and
+//! takes them through their lifecycle in stages:
+//!
+//! - `start`: both participants bind their ports and start their event loops;
+//! - `handshake`: each lists the other as connected, under the node id the other reports;
+//! - `list exchange`: each received the participants list of the other;
+//! - `broadcast`: each received `SELFTEST_TICKS` gossip texts of the other;
+//! - `probe`: a run of `SELFTEST_PROBES` probes from the second is answered by the first;
+//! - `shutdown`: the second shuts down gracefully and the first sees it depart.
+//!
+//! Each stage is checked against the `Report` of the participants, taken through their
+//! `ParticipantHandle`, never against their logs, which are silenced. A `StageRunner` polls the
+//! check of a stage until it passes, fails or `STAGE_TIMEOUT` elapses, so a hang makes a failed
+//! stage rather than a stuck process, and prints one line per stage:
+//!
+//! ```plaintext
+//! PASS start (3ms)
+//! PASS handshake (12ms)
+//! FAIL broadcast: timed out after 2s (received 1 and 2 texts)
+//! SKIP probe
+//! ```
+//!
+//! The stages after a failed one are skipped, since each builds on the previous ones. The
+//! participants keep their node ids in a temporary directory removed at the end, and use no
+//! peer cache, so the test leaves nothing behind.
+
+use crate::console::Command;
+use crate::participant::config::NodeConfig;
+use crate::participant::handle::ParticipantHandle;
+use crate::participant::message::MessageKind;
+use crate::participant::model::Participant;
+use crate::participant::report::Report;
+use crate::participant::session::DepartureReason;
+use crate::participant::transport::InMemoryNetwork;
+use crate::printer::print_line;
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The longest a stage may take before it counts as failed.
+pub const STAGE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the check of a running stage is repeated.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a participant is given to hand over its report.
+const REPORT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The gossip period of the participants of the test.
+const SELFTEST_PERIOD: &str = "200ms";
+
+/// The gossip texts each participant must receive from the other.
+pub const SELFTEST_TICKS: u64 = 3;
+
+/// The probes sent by the second participant to the first.
+pub const SELFTEST_PROBES: u32 = 3;
+
+/// The ports the two participants listen on, in their in-memory network.
+const SELFTEST_PORTS: [u16; 2] = [9000, 9001];
+
+/// The time between two probes of the test.
+const PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a stage ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageOutcome {
+    /// The check passed after the given time.
+    Passed(Duration),
+    /// The check failed for the given reason.
+    Failed(String),
+    /// The check did not pass within the timeout; the last reason it gave, if any.
+    TimedOut(Duration, Option<String>),
+    /// A previous stage failed, so the stage was not run.
+    Skipped,
+}
+
+/// The answer of a stage check.
+pub enum Check {
+    /// The stage passed.
+    Pass,
+    /// The stage has not passed yet, for the given reason; it is checked again.
+    Wait(String),
+    /// The stage failed and checking it again would not help.
+    Fail(String),
+}
+
+/// Runs the stages of a test in order, printing the outcome of each.
+#[derive(Debug, Default)]
+pub struct StageRunner {
+    outcomes: Vec<(String, StageOutcome)>,
+}
+
+impl StageRunner {
+    /// Constructs a runner that ran no stage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the stage `name`, repeating `check` every `POLL_INTERVAL` until it passes, fails or
+    /// `timeout` elapses. The stage is skipped if a previous one did not pass.
+    ///
+    /// # Returns
+    ///
+    /// Whether the stage passed.
+    pub fn stage(
+        &mut self,
+        name: &str,
+        timeout: Duration,
+        mut check: impl FnMut() -> Check,
+    ) -> bool {
+        let outcome = if self.failures() > 0 {
+            StageOutcome::Skipped
+        } else {
+            let started = Instant::now();
+            loop {
+                match check() {
+                    Check::Pass => break StageOutcome::Passed(started.elapsed()),
+                    Check::Fail(reason) => break StageOutcome::Failed(reason),
+                    Check::Wait(reason) if started.elapsed() >= timeout => {
+                        break StageOutcome::TimedOut(timeout, Some(reason))
+                    }
+                    Check::Wait(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        };
+        print_line(&describe(name, &outcome));
+        let passed = matches!(outcome, StageOutcome::Passed(_));
+        self.outcomes.push((name.to_owned(), outcome));
+        passed
+    }
+
+    /// Returns the number of stages that did not pass, skipped ones included.
+    pub fn failures(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|(_, outcome)| !matches!(outcome, StageOutcome::Passed(_)))
+            .count()
+    }
+
+    /// Returns the number of stages run or skipped.
+    pub fn stages(&self) -> usize {
+        self.outcomes.len()
+    }
+}
+
+/// Formats the line printed for a stage.
+fn describe(name: &str, outcome: &StageOutcome) -> String {
+    match outcome {
+        StageOutcome::Passed(elapsed) => format!("PASS {} ({}ms)", name, elapsed.as_millis()),
+        StageOutcome::Failed(reason) => format!("FAIL {}: {}", name, reason),
+        StageOutcome::TimedOut(timeout, None) => {
+            format!("FAIL {}: timed out after {:?}", name, timeout)
+        }
+        StageOutcome::TimedOut(timeout, Some(reason)) => {
+            format!("FAIL {}: timed out after {:?} ({})", name, timeout, reason)
+        }
+        StageOutcome::Skipped => format!("SKIP {}", name),
+    }
+}
+
+/// A participant of the test, running on its own thread.
+struct Node {
+    addr: SocketAddr,
+    handle: ParticipantHandle,
+    thread: JoinHandle<Result<(), String>>,
+}
+
+impl Node {
+    /// Takes the report of the participant.
+    fn report(&self) -> Result<Report, String> {
+        self.handle
+            .report(REPORT_TIMEOUT)
+            .ok_or_else(|| format!("\"{}\" did not hand over its report", self.addr))
+    }
+}
+
+/// Runs the self test.
+///
+/// # Parameters
+///
+/// * `config` - Builds the configuration of a participant from command-line arguments, as
+///   the participant would be started with.
+///
+/// # Returns
+///
+/// The number of stages that did not pass, out of the number of stages.
+pub fn run(config: impl Fn(&[String]) -> Result<NodeConfig, String>) -> (usize, usize) {
+    crate::printer::silence_events();
+    let dir = std::env::temp_dir().join(format!("gossip-p2p-selftest-{}", std::process::id()));
+    let mut runner = StageRunner::new();
+
+    let mut nodes: Option<(Node, Node)> = None;
+    runner.stage("start", STAGE_TIMEOUT, || match start(&config, &dir) {
+        Ok(started) => {
+            nodes = Some(started);
+            Check::Pass
+        }
+        Err(reason) => Check::Fail(reason),
+    });
+
+    if let Some((first, second)) = nodes {
+        run_stages(&mut runner, &first, &second);
+        first.handle.shutdown();
+        second.handle.shutdown();
+        let deadline = Instant::now() + STAGE_TIMEOUT;
+        while !(first.thread.is_finished() && second.thread.is_finished())
+            && Instant::now() < deadline
+        {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+
+    let failures = runner.failures();
+    match failures {
+        0 => print_line(&format!("Selftest passed: {} stages", runner.stages())),
+        failures => print_line(&format!(
+            "Selftest failed: {} of {} stages did not pass",
+            failures,
+            runner.stages()
+        )),
+    }
+    (failures, runner.stages())
+}
+
+/// Starts the two participants, the second dialing the first.
+fn start(
+    config: &impl Fn(&[String]) -> Result<NodeConfig, String>,
+    dir: &Path,
+) -> Result<(Node, Node), String> {
+    fs::create_dir_all(dir)
+        .map_err(|err| format!("can not create \"{}\": {}", dir.display(), err))?;
+    let network = InMemoryNetwork::new();
+    let [first_port, second_port] = SELFTEST_PORTS;
+    let first = spawn(config, dir, &network, first_port, None)?;
+    let second = spawn(config, dir, &network, second_port, Some(first.addr))?;
+    Ok((first, second))
+}
+
+/// Starts a participant of `network` listening on `port`, dialing `connect` if given.
+fn spawn(
+    config: &impl Fn(&[String]) -> Result<NodeConfig, String>,
+    dir: &Path,
+    network: &InMemoryNetwork,
+    port: u16,
+    connect: Option<SocketAddr>,
+) -> Result<Node, String> {
+    let identity: PathBuf = dir.join(format!("node-{}.id", port));
+    let mut args = vec![
+        format!("--period={}", SELFTEST_PERIOD),
+        format!("--port={}", port),
+        "--no-peer-cache".to_owned(),
+        format!("--identity-file={}", identity.display()),
+    ];
+    if let Some(connect) = connect {
+        args.push(format!("--connect={}", connect));
+    }
+    let config = config(&args)?;
+    let transport = network.transport();
+
+    // A participant stays on the thread it was made on, and hands its handle back from there.
+    let (started, waiter) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name(format!("selftest-{}", port))
+        .spawn(move || {
+            let participant = match Participant::with_transport(config, transport) {
+                Ok(participant) => participant,
+                Err(err) => {
+                    let reason = format!("port {} can not run: {}", port, err);
+                    let _ = started.send(Err(reason.clone()));
+                    return Err(reason);
+                }
+            };
+            let _ = started.send(Ok(participant.handle()));
+            participant.run().map_err(|err| err.to_string())
+        })
+        .map_err(|err| format!("can not start a thread: {}", err))?;
+    let handle = waiter
+        .recv_timeout(STAGE_TIMEOUT)
+        .map_err(|_| format!("port {} did not start in time", port))??;
+    Ok(Node {
+        addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        handle,
+        thread,
+    })
+}
+
+/// Runs the stages following `start`.
+fn run_stages(runner: &mut StageRunner, first: &Node, second: &Node) {
+    runner.stage("handshake", STAGE_TIMEOUT, || {
+        both(first, second, |report, peer| {
+            let peer_id = peer.report()?.node_id;
+            let connected = report.peers.iter().any(|listed| {
+                listed.addr == peer.addr
+                    && listed.connected_for.is_some()
+                    && listed.node_id == Some(peer_id)
+            });
+            Ok(connected.then_some(()).ok_or(format!(
+                "\"{}\" does not list \"{}\" as connected",
+                report.node, peer.addr
+            )))
+        })
+    });
+
+    runner.stage("list exchange", STAGE_TIMEOUT, || {
+        both(first, second, |report, _| {
+            let lists = [
+                MessageKind::PullParticipantsList,
+                MessageKind::SharedPeers,
+                MessageKind::ListReply,
+                MessageKind::CompactListReply,
+            ]
+            .iter()
+            .map(|kind| report.received[kind.index()])
+            .sum::<u64>();
+            Ok((lists > 0)
+                .then_some(())
+                .ok_or(format!("\"{}\" received no participants list", report.node)))
+        })
+    });
+
+    runner.stage("broadcast", STAGE_TIMEOUT, || {
+        both(first, second, |report, _| {
+            let texts = report.received[MessageKind::Text.index()];
+            Ok((texts >= SELFTEST_TICKS).then_some(()).ok_or(format!(
+                "\"{}\" received {} of {} texts",
+                report.node, texts, SELFTEST_TICKS
+            )))
+        })
+    });
+
+    let mut probing = false;
+    runner.stage("probe", STAGE_TIMEOUT, || {
+        if !probing {
+            let command = Command::Probe {
+                peer: first.addr,
+                count: SELFTEST_PROBES,
+                interval: PROBE_INTERVAL,
+            };
+            if second.handle.command(command, REPORT_TIMEOUT).is_none() {
+                return Check::Fail(format!("\"{}\" did not start the probes", second.addr));
+            }
+            probing = true;
+        }
+        match second.report() {
+            Ok(report) => {
+                let replies = report.received[MessageKind::ProbeReply.index()];
+                if replies >= u64::from(SELFTEST_PROBES) {
+                    Check::Pass
+                } else {
+                    Check::Wait(format!(
+                        "{} of {} probes answered",
+                        replies, SELFTEST_PROBES
+                    ))
+                }
+            }
+            Err(reason) => Check::Fail(reason),
+        }
+    });
+
+    let mut stopping = false;
+    runner.stage("shutdown", STAGE_TIMEOUT, || {
+        if !stopping {
+            second.handle.shutdown();
+            stopping = true;
+        }
+        if !second.thread.is_finished() {
+            return Check::Wait(format!("\"{}\" is still running", second.addr));
+        }
+        let report = match first.report() {
+            Ok(report) => report,
+            Err(reason) => return Check::Fail(reason),
+        };
+        let departed = report
+            .departures
+            .iter()
+            .any(|(reason, count)| *reason == DepartureReason::Disconnected && *count > 0);
+        let connected = report
+            .peers
+            .iter()
+            .any(|peer| peer.addr == second.addr && peer.connected_for.is_some());
+        if departed && !connected {
+            Check::Pass
+        } else {
+            Check::Wait(format!(
+                "\"{}\" did not see \"{}\" depart",
+                first.addr, second.addr
+            ))
+        }
+    });
+}
+
+/// Checks `check` for both participants, each along with the other; the stage passes once it
+/// holds for both.
+fn both(
+    first: &Node,
+    second: &Node,
+    check: impl Fn(&Report, &Node) -> Result<Result<(), String>, String>,
+) -> Check {
+    for (node, peer) in [(first, second), (second, first)] {
+        let outcome = node.report().and_then(|report| check(&report, peer));
+        match outcome {
+            Ok(Ok(())) => {}
+            Ok(Err(reason)) => return Check::Wait(reason),
+            Err(reason) => return Check::Fail(reason),
+        }
+    }
+    Check::Pass
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How long the stages of the tests of the runner may take.
+    const TIMEOUT: Duration = Duration::from_millis(60);
+
+    #[test]
+    fn a_stage_passes_once_its_check_does() {
+        let mut runner = StageRunner::new();
+        let mut checks = 0;
+        let passed = runner.stage("waits", STAGE_TIMEOUT, || {
+            checks += 1;
+            match checks {
+                3 => Check::Pass,
+                _ => Check::Wait("not yet".to_owned()),
+            }
+        });
+        assert!(passed);
+        assert_eq!(checks, 3);
+        assert_eq!((runner.failures(), runner.stages()), (0, 1));
+        assert!(matches!(runner.outcomes[0].1, StageOutcome::Passed(_)));
+    }
+
+    #[test]
+    fn a_failed_check_ends_its_stage_at_once() {
+        let mut runner = StageRunner::new();
+        let mut checks = 0;
+        let passed = runner.stage("fails", STAGE_TIMEOUT, || {
+            checks += 1;
+            Check::Fail("broken".to_owned())
+        });
+        assert!(!passed);
+        assert_eq!(checks, 1);
+        assert_eq!(
+            runner.outcomes[0].1,
+            StageOutcome::Failed("broken".to_owned())
+        );
+    }
+
+    #[test]
+    fn a_stage_still_waiting_at_its_timeout_times_out_with_the_last_reason() {
+        let mut runner = StageRunner::new();
+        let started = Instant::now();
+        let mut checks = 0;
+        let passed = runner.stage("hangs", TIMEOUT, || {
+            checks += 1;
+            Check::Wait(format!("check {}", checks))
+        });
+        assert!(!passed);
+        assert!(started.elapsed() >= TIMEOUT);
+        assert!(started.elapsed() < STAGE_TIMEOUT);
+        assert_eq!(
+            runner.outcomes[0].1,
+            StageOutcome::TimedOut(TIMEOUT, Some(format!("check {}", checks)))
+        );
+    }
+
+    #[test]
+    fn the_stages_after_a_failed_one_are_skipped_and_counted() {
+        let mut runner = StageRunner::new();
+        runner.stage("fails", TIMEOUT, || Check::Fail("broken".to_owned()));
+        let mut checked = false;
+        assert!(!runner.stage("skipped", TIMEOUT, || {
+            checked = true;
+            Check::Pass
+        }));
+        assert!(!checked);
+        assert_eq!(runner.outcomes[1].1, StageOutcome::Skipped);
+        assert_eq!((runner.failures(), runner.stages()), (2, 2));
+    }
+
+    #[test]
+    fn the_line_of_a_stage_tells_its_outcome() {
+        let lines = [
+            StageOutcome::Passed(Duration::from_millis(12)),
+            StageOutcome::Failed("broken".to_owned()),
+            StageOutcome::TimedOut(Duration::from_secs(2), None),
+            StageOutcome::TimedOut(Duration::from_secs(2), Some("1 of 3".to_owned())),
+            StageOutcome::Skipped,
+        ]
+        .iter()
+        .map(|outcome| describe("probe", outcome))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "PASS probe (12ms)",
+                "FAIL probe: broken",
+                "FAIL probe: timed out after 2s",
+                "FAIL probe: timed out after 2s (1 of 3)",
+                "SKIP probe",
+            ]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Signal Handling.
+//!
+//! This module turns Ctrl-C (`SIGINT`) and `SIGTERM` into a graceful shutdown request, and
+//! `SIGHUP` into a configuration reload request. The installed handlers only raise an atomic
+//! flag, which is the only kind of work that is safe to do inside a signal handler; the
+//! participant polls `shutdown_requested` and `reload_requested` and acts on its own thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Raised by the signal handler once a shutdown has been requested.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Raised by the signal handler when a reload is requested, lowered once it is taken.
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Records the shutdown request. Runs in signal context, so it must stay trivial.
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Records the reload request. Runs in signal context, so it must stay trivial.
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs the shutdown handler for `SIGINT` and `SIGTERM`, and the reload handler for
+/// `SIGHUP`, which would otherwise end the process.
+pub fn install() {
+    let shutdown = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    let reload = request_reload as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    // SAFETY: the handlers only perform an atomic store, which is async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, shutdown);
+        libc::signal(libc::SIGTERM, shutdown);
+        libc::signal(libc::SIGHUP, reload);
+    }
+}
+
+/// Returns `true` once a shutdown signal has been received.
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Takes the pending reload request: returns `true` once per batch of `SIGHUP` received since
+/// the last call.
+pub fn reload_requested() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::SeqCst)
+}
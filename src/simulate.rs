@@ -0,0 +1,314 @@
+//! Simulation.
+//!
+//! Runs named participants against each other in this process, on an `InMemoryNetwork`, and
+//! takes them through a timeline of events: nodes start and stop, partitions split the
+//! network and heal, links lose and delay frames, nodes reload their settings and publish.
+//! Assertions on the timeline and at its end check what the cluster holds, up to the
+//! invariants every healed network must hold.
+//!
+//! The `simulate --scenario=<path>` command runs the scenario file at the path, described in
+//! `scenario`, in real time or, with `--fast`, ten times faster. It prints the line of each
+//! event as it happens, then whether each assertion held:
+//!
+//! ```plaintext
+//! [   0.000s] start a
+//! [   1.000s] start b connecting to a
+//! [  10.000s] assert all connected within 5s
+//! [      end] assert the invariants hold
+//! PASS [  10.000s] assert all connected within 5s
+//! FAIL [      end] assert the invariants hold: runs: a run of b ended: panicked
+//! Scenario failed: 1 of 2 assertions did not hold
+//! ```
+//!
+//! An event that can not be taken, such as a node that does not start, stops the run; the
+//! assertions after it are skipped.
+//!
+//! ## Modules
+//!
+//! - `assertion`: Checks what a scenario expects of its cluster.
+//! - `churn`: Draws a random timeline of churn and chaos from a seed, and runs it; built for
+//!   the tests only.
+//! - `cluster`: Runs the participants of a simulation, each on a thread of its own.
+//! - `invariant`: Checks what must hold of a cluster once its network healed.
+//! - `scenario`: Parses the scenario files of the `simulate` command.
+//! - `script`: Holds the timeline of a simulation and takes its steps at their time.
+
+mod assertion;
+#[cfg(test)]
+mod churn;
+mod cluster;
+mod invariant;
+mod scenario;
+mod script;
+
+use crate::printer::print_line;
+use crate::simulate::cluster::{Cluster, TimeScale};
+use crate::simulate::scenario::Scenario;
+use crate::simulate::script::{execute, Action};
+
+use std::path::Path;
+
+/// The label of the assertions checked once the timeline is over, in place of their time.
+const END: &str = "[      end]";
+
+/// How an assertion of a scenario ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// It held.
+    Passed,
+    /// It did not hold, for the given reason.
+    Failed(String),
+    /// It was not checked, since the run stopped before it.
+    Skipped,
+}
+
+/// How the run of a scenario went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// The verdict of each assertion, with its line, in order.
+    pub verdicts: Vec<(String, Verdict)>,
+    /// The event the run stopped at and why, if one could not be taken.
+    pub stopped: Option<String>,
+}
+
+impl Outcome {
+    /// Returns the assertions that did not hold or were skipped.
+    pub fn failures(&self) -> usize {
+        self.verdicts
+            .iter()
+            .filter(|(_, verdict)| *verdict != Verdict::Passed)
+            .count()
+    }
+
+    /// Returns whether every event was taken and every assertion held.
+    pub fn passed(&self) -> bool {
+        self.stopped.is_none() && self.failures() == 0
+    }
+}
+
+/// Runs `scenario` on a cluster at `scale`, then checks its final assertions.
+///
+/// # Parameters
+///
+/// * `log` - Receives the line of each event, once it is taken.
+pub fn execute_scenario(
+    scenario: &Scenario,
+    scale: TimeScale,
+    log: &mut dyn FnMut(String),
+) -> Outcome {
+    let mut outcome = Outcome {
+        verdicts: Vec::new(),
+        stopped: None,
+    };
+    let declared = Cluster::new(scale, scenario.seed).and_then(|mut cluster| {
+        for node in &scenario.nodes {
+            cluster.declare(&node.name, node.settings.clone())?;
+        }
+        Ok(cluster)
+    });
+    let mut cluster = match declared {
+        Ok(cluster) => cluster,
+        Err(reason) => {
+            outcome.stopped = Some(reason);
+            skip(&mut outcome, scenario, 0);
+            return outcome;
+        }
+    };
+
+    let run = execute(&mut cluster, &scenario.script, log);
+    let checked = run.verdicts.len();
+    outcome.verdicts = run
+        .verdicts
+        .into_iter()
+        .map(|(step, verdict)| (step.to_string(), verdict_of(verdict)))
+        .collect();
+    if run.failed.is_some() {
+        outcome.stopped = run.failed;
+        skip(&mut outcome, scenario, checked);
+        return outcome;
+    }
+    for assertion in &scenario.assertions {
+        let line = format!("{} assert {}", END, assertion);
+        log(line.clone());
+        let verdict = assertion::evaluate(&cluster, assertion);
+        outcome.verdicts.push((line, verdict_of(verdict)));
+    }
+    outcome
+}
+
+/// Runs the scenario file at `path`, in real time or ten times faster if `fast`, printing
+/// the line of each event, the verdict of each assertion and a summary.
+///
+/// # Returns
+///
+/// How the run went, or why the file does not hold a scenario that can run.
+pub fn run(path: &Path, fast: bool) -> Result<Outcome, String> {
+    let scenario = scenario::load(path)?;
+    let scale = if fast {
+        TimeScale::FAST
+    } else {
+        TimeScale::REAL
+    };
+    let outcome = execute_scenario(&scenario, scale, &mut |line| print_line(&line));
+    if let Some(stopped) = &outcome.stopped {
+        print_line(&format!("STOP {}", stopped));
+    }
+    for (line, verdict) in &outcome.verdicts {
+        match verdict {
+            Verdict::Passed => print_line(&format!("PASS {}", line)),
+            Verdict::Failed(reason) => print_line(&format!("FAIL {}: {}", line, reason)),
+            Verdict::Skipped => print_line(&format!("SKIP {}", line)),
+        }
+    }
+    let assertions = outcome.verdicts.len();
+    match (outcome.passed(), outcome.failures()) {
+        (true, _) => print_line(&format!("Scenario passed: {} assertions", assertions)),
+        (false, 0) => print_line("Scenario failed: an event could not be taken"),
+        (false, failures) => print_line(&format!(
+            "Scenario failed: {} of {} assertions did not hold",
+            failures, assertions
+        )),
+    }
+    Ok(outcome)
+}
+
+/// Marks the assertions of `scenario` after the first `checked` as skipped.
+fn skip(outcome: &mut Outcome, scenario: &Scenario, checked: usize) {
+    let timeline = scenario
+        .script
+        .steps
+        .iter()
+        .filter(|step| matches!(step.action, Action::Assert(_)))
+        .skip(checked)
+        .map(|step| step.to_string());
+    let end = scenario
+        .assertions
+        .iter()
+        .map(|assertion| format!("{} assert {}", END, assertion));
+    outcome
+        .verdicts
+        .extend(timeline.chain(end).map(|line| (line, Verdict::Skipped)));
+}
+
+fn verdict_of(result: Result<(), String>) -> Verdict {
+    match result {
+        Ok(()) => Verdict::Passed,
+        Err(reason) => Verdict::Failed(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(verdicts: &[Verdict], stopped: Option<&str>) -> Outcome {
+        Outcome {
+            verdicts: verdicts
+                .iter()
+                .map(|verdict| ("line".to_owned(), verdict.clone()))
+                .collect(),
+            stopped: stopped.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn an_outcome_passes_when_every_event_was_taken_and_every_assertion_held() {
+        let failed = Verdict::Failed("no".to_owned());
+        assert!(outcome(&[], None).passed());
+        assert!(outcome(&[Verdict::Passed, Verdict::Passed], None).passed());
+        let missed = outcome(&[Verdict::Passed, failed, Verdict::Skipped], None);
+        assert_eq!(missed.failures(), 2);
+        assert!(!missed.passed());
+        let stopped = outcome(&[Verdict::Passed], Some("stop b: no"));
+        assert_eq!(stopped.failures(), 0);
+        assert!(!stopped.passed());
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_scenario_checks_its_assertions_on_the_timeline_and_at_its_end() {
+        let scenario = scenario::parse(
+            r#"{ "nodes": ["a", "b"], "bootstrap": [["b", "a"]],
+                 "timeline": [
+                     { "at": "1s", "assert": { "all-connected": { "within": "20s" } } },
+                     { "at": "2s", "publish": "a", "count": 2 }
+                 ],
+                 "assertions": [
+                     { "received": { "node": "b", "from": "a", "at-least": 2, "within": "10s" } },
+                     { "received": { "node": "a", "from": "b", "at-least": 1 } }
+                 ] }"#,
+        )
+        .unwrap();
+        let mut log = Vec::new();
+        let outcome = execute_scenario(&scenario, TimeScale::FAST, &mut |line| log.push(line));
+        assert_eq!(
+            outcome.verdicts,
+            [
+                (
+                    "[   1.000s] assert all connected within 20s".to_owned(),
+                    Verdict::Passed
+                ),
+                (
+                    "[      end] assert b received at least 2 from a within 10s".to_owned(),
+                    Verdict::Passed
+                ),
+                (
+                    "[      end] assert a received at least 1 from b".to_owned(),
+                    Verdict::Failed("a received 0 from b".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(outcome.stopped, None);
+        assert_eq!(outcome.failures(), 1);
+        assert_eq!(log.len(), 6);
+        assert_eq!(log[3], "[   2.000s] publish 2 from a");
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_run_that_stops_skips_the_assertions_after_it() {
+        let mut scenario = scenario::parse(
+            r#"{ "nodes": ["a"],
+                 "timeline": [
+                     { "at": "0s", "assert": { "invariants": {} } },
+                     { "at": "2s", "assert": { "all-connected": {} } }
+                 ],
+                 "assertions": [{ "invariants": {} }] }"#,
+        )
+        .unwrap();
+        // The parser lets no event through that can not be taken, so one is slipped in.
+        let stop = Action::Stop {
+            node: "x".to_owned(),
+        };
+        scenario.script.steps.insert(
+            2,
+            script::Step {
+                at: std::time::Duration::from_secs(1),
+                action: stop,
+            },
+        );
+        let outcome = execute_scenario(&scenario, TimeScale::FAST, &mut |_| {});
+        assert_eq!(
+            outcome.stopped.as_deref(),
+            Some("[   1.000s] stop x: node \"x\" is not declared")
+        );
+        assert_eq!(
+            outcome.verdicts,
+            [
+                (
+                    "[   0.000s] assert the invariants hold".to_owned(),
+                    Verdict::Passed
+                ),
+                (
+                    "[   2.000s] assert all connected".to_owned(),
+                    Verdict::Skipped
+                ),
+                (
+                    "[      end] assert the invariants hold".to_owned(),
+                    Verdict::Skipped
+                ),
+            ]
+        );
+        assert_eq!(outcome.failures(), 2);
+    }
+}
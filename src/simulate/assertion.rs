@@ -0,0 +1,251 @@
+//! Simulation Assertions.
+//!
+//! What a scenario expects of its cluster, at a point of its timeline or once it is over:
+//!
+//! - `AllConnected`: the running nodes converge to a full mesh within a time;
+//! - `Received`: a node received at least a number of messages from another on the traffic
+//!   topic, within a time;
+//! - `Invariants`: every invariant of `invariant` holds, within a time.
+//!
+//! `evaluate` checks an assertion on a running cluster and explains why it does not hold;
+//! `received` counts the messages of one publisher from what the cluster observed, so the
+//! count can be tested on its own.
+
+use crate::simulate::cluster::{Cluster, Traffic};
+use crate::simulate::invariant::{self, Violation};
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often an assertion given time is checked again.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What a scenario expects of its cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assertion {
+    /// The running nodes are all connected to each other, within the given simulated time.
+    AllConnected { within: Duration },
+    /// The node `node` received at least `at_least` messages from the node `from` over all of
+    /// its runs, within `within` of simulated time.
+    Received {
+        node: String,
+        from: String,
+        at_least: u64,
+        within: Duration,
+    },
+    /// Every invariant holds of the running nodes, within the given simulated time. Two nodes
+    /// dialing each other at once stay connected twice for a little while, which the audits
+    /// find, so the invariants may need time to hold once the nodes converged.
+    Invariants { within: Duration },
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let within = match self {
+            Assertion::AllConnected { within } => {
+                write!(f, "all connected")?;
+                within
+            }
+            Assertion::Received {
+                node,
+                from,
+                at_least,
+                within,
+            } => {
+                write!(f, "{} received at least {} from {}", node, at_least, from)?;
+                within
+            }
+            Assertion::Invariants { within } => {
+                write!(f, "the invariants hold")?;
+                within
+            }
+        };
+        if within.is_zero() {
+            return Ok(());
+        }
+        write!(f, " within {:?}", within)
+    }
+}
+
+/// Checks `assertion` on `cluster`, waiting for as long as it allows.
+///
+/// # Returns
+///
+/// `Ok(())` when it holds, or why it does not.
+pub fn evaluate(cluster: &Cluster, assertion: &Assertion) -> Result<(), String> {
+    match assertion {
+        Assertion::AllConnected { within } => {
+            invariant::converge(cluster, cluster.scale().real(*within))
+                .map_err(|violations| describe(&violations))
+        }
+        Assertion::Received {
+            node,
+            from,
+            at_least,
+            within,
+        } => {
+            let addr = cluster.addr(from)?;
+            let deadline = Instant::now() + cluster.scale().real(*within);
+            loop {
+                let count = received(&cluster.traffic(), node, addr);
+                if count >= *at_least {
+                    return Ok(());
+                }
+                if Instant::now() >= deadline {
+                    return Err(format!("{} received {} from {}", node, count, from));
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        Assertion::Invariants { within } => {
+            let deadline = Instant::now() + cluster.scale().real(*within);
+            loop {
+                let observations = invariant::observe(cluster)?;
+                let violations = invariant::check(cluster, &observations);
+                if violations.is_empty() {
+                    return Ok(());
+                }
+                if Instant::now() >= deadline {
+                    return Err(describe(&violations));
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Returns the messages the node `node` received from the publisher at `from`.
+pub fn received(traffic: &Traffic, node: &str, from: SocketAddr) -> u64 {
+    traffic
+        .received
+        .get(&(node.to_owned(), from))
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Joins `violations` into one explanation.
+fn describe(violations: &[Violation]) -> String {
+    let violations: Vec<String> = violations.iter().map(Violation::to_string).collect();
+    violations.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "net-tests")]
+    use crate::simulate::cluster::{Settings, TimeScale};
+
+    #[cfg(feature = "net-tests")]
+    fn pair() -> Cluster {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.declare("b", Settings::new()).unwrap();
+        cluster.start("a", None).unwrap();
+        cluster
+    }
+
+    #[test]
+    fn an_assertion_formats_as_its_log_line() {
+        let received = Assertion::Received {
+            node: "a".to_owned(),
+            from: "f".to_owned(),
+            at_least: 5,
+            within: Duration::from_secs(2),
+        };
+        assert_eq!(
+            received.to_string(),
+            "a received at least 5 from f within 2s"
+        );
+        let connected = Assertion::AllConnected {
+            within: Duration::from_secs(10),
+        };
+        assert_eq!(connected.to_string(), "all connected within 10s");
+        let connected = Assertion::AllConnected {
+            within: Duration::ZERO,
+        };
+        assert_eq!(connected.to_string(), "all connected");
+        let invariants = Assertion::Invariants {
+            within: Duration::ZERO,
+        };
+        assert_eq!(invariants.to_string(), "the invariants hold");
+    }
+
+    #[test]
+    fn the_messages_received_are_counted_for_one_publisher() {
+        let first = SocketAddr::from(([127, 0, 0, 1], 9000));
+        let second = SocketAddr::from(([127, 0, 0, 1], 9001));
+        let mut traffic = Traffic::default();
+        traffic.received.insert(("b".to_owned(), first), 3);
+        traffic.received.insert(("c".to_owned(), first), 7);
+        traffic.received.insert(("b".to_owned(), second), 1);
+        assert_eq!(received(&traffic, "b", first), 3);
+        assert_eq!(received(&traffic, "b", second), 1);
+        assert_eq!(received(&traffic, "c", second), 0);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn all_connected_holds_once_the_nodes_meet() {
+        let mut cluster = pair();
+        cluster.start("b", Some("a")).unwrap();
+        let within = Duration::from_secs(30);
+        assert_eq!(
+            evaluate(&cluster, &Assertion::AllConnected { within }),
+            Ok(())
+        );
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn all_connected_fails_with_the_nodes_that_did_not_meet() {
+        let mut cluster = pair();
+        cluster.start("b", None).unwrap();
+        let within = Duration::from_secs(1);
+        let err = evaluate(&cluster, &Assertion::AllConnected { within }).unwrap_err();
+        assert_eq!(
+            err,
+            "membership: a is not connected to b; membership: b is not connected to a"
+        );
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn received_waits_for_the_messages_and_counts_them_when_they_are_missing() {
+        let mut cluster = pair();
+        cluster.start("b", Some("a")).unwrap();
+        let within = Duration::from_secs(30);
+        evaluate(&cluster, &Assertion::AllConnected { within }).unwrap();
+        // The interest of b in the topic reaches a with its first frames.
+        thread::sleep(Duration::from_millis(300));
+        for index in 0..3 {
+            cluster.publish("a", &format!("a #{}", index)).unwrap();
+        }
+        let received = |at_least| Assertion::Received {
+            node: "b".to_owned(),
+            from: "a".to_owned(),
+            at_least,
+            within: Duration::from_secs(10),
+        };
+        assert_eq!(evaluate(&cluster, &received(3)), Ok(()));
+        assert_eq!(
+            evaluate(&cluster, &received(4)),
+            Err("b received 3 from a".to_owned())
+        );
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn the_invariants_hold_of_a_converged_pair() {
+        let mut cluster = pair();
+        cluster.start("b", Some("a")).unwrap();
+        let within = Duration::from_secs(30);
+        evaluate(&cluster, &Assertion::AllConnected { within }).unwrap();
+        let within = Duration::from_secs(10);
+        assert_eq!(
+            evaluate(&cluster, &Assertion::Invariants { within }),
+            Ok(())
+        );
+    }
+}
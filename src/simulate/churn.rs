@@ -0,0 +1,386 @@
+//! Randomized Churn.
+//!
+//! `script` draws a `Script` from a seed: up to `ChurnConfig::max_nodes` nodes start, stop and
+//! start again, partitions split the running ones and heal, links are given latency and loss,
+//! nodes reload new hot settings and publish on the traffic topic. The same seed always draws
+//! the same script. `run` takes the script on a cluster at `TimeScale::FAST`, heals the
+//! network, waits for the running nodes to converge and checks every invariant of
+//! `invariant`. A failed run returns the seed and the script along with what did not hold, so
+//! it can be replayed exactly. A run takes seconds of real time, so `run` and the tests
+//! starting a cluster only build with the `net-tests` feature.
+
+use crate::participant::transport::LinkConditions;
+use crate::simulate::cluster::Settings;
+#[cfg(feature = "net-tests")]
+use crate::simulate::cluster::{Cluster, TimeScale};
+#[cfg(feature = "net-tests")]
+use crate::simulate::invariant::{self, Violation};
+#[cfg(feature = "net-tests")]
+use crate::simulate::script::execute;
+use crate::simulate::script::{Action, Script};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+/// How long the running nodes are given to converge once the script is over, in simulated
+/// time.
+///
+/// A node that lost a peer to a partition dialed it in vain, and neither dials it again nor
+/// probes it before its dial memory, scaled with the timeline, forgets the address. The wait
+/// ends as soon as the nodes converge, so it is only the bound of a run that does not.
+#[cfg(feature = "net-tests")]
+const SETTLE_TIME: Duration = Duration::from_secs(300);
+
+/// The nodes started at the beginning of a script.
+const FIRST_NODES: usize = 3;
+
+/// The fewest nodes a script leaves running.
+const MIN_RUNNING: usize = 2;
+
+/// How often a node probes the peers that departed, in simulated time, for the halves of a
+/// partition to find each other again within the settle time.
+const HEAL_PROBE_INTERVAL: &str = "10s";
+
+/// The shares of frames a link draws its loss from.
+const LOSSES: [f64; 4] = [0.0, 0.01, 0.05, 0.2];
+
+/// What `script` draws.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChurnConfig {
+    /// The seed the script and the lost frames are drawn from.
+    pub seed: u64,
+    /// How long the script lasts, in simulated time.
+    pub duration: Duration,
+    /// The most nodes the script declares.
+    pub max_nodes: usize,
+    /// The mean time between two steps, in simulated time.
+    pub interval: Duration,
+}
+
+impl Default for ChurnConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            duration: Duration::from_secs(300),
+            max_nodes: 12,
+            interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Returns the name of the node `index`.
+fn name(index: usize) -> String {
+    format!("n{:02}", index)
+}
+
+/// Draws the script of `config` from its seed.
+pub fn script(config: &ChurnConfig) -> Script {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut script = Script::default();
+    let mut running: Vec<String> = Vec::new();
+    let mut stopped: Vec<String> = (0..config.max_nodes.max(MIN_RUNNING)).map(name).collect();
+    stopped.reverse();
+
+    for _ in 0..FIRST_NODES.min(stopped.len()) {
+        let node = stopped.pop().expect("a node is left to start");
+        start(&mut script, Duration::ZERO, &mut rng, &mut running, node);
+    }
+
+    let mut at = Duration::ZERO;
+    let mut heal_at: Option<Duration> = None;
+    loop {
+        at += config.interval.mul_f64(rng.gen_range(0.5..1.5));
+        if at >= config.duration {
+            break;
+        }
+        if let Some(due) = heal_at.filter(|due| *due <= at) {
+            script.push(due, Action::Heal);
+            heal_at = None;
+        }
+        match rng.gen_range(0..100) {
+            0..=19 if !stopped.is_empty() => {
+                let node = stopped.swap_remove(rng.gen_range(0..stopped.len()));
+                start(&mut script, at, &mut rng, &mut running, node);
+            }
+            20..=34 if running.len() > MIN_RUNNING => {
+                let node = running.swap_remove(rng.gen_range(0..running.len()));
+                script.push(at, Action::Stop { node: node.clone() });
+                stopped.push(node);
+            }
+            35..=44 if heal_at.is_none() && running.len() >= 2 * MIN_RUNNING => {
+                let mut nodes = running.clone();
+                nodes.shuffle(&mut rng);
+                let split = rng.gen_range(1..nodes.len());
+                let mut groups = vec![nodes[..split].to_vec(), nodes[split..].to_vec()];
+                for group in &mut groups {
+                    group.sort();
+                }
+                script.push(at, Action::Partition { groups });
+                heal_at = Some(at + Duration::from_secs(rng.gen_range(5..20)));
+            }
+            45..=59 => {
+                let mut pair = running.choose_multiple(&mut rng, 2).cloned();
+                let (Some(first), Some(second)) = (pair.next(), pair.next()) else {
+                    continue;
+                };
+                let conditions = LinkConditions {
+                    latency: Duration::from_millis(rng.gen_range(0..250)),
+                    loss: *LOSSES.choose(&mut rng).expect("there are losses"),
+                };
+                script.push(
+                    at,
+                    Action::Conditions {
+                        first,
+                        second,
+                        conditions,
+                    },
+                );
+            }
+            60..=69 => {
+                let node = running.choose(&mut rng).expect("a node runs").clone();
+                let mut settings = Settings::new();
+                settings.insert(
+                    "share-limit".to_owned(),
+                    Some(rng.gen_range(4..=16).to_string()),
+                );
+                if rng.gen_bool(0.5) {
+                    settings.insert("legacy-payload".to_owned(), None);
+                }
+                let settings = self::settings(settings);
+                script.push(at, Action::Reload { node, settings });
+            }
+            _ => {
+                let node = running.choose(&mut rng).expect("a node runs").clone();
+                let count = rng.gen_range(1..=5);
+                script.push(at, Action::Publish { node, count });
+            }
+        }
+    }
+    script.push(config.duration, Action::Heal);
+    script
+}
+
+/// Returns `settings` with those every node of a churn runs with.
+fn settings(mut settings: Settings) -> Settings {
+    settings.insert(
+        "heal-probe-interval".to_owned(),
+        Some(HEAL_PROBE_INTERVAL.to_owned()),
+    );
+    settings
+}
+
+/// Starts `node` at `at`, dialing one of the `running` nodes if there is one.
+fn start(
+    script: &mut Script,
+    at: Duration,
+    rng: &mut StdRng,
+    running: &mut Vec<String>,
+    node: String,
+) {
+    let connect = running.choose(rng).cloned();
+    script.push(
+        at,
+        Action::Start {
+            node: node.clone(),
+            connect,
+        },
+    );
+    running.push(node);
+}
+
+/// Takes the script of `config` on a cluster, and checks the invariants once it converged.
+///
+/// # Returns
+///
+/// `Ok(())` when every invariant holds, or the description of the failed run: its seed, what
+/// did not hold and the script.
+#[cfg(feature = "net-tests")]
+pub fn run(config: &ChurnConfig) -> Result<(), String> {
+    let script = script(config);
+    let violations = run_script(config.seed, &script, config.max_nodes);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let violations: Vec<String> = violations.iter().map(Violation::to_string).collect();
+    Err(format!(
+        "churn seed {} failed:\n{}\nscript:\n{}",
+        config.seed,
+        violations.join("\n"),
+        script
+    ))
+}
+
+/// Takes `script` on a cluster of `nodes` nodes, heals its network, and checks the
+/// invariants once it converged.
+#[cfg(feature = "net-tests")]
+fn run_script(seed: u64, script: &Script, nodes: usize) -> Vec<Violation> {
+    let failed = |reason: String| {
+        vec![Violation {
+            invariant: "script",
+            detail: reason,
+        }]
+    };
+    let mut cluster = match Cluster::new(TimeScale::FAST, seed) {
+        Ok(cluster) => cluster,
+        Err(reason) => return failed(reason),
+    };
+    for index in 0..nodes.max(MIN_RUNNING) {
+        if let Err(reason) = cluster.declare(&name(index), settings(Settings::new())) {
+            return failed(reason);
+        }
+    }
+    if let Some(reason) = execute(&mut cluster, script, &mut |_| {}).failed {
+        return failed(reason);
+    }
+    cluster.heal();
+    if let Err(violations) = invariant::converge(&cluster, cluster.scale().real(SETTLE_TIME)) {
+        return violations;
+    }
+    match invariant::observe(&cluster) {
+        Ok(observations) => invariant::check(&cluster, &observations),
+        Err(reason) => failed(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(seed: u64) -> ChurnConfig {
+        ChurnConfig {
+            seed,
+            duration: Duration::from_secs(60),
+            ..ChurnConfig::default()
+        }
+    }
+
+    #[test]
+    fn the_same_seed_draws_the_same_script() {
+        assert_eq!(script(&config(7)), script(&config(7)));
+        assert_ne!(script(&config(7)), script(&config(8)));
+    }
+
+    #[test]
+    fn a_script_keeps_to_the_nodes_it_declares_and_starts_them_before_it_stops_them() {
+        for seed in 0..50 {
+            let config = ChurnConfig {
+                seed,
+                max_nodes: 5,
+                ..ChurnConfig::default()
+            };
+            let script = script(&config);
+            let mut running: Vec<String> = Vec::new();
+            let mut partitioned = false;
+            for step in &script.steps {
+                assert!(step.at <= config.duration, "{}", step);
+                match &step.action {
+                    Action::Start { node, connect } => {
+                        assert!(node < &name(5), "{}", step);
+                        assert!(!running.contains(node), "{}", step);
+                        if let Some(connect) = connect {
+                            assert!(running.contains(connect), "{}", step);
+                        }
+                        running.push(node.clone());
+                    }
+                    Action::Stop { node } => {
+                        assert!(running.contains(node), "{}", step);
+                        running.retain(|running| running != node);
+                        assert!(running.len() >= MIN_RUNNING, "{}", step);
+                    }
+                    Action::Partition { groups } => {
+                        assert!(!partitioned, "{}", step);
+                        assert!(groups.iter().all(|group| !group.is_empty()), "{}", step);
+                        partitioned = true;
+                    }
+                    Action::Heal => partitioned = false,
+                    Action::Conditions { first, second, .. } => {
+                        assert_ne!(first, second);
+                        assert!(running.contains(first) && running.contains(second));
+                    }
+                    Action::Reload { node, .. } | Action::Publish { node, .. } => {
+                        assert!(running.contains(node), "{}", step);
+                    }
+                    Action::Assert(_) => panic!("a churn draws no assertion: {}", step),
+                }
+            }
+            assert_eq!(
+                script.steps.last().map(|step| &step.action),
+                Some(&Action::Heal)
+            );
+            assert!(script.steps.windows(2).all(|pair| pair[0].at <= pair[1].at));
+        }
+    }
+
+    #[test]
+    fn a_script_draws_every_kind_of_step() {
+        let script = script(&ChurnConfig::default());
+        let drawn =
+            |matches: fn(&Action) -> bool| script.steps.iter().any(|step| matches(&step.action));
+        assert!(drawn(|action| matches!(action, Action::Start { .. })));
+        assert!(drawn(|action| matches!(action, Action::Stop { .. })));
+        assert!(drawn(|action| matches!(action, Action::Partition { .. })));
+        assert!(drawn(|action| matches!(action, Action::Conditions { .. })));
+        assert!(drawn(|action| matches!(action, Action::Reload { .. })));
+        assert!(drawn(|action| matches!(action, Action::Publish { .. })));
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_failed_run_names_its_seed_and_prints_its_script() {
+        let config = ChurnConfig {
+            seed: 3,
+            duration: Duration::from_secs(1),
+            max_nodes: 2,
+            ..ChurnConfig::default()
+        };
+        let mut script = script(&config);
+        script.push(Duration::from_secs(1), Action::Stop { node: name(7) });
+        let violations = run_script(config.seed, &script, config.max_nodes);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, "script");
+        assert!(
+            violations[0].detail.contains("stop n07"),
+            "{}",
+            violations[0]
+        );
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn churn_keeps_the_invariants_with_seed_1() {
+        run(&config(1)).unwrap_or_else(|failure| panic!("{}", failure));
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn churn_keeps_the_invariants_with_seed_2() {
+        run(&config(2)).unwrap_or_else(|failure| panic!("{}", failure));
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn churn_keeps_the_invariants_with_seed_3() {
+        run(&config(3)).unwrap_or_else(|failure| panic!("{}", failure));
+    }
+
+    /// Runs five minutes of churn for each of `CHURN_SEEDS` random seeds, 20 by default:
+    /// `cargo test --features net-tests churn_soak -- --ignored`.
+    #[cfg(feature = "net-tests")]
+    #[test]
+    #[ignore]
+    fn churn_soak() {
+        let seeds = std::env::var("CHURN_SEEDS")
+            .ok()
+            .and_then(|seeds| seeds.parse().ok())
+            .unwrap_or(20);
+        for _ in 0..seeds {
+            let config = ChurnConfig {
+                seed: rand::random(),
+                ..ChurnConfig::default()
+            };
+            run(&config).unwrap_or_else(|failure| panic!("{}", failure));
+        }
+    }
+}
@@ -0,0 +1,668 @@
+//! Simulated Cluster.
+//!
+//! A `Cluster` runs named participants in this process, connected through an
+//! `InMemoryNetwork`, each on a thread of its own. A node is declared once with its settings
+//! and keeps its port and node id for the whole simulation, so it can be stopped and started
+//! again as the same participant. Every node subscribes in publish order to `TRAFFIC_TOPIC`
+//! the moment it is made, and the cluster counts what each one receives there, per publisher,
+//! over all of its runs.
+
+use crate::console::Command;
+use crate::participant::config::NodeConfig;
+use crate::participant::handle::ParticipantHandle;
+use crate::participant::model::Participant;
+use crate::participant::reorder::Delivery;
+use crate::participant::report::Report;
+use crate::participant::transport::{InMemoryNetwork, LinkConditions};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The topic the nodes publish on and subscribe to.
+pub const TRAFFIC_TOPIC: &str = "traffic";
+
+/// The gossip period of a node that does not set its own, in simulated time.
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(1);
+
+/// How long a node that does not set its own remembers the addresses it dialed, in simulated
+/// time, as long as a node outside a simulation does.
+const DEFAULT_DIAL_MEMORY: Duration = Duration::from_secs(30);
+
+/// The settings holding a duration of simulated time, scaled along with the timeline.
+const SCALED_SETTINGS: [&str; 6] = [
+    "period",
+    "min-period",
+    "max-period",
+    "connect-timeout",
+    "dial-memory",
+    "heal-probe-interval",
+];
+
+/// The settings the cluster gives every node itself, which a node can not set.
+pub const RESERVED_SETTINGS: [&str; 4] = ["port", "connect", "no-peer-cache", "identity-file"];
+
+/// The port of the first node declared; the others follow it.
+const FIRST_PORT: u16 = 9000;
+
+/// How long a node is given to start, to answer a command or to hand over its report.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a node is given to stop once asked to.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a stopping node is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Keeps the directories of the clusters of one process apart.
+static CLUSTERS: AtomicU32 = AtomicU32::new(0);
+
+/// The settings of a node, each the command-line option `--<name>=<value>`, or `--<name>` for
+/// a flag without a value.
+pub type Settings = BTreeMap<String, Option<String>>;
+
+/// How much faster than the real time a simulation runs.
+///
+/// The participants run on the wall clock, so a simulation is sped up by scaling what it
+/// controls: the timeline, the latency of the links and the settings of the nodes that hold a
+/// duration, such as their gossip period, are divided by the factor. The timeouts built into
+/// the participants are not, so a compressed run is not the exact replica of a real one, only
+/// a close one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeScale(u32);
+
+impl TimeScale {
+    /// Runs in real time.
+    pub const REAL: Self = Self(1);
+
+    /// Runs ten times faster than the real time, the default period of a node taking 100ms.
+    pub const FAST: Self = Self(10);
+
+    /// Returns the real time simulated `time` takes.
+    pub fn real(self, time: Duration) -> Duration {
+        time / self.0
+    }
+}
+
+/// What the nodes of a cluster received on `TRAFFIC_TOPIC`.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    /// The messages received, by receiving node and publisher address.
+    pub received: HashMap<(String, SocketAddr), u64>,
+    /// The messages given up as gaps, by receiving node.
+    pub missed: HashMap<String, u64>,
+    /// The gaps reported to the current run of each node.
+    pub gaps: HashMap<String, u64>,
+}
+
+/// A node running in the cluster.
+struct Running {
+    handle: ParticipantHandle,
+    thread: JoinHandle<Result<(), String>>,
+}
+
+/// A node declared in the cluster.
+struct Node {
+    addr: SocketAddr,
+    settings: Arc<Mutex<Settings>>,
+    running: Option<Running>,
+}
+
+/// Named participants running against each other on an in-memory network.
+pub struct Cluster {
+    network: InMemoryNetwork,
+    scale: TimeScale,
+    dir: PathBuf,
+    nodes: BTreeMap<String, Node>,
+    traffic: Arc<Mutex<Traffic>>,
+    /// The directions given conditions, to clear them on `heal`.
+    conditioned: Vec<(SocketAddr, SocketAddr)>,
+    /// How each run that stopped ended, by node.
+    stopped: Vec<(String, Result<(), String>)>,
+    /// The internal threads each running node ran when it started.
+    threads: HashMap<String, usize>,
+}
+
+impl Cluster {
+    /// Creates an empty cluster running at `scale`, whose network draws its lost frames from
+    /// `seed`.
+    pub fn new(scale: TimeScale, seed: u64) -> Result<Self, String> {
+        crate::printer::silence_events();
+        let dir = std::env::temp_dir().join(format!(
+            "gossip-p2p-simulate-{}-{}",
+            std::process::id(),
+            CLUSTERS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("can not create \"{}\": {}", dir.display(), err))?;
+        Ok(Self {
+            network: InMemoryNetwork::with_seed(seed),
+            scale,
+            dir,
+            nodes: BTreeMap::new(),
+            traffic: Arc::default(),
+            conditioned: Vec::new(),
+            stopped: Vec::new(),
+            threads: HashMap::new(),
+        })
+    }
+
+    /// Returns the time scale of the cluster.
+    pub fn scale(&self) -> TimeScale {
+        self.scale
+    }
+
+    /// Returns the network the nodes run on.
+    pub fn network(&self) -> &InMemoryNetwork {
+        &self.network
+    }
+
+    /// Declares the node `name` with `settings`, without starting it.
+    pub fn declare(&mut self, name: &str, settings: Settings) -> Result<(), String> {
+        if self.nodes.contains_key(name) {
+            return Err(format!("node \"{}\" is declared twice", name));
+        }
+        let port = FIRST_PORT + self.nodes.len() as u16;
+        self.nodes.insert(
+            name.to_owned(),
+            Node {
+                addr: SocketAddr::from(([127, 0, 0, 1], port)),
+                settings: Arc::new(Mutex::new(settings)),
+                running: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the names of the running nodes, in order.
+    pub fn running(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.running.is_some())
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Returns the address the node `name` listens on.
+    pub fn addr(&self, name: &str) -> Result<SocketAddr, String> {
+        Ok(self.node(name)?.addr)
+    }
+
+    /// Starts the node `name`, dialing the node `connect` if given.
+    pub fn start(&mut self, name: &str, connect: Option<&str>) -> Result<(), String> {
+        let connect = connect.map(|target| self.addr(target)).transpose()?;
+        let dir = self.dir.clone();
+        let scale = self.scale;
+        let traffic = self.traffic.clone();
+        let node = self.node_mut(name)?;
+        if node.running.is_some() {
+            return Err(format!("node \"{}\" is already running", name));
+        }
+        let port = node.addr.port();
+        let mut base = vec![
+            format!("--port={}", port),
+            "--no-peer-cache".to_owned(),
+            format!(
+                "--identity-file={}",
+                dir.join(format!("{}.id", port)).display()
+            ),
+        ];
+        if let Some(connect) = connect {
+            base.push(format!("--connect={}", connect));
+        }
+        let settings = node.settings.clone();
+        let config = node_config(&base, &lock(&settings), scale)?;
+
+        let receiver = name.to_owned();
+        let (started, waiter) = mpsc::channel();
+        let transport = self.network.transport();
+        let thread = thread::Builder::new()
+            .name(format!("simulate-{}", name))
+            .spawn(move || {
+                let mut participant = match Participant::with_transport(config, transport) {
+                    Ok(participant) => participant,
+                    Err(err) => {
+                        let _ = started.send(Err(err.to_string()));
+                        return Err(err.to_string());
+                    }
+                };
+                lock(&traffic).gaps.insert(receiver.clone(), 0);
+                let subscribed =
+                    participant.subscribe_ordered(TRAFFIC_TOPIC, move |from, delivery| {
+                        let mut traffic = lock(&traffic);
+                        match delivery {
+                            Delivery::Gap { from: first, to } => {
+                                *traffic.missed.entry(receiver.clone()).or_insert(0) +=
+                                    to.saturating_sub(first).saturating_add(1);
+                                *traffic.gaps.entry(receiver.clone()).or_insert(0) += 1;
+                            }
+                            _ => {
+                                *traffic
+                                    .received
+                                    .entry((receiver.clone(), from))
+                                    .or_insert(0) += 1;
+                            }
+                        }
+                    });
+                if let Err(err) = subscribed {
+                    let _ = started.send(Err(err.to_string()));
+                    return Err(err.to_string());
+                }
+                participant.on_reload(move || node_config(&base, &lock(&settings), scale));
+                let _ = started.send(Ok(participant.handle()));
+                participant.run().map_err(|err| err.to_string())
+            })
+            .map_err(|err| format!("can not start a thread: {}", err))?;
+        let handle = waiter
+            .recv_timeout(REPLY_TIMEOUT)
+            .map_err(|_| format!("node \"{}\" did not start in time", name))?
+            .map_err(|err| format!("node \"{}\" can not run: {}", name, err))?;
+        self.node_mut(name)?.running = Some(Running { handle, thread });
+        let threads = self.live_threads(name)?;
+        self.threads.insert(name.to_owned(), threads);
+        Ok(())
+    }
+
+    /// Stops the node `name` and waits for it to end.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the node stopped, whether or not its run ended well; how it ended is
+    /// kept for `stopped`.
+    pub fn stop(&mut self, name: &str) -> Result<(), String> {
+        let running = self
+            .node_mut(name)?
+            .running
+            .take()
+            .ok_or_else(|| format!("node \"{}\" is not running", name))?;
+        let outcome = stop(running);
+        self.stopped.push((name.to_owned(), outcome));
+        Ok(())
+    }
+
+    /// Returns the internal threads the running node `name` runs.
+    pub fn live_threads(&self, name: &str) -> Result<usize, String> {
+        self.command(name, Command::Threads)?
+            .last()
+            .and_then(|line| line.split(' ').next()?.parse().ok())
+            .ok_or_else(|| format!("node \"{}\" did not count its threads", name))
+    }
+
+    /// Returns the internal threads the running node `name` ran when it started.
+    pub fn threads_at_start(&self, name: &str) -> Option<usize> {
+        self.threads.get(name).copied()
+    }
+
+    /// Returns how each run that stopped ended, by node, in the order they stopped.
+    pub fn stopped(&self) -> &[(String, Result<(), String>)] {
+        &self.stopped
+    }
+
+    /// Replaces the settings of the node `name`, and has it reload them if it runs.
+    ///
+    /// # Returns
+    ///
+    /// The lines the reload logged, none if the node is not running.
+    pub fn reload(&mut self, name: &str, settings: Settings) -> Result<Vec<String>, String> {
+        let node = self.node(name)?;
+        *lock(&node.settings) = settings;
+        if node.running.is_none() {
+            return Ok(Vec::new());
+        }
+        self.command(name, Command::Reload)
+    }
+
+    /// Has the running node `name` publish `text` on `TRAFFIC_TOPIC`.
+    pub fn publish(&self, name: &str, text: &str) -> Result<(), String> {
+        let out = self.command(
+            name,
+            Command::Publish {
+                topic: TRAFFIC_TOPIC.to_owned(),
+                text: text.to_owned(),
+            },
+        )?;
+        match out.first() {
+            Some(line) => Err(line.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `command` on the running node `name`.
+    pub fn command(&self, name: &str, command: Command) -> Result<Vec<String>, String> {
+        self.handle(name)?
+            .command(command, REPLY_TIMEOUT)
+            .ok_or_else(|| format!("node \"{}\" did not answer in time", name))
+    }
+
+    /// Takes the report of the running node `name`.
+    pub fn report(&self, name: &str) -> Result<Report, String> {
+        self.handle(name)?
+            .report(REPLY_TIMEOUT)
+            .ok_or_else(|| format!("node \"{}\" did not report in time", name))
+    }
+
+    /// Keeps each group of nodes apart from the others, until `heal`.
+    pub fn partition(&self, groups: &[Vec<String>]) -> Result<(), String> {
+        let addrs = groups
+            .iter()
+            .map(|group| group.iter().map(|name| self.addr(name)).collect())
+            .collect::<Result<Vec<Vec<SocketAddr>>, String>>()?;
+        for (index, side) in addrs.iter().enumerate() {
+            for other in &addrs[index + 1..] {
+                self.network.partition(side, other);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the conditions of both directions between the nodes `first` and `second`, the
+    /// latency in simulated time.
+    pub fn set_conditions(
+        &mut self,
+        first: &str,
+        second: &str,
+        conditions: LinkConditions,
+    ) -> Result<(), String> {
+        let (first, second) = (self.addr(first)?, self.addr(second)?);
+        let scaled = LinkConditions {
+            latency: self.scale.real(conditions.latency),
+            loss: conditions.loss,
+        };
+        for direction in [(first, second), (second, first)] {
+            self.network
+                .set_conditions(direction.0, direction.1, scaled);
+            if !self.conditioned.contains(&direction) {
+                self.conditioned.push(direction);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends every partition and clears the conditions of every link.
+    pub fn heal(&mut self) {
+        self.network.heal();
+        for (from, to) in self.conditioned.drain(..) {
+            self.network
+                .set_conditions(from, to, LinkConditions::default());
+        }
+    }
+
+    /// Returns what the nodes received on `TRAFFIC_TOPIC` so far.
+    pub fn traffic(&self) -> MutexGuard<'_, Traffic> {
+        lock(&self.traffic)
+    }
+
+    fn node(&self, name: &str) -> Result<&Node, String> {
+        self.nodes
+            .get(name)
+            .ok_or_else(|| format!("node \"{}\" is not declared", name))
+    }
+
+    fn node_mut(&mut self, name: &str) -> Result<&mut Node, String> {
+        self.nodes
+            .get_mut(name)
+            .ok_or_else(|| format!("node \"{}\" is not declared", name))
+    }
+
+    fn handle(&self, name: &str) -> Result<&ParticipantHandle, String> {
+        self.node(name)?
+            .running
+            .as_ref()
+            .map(|running| &running.handle)
+            .ok_or_else(|| format!("node \"{}\" is not running", name))
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        for node in self.nodes.values_mut() {
+            if let Some(running) = node.running.take() {
+                let _ = stop(running);
+            }
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Checks that a node can run with `settings`.
+///
+/// # Returns
+///
+/// `Ok(())` if it can, or why not, such as an unknown option or a value out of its range.
+pub fn check_settings(settings: &Settings) -> Result<(), String> {
+    if let Some(name) = settings
+        .keys()
+        .find(|name| RESERVED_SETTINGS.contains(&name.as_str()))
+    {
+        return Err(format!("\"{}\" is set by the simulation", name));
+    }
+    let base = [format!("--port={}", FIRST_PORT)];
+    node_config(&base, settings, TimeScale::REAL).map(|_| ())
+}
+
+/// Builds the configuration of a node from its `base` arguments and its `settings`, those of
+/// `SCALED_SETTINGS` scaled, and the period and dial memory it does not set scaled from their
+/// defaults.
+fn node_config(
+    base: &[String],
+    settings: &Settings,
+    scale: TimeScale,
+) -> Result<NodeConfig, String> {
+    let mut args = base.to_vec();
+    if !settings.contains_key("period") {
+        args.push(format!("--period={}", millis(scale.real(DEFAULT_PERIOD))));
+    }
+    if !settings.contains_key("dial-memory") {
+        args.push(format!(
+            "--dial-memory={}",
+            millis(scale.real(DEFAULT_DIAL_MEMORY))
+        ));
+    }
+    for (name, value) in settings {
+        args.push(match value {
+            Some(value) => match crate::cli::parse_duration(value) {
+                Ok(duration) if SCALED_SETTINGS.contains(&name.as_str()) => {
+                    format!("--{}={}", name, millis(scale.real(duration)))
+                }
+                _ => format!("--{}={}", name, value),
+            },
+            None => format!("--{}", name),
+        });
+    }
+    NodeConfig::from_args(&args)
+}
+
+/// Formats `duration` as a command-line duration in milliseconds.
+fn millis(duration: Duration) -> String {
+    format!("{}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Asks a running node to stop, and waits for its run to end.
+fn stop(running: Running) -> Result<(), String> {
+    running.handle.shutdown();
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while !running.thread.is_finished() {
+        if Instant::now() >= deadline {
+            return Err(format!("did not stop within {:?}", STOP_TIMEOUT));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    running
+        .thread
+        .join()
+        .unwrap_or_else(|_| Err("panicked".to_owned()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::period::GossipPeriod;
+
+    fn settings(pairs: &[(&str, Option<&str>)]) -> Settings {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.map(str::to_owned)))
+            .collect()
+    }
+
+    #[test]
+    fn the_settings_holding_a_duration_are_scaled() {
+        let config = node_config(
+            &["--port=9000".to_owned()],
+            &settings(&[
+                ("connect-timeout", Some("10s")),
+                ("share-limit", Some("3")),
+                ("legacy-payload", None),
+            ]),
+            TimeScale::FAST,
+        )
+        .unwrap();
+        assert_eq!(
+            config.period,
+            GossipPeriod::Fixed(Duration::from_millis(100))
+        );
+        assert_eq!(config.connect_timeout, Duration::from_secs(1));
+        assert_eq!(config.dial_memory, Duration::from_secs(3));
+        assert_eq!(config.share_limit, 3);
+        assert!(config.legacy_payload);
+
+        let config = node_config(
+            &["--port=9000".to_owned()],
+            &settings(&[("period", Some("2s"))]),
+            TimeScale::FAST,
+        )
+        .unwrap();
+        assert_eq!(
+            config.period,
+            GossipPeriod::Fixed(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn the_settings_are_checked_as_the_node_would_run_with_them() {
+        assert_eq!(
+            check_settings(&settings(&[("share-limit", Some("4"))])),
+            Ok(())
+        );
+        assert_eq!(
+            check_settings(&settings(&[("port", Some("8080"))])),
+            Err("\"port\" is set by the simulation".to_owned())
+        );
+        assert_eq!(
+            check_settings(&settings(&[("fanout", Some("3"))])),
+            Err("Unknown argument \"--fanout=3\"".to_owned())
+        );
+        assert!(check_settings(&settings(&[("share-limit", Some("many"))])).is_err());
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_node_is_declared_once_and_started_once() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        assert!(cluster.declare("a", Settings::new()).is_err());
+        assert!(cluster.start("b", None).is_err());
+        assert!(cluster.stop("a").is_err());
+
+        cluster.start("a", None).unwrap();
+        assert!(cluster.start("a", None).is_err());
+        assert_eq!(cluster.running(), ["a"]);
+        assert!(cluster
+            .threads_at_start("a")
+            .is_some_and(|threads| threads > 0));
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_restarted_node_keeps_its_address_and_node_id() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.declare("b", Settings::new()).unwrap();
+        cluster.start("a", None).unwrap();
+        cluster.start("b", Some("a")).unwrap();
+        let before = cluster.report("a").unwrap();
+
+        cluster.stop("a").unwrap();
+        assert_eq!(cluster.running(), ["b"]);
+        assert!(cluster.report("a").is_err());
+        cluster.start("a", Some("b")).unwrap();
+        let after = cluster.report("a").unwrap();
+
+        assert_eq!(after.node, cluster.addr("a").unwrap());
+        assert_eq!(after.node_id, before.node_id);
+        assert_eq!(cluster.stopped(), [("a".to_owned(), Ok(()))]);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_reload_applies_the_new_settings_of_a_running_node() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        assert!(cluster
+            .reload("a", settings(&[("share-limit", Some("5"))]))
+            .unwrap()
+            .is_empty());
+        cluster.start("a", None).unwrap();
+
+        let out = cluster
+            .reload("a", settings(&[("share-limit", Some("7"))]))
+            .unwrap();
+        assert!(out[0].starts_with("Reloaded"), "{:?}", out);
+        assert!(out.iter().any(|line| line.contains("share_limit: 5 -> 7")));
+        let out = cluster
+            .reload("a", settings(&[("share-limit", Some("0"))]))
+            .unwrap();
+        assert!(out[0].starts_with("Rejected"), "{:?}", out);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn the_traffic_received_is_counted_per_publisher() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.declare("b", Settings::new()).unwrap();
+        cluster.start("a", None).unwrap();
+        cluster.start("b", Some("a")).unwrap();
+        let (a, b) = (cluster.addr("a").unwrap(), cluster.addr("b").unwrap());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !cluster
+            .report("b")
+            .unwrap()
+            .peers
+            .iter()
+            .any(|peer| peer.addr == a)
+        {
+            assert!(Instant::now() < deadline, "b never connected to a");
+            thread::sleep(POLL_INTERVAL);
+        }
+        // The interest of a in the topic reaches b with its first frames.
+        thread::sleep(Duration::from_millis(300));
+
+        for index in 0..3 {
+            cluster.publish("b", &format!("b #{}", index)).unwrap();
+        }
+        let received = || {
+            cluster
+                .traffic()
+                .received
+                .get(&("a".to_owned(), b))
+                .copied()
+        };
+        while received() != Some(3) {
+            assert!(Instant::now() < deadline, "a received {:?}", received());
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert_eq!(cluster.traffic().missed.get("a"), None);
+    }
+}
@@ -0,0 +1,525 @@
+//! Simulation Invariants.
+//!
+//! What must hold of a cluster once a simulation is over and its network healed:
+//!
+//! - `membership`: every running node is connected to each of the others, once, and lists no
+//!   other peer, neither a node that stopped nor an entry still being dialed;
+//! - `sequences`: the messages given up as missing on the traffic topic are no more than the
+//!   frames the network lost, and each node counts the gaps its subscription was handed;
+//! - `threads`: no node runs more internal threads than it did when it started;
+//! - `audits`: a storage audit run at the end finds nothing it can not repair, and a second
+//!   one right after finds nothing at all;
+//! - `reports`: the report of every node formats as valid JSON;
+//! - `runs`: every run of a node that was stopped ended without an error.
+//!
+//! Each check is a plain function over what `observe` read from the nodes, returning a
+//! `Violation` per finding, so it can be tested on made-up observations.
+
+use crate::console::Command;
+use crate::participant::audit::AuditSummary;
+use crate::simulate::cluster::Cluster;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often `converge` reads the peer lists again.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long `observe` waits for the traffic subscription of a node to be handed the gaps its
+/// report counts, since the callbacks run behind the event loop.
+const CALLBACK_LAG: Duration = Duration::from_millis(500);
+
+/// An invariant that does not hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The name of the check finding it.
+    pub invariant: &'static str,
+    /// What was found.
+    pub detail: String,
+}
+
+impl Violation {
+    fn new(invariant: &'static str, detail: String) -> Self {
+        Self { invariant, detail }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.invariant, self.detail)
+    }
+}
+
+/// The peers a running node lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerList {
+    /// The name of the node.
+    pub name: String,
+    /// The address of the node.
+    pub addr: SocketAddr,
+    /// The listed peers, each with whether its session is up.
+    pub peers: Vec<(SocketAddr, bool)>,
+}
+
+/// What the checks read from a running node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    /// The peers the node lists.
+    pub list: PeerList,
+    /// The gaps the report of the node counts, and those its traffic subscription was handed.
+    pub gaps: (u64, u64),
+    /// The internal threads the node ran when it started, and those it runs now.
+    pub threads: (usize, usize),
+    /// The summaries of two audits run in a row now, `None` if the node did not reply with
+    /// them.
+    pub audits: Option<(AuditSummary, AuditSummary)>,
+    /// The report of the node, formatted as JSON.
+    pub json: String,
+}
+
+/// Reads the peer lists of the running nodes of `cluster`.
+pub fn peer_lists(cluster: &Cluster) -> Result<Vec<PeerList>, String> {
+    cluster
+        .running()
+        .into_iter()
+        .map(|name| {
+            let report = cluster.report(&name)?;
+            Ok(PeerList {
+                addr: cluster.addr(&name)?,
+                peers: report
+                    .peers
+                    .iter()
+                    .map(|peer| (peer.addr, peer.connected_for.is_some()))
+                    .collect(),
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Reads what the checks need from the running nodes of `cluster`, running an audit on each.
+pub fn observe(cluster: &Cluster) -> Result<Vec<Observation>, String> {
+    let lists = peer_lists(cluster)?;
+    let mut observations = Vec::with_capacity(lists.len());
+    for list in lists {
+        let report = cluster.report(&list.name)?;
+        let audit = || -> Result<Option<AuditSummary>, String> {
+            Ok(cluster
+                .command(&list.name, Command::Audit)?
+                .last()
+                .and_then(|line| parse_audit(line)))
+        };
+        let audits = audit()?.zip(audit()?);
+        let handed = || cluster.traffic().gaps.get(&list.name).copied().unwrap_or(0);
+        let deadline = Instant::now() + CALLBACK_LAG;
+        while handed() < report.ordered_gaps && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let handed = handed();
+        observations.push(Observation {
+            gaps: (report.ordered_gaps, handed),
+            threads: (
+                cluster.threads_at_start(&list.name).unwrap_or(0),
+                cluster.live_threads(&list.name)?,
+            ),
+            audits,
+            json: report.to_json(),
+            list,
+        });
+    }
+    Ok(observations)
+}
+
+/// Waits until the running nodes of `cluster` hold `membership`, for `within` at most.
+///
+/// # Returns
+///
+/// `Ok(())` once they do, or the violations found last.
+pub fn converge(cluster: &Cluster, within: Duration) -> Result<(), Vec<Violation>> {
+    let deadline = Instant::now() + within;
+    loop {
+        let violations = match peer_lists(cluster) {
+            Ok(lists) => membership(&lists),
+            Err(reason) => vec![Violation::new("membership", reason)],
+        };
+        if violations.is_empty() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(violations);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Checks that every node of `lists` is connected to each of the others through a single
+/// connection, and lists no other peer.
+///
+/// A peer listed twice is held through two connections, a crossed one or a stale one not
+/// closed yet, which a storage audit reports as left in place.
+pub fn membership(lists: &[PeerList]) -> Vec<Violation> {
+    let members: Vec<SocketAddr> = lists.iter().map(|list| list.addr).collect();
+    let name_of = |addr: SocketAddr| {
+        lists
+            .iter()
+            .find(|list| list.addr == addr)
+            .map_or(addr.to_string(), |list| list.name.clone())
+    };
+    let mut violations = Vec::new();
+    for list in lists {
+        for (index, (peer, connected)) in list.peers.iter().enumerate() {
+            if list.peers[..index].iter().any(|(listed, _)| listed == peer) {
+                violations.push(Violation::new(
+                    "membership",
+                    format!("{} lists {} twice", list.name, peer),
+                ));
+            } else if !connected {
+                violations.push(Violation::new(
+                    "membership",
+                    format!("{} lists {} while it is being dialed", list.name, peer),
+                ));
+            } else if !members.contains(peer) {
+                violations.push(Violation::new(
+                    "membership",
+                    format!("{} lists {}, which is not running", list.name, peer),
+                ));
+            }
+        }
+        for member in members.iter().filter(|member| **member != list.addr) {
+            if !list
+                .peers
+                .iter()
+                .any(|(peer, connected)| peer == member && *connected)
+            {
+                violations.push(Violation::new(
+                    "membership",
+                    format!("{} is not connected to {}", list.name, name_of(*member)),
+                ));
+            }
+        }
+    }
+    violations
+}
+
+/// Checks that the messages given up as missing are no more than the `dropped` frames, and
+/// that each node counts the gaps its subscription was handed.
+///
+/// # Parameters
+///
+/// * `missed` - The messages given up as missing by every run of every node.
+/// * `dropped` - The frames the network lost.
+pub fn sequences(observations: &[Observation], missed: u64, dropped: u64) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if missed > dropped {
+        violations.push(Violation::new(
+            "sequences",
+            format!(
+                "{} messages were missed while the network lost {} frames",
+                missed, dropped
+            ),
+        ));
+    }
+    for observation in observations {
+        let (counted, handed) = observation.gaps;
+        if counted != handed {
+            violations.push(Violation::new(
+                "sequences",
+                format!(
+                    "{} counts {} gaps while its subscription was handed {}",
+                    observation.list.name, counted, handed
+                ),
+            ));
+        }
+    }
+    violations
+}
+
+/// Checks that no node runs more internal threads than it did when it started.
+pub fn threads(observations: &[Observation]) -> Vec<Violation> {
+    observations
+        .iter()
+        .filter(|observation| observation.threads.1 > observation.threads.0)
+        .map(|observation| {
+            Violation::new(
+                "threads",
+                format!(
+                    "{} runs {} internal threads, {} when it started",
+                    observation.list.name, observation.threads.1, observation.threads.0
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Checks that the first audit of every node found nothing it could not repair, and the
+/// second nothing at all.
+pub fn audits(observations: &[Observation]) -> Vec<Violation> {
+    observations
+        .iter()
+        .filter_map(|observation| {
+            let name = &observation.list.name;
+            match observation.audits {
+                None => Some(format!("{} did not reply with audit summaries", name)),
+                Some((first, _)) if first.unrepairable > 0 => {
+                    Some(format!("{} left inconsistencies in place: {}", name, first))
+                }
+                Some((_, second)) if second.repaired > 0 || second.unrepairable > 0 => {
+                    Some(format!(
+                        "{} found inconsistencies right after an audit: {}",
+                        name, second
+                    ))
+                }
+                Some(_) => None,
+            }
+        })
+        .map(|detail| Violation::new("audits", detail))
+        .collect()
+}
+
+/// Checks that the report of every node is valid JSON.
+pub fn reports(observations: &[Observation]) -> Vec<Violation> {
+    observations
+        .iter()
+        .filter_map(|observation| {
+            serde_json::from_str::<serde_json::Value>(&observation.json)
+                .err()
+                .map(|err| {
+                    Violation::new(
+                        "reports",
+                        format!(
+                            "the report of {} is not JSON: {}",
+                            observation.list.name, err
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Checks that every run that was stopped ended without an error.
+pub fn runs(stopped: &[(String, Result<(), String>)]) -> Vec<Violation> {
+    stopped
+        .iter()
+        .filter_map(|(name, outcome)| outcome.as_ref().err().map(|err| (name, err)))
+        .map(|(name, err)| Violation::new("runs", format!("a run of {} ended: {}", name, err)))
+        .collect()
+}
+
+/// Runs every check on the observations of `cluster`.
+pub fn check(cluster: &Cluster, observations: &[Observation]) -> Vec<Violation> {
+    let lists: Vec<PeerList> = observations
+        .iter()
+        .map(|observation| observation.list.clone())
+        .collect();
+    let missed = cluster.traffic().missed.values().sum();
+    let mut violations = membership(&lists);
+    violations.extend(sequences(observations, missed, cluster.network().dropped()));
+    violations.extend(threads(observations));
+    violations.extend(audits(observations));
+    violations.extend(reports(observations));
+    violations.extend(runs(cluster.stopped()));
+    violations
+}
+
+/// Reads the summary line of an audit back.
+fn parse_audit(line: &str) -> Option<AuditSummary> {
+    let rest = line.strip_prefix("Audit checked ")?;
+    let (checked, rest) = rest.split_once(" entries: ")?;
+    let (repaired, rest) = rest.split_once(" repaired, ")?;
+    let unrepairable = rest.strip_suffix(" unrepairable")?;
+    Some(AuditSummary {
+        checked: checked.parse().ok()?,
+        repaired: repaired.parse().ok()?,
+        unrepairable: unrepairable.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::test_support::addr;
+    #[cfg(feature = "net-tests")]
+    use crate::simulate::cluster::{Settings, TimeScale};
+
+    fn list(name: &str, port: u16, peers: &[(u16, bool)]) -> PeerList {
+        PeerList {
+            name: name.to_owned(),
+            addr: addr(port),
+            peers: peers
+                .iter()
+                .map(|(port, connected)| (addr(*port), *connected))
+                .collect(),
+        }
+    }
+
+    fn observation(name: &str) -> Observation {
+        let clean = AuditSummary {
+            checked: 4,
+            repaired: 0,
+            unrepairable: 0,
+        };
+        Observation {
+            list: list(name, 9000, &[]),
+            gaps: (0, 0),
+            threads: (6, 6),
+            audits: Some((clean, clean)),
+            json: "{\"node\":\"127.0.0.1:9000\"}".to_owned(),
+        }
+    }
+
+    fn details(violations: Vec<Violation>) -> Vec<String> {
+        violations.into_iter().map(|found| found.detail).collect()
+    }
+
+    #[test]
+    fn a_full_mesh_holds_membership() {
+        let lists = [
+            list("a", 9000, &[(9001, true), (9002, true)]),
+            list("b", 9001, &[(9000, true), (9002, true)]),
+            list("c", 9002, &[(9000, true), (9001, true)]),
+        ];
+        assert_eq!(membership(&lists), []);
+    }
+
+    #[test]
+    fn membership_finds_missing_phantom_stale_and_doubled_peers() {
+        let lists = [
+            list("a", 9000, &[(9001, true), (9005, true)]),
+            list("b", 9001, &[(9000, true), (9002, false)]),
+            list("c", 9002, &[(9000, true), (9001, true), (9001, true)]),
+        ];
+        assert_eq!(
+            details(membership(&lists)),
+            [
+                "a lists 127.0.0.1:9005, which is not running",
+                "a is not connected to c",
+                "b lists 127.0.0.1:9002 while it is being dialed",
+                "b is not connected to c",
+                "c lists 127.0.0.1:9001 twice",
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_find_misses_beyond_the_lost_frames_and_uncounted_gaps() {
+        let mut observed = observation("a");
+        observed.gaps = (2, 1);
+        assert_eq!(sequences(&[observation("a")], 3, 3), []);
+        assert_eq!(
+            details(sequences(&[observed], 4, 3)),
+            [
+                "4 messages were missed while the network lost 3 frames",
+                "a counts 2 gaps while its subscription was handed 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn threads_find_a_node_running_more_than_it_started_with() {
+        let mut leaking = observation("b");
+        leaking.threads = (6, 7);
+        let mut shrunk = observation("c");
+        shrunk.threads = (6, 5);
+        assert_eq!(
+            details(threads(&[observation("a"), leaking, shrunk])),
+            ["b runs 7 internal threads, 6 when it started"]
+        );
+    }
+
+    #[test]
+    fn audits_find_inconsistencies_left_or_found_again() {
+        let summary = |repaired, unrepairable| AuditSummary {
+            checked: 4,
+            repaired,
+            unrepairable,
+        };
+        let mut repaired = observation("a");
+        repaired.audits = Some((summary(2, 0), summary(0, 0)));
+        let mut left = observation("b");
+        left.audits = Some((summary(0, 1), summary(0, 1)));
+        let mut again = observation("c");
+        again.audits = Some((summary(1, 0), summary(1, 0)));
+        let mut silent = observation("d");
+        silent.audits = None;
+        assert_eq!(
+            details(audits(&[repaired, left, again, silent])),
+            [
+                "b left inconsistencies in place: Audit checked 4 entries: 0 repaired, 1 unrepairable",
+                "c found inconsistencies right after an audit: Audit checked 4 entries: 1 repaired, 0 unrepairable",
+                "d did not reply with audit summaries",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_find_the_one_that_is_not_json() {
+        let mut broken = observation("b");
+        broken.json = "{\"node\":}".to_owned();
+        let found = details(reports(&[observation("a"), broken]));
+        assert_eq!(found.len(), 1);
+        assert!(
+            found[0].starts_with("the report of b is not JSON"),
+            "{:?}",
+            found
+        );
+    }
+
+    #[test]
+    fn runs_find_the_one_that_ended_with_an_error() {
+        let stopped = [
+            ("a".to_owned(), Ok(())),
+            ("b".to_owned(), Err("did not stop within 5s".to_owned())),
+        ];
+        assert_eq!(
+            details(runs(&stopped)),
+            ["a run of b ended: did not stop within 5s"]
+        );
+    }
+
+    #[test]
+    fn an_audit_summary_reads_back_from_its_line() {
+        let summary = AuditSummary {
+            checked: 23,
+            repaired: 3,
+            unrepairable: 1,
+        };
+        assert_eq!(parse_audit(&summary.to_string()), Some(summary));
+        assert_eq!(parse_audit("Audit checked many entries"), None);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_converged_pair_holds_every_invariant() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.declare("b", Settings::new()).unwrap();
+        cluster.start("a", None).unwrap();
+        cluster.start("b", Some("a")).unwrap();
+        converge(&cluster, Duration::from_secs(5)).unwrap();
+
+        let observations = observe(&cluster).unwrap();
+        assert_eq!(observations.len(), 2);
+        assert_eq!(check(&cluster, &observations), []);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_stopped_node_is_not_waited_for() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        for name in ["a", "b", "c"] {
+            cluster.declare(name, Settings::new()).unwrap();
+        }
+        cluster.start("a", None).unwrap();
+        cluster.start("b", Some("a")).unwrap();
+        cluster.start("c", Some("a")).unwrap();
+        converge(&cluster, Duration::from_secs(5)).unwrap();
+        cluster.stop("c").unwrap();
+        converge(&cluster, Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            details(converge(&cluster, Duration::ZERO).err().unwrap_or_default()),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,1122 @@
+//! Simulation Scenarios.
+//!
+//! A scenario describes a simulation in JSON: the nodes of the cluster, the bootstrap edges
+//! they dial when they start, a timeline of events and the assertions checked once it is
+//! over.
+//!
+//! ```json
+//! {
+//!     "description": "f joins while b is away, and the network heals from a partition",
+//!     "seed": 7,
+//!     "defaults": { "heal-probe-interval": "10s" },
+//!     "nodes": ["a", "b", { "name": "c", "settings": { "period": "2s" } },
+//!               { "name": "f", "start": false }],
+//!     "bootstrap": [["b", "a"], ["c", "a"], ["f", "c"]],
+//!     "timeline": [
+//!         { "at": "5s", "stop": "b" },
+//!         { "at": "8s", "start": "f" },
+//!         { "at": "10s", "partition": [["a"], ["c", "f"]], "for": "15s" },
+//!         { "at": "12s", "publish": "f", "count": 5 },
+//!         { "at": "30s", "assert": { "all-connected": { "within": "10s" } } }
+//!     ],
+//!     "assertions": [
+//!         { "received": { "node": "a", "from": "f", "at-least": 5 } },
+//!         { "invariants": { "within": "20s" } }
+//!     ]
+//! }
+//! ```
+//!
+//! Only `nodes` is required; the `description` is for the reader. `nodes` is either the number of nodes, named `n1`, `n2` and so on, or their entries: a
+//! name, or an object with its `name`, its `settings` and whether it `start`s with the
+//! cluster, as it does by default. The settings are command-line options without their
+//! leading `--`, a string or a number as their value and `true` for a flag; the `defaults`
+//! apply to every node. The nodes starting with the cluster start in the order they are
+//! declared, each dialing the node its bootstrap edge names.
+//!
+//! Each event of the timeline happens `at` a time from the start, a duration or a number of
+//! seconds, and is one of:
+//!
+//! - `start`: starts a node, dialing its `connect` node if given, its bootstrap edge
+//!   otherwise, and no node with `"connect": null`;
+//! - `stop`: stops a node;
+//! - `partition`: splits the network into groups of nodes, for the time `for` if given;
+//! - `heal`, given `true`: ends every partition and clears the conditions of every link;
+//! - `link`: gives the links between two nodes a `latency` and a share of `loss`;
+//! - `reload`: has a node reload its settings, overridden by `settings`;
+//! - `publish`: has a node publish `count` messages on the traffic topic, one by default;
+//! - `assert`: checks an assertion.
+//!
+//! An assertion is `all-connected`, with the time the nodes have to meet `within`,
+//! `received`, with the `node` that received `at-least` that many messages from the node
+//! `from` and its time `within`, or `invariants`, with the time they have to hold `within`.
+//!
+//! `parse` rejects a scenario that can not run: unknown keys, values of the wrong type,
+//! settings a node can not run with, and timelines that can not happen, such as stopping a
+//! node that does not run or dialing one that is not declared. The error names the offending
+//! entry, such as `timeline[3] {"at":"5s","stop":"b"}: b is not running at 5s`.
+
+use crate::participant::transport::LinkConditions;
+use crate::simulate::assertion::Assertion;
+use crate::simulate::cluster::{check_settings, Settings};
+use crate::simulate::script::{Action, Script};
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// The most nodes a scenario declares.
+const MAX_NODES: usize = 256;
+
+/// The keys of the scenario object.
+const SCENARIO_KEYS: [&str; 7] = [
+    "description",
+    "seed",
+    "defaults",
+    "nodes",
+    "bootstrap",
+    "timeline",
+    "assertions",
+];
+
+/// The keys of a node entry.
+const NODE_KEYS: [&str; 3] = ["name", "settings", "start"];
+
+/// The events of the timeline, each with the keys of its entry besides `at` and its own.
+const EVENTS: [(&str, &[&str]); 8] = [
+    ("start", &["connect"]),
+    ("stop", &[]),
+    ("partition", &["for"]),
+    ("heal", &[]),
+    ("link", &["latency", "loss"]),
+    ("reload", &["settings"]),
+    ("publish", &["count"]),
+    ("assert", &[]),
+];
+
+/// A node of a scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeSpec {
+    /// The name of the node.
+    pub name: String,
+    /// The settings the node starts with.
+    pub settings: Settings,
+}
+
+/// A simulation ready to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    /// The seed the network draws its lost frames from.
+    pub seed: u64,
+    /// The nodes, in the order they are declared.
+    pub nodes: Vec<NodeSpec>,
+    /// The timeline, from the nodes starting with the cluster on, the partitions given a time
+    /// followed by their heal.
+    pub script: Script,
+    /// The assertions checked once the timeline is over.
+    pub assertions: Vec<Assertion>,
+}
+
+/// A node as its entry declares it.
+struct Declared {
+    name: String,
+    settings: Settings,
+    start: bool,
+}
+
+/// The state of the cluster as the timeline unfolds, to tell the events that can not happen.
+struct Timeline {
+    /// The declared nodes.
+    names: BTreeSet<String>,
+    /// The node each node dials when it starts, by node.
+    edges: BTreeMap<String, String>,
+    /// The settings each node runs with, by node.
+    settings: BTreeMap<String, Settings>,
+    /// The nodes running.
+    running: BTreeSet<String>,
+    /// The steps of the events taken so far.
+    script: Script,
+    /// The heals ending the partitions given a time.
+    heals: Script,
+}
+
+/// Reads and parses the scenario file at `path`.
+pub fn load(path: &Path) -> Result<Scenario, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("can not read \"{}\": {}", path.display(), err))?;
+    parse(&text)
+}
+
+/// Parses a scenario, and checks that it can run.
+///
+/// # Returns
+///
+/// The scenario, or what is wrong with it along with the entry at fault.
+pub fn parse(text: &str) -> Result<Scenario, String> {
+    let value: Value =
+        serde_json::from_str(text).map_err(|err| at_scenario(&format!("not JSON: {}", err)))?;
+    let scenario = object(&value, &SCENARIO_KEYS).map_err(|reason| at_scenario(&reason))?;
+    if scenario
+        .get("description")
+        .is_some_and(|text| !text.is_string())
+    {
+        return Err(at_scenario("\"description\" must be a string"));
+    }
+    let seed = match scenario.get("seed") {
+        Some(seed) => count("seed", seed).map_err(|reason| at_scenario(&reason))?,
+        None => 0,
+    };
+    let defaults = match scenario.get("defaults") {
+        Some(defaults) => settings("defaults", defaults, &Settings::new())
+            .map_err(|reason| at_scenario(&reason))?,
+        None => Settings::new(),
+    };
+    let declared = nodes(
+        scenario
+            .get("nodes")
+            .ok_or_else(|| at_scenario("\"nodes\" is missing"))?,
+        &defaults,
+    )?;
+    let edges = bootstrap(scenario.get("bootstrap"), &declared)?;
+
+    let mut timeline = Timeline::new(&declared, edges);
+    let mut events = Vec::new();
+    for (label, entry) in entries(scenario.get("timeline"), "timeline")? {
+        let at = entry
+            .as_object()
+            .ok_or_else(|| "must be an object".to_owned())
+            .and_then(|map| map.get("at").ok_or_else(|| "\"at\" is missing".to_owned()))
+            .and_then(|at| duration("at", at))
+            .map_err(|reason| fail(&label, entry, &reason))?;
+        events.push((at, label, entry));
+    }
+    // Sorting is stable, so the events of the same time keep the order they are written in.
+    events.sort_by_key(|(at, _, _)| *at);
+    for (at, label, entry) in events {
+        timeline
+            .take(at, entry)
+            .map_err(|reason| fail(&label, entry, &reason))?;
+    }
+
+    let mut assertions = Vec::new();
+    for (label, entry) in entries(scenario.get("assertions"), "assertions")? {
+        assertions.push(
+            assertion(entry, &timeline.names).map_err(|reason| fail(&label, entry, &reason))?,
+        );
+    }
+    Ok(Scenario {
+        seed,
+        nodes: declared
+            .into_iter()
+            .map(|node| NodeSpec {
+                name: node.name,
+                settings: node.settings,
+            })
+            .collect(),
+        script: timeline.script(),
+        assertions,
+    })
+}
+
+impl Timeline {
+    /// Starts the timeline with the nodes starting with the cluster, in order.
+    fn new(declared: &[Declared], edges: BTreeMap<String, String>) -> Self {
+        let mut timeline = Self {
+            names: declared.iter().map(|node| node.name.clone()).collect(),
+            settings: declared
+                .iter()
+                .map(|node| (node.name.clone(), node.settings.clone()))
+                .collect(),
+            edges,
+            running: BTreeSet::new(),
+            script: Script::default(),
+            heals: Script::default(),
+        };
+        for node in declared.iter().filter(|node| node.start) {
+            let connect = timeline.edges.get(&node.name).cloned();
+            timeline.script.push(
+                Duration::ZERO,
+                Action::Start {
+                    node: node.name.clone(),
+                    connect,
+                },
+            );
+            timeline.running.insert(node.name.clone());
+        }
+        timeline
+    }
+
+    /// Takes the event of the timeline `entry`, at `at`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the event can happen, or why not.
+    fn take(&mut self, at: Duration, entry: &Value) -> Result<(), String> {
+        let map = entry.as_object().ok_or("must be an object")?;
+        let event = match EVENTS
+            .iter()
+            .filter(|(name, _)| map.contains_key(*name))
+            .collect::<Vec<_>>()[..]
+        {
+            [] => {
+                let names: Vec<&str> = EVENTS.iter().map(|(name, _)| *name).collect();
+                return Err(format!("names no event, one of {}", names.join(", ")));
+            }
+            [event] => event,
+            [first, second, ..] => {
+                return Err(format!("names two events, {} and {}", first.0, second.0))
+            }
+        };
+        let (name, keys) = *event;
+        let allowed: Vec<&str> = ["at", name].iter().chain(keys.iter()).copied().collect();
+        object(entry, &allowed)?;
+        let value = &map[name];
+
+        let action = match name {
+            "start" => {
+                let node = self.node(name, value)?;
+                if self.running.contains(&node) {
+                    return Err(format!("{} is already running at {:?}", node, at));
+                }
+                let connect = match map.get("connect") {
+                    Some(Value::Null) => None,
+                    Some(connect) => Some(self.node("connect", connect)?),
+                    None => self.edges.get(&node).cloned(),
+                };
+                if let Some(connect) = &connect {
+                    if *connect == node {
+                        return Err(format!("{} dials itself", node));
+                    }
+                    if !self.running.contains(connect) {
+                        return Err(format!(
+                            "{} dials {}, which is not running at {:?}",
+                            node, connect, at
+                        ));
+                    }
+                }
+                self.running.insert(node.clone());
+                Action::Start { node, connect }
+            }
+            "stop" => {
+                let node = self.node(name, value)?;
+                if !self.running.remove(&node) {
+                    return Err(format!("{} is not running at {:?}", node, at));
+                }
+                Action::Stop { node }
+            }
+            "partition" => {
+                let groups = self.groups(value)?;
+                if let Some(time) = map.get("for") {
+                    let time = duration("for", time)?;
+                    if time.is_zero() {
+                        return Err("\"for\" must be longer than zero".to_owned());
+                    }
+                    self.heals.push(at + time, Action::Heal);
+                }
+                Action::Partition { groups }
+            }
+            "heal" => match value {
+                Value::Bool(true) => Action::Heal,
+                _ => return Err("\"heal\" must be true".to_owned()),
+            },
+            "link" => {
+                let nodes = self.names(name, value)?;
+                let [first, second] = <[String; 2]>::try_from(nodes)
+                    .map_err(|_| "\"link\" must name two nodes".to_owned())?;
+                if first == second {
+                    return Err(format!("\"link\" names {} twice", first));
+                }
+                let latency = match map.get("latency") {
+                    Some(latency) => duration("latency", latency)?,
+                    None => Duration::ZERO,
+                };
+                let loss = match map.get("loss") {
+                    Some(loss) => loss
+                        .as_f64()
+                        .filter(|loss| (0.0..=1.0).contains(loss))
+                        .ok_or("\"loss\" must be a number from 0 to 1")?,
+                    None => 0.0,
+                };
+                Action::Conditions {
+                    first,
+                    second,
+                    conditions: LinkConditions { latency, loss },
+                }
+            }
+            "reload" => {
+                let node = self.node(name, value)?;
+                let overrides = map.get("settings").ok_or("\"settings\" is missing")?;
+                let settings = settings("settings", overrides, &self.settings[&node])?;
+                self.settings.insert(node.clone(), settings.clone());
+                Action::Reload { node, settings }
+            }
+            "publish" => {
+                let node = self.node(name, value)?;
+                if !self.running.contains(&node) {
+                    return Err(format!("{} is not running at {:?}", node, at));
+                }
+                let count = match map.get("count") {
+                    Some(value) => u32::try_from(count("count", value)?)
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or("\"count\" must be from 1 to 4294967295")?,
+                    None => 1,
+                };
+                Action::Publish { node, count }
+            }
+            _ => Action::Assert(assertion(value, &self.names)?),
+        };
+        self.script.push(at, action);
+        Ok(())
+    }
+
+    /// Returns the script of the timeline: its steps in time order, each heal of a
+    /// partition first among the steps of its time.
+    fn script(self) -> Script {
+        let mut steps = self.heals.steps;
+        steps.extend(self.script.steps);
+        steps.sort_by_key(|step| step.at);
+        Script { steps }
+    }
+
+    /// Reads the declared node `value` names, the value of `key`.
+    fn node(&self, key: &str, value: &Value) -> Result<String, String> {
+        declared_node(key, value, &self.names)
+    }
+
+    /// Reads the declared nodes `value` lists, the value of `key`.
+    fn names(&self, key: &str, value: &Value) -> Result<Vec<String>, String> {
+        value
+            .as_array()
+            .ok_or_else(|| format!("\"{}\" must be an array of node names", key))?
+            .iter()
+            .map(|value| self.node(key, value))
+            .collect()
+    }
+
+    /// Reads the groups of a partition, at least two, none empty, and no node in two.
+    fn groups(&self, value: &Value) -> Result<Vec<Vec<String>>, String> {
+        let groups = value
+            .as_array()
+            .ok_or("\"partition\" must be an array of groups of node names")?
+            .iter()
+            .map(|group| self.names("partition", group))
+            .collect::<Result<Vec<_>, String>>()?;
+        if groups.len() < 2 {
+            return Err("\"partition\" must have two groups at least".to_owned());
+        }
+        if groups.iter().any(Vec::is_empty) {
+            return Err("\"partition\" has an empty group".to_owned());
+        }
+        let mut seen = BTreeSet::new();
+        if let Some(node) = groups.iter().flatten().find(|node| !seen.insert(*node)) {
+            return Err(format!("\"partition\" has {} in two groups", node));
+        }
+        Ok(groups)
+    }
+}
+
+/// Reads the nodes of the scenario, each with the `defaults` under its settings.
+fn nodes(value: &Value, defaults: &Settings) -> Result<Vec<Declared>, String> {
+    let declared: Vec<Declared> = match value {
+        Value::Number(_) => {
+            let nodes = count("nodes", value).map_err(|reason| at_scenario(&reason))?;
+            (1..=nodes.min(MAX_NODES as u64 + 1))
+                .map(|index| Declared {
+                    name: format!("n{}", index),
+                    settings: defaults.clone(),
+                    start: true,
+                })
+                .collect()
+        }
+        Value::Array(entries) => {
+            let mut declared: Vec<Declared> = Vec::with_capacity(entries.len());
+            for (index, entry) in entries.iter().enumerate() {
+                let fail = |reason: &str| fail(&format!("nodes[{}]", index), entry, reason);
+                let node = node(entry, defaults).map_err(|reason| fail(&reason))?;
+                if declared.iter().any(|other| other.name == node.name) {
+                    return Err(fail(&format!("{} is declared twice", node.name)));
+                }
+                declared.push(node);
+            }
+            declared
+        }
+        _ => {
+            return Err(at_scenario(
+                "\"nodes\" must be a number of nodes or an array of node entries",
+            ))
+        }
+    };
+    if declared.is_empty() {
+        return Err(at_scenario("\"nodes\" declares no node"));
+    }
+    if declared.len() > MAX_NODES {
+        return Err(at_scenario(&format!(
+            "\"nodes\" declares more than {} nodes",
+            MAX_NODES
+        )));
+    }
+    Ok(declared)
+}
+
+/// Reads the node entry `entry`.
+fn node(entry: &Value, defaults: &Settings) -> Result<Declared, String> {
+    if entry.is_string() {
+        return Ok(Declared {
+            name: name("name", entry)?,
+            settings: defaults.clone(),
+            start: true,
+        });
+    }
+    let map = object(entry, &NODE_KEYS)?;
+    let name = name("name", map.get("name").ok_or("\"name\" is missing")?)?;
+    let settings = match map.get("settings") {
+        Some(settings) => self::settings("settings", settings, defaults)?,
+        None => defaults.clone(),
+    };
+    let start = match map.get("start") {
+        Some(start) => start.as_bool().ok_or("\"start\" must be true or false")?,
+        None => true,
+    };
+    Ok(Declared {
+        name,
+        settings,
+        start,
+    })
+}
+
+/// Reads the bootstrap edges, each a pair of the node dialing and the node it dials.
+///
+/// # Returns
+///
+/// The node each node dials, by node, or what is wrong with an edge. A node starting with the
+/// cluster may only dial one that starts with it and is declared before it.
+fn bootstrap(
+    value: Option<&Value>,
+    declared: &[Declared],
+) -> Result<BTreeMap<String, String>, String> {
+    let names: BTreeSet<String> = declared.iter().map(|node| node.name.clone()).collect();
+    let position = |name: &str| declared.iter().position(|node| node.name == name);
+    let mut edges = BTreeMap::new();
+    for (label, entry) in entries(value, "bootstrap")? {
+        let fail = |reason: &str| fail(&label, entry, reason);
+        let [from, to] = entry
+            .as_array()
+            .and_then(|pair| <&[Value; 2]>::try_from(pair.as_slice()).ok())
+            .ok_or_else(|| fail("must be a pair of node names, the dialing one first"))?;
+        let from = declared_node("bootstrap", from, &names).map_err(|reason| fail(&reason))?;
+        let to = declared_node("bootstrap", to, &names).map_err(|reason| fail(&reason))?;
+        if from == to {
+            return Err(fail(&format!("{} dials itself", from)));
+        }
+        if edges.contains_key(&from) {
+            return Err(fail(&format!("{} has a bootstrap edge already", from)));
+        }
+        let (dialing, dialed) = (position(&from), position(&to));
+        if declared[dialing.unwrap_or(0)].start
+            && !(dialed < dialing && declared[dialed.unwrap_or(0)].start)
+        {
+            return Err(fail(&format!(
+                "{} starts with the cluster, {} does not start before it",
+                from, to
+            )));
+        }
+        edges.insert(from, to);
+    }
+    Ok(edges)
+}
+
+/// Reads an assertion, naming declared nodes only.
+fn assertion(value: &Value, names: &BTreeSet<String>) -> Result<Assertion, String> {
+    let map = object(value, &["all-connected", "received", "invariants"])?;
+    let (kind, parameters) = match map.iter().collect::<Vec<_>>()[..] {
+        [(kind, parameters)] => (kind.as_str(), parameters),
+        _ => {
+            return Err(
+                "an assertion must be one of all-connected, received and invariants".to_owned(),
+            )
+        }
+    };
+    let within = |map: &Map<String, Value>| match map.get("within") {
+        Some(within) => duration("within", within),
+        None => Ok(Duration::ZERO),
+    };
+    match kind {
+        "all-connected" => {
+            let map = object(parameters, &["within"]).map_err(|reason| in_kind(kind, &reason))?;
+            Ok(Assertion::AllConnected {
+                within: within(map)?,
+            })
+        }
+        "received" => {
+            let map = object(parameters, &["node", "from", "at-least", "within"])
+                .map_err(|reason| in_kind(kind, &reason))?;
+            let node = |key: &str| {
+                let value = map
+                    .get(key)
+                    .ok_or_else(|| in_kind(kind, &format!("\"{}\" is missing", key)))?;
+                declared_node(key, value, names)
+            };
+            let at_least = map
+                .get("at-least")
+                .ok_or_else(|| in_kind(kind, "\"at-least\" is missing"))?;
+            Ok(Assertion::Received {
+                node: node("node")?,
+                from: node("from")?,
+                at_least: count("at-least", at_least)?,
+                within: within(map)?,
+            })
+        }
+        _ => {
+            let map = object(parameters, &["within"]).map_err(|reason| in_kind(kind, &reason))?;
+            Ok(Assertion::Invariants {
+                within: within(map)?,
+            })
+        }
+    }
+}
+
+/// Reads the settings `value` holds, the value of `key`, over `base`.
+fn settings(key: &str, value: &Value, base: &Settings) -> Result<Settings, String> {
+    let map = value
+        .as_object()
+        .ok_or_else(|| format!("\"{}\" must be an object of settings", key))?;
+    let mut settings = base.clone();
+    for (name, value) in map {
+        match value {
+            Value::String(text) => settings.insert(name.clone(), Some(text.clone())),
+            Value::Number(number) => settings.insert(name.clone(), Some(number.to_string())),
+            Value::Bool(true) => settings.insert(name.clone(), None),
+            Value::Bool(false) => settings.remove(name),
+            _ => {
+                return Err(format!(
+                    "setting \"{}\" must be a string, a number, or true or false for a flag",
+                    name
+                ))
+            }
+        };
+    }
+    check_settings(&settings)?;
+    Ok(settings)
+}
+
+/// Returns the entries of the array `value`, the value of `key`, each with its label.
+fn entries<'v>(value: Option<&'v Value>, key: &str) -> Result<Vec<(String, &'v Value)>, String> {
+    match value {
+        None => Ok(Vec::new()),
+        Some(Value::Array(entries)) => Ok(entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (format!("{}[{}]", key, index), entry))
+            .collect()),
+        Some(_) => Err(at_scenario(&format!("\"{}\" must be an array", key))),
+    }
+}
+
+/// Returns the object `value`, if it has only the keys `keys`.
+fn object<'v>(value: &'v Value, keys: &[&str]) -> Result<&'v Map<String, Value>, String> {
+    let map = value.as_object().ok_or("must be an object")?;
+    match map.keys().find(|key| !keys.contains(&key.as_str())) {
+        Some(key) => Err(format!("unknown key \"{}\"", key)),
+        None => Ok(map),
+    }
+}
+
+/// Reads the name of a node, letters, digits, `-` and `_`.
+fn name(key: &str, value: &Value) -> Result<String, String> {
+    value
+        .as_str()
+        .filter(|name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(str::to_owned)
+        .ok_or_else(|| {
+            format!(
+                "\"{}\" must be a node name, of letters, digits, \"-\" and \"_\"",
+                key
+            )
+        })
+}
+
+/// Reads the name of a node among the declared `names`.
+fn declared_node(key: &str, value: &Value, names: &BTreeSet<String>) -> Result<String, String> {
+    let name = name(key, value)?;
+    if !names.contains(&name) {
+        return Err(format!("{} is not declared", name));
+    }
+    Ok(name)
+}
+
+/// Reads a non-negative integer.
+fn count(key: &str, value: &Value) -> Result<u64, String> {
+    value
+        .as_u64()
+        .ok_or_else(|| format!("\"{}\" must be a non-negative integer", key))
+}
+
+/// Reads a duration, such as `"1.5s"` or `"200ms"`, or a number of seconds; either may be
+/// zero.
+fn duration(key: &str, value: &Value) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "\"{}\" must be a duration, such as \"5s\", or a number of seconds",
+            key
+        )
+    };
+    match value {
+        Value::Number(number) => number
+            .as_f64()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(invalid),
+        Value::String(text) => crate::cli::parse_duration(text).or_else(|_| {
+            let number = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            match number.parse::<f64>() {
+                Ok(0.0) => Ok(Duration::ZERO),
+                _ => Err(invalid()),
+            }
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+/// Prefixes `reason` with the kind of assertion it is about.
+fn in_kind(kind: &str, reason: &str) -> String {
+    format!("{}: {}", kind, reason)
+}
+
+/// Prefixes `reason` with the scenario it is about.
+fn at_scenario(reason: &str) -> String {
+    format!("scenario: {}", reason)
+}
+
+/// Formats `reason` along with the entry `label` it is about.
+fn fail(label: &str, entry: &Value, reason: &str) -> String {
+    format!("{} {}: {}", label, entry, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the scenario of four nodes, started in a chain, with `timeline`.
+    fn with_timeline(timeline: &str) -> Result<Scenario, String> {
+        parse(&format!(
+            r#"{{
+                "nodes": ["a", "b", "c", {{ "name": "f", "start": false }}],
+                "bootstrap": [["b", "a"], ["c", "b"], ["f", "c"]],
+                "timeline": [{}]
+            }}"#,
+            timeline
+        ))
+    }
+
+    fn lines(script: &Script) -> Vec<String> {
+        script.steps.iter().map(|step| step.to_string()).collect()
+    }
+
+    #[test]
+    fn the_example_of_the_documentation_parses() {
+        let text = r#"{
+            "description": "f joins while b is away, and the network heals from a partition",
+            "seed": 7,
+            "defaults": { "heal-probe-interval": "10s" },
+            "nodes": ["a", "b", { "name": "c", "settings": { "period": "2s" } },
+                      { "name": "f", "start": false }],
+            "bootstrap": [["b", "a"], ["c", "a"], ["f", "c"]],
+            "timeline": [
+                { "at": "5s", "stop": "b" },
+                { "at": "8s", "start": "f" },
+                { "at": "10s", "partition": [["a"], ["c", "f"]], "for": "15s" },
+                { "at": "12s", "publish": "f", "count": 5 },
+                { "at": "30s", "assert": { "all-connected": { "within": "10s" } } }
+            ],
+            "assertions": [
+                { "received": { "node": "a", "from": "f", "at-least": 5 } },
+                { "invariants": { "within": "20s" } }
+            ]
+        }"#;
+        let scenario = parse(text).unwrap();
+        assert_eq!(scenario.seed, 7);
+        let probe = ("heal-probe-interval".to_owned(), Some("10s".to_owned()));
+        assert_eq!(
+            scenario.nodes[2],
+            NodeSpec {
+                name: "c".to_owned(),
+                settings: [probe.clone(), ("period".to_owned(), Some("2s".to_owned()))].into(),
+            }
+        );
+        assert_eq!(scenario.nodes[3].settings, [probe].into());
+        assert_eq!(
+            lines(&scenario.script),
+            [
+                "[   0.000s] start a",
+                "[   0.000s] start b connecting to a",
+                "[   0.000s] start c connecting to a",
+                "[   5.000s] stop b",
+                "[   8.000s] start f connecting to c",
+                "[  10.000s] partition [a] vs [c, f]",
+                "[  12.000s] publish 5 from f",
+                "[  25.000s] heal",
+                "[  30.000s] assert all connected within 10s",
+            ]
+        );
+        assert_eq!(
+            scenario.assertions,
+            [
+                Assertion::Received {
+                    node: "a".to_owned(),
+                    from: "f".to_owned(),
+                    at_least: 5,
+                    within: Duration::ZERO,
+                },
+                Assertion::Invariants {
+                    within: Duration::from_secs(20),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_number_of_nodes_declares_that_many_with_the_defaults() {
+        let scenario = parse(
+            r#"{ "nodes": 3, "defaults": { "share-limit": 4, "legacy-payload": true },
+                 "bootstrap": [["n2", "n1"], ["n3", "n1"]] }"#,
+        )
+        .unwrap();
+        let settings: Settings = [
+            ("legacy-payload".to_owned(), None),
+            ("share-limit".to_owned(), Some("4".to_owned())),
+        ]
+        .into();
+        let names: Vec<&str> = scenario
+            .nodes
+            .iter()
+            .map(|node| node.name.as_str())
+            .collect();
+        assert_eq!(names, ["n1", "n2", "n3"]);
+        assert!(scenario.nodes.iter().all(|node| node.settings == settings));
+        assert_eq!(
+            lines(&scenario.script),
+            [
+                "[   0.000s] start n1",
+                "[   0.000s] start n2 connecting to n1",
+                "[   0.000s] start n3 connecting to n1",
+            ]
+        );
+        assert!(scenario.assertions.is_empty());
+    }
+
+    #[test]
+    fn the_timeline_is_taken_in_time_order_with_the_heals_first() {
+        let scenario = with_timeline(
+            r#"{ "at": 20, "heal": true },
+               { "at": "4s", "partition": [["a"], ["b", "c"]], "for": 6 },
+               { "at": "10s", "publish": "a" },
+               { "at": "2s", "stop": "c" },
+               { "at": "2s", "start": "c", "connect": null },
+               { "at": "3s", "link": ["a", "c"], "latency": "150ms", "loss": 0.5 },
+               { "at": "0", "reload": "b", "settings": { "share-limit": 9 } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            lines(&scenario.script)[3..],
+            [
+                "[   0.000s] reload b with [--share-limit=9]",
+                "[   2.000s] stop c",
+                "[   2.000s] start c",
+                "[   3.000s] link a <-> c: latency 150ms, loss 0.5",
+                "[   4.000s] partition [a] vs [b, c]",
+                "[  10.000s] heal",
+                "[  10.000s] publish 1 from a",
+                "[  20.000s] heal",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_reload_overrides_the_settings_the_node_runs_with() {
+        let scenario = parse(
+            r#"{ "nodes": [{ "name": "a", "settings": { "share-limit": 4, "legacy-payload": true } }],
+                 "timeline": [
+                     { "at": "1s", "reload": "a", "settings": { "legacy-payload": false } },
+                     { "at": "2s", "reload": "a", "settings": { "corroboration": 2 } }
+                 ] }"#,
+        )
+        .unwrap();
+        let settings: Vec<&Settings> = scenario.script.steps[1..]
+            .iter()
+            .map(|step| match &step.action {
+                Action::Reload { settings, .. } => settings,
+                action => panic!("not a reload: {}", action),
+            })
+            .collect();
+        let share_limit = ("share-limit".to_owned(), Some("4".to_owned()));
+        assert_eq!(
+            settings,
+            [
+                &[share_limit.clone()].into(),
+                &[
+                    ("corroboration".to_owned(), Some("2".to_owned())),
+                    share_limit
+                ]
+                .into()
+            ]
+        );
+    }
+
+    #[test]
+    fn a_scenario_that_does_not_parse_names_its_fault() {
+        let cases = [
+            (
+                "[",
+                "scenario: not JSON: EOF while parsing a list at line 1 column 1",
+            ),
+            ("[]", "scenario: must be an object"),
+            (r#"{ "node": 3 }"#, "scenario: unknown key \"node\""),
+            (r#"{ "seed": 7 }"#, "scenario: \"nodes\" is missing"),
+            (
+                r#"{ "nodes": 2, "seed": -1 }"#,
+                "scenario: \"seed\" must be a non-negative integer",
+            ),
+            (r#"{ "nodes": 0 }"#, "scenario: \"nodes\" declares no node"),
+            (
+                r#"{ "nodes": 300 }"#,
+                "scenario: \"nodes\" declares more than 256 nodes",
+            ),
+            (
+                r#"{ "nodes": "a" }"#,
+                "scenario: \"nodes\" must be a number of nodes or an array of node entries",
+            ),
+            (
+                r#"{ "nodes": 2, "timeline": {} }"#,
+                "scenario: \"timeline\" must be an array",
+            ),
+            (
+                r#"{ "nodes": 2, "defaults": { "port": 8080 } }"#,
+                "scenario: \"port\" is set by the simulation",
+            ),
+        ];
+        for (text, err) in cases {
+            assert_eq!(parse(text).unwrap_err(), err, "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_node_that_can_not_run_is_reported_with_its_entry() {
+        let cases = [
+            (r#"["a", "a"]"#, r#"nodes[1] "a": a is declared twice"#),
+            (
+                r#"["a b"]"#,
+                r#"nodes[0] "a b": "name" must be a node name, of letters, digits, "-" and "_""#,
+            ),
+            (
+                r#"[{ "name": "a", "start": "yes" }]"#,
+                r#"nodes[0] {"name":"a","start":"yes"}: "start" must be true or false"#,
+            ),
+            (
+                r#"[{ "name": "a", "setting": {} }]"#,
+                r#"nodes[0] {"name":"a","setting":{}}: unknown key "setting""#,
+            ),
+            (
+                r#"[{ "name": "a", "settings": { "fanout": 3 } }]"#,
+                r#"nodes[0] {"name":"a","settings":{"fanout":3}}: Unknown argument "--fanout=3""#,
+            ),
+            (
+                r#"[{ "name": "a", "settings": { "topics": ["x"] } }]"#,
+                r#"nodes[0] {"name":"a","settings":{"topics":["x"]}}: setting "topics" must be a string, a number, or true or false for a flag"#,
+            ),
+            (
+                r#"[{ "settings": {} }]"#,
+                r#"nodes[0] {"settings":{}}: "name" is missing"#,
+            ),
+        ];
+        for (nodes, err) in cases {
+            let text = format!(r#"{{ "nodes": {} }}"#, nodes);
+            assert_eq!(parse(&text).unwrap_err(), err, "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_bootstrap_edge_that_can_not_be_dialed_is_reported() {
+        let cases = [
+            (
+                r#"[["b"]]"#,
+                r#"bootstrap[0] ["b"]: must be a pair of node names, the dialing one first"#,
+            ),
+            (
+                r#"[["b", "x"]]"#,
+                r#"bootstrap[0] ["b","x"]: x is not declared"#,
+            ),
+            (
+                r#"[["b", "b"]]"#,
+                r#"bootstrap[0] ["b","b"]: b dials itself"#,
+            ),
+            (
+                r#"[["b", "a"], ["b", "c"]]"#,
+                r#"bootstrap[1] ["b","c"]: b has a bootstrap edge already"#,
+            ),
+            (
+                r#"[["a", "b"]]"#,
+                r#"bootstrap[0] ["a","b"]: a starts with the cluster, b does not start before it"#,
+            ),
+            (
+                r#"[["c", "f"]]"#,
+                r#"bootstrap[0] ["c","f"]: c starts with the cluster, f does not start before it"#,
+            ),
+        ];
+        for (bootstrap, err) in cases {
+            let text = format!(
+                r#"{{ "nodes": ["a", "b", "c", {{ "name": "f", "start": false }}], "bootstrap": {} }}"#,
+                bootstrap
+            );
+            assert_eq!(parse(&text).unwrap_err(), err, "{}", text);
+        }
+        let late =
+            r#"{ "nodes": ["a", { "name": "f", "start": false }], "bootstrap": [["f", "a"]] }"#;
+        assert!(parse(late).is_ok());
+    }
+
+    #[test]
+    fn an_event_that_can_not_happen_is_reported_with_its_entry() {
+        let cases = [
+            (
+                r#"{ "stop": "a" }"#,
+                r#"timeline[0] {"stop":"a"}: "at" is missing"#,
+            ),
+            (r#""stop""#, r#"timeline[0] "stop": must be an object"#),
+            (
+                r#"{ "at": "soon", "stop": "a" }"#,
+                r#"timeline[0] {"at":"soon","stop":"a"}: "at" must be a duration, such as "5s", or a number of seconds"#,
+            ),
+            (
+                r#"{ "at": "1s" }"#,
+                r#"timeline[0] {"at":"1s"}: names no event, one of start, stop, partition, heal, link, reload, publish, assert"#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": "a", "start": "f" }"#,
+                r#"timeline[0] {"at":"1s","start":"f","stop":"a"}: names two events, start and stop"#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": "a", "for": "2s" }"#,
+                r#"timeline[0] {"at":"1s","for":"2s","stop":"a"}: unknown key "for""#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": 3 }"#,
+                r#"timeline[0] {"at":"1s","stop":3}: "stop" must be a node name, of letters, digits, "-" and "_""#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": "f" }"#,
+                r#"timeline[0] {"at":"1s","stop":"f"}: f is not running at 1s"#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": "x" }"#,
+                r#"timeline[0] {"at":"1s","stop":"x"}: x is not declared"#,
+            ),
+            (
+                r#"{ "at": "1s", "start": "a" }"#,
+                r#"timeline[0] {"at":"1s","start":"a"}: a is already running at 1s"#,
+            ),
+            (
+                r#"{ "at": "1s", "start": "f", "connect": "f" }"#,
+                r#"timeline[0] {"at":"1s","connect":"f","start":"f"}: f dials itself"#,
+            ),
+            (
+                r#"{ "at": "1s", "stop": "c" }, { "at": "2s", "start": "f" }"#,
+                r#"timeline[1] {"at":"2s","start":"f"}: f dials c, which is not running at 2s"#,
+            ),
+            (
+                r#"{ "at": "1s", "publish": "f" }"#,
+                r#"timeline[0] {"at":"1s","publish":"f"}: f is not running at 1s"#,
+            ),
+            (
+                r#"{ "at": "1s", "publish": "a", "count": 0 }"#,
+                r#"timeline[0] {"at":"1s","count":0,"publish":"a"}: "count" must be from 1 to 4294967295"#,
+            ),
+            (
+                r#"{ "at": "1s", "partition": [["a", "b", "c"]] }"#,
+                r#"timeline[0] {"at":"1s","partition":[["a","b","c"]]}: "partition" must have two groups at least"#,
+            ),
+            (
+                r#"{ "at": "1s", "partition": [["a", "b"], ["b", "c"]] }"#,
+                r#"timeline[0] {"at":"1s","partition":[["a","b"],["b","c"]]}: "partition" has b in two groups"#,
+            ),
+            (
+                r#"{ "at": "1s", "partition": [["a"], []] }"#,
+                r#"timeline[0] {"at":"1s","partition":[["a"],[]]}: "partition" has an empty group"#,
+            ),
+            (
+                r#"{ "at": "1s", "partition": [["a"], ["b"]], "for": 0 }"#,
+                r#"timeline[0] {"at":"1s","for":0,"partition":[["a"],["b"]]}: "for" must be longer than zero"#,
+            ),
+            (
+                r#"{ "at": "1s", "heal": false }"#,
+                r#"timeline[0] {"at":"1s","heal":false}: "heal" must be true"#,
+            ),
+            (
+                r#"{ "at": "1s", "link": ["a"] }"#,
+                r#"timeline[0] {"at":"1s","link":["a"]}: "link" must name two nodes"#,
+            ),
+            (
+                r#"{ "at": "1s", "link": ["a", "b"], "loss": 2 }"#,
+                r#"timeline[0] {"at":"1s","link":["a","b"],"loss":2}: "loss" must be a number from 0 to 1"#,
+            ),
+            (
+                r#"{ "at": "1s", "reload": "a" }"#,
+                r#"timeline[0] {"at":"1s","reload":"a"}: "settings" is missing"#,
+            ),
+            (
+                r#"{ "at": "1s", "reload": "a", "settings": { "share-limit": 0 } }"#,
+                r#"timeline[0] {"at":"1s","reload":"a","settings":{"share-limit":0}}: Share limit must be a positive number"#,
+            ),
+            (
+                r#"{ "at": "1s", "assert": { "connected": {} } }"#,
+                r#"timeline[0] {"assert":{"connected":{}},"at":"1s"}: unknown key "connected""#,
+            ),
+            (
+                r#"{ "at": "1s", "assert": { "received": { "node": "a", "from": "x", "at-least": 1 } } }"#,
+                r#"timeline[0] {"assert":{"received":{"at-least":1,"from":"x","node":"a"}},"at":"1s"}: x is not declared"#,
+            ),
+            (
+                r#"{ "at": "1s", "assert": { "received": { "node": "a", "from": "b" } } }"#,
+                r#"timeline[0] {"assert":{"received":{"from":"b","node":"a"}},"at":"1s"}: received: "at-least" is missing"#,
+            ),
+            (
+                r#"{ "at": "1s", "assert": { "all-connected": { "in": "2s" } } }"#,
+                r#"timeline[0] {"assert":{"all-connected":{"in":"2s"}},"at":"1s"}: all-connected: unknown key "in""#,
+            ),
+            (
+                r#"{ "at": "1s", "assert": { "all-connected": {}, "invariants": {} } }"#,
+                r#"timeline[0] {"assert":{"all-connected":{},"invariants":{}},"at":"1s"}: an assertion must be one of all-connected, received and invariants"#,
+            ),
+        ];
+        for (timeline, err) in cases {
+            assert_eq!(with_timeline(timeline).unwrap_err(), err, "{}", timeline);
+        }
+    }
+
+    #[test]
+    fn a_final_assertion_that_does_not_parse_is_reported_with_its_entry() {
+        let text = r#"{ "nodes": ["a"], "assertions": [{ "invariants": {} }, { "invariants": { "within": true } }] }"#;
+        assert_eq!(
+            parse(text).unwrap_err(),
+            r#"assertions[1] {"invariants":{"within":true}}: "within" must be a duration, such as "5s", or a number of seconds"#
+        );
+    }
+
+    #[test]
+    fn the_examples_parse() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/scenarios");
+        for name in ["convergence", "partition-heal", "churn"] {
+            let path = dir.join(format!("{}.json", name));
+            let scenario = load(&path).unwrap_or_else(|err| panic!("{}: {}", name, err));
+            assert!(!scenario.assertions.is_empty(), "{}", name);
+        }
+        assert!(load(&dir.join("missing.json"))
+            .unwrap_err()
+            .starts_with("can not read"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Simulation Scripts.
+//!
+//! A `Script` is the timeline of a simulation: the `Step`s it takes on a `Cluster`, each at a
+//! simulated time from the start. `execute` takes them in time order, waiting between them as
+//! the time scale of the cluster says, and logs one line per step, such as:
+//!
+//! ```plaintext
+//! [  12.500s] stop b
+//! [  14.000s] start f connecting to c
+//! ```
+//!
+//! An assertion on the timeline is checked when its time comes, and a run goes on whether it
+//! holds or not; the run stops at the first other step that fails. A script formats as the
+//! lines its run logs, so a printed script tells exactly what a run did.
+
+use crate::participant::transport::LinkConditions;
+use crate::simulate::assertion::{self, Assertion};
+use crate::simulate::cluster::{Cluster, Settings};
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What a step does to the cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Starts the node, dialing the node `connect` if given.
+    Start {
+        node: String,
+        connect: Option<String>,
+    },
+    /// Stops the node.
+    Stop { node: String },
+    /// Keeps each group of nodes apart from the others.
+    Partition { groups: Vec<Vec<String>> },
+    /// Ends the partitions and clears the conditions of the links.
+    Heal,
+    /// Sets the conditions of both directions between two nodes.
+    Conditions {
+        first: String,
+        second: String,
+        conditions: LinkConditions,
+    },
+    /// Replaces the settings of the node, which reloads them if it runs.
+    Reload { node: String, settings: Settings },
+    /// Has the node publish `count` messages on the traffic topic.
+    Publish { node: String, count: u32 },
+    /// Checks what the cluster is expected to hold at this point.
+    Assert(Assertion),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Start {
+                node,
+                connect: Some(connect),
+            } => write!(f, "start {} connecting to {}", node, connect),
+            Action::Start {
+                node,
+                connect: None,
+            } => write!(f, "start {}", node),
+            Action::Stop { node } => write!(f, "stop {}", node),
+            Action::Partition { groups } => {
+                let groups: Vec<String> = groups
+                    .iter()
+                    .map(|group| format!("[{}]", group.join(", ")))
+                    .collect();
+                write!(f, "partition {}", groups.join(" vs "))
+            }
+            Action::Heal => write!(f, "heal"),
+            Action::Conditions {
+                first,
+                second,
+                conditions,
+            } => write!(
+                f,
+                "link {} <-> {}: latency {:?}, loss {}",
+                first, second, conditions.latency, conditions.loss
+            ),
+            Action::Reload { node, settings } => {
+                let settings: Vec<String> = settings
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("--{}={}", name, value),
+                        None => format!("--{}", name),
+                    })
+                    .collect();
+                write!(f, "reload {} with [{}]", node, settings.join(" "))
+            }
+            Action::Publish { node, count } => write!(f, "publish {} from {}", count, node),
+            Action::Assert(assertion) => write!(f, "assert {}", assertion),
+        }
+    }
+}
+
+/// An action taken at a simulated time from the start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// When the action is taken.
+    pub at: Duration,
+    /// What is done.
+    pub action: Action,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>8.3}s] {}", self.at.as_secs_f64(), self.action)
+    }
+}
+
+/// The timeline of a simulation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    /// The steps, by time.
+    pub steps: Vec<Step>,
+}
+
+impl Script {
+    /// Appends `action` at `at`.
+    pub fn push(&mut self, at: Duration, action: Action) {
+        self.steps.push(Step { at, action });
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the run of a script went.
+#[derive(Debug, Default, PartialEq)]
+pub struct Run {
+    /// Whether each assertion checked held, with its step, in order.
+    pub verdicts: Vec<(Step, Result<(), String>)>,
+    /// The step the run stopped at and why, if one failed.
+    pub failed: Option<String>,
+}
+
+/// Takes the steps of `script` on `cluster`, in order, at their time, until one fails.
+///
+/// # Parameters
+///
+/// * `log` - Receives the line of each step, once it is taken.
+pub fn execute(cluster: &mut Cluster, script: &Script, log: &mut dyn FnMut(String)) -> Run {
+    let started = Instant::now();
+    let mut run = Run::default();
+    for step in &script.steps {
+        let due = started + cluster.scale().real(step.at);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        match &step.action {
+            Action::Assert(assertion) => {
+                let verdict = assertion::evaluate(cluster, assertion);
+                run.verdicts.push((step.clone(), verdict));
+            }
+            action => {
+                if let Err(reason) = apply(cluster, action) {
+                    run.failed = Some(format!("{}: {}", step, reason));
+                    return run;
+                }
+            }
+        }
+        log(step.to_string());
+    }
+    run
+}
+
+/// Takes `action` on `cluster`.
+pub fn apply(cluster: &mut Cluster, action: &Action) -> Result<(), String> {
+    match action {
+        Action::Start { node, connect } => cluster.start(node, connect.as_deref()),
+        Action::Stop { node } => cluster.stop(node),
+        Action::Partition { groups } => cluster.partition(groups),
+        Action::Heal => {
+            cluster.heal();
+            Ok(())
+        }
+        Action::Conditions {
+            first,
+            second,
+            conditions,
+        } => cluster.set_conditions(first, second, *conditions),
+        Action::Reload { node, settings } => {
+            let out = cluster.reload(node, settings.clone())?;
+            match out.first() {
+                Some(line) if line.starts_with("Rejected") => Err(out.join("\n")),
+                _ => Ok(()),
+            }
+        }
+        Action::Publish { node, count } => {
+            (0..*count).try_for_each(|index| cluster.publish(node, &format!("{} #{}", node, index)))
+        }
+        Action::Assert(assertion) => assertion::evaluate(cluster, assertion),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "net-tests")]
+    use crate::simulate::cluster::TimeScale;
+
+    fn start(node: &str, connect: Option<&str>) -> Action {
+        Action::Start {
+            node: node.to_owned(),
+            connect: connect.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn a_script_formats_one_line_per_step() {
+        let mut script = Script::default();
+        script.push(Duration::ZERO, start("a", None));
+        script.push(Duration::from_millis(12500), start("f", Some("c")));
+        script.push(
+            Duration::from_secs(20),
+            Action::Partition {
+                groups: vec![vec!["a".to_owned(), "b".to_owned()], vec!["c".to_owned()]],
+            },
+        );
+        script.push(
+            Duration::from_secs(21),
+            Action::Conditions {
+                first: "a".to_owned(),
+                second: "b".to_owned(),
+                conditions: LinkConditions {
+                    latency: Duration::from_millis(80),
+                    loss: 0.05,
+                },
+            },
+        );
+        let settings = [("share-limit".to_owned(), Some("4".to_owned()))].into();
+        script.push(
+            Duration::from_secs(22),
+            Action::Reload {
+                node: "b".to_owned(),
+                settings,
+            },
+        );
+        assert_eq!(
+            script.to_string(),
+            "[   0.000s] start a\n\
+             [  12.500s] start f connecting to c\n\
+             [  20.000s] partition [a, b] vs [c]\n\
+             [  21.000s] link a <-> b: latency 80ms, loss 0.05\n\
+             [  22.000s] reload b with [--share-limit=4]\n"
+        );
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn the_steps_are_taken_at_their_time_and_logged() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.declare("b", Settings::new()).unwrap();
+        let mut script = Script::default();
+        script.push(Duration::ZERO, start("a", None));
+        script.push(Duration::from_secs(1), start("b", Some("a")));
+        script.push(
+            Duration::from_secs(3),
+            Action::Stop {
+                node: "a".to_owned(),
+            },
+        );
+
+        let started = Instant::now();
+        let mut log = Vec::new();
+        let run = execute(&mut cluster, &script, &mut |line| log.push(line));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(run, Run::default());
+        assert_eq!(
+            log,
+            script.steps.iter().map(Step::to_string).collect::<Vec<_>>()
+        );
+        assert_eq!(cluster.running(), ["b"]);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_script_stops_at_the_step_that_fails() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        let mut script = Script::default();
+        script.push(Duration::ZERO, start("a", None));
+        script.push(
+            Duration::ZERO,
+            Action::Stop {
+                node: "b".to_owned(),
+            },
+        );
+        script.push(Duration::ZERO, Action::Heal);
+
+        let mut log = Vec::new();
+        let run = execute(&mut cluster, &script, &mut |line| log.push(line));
+        assert_eq!(
+            run.failed.as_deref(),
+            Some("[   0.000s] stop b: node \"b\" is not declared")
+        );
+        assert_eq!(log, ["[   0.000s] start a"]);
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn an_assertion_that_does_not_hold_leaves_the_run_going() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        let received = Assertion::Received {
+            node: "a".to_owned(),
+            from: "a".to_owned(),
+            at_least: 1,
+            within: Duration::ZERO,
+        };
+        let mut script = Script::default();
+        script.push(Duration::ZERO, start("a", None));
+        script.push(Duration::ZERO, Action::Assert(received));
+        script.push(
+            Duration::ZERO,
+            Action::Stop {
+                node: "a".to_owned(),
+            },
+        );
+
+        let mut log = Vec::new();
+        let run = execute(&mut cluster, &script, &mut |line| log.push(line));
+        assert_eq!(
+            run.verdicts,
+            [(
+                script.steps[1].clone(),
+                Err("a received 0 from a".to_owned())
+            )]
+        );
+        assert_eq!(run.failed, None);
+        assert_eq!(
+            log,
+            [
+                "[   0.000s] start a",
+                "[   0.000s] assert a received at least 1 from a",
+                "[   0.000s] stop a",
+            ]
+        );
+        assert!(cluster.running().is_empty());
+    }
+
+    #[cfg(feature = "net-tests")]
+    #[test]
+    fn a_rejected_reload_fails_its_step() {
+        let mut cluster = Cluster::new(TimeScale::FAST, 0).unwrap();
+        cluster.declare("a", Settings::new()).unwrap();
+        cluster.start("a", None).unwrap();
+        let reload = Action::Reload {
+            node: "a".to_owned(),
+            settings: [("share-limit".to_owned(), Some("0".to_owned()))].into(),
+        };
+        let err = apply(&mut cluster, &reload).unwrap_err();
+        assert!(err.starts_with("Rejected"), "{}", err);
+    }
+}
@@ -0,0 +1,1022 @@
+//! Wire Transcripts.
+//!
+//! bincode frames carry no type information, so a change of the `Message` layout, a variant
+//! moved or a field added, changes the bytes on the wire without failing a round trip test:
+//! both ends of the test use the same layout. A transcript pins the bytes of the current
+//! protocol version down instead. It is a recorded conversation between an initiator and a
+//! live participant acting as the responder, each frame as FramedTcp writes it, length prefix
+//! included, and `tests/transcripts.rs` replays every transcript of `tests/transcripts/`
+//! against a fresh responder, comparing what it answers byte for byte.
+//!
+//! A transcript is a text file:
+//!
+//! ```plaintext
+//! # What the conversation shows, in comment lines.
+//! protocol 12
+//! responder --period=1h --batch-window-ms=0
+//!
+//! > PublicAddress
+//!   2a 00 00 00 00 ...
+//! < SharedPeers
+//!   4b 0b 00 00 00 ...
+//!   mask 43..59 node id
+//! ```
+//!
+//! `protocol` names the version the transcript was recorded with, and `responder` the
+//! arguments of the participant answering it. Each frame opens with `>` when the initiator
+//! sends it and `<` when the responder does, followed by the kind of its message, and goes on
+//! with indented lines of hex bytes. The `mask` lines of a frame leave the bytes of a range,
+//! given as offsets into the frame, out of the comparison: the fields no two runs share, such
+//! as the node id of the responder, a timestamp, or the random part of a gossip text. A range
+//! without an end masks the rest of the frame, however long, for a field of variable length.
+//!
+//! The replay goes in lockstep: each frame of the initiator is sent once every frame of the
+//! responder before it was received, so the responder answers a frame at a time and the
+//! transcript does not depend on how fast it does. A frame of the responder that is not the
+//! next one of the transcript fails the replay with a `Mismatch`, which reports the offset of
+//! the first differing byte with the bytes around it in hex, and the kinds both frames decode
+//! into on this build.
+//!
+//! `record` runs a script of the initiator against a responder to write a transcript, masking
+//! the received frames with `canonical_masks`.
+//!
+//! The module is public only for the tests, hidden from the documentation, and not part of
+//! the API.
+
+pub use crate::participant::message::PROTOCOL_VERSION;
+
+use crate::participant::descriptors::DescriptorBudget;
+use crate::participant::event::InternalEvent;
+use crate::participant::message::{Message, MessageKind};
+use crate::participant::payload::parse_default_payload;
+use crate::participant::transport::{
+    Connection, InMemoryNetwork, InMemoryTransport, OwnedTransportEvent, Transport,
+    TransportContext,
+};
+
+use message_io::events::EventReceiver;
+use message_io::node::{self, NodeHandler, NodeTask, StoredNodeEvent};
+use message_io::util::encoding::{self, MAX_ENCODED_SIZE};
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// How many bytes a mismatch shows on each side of the first differing one.
+const CONTEXT: usize = 8;
+
+/// How many bytes a line of hex of a transcript holds.
+const LINE_BYTES: usize = 16;
+
+/// The descriptors of a raw peer, which needs one for its connection.
+const PEER_DESCRIPTORS: usize = 8;
+
+/// Which end of the conversation sends a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The raw peer replaying the transcript.
+    Initiator,
+    /// The participant the transcript is replayed against.
+    Responder,
+}
+
+impl Side {
+    fn marker(self) -> char {
+        match self {
+            Side::Initiator => '>',
+            Side::Responder => '<',
+        }
+    }
+}
+
+/// A range of the bytes of a frame left out of the comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mask {
+    /// The offset of the first masked byte.
+    pub start: usize,
+    /// The offset after the last masked byte, or `None` to mask the rest of the frame.
+    pub end: Option<usize>,
+    /// The field the range holds.
+    pub reason: String,
+}
+
+impl Mask {
+    /// Masks the bytes of `range`.
+    pub fn range(range: Range<usize>, reason: &str) -> Self {
+        Self {
+            start: range.start,
+            end: Some(range.end),
+            reason: reason.to_owned(),
+        }
+    }
+
+    /// Masks the bytes from `start` to the end of the frame.
+    pub fn tail(start: usize, reason: &str) -> Self {
+        Self {
+            start,
+            end: None,
+            reason: reason.to_owned(),
+        }
+    }
+
+    fn covers(&self, offset: usize) -> bool {
+        offset >= self.start && self.end.is_none_or(|end| offset < end)
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "mask {}..{} {}", self.start, end, self.reason),
+            None => write!(f, "mask {}.. {}", self.start, self.reason),
+        }
+    }
+}
+
+/// Returns the bytes of `frame` with those `masks` cover as `None`.
+///
+/// A mask without an end covers the rest of the frame, so the bytes from its start are left
+/// out, and masked frames of different lengths compare equal past it.
+pub fn apply_masks(frame: &[u8], masks: &[Mask]) -> Vec<Option<u8>> {
+    let len = masks
+        .iter()
+        .filter(|mask| mask.end.is_none())
+        .map(|mask| mask.start.min(frame.len()))
+        .fold(frame.len(), usize::min);
+    frame[..len]
+        .iter()
+        .enumerate()
+        .map(|(offset, byte)| (!masks.iter().any(|mask| mask.covers(offset))).then_some(*byte))
+        .collect()
+}
+
+/// Returns the offset of the first byte where `actual` differs from `expected` out of the
+/// `masks`, or where the shorter of them ends, or `None` when they are the same.
+pub fn first_difference(expected: &[u8], masks: &[Mask], actual: &[u8]) -> Option<usize> {
+    let (expected_view, actual_view) = (apply_masks(expected, masks), apply_masks(actual, masks));
+    let unbounded = masks.iter().any(|mask| mask.end.is_none());
+    let differs = expected_view
+        .iter()
+        .zip(&actual_view)
+        .position(|(expected, actual)| expected != actual);
+    match differs {
+        Some(offset) => Some(offset),
+        None if unbounded && expected_view.len() == actual_view.len() => None,
+        None if !unbounded && expected.len() == actual.len() => None,
+        None => Some(expected_view.len().min(actual_view.len())),
+    }
+}
+
+/// A frame of a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The end that sends the frame.
+    pub side: Side,
+    /// The kind of the message, as the transcript names it.
+    pub label: String,
+    /// The frame, length prefix included.
+    pub bytes: Vec<u8>,
+    /// The ranges of a frame of the responder the comparison leaves out.
+    pub masks: Vec<Mask>,
+}
+
+/// A recorded conversation, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    /// The comment lines at the top of the file, without their `#`.
+    pub description: Vec<String>,
+    /// The protocol version the transcript was recorded with.
+    pub protocol: u16,
+    /// The arguments of the responder.
+    pub responder: Vec<String>,
+    /// The frames, in the order they are sent.
+    pub frames: Vec<Frame>,
+}
+
+/// Why the text of a transcript does not parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptError {
+    /// The line at fault, from 1.
+    pub line: usize,
+    /// What is wrong with it.
+    pub reason: String,
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
+impl Transcript {
+    /// Parses the text of a transcript.
+    pub fn parse(text: &str) -> Result<Self, TranscriptError> {
+        let mut description = Vec::new();
+        let mut protocol = None;
+        let mut responder = None;
+        let mut frames: Vec<Frame> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |reason: String| TranscriptError {
+                line: index + 1,
+                reason,
+            };
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                if frames.is_empty() {
+                    description.push(comment.strip_prefix(' ').unwrap_or(comment).to_owned());
+                }
+                continue;
+            }
+            let indented = line.starts_with(char::is_whitespace);
+            if indented {
+                let frame = frames
+                    .last_mut()
+                    .ok_or_else(|| error("bytes before the first frame".to_owned()))?;
+                match trimmed.strip_prefix("mask ") {
+                    Some(mask) => frame.masks.push(parse_mask(mask).map_err(error)?),
+                    None => frame.bytes.extend(parse_hex(trimmed).map_err(error)?),
+                }
+                continue;
+            }
+            let side = match trimmed.chars().next() {
+                Some('>') => Some(Side::Initiator),
+                Some('<') => Some(Side::Responder),
+                _ => None,
+            };
+            if let Some(side) = side {
+                frames.push(Frame {
+                    side,
+                    label: trimmed[1..].trim().to_owned(),
+                    bytes: Vec::new(),
+                    masks: Vec::new(),
+                });
+            } else if let Some(version) = trimmed.strip_prefix("protocol ") {
+                let version = version
+                    .trim()
+                    .parse()
+                    .map_err(|_| error(format!("\"{}\" is not a protocol version", version)))?;
+                protocol = Some(version);
+            } else if let Some(args) = trimmed.strip_prefix("responder") {
+                responder = Some(args.split_whitespace().map(str::to_owned).collect());
+            } else {
+                return Err(error(format!("\"{}\" is not a transcript line", trimmed)));
+            }
+        }
+        let missing = |what: &str| TranscriptError {
+            line: text.lines().count(),
+            reason: format!("no {} line", what),
+        };
+        if let Some(empty) = frames.iter().position(|frame| frame.bytes.is_empty()) {
+            return Err(missing(&format!("bytes for frame {}", empty + 1)));
+        }
+        Ok(Self {
+            description,
+            protocol: protocol.ok_or_else(|| missing("protocol"))?,
+            responder: responder.unwrap_or_default(),
+            frames,
+        })
+    }
+
+    /// Writes the text of the transcript, which `parse` reads back.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for line in &self.description {
+            text.push_str(format!("# {}", line).trim_end());
+            text.push('\n');
+        }
+        text.push_str(&format!("protocol {}\n", self.protocol));
+        text.push_str(format!("responder {}", self.responder.join(" ")).trim_end());
+        text.push_str("\n\n");
+        for frame in &self.frames {
+            text.push_str(&format!("{} {}\n", frame.side.marker(), frame.label));
+            for line in frame.bytes.chunks(LINE_BYTES) {
+                text.push_str(&format!("  {}\n", hex_bytes(line)));
+            }
+            for mask in &frame.masks {
+                text.push_str(&format!("  {}\n", mask));
+            }
+        }
+        text
+    }
+}
+
+fn parse_mask(mask: &str) -> Result<Mask, String> {
+    let (range, reason) = mask.split_once(' ').unwrap_or((mask, ""));
+    let invalid = || format!("\"{}\" is not a mask range such as 4..12", range);
+    let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+    let start = start.parse().map_err(|_| invalid())?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().map_err(|_| invalid())?),
+    };
+    if end.is_some_and(|end| end <= start) {
+        return Err(invalid());
+    }
+    Ok(Mask {
+        start,
+        end,
+        reason: reason.trim().to_owned(),
+    })
+}
+
+fn parse_hex(line: &str) -> Result<Vec<u8>, String> {
+    let digits: String = line.split_whitespace().collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("\"{}\" has an odd number of hex digits", line));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|at| {
+            u8::from_str_radix(&digits[at..at + 2], 16)
+                .map_err(|_| format!("\"{}\" is not hex", line))
+        })
+        .collect()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes the bytes of `frame` from `start` to `end` in hex, the masked ones as `..`, and the
+/// byte at `marked` in brackets.
+fn hex_context(frame: &[u8], masks: &[Mask], window: Range<usize>, marked: usize) -> String {
+    let view = apply_masks(frame, masks);
+    let mut words = Vec::new();
+    for offset in window {
+        let word = match (frame.get(offset), view.get(offset)) {
+            (None, _) => "--".to_owned(),
+            (Some(_), None | Some(None)) => "..".to_owned(),
+            (Some(byte), Some(Some(_))) => format!("{:02x}", byte),
+        };
+        if offset == marked {
+            words.push(format!("[{}]", word));
+        } else {
+            words.push(word);
+        }
+    }
+    words.join(" ")
+}
+
+/// Describes the message `frame` decodes into on this build.
+fn describe(frame: &[u8]) -> String {
+    let decoded = encoding::decode_size(frame)
+        .and_then(|(len, prefix)| frame.get(prefix..prefix + len))
+        .and_then(|body| Message::decode(body).ok());
+    match decoded {
+        Some((message, _)) => format!("a {} message", message.kind().name()),
+        None => "no message".to_owned(),
+    }
+}
+
+/// A frame of the responder that is not the one the transcript expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The index of the frame in the transcript, from 1.
+    pub frame: usize,
+    /// The kind the transcript names.
+    pub label: String,
+    /// The offset of the first differing byte.
+    pub offset: usize,
+    /// The frame of the transcript.
+    pub expected: Vec<u8>,
+    /// Its masks.
+    pub masks: Vec<Mask>,
+    /// The frame the responder sent.
+    pub actual: Vec<u8>,
+}
+
+impl Mismatch {
+    /// Compares the frame of the responder `actual` with `expected`, the frame `index` of a
+    /// transcript counted from 1.
+    pub fn check(index: usize, expected: &Frame, actual: &[u8]) -> Result<(), Self> {
+        match first_difference(&expected.bytes, &expected.masks, actual) {
+            None => Ok(()),
+            Some(offset) => Err(Self {
+                frame: index,
+                label: expected.label.clone(),
+                offset,
+                expected: expected.bytes.clone(),
+                masks: expected.masks.clone(),
+                actual: actual.to_vec(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let longest = self.expected.len().max(self.actual.len());
+        let window = self.offset.saturating_sub(CONTEXT)..(self.offset + CONTEXT + 1).min(longest);
+        writeln!(
+            f,
+            "frame {} (< {}) differs at offset {} (0x{:x}), {} bytes expected, {} received",
+            self.frame,
+            self.label,
+            self.offset,
+            self.offset,
+            self.expected.len(),
+            self.actual.len()
+        )?;
+        writeln!(
+            f,
+            "  expected at {:>4}: {}  ({})",
+            window.start,
+            hex_context(&self.expected, &self.masks, window.clone(), self.offset),
+            describe(&self.expected)
+        )?;
+        write!(
+            f,
+            "  received at {:>4}: {}  ({})",
+            window.start,
+            hex_context(&self.actual, &self.masks, window.clone(), self.offset),
+            describe(&self.actual)
+        )
+    }
+}
+
+/// Why a replay failed.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The transcript was recorded with another protocol version than this build speaks.
+    Version { recorded: u16, current: u16 },
+    /// The frame of the initiator at the index is not a FramedTcp frame.
+    Malformed(usize),
+    /// The responder could not be reached.
+    Connect(io::Error),
+    /// The responder did not send the frame at the index, for the reason of the error. `sent`
+    /// is the first frame of the initiator this build reads as another message than the
+    /// transcript names, or else the last one sent, with its index.
+    Missing {
+        frame: usize,
+        label: String,
+        error: io::Error,
+        sent: Option<(usize, Box<Frame>)>,
+    },
+    /// The responder sent another frame than the one of the transcript.
+    Mismatch(Mismatch),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Version { recorded, current } => write!(
+                f,
+                "recorded with protocol version {}, this build speaks {}; regenerate the transcripts",
+                recorded, current
+            ),
+            ReplayError::Malformed(frame) => {
+                write!(f, "frame {} is not a FramedTcp frame", frame)
+            }
+            ReplayError::Connect(err) => write!(f, "can not reach the responder: {}", err),
+            ReplayError::Missing {
+                frame,
+                label,
+                error,
+                sent,
+            } => {
+                write!(f, "frame {} (< {}) never came: {}", frame, label, error)?;
+                match sent {
+                    // A frame read as another message than it was recorded as is a sign that
+                    // the layout of `Message` changed.
+                    Some((index, sent)) => write!(
+                        f,
+                        "\n  after frame {} (> {}), which this build reads as {}: {}",
+                        index,
+                        sent.label,
+                        describe(&sent.bytes),
+                        hex_bytes(&sent.bytes[..sent.bytes.len().min(2 * CONTEXT + 1)])
+                    ),
+                    None => Ok(()),
+                }
+            }
+            ReplayError::Mismatch(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Replays `transcript` against the responder listening on `responder` in `network`,
+/// waiting at most `timeout` for each of its frames.
+pub fn replay(
+    transcript: &Transcript,
+    network: &InMemoryNetwork,
+    responder: SocketAddr,
+    timeout: Duration,
+) -> Result<(), ReplayError> {
+    if transcript.protocol != PROTOCOL_VERSION {
+        return Err(ReplayError::Version {
+            recorded: transcript.protocol,
+            current: PROTOCOL_VERSION,
+        });
+    }
+    let mut peer = RawPeer::connect(network, responder, timeout).map_err(ReplayError::Connect)?;
+    let (mut misread, mut last) = (None, None);
+    for (index, frame) in transcript.frames.iter().enumerate() {
+        match frame.side {
+            Side::Initiator => {
+                peer.send(&frame.bytes)
+                    .ok_or(ReplayError::Malformed(index + 1))?;
+                if misread.is_none()
+                    && describe(&frame.bytes) != format!("a {} message", frame.label)
+                {
+                    misread = Some((index + 1, frame));
+                }
+                last = Some((index + 1, frame));
+            }
+            Side::Responder => {
+                let actual = peer
+                    .receive(timeout)
+                    .map_err(|error| ReplayError::Missing {
+                        frame: index + 1,
+                        label: frame.label.clone(),
+                        error,
+                        sent: misread
+                            .or(last)
+                            .map(|(index, frame)| (index, Box::new(frame.clone()))),
+                    })?;
+                Mismatch::check(index + 1, frame, &actual).map_err(ReplayError::Mismatch)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A step of the script `record` runs.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// The initiator sends the frame, length prefix included.
+    Send(Vec<u8>),
+    /// The responder answers with a message of the kind.
+    Expect(MessageKind),
+}
+
+/// Runs `script` against the responder listening on `responder` in `network`, and returns
+/// the frames of the conversation, those of the responder masked with `canonical_masks`.
+///
+/// # Errors
+///
+/// When the responder does not answer in time, or with a message of another kind than the
+/// script expects.
+pub fn record(
+    script: &[Step],
+    network: &InMemoryNetwork,
+    responder: SocketAddr,
+    timeout: Duration,
+) -> io::Result<Vec<Frame>> {
+    let mut peer = RawPeer::connect(network, responder, timeout)?;
+    let mut frames = Vec::new();
+    for step in script {
+        let (side, bytes) = match step {
+            Step::Send(bytes) => {
+                peer.send(bytes).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "not a FramedTcp frame")
+                })?;
+                (Side::Initiator, bytes.clone())
+            }
+            Step::Expect(kind) => {
+                let bytes = peer.receive(timeout)?;
+                let received = describe(&bytes);
+                if received != format!("a {} message", kind.name()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected a {} message, received {}", kind.name(), received),
+                    ));
+                }
+                (Side::Responder, bytes)
+            }
+        };
+        let (label, masks) = match decode_wire(&bytes) {
+            Some((message, prefix)) => {
+                let masks = match side {
+                    Side::Initiator => Vec::new(),
+                    Side::Responder => canonical_masks(&message, prefix),
+                };
+                (message.kind().name().to_owned(), masks)
+            }
+            None => ("undecodable".to_owned(), Vec::new()),
+        };
+        frames.push(Frame {
+            side,
+            label,
+            bytes,
+            masks,
+        });
+    }
+    Ok(frames)
+}
+
+fn decode_wire(frame: &[u8]) -> Option<(Message, usize)> {
+    let (len, prefix) = encoding::decode_size(frame)?;
+    let body = frame.get(prefix..prefix + len)?;
+    Message::decode(body)
+        .ok()
+        .map(|(message, _)| (message, prefix))
+}
+
+/// Returns the masks of the fields of `message` no two runs of a responder share, for its
+/// frame whose length prefix takes `prefix` bytes:
+///
+/// - the node id in the trailer of `PublicAddress` and `SharedPeers`, random per participant;
+/// - the uptime in a `Probe` or a `ProbeReply`, and the clock of a `ClockRequest`, or the
+///   times a `ClockReply` was received and sent at;
+/// - the tick and the random number of a gossip text, along with the lengths they change.
+pub fn canonical_masks(message: &Message, prefix: usize) -> Vec<Mask> {
+    // A variant index is a u32, a length and an id a u64.
+    let (variant, word) = (4, 8);
+    let body = prefix + variant;
+    match message {
+        Message::PublicAddress(_) | Message::SharedPeers(_) => {
+            let fields = bincode::serialized_size(message).unwrap_or_default() as usize;
+            let version = bincode::serialized_size(&PROTOCOL_VERSION).unwrap_or_default() as usize;
+            let at = prefix + fields + version;
+            vec![Mask::range(at..at + 16, "node id")]
+        }
+        Message::Probe { .. } => vec![Mask::range(body + word..body + 2 * word, "uptime")],
+        Message::ProbeReply { .. } => vec![Mask::range(body + word..body + 2 * word, "uptime")],
+        Message::ClockRequest { .. } => vec![Mask::range(body..body + word, "wall clock")],
+        Message::ClockReply { .. } => {
+            vec![Mask::range(body + word..body + 3 * word, "wall clock")]
+        }
+        Message::Text(text) if parse_default_payload(text).is_some() => {
+            let tag = "msg tick=".len();
+            vec![
+                Mask::range(0..prefix, "frame length"),
+                Mask::range(body..body + word, "text length"),
+                Mask::tail(body + word + tag, "tick and random number"),
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// A peer of an `InMemoryNetwork` that writes and reads FramedTcp frames.
+///
+/// It listens on nothing, so a responder dialing back the address the peer announces is
+/// refused, and keeps to the one connection it dials.
+pub struct RawPeer {
+    transport: InMemoryTransport,
+    handler: NodeHandler<InternalEvent>,
+    events: EventReceiver<StoredNodeEvent<InternalEvent>>,
+    _task: NodeTask,
+    conn: Connection,
+}
+
+impl RawPeer {
+    /// Dials `to` in `network`, waiting at most `timeout` for the connection.
+    pub fn connect(
+        network: &InMemoryNetwork,
+        to: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        Self::connect_from(network, Ipv4Addr::LOCALHOST.into(), to, timeout)
+    }
+
+    /// Dials `to` in `network` from `ip`, waiting at most `timeout` for the connection.
+    pub fn connect_from(
+        network: &InMemoryNetwork,
+        ip: IpAddr,
+        to: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let (handler, listener) = node::split::<InternalEvent>();
+        let (task, events) = listener.enqueue();
+        let mut transport = network.transport_from(ip);
+        transport.attach(TransportContext::new(
+            handler.clone(),
+            DescriptorBudget::new(PEER_DESCRIPTORS, 0),
+        ));
+        let (conn, _) = transport.dial(to)?;
+        let mut peer = Self {
+            transport,
+            handler,
+            events,
+            _task: task,
+            conn,
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            match peer.next_event(deadline)? {
+                OwnedTransportEvent::Connected(dialed, true) if dialed == conn => return Ok(peer),
+                OwnedTransportEvent::Connected(dialed, false) if dialed == conn => {
+                    return Err(io::ErrorKind::ConnectionRefused.into())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends `frame`, length prefix included.
+    ///
+    /// # Returns
+    ///
+    /// `None` when `frame` is not a whole FramedTcp frame.
+    pub fn send(&mut self, frame: &[u8]) -> Option<()> {
+        let (len, prefix) = encoding::decode_size(frame)?;
+        let body = frame.get(prefix..).filter(|body| body.len() == len)?;
+        self.transport.send(self.conn, body);
+        Some(())
+    }
+
+    /// Receives the next frame of the responder within `timeout`, length prefix included.
+    pub fn receive(&mut self, timeout: Duration) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.next_event(deadline)? {
+                OwnedTransportEvent::Frame(from, body) if from == self.conn => {
+                    let mut buf = [0; MAX_ENCODED_SIZE];
+                    let mut frame = encoding::encode_size(&body, &mut buf).to_vec();
+                    frame.extend(body);
+                    return Ok(frame);
+                }
+                OwnedTransportEvent::Closed(conn) if conn == self.conn => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "the responder closed the connection",
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_event(&mut self, deadline: Instant) -> io::Result<OwnedTransportEvent> {
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "nothing came in time");
+        loop {
+            let left = deadline
+                .checked_duration_since(Instant::now())
+                .ok_or_else(timed_out)?;
+            match self.events.receive_timeout(left) {
+                Some(StoredNodeEvent::Signal(InternalEvent::Transport(event))) => return Ok(event),
+                Some(_) => {}
+                None => return Err(timed_out()),
+            }
+        }
+    }
+}
+
+impl Drop for RawPeer {
+    fn drop(&mut self) {
+        self.handler.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participant::builder::ParticipantBuilder;
+    use crate::participant::identity::NodeId;
+    use crate::participant::message::builder::MessageBuilder;
+
+    use std::thread;
+
+    /// Returns `message` as FramedTcp writes it.
+    fn wire(builder: MessageBuilder) -> Vec<u8> {
+        builder.frame().unwrap()
+    }
+
+    fn responder_frame(bytes: Vec<u8>, masks: Vec<Mask>) -> Frame {
+        Frame {
+            side: Side::Responder,
+            label: "Text".to_owned(),
+            bytes,
+            masks,
+        }
+    }
+
+    #[test]
+    fn a_range_mask_hides_its_bytes_and_a_tail_mask_the_rest() {
+        let frame = [1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            apply_masks(&frame, &[Mask::range(1..3, "id")]),
+            [Some(1), None, None, Some(4), Some(5), Some(6)]
+        );
+        assert_eq!(
+            apply_masks(
+                &frame,
+                &[Mask::range(0..1, "length"), Mask::tail(4, "body")]
+            ),
+            [None, Some(2), Some(3), Some(4)]
+        );
+        assert_eq!(
+            apply_masks(&frame, &[Mask::tail(9, "past the end")]).len(),
+            6
+        );
+        assert_eq!(apply_masks(&frame, &[]).len(), 6);
+    }
+
+    #[test]
+    fn masked_bytes_never_differ() {
+        let masks = [Mask::range(1..3, "id"), Mask::tail(5, "body")];
+        assert_eq!(
+            first_difference(&[1, 2, 3, 4, 5, 6], &masks, &[1, 9, 9, 4, 5]),
+            None
+        );
+        assert_eq!(
+            first_difference(&[1, 2, 3, 4, 5, 6], &masks, &[1, 9, 9, 4, 5, 7, 7, 7]),
+            None
+        );
+        assert_eq!(
+            first_difference(&[1, 2, 3, 4, 5], &masks, &[1, 2, 3, 0, 5]),
+            Some(3)
+        );
+        // The frame has to reach the start of a tail mask.
+        assert_eq!(
+            first_difference(&[1, 2, 3, 4, 5, 6], &masks, &[1, 2, 3]),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn frames_of_different_lengths_differ_where_the_shorter_ends() {
+        assert_eq!(first_difference(&[1, 2, 3], &[], &[1, 2, 3, 4]), Some(3));
+        assert_eq!(first_difference(&[1, 2, 3], &[], &[1, 2]), Some(2));
+        assert_eq!(first_difference(&[1, 2, 3], &[], &[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn a_mismatch_reports_the_offset_with_the_bytes_around_it() {
+        let expected = wire(MessageBuilder::text("hello"));
+        let mut actual = expected.clone();
+        // The first byte of the variant index, past the length prefix, as a moved variant
+        // would change it.
+        actual[1] = 2;
+        let frame = responder_frame(expected, vec![Mask::range(0..1, "frame length")]);
+        assert_eq!(Mismatch::check(4, &frame, &frame.bytes), Ok(()));
+
+        let mismatch = Mismatch::check(4, &frame, &actual).unwrap_err();
+        assert_eq!(mismatch.offset, 1);
+        let report = mismatch.to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(
+            lines[0],
+            "frame 4 (< Text) differs at offset 1 (0x1), 18 bytes expected, 18 received"
+        );
+        assert_eq!(
+            lines[1],
+            "  expected at    0: .. [03] 00 00 00 05 00 00 00 00  (a Text message)"
+        );
+        assert_eq!(
+            lines[2],
+            "  received at    0: .. [02] 00 00 00 05 00 00 00 00  (no message)"
+        );
+    }
+
+    #[test]
+    fn a_mismatch_past_the_end_of_a_frame_shows_where_it_ends() {
+        let frame = responder_frame(wire(MessageBuilder::text("hello there")), Vec::new());
+        let actual = &frame.bytes[..frame.bytes.len() - 2];
+        let mismatch = Mismatch::check(1, &frame, actual).unwrap_err();
+        assert_eq!(mismatch.offset, actual.len());
+        let report = mismatch.to_string();
+        assert!(
+            report.contains("24 bytes expected, 22 received"),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains("74 68 65 [72] 65  (a Text message)"),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains("74 68 65 [--] --  (no message)"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn a_transcript_renders_as_it_parses() {
+        let text = "\
+# A short one.
+protocol 12
+responder --period=1h
+
+> PushParticipantsList
+  04 01 00 00 00
+< Text
+  08 03 00 00 00 01 02 03 04
+  mask 0..1 frame length
+  mask 5.. body
+";
+        let transcript = Transcript::parse(text).unwrap();
+        assert_eq!(transcript.description, ["A short one."]);
+        assert_eq!(transcript.protocol, 12);
+        assert_eq!(transcript.responder, ["--period=1h"]);
+        assert_eq!(transcript.frames.len(), 2);
+        assert_eq!(transcript.frames[1].side, Side::Responder);
+        assert_eq!(
+            transcript.frames[1].masks,
+            [Mask::range(0..1, "frame length"), Mask::tail(5, "body")]
+        );
+        assert_eq!(transcript.render(), text);
+    }
+
+    #[test]
+    fn a_malformed_transcript_names_its_line() {
+        let error = |text: &str| Transcript::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("protocol 12\n> Text\n  0g\n"),
+            "line 3: \"0g\" is not hex"
+        );
+        assert_eq!(
+            error("protocol 12\n< Text\n  01 02\n  mask 4..2 id\n"),
+            "line 4: \"4..2\" is not a mask range such as 4..12"
+        );
+        assert_eq!(error("> Text\n  01\n"), "line 2: no protocol line");
+        assert_eq!(
+            error("protocol 12\nhello\n"),
+            "line 2: \"hello\" is not a transcript line"
+        );
+    }
+
+    #[test]
+    fn the_canonical_masks_cover_the_node_id_of_a_trailer() {
+        let id = NodeId(0x0011_2233_4455_6677_8899_aabb_ccdd_eeff);
+        let frame = wire(
+            MessageBuilder::public_address(SocketAddr::from(([127, 0, 0, 1], 9100))).sender(
+                id,
+                None,
+                Duration::from_secs(1),
+                None,
+            ),
+        );
+        let (message, prefix) = decode_wire(&frame).unwrap();
+        let masks = canonical_masks(&message, prefix);
+        let Some(end) = masks[0].end else {
+            panic!("the node id has a length");
+        };
+        assert_eq!(frame[masks[0].start..end], id.0.to_le_bytes());
+    }
+
+    #[test]
+    fn a_replay_matches_a_masked_reply_and_reports_the_first_difference() {
+        let network = InMemoryNetwork::new();
+        let participant = ParticipantBuilder::new(Duration::from_secs(3600))
+            .port(9000)
+            .arg("--batch-window-ms=0")
+            .print_events()
+            .build_with_transport(network.transport())
+            .unwrap();
+        let handle = participant.handle();
+        let running = thread::spawn(move || participant.run());
+        let responder = SocketAddr::from(([127, 0, 0, 1], 9000));
+
+        let transcript = |reply_id: u64| Transcript {
+            description: Vec::new(),
+            protocol: PROTOCOL_VERSION,
+            responder: Vec::new(),
+            frames: vec![
+                Frame {
+                    side: Side::Initiator,
+                    label: "PublicAddress".to_owned(),
+                    bytes: wire(MessageBuilder::public_address(SocketAddr::from((
+                        [127, 0, 0, 1],
+                        9100,
+                    )))),
+                    masks: Vec::new(),
+                },
+                Frame {
+                    side: Side::Initiator,
+                    label: "Probe".to_owned(),
+                    bytes: wire(MessageBuilder::probe(1, 0)),
+                    masks: Vec::new(),
+                },
+                Frame {
+                    side: Side::Responder,
+                    label: "ProbeReply".to_owned(),
+                    bytes: wire(MessageBuilder::probe_reply(reply_id, 0)),
+                    masks: vec![Mask::range(13..21, "uptime")],
+                },
+            ],
+        };
+        replay(&transcript(1), &network, responder, Duration::from_secs(3)).unwrap();
+        match replay(&transcript(2), &network, responder, Duration::from_secs(3)) {
+            Err(ReplayError::Mismatch(mismatch)) => {
+                assert_eq!((mismatch.frame, mismatch.offset), (3, 5));
+            }
+            other => panic!("the reply matched: {:?}", other),
+        }
+
+        let mut recorded = transcript(1);
+        recorded.protocol -= 1;
+        assert!(matches!(
+            replay(&recorded, &network, responder, Duration::from_secs(3)),
+            Err(ReplayError::Version { .. })
+        ));
+        handle.shutdown();
+        running.join().unwrap().unwrap();
+    }
+}
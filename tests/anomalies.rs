@@ -0,0 +1,275 @@
+//! Every `AnomalyKind` triggered by the raw frames of a peer: a participant counts it and
+//! recovers, while a strict one stops on it with `ParticipantError::Anomaly`, and the binary
+//! turns that into its exit code.
+//!
+//! The test of the binary opens a loopback socket, so it only runs with the `net-tests`
+//! feature.
+
+use gossip_p2p::transcript::RawPeer;
+use gossip_p2p::{
+    AnomalyKind, InMemoryNetwork, Message, MessageBuilder, NodeConfig, NodeId, Participant,
+    ParticipantError, ParticipantHandle,
+};
+use message_io::util::encoding::{self, MAX_ENCODED_SIZE};
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The port the participant under test listens on.
+const PORT: u16 = 9000;
+
+/// A participant fed raw frames, on an in-memory network of its own.
+struct Target {
+    network: InMemoryNetwork,
+    addr: SocketAddr,
+    node_id: NodeId,
+    dir: PathBuf,
+    handle: ParticipantHandle,
+    thread: Option<JoinHandle<Result<(), ParticipantError>>>,
+}
+
+impl Target {
+    /// Starts a participant, strict or not, keeping its node id in a directory named after
+    /// `name`.
+    fn start(name: &str, strict: bool) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "gossip-p2p-anomalies-{}-{}-{}",
+            std::process::id(),
+            name,
+            strict
+        ));
+        fs::create_dir_all(&dir).expect("the directory of the participant can be created");
+        let mut args = vec![
+            format!("--port={}", PORT),
+            "--no-peer-cache".to_owned(),
+            "--period=1h".to_owned(),
+            format!("--identity-file={}", dir.join("target.id").display()),
+        ];
+        if strict {
+            args.push("--strict-protocol".to_owned());
+        }
+        let config = NodeConfig::from_args(&args).expect("the arguments are valid");
+        let network = InMemoryNetwork::new();
+        let participant = Participant::with_transport(config, network.transport())
+            .expect("the participant starts");
+        let handle = participant.handle();
+        let thread = thread::spawn(move || participant.run());
+        let node_id = handle
+            .report(TIMEOUT)
+            .expect("the participant reports")
+            .node_id;
+        Self {
+            network,
+            addr: SocketAddr::from(([127, 0, 0, 1], PORT)),
+            node_id,
+            dir,
+            handle,
+            thread: Some(thread),
+        }
+    }
+
+    /// Dials the participant and sends it `frames`, in order.
+    fn feed(&self, frames: &[Vec<u8>]) -> RawPeer {
+        let mut peer =
+            RawPeer::connect(&self.network, self.addr, TIMEOUT).expect("the participant accepts");
+        for frame in frames {
+            peer.send(frame).expect("the frames are whole");
+        }
+        peer
+    }
+
+    /// Returns the anomalies of `kind` the participant counted.
+    fn anomalies(&self, kind: AnomalyKind) -> u64 {
+        let position = AnomalyKind::ALL
+            .iter()
+            .position(|listed| *listed == kind)
+            .expect("every kind is listed");
+        self.handle
+            .report(TIMEOUT)
+            .expect("the participant reports")
+            .anomalies[position]
+    }
+
+    /// Waits for the participant to stop by itself, and returns how its run ended.
+    fn stopped(&mut self) -> Result<(), ParticipantError> {
+        let thread = self.thread.take().expect("the participant runs");
+        let deadline = Instant::now() + TIMEOUT;
+        while !thread.is_finished() {
+            assert!(Instant::now() < deadline, "the participant did not stop");
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread.join().expect("the participant does not panic")
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Prefixes `body` with its FramedTcp length.
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut buf = [0; MAX_ENCODED_SIZE];
+    let mut frame = encoding::encode_size(body, &mut buf).to_vec();
+    frame.extend(body);
+    frame
+}
+
+/// The announcement of a peer that nobody listens on, which establishes its connection.
+fn announce() -> Vec<u8> {
+    MessageBuilder::public_address(SocketAddr::from(([127, 0, 0, 1], 9100)))
+        .sender(
+            NodeId(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef),
+            None,
+            Duration::from_secs(1),
+            None,
+        )
+        .frame()
+        .expect("the announcement is valid")
+}
+
+/// A body that is no `Message`: its variant index is out of range.
+const GARBAGE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// The frames triggering an anomaly, as a function of the participant they are sent to.
+type Trigger = fn(&Target) -> Vec<Vec<u8>>;
+
+/// Returns the trigger of each anomaly.
+fn triggers() -> Vec<(AnomalyKind, Trigger)> {
+    vec![
+        (AnomalyKind::Undecodable, |_| {
+            vec![announce(), frame(&GARBAGE)]
+        }),
+        (AnomalyKind::ProtocolMismatch, |_| vec![frame(&GARBAGE)]),
+        (AnomalyKind::Violation, |_| {
+            let nested = Message::Batch(vec![Message::Batch(Vec::new())]);
+            vec![announce(), frame(&nested.encode())]
+        }),
+        (AnomalyKind::Echo, |target| {
+            let handshake = MessageBuilder::public_address(target.addr)
+                .sender(target.node_id, None, Duration::from_secs(3600), None)
+                .frame()
+                .expect("the handshake is valid");
+            vec![handshake]
+        }),
+        (AnomalyKind::InvalidTopic, |_| {
+            let topic = Message::Topic {
+                topic: "no such\ttopic".to_owned(),
+                payload: b"payload".to_vec(),
+            };
+            vec![announce(), frame(&topic.encode())]
+        }),
+    ]
+}
+
+#[test]
+fn every_kind_has_a_trigger() {
+    let triggered: Vec<AnomalyKind> = triggers().into_iter().map(|(kind, _)| kind).collect();
+    for kind in AnomalyKind::ALL {
+        assert!(triggered.contains(&kind), "nothing triggers {}", kind);
+    }
+}
+
+#[test]
+fn a_participant_counts_each_anomaly_and_keeps_running() {
+    for (kind, frames) in triggers() {
+        let target = Target::start(kind.name(), false);
+        let _peer = target.feed(&frames(&target));
+        let deadline = Instant::now() + TIMEOUT;
+        while target.anomalies(kind) == 0 {
+            assert!(Instant::now() < deadline, "no {} anomaly counted", kind);
+            thread::sleep(Duration::from_millis(10));
+        }
+        for other in AnomalyKind::ALL {
+            let expected = u64::from(other == kind);
+            assert_eq!(
+                target.anomalies(other),
+                expected,
+                "{} counted for {}",
+                other,
+                kind
+            );
+        }
+    }
+}
+
+#[test]
+fn a_strict_participant_stops_on_the_first_anomaly_of_each_kind() {
+    for (kind, frames) in triggers() {
+        let mut target = Target::start(kind.name(), true);
+        let _peer = target.feed(&frames(&target));
+        match target.stopped() {
+            Err(ParticipantError::Anomaly(anomaly)) => {
+                assert_eq!(anomaly.kind, kind, "{}", anomaly);
+                assert!(!anomaly.context.is_empty(), "{}", anomaly);
+            }
+            other => panic!("a {} anomaly ended the run with {:?}", kind, other),
+        }
+    }
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn the_binary_exits_with_the_anomaly_code_on_a_malformed_frame() {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::process::{Command, Stdio};
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("a loopback port is free")
+        .port();
+    let dir = std::env::temp_dir().join(format!("gossip-p2p-anomalies-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .arg(format!("--port={}", port))
+        .arg("--period=1h")
+        .arg("--no-peer-cache")
+        .arg("--strict-protocol")
+        .arg(format!("--identity-file={}", dir.join("node.id").display()))
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => {
+                assert!(child.try_wait().unwrap().is_none(), "the binary exited");
+                thread::sleep(Duration::from_millis(20));
+            }
+            Err(err) => panic!("the binary does not listen: {}", err),
+        }
+    };
+    stream.write_all(&frame(&GARBAGE)).unwrap();
+
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            panic!("the binary did not stop on the anomaly");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let output = child.wait_with_output().unwrap();
+    let _ = fs::remove_dir_all(dir);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr);
+    assert!(
+        stderr.contains("Stopped on a protocol anomaly: protocol_mismatch from"),
+        "{}",
+        stderr
+    );
+}
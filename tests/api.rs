@@ -0,0 +1,143 @@
+//! The supported API of the crate.
+//!
+//! `the_public_items_are_those_of_the_snapshot` lists what `src/lib.rs` makes public, the
+//! items re-exported at the root and in `prelude`, by the paths an embedder imports them
+//! from, and compares the list with `tests/public-api.txt`. An item added, removed or renamed
+//! fails the test until the snapshot is regenerated, on purpose, with:
+//!
+//! ```sh
+//! UPDATE_PUBLIC_API=1 cargo test --test api
+//! ```
+//!
+//! The path an item is defined at is not part of the list, so moving it between the private
+//! modules changes nothing. `the_internal_paths_do_not_compile` checks with `trybuild` that
+//! the previously public modules and what the types keep private are out of reach.
+
+use std::fs;
+use std::path::Path;
+
+/// Where the snapshot of the public items is kept.
+const SNAPSHOT: &str = "tests/public-api.txt";
+
+/// Lists the public items of `source`, the content of `lib.rs`, sorted.
+fn public_items(source: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut module = String::new();
+    let mut hidden = false;
+    let mut statement = String::new();
+    for line in source.lines().map(str::trim) {
+        if line.starts_with("//") || line.is_empty() {
+            continue;
+        }
+        if line == "#[doc(hidden)]" {
+            hidden = true;
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix("pub mod ")
+            .and_then(|rest| rest.strip_suffix(" {"))
+        {
+            items.push(format!("gossip_p2p::{}", name));
+            module = format!("{}::", name);
+            continue;
+        }
+        if line == "}" {
+            module.clear();
+            continue;
+        }
+        if !statement.is_empty() || line.starts_with("pub use ") {
+            statement.push_str(line);
+            statement.push(' ');
+        } else if line.starts_with("pub mod ") && !hidden {
+            let name = line["pub mod ".len()..].trim_end_matches(';');
+            items.push(format!("gossip_p2p::{}", name));
+        }
+        hidden = false;
+        if !statement.trim_end().ends_with(';') {
+            continue;
+        }
+        let used = statement.trim_end().trim_end_matches(';');
+        let names = match used.find('{') {
+            Some(open) => &used[open + 1..used.rfind('}').unwrap()],
+            None => used.rsplit("::").next().unwrap(),
+        };
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            items.push(format!("gossip_p2p::{}{}", module, name));
+        }
+        statement.clear();
+    }
+    items.sort();
+    items
+}
+
+#[test]
+fn the_public_items_are_those_of_the_snapshot() {
+    let items = public_items(include_str!("../src/lib.rs"));
+    let mut current = items.join("\n");
+    current.push('\n');
+    let snapshot = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    if std::env::var_os("UPDATE_PUBLIC_API").is_some() {
+        fs::write(&snapshot, &current).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&snapshot).unwrap_or_default();
+    assert!(
+        current == expected,
+        "the public items are not those of {}; regenerate it with \
+         `UPDATE_PUBLIC_API=1 cargo test --test api` if the change is intended:\n{}",
+        SNAPSHOT,
+        difference(&expected, &current)
+    );
+}
+
+/// Tells the items only one of two lists has.
+fn difference(expected: &str, current: &str) -> String {
+    let removed = expected
+        .lines()
+        .filter(|item| !current.lines().any(|line| line == *item))
+        .map(|item| format!("- {}", item));
+    let added = current
+        .lines()
+        .filter(|item| !expected.lines().any(|line| line == *item))
+        .map(|item| format!("+ {}", item));
+    removed.chain(added).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn the_public_items_are_listed_by_the_paths_they_are_imported_from() {
+    let source = "\
+        //! Docs.\n\
+        mod participant;\n\
+        #[doc(hidden)]\n\
+        pub mod app;\n\
+        pub mod extra;\n\
+        pub use participant::model::Participant;\n\
+        pub use participant::transport::{\n\
+            Connection, Transport,\n\
+        };\n\
+        pub mod prelude {\n\
+            pub use crate::{Participant, Transport};\n\
+        }\n";
+    assert_eq!(
+        public_items(source),
+        [
+            "gossip_p2p::Connection",
+            "gossip_p2p::Participant",
+            "gossip_p2p::Transport",
+            "gossip_p2p::extra",
+            "gossip_p2p::prelude",
+            "gossip_p2p::prelude::Participant",
+            "gossip_p2p::prelude::Transport",
+        ]
+    );
+}
+
+#[test]
+fn the_internal_paths_do_not_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/compile_fail/*.rs");
+}
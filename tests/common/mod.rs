@@ -0,0 +1,276 @@
+//! The multi-node harness of the integration tests.
+//!
+//! A `Harness` starts participants on one backend, each on a thread of its own, and waits for
+//! conditions on their reports. On `Harness::in_memory` the participants share an
+//! `InMemoryNetwork` and open no socket; on `Harness::tcp` they listen on loopback ports the
+//! system finds free. Either way they keep their node ids in a temporary directory removed
+//! with the harness, and use no peer cache unless `Harness::with_peer_cache` asks for one.
+
+#![allow(dead_code)]
+
+use gossip_p2p::{
+    InMemoryNetwork, NodeConfig, Participant, ParticipantHandle, Report, RequestClient,
+};
+
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The gossip period of the participants, unless a test gives its own.
+pub const PERIOD: &str = "100ms";
+
+/// How long a participant is given to start or to hand over its report.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often `Harness::wait_until` checks its condition.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The first port given to the participants of an in-memory network.
+const FIRST_VIRTUAL_PORT: u16 = 9000;
+
+/// Keeps the directories of the harnesses of one test binary apart.
+static HARNESSES: AtomicU32 = AtomicU32::new(0);
+
+/// The network the participants of a harness run on.
+enum Backend {
+    InMemory(InMemoryNetwork),
+    Tcp,
+}
+
+/// A participant started by a harness.
+pub struct Node {
+    /// The address the participant listens on.
+    pub addr: SocketAddr,
+    /// The handle of the participant.
+    pub handle: ParticipantHandle,
+    /// Sends requests through the participant.
+    pub requests: RequestClient,
+    thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl Node {
+    /// Returns the report of the participant, which has to hand it over in time.
+    pub fn report(&self) -> Report {
+        self.handle
+            .report(REPLY_TIMEOUT)
+            .unwrap_or_else(|| panic!("\"{}\" did not report in time", self.addr))
+    }
+
+    /// Determines whether the participant lists `peer` as connected.
+    pub fn is_connected_to(&self, peer: SocketAddr) -> bool {
+        self.report()
+            .peers
+            .iter()
+            .any(|listed| listed.addr == peer && listed.connected_for.is_some())
+    }
+
+    /// Determines whether the event loop of the participant stopped, on its own or not.
+    pub fn has_stopped(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Shuts the participant down, and waits for its event loop to stop.
+    pub fn stop(&mut self) -> Result<(), String> {
+        self.handle.shutdown();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or(Err("the participant panicked".to_owned())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Participants running against each other on one backend.
+pub struct Harness {
+    backend: Backend,
+    dir: PathBuf,
+    nodes: Vec<Node>,
+    peer_cache: bool,
+}
+
+impl Harness {
+    /// Creates a harness whose participants share an in-memory network.
+    pub fn in_memory() -> Self {
+        Self::new(Backend::InMemory(InMemoryNetwork::new()))
+    }
+
+    /// Creates a harness whose participants listen on loopback ports.
+    pub fn tcp() -> Self {
+        Self::new(Backend::Tcp)
+    }
+
+    fn new(backend: Backend) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "gossip-p2p-harness-{}-{}",
+            std::process::id(),
+            HARNESSES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).expect("the harness directory can be created");
+        Self {
+            backend,
+            dir,
+            nodes: Vec::new(),
+            peer_cache: false,
+        }
+    }
+
+    /// Has the participants keep the recent peers cache, in the `gossip-p2p` directory of
+    /// `$XDG_CACHE_HOME`.
+    pub fn with_peer_cache(mut self) -> Self {
+        self.peer_cache = true;
+        self
+    }
+
+    /// Returns the in-memory network of the harness, if it runs on one.
+    pub fn network(&self) -> Option<&InMemoryNetwork> {
+        match &self.backend {
+            Backend::InMemory(network) => Some(network),
+            Backend::Tcp => None,
+        }
+    }
+
+    /// Starts a participant dialing `connect`, if given, configured with the arguments of the
+    /// command line besides `--period`, `--port` and `--connect`.
+    ///
+    /// # Returns
+    ///
+    /// The index of the participant among those of the harness.
+    pub fn start(&mut self, connect: Option<SocketAddr>, args: &[&str]) -> usize {
+        self.start_with(connect, args, |_| {})
+    }
+
+    /// Starts a participant like `start`, calling `setup` on it before it runs, such as to
+    /// register its request handlers.
+    pub fn start_with(
+        &mut self,
+        connect: Option<SocketAddr>,
+        args: &[&str],
+        setup: impl FnOnce(&mut Participant) + Send + 'static,
+    ) -> usize {
+        let port = match self.backend {
+            Backend::InMemory(_) => FIRST_VIRTUAL_PORT + self.nodes.len() as u16,
+            Backend::Tcp => TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("a loopback port is free")
+                .port(),
+        };
+        let node = self.launch(port, connect, args, setup);
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Stops the participant `index` and starts it again on its port, with its node id,
+    /// configured with `args` alone and dialing nobody.
+    pub fn restart(&mut self, index: usize, args: &[&str]) {
+        let port = self.nodes[index].addr.port();
+        self.nodes[index]
+            .stop()
+            .unwrap_or_else(|err| panic!("port {} did not stop cleanly: {}", port, err));
+        self.nodes[index] = self.launch(port, None, args, |_| {});
+    }
+
+    /// Starts the participant of `port`, see `start_with`.
+    fn launch(
+        &self,
+        port: u16,
+        connect: Option<SocketAddr>,
+        args: &[&str],
+        setup: impl FnOnce(&mut Participant) + Send + 'static,
+    ) -> Node {
+        let identity = self.dir.join(format!("node-{}.id", port));
+        let mut full_args = vec![
+            format!("--period={}", PERIOD),
+            format!("--port={}", port),
+            format!("--identity-file={}", identity.display()),
+        ];
+        if !self.peer_cache {
+            full_args.push("--no-peer-cache".to_owned());
+        }
+        if let Some(connect) = connect {
+            full_args.push(format!("--connect={}", connect));
+        }
+        full_args.extend(args.iter().map(|arg| arg.to_string()));
+        let config = NodeConfig::from_args(&full_args).expect("the arguments are valid");
+
+        let transport = self.network().map(InMemoryNetwork::transport);
+        let (started, waiter) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("harness-{}", port))
+            .spawn(move || {
+                let participant = match transport {
+                    Some(transport) => Participant::with_transport(config, transport),
+                    None => Participant::new(config),
+                };
+                let mut participant = match participant {
+                    Ok(participant) => participant,
+                    Err(err) => {
+                        let _ = started.send(Err(err.to_string()));
+                        return Err(err.to_string());
+                    }
+                };
+                setup(&mut participant);
+                let _ = started.send(Ok((participant.handle(), participant.request_client())));
+                participant.run().map_err(|err| err.to_string())
+            })
+            .expect("the participant thread can be spawned");
+        let (handle, requests) = waiter
+            .recv_timeout(REPLY_TIMEOUT)
+            .expect("the participant starts in time")
+            .unwrap_or_else(|err| panic!("port {} can not run: {}", port, err));
+        Node {
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+            handle,
+            requests,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the participant `index`.
+    pub fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    /// Returns the participant `index`, to stop it.
+    pub fn node_mut(&mut self, index: usize) -> &mut Node {
+        &mut self.nodes[index]
+    }
+
+    /// Waits until `condition` holds, checking it every `POLL_INTERVAL`.
+    ///
+    /// # Panics
+    ///
+    /// When `timeout` elapses first, naming `what` was waited for.
+    pub fn wait_until(
+        &self,
+        what: &str,
+        timeout: Duration,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) {
+        let deadline = Instant::now() + timeout;
+        while !condition(self) {
+            assert!(
+                Instant::now() < deadline,
+                "timed out after {:?} waiting until {}",
+                timeout,
+                what
+            );
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        for node in &mut self.nodes {
+            let _ = node.stop();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,7 @@
+// The settings of a builder are only reached through its methods.
+use gossip_p2p::ParticipantBuilder;
+
+fn main() {
+    let builder = ParticipantBuilder::new(std::time::Duration::from_secs(1));
+    let _ = builder.args;
+}
@@ -0,0 +1,23 @@
+// A growing enum is matched with a wildcard arm, so a new variant breaks no embedder.
+use gossip_p2p::ParticipantError;
+
+fn describe(err: &ParticipantError) -> &'static str {
+    match err {
+        ParticipantError::Bind(_) => "bind",
+        ParticipantError::Resolve(_) => "resolve",
+        ParticipantError::BootstrapExhausted => "bootstrap",
+        ParticipantError::JoinTimeout => "join",
+        ParticipantError::StateFile(_) => "state file",
+        ParticipantError::Snapshot(_) => "snapshot",
+        ParticipantError::Filter(_) => "filter",
+        ParticipantError::AdminSocket(_) => "admin socket",
+        ParticipantError::Threads(_) => "threads",
+        ParticipantError::Anomaly(_) => "anomaly",
+        ParticipantError::BootstrapUnverified(_) => "unverified",
+        ParticipantError::Config(_) => "config",
+    }
+}
+
+fn main() {
+    let _ = describe;
+}
@@ -0,0 +1,6 @@
+// The modules the API lives in are private: an embedder reaches it from the root only.
+use gossip_p2p::participant::model::Participant;
+
+fn main() {
+    let _ = std::mem::size_of::<Participant>();
+}
@@ -0,0 +1,4 @@
+// The output of the participants is no API of the crate.
+fn main() {
+    gossip_p2p::printer::print_line("hello");
+}
@@ -0,0 +1,118 @@
+//! The console of the binary fed from a script: commands piped into `--interactive` run and
+//! the end of the input shuts the participant down gracefully, and `--exec` runs each command
+//! of its script.
+//!
+//! The binary listens on a loopback port, so these tests only run with the `net-tests`
+//! feature.
+#![cfg(feature = "net-tests")]
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::DepartureReason;
+
+use std::fs;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns a loopback port nothing listens on.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("a loopback port is free")
+        .port()
+}
+
+/// Returns a directory of its own for the files of the test `name`.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "gossip-p2p-console-{}-{}",
+        name,
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Starts the binary on `port` with its node id in `dir`, configured with `args`.
+fn spawn(port: u16, dir: &Path, args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .arg(format!("--port={}", port))
+        .arg("--period=100ms")
+        .arg("--no-peer-cache")
+        .arg(format!("--identity-file={}", dir.join("node.id").display()))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs")
+}
+
+/// Waits for `child` to exit by itself, killing it after `TIMEOUT`.
+fn wait(mut child: Child) -> Output {
+    let deadline = Instant::now() + TIMEOUT;
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            panic!("the binary did not exit");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn piped_commands_run_and_the_end_of_the_input_leaves_gracefully() {
+    let mut harness = Harness::tcp();
+    let peer = harness.start(None, &[]);
+    let port = free_port();
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let dir = scratch("eof");
+    let connect = format!("--connect={}", harness.node(peer).addr);
+    let mut child = spawn(port, &dir, &["--interactive", &connect]);
+    harness.wait_until("the binary joins the peer", TIMEOUT, |harness| {
+        harness.node(peer).is_connected_to(addr)
+    });
+
+    let mut stdin = child.stdin.take().expect("the input is piped");
+    stdin.write_all(b"stats\n\n").unwrap();
+    drop(stdin);
+    let output = wait(child);
+    let _ = fs::remove_dir_all(dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("Participants: 1"), "{}", stdout);
+
+    harness.wait_until("the peer sees the binary leave", TIMEOUT, |harness| {
+        let report = harness.node(peer).report();
+        !harness.node(peer).is_connected_to(addr)
+            && report
+                .departures
+                .iter()
+                .any(|(reason, count)| *reason == DepartureReason::Disconnected && *count > 0)
+    });
+}
+
+#[test]
+fn exec_runs_each_command_of_its_script() {
+    let port = free_port();
+    let dir = scratch("exec");
+    let child = spawn(port, &dir, &["--exec=limits; stats;; quit"]);
+    let output = wait(child);
+    let _ = fs::remove_dir_all(dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    let limits = stdout.find("Batch budget:").expect("limits ran");
+    let stats = stdout.find("Participants: 0").expect("stats ran");
+    assert!(limits < stats, "{}", stdout);
+}
@@ -0,0 +1,142 @@
+//! The exit codes of the binary, as a script starting it would see them.
+//!
+//! The tests that bind a loopback port, to check a configuration or to hold one against the
+//! binary, only run with the `net-tests` feature.
+
+use std::process::{Command, Output};
+#[cfg(feature = "net-tests")]
+use std::{fs, path::PathBuf};
+
+/// Runs the binary with `args` and waits for it to exit.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .args(args)
+        .output()
+        .expect("the binary runs")
+}
+
+/// Returns a directory of its own for the files of the test `name`.
+#[cfg(feature = "net-tests")]
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "gossip-p2p-exit-codes-{}-{}",
+        name,
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn invalid_arguments_exit_with_two() {
+    for args in [
+        &["--port=8080"][..],
+        &["--period=1", "--port=70000"],
+        &["--period=1", "--port=8080", "--unknown"],
+        &["--period=1000000000h", "--port=8080", "--check"],
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr);
+        assert!(stderr.contains("Usage:"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn check_refuses_what_startup_refuses() {
+    for args in [
+        &["--period=1", "--port=0", "--fd-headroom=100000", "--check"][..],
+        &[
+            "--period=10ms",
+            "--port=0",
+            "--per-peer-send-budget-ms=50",
+            "--check",
+        ],
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr);
+        assert!(
+            stderr.contains("Invalid configuration:"),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn a_bad_combination_names_both_options() {
+    let output = run(&[
+        "--period=1",
+        "--port=0",
+        "--busy-lag=500ms",
+        "--overload-lag=200ms",
+        "--check",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("busy-lag (500ms)"), "{}", stderr);
+    assert!(stderr.contains("overload-lag (200ms)"), "{}", stderr);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn a_valid_configuration_checks_with_zero() {
+    let dir = scratch("check");
+    let identity = format!("--identity-file={}", dir.join("node.id").display());
+    let output = run(&[
+        "--period=1",
+        "--port=0",
+        "--no-peer-cache",
+        &identity,
+        "--check",
+    ]);
+    let _ = fs::remove_dir_all(dir);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Configuration is valid"));
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn a_port_in_use_exits_with_three() {
+    use std::net::TcpListener;
+
+    let held = TcpListener::bind("127.0.0.1:0").expect("a loopback port is free");
+    let port = format!("--port={}", held.local_addr().unwrap().port());
+    let dir = scratch("bind");
+    let identity = format!("--identity-file={}", dir.join("node.id").display());
+    for check in [true, false] {
+        let mut args = vec!["--period=1h", &port, "--no-peer-cache", &identity];
+        if check {
+            args.push("--check");
+        }
+        let output = run(&args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(3), "{:?}: {}", args, stderr);
+    }
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn check_refuses_an_admin_socket_in_a_missing_directory() {
+    let dir = scratch("admin");
+    let identity = format!("--identity-file={}", dir.join("node.id").display());
+    let socket = format!(
+        "--admin-socket={}",
+        dir.join("missing").join("gossip.sock").display()
+    );
+    let output = run(&[
+        "--period=1",
+        "--port=0",
+        "--no-peer-cache",
+        &identity,
+        &socket,
+        "--check",
+    ]);
+    let _ = fs::remove_dir_all(dir);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+}
@@ -0,0 +1,445 @@
+//! Participants on the in-memory network, along with what it simulates of a real one: refused
+//! dials, partitions and half-open connections, raw peers on hosts of their own listing
+//! addresses and asking for introductions, bootstrap targets of another network, and a
+//! participant listening on two loopback addresses. None of these tests opens a socket, which the
+//! guard test checks on Linux.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::transcript::RawPeer;
+use gossip_p2p::{
+    AgeBucket, DialOutcome, DialTrigger, LinkConditions, MessageBuilder, MessageKind, NodeId,
+    SharedPeer,
+};
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Returns the number of dials the participant `index` of `harness` saw end with `outcome`.
+fn dials(harness: &Harness, index: usize, outcome: DialOutcome) -> u64 {
+    let position = DialOutcome::ALL
+        .iter()
+        .position(|listed| *listed == outcome)
+        .expect("every outcome is listed");
+    harness.node(index).report().dial_outcomes[position]
+}
+
+/// Returns the number of dials the participant `index` of `harness` started for `trigger`.
+fn triggered(harness: &Harness, index: usize, trigger: DialTrigger) -> u64 {
+    let position = DialTrigger::ALL
+        .iter()
+        .position(|listed| *listed == trigger)
+        .expect("every trigger is listed");
+    harness.node(index).report().dial_triggers[position]
+}
+
+/// Starts `count` participants, each dialing the previous one, and waits until every one is
+/// connected to every other.
+fn converged_chain(harness: &mut Harness, count: usize) {
+    let mut previous = None;
+    for _ in 0..count {
+        let index = harness.start(previous, &[]);
+        previous = Some(harness.node(index).addr);
+    }
+    harness.wait_until("the chain is a full mesh", TIMEOUT, |harness| {
+        (0..count).all(|index| {
+            (0..count)
+                .filter(|peer| *peer != index)
+                .all(|peer| harness.node(index).is_connected_to(harness.node(peer).addr))
+        })
+    });
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn in_memory_participants_open_no_socket() {
+    let sockets = || {
+        std::fs::read_dir("/proc/self/fd")
+            .expect("the descriptors of the process are listed")
+            .filter_map(|entry| std::fs::read_link(entry.ok()?.path()).ok())
+            .filter(|target| target.to_string_lossy().starts_with("socket:"))
+            .count()
+    };
+    let before = sockets();
+    let mut harness = Harness::in_memory();
+    converged_chain(&mut harness, 3);
+    assert_eq!(sockets(), before);
+    drop(harness);
+    assert_eq!(sockets(), before);
+}
+
+#[test]
+fn a_chain_of_participants_converges_to_a_full_mesh() {
+    let mut harness = Harness::in_memory();
+    converged_chain(&mut harness, 5);
+    let network = harness.network().expect("the harness is in memory");
+    assert_eq!(network.connections(), 5 * 4 / 2);
+}
+
+#[test]
+fn a_refused_bootstrap_counts_as_a_failed_dial() {
+    let mut harness = Harness::in_memory();
+    let first = harness.start(None, &[]);
+    let target = harness.node(first).addr;
+    harness
+        .network()
+        .expect("the harness is in memory")
+        .refuse(target, true);
+    let second = harness.start(Some(target), &[]);
+    harness.wait_until("the bootstrap failed", TIMEOUT, |harness| {
+        dials(harness, second, DialOutcome::Failed) > 0
+    });
+    assert!(!harness.node(second).is_connected_to(target));
+    assert_eq!(dials(&harness, second, DialOutcome::Connected), 0);
+}
+
+#[test]
+fn a_partition_stops_the_broadcasts_until_it_heals() {
+    let mut harness = Harness::in_memory();
+    converged_chain(&mut harness, 2);
+    let (first, second) = (harness.node(0).addr, harness.node(1).addr);
+    let network = harness
+        .network()
+        .cloned()
+        .expect("the harness is in memory");
+    let texts = |harness: &Harness| harness.node(0).report().received[MessageKind::Text.index()];
+
+    network.partition(&[first], &[second]);
+    // A text already on its way when the partition started may still arrive.
+    std::thread::sleep(Duration::from_millis(50));
+    let cut_off = texts(&harness);
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(texts(&harness), cut_off);
+
+    network.heal();
+    harness.wait_until("the broadcasts arrive again", TIMEOUT, |harness| {
+        texts(harness) > cut_off
+    });
+}
+
+#[test]
+fn a_half_closed_connection_is_replaced() {
+    let mut harness = Harness::in_memory();
+    let mut previous = None;
+    for _ in 0..3 {
+        let index = harness.start(previous, &[]);
+        previous = Some(harness.node(index).addr);
+    }
+    let full_mesh = |harness: &Harness| {
+        (0..3).all(|index| {
+            (0..3)
+                .filter(|peer| *peer != index)
+                .all(|peer| harness.node(index).is_connected_to(harness.node(peer).addr))
+        })
+    };
+    harness.wait_until("the participants are a full mesh", TIMEOUT, full_mesh);
+    let network = harness
+        .network()
+        .cloned()
+        .expect("the harness is in memory");
+
+    // The second keeps sending into the connection the first saw close, until one of them
+    // dials the other again.
+    let (first, second) = (harness.node(0).addr, harness.node(1).addr);
+    assert_eq!(network.half_close(second, first), 1);
+    harness.wait_until("the connection is replaced", TIMEOUT, |harness| {
+        full_mesh(harness) && network.connections() == 3
+    });
+    assert!(harness
+        .node(0)
+        .report()
+        .departures
+        .iter()
+        .any(|(_, count)| *count > 0));
+}
+
+#[test]
+fn a_peer_whose_frames_are_all_lost_is_redialed_until_they_arrive_again() {
+    let mut harness = Harness::in_memory();
+    converged_chain(&mut harness, 3);
+    let network = harness
+        .network()
+        .cloned()
+        .expect("the harness is in memory");
+    let (victim, silent) = (harness.node(0).addr, harness.node(1).addr);
+    let verified_ago = |harness: &Harness| {
+        harness
+            .node(0)
+            .report()
+            .peers
+            .into_iter()
+            .find(|peer| peer.addr == silent)
+            .and_then(|peer| peer.verified_ago)
+    };
+
+    // Nothing of the second reaches the first, which keeps hearing from the third: after 10
+    // unanswered broadcasts of 100ms, the first suspects the connection and dials it again.
+    network.set_conditions(
+        silent,
+        victim,
+        LinkConditions {
+            latency: Duration::ZERO,
+            loss: 1.0,
+        },
+    );
+    harness.wait_until("the silent peer is redialed", TIMEOUT, |harness| {
+        triggered(harness, 0, DialTrigger::Redial) > 0
+    });
+    assert_eq!(triggered(&harness, 2, DialTrigger::Redial), 0);
+
+    network.set_conditions(silent, victim, LinkConditions::default());
+    harness.wait_until("the peer is heard from again", TIMEOUT, |harness| {
+        harness.node(0).is_connected_to(silent)
+            && verified_ago(harness).is_some_and(|ago| ago < Duration::from_millis(300))
+    });
+}
+
+/// Connects a raw peer from `ip` to `target`, announcing `own` and listing `listed` after it.
+fn report(
+    harness: &Harness,
+    ip: Ipv4Addr,
+    target: SocketAddr,
+    own: SocketAddr,
+    listed: SocketAddr,
+) -> RawPeer {
+    let network = harness.network().expect("the harness is in memory");
+    let mut peer = RawPeer::connect_from(network, IpAddr::V4(ip), target, TIMEOUT)
+        .expect("the participant accepts");
+    let announce = MessageBuilder::public_address(own)
+        .sender(
+            NodeId(u128::from(own.port())),
+            None,
+            Duration::from_secs(1),
+            None,
+        )
+        .frame()
+        .expect("the announcement is valid");
+    let peers = [own, listed].map(|addr| SharedPeer {
+        addr,
+        age: AgeBucket::Fresh,
+    });
+    let list = MessageBuilder::shared_peers(peers.to_vec())
+        .frame()
+        .expect("the list is valid");
+    peer.send(&announce).expect("the announcement is whole");
+    peer.send(&list).expect("the list is whole");
+    peer
+}
+
+#[test]
+fn a_listed_address_is_only_dialed_once_enough_hosts_list_it() {
+    let mut harness = Harness::in_memory();
+    let target = harness.start(None, &["--corroboration=2"]);
+    let listed = harness.start(None, &[]);
+    let (target_addr, listed_addr) = (harness.node(target).addr, harness.node(listed).addr);
+    let own = |port| SocketAddr::from(([127, 0, 0, 1], port));
+
+    // Connections from one host count once, whatever addresses they announce.
+    let same_host = Ipv4Addr::new(127, 0, 0, 2);
+    let _first = report(&harness, same_host, target_addr, own(9100), listed_addr);
+    let _second = report(&harness, same_host, target_addr, own(9101), listed_addr);
+    harness.wait_until("both lists were heard", TIMEOUT, |harness| {
+        dials(harness, target, DialOutcome::CorroborationPending) >= 2
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(!harness.node(target).is_connected_to(listed_addr));
+    assert_eq!(dials(&harness, target, DialOutcome::Connected), 0);
+
+    let other_host = Ipv4Addr::new(127, 0, 0, 3);
+    let _third = report(&harness, other_host, target_addr, own(9102), listed_addr);
+    harness.wait_until("the listed address is dialed", TIMEOUT, |harness| {
+        harness.node(target).is_connected_to(listed_addr)
+    });
+    assert_eq!(dials(&harness, target, DialOutcome::Connected), 1);
+}
+
+/// Connects a raw peer announcing `own` to `relay`, and asks it to introduce `my_addr` to
+/// `target`.
+fn introduce(
+    harness: &Harness,
+    relay: SocketAddr,
+    own: SocketAddr,
+    target: SocketAddr,
+    my_addr: SocketAddr,
+) -> RawPeer {
+    let network = harness.network().expect("the harness is in memory");
+    let mut peer = RawPeer::connect(network, relay, TIMEOUT).expect("the participant accepts");
+    let announce = MessageBuilder::public_address(own)
+        .sender(
+            NodeId(u128::from(own.port())),
+            None,
+            Duration::from_secs(1),
+            None,
+        )
+        .frame()
+        .expect("the announcement is valid");
+    peer.send(&announce).expect("the announcement is whole");
+    send_introduction(&mut peer, target, my_addr);
+    peer
+}
+
+/// Has `peer` ask to introduce `my_addr` to `target`.
+fn send_introduction(peer: &mut RawPeer, target: SocketAddr, my_addr: SocketAddr) {
+    let request = MessageBuilder::please_introduce(target, my_addr)
+        .frame()
+        .expect("the request is valid");
+    peer.send(&request).expect("the request is whole");
+}
+
+/// Starts a relay and a participant connected to it, returning the address of the relay and
+/// the index of the participant.
+fn relay_and_target(harness: &mut Harness, target_args: &[&str]) -> (SocketAddr, usize) {
+    let relay = harness.start(None, &[]);
+    let relay_addr = harness.node(relay).addr;
+    let target = harness.start(Some(relay_addr), target_args);
+    harness.wait_until("the target is connected to the relay", TIMEOUT, |harness| {
+        harness
+            .node(relay)
+            .is_connected_to(harness.node(target).addr)
+    });
+    (relay_addr, target)
+}
+
+#[test]
+fn an_introduction_is_relayed_once_per_pair() {
+    let mut harness = Harness::in_memory();
+    let (relay, target) = relay_and_target(&mut harness, &[]);
+    let target_addr = harness.node(target).addr;
+    let own = SocketAddr::from(([127, 0, 0, 1], 9100));
+
+    let mut peer = introduce(&harness, relay, own, target_addr, own);
+    harness.wait_until("the target dials the introduced peer", TIMEOUT, |harness| {
+        triggered(harness, target, DialTrigger::Introduction) == 1
+    });
+    send_introduction(&mut peer, target_addr, own);
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(triggered(&harness, target, DialTrigger::Introduction), 1);
+}
+
+#[test]
+fn a_peer_can_only_have_its_own_address_introduced() {
+    let mut harness = Harness::in_memory();
+    let (relay, target) = relay_and_target(&mut harness, &[]);
+    let target_addr = harness.node(target).addr;
+    let own = SocketAddr::from(([127, 0, 0, 1], 9100));
+    let third = SocketAddr::from(([127, 0, 0, 1], 9200));
+
+    let mut peer = introduce(&harness, relay, own, target_addr, third);
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(triggered(&harness, target, DialTrigger::Introduction), 0);
+
+    send_introduction(&mut peer, target_addr, own);
+    harness.wait_until("the target dials the introduced peer", TIMEOUT, |harness| {
+        triggered(harness, target, DialTrigger::Introduction) == 1
+    });
+}
+
+#[test]
+fn a_blocked_or_uncorroborated_introduction_is_not_dialed() {
+    let own = SocketAddr::from(([127, 0, 0, 1], 9100));
+    let block = format!("--block={}", own);
+    for (args, outcome) in [
+        ([block.as_str()], DialOutcome::Filtered),
+        (["--corroboration=2"], DialOutcome::CorroborationPending),
+    ] {
+        let mut harness = Harness::in_memory();
+        let (relay, target) = relay_and_target(&mut harness, &args);
+        let target_addr = harness.node(target).addr;
+        let _peer = introduce(&harness, relay, own, target_addr, own);
+        harness.wait_until("the introduction is settled", TIMEOUT, |harness| {
+            dials(harness, target, outcome) == 1
+        });
+        assert_eq!(dials(&harness, target, DialOutcome::Failed), 0);
+    }
+}
+
+/// Starts a seed of the network `test` with a member, and a participant bootstrapping from the
+/// seed with `prober_args`.
+///
+/// # Returns
+///
+/// The indexes of the member and of the prober.
+fn probe_test_network(harness: &mut Harness, prober_args: &[&str]) -> (usize, usize) {
+    let seed = harness.start(None, &["--network=test"]);
+    let seed_addr = harness.node(seed).addr;
+    let member = harness.start(Some(seed_addr), &["--network=test"]);
+    let member_addr = harness.node(member).addr;
+    harness.wait_until("the member joins the seed", TIMEOUT, |harness| {
+        harness.node(seed).is_connected_to(member_addr)
+    });
+    let prober = harness.start(Some(seed_addr), prober_args);
+    (member, prober)
+}
+
+#[test]
+fn a_target_announcing_another_network_stops_the_bootstrap() {
+    let mut harness = Harness::in_memory();
+    let (member, prober) = probe_test_network(&mut harness, &["--expect-network-id=prod"]);
+    let prober_addr = harness.node(prober).addr;
+    harness.wait_until("the prober stops", TIMEOUT, |harness| {
+        harness.node(prober).has_stopped()
+    });
+    let err = harness.node_mut(prober).stop().unwrap_err();
+    assert_eq!(
+        err,
+        "the bootstrap target failed its verification: \"127.0.0.1:9000\" announced network \"test\", expected \"prod\""
+    );
+    // The list of the seed was never imported, so the member was never dialed.
+    assert!(harness
+        .node(member)
+        .report()
+        .peers
+        .iter()
+        .all(|peer| peer.addr != prober_addr));
+}
+
+#[test]
+fn a_target_announcing_the_expected_network_is_trusted() {
+    let mut harness = Harness::in_memory();
+    let (member, prober) = probe_test_network(&mut harness, &["--expect-network-id=test"]);
+    let prober_addr = harness.node(prober).addr;
+    harness.wait_until("the prober joins the member", TIMEOUT, |harness| {
+        harness.node(member).is_connected_to(prober_addr)
+    });
+    assert!(!harness.node(prober).has_stopped());
+}
+
+/// Returns the number of `Text` messages the participant `index` of `harness` received.
+fn texts_received(harness: &Harness, index: usize) -> u64 {
+    harness.node(index).report().received[MessageKind::Text.index()]
+}
+
+/// Returns the addresses the participant `index` of `harness` lists as its peers.
+fn peer_addrs(harness: &Harness, index: usize) -> Vec<SocketAddr> {
+    let report = harness.node(index).report();
+    report.peers.iter().map(|peer| peer.addr).collect()
+}
+
+#[test]
+fn a_dual_homed_participant_keeps_the_lists_of_its_networks_apart() {
+    let mut harness = Harness::in_memory();
+    let dual = harness.start(None, &["--listen=127.0.0.2:9100"]);
+    let primary = harness.node(dual).addr;
+    let secondary = SocketAddr::from(([127, 0, 0, 2], 9100));
+    let left = harness.start(Some(primary), &[]);
+    let right = harness.start(Some(secondary), &[]);
+    let (left_addr, right_addr) = (harness.node(left).addr, harness.node(right).addr);
+    harness.wait_until("both sides join", TIMEOUT, |harness| {
+        harness.node(dual).is_connected_to(left_addr)
+            && harness.node(dual).is_connected_to(right_addr)
+    });
+
+    // Gossip flows to both sides through the dual-homed participant, over several rounds of
+    // list exchanges.
+    harness.wait_until("both sides hear the gossip", TIMEOUT, |harness| {
+        texts_received(harness, left) >= 3 && texts_received(harness, right) >= 3
+    });
+    // Each side knows the dual-homed participant by the address it reached it at, and never
+    // learned of the other side.
+    assert_eq!(peer_addrs(&harness, left), [primary]);
+    assert_eq!(peer_addrs(&harness, right), [secondary]);
+}
@@ -0,0 +1,63 @@
+//! A consumer polling the inbox of a participant receives every topic message a peer
+//! publishes, in order and with none dropped.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::{Command, Inbox};
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long a condition may take to hold, and a message to arrive.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The number of messages published.
+const PUBLISHED: u32 = 200;
+
+#[test]
+fn a_polling_consumer_receives_every_message_in_order() {
+    let mut harness = Harness::in_memory();
+    let (opened, inbox) = mpsc::channel::<Inbox>();
+    let consumer = harness.start_with(None, &[], move |participant| {
+        let _ = opened.send(participant.inbox());
+    });
+    let inbox = inbox.recv().expect("the inbox is opened");
+    // The consumer subscribes to no topic, so its interest would spare it the topic otherwise.
+    let source = harness.start(
+        Some(harness.node(consumer).addr),
+        &["--topic-broadcast=counter"],
+    );
+    let consumer_addr = harness.node(consumer).addr;
+    harness.wait_until("the source is connected", TIMEOUT, |harness| {
+        harness.node(source).is_connected_to(consumer_addr)
+    });
+
+    for counter in 0..PUBLISHED {
+        harness.node(source).handle.command(
+            Command::Publish {
+                topic: "counter".to_owned(),
+                text: counter.to_string(),
+            },
+            TIMEOUT,
+        );
+    }
+
+    let mut received = Vec::new();
+    let deadline = Instant::now() + TIMEOUT;
+    while received.len() < PUBLISHED as usize {
+        let message = inbox
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_else(|| panic!("{} messages arrived in time", received.len()));
+        // The gossip texts of the source arrive along with the topic messages.
+        if message.topic.as_deref() != Some("counter") {
+            continue;
+        }
+        assert_eq!(message.sender, harness.node(source).addr);
+        let text = String::from_utf8(message.payload).expect("the payload is the text");
+        received.push(text.parse::<u32>().expect("the payload is a counter"));
+    }
+    assert_eq!(received, (0..PUBLISHED).collect::<Vec<u32>>());
+    assert_eq!(inbox.dropped(), 0);
+    assert_eq!(inbox.capacity(), 1024);
+}
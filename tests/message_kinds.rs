@@ -0,0 +1,78 @@
+//! The per-kind counters of the `peer` command: what one participant counts as sent to a peer,
+//! the peer counts as received from it.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::{Command, MessageKind};
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The messages two participants exchange once when they meet.
+const HANDSHAKE: [MessageKind; 5] = [
+    MessageKind::PublicAddress,
+    MessageKind::PushParticipantsList,
+    MessageKind::SharedPeers,
+    MessageKind::ListRequest,
+    MessageKind::CompactListReply,
+];
+
+/// Returns the messages of each kind the participant `index` sent to `peer` and received from
+/// it, as the `peer` command describes them.
+fn exchanged(harness: &Harness, index: usize, peer: SocketAddr) -> Vec<(u64, u64)> {
+    let out = harness
+        .node(index)
+        .handle
+        .command(Command::Peer(peer), TIMEOUT)
+        .expect("the peer command is answered");
+    MessageKind::ALL
+        .iter()
+        .map(|kind| {
+            let prefix = format!("\t{}: sent ", kind.name());
+            let line = out
+                .iter()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .unwrap_or_else(|| panic!("no {} counters in {:?}", kind.name(), out));
+            let (sent, received) = line
+                .split_once(", received ")
+                .unwrap_or_else(|| panic!("malformed counters {:?}", line));
+            (sent.parse().unwrap(), received.parse().unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn both_sides_of_a_connection_count_the_same_handshake_messages() {
+    let mut harness = Harness::in_memory();
+    let first = harness.start(None, &[]);
+    let second = harness.start(Some(harness.node(first).addr), &[]);
+    let (first_addr, second_addr) = (harness.node(first).addr, harness.node(second).addr);
+    harness.wait_until("a few gossip rounds went by", TIMEOUT, |harness| {
+        [first, second]
+            .iter()
+            .all(|index| harness.node(*index).report().received[MessageKind::Text.index()] >= 3)
+    });
+
+    let first_counts = exchanged(&harness, first, second_addr);
+    let second_counts = exchanged(&harness, second, first_addr);
+    for kind in HANDSHAKE {
+        let (first_sent, first_received) = first_counts[kind.index()];
+        let (second_sent, second_received) = second_counts[kind.index()];
+        assert_eq!(
+            (first_sent, first_received),
+            (second_received, second_sent),
+            "{} (sent, received) of the first participant mirrored by the second",
+            kind.name()
+        );
+    }
+    // The dialing participant announced itself and asked for the list.
+    assert_eq!(second_counts[MessageKind::PublicAddress.index()].0, 1);
+    assert_eq!(
+        second_counts[MessageKind::PushParticipantsList.index()].0,
+        1
+    );
+}
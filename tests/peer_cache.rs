@@ -0,0 +1,67 @@
+//! A participant restarted without any `--connect` target rejoins the network through its
+//! recent peers cache.
+//!
+//! The cache lives in the directory `$XDG_CACHE_HOME` names, which this test points to a
+//! directory of its own; it is the only test of the binary, so no other one sees the change.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::{Direction, LearnedVia};
+
+use std::fs;
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[test]
+fn a_restarted_participant_rejoins_through_its_cache() {
+    let cache = std::env::temp_dir().join(format!("gossip-p2p-cache-{}", std::process::id()));
+    fs::create_dir_all(&cache).unwrap();
+    std::env::set_var("XDG_CACHE_HOME", &cache);
+
+    let mut harness = Harness::in_memory().with_peer_cache();
+    let seed = harness.start(None, &[]);
+    let seed_addr = harness.node(seed).addr;
+    let member = harness.start(Some(seed_addr), &[]);
+    let member_addr = harness.node(member).addr;
+    harness.wait_until("the member joined the seed", TIMEOUT, |harness| {
+        harness.node(seed).is_connected_to(member_addr)
+            && harness.node(member).is_connected_to(seed_addr)
+    });
+
+    harness.restart(member, &[]);
+    harness.wait_until(
+        "the restarted member rejoined the seed",
+        TIMEOUT,
+        |harness| {
+            harness.node(member).is_connected_to(seed_addr)
+                && harness.node(seed).is_connected_to(member_addr)
+        },
+    );
+    // The member dialed the seed itself, from the address its cache kept.
+    let report = harness.node(member).report();
+    let seed_peer = report
+        .peers
+        .iter()
+        .find(|peer| peer.addr == seed_addr)
+        .unwrap();
+    assert_eq!(seed_peer.direction, Direction::Outbound);
+    assert_eq!(
+        seed_peer
+            .provenance
+            .as_ref()
+            .map(|provenance| &provenance.source),
+        Some(&LearnedVia::PeerCache)
+    );
+    let cached = fs::read_to_string(cache.join("gossip-p2p").join("peers-9001.cache")).unwrap();
+    assert!(
+        cached.lines().any(|line| line == seed_addr.to_string()),
+        "{}",
+        cached
+    );
+
+    drop(harness);
+    let _ = fs::remove_dir_all(cache);
+}
@@ -0,0 +1,58 @@
+//! An embedder importing nothing but the prelude runs two participants in one process.
+
+use gossip_p2p::prelude::*;
+
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn two_participants_built_from_the_prelude_exchange_a_topic_message() {
+    let network = InMemoryNetwork::new();
+    let first = ParticipantBuilder::new(Duration::from_millis(100))
+        .port(9700)
+        .build_with_transport(network.transport())
+        .unwrap();
+    let mut second = ParticipantBuilder::new(Duration::from_millis(100))
+        .port(9701)
+        .connect("127.0.0.1:9700")
+        .build_with_transport(network.transport())
+        .unwrap();
+    let (received, waiter) = mpsc::channel();
+    second
+        .subscribe("chat", move |from, payload| {
+            let _ = received.send((from, payload));
+        })
+        .unwrap();
+    let (first_handle, second_handle) = (first.handle(), second.handle());
+    let threads = [
+        thread::spawn(move || first.run()),
+        thread::spawn(move || second.run()),
+    ];
+
+    // The interest of the second participant reaches the first with its first frames, so the
+    // message is published again until it arrives.
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let delivery = loop {
+        let publish = Command::Publish {
+            topic: "chat".to_owned(),
+            text: "hello".to_owned(),
+        };
+        let out = first_handle.command(publish, Duration::from_secs(5));
+        assert_eq!(out, Some(Vec::new()));
+        if let Ok(delivery) = waiter.recv_timeout(Duration::from_millis(200)) {
+            break delivery;
+        }
+        assert!(Instant::now() < deadline, "the message never arrived");
+    };
+    first_handle.shutdown();
+    second_handle.shutdown();
+    for thread in threads {
+        thread.join().unwrap().unwrap();
+    }
+    assert_eq!(
+        delivery,
+        (SocketAddr::from(([127, 0, 0, 1], 9700)), b"hello".to_vec())
+    );
+}
@@ -0,0 +1,94 @@
+//! The `probe` command of the binary measures the hop between two participants through the
+//! admin socket of the first.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::LinkConditions;
+
+use std::process::{Command, Output};
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+fn probe(socket: &str, peer: &str, count: u32) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .args(["probe", socket, peer])
+        .arg(format!("--count={}", count))
+        .arg("--interval=20ms")
+        .output()
+        .expect("the binary runs")
+}
+
+/// Reads the loss and the round trips in milliseconds from a summary line.
+fn parse_summary(line: &str) -> (f64, Vec<f64>) {
+    let loss = line
+        .split(", ")
+        .find_map(|field| field.strip_suffix("% loss"))
+        .and_then(|loss| loss.parse().ok())
+        .unwrap_or_else(|| panic!("no loss in {}", line));
+    let rtts = line
+        .split_once("rtt min/avg/p95/max = ")
+        .map(|(_, rtts)| rtts.trim_end_matches(" ms"))
+        .map_or(Vec::new(), |rtts| {
+            rtts.split('/').map(|rtt| rtt.parse().unwrap()).collect()
+        });
+    (loss, rtts)
+}
+
+#[test]
+fn a_probe_through_the_admin_socket_sums_up_the_loss_and_the_round_trips() {
+    let socket = std::env::temp_dir().join(format!("gossip-p2p-probe-{}.sock", std::process::id()));
+    let socket_arg = format!("--admin-socket={}", socket.display());
+    let mut harness = Harness::in_memory();
+    let prober = harness.start(None, &[&socket_arg]);
+    let target = harness.start(Some(harness.node(prober).addr), &[]);
+    let (prober_addr, target_addr) = (harness.node(prober).addr, harness.node(target).addr);
+    harness.wait_until("the two participants are connected", TIMEOUT, |harness| {
+        harness.node(prober).is_connected_to(target_addr)
+    });
+    let socket = socket.to_str().unwrap();
+
+    let output = probe(socket, &target_addr.to_string(), 20);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("Probe run 1 of "), "{}", stdout);
+    assert!(
+        lines[1].contains(": 20 sent, 20 received, 0.0% loss"),
+        "{}",
+        stdout
+    );
+    let (loss, rtts) = parse_summary(lines[1]);
+    assert_eq!(loss, 0.0);
+    let [min, avg, p95, max] = rtts[..] else {
+        panic!("no round trips in {}", lines[1]);
+    };
+    assert!(
+        min <= avg && avg <= max && min <= p95 && p95 <= max,
+        "{}",
+        lines[1]
+    );
+    assert!(max < 1000.0, "{}", lines[1]);
+
+    // Half of the probes are lost on their way to the peer.
+    let network = harness.network().unwrap();
+    network.set_conditions(
+        prober_addr,
+        target_addr,
+        LinkConditions {
+            latency: Duration::ZERO,
+            loss: 0.5,
+        },
+    );
+    let output = probe(socket, &target_addr.to_string(), 20);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let (loss, _) = parse_summary(stdout.lines().nth(1).unwrap());
+    assert!((20.0..=80.0).contains(&loss), "{}", stdout);
+
+    let output = probe(socket, "127.0.0.1:1", 1);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a connected participant"));
+}
@@ -0,0 +1,112 @@
+//! The `replay` command of the binary, run on a session recorded on the in-memory network.
+
+mod common;
+
+use common::Harness;
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+fn replay(recording: &Path, args: &[String]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .arg("replay")
+        .arg(format!("--file={}", recording.display()))
+        .arg("--fast")
+        .args(args)
+        .output()
+        .expect("the binary runs")
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gossip-p2p-replay-{}-{}", name, std::process::id()))
+}
+
+/// Records a session of three participants on the side of the first, which the others dial.
+///
+/// # Returns
+///
+/// The peers the recorded participant had connected when it stopped, sorted.
+fn record_session(recording: &Path) -> Vec<SocketAddr> {
+    let record = format!("--record={}", recording.display());
+    let mut harness = Harness::in_memory();
+    let first = harness.start(None, &[&record]);
+    let addr = harness.node(first).addr;
+    let second = harness.start(Some(addr), &[]);
+    let third = harness.start(Some(addr), &[]);
+    let peers = [harness.node(second).addr, harness.node(third).addr];
+    harness.wait_until("the first has both peers", TIMEOUT, |harness| {
+        peers
+            .iter()
+            .all(|peer| harness.node(first).is_connected_to(*peer))
+    });
+    // A few rounds of gossip, then the third leaves before the recording ends.
+    std::thread::sleep(Duration::from_millis(500));
+    harness.node_mut(third).stop().unwrap();
+    harness.wait_until("the first sees the third leave", TIMEOUT, |harness| {
+        !harness.node(first).is_connected_to(peers[1])
+    });
+    let live: Vec<SocketAddr> = harness
+        .node(first)
+        .report()
+        .peers
+        .iter()
+        .filter(|peer| peer.connected_for.is_some())
+        .map(|peer| peer.addr)
+        .collect();
+    harness.node_mut(first).stop().unwrap();
+    live
+}
+
+#[test]
+fn a_replayed_participant_ends_with_the_peers_of_the_live_one() {
+    let recording = temp_path("session.record");
+    let live = record_session(&recording);
+    assert_eq!(live.len(), 1);
+
+    let summary = temp_path("session.summary");
+    let output = replay(&recording, &[format!("--output={}", summary.display())]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    let live: Vec<String> = live.iter().map(SocketAddr::to_string).collect();
+    let connected = format!("Connected participants: [{}]", live.join(", "));
+    assert!(stdout.lines().any(|line| line == connected), "{}", stdout);
+    assert!(
+        stdout.contains("Events without a connection: 0"),
+        "{}",
+        stdout
+    );
+    // The sandbox answered what the first peer shared, without a socket of its own.
+    assert!(stdout.contains("send SharedPeers to "), "{}", stdout);
+
+    // The same build replaying the same recording does what it did before.
+    let output = replay(&recording, &[format!("--diff={}", summary.display())]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("No difference from "), "{}", stdout);
+
+    // A summary that differs is told apart, line by line.
+    let content = fs::read_to_string(&summary).unwrap();
+    fs::write(
+        &summary,
+        content.replace(&connected, "Connected participants: []"),
+    )
+    .unwrap();
+    let output = replay(&recording, &[format!("--diff={}", summary.display())]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(11), "{}", stdout);
+    assert!(
+        stdout.contains("- Connected participants: []"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(&format!("+ {}", connected)), "{}", stdout);
+
+    fs::remove_file(&recording).unwrap();
+    fs::remove_file(&summary).unwrap();
+}
@@ -0,0 +1,92 @@
+//! Requests between two participants on the in-memory network: a registered topic answers with
+//! its handler, any other with a clean error.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::RequestError;
+
+use std::time::Duration;
+
+/// How long a condition may take to hold, and a request to be answered.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Starts a participant answering on `echo` and `panic`, and a second one connected to it.
+///
+/// # Returns
+///
+/// The indexes of the answering participant and of the requesting one.
+fn answering_pair(harness: &mut Harness) -> (usize, usize) {
+    let answering = harness.start_with(None, &[], |participant| {
+        participant
+            .on_request("echo", |_, payload| Ok(payload))
+            .expect("echo is a valid topic name");
+        participant
+            .on_request("panic", |_, _| panic!("the handler fails"))
+            .expect("panic is a valid topic name");
+    });
+    let requesting = harness.start(Some(harness.node(answering).addr), &[]);
+    let addr = harness.node(answering).addr;
+    // The node id comes in the trailer announcing the protocol version, which tells the
+    // requesting participant that its peer speaks requests.
+    harness.wait_until("the answering participant is known", TIMEOUT, |harness| {
+        harness
+            .node(requesting)
+            .report()
+            .peers
+            .iter()
+            .any(|peer| peer.addr == addr && peer.node_id.is_some())
+    });
+    (answering, requesting)
+}
+
+#[test]
+fn a_registered_topic_answers_with_the_payload() {
+    let mut harness = Harness::in_memory();
+    let (answering, requesting) = answering_pair(&mut harness);
+    let peer = harness.node(answering).addr;
+    let client = &harness.node(requesting).requests;
+
+    assert_eq!(
+        client.request(peer, "echo", b"ping".to_vec(), TIMEOUT),
+        Ok(b"ping".to_vec())
+    );
+    // A timeout past what an instant can hold is shortened rather than panicking the loop.
+    assert_eq!(
+        client.request(peer, "echo", b"pong".to_vec(), Duration::MAX),
+        Ok(b"pong".to_vec())
+    );
+}
+
+#[test]
+fn other_topics_and_panics_are_answered_with_an_error() {
+    let mut harness = Harness::in_memory();
+    let (answering, requesting) = answering_pair(&mut harness);
+    let peer = harness.node(answering).addr;
+    let client = &harness.node(requesting).requests;
+
+    match client.request(peer, "missing", Vec::new(), TIMEOUT) {
+        Err(RequestError::Remote(reason)) => assert!(reason.contains("no handler"), "{}", reason),
+        other => panic!("unexpected outcome {:?}", other),
+    }
+    assert_eq!(
+        client.request(peer, "panic", Vec::new(), TIMEOUT),
+        Err(RequestError::Remote(
+            "the request handler panicked".to_owned()
+        ))
+    );
+    // The participant still answers after a handler panicked.
+    assert_eq!(client.request(peer, "echo", vec![1], TIMEOUT), Ok(vec![1]));
+}
+
+#[test]
+fn a_request_to_an_unknown_peer_fails_at_once() {
+    let mut harness = Harness::in_memory();
+    let (_, requesting) = answering_pair(&mut harness);
+    let client = &harness.node(requesting).requests;
+
+    assert_eq!(
+        client.request("127.0.0.1:1".parse().unwrap(), "echo", Vec::new(), TIMEOUT),
+        Err(RequestError::UnknownPeer)
+    );
+}
@@ -0,0 +1,34 @@
+//! The `selftest` command of the binary, run as a packaging step would run it.
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// The stages the self test runs, in order.
+const STAGES: [&str; 6] = [
+    "start",
+    "handshake",
+    "list exchange",
+    "broadcast",
+    "probe",
+    "shutdown",
+];
+
+#[test]
+fn the_selftest_passes_every_stage_and_exits_with_zero() {
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .arg("selftest")
+        .output()
+        .expect("the binary runs");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+
+    let passed: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("PASS "))
+        .map(|line| line.split(" (").next().unwrap_or(line))
+        .collect();
+    assert_eq!(passed, STAGES);
+    assert!(stdout.contains("Selftest passed: 6 stages"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
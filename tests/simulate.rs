@@ -0,0 +1,80 @@
+//! The `simulate` command of the binary, run on the example scenarios and on scenarios that
+//! fail.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+fn simulate(scenario: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gossip_p2p"))
+        .arg("simulate")
+        .arg(format!("--scenario={}", scenario.display()))
+        .arg("--fast")
+        .output()
+        .expect("the binary runs")
+}
+
+/// Writes `text` to a scenario file of its own for the test `name`.
+fn scenario_file(name: &str, text: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "gossip-p2p-simulate-{}-{}.json",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn the_example_scenarios_pass_and_exit_with_zero() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/scenarios");
+    for name in ["convergence", "partition-heal", "churn"] {
+        let started = Instant::now();
+        let output = simulate(&dir.join(format!("{}.json", name)));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(0), "{}: {}", name, stdout);
+        assert!(stdout.contains("Scenario passed: "), "{}: {}", name, stdout);
+        assert!(!stdout.contains("FAIL "), "{}: {}", name, stdout);
+        assert!(started.elapsed() < Duration::from_secs(30), "{}", name);
+    }
+}
+
+#[test]
+fn a_scenario_with_an_assertion_that_does_not_hold_exits_with_ten() {
+    let path = scenario_file(
+        "fails",
+        r#"{ "nodes": ["a", "b"],
+             "timeline": [{ "at": "1s", "assert": { "all-connected": { "within": "2s" } } }],
+             "assertions": [{ "received": { "node": "a", "from": "b", "at-least": 1 } }] }"#,
+    );
+    let output = simulate(&path);
+    fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(10), "{}", stdout);
+    assert!(stdout.contains(
+        "FAIL [   1.000s] assert all connected within 2s: \
+         membership: a is not connected to b; membership: b is not connected to a"
+    ));
+    assert!(stdout
+        .contains("FAIL [      end] assert a received at least 1 from b: a received 0 from b"));
+    assert!(stdout.contains("Scenario failed: 2 of 2 assertions did not hold"));
+}
+
+#[test]
+fn a_scenario_that_does_not_parse_exits_with_two_and_names_its_entry() {
+    let path = scenario_file(
+        "invalid",
+        r#"{ "nodes": ["a", "b"], "timeline": [{ "at": "5s", "stop": "b" }, { "at": "6s", "stop": "b" }] }"#,
+    );
+    let output = simulate(&path);
+    fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(
+        stderr.contains(r#"timeline[1] {"at":"6s","stop":"b"}: b is not running at 6s"#),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,49 @@
+//! A participant bootstraps from a membership snapshot exported by another, with no
+//! `--connect` target.
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::Command;
+
+use std::fs;
+use std::time::Duration;
+
+/// How long a condition may take to hold.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[test]
+fn a_participant_importing_a_snapshot_joins_every_exported_peer() {
+    let mut harness = Harness::in_memory();
+    let first = harness.start(None, &[]);
+    let second = harness.start(Some(harness.node(first).addr), &[]);
+    let (first_addr, second_addr) = (harness.node(first).addr, harness.node(second).addr);
+    harness.wait_until("the two participants are connected", TIMEOUT, |harness| {
+        harness.node(first).is_connected_to(second_addr)
+            && harness.node(second).is_connected_to(first_addr)
+    });
+
+    let path =
+        std::env::temp_dir().join(format!("gossip-p2p-membership-{}.json", std::process::id()));
+    let out = harness
+        .node(first)
+        .handle
+        .command(Command::Export(path.clone()), TIMEOUT)
+        .expect("the export is answered");
+    assert!(
+        out[0].starts_with("Exported 2 participants") && out[0].contains("signed by key"),
+        "{:?}",
+        out
+    );
+
+    // The key of the exporter is trusted as an operator would, from the export it logged.
+    let key = out[0].rsplit(' ').next().expect("the export names its key");
+    let import = format!("--import-membership={}", path.display());
+    let trusted = format!("--trusted-snapshot-key={}", key);
+    let third = harness.start(None, &[&import, &trusted, "--require-signed-import"]);
+    harness.wait_until("the importer is connected to both", TIMEOUT, |harness| {
+        harness.node(third).is_connected_to(first_addr)
+            && harness.node(third).is_connected_to(second_addr)
+    });
+    let _ = fs::remove_file(path);
+}
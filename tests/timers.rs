@@ -0,0 +1,40 @@
+//! A participant whose timers are further away than an `Instant` can tell, as an embedder can
+//! configure through `NodeConfig`, runs without ever firing them.
+
+use gossip_p2p::{InMemoryNetwork, Participant, ParticipantBuilder};
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+/// How long the participant is given to hand over its report.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[test]
+fn timers_past_the_clock_never_fire() {
+    let network = InMemoryNetwork::new();
+    let state_file =
+        std::env::temp_dir().join(format!("gossip-p2p-timers-{}.state", std::process::id()));
+    let mut config = ParticipantBuilder::new(Duration::from_millis(100))
+        .port(9000)
+        .config()
+        .expect("the settings are valid");
+    config.warmup = Some(Duration::MAX);
+    config.heal_probe_interval = Duration::MAX;
+    config.record_snapshot_every = Duration::MAX;
+    config.state_file = Some(state_file.clone());
+    config.state_save_interval = Some(Duration::MAX);
+
+    let participant =
+        Participant::with_transport(config, network.transport()).expect("the participant starts");
+    let handle = participant.handle();
+    let thread = thread::spawn(move || participant.run());
+
+    // Several maintenance ticks go by.
+    thread::sleep(Duration::from_millis(300));
+    assert!(handle.report(REPLY_TIMEOUT).is_some());
+    handle.shutdown();
+    let outcome = thread.join().expect("the event loop does not panic");
+    let _ = fs::remove_file(&state_file);
+    assert!(outcome.is_ok(), "{:?}", outcome.err());
+}
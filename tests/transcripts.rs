@@ -0,0 +1,318 @@
+//! Golden wire transcripts: each file of `tests/transcripts/` is a conversation of the current
+//! protocol version recorded byte for byte, and `every_transcript_replays` plays the frames of
+//! its initiator to a live in-memory participant, checking that the responder answers with
+//! the recorded frames, out of their masks. A change of the wire format, such as a `Message`
+//! variant moved, fails it with the offset of the first differing byte and the bytes around
+//! it; see the `transcript` module for the format.
+//!
+//! After an intended change of the protocol, regenerate the transcripts and check them in:
+//!
+//! ```sh
+//! cargo test --features gen-transcripts --test transcripts -- --ignored generate_transcripts
+//! ```
+
+use gossip_p2p::transcript::{self, Transcript};
+use gossip_p2p::{InMemoryNetwork, NodeConfig, Participant, ParticipantHandle};
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Where the transcripts are kept.
+const TRANSCRIPTS: &str = "tests/transcripts";
+
+/// How long a frame of the responder may take.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The port the responder listens on.
+const RESPONDER_PORT: u16 = 9000;
+
+/// A participant answering a transcript on an in-memory network of its own.
+struct Responder {
+    network: InMemoryNetwork,
+    addr: SocketAddr,
+    dir: PathBuf,
+    handle: ParticipantHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Responder {
+    /// Starts a participant with the arguments `args`, keeping its node id in a directory
+    /// named after the transcript `name`.
+    fn start(name: &str, args: &[String]) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "gossip-p2p-transcript-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).expect("the responder directory can be created");
+        let mut full_args = vec![
+            format!("--port={}", RESPONDER_PORT),
+            "--no-peer-cache".to_owned(),
+            format!("--identity-file={}", dir.join("responder.id").display()),
+        ];
+        full_args.extend(args.iter().cloned());
+        let config = NodeConfig::from_args(&full_args).expect("the arguments are valid");
+        let network = InMemoryNetwork::new();
+        let participant =
+            Participant::with_transport(config, network.transport()).expect("the responder starts");
+        let handle = participant.handle();
+        let thread = thread::spawn(move || {
+            let _ = participant.run();
+        });
+        Self {
+            network,
+            addr: SocketAddr::from(([127, 0, 0, 1], RESPONDER_PORT)),
+            dir,
+            handle,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns the transcripts, by file name.
+fn transcripts() -> Vec<(String, PathBuf)> {
+    let mut files: Vec<(String, PathBuf)> = fs::read_dir(TRANSCRIPTS)
+        .expect("the transcripts directory exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, path)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn load(path: &Path) -> Transcript {
+    let text = fs::read_to_string(path).expect("the transcript can be read");
+    Transcript::parse(&text).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+fn every_transcript_replays() {
+    let files = transcripts();
+    let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    for canonical in [
+        "handshake",
+        "peer-list-sync",
+        "text-message",
+        "heartbeat-round",
+    ] {
+        assert!(names.contains(&canonical), "no {} transcript", canonical);
+    }
+
+    let failures: Vec<String> = thread::scope(|scope| {
+        let replays: Vec<_> = files
+            .iter()
+            .map(|(name, path)| {
+                scope.spawn(move || {
+                    let transcript = load(path);
+                    let responder = Responder::start(name, &transcript.responder);
+                    transcript::replay(&transcript, &responder.network, responder.addr, TIMEOUT)
+                        .map_err(|err| format!("{}:\n{}", path.display(), err))
+                })
+            })
+            .collect();
+        replays
+            .into_iter()
+            .filter_map(|replay| replay.join().unwrap().err())
+            .collect()
+    });
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn every_transcript_renders_as_it_reads() {
+    for (_, path) in transcripts() {
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(load(&path).render(), text, "{}", path.display());
+    }
+}
+
+/// The conversations of the transcripts, recorded by `generate_transcripts`.
+#[cfg(feature = "gen-transcripts")]
+mod scripts {
+    use gossip_p2p::transcript::Step;
+    use gossip_p2p::{AgeBucket, MessageBuilder, MessageKind, NodeId, SharedPeer};
+
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    /// A transcript to record: its name, what it shows, the arguments of the responder and
+    /// the script of the initiator.
+    pub struct Script {
+        pub name: &'static str,
+        pub description: &'static [&'static str],
+        pub responder: &'static [&'static str],
+        pub steps: Vec<Step>,
+    }
+
+    /// The address the initiator announces, which nobody listens on.
+    fn initiator() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 9100))
+    }
+
+    fn frame(builder: MessageBuilder) -> Step {
+        Step::Send(builder.frame().expect("the script sends valid messages"))
+    }
+
+    /// The announcement of the initiator, with the trailer of a participant of its own.
+    fn announce() -> Step {
+        frame(MessageBuilder::public_address(initiator()).sender(
+            NodeId(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef),
+            None,
+            Duration::from_secs(1),
+            None,
+        ))
+    }
+
+    /// The handshake every conversation but the heartbeat round opens with.
+    fn handshake() -> Vec<Step> {
+        vec![
+            announce(),
+            frame(MessageBuilder::push_participants_list()),
+            Step::Expect(MessageKind::SharedPeers),
+            Step::Expect(MessageKind::ListRequest),
+        ]
+    }
+
+    pub fn all() -> Vec<Script> {
+        // No broadcast comes between the frames of a quiet responder, nor a batch grouping
+        // them, and a frame it can not read stops it, closing the connection.
+        let quiet: &[&str] = &["--period=1h", "--batch-window-ms=0", "--strict-protocol"];
+        let unreachable = SocketAddr::from(([127, 0, 0, 1], 9200));
+        vec![
+            Script {
+                name: "handshake",
+                description: &[
+                    "Handshake: the initiator announces its public address, with the trailer of its",
+                    "protocol version, node id, interest and period, and asks for the participants",
+                    "list. The responder answers with its age-tagged peers and the same trailer, then",
+                    "asks for the list of the initiator in turn, with an id the reply echoes.",
+                ],
+                responder: quiet,
+                steps: handshake(),
+            },
+            Script {
+                name: "peer-list-sync",
+                description: &[
+                    "Peer-list sync: after the handshake, the initiator answers the list request",
+                    "with itself and a peer nobody listens on. The responder dials that peer, fails,",
+                    "and asks the initiator to introduce them; then it answers the list request of",
+                    "the initiator with a packed list, which protocol version 12 peers are sent.",
+                ],
+                responder: quiet,
+                steps: [
+                    handshake(),
+                    vec![
+                        frame(MessageBuilder::list_reply(
+                            1,
+                            vec![
+                                SharedPeer {
+                                    addr: initiator(),
+                                    age: AgeBucket::Fresh,
+                                },
+                                SharedPeer {
+                                    addr: unreachable,
+                                    age: AgeBucket::Fresh,
+                                },
+                            ],
+                        )),
+                        Step::Expect(MessageKind::PleaseIntroduce),
+                        frame(MessageBuilder::list_request(7)),
+                        Step::Expect(MessageKind::CompactListReply),
+                    ],
+                ]
+                .concat(),
+            },
+            Script {
+                name: "text-message",
+                description: &[
+                    "Text message: after the handshake, the initiator sends a text, then a probe,",
+                    "whose reply shows the responder took the text without closing the connection.",
+                ],
+                responder: quiet,
+                steps: [
+                    handshake(),
+                    vec![
+                        frame(MessageBuilder::text("hello from the transcript")),
+                        frame(MessageBuilder::probe(1, 1_000)),
+                        Step::Expect(MessageKind::ProbeReply),
+                    ],
+                ]
+                .concat(),
+            },
+            Script {
+                name: "observer",
+                description: &[
+                    "Observer: the initiator subscribes as an observer instead of announcing itself,",
+                    "and the responder describes its view of the network: its peers, none, and the",
+                    "messages it sent and received so far, per kind.",
+                ],
+                responder: quiet,
+                steps: vec![
+                    frame(MessageBuilder::observer_hello()),
+                    Step::Expect(MessageKind::ObserverSnapshot),
+                ],
+            },
+            Script {
+                name: "heartbeat-round",
+                description: &[
+                    "Heartbeat round: the initiator announces itself and reads the clock of the",
+                    "responder, then the responder broadcasts its gossip text, the heartbeat of",
+                    "the period, and the initiator broadcasts its own.",
+                ],
+                responder: &["--period=1s", "--batch-window-ms=0", "--strict-protocol"],
+                steps: vec![
+                    announce(),
+                    frame(MessageBuilder::clock_request(1_700_000_000_000_000)),
+                    Step::Expect(MessageKind::ClockReply),
+                    Step::Expect(MessageKind::Text),
+                    frame(MessageBuilder::text("msg tick=1 from=127.0.0.1:9100 rnd=7")),
+                ],
+            },
+        ]
+    }
+}
+
+#[cfg(feature = "gen-transcripts")]
+#[test]
+#[ignore]
+fn generate_transcripts() {
+    use gossip_p2p::transcript::record;
+
+    fs::create_dir_all(TRANSCRIPTS).unwrap();
+    for script in scripts::all() {
+        let args: Vec<String> = script.responder.iter().map(|arg| arg.to_string()).collect();
+        let responder = Responder::start(script.name, &args);
+        let frames = record(&script.steps, &responder.network, responder.addr, TIMEOUT)
+            .unwrap_or_else(|err| panic!("{} can not be recorded: {}", script.name, err));
+        let transcript = Transcript {
+            description: script
+                .description
+                .iter()
+                .map(|line| line.to_string())
+                .collect(),
+            protocol: transcript::PROTOCOL_VERSION,
+            responder: args,
+            frames,
+        };
+        let path = Path::new(TRANSCRIPTS).join(format!("{}.txt", script.name));
+        fs::write(&path, transcript.render()).unwrap();
+        println!("Wrote {}", path.display());
+    }
+}
@@ -0,0 +1,168 @@
+//! The participants running on each transport: the same lifecycle passes on the in-memory
+//! network, without a socket and within a second, and on the TCP transport of `message-io`.
+//!
+//! The tests on TCP open loopback sockets, so they only run with the `net-tests` feature:
+//!
+//! ```sh
+//! cargo test --features net-tests
+//! ```
+
+mod common;
+
+use common::Harness;
+use gossip_p2p::{MessageKind, Report};
+
+use std::time::{Duration, Instant};
+
+/// How long the in-memory tests may take, each.
+const IN_MEMORY_BUDGET: Duration = Duration::from_secs(1);
+
+/// How long a stage may take on the TCP transport.
+#[cfg(feature = "net-tests")]
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counts the participants lists `report` received, in any of their forms.
+fn lists_received(report: &Report) -> u64 {
+    [
+        MessageKind::PullParticipantsList,
+        MessageKind::SharedPeers,
+        MessageKind::ListReply,
+        MessageKind::CompactListReply,
+    ]
+    .iter()
+    .map(|kind| report.received[kind.index()])
+    .sum()
+}
+
+/// Starts two participants, the second dialing the first, and waits until both list the
+/// other as connected under the node id it reports.
+fn handshake(harness: &mut Harness, timeout: Duration) {
+    let first = harness.start(None, &[]);
+    let second = harness.start(Some(harness.node(first).addr), &[]);
+    harness.wait_until("both are connected", timeout, |harness| {
+        let (first, second) = (harness.node(first), harness.node(second));
+        first.is_connected_to(second.addr) && second.is_connected_to(first.addr)
+    });
+    let first_id = harness.node(first).report().node_id;
+    let listed = harness.node(second).report().peers;
+    assert!(listed
+        .iter()
+        .any(|peer| peer.addr == harness.node(first).addr && peer.node_id == Some(first_id)));
+}
+
+/// Starts a chain of three participants, each dialing the previous one, and waits until the
+/// last learns the first from the lists and connects to it.
+fn list_exchange(harness: &mut Harness, timeout: Duration) {
+    let first = harness.start(None, &[]);
+    let second = harness.start(Some(harness.node(first).addr), &[]);
+    let third = harness.start(Some(harness.node(second).addr), &[]);
+    harness.wait_until("the ends of the chain are connected", timeout, |harness| {
+        let (first, third) = (harness.node(first), harness.node(third));
+        first.is_connected_to(third.addr) && third.is_connected_to(first.addr)
+    });
+    assert!(lists_received(&harness.node(third).report()) > 0);
+}
+
+/// Starts two participants and waits until each received gossip texts of the other.
+fn broadcast(harness: &mut Harness, timeout: Duration) {
+    let first = harness.start(None, &[]);
+    let second = harness.start(Some(harness.node(first).addr), &[]);
+    harness.wait_until("both received texts", timeout, |harness| {
+        [first, second]
+            .iter()
+            .all(|node| harness.node(*node).report().received[MessageKind::Text.index()] >= 2)
+    });
+}
+
+/// Runs `stage` on an in-memory harness, checking it opens no connection past its lifetime and
+/// fits `IN_MEMORY_BUDGET`.
+fn in_memory(stage: fn(&mut Harness, Duration)) {
+    let started = Instant::now();
+    let network = {
+        let mut harness = Harness::in_memory();
+        stage(&mut harness, IN_MEMORY_BUDGET);
+        let network = harness
+            .network()
+            .cloned()
+            .expect("the harness is in memory");
+        assert!(network.connections() > 0);
+        network
+    };
+    assert_eq!(network.connections(), 0);
+    assert!(
+        started.elapsed() < IN_MEMORY_BUDGET,
+        "took {:?}",
+        started.elapsed()
+    );
+}
+
+#[test]
+fn in_memory_participants_shake_hands() {
+    in_memory(handshake);
+}
+
+#[test]
+fn in_memory_participants_exchange_their_lists() {
+    in_memory(list_exchange);
+}
+
+#[test]
+fn in_memory_participants_receive_the_broadcasts_of_each_other() {
+    in_memory(broadcast);
+}
+
+#[test]
+fn in_memory_participants_see_a_peer_shut_down() {
+    let mut harness = Harness::in_memory();
+    handshake(&mut harness, IN_MEMORY_BUDGET);
+    let first = harness.node(0).addr;
+    harness
+        .node_mut(1)
+        .stop()
+        .expect("the participant stops cleanly");
+    harness.wait_until(
+        "the first lists no connected peer",
+        IN_MEMORY_BUDGET,
+        |harness| !harness.node(0).is_connected_to(harness.node(1).addr),
+    );
+    assert_eq!(
+        harness.network().map(|network| network.connections()),
+        Some(0)
+    );
+    assert_eq!(harness.node(0).addr, first);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn message_io_participants_shake_hands() {
+    handshake(&mut Harness::tcp(), TCP_TIMEOUT);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn message_io_participants_exchange_their_lists() {
+    list_exchange(&mut Harness::tcp(), TCP_TIMEOUT);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn message_io_participants_receive_the_broadcasts_of_each_other() {
+    broadcast(&mut Harness::tcp(), TCP_TIMEOUT);
+}
+
+#[cfg(feature = "net-tests")]
+#[test]
+fn message_io_participants_can_not_listen_on_a_taken_port() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").expect("a loopback port is free");
+    let port = taken.local_addr().expect("the listener is bound").port();
+    let config = gossip_p2p::NodeConfig::from_args(&[
+        "--period=1".to_owned(),
+        format!("--port={}", port),
+        "--no-peer-cache".to_owned(),
+    ])
+    .expect("the arguments are valid");
+    assert!(matches!(
+        gossip_p2p::Participant::new(config),
+        Err(gossip_p2p::ParticipantError::Bind(_))
+    ));
+}